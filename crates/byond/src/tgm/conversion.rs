@@ -1,7 +1,10 @@
 use bevy::{asset::AssetPathId, math::UVec2, utils::HashMap};
 
-use super::{Tile, TileMap, Value};
-use maps::{Direction, TileData, TileMapData, DIRECTIONS};
+use super::{Object, Tile, TileMap, Value};
+use maps::{
+    Direction, FootstepMaterial, TileData, TileLayer, TileMapData, ARRIVALS_LANDMARK,
+    CARP_SPAWN_LANDMARK, DIRECTIONS,
+};
 
 pub fn to_map_data(tilemap: &TileMap) -> TileMapData {
     let size = tilemap.size();
@@ -9,13 +12,25 @@ pub fn to_map_data(tilemap: &TileMap) -> TileMapData {
     let mut temporary_tiles = Vec::new();
     temporary_tiles.resize_with(size.x as usize * size.y as usize, Default::default);
     let mut job_spawns = HashMap::<String, Vec<UVec2>>::default();
+    let mut areas = Vec::<String>::new();
+    let mut area_indices = HashMap::<String, u16>::default();
 
     // Loop through all positions and convert the tile format
     for (position, &definition_index) in tilemap.tiles.iter() {
         let index = position.x + position.z * size.x;
         let definition = tilemap.definitions.get(definition_index).unwrap();
         // TODO: Cache this conversion (indexed by definition id)
-        let tile_data = tile_to_data(definition);
+        let mut tile_data = tile_to_data(definition);
+        tile_data.area = definition
+            .components
+            .iter()
+            .find(|c| c.path.starts_with("/area/"))
+            .map(|area| {
+                *area_indices.entry_ref(&area.path).or_insert_with(|| {
+                    areas.push(area.path.clone());
+                    (areas.len() - 1) as u16
+                })
+            });
         *temporary_tiles.get_mut(index as usize).unwrap() = Some(tile_data);
 
         // Find job spawn on tile
@@ -33,6 +48,30 @@ pub fn to_map_data(tilemap: &TileMap) -> TileMapData {
                 .or_default()
                 .push(UVec2::new(position.x, position.z));
         }
+
+        // Latejoiners arrive at the arrivals shuttle instead of their job spawn
+        if definition
+            .components
+            .iter()
+            .any(|c| c.path == "/obj/effect/landmark/latejoin")
+        {
+            job_spawns
+                .entry_ref(ARRIVALS_LANDMARK)
+                .or_default()
+                .push(UVec2::new(position.x, position.z));
+        }
+
+        // Space carp migrate in from these
+        if definition
+            .components
+            .iter()
+            .any(|c| c.path == "/obj/effect/landmark/carpspawn")
+        {
+            job_spawns
+                .entry_ref(CARP_SPAWN_LANDMARK)
+                .or_default()
+                .push(UVec2::new(position.x, position.z));
+        }
     }
 
     for index in 0..temporary_tiles.len() {
@@ -71,7 +110,95 @@ pub fn to_map_data(tilemap: &TileMap) -> TileMapData {
             .map(|t| t.unwrap_or_default())
             .collect(),
         job_spawn_positions: job_spawns,
+        areas,
+    }
+}
+
+/// A map object that isn't converted into a tile layer.
+/// These are spawned as prefabs by the game.
+#[derive(Clone)]
+pub struct ObjectPlacement {
+    pub byond_path: String,
+    pub tile_position: UVec2,
+    pub direction: Direction,
+    /// Objects with the same tag are linked to each other, like a button and the doors it opens
+    pub id_tag: Option<String>,
+}
+
+/// The id tag of an object that is converted as part of a tile.
+#[derive(Clone)]
+pub struct TileObjectTag {
+    pub tile_position: UVec2,
+    pub layer: TileLayer,
+    pub id_tag: String,
+}
+
+/// Collects all objects on the map which aren't part of [`to_map_data`].
+pub fn object_placements(tilemap: &TileMap) -> Vec<ObjectPlacement> {
+    let mut placements = Vec::new();
+    for (position, &definition_index) in tilemap.tiles.iter() {
+        let definition = tilemap.definitions.get(definition_index).unwrap();
+        for object in definition.components.iter() {
+            let path = object.path.as_str();
+            if !path.starts_with("/obj/") || is_tile_object(path) {
+                continue;
+            }
+
+            let direction = match object.variable("dir") {
+                Some(Value::Number(dir)) => Direction::from_byond(*dir as u8),
+                _ => None,
+            };
+            placements.push(ObjectPlacement {
+                byond_path: object.path.clone(),
+                tile_position: UVec2::new(position.x, position.z),
+                // BYOND objects face south by default
+                direction: direction.unwrap_or(Direction::South),
+                id_tag: id_tag(object),
+            });
+        }
+    }
+    placements
+}
+
+/// Collects the id tags of furniture converted by [`to_map_data`], like airlocks opened by buttons.
+// TODO: Wall mounts are moved to the neighbouring tile, so their tags aren't collected yet
+pub fn tile_object_tags(tilemap: &TileMap) -> Vec<TileObjectTag> {
+    let mut tags = Vec::new();
+    for (position, &definition_index) in tilemap.tiles.iter() {
+        let definition = tilemap.definitions.get(definition_index).unwrap();
+        for object in definition.components.iter() {
+            if furniture_name(&object.path).is_none() {
+                continue;
+            }
+            if let Some(id_tag) = id_tag(object) {
+                tags.push(TileObjectTag {
+                    tile_position: UVec2::new(position.x, position.z),
+                    layer: TileLayer::Furniture,
+                    id_tag,
+                });
+            }
+        }
     }
+    tags
+}
+
+/// The tag linking the object to others. Buttons call it `id`, the doors they open `id_tag`.
+fn id_tag(object: &Object) -> Option<String> {
+    ["id_tag", "id"]
+        .into_iter()
+        .find_map(|name| match object.variable(name) {
+            Some(Value::Literal(tag)) if !tag.is_empty() => Some(tag.clone()),
+            _ => None,
+        })
+}
+
+/// If the object is already converted as part of a tile or used as a landmark
+fn is_tile_object(path: &str) -> bool {
+    path.starts_with("/obj/effect/landmark")
+        || turf_name(path).is_some()
+        || path.starts_with("/obj/structure/window")
+        || furniture_name(path).is_some()
+        || high_mount_name(path).is_some()
 }
 
 fn tile_to_data(tile: &Tile) -> TileData {
@@ -79,40 +206,41 @@ fn tile_to_data(tile: &Tile) -> TileData {
         turf: get_turf_path(tile),
         furniture: get_furniture_path(tile),
         high_mounts: get_high_mounts_path(tile),
+        footstep_material: get_footstep_material(tile),
+        ..Default::default()
     }
 }
 
+fn get_footstep_material(tile: &Tile) -> Option<FootstepMaterial> {
+    tile.components
+        .iter()
+        .filter(|o| o.path.starts_with("/turf/open"))
+        .find_map(|o| {
+            let path = o.path.as_str();
+            if path.starts_with("/turf/open/floor/carpet") {
+                Some(FootstepMaterial::Carpet)
+            } else if path.starts_with("/turf/open/floor/wood") {
+                Some(FootstepMaterial::Wood)
+            } else if path.starts_with("/turf/open/floor/plating") {
+                Some(FootstepMaterial::Metal)
+            } else if path.starts_with("/turf/open/floor") {
+                Some(FootstepMaterial::Tile)
+            } else {
+                None
+            }
+        })
+}
+
 fn get_turf_path(tile: &Tile) -> Option<AssetPathId> {
     let turf_name = tile
         .components
         .iter()
         .filter_map(|o| {
             let priority = i32::from(o.path.starts_with("/obj"));
-            let mut name = match o.path.as_str() {
-                "/turf/closed/wall" => Some("wall"),
-                "/turf/closed/wall/r_wall" => Some("reinforced wall"),
-                "/obj/structure/grille" => Some("grille"),
-                "/obj/structure/plasticflaps/opaque" => Some("wall"),
-                "/obj/effect/spawner/structure/window" => Some("window"),
-                "/obj/effect/spawner/structure/window/reinforced" => Some("reinforced window"),
-                "/obj/effect/spawner/structure/window/reinforced/tinted" => {
-                    Some("reinforced window")
-                }
-                "/turf/open/floor/plasteel" => Some("floor"),
-                "/turf/open/floor/plasteel/white" => Some("white floor"),
-                "/turf/open/floor/plasteel/white/corner" => Some("white floor"),
-                "/turf/open/floor/plasteel/dark" => Some("dark floor"),
-                "/turf/open/floor/plasteel/grimy" => Some("floor"),
-                "/turf/open/floor/plating" => Some("plating"),
-                "/turf/open/floor/wood" => Some("wood floor"),
-                _ => None,
-            };
-            // Fallback for all floors
-            if name.is_none() && o.path.starts_with("/turf/open/floor") {
-                name = Some("floor");
-            }
-
-            Some((priority, name?))
+            Some((
+                priority,
+                turf_name(&o.path).or_else(|| thin_window_name(o))?,
+            ))
         })
         .max_by_key(|x| x.0)?
         .1;
@@ -124,40 +252,103 @@ fn get_turf_path(tile: &Tile) -> Option<AssetPathId> {
     )
 }
 
+fn turf_name(path: &str) -> Option<&'static str> {
+    let name = match path {
+        "/turf/closed/wall" => Some("wall"),
+        "/turf/closed/wall/r_wall" => Some("reinforced wall"),
+        "/obj/structure/plasticflaps/opaque" => Some("wall"),
+        "/obj/effect/spawner/structure/window" => Some("window"),
+        "/obj/effect/spawner/structure/window/reinforced" => Some("reinforced window"),
+        "/obj/effect/spawner/structure/window/reinforced/tinted" => Some("reinforced window"),
+        "/obj/structure/window/fulltile" => Some("window"),
+        "/obj/structure/window/reinforced/fulltile" => Some("reinforced window"),
+        "/turf/open/floor/plasteel" => Some("floor"),
+        "/turf/open/floor/plasteel/white" => Some("white floor"),
+        "/turf/open/floor/plasteel/white/corner" => Some("white floor"),
+        "/turf/open/floor/plasteel/dark" => Some("dark floor"),
+        "/turf/open/floor/plasteel/grimy" => Some("floor"),
+        "/turf/open/floor/plating" => Some("plating"),
+        "/turf/open/floor/wood" => Some("wood floor"),
+        // Raised turfs, single Z-level stand-ins until maps have multiple levels
+        "/obj/structure/lattice/catwalk" => Some("catwalk"),
+        _ => None,
+    };
+    // Stairs come in a variant per direction
+    if name.is_none() && path.starts_with("/obj/structure/stairs") {
+        return Some("stairs");
+    }
+    // Fallback for all floors
+    if name.is_none() && path.starts_with("/turf/open/floor") {
+        return Some("floor");
+    }
+    name
+}
+
+/// Windows that aren't full tile sit on the edge they face, there's a turf variant per edge.
+// TODO: Only one window per tile is kept, and it takes the place of the floor below
+fn thin_window_name(object: &Object) -> Option<&'static str> {
+    if !object.path.starts_with("/obj/structure/window") || object.path.contains("fulltile") {
+        return None;
+    }
+    // Reinforced thin windows don't have their own variant yet
+    let direction = match object.variable("dir") {
+        Some(Value::Number(dir)) => Direction::from_byond(*dir as u8),
+        _ => None,
+    };
+    // Byond objects face south by default
+    Some(match direction.unwrap_or(Direction::South) {
+        Direction::North => "thin window north",
+        Direction::East => "thin window east",
+        Direction::South => "thin window south",
+        Direction::West => "thin window west",
+    })
+}
+
+fn furniture_name(path: &str) -> Option<&'static str> {
+    if path.contains("door/airlock") {
+        if path.contains("maintenance") {
+            Some("airlock maintenance")
+        } else if path.contains("command") {
+            Some("airlock command")
+        } else if path.contains("mining") {
+            Some("airlock supply")
+        } else if path.contains("security") {
+            Some("airlock security")
+        } else if path.contains("engineering") {
+            Some("airlock engineering")
+        } else if path.contains("atmos") {
+            Some("airlock atmospherics")
+        } else if path.contains("research") {
+            Some("airlock research")
+        } else if path.contains("medical") {
+            Some("airlock medical")
+        } else {
+            Some("airlock")
+        }
+    } else if path.starts_with("/obj/structure/grille") {
+        Some("grille")
+    } else if path.starts_with("/obj/structure/table") {
+        Some("table")
+    } else if path.starts_with("/obj/structure/chair") {
+        Some("chair")
+    } else {
+        None
+    }
+}
+
+fn high_mount_name(path: &str) -> Option<&'static str> {
+    match path {
+        "/obj/machinery/light" => Some("light_tube"),
+        "/obj/machinery/camera" => Some("security_camera"),
+        _ => None,
+    }
+}
+
 fn get_furniture_path(tile: &Tile) -> Option<AssetPathId> {
     let furniture_name = tile
         .components
         .iter()
-        .filter_map(|o| {
-            if o.path.contains("door/airlock") {
-                if o.path.contains("maintenance") {
-                    Some("airlock maintenance")
-                } else if o.path.contains("command") {
-                    Some("airlock command")
-                } else if o.path.contains("mining") {
-                    Some("airlock supply")
-                } else if o.path.contains("security") {
-                    Some("airlock security")
-                } else if o.path.contains("engineering") {
-                    Some("airlock engineering")
-                } else if o.path.contains("atmos") {
-                    Some("airlock atmospherics")
-                } else if o.path.contains("research") {
-                    Some("airlock research")
-                } else if o.path.contains("medical") {
-                    Some("airlock medical")
-                } else {
-                    Some("airlock")
-                }
-            } else if o.path.starts_with("/obj/structure/table") {
-                Some("table")
-            } else if o.path.starts_with("/obj/structure/chair") {
-                Some("chair")
-            } else {
-                None
-            }
-        })
-        .next()?;
+        .find_map(|o| furniture_name(&o.path))?;
 
     Some(
         format!("tilemap/furniture/{}.scn.ron", furniture_name)
@@ -172,13 +363,7 @@ fn get_high_mounts_path(tile: &Tile) -> [Option<AssetPathId>; 4] {
     for (byond_dir, name) in tile
         .components
         .iter()
-        .filter_map(|o| {
-            match o.path.as_str() {
-                "/obj/machinery/light" => Some("light_tube"),
-                _ => None,
-            }
-            .map(|n| (o, n))
-        })
+        .filter_map(|o| high_mount_name(&o.path).map(|n| (o, n)))
         .filter_map(|(o, n)| match o.variable("dir") {
             Some(Value::Number(dir)) => Some((*dir as u8, n)),
             _ => None,
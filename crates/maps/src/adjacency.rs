@@ -154,4 +154,21 @@ pub(crate) struct TilemapAdjacency {
     // TODO: Allow multiple categories to mesh together
     pub category: String,
     pub meshes: AdjacencyVariants<Handle<Mesh>>,
+    /// The side of the tile the object is mounted on, like a thin window.
+    /// Objects filling the whole tile have none.
+    pub edge: Option<Direction>,
+}
+
+impl TilemapAdjacency {
+    /// If the object connects with a neighbour in the given direction.
+    /// Edge mounted objects only connect along their edge, with others on the same edge.
+    pub(crate) fn connects(&self, neighbour: &TilemapAdjacency, direction: Direction) -> bool {
+        if self.category != neighbour.category || self.edge != neighbour.edge {
+            return false;
+        }
+        match self.edge {
+            Some(edge) => direction != edge && direction != -edge,
+            None => true,
+        }
+    }
 }
@@ -0,0 +1,143 @@
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+use bevy_rapier3d::prelude::{Collider, ColliderDisabled, CollisionGroups};
+use physics::ColliderGroup;
+
+use crate::{world_to_tile, TileEntity, CHUNK_SIZE};
+
+/// A turf whose colliders are merged into one compound collider per chunk on the server.
+/// Only use it for turfs that are never hit individually by raycasts, like plain walls.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct BatchedCollider;
+
+/// The compound collider made from the batched turfs in a chunk.
+#[derive(Component)]
+pub struct ChunkCollider {
+    pub chunk: UVec2,
+}
+
+/// How far into a surface a ray hit is moved, so it lands inside the tile that was hit
+const HIT_DEPTH: f32 = 0.05;
+
+/// The tile a ray hit, for rays that hit a [`ChunkCollider`] and not the turf itself.
+/// Expects the map to be at the origin.
+pub fn hit_tile(origin: Vec3, direction: Vec3, toi: f32) -> Option<UVec2> {
+    world_to_tile(origin + direction * (toi + HIT_DEPTH))
+}
+
+/// Tilemap and chunk position
+type ChunkKey = (Entity, UVec2);
+
+#[derive(Resource, Default)]
+pub(crate) struct ChunkColliders {
+    tiles: HashMap<Entity, ChunkKey>,
+    chunks: HashMap<ChunkKey, Entity>,
+    dirty: HashSet<ChunkKey>,
+}
+
+/// Finds batched turfs that got their colliders or were removed, so their chunks are rebuilt.
+pub(crate) fn track_batched_tiles(
+    added: Query<Entity, (Added<Collider>, Without<ChunkCollider>)>,
+    batched: Query<&TileEntity, With<BatchedCollider>>,
+    parents: Query<&Parent>,
+    mut removed: RemovedComponents<BatchedCollider>,
+    mut colliders: ResMut<ChunkColliders>,
+) {
+    for entity in added.iter() {
+        // Colliders are usually children of the turf
+        let Some((tile_entity, tile)) = std::iter::once(entity)
+            .chain(parents.iter_ancestors(entity))
+            .find_map(|e| batched.get(e).ok().map(|tile| (e, tile)))
+        else {
+            continue;
+        };
+        let key = (tile.tilemap(), tile.position() / CHUNK_SIZE);
+        colliders.tiles.insert(tile_entity, key);
+        colliders.dirty.insert(key);
+    }
+
+    for entity in removed.iter() {
+        if let Some(key) = colliders.tiles.remove(&entity) {
+            colliders.dirty.insert(key);
+        }
+    }
+}
+
+/// Replaces the compound colliders of chunks whose batched turfs changed.
+/// The colliders of the turfs stay, but are disabled so only the compound is simulated.
+pub(crate) fn rebuild_chunk_colliders(
+    shapes: Query<(Entity, &Collider), Without<ChunkCollider>>,
+    transforms: Query<&Transform>,
+    parents: Query<&Parent>,
+    children: Query<&Children>,
+    mut colliders: ResMut<ChunkColliders>,
+    mut commands: Commands,
+) {
+    let colliders = colliders.as_mut();
+    for key in colliders.dirty.drain() {
+        let (tilemap, chunk) = key;
+        let mut compound = Vec::new();
+        for (&tile, _) in colliders.tiles.iter().filter(|(_, k)| **k == key) {
+            for (entity, shape) in std::iter::once(tile)
+                .chain(children.iter_descendants(tile))
+                .filter_map(|e| shapes.get(e).ok())
+            {
+                let Some(transform) = transform_in_map(entity, tile, &transforms, &parents) else {
+                    continue;
+                };
+                compound.push((transform.translation, transform.rotation, shape.clone()));
+                commands.entity(entity).insert(ColliderDisabled);
+            }
+        }
+
+        if compound.is_empty() {
+            if let Some(entity) = colliders.chunks.remove(&key) {
+                commands.entity(entity).despawn();
+            }
+            continue;
+        }
+
+        let collider = Collider::compound(compound);
+        match colliders.chunks.get(&key) {
+            Some(&entity) => {
+                commands.entity(entity).insert(collider);
+            }
+            None => {
+                let Some(mut map) = commands.get_entity(tilemap) else {
+                    continue;
+                };
+                let mut entity = Entity::PLACEHOLDER;
+                map.with_children(|builder| {
+                    entity = builder
+                        .spawn((
+                            ChunkCollider { chunk },
+                            collider,
+                            CollisionGroups::from(ColliderGroup::Static),
+                            TransformBundle::default(),
+                        ))
+                        .id();
+                });
+                colliders.chunks.insert(key, entity);
+            }
+        }
+    }
+}
+
+/// Position of a collider relative to the tilemap, found by walking up to the turf.
+fn transform_in_map(
+    entity: Entity,
+    tile: Entity,
+    transforms: &Query<&Transform>,
+    parents: &Query<&Parent>,
+) -> Option<Transform> {
+    let mut transform = *transforms.get(entity).ok()?;
+    let mut current = entity;
+    while current != tile {
+        current = parents.get(current).ok()?.get();
+        transform = transforms.get(current).ok()?.mul_transform(transform);
+    }
+    Some(transform)
+}
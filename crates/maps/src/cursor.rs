@@ -0,0 +1,213 @@
+use bevy::prelude::*;
+
+use crate::{world_to_tile, TileMapClient};
+
+/// Cursor rays that hit the tile plane further away than this are ignored.
+/// Prevents a camera looking at a steep angle from selecting tiles far off screen.
+const MAX_CURSOR_DISTANCE: f32 = 100.0;
+
+/// Height above the floor the highlight is drawn at, so it doesn't clip with the turf
+const HIGHLIGHT_HEIGHT: f32 = 0.02;
+
+/// Finds the tile under the cursor by intersecting the cursor ray with the tilemap floor plane.
+/// The result is clamped to the bounds of the tiles known to the client.
+pub fn cursor_tile(
+    window: &Window,
+    camera: &Camera,
+    camera_transform: &GlobalTransform,
+    map: &TileMapClient,
+    map_transform: &GlobalTransform,
+) -> Option<UVec2> {
+    let ray = camera.viewport_to_world(camera_transform, window.cursor_position()?)?;
+
+    // Work in tilemap space, so maps not placed at the world origin work too
+    let to_map = map_transform.affine().inverse();
+    let origin = to_map.transform_point3(ray.origin);
+    let direction = to_map.transform_vector3(ray.direction);
+    if direction.y.abs() <= f32::EPSILON {
+        return None;
+    }
+
+    let distance = -origin.y / direction.y;
+    if !(0.0..=MAX_CURSOR_DISTANCE).contains(&distance) {
+        return None;
+    }
+
+    let tile = world_to_tile(origin + direction * distance)?;
+    let bounds = map.bounds();
+    if bounds.min_element() == 0 {
+        return None;
+    }
+    Some(tile.min(bounds - UVec2::ONE))
+}
+
+/// The world position of the center of a tile on the floor.
+pub fn tile_to_world(map_transform: &GlobalTransform, position: UVec2) -> Vec3 {
+    map_transform.transform_point(Vec3::new(position.x as f32, 0.0, position.y as f32))
+}
+
+/// Set by placement systems to show which tile they are targeting.
+/// Reset every frame, so a system has to keep requesting for the highlight to stay visible.
+#[derive(Resource, Default)]
+pub struct HighlightRequest {
+    pub target: Option<HighlightTarget>,
+}
+
+pub struct HighlightTarget {
+    pub tilemap: Entity,
+    pub position: UVec2,
+    /// If placing is allowed on this tile. Shows green when valid and red otherwise.
+    pub valid: bool,
+}
+
+/// The single quad used to highlight a tile.
+#[derive(Component)]
+pub struct TileHighlight;
+
+#[derive(Resource)]
+pub(crate) struct TileHighlightAssets {
+    mesh: Handle<Mesh>,
+    valid_material: Handle<StandardMaterial>,
+    blocked_material: Handle<StandardMaterial>,
+}
+
+pub(crate) fn setup_tile_highlight(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let mut highlight_material = |color: Color| {
+        materials.add(StandardMaterial {
+            base_color: color.with_a(0.4),
+            emissive: color,
+            alpha_mode: AlphaMode::Blend,
+            unlit: true,
+            ..Default::default()
+        })
+    };
+
+    commands.insert_resource(TileHighlightAssets {
+        mesh: meshes.add(shape::Quad::new(Vec2::ONE).into()),
+        valid_material: highlight_material(Color::GREEN),
+        blocked_material: highlight_material(Color::RED),
+    });
+}
+
+pub(crate) fn update_tile_highlight(
+    mut request: ResMut<HighlightRequest>,
+    mut highlight: Query<
+        (
+            &mut Transform,
+            &mut Visibility,
+            &mut Handle<StandardMaterial>,
+        ),
+        With<TileHighlight>,
+    >,
+    tilemaps: Query<&GlobalTransform, With<TileMapClient>>,
+    assets: Res<TileHighlightAssets>,
+    mut commands: Commands,
+) {
+    let target = request
+        .target
+        .take()
+        .and_then(|t| Some((tilemaps.get(t.tilemap).ok()?, t)));
+
+    let Ok((mut transform, mut visibility, mut material)) = highlight.get_single_mut() else {
+        // The highlight is created on demand, as it's removed with other entities when leaving a server
+        if target.is_some() {
+            commands.spawn((
+                PbrBundle {
+                    mesh: assets.mesh.clone(),
+                    material: assets.valid_material.clone(),
+                    visibility: Visibility::Hidden,
+                    ..Default::default()
+                },
+                TileHighlight,
+            ));
+        }
+        return;
+    };
+
+    let Some((map_transform, target)) = target else {
+        if *visibility != Visibility::Hidden {
+            *visibility = Visibility::Hidden;
+        }
+        return;
+    };
+
+    // Quads face +Z, so rotate them to lie flat on the floor
+    let flat = Quat::from_rotation_x(-std::f32::consts::FRAC_PI_2);
+    let (_, map_rotation, _) = map_transform.to_scale_rotation_translation();
+    *transform = Transform {
+        translation: tile_to_world(map_transform, target.position)
+            + map_rotation * Vec3::Y * HIGHLIGHT_HEIGHT,
+        rotation: map_rotation * flat,
+        ..Default::default()
+    };
+    *visibility = Visibility::Visible;
+
+    let wanted = if target.valid {
+        &assets.valid_material
+    } else {
+        &assets.blocked_material
+    };
+    if *material != *wanted {
+        *material = wanted.clone();
+    }
+}
+
+/// Tiles tinted by debug overlays, like the navigation grid.
+/// Unlike [`HighlightRequest`] the tiles stay visible until the overlay is changed.
+#[derive(Resource, Default)]
+pub struct TileOverlay {
+    pub tilemap: Option<Entity>,
+    /// Tiles shown green when `true` and red otherwise
+    pub tiles: Vec<(UVec2, bool)>,
+}
+
+/// One of the quads spawned for the [`TileOverlay`].
+#[derive(Component)]
+pub struct TileOverlayQuad;
+
+pub(crate) fn update_tile_overlay(
+    overlay: Res<TileOverlay>,
+    quads: Query<Entity, With<TileOverlayQuad>>,
+    tilemaps: Query<&GlobalTransform, With<TileMapClient>>,
+    assets: Res<TileHighlightAssets>,
+    mut commands: Commands,
+) {
+    if !overlay.is_changed() {
+        return;
+    }
+
+    for quad in quads.iter() {
+        commands.entity(quad).despawn();
+    }
+
+    let Some(map_transform) = overlay.tilemap.and_then(|t| tilemaps.get(t).ok()) else {
+        return;
+    };
+    let flat = Quat::from_rotation_x(-std::f32::consts::FRAC_PI_2);
+    let (_, map_rotation, _) = map_transform.to_scale_rotation_translation();
+    for &(position, valid) in overlay.tiles.iter() {
+        let material = if valid {
+            &assets.valid_material
+        } else {
+            &assets.blocked_material
+        };
+        commands.spawn((
+            PbrBundle {
+                mesh: assets.mesh.clone(),
+                material: material.clone(),
+                transform: Transform {
+                    translation: tile_to_world(map_transform, position)
+                        + map_rotation * Vec3::Y * HIGHLIGHT_HEIGHT,
+                    rotation: map_rotation * flat,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            TileOverlayQuad,
+        ));
+    }
+}
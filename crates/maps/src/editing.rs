@@ -0,0 +1,81 @@
+use bevy::{ecs::system::Command, prelude::*};
+use networking::{scene::NetworkSceneBundle, variable::ServerVar};
+
+use crate::{TileEntityClient, TileEntityPath, TileLayer, TileMapClient};
+
+/// Spawns and removes tile objects on a client without a server, as done by the map editor.
+/// The tiles go through the same client systems as networked tiles, so adjacencies keep working.
+pub trait LocalTileCommandsExt {
+    fn spawn_local_tile(
+        &mut self,
+        tilemap: Entity,
+        position: UVec2,
+        layer: TileLayer,
+        index_in_layer: Option<u8>,
+        scene: Handle<DynamicScene>,
+    ) -> Entity;
+
+    fn despawn_local_tile(&mut self, entity: Entity);
+}
+
+impl<'w, 's> LocalTileCommandsExt for Commands<'w, 's> {
+    fn spawn_local_tile(
+        &mut self,
+        tilemap: Entity,
+        position: UVec2,
+        layer: TileLayer,
+        index_in_layer: Option<u8>,
+        scene: Handle<DynamicScene>,
+    ) -> Entity {
+        // The position and rotation are set from the path by the client tile systems
+        self.spawn((
+            NetworkSceneBundle {
+                scene: scene.into(),
+                ..Default::default()
+            },
+            TileEntityClient {
+                tilemap: ServerVar::from_default(tilemap),
+                path: ServerVar::from_default(TileEntityPath {
+                    position,
+                    layer,
+                    index_in_layer,
+                }),
+                old_path: None,
+            },
+        ))
+        .id()
+    }
+
+    fn despawn_local_tile(&mut self, entity: Entity) {
+        self.add(DespawnLocalTileCommand { entity });
+    }
+}
+
+struct DespawnLocalTileCommand {
+    entity: Entity,
+}
+
+impl Command for DespawnLocalTileCommand {
+    fn apply(self, world: &mut World) {
+        if let Some(tile) = world.get::<TileEntityClient>(self.entity) {
+            let (tilemap, path) = (*tile.tilemap, *tile.path);
+            if let Some(mut map) = world.get_mut::<TileMapClient>(tilemap) {
+                map.remove_at(path);
+            }
+        }
+        if let Some(entity) = world.get_entity_mut(self.entity) {
+            entity.despawn_recursive();
+        }
+    }
+}
+
+impl TileMapClient {
+    /// Creates an empty tilemap that is edited locally.
+    /// The bounds are set up front, so tiles can be placed anywhere inside them.
+    pub fn local(size: UVec2) -> Self {
+        Self {
+            bounds: size,
+            ..Default::default()
+        }
+    }
+}
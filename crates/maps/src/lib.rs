@@ -4,8 +4,8 @@ use adjacency::{AdjacencyInformation, TilemapAdjacency};
 use arrayvec::ArrayVec;
 use bevy::{
     asset::AssetPathId,
-    ecs::system::Command,
-    math::{IVec2, UVec2},
+    ecs::system::{Command, SystemParam},
+    math::{IVec2, UVec2, Vec3Swizzles},
     prelude::*,
     reflect::TypeUuid,
     utils::{HashMap, HashSet},
@@ -13,7 +13,7 @@ use bevy::{
 use networking::{
     component::AppExt,
     identity::{NetworkIdentities, NetworkIdentity},
-    scene::NetworkSceneBundle,
+    scene::{NetworkScene, NetworkSceneBundle},
     spawning::{NetworkedEntityEvent, SpawningSet},
     transform::NetworkTransform,
     variable::{NetworkVar, ServerVar},
@@ -25,7 +25,25 @@ use serde::{Deserialize, Serialize};
 pub use enum_map::enum_map;
 
 mod adjacency;
+pub mod chunk_colliders;
+mod cursor;
+mod editing;
+pub mod save;
+mod sub_grid;
+mod variants;
 pub use adjacency::Surrounded;
+pub use cursor::{
+    cursor_tile, tile_to_world, HighlightRequest, HighlightTarget, TileHighlight, TileOverlay,
+    TileOverlayQuad,
+};
+pub use editing::LocalTileCommandsExt;
+pub use sub_grid::{SubGrid, SubGridData, SubGridTile};
+pub use variants::{tile_hash, TurfVariant, TurfVariants};
+
+/// Key in the job spawn positions where players joining a running round arrive.
+pub const ARRIVALS_LANDMARK: &str = "arrivals";
+/// Key in the job spawn positions where space carp arrive from, usually just outside the station.
+pub const CARP_SPAWN_LANDMARK: &str = "carp_spawn";
 
 #[derive(Component, Networked)]
 #[networked(client = "TileMapClient", priority = 10)]
@@ -34,6 +52,12 @@ pub struct TileMap {
     size: UVec2,
     chunks: Vec<Option<Box<Chunk>>>,
     pub job_spawn_positions: HashMap<String, Vec<UVec2>>,
+    /// Names of the areas tiles can belong to
+    pub areas: Vec<String>,
+    /// Texture folder turfs use, see [`TurfVariants`]
+    theme: NetworkVar<Option<String>>,
+    /// Mixed into the tile position to pick turf variants
+    variant_seed: NetworkVar<u64>,
 }
 
 impl TileMap {
@@ -44,6 +68,9 @@ impl TileMap {
             size,
             chunks,
             job_spawn_positions: Default::default(),
+            areas: Default::default(),
+            theme: Default::default(),
+            variant_seed: Default::default(),
         }
     }
 
@@ -51,6 +78,17 @@ impl TileMap {
         self.size
     }
 
+    pub fn theme(&self) -> Option<&str> {
+        self.theme.as_deref()
+    }
+
+    /// Changes the textures of turfs on all clients, without respawning them
+    pub fn set_theme(&mut self, theme: Option<String>) {
+        if *self.theme != theme {
+            *self.theme = theme;
+        }
+    }
+
     pub fn iter_chunks(&self) -> impl Iterator<Item = (usize, &Chunk)> {
         self.chunks
             .iter()
@@ -108,6 +146,29 @@ impl TileMap {
         )
     }
 
+    /// Returns the tile at a world position.
+    /// This is a single indexed tile read, so it's cheap enough to call every frame.
+    pub fn tile_at(&self, position: Vec3) -> Option<&TileReference> {
+        let tile_position = world_to_tile(position)?;
+        if tile_position.x >= self.size.x * CHUNK_SIZE
+            || tile_position.y >= self.size.y * CHUNK_SIZE
+        {
+            return None;
+        }
+        self.tile(tile_position)
+    }
+
+    /// Returns the footstep material of the turf at a world position.
+    pub fn footstep_material_at(&self, position: Vec3) -> Option<FootstepMaterial> {
+        self.tile_at(position)?.footstep_material
+    }
+
+    /// Returns the name of the area at a world position.
+    pub fn area_at(&self, position: Vec3) -> Option<&str> {
+        let index = self.tile_at(position)?.area?;
+        self.areas.get(index as usize).map(String::as_str)
+    }
+
     pub fn tile_mut(&mut self, position: UVec2) -> Option<&mut TileReference> {
         let chunk_index = self.index_from_position(position);
         let position_in_chunk = self.position_inside_chunk(position);
@@ -154,6 +215,17 @@ impl TileMap {
     }
 }
 
+/// Converts a world position to the position of the tile it is on.
+/// Returns `None` if the position is outside of the positive tile space.
+pub fn world_to_tile(position: Vec3) -> Option<UVec2> {
+    // Tiles are centered on their position
+    let tile = (position.xz() + Vec2::splat(0.5)).floor();
+    if tile.min_element() < 0.0 {
+        return None;
+    }
+    Some(tile.as_uvec2())
+}
+
 pub const CHUNK_SIZE: u32 = 16;
 const CHUNK_LENGTH: usize = (CHUNK_SIZE * CHUNK_SIZE) as usize;
 
@@ -207,7 +279,7 @@ pub fn tile_neighbours(position: UVec2) -> impl Iterator<Item = (Direction, UVec
         .map(|(dir, p)| (dir, p.as_uvec2()))
 }
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq, Reflect)]
 pub enum Direction {
     North = 0,
     East,
@@ -219,6 +291,11 @@ impl Direction {
     fn rotate_around(self, axis: Vec3) -> Quat {
         Quat::from_axis_angle(axis, std::f32::consts::FRAC_PI_2 * (self as u8 as f32))
     }
+
+    /// The rotation of an object facing this direction.
+    pub fn rotation(self) -> Quat {
+        self.rotate_around(Vec3::Y)
+    }
 }
 
 pub const DIRECTIONS: [Direction; 4] = [
@@ -286,17 +363,74 @@ pub enum TileLayer {
     Turf,
     Furniture,
     HighMount,
+    /// Pipes running under the floor, which don't block anything
+    Pipe,
 }
 
 impl TileLayer {
     fn default_offset(&self) -> Vec3 {
         match self {
-            TileLayer::Furniture | TileLayer::Turf => Vec3::ZERO,
+            TileLayer::Furniture | TileLayer::Turf | TileLayer::Pipe => Vec3::ZERO,
             TileLayer::HighMount => Vec3::new(0.5, 2.0, 0.0),
         }
     }
 }
 
+/// What a turf sounds like when walked on.
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum FootstepMaterial {
+    #[default]
+    Metal,
+    Carpet,
+    Tile,
+    Wood,
+}
+
+/// Raises the floor of the tile a turf is on, like catwalks and stairs.
+/// The turf brings its own collider for the raised part.
+#[derive(Component, Reflect, Default, Clone, Copy)]
+#[reflect(Component)]
+pub struct FloorHeight {
+    /// Height of the walkable surface above the ground in meters
+    pub height: f32,
+}
+
+/// Looks up how high the floor is at a position, on both the server and the client.
+#[derive(SystemParam)]
+pub struct FloorHeights<'w, 's> {
+    maps: Query<'w, 's, &'static TileMap>,
+    client_maps: Query<'w, 's, &'static TileMapClient>,
+    heights: Query<'w, 's, &'static FloorHeight>,
+}
+
+impl<'w, 's> FloorHeights<'w, 's> {
+    /// Height of the floor on a tile. Tiles without a raised turf are at ground level.
+    pub fn tile_height(&self, position: UVec2) -> f32 {
+        // TODO: Support multiple maps
+        let turf = self
+            .maps
+            .iter()
+            .find_map(|map| {
+                let size = map.size() * CHUNK_SIZE;
+                (position.x < size.x && position.y < size.y)
+                    .then(|| map.tile(position)?.turf)
+                    .flatten()
+            })
+            .or_else(|| {
+                self.client_maps
+                    .iter()
+                    .find_map(|map| map.tile(position)?.turf)
+            });
+        turf.and_then(|turf| self.heights.get(turf).ok())
+            .map_or(0.0, |floor| floor.height)
+    }
+
+    /// Height of the floor under a world position.
+    pub fn height_at(&self, position: Vec3) -> f32 {
+        world_to_tile(position).map_or(0.0, |tile| self.tile_height(tile))
+    }
+}
+
 /// Uniquely references a tile entity in a [`TileMap`].
 ///
 /// ## Remarks
@@ -315,6 +449,8 @@ pub struct TileMapData {
     pub size: UVec2,
     pub tiles: Vec<TileData>,
     pub job_spawn_positions: HashMap<String, Vec<UVec2>>,
+    /// Area names referenced by [`TileData::area`]
+    pub areas: Vec<String>,
 }
 
 impl TileMapData {
@@ -349,6 +485,10 @@ pub struct TileData {
     pub turf: Option<AssetPathId>,
     pub furniture: Option<AssetPathId>,
     pub high_mounts: [Option<AssetPathId>; 4],
+    pub pipe: Option<AssetPathId>,
+    pub footstep_material: Option<FootstepMaterial>,
+    /// Index into the map's area names
+    pub area: Option<u16>,
 }
 
 impl TileData {
@@ -360,6 +500,7 @@ impl TileData {
                 TileLayer::HighMount,
                 TileLayerData::Directional(self.high_mounts),
             ),
+            (TileLayer::Pipe, TileLayerData::Single(self.pipe)),
         ]
         .into_iter()
     }
@@ -371,6 +512,9 @@ pub struct TileReference {
     pub turf: Option<Entity>,
     pub furniture: Option<Entity>,
     pub high_mounts: [Option<Entity>; 4],
+    pub pipe: Option<Entity>,
+    pub footstep_material: Option<FootstepMaterial>,
+    pub area: Option<u16>,
 }
 
 impl TileReference {
@@ -388,6 +532,7 @@ impl TileReference {
             TileLayer::Turf => self.turf.into(),
             TileLayer::Furniture => self.furniture.into(),
             TileLayer::HighMount => self.high_mounts.into(),
+            TileLayer::Pipe => self.pipe.into(),
         }
     }
 
@@ -396,6 +541,7 @@ impl TileReference {
             (TileLayer::Turf, TileLayerData::Single(v)) => self.turf = v,
             (TileLayer::Furniture, TileLayerData::Single(v)) => self.furniture = v,
             (TileLayer::HighMount, TileLayerData::Directional(v)) => self.high_mounts = v,
+            (TileLayer::Pipe, TileLayerData::Single(v)) => self.pipe = v,
             (layer, data) => panic!(
                 "Invalid combination of layer '{:?}' and data format '{:?}'",
                 layer, data
@@ -405,7 +551,7 @@ impl TileReference {
 
     fn set_index(&mut self, layer: TileLayer, index: usize, value: Option<Entity>) {
         match layer {
-            TileLayer::Turf | TileLayer::Furniture => panic!(
+            TileLayer::Turf | TileLayer::Furniture | TileLayer::Pipe => panic!(
                 "Can't set index on tile layer '{:?}' with single slot",
                 layer
             ),
@@ -431,7 +577,7 @@ impl TileReference {
 /// Attached to an entity that is a part of a tile.
 #[derive(Component, Networked)]
 #[networked(client = "TileEntityClient")]
-struct TileEntity {
+pub struct TileEntity {
     #[networked(
         with = "Self::network_tilemap(Res<'static, NetworkIdentities>) -> NetworkIdentity"
     )]
@@ -445,6 +591,20 @@ impl TileEntity {
             .get_identity(*entity)
             .expect("Tilemap entity must have network identity")
     }
+
+    /// The tilemap the entity is a part of
+    pub fn tilemap(&self) -> Entity {
+        *self.tilemap
+    }
+
+    /// The tile position inside the tilemap
+    pub fn position(&self) -> UVec2 {
+        self.path.position
+    }
+
+    pub fn layer(&self) -> TileLayer {
+        self.path.layer
+    }
 }
 
 #[derive(Default, Component, TypeUuid, Networked)]
@@ -473,21 +633,38 @@ impl TileEntityClient {
     }
 }
 
+/// How turfs of newly spawned maps look.
+/// Set by the game from the server config, the theme of running maps is changed on [`TileMap`].
+#[derive(Resource, Default, Clone)]
+pub struct MapStyle {
+    pub theme: Option<String>,
+    /// Seed for picking turf variants
+    pub variant_seed: u64,
+}
+
 /// Creates a tilemap from data and spawns the tile objects into the world
 fn spawn_from_data(
     query: Query<(Entity, &TileMapData), Without<TileMap>>,
     mut commands: Commands,
     server: ResMut<AssetServer>,
+    style: Res<MapStyle>,
 ) {
     for (map_entity, data) in query.iter() {
         let mut map = TileMap::new(data.size_in_chunks());
         map.job_spawn_positions = data.job_spawn_positions.clone();
+        map.areas = data.areas.clone();
+        *map.theme = style.theme.clone();
+        *map.variant_seed = style.variant_seed;
 
         for (data_index, tile_data) in data.tiles.iter().enumerate() {
             let y = data_index as u32 / data.size.x;
             let x = data_index as u32 - y * data.size.x;
 
-            let mut tile_ref = TileReference::default();
+            let mut tile_ref = TileReference {
+                footstep_material: tile_data.footstep_material,
+                area: tile_data.area,
+                ..Default::default()
+            };
 
             // Spawn tile entities for each layer
             for (layer, layer_data) in tile_data.layers() {
@@ -573,6 +750,16 @@ fn update_grid_aabb(mut query: Query<(&TileMap, &mut GridAabb), Changed<TileMap>
 
 pub trait MapCommandsExt {
     fn despawn_tile_entity(&mut self, entity: Entity);
+
+    /// Spawns a tile object into the turf, furniture or pipe slot of a tile while the map is running.
+    /// An object already in the slot is despawned.
+    fn spawn_tile_entity(
+        &mut self,
+        tilemap: Entity,
+        position: UVec2,
+        layer: TileLayer,
+        scene: Handle<DynamicScene>,
+    ) -> Entity;
 }
 
 impl<'w, 's> MapCommandsExt for Commands<'w, 's> {
@@ -580,6 +767,76 @@ impl<'w, 's> MapCommandsExt for Commands<'w, 's> {
         self.add(DespawnTileEntityCommand { entity });
         self.entity(entity).despawn_recursive();
     }
+
+    fn spawn_tile_entity(
+        &mut self,
+        tilemap: Entity,
+        position: UVec2,
+        layer: TileLayer,
+        scene: Handle<DynamicScene>,
+    ) -> Entity {
+        assert!(
+            layer != TileLayer::HighMount,
+            "Only single slot layers can be spawned into"
+        );
+        let path = TileEntityPath {
+            position,
+            layer,
+            index_in_layer: None,
+        };
+        let entity = self
+            .spawn((
+                NetworkSceneBundle {
+                    scene: scene.into(),
+                    transform: Transform::from_translation(
+                        Vec3::new(position.x as f32, 0.0, position.y as f32)
+                            + layer.default_offset(),
+                    ),
+                    ..Default::default()
+                },
+                TileEntity {
+                    tilemap: tilemap.into(),
+                    path: path.into(),
+                },
+            ))
+            .id();
+        self.entity(tilemap).add_child(entity);
+        self.add(PlaceTileEntityCommand {
+            tilemap,
+            entity,
+            path,
+        });
+        entity
+    }
+}
+
+struct PlaceTileEntityCommand {
+    tilemap: Entity,
+    entity: Entity,
+    path: TileEntityPath,
+}
+
+impl Command for PlaceTileEntityCommand {
+    fn apply(self, world: &mut World) {
+        let Some(mut map) = world.get_mut::<TileMap>(self.tilemap) else {
+            return;
+        };
+        let mut tile = map.tile(self.path.position).copied().unwrap_or_default();
+        let replaced = match tile.get(self.path.layer) {
+            TileLayerData::Single(entity) => entity,
+            TileLayerData::Directional(_) => None,
+        };
+        tile.set(self.path.layer, TileLayerData::Single(Some(self.entity)));
+        if map.set_tile(self.path.position, tile).is_err() {
+            warn!(position = ?self.path.position, "Tile entity spawned outside of the map");
+        }
+
+        if let Some(replaced) = replaced.filter(|&e| e != self.entity) {
+            if let Some(entity) = world.get_entity_mut(replaced) {
+                entity.despawn_recursive();
+            }
+        }
+    }
 }
 
 struct DespawnTileEntityCommand {
@@ -601,38 +858,77 @@ impl Command for DespawnTileEntityCommand {
 
 // TODO: Remove once scenes support composition
 /// Adds some bundles to spawned tile scenes, so we don't need to specify them every time
+#[allow(clippy::too_many_arguments)]
 fn client_initialize_tile_objects(
-    new: Query<Entity, Added<TileEntityClient>>,
+    new: Query<Entity, Or<(Added<TileEntityClient>, Added<sub_grid::SubGridTileClient>)>>,
     children_query: Query<&Children>,
-    existing_meshes: Query<(&Handle<Mesh>, Option<&Transform>)>,
+    existing_meshes: Query<&Handle<Mesh>>,
+    transforms: Query<&Transform>,
     tile_entities: Query<&TileEntityClient>,
+    turf_variants: Query<(&TurfVariants, &NetworkScene)>,
     mut tilemaps: Query<&mut TileMapClient>,
     assets: Res<MapAssets>,
+    server: Res<AssetServer>,
+    mut turf_materials: ResMut<variants::TurfMaterials>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
     mut commands: Commands,
 ) {
     let Some(assets) = assets.client.as_ref() else {
         return;
     };
 
-    let mut process_entity = |entity| {
-        if let Ok((mesh, transform)) = existing_meshes.get(entity) {
+    let mut process_entity = |entity, material: &Handle<StandardMaterial>, rotation: Quat| {
+        let mut transform = transforms.get(entity).cloned().unwrap_or_default();
+        transform.rotation = rotation * transform.rotation;
+        if let Ok(mesh) = existing_meshes.get(entity) {
             commands.entity(entity).insert(PbrBundle {
                 mesh: mesh.clone(),
-                material: assets.default_material.clone(),
-                transform: transform.cloned().unwrap_or_default(),
+                material: material.clone(),
+                transform,
                 ..Default::default()
             });
+        } else if rotation != Quat::IDENTITY {
+            commands.entity(entity).insert(transform);
         }
     };
 
     for root in new.iter() {
-        process_entity(root);
+        let tile = tile_entities.get(root).ok();
+
+        // Turfs with variants get the material picked for their tile
+        let variant =
+            tile.zip(turf_variants.get(root).ok())
+                .and_then(|(tile, (variants, scene))| {
+                    let seed = tilemaps
+                        .get(*tile.tilemap)
+                        .ok()?
+                        .variant_seed
+                        .get()
+                        .copied()?;
+                    let (index, turns) = variants.pick(tile.path.position, seed)?;
+                    let material = turf_materials.get_or_create(
+                        scene.handle().id(),
+                        index,
+                        &variants.variants[index],
+                        &server,
+                        &mut materials,
+                    );
+                    Some((
+                        material,
+                        Quat::from_rotation_y(std::f32::consts::FRAC_PI_2 * turns as f32),
+                    ))
+                });
+        let (material, rotation) =
+            variant.unwrap_or_else(|| (assets.default_material.clone(), Quat::IDENTITY));
+
+        // Directional variants turn the whole turf, the position update afterwards keeps the rotation
+        process_entity(root, &material, rotation);
         for child in children_query.iter_descendants(root) {
-            process_entity(child);
+            process_entity(child, &material, Quat::IDENTITY);
         }
 
         // Add to dirty tiles for adjacency
-        let Ok(tile) = tile_entities.get(root) else {
+        let Some(tile) = tile else {
             continue;
         };
         let mut map = tilemaps.get_mut(*tile.tilemap).unwrap();
@@ -645,12 +941,24 @@ fn client_initialize_tile_objects(
 #[derive(Default, Component, TypeUuid, Networked)]
 #[uuid = "9036e9c7-f3c4-478e-81ed-3084e52d2253"]
 #[networked(server = "TileMap")]
-struct TileMapClient {
+pub struct TileMapClient {
+    theme: ServerVar<Option<String>>,
+    variant_seed: ServerVar<u64>,
     tiles: HashMap<UVec2, TileReference>,
     dirty_tiles: HashSet<(UVec2, TileLayer)>,
+    /// Size of the area containing all known tiles
+    bounds: UVec2,
 }
 
 impl TileMapClient {
+    pub fn tile(&self, position: UVec2) -> Option<&TileReference> {
+        self.tiles.get(&position)
+    }
+
+    pub fn bounds(&self) -> UVec2 {
+        self.bounds
+    }
+
     fn remove_at(&mut self, path: TileEntityPath) {
         let Some(entry) = self.tiles.get_mut(&path.position) else {
             return;
@@ -694,6 +1002,7 @@ fn client_update_tile_entities(
             }
 
             // Add to new path
+            tilemap.bounds = tilemap.bounds.max(tile_path.position + UVec2::ONE);
             let entry = tilemap.tiles.entry(tile_path.position).or_default();
             match tile_path.index_in_layer {
                 Some(i) => {
@@ -783,7 +1092,7 @@ fn client_update_adjacencies(
                         // TODO: Support cross-layer checks
                         if let TileLayerData::Single(Some(adjacent_entity)) = tile_ref.get(layer) {
                             if let Ok(info) = adjacencies.get(adjacent_entity) {
-                                if adjacency_settings.category == info.category {
+                                if adjacency_settings.connects(info, direction) {
                                     adjacency_info.add(direction);
                                 }
                             }
@@ -841,9 +1150,17 @@ impl Plugin for MapPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
         app.add_systems(Startup, load_tilemap_assets)
             .register_type::<TilemapAdjacency>()
+            .register_type::<Direction>()
+            .register_type::<Option<Direction>>()
+            .register_type::<FloorHeight>()
+            .register_type::<TurfVariants>()
+            .register_type::<TurfVariant>()
+            .register_type::<Vec<TurfVariant>>()
+            .register_type::<chunk_colliders::BatchedCollider>()
             .register_type::<adjacency::AdjacencyVariants<Handle<Mesh>>>()
             .add_networked_component::<TileEntity, TileEntityClient>()
-            .add_networked_component::<TileMap, TileMapClient>();
+            .add_networked_component::<TileMap, TileMapClient>()
+            .add_networked_component::<SubGridTile, sub_grid::SubGridTileClient>();
 
         if app
             .world
@@ -851,22 +1168,46 @@ impl Plugin for MapPlugin {
             .unwrap()
             .is_client()
         {
-            app.add_systems(
-                PreUpdate,
-                client_mark_deleted_tile_entities.in_set(SpawningSet::BeforeDespawn),
-            )
-            .add_systems(
-                Update,
-                (
-                    client_initialize_tile_objects,
-                    client_update_tile_entities,
-                    apply_deferred,
-                    client_update_adjacencies,
+            app.init_resource::<HighlightRequest>()
+                .init_resource::<TileOverlay>()
+                .init_resource::<variants::TurfMaterials>()
+                .add_systems(Startup, cursor::setup_tile_highlight)
+                .add_systems(
+                    PreUpdate,
+                    client_mark_deleted_tile_entities.in_set(SpawningSet::BeforeDespawn),
                 )
-                    .chain(),
-            );
+                .add_systems(
+                    Update,
+                    (
+                        variants::client_apply_theme,
+                        client_initialize_tile_objects,
+                        apply_deferred,
+                        client_update_tile_entities,
+                        sub_grid::client_place_sub_grid_tiles,
+                        apply_deferred,
+                        client_update_adjacencies,
+                    )
+                        .chain(),
+                )
+                .add_systems(
+                    PostUpdate,
+                    (cursor::update_tile_highlight, cursor::update_tile_overlay),
+                );
         } else {
-            app.add_systems(Update, spawn_from_data)
+            app.init_resource::<chunk_colliders::ChunkColliders>()
+                .init_resource::<MapStyle>()
+                .add_systems(
+                    Update,
+                    (
+                        spawn_from_data,
+                        sub_grid::spawn_sub_grids,
+                        (
+                            chunk_colliders::track_batched_tiles,
+                            chunk_colliders::rebuild_chunk_colliders,
+                        )
+                            .chain(),
+                    ),
+                )
                 .add_systems(PostUpdate, update_grid_aabb);
         }
     }
@@ -0,0 +1,137 @@
+use std::{fs, path::Path};
+
+use bevy::{math::UVec2, utils::HashMap};
+use serde::{Deserialize, Serialize};
+
+use crate::{FootstepMaterial, TileData, TileMapData};
+
+/// A map stored on disk, written by the map editor and loaded by the server with `--map-save`.
+///
+/// Tile objects are referenced by their asset path, so the file stays readable and survives asset changes.
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct MapSave {
+    /// Size in tiles
+    pub size: UVec2,
+    /// Asset paths of the tile objects used in this map. Tiles index into this list.
+    pub palette: Vec<String>,
+    /// Tiles in rows, starting at the top left
+    pub tiles: Vec<SavedTile>,
+    /// Area names referenced by [`SavedTile::area`]
+    #[serde(default)]
+    pub areas: Vec<String>,
+    #[serde(default)]
+    pub job_spawn_positions: HashMap<String, Vec<UVec2>>,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SavedTile {
+    #[serde(default)]
+    pub turf: Option<u16>,
+    #[serde(default)]
+    pub furniture: Option<u16>,
+    #[serde(default)]
+    pub high_mounts: [Option<u16>; 4],
+    #[serde(default)]
+    pub pipe: Option<u16>,
+    #[serde(default)]
+    pub footstep_material: Option<FootstepMaterial>,
+    #[serde(default)]
+    pub area: Option<u16>,
+}
+
+impl MapSave {
+    /// Creates an empty map of the given size in tiles.
+    pub fn new(size: UVec2) -> Self {
+        Self {
+            size,
+            tiles: vec![SavedTile::default(); (size.x * size.y) as usize],
+            ..Default::default()
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let save: Self = ron::from_str(&text).map_err(|e| e.to_string())?;
+        if save.tiles.len() != (save.size.x * save.size.y) as usize {
+            return Err(format!(
+                "expected {} tiles for a {}x{} map, found {}",
+                save.size.x * save.size.y,
+                save.size.x,
+                save.size.y,
+                save.tiles.len()
+            ));
+        }
+        Ok(save)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let text =
+            ron::ser::to_string_pretty(self, Default::default()).map_err(|e| e.to_string())?;
+        fs::write(path, text).map_err(|e| e.to_string())
+    }
+
+    pub fn tile(&self, position: UVec2) -> Option<&SavedTile> {
+        self.tiles.get(self.index(position)?)
+    }
+
+    pub fn tile_mut(&mut self, position: UVec2) -> Option<&mut SavedTile> {
+        let index = self.index(position)?;
+        self.tiles.get_mut(index)
+    }
+
+    fn index(&self, position: UVec2) -> Option<usize> {
+        if position.x >= self.size.x || position.y >= self.size.y {
+            return None;
+        }
+        Some((position.y * self.size.x + position.x) as usize)
+    }
+
+    /// The palette index of an asset path, adding it if it's not used yet.
+    pub fn palette_index(&mut self, path: &str) -> u16 {
+        match self.palette.iter().position(|p| p == path) {
+            Some(index) => index as u16,
+            None => {
+                self.palette.push(path.to_owned());
+                (self.palette.len() - 1) as u16
+            }
+        }
+    }
+
+    /// The area index of an area name, adding it if it's not used yet.
+    pub fn area_index(&mut self, name: &str) -> u16 {
+        match self.areas.iter().position(|a| a == name) {
+            Some(index) => index as u16,
+            None => {
+                self.areas.push(name.to_owned());
+                (self.areas.len() - 1) as u16
+            }
+        }
+    }
+
+    /// The asset path of a palette entry.
+    pub fn path(&self, index: u16) -> Option<&str> {
+        self.palette.get(index as usize).map(String::as_str)
+    }
+
+    /// Converts the save into data the server can spawn a [`TileMap`](crate::TileMap) from.
+    pub fn to_data(&self) -> TileMapData {
+        let path_id = |index: Option<u16>| Some(self.path(index?)?.into());
+        TileMapData {
+            size: self.size,
+            tiles: self
+                .tiles
+                .iter()
+                .map(|tile| TileData {
+                    turf: path_id(tile.turf),
+                    furniture: path_id(tile.furniture),
+                    high_mounts: tile.high_mounts.map(path_id),
+                    pipe: path_id(tile.pipe),
+                    footstep_material: tile.footstep_material,
+                    area: tile.area,
+                })
+                .collect(),
+            job_spawn_positions: self.job_spawn_positions.clone(),
+            areas: self.areas.clone(),
+        }
+    }
+}
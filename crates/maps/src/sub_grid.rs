@@ -0,0 +1,147 @@
+use bevy::{prelude::*, reflect::TypeUuid};
+use networking::{
+    identity::{NetworkIdentities, NetworkIdentity},
+    scene::NetworkSceneBundle,
+    variable::{NetworkVar, ServerVar},
+    Networked,
+};
+
+/// A small grid of tiles outside the [`TileMap`](crate::TileMap).
+/// Its tiles are children of the grid entity, so it can be moved with the grid's transform.
+#[derive(Component)]
+pub struct SubGrid {
+    /// Size in tiles
+    size: UVec2,
+    turfs: Vec<Option<Entity>>,
+}
+
+impl SubGrid {
+    pub fn size(&self) -> UVec2 {
+        self.size
+    }
+
+    pub fn turf(&self, position: IVec2) -> Option<Entity> {
+        if position.min_element() < 0 {
+            return None;
+        }
+        let position = position.as_uvec2();
+        if position.x >= self.size.x || position.y >= self.size.y {
+            return None;
+        }
+        self.turfs[(position.y * self.size.x + position.x) as usize]
+    }
+
+    /// If there is a tile at a position
+    pub fn contains(&self, position: IVec2) -> bool {
+        self.turf(position).is_some()
+    }
+
+    /// The tile a position relative to the grid entity is on.
+    pub fn local_tile(local_position: Vec3) -> IVec2 {
+        IVec2::new(
+            local_position.x.round() as i32,
+            local_position.z.round() as i32,
+        )
+    }
+}
+
+/// Data which can be used to spawn a [`SubGrid`]
+#[derive(Component)]
+pub struct SubGridData {
+    /// Size in tiles
+    pub size: UVec2,
+    /// Scene paths of the turfs, row by row
+    pub turfs: Vec<Option<String>>,
+}
+
+/// Attached to a turf that is part of a [`SubGrid`].
+#[derive(Component, Networked)]
+#[networked(client = "SubGridTileClient")]
+pub struct SubGridTile {
+    #[networked(with = "Self::network_grid(Res<'static, NetworkIdentities>) -> NetworkIdentity")]
+    grid: NetworkVar<Entity>,
+    position: NetworkVar<UVec2>,
+}
+
+impl SubGridTile {
+    fn network_grid(entity: &Entity, param: Res<NetworkIdentities>) -> NetworkIdentity {
+        param
+            .get_identity(*entity)
+            .expect("Sub-grid entity must have network identity")
+    }
+}
+
+#[derive(Default, Component, TypeUuid, Networked)]
+#[uuid = "5b9e1c47-0a2d-4e83-b6f1-3c8d7a94e210"]
+#[networked(server = "SubGridTile")]
+pub(crate) struct SubGridTileClient {
+    grid: ServerVar<NetworkIdentity>,
+    position: ServerVar<UVec2>,
+}
+
+/// Spawns the turfs of new sub-grids
+pub(crate) fn spawn_sub_grids(
+    query: Query<(Entity, &SubGridData), Without<SubGrid>>,
+    mut commands: Commands,
+    server: Res<AssetServer>,
+) {
+    for (grid_entity, data) in query.iter() {
+        let mut turfs = Vec::with_capacity(data.turfs.len());
+        for (index, path) in data.turfs.iter().enumerate() {
+            let Some(path) = path else {
+                turfs.push(None);
+                continue;
+            };
+            let position = UVec2::new(index as u32 % data.size.x, index as u32 / data.size.x);
+            let turf = commands
+                .spawn((
+                    NetworkSceneBundle {
+                        scene: server.load(path.clone()).into(),
+                        transform: Transform::from_xyz(position.x as f32, 0.0, position.y as f32),
+                        ..Default::default()
+                    },
+                    SubGridTile {
+                        grid: grid_entity.into(),
+                        position: position.into(),
+                    },
+                ))
+                .id();
+            commands.entity(grid_entity).add_child(turf);
+            turfs.push(Some(turf));
+        }
+
+        commands.entity(grid_entity).insert(SubGrid {
+            size: data.size,
+            turfs,
+        });
+        info!(entity = ?grid_entity, size = ?data.size, "Spawned sub-grid");
+    }
+}
+
+/// Places sub-grid turfs under their grid, once the grid exists on the client
+pub(crate) fn client_place_sub_grid_tiles(
+    tiles: Query<(
+        Entity,
+        &SubGridTileClient,
+        Option<&Parent>,
+        Option<&Transform>,
+    )>,
+    identities: Res<NetworkIdentities>,
+    mut commands: Commands,
+) {
+    for (entity, tile, parent, transform) in tiles.iter() {
+        let Some(grid) = identities.get_entity(*tile.grid) else {
+            continue;
+        };
+        if parent.map(|p| p.get()) == Some(grid) {
+            continue;
+        }
+
+        let mut new_transform = transform.cloned().unwrap_or_default();
+        new_transform.translation = Vec3::new(tile.position.x as f32, 0.0, tile.position.y as f32);
+        commands
+            .entity(entity)
+            .insert(new_transform)
+            .set_parent(grid);
+    }
+}
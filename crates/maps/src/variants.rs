@@ -0,0 +1,226 @@
+use std::path::{Path, PathBuf};
+
+use bevy::{asset::HandleId, prelude::*, utils::HashMap};
+
+use crate::TileMapClient;
+
+/// Folder turf textures are loaded from
+const TEXTURE_FOLDER: &str = "textures/turfs";
+/// Folder below the texture folder that contains a folder per theme
+const THEME_FOLDER: &str = "themes";
+
+/// Material variants of a turf, one of which is picked for every tile the turf is on.
+/// The pick only depends on the tile position and the map's variant seed, so all clients agree without asking the server.
+#[derive(Component, Reflect, Default, Clone)]
+#[reflect(Component)]
+pub struct TurfVariants {
+    pub variants: Vec<TurfVariant>,
+}
+
+#[derive(Reflect, Default, Clone, Debug)]
+pub struct TurfVariant {
+    /// Texture path inside `textures/turfs`, or empty for an untextured material.
+    /// Themes can replace it with a file at the same path inside `textures/turfs/themes/<theme>`.
+    pub texture: String,
+    /// Tint multiplied with the texture. Turfs with a see-through tint, like windows, are drawn transparent.
+    pub color: Color,
+    /// How likely the variant is compared to the others
+    pub weight: u32,
+    /// If the texture has a visible orientation. It is turned by a multiple of 90 degrees per tile,
+    /// so patterns don't repeat in the same direction across a room.
+    pub directional: bool,
+}
+
+/// Hash of a tile position mixed with a seed (splitmix64 finalizer).
+pub fn tile_hash(position: UVec2, seed: u64) -> u64 {
+    let mut hash = seed ^ (((position.x as u64) << 32) | position.y as u64);
+    hash = hash.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    hash ^ (hash >> 31)
+}
+
+impl TurfVariants {
+    /// Index of the variant used on a tile and how many quarter turns it is rotated by.
+    /// Returns `None` if there are no variants with a weight.
+    pub fn pick(&self, position: UVec2, seed: u64) -> Option<(usize, u8)> {
+        let total: u64 = self.variants.iter().map(|v| v.weight as u64).sum();
+        if total == 0 {
+            return None;
+        }
+
+        let hash = tile_hash(position, seed);
+        // Low bits choose the variant, high bits the rotation
+        let mut roll = (hash & 0xffff_ffff) % total;
+        let index = self.variants.iter().position(|variant| {
+            if roll < variant.weight as u64 {
+                return true;
+            }
+            roll -= variant.weight as u64;
+            false
+        })?;
+        let turns = match self.variants[index].directional {
+            true => (hash >> 32) as u8 % 4,
+            false => 0,
+        };
+        Some((index, turns))
+    }
+}
+
+struct CachedMaterial {
+    handle: Handle<StandardMaterial>,
+    texture: String,
+}
+
+/// Materials for turf variants, shared by all tiles with the same turf definition and variant.
+#[derive(Resource, Default)]
+pub(crate) struct TurfMaterials {
+    /// Keyed by the scene of the turf definition and the variant index
+    materials: HashMap<(HandleId, usize), CachedMaterial>,
+    theme: Option<String>,
+}
+
+impl TurfMaterials {
+    /// The material of a variant, created the first time it's used.
+    pub(crate) fn get_or_create(
+        &mut self,
+        definition: HandleId,
+        index: usize,
+        variant: &TurfVariant,
+        server: &AssetServer,
+        materials: &mut Assets<StandardMaterial>,
+    ) -> Handle<StandardMaterial> {
+        let theme = self.theme.as_deref();
+        self.materials
+            .entry((definition, index))
+            .or_insert_with(|| CachedMaterial {
+                handle: materials.add(StandardMaterial {
+                    base_color: variant.color,
+                    base_color_texture: load_texture(server, theme, &variant.texture),
+                    perceptual_roughness: 0.8,
+                    alpha_mode: match variant.color.a() < 1.0 {
+                        true => AlphaMode::Blend,
+                        false => AlphaMode::Opaque,
+                    },
+                    ..Default::default()
+                }),
+                texture: variant.texture.clone(),
+            })
+            .handle
+            .clone()
+    }
+}
+
+/// Finds the texture in the theme folder, falling back to the default texture if the theme doesn't replace it.
+/// Resource packs are mounted below the asset folder, so they can add themes or replace textures of existing ones.
+fn themed_path(server: &AssetServer, theme: Option<&str>, texture: &str) -> PathBuf {
+    if let Some(theme) = theme {
+        let path = Path::new(TEXTURE_FOLDER)
+            .join(THEME_FOLDER)
+            .join(theme)
+            .join(texture);
+        if server.asset_io().get_metadata(&path).is_ok() {
+            return path;
+        }
+    }
+    Path::new(TEXTURE_FOLDER).join(texture)
+}
+
+fn load_texture(server: &AssetServer, theme: Option<&str>, texture: &str) -> Option<Handle<Image>> {
+    (!texture.is_empty()).then(|| server.load(themed_path(server, theme, texture)))
+}
+
+/// Swaps the textures of all cached materials when the server changes the theme.
+/// Tiles keep their material handles, so they pick up the new textures without being touched.
+pub(crate) fn client_apply_theme(
+    tilemaps: Query<&TileMapClient, Changed<TileMapClient>>,
+    mut cache: ResMut<TurfMaterials>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    server: Res<AssetServer>,
+) {
+    // TODO: Support multiple maps
+    let Some(theme) = tilemaps.iter().find_map(|map| map.theme.get()) else {
+        return;
+    };
+    if &cache.theme == theme {
+        return;
+    }
+
+    info!(theme = ?theme, "Applying map theme");
+    cache.theme = theme.clone();
+    for cached in cache.materials.values() {
+        if let Some(material) = materials.get_mut(&cached.handle) {
+            material.base_color_texture = load_texture(&server, theme.as_deref(), &cached.texture);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variant(weight: u32, directional: bool) -> TurfVariant {
+        TurfVariant {
+            weight,
+            directional,
+            ..Default::default()
+        }
+    }
+
+    fn picks(variants: &TurfVariants, seed: u64) -> Vec<(usize, u8)> {
+        let size = 32;
+        (0..size * size)
+            .map(|i| variants.pick(UVec2::new(i % size, i / size), seed).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn hash_is_stable() {
+        // Clients on other platforms and versions have to agree with the server
+        assert_eq!(tile_hash(UVec2::ZERO, 0), 0xe220_a839_7b1d_cdaf);
+        assert_eq!(tile_hash(UVec2::new(3, 7), 42), 0x9cee_8136_b7d5_21a8);
+    }
+
+    #[test]
+    fn same_seed_picks_the_same_variants() {
+        let variants = TurfVariants {
+            variants: vec![variant(1, true), variant(1, false), variant(2, false)],
+        };
+        assert_eq!(picks(&variants, 7), picks(&variants, 7));
+        assert_ne!(picks(&variants, 7), picks(&variants, 8));
+    }
+
+    #[test]
+    fn variants_are_picked_by_weight() {
+        let variants = TurfVariants {
+            variants: vec![variant(3, false), variant(0, false), variant(1, false)],
+        };
+        let picks = picks(&variants, 1);
+        let count = |index| picks.iter().filter(|(i, _)| *i == index).count();
+        assert_eq!(count(1), 0);
+        // 768 of 1024 tiles are expected to use the first variant
+        assert!((700..840).contains(&count(0)), "{}", count(0));
+        assert_eq!(count(0) + count(2), picks.len());
+    }
+
+    #[test]
+    fn only_directional_variants_are_turned() {
+        let variants = TurfVariants {
+            variants: vec![variant(1, false), variant(1, true)],
+        };
+        let picks = picks(&variants, 3);
+        assert!(picks.iter().all(|&(index, turns)| index == 1 || turns == 0));
+        for turns in 0..4 {
+            assert!(picks.contains(&(1, turns)), "never turned {} times", turns);
+        }
+    }
+
+    #[test]
+    fn nothing_is_picked_without_weights() {
+        let variants = TurfVariants {
+            variants: vec![variant(0, false)],
+        };
+        assert_eq!(variants.pick(UVec2::ONE, 0), None);
+        assert_eq!(TurfVariants::default().pick(UVec2::ONE, 0), None);
+    }
+}
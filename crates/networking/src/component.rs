@@ -1,9 +1,12 @@
 use std::clone::Clone;
 
+#[cfg(debug_assertions)]
+use bevy::ecs::{component::Tick, system::SystemChangeTick};
 use bevy::{prelude::*, utils::HashSet};
 use serde::{Deserialize, Serialize};
 
 use crate::{
+    diagnostics::{DebugNames, UnresolvedIdentities},
     identity::{NetworkIdentities, NetworkIdentity},
     messaging::{AppExt as MessagingAppExt, MessageEvent, MessageReceivers, MessageSender},
     time::ServerNetworkTime,
@@ -180,8 +183,10 @@ fn receive_networked_component<C: NetworkedFromServer + Component>(
     mut components: Query<&mut C>,
     registry: Res<NetworkedComponentRegistry>,
     identities: Res<NetworkIdentities>,
+    mut unresolved: ResMut<UnresolvedIdentities>,
     mut param: bevy::ecs::system::StaticSystemParam<C::Param>,
     mut commands: Commands,
+    names: DebugNames,
 ) {
     for event in events.iter() {
         // TODO: Move the id->uuid conversion into one system for performance?
@@ -202,9 +207,21 @@ fn receive_networked_component<C: NetworkedFromServer + Component>(
             return true;
         };
 
-        apply_component_update(entity, message, &mut components, &mut param, &mut commands);
+        apply_component_update(
+            entity,
+            message,
+            &mut components,
+            &mut param,
+            &mut commands,
+            &names,
+        );
         false
     });
+
+    unresolved.set(
+        std::any::type_name::<C>(),
+        buffer.iter().map(|m| m.identity).collect(),
+    );
 }
 
 fn apply_component_update<C: NetworkedFromServer + Component>(
@@ -213,6 +230,7 @@ fn apply_component_update<C: NetworkedFromServer + Component>(
     components: &mut Query<&mut C>,
     param: &mut bevy::ecs::system::StaticSystemParam<C::Param>,
     commands: &mut Commands,
+    names: &DebugNames,
 ) {
     match components.get_mut(entity) {
         Ok(mut c) => c.deserialize(param, &message.data),
@@ -223,7 +241,7 @@ fn apply_component_update<C: NetworkedFromServer + Component>(
                 commands.entity(entity).insert(default);
             } else {
                 warn!(
-                    ?entity,
+                    entity = %names.debug_name(entity),
                     component = std::any::type_name::<C>(),
                     "Received component message for entity without that component"
                 );
@@ -328,6 +346,8 @@ impl AppExt for App {
                 )
                     .in_set(NetworkSet::ServerWrite),
             );
+            #[cfg(debug_assertions)]
+            self.add_systems(Last, report_changes_after_snapshot::<S>);
         } else {
             self.add_systems(
                 PreUpdate,
@@ -347,12 +367,62 @@ pub enum ComponentSystem {
     Apply,
 }
 
+/// When the networked components of this tick were serialized.
+/// Everything changing networked state has to run before [`NetworkSet::ServerWrite`],
+/// otherwise the change is only sent a tick later.
+#[cfg(debug_assertions)]
+#[derive(Resource, Default)]
+struct SnapshotTick(Option<Tick>);
+
+#[cfg(debug_assertions)]
+fn record_snapshot_tick(mut snapshot: ResMut<SnapshotTick>, ticks: SystemChangeTick) {
+    snapshot.0 = Some(ticks.this_run());
+}
+
+/// Catches systems changing networked components after they were sent this tick.
+#[cfg(debug_assertions)]
+fn report_changes_after_snapshot<S: Component>(
+    components: Query<(Entity, Ref<S>), Changed<S>>,
+    snapshot: Res<SnapshotTick>,
+    ticks: SystemChangeTick,
+) {
+    let Some(snapshot) = snapshot.0 else {
+        return;
+    };
+    for (entity, component) in components.iter() {
+        // Components inserted by commands are sent whole when they are first seen
+        if component
+            .last_changed()
+            .is_newer_than(snapshot, ticks.this_run())
+            && !component.is_added()
+        {
+            error!(
+                ?entity,
+                component = std::any::type_name::<S>(),
+                "Networked component changed after it was sent, run the system before NetworkSet::ServerWrite"
+            );
+        }
+    }
+}
+
 pub(crate) struct ComponentPlugin;
 
 impl Plugin for ComponentPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<NetworkedComponentRegistry>()
-            .add_network_message::<NetworkedComponentMessage>()
-            .add_network_message::<RemoveNetworkedComponentMessage>();
+            .add_network_message::<NetworkedComponentMessage>("NetworkedComponentMessage")
+            .add_network_message::<RemoveNetworkedComponentMessage>(
+                "RemoveNetworkedComponentMessage",
+            );
+
+        #[cfg(debug_assertions)]
+        if app.world.resource::<NetworkManager>().is_server() {
+            app.init_resource::<SnapshotTick>().add_systems(
+                PostUpdate,
+                record_snapshot_tick
+                    .after(NetworkSet::ServerWrite)
+                    .before(NetworkSet::SendOutgoing),
+            );
+        }
     }
 }
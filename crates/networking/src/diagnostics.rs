@@ -0,0 +1,164 @@
+use std::fmt;
+
+use bevy::{ecs::system::SystemParam, prelude::*, utils::HashMap};
+use bevy_renet::renet::RenetClient;
+
+use crate::{
+    identity::NetworkIdentity, quality::LinkTier, scene::NetworkScene, spawning::PrefabPath,
+    time::ClientNetworkTime, NetworkManager,
+};
+
+/// Statistics about the connection to the server, updated every frame while connected.
+#[derive(Resource, Default, Debug, Clone)]
+pub struct ConnectionStats {
+    /// Round-trip-time in milliseconds
+    pub rtt: f64,
+    /// Fraction of packets that were lost
+    pub packet_loss: f64,
+    pub bytes_sent_per_second: f64,
+    pub bytes_received_per_second: f64,
+    /// The server tick the client is currently showing
+    pub interpolated_tick: f32,
+    /// How the server rates the connection, see [`LinkTier`]
+    pub link_tier: LinkTier,
+}
+
+/// Network identities that received updates before the client knew about their entity.
+/// Identities staying in here for long usually point to a desync.
+#[derive(Resource, Default, Debug)]
+pub struct UnresolvedIdentities {
+    /// Identities waiting for their entity, grouped by what is waiting on them
+    by_source: HashMap<&'static str, Vec<NetworkIdentity>>,
+}
+
+impl UnresolvedIdentities {
+    pub(crate) fn set(&mut self, source: &'static str, identities: Vec<NetworkIdentity>) {
+        if identities.is_empty() {
+            self.by_source.remove(source);
+        } else {
+            self.by_source.insert(source, identities);
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, &[NetworkIdentity])> {
+        self.by_source.iter().map(|(k, v)| (*k, v.as_slice()))
+    }
+
+    pub fn count(&self) -> usize {
+        self.by_source.values().map(Vec::len).sum()
+    }
+}
+
+/// How often the entities the client spawned didn't match what the server spawned for it.
+#[derive(Resource, Default, Debug)]
+pub struct SpawnAuditStats {
+    /// Server answers to mismatching spawn audits
+    pub reconciliations: u32,
+    /// Entities despawned because the server no longer knew about them
+    pub orphans_removed: u32,
+    /// Entities the client was missing and asked the server to spawn again
+    pub missing_requested: u32,
+}
+
+/// Looks up readable descriptions of entities for log messages and debugging commands.
+///
+/// Works on both server and client, as it only reads components that exist on both.
+#[derive(SystemParam)]
+pub struct DebugNames<'w, 's> {
+    asset_server: Res<'w, AssetServer>,
+    query: Query<
+        'w,
+        's,
+        (
+            Option<&'static Name>,
+            Option<&'static PrefabPath>,
+            Option<&'static NetworkScene>,
+            Option<&'static NetworkIdentity>,
+        ),
+    >,
+}
+
+impl<'w, 's> DebugNames<'w, 's> {
+    /// Describes an entity like `"Name" items/knife.ron #12 (3v0)`.
+    ///
+    /// Nothing is formatted until the result is displayed,
+    /// so passing it to a disabled log level costs only the component lookup.
+    pub fn debug_name(&self, entity: Entity) -> DebugName<'_> {
+        let (name, prefab, scene, identity) = self.query.get(entity).unwrap_or_default();
+        DebugName {
+            entity,
+            name,
+            prefab,
+            scene,
+            identity,
+            asset_server: &self.asset_server,
+        }
+    }
+}
+
+/// A lazily formatted description of an entity, created by [`DebugNames::debug_name`].
+pub struct DebugName<'a> {
+    entity: Entity,
+    name: Option<&'a Name>,
+    prefab: Option<&'a PrefabPath>,
+    scene: Option<&'a NetworkScene>,
+    identity: Option<&'a NetworkIdentity>,
+    asset_server: &'a AssetServer,
+}
+
+impl fmt::Display for DebugName<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(name) = self.name {
+            write!(f, "\"{}\" ", name.as_str())?;
+        }
+        if let Some(prefab) = self.prefab {
+            write!(f, "{} ", prefab.0)?;
+        } else if let Some(path) = self
+            .scene
+            .and_then(|scene| self.asset_server.get_handle_path(scene.handle()))
+        {
+            write!(f, "{} ", path.path().display())?;
+        }
+        if let Some(identity) = self.identity {
+            write!(f, "{} ", identity)?;
+        }
+        write!(f, "({:?})", self.entity)
+    }
+}
+
+fn update_connection_stats(
+    client: Res<RenetClient>,
+    time: Res<ClientNetworkTime>,
+    mut stats: ResMut<ConnectionStats>,
+) {
+    let info = client.network_info();
+    *stats = ConnectionStats {
+        rtt: info.rtt,
+        packet_loss: info.packet_loss,
+        bytes_sent_per_second: info.bytes_sent_per_second,
+        bytes_received_per_second: info.bytes_received_per_second,
+        interpolated_tick: time.interpolated_tick(),
+        link_tier: stats.link_tier,
+    };
+}
+
+pub(crate) struct DiagnosticsPlugin;
+
+impl Plugin for DiagnosticsPlugin {
+    fn build(&self, app: &mut App) {
+        if app
+            .world
+            .get_resource::<NetworkManager>()
+            .unwrap()
+            .is_client()
+        {
+            app.init_resource::<ConnectionStats>()
+                .init_resource::<UnresolvedIdentities>()
+                .init_resource::<SpawnAuditStats>()
+                .add_systems(
+                    Update,
+                    update_connection_stats.run_if(resource_exists::<RenetClient>()),
+                );
+        }
+    }
+}
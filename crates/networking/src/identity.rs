@@ -9,7 +9,20 @@ use serde::{Deserialize, Serialize};
 use crate::{visibility::InGrid, NetworkManager};
 
 /// A numeric id which matches on the server and clients
-#[derive(Component, Debug, Copy, Clone, Hash, PartialEq, Eq, Serialize, Deserialize, Reflect)]
+#[derive(
+    Component,
+    Debug,
+    Copy,
+    Clone,
+    Hash,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Serialize,
+    Deserialize,
+    Reflect,
+)]
 #[reflect(Component)]
 pub struct NetworkIdentity(u32);
 
@@ -17,6 +30,21 @@ impl NetworkIdentity {
     pub(crate) fn next(&self) -> Self {
         Self(self.0 + 1)
     }
+
+    /// Creates an identity from its number, for debugging tools that look up entities by id.
+    pub fn from_raw(id: u32) -> Self {
+        Self(id)
+    }
+
+    pub(crate) fn raw(&self) -> u32 {
+        self.0
+    }
+}
+
+impl std::fmt::Display for NetworkIdentity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{}", self.0)
+    }
 }
 
 // Mock implementation for component reflection
@@ -53,6 +81,11 @@ impl NetworkIdentities {
         self.identities.get(&identity).copied()
     }
 
+    /// Iterates over all identities and their entities, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (NetworkIdentity, Entity)> + '_ {
+        self.identities.iter().map(|(i, e)| (*i, *e))
+    }
+
     pub fn get_identity(&self, entity: Entity) -> Option<NetworkIdentity> {
         self.entities.get(&entity).copied()
     }
@@ -1,11 +1,15 @@
 #![allow(clippy::type_complexity)]
 
 pub mod component;
+pub mod diagnostics;
 pub mod identity;
+pub mod loopback;
 pub mod messaging;
+pub mod quality;
 pub mod resource;
 pub mod scene;
 pub mod spawning;
+pub mod testing;
 pub mod time;
 pub mod transform;
 pub mod variable;
@@ -26,6 +30,8 @@ use bevy_renet::{
     RenetClientPlugin, RenetServerPlugin,
 };
 use component::ComponentPlugin;
+use diagnostics::DiagnosticsPlugin;
+use quality::LinkQualityPlugin;
 use resource::ResourcePlugin;
 use scene::ScenePlugin;
 use time::{ClientNetworkTime, ServerNetworkTime, TimePlugin};
@@ -45,14 +51,20 @@ use bevy::{
     utils::{HashMap, Uuid},
 };
 use identity::IdentityPlugin;
-use messaging::{AppExt, Channel, MessageEvent, MessageReceivers, MessageSender, MessagingPlugin};
+use loopback::{
+    LoopbackClientPlugin, LoopbackConnector, LoopbackServerPlugin, LoopbackServerTransport,
+};
+use messaging::{
+    AppExt, Channel, MessageEvent, MessageReceivers, MessageSender, MessageTypes, MessagingPlugin,
+};
 use serde::{Deserialize, Serialize};
 use spawning::SpawningPlugin;
 use transform::TransformPlugin;
 use visibility::VisibilityPlugin;
 
-/// A "unique" id for the protocol used by this application
-const PROTOCOL_ID: u64 = 859058192;
+/// A "unique" id for the protocol used by this application.
+/// Change it when the message envelope or encoding changes, so old clients can't connect.
+pub const PROTOCOL_ID: u64 = 859058194;
 
 #[derive(PartialEq, Eq, Clone, Copy)]
 pub enum NetworkRole {
@@ -97,6 +109,8 @@ pub enum ClientEvent {
 pub enum TargetServer {
     Raw(SocketAddr),
     Token(Box<ConnectToken>),
+    /// A server in the same process
+    Loopback(LoopbackConnector),
 }
 
 impl Display for TargetServer {
@@ -109,6 +123,9 @@ impl Display for TargetServer {
             TargetServer::Token(_) => {
                 write!(f, "(opaque token)")
             }
+            TargetServer::Loopback(_) => {
+                write!(f, "(local server)")
+            }
         }
     }
 }
@@ -125,6 +142,14 @@ pub enum ServerEvent {
     PlayerDisconnected(ConnectionId),
 }
 
+/// Sent to forcibly disconnect a player from the server.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct DisconnectPlayer(pub ConnectionId);
+
+/// Sent to remove a player that isn't connected to the transport, like a player in a simulated replay.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct SimulatedDisconnect(pub ConnectionId);
+
 #[derive(Resource)]
 pub struct UserData {
     pub username: String,
@@ -137,12 +162,16 @@ struct ClientHello {
     // TODO: Put these into the token
     username: String,
     id: Uuid,
+    /// Ids of the message types the client knows, see [`MessageTypes`]
+    message_ids: Vec<u32>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 struct ServerInfo {
     /// How many seconds a server tick takes
     tick_duration_seconds: f32,
+    /// Ids of the message types the server knows, see [`MessageTypes`]
+    message_ids: Vec<u32>,
 }
 
 pub fn create_server(
@@ -167,7 +196,29 @@ pub fn create_server(
     (server, transport)
 }
 
-fn connection_config() -> ConnectionConfig {
+/// Like [`create_server`], but clients in the same process can join as well with the returned connector.
+pub fn create_listen_server(
+    listen_address: SocketAddr,
+    public_address: Option<IpAddr>,
+    authentication: ServerAuthentication,
+) -> (
+    RenetServer,
+    NetcodeServerTransport,
+    LoopbackServerTransport,
+    LoopbackConnector,
+) {
+    let (server, transport) = create_server(listen_address, public_address, authentication);
+    let (loopback, connector) = LoopbackServerTransport::new();
+    (server, transport, loopback, connector)
+}
+
+/// Creates a server without a socket, only clients in the same process can join it.
+pub fn create_local_server() -> (RenetServer, LoopbackServerTransport, LoopbackConnector) {
+    let (loopback, connector) = LoopbackServerTransport::new();
+    (RenetServer::new(connection_config()), loopback, connector)
+}
+
+pub(crate) fn connection_config() -> ConnectionConfig {
     ConnectionConfig {
         client_channels_config: Channel::channels_config(),
         server_channels_config: Channel::channels_config(),
@@ -191,6 +242,11 @@ fn handle_joining_server(
                     next_state.set(ClientState::Joining);
                     info!("Joining server {}", target);
 
+                    if let TargetServer::Loopback(connector) = target {
+                        loopback::join(connector, &mut commands);
+                        continue;
+                    }
+
                     let socket = UdpSocket::bind("0.0.0.0:0").unwrap();
                     let current_time = SystemTime::now()
                         .duration_since(SystemTime::UNIX_EPOCH)
@@ -208,6 +264,7 @@ fn handle_joining_server(
                         TargetServer::Token(token) => ClientAuthentication::Secure {
                             connect_token: *token.clone(),
                         },
+                        TargetServer::Loopback(_) => unreachable!(),
                     };
                     let client = RenetClient::new(connection_config());
                     commands.insert_resource(client);
@@ -221,12 +278,13 @@ fn handle_joining_server(
 }
 
 fn client_send_hello(
-    transport: Res<NetcodeClientTransport>,
+    client: Res<RenetClient>,
     data: Option<Res<UserData>>,
+    types: Res<MessageTypes>,
     mut sender: MessageSender,
     mut last_state: Local<bool>,
 ) {
-    match (transport.is_connected(), *last_state) {
+    match (client.is_connected(), *last_state) {
         // Connected
         (true, false) => *last_state = true,
         // Disconnected
@@ -253,14 +311,22 @@ fn client_send_hello(
         username,
         // 128 bits, trust me bro
         id: Uuid::from_u64_pair(hash, hash),
+        message_ids: types.ids().collect(),
     });
 }
 
+/// Messages the client sends that the server it joined doesn't know.
+/// The server skips them, so features using them won't work.
+#[derive(Resource, Default, Debug)]
+pub struct ServerMissingMessages(pub Vec<&'static str>);
+
 fn client_joined_server(
     mut server_infos: EventReader<MessageEvent<ServerInfo>>,
     mut client_events: EventWriter<ClientEvent>,
     mut next_state: ResMut<NextState<ClientState>>,
     mut network_time: ResMut<ClientNetworkTime>,
+    types: Res<MessageTypes>,
+    mut missing: ResMut<ServerMissingMessages>,
 ) {
     for event in server_infos.iter() {
         next_state.set(ClientState::Connected);
@@ -268,6 +334,13 @@ fn client_joined_server(
         let tick_duration = event.message.tick_duration_seconds;
         network_time.server_tick_seconds = Some(tick_duration);
         info!("Joined server tick={}", tick_duration);
+
+        // Messages the server doesn't know are skipped by it, features using them won't work
+        let server_ids = event.message.message_ids.iter().copied().collect();
+        missing.0 = types.missing_from(&server_ids);
+        for name in missing.0.iter() {
+            warn!("Client sends {} which the server doesn't know", name);
+        }
     }
 }
 
@@ -316,7 +389,7 @@ fn client_handle_tasks(
     }
 }
 
-#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ConnectionId(u64);
 
 impl Display for ConnectionId {
@@ -328,6 +401,9 @@ impl Display for ConnectionId {
 pub struct Player {
     pub id: Uuid,
     pub username: String,
+    /// Messages the server sends that the client of this player doesn't know.
+    /// The client skips them, so features using them won't work.
+    pub missing_messages: Vec<&'static str>,
 }
 
 #[derive(Default, Resource)]
@@ -337,12 +413,18 @@ pub struct Players {
 }
 
 impl Players {
-    fn add(&mut self, connection: ConnectionId, message: &ClientHello) {
+    fn add(
+        &mut self,
+        connection: ConnectionId,
+        message: &ClientHello,
+        missing_messages: Vec<&'static str>,
+    ) {
         self.players.insert(
             connection,
             Player {
                 id: message.id,
                 username: message.username.clone(),
+                missing_messages,
             },
         );
         self.user_ids.insert(message.id, connection);
@@ -376,14 +458,23 @@ fn server_handle_connect(
     mut server_events: EventWriter<ServerEvent>,
     mut sender: MessageSender,
     network_time: Res<ServerNetworkTime>,
+    types: Res<MessageTypes>,
 ) {
     for event in hello_messages.iter() {
+        // Messages the client doesn't know are skipped by it, features using them won't work
+        let client_ids = event.message.message_ids.iter().copied().collect();
+        let missing = types.missing_from(&client_ids);
+        for name in missing.iter() {
+            warn!(connection = ?event.connection, "Server sends {} which the client doesn't know", name);
+        }
+
         // TODO: Auth
         let server_info = ServerInfo {
             tick_duration_seconds: network_time.tick_in_seconds() as f32,
+            message_ids: types.ids().collect(),
         };
         sender.send(&server_info, MessageReceivers::Single(event.connection));
-        players.add(event.connection, &event.message);
+        players.add(event.connection, &event.message, missing);
         server_events.send(ServerEvent::PlayerConnected(event.connection));
 
         let uuid = event.message.id.to_string();
@@ -393,21 +484,38 @@ fn server_handle_connect(
 
 fn server_handle_disconnect(
     mut renet_events: EventReader<bevy_renet::renet::ServerEvent>,
+    mut simulated: EventReader<SimulatedDisconnect>,
     mut players: ResMut<Players>,
     mut server_events: EventWriter<ServerEvent>,
 ) {
-    for event in renet_events.iter() {
-        if let bevy_renet::renet::ServerEvent::ClientDisconnected { client_id: id, .. } = event {
-            let connection = ConnectionId(*id);
-            if let Some(player) = players.remove(connection) {
-                let uuid = player.id.to_string();
-                info!(connection = ?connection, id = uuid.as_str(), "Player disconnected");
-                server_events.send(ServerEvent::PlayerDisconnected(connection));
+    let disconnected = renet_events
+        .iter()
+        .filter_map(|event| match event {
+            bevy_renet::renet::ServerEvent::ClientDisconnected { client_id: id, .. } => {
+                Some(ConnectionId(*id))
             }
+            _ => None,
+        })
+        .chain(simulated.iter().map(|event| event.0));
+    for connection in disconnected {
+        if let Some(player) = players.remove(connection) {
+            let uuid = player.id.to_string();
+            info!(connection = ?connection, id = uuid.as_str(), "Player disconnected");
+            server_events.send(ServerEvent::PlayerDisconnected(connection));
         }
     }
 }
 
+fn server_disconnect_players(
+    mut events: EventReader<DisconnectPlayer>,
+    mut server: ResMut<RenetServer>,
+) {
+    for DisconnectPlayer(connection) in events.iter() {
+        info!(connection = ?connection, "Disconnecting player");
+        server.disconnect(connection.0);
+    }
+}
+
 fn report_errors(mut events: EventReader<NetcodeTransportError>) {
     for error in events.iter() {
         error!(?error, "Network error");
@@ -446,6 +554,9 @@ pub enum NetworkSet {
     ServerVisibility,
     ClientSpawn,
     ClientApply,
+    /// Networked components are serialized for this tick.
+    /// Systems changing networked state must run before this, changes made later are only sent next tick.
+    /// Debug builds log an error when a networked component is changed after this set.
     ServerWrite,
     SendOutgoing,
     ServerSyncPhysics,
@@ -457,8 +568,12 @@ struct NetworkUpdate;
 impl Plugin for NetworkingPlugin {
     fn build(&self, app: &mut App) {
         match self.role {
-            NetworkRole::Server => app.add_plugins((RenetServerPlugin, NetcodeServerPlugin)),
-            NetworkRole::Client => app.add_plugins((RenetClientPlugin, NetcodeClientPlugin)),
+            NetworkRole::Server => {
+                app.add_plugins((RenetServerPlugin, NetcodeServerPlugin, LoopbackServerPlugin))
+            }
+            NetworkRole::Client => {
+                app.add_plugins((RenetClientPlugin, NetcodeClientPlugin, LoopbackClientPlugin))
+            }
         };
 
         app.insert_resource(NetworkManager { role: self.role })
@@ -483,8 +598,8 @@ impl Plugin for NetworkingPlugin {
                     .chain(),
             )
             .add_plugins(MessagingPlugin)
-            .add_network_message::<ClientHello>()
-            .add_network_message::<ServerInfo>()
+            .add_network_message::<ClientHello>("ClientHello")
+            .add_network_message::<ServerInfo>("ServerInfo")
             .add_plugins((
                 TimePlugin,
                 IdentityPlugin,
@@ -494,6 +609,8 @@ impl Plugin for NetworkingPlugin {
                 ResourcePlugin,
                 TransformPlugin,
                 ScenePlugin,
+                DiagnosticsPlugin,
+                LinkQualityPlugin,
             ))
             .add_systems(
                 Update,
@@ -502,6 +619,7 @@ impl Plugin for NetworkingPlugin {
 
         if self.role == NetworkRole::Client {
             app.add_state::<ClientState>()
+                .init_resource::<ServerMissingMessages>()
                 .add_event::<ClientEvent>()
                 .add_event::<ClientTask>()
                 .configure_sets(
@@ -519,7 +637,7 @@ impl Plugin for NetworkingPlugin {
                     (
                         handle_joining_server,
                         client_joined_server,
-                        client_send_hello.run_if(resource_exists::<NetcodeClientTransport>()),
+                        client_send_hello.run_if(has_client()),
                         (
                             client_handle_join_error.run_if(in_state(ClientState::Joining)),
                             client_handle_disconnect.run_if(in_state(ClientState::Connected)),
@@ -533,8 +651,17 @@ impl Plugin for NetworkingPlugin {
                 );
         } else {
             app.add_event::<ServerEvent>()
+                .add_event::<DisconnectPlayer>()
+                .add_event::<SimulatedDisconnect>()
                 .init_resource::<Players>()
-                .add_systems(Update, (server_handle_connect, server_handle_disconnect));
+                .add_systems(
+                    Update,
+                    (
+                        server_handle_connect,
+                        server_handle_disconnect,
+                        server_disconnect_players,
+                    ),
+                );
         }
     }
 }
@@ -0,0 +1,399 @@
+//! A transport for clients in the same process as the server, without sockets or encryption.
+//! Packets are passed through channels and can be delayed or dropped to simulate a bad connection.
+
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+use bevy::prelude::*;
+use bevy_renet::{
+    renet::{RenetClient, RenetServer},
+    transport::NetcodeServerPlugin,
+    RenetClientPlugin, RenetServerPlugin,
+};
+use flume::{Receiver, Sender, TryRecvError};
+
+use crate::{messaging::ReadMessagesSet, ClientEvent, ClientState, NetworkSet};
+
+/// Client ids of local clients start here, far away from the ids netcode hands out
+const FIRST_LOOPBACK_CLIENT_ID: u64 = u64::MAX / 2;
+
+pub(crate) struct LoopbackServerPlugin;
+
+impl Plugin for LoopbackServerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PreUpdate,
+            receive_server
+                .run_if(resource_exists::<LoopbackServerTransport>())
+                .after(RenetServerPlugin::update_system)
+                .before(ReadMessagesSet::ReadChannel),
+        )
+        .add_systems(
+            PostUpdate,
+            // Netcode takes the packets of every client, including local ones, so they go first
+            send_server
+                .run_if(resource_exists::<LoopbackServerTransport>())
+                .after(NetworkSet::SendOutgoing)
+                .before(NetcodeServerPlugin::send_packets),
+        );
+    }
+}
+
+pub(crate) struct LoopbackClientPlugin;
+
+impl Plugin for LoopbackClientPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PreUpdate,
+            receive_client
+                .run_if(resource_exists::<LoopbackClientTransport>())
+                .after(RenetClientPlugin::update_system)
+                .before(ReadMessagesSet::ReadChannel),
+        )
+        .add_systems(
+            PostUpdate,
+            send_client
+                .run_if(resource_exists::<LoopbackClientTransport>())
+                .after(NetworkSet::SendOutgoing),
+        );
+    }
+}
+
+/// Simulated network conditions of a local connection, applied in both directions.
+/// The default passes every packet on immediately.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct LinkConditions {
+    /// Time every packet takes to arrive
+    pub latency: Duration,
+    /// Longest extra random delay added to each packet. Packets can arrive out of order.
+    pub jitter: Duration,
+    /// Fraction of packets that are dropped, between 0 and 1
+    pub loss: f32,
+    /// Seed for which packets are delayed and dropped, so a run can be repeated
+    pub seed: u64,
+}
+
+struct LoopbackPacket {
+    deliver_at: Instant,
+    payload: Vec<u8>,
+}
+
+/// One end of a local connection.
+struct LinkEnd {
+    sender: Sender<LoopbackPacket>,
+    receiver: Receiver<LoopbackPacket>,
+    /// Packets received, but not due yet
+    pending: Vec<LoopbackPacket>,
+    conditions: LinkConditions,
+    rng: u64,
+    /// If the other end hung up
+    closed: bool,
+}
+
+impl LinkEnd {
+    fn pair(conditions: LinkConditions) -> (Self, Self) {
+        let (to_server, from_client) = flume::unbounded();
+        let (to_client, from_server) = flume::unbounded();
+        let end = |sender, receiver, seed: u64| Self {
+            sender,
+            receiver,
+            pending: Vec::new(),
+            conditions,
+            // Xorshift gets stuck on zero
+            rng: seed | 1,
+            closed: false,
+        };
+        (
+            end(to_server, from_server, conditions.seed),
+            end(to_client, from_client, conditions.seed.rotate_left(32)),
+        )
+    }
+
+    /// Random number between 0 and 1
+    fn random(&mut self) -> f32 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        (self.rng >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    fn send(&mut self, payload: Vec<u8>) {
+        if self.conditions.loss > 0.0 && self.random() < self.conditions.loss {
+            return;
+        }
+        let jitter = self.conditions.jitter.mul_f32(self.random());
+        let packet = LoopbackPacket {
+            deliver_at: Instant::now() + self.conditions.latency + jitter,
+            payload,
+        };
+        if self.sender.send(packet).is_err() {
+            self.closed = true;
+        }
+    }
+
+    /// Takes the packets that have arrived by now.
+    fn receive(&mut self) -> Vec<Vec<u8>> {
+        loop {
+            match self.receiver.try_recv() {
+                Ok(packet) => self.pending.push(packet),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.closed = true;
+                    break;
+                }
+            }
+        }
+
+        let now = Instant::now();
+        let mut due: Vec<_> = Vec::new();
+        self.pending.retain_mut(|packet| {
+            if packet.deliver_at > now {
+                return true;
+            }
+            due.push((packet.deliver_at, std::mem::take(&mut packet.payload)));
+            false
+        });
+        due.sort_by_key(|(deliver_at, _)| *deliver_at);
+        due.into_iter().map(|(_, payload)| payload).collect()
+    }
+}
+
+/// Connects clients in the same process to a server created with
+/// [`create_local_server`](crate::create_local_server) or [`create_listen_server`](crate::create_listen_server).
+/// Joined with [`TargetServer::Loopback`](crate::TargetServer::Loopback).
+#[derive(Clone)]
+pub struct LoopbackConnector {
+    server: Sender<LinkEnd>,
+    conditions: LinkConditions,
+}
+
+impl LoopbackConnector {
+    /// Simulates the given network conditions on connections made with this connector.
+    pub fn with_conditions(mut self, conditions: LinkConditions) -> Self {
+        self.conditions = conditions;
+        self
+    }
+
+    /// If the server is gone the link is dropped right away, and the client sees it as closed.
+    fn connect(&self) -> LoopbackClientTransport {
+        let (client, server) = LinkEnd::pair(self.conditions);
+        let _ = self.server.send(server);
+        LoopbackClientTransport { link: client }
+    }
+}
+
+impl fmt::Debug for LoopbackConnector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LoopbackConnector")
+            .field("conditions", &self.conditions)
+            .finish()
+    }
+}
+
+/// Connectors are the same if they connect to the same server.
+impl PartialEq for LoopbackConnector {
+    fn eq(&self, other: &Self) -> bool {
+        self.server.same_channel(&other.server)
+    }
+}
+
+impl Eq for LoopbackConnector {}
+
+/// The server side of local connections. Works next to the netcode transport.
+#[derive(Resource)]
+pub struct LoopbackServerTransport {
+    incoming: Receiver<LinkEnd>,
+    clients: Vec<(u64, LinkEnd)>,
+    next_id: u64,
+}
+
+impl LoopbackServerTransport {
+    pub fn new() -> (Self, LoopbackConnector) {
+        let (sender, incoming) = flume::unbounded();
+        let transport = Self {
+            incoming,
+            clients: Vec::new(),
+            next_id: FIRST_LOOPBACK_CLIENT_ID,
+        };
+        let connector = LoopbackConnector {
+            server: sender,
+            conditions: LinkConditions::default(),
+        };
+        (transport, connector)
+    }
+}
+
+fn receive_server(mut transport: ResMut<LoopbackServerTransport>, mut server: ResMut<RenetServer>) {
+    let transport = &mut *transport;
+    while let Ok(link) = transport.incoming.try_recv() {
+        let id = transport.next_id;
+        transport.next_id += 1;
+        server.add_connection(id);
+        transport.clients.push((id, link));
+        debug!(client_id = id, "Local client connected");
+    }
+
+    let disconnected = server.disconnections_id();
+    transport.clients.retain_mut(|(id, link)| {
+        for payload in link.receive() {
+            if let Err(err) = server.process_packet_from(&payload, *id) {
+                error!(client_id = *id, error = ?err, "Error processing local packet");
+            }
+        }
+        // Dropping the link tells the client about the disconnect
+        if link.closed || disconnected.contains(id) {
+            server.remove_connection(*id);
+            debug!(client_id = *id, "Local client disconnected");
+            return false;
+        }
+        true
+    });
+}
+
+fn send_server(mut transport: ResMut<LoopbackServerTransport>, mut server: ResMut<RenetServer>) {
+    for (id, link) in transport.clients.iter_mut() {
+        let Ok(packets) = server.get_packets_to_send(*id) else {
+            continue;
+        };
+        for payload in packets {
+            link.send(payload);
+        }
+    }
+}
+
+/// The client side of a local connection.
+#[derive(Resource)]
+pub struct LoopbackClientTransport {
+    link: LinkEnd,
+}
+
+/// Starts joining a local server.
+pub(crate) fn join(connector: &LoopbackConnector, commands: &mut Commands) {
+    let transport = connector.connect();
+    let mut client = RenetClient::new(crate::connection_config());
+    // There is no handshake, the server accepts the connection right away
+    client.set_connected();
+    commands.insert_resource(client);
+    commands.insert_resource(transport);
+}
+
+fn receive_client(
+    mut transport: ResMut<LoopbackClientTransport>,
+    mut client: ResMut<RenetClient>,
+    state: Res<State<ClientState>>,
+    mut client_events: EventWriter<ClientEvent>,
+    mut next_state: ResMut<NextState<ClientState>>,
+    mut commands: Commands,
+) {
+    for payload in transport.link.receive() {
+        client.process_packet(&payload);
+    }
+
+    let reason = if transport.link.closed {
+        "The local server closed the connection"
+    } else if client.is_disconnected() {
+        "Left the local server"
+    } else {
+        return;
+    };
+    next_state.set(ClientState::Initial);
+    client_events.send(match state.get() {
+        ClientState::Joining => ClientEvent::JoinFailed(reason.into()),
+        _ => ClientEvent::Disconnected(reason.into()),
+    });
+    commands.remove_resource::<RenetClient>();
+    commands.remove_resource::<LoopbackClientTransport>();
+}
+
+fn send_client(mut transport: ResMut<LoopbackClientTransport>, mut client: ResMut<RenetClient>) {
+    for payload in client.get_packets_to_send() {
+        transport.link.send(payload);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn send_all(end: &mut LinkEnd, count: u32) {
+        for i in 0..count {
+            end.send(i.to_le_bytes().to_vec());
+        }
+    }
+
+    #[test]
+    fn passes_everything_by_default() {
+        let (mut client, mut server) = LinkEnd::pair(LinkConditions::default());
+        send_all(&mut server, 100);
+        let received = client.receive();
+        assert_eq!(received.len(), 100);
+        // Without jitter packets keep their order
+        for (i, payload) in received.iter().enumerate() {
+            assert_eq!(payload, &(i as u32).to_le_bytes());
+        }
+    }
+
+    #[test]
+    fn drops_the_configured_fraction() {
+        let conditions = LinkConditions {
+            loss: 0.25,
+            seed: 7,
+            ..Default::default()
+        };
+        let (mut client, mut server) = LinkEnd::pair(conditions);
+        send_all(&mut server, 1000);
+        let received = client.receive().len();
+        assert!((650..=850).contains(&received), "received {}", received);
+
+        let (mut client, mut server) = LinkEnd::pair(LinkConditions {
+            loss: 1.0,
+            ..Default::default()
+        });
+        send_all(&mut server, 100);
+        assert!(client.receive().is_empty());
+    }
+
+    #[test]
+    fn same_seed_drops_same_packets() {
+        let conditions = LinkConditions {
+            loss: 0.5,
+            seed: 42,
+            ..Default::default()
+        };
+        let run = || {
+            let (mut client, mut server) = LinkEnd::pair(conditions);
+            send_all(&mut server, 200);
+            client.receive()
+        };
+        assert_eq!(run(), run());
+    }
+
+    #[test]
+    fn delays_packets_by_latency() {
+        let conditions = LinkConditions {
+            latency: Duration::from_millis(50),
+            ..Default::default()
+        };
+        let (mut client, mut server) = LinkEnd::pair(conditions);
+        send_all(&mut server, 10);
+        assert!(client.receive().is_empty());
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(client.receive().len(), 10);
+    }
+
+    #[test]
+    fn jitter_delays_within_bounds() {
+        let conditions = LinkConditions {
+            jitter: Duration::from_millis(30),
+            seed: 3,
+            ..Default::default()
+        };
+        let (mut client, mut server) = LinkEnd::pair(conditions);
+        send_all(&mut server, 50);
+        std::thread::sleep(Duration::from_millis(40));
+        assert_eq!(client.receive().len(), 50);
+    }
+}
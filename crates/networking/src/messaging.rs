@@ -1,4 +1,9 @@
-use std::{any::TypeId, time::Duration};
+use std::{
+    any::TypeId,
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    time::Duration,
+};
 
 use bevy::{
     ecs::system::SystemParam,
@@ -10,7 +15,7 @@ use bincode::Options;
 use bytes::{BufMut, Bytes};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
-use crate::{ConnectionId, NetworkManager, NetworkSet, Players};
+use crate::{ConnectionId, DisconnectPlayer, NetworkManager, NetworkSet, Players, ServerEvent};
 
 /// Serialize data once and allow it to be shared in multiple places without reallocating.
 pub(crate) fn serialize_once<T: Serialize>(data: &T) -> Bytes {
@@ -29,37 +34,163 @@ where
     options.deserialize(data)
 }
 
-/// Assigns packet numbers to types uniquely and allows to lookup the id for a specific type.
+/// Deserializes data encoded with [`bincode::serialize`], failing instead of reading or allocating more than `limit` bytes.
+/// Length prefixes are checked against the limit before allocating, so hostile payloads can't claim huge collections.
+pub fn deserialize_limited<T>(data: &[u8], limit: u64) -> bincode::Result<T>
+where
+    T: for<'a> Deserialize<'a>,
+{
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .allow_trailing_bytes()
+        .with_limit(limit)
+        .deserialize(data)
+}
+
+/// Largest serialized size of a message sent to the server, unless registered with a different limit
+pub const DEFAULT_MAX_MESSAGE_SIZE: u64 = 16 * 1024;
+/// Bytes used by the message envelope around the content
+const MESSAGE_HEADER_SIZE: u64 = 16;
+/// How many invalid messages a client can send before being disconnected
+const MAX_STRIKES: u32 = 5;
+
+/// Message ids of registered types and allows to lookup the id for a specific type.
 /// Used in packet registration, serialization and deserialization.
+///
+/// Ids are derived from the name a message is registered with instead of the registration order,
+/// so peers that don't register the same messages, or not in the same order, still agree on the ids of the others.
 #[derive(Default, Resource)]
 pub struct MessageTypes {
-    last_type: u16,
-    types: HashMap<TypeId, u16>,
+    types: HashMap<TypeId, u32>,
+    /// Message names by id, used to report protocol mismatches
+    names: HashMap<u32, &'static str>,
+    /// Largest serialized size accepted from clients for each message id
+    max_sizes: HashMap<u32, u64>,
+    /// Names that got the id of an already registered message, and that message
+    collisions: Vec<(&'static str, &'static str)>,
 }
 
 impl MessageTypes {
-    fn register<T: 'static>(&mut self) -> u16 {
-        let type_id = self.last_type + 1;
-        self.last_type = type_id;
+    /// Registers a type, returning `None` if it already was or its id is taken.
+    fn register<T: 'static>(&mut self, name: &'static str, max_size: u64) -> Option<u32> {
+        if let Some(id) = self.types.get(&TypeId::of::<T>()) {
+            if self.names[id] != name {
+                error!(
+                    "Message already registered as {}, ignoring the name {}",
+                    self.names[id], name
+                );
+            }
+            return None;
+        }
 
-        self.types.insert(TypeId::of::<T>(), type_id);
-        trace!(type_id = ?TypeId::of::<T>(), message_id = type_id, "Registered message type {}", std::any::type_name::<T>());
+        let message_id = message_id(name);
+        if let Some(&existing) = self.names.get(&message_id) {
+            error!(
+                message_id,
+                "Message {} has the same id as {} and can't be used, rename one of them",
+                name,
+                existing
+            );
+            self.collisions.push((name, existing));
+            return None;
+        }
+
+        self.types.insert(TypeId::of::<T>(), message_id);
+        self.names.insert(message_id, name);
+        self.max_sizes.insert(message_id, max_size);
+        trace!(type_id = ?TypeId::of::<T>(), message_id, max_size, "Registered message type {}", name);
+
+        Some(message_id)
+    }
+
+    /// Messages that weren't registered because their id was taken, and the messages that have the id.
+    /// Tests check this is empty, so a new message with a colliding name is renamed before it's merged.
+    pub fn collisions(&self) -> &[(&'static str, &'static str)] {
+        &self.collisions
+    }
+
+    /// The largest size a client message with this id may have, if the id is registered.
+    pub fn max_size(&self, message_id: u32) -> Option<u64> {
+        self.max_sizes.get(&message_id).copied()
+    }
+
+    /// The name of a message id, if it is registered.
+    pub fn name(&self, message_id: u32) -> Option<&'static str> {
+        self.names.get(&message_id).copied()
+    }
 
-        type_id
+    /// Ids of every registered message type.
+    pub fn ids(&self) -> impl Iterator<Item = u32> + '_ {
+        self.names.keys().copied()
+    }
+
+    /// Names of the registered message types that aren't in `ids`, sorted.
+    /// Used to tell which messages a peer doesn't know.
+    pub fn missing_from(&self, ids: &HashSet<u32>) -> Vec<&'static str> {
+        let mut missing: Vec<_> = self
+            .names
+            .iter()
+            .filter(|(id, _)| !ids.contains(id))
+            .map(|(_, &name)| name)
+            .collect();
+        missing.sort_unstable();
+        missing
+    }
+
+    /// A hash of all registered message types. Peers with a different hash don't know the same messages.
+    pub fn protocol_hash(&self) -> u64 {
+        let mut names: Vec<_> = self.names.values().collect();
+        names.sort_unstable();
+        let mut hasher = DefaultHasher::new();
+        names.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// The largest size any client message may have.
+    fn largest_size(&self) -> u64 {
+        self.max_sizes.values().copied().max().unwrap_or(0)
+    }
+}
+
+/// The id of a message, a 32 bit FNV-1a hash of the name it's registered with.
+/// Unlike [`DefaultHasher`] the result is the same on every platform and Rust version.
+fn message_id(name: &str) -> u32 {
+    name.bytes().fold(0x811c_9dc5, |hash: u32, byte| {
+        (hash ^ byte as u32).wrapping_mul(0x0100_0193)
+    })
+}
+
+/// Messages received with an id this peer doesn't know, like messages of a feature it was built without.
+/// They are skipped, the messages after them are read as usual.
+#[derive(Resource, Default)]
+pub struct UnknownMessages {
+    /// How many messages were skipped for each id
+    pub counts: HashMap<u32, u32>,
+}
+
+impl UnknownMessages {
+    /// Counts a skipped message. Returns true the first time an id is seen.
+    fn count(&mut self, message_id: u32) -> bool {
+        let count = self.counts.entry(message_id).or_default();
+        *count += 1;
+        *count == 1
     }
 }
 
 enum MessageKind {
     Reliable,
     Unreliable,
+    /// Reliable, but on its own channel so large transfers don't hold up other messages
+    Bulk,
 }
 
-/// A message received from a peer
-#[derive(Event)]
-struct IncomingMessage {
-    connection: ConnectionId,
-    type_id: u16,
-    content: Bytes,
+/// A message received from a peer, before its content is read.
+/// Replays record these and send them again to simulate what players did.
+#[derive(Event, Serialize, Deserialize, Clone, Debug)]
+pub struct IncomingMessage {
+    pub connection: ConnectionId,
+    pub type_id: u32,
+    pub content: Bytes,
 }
 
 /// Specifies to which peers a message should be sent
@@ -76,7 +207,7 @@ pub enum MessageReceivers {
 
 /// A message that will be sent to a single or multiple peers
 pub struct OutboundMessage {
-    type_id: u16,
+    type_id: u32,
     content: Bytes,
     receivers: MessageReceivers,
     kind: MessageKind,
@@ -87,7 +218,7 @@ pub struct OutboundMessage {
 #[derive(Serialize, Deserialize, Debug, Clone)]
 struct NetworkMessage {
     /// The id registered in [`MessageTypes`]
-    type_id: u16,
+    type_id: u32,
     /// The serialized content of the message
     content: Bytes,
 }
@@ -105,6 +236,44 @@ impl From<OutboundMessage> for NetworkMessage {
 #[derive(Serialize, Deserialize, Debug, Clone)]
 struct UnreliableNetworkMessage(pub NetworkMessage);
 
+/// Sent when a client sent a message that could not be read or contained invalid values.
+/// Clients that send too many of these are disconnected.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct InvalidMessage {
+    pub connection: ConnectionId,
+    pub reason: &'static str,
+}
+
+/// Values received from a peer that may contain NaN or infinite floats.
+/// Handlers of messages with float fields should reject messages that aren't finite.
+pub trait Finite {
+    fn is_finite(&self) -> bool;
+}
+
+impl Finite for f32 {
+    fn is_finite(&self) -> bool {
+        f32::is_finite(*self)
+    }
+}
+
+impl Finite for Vec2 {
+    fn is_finite(&self) -> bool {
+        Vec2::is_finite(*self)
+    }
+}
+
+impl Finite for Vec3 {
+    fn is_finite(&self) -> bool {
+        Vec3::is_finite(*self)
+    }
+}
+
+impl Finite for Quat {
+    fn is_finite(&self) -> bool {
+        Quat::is_finite(*self)
+    }
+}
+
 // A typed event sent for every received message
 #[derive(Clone, Copy, Event)]
 pub struct MessageEvent<T> {
@@ -119,39 +288,71 @@ pub struct InternalSenderRes {
 }
 
 pub trait AppExt {
-    fn add_network_message<T>(&mut self) -> &mut Self
+    fn add_network_message<T>(&mut self, name: &'static str) -> &mut Self
+    where
+        T: 'static + Serialize + DeserializeOwned + Send + Sync;
+
+    fn add_network_message_with_limit<T>(&mut self, name: &'static str, max_size: u64) -> &mut Self
     where
         T: 'static + Serialize + DeserializeOwned + Send + Sync;
 }
 
 impl AppExt for App {
     /// Registers a message type which can be sent over the network.
+    /// Clients can send messages up to [`DEFAULT_MAX_MESSAGE_SIZE`] bytes of this type.
+    /// Server and client don't need to register the same messages, messages the other side doesn't know are skipped.
+    ///
+    /// The message id is derived from `name`, so it must be unique and shouldn't change when the type is renamed.
+    /// Peers with a different name for a message don't understand each other.
     ///
     /// Messages can be read from an [`EventReader<MessageEvent<T>>`] and sent using a [`MessageSender`].
-    fn add_network_message<T>(&mut self) -> &mut Self
+    fn add_network_message<T>(&mut self, name: &'static str) -> &mut Self
+    where
+        T: 'static + Serialize + DeserializeOwned + Send + Sync,
+    {
+        self.add_network_message_with_limit::<T>(name, DEFAULT_MAX_MESSAGE_SIZE)
+    }
+
+    /// Registers a message type which can be sent over the network,
+    /// rejecting messages from clients that are larger than `max_size` bytes when serialized.
+    fn add_network_message_with_limit<T>(&mut self, name: &'static str, max_size: u64) -> &mut Self
     where
         T: 'static + Serialize + DeserializeOwned + Send + Sync,
     {
+        let is_server = self
+            .world
+            .get_resource::<NetworkManager>()
+            .unwrap()
+            .is_server();
         let mut types = self.world.get_resource_mut::<MessageTypes>().unwrap();
-        let type_id = types.register::<T>();
+        // Plugins sharing a message can both register it
+        let Some(type_id) = types.register::<T>(name, max_size) else {
+            return self;
+        };
+        // The server is trusted, so only client messages are limited
+        let limit = if is_server { max_size } else { u64::MAX };
 
         let packet_reader =
             move |mut raw_events: EventReader<IncomingMessage>,
-                  mut events: EventWriter<MessageEvent<T>>| {
+                  mut events: EventWriter<MessageEvent<T>>,
+                  mut invalid: EventWriter<InvalidMessage>| {
                 for event in raw_events.iter() {
                     // TODO: don't run a system for every kind of message
                     if event.type_id != type_id {
                         continue;
                     }
 
-                    let message: T = match bincode::deserialize(&event.content) {
+                    let message: T = match deserialize_limited(&event.content, limit) {
                         Ok(m) => m,
                         Err(_) => {
-                            // TODO: Disconnect after X invalid packets?
                             warn!(
                                 "Received malformed packet from connection={} message_id={}",
                                 event.connection, event.type_id
                             );
+                            invalid.send(InvalidMessage {
+                                connection: event.connection,
+                                reason: "malformed message",
+                            });
                             continue;
                         }
                     };
@@ -203,6 +404,15 @@ impl<'w, 's> MessageSender<'w, 's> {
         self.send_internal(message, receivers, MessageKind::Unreliable, 0);
     }
 
+    /// Sends a message over the bulk channel, which doesn't block reliable messages while it's busy.
+    /// Used for large transfers like files, which should be split into smaller messages and paced by the sender.
+    pub fn send_bulk<T>(&mut self, message: &T, receivers: MessageReceivers)
+    where
+        T: 'static + Serialize + Send + Sync,
+    {
+        self.send_internal(message, receivers, MessageKind::Bulk, 0);
+    }
+
     fn send_internal<T>(
         &mut self,
         message: &T,
@@ -212,13 +422,15 @@ impl<'w, 's> MessageSender<'w, 's> {
     ) where
         T: 'static + Serialize + Send + Sync,
     {
-        let type_id = self
-            .types
-            .types
-            .get(&TypeId::of::<T>())
-            .expect("Tried to send unregistered message type");
+        let Some(&type_id) = self.types.types.get(&TypeId::of::<T>()) else {
+            error!(
+                "Tried to send unregistered message type {}",
+                std::any::type_name::<T>()
+            );
+            return;
+        };
         let event = OutboundMessage {
-            type_id: *type_id,
+            type_id,
             content: bincode::serialize(message)
                 .expect("Unable to serialize message")
                 .into(),
@@ -240,6 +452,7 @@ pub(crate) enum Channel {
     DefaultUnreliable,
     Timing,
     Transforms,
+    Bulk,
 }
 
 impl Channel {
@@ -249,6 +462,7 @@ impl Channel {
             Self::DefaultUnreliable => 1,
             Self::Timing => 2,
             Self::Transforms => 3,
+            Self::Bulk => 4,
         }
     }
 
@@ -276,34 +490,94 @@ impl Channel {
                 send_type: SendType::Unreliable,
                 max_memory_usage_bytes: 5 * 1024 * 1024,
             },
+            ChannelConfig {
+                channel_id: Self::Bulk.id(),
+                send_type: SendType::ReliableOrdered {
+                    resend_time: Duration::from_millis(300),
+                },
+                max_memory_usage_bytes: 16 * 1024 * 1024,
+            },
         ]
     }
 }
 
 /// Reads from the network channels and sends message events
-fn read_channel_server(mut events: EventWriter<IncomingMessage>, mut server: ResMut<RenetServer>) {
+fn read_channel_server(
+    mut events: EventWriter<IncomingMessage>,
+    mut invalid: EventWriter<InvalidMessage>,
+    mut server: ResMut<RenetServer>,
+    types: Res<MessageTypes>,
+    mut unknown: ResMut<UnknownMessages>,
+) {
+    let envelope_limit = types.largest_size() + MESSAGE_HEADER_SIZE;
     'clients: for client_id in server.clients_id().into_iter() {
-        for channel_id in [Channel::Default.id(), Channel::DefaultUnreliable.id()] {
+        let connection = ConnectionId(client_id);
+        for channel_id in [
+            Channel::Default.id(),
+            Channel::DefaultUnreliable.id(),
+            Channel::Bulk.id(),
+        ] {
             while let Some(message) = server.receive_message(client_id, channel_id) {
-                let message: NetworkMessage = match bincode::deserialize(&message) {
+                let message: NetworkMessage = match deserialize_limited(&message, envelope_limit) {
                     Ok(m) => m,
                     Err(_) => {
                         warn!(client_id, "Invalid message from client");
+                        invalid.send(InvalidMessage {
+                            connection,
+                            reason: "invalid envelope",
+                        });
                         continue 'clients;
                     }
                 };
+
+                // Check the size before the content is deserialized
+                let Some(max_size) = types.max_size(message.type_id) else {
+                    // Clients can be built with features the server doesn't have
+                    if unknown.count(message.type_id) {
+                        warn!(
+                            client_id,
+                            message_id = message.type_id,
+                            "Skipping unknown message type from client"
+                        );
+                    }
+                    continue;
+                };
+                if message.content.len() as u64 > max_size {
+                    warn!(
+                        client_id,
+                        message_id = message.type_id,
+                        size = message.content.len(),
+                        max_size,
+                        "Oversized message from client"
+                    );
+                    invalid.send(InvalidMessage {
+                        connection,
+                        reason: "oversized message",
+                    });
+                    continue;
+                }
+
                 events.send(IncomingMessage {
                     type_id: message.type_id,
                     content: message.content,
-                    connection: ConnectionId(client_id),
+                    connection,
                 });
             }
         }
     }
 }
 
-fn read_channel_client(mut events: EventWriter<IncomingMessage>, mut client: ResMut<RenetClient>) {
-    for channel_id in [Channel::Default.id(), Channel::DefaultUnreliable.id()] {
+fn read_channel_client(
+    mut events: EventWriter<IncomingMessage>,
+    mut client: ResMut<RenetClient>,
+    types: Res<MessageTypes>,
+    mut unknown: ResMut<UnknownMessages>,
+) {
+    for channel_id in [
+        Channel::Default.id(),
+        Channel::DefaultUnreliable.id(),
+        Channel::Bulk.id(),
+    ] {
         while let Some(message) = client.receive_message(channel_id) {
             let message: NetworkMessage = match bincode::deserialize(&message) {
                 Ok(m) => m,
@@ -312,6 +586,15 @@ fn read_channel_client(mut events: EventWriter<IncomingMessage>, mut client: Res
                     continue;
                 }
             };
+            if types.name(message.type_id).is_none() {
+                if unknown.count(message.type_id) {
+                    warn!(
+                        message_id = message.type_id,
+                        "Skipping unknown message type from server"
+                    );
+                }
+                continue;
+            }
             events.send(IncomingMessage {
                 type_id: message.type_id,
                 content: message.content,
@@ -373,9 +656,13 @@ fn send_message_to(
     let channel = match kind {
         MessageKind::Reliable => Channel::Default,
         MessageKind::Unreliable => Channel::DefaultUnreliable,
+        MessageKind::Bulk => Channel::Bulk,
     };
     for id in receivers {
-        server.send_message(id.0, channel.id(), serialized.clone());
+        // Players can leave before their messages are sent, and simulated players never connect
+        if server.is_connected(id.0) {
+            server.send_message(id.0, channel.id(), serialized.clone());
+        }
     }
 }
 
@@ -387,6 +674,7 @@ fn send_outbound_messages_client(
         let channel = match outbound.kind {
             MessageKind::Reliable => Channel::Default,
             MessageKind::Unreliable => Channel::DefaultUnreliable,
+            MessageKind::Bulk => Channel::Bulk,
         };
 
         let message: NetworkMessage = outbound.into();
@@ -394,8 +682,52 @@ fn send_outbound_messages_client(
     }
 }
 
+/// How many invalid messages each client has sent.
+#[derive(Resource, Default)]
+struct MessageStrikes {
+    strikes: HashMap<ConnectionId, u32>,
+}
+
+/// Counts invalid messages and disconnects clients that keep sending them.
+fn punish_invalid_messages(
+    mut events: EventReader<InvalidMessage>,
+    mut server_events: EventReader<ServerEvent>,
+    mut strikes: ResMut<MessageStrikes>,
+    mut disconnect: EventWriter<DisconnectPlayer>,
+    players: Res<Players>,
+) {
+    for event in server_events.iter() {
+        if let ServerEvent::PlayerDisconnected(connection) = event {
+            strikes.strikes.remove(connection);
+        }
+    }
+
+    for event in events.iter() {
+        let count = strikes.strikes.entry(event.connection).or_default();
+        *count += 1;
+
+        let player = players.get(event.connection);
+        let username = player.map(|p| p.username.as_str()).unwrap_or("unknown");
+        let id = player.map(|p| p.id.to_string()).unwrap_or_default();
+        warn!(
+            connection = ?event.connection,
+            username,
+            id = id.as_str(),
+            reason = event.reason,
+            strikes = *count,
+            "Invalid message from client"
+        );
+
+        if *count >= MAX_STRIKES {
+            warn!(connection = ?event.connection, username, id = id.as_str(), "Disconnecting client for sending invalid messages");
+            strikes.strikes.remove(&event.connection);
+            disconnect.send(DisconnectPlayer(event.connection));
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, SystemSet)]
-pub(crate) enum ReadMessagesSet {
+pub enum ReadMessagesSet {
     /// Read network messages from the underlying transport
     ReadChannel,
     /// Emit network messages as events for other systems to consume
@@ -409,8 +741,10 @@ impl Plugin for MessagingPlugin {
         let (tx, rx) = flume::unbounded();
 
         app.init_resource::<MessageTypes>()
+            .init_resource::<UnknownMessages>()
             .insert_resource(InternalSenderRes { sender: tx })
             .add_event::<IncomingMessage>()
+            .add_event::<InvalidMessage>()
             .configure_sets(
                 PreUpdate,
                 (
@@ -442,11 +776,240 @@ impl Plugin for MessagingPlugin {
                                  buffer: Local<Vec<OutboundMessage>>| {
                 send_outbound_messages_server(&rx, server, players, buffer);
             };
-            app.add_systems(
-                PreUpdate,
-                read_channel_server.in_set(ReadMessagesSet::ReadChannel),
-            )
-            .add_systems(PostUpdate, outbound.in_set(NetworkSet::SendOutgoing));
+            app.init_resource::<MessageStrikes>()
+                .add_systems(
+                    PreUpdate,
+                    read_channel_server.in_set(ReadMessagesSet::ReadChannel),
+                )
+                .add_systems(Update, punish_invalid_messages)
+                .add_systems(PostUpdate, outbound.in_set(NetworkSet::SendOutgoing));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct First;
+    struct Second;
+    struct Third;
+
+    #[test]
+    fn ids_only_depend_on_names() {
+        // FNV-1a test vectors, so ids can't change with the compiler or platform
+        assert_eq!(message_id(""), 0x811c_9dc5);
+        assert_eq!(message_id("a"), 0xe40c_292c);
+        assert_eq!(message_id("foobar"), 0xbf9c_f968);
+
+        let mut types = MessageTypes::default();
+        assert_eq!(
+            types.register::<First>("First", 10),
+            Some(message_id("First"))
+        );
+        assert_eq!(types.name(message_id("First")), Some("First"));
+    }
+
+    #[test]
+    fn registering_twice_keeps_the_first() {
+        let mut types = MessageTypes::default();
+        assert!(types.register::<First>("First", 10).is_some());
+        assert_eq!(types.register::<First>("First", 20), None);
+        assert_eq!(types.max_size(message_id("First")), Some(10));
+        assert!(types.collisions().is_empty());
+    }
+
+    #[test]
+    fn collisions_are_reported() {
+        let mut types = MessageTypes::default();
+        // These names have the same FNV-1a hash
+        assert!(types.register::<First>("costarring", 10).is_some());
+        assert_eq!(types.register::<Second>("liquid", 10), None);
+        assert!(types.register::<Third>("Third", 10).is_some());
+        assert_eq!(types.collisions(), &[("liquid", "costarring")]);
+        assert_eq!(types.name(message_id("liquid")), Some("costarring"));
+    }
+
+    #[test]
+    fn missing_names_are_exact() {
+        let mut types = MessageTypes::default();
+        types.register::<First>("First", 10);
+        types.register::<Second>("Second", 10);
+        types.register::<Third>("Third", 10);
+
+        let peer: HashSet<u32> = [message_id("Second"), message_id("Unknown")]
+            .into_iter()
+            .collect();
+        assert_eq!(types.missing_from(&peer), vec!["First", "Third"]);
+
+        let everything: HashSet<u32> = types.ids().collect();
+        assert!(types.missing_from(&everything).is_empty());
+    }
+
+    /// Shaped like messages clients send: floats, strings, collections and enums.
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct FuzzMessage {
+        id: u32,
+        aim: Quat,
+        actions: Vec<FuzzAction>,
+        target: Option<u64>,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    enum FuzzAction {
+        Move(Vec3),
+        Say(String),
+        Spawn { position: Vec2, names: Vec<String> },
+    }
+
+    impl Finite for FuzzMessage {
+        fn is_finite(&self) -> bool {
+            self.aim.is_finite()
+                && self.actions.iter().all(|action| match action {
+                    FuzzAction::Move(direction) => direction.is_finite(),
+                    FuzzAction::Say(_) => true,
+                    FuzzAction::Spawn { position, .. } => position.is_finite(),
+                })
+        }
+    }
+
+    const FUZZ_LIMIT: u64 = 1024;
+
+    fn fuzz_message() -> FuzzMessage {
+        FuzzMessage {
+            id: 7,
+            aim: Quat::from_rotation_y(1.0),
+            actions: vec![
+                FuzzAction::Move(Vec3::new(1.0, 0.0, -1.0)),
+                FuzzAction::Say("hello".into()),
+                FuzzAction::Spawn {
+                    position: Vec2::new(3.0, 4.0),
+                    names: vec!["wrench".into(), "crowbar".into()],
+                },
+            ],
+            target: Some(12),
+        }
+    }
+
+    /// Xorshift, so every run fuzzes the same inputs
+    struct FuzzRng(u64);
+
+    impl FuzzRng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn bytes(&mut self, max_len: u64) -> Vec<u8> {
+            let len = self.next() % (max_len + 1);
+            (0..len).map(|_| self.next() as u8).collect()
+        }
+    }
+
+    /// Runs every message type clients can send through the reader. Only checks nothing panics.
+    fn read_everything(data: &[u8]) {
+        let _ = deserialize_limited::<FuzzMessage>(data, FUZZ_LIMIT).map(|m| m.is_finite());
+        let _ = deserialize_limited::<NetworkMessage>(data, FUZZ_LIMIT);
+        let _ = deserialize_limited::<String>(data, FUZZ_LIMIT);
+        let _ = deserialize_limited::<Vec<Vec3>>(data, FUZZ_LIMIT);
+    }
+
+    #[test]
+    fn valid_payloads_round_trip() {
+        let data = bincode::serialize(&fuzz_message()).unwrap();
+        assert_eq!(
+            deserialize_limited::<FuzzMessage>(&data, FUZZ_LIMIT).unwrap(),
+            fuzz_message()
+        );
+    }
+
+    #[test]
+    fn truncated_payloads_are_rejected() {
+        let data = bincode::serialize(&fuzz_message()).unwrap();
+        for len in 0..data.len() {
+            assert!(
+                deserialize_limited::<FuzzMessage>(&data[..len], FUZZ_LIMIT).is_err(),
+                "accepted {} of {} bytes",
+                len,
+                data.len()
+            );
+        }
+
+        let envelope = bincode::serialize(&NetworkMessage {
+            type_id: 1,
+            content: Bytes::from(data),
+        })
+        .unwrap();
+        for len in 0..envelope.len() {
+            assert!(deserialize_limited::<NetworkMessage>(&envelope[..len], FUZZ_LIMIT).is_err());
+        }
+    }
+
+    #[test]
+    fn oversized_payloads_are_rejected() {
+        let data = bincode::serialize(&fuzz_message()).unwrap();
+        assert!(deserialize_limited::<FuzzMessage>(&data, data.len() as u64).is_ok());
+        assert!(deserialize_limited::<FuzzMessage>(&data, data.len() as u64 - 1).is_err());
+
+        let long = bincode::serialize(&"a".repeat(FUZZ_LIMIT as usize)).unwrap();
+        assert!(deserialize_limited::<String>(&long, FUZZ_LIMIT).is_err());
+
+        // Length prefixes claiming huge collections fail before anything is allocated
+        for claimed in [FUZZ_LIMIT, u32::MAX as u64, u64::MAX] {
+            let mut hostile = claimed.to_le_bytes().to_vec();
+            hostile.extend([0; 16]);
+            assert!(deserialize_limited::<Vec<Vec3>>(&hostile, FUZZ_LIMIT).is_err());
+            assert!(deserialize_limited::<String>(&hostile, FUZZ_LIMIT).is_err());
+            assert!(deserialize_limited::<Vec<String>>(&hostile, FUZZ_LIMIT).is_err());
+        }
+    }
+
+    #[test]
+    fn non_finite_values_are_detected() {
+        for value in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY, -f32::NAN] {
+            let mut message = fuzz_message();
+            message.actions[0] = FuzzAction::Move(Vec3::new(0.0, value, 0.0));
+            let data = bincode::serialize(&message).unwrap();
+            // Bincode reads any bit pattern, handlers have to check the values
+            let read = deserialize_limited::<FuzzMessage>(&data, FUZZ_LIMIT).unwrap();
+            assert!(!read.is_finite());
+
+            let mut message = fuzz_message();
+            message.aim.w = value;
+            let data = bincode::serialize(&message).unwrap();
+            assert!(!deserialize_limited::<FuzzMessage>(&data, FUZZ_LIMIT)
+                .unwrap()
+                .is_finite());
+        }
+        assert!(fuzz_message().is_finite());
+    }
+
+    #[test]
+    fn random_payloads_dont_panic() {
+        let mut rng = FuzzRng(0x5eed);
+        for _ in 0..20_000 {
+            read_everything(&rng.bytes(256));
+        }
+    }
+
+    #[test]
+    fn corrupted_payloads_dont_panic() {
+        let mut rng = FuzzRng(0xf1a5);
+        let valid = bincode::serialize(&fuzz_message()).unwrap();
+        for _ in 0..20_000 {
+            let mut data = valid.clone();
+            for _ in 0..1 + rng.next() % 4 {
+                let index = (rng.next() % data.len() as u64) as usize;
+                data[index] = rng.next() as u8;
+            }
+            read_everything(&data);
+
+            // Whatever is accepted has to fit the limit when written again
+            if let Ok(message) = deserialize_limited::<FuzzMessage>(&data, FUZZ_LIMIT) {
+                assert!(bincode::serialized_size(&message).unwrap() <= FUZZ_LIMIT);
+            }
         }
     }
 }
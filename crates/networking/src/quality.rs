@@ -0,0 +1,278 @@
+use std::{fmt, time::Duration};
+
+use bevy::{prelude::*, time::common_conditions::on_timer, utils::HashMap};
+use bevy_renet::renet::RenetServer;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    diagnostics::ConnectionStats,
+    messaging::{AppExt, Channel, MessageEvent, MessageReceivers, MessageSender},
+    ClientEvent, ConnectionId, NetworkManager,
+};
+
+/// How well a client's connection keeps up.
+/// Clients on worse tiers get non-critical updates less often, so their connection isn't flooded.
+#[derive(
+    Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default,
+)]
+pub enum LinkTier {
+    #[default]
+    Good,
+    Degraded,
+    Poor,
+}
+
+impl fmt::Display for LinkTier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            LinkTier::Good => "good",
+            LinkTier::Degraded => "degraded",
+            LinkTier::Poor => "poor",
+        };
+        f.write_str(name)
+    }
+}
+
+/// When connections change tiers and how much their updates are throttled.
+/// Inserted by the game from the server config, defaults are used otherwise.
+#[derive(Resource, Deserialize, Clone)]
+#[serde(default)]
+pub struct LinkQualityConfig {
+    /// Round-trip time in milliseconds above which a connection is degraded
+    pub degraded_rtt: f64,
+    pub poor_rtt: f64,
+    /// Fraction of lost packets above which a connection is degraded
+    pub degraded_packet_loss: f64,
+    pub poor_packet_loss: f64,
+    /// Fraction of a channel's send buffer in use above which a connection is degraded
+    pub degraded_queue: f64,
+    pub poor_queue: f64,
+    /// Fraction of the normal rate non-critical updates are sent at on a degraded connection
+    pub degraded_rate: f32,
+    pub poor_rate: f32,
+    /// Seconds a connection has to measure worse before it's moved to a worse tier
+    pub downgrade_seconds: f32,
+    /// Seconds a connection has to measure better before it's moved back.
+    /// Longer than the downgrade, so a flaky connection doesn't keep switching.
+    pub upgrade_seconds: f32,
+}
+
+impl Default for LinkQualityConfig {
+    fn default() -> Self {
+        Self {
+            degraded_rtt: 150.0,
+            poor_rtt: 300.0,
+            degraded_packet_loss: 0.02,
+            poor_packet_loss: 0.08,
+            degraded_queue: 0.25,
+            poor_queue: 0.5,
+            degraded_rate: 0.5,
+            poor_rate: 0.25,
+            downgrade_seconds: 2.0,
+            upgrade_seconds: 10.0,
+        }
+    }
+}
+
+impl LinkQualityConfig {
+    /// The tier a measurement falls into. Limits are multiplied with `margin`.
+    fn tier(&self, stats: &LinkStats, margin: f64) -> LinkTier {
+        let over = |value: f64, limit: f64| value > limit * margin;
+        if over(stats.rtt, self.poor_rtt)
+            || over(stats.packet_loss, self.poor_packet_loss)
+            || over(stats.queue, self.poor_queue)
+        {
+            LinkTier::Poor
+        } else if over(stats.rtt, self.degraded_rtt)
+            || over(stats.packet_loss, self.degraded_packet_loss)
+            || over(stats.queue, self.degraded_queue)
+        {
+            LinkTier::Degraded
+        } else {
+            LinkTier::Good
+        }
+    }
+}
+
+/// Measurements need to be this far below a limit to count as better, so values right at a limit don't flip the tier
+const RECOVERY_MARGIN: f64 = 0.75;
+/// Seconds between connection measurements
+const CHECK_INTERVAL: f32 = 0.5;
+/// Channels whose send buffers show if a client falls behind
+const QUEUE_CHANNELS: [Channel; 2] = [Channel::Default, Channel::Transforms];
+
+/// The last measurement of a connection.
+#[derive(Serialize, Deserialize, Clone, Copy, Default, Debug)]
+pub struct LinkStats {
+    /// Round-trip time in milliseconds
+    pub rtt: f64,
+    pub packet_loss: f64,
+    /// Fraction of the fullest send buffer that is in use
+    pub queue: f64,
+    pub bytes_sent_per_second: f64,
+    pub bytes_received_per_second: f64,
+}
+
+#[derive(Default)]
+struct Link {
+    tier: LinkTier,
+    stats: LinkStats,
+    /// A tier the connection has measured at since the time
+    pending: Option<(LinkTier, f32)>,
+}
+
+/// Connection tiers of all clients on the server.
+#[derive(Resource, Default)]
+pub struct LinkQuality {
+    links: HashMap<ConnectionId, Link>,
+}
+
+impl LinkQuality {
+    pub fn iter(&self) -> impl Iterator<Item = (ConnectionId, LinkTier, &LinkStats)> {
+        self.links
+            .iter()
+            .map(|(connection, link)| (*connection, link.tier, &link.stats))
+    }
+
+    pub fn tier(&self, connection: ConnectionId) -> LinkTier {
+        self.links
+            .get(&connection)
+            .map(|link| link.tier)
+            .unwrap_or_default()
+    }
+
+    pub fn stats(&self, connection: ConnectionId) -> Option<LinkStats> {
+        self.links.get(&connection).map(|link| link.stats)
+    }
+
+    /// Fraction of the normal rate non-critical updates are sent to a client at.
+    /// Critical messages like spawns, inputs and combat results are never throttled.
+    pub fn rate_factor(&self, connection: ConnectionId, config: &LinkQualityConfig) -> f32 {
+        match self.tier(connection) {
+            LinkTier::Good => 1.0,
+            LinkTier::Degraded => config.degraded_rate,
+            LinkTier::Poor => config.poor_rate,
+        }
+    }
+}
+
+/// Tells a client which tier the server put its connection in.
+#[derive(Serialize, Deserialize)]
+struct LinkTierMessage(LinkTier);
+
+fn measure_links(
+    server: Res<RenetServer>,
+    config: Res<LinkQualityConfig>,
+    mut quality: ResMut<LinkQuality>,
+    time: Res<Time>,
+    mut sender: MessageSender,
+) {
+    let clients = server.clients_id();
+    quality
+        .links
+        .retain(|connection, _| clients.contains(&connection.0));
+
+    let now = time.raw_elapsed_seconds();
+    let channels = Channel::channels_config();
+    for client_id in clients {
+        let Ok(info) = server.network_info(client_id) else {
+            continue;
+        };
+        let queue = channels
+            .iter()
+            .filter(|channel| QUEUE_CHANNELS.iter().any(|c| c.id() == channel.channel_id))
+            .map(|channel| {
+                let available = server.channel_available_memory(client_id, channel.channel_id);
+                1.0 - available as f64 / channel.max_memory_usage_bytes as f64
+            })
+            .fold(0.0, f64::max);
+        let stats = LinkStats {
+            rtt: info.rtt,
+            packet_loss: info.packet_loss,
+            queue,
+            bytes_sent_per_second: info.bytes_sent_per_second,
+            bytes_received_per_second: info.bytes_received_per_second,
+        };
+
+        let connection = ConnectionId(client_id);
+        let link = quality.links.entry(connection).or_default();
+        link.stats = stats;
+
+        let worse = config.tier(&stats, 1.0);
+        let better = config.tier(&stats, RECOVERY_MARGIN);
+        let (target, hold) = if worse > link.tier {
+            (worse, config.downgrade_seconds)
+        } else if better < link.tier {
+            (better, config.upgrade_seconds)
+        } else {
+            link.pending = None;
+            continue;
+        };
+
+        let since = match link.pending {
+            // A connection getting even worse doesn't restart the wait
+            Some((pending, since)) if (pending > link.tier) == (target > link.tier) => since,
+            _ => now,
+        };
+        link.pending = Some((target, since));
+        if now - since < hold {
+            continue;
+        }
+
+        info!(
+            connection = ?connection,
+            from = %link.tier,
+            to = %target,
+            rtt = stats.rtt,
+            packet_loss = stats.packet_loss,
+            queue = stats.queue,
+            "Connection changed quality tier"
+        );
+        link.tier = target;
+        link.pending = None;
+        sender.send(
+            &LinkTierMessage(target),
+            MessageReceivers::Single(connection),
+        );
+    }
+}
+
+fn receive_link_tier(
+    mut messages: EventReader<MessageEvent<LinkTierMessage>>,
+    mut client_events: EventReader<ClientEvent>,
+    mut stats: ResMut<ConnectionStats>,
+) {
+    // Servers only send the tier when it changes
+    if client_events.iter().any(|e| *e == ClientEvent::Joined) {
+        stats.link_tier = LinkTier::Good;
+    }
+    for event in messages.iter() {
+        stats.link_tier = event.message.0;
+    }
+}
+
+pub(crate) struct LinkQualityPlugin;
+
+impl Plugin for LinkQualityPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_network_message::<LinkTierMessage>("LinkTierMessage");
+
+        if app
+            .world
+            .get_resource::<NetworkManager>()
+            .unwrap()
+            .is_server()
+        {
+            app.init_resource::<LinkQualityConfig>()
+                .init_resource::<LinkQuality>()
+                .add_systems(
+                    Update,
+                    measure_links
+                        .run_if(resource_exists::<RenetServer>())
+                        .run_if(on_timer(Duration::from_secs_f32(CHECK_INTERVAL))),
+                );
+        } else {
+            app.add_systems(Update, receive_link_tier);
+        }
+    }
+}
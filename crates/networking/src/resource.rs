@@ -202,6 +202,6 @@ pub(crate) struct ResourcePlugin;
 impl Plugin for ResourcePlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<NetworkedResourceRegistry>()
-            .add_network_message::<NetworkedResourceMessage>();
+            .add_network_message::<NetworkedResourceMessage>("NetworkedResourceMessage");
     }
 }
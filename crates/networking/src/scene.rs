@@ -40,6 +40,12 @@ impl Plugin for ScenePlugin {
 #[derive(Component, Default)]
 pub struct NetworkScene(pub(crate) Handle<DynamicScene>);
 
+impl NetworkScene {
+    pub fn handle(&self) -> &Handle<DynamicScene> {
+        &self.0
+    }
+}
+
 impl From<Handle<DynamicScene>> for NetworkScene {
     fn from(handle: Handle<DynamicScene>) -> Self {
         Self(handle)
@@ -83,11 +89,19 @@ pub struct NetworkSceneBundle {
     pub computed_visibility: ComputedVisibility,
 }
 
+/// Networked scenes waiting for their assets before they are spawned.
 #[derive(Resource, Default)]
-struct NetworkSceneSpawner {
+pub struct NetworkSceneSpawner {
     scenes_to_spawn: Vec<(Entity, Handle<DynamicScene>)>,
 }
 
+impl NetworkSceneSpawner {
+    /// True if every scene added so far has been spawned.
+    pub fn is_idle(&self) -> bool {
+        self.scenes_to_spawn.is_empty()
+    }
+}
+
 fn prepare_loaded_scenes(
     mut scenes: ResMut<Assets<DynamicScene>>,
     mut events: EventReader<AssetEvent<DynamicScene>>,
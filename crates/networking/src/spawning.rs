@@ -1,18 +1,22 @@
+use std::time::Duration;
+
 use bevy::{
     asset::AssetPathId,
     ecs::query::{Has, QuerySingleError},
     prelude::*,
     scene::DynamicScene,
+    time::common_conditions::on_timer,
     utils::{HashMap, HashSet, Uuid},
 };
 use serde::{Deserialize, Serialize};
 
 use crate::{
+    diagnostics::{DebugNames, SpawnAuditStats},
     identity::{IdentitySystem, NetworkIdentities, NetworkIdentity},
     messaging::{AppExt, MessageEvent, MessageReceivers, MessageSender},
     scene::{NetworkScene, NetworkSceneBundle, NetworkedChild},
-    visibility::NetworkVisibilities,
-    ConnectionId, NetworkManager, NetworkSet, Players, ServerEvent,
+    visibility::{chunk_position, NetworkVisibilities, VisibilitySystem},
+    ClientState, ConnectionId, NetworkManager, NetworkSet, Players, ServerEvent,
 };
 
 /// A message that instructs the client to spawn a specific entity.
@@ -35,12 +39,92 @@ enum SpawnAssetIdentifier {
     },
 }
 
+/// Why the server despawned an entity for a client.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+enum DespawnReason {
+    /// The entity was deleted on the server
+    Deleted,
+    /// The entity is still there, but the client no longer observes it
+    OutOfView,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct DespawnEntity {
+    network_id: NetworkIdentity,
+    reason: DespawnReason,
+}
+
+/// Counts the root entities the server spawned for a client in one chunk, so lost despawns can be found.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+struct SpawnAudit {
+    count: u32,
+    checksum: u32,
+}
+
+impl SpawnAudit {
+    fn new<'a>(identities: impl IntoIterator<Item = &'a NetworkIdentity>) -> Self {
+        identities
+            .into_iter()
+            .fold(Self::default(), |audit, identity| Self {
+                count: audit.count + 1,
+                // Order independent, so both sides can use their unordered sets
+                checksum: audit.checksum ^ identity.raw().wrapping_mul(0x9E37_79B1),
+            })
+    }
+}
+
+/// The chunk of the global grid an entity is audited in. `None` for entities that aren't in the world.
+type AuditChunk = Option<IVec2>;
+
+type AuditPositions<'w, 's> = Query<'w, 's, &'static GlobalTransform, With<ComputedVisibility>>;
+
+/// Groups spawned entities by the chunk they are in.
+/// The server and client can disagree about moving entities at the edge of a chunk,
+/// which only costs an unneeded reconciliation.
+fn group_by_chunk(
+    identities: impl IntoIterator<Item = NetworkIdentity>,
+    ids: &NetworkIdentities,
+    positions: &AuditPositions,
+) -> HashMap<AuditChunk, Vec<NetworkIdentity>> {
+    let mut chunks: HashMap<AuditChunk, Vec<NetworkIdentity>> = HashMap::default();
+    for identity in identities {
+        let chunk = ids
+            .get_entity(identity)
+            .and_then(|entity| positions.get(entity).ok())
+            .map(|transform| chunk_position(transform.translation()));
+        chunks.entry(chunk).or_default().push(identity);
+    }
+    chunks
+}
+
+/// Spawn related messages share one type, so the client handles them in the order they were sent.
+/// Audits and reconciliation rely on this to describe the same point in the stream.
 #[derive(Serialize, Deserialize, Debug, Clone)]
 enum SpawnMessage {
     Spawn(SpawnEntity),
-    Despawn(NetworkIdentity),
+    Despawn(DespawnEntity),
+    /// Audits of every chunk with entities spawned for the client
+    Audit(Vec<(AuditChunk, SpawnAudit)>),
+    /// Answer to a [`ReconcileRequest`]
+    Reconcile {
+        /// Entities the client listed that the server hasn't spawned for it
+        orphans: Vec<NetworkIdentity>,
+        /// Every root entity the server has spawned for the client in the requested chunks
+        expected: Vec<NetworkIdentity>,
+    },
 }
 
+/// Sent by a client whose entities didn't match the last audit in some chunks.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct ReconcileRequest {
+    /// The chunks that didn't match, with the entities the client has in them
+    chunks: Vec<(AuditChunk, Vec<NetworkIdentity>)>,
+}
+
+/// Asks the server to spawn entities again that the client is missing.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct RespawnRequest(Vec<NetworkIdentity>);
+
 // Temporary struct to label networked objects
 // This should be replaced with the scene identifier in a future bevy release
 #[derive(Component)]
@@ -56,6 +140,22 @@ pub enum ServerEntityEvent {
 }
 
 const DESPAWN_MESSAGE_PRIORITY: i16 = -10;
+/// Sent after spawns and despawns of the same frame, so audits account for them
+const AUDIT_MESSAGE_PRIORITY: i16 = -20;
+/// Seconds between audits of the entities spawned for each client
+const AUDIT_INTERVAL: f32 = 5.0;
+/// Seconds a despawn for an unknown entity cancels its spawn, in case it arrives afterwards
+const TOMBSTONE_SECONDS: f32 = 10.0;
+/// Most chunks a client reconciles at once, the rest are caught by the next audit
+const MAX_RECONCILE_CHUNKS: usize = 8;
+/// Most entities a client can ask to be spawned again at once
+const MAX_RESPAWNS: usize = 256;
+
+/// Root entities the server has told each client to spawn and not despawned since.
+#[derive(Resource, Default)]
+struct SpawnedIdentities {
+    connections: HashMap<ConnectionId, HashSet<NetworkIdentity>>,
+}
 
 /// Events related to networked entities on the client
 #[derive(Event)]
@@ -84,7 +184,9 @@ fn send_spawn_messages(
     players: Res<Players>,
     mut sender: MessageSender,
     mut entity_events: EventWriter<ServerEntityEvent>,
+    mut spawned: ResMut<SpawnedIdentities>,
     scenes: Res<Assets<DynamicScene>>,
+    names: DebugNames,
 ) {
     for (entity, identity, name, scene, has_visibiliy) in query.iter() {
         // Only send scenes once they're loaded
@@ -113,14 +215,14 @@ fn send_spawn_messages(
                     }
                     (None, Some(scene)) => SpawnAssetIdentifier::AssetPath(match scene.0.id() {
                         bevy::asset::HandleId::Id(_, _) => {
-                            warn!(entity = ?entity, "Cannot spawn networked object with dynamic handle id. Handle must be created from a loaded asset.");
+                            warn!(entity = %names.debug_name(entity), "Cannot spawn networked object with dynamic handle id. Handle must be created from a loaded asset.");
                             continue;
                         }
                         bevy::asset::HandleId::AssetPathId(p) => p,
                     }),
                     (Some(name), None) => SpawnAssetIdentifier::Named(name.0.clone()),
                     (Some(_), Some(_)) => {
-                        warn!("Entity has both an asset path id and a prefab path. Skipping.");
+                        warn!(entity = %names.debug_name(entity), "Entity has both an asset path id and a prefab path. Skipping.");
                         continue;
                     }
                 };
@@ -146,6 +248,13 @@ fn send_spawn_messages(
                         .iter()
                         .map(|c| ServerEntityEvent::Spawned((entity, *c))),
                 );
+                for connection in new_observers {
+                    spawned
+                        .connections
+                        .entry(connection)
+                        .or_default()
+                        .insert(*identity);
+                }
             }
 
             let connected_players = players.players();
@@ -160,9 +269,17 @@ fn send_spawn_messages(
                         .iter()
                         .map(|c| ServerEntityEvent::Despawned((entity, *c))),
                 );
+                for connection in removed_observers.iter() {
+                    if let Some(identities) = spawned.connections.get_mut(connection) {
+                        identities.remove(identity);
+                    }
+                }
                 // Send despawn message
                 sender.send_with_priority(
-                    &SpawnMessage::Despawn(*identity),
+                    &SpawnMessage::Despawn(DespawnEntity {
+                        network_id: *identity,
+                        reason: DespawnReason::OutOfView,
+                    }),
                     MessageReceivers::Set(removed_observers),
                     DESPAWN_MESSAGE_PRIORITY,
                 );
@@ -178,9 +295,13 @@ fn network_deleted_entities(
     visibilities: Res<NetworkVisibilities>,
     mut sender: MessageSender,
     mut entity_events: EventWriter<ServerEntityEvent>,
+    mut spawned: ResMut<SpawnedIdentities>,
 ) {
     for entity in removed.iter() {
         let identity = identities.get_identity(entity).unwrap();
+        for identities in spawned.connections.values_mut() {
+            identities.remove(&identity);
+        }
         if let Some(visibility) = visibilities.visibility.get(&identity) {
             let observers: HashSet<ConnectionId> = visibility.all_observers().copied().collect();
             if !observers.is_empty() {
@@ -190,7 +311,10 @@ fn network_deleted_entities(
                         .map(|c| ServerEntityEvent::Despawned((entity, *c))),
                 );
                 sender.send_with_priority(
-                    &SpawnMessage::Despawn(identity),
+                    &SpawnMessage::Despawn(DespawnEntity {
+                        network_id: identity,
+                        reason: DespawnReason::Deleted,
+                    }),
                     MessageReceivers::Set(observers),
                     DESPAWN_MESSAGE_PRIORITY,
                 );
@@ -199,26 +323,188 @@ fn network_deleted_entities(
     }
 }
 
+/// Periodically tells each client what the server thinks it has spawned, per chunk.
+fn send_spawn_audits(
+    mut spawned: ResMut<SpawnedIdentities>,
+    players: Res<Players>,
+    ids: Res<NetworkIdentities>,
+    positions: AuditPositions,
+    mut sender: MessageSender,
+) {
+    let connected = players.players();
+    spawned
+        .connections
+        .retain(|connection, _| connected.contains_key(connection));
+
+    for &connection in connected.keys() {
+        let identities = spawned.connections.get(&connection).into_iter().flatten();
+        let audits = group_by_chunk(identities.copied(), &ids, &positions)
+            .into_iter()
+            .map(|(chunk, identities)| (chunk, SpawnAudit::new(&identities)))
+            .collect();
+        sender.send_with_priority(
+            &SpawnMessage::Audit(audits),
+            MessageReceivers::Single(connection),
+            AUDIT_MESSAGE_PRIORITY,
+        );
+    }
+}
+
+fn answer_reconcile_requests(
+    mut requests: EventReader<MessageEvent<ReconcileRequest>>,
+    spawned: Res<SpawnedIdentities>,
+    ids: Res<NetworkIdentities>,
+    positions: AuditPositions,
+    time: Res<Time>,
+    mut last_answered: Local<HashMap<ConnectionId, f32>>,
+    mut sender: MessageSender,
+) {
+    let now = time.raw_elapsed_seconds();
+    let nothing_spawned = HashSet::default();
+    for event in requests.iter() {
+        // Don't let clients ask much more often than audits happen
+        if last_answered
+            .get(&event.connection)
+            .is_some_and(|last| now - last < AUDIT_INTERVAL / 2.0)
+        {
+            continue;
+        }
+        last_answered.insert(event.connection, now);
+
+        let spawned = spawned
+            .connections
+            .get(&event.connection)
+            .unwrap_or(&nothing_spawned);
+        let chunks = &event.message.chunks;
+        let requested = &chunks[..chunks.len().min(MAX_RECONCILE_CHUNKS)];
+        let orphans = requested
+            .iter()
+            .flat_map(|(_, identities)| identities)
+            .filter(|identity| !spawned.contains(*identity))
+            .copied()
+            .collect();
+        let mut expected = group_by_chunk(spawned.iter().copied(), &ids, &positions);
+        let expected = requested
+            .iter()
+            .filter_map(|(chunk, _)| expected.remove(chunk))
+            .flatten()
+            .collect();
+        sender.send_with_priority(
+            &SpawnMessage::Reconcile { orphans, expected },
+            MessageReceivers::Single(event.connection),
+            AUDIT_MESSAGE_PRIORITY,
+        );
+    }
+}
+
+/// Spawns entities again for clients that are missing them.
+/// The client is made a new observer of the entity, so its components are sent again too.
+fn answer_respawn_requests(
+    mut requests: EventReader<MessageEvent<RespawnRequest>>,
+    mut spawned: ResMut<SpawnedIdentities>,
+    mut visibilities: ResMut<NetworkVisibilities>,
+    time: Res<Time>,
+    mut last_answered: Local<HashMap<ConnectionId, f32>>,
+) {
+    let now = time.raw_elapsed_seconds();
+    for event in requests.iter() {
+        if last_answered
+            .get(&event.connection)
+            .is_some_and(|last| now - last < AUDIT_INTERVAL / 2.0)
+        {
+            continue;
+        }
+        last_answered.insert(event.connection, now);
+
+        let Some(identities) = spawned.connections.get_mut(&event.connection) else {
+            continue;
+        };
+        for identity in event.message.0.iter().take(MAX_RESPAWNS) {
+            // Only entities spawned for the client, so it can't reveal anything else
+            if !identities.remove(identity) {
+                continue;
+            }
+            if let Some(visibility) = visibilities.get_mut(*identity) {
+                visibility.forget_observer(event.connection);
+            }
+        }
+    }
+}
+
+/// Root entities the client spawned for the server, and despawns that arrived for unknown entities.
+/// Reset when joining a server.
+#[derive(Resource, Default)]
+struct ClientSpawns {
+    spawned: HashSet<NetworkIdentity>,
+    /// Despawned identities and when their despawn arrived
+    tombstones: HashMap<NetworkIdentity, f32>,
+    /// A reconciliation was requested and hasn't been answered
+    reconciling: bool,
+}
+
+impl ClientSpawns {
+    fn despawn(
+        &mut self,
+        identity: NetworkIdentity,
+        ids: &mut NetworkIdentities,
+        entity_events: &mut EventWriter<NetworkedEntityEvent>,
+        commands: &mut Commands,
+    ) -> bool {
+        self.spawned.remove(&identity);
+        let Some(entity) = ids.get_entity(identity) else {
+            return false;
+        };
+        commands.entity(entity).despawn_recursive();
+        ids.remove_entity(entity);
+        entity_events.send(NetworkedEntityEvent::Despawned(entity));
+        true
+    }
+}
+
+fn reset_client_spawns(mut state: ResMut<ClientSpawns>) {
+    *state = ClientSpawns::default();
+}
+
+#[allow(clippy::too_many_arguments)]
 fn receive_spawn(
     mut spawn_events: EventReader<MessageEvent<SpawnMessage>>,
     mut entity_events: EventWriter<NetworkedEntityEvent>,
     mut ids: ResMut<NetworkIdentities>,
+    mut state: ResMut<ClientSpawns>,
+    positions: AuditPositions,
+    mut stats: ResMut<SpawnAuditStats>,
+    mut sender: MessageSender,
+    time: Res<Time>,
     mut commands: Commands,
-    asset_server: ResMut<AssetServer>,
+    asset_server: Res<AssetServer>,
+    names: DebugNames,
 ) {
+    let now = time.raw_elapsed_seconds();
+    state
+        .tombstones
+        .retain(|_, despawned| now - *despawned < TOMBSTONE_SECONDS);
+
     for event in spawn_events.iter() {
         match &event.message {
             SpawnMessage::Spawn(s) => {
                 let spawn = s.clone();
 
-                if ids.get_entity(spawn.network_id).is_some() {
-                    warn!(
-                        "Received spawn message for already existing {:?}",
+                if state.tombstones.remove(&spawn.network_id).is_some() {
+                    debug!(
+                        "Skipping spawn of {} that was already despawned",
                         spawn.network_id
                     );
                     continue;
                 }
 
+                if let Some(existing) = ids.get_entity(spawn.network_id) {
+                    warn!(
+                        "Received spawn message for already existing {}",
+                        names.debug_name(existing)
+                    );
+                    continue;
+                }
+
                 let mut builder = commands.spawn(spawn.network_id);
 
                 match spawn.identifier {
@@ -240,18 +526,79 @@ fn receive_spawn(
 
                 let entity = builder.id();
                 ids.set_identity(entity, spawn.network_id);
+                state.spawned.insert(spawn.network_id);
                 entity_events.send(NetworkedEntityEvent::Spawned(entity));
 
                 debug!("Received spawn message for {:?}", spawn.network_id);
             }
-            SpawnMessage::Despawn(id) => {
-                if let Some(entity) = ids.get_entity(*id) {
-                    commands.entity(entity).despawn_recursive();
-                    ids.remove_entity(entity);
-                    entity_events.send(NetworkedEntityEvent::Despawned(entity));
-                    debug!("Received despawn message for {:?}", id);
+            SpawnMessage::Despawn(despawn) => {
+                let id = despawn.network_id;
+                if state.despawn(id, &mut ids, &mut entity_events, &mut commands) {
+                    debug!(reason = ?despawn.reason, "Received despawn message for {:?}", id);
                 } else {
-                    warn!("Received despawn message for non-existent {:?}", id);
+                    // The spawn may still arrive, it is skipped then
+                    debug!(reason = ?despawn.reason, "Received despawn message before spawn for {}", id);
+                    state.tombstones.insert(id, now);
+                }
+            }
+            SpawnMessage::Audit(audits) => {
+                // An unanswered request is given up on, so a dropped request can't stop audits
+                if std::mem::take(&mut state.reconciling) {
+                    continue;
+                }
+                let mut ours = group_by_chunk(state.spawned.iter().copied(), &ids, &positions);
+                let server: HashMap<AuditChunk, SpawnAudit> = audits.iter().copied().collect();
+                let mismatched: HashSet<AuditChunk> = ours
+                    .keys()
+                    .chain(server.keys())
+                    .copied()
+                    .filter(|chunk| {
+                        ours.get(chunk).map(SpawnAudit::new).unwrap_or_default()
+                            != server.get(chunk).copied().unwrap_or_default()
+                    })
+                    .collect();
+                if mismatched.is_empty() {
+                    continue;
+                }
+                debug!(
+                    chunks = mismatched.len(),
+                    "Spawned entities don't match the server, reconciling"
+                );
+                let chunks = mismatched
+                    .into_iter()
+                    .take(MAX_RECONCILE_CHUNKS)
+                    .map(|chunk| (chunk, ours.remove(&chunk).unwrap_or_default()))
+                    .collect();
+                state.reconciling = true;
+                sender.send_to_server(&ReconcileRequest { chunks });
+            }
+            SpawnMessage::Reconcile { orphans, expected } => {
+                state.reconciling = false;
+                stats.reconciliations += 1;
+
+                for &identity in orphans {
+                    if state.despawn(identity, &mut ids, &mut entity_events, &mut commands) {
+                        warn!("Despawned {} the server no longer knows about", identity);
+                        stats.orphans_removed += 1;
+                    }
+                }
+
+                let missing: Vec<NetworkIdentity> = expected
+                    .iter()
+                    .filter(|identity| !state.spawned.contains(*identity))
+                    .copied()
+                    .collect();
+                if !missing.is_empty() {
+                    warn!(
+                        missing = missing.len(),
+                        "Server spawned entities the client doesn't have, requesting them"
+                    );
+                    for identity in missing.iter() {
+                        // A wrong tombstone is a likely reason for the entity to be missing
+                        state.tombstones.remove(identity);
+                    }
+                    stats.missing_requested += missing.len() as u32;
+                    sender.send_to_server(&RespawnRequest(missing));
                 }
             }
         }
@@ -406,8 +753,10 @@ pub(crate) struct SpawningPlugin;
 
 impl Plugin for SpawningPlugin {
     fn build(&self, app: &mut App) {
-        app.add_network_message::<SpawnMessage>()
-            .add_network_message::<ControlUpdate>();
+        app.add_network_message::<SpawnMessage>("SpawnMessage")
+            .add_network_message::<ReconcileRequest>("ReconcileRequest")
+            .add_network_message::<RespawnRequest>("RespawnRequest")
+            .add_network_message::<ControlUpdate>("ControlUpdate");
 
         if app
             .world
@@ -417,6 +766,7 @@ impl Plugin for SpawningPlugin {
         {
             app.add_event::<ServerEntityEvent>()
                 .init_resource::<ClientControls>()
+                .init_resource::<SpawnedIdentities>()
                 .add_systems(
                     PostUpdate,
                     (
@@ -424,11 +774,26 @@ impl Plugin for SpawningPlugin {
                         send_control_updates,
                         send_control_updates_to_rejoined,
                         network_deleted_entities.before(IdentitySystem::ClearRemoved),
+                        (
+                            send_spawn_audits
+                                .run_if(on_timer(Duration::from_secs_f32(AUDIT_INTERVAL))),
+                            answer_reconcile_requests,
+                        )
+                            .after(send_spawn_messages)
+                            .after(network_deleted_entities),
                     )
                         .in_set(NetworkSet::ServerWrite),
+                )
+                .add_systems(
+                    PreUpdate,
+                    answer_respawn_requests
+                        .in_set(NetworkSet::ServerVisibility)
+                        .before(VisibilitySystem::GridVisibility),
                 );
         } else {
             app.add_event::<NetworkedEntityEvent>()
+                .init_resource::<ClientSpawns>()
+                .add_systems(OnEnter(ClientState::Joining), reset_client_spawns)
                 .configure_sets(
                     PreUpdate,
                     (
@@ -449,3 +814,170 @@ impl Plugin for SpawningPlugin {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use bevy::{
+        ecs::{event::ManualEventReader, system::Command},
+        time::TimeUpdateStrategy,
+    };
+
+    use super::*;
+    use crate::{
+        identity::NetworkCommand, loopback::LinkConditions, testing, visibility::Relevancy,
+        NetworkRole,
+    };
+
+    /// Game time per frame, so audits happen within a few dozen frames
+    const FRAME: Duration = Duration::from_millis(100);
+    /// Frames in which the next audit happened and was reconciled
+    const AUDIT_FRAMES: u32 = 2 * (AUDIT_INTERVAL * 1000.0) as u32 / FRAME.as_millis() as u32;
+
+    /// Spawn messages the client loses before handling them
+    #[derive(Resource, Default)]
+    struct DropMessages {
+        spawns: bool,
+        despawns: bool,
+    }
+
+    fn drop_messages(
+        dropping: Res<DropMessages>,
+        mut events: ResMut<Events<MessageEvent<SpawnMessage>>>,
+        mut reader: Local<ManualEventReader<MessageEvent<SpawnMessage>>>,
+    ) {
+        let new = reader.len(&events);
+        let drained: Vec<_> = events.drain().collect();
+        let handled = drained.len() - new;
+        for event in drained.into_iter().skip(handled) {
+            let dropped = match event.message {
+                SpawnMessage::Spawn(_) => dropping.spawns,
+                SpawnMessage::Despawn(_) => dropping.despawns,
+                _ => false,
+            };
+            if !dropped {
+                events.send(event);
+            }
+        }
+        reader.clear(&events);
+    }
+
+    fn connected() -> (App, App) {
+        let mut server = testing::app(NetworkRole::Server);
+        let mut client = testing::app(NetworkRole::Client);
+        for app in [&mut server, &mut client] {
+            app.insert_resource(TimeUpdateStrategy::ManualDuration(FRAME));
+        }
+        client.init_resource::<DropMessages>().add_systems(
+            PreUpdate,
+            drop_messages
+                .after(NetworkSet::ReadIncoming)
+                .before(NetworkSet::ClientSpawn),
+        );
+        let connector = testing::listen(&mut server);
+        testing::join(&mut client, &connector, LinkConditions::default());
+        testing::connect(&mut server, &mut [&mut client], 200);
+        (server, client)
+    }
+
+    /// Spawns an entity on the server that the client sees.
+    fn spawn_visible(server: &mut App) -> (Entity, NetworkIdentity) {
+        let entity = server.world.spawn(SpatialBundle::default()).id();
+        NetworkCommand { entity }.apply(&mut server.world);
+        let connection = *server
+            .world
+            .resource::<Players>()
+            .players()
+            .keys()
+            .next()
+            .unwrap();
+        let owner = server.world.spawn_empty().id();
+        server
+            .world
+            .resource_mut::<Relevancy>()
+            .add_viewer(owner, entity, connection);
+        (
+            entity,
+            *server.world.get::<NetworkIdentity>(entity).unwrap(),
+        )
+    }
+
+    fn has_entity(client: &App, identity: NetworkIdentity) -> bool {
+        client
+            .world
+            .resource::<NetworkIdentities>()
+            .get_entity(identity)
+            .is_some()
+    }
+
+    fn update_until(
+        server: &mut App,
+        client: &mut App,
+        max_frames: u32,
+        done: impl Fn(&App) -> bool,
+    ) -> bool {
+        for _ in 0..max_frames {
+            testing::update(server, &mut [&mut *client], 1);
+            if done(client) {
+                return true;
+            }
+        }
+        false
+    }
+
+    #[test]
+    fn audit_removes_entity_with_dropped_despawn() {
+        let (mut server, mut client) = connected();
+        let (entity, identity) = spawn_visible(&mut server);
+        let spawned = |client: &App| has_entity(client, identity);
+        assert!(update_until(&mut server, &mut client, 20, spawned));
+
+        client.world.resource_mut::<DropMessages>().despawns = true;
+        server.world.despawn(entity);
+        testing::update(&mut server, &mut [&mut client], 5);
+        client.world.resource_mut::<DropMessages>().despawns = false;
+        assert!(has_entity(&client, identity), "Despawn wasn't dropped");
+
+        let despawned = |client: &App| !has_entity(client, identity);
+        assert!(
+            update_until(&mut server, &mut client, AUDIT_FRAMES, despawned),
+            "Stale entity wasn't removed within the audit interval"
+        );
+        let stats = client.world.resource::<SpawnAuditStats>();
+        assert_eq!(stats.orphans_removed, 1);
+        assert_eq!(stats.missing_requested, 0);
+    }
+
+    #[test]
+    fn audit_requests_entity_with_dropped_spawn() {
+        let (mut server, mut client) = connected();
+        client.world.resource_mut::<DropMessages>().spawns = true;
+        let (_, identity) = spawn_visible(&mut server);
+        testing::update(&mut server, &mut [&mut client], 5);
+        client.world.resource_mut::<DropMessages>().spawns = false;
+        assert!(!has_entity(&client, identity), "Spawn wasn't dropped");
+
+        let spawned = |client: &App| has_entity(client, identity);
+        assert!(
+            update_until(&mut server, &mut client, AUDIT_FRAMES, spawned),
+            "Missing entity wasn't spawned again within the audit interval"
+        );
+        let stats = client.world.resource::<SpawnAuditStats>();
+        assert_eq!(stats.missing_requested, 1);
+        assert_eq!(stats.orphans_removed, 0);
+    }
+
+    #[test]
+    fn client_spawns_reset_when_joining() {
+        let mut client = testing::app(NetworkRole::Client);
+        client
+            .world
+            .resource_mut::<ClientSpawns>()
+            .spawned
+            .insert(NetworkIdentity::from_raw(1));
+        let mut server = testing::app(NetworkRole::Server);
+        let connector = testing::listen(&mut server);
+        testing::join(&mut client, &connector, LinkConditions::default());
+        testing::update(&mut server, &mut [&mut client], 2);
+        assert!(client.world.resource::<ClientSpawns>().spawned.is_empty());
+    }
+}
@@ -0,0 +1,78 @@
+//! Runs a server and its clients in one process for tests.
+//! Clients join over the [loopback transport](crate::loopback), so no sockets are opened.
+
+use std::time::Duration;
+
+use bevy::{asset::AssetPlugin, prelude::*};
+
+use crate::{
+    create_local_server,
+    loopback::{LinkConditions, LoopbackConnector},
+    ClientEvent, ClientState, NetworkRole, NetworkingPlugin, TargetServer, UserData,
+};
+
+/// Time between frames while waiting for something to arrive
+const FRAME_TIME: Duration = Duration::from_millis(5);
+
+/// A headless app with everything the networking plugin needs.
+/// Add the plugins under test before calling [`listen`] or [`join`].
+pub fn app(role: NetworkRole) -> App {
+    let mut app = App::new();
+    app.add_plugins((
+        MinimalPlugins,
+        AssetPlugin::default(),
+        bevy::scene::ScenePlugin,
+        HierarchyPlugin,
+        TransformPlugin,
+        NetworkingPlugin { role },
+    ));
+    app
+}
+
+/// Starts the server without a socket. Clients join with the returned connector.
+pub fn listen(server: &mut App) -> LoopbackConnector {
+    let (renet, transport, connector) = create_local_server();
+    server.insert_resource(renet).insert_resource(transport);
+    connector
+}
+
+/// Starts joining the server of `connector`, over a link with the given conditions.
+pub fn join(client: &mut App, connector: &LoopbackConnector, conditions: LinkConditions) {
+    client.insert_resource(UserData {
+        username: "Test".into(),
+    });
+    client
+        .world
+        .resource_mut::<Events<ClientEvent>>()
+        .send(ClientEvent::Join(TargetServer::Loopback(
+            connector.clone().with_conditions(conditions),
+        )));
+}
+
+/// If a client finished joining its server.
+pub fn is_connected(client: &App) -> bool {
+    client.world.resource::<State<ClientState>>().get() == &ClientState::Connected
+}
+
+/// Updates the server and then each client, `frames` times.
+pub fn update(server: &mut App, clients: &mut [&mut App], frames: u32) {
+    for _ in 0..frames {
+        server.update();
+        for client in clients.iter_mut() {
+            client.update();
+        }
+    }
+}
+
+/// Updates the apps until every client is connected. Panics if that takes more than `max_frames`.
+/// Frames are a few milliseconds apart, because renet only resends lost packets after some time.
+pub fn connect(server: &mut App, clients: &mut [&mut App], max_frames: u32) {
+    for _ in 0..max_frames {
+        update(server, clients, 1);
+        if clients.iter().all(|client| is_connected(client)) {
+            return;
+        }
+        std::thread::sleep(FRAME_TIME);
+    }
+    panic!("Clients didn't connect within {} frames", max_frames);
+}
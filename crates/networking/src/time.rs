@@ -23,6 +23,11 @@ impl ServerNetworkTime {
     pub fn tick_in_seconds(&self) -> f64 {
         self.server_tick_seconds
     }
+
+    /// Continues counting from another tick, used by replays to line up with the recorded ticks.
+    pub fn set_current_tick(&mut self, tick: u32) {
+        self.server_tick = tick;
+    }
 }
 
 #[derive(Default)]
@@ -49,10 +54,11 @@ struct ReceivedServerTick {
     tick: u32,
 }
 
+/// Timing data of the client, estimated from the ticks the server sends.
 #[derive(Resource)]
-pub(crate) struct ClientNetworkTime {
+pub struct ClientNetworkTime {
     /// How many seconds a server tick lasts
-    pub server_tick_seconds: Option<f32>,
+    pub(crate) server_tick_seconds: Option<f32>,
     /// The last received server tick
     server_tick: Option<ReceivedServerTick>,
     /// The last round-trip-times received
@@ -83,6 +89,7 @@ impl Default for ClientNetworkTime {
 }
 
 impl ClientNetworkTime {
+    /// The server tick the client is currently displaying.
     pub fn interpolated_tick(&self) -> f32 {
         self.interpolated_tick
     }
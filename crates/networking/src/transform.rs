@@ -15,9 +15,11 @@ use physics::{ColliderGroup, SetPhysicsCommand};
 use serde::{Deserialize, Serialize};
 
 use crate::{
+    diagnostics::{DebugNames, UnresolvedIdentities},
     identity::{NetworkIdentities, NetworkIdentity},
     messaging::{deserialize, serialize_once, Channel},
-    spawning::ClientControlled,
+    quality::{LinkQuality, LinkQualityConfig},
+    spawning::{ClientControlled, ClientControls},
     time::{ClientNetworkTime, ServerNetworkTime},
     visibility::NetworkVisibilities,
     ConnectionId, NetworkManager, NetworkSet,
@@ -301,6 +303,8 @@ struct ClientData {
     // sent_sequence: Option<SequenceNumber>,
     /// The last sequence that was confirmed to have arrived
     acked_sequence: Option<SequenceNumber>,
+    /// When an update was last sent to this client
+    last_sent: f32,
     // /// The complete state the object was in at the last ack
     // acked_state: Option<TransformSnapshot>,
 }
@@ -368,6 +372,7 @@ impl NetworkTransform {
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn update_transform(
     mut query: Query<(
         Entity,
@@ -386,6 +391,8 @@ fn update_transform(
     visibilities: Res<NetworkVisibilities>,
     mut server: ResMut<RenetServer>,
     network_time: Res<ServerNetworkTime>,
+    controls: Res<ClientControls>,
+    (quality, quality_config): (Res<LinkQuality>, Res<LinkQualityConfig>),
     mut commands: Commands,
 ) {
     let seconds = time.raw_elapsed_seconds();
@@ -405,6 +412,14 @@ fn update_transform(
     {
         let networked: &mut NetworkTransform = &mut networked;
 
+        // Invalid values would break interpolation on every client, so they are never sent
+        let finite = transform.translation.is_finite()
+            && transform.rotation.is_finite()
+            && velocity.map_or(true, |v| v.linvel.is_finite() && v.angvel.is_finite());
+        if !finite {
+            continue;
+        }
+
         // Respect update rate
         if networked.last_update + 1.0 / networked.update_rate > seconds {
             continue;
@@ -455,9 +470,23 @@ fn update_transform(
             continue;
         };
 
+        // Creatures controlled by players are always sent at the full rate
+        let throttled = controls.controlling_player(entity).is_none();
+        let update_rate = networked.update_rate;
+
         // TODO: We could group clients by their acked sequence
         for connection in visibility.observers() {
             let client_data = networked.client_data.entry(*connection).or_default();
+            // Clients on bad connections get fewer updates once they know the entity
+            let rate_factor = quality.rate_factor(*connection, &quality_config);
+            if throttled
+                && rate_factor < 1.0
+                && client_data.acked_sequence.is_some()
+                && seconds - client_data.last_sent < 1.0 / (update_rate * rate_factor)
+            {
+                continue;
+            }
+
             // Get the snapshot the client last acknowledged
             let base_snapshot = client_data.acked_sequence.and_then(|sequence| {
                 networked
@@ -481,6 +510,7 @@ fn update_transform(
             });
             let serialized = serialize_once(&message);
             server.send_message(connection.0, Channel::Transforms.id(), serialized.clone());
+            client_data.last_sent = seconds;
         }
     }
 }
@@ -491,6 +521,7 @@ fn handle_acks(
     mut server: ResMut<RenetServer>,
     identities: Res<NetworkIdentities>,
     time: Res<Time>,
+    names: DebugNames,
 ) {
     let seconds = time.raw_elapsed_seconds();
     'clients: for client_id in server.clients_id().into_iter() {
@@ -508,7 +539,7 @@ fn handle_acks(
                         Some(e) => e,
                         None => {
                             warn!(
-                                "Received transform ack for non-existent {:?} from {}",
+                                "Received transform ack for non-existent {} from {}",
                                 ack.identity, client_id
                             );
                             continue;
@@ -518,7 +549,11 @@ fn handle_acks(
                     let mut transform = match query.get_mut(entity) {
                         Ok(t) => t,
                         Err(_) => {
-                            warn!("Received transform ack for entity without network transform {:?} from {}", entity, client_id);
+                            warn!(
+                                "Received transform ack for entity without network transform {} from {}",
+                                names.debug_name(entity),
+                                client_id
+                            );
                             continue;
                         }
                     };
@@ -636,7 +671,7 @@ impl BufferedTransformUpdates {
         if self.updates.len() >= UPDATE_BUFFER_SIZE {
             self.updates.pop_front();
             warn!(
-                "Dropped transform update (buffer full) for {:?}",
+                "Dropped transform update (buffer full) for {}",
                 update.identity
             );
         }
@@ -695,6 +730,7 @@ fn apply_buffered_updates(
     mut query: Query<Option<&mut NetworkedTransform>, With<NetworkIdentity>>,
     identities: Res<NetworkIdentities>,
     mut unique_updates: Local<HashMap<NetworkIdentity, TransformUpdate>>,
+    mut unresolved: ResMut<UnresolvedIdentities>,
     mut commands: Commands,
 ) {
     buffer.updates.retain(|update| {
@@ -767,6 +803,11 @@ fn apply_buffered_updates(
     buffer
         .updates
         .extend(unique_updates.drain().map(|(_, u)| u));
+
+    unresolved.set(
+        "transform",
+        buffer.updates.iter().map(|u| u.identity).collect(),
+    );
 }
 
 /// Applies transform snapshots to entities without physics simulation
@@ -812,6 +853,7 @@ fn sync_networked_transform_physics(
     identities: Res<NetworkIdentities>,
     network_time: Res<ClientNetworkTime>,
     mut commands: Commands,
+    names: DebugNames,
 ) {
     let current_tick = network_time.interpolated_tick();
     for (
@@ -854,7 +896,7 @@ fn sync_networked_transform_physics(
                 if let Some(parent_entity) = identities.get_entity(parent) {
                     commands.entity(entity).set_parent(parent_entity);
                 } else {
-                    warn!(parent_id = ?parent, entity = ?entity, "Transform parent not found");
+                    warn!(parent_id = %parent, entity = %names.debug_name(entity), "Transform parent not found");
                 }
             } else {
                 commands.entity(entity).remove_parent();
@@ -968,3 +1010,144 @@ impl Plugin for TransformPlugin {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use bevy::ecs::system::Command;
+
+    use super::*;
+    use crate::{
+        identity::NetworkCommand, loopback::LinkConditions, quality::LinkTier, testing,
+        visibility::Relevancy, NetworkRole, Players,
+    };
+
+    /// Real time between frames, transform updates and link measurements run on real time
+    const FRAME_TIME: Duration = Duration::from_millis(5);
+    /// How long sent updates are counted for
+    const MEASURE_TIME: Duration = Duration::from_secs(2);
+    const MAX_WAIT: Duration = Duration::from_secs(15);
+
+    /// Transform updates sent to each connection
+    #[derive(Resource, Default)]
+    struct SentUpdates(HashMap<ConnectionId, u32>);
+
+    fn count_sent_updates(
+        query: Query<&NetworkTransform>,
+        mut sent: ResMut<SentUpdates>,
+        mut last_sent: Local<HashMap<ConnectionId, f32>>,
+    ) {
+        for networked in query.iter() {
+            for (connection, data) in networked.client_data.iter() {
+                if last_sent.insert(*connection, data.last_sent) != Some(data.last_sent) {
+                    *sent.0.entry(*connection).or_default() += 1;
+                }
+            }
+        }
+    }
+
+    #[derive(Component)]
+    struct Moving;
+
+    fn move_entities(mut query: Query<&mut Transform, With<Moving>>) {
+        for mut transform in query.iter_mut() {
+            transform.translation.x += 0.1;
+        }
+    }
+
+    fn update_for(server: &mut App, clients: &mut [&mut App], duration: Duration) {
+        let start = Instant::now();
+        while start.elapsed() < duration {
+            testing::update(server, clients, 1);
+            std::thread::sleep(FRAME_TIME);
+        }
+    }
+
+    fn new_connection(server: &App, known: &[ConnectionId]) -> ConnectionId {
+        *server
+            .world
+            .resource::<Players>()
+            .players()
+            .keys()
+            .find(|c| !known.contains(c))
+            .unwrap()
+    }
+
+    #[test]
+    fn packet_loss_only_throttles_the_lossy_client() {
+        let mut server = testing::app(NetworkRole::Server);
+        server
+            .insert_resource(LinkQualityConfig {
+                downgrade_seconds: 0.5,
+                ..Default::default()
+            })
+            .init_resource::<SentUpdates>()
+            .add_systems(Update, move_entities)
+            .add_systems(PostUpdate, count_sent_updates.after(update_transform));
+        let connector = testing::listen(&mut server);
+
+        let mut good = testing::app(NetworkRole::Client);
+        testing::join(&mut good, &connector, LinkConditions::default());
+        testing::connect(&mut server, &mut [&mut good], 200);
+        let good_connection = new_connection(&server, &[]);
+
+        let mut lossy = testing::app(NetworkRole::Client);
+        testing::join(
+            &mut lossy,
+            &connector,
+            LinkConditions {
+                loss: 0.3,
+                seed: 11,
+                ..Default::default()
+            },
+        );
+        testing::connect(&mut server, &mut [&mut good, &mut lossy], 2000);
+        let lossy_connection = new_connection(&server, &[good_connection]);
+
+        let entity = server
+            .world
+            .spawn((
+                SpatialBundle::default(),
+                NetworkTransform::default(),
+                Moving,
+            ))
+            .id();
+        NetworkCommand { entity }.apply(&mut server.world);
+        let owner = server.world.spawn_empty().id();
+        let mut relevancy = server.world.resource_mut::<Relevancy>();
+        relevancy.add_viewer(owner, entity, good_connection);
+        relevancy.add_viewer(owner, entity, lossy_connection);
+
+        let start = Instant::now();
+        while server
+            .world
+            .resource::<LinkQuality>()
+            .tier(lossy_connection)
+            == LinkTier::Good
+        {
+            assert!(
+                start.elapsed() < MAX_WAIT,
+                "Lossy connection was never downgraded"
+            );
+            update_for(&mut server, &mut [&mut good, &mut lossy], FRAME_TIME);
+        }
+
+        server.world.resource_mut::<SentUpdates>().0.clear();
+        update_for(&mut server, &mut [&mut good, &mut lossy], MEASURE_TIME);
+
+        let quality = server.world.resource::<LinkQuality>();
+        assert_eq!(quality.tier(good_connection), LinkTier::Good);
+        assert_ne!(quality.tier(lossy_connection), LinkTier::Good);
+        let sent = &server.world.resource::<SentUpdates>().0;
+        let good_sent = sent.get(&good_connection).copied().unwrap_or_default();
+        let lossy_sent = sent.get(&lossy_connection).copied().unwrap_or_default();
+        assert!(good_sent > 0);
+        assert!(
+            (lossy_sent as f32) < good_sent as f32 * 0.75,
+            "good client got {} updates, lossy client {}",
+            good_sent,
+            lossy_sent
+        );
+    }
+}
@@ -1,20 +1,26 @@
 use std::{
     any::TypeId,
     borrow::Cow,
+    cell::Cell,
+    fmt::Debug,
     marker::PhantomData,
     ops::{Deref, DerefMut},
 };
 
 use bevy::{
-    ecs::system::{StaticSystemParam, SystemParam},
-    prelude::Resource,
+    ecs::{
+        component::Tick,
+        system::{StaticSystemParam, SystemMeta, SystemParam},
+        world::unsafe_world_cell::UnsafeWorldCell,
+    },
+    prelude::{Res, Resource, World},
     reflect::TypeUuid,
     utils::Uuid,
 };
 use serde::{Deserialize, Serialize};
 pub use smallvec::SmallVec;
 
-use crate::ConnectionId;
+use crate::{time::ServerNetworkTime, ConnectionId};
 
 pub use bytes::{Buf, BufMut, Bytes, BytesMut};
 // TODO: Replace with handy method
@@ -101,6 +107,133 @@ pub(crate) fn assert_compatible<
     );
 }
 
+/// The system changing networked variables on this thread, and the server tick of its world.
+#[derive(Clone, Copy)]
+struct WriteSource {
+    system: &'static str,
+    tick: u32,
+}
+
+thread_local! {
+    static CURRENT_SOURCE: Cell<Option<WriteSource>> = Cell::new(None);
+}
+
+/// Attributes networked variable changes to `source` until the guard is dropped.
+fn set_write_source(source: Option<WriteSource>) -> WriteSourceGuard {
+    let previous = CURRENT_SOURCE.with(|current| current.replace(source));
+    WriteSourceGuard { previous }
+}
+
+struct WriteSourceGuard {
+    previous: Option<WriteSource>,
+}
+
+impl Drop for WriteSourceGuard {
+    fn drop(&mut self) {
+        CURRENT_SOURCE.with(|current| current.set(self.previous));
+    }
+}
+
+/// Attributes every networked variable the system changes to the system, while it runs.
+/// In debug builds, variables changed by two systems with this parameter in the same server tick are logged,
+/// as the value sent depends on system order. Changes from systems without it aren't checked.
+pub struct NetworkWriter {
+    _guard: WriteSourceGuard,
+}
+
+// SAFETY: Only reads the server time, which is registered as a resource read in the system meta
+unsafe impl SystemParam for NetworkWriter {
+    type State = (
+        &'static str,
+        <Option<Res<'static, ServerNetworkTime>> as SystemParam>::State,
+    );
+    type Item<'w, 's> = NetworkWriter;
+
+    fn init_state(world: &mut World, system_meta: &mut SystemMeta) -> Self::State {
+        // Once per system, so leaking the name is fine
+        let name: &'static str = Box::leak(system_meta.name().to_owned().into_boxed_str());
+        let time = <Option<Res<ServerNetworkTime>> as SystemParam>::init_state(world, system_meta);
+        (name, time)
+    }
+
+    unsafe fn get_param<'w, 's>(
+        (name, time): &'s mut Self::State,
+        system_meta: &SystemMeta,
+        world: UnsafeWorldCell<'w>,
+        change_tick: Tick,
+    ) -> Self::Item<'w, 's> {
+        let time = <Option<Res<ServerNetworkTime>> as SystemParam>::get_param(
+            time,
+            system_meta,
+            world,
+            change_tick,
+        );
+        // Clients don't track writes, they only have server variables
+        let source = time.map(|time| WriteSource {
+            system: *name,
+            tick: time.current_tick(),
+        });
+        // The param is dropped when the system returns, which resets the source
+        NetworkWriter {
+            _guard: set_write_source(source),
+        }
+    }
+}
+
+/// Sources that changed a variable in the current tick. Only tracked in debug builds.
+#[cfg(debug_assertions)]
+#[derive(Default)]
+struct WriteLog {
+    tick: u32,
+    first: Option<&'static str>,
+    /// A different system that changed the variable after `first`, and the value `first` left.
+    /// Reported once the final value of the tick is known.
+    conflict: Option<(&'static str, String)>,
+    /// Set once a conflict was reported, to only warn once per tick
+    reported: bool,
+}
+
+#[cfg(debug_assertions)]
+impl WriteLog {
+    /// Records a change by `source`, before it happens. `current` is the value up to now.
+    fn record<T: Debug>(&mut self, source: WriteSource, current: &T) {
+        if self.tick != source.tick {
+            // The value is still what the last tick ended with
+            self.report(current);
+            *self = WriteLog {
+                tick: source.tick,
+                first: Some(source.system),
+                ..Default::default()
+            };
+            return;
+        }
+
+        let Some(first) = self.first else {
+            self.first = Some(source.system);
+            return;
+        };
+        if first != source.system && !self.reported && self.conflict.is_none() {
+            self.conflict = Some((source.system, format!("{:?}", current)));
+        }
+    }
+
+    /// Logs a conflict found in this tick, with the values both sources wanted.
+    fn report<T: Debug>(&mut self, value: &T) {
+        let (Some(first), Some((second, first_value))) = (self.first, self.conflict.take()) else {
+            return;
+        };
+        self.reported = true;
+        bevy::log::warn!(
+            variable = std::any::type_name::<T>(),
+            %first,
+            first_value = %first_value,
+            %second,
+            second_value = ?value,
+            "Networked variable changed by multiple sources in one tick, the result depends on system order"
+        );
+    }
+}
+
 /// A variable that is networked to clients.
 pub struct NetworkVar<T> {
     value: T,
@@ -108,6 +241,8 @@ pub struct NetworkVar<T> {
     /// Used to diff the most recent change.
     last_value: Option<T>,
     change_state: ChangeState,
+    #[cfg(debug_assertions)]
+    writes: WriteLog,
 }
 
 impl<T: Default> Default for NetworkVar<T> {
@@ -118,6 +253,8 @@ impl<T: Default> Default for NetworkVar<T> {
             change_state: ChangeState::Clean {
                 last_changed_tick: 0,
             },
+            #[cfg(debug_assertions)]
+            writes: Default::default(),
         }
     }
 }
@@ -131,7 +268,13 @@ impl<T> NetworkVar<T> {
         }
     }
 
-    pub fn update_state(&mut self, tick: u32) -> bool {
+    pub fn update_state(&mut self, tick: u32) -> bool
+    where
+        T: Debug,
+    {
+        #[cfg(debug_assertions)]
+        self.writes.report(&self.value);
+
         if matches!(self.change_state, ChangeState::Dirty) {
             self.change_state = ChangeState::Clean {
                 last_changed_tick: tick,
@@ -152,6 +295,8 @@ impl<T> NetworkVar<T> {
             change_state: ChangeState::Clean {
                 last_changed_tick: 0,
             },
+            #[cfg(debug_assertions)]
+            writes: Default::default(),
         }
     }
 }
@@ -162,6 +307,8 @@ impl<T> From<T> for NetworkVar<T> {
             value,
             last_value: None,
             change_state: ChangeState::Dirty,
+            #[cfg(debug_assertions)]
+            writes: Default::default(),
         }
     }
 }
@@ -176,9 +323,16 @@ impl<T> Deref for NetworkVar<T> {
 
 impl<T> DerefMut for NetworkVar<T>
 where
-    T: Clone,
+    T: Clone + Debug,
 {
     fn deref_mut(&mut self) -> &mut Self::Target {
+        #[cfg(debug_assertions)]
+        {
+            if let Some(source) = CURRENT_SOURCE.with(|source| source.get()) {
+                self.writes.record(source, &self.value);
+            }
+        }
+
         self.last_value = Some(self.value.clone());
         self.change_state = ChangeState::Dirty;
         &mut self.value
@@ -316,3 +470,144 @@ impl<T: Into<u16> + From<u16>> NetworkRegistry<T> {
         self.entries.get(id.into() as usize)
     }
 }
+
+#[cfg(all(test, debug_assertions))]
+mod tests {
+    use bevy::{ecs::schedule::ExecutorKind, prelude::*};
+
+    use super::*;
+    use crate::{testing, NetworkRole};
+
+    #[derive(Resource, Default)]
+    struct Counter(NetworkVar<u32>);
+
+    fn write_as(system: &'static str, tick: u32) -> WriteSourceGuard {
+        set_write_source(Some(WriteSource { system, tick }))
+    }
+
+    #[test]
+    fn different_sources_conflict() {
+        let mut var = NetworkVar::from(0u32);
+        {
+            let _source = write_as("damage", 1);
+            *var = 5;
+        }
+        {
+            let _source = write_as("heal", 1);
+            *var = 10;
+        }
+        let (second, first_value) = var.writes.conflict.clone().expect("no conflict");
+        assert_eq!(var.writes.first, Some("damage"));
+        assert_eq!(second, "heal");
+        assert_eq!(first_value, "5");
+
+        // Reported when the tick is sent, and only once
+        var.update_state(1);
+        assert!(var.writes.conflict.is_none());
+        assert!(var.writes.reported);
+    }
+
+    #[test]
+    fn same_source_doesnt_conflict() {
+        let mut var = NetworkVar::from(0u32);
+        for value in 1..4 {
+            let _source = write_as("damage", 1);
+            *var = value;
+        }
+        assert!(var.writes.conflict.is_none());
+    }
+
+    #[test]
+    fn new_tick_forgets_sources() {
+        let mut var = NetworkVar::from(0u32);
+        {
+            let _source = write_as("damage", 1);
+            *var = 5;
+        }
+        {
+            let _source = write_as("heal", 2);
+            *var = 10;
+        }
+        assert!(var.writes.conflict.is_none());
+        assert_eq!(var.writes.first, Some("heal"));
+    }
+
+    /// A server app running its systems on this thread, to see the source after them.
+    fn server() -> App {
+        let mut app = testing::app(NetworkRole::Server);
+        testing::listen(&mut app);
+        app.init_resource::<Counter>();
+        app.edit_schedule(Update, |schedule| {
+            schedule.set_executor_kind(ExecutorKind::SingleThreaded);
+        });
+        app
+    }
+
+    #[test]
+    fn writer_attributes_writes_to_system() {
+        fn named(mut counter: ResMut<Counter>, _writer: NetworkWriter) {
+            *counter.0 += 1;
+            *counter.0 += 1;
+        }
+
+        let mut app = server();
+        app.add_systems(Update, named);
+        app.update();
+        let counter = app.world.resource::<Counter>();
+        assert!(counter.0.writes.conflict.is_none());
+        assert!(matches!(
+            counter.0.writes.first,
+            Some(name) if name.ends_with("named")
+        ));
+        assert_eq!(
+            counter.0.writes.tick,
+            app.world.resource::<ServerNetworkTime>().current_tick()
+        );
+        // The source is reset once the system returns
+        assert!(CURRENT_SOURCE.with(|source| source.get()).is_none());
+    }
+
+    #[test]
+    fn writers_in_one_tick_conflict() {
+        fn damage(mut counter: ResMut<Counter>, _writer: NetworkWriter) {
+            *counter.0 += 1;
+        }
+        fn heal(mut counter: ResMut<Counter>, _writer: NetworkWriter) {
+            *counter.0 += 1;
+        }
+
+        let mut app = server();
+        app.add_systems(Update, (damage, heal).chain());
+        app.update();
+        assert!(app.world.resource::<Counter>().0.writes.conflict.is_some());
+    }
+
+    #[test]
+    fn writers_in_different_ticks_dont_conflict() {
+        fn damage(mut counter: ResMut<Counter>, _writer: NetworkWriter) {
+            *counter.0 += 1;
+        }
+
+        let mut app = server();
+        app.add_systems(Update, damage);
+        for _ in 0..3 {
+            app.update();
+            assert!(app.world.resource::<Counter>().0.writes.conflict.is_none());
+        }
+    }
+
+    #[test]
+    fn writes_without_a_writer_arent_tracked() {
+        fn unnamed(mut counter: ResMut<Counter>) {
+            *counter.0 += 1;
+            *counter.0 += 1;
+        }
+
+        let mut app = server();
+        app.add_systems(Update, unnamed);
+        app.update();
+        let counter = app.world.resource::<Counter>();
+        assert!(counter.0.writes.first.is_none());
+        assert!(counter.0.writes.conflict.is_none());
+    }
+}
@@ -1,5 +1,5 @@
 use bevy::{
-    math::{IVec2, Vec2, Vec3Swizzles},
+    math::{IVec2, Vec2, Vec3, Vec3Swizzles},
     prelude::*,
     time::Time,
     transform::TransformSystem,
@@ -78,6 +78,12 @@ impl NetworkVisibility {
         }
     }
 
+    /// Forgets a connection was observing, so it is a new observer again if it still is next frame.
+    /// The entity is spawned for it again, without despawning it first.
+    pub(crate) fn forget_observer(&mut self, connection: ConnectionId) {
+        self.observers.remove(&connection);
+    }
+
     /// Marks all observer states as removed.
     /// Panics if called between visibility modification and update.
     fn assume_removed(&mut self) {
@@ -146,6 +152,10 @@ pub struct NetworkVisibilities {
 }
 
 impl NetworkVisibilities {
+    pub fn get(&self, identity: NetworkIdentity) -> Option<&NetworkVisibility> {
+        self.visibility.get(&identity)
+    }
+
     pub fn get_mut(&mut self, identity: NetworkIdentity) -> Option<&mut NetworkVisibility> {
         self.visibility.get_mut(&identity)
     }
@@ -363,6 +373,183 @@ fn always_visible(
     }
 }
 
+/// Something that can be made visible to a connection independent of distance.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum RelevancyTarget {
+    /// A single networked entity
+    Entity(Entity),
+    /// All entities in a cell of the global grid
+    Chunk(IVec2),
+}
+
+impl RelevancyTarget {
+    /// The chunk containing a world position.
+    pub fn chunk_at(position: Vec3) -> Self {
+        Self::Chunk(chunk_position(position))
+    }
+
+    /// The chunk containing a world position and its neighbours.
+    pub fn chunks_around(position: Vec3) -> impl Iterator<Item = Self> {
+        let center = chunk_position(position);
+        (-1..=1).flat_map(move |x| (-1..=1).map(move |y| Self::Chunk(center + IVec2::new(x, y))))
+    }
+}
+
+pub(crate) fn chunk_position(position: Vec3) -> IVec2 {
+    let size = i32::from(GLOBAL_GRID_CELL_SIZE);
+    position.xz().as_ivec2() / IVec2::new(size, size)
+}
+
+impl From<Entity> for RelevancyTarget {
+    fn from(entity: Entity) -> Self {
+        Self::Entity(entity)
+    }
+}
+
+/// Explicit visibility overrides on top of the range based visibility.
+///
+/// Every override belongs to an owner entity (like a console session).
+/// Overrides are removed when their owner is despawned or the connection is closed.
+/// Multiple overrides for the same target and connection are allowed,
+/// the target stays visible until all of them are removed.
+#[derive(Default, Resource)]
+pub struct Relevancy {
+    overrides: HashMap<Entity, HashSet<(RelevancyTarget, ConnectionId)>>,
+}
+
+impl Relevancy {
+    pub fn add_viewer(
+        &mut self,
+        owner: Entity,
+        target: impl Into<RelevancyTarget>,
+        connection: ConnectionId,
+    ) {
+        self.overrides
+            .entry(owner)
+            .or_default()
+            .insert((target.into(), connection));
+    }
+
+    pub fn remove_viewer(
+        &mut self,
+        owner: Entity,
+        target: impl Into<RelevancyTarget>,
+        connection: ConnectionId,
+    ) {
+        if let Some(overrides) = self.overrides.get_mut(&owner) {
+            overrides.remove(&(target.into(), connection));
+            if overrides.is_empty() {
+                self.overrides.remove(&owner);
+            }
+        }
+    }
+
+    /// Replaces all overrides of an owner for a connection.
+    pub fn set_viewers(
+        &mut self,
+        owner: Entity,
+        targets: impl IntoIterator<Item = RelevancyTarget>,
+        connection: ConnectionId,
+    ) {
+        let overrides = self.overrides.entry(owner).or_default();
+        overrides.retain(|&(_, c)| c != connection);
+        overrides.extend(targets.into_iter().map(|t| (t, connection)));
+    }
+
+    /// Removes all overrides of an owner.
+    pub fn remove_owner(&mut self, owner: Entity) {
+        self.overrides.remove(&owner);
+    }
+}
+
+fn relevancy_visibility(
+    mut relevancy: ResMut<Relevancy>,
+    mut visibilities: ResMut<NetworkVisibilities>,
+    players: Res<Players>,
+    grid: Res<GlobalGrid>,
+    entities: Query<()>,
+    identities: Query<&NetworkIdentity>,
+) {
+    // Drop overrides of despawned owners and closed connections
+    relevancy.overrides.retain(|owner, overrides| {
+        overrides.retain(|(_, connection)| players.get(*connection).is_some());
+        entities.contains(*owner) && !overrides.is_empty()
+    });
+
+    // Observers are idempotent per connection, so overlapping overrides
+    // and range based visibility don't spawn an entity twice
+    for &(target, connection) in relevancy.overrides.values().flatten() {
+        match target {
+            RelevancyTarget::Entity(entity) => {
+                if let Ok(identity) = identities.get(entity) {
+                    let visibility = visibilities.visibility.entry(*identity).or_default();
+                    visibility.add_observer(connection);
+                }
+            }
+            RelevancyTarget::Chunk(position) => {
+                let Some(cell) = grid.cells.get(&position) else {
+                    continue;
+                };
+                for identity in identities.iter_many(&cell.entities) {
+                    let visibility = visibilities.visibility.entry(*identity).or_default();
+                    visibility.add_observer(connection);
+                }
+            }
+        }
+    }
+}
+
+/// Entities hidden from connections even though they are in range.
+///
+/// Applied after range based visibility, so [`AlwaysVisible`] and [`Relevancy`] overrides still show hidden entities.
+#[derive(Default, Resource)]
+pub struct Concealment {
+    hidden: HashMap<ConnectionId, HashSet<Entity>>,
+}
+
+impl Concealment {
+    /// Replaces all entities hidden from a connection.
+    pub fn set_hidden(
+        &mut self,
+        connection: ConnectionId,
+        entities: impl IntoIterator<Item = Entity>,
+    ) {
+        let hidden = self.hidden.entry(connection).or_default();
+        hidden.clear();
+        hidden.extend(entities);
+    }
+
+    pub fn clear(&mut self) {
+        self.hidden.clear();
+    }
+
+    pub fn is_hidden(&self, connection: ConnectionId, entity: Entity) -> bool {
+        self.hidden
+            .get(&connection)
+            .map(|h| h.contains(&entity))
+            .unwrap_or(false)
+    }
+}
+
+fn concealment_visibility(
+    mut concealment: ResMut<Concealment>,
+    mut visibilities: ResMut<NetworkVisibilities>,
+    players: Res<Players>,
+    identities: Query<&NetworkIdentity>,
+) {
+    concealment
+        .hidden
+        .retain(|connection, hidden| players.get(*connection).is_some() && !hidden.is_empty());
+
+    for (&connection, hidden) in concealment.hidden.iter() {
+        for identity in identities.iter_many(hidden) {
+            if let Some(visibility) = visibilities.visibility.get_mut(identity) {
+                visibility.remove_observer(connection);
+            }
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, SystemSet)]
 pub enum VisibilitySystem {
     UpdateGrid,
@@ -380,6 +567,8 @@ impl Plugin for VisibilityPlugin {
             .is_server()
         {
             app.init_resource::<NetworkVisibilities>()
+                .init_resource::<Relevancy>()
+                .init_resource::<Concealment>()
                 .insert_resource(GlobalGrid {
                     cell_size: GLOBAL_GRID_CELL_SIZE,
                     ..Default::default()
@@ -389,7 +578,9 @@ impl Plugin for VisibilityPlugin {
                     (
                         update_visibility,
                         grid_visibility.in_set(VisibilitySystem::GridVisibility),
+                        concealment_visibility,
                         always_visible,
+                        relevancy_visibility,
                     )
                         .chain()
                         .in_set(NetworkSet::ServerVisibility),
@@ -0,0 +1,52 @@
+use networking::{
+    loopback::LinkConditions,
+    testing::{self, connect},
+    NetworkRole, Players,
+};
+
+/// Sockets this process has open
+#[cfg(target_os = "linux")]
+fn open_sockets() -> usize {
+    std::fs::read_dir("/proc/self/fd")
+        .unwrap()
+        .filter_map(|entry| std::fs::read_link(entry.ok()?.path()).ok())
+        .filter(|target| target.to_string_lossy().starts_with("socket:"))
+        .count()
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn harness_opens_no_sockets() {
+    let before = open_sockets();
+
+    let mut server = testing::app(NetworkRole::Server);
+    let mut client = testing::app(NetworkRole::Client);
+    let connector = testing::listen(&mut server);
+    testing::join(&mut client, &connector, LinkConditions::default());
+    connect(&mut server, &mut [&mut client], 100);
+    testing::update(&mut server, &mut [&mut client], 10);
+
+    assert_eq!(open_sockets(), before);
+}
+
+#[test]
+fn clients_join_over_bad_links() {
+    let mut server = testing::app(NetworkRole::Server);
+    let mut good = testing::app(NetworkRole::Client);
+    let mut bad = testing::app(NetworkRole::Client);
+    let connector = testing::listen(&mut server);
+    testing::join(&mut good, &connector, LinkConditions::default());
+    testing::join(
+        &mut bad,
+        &connector,
+        LinkConditions {
+            loss: 0.3,
+            seed: 1,
+            ..Default::default()
+        },
+    );
+
+    // Renet resends lost reliable messages, so the handshake gets through
+    connect(&mut server, &mut [&mut good, &mut bad], 1000);
+    assert_eq!(server.world.resource::<Players>().players().len(), 2);
+}
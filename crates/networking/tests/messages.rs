@@ -0,0 +1,90 @@
+use bevy::prelude::*;
+use networking::{
+    loopback::LinkConditions,
+    messaging::{AppExt, MessageEvent, MessageReceivers, MessageSender, UnknownMessages},
+    testing, NetworkRole, Players, ServerMissingMessages,
+};
+use serde::{Deserialize, Serialize};
+
+/// Known by both sides
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+struct Shared(u32);
+
+/// Only known by the server
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+struct ServerOnly(u32);
+
+/// Only known by the client
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+struct ClientOnly(u32);
+
+#[derive(Resource, Default)]
+struct Received(Vec<Shared>);
+
+/// Sends a message the client doesn't know, followed by one it does, once a player joined.
+fn send_messages(players: Res<Players>, mut sender: MessageSender, mut sent: Local<bool>) {
+    if *sent || players.players().is_empty() {
+        return;
+    }
+    *sent = true;
+    sender.send(&ServerOnly(1), MessageReceivers::AllPlayers);
+    sender.send(&Shared(2), MessageReceivers::AllPlayers);
+    sender.send(&ServerOnly(3), MessageReceivers::AllPlayers);
+    sender.send(&Shared(4), MessageReceivers::AllPlayers);
+}
+
+fn receive_messages(mut events: EventReader<MessageEvent<Shared>>, mut received: ResMut<Received>) {
+    received.0.extend(events.iter().map(|event| event.message));
+}
+
+fn apps() -> (App, App) {
+    let mut server = testing::app(NetworkRole::Server);
+    server
+        .add_network_message::<Shared>("Shared")
+        .add_network_message::<ServerOnly>("ServerOnly")
+        .add_systems(Update, send_messages);
+
+    let mut client = testing::app(NetworkRole::Client);
+    client
+        .add_network_message::<ClientOnly>("ClientOnly")
+        .add_network_message::<Shared>("Shared")
+        .init_resource::<Received>()
+        .add_systems(Update, receive_messages);
+
+    let connector = testing::listen(&mut server);
+    testing::join(&mut client, &connector, LinkConditions::default());
+    testing::connect(&mut server, &mut [&mut client], 100);
+    (server, client)
+}
+
+#[test]
+fn client_missing_a_message_still_plays() {
+    let (mut server, mut client) = apps();
+    testing::update(&mut server, &mut [&mut client], 20);
+
+    // The unknown messages are skipped without breaking the ones after them
+    assert_eq!(
+        client.world.resource::<Received>().0,
+        vec![Shared(2), Shared(4)]
+    );
+    let unknown = client.world.resource::<UnknownMessages>();
+    assert_eq!(unknown.counts.len(), 1);
+    assert_eq!(
+        unknown.counts.values().copied().collect::<Vec<_>>(),
+        vec![2]
+    );
+    assert!(testing::is_connected(&client));
+}
+
+#[test]
+fn handshake_reports_exact_delta() {
+    let (server, client) = apps();
+
+    let players = server.world.resource::<Players>();
+    let player = players.players().values().next().unwrap();
+    assert_eq!(player.missing_messages, vec!["ServerOnly"]);
+    assert_eq!(
+        client.world.resource::<ServerMissingMessages>().0,
+        vec!["ClientOnly"]
+    );
+}
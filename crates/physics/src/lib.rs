@@ -63,10 +63,20 @@ pub enum ColliderGroup {
     Default,
     CharacterColliders,
     AttachedLimbs,
+    Passable,
+    Corpse,
+    Static,
+    Item,
 }
 
 pub const DEFAULT_GROUP: Group = Group::GROUP_1;
 pub const LIMB_GROUP: Group = Group::GROUP_3;
+pub const PASSABLE_GROUP: Group = Group::GROUP_4;
+pub const CORPSE_GROUP: Group = Group::GROUP_5;
+pub const STATIC_GROUP: Group = Group::GROUP_6;
+pub const ITEM_GROUP: Group = Group::GROUP_7;
+/// Everything that blocks sight and reach, for raycasts that only care about the level
+pub const OBSTACLE_GROUPS: Group = DEFAULT_GROUP.union(STATIC_GROUP);
 pub const RAYCASTING_GROUP: Group = Group::GROUP_32;
 
 impl From<ColliderGroup> for CollisionGroups {
@@ -77,6 +87,22 @@ impl From<ColliderGroup> for CollisionGroups {
             ColliderGroup::CharacterColliders => CollisionGroups::new(Group::GROUP_2, Group::ALL),
             // Limbs attached to bodies collide with raycasts
             ColliderGroup::AttachedLimbs => CollisionGroups::new(LIMB_GROUP, RAYCASTING_GROUP),
+            // Objects that can be clicked but not bumped into (ex. open doors)
+            ColliderGroup::Passable => CollisionGroups::new(PASSABLE_GROUP, RAYCASTING_GROUP),
+            // Bodies lying on the floor, which characters can walk over
+            ColliderGroup::Corpse => CollisionGroups::new(
+                CORPSE_GROUP,
+                DEFAULT_GROUP | STATIC_GROUP | CORPSE_GROUP | RAYCASTING_GROUP,
+            ),
+            // Walls and other turfs that never move. They don't need to check against each other
+            ColliderGroup::Static => {
+                CollisionGroups::new(STATIC_GROUP, Group::ALL.difference(STATIC_GROUP))
+            }
+            // Loose items, which characters walk through instead of kicking around
+            ColliderGroup::Item => CollisionGroups::new(
+                ITEM_GROUP,
+                DEFAULT_GROUP | STATIC_GROUP | ITEM_GROUP | RAYCASTING_GROUP,
+            ),
         }
     }
 }
@@ -89,6 +115,20 @@ impl TryFrom<CollisionGroups> for ColliderGroup {
             (DEFAULT_GROUP, Group::ALL) => Ok(ColliderGroup::Default),
             (Group::GROUP_2, Group::ALL) => Ok(ColliderGroup::CharacterColliders),
             (LIMB_GROUP, RAYCASTING_GROUP) => Ok(ColliderGroup::AttachedLimbs),
+            (PASSABLE_GROUP, RAYCASTING_GROUP) => Ok(ColliderGroup::Passable),
+            (CORPSE_GROUP, filters)
+                if filters == DEFAULT_GROUP | STATIC_GROUP | CORPSE_GROUP | RAYCASTING_GROUP =>
+            {
+                Ok(ColliderGroup::Corpse)
+            }
+            (STATIC_GROUP, filters) if filters == Group::ALL.difference(STATIC_GROUP) => {
+                Ok(ColliderGroup::Static)
+            }
+            (ITEM_GROUP, filters)
+                if filters == DEFAULT_GROUP | STATIC_GROUP | ITEM_GROUP | RAYCASTING_GROUP =>
+            {
+                Ok(ColliderGroup::Item)
+            }
             _ => {
                 bevy::log::info!("Error converting collision groups {:?}", value);
                 Err(())
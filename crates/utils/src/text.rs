@@ -6,3 +6,12 @@ pub fn truncate(s: &str, max_chars: usize) -> Cow<str> {
         Some((idx, _)) => [&s[..idx - 3], "..."].concat().into(),
     }
 }
+
+/// Removes control characters and surrounding whitespace from player provided text.
+pub fn sanitize(s: &str, max_chars: usize) -> String {
+    s.trim()
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(max_chars)
+        .collect()
+}
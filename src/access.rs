@@ -0,0 +1,110 @@
+use bevy::{
+    ecs::system::SystemParam,
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+use networking::is_server;
+use serde::Deserialize;
+
+use crate::{
+    body::Hand, config::ServerConfig, interaction::ValidationBypass, items::clothes::ClothingHolder,
+};
+
+/// Lets doors and machines only be used by creatures with the right ID card.
+pub struct AccessPlugin;
+
+impl Plugin for AccessPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<IdCard>()
+            .register_type::<RequiresAccess>();
+
+        if is_server(app) {
+            let grants = app
+                .world
+                .get_resource::<ServerConfig>()
+                .map(|config| config.access_grants.clone())
+                .unwrap_or_default();
+            app.insert_resource(grants);
+        }
+    }
+}
+
+/// An item that grants its wearer or holder access.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct IdCard {
+    pub accesses: Vec<String>,
+    /// The job shown on the card
+    pub title: String,
+}
+
+/// Which accesses a card can give to other cards at an ID card console, by the access it needs for that.
+#[derive(Deserialize, Resource, Clone)]
+pub struct AccessGrants(pub HashMap<String, Vec<String>>);
+
+impl Default for AccessGrants {
+    fn default() -> Self {
+        let all = ["command", "security", "medical", "engineering"].map(String::from);
+        Self([("command".to_owned(), all.to_vec())].into_iter().collect())
+    }
+}
+
+impl AccessGrants {
+    /// All accesses the holder of the given accesses can grant, sorted by name.
+    pub fn grantable<'a>(&self, accesses: impl Iterator<Item = &'a String>) -> Vec<String> {
+        let grantable: HashSet<&String> = accesses
+            .filter_map(|access| self.0.get(access))
+            .flatten()
+            .collect();
+        let mut grantable: Vec<String> = grantable.into_iter().cloned().collect();
+        grantable.sort_unstable();
+        grantable
+    }
+}
+
+/// Restricts interactions with an object to creatures with one of the accesses.
+/// An empty list doesn't restrict anything.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct RequiresAccess {
+    pub accesses: Vec<String>,
+}
+
+impl RequiresAccess {
+    pub fn allows<'a>(&self, mut accesses: impl Iterator<Item = &'a String>) -> bool {
+        self.accesses.is_empty() || accesses.any(|access| self.accesses.contains(access))
+    }
+}
+
+/// Checks the ID cards a creature has against the access an object requires.
+#[derive(SystemParam)]
+pub struct AccessReader<'w, 's> {
+    children: Query<'w, 's, &'static Children>,
+    cards: Query<'w, 's, (&'static IdCard, &'static Parent)>,
+    slots: Query<'w, 's, (), Or<(With<Hand>, With<ClothingHolder>)>>,
+    requirements: Query<'w, 's, &'static RequiresAccess>,
+    bypass: Query<'w, 's, (), With<ValidationBypass>>,
+}
+
+impl<'w, 's> AccessReader<'w, 's> {
+    /// All accesses of the ID cards a creature is holding or wearing.
+    /// Cards inside of bags don't count.
+    pub fn accesses(&self, creature: Entity) -> impl Iterator<Item = &String> + '_ {
+        self.children
+            .iter_descendants(creature)
+            .filter_map(|e| self.cards.get(e).ok())
+            .filter(|(_, parent)| self.slots.contains(parent.get()))
+            .flat_map(|(card, _)| card.accesses.iter())
+    }
+
+    /// If a creature has the access to use an object. Objects without [`RequiresAccess`] can always be used.
+    pub fn can_access(&self, creature: Entity, target: Entity) -> bool {
+        if self.bypass.contains(creature) {
+            return true;
+        }
+        match self.requirements.get(target) {
+            Ok(requirement) => requirement.allows(self.accesses(creature)),
+            Err(_) => true,
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiSettings};
+
+/// Client options for players that have trouble telling colors apart, with flashing or with small text.
+/// The server only sends what something means, like a fire alarm or being blinded,
+/// so the client is free to show it in a different way.
+pub struct AccessibilityPlugin;
+
+impl Plugin for AccessibilityPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<AccessibilitySettings>()
+            .add_systems(Update, apply_ui_scale);
+    }
+}
+
+/// Player preferences for accessibility.
+#[derive(Resource)]
+pub struct AccessibilitySettings {
+    pub palette: Palette,
+    /// Replaces flashing and strobing effects with static ones that mean the same
+    pub reduce_flashing: bool,
+    /// Size of the interface, 1 being the normal size
+    pub ui_scale: f32,
+}
+
+impl Default for AccessibilitySettings {
+    fn default() -> Self {
+        Self {
+            palette: Palette::Standard,
+            reduce_flashing: false,
+            ui_scale: 1.0,
+        }
+    }
+}
+
+/// Colors used for alerts and wires.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Palette {
+    Standard,
+    /// Avoids telling red and green apart
+    RedGreen,
+    /// Avoids telling blue and yellow apart
+    BlueYellow,
+}
+
+impl Palette {
+    pub const ALL: [Palette; 3] = [Palette::Standard, Palette::RedGreen, Palette::BlueYellow];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Palette::Standard => "Standard",
+            Palette::RedGreen => "Red-green colorblind",
+            Palette::BlueYellow => "Blue-yellow colorblind",
+        }
+    }
+}
+
+/// What a color means in the interface. The palette picks the actual color.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum UiColor {
+    /// Something bad or not allowed, like a wanted criminal or an item that doesn't fit
+    Danger,
+    /// Something to look out for
+    Warning,
+    /// Something fine or allowed
+    Safe,
+}
+
+impl AccessibilitySettings {
+    pub fn color(&self, color: UiColor) -> egui::Color32 {
+        match (self.palette, color) {
+            (Palette::Standard, UiColor::Danger) => egui::Color32::RED,
+            (Palette::Standard, UiColor::Warning) => egui::Color32::YELLOW,
+            (Palette::Standard, UiColor::Safe) => egui::Color32::GREEN,
+            (Palette::RedGreen, UiColor::Danger) => egui::Color32::from_rgb(230, 97, 0),
+            (Palette::RedGreen, UiColor::Warning) => egui::Color32::from_rgb(240, 228, 66),
+            (Palette::RedGreen, UiColor::Safe) => egui::Color32::from_rgb(93, 58, 255),
+            (Palette::BlueYellow, UiColor::Danger) => egui::Color32::from_rgb(220, 30, 60),
+            (Palette::BlueYellow, UiColor::Warning) => egui::Color32::from_rgb(255, 130, 200),
+            (Palette::BlueYellow, UiColor::Safe) => egui::Color32::from_rgb(0, 190, 190),
+        }
+    }
+
+    /// Blends from the danger color at 0 to the safe color at 1, like for health.
+    pub fn gradient(&self, fraction: f32) -> egui::Color32 {
+        let danger = egui::Rgba::from(self.color(UiColor::Danger));
+        let safe = egui::Rgba::from(self.color(UiColor::Safe));
+        let fraction = fraction.clamp(0.0, 1.0);
+        (danger * (1.0 - fraction) + safe * fraction).into()
+    }
+
+    /// If colors should come with symbols, so they can be told apart without seeing the color
+    pub fn symbols(&self) -> bool {
+        self.palette != Palette::Standard
+    }
+
+    /// Color of the lights in an area with a fire alarm
+    pub fn alarm_light(&self) -> Color {
+        match self.palette {
+            Palette::Standard => Color::rgb(1.0, 0.1, 0.1),
+            Palette::RedGreen => Color::rgb(1.0, 0.45, 0.0),
+            Palette::BlueYellow => Color::rgb(1.0, 0.1, 0.35),
+        }
+    }
+}
+
+fn apply_ui_scale(settings: Res<AccessibilitySettings>, mut egui: ResMut<EguiSettings>) {
+    if !settings.is_changed() {
+        return;
+    }
+    let scale = settings.ui_scale as f64;
+    if egui.scale_factor != scale {
+        egui.scale_factor = scale;
+    }
+}
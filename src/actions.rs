@@ -0,0 +1,208 @@
+use bevy::{
+    math::Vec3Swizzles,
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+use networking::{
+    identity::{NetworkIdentities, NetworkIdentity},
+    is_server,
+    messaging::{AppExt, MessageEvent, MessageReceivers, MessageSender},
+    spawning::ClientControlled,
+    visibility::NetworkVisibilities,
+    NetworkSet,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    sound::{PlaySoundMessage, SoundId},
+    GameState,
+};
+
+/// Replicates what creatures are doing, so other players can see it.
+pub struct ActionsPlugin;
+
+impl Plugin for ActionsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_network_message::<ActorActionMessage>("ActorActionMessage");
+
+        if is_server(app) {
+            app.add_event::<ActorActionEvent>().add_systems(
+                PostUpdate,
+                // Messages sent later would wait for the next tick
+                broadcast_actions.before(NetworkSet::SendOutgoing),
+            );
+        } else {
+            app.init_resource::<ActionPresentation>().add_systems(
+                Update,
+                (receive_actions, present_actions)
+                    .chain()
+                    .run_if(in_state(GameState::Game)),
+            );
+        }
+    }
+}
+
+/// A notable action a creature started or stopped.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub enum ActorAction {
+    /// Melee attack towards a horizontal direction
+    Swing { direction: Vec2 },
+    /// Fired a ranged weapon
+    Fire,
+    /// Started an interaction that takes some time
+    Channel { duration: f32 },
+    /// The timed interaction ended
+    StopChannel,
+    /// Picked up an item
+    Pickup,
+}
+
+/// Sent by gameplay systems on the server when an actor does something others should see.
+#[derive(Event)]
+pub struct ActorActionEvent {
+    pub actor: Entity,
+    pub action: ActorAction,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ActorActionMessage {
+    actor: NetworkIdentity,
+    action: ActorAction,
+}
+
+fn broadcast_actions(
+    mut events: EventReader<ActorActionEvent>,
+    identities: Query<&NetworkIdentity>,
+    visibilities: Res<NetworkVisibilities>,
+    mut sender: MessageSender,
+) {
+    for event in events.iter() {
+        let Ok(&actor) = identities.get(event.actor) else {
+            continue;
+        };
+        // Only players that can see the actor need to know
+        let Some(visibility) = visibilities.get(actor) else {
+            continue;
+        };
+        let receivers: HashSet<_> = visibility.observers().copied().collect();
+        if receivers.is_empty() {
+            continue;
+        }
+
+        // Losing one of these only loses an animation
+        sender.send_unreliable(
+            &ActorActionMessage {
+                actor,
+                action: event.action,
+            },
+            MessageReceivers::Set(receivers),
+        );
+    }
+}
+
+/// How long a swing is shown
+const SWING_SECONDS: f32 = 0.25;
+/// How far the swing reaches out from the actor
+const SWING_LENGTH: f32 = 1.0;
+/// Height of effects above the actor's origin
+const EFFECT_HEIGHT: f32 = 1.0;
+/// Height of the progress circle above channeling actors
+const CHANNEL_HEIGHT: f32 = 2.2;
+const CHANNEL_RADIUS: f32 = 0.25;
+
+/// Placeholder effects for actions of other creatures.
+#[derive(Resource, Default)]
+struct ActionPresentation {
+    swings: Vec<(Entity, Vec2, f32)>,
+    /// Start time and duration of timed interactions
+    channels: HashMap<Entity, (f32, f32)>,
+}
+
+fn receive_actions(
+    mut messages: EventReader<MessageEvent<ActorActionMessage>>,
+    mut presentation: ResMut<ActionPresentation>,
+    identities: Res<NetworkIdentities>,
+    transforms: Query<&GlobalTransform>,
+    controlled: Query<(), With<ClientControlled>>,
+    mut sounds: EventWriter<PlaySoundMessage>,
+    time: Res<Time>,
+) {
+    let now = time.elapsed_seconds();
+    for event in messages.iter() {
+        let Some(actor) = identities.get_entity(event.message.actor) else {
+            continue;
+        };
+
+        match event.message.action {
+            ActorAction::Swing { direction } => {
+                presentation.swings.push((actor, direction, now));
+            }
+            // Shots already have their own tracer effect
+            ActorAction::Fire => {}
+            // Our own progress is already shown by the interaction UI
+            ActorAction::Channel { .. } if controlled.contains(actor) => {}
+            ActorAction::Channel { duration } => {
+                presentation.channels.insert(actor, (now, duration));
+            }
+            ActorAction::StopChannel => {
+                presentation.channels.remove(&actor);
+            }
+            ActorAction::Pickup => {
+                if let Ok(transform) = transforms.get(actor) {
+                    sounds.send(PlaySoundMessage {
+                        sound: SoundId::ItemPickup,
+                        position: transform.translation(),
+                        surface: None,
+                        impact: None,
+                    });
+                }
+            }
+        }
+    }
+}
+
+fn present_actions(
+    mut presentation: ResMut<ActionPresentation>,
+    transforms: Query<&GlobalTransform>,
+    time: Res<Time>,
+    mut gizmos: Gizmos,
+) {
+    let now = time.elapsed_seconds();
+    // Actors may have left our view
+    presentation
+        .swings
+        .retain(|(actor, _, started)| now - started < SWING_SECONDS && transforms.contains(*actor));
+    presentation.channels.retain(|actor, (started, duration)| {
+        now - *started < *duration && transforms.contains(*actor)
+    });
+
+    for (actor, direction, started) in presentation.swings.iter() {
+        let position = transforms.get(*actor).unwrap().translation() + Vec3::Y * EFFECT_HEIGHT;
+        // Lunge out and back
+        let progress = (now - started) / SWING_SECONDS;
+        let reach = (progress * std::f32::consts::PI).sin() * SWING_LENGTH;
+        let direction = Vec3::new(direction.x, 0.0, direction.y).normalize_or_zero();
+        gizmos.line(position, position + direction * reach, Color::ORANGE);
+    }
+
+    for (actor, (started, duration)) in presentation.channels.iter() {
+        let center = transforms.get(*actor).unwrap().translation() + Vec3::Y * CHANNEL_HEIGHT;
+        let progress = ((now - started) / duration).clamp(0.0, 1.0);
+        gizmos.circle(center, Vec3::Y, CHANNEL_RADIUS, Color::GRAY);
+
+        // Filled part of the circle
+        let segments = (progress * 32.0).ceil() as usize;
+        gizmos.linestrip(
+            (0..=segments).map(|i| {
+                let angle = i as f32 / 32.0 * std::f32::consts::TAU;
+                center + Vec3::new(angle.sin(), 0.0, -angle.cos()) * CHANNEL_RADIUS
+            }),
+            Color::GREEN,
+        );
+    }
+}
+
+/// The horizontal direction from a position towards a target.
+pub fn direction_towards(from: Vec3, to: Vec3) -> Vec2 {
+    (to.xz() - from.xz()).normalize_or_zero()
+}
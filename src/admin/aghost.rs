@@ -0,0 +1,374 @@
+use bevy::{ecs::system::SystemState, prelude::*};
+use networking::{
+    diagnostics::DebugNames,
+    is_server,
+    messaging::{MessageReceivers, MessageSender},
+    scene::NetworkSceneBundle,
+    spawning::ClientControls,
+    visibility::{NetworkObserver, NetworkObserverBundle},
+    Players,
+};
+
+use crate::{
+    console::{
+        CommandContext, CommandResult, CommandSource, ConsoleAppExt, ConsoleCommand,
+        PermissionLevel,
+    },
+    interaction::{
+        clear_completed_interactions, run_interactions, ActiveInteraction, ValidationBypass,
+    },
+    movement::ForcePositionMessage,
+    vision::Invisible,
+};
+
+/// Lets admins leave their body as an invisible ghost that can still act on the world.
+pub(crate) struct AghostPlugin;
+
+impl Plugin for AghostPlugin {
+    fn build(&self, app: &mut App) {
+        if !is_server(app) {
+            return;
+        }
+
+        app.add_console_command(ConsoleCommand {
+            name: "aghost",
+            description:
+                "Leaves your body as an invisible ghost that can still interact, or returns to it",
+            parameters: &[],
+            permission: PermissionLevel::Admin,
+            handler: aghost_command,
+        })
+        .add_systems(
+            Update,
+            // Quick interactions finish in the frame they start, so they are only seen in between
+            log_aghost_interactions
+                .after(run_interactions)
+                .before(clear_completed_interactions),
+        );
+    }
+}
+
+/// The ghost of an admin in aghost mode. Remembers the creature the admin left to return to it.
+#[derive(Component)]
+struct AdminGhost {
+    previous: Entity,
+}
+
+fn aghost_command(world: &mut World, context: &CommandContext) -> CommandResult {
+    let CommandSource::Player(connection) = context.source else {
+        return Err("Only players can become a ghost".into());
+    };
+    let Some(player) = world.resource::<Players>().get(connection).map(|p| p.id) else {
+        return Err("player disconnected".into());
+    };
+    let Some(controlled) = world.resource::<ClientControls>().controlled_entity(player) else {
+        return Err("You need to be in the round to become a ghost".into());
+    };
+
+    let (target, position, rotation, text) = match world.get::<AdminGhost>(controlled) {
+        Some(ghost) => {
+            let previous = ghost.previous;
+            let Some(transform) = world.get::<GlobalTransform>(previous) else {
+                return Err("The creature you left doesn't exist anymore".into());
+            };
+            let (_, rotation, position) = transform.to_scale_rotation_translation();
+            world.entity_mut(controlled).despawn_recursive();
+            info!(player = %player, admin = true, "Admin returned from aghost");
+            (previous, position, rotation, "Returned to your body")
+        }
+        None => {
+            let position = world
+                .get::<GlobalTransform>(controlled)
+                .map_or(Vec3::Y, |t| t.translation());
+            let scene = world
+                .resource::<AssetServer>()
+                .load("creatures/ghost.scn.ron");
+            let ghost = world
+                .spawn((
+                    NetworkSceneBundle {
+                        scene: scene.into(),
+                        transform: Transform::from_translation(position),
+                        ..Default::default()
+                    },
+                    NetworkObserverBundle {
+                        observer: NetworkObserver {
+                            range: 1,
+                            player_id: player,
+                        },
+                        cells: Default::default(),
+                    },
+                    networking::transform::ClientMovement,
+                    AdminGhost {
+                        previous: controlled,
+                    },
+                    ValidationBypass,
+                    Invisible,
+                ))
+                .id();
+            info!(player = %player, admin = true, "Admin entered aghost");
+            (
+                ghost,
+                position,
+                Quat::IDENTITY,
+                "You are an invisible ghost now, use /aghost again to return",
+            )
+        }
+    };
+    // Same rebinding as ghosts returning to a revived body
+    world
+        .resource_mut::<ClientControls>()
+        .give_control(player, target);
+
+    // Movement is client authoritative, so the client has to be told
+    let mut state = SystemState::<MessageSender>::new(world);
+    state.get_mut(world).send_with_priority(
+        &ForcePositionMessage { position, rotation },
+        MessageReceivers::Single(connection),
+        10,
+    );
+    state.apply(world);
+    Ok(text.into())
+}
+
+/// Nobody sees what an aghosted admin does, so every interaction is logged.
+fn log_aghost_interactions(
+    interactions: Query<(Entity, &ActiveInteraction), (Added<ActiveInteraction>, With<AdminGhost>)>,
+    controls: Res<ClientControls>,
+    names: DebugNames,
+) {
+    for (ghost, interaction) in interactions.iter() {
+        info!(
+            player = ?controls.controlling_player(ghost),
+            admin = true,
+            target = %names.debug_name(interaction.target),
+            "Aghost interaction"
+        );
+    }
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use bevy::ecs::{schedule::ExecutorKind, system::Command};
+    use networking::{
+        identity::{NetworkCommand, NetworkIdentities},
+        loopback::LinkConditions,
+        messaging::MessageEvent,
+        testing, ConnectionId, NetworkRole,
+    };
+
+    use super::*;
+    use crate::{
+        access::RequiresAccess,
+        communication::SystemMessageEvent,
+        config::ServerConfig,
+        console::ConsoleInputEvent,
+        interaction::InteractionExecuteDefaultRequest,
+        machines::{Machine, MachineViewers},
+        testing::{capture_logs, server_app, CapturedLog},
+    };
+
+    #[derive(Resource, Default)]
+    struct Replies(Vec<String>);
+
+    fn record_replies(mut events: EventReader<SystemMessageEvent>, mut replies: ResMut<Replies>) {
+        replies.0.extend(events.iter().map(|e| e.text.clone()));
+    }
+
+    struct Scene {
+        server: App,
+        client: App,
+        connection: ConnectionId,
+        body: Entity,
+        locker: Entity,
+    }
+
+    impl Scene {
+        /// A player standing in their body, far away from a locker only security can open.
+        fn new(admin: bool) -> Self {
+            let mut server = server_app(ServerConfig::default());
+            server
+                .init_resource::<Replies>()
+                .add_systems(Update, record_replies)
+                // Logs are only captured on the test thread
+                .edit_schedule(Update, |schedule| {
+                    schedule.set_executor_kind(ExecutorKind::SingleThreaded);
+                });
+            let mut client = testing::app(NetworkRole::Client);
+            let connector = testing::listen(&mut server);
+            testing::join(&mut client, &connector, LinkConditions::default());
+            testing::connect(&mut server, &mut [&mut client], 200);
+
+            let (&connection, player) = server
+                .world
+                .resource::<Players>()
+                .players()
+                .iter()
+                .next()
+                .unwrap();
+            let player = player.id;
+            if admin {
+                server
+                    .world
+                    .resource_mut::<ServerConfig>()
+                    .admins
+                    .push(player);
+            }
+            let body = server.world.spawn(SpatialBundle::default()).id();
+            NetworkCommand { entity: body }.apply(&mut server.world);
+            server
+                .world
+                .resource_mut::<ClientControls>()
+                .give_control(player, body);
+            let locker = server
+                .world
+                .spawn((
+                    SpatialBundle::from_transform(Transform::from_xyz(20.0, 0.0, 0.0)),
+                    Machine {
+                        name: "Locker".into(),
+                    },
+                    RequiresAccess {
+                        accesses: vec!["security".into()],
+                    },
+                    Name::new("Locker"),
+                ))
+                .id();
+            NetworkCommand { entity: locker }.apply(&mut server.world);
+            testing::update(&mut server, &mut [&mut client], 2);
+
+            Self {
+                server,
+                client,
+                connection,
+                body,
+                locker,
+            }
+        }
+
+        fn run(&mut self, frames: u32) -> Vec<CapturedLog> {
+            let Self { server, client, .. } = self;
+            capture_logs(|| testing::update(server, &mut [client], frames)).1
+        }
+
+        fn command(&mut self, line: &str) -> Vec<CapturedLog> {
+            self.server.world.send_event(ConsoleInputEvent {
+                source: CommandSource::Player(self.connection),
+                line: line.into(),
+                cursor: None,
+            });
+            self.run(3)
+        }
+
+        fn use_locker(&mut self) -> Vec<CapturedLog> {
+            let target = self
+                .server
+                .world
+                .resource::<NetworkIdentities>()
+                .get_identity(self.locker)
+                .unwrap();
+            self.server.world.send_event(MessageEvent {
+                message: InteractionExecuteDefaultRequest {
+                    target,
+                    point: None,
+                },
+                connection: self.connection,
+            });
+            self.run(5)
+        }
+
+        fn controlled(&self) -> Option<Entity> {
+            let player = self
+                .server
+                .world
+                .resource::<Players>()
+                .get(self.connection)
+                .unwrap()
+                .id;
+            self.server
+                .world
+                .resource::<ClientControls>()
+                .controlled_entity(player)
+        }
+
+        fn locker_open(&self) -> bool {
+            let identity = self
+                .server
+                .world
+                .resource::<NetworkIdentities>()
+                .get_identity(self.locker)
+                .unwrap();
+            self.server
+                .world
+                .resource::<MachineViewers>()
+                .get(self.connection, identity)
+                .is_some()
+        }
+    }
+
+    #[test]
+    fn players_cant_aghost() {
+        let mut scene = Scene::new(false);
+
+        scene.command("aghost");
+
+        assert_eq!(
+            scene.server.world.resource::<Replies>().0,
+            ["Error: Unknown command 'aghost'. Use /help to list commands."]
+        );
+        assert_eq!(scene.controlled(), Some(scene.body));
+        let mut ghosts = scene.server.world.query::<&AdminGhost>();
+        assert_eq!(ghosts.iter(&scene.server.world).count(), 0);
+    }
+
+    #[test]
+    fn aghost_uses_a_distant_locked_locker() {
+        let mut scene = Scene::new(true);
+
+        // Too far away and without access while in the body
+        let logs = scene.use_locker();
+        assert!(!scene.locker_open());
+        assert!(!logs.iter().any(|log| log.message == "Aghost interaction"));
+
+        let logs = scene.command("aghost");
+        let entered = logs
+            .iter()
+            .find(|log| log.message == "Admin entered aghost")
+            .unwrap();
+        assert_eq!(entered.field("admin"), Some("true"));
+        let ghost = scene.controlled().unwrap();
+        assert_ne!(ghost, scene.body);
+        assert!(scene.server.world.get::<AdminGhost>(ghost).is_some());
+
+        let logs = scene.use_locker();
+        assert!(scene.locker_open());
+        let interaction = logs
+            .iter()
+            .find(|log| log.message == "Aghost interaction")
+            .unwrap();
+        assert_eq!(interaction.field("admin"), Some("true"));
+        assert!(interaction.field("target").unwrap().contains("Locker"));
+    }
+
+    #[test]
+    fn returning_from_aghost_restores_the_body() {
+        let mut scene = Scene::new(true);
+        scene.command("aghost");
+        let ghost = scene.controlled().unwrap();
+
+        let logs = scene.command("aghost");
+
+        assert_eq!(scene.controlled(), Some(scene.body));
+        assert!(scene.server.world.get_entity(ghost).is_none());
+        let returned = logs
+            .iter()
+            .find(|log| log.message == "Admin returned from aghost")
+            .unwrap();
+        assert_eq!(returned.field("admin"), Some("true"));
+        assert_eq!(
+            scene.server.world.resource::<Replies>().0,
+            [
+                "You are an invisible ghost now, use /aghost again to return",
+                "Returned to your body"
+            ]
+        );
+    }
+}
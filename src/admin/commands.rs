@@ -0,0 +1,377 @@
+use std::{
+    fmt::Write,
+    fs::{read_to_string, write},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bevy::{
+    ecs::system::SystemState,
+    prelude::*,
+    utils::{get_short_name, HashMap, Uuid},
+};
+use maps::TileMap;
+use networking::{
+    diagnostics::DebugNames,
+    identity::{NetworkIdentities, NetworkIdentity},
+    is_server,
+    messaging::{MessageReceivers, MessageSender},
+    spawning::ClientControls,
+    DisconnectPlayer, Players, ServerEvent,
+};
+
+use crate::{
+    autosave::request_autosave,
+    communication::SystemMessageEvent,
+    console::{
+        ArgumentKind, CommandContext, CommandResult, ConsoleAppExt, ConsoleCommand, PermissionLevel,
+    },
+    movement::{speed::SpeedModifiers, ForcePositionMessage},
+    physics_tuning::PhysicsTimings,
+};
+
+/// Moderation and debugging commands for admins.
+pub(crate) struct AdminCommandsPlugin;
+
+impl Plugin for AdminCommandsPlugin {
+    fn build(&self, app: &mut App) {
+        if !is_server(app) {
+            return;
+        }
+
+        app.add_systems(Startup, load_bans)
+            .add_systems(Update, reject_banned_players)
+            .add_console_command(ConsoleCommand {
+                name: "kick",
+                description: "Disconnects a player",
+                parameters: &[
+                    ("player", ArgumentKind::Player),
+                    ("reason", ArgumentKind::Text),
+                ],
+                permission: PermissionLevel::Admin,
+                handler: kick_command,
+            })
+            .add_console_command(ConsoleCommand {
+                name: "ban",
+                description: "Disconnects a player and prevents them from joining again",
+                parameters: &[
+                    ("player", ArgumentKind::Player),
+                    ("reason", ArgumentKind::Text),
+                ],
+                permission: PermissionLevel::Admin,
+                handler: ban_command,
+            })
+            .add_console_command(ConsoleCommand {
+                name: "tp",
+                description: "Teleports a player's creature to a tile",
+                parameters: &[
+                    ("player", ArgumentKind::Player),
+                    ("position", ArgumentKind::Tile),
+                ],
+                permission: PermissionLevel::Admin,
+                handler: teleport_command,
+            })
+            .add_console_command(ConsoleCommand {
+                name: "save",
+                description: "Writes an autosave now",
+                parameters: &[],
+                permission: PermissionLevel::Admin,
+                handler: save_command,
+            })
+            .add_console_command(ConsoleCommand {
+                name: "perf",
+                description: "Shows server performance statistics",
+                parameters: &[],
+                permission: PermissionLevel::Admin,
+                handler: perf_command,
+            })
+            .add_console_command(ConsoleCommand {
+                name: "ident",
+                description: "Describes the entity with a network identity",
+                parameters: &[("id", ArgumentKind::Integer)],
+                permission: PermissionLevel::Admin,
+                handler: ident_command,
+            })
+            .add_console_command(ConsoleCommand {
+                name: "idents",
+                description: "Writes all network identities and their entities to a file",
+                parameters: &[],
+                permission: PermissionLevel::Admin,
+                handler: idents_command,
+            });
+    }
+}
+
+const BANS_FILE: &str = "bans.ron";
+
+/// Banned player ids and the reason they were banned for.
+#[derive(Resource, Default)]
+pub(super) struct Bans {
+    permanent: HashMap<Uuid, String>,
+    /// Bans given out automatically, with the unix time they run out at.
+    /// These are not written to the bans file, so a restart lifts them too.
+    temporary: HashMap<Uuid, (String, u64)>,
+}
+
+impl Bans {
+    /// Why a player can't join right now, `None` if they can
+    pub(super) fn reason(&self, id: Uuid, now: u64) -> Option<&str> {
+        if let Some(reason) = self.permanent.get(&id) {
+            return Some(reason);
+        }
+        self.temporary
+            .get(&id)
+            .filter(|(_, expires)| now < *expires)
+            .map(|(reason, _)| reason.as_str())
+    }
+
+    /// Bans a player until the unix time `expires`.
+    pub(super) fn ban_until(&mut self, id: Uuid, reason: String, expires: u64) {
+        self.temporary.insert(id, (reason, expires));
+    }
+
+    fn remove_expired(&mut self, now: u64) {
+        self.temporary.retain(|_, (_, expires)| now < *expires);
+    }
+}
+
+/// Seconds since the unix epoch, which temporary bans expire by
+pub(super) fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+fn load_bans(mut commands: Commands) {
+    let bans = match read_to_string(BANS_FILE) {
+        Ok(text) => ron::from_str(&text).unwrap_or_else(|err| {
+            error!(error = %err, "Error parsing {}", BANS_FILE);
+            HashMap::default()
+        }),
+        // No one has been banned yet
+        Err(_) => HashMap::default(),
+    };
+    commands.insert_resource(Bans {
+        permanent: bans,
+        temporary: HashMap::default(),
+    });
+}
+
+pub(super) fn reject_banned_players(
+    mut server_events: EventReader<ServerEvent>,
+    players: Res<Players>,
+    mut bans: ResMut<Bans>,
+    mut disconnect: EventWriter<DisconnectPlayer>,
+) {
+    let now = unix_now();
+    bans.remove_expired(now);
+    for event in server_events.iter() {
+        let ServerEvent::PlayerConnected(connection) = event else {
+            continue;
+        };
+        let Some(player) = players.get(*connection) else {
+            continue;
+        };
+        if let Some(reason) = bans.reason(player.id, now) {
+            info!(id = %player.id, reason, "Rejecting banned player");
+            disconnect.send(DisconnectPlayer(*connection));
+        }
+    }
+}
+
+fn kick_command(world: &mut World, context: &CommandContext) -> CommandResult {
+    let connection = context.player(0);
+    let reason = context.text(1);
+    let username = world
+        .resource::<Players>()
+        .get(connection)
+        .map(|p| p.username.clone())
+        .unwrap_or_default();
+
+    // TODO: Show the reason on the client's disconnect screen
+    world
+        .resource_mut::<Events<SystemMessageEvent>>()
+        .send(SystemMessageEvent {
+            receiver: connection,
+            text: format!("You have been kicked: {}", reason),
+        });
+    world
+        .resource_mut::<Events<DisconnectPlayer>>()
+        .send(DisconnectPlayer(connection));
+    info!(username = username.as_str(), reason, "Kicked player");
+    Ok(format!("Kicked {}", username))
+}
+
+fn ban_command(world: &mut World, context: &CommandContext) -> CommandResult {
+    let connection = context.player(0);
+    let reason = context.text(1);
+    let Some((id, username)) = world
+        .resource::<Players>()
+        .get(connection)
+        .map(|p| (p.id, p.username.clone()))
+    else {
+        return Err("player disconnected".into());
+    };
+
+    let mut bans = world.resource_mut::<Bans>();
+    bans.permanent.insert(id, reason.to_owned());
+    let text = ron::ser::to_string_pretty(&bans.permanent, Default::default())
+        .map_err(|e| e.to_string())?;
+    if let Err(err) = write(BANS_FILE, text) {
+        error!(error = %err, "Could not write {}", BANS_FILE);
+    }
+
+    world
+        .resource_mut::<Events<SystemMessageEvent>>()
+        .send(SystemMessageEvent {
+            receiver: connection,
+            text: format!("You have been banned: {}", reason),
+        });
+    world
+        .resource_mut::<Events<DisconnectPlayer>>()
+        .send(DisconnectPlayer(connection));
+    info!(username = username.as_str(), id = %id, reason, "Banned player");
+    Ok(format!("Banned {}", username))
+}
+
+fn teleport_command(world: &mut World, context: &CommandContext) -> CommandResult {
+    let connection = context.player(0);
+    let tile = context.tile(1);
+
+    let Some(id) = world.resource::<Players>().get(connection).map(|p| p.id) else {
+        return Err("player disconnected".into());
+    };
+    let Some(entity) = world.resource::<ClientControls>().controlled_entity(id) else {
+        return Err("player is not controlling a creature".into());
+    };
+
+    // Same height as job spawn points
+    let position = Vec3::new(tile.x as f32, 1.0, tile.y as f32);
+    let mut maps = world.query::<&TileMap>();
+    // TODO: Support multiple maps
+    let on_map = maps
+        .get_single(world)
+        .map(|map| map.tile_at(position).is_some())
+        .unwrap_or(false);
+    if !on_map {
+        return Err(format!("{},{} is outside the map", tile.x, tile.y));
+    }
+
+    let Some(mut transform) = world.get_mut::<Transform>(entity) else {
+        return Err("creature has no position".into());
+    };
+    transform.translation = position;
+    let rotation = transform.rotation;
+
+    // Movement is client authoritative, so the client has to be told
+    let mut state = SystemState::<MessageSender>::new(world);
+    state.get_mut(world).send(
+        &ForcePositionMessage { position, rotation },
+        MessageReceivers::Single(connection),
+    );
+    state.apply(world);
+    Ok(format!("Teleported to {},{}", tile.x, tile.y))
+}
+
+fn save_command(world: &mut World, _: &CommandContext) -> CommandResult {
+    request_autosave(world)?;
+    Ok("Saving the world".into())
+}
+
+fn perf_command(world: &mut World, _: &CommandContext) -> CommandResult {
+    let frame_time = world.resource::<Time>().delta_seconds() * 1000.0;
+    let entities = world.entities().len();
+    let players = world.resource::<Players>().players().len();
+    let mut text = format!(
+        "Frame time: {:.2} ms, entities: {}, players: {}",
+        frame_time, entities, players
+    );
+    if let Some(physics) = world.get_resource::<PhysicsTimings>() {
+        let _ = write!(
+            text,
+            "\nPhysics step: {:.2} ms (broad phase: {:.2} ms, narrow phase: {:.2} ms, solver: {:.2} ms)",
+            physics.step, physics.broad_phase, physics.narrow_phase, physics.solver
+        );
+    }
+    Ok(text)
+}
+
+fn ident_command(world: &mut World, context: &CommandContext) -> CommandResult {
+    let id = u32::try_from(context.integer(0)).map_err(|_| "invalid identity".to_string())?;
+    let identity = NetworkIdentity::from_raw(id);
+    let Some(entity) = world.resource::<NetworkIdentities>().get_entity(identity) else {
+        return Err(format!("no entity has identity {}", identity));
+    };
+
+    let components: Vec<String> = world
+        .inspect_entity(entity)
+        .into_iter()
+        .map(|info| get_short_name(info.name()))
+        .collect();
+    let owner = world
+        .resource::<ClientControls>()
+        .controlling_player(entity)
+        .and_then(|id| {
+            let players = world.resource::<Players>();
+            let connection = players.get_connection(&id)?;
+            players
+                .get(connection)
+                .map(|p| format!("{} ({:?})", p.username, connection))
+        });
+    let position = world
+        .get::<GlobalTransform>(entity)
+        .map(|t| t.translation());
+    let mut parents = Vec::new();
+    let mut current = entity;
+    while let Some(parent) = world.get::<Parent>(current) {
+        current = parent.get();
+        parents.push(current);
+    }
+
+    let mut state = SystemState::<DebugNames>::new(world);
+    let names = state.get(world);
+    let mut text = format!("{}\n", names.debug_name(entity));
+    let _ = writeln!(text, "Components: {}", components.join(", "));
+    let _ = writeln!(text, "Owner: {}", owner.as_deref().unwrap_or("none"));
+    match position {
+        Some(position) => {
+            let _ = writeln!(text, "Position: {}", position);
+        }
+        None => text.push_str("Position: none\n"),
+    }
+    for parent in parents {
+        let _ = writeln!(text, "Parent: {}", names.debug_name(parent));
+    }
+    if let Some(modifiers) = world.get::<SpeedModifiers>(entity) {
+        let active: Vec<String> = modifiers
+            .iter()
+            .map(|(name, modifier)| format!("{} {}", name, modifier))
+            .collect();
+        let _ = writeln!(
+            text,
+            "Speed modifiers: {}",
+            if active.is_empty() {
+                "none".to_owned()
+            } else {
+                active.join(", ")
+            }
+        );
+    }
+    Ok(text.trim_end().to_owned())
+}
+
+fn idents_command(world: &mut World, _: &CommandContext) -> CommandResult {
+    let mut identities: Vec<_> = world.resource::<NetworkIdentities>().iter().collect();
+    identities.sort_unstable_by_key(|(identity, _)| *identity);
+
+    let mut state = SystemState::<DebugNames>::new(world);
+    let names = state.get(world);
+    let mut text = String::new();
+    for (identity, entity) in identities {
+        let _ = writeln!(text, "{}\t{}", identity, names.debug_name(entity));
+    }
+
+    let path = format!("idents-{}.txt", unix_now());
+    write(&path, text).map_err(|e| e.to_string())?;
+    Ok(format!("Wrote identities to {}", path))
+}
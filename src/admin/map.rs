@@ -1,22 +1,49 @@
 use std::path::PathBuf;
 
 use bevy::prelude::*;
-use bevy_egui::*;
 use maps::TileMap;
 use networking::{
-    messaging::{AppExt, MessageEvent, MessageSender},
-    NetworkManager,
+    messaging::{AppExt, MessageEvent, MessageReceivers, MessageSender},
+    NetworkManager, Players,
 };
 use serde::{Deserialize, Serialize};
 
-use crate::{ui::has_window, GameState};
+use crate::{
+    config::ServerConfig,
+    map_objects::{MapObject, UnmappedObjects},
+};
+
+#[cfg(feature = "client")]
+use {
+    crate::{ui::has_window, GameState},
+    bevy_egui::*,
+};
 
 #[derive(Serialize, Deserialize, Clone)]
 struct ChangeMapMessage {
     name: String,
 }
 
-fn client_map_selection_ui(mut contexts: EguiContexts, mut sender: MessageSender) {
+/// Asks the server which map objects had no prefab
+#[derive(Serialize, Deserialize)]
+struct UnmappedObjectsRequest;
+
+#[derive(Serialize, Deserialize)]
+struct UnmappedObjectsMessage {
+    /// BYOND paths and how often they appeared, most common first
+    objects: Vec<(String, usize)>,
+}
+
+#[cfg(feature = "client")]
+#[derive(Resource, Default)]
+struct UnmappedObjectsList(Option<Vec<(String, usize)>>);
+
+#[cfg(feature = "client")]
+fn client_map_selection_ui(
+    mut contexts: EguiContexts,
+    mut sender: MessageSender,
+    mut unmapped: ResMut<UnmappedObjectsList>,
+) {
     egui::Window::new("Load map").show(contexts.ctx_mut(), |ui| {
         for &map_name in ["DeltaStation2", "BoxStation", "MetaStation"].iter() {
             if ui.button(map_name).clicked() {
@@ -25,18 +52,74 @@ fn client_map_selection_ui(mut contexts: EguiContexts, mut sender: MessageSender
                 });
             }
         }
+
+        ui.separator();
+        if ui.button("Unmapped objects").clicked() {
+            sender.send_to_server(&UnmappedObjectsRequest);
+        }
+        if let Some(objects) = unmapped.0.as_ref() {
+            egui::ScrollArea::vertical()
+                .max_height(200.0)
+                .show(ui, |ui| {
+                    for (path, count) in objects.iter() {
+                        ui.label(format!("{} x{}", path, count));
+                    }
+                });
+            if ui.button("Hide").clicked() {
+                unmapped.0 = None;
+            }
+        }
     });
 }
 
+#[cfg(feature = "client")]
+fn receive_unmapped_objects(
+    mut messages: EventReader<MessageEvent<UnmappedObjectsMessage>>,
+    mut list: ResMut<UnmappedObjectsList>,
+) {
+    for event in messages.iter() {
+        list.0 = Some(event.message.objects.clone());
+    }
+}
+
+fn handle_unmapped_objects_request(
+    mut messages: EventReader<MessageEvent<UnmappedObjectsRequest>>,
+    unmapped: Res<UnmappedObjects>,
+    players: Res<Players>,
+    config: Res<ServerConfig>,
+    mut sender: MessageSender,
+) {
+    for event in messages.iter() {
+        let is_admin = players
+            .get(event.connection)
+            .is_some_and(|player| config.admins.contains(&player.id));
+        if !is_admin {
+            warn!(connection = ?event.connection, "Non-admin asked for unmapped objects");
+            continue;
+        }
+
+        let mut objects: Vec<_> = unmapped
+            .counts
+            .iter()
+            .map(|(path, &count)| (path.clone(), count))
+            .collect();
+        objects.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        sender.send(
+            &UnmappedObjectsMessage { objects },
+            MessageReceivers::Single(event.connection),
+        );
+    }
+}
+
 fn map_loader_system(
     mut messages: EventReader<MessageEvent<ChangeMapMessage>>,
     mut commands: Commands,
     server: Res<AssetServer>,
-    tilemaps: Query<Entity, With<TileMap>>,
+    tilemaps: Query<Entity, Or<(With<TileMap>, With<MapObject>)>>,
 ) {
     let message = &messages.iter().last().unwrap().message;
 
-    // Delete existing maps
+    // Delete existing maps and their objects
     for entity in tilemaps.iter() {
         commands.entity(entity).despawn_recursive();
     }
@@ -54,7 +137,9 @@ pub struct MapManagementPlugin;
 
 impl Plugin for MapManagementPlugin {
     fn build(&self, app: &mut App) {
-        app.add_network_message::<ChangeMapMessage>();
+        app.add_network_message::<ChangeMapMessage>("ChangeMapMessage")
+            .add_network_message::<UnmappedObjectsRequest>("UnmappedObjectsRequest")
+            .add_network_message::<UnmappedObjectsMessage>("UnmappedObjectsMessage");
 
         if app
             .world
@@ -64,14 +149,21 @@ impl Plugin for MapManagementPlugin {
         {
             app.add_systems(
                 Update,
-                map_loader_system.run_if(on_event::<MessageEvent<ChangeMapMessage>>()),
+                (
+                    map_loader_system.run_if(on_event::<MessageEvent<ChangeMapMessage>>()),
+                    handle_unmapped_objects_request,
+                ),
             );
         } else {
-            app.add_systems(
+            #[cfg(feature = "client")]
+            app.init_resource::<UnmappedObjectsList>().add_systems(
                 Update,
-                client_map_selection_ui
-                    .run_if(in_state(GameState::Game))
-                    .run_if(has_window),
+                (
+                    receive_unmapped_objects,
+                    client_map_selection_ui.run_if(has_window),
+                )
+                    .chain()
+                    .run_if(in_state(GameState::Game)),
             );
         }
     }
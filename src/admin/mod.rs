@@ -1,12 +1,25 @@
 use bevy::prelude::{App, Plugin};
 
+mod aghost;
+mod commands;
 mod map;
+pub mod moderation;
+mod players;
+pub mod role_bans;
 mod spawning;
 
 pub(crate) struct AdminPlugin;
 
 impl Plugin for AdminPlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugins((spawning::SpawningPlugin, map::MapManagementPlugin));
+        app.add_plugins((
+            commands::AdminCommandsPlugin,
+            aghost::AghostPlugin,
+            spawning::SpawningPlugin,
+            map::MapManagementPlugin,
+            players::PlayerPanelPlugin,
+            moderation::ModerationPlugin,
+            role_bans::RoleBansPlugin,
+        ));
     }
 }
@@ -0,0 +1,423 @@
+use std::collections::VecDeque;
+
+use bevy::{
+    prelude::*,
+    utils::{HashMap, Uuid},
+};
+use networking::{
+    is_server, spawning::ClientControls, ConnectionId, DisconnectPlayer, Players, ServerEvent,
+};
+use serde::Deserialize;
+
+use crate::{
+    body::{health::receive_damage, self_or_ancestor, Body},
+    combat::damage::{AffectedEntity, Attack, AttackSource},
+    communication::SystemMessageEvent,
+    config::ServerConfig,
+};
+
+use super::commands::{reject_banned_players, unix_now, Bans};
+
+/// Catches players leaving in the middle of a fight and players reconnecting over and over.
+pub(crate) struct ModerationPlugin;
+
+impl Plugin for ModerationPlugin {
+    fn build(&self, app: &mut App) {
+        if !is_server(app) {
+            return;
+        }
+
+        app.init_resource::<ConnectionHistory>().add_systems(
+            Update,
+            (
+                track_combat.before(receive_damage),
+                (
+                    limit_connection_rate.before(reject_banned_players),
+                    mark_combat_loggers,
+                    expire_combat_logged,
+                )
+                    .chain(),
+            ),
+        );
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ModerationConfig {
+    /// Players leaving less than this many seconds after a fight are combat logging
+    #[serde(default = "ModerationConfig::default_combat_log_seconds")]
+    pub combat_log_seconds: f32,
+    /// How long a combat logged body stays marked while waiting for its player, in seconds
+    #[serde(default = "ModerationConfig::default_combat_log_grace_seconds")]
+    pub combat_log_grace_seconds: f32,
+    /// Connections a player can make per minute before they have to wait. 0 disables the limit.
+    #[serde(default = "ModerationConfig::default_connects_per_minute")]
+    pub connects_per_minute: usize,
+    /// Seconds a player connecting too often is banned for
+    #[serde(default = "ModerationConfig::default_connect_cooldown_seconds")]
+    pub connect_cooldown_seconds: u64,
+}
+
+impl ModerationConfig {
+    fn default_combat_log_seconds() -> f32 {
+        30.0
+    }
+
+    fn default_combat_log_grace_seconds() -> f32 {
+        600.0
+    }
+
+    fn default_connects_per_minute() -> usize {
+        5
+    }
+
+    fn default_connect_cooldown_seconds() -> u64 {
+        300
+    }
+}
+
+impl Default for ModerationConfig {
+    fn default() -> Self {
+        Self {
+            combat_log_seconds: Self::default_combat_log_seconds(),
+            combat_log_grace_seconds: Self::default_combat_log_grace_seconds(),
+            connects_per_minute: Self::default_connects_per_minute(),
+            connect_cooldown_seconds: Self::default_connect_cooldown_seconds(),
+        }
+    }
+}
+
+/// When a body last attacked or was attacked.
+#[derive(Component)]
+struct RecentCombat {
+    last: f32,
+}
+
+/// A body whose player disconnected right after a fight.
+/// It stays in the world, and the player takes it back over when they reconnect.
+#[derive(Component)]
+pub struct CombatLogged {
+    pub player: Uuid,
+    /// Elapsed seconds when the player left
+    pub since: f32,
+}
+
+fn track_combat(
+    attacks: Query<(&AffectedEntity, Option<&AttackSource>), Added<Attack>>,
+    bodies: Query<(), With<Body>>,
+    parents: Query<&Parent>,
+    time: Res<Time>,
+    mut commands: Commands,
+) {
+    let now = time.elapsed_seconds();
+    for (affected, source) in attacks.iter() {
+        let participants = [Some(affected.0), source.map(|s| s.attacker)];
+        for entity in participants.into_iter().flatten() {
+            // Attacks hit body parts, so the body is further up
+            let body = self_or_ancestor(&parents, entity, |e| bodies.contains(e));
+            if let Some(body) = body {
+                commands.entity(body).insert(RecentCombat { last: now });
+            }
+        }
+    }
+}
+
+/// Sends a message to every connected admin.
+fn notify_admins(
+    text: &str,
+    players: &Players,
+    config: &ServerConfig,
+    messages: &mut EventWriter<SystemMessageEvent>,
+) {
+    for (&connection, player) in players.players() {
+        if config.admins.contains(&player.id) {
+            messages.send(SystemMessageEvent {
+                receiver: connection,
+                text: text.to_owned(),
+            });
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn mark_combat_loggers(
+    mut server_events: EventReader<ServerEvent>,
+    mut connected: Local<HashMap<ConnectionId, (Uuid, String)>>,
+    bodies: Query<(&RecentCombat, Option<&CombatLogged>)>,
+    controls: Res<ClientControls>,
+    players: Res<Players>,
+    config: Res<ServerConfig>,
+    time: Res<Time>,
+    mut messages: EventWriter<SystemMessageEvent>,
+    mut commands: Commands,
+) {
+    let now = time.elapsed_seconds();
+    for event in server_events.iter() {
+        match *event {
+            ServerEvent::PlayerConnected(connection) => {
+                let Some(player) = players.get(connection) else {
+                    continue;
+                };
+                connected.insert(connection, (player.id, player.username.clone()));
+
+                // Coming back in time lets them continue like any rejoin
+                let Some(body) = controls.controlled_entity(player.id) else {
+                    continue;
+                };
+                if let Ok((_, Some(_))) = bodies.get(body) {
+                    commands.entity(body).remove::<CombatLogged>();
+                    info!(
+                        id = %player.id,
+                        username = player.username.as_str(),
+                        "Combat logged player returned"
+                    );
+                    notify_admins(
+                        &format!("{} returned to their body", player.username),
+                        &players,
+                        &config,
+                        &mut messages,
+                    );
+                }
+            }
+            ServerEvent::PlayerDisconnected(connection) => {
+                // The player is already gone from the player list
+                let Some((id, username)) = connected.remove(&connection) else {
+                    continue;
+                };
+                let Some(body) = controls.controlled_entity(id) else {
+                    continue;
+                };
+                let Ok((combat, None)) = bodies.get(body) else {
+                    continue;
+                };
+                let seconds = now - combat.last;
+                if seconds > config.moderation.combat_log_seconds {
+                    continue;
+                }
+
+                commands.entity(body).insert(CombatLogged {
+                    player: id,
+                    since: now,
+                });
+                info!(
+                    id = %id,
+                    username = username.as_str(),
+                    seconds_after_combat = seconds,
+                    "Player disconnected during combat"
+                );
+                notify_admins(
+                    &format!(
+                        "{} disconnected {:.0} seconds after fighting",
+                        username, seconds
+                    ),
+                    &players,
+                    &config,
+                    &mut messages,
+                );
+            }
+            _ => {}
+        }
+    }
+}
+
+fn expire_combat_logged(
+    bodies: Query<(Entity, &CombatLogged)>,
+    config: Res<ServerConfig>,
+    time: Res<Time>,
+    mut commands: Commands,
+) {
+    let now = time.elapsed_seconds();
+    for (entity, logged) in bodies.iter() {
+        if now - logged.since >= config.moderation.combat_log_grace_seconds {
+            commands.entity(entity).remove::<CombatLogged>();
+            info!(id = %logged.player, "Combat logged player did not return");
+        }
+    }
+}
+
+/// Recent connection times of every player, in unix seconds.
+#[derive(Resource, Default)]
+struct ConnectionHistory(HashMap<Uuid, VecDeque<u64>>);
+
+/// Window the connection limit counts connections in
+const CONNECTION_WINDOW_SECONDS: u64 = 60;
+
+fn limit_connection_rate(
+    mut server_events: EventReader<ServerEvent>,
+    mut history: ResMut<ConnectionHistory>,
+    mut bans: ResMut<Bans>,
+    players: Res<Players>,
+    config: Res<ServerConfig>,
+    mut messages: EventWriter<SystemMessageEvent>,
+    mut disconnect: EventWriter<DisconnectPlayer>,
+) {
+    let limit = config.moderation.connects_per_minute;
+    if limit == 0 {
+        return;
+    }
+    let now = unix_now();
+    for event in server_events.iter() {
+        let ServerEvent::PlayerConnected(connection) = *event else {
+            continue;
+        };
+        let Some(player) = players.get(connection) else {
+            continue;
+        };
+
+        let times = history.0.entry(player.id).or_default();
+        while times
+            .front()
+            .is_some_and(|&time| now.saturating_sub(time) >= CONNECTION_WINDOW_SECONDS)
+        {
+            times.pop_front();
+        }
+        times.push_back(now);
+        if times.len() <= limit {
+            continue;
+        }
+
+        times.clear();
+        let cooldown = config.moderation.connect_cooldown_seconds;
+        let reason = format!("Reconnecting too often, try again in {} seconds", cooldown);
+        bans.ban_until(player.id, reason.clone(), now + cooldown);
+        messages.send(SystemMessageEvent {
+            receiver: connection,
+            text: reason,
+        });
+        disconnect.send(DisconnectPlayer(connection));
+        info!(
+            id = %player.id,
+            username = player.username.as_str(),
+            expires = now + cooldown,
+            "Banned player for reconnecting too often"
+        );
+    }
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use networking::{loopback::LinkConditions, testing, NetworkRole, UserData};
+
+    use super::*;
+    use crate::testing::server_app;
+
+    #[derive(Resource, Default)]
+    struct AdminMessages(Vec<String>);
+
+    fn record_messages(
+        mut events: EventReader<SystemMessageEvent>,
+        mut messages: ResMut<AdminMessages>,
+    ) {
+        messages.0.extend(events.iter().map(|e| e.text.clone()));
+    }
+
+    fn player_named(server: &App, username: &str) -> (ConnectionId, Uuid) {
+        let (&connection, player) = server
+            .world
+            .resource::<Players>()
+            .players()
+            .iter()
+            .find(|(_, player)| player.username == username)
+            .unwrap();
+        (connection, player.id)
+    }
+
+    /// Lets a player with a body and an admin watching disconnect the player,
+    /// `seconds_since_combat` after their last fight.
+    fn disconnect_after_combat(seconds_since_combat: f32) -> (App, Entity, Uuid) {
+        let mut server = server_app(ServerConfig::default());
+        server
+            .init_resource::<AdminMessages>()
+            .add_systems(Update, record_messages);
+        let mut player = testing::app(NetworkRole::Client);
+        let mut admin = testing::app(NetworkRole::Client);
+        let connector = testing::listen(&mut server);
+        testing::join(&mut player, &connector, LinkConditions::default());
+        testing::join(&mut admin, &connector, LinkConditions::default());
+        // The username is sent once connected, so this renames the admin
+        admin.insert_resource(UserData {
+            username: "Admin".into(),
+        });
+        testing::connect(&mut server, &mut [&mut player, &mut admin], 200);
+
+        let (_, admin_id) = player_named(&server, "Admin");
+        server
+            .world
+            .resource_mut::<ServerConfig>()
+            .admins
+            .push(admin_id);
+        let (connection, id) = player_named(&server, "Test");
+        let now = server.world.resource::<Time>().elapsed_seconds();
+        let body = server
+            .world
+            .spawn((
+                Body::default(),
+                RecentCombat {
+                    last: now - seconds_since_combat,
+                },
+            ))
+            .id();
+        server
+            .world
+            .resource_mut::<ClientControls>()
+            .give_control(id, body);
+
+        server.world.send_event(DisconnectPlayer(connection));
+        testing::update(&mut server, &mut [&mut player, &mut admin], 10);
+        (server, body, id)
+    }
+
+    #[test]
+    fn leaving_after_a_fight_marks_the_body() {
+        let (server, body, id) = disconnect_after_combat(5.0);
+
+        let logged = server.world.get::<CombatLogged>(body).unwrap();
+        assert_eq!(logged.player, id);
+        let messages = &server.world.resource::<AdminMessages>().0;
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].starts_with("Test disconnected"));
+        assert!(messages[0].ends_with("seconds after fighting"));
+    }
+
+    #[test]
+    fn leaving_long_after_a_fight_is_fine() {
+        let (server, body, _) = disconnect_after_combat(60.0);
+
+        assert!(server.world.get::<CombatLogged>(body).is_none());
+        assert!(server.world.resource::<AdminMessages>().0.is_empty());
+    }
+
+    #[test]
+    fn reconnecting_too_often_bans_until_the_cooldown_ends() {
+        let mut config = ServerConfig::default();
+        config.moderation.connects_per_minute = 2;
+        let cooldown = config.moderation.connect_cooldown_seconds;
+        let mut server = server_app(config);
+        let mut client = testing::app(NetworkRole::Client);
+        let connector = testing::listen(&mut server);
+        let before = unix_now();
+        testing::join(&mut client, &connector, LinkConditions::default());
+        testing::connect(&mut server, &mut [&mut client], 200);
+        let (connection, id) = player_named(&server, "Test");
+
+        // Reconnects look the same as the first connection to the limit
+        server
+            .world
+            .send_event(ServerEvent::PlayerConnected(connection));
+        testing::update(&mut server, &mut [&mut client], 1);
+        assert!(server.world.resource::<Bans>().reason(id, before).is_none());
+
+        server
+            .world
+            .send_event(ServerEvent::PlayerConnected(connection));
+        testing::update(&mut server, &mut [&mut client], 1);
+        let after = unix_now();
+
+        let bans = server.world.resource::<Bans>();
+        assert_eq!(
+            bans.reason(id, before + cooldown - 1),
+            Some("Reconnecting too often, try again in 300 seconds")
+        );
+        assert!(bans.reason(id, after + cooldown).is_none());
+    }
+}
@@ -0,0 +1,538 @@
+use bevy::{prelude::*, utils::Uuid};
+use networking::{
+    identity::NetworkIdentity,
+    is_server,
+    messaging::{AppExt, MessageEvent, MessageReceivers, MessageSender},
+    quality::{LinkQuality, LinkStats, LinkTier},
+    spawning::ClientControls,
+    visibility::{Relevancy, RelevancyTarget},
+    ConnectionId, Players,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::ServerConfig,
+    job::JobDefinition,
+    stats::{PlayerStats, StatsSummary},
+};
+
+use super::role_bans::{RoleBan, RoleBanScope, RoleBans};
+
+#[cfg(feature = "client")]
+use {
+    crate::{camera::TopDownCamera, ui::has_window, GameState},
+    bevy::utils::HashMap,
+    bevy_egui::{egui, EguiContexts},
+    networking::{identity::NetworkIdentities, spawning::ClientControlled},
+};
+
+#[derive(Serialize, Deserialize)]
+enum PlayerPanelRequest {
+    Refresh,
+    /// Start or stop following a player
+    Follow(Option<Uuid>),
+    /// Saved statistics of a player
+    Stats(Uuid),
+    /// Active role bans of a player
+    RoleBans(Uuid),
+    IssueRoleBan {
+        player: Uuid,
+        scope: RoleBanScope,
+        /// 0 is permanent
+        hours: u64,
+        reason: String,
+    },
+    /// Lift a ban by its number and send the player's remaining bans
+    LiftRoleBan(Uuid, u32),
+}
+
+#[derive(Serialize, Deserialize)]
+enum PlayerPanelMessage {
+    Players(Vec<PlayerEntry>),
+    /// The creature the admin camera should follow
+    Following(Option<NetworkIdentity>),
+    Stats(Uuid, StatsSummary),
+    RoleBans(Uuid, Vec<RoleBan>),
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct PlayerEntry {
+    id: Uuid,
+    username: String,
+    /// How the server rates the player's connection
+    link_tier: LinkTier,
+    link_stats: Option<LinkStats>,
+}
+
+/// An admin following a player. Owns the relevancy override for the surroundings.
+#[derive(Component)]
+struct FollowSession {
+    admin: ConnectionId,
+    target: Uuid,
+    followed_entity: Option<Entity>,
+}
+
+#[allow(clippy::too_many_arguments)]
+fn handle_player_panel_requests(
+    mut messages: EventReader<MessageEvent<PlayerPanelRequest>>,
+    sessions: Query<(Entity, &FollowSession)>,
+    players: Res<Players>,
+    quality: Res<LinkQuality>,
+    stats: Res<PlayerStats>,
+    jobs: Res<Assets<JobDefinition>>,
+    config: Res<ServerConfig>,
+    mut role_bans: ResMut<RoleBans>,
+    mut sender: MessageSender,
+    mut commands: Commands,
+) {
+    for event in messages.iter() {
+        let Some(admin) = players.get(event.connection) else {
+            continue;
+        };
+        if !config.admins.contains(&admin.id) {
+            warn!(connection = ?event.connection, "Non-admin sent a player panel request");
+            continue;
+        }
+        match &event.message {
+            PlayerPanelRequest::Refresh => {
+                let mut entries: Vec<_> = players
+                    .players()
+                    .iter()
+                    .map(|(&connection, p)| PlayerEntry {
+                        id: p.id,
+                        username: p.username.clone(),
+                        link_tier: quality.tier(connection),
+                        link_stats: quality.stats(connection),
+                    })
+                    .collect();
+                entries.sort_unstable_by(|a, b| a.username.cmp(&b.username));
+                sender.send(
+                    &PlayerPanelMessage::Players(entries),
+                    MessageReceivers::Single(event.connection),
+                );
+            }
+            &PlayerPanelRequest::Follow(target) => {
+                // Only one follow per admin
+                for (entity, session) in sessions.iter() {
+                    if session.admin == event.connection {
+                        commands.entity(entity).despawn();
+                    }
+                }
+
+                match target {
+                    Some(target) => {
+                        commands.spawn(FollowSession {
+                            admin: event.connection,
+                            target,
+                            followed_entity: None,
+                        });
+                        info!(
+                            connection = ?event.connection,
+                            player = %target,
+                            "Admin following player"
+                        );
+                    }
+                    None => sender.send(
+                        &PlayerPanelMessage::Following(None),
+                        MessageReceivers::Single(event.connection),
+                    ),
+                }
+            }
+            &PlayerPanelRequest::Stats(player) => {
+                sender.send(
+                    &PlayerPanelMessage::Stats(player, stats.summary(player, &jobs)),
+                    MessageReceivers::Single(event.connection),
+                );
+            }
+            &PlayerPanelRequest::RoleBans(player) => {
+                send_role_bans(player, event.connection, &role_bans, &mut sender);
+            }
+            PlayerPanelRequest::IssueRoleBan {
+                player,
+                scope,
+                hours,
+                reason,
+            } => {
+                let Some(username) = players
+                    .get_connection(player)
+                    .and_then(|connection| players.get(connection))
+                    .map(|p| p.username.clone())
+                else {
+                    continue;
+                };
+                role_bans.issue(
+                    *player,
+                    username,
+                    scope.clone(),
+                    *hours,
+                    reason.clone(),
+                    admin.username.clone(),
+                );
+                send_role_bans(*player, event.connection, &role_bans, &mut sender);
+            }
+            &PlayerPanelRequest::LiftRoleBan(player, id) => {
+                role_bans.lift(id, &admin.username);
+                send_role_bans(player, event.connection, &role_bans, &mut sender);
+            }
+        }
+    }
+}
+
+fn send_role_bans(
+    player: Uuid,
+    admin: ConnectionId,
+    role_bans: &RoleBans,
+    sender: &mut MessageSender,
+) {
+    let bans = role_bans
+        .active()
+        .filter(|ban| ban.player == player)
+        .cloned()
+        .collect();
+    sender.send(
+        &PlayerPanelMessage::RoleBans(player, bans),
+        MessageReceivers::Single(admin),
+    );
+}
+
+fn update_follow_sessions(
+    mut sessions: Query<(Entity, &mut FollowSession)>,
+    transforms: Query<(&GlobalTransform, &NetworkIdentity)>,
+    mut relevancy: ResMut<Relevancy>,
+    controls: Res<ClientControls>,
+    players: Res<Players>,
+    mut sender: MessageSender,
+    mut commands: Commands,
+) {
+    for (session_entity, mut session) in sessions.iter_mut() {
+        if players.get(session.admin).is_none() {
+            commands.entity(session_entity).despawn();
+            continue;
+        }
+
+        let Some((followed, (transform, &identity))) = controls
+            .controlled_entity(session.target)
+            .and_then(|e| Some((e, transforms.get(e).ok()?)))
+        else {
+            relevancy.remove_owner(session_entity);
+            continue;
+        };
+
+        if session.followed_entity != Some(followed) {
+            // Tell the admin when the player changes bodies
+            session.followed_entity = Some(followed);
+            sender.send(
+                &PlayerPanelMessage::Following(Some(identity)),
+                MessageReceivers::Single(session.admin),
+            );
+        }
+
+        relevancy.set_viewers(
+            session_entity,
+            RelevancyTarget::chunks_around(transform.translation())
+                .chain(std::iter::once(followed.into())),
+            session.admin,
+        );
+    }
+}
+
+#[cfg(feature = "client")]
+#[derive(Resource, Default)]
+struct PlayerPanelState {
+    players: Vec<PlayerEntry>,
+    following_player: Option<Uuid>,
+    following_entity: Option<NetworkIdentity>,
+    /// Statistics of the players that are expanded
+    stats: HashMap<Uuid, Option<StatsSummary>>,
+    /// Role bans of the players that are expanded
+    role_bans: HashMap<Uuid, Option<Vec<RoleBan>>>,
+    new_role_ban: NewRoleBan,
+}
+
+/// Role ban being written in the panel.
+#[cfg(feature = "client")]
+#[derive(Default)]
+struct NewRoleBan {
+    scope: String,
+    /// 0 is permanent
+    hours: u64,
+    reason: String,
+}
+
+#[cfg(feature = "client")]
+fn receive_player_panel_messages(
+    mut messages: EventReader<MessageEvent<PlayerPanelMessage>>,
+    mut state: ResMut<PlayerPanelState>,
+    controlled: Query<Entity, With<ClientControlled>>,
+    mut main_camera: Query<&mut TopDownCamera>,
+) {
+    for event in messages.iter() {
+        match &event.message {
+            PlayerPanelMessage::Players(players) => state.players = players.clone(),
+            PlayerPanelMessage::Stats(player, summary) => {
+                if let Some(stats) = state.stats.get_mut(player) {
+                    *stats = Some(summary.clone());
+                }
+            }
+            PlayerPanelMessage::RoleBans(player, bans) => {
+                if let Some(role_bans) = state.role_bans.get_mut(player) {
+                    *role_bans = Some(bans.clone());
+                }
+            }
+            PlayerPanelMessage::Following(entity) => {
+                state.following_entity = *entity;
+                if entity.is_none() {
+                    // Move camera back to our own creature
+                    if let (Ok(player), Ok(mut main_camera)) =
+                        (controlled.get_single(), main_camera.get_single_mut())
+                    {
+                        main_camera.target = player;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "client")]
+fn follow_camera(
+    state: Res<PlayerPanelState>,
+    identities: Res<NetworkIdentities>,
+    mut main_camera: Query<&mut TopDownCamera>,
+) {
+    let Some(target) = state
+        .following_entity
+        .and_then(|id| identities.get_entity(id))
+    else {
+        return;
+    };
+    let Ok(mut main_camera) = main_camera.get_single_mut() else {
+        return;
+    };
+    if main_camera.target != target {
+        main_camera.target = target;
+    }
+}
+
+#[cfg(feature = "client")]
+fn player_panel_ui(
+    mut contexts: EguiContexts,
+    mut state: ResMut<PlayerPanelState>,
+    mut sender: MessageSender,
+) {
+    let state = state.as_mut();
+    egui::Window::new("Players").show(contexts.ctx_mut(), |ui| {
+        if ui.button("Refresh").clicked() {
+            sender.send_to_server(&PlayerPanelRequest::Refresh);
+        }
+
+        for entry in state.players.iter() {
+            ui.horizontal(|ui| {
+                ui.label(&entry.username);
+                let color = match entry.link_tier {
+                    LinkTier::Good => egui::Color32::GREEN,
+                    LinkTier::Degraded => egui::Color32::YELLOW,
+                    LinkTier::Poor => egui::Color32::RED,
+                };
+                let link = ui.colored_label(color, entry.link_tier.to_string());
+                if let Some(stats) = entry.link_stats {
+                    link.on_hover_text(format!(
+                        "{:.0} ms, {:.1}% loss, {:.0}% queued",
+                        stats.rtt,
+                        stats.packet_loss * 100.0,
+                        stats.queue * 100.0
+                    ));
+                }
+                let following = state.following_player == Some(entry.id);
+                if ui.selectable_label(following, "Follow").clicked() {
+                    let target = if following { None } else { Some(entry.id) };
+                    state.following_player = target;
+                    sender.send_to_server(&PlayerPanelRequest::Follow(target));
+                }
+                let expanded = state.stats.contains_key(&entry.id);
+                if ui.selectable_label(expanded, "Stats").clicked() {
+                    if expanded {
+                        state.stats.remove(&entry.id);
+                    } else {
+                        state.stats.insert(entry.id, None);
+                        sender.send_to_server(&PlayerPanelRequest::Stats(entry.id));
+                    }
+                }
+                let expanded = state.role_bans.contains_key(&entry.id);
+                if ui.selectable_label(expanded, "Role bans").clicked() {
+                    if expanded {
+                        state.role_bans.remove(&entry.id);
+                    } else {
+                        state.role_bans.insert(entry.id, None);
+                        sender.send_to_server(&PlayerPanelRequest::RoleBans(entry.id));
+                    }
+                }
+            });
+
+            match state.stats.get(&entry.id) {
+                Some(Some(summary)) => {
+                    ui.indent(("stats", entry.id), |ui| {
+                        for line in summary.lines() {
+                            ui.label(line);
+                        }
+                    });
+                }
+                Some(None) => {
+                    ui.spinner();
+                }
+                None => {}
+            }
+
+            match state.role_bans.get(&entry.id) {
+                Some(Some(bans)) => {
+                    ui.indent(("role_bans", entry.id), |ui| {
+                        role_bans_ui(ui, entry.id, bans, &mut state.new_role_ban, &mut sender);
+                    });
+                }
+                Some(None) => {
+                    ui.spinner();
+                }
+                None => {}
+            }
+        }
+    });
+}
+
+#[cfg(feature = "client")]
+fn role_bans_ui(
+    ui: &mut egui::Ui,
+    player: Uuid,
+    bans: &[RoleBan],
+    new_ban: &mut NewRoleBan,
+    sender: &mut MessageSender,
+) {
+    for ban in bans.iter() {
+        ui.horizontal(|ui| {
+            ui.label(format!(
+                "{} by {}. {}",
+                ban.scope,
+                ban.admin,
+                ban.describe()
+            ));
+            if ui.small_button("Lift").clicked() {
+                sender.send_to_server(&PlayerPanelRequest::LiftRoleBan(player, ban.id));
+            }
+        });
+    }
+
+    ui.horizontal(|ui| {
+        ui.add(
+            egui::TextEdit::singleline(&mut new_ban.scope)
+                .hint_text("job:<id>, antag:<role> or antags")
+                .desired_width(120.0),
+        );
+        ui.add(egui::DragValue::new(&mut new_ban.hours).suffix(" h"))
+            .on_hover_text("0 is permanent");
+        ui.add(
+            egui::TextEdit::singleline(&mut new_ban.reason)
+                .hint_text("Reason")
+                .desired_width(160.0),
+        );
+        let scope = new_ban.scope.parse::<RoleBanScope>();
+        let reason = new_ban.reason.trim();
+        let response = ui.add_enabled(
+            scope.is_ok() && !reason.is_empty(),
+            egui::Button::new("Ban"),
+        );
+        let response = match &scope {
+            Err(err) => response.on_disabled_hover_text(err.as_str()),
+            Ok(_) => response,
+        };
+        if let (true, Ok(scope)) = (response.clicked(), scope) {
+            sender.send_to_server(&PlayerPanelRequest::IssueRoleBan {
+                player,
+                scope,
+                hours: new_ban.hours,
+                reason: reason.to_owned(),
+            });
+            new_ban.reason.clear();
+        }
+    });
+}
+
+pub(crate) struct PlayerPanelPlugin;
+
+impl Plugin for PlayerPanelPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_network_message::<PlayerPanelRequest>("PlayerPanelRequest")
+            .add_network_message::<PlayerPanelMessage>("PlayerPanelMessage");
+
+        if is_server(app) {
+            app.add_systems(
+                Update,
+                (handle_player_panel_requests, update_follow_sessions).chain(),
+            );
+        } else {
+            #[cfg(feature = "client")]
+            app.init_resource::<PlayerPanelState>().add_systems(
+                Update,
+                (
+                    receive_player_panel_messages,
+                    follow_camera,
+                    player_panel_ui.run_if(has_window),
+                )
+                    .chain()
+                    .run_if(in_state(GameState::Game)),
+            );
+        }
+    }
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use networking::{loopback::LinkConditions, testing, NetworkRole};
+
+    use super::*;
+    use crate::testing::server_app;
+
+    /// Asks to follow a player from a connected client and counts the follow sessions.
+    fn follow_sessions(admin: bool) -> usize {
+        let mut server = server_app(ServerConfig::default());
+        let mut client = testing::app(NetworkRole::Client);
+        let connector = testing::listen(&mut server);
+        testing::join(&mut client, &connector, LinkConditions::default());
+        testing::connect(&mut server, &mut [&mut client], 200);
+
+        let (&connection, player) = server
+            .world
+            .resource::<Players>()
+            .players()
+            .iter()
+            .next()
+            .unwrap();
+        let player = player.id;
+        if admin {
+            server
+                .world
+                .resource_mut::<ServerConfig>()
+                .admins
+                .push(player);
+        }
+
+        server.world.send_event(MessageEvent {
+            message: PlayerPanelRequest::Follow(Some(Uuid::from_u128(1))),
+            connection,
+        });
+        testing::update(&mut server, &mut [&mut client], 2);
+        server
+            .world
+            .query::<&FollowSession>()
+            .iter(&server.world)
+            .count()
+    }
+
+    #[test]
+    fn admin_follow_starts_session() {
+        assert_eq!(follow_sessions(true), 1);
+    }
+
+    #[test]
+    fn non_admin_follow_is_ignored() {
+        assert_eq!(follow_sessions(false), 0);
+    }
+}
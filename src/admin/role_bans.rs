@@ -0,0 +1,460 @@
+use std::{
+    fmt::{Display, Write},
+    fs::{read_to_string, write},
+    str::FromStr,
+};
+
+use bevy::{prelude::*, utils::Uuid};
+use networking::{is_server, Players};
+use serde::{Deserialize, Serialize};
+
+use crate::console::{
+    ArgumentKind, CommandContext, CommandResult, CommandSource, ConsoleAppExt, ConsoleCommand,
+    PermissionLevel,
+};
+
+use super::commands::unix_now;
+
+/// Bans from single jobs or antagonist roles that keep the player on the server.
+pub(crate) struct RoleBansPlugin;
+
+impl Plugin for RoleBansPlugin {
+    fn build(&self, app: &mut App) {
+        if !is_server(app) {
+            return;
+        }
+
+        app.add_systems(Startup, load_role_bans)
+            .add_console_command(ConsoleCommand {
+                name: "roleban",
+                description: "Bans a player from a job (job:<id>), an antagonist role (antag:<role>) or all antagonist roles (antags) for some hours, 0 is permanent",
+                parameters: &[
+                    ("player", ArgumentKind::Player),
+                    ("scope", ArgumentKind::Word),
+                    ("hours", ArgumentKind::Integer),
+                    ("reason", ArgumentKind::Text),
+                ],
+                permission: PermissionLevel::Admin,
+                handler: roleban_command,
+            })
+            .add_console_command(ConsoleCommand {
+                name: "rolebans",
+                description: "Lists the role bans that haven't run out",
+                parameters: &[],
+                permission: PermissionLevel::Admin,
+                handler: rolebans_command,
+            })
+            .add_console_command(ConsoleCommand {
+                name: "unroleban",
+                description: "Lifts a role ban by its number",
+                parameters: &[("id", ArgumentKind::Integer)],
+                permission: PermissionLevel::Admin,
+                handler: unroleban_command,
+            });
+    }
+}
+
+const ROLE_BANS_FILE: &str = "role_bans.ron";
+
+/// What a role ban keeps a player from.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub enum RoleBanScope {
+    /// A job by its id
+    Job(String),
+    /// An antagonist role by its id, like `traitor`
+    Antagonist(String),
+    AllAntagonists,
+}
+
+impl RoleBanScope {
+    fn covers_job(&self, job: &str) -> bool {
+        matches!(self, RoleBanScope::Job(id) if id == job)
+    }
+
+    fn covers_antagonist(&self, role: &str) -> bool {
+        match self {
+            RoleBanScope::Job(_) => false,
+            RoleBanScope::Antagonist(id) => id == role,
+            RoleBanScope::AllAntagonists => true,
+        }
+    }
+}
+
+impl FromStr for RoleBanScope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "antags" {
+            return Ok(RoleBanScope::AllAntagonists);
+        }
+        match s.split_once(':') {
+            Some(("job", id)) if !id.is_empty() => Ok(RoleBanScope::Job(id.to_owned())),
+            Some(("antag", id)) if !id.is_empty() => Ok(RoleBanScope::Antagonist(id.to_owned())),
+            _ => Err(format!(
+                "{} is not a role, use job:<id>, antag:<role> or antags",
+                s
+            )),
+        }
+    }
+}
+
+impl Display for RoleBanScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RoleBanScope::Job(id) => write!(f, "job:{}", id),
+            RoleBanScope::Antagonist(id) => write!(f, "antag:{}", id),
+            RoleBanScope::AllAntagonists => write!(f, "antags"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RoleBan {
+    /// Number admins lift the ban by
+    pub id: u32,
+    pub player: Uuid,
+    /// Name of the player when they were banned
+    pub username: String,
+    pub scope: RoleBanScope,
+    pub reason: String,
+    /// Name of the admin that issued the ban
+    pub admin: String,
+    /// Unix time the ban runs out at, `None` if it is permanent
+    pub expires: Option<u64>,
+}
+
+impl RoleBan {
+    fn active(&self, now: u64) -> bool {
+        self.expires.map_or(true, |expires| now < expires)
+    }
+
+    /// Reason and duration, as shown to the banned player.
+    pub fn describe(&self) -> String {
+        let now = unix_now();
+        match self.expires {
+            Some(expires) => format!(
+                "Banned for {} more hours: {}",
+                (expires.saturating_sub(now) + 3599) / 3600,
+                self.reason
+            ),
+            None => format!("Banned: {}", self.reason),
+        }
+    }
+}
+
+/// All role bans, including the ones that ran out since the file was last written.
+#[derive(Resource, Default)]
+pub struct RoleBans {
+    bans: Vec<RoleBan>,
+}
+
+impl RoleBans {
+    /// The active ban keeping the player from the job, if there is one.
+    pub fn job_ban(&self, player: Uuid, job: &str) -> Option<&RoleBan> {
+        let now = unix_now();
+        self.bans
+            .iter()
+            .find(|ban| ban.player == player && ban.active(now) && ban.scope.covers_job(job))
+    }
+
+    /// The active ban keeping the player from the antagonist role, if there is one.
+    pub fn antagonist_ban(&self, player: Uuid, role: &str) -> Option<&RoleBan> {
+        let now = unix_now();
+        self.bans.iter().find(|ban| {
+            ban.player == player && ban.active(now) && ban.scope.covers_antagonist(role)
+        })
+    }
+
+    /// Active bans of every player, oldest first.
+    pub fn active(&self) -> impl Iterator<Item = &RoleBan> {
+        let now = unix_now();
+        self.bans.iter().filter(move |ban| ban.active(now))
+    }
+
+    /// Bans the player and writes the bans file.
+    /// `hours` of 0 makes the ban permanent.
+    pub fn issue(
+        &mut self,
+        player: Uuid,
+        username: String,
+        scope: RoleBanScope,
+        hours: u64,
+        reason: String,
+        admin: String,
+    ) -> &RoleBan {
+        let now = unix_now();
+        self.bans.retain(|ban| ban.active(now));
+        let id = self.bans.iter().map(|ban| ban.id + 1).max().unwrap_or(1);
+        let expires = (hours > 0).then(|| now + hours * 3600);
+        info!(
+            id,
+            player = %player,
+            username = username.as_str(),
+            scope = %scope,
+            hours,
+            reason = reason.as_str(),
+            admin = admin.as_str(),
+            "Role banned player"
+        );
+        self.bans.push(RoleBan {
+            id,
+            player,
+            username,
+            scope,
+            reason,
+            admin,
+            expires,
+        });
+        self.save();
+        self.bans.last().unwrap()
+    }
+
+    /// Removes the ban and writes the bans file. Returns `None` if there is no ban with the id.
+    pub fn lift(&mut self, id: u32, admin: &str) -> Option<RoleBan> {
+        let index = self.bans.iter().position(|ban| ban.id == id)?;
+        let ban = self.bans.remove(index);
+        info!(
+            id,
+            player = %ban.player,
+            username = ban.username.as_str(),
+            scope = %ban.scope,
+            admin,
+            "Lifted role ban"
+        );
+        self.save();
+        Some(ban)
+    }
+
+    fn save(&self) {
+        let text = match ron::ser::to_string_pretty(&self.bans, Default::default()) {
+            Ok(text) => text,
+            Err(err) => {
+                error!(error = %err, "Could not serialize role bans");
+                return;
+            }
+        };
+        if let Err(err) = write(ROLE_BANS_FILE, text) {
+            error!(error = %err, "Could not write {}", ROLE_BANS_FILE);
+        }
+    }
+}
+
+fn load_role_bans(mut commands: Commands) {
+    let bans = match read_to_string(ROLE_BANS_FILE) {
+        Ok(text) => ron::from_str(&text).unwrap_or_else(|err| {
+            error!(error = %err, "Error parsing {}", ROLE_BANS_FILE);
+            Vec::new()
+        }),
+        // No one has been role banned yet
+        Err(_) => Vec::new(),
+    };
+    commands.insert_resource(RoleBans { bans });
+}
+
+/// Name logged as the issuer of a ban.
+pub(super) fn admin_name(world: &World, source: CommandSource) -> String {
+    match source {
+        CommandSource::Console => "console".to_owned(),
+        CommandSource::Player(connection) => world
+            .resource::<Players>()
+            .get(connection)
+            .map(|p| p.username.clone())
+            .unwrap_or_default(),
+    }
+}
+
+fn roleban_command(world: &mut World, context: &CommandContext) -> CommandResult {
+    let connection = context.player(0);
+    let scope: RoleBanScope = context.text(1).parse()?;
+    let hours = u64::try_from(context.integer(2)).map_err(|_| "invalid hours".to_string())?;
+    let reason = context.text(3);
+    let Some((id, username)) = world
+        .resource::<Players>()
+        .get(connection)
+        .map(|p| (p.id, p.username.clone()))
+    else {
+        return Err("player disconnected".into());
+    };
+
+    let admin = admin_name(world, context.source);
+    let ban = world.resource_mut::<RoleBans>().issue(
+        id,
+        username,
+        scope,
+        hours,
+        reason.to_owned(),
+        admin,
+    );
+    Ok(format!(
+        "Banned {} from {} (ban {})",
+        ban.username, ban.scope, ban.id
+    ))
+}
+
+fn rolebans_command(world: &mut World, _: &CommandContext) -> CommandResult {
+    let mut text = String::new();
+    for ban in world.resource::<RoleBans>().active() {
+        let _ = writeln!(
+            text,
+            "{}: {} from {} by {}. {}",
+            ban.id,
+            ban.username,
+            ban.scope,
+            ban.admin,
+            ban.describe()
+        );
+    }
+    if text.is_empty() {
+        return Ok("No one is role banned".into());
+    }
+    Ok(text.trim_end().to_owned())
+}
+
+fn unroleban_command(world: &mut World, context: &CommandContext) -> CommandResult {
+    let id = u32::try_from(context.integer(0)).map_err(|_| "invalid ban".to_string())?;
+    let admin = admin_name(world, context.source);
+    let Some(ban) = world.resource_mut::<RoleBans>().lift(id, &admin) else {
+        return Err(format!("there is no role ban {}", id));
+    };
+    Ok(format!(
+        "Lifted the ban of {} from {}",
+        ban.username, ban.scope
+    ))
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use bevy::{
+        asset::{AssetPath, AssetPathId},
+        ecs::system::SystemState,
+    };
+    use networking::{loopback::LinkConditions, testing, NetworkRole};
+
+    use super::*;
+    use crate::{
+        config::ServerConfig,
+        job::{JobAssigner, JobDefinition, JobRejection, JobSlots, SelectedJobs},
+        rng::GameRng,
+        round::{modes::TRAITOR_ROLE, traitor::draw_traitors},
+        testing::server_app,
+    };
+
+    fn ban(player: Uuid, scope: RoleBanScope, expires: Option<u64>) -> RoleBan {
+        RoleBan {
+            id: 1,
+            player,
+            username: "griefer".into(),
+            scope,
+            reason: "testing".into(),
+            admin: "console".into(),
+            expires,
+        }
+    }
+
+    #[test]
+    fn job_ban_skips_the_selected_job_at_roundstart() {
+        let mut server = server_app(ServerConfig::default());
+        let mut client = testing::app(NetworkRole::Client);
+        let connector = testing::listen(&mut server);
+        testing::join(&mut client, &connector, LinkConditions::default());
+        testing::connect(&mut server, &mut [&mut client], 200);
+
+        let job: JobDefinition = ron::from_str(
+            r#"(id: "security", name: "Security Officer", description: "", clothing: [], max_slots: Some(1))"#,
+        )
+        .unwrap();
+        let job_id = AssetPathId::from(AssetPath::from("jobs/security.job.ron"));
+        server
+            .world
+            .resource_mut::<Assets<JobDefinition>>()
+            .set_untracked(job_id, job);
+        let (&connection, player) = server
+            .world
+            .resource::<Players>()
+            .players()
+            .iter()
+            .next()
+            .unwrap();
+        let player = player.id;
+        server
+            .world
+            .resource_mut::<SelectedJobs>()
+            .select(connection, job_id);
+        server.world.insert_resource(RoleBans {
+            bans: vec![ban(player, RoleBanScope::Job("security".into()), None)],
+        });
+
+        let mut state = SystemState::<JobAssigner>::new(&mut server.world);
+        let mut jobs = state.get_mut(&mut server.world);
+        assert!(matches!(
+            jobs.assign(connection, player),
+            Err(JobRejection::Banned(_, _))
+        ));
+        let slots = server.world.resource::<JobSlots>();
+        let assets = server.world.resource::<Assets<JobDefinition>>();
+        let job = assets.get(&assets.get_handle(job_id)).unwrap();
+        assert_eq!(slots.open(job), Some(1));
+
+        server.world.resource_mut::<RoleBans>().bans.clear();
+        let mut jobs = state.get_mut(&mut server.world);
+        assert_eq!(jobs.assign(connection, player).unwrap().id, "security");
+    }
+
+    #[test]
+    fn antagonist_ban_is_never_drawn() {
+        let banned = Uuid::from_u128(1);
+        let crew: Vec<_> = (1..=8).map(Uuid::from_u128).collect();
+        let role_bans = RoleBans {
+            bans: vec![ban(banned, RoleBanScope::AllAntagonists, None)],
+        };
+
+        for seed in 0..200 {
+            let mut rng = GameRng::new(seed);
+            let candidates = crew.iter().map(|&player| (player, ())).collect();
+            let traitors = draw_traitors(candidates, &role_bans, 0.5, rng.stream("traitors"));
+            assert_eq!(traitors.len(), 4);
+            assert!(traitors.iter().all(|(player, _)| *player != banned));
+        }
+
+        // Without the ban they are drawn with the same seeds
+        let unbanned = (0..200).any(|seed| {
+            let mut rng = GameRng::new(seed);
+            let candidates = crew.iter().map(|&player| (player, ())).collect();
+            draw_traitors(
+                candidates,
+                &RoleBans::default(),
+                0.5,
+                rng.stream("traitors"),
+            )
+            .iter()
+            .any(|(player, _)| *player == banned)
+        });
+        assert!(unbanned);
+    }
+
+    #[test]
+    fn expired_bans_are_ignored() {
+        let player = Uuid::from_u128(1);
+        let now = unix_now();
+        let mut role_bans = RoleBans {
+            bans: vec![
+                ban(player, RoleBanScope::Job("security".into()), Some(now - 1)),
+                ban(
+                    player,
+                    RoleBanScope::Antagonist(TRAITOR_ROLE.into()),
+                    Some(now - 1),
+                ),
+            ],
+        };
+        assert!(role_bans.job_ban(player, "security").is_none());
+        assert!(role_bans.antagonist_ban(player, TRAITOR_ROLE).is_none());
+        assert_eq!(role_bans.active().count(), 0);
+
+        for ban in &mut role_bans.bans {
+            ban.expires = Some(now + 3600);
+        }
+        assert!(role_bans.job_ban(player, "security").is_some());
+        assert!(role_bans.antagonist_ban(player, TRAITOR_ROLE).is_some());
+        assert_eq!(role_bans.active().count(), 2);
+    }
+}
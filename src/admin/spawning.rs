@@ -1,40 +1,46 @@
 use bevy::{
     asset::{AssetPathId, HandleId},
-    input::Input,
     math::Vec3,
     prelude::*,
-    reflect::Reflect,
-    scene::DynamicScene,
-    window::PrimaryWindow,
 };
-use bevy_egui::{egui, EguiContexts};
-use bevy_rapier3d::plugin::RapierContext;
 use networking::{
     is_server,
-    messaging::{AppExt, MessageEvent, MessageSender},
+    messaging::{AppExt, InvalidMessage, MessageEvent},
     scene::NetworkSceneBundle,
 };
 use serde::{Deserialize, Serialize};
 
-use crate::{
-    camera::MainCamera,
-    interaction::InteractionSystem,
-    items::{Item, ItemAssets},
-    ui::has_window,
-    GameState,
+use crate::items::ItemAssets;
+
+#[cfg(feature = "client")]
+use {
+    crate::{
+        camera::MainCamera,
+        interaction::InteractionSystem,
+        items::Item,
+        ui::{has_window, InputBlocks},
+        GameState,
+    },
+    bevy::{input::Input, reflect::Reflect, scene::DynamicScene, window::PrimaryWindow},
+    bevy_egui::{egui, EguiContexts},
+    maps::{cursor_tile, tile_to_world, HighlightRequest, HighlightTarget, TileMapClient},
+    networking::messaging::MessageSender,
 };
 
+#[cfg(feature = "client")]
 struct ItemData {
     name: String,
     id: AssetPathId,
 }
 
+#[cfg(feature = "client")]
 #[derive(Resource, Default)]
 struct SpawnerUiState {
     all_items: Vec<ItemData>,
     to_spawn: Option<AssetPathId>,
 }
 
+#[cfg(feature = "client")]
 fn spawning_ui(mut contexts: EguiContexts, mut state: ResMut<SpawnerUiState>) {
     let state = state.as_mut();
     egui::Window::new("Spawning").show(contexts.ctx_mut(), |ui| {
@@ -45,6 +51,7 @@ fn spawning_ui(mut contexts: EguiContexts, mut state: ResMut<SpawnerUiState>) {
     });
 }
 
+#[cfg(feature = "client")]
 fn prepare_item_ui_data(
     assets: Res<ItemAssets>,
     mut events: EventReader<AssetEvent<DynamicScene>>,
@@ -94,73 +101,87 @@ enum SpawnerMessage {
     Request((Vec3, AssetPathId)),
 }
 
+#[cfg(feature = "client")]
 #[allow(clippy::too_many_arguments)]
 fn spawn_requesting(
     ui_state: Res<SpawnerUiState>,
     mut buttons: ResMut<Input<MouseButton>>,
     mut contexts: EguiContexts,
-    rapier_context: Res<RapierContext>,
     windows: Query<(Entity, &Window), With<PrimaryWindow>>,
     cameras: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    tilemaps: Query<(Entity, &TileMapClient, &GlobalTransform)>,
+    mut highlight: ResMut<HighlightRequest>,
+    blocks: Res<InputBlocks>,
     mut sender: MessageSender,
 ) {
-    if ui_state.to_spawn.is_none() {
+    let Some(to_spawn) = ui_state.to_spawn else {
         return;
-    }
-
-    if !buttons.just_pressed(MouseButton::Left) {
-        return;
-    }
+    };
 
     let Ok((window_entity, window)) = windows.get_single() else {
         return;
     };
 
-    if contexts
-        .try_ctx_for_window_mut(window_entity)
-        .map(|c| c.wants_pointer_input())
-        == Some(true)
+    // Dragged items and the radial menu own the mouse until they are released
+    if blocks.is_blocked()
+        || contexts
+            .try_ctx_for_window_mut(window_entity)
+            .map(|c| c.wants_pointer_input())
+            == Some(true)
     {
         return;
     }
 
-    // Consume the click
-    buttons.clear_just_pressed(MouseButton::Left);
-
     let (camera, camera_transform) = match cameras.iter().next() {
         Some(o) => o,
         None => return,
     };
-    let cursor_position = match window.cursor_position() {
-        Some(p) => p,
-        None => return,
+    let Ok((map_entity, map, map_transform)) = tilemaps.get_single() else {
+        return;
+    };
+    let Some(tile) = cursor_tile(window, camera, camera_transform, map, map_transform) else {
+        return;
     };
 
-    let Ray { origin, direction } =
-        match camera.viewport_to_world(camera_transform, cursor_position) {
-            Some(r) => r,
-            None => return,
-        };
+    // Items can be spawned anywhere there's a floor
+    let valid = map.tile(tile).and_then(|t| t.turf).is_some();
+    highlight.target = Some(HighlightTarget {
+        tilemap: map_entity,
+        position: tile,
+        valid,
+    });
 
-    if let Some((_, toi)) =
-        rapier_context.cast_ray(origin, direction, 100.0, true, Default::default())
-    {
-        let hit_point = origin + direction * toi;
-        info!(position=?hit_point, "Requesting object spawn");
-        sender.send_to_server(&SpawnerMessage::Request((
-            hit_point,
-            ui_state.to_spawn.unwrap(),
-        )));
+    if !buttons.just_pressed(MouseButton::Left) {
+        return;
     }
+
+    // Consume the click
+    buttons.clear_just_pressed(MouseButton::Left);
+
+    if !valid {
+        return;
+    }
+
+    let position = tile_to_world(map_transform, tile);
+    info!(?position, "Requesting object spawn");
+    sender.send_to_server(&SpawnerMessage::Request((position, to_spawn)));
 }
 
 fn handle_spawn_request(
     mut messages: EventReader<MessageEvent<SpawnerMessage>>,
+    mut invalid: EventWriter<InvalidMessage>,
     mut commands: Commands,
     assets: Res<ItemAssets>,
 ) {
     for event in messages.iter() {
         let SpawnerMessage::Request((position, id)) = event.message;
+        if !position.is_finite() {
+            invalid.send(InvalidMessage {
+                connection: event.connection,
+                reason: "non-finite spawn position",
+            });
+            continue;
+        }
         let exists = assets
             .definitions
             .iter()
@@ -183,7 +204,7 @@ pub(crate) struct SpawningPlugin;
 
 impl Plugin for SpawningPlugin {
     fn build(&self, app: &mut App) {
-        app.add_network_message::<SpawnerMessage>();
+        app.add_network_message::<SpawnerMessage>("SpawnerMessage");
 
         if is_server(app) {
             app.add_systems(
@@ -191,6 +212,7 @@ impl Plugin for SpawningPlugin {
                 handle_spawn_request.run_if(on_event::<MessageEvent<SpawnerMessage>>()),
             );
         } else {
+            #[cfg(feature = "client")]
             app.init_resource::<SpawnerUiState>().add_systems(
                 Update,
                 (
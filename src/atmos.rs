@@ -0,0 +1,57 @@
+use bevy::prelude::*;
+use networking::is_server;
+
+use crate::diffusion::{diffuse, disturb_changed_tiles, resize_grid, Diffusing, DiffusionGrid};
+
+use self::pipes::PipesPlugin;
+
+pub mod pipes;
+
+/// Air pressure of every tile, which spreads between open tiles and is lost to space.
+pub struct AtmosPlugin;
+
+impl Plugin for AtmosPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(PipesPlugin);
+
+        if is_server(app) {
+            app.init_resource::<AtmosGrid>().add_systems(
+                Update,
+                (
+                    resize_grid::<Air>,
+                    disturb_changed_tiles::<Air>,
+                    diffuse::<Air>,
+                )
+                    .chain(),
+            );
+        }
+    }
+}
+
+/// Pressure of air at sea level on earth, in kilopascal
+pub const ONE_ATMOSPHERE: f32 = 101.325;
+/// Volume of air above a tile, in liters
+pub const TILE_VOLUME: f32 = 2500.0;
+
+/// Air spreading between tiles, measured in kilopascal.
+pub struct Air;
+
+impl Diffusing for Air {
+    const INITIAL: f32 = ONE_ATMOSPHERE;
+    const SPACE: f32 = 0.0;
+}
+
+/// Air pressure of every tile on the map.
+pub type AtmosGrid = DiffusionGrid<Air>;
+
+impl AtmosGrid {
+    /// Adds (or removes) an amount of gas to a tile, in kilopascal liters.
+    pub fn add_gas(&mut self, position: UVec2, amount: f32) {
+        if !self.contains(position) {
+            return;
+        }
+        let pressure = (self.get(position) + amount / TILE_VOLUME).max(0.0);
+        self.set(position, pressure);
+        self.disturb(position);
+    }
+}
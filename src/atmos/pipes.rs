@@ -0,0 +1,826 @@
+use std::time::Duration;
+
+use bevy::{
+    prelude::*,
+    reflect::TypeUuid,
+    time::common_conditions::on_timer,
+    utils::{HashMap, HashSet},
+};
+use maps::{
+    tile_neighbours, tile_to_world, world_to_tile, TileEntity, TileMap, TileMapClient, CHUNK_SIZE,
+};
+use networking::{
+    component::AppExt as ComponentAppExt,
+    is_server,
+    messaging::{AppExt, MessageEvent, MessageReceivers, MessageSender},
+    spawning::ClientControls,
+    variable::{NetworkVar, ServerVar},
+    ConnectionId, Networked, Players,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    communication::SystemMessageEvent,
+    console::{
+        CommandContext, CommandResult, CommandSource, ConsoleAppExt, ConsoleCommand,
+        PermissionLevel,
+    },
+    diffusion::{tile_kind, TileKind, SIMULATION_INTERVAL},
+    door::{Door, DoorState},
+    interaction::{
+        ActiveInteraction, GenerateInteractionList, InteractionListEvents, InteractionOption,
+        InteractionSpecificity, InteractionStatus, Reach,
+    },
+    machines::apc::{PowerConsumer, UnpoweredAreas},
+    temperature::Airtight,
+};
+
+use super::{AtmosGrid, TILE_VOLUME};
+
+/// Pipes under the floor that join into networks, and the vents, scrubbers and pumps moving gas through them.
+pub(super) struct PipesPlugin;
+
+impl Plugin for PipesPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Pipe>()
+            .register_type::<PipeDevice>()
+            .register_type::<PipeDeviceKind>()
+            .register_type::<DeviceIndicator>()
+            .add_networked_component::<PipeDeviceState, PipeDeviceStateClient>()
+            .add_network_message::<PipeNetDebugMessage>("PipeNetDebugMessage");
+
+        if is_server(app) {
+            app.init_resource::<PipeNetworks>()
+                .init_resource::<PipeDebugViewers>()
+                .register_type::<TogglePipeDeviceInteraction>()
+                .register_type::<ExaminePipeDeviceInteraction>()
+                .add_console_command(ConsoleCommand {
+                    name: "pipenet",
+                    description: "Toggles the pipe network overlay around you",
+                    parameters: &[],
+                    permission: PermissionLevel::Admin,
+                    handler: pipenet_command,
+                })
+                .add_systems(
+                    Update,
+                    (
+                        setup_devices,
+                        (track_pipes, exchange_gas).chain(),
+                        prepare_device_interactions.in_set(GenerateInteractionList),
+                        toggle_device_interaction,
+                        examine_device_interaction,
+                        send_pipe_debug
+                            .after(track_pipes)
+                            .run_if(on_timer(Duration::from_secs_f32(PIPE_DEBUG_INTERVAL))),
+                    ),
+                );
+        } else {
+            app.init_resource::<ClientPipeNets>().add_systems(
+                Update,
+                (
+                    show_device_state,
+                    (receive_pipe_debug, draw_pipe_nets).chain(),
+                ),
+            );
+        }
+    }
+}
+
+/// Volume of gas a single pipe holds, in liters
+pub const PIPE_VOLUME: f32 = 70.0;
+/// Most devices exchanging gas in a simulation step.
+/// With more devices than this, each one moves more gas less often.
+const DEVICE_UPDATES_PER_STEP: usize = 64;
+/// Seconds between pipe network updates sent to admins viewing them
+const PIPE_DEBUG_INTERVAL: f32 = 1.0;
+
+/// A gas pipe in the pipe layer of a tile. Pipes on neighbouring tiles join into one network sharing their gas.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct Pipe;
+
+#[derive(Reflect, Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PipeDeviceKind {
+    /// Lets gas out of the pipes into the tile
+    #[default]
+    Vent,
+    /// Pulls gas from the tile into the pipes
+    Scrubber,
+    /// Moves gas from the pipe behind it to the pipe in front of it while powered
+    Pump,
+}
+
+impl PipeDeviceKind {
+    fn name(self) -> &'static str {
+        match self {
+            PipeDeviceKind::Vent => "vent",
+            PipeDeviceKind::Scrubber => "scrubber",
+            PipeDeviceKind::Pump => "pump",
+        }
+    }
+}
+
+/// A device connected to the pipe network of the tile it is on.
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct PipeDevice {
+    pub kind: PipeDeviceKind,
+    /// Most gas moved per second, in kilopascal liters
+    pub rate: f32,
+    /// Pumps stop when the pipes they push into reach this pressure, in kilopascal
+    pub max_pressure: f32,
+    /// If the device is on when it is spawned
+    pub on: bool,
+}
+
+impl Default for PipeDevice {
+    fn default() -> Self {
+        Self {
+            kind: PipeDeviceKind::Vent,
+            rate: 5000.0,
+            max_pressure: 4500.0,
+            on: true,
+        }
+    }
+}
+
+/// Part of a device that is only shown while the device is on.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct DeviceIndicator;
+
+/// If a pipe device is on, visible to everyone near it.
+#[derive(Component, Networked)]
+#[networked(client = "PipeDeviceStateClient")]
+pub struct PipeDeviceState {
+    on: NetworkVar<bool>,
+    /// Elapsed seconds when the device last exchanged gas
+    last_update: f32,
+}
+
+impl PipeDeviceState {
+    pub fn is_on(&self) -> bool {
+        *self.on
+    }
+
+    pub fn set_on(&mut self, on: bool) {
+        if *self.on != on {
+            *self.on = on;
+        }
+    }
+}
+
+#[derive(Component, Default, TypeUuid, Networked)]
+#[uuid = "3e9b5c71-0d4a-4f26-b8e1-7a2c6f915d08"]
+#[networked(server = "PipeDeviceState")]
+pub struct PipeDeviceStateClient {
+    on: ServerVar<bool>,
+}
+
+/// Pipes connected to each other and the gas they share.
+#[derive(Default)]
+pub struct PipeNetwork {
+    pipes: u32,
+    /// Amount of gas in kilopascal liters
+    gas: f32,
+}
+
+impl PipeNetwork {
+    /// Volume of all pipes in liters
+    pub fn volume(&self) -> f32 {
+        self.pipes as f32 * PIPE_VOLUME
+    }
+
+    /// Pressure of the gas in kilopascal
+    pub fn pressure(&self) -> f32 {
+        if self.pipes == 0 {
+            0.0
+        } else {
+            self.gas / self.volume()
+        }
+    }
+}
+
+/// All pipe networks on the map.
+/// Networks are kept up to date as pipes are added and removed, only looking at the networks next to the change.
+// TODO: Support multiple maps
+#[derive(Resource, Default)]
+pub struct PipeNetworks {
+    /// Network id of the pipe on every tile that has one
+    tiles: HashMap<UVec2, u32>,
+    networks: HashMap<u32, PipeNetwork>,
+    /// Tile of every pipe, to know where removed pipes were
+    pipes: HashMap<Entity, UVec2>,
+    next_id: u32,
+}
+
+impl PipeNetworks {
+    /// Id of the network the pipe on a tile is part of.
+    pub fn network_at(&self, position: UVec2) -> Option<u32> {
+        self.tiles.get(&position).copied()
+    }
+
+    pub fn get(&self, id: u32) -> Option<&PipeNetwork> {
+        self.networks.get(&id)
+    }
+
+    fn get_mut(&mut self, id: u32) -> Option<&mut PipeNetwork> {
+        self.networks.get_mut(&id)
+    }
+
+    fn new_network(&mut self) -> u32 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.networks.insert(id, PipeNetwork::default());
+        id
+    }
+
+    /// Pipes of a network that are connected to the start, found with a flood fill.
+    fn connected(&self, start: UVec2, id: u32) -> HashSet<UVec2> {
+        let mut found = HashSet::default();
+        found.insert(start);
+        let mut open = vec![start];
+        while let Some(position) = open.pop() {
+            for (_, neighbour) in tile_neighbours(position) {
+                if self.network_at(neighbour) == Some(id) && found.insert(neighbour) {
+                    open.push(neighbour);
+                }
+            }
+        }
+        found
+    }
+
+    /// Adds a pipe, joining all networks next to it.
+    fn add_pipe(&mut self, position: UVec2) {
+        if self.tiles.contains_key(&position) {
+            return;
+        }
+
+        let mut neighbours: Vec<(UVec2, u32)> = tile_neighbours(position)
+            .filter_map(|(_, n)| Some((n, self.network_at(n)?)))
+            .collect();
+        neighbours.sort_unstable_by_key(|(_, id)| *id);
+        neighbours.dedup_by_key(|(_, id)| *id);
+
+        // The biggest network is kept, so the fewest pipes have to be moved into it
+        let id = neighbours
+            .iter()
+            .map(|&(_, id)| id)
+            .max_by_key(|id| self.networks.get(id).map_or(0, |n| n.pipes))
+            .unwrap_or_else(|| self.new_network());
+        for (start, other) in neighbours.into_iter().filter(|&(_, other)| other != id) {
+            for pipe in self.connected(start, other) {
+                self.tiles.insert(pipe, id);
+            }
+            let merged = self.networks.remove(&other).unwrap_or_default();
+            let network = self.networks.get_mut(&id).unwrap();
+            network.pipes += merged.pipes;
+            network.gas += merged.gas;
+        }
+
+        self.tiles.insert(position, id);
+        self.networks.get_mut(&id).unwrap().pipes += 1;
+    }
+
+    /// Removes a pipe, splitting its network if it was the only connection.
+    /// Returns the gas that was in the pipe.
+    fn remove_pipe(&mut self, position: UVec2) -> f32 {
+        let Some(id) = self.tiles.remove(&position) else {
+            return 0.0;
+        };
+        let Some(network) = self.networks.get_mut(&id) else {
+            return 0.0;
+        };
+        let released = network.gas / network.pipes as f32;
+        network.gas -= released;
+        network.pipes -= 1;
+        if network.pipes == 0 {
+            self.networks.remove(&id);
+            return released;
+        }
+
+        let (total_pipes, total_gas) = (network.pipes, network.gas);
+        let starts: Vec<UVec2> = tile_neighbours(position)
+            .map(|(_, n)| n)
+            .filter(|&n| self.network_at(n) == Some(id))
+            .collect();
+        let Some(&first) = starts.first() else {
+            return released;
+        };
+        let kept = self.connected(first, id);
+        if kept.len() as u32 == total_pipes {
+            return released;
+        }
+
+        // Every other part becomes its own network, with gas for its share of the pipes
+        for &start in starts.iter().skip(1) {
+            if kept.contains(&start) || self.network_at(start) != Some(id) {
+                continue;
+            }
+            let part = self.connected(start, id);
+            let new_id = self.new_network();
+            for &pipe in part.iter() {
+                self.tiles.insert(pipe, new_id);
+            }
+            let gas = total_gas * part.len() as f32 / total_pipes as f32;
+            let new_network = self.networks.get_mut(&new_id).unwrap();
+            new_network.pipes = part.len() as u32;
+            new_network.gas = gas;
+            let old = self.networks.get_mut(&id).unwrap();
+            old.pipes -= part.len() as u32;
+            old.gas -= gas;
+        }
+        released
+    }
+}
+
+fn setup_devices(
+    devices: Query<(Entity, &PipeDevice), Added<PipeDevice>>,
+    time: Res<Time>,
+    mut commands: Commands,
+) {
+    for (entity, device) in devices.iter() {
+        commands.entity(entity).insert(PipeDeviceState {
+            on: device.on.into(),
+            last_update: time.elapsed_seconds(),
+        });
+    }
+}
+
+fn track_pipes(
+    added: Query<(Entity, &TileEntity), Added<Pipe>>,
+    mut removed: RemovedComponents<Pipe>,
+    mut networks: ResMut<PipeNetworks>,
+    mut atmos: ResMut<AtmosGrid>,
+) {
+    for entity in removed.iter() {
+        let Some(position) = networks.pipes.remove(&entity) else {
+            continue;
+        };
+        // Whatever was in the pipe leaks out
+        let released = networks.remove_pipe(position);
+        atmos.add_gas(position, released);
+    }
+    for (entity, tile) in added.iter() {
+        networks.pipes.insert(entity, tile.position());
+        networks.add_pipe(tile.position());
+    }
+}
+
+/// Moves gas between pipe networks and the tiles their devices are on.
+#[allow(clippy::too_many_arguments)]
+fn exchange_gas(
+    mut devices: Query<(
+        &PipeDevice,
+        &mut PipeDeviceState,
+        &GlobalTransform,
+        Option<&PowerConsumer>,
+    )>,
+    mut networks: ResMut<PipeNetworks>,
+    mut atmos: ResMut<AtmosGrid>,
+    unpowered: Res<UnpoweredAreas>,
+    maps: Query<&TileMap>,
+    airtight: Query<(), With<Airtight>>,
+    doors: Query<&DoorState, With<Door>>,
+    time: Res<Time>,
+    mut last_step: Local<f32>,
+    mut next_device: Local<usize>,
+) {
+    let now = time.elapsed_seconds();
+    if now - *last_step < SIMULATION_INTERVAL {
+        return;
+    }
+    *last_step = now;
+    let map = maps.get_single().ok();
+
+    // Devices take turns, so the cost of a step stays the same no matter how many there are
+    let count = devices.iter().len();
+    if count == 0 {
+        return;
+    }
+    let first = *next_device % count;
+    let updates = count.min(DEVICE_UPDATES_PER_STEP);
+    *next_device = first + updates;
+
+    for (index, (device, mut state, transform, consumer)) in devices.iter_mut().enumerate() {
+        if (index + count - first) % count >= updates {
+            continue;
+        }
+        let elapsed = now - state.last_update;
+        state.last_update = now;
+        if !state.is_on() {
+            continue;
+        }
+        let translation = transform.translation();
+        let Some(position) = world_to_tile(translation) else {
+            continue;
+        };
+        let limit = device.rate * elapsed;
+
+        match device.kind {
+            PipeDeviceKind::Vent | PipeDeviceKind::Scrubber => {
+                let open = map.is_some_and(|map| {
+                    tile_kind(map, position, &airtight, &doors) == TileKind::Open
+                });
+                let Some(network) = networks
+                    .network_at(position)
+                    .and_then(|id| networks.get_mut(id))
+                else {
+                    continue;
+                };
+                if !open {
+                    continue;
+                }
+
+                // Gas that has to leave the pipes for both to have the same pressure
+                let tile_gas = atmos.get(position) * TILE_VOLUME;
+                let balanced =
+                    (network.gas + tile_gas) / (network.volume() + TILE_VOLUME) * network.volume();
+                let to_tile = network.gas - balanced;
+                let amount = if device.kind == PipeDeviceKind::Vent {
+                    to_tile.clamp(0.0, limit)
+                } else {
+                    to_tile.clamp(-limit, 0.0)
+                };
+                network.gas -= amount;
+                atmos.add_gas(position, amount);
+            }
+            PipeDeviceKind::Pump => {
+                if consumer.is_some() && !unpowered.is_powered(map, translation) {
+                    continue;
+                }
+                let forward = transform.forward();
+                let (Some(input), Some(output)) = (
+                    world_to_tile(translation - forward).and_then(|p| networks.network_at(p)),
+                    world_to_tile(translation + forward).and_then(|p| networks.network_at(p)),
+                ) else {
+                    continue;
+                };
+                if input == output {
+                    continue;
+                }
+                let (Some(from), Some(to)) = (networks.get(input), networks.get(output)) else {
+                    continue;
+                };
+                let room = (device.max_pressure - to.pressure()).max(0.0) * to.volume();
+                let amount = limit.min(from.gas).min(room);
+                if amount <= 0.0 {
+                    continue;
+                }
+                networks.get_mut(input).unwrap().gas -= amount;
+                networks.get_mut(output).unwrap().gas += amount;
+            }
+        }
+    }
+}
+
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+#[component(storage = "SparseSet")]
+struct TogglePipeDeviceInteraction;
+
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+#[component(storage = "SparseSet")]
+struct ExaminePipeDeviceInteraction {
+    user: Entity,
+}
+
+// Dummy default for Reflect
+impl Default for ExaminePipeDeviceInteraction {
+    fn default() -> Self {
+        Self {
+            user: Entity::from_raw(0),
+        }
+    }
+}
+
+fn prepare_device_interactions(
+    list: Res<InteractionListEvents>,
+    devices: Query<(&PipeDevice, &PipeDeviceState)>,
+    reach: Reach,
+) {
+    for event in list.events.iter() {
+        let Ok((device, state)) = devices.get(event.target) else {
+            continue;
+        };
+
+        event.add_interaction(InteractionOption {
+            text: "Examine".into(),
+            interaction: Box::new(ExaminePipeDeviceInteraction { user: event.source }),
+            specificity: InteractionSpecificity::Common,
+        });
+        if !reach.can_reach(event.source, event.target) {
+            continue;
+        }
+        let action = if state.is_on() { "Turn off" } else { "Turn on" };
+        event.add_interaction(InteractionOption {
+            text: format!("{} {}", action, device.kind.name()),
+            interaction: Box::<TogglePipeDeviceInteraction>::default(),
+            specificity: InteractionSpecificity::Specific,
+        });
+    }
+}
+
+fn toggle_device_interaction(
+    mut query: Query<&mut ActiveInteraction, With<TogglePipeDeviceInteraction>>,
+    mut devices: Query<&mut PipeDeviceState>,
+) {
+    for mut active in query.iter_mut() {
+        let Ok(mut state) = devices.get_mut(active.target) else {
+            active.status = InteractionStatus::Canceled;
+            continue;
+        };
+        let on = !state.is_on();
+        state.set_on(on);
+        active.status = InteractionStatus::Completed;
+    }
+}
+
+/// Tells the player the pressure in the pipes and around the device.
+#[allow(clippy::too_many_arguments)]
+fn examine_device_interaction(
+    mut query: Query<(&ExaminePipeDeviceInteraction, &mut ActiveInteraction)>,
+    devices: Query<(&PipeDevice, &PipeDeviceState, &GlobalTransform)>,
+    networks: Res<PipeNetworks>,
+    atmos: Res<AtmosGrid>,
+    controls: Res<ClientControls>,
+    players: Res<Players>,
+    mut messages: EventWriter<SystemMessageEvent>,
+) {
+    for (interaction, mut active) in query.iter_mut() {
+        let Ok((device, state, transform)) = devices.get(active.target) else {
+            active.status = InteractionStatus::Canceled;
+            continue;
+        };
+        active.status = InteractionStatus::Completed;
+
+        let translation = transform.translation();
+        let pipe_pressure = |position: Option<UVec2>| match position
+            .and_then(|p| networks.network_at(p))
+            .and_then(|id| networks.get(id))
+        {
+            Some(network) => format!("{:.1} kPa", network.pressure()),
+            None => "not connected".to_owned(),
+        };
+        let mut text = format!(
+            "The {} is {}.",
+            device.kind.name(),
+            if state.is_on() { "on" } else { "off" }
+        );
+        if device.kind == PipeDeviceKind::Pump {
+            let forward = transform.forward();
+            text.push_str(&format!(
+                " Input: {}. Output: {}.",
+                pipe_pressure(world_to_tile(translation - forward)),
+                pipe_pressure(world_to_tile(translation + forward))
+            ));
+        } else {
+            let position = world_to_tile(translation);
+            text.push_str(&format!(
+                " Pipes: {}. Air: {:.1} kPa.",
+                pipe_pressure(position),
+                position.map_or(0.0, |p| atmos.get(p))
+            ));
+        }
+
+        let Some(connection) = controls
+            .controlling_player(interaction.user)
+            .and_then(|p| players.get_connection(&p))
+        else {
+            continue;
+        };
+        messages.send(SystemMessageEvent {
+            receiver: connection,
+            text,
+        });
+    }
+}
+
+fn show_device_state(
+    devices: Query<(Entity, &PipeDeviceStateClient), Changed<PipeDeviceStateClient>>,
+    children: Query<&Children>,
+    mut indicators: Query<&mut Visibility, With<DeviceIndicator>>,
+) {
+    for (entity, state) in devices.iter() {
+        let visibility = if *state.on {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+        for child in children.iter_descendants(entity) {
+            if let Ok(mut indicator) = indicators.get_mut(child) {
+                *indicator = visibility;
+            }
+        }
+    }
+}
+
+/// Admins that see the pipe networks around them.
+#[derive(Resource, Default)]
+struct PipeDebugViewers {
+    viewers: HashSet<ConnectionId>,
+    /// Admins that turned the overlay off and still need to have it cleared
+    hidden: Vec<ConnectionId>,
+}
+
+/// The pipes in the chunk around the player and their network ids, sent to admins debugging them.
+#[derive(Serialize, Deserialize)]
+struct PipeNetDebugMessage {
+    /// Empty when the overlay is turned off
+    pipes: Vec<(UVec2, u32)>,
+}
+
+fn pipenet_command(world: &mut World, context: &CommandContext) -> CommandResult {
+    let CommandSource::Player(connection) = context.source else {
+        return Err("The overlay can only be shown to players".into());
+    };
+
+    let mut viewers = world.resource_mut::<PipeDebugViewers>();
+    if viewers.viewers.remove(&connection) {
+        viewers.hidden.push(connection);
+        Ok("Pipe network overlay hidden".into())
+    } else {
+        viewers.viewers.insert(connection);
+        Ok("Pipe network overlay shown".into())
+    }
+}
+
+fn send_pipe_debug(
+    mut viewers: ResMut<PipeDebugViewers>,
+    networks: Res<PipeNetworks>,
+    players: Res<Players>,
+    controls: Res<ClientControls>,
+    transforms: Query<&GlobalTransform>,
+    mut sender: MessageSender,
+) {
+    for connection in viewers.hidden.drain(..) {
+        sender.send(
+            &PipeNetDebugMessage { pipes: Vec::new() },
+            MessageReceivers::Single(connection),
+        );
+    }
+
+    // Viewers that left are forgotten
+    viewers
+        .viewers
+        .retain(|connection| players.get(*connection).is_some());
+
+    for &connection in viewers.viewers.iter() {
+        let Some(tile) = players
+            .get(connection)
+            .and_then(|player| controls.controlled_entity(player.id))
+            .and_then(|entity| transforms.get(entity).ok())
+            .and_then(|transform| world_to_tile(transform.translation()))
+        else {
+            continue;
+        };
+
+        let origin = tile / CHUNK_SIZE * CHUNK_SIZE;
+        let pipes = (0..CHUNK_SIZE)
+            .flat_map(|y| (0..CHUNK_SIZE).map(move |x| origin + UVec2::new(x, y)))
+            .filter_map(|position| Some((position, networks.network_at(position)?)))
+            .collect();
+        sender.send(
+            &PipeNetDebugMessage { pipes },
+            MessageReceivers::Single(connection),
+        );
+    }
+}
+
+/// Pipes received from the server for the pipe network overlay.
+#[derive(Resource, Default)]
+struct ClientPipeNets(HashMap<UVec2, u32>);
+
+fn receive_pipe_debug(
+    mut messages: EventReader<MessageEvent<PipeNetDebugMessage>>,
+    mut pipes: ResMut<ClientPipeNets>,
+) {
+    let Some(event) = messages.iter().last() else {
+        return;
+    };
+    pipes.0 = event.message.pipes.iter().copied().collect();
+}
+
+/// Height above the floor pipes are drawn at
+const OVERLAY_HEIGHT: f32 = 0.1;
+
+fn draw_pipe_nets(
+    pipes: Res<ClientPipeNets>,
+    tilemaps: Query<&GlobalTransform, With<TileMapClient>>,
+    mut gizmos: Gizmos,
+) {
+    let Ok(map_transform) = tilemaps.get_single() else {
+        return;
+    };
+    let point = |tile| tile_to_world(map_transform, tile) + Vec3::Y * OVERLAY_HEIGHT;
+    for (&position, &id) in pipes.0.iter() {
+        // Spread out hues so neighbouring network ids are easy to tell apart
+        let color = Color::hsl((id as f32 * 137.5) % 360.0, 0.8, 0.5);
+        gizmos.circle(point(position), Vec3::Y, 0.15, color);
+        for (_, neighbour) in tile_neighbours(position) {
+            if pipes.0.get(&neighbour) == Some(&id) {
+                gizmos.line(point(position), point(neighbour), color);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::time::TimeUpdateStrategy;
+    use maps::TileReference;
+
+    use super::*;
+    use crate::{
+        atmos::{Air, ONE_ATMOSPHERE},
+        diffusion::resize_grid,
+    };
+
+    const SCRUBBER: UVec2 = UVec2::new(2, 2);
+
+    /// A map with floor on every tile, and a pipe network of two pipes under the scrubber.
+    fn app(on: bool) -> App {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f32(
+                SIMULATION_INTERVAL,
+            )))
+            .init_resource::<AtmosGrid>()
+            .init_resource::<PipeNetworks>()
+            .init_resource::<UnpoweredAreas>()
+            .add_systems(
+                Update,
+                (resize_grid::<Air>, setup_devices, exchange_gas).chain(),
+            );
+
+        let floor = app.world.spawn_empty().id();
+        let mut map = TileMap::new(UVec2::ONE);
+        for position in (0..CHUNK_SIZE).flat_map(|x| (0..CHUNK_SIZE).map(move |y| UVec2::new(x, y)))
+        {
+            let tile = TileReference {
+                turf: Some(floor),
+                ..Default::default()
+            };
+            map.set_tile(position, tile).unwrap();
+        }
+        app.world.spawn(map);
+
+        let mut networks = app.world.resource_mut::<PipeNetworks>();
+        networks.add_pipe(SCRUBBER);
+        networks.add_pipe(SCRUBBER + UVec2::X);
+        app.world.spawn((
+            PipeDevice {
+                kind: PipeDeviceKind::Scrubber,
+                on,
+                ..Default::default()
+            },
+            GlobalTransform::from_translation(Vec3::new(SCRUBBER.x as f32, 0.0, SCRUBBER.y as f32)),
+        ));
+        app
+    }
+
+    fn pipe_gas(app: &App) -> f32 {
+        let networks = app.world.resource::<PipeNetworks>();
+        let id = networks.network_at(SCRUBBER).unwrap();
+        networks.get(id).unwrap().gas
+    }
+
+    #[test]
+    fn scrubber_moves_air_into_pipes() {
+        let mut app = app(true);
+        for _ in 0..10 {
+            app.update();
+        }
+
+        let pressure = app.world.resource::<AtmosGrid>().get(SCRUBBER);
+        assert!(pressure < ONE_ATMOSPHERE, "pressure {}", pressure);
+        // Gas is moved, not created or lost
+        let removed = (ONE_ATMOSPHERE - pressure) * TILE_VOLUME;
+        let gas = pipe_gas(&app);
+        assert!(gas > 0.0);
+        assert!(
+            (removed - gas).abs() < 1.0,
+            "removed {} stored {}",
+            removed,
+            gas
+        );
+        // Other tiles are left alone without diffusion
+        let grid = app.world.resource::<AtmosGrid>();
+        assert_eq!(grid.get(SCRUBBER + UVec2::Y), ONE_ATMOSPHERE);
+    }
+
+    #[test]
+    fn scrubber_that_is_off_does_nothing() {
+        let mut app = app(false);
+        for _ in 0..10 {
+            app.update();
+        }
+
+        let grid = app.world.resource::<AtmosGrid>();
+        assert_eq!(grid.get(SCRUBBER), ONE_ATMOSPHERE);
+        assert_eq!(pipe_gas(&app), 0.0);
+    }
+}
@@ -0,0 +1,550 @@
+use std::{
+    any::TypeId,
+    fs::{create_dir_all, read_to_string, write},
+    path::{Path, PathBuf},
+};
+
+use bevy::{
+    ecs::reflect::AppTypeRegistry,
+    prelude::*,
+    reflect::serde::{ReflectSerializer, UntypedReflectDeserializer},
+    tasks::{IoTaskPool, Task},
+    utils::HashMap,
+};
+use futures_lite::future;
+use maps::{TileEntity, TileLayer, TileMap};
+use networking::{
+    is_server,
+    scene::{NetworkScene, NetworkSceneBundle, NetworkSceneEvent},
+};
+use serde::{de::DeserializeSeed, Deserialize, Serialize};
+
+use crate::{
+    config::ServerConfig,
+    device_link::{DeviceLink, PendingTileLinks},
+    items::Item,
+    map_objects::SkipMapObjects,
+    round::RoundState,
+    Map,
+};
+
+/// Periodically saves the world to disk, so a crashed server can be recovered with `--recover`.
+pub struct AutosavePlugin;
+
+impl Plugin for AutosavePlugin {
+    fn build(&self, app: &mut App) {
+        if !is_server(app) {
+            return;
+        }
+
+        app.init_resource::<AutosaveState>().add_systems(
+            Update,
+            (
+                track_current_map,
+                autosave.run_if(in_state(RoundState::Running)),
+                finish_autosave,
+            ),
+        );
+
+        if app.world.contains_resource::<RecoveredWorld>() {
+            // The saved entities already contain the objects placed by the map
+            app.insert_resource(SkipMapObjects).add_systems(
+                Update,
+                (
+                    restore_entities.run_if(in_state(RoundState::Ready)),
+                    restore_components,
+                ),
+            );
+        }
+    }
+}
+
+/// Components saved on top of the prefab and transform.
+/// Only add components here that don't reference other entities.
+fn saved_components() -> [TypeId; 2] {
+    [TypeId::of::<Item>(), TypeId::of::<DeviceLink>()]
+}
+
+/// The default map, if no map has been loaded yet
+const DEFAULT_MAP: &str = "maps/BoxStation.dmm";
+
+#[derive(Deserialize)]
+pub struct AutosaveConfig {
+    /// Minutes between autosaves
+    #[serde(default = "AutosaveConfig::default_interval")]
+    pub interval_minutes: f32,
+    /// How many autosaves are kept before the oldest is overwritten
+    #[serde(default = "AutosaveConfig::default_keep")]
+    pub keep: usize,
+    #[serde(default = "AutosaveConfig::default_directory")]
+    pub directory: PathBuf,
+}
+
+impl AutosaveConfig {
+    fn default_interval() -> f32 {
+        10.0
+    }
+
+    fn default_keep() -> usize {
+        3
+    }
+
+    fn default_directory() -> PathBuf {
+        "autosaves".into()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct WorldSnapshot {
+    /// Asset path of the map source. Tile changes are not saved yet.
+    map: String,
+    round_state: RoundState,
+    entities: Vec<EntitySnapshot>,
+    /// Channels of linked tile objects, which are spawned by the map instead of being saved
+    #[serde(default)]
+    tile_links: Vec<TileLinkSnapshot>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct TileLinkSnapshot {
+    position: UVec2,
+    layer: TileLayer,
+    channel: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct EntitySnapshot {
+    /// Asset path of the scene the entity was spawned from
+    prefab: String,
+    translation: Vec3,
+    rotation: Quat,
+    /// Reflect-serialized components from [`saved_components`]
+    components: Vec<String>,
+}
+
+/// The snapshot the server was started from.
+#[derive(Resource)]
+pub struct RecoveredWorld(pub WorldSnapshot);
+
+impl RecoveredWorld {
+    pub fn map(&self) -> &str {
+        &self.0.map
+    }
+}
+
+pub fn load_snapshot(path: &Path) -> Result<WorldSnapshot, String> {
+    let text = read_to_string(path).map_err(|e| e.to_string())?;
+    ron::from_str(&text).map_err(|e| e.to_string())
+}
+
+#[derive(Resource)]
+struct AutosaveState {
+    current_map: String,
+    last_save: f32,
+    next_slot: usize,
+    task: Option<Task<Result<PathBuf, String>>>,
+    /// Save on the next frame, regardless of the interval
+    requested: bool,
+}
+
+impl Default for AutosaveState {
+    fn default() -> Self {
+        Self {
+            current_map: DEFAULT_MAP.into(),
+            last_save: 0.0,
+            next_slot: 0,
+            task: None,
+            requested: false,
+        }
+    }
+}
+
+fn track_current_map(
+    map: Option<Res<Map>>,
+    asset_server: Res<AssetServer>,
+    mut state: ResMut<AutosaveState>,
+) {
+    let Some(map) = map.filter(|m| m.is_added()) else {
+        return;
+    };
+    if let Some(path) = asset_server.get_handle_path(&map.handle) {
+        state.current_map = path.path().to_string_lossy().into_owned();
+    }
+}
+
+struct ExtractedEntity {
+    prefab: String,
+    translation: Vec3,
+    rotation: Quat,
+    /// Cloned out of the world, serialized later on the background task
+    components: Vec<Box<dyn Reflect>>,
+}
+
+/// Extracts the world on the main thread and writes it to disk on a background task.
+fn autosave(world: &mut World) {
+    let Some(config) = world.resource::<ServerConfig>().autosave.as_ref() else {
+        return;
+    };
+    let interval = config.interval_minutes * 60.0;
+    let keep = config.keep.max(1);
+    let directory = config.directory.clone();
+
+    let now = world.resource::<Time>().elapsed_seconds();
+    let state = world.resource::<AutosaveState>();
+    // Don't start a new save while the last one is still being written
+    if (now - state.last_save < interval && !state.requested) || state.task.is_some() {
+        return;
+    }
+    let map = state.current_map.clone();
+    let slot = state.next_slot;
+
+    let registry = world.resource::<AppTypeRegistry>().clone();
+    let asset_server = world.resource::<AssetServer>().clone();
+    let round_state = *world.resource::<State<RoundState>>().get();
+
+    let mut query = world.query_filtered::<(Entity, &NetworkScene, &Transform), (
+        Without<Parent>,
+        Without<TileEntity>,
+        Without<TileMap>,
+    )>();
+    let read_registry = registry.read();
+    let reflect_components: Vec<_> = saved_components()
+        .iter()
+        .filter_map(|id| read_registry.get(*id)?.data::<ReflectComponent>())
+        .collect();
+
+    // TODO: Save items inside containers and hands
+    let extracted: Vec<_> = query
+        .iter(world)
+        .filter_map(|(entity, scene, transform)| {
+            let prefab = asset_server.get_handle_path(scene.handle())?;
+            let entity_ref = world.entity(entity);
+            Some(ExtractedEntity {
+                prefab: prefab.path().to_string_lossy().into_owned(),
+                translation: transform.translation,
+                rotation: transform.rotation,
+                components: reflect_components
+                    .iter()
+                    .filter_map(|c| c.reflect(entity_ref))
+                    .map(|c| c.clone_value())
+                    .collect(),
+            })
+        })
+        .collect();
+    drop(read_registry);
+
+    let tile_links: Vec<_> = world
+        .query::<(&TileEntity, &DeviceLink)>()
+        .iter(world)
+        .map(|(tile, link)| TileLinkSnapshot {
+            position: tile.position(),
+            layer: tile.layer(),
+            channel: link.channel.clone(),
+        })
+        .collect();
+
+    let path = directory.join(format!("autosave-{}.ron", slot));
+    let task_registry = registry.clone();
+    let task = IoTaskPool::get().spawn(async move {
+        let registry = task_registry.read();
+        let entities = extracted
+            .into_iter()
+            .map(|e| EntitySnapshot {
+                prefab: e.prefab,
+                translation: e.translation,
+                rotation: e.rotation,
+                components: e
+                    .components
+                    .iter()
+                    .filter_map(|c| {
+                        ron::to_string(&ReflectSerializer::new(c.as_ref(), &registry)).ok()
+                    })
+                    .collect(),
+            })
+            .collect();
+        let snapshot = WorldSnapshot {
+            map,
+            round_state,
+            entities,
+            tile_links,
+        };
+        let text =
+            ron::ser::to_string_pretty(&snapshot, Default::default()).map_err(|e| e.to_string())?;
+        create_dir_all(&directory).map_err(|e| e.to_string())?;
+        write(&path, text).map_err(|e| e.to_string())?;
+        Ok(path)
+    });
+
+    let mut state = world.resource_mut::<AutosaveState>();
+    state.last_save = now;
+    state.requested = false;
+    state.next_slot = (slot + 1) % keep;
+    state.task = Some(task);
+}
+
+/// Writes an autosave as soon as possible.
+pub(crate) fn request_autosave(world: &mut World) -> Result<(), String> {
+    if world.resource::<ServerConfig>().autosave.is_none() {
+        return Err("autosaves are not enabled on this server".into());
+    }
+    if *world.resource::<State<RoundState>>().get() != RoundState::Running {
+        return Err("there is no running round to save".into());
+    }
+    world.resource_mut::<AutosaveState>().requested = true;
+    Ok(())
+}
+
+fn finish_autosave(mut state: ResMut<AutosaveState>) {
+    let Some(task) = state.task.as_mut() else {
+        return;
+    };
+    let Some(result) = future::block_on(future::poll_once(task)) else {
+        return;
+    };
+    state.task = None;
+    match result {
+        Ok(path) => info!(path = %path.display(), "Autosave written"),
+        Err(err) => error!(error = err.as_str(), "Autosave failed"),
+    }
+}
+
+/// Components to apply once the restored entity's scene has spawned
+#[derive(Resource, Default)]
+struct PendingComponents(HashMap<Entity, Vec<String>>);
+
+fn restore_entities(
+    recovered: Res<RecoveredWorld>,
+    asset_server: Res<AssetServer>,
+    mut tile_links: ResMut<PendingTileLinks>,
+    mut next_state: ResMut<NextState<RoundState>>,
+    mut commands: Commands,
+) {
+    let mut pending = PendingComponents::default();
+    for saved in recovered.0.entities.iter() {
+        let entity = commands
+            .spawn(NetworkSceneBundle {
+                scene: asset_server.load(saved.prefab.as_str()).into(),
+                transform: Transform {
+                    translation: saved.translation,
+                    rotation: saved.rotation,
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .id();
+        pending.0.insert(entity, saved.components.clone());
+    }
+
+    for link in recovered.0.tile_links.iter() {
+        tile_links
+            .0
+            .insert((link.position, link.layer), link.channel.clone());
+    }
+
+    info!(
+        entities = recovered.0.entities.len(),
+        tile_links = recovered.0.tile_links.len(),
+        "Restored entities from autosave"
+    );
+    // Players rejoin and get new bodies, so the round can continue right away
+    if recovered.0.round_state == RoundState::Running {
+        next_state.set(RoundState::Running);
+    }
+    commands.insert_resource(pending);
+    commands.remove_resource::<RecoveredWorld>();
+}
+
+/// Applies saved components after the scene has overwritten the defaults.
+fn restore_components(
+    mut events: EventReader<NetworkSceneEvent>,
+    pending: Option<ResMut<PendingComponents>>,
+    registry: Res<AppTypeRegistry>,
+    mut commands: Commands,
+) {
+    let Some(mut pending) = pending else {
+        return;
+    };
+
+    for event in events.iter() {
+        let NetworkSceneEvent::Created(entity) = *event;
+        let Some(components) = pending.0.remove(&entity) else {
+            continue;
+        };
+
+        let registry = registry.read();
+        let reflected: Vec<_> = components
+            .iter()
+            .filter_map(|text| {
+                let mut deserializer = ron::Deserializer::from_str(text).ok()?;
+                match UntypedReflectDeserializer::new(&registry).deserialize(&mut deserializer) {
+                    Ok(c) => Some(c),
+                    Err(err) => {
+                        warn!(error = %err, "Skipping invalid saved component");
+                        None
+                    }
+                }
+            })
+            .collect();
+        drop(registry);
+
+        commands.add(move |world: &mut World| {
+            let registry = world.resource::<AppTypeRegistry>().clone();
+            let registry = registry.read();
+            for component in reflected.iter() {
+                let Some(reflect_component) = registry
+                    .get_with_name(component.type_name())
+                    .and_then(|r| r.data::<ReflectComponent>())
+                else {
+                    continue;
+                };
+                reflect_component.insert(&mut world.entity_mut(entity), component.as_ref());
+            }
+        });
+    }
+
+    if pending.0.is_empty() {
+        commands.remove_resource::<PendingComponents>();
+    }
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use std::{fs::remove_dir_all, time::Duration};
+
+    use super::*;
+    use crate::testing::{server_app, server_app_with};
+
+    /// Prefabs that no map places, so only the objects spawned by the test are compared
+    const SAVED_PREFABS: [&str; 3] = [
+        "items/carp_fang.scn.ron",
+        "items/steak.scn.ron",
+        "items/raw_meat.scn.ron",
+    ];
+    const CHANNEL: &str = "test channel";
+    const MAX_FRAMES: u32 = 2000;
+    const FRAME_TIME: Duration = Duration::from_millis(5);
+
+    fn config(directory: &Path) -> ServerConfig {
+        ServerConfig {
+            autosave: Some(AutosaveConfig {
+                interval_minutes: 10.0,
+                keep: 1,
+                directory: directory.into(),
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn update_until(app: &mut App, what: &str, mut done: impl FnMut(&mut App) -> bool) {
+        for _ in 0..MAX_FRAMES {
+            app.update();
+            if done(app) {
+                return;
+            }
+            std::thread::sleep(FRAME_TIME);
+        }
+        panic!("Waited too long for {}", what);
+    }
+
+    /// Replaces the default map with an empty one, which makes the round ready.
+    fn load_empty_map(app: &mut App) {
+        app.update();
+        app.world.remove_resource::<Map>();
+        app.world
+            .spawn((TileMap::new(UVec2::ONE), SpatialBundle::default()));
+        update_until(app, "the round to be ready", |app| {
+            *app.world.resource::<State<RoundState>>().get() != RoundState::Loading
+        });
+    }
+
+    fn spawned_prefabs(app: &mut App) -> usize {
+        app.world
+            .query_filtered::<(), (With<NetworkScene>, With<Item>)>()
+            .iter(&app.world)
+            .count()
+    }
+
+    fn save(app: &mut App, directory: &Path) -> WorldSnapshot {
+        request_autosave(&mut app.world).unwrap();
+        update_until(app, "the autosave", |app| {
+            let state = app.world.resource::<AutosaveState>();
+            !state.requested && state.task.is_none()
+        });
+        load_snapshot(&directory.join("autosave-0.ron")).unwrap()
+    }
+
+    /// The saved objects of the test, in a comparable form.
+    /// Items fall while the test waits, so only the horizontal position is compared.
+    fn saved_objects(snapshot: &WorldSnapshot) -> Vec<String> {
+        let mut objects: Vec<_> = snapshot
+            .entities
+            .iter()
+            .filter(|e| SAVED_PREFABS.contains(&e.prefab.as_str()))
+            .map(|e| {
+                format!(
+                    "{} ({:.2}, {:.2}) {:?}",
+                    e.prefab, e.translation.x, e.translation.z, e.components
+                )
+            })
+            .collect();
+        objects.sort();
+        objects
+    }
+
+    #[test]
+    fn recovered_world_saves_the_same() {
+        let directory = std::env::temp_dir().join(format!("ssnt-autosave-{}", std::process::id()));
+        let _ = remove_dir_all(&directory);
+        let first_directory = directory.join("first");
+        let second_directory = directory.join("second");
+
+        let mut app = server_app(config(&first_directory));
+        load_empty_map(&mut app);
+        app.world
+            .resource_mut::<NextState<RoundState>>()
+            .set(RoundState::Running);
+        let asset_server = app.world.resource::<AssetServer>().clone();
+        for (i, prefab) in SAVED_PREFABS.into_iter().enumerate() {
+            let mut object = app.world.spawn(NetworkSceneBundle {
+                scene: asset_server.load(prefab).into(),
+                transform: Transform::from_xyz(i as f32 * 2.0, 0.5, 3.0),
+                ..Default::default()
+            });
+            if i == 0 {
+                object.insert(DeviceLink {
+                    channel: CHANNEL.into(),
+                });
+            }
+        }
+        update_until(&mut app, "the prefabs to spawn", |app| {
+            spawned_prefabs(app) == SAVED_PREFABS.len()
+        });
+        let saved = save(&mut app, &first_directory);
+        assert_eq!(saved.round_state, RoundState::Running);
+        assert_eq!(saved_objects(&saved).len(), SAVED_PREFABS.len());
+        drop(app);
+
+        // Reboot from the save
+        let expected = saved_objects(&saved);
+        let mut app = server_app_with(config(&second_directory), |app| {
+            app.insert_resource(RecoveredWorld(saved));
+        });
+        load_empty_map(&mut app);
+        update_until(&mut app, "the saved prefabs to spawn", |app| {
+            spawned_prefabs(app) == SAVED_PREFABS.len()
+                && app
+                    .world
+                    .query::<&DeviceLink>()
+                    .iter(&app.world)
+                    .any(|link| link.channel == CHANNEL)
+        });
+        assert_eq!(
+            *app.world.resource::<State<RoundState>>().get(),
+            RoundState::Running
+        );
+
+        let resaved = save(&mut app, &second_directory);
+        assert_eq!(saved_objects(&resaved), expected);
+        let _ = remove_dir_all(&directory);
+    }
+}
@@ -10,14 +10,13 @@ use bevy::{
     reflect::TypeUuid,
     utils::HashSet,
 };
-use bevy_egui::{egui, EguiContexts};
 use networking::{
     component::AppExt as ComponentAppExt,
     identity::{NetworkIdentities, NetworkIdentity},
     is_server,
-    messaging::{AppExt, MessageEvent, MessageSender},
+    messaging::{AppExt, MessageEvent},
     scene::NetworkSceneBundle,
-    spawning::{ClientControlled, ClientControls},
+    spawning::ClientControls,
     variable::{NetworkVar, ServerVar},
     Networked, Players,
 };
@@ -26,19 +25,41 @@ use serde::{Deserialize, Serialize};
 use utils::task::*;
 
 use crate::{
+    actions::{ActorAction, ActorActionEvent},
+    feedback::{Feedback, FeedbackKind},
     interaction::{
-        ActiveInteraction, GenerateInteractionList, InteractionListEvents, InteractionListRequest,
-        InteractionOption, InteractionSpecificity, InteractionStatus,
+        ActiveInteraction, GenerateInteractionList, InteractionAppExt, InteractionListEvents,
+        InteractionOption, InteractionSpecificity, InteractionStatus, Reach,
     },
     items::{
         containers::{Container, MoveItem},
-        Item, StoredItem, StoredItemClient,
+        Item, StoredItem,
     },
-    ui::has_window,
+};
+
+use self::variant::{BodyTemplate, BodyVariant, LimbTemplate};
+
+#[cfg(feature = "client")]
+use {
+    crate::{
+        accessibility::{AccessibilitySettings, UiColor},
+        interaction::InteractionListRequest,
+        items::{
+            containers::MoveItemMessage,
+            drag::{DraggedItem, DropTargets},
+            durability::ItemConditionClient,
+            labels::{display_name, ItemLabelClient},
+            StoredItemClient,
+        },
+        ui::has_window,
+    },
+    bevy_egui::{egui, EguiContexts},
+    networking::{messaging::MessageSender, spawning::ClientControlled},
 };
 
 mod ghost;
 pub mod health;
+pub mod variant;
 
 pub struct BodyPlugin;
 
@@ -49,11 +70,11 @@ impl Plugin for BodyPlugin {
             .register_type::<Limb>()
             .register_type::<Hand>()
             .register_type::<Cutting>()
-            .add_network_message::<ChangeHandRequest>()
+            .add_network_message::<ChangeHandRequest>("ChangeHandRequest")
             .add_networked_component::<Hands, HandsClient>();
 
         if is_server(app) {
-            app.register_type::<PickupInteraction>()
+            app.register_queueable_interaction::<PickupInteraction>()
                 .register_type::<DropInteraction>()
                 .register_type::<CutInteraction>()
                 .add_event::<LimbEvent>()
@@ -77,16 +98,25 @@ impl Plugin for BodyPlugin {
                     ),
                 );
         } else {
+            #[cfg(feature = "client")]
             app.add_systems(
                 Update,
                 (
-                    (client_update_limbs, hand_ui.run_if(has_window)).chain(),
+                    (
+                        client_update_limbs,
+                        hand_ui.in_set(DropTargets).run_if(has_window),
+                    )
+                        .chain(),
                     client_hands_keybind,
                 ),
             );
         }
 
-        app.add_plugins((health::HealthPlugin, ghost::GhostPlugin));
+        app.add_plugins((
+            health::HealthPlugin,
+            ghost::GhostPlugin,
+            variant::BodyVariantPlugin,
+        ));
 
         app.insert_resource(BodyAssets {
             scenes: app
@@ -106,6 +136,33 @@ pub struct Body {
     limbs_to_remove: Vec<Entity>,
 }
 
+impl Body {
+    /// Limbs currently attached to the body.
+    pub fn limbs(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.limbs.iter().copied()
+    }
+
+    #[cfg(test)]
+    pub(crate) fn with_limbs(limbs: impl IntoIterator<Item = Entity>) -> Self {
+        Self {
+            limbs: limbs.into_iter().collect(),
+            ..Default::default()
+        }
+    }
+}
+
+/// The entity itself or its closest ancestor that `matches`.
+/// Attacks and ray casts hit colliders, which are children of the limb, creature or object they belong to.
+pub fn self_or_ancestor(
+    parents: &Query<&Parent>,
+    entity: Entity,
+    matches: impl Fn(Entity) -> bool,
+) -> Option<Entity> {
+    std::iter::once(entity)
+        .chain(parents.iter_ancestors(entity))
+        .find(|&e| matches(e))
+}
+
 impl MapEntities for Body {
     fn map_entities(&mut self, entity_mapper: &mut EntityMapper) {
         self.limbs = self
@@ -199,7 +256,7 @@ fn process_limb_removal(
             commands
                 .entity(limb_entity)
                 .remove_parent()
-                .unfreeze(Some(ColliderGroup::Default));
+                .unfreeze(Some(ColliderGroup::Item));
             writer.send(LimbEvent {
                 limb_entity,
                 kind: LimbEventKind::Removed,
@@ -208,6 +265,7 @@ fn process_limb_removal(
     }
 }
 
+#[cfg(feature = "client")]
 fn client_update_limbs(
     mut added_limbs: Query<(Entity, &Parent), (Or<(Added<Limb>, Changed<Parent>)>,)>,
     parents: Query<&Parent>,
@@ -283,6 +341,7 @@ impl HandsClient {
 }
 
 /// Get the item currently held by the player with their active hand
+#[cfg(feature = "client")]
 #[derive(SystemParam)]
 pub struct ClientHeldItem<'w, 's> {
     client_body: Query<'w, 's, &'static HandsClient, With<ClientControlled>>,
@@ -291,6 +350,7 @@ pub struct ClientHeldItem<'w, 's> {
     identities: Res<'w, NetworkIdentities>,
 }
 
+#[cfg(feature = "client")]
 impl<'w, 's> ClientHeldItem<'w, 's> {
     pub fn get(&self) -> Option<Entity> {
         let hands = self.client_body.get_single().ok()?;
@@ -300,6 +360,32 @@ impl<'w, 's> ClientHeldItem<'w, 's> {
     }
 }
 
+/// Get the item a creature is holding with its active hand
+#[derive(SystemParam)]
+pub struct HeldItem<'w, 's> {
+    hands: Query<'w, 's, &'static Hands>,
+    containers: Query<'w, 's, &'static Container, With<Hand>>,
+    bodies: Query<'w, 's, &'static Body>,
+}
+
+impl<'w, 's> HeldItem<'w, 's> {
+    pub fn get(&self, creature: Entity) -> Option<Entity> {
+        let hands = self.hands.get(creature).ok()?;
+        let container = self.containers.get(hands.active_hand()).ok()?;
+        container.iter().next().map(|(_, item)| *item)
+    }
+
+    /// Items held in any hand of a creature.
+    pub fn all(&self, creature: Entity) -> impl Iterator<Item = Entity> + '_ {
+        self.bodies
+            .get(creature)
+            .into_iter()
+            .flat_map(|body| body.limbs.iter())
+            .filter_map(|limb| self.containers.get(*limb).ok())
+            .flat_map(|container| container.iter().map(|(_, item)| *item))
+    }
+}
+
 /// Updates the selected hand when limbs of a body get changed
 fn handle_hand_modification(
     mut bodies: Query<(Entity, &Body, Option<&mut Hands>), Changed<Body>>,
@@ -366,12 +452,22 @@ struct ChangeHandRequest {
     identity: NetworkIdentity,
 }
 
+#[cfg(feature = "client")]
+#[allow(clippy::too_many_arguments)]
 fn hand_ui(
     mut contexts: EguiContexts,
     mut bodies: Query<(&Body, &mut HandsClient), With<ClientControlled>>,
     hands: Query<(Entity, &NetworkIdentity, &Hand, Option<&Children>)>,
-    items: Query<(&Item, &NetworkIdentity)>,
+    items: Query<(
+        Entity,
+        &Item,
+        Option<&ItemLabelClient>,
+        Option<&ItemConditionClient>,
+        &NetworkIdentity,
+    )>,
     mut ordered_hands: Local<Vec<(Entity, u32)>>,
+    mut dragged: ResMut<DraggedItem>,
+    accessibility: Res<AccessibilitySettings>,
     mut sender: MessageSender,
 ) {
     let Ok((body, hand_data)) = bodies.get_single_mut() else {
@@ -398,22 +494,71 @@ fn hand_ui(
                 {
                     let mut held_item_name = None;
                     let mut held_item_id = None;
+                    let mut held_item = None;
                     if let Some(children) = children {
-                        if let Some((item, identity)) = items.iter_many(children).next() {
-                            held_item_name = Some(item.name.as_str());
+                        if let Some((entity, item, label, condition, identity)) =
+                            items.iter_many(children).next()
+                        {
+                            held_item_name = Some(display_name(item, label, condition));
                             held_item_id = Some(*identity);
+                            held_item = Some((entity, item.size));
                         }
                     }
-                    let label = ui.selectable_label(
-                        identity == *hand_data.active_hand,
-                        format!("{}: {}", hand.side, held_item_name.unwrap_or("empty")),
-                    );
+                    let label = ui
+                        .selectable_label(
+                            identity == *hand_data.active_hand,
+                            format!(
+                                "{}: {}",
+                                hand.side,
+                                held_item_name.as_deref().unwrap_or("empty")
+                            ),
+                        )
+                        .interact(egui::Sense::click_and_drag());
+
+                    if let (true, Some((entity, size)), Some(item_id), Some(name)) = (
+                        label.drag_started(),
+                        held_item,
+                        held_item_id,
+                        held_item_name.as_ref(),
+                    ) {
+                        dragged.start(entity, item_id, name.clone(), size);
+                    }
+
+                    // Hands take dragged items if they are empty
+                    if let Some(info) = dragged.get() {
+                        if ui.rect_contains_pointer(label.rect)
+                            && held_item.map(|(e, _)| e) != Some(info.entity)
+                        {
+                            let item = info.identity;
+                            let color = accessibility.color(if held_item.is_none() {
+                                UiColor::Safe
+                            } else {
+                                UiColor::Danger
+                            });
+                            ui.painter()
+                                .rect_filled(label.rect, 2., color.gamma_multiply(0.25));
+                            dragged.hover_target();
+
+                            if held_item.is_none() && ui.input(|i| i.pointer.any_released()) {
+                                sender.send_to_server(&MoveItemMessage {
+                                    item,
+                                    to_container: Some(identity),
+                                    to_slot: UVec2::ZERO,
+                                });
+                                dragged.dropped();
+                            }
+                        }
+                    }
+
                     if label.clicked() {
                         sender.send_to_server(&ChangeHandRequest { identity });
                     } else if label.clicked_by(egui::PointerButton::Secondary) {
                         // Request interaction list on right-click
                         if let Some(target) = held_item_id {
-                            sender.send_to_server(&InteractionListRequest { target });
+                            sender.send_to_server(&InteractionListRequest {
+                                target,
+                                point: None,
+                            });
                         }
                     }
                 }
@@ -421,6 +566,7 @@ fn hand_ui(
         });
 }
 
+#[cfg(feature = "client")]
 fn client_hands_keybind(
     keyboard_input: Res<Input<KeyCode>>,
     mut bodies: Query<(&Body, &mut HandsClient), With<ClientControlled>>,
@@ -488,8 +634,9 @@ struct BodyAssets {
     scenes: Vec<HandleUntyped>,
 }
 
-/// Task to create the body of a given creature archetype
+/// Task to create the body of a creature from a body template
 pub struct SpawnCreature {
+    /// Id of the [`BodyTemplate`]
     pub archetype: String,
 }
 
@@ -512,92 +659,65 @@ fn spawn_limb<'w, 's, 'a: 'b, 'b: 'c, 'c>(
     })
 }
 
+/// Spawns a limb of a template with everything attached to it.
+fn spawn_limb_tree(
+    builder: &mut ChildBuilder,
+    server: &AssetServer,
+    limb: &LimbTemplate,
+    limbs: &mut HashSet<Entity>,
+) {
+    let entity = spawn_limb(builder, server, &limb.scene)
+        .with_children(|builder| {
+            for child in limb.children.iter() {
+                spawn_limb_tree(builder, server, child, limbs);
+            }
+        })
+        .id();
+    limbs.insert(entity);
+}
+
 fn create_creature(
     mut tasks: ResMut<Tasks<SpawnCreature>>,
     server: Res<AssetServer>,
+    templates: Res<Assets<BodyTemplate>>,
     mut commands: Commands,
 ) {
     tasks.process(|data| {
+        let Some(template) = BodyTemplate::find(&templates, &data.archetype) else {
+            error!(
+                archetype = data.archetype.as_str(),
+                "Body template is missing"
+            );
+            let creature = commands.spawn(NetworkSceneBundle {
+                scene: server.load("creatures/player.scn.ron").into(),
+                ..Default::default()
+            });
+            return SpawnCreatureResult {
+                root: creature.id(),
+            };
+        };
+
         let mut creature = commands.spawn(NetworkSceneBundle {
-            scene: server.load("creatures/player.scn.ron").into(),
+            scene: server
+                .load(format!("creatures/{}.scn.ron", template.scene))
+                .into(),
             ..Default::default()
         });
-        // TODO: Replace with species configuration in assets
-        match data.archetype.as_str() {
-            "human" => {
-                let mut limbs = HashSet::default();
-                creature.with_children(|builder| {
-                    let torso = spawn_limb(builder, server.as_ref(), "human_torso")
-                        .with_children(|builder| {
-                            // Head
-                            let head = spawn_limb(builder, server.as_ref(), "human_head")
-                                .with_children(|builder| {
-                                    let brain =
-                                        spawn_limb(builder, server.as_ref(), "organic_brain").id();
-                                    limbs.insert(brain);
-                                })
-                                .id();
-                            limbs.insert(head);
-
-                            // Arms
-                            let arm_left = spawn_limb(builder, server.as_ref(), "human_arm_left")
-                                .with_children(|builder| {
-                                    let hand_left =
-                                        spawn_limb(builder, server.as_ref(), "human_hand_left")
-                                            .id();
-                                    limbs.insert(hand_left);
-                                })
-                                .id();
-                            limbs.insert(arm_left);
-                            let arm_right = spawn_limb(builder, server.as_ref(), "human_arm_right")
-                                .with_children(|builder| {
-                                    let hand_right =
-                                        spawn_limb(builder, server.as_ref(), "human_hand_right")
-                                            .id();
-                                    limbs.insert(hand_right);
-                                })
-                                .id();
-                            limbs.insert(arm_right);
-
-                            // Legs
-                            let leg_left = spawn_limb(builder, server.as_ref(), "human_leg_left")
-                                .with_children(|builder| {
-                                    let foot_left =
-                                        spawn_limb(builder, server.as_ref(), "human_foot_left")
-                                            .id();
-                                    limbs.insert(foot_left);
-                                })
-                                .id();
-                            limbs.insert(leg_left);
-                            let leg_right = spawn_limb(builder, server.as_ref(), "human_leg_right")
-                                .with_children(|builder| {
-                                    let foot_right =
-                                        spawn_limb(builder, server.as_ref(), "human_foot_right")
-                                            .id();
-                                    limbs.insert(foot_right);
-                                })
-                                .id();
-                            limbs.insert(leg_right);
-
-                            let heart = spawn_limb(builder, server.as_ref(), "organic_heart").id();
-                            limbs.insert(heart);
-                            let lung = spawn_limb(builder, server.as_ref(), "organic_lung").id();
-                            limbs.insert(lung);
-                        })
-                        .id();
-                    limbs.insert(torso);
-                });
-                let added_limbs = limbs.iter().copied().collect();
-                creature.insert(Body {
-                    limbs,
-                    added_limbs,
-                    ..Default::default()
-                });
-            }
-            _ => todo!(),
-        }
-
-        bevy::log::info!("Created creature");
+        let mut limbs = HashSet::default();
+        creature.with_children(|builder| {
+            spawn_limb_tree(builder, server.as_ref(), &template.root, &mut limbs);
+        });
+        let added_limbs = limbs.iter().copied().collect();
+        creature.insert((
+            Body {
+                limbs,
+                added_limbs,
+                ..Default::default()
+            },
+            BodyVariant::new(template),
+        ));
+
+        bevy::log::info!(archetype = template.id.as_str(), "Created creature");
         SpawnCreatureResult {
             root: creature.id(),
         }
@@ -630,6 +750,7 @@ fn prepare_pickup_interaction(
     items: Query<&Item>,
     bodies: Query<(&Body, &Hands)>,
     hand_query: Query<(&Hand, &Container)>,
+    reach: Reach,
 ) {
     for event in interaction_lists.events.iter() {
         let Ok(_) = items.get(event.target) else {
@@ -653,6 +774,10 @@ fn prepare_pickup_interaction(
             continue;
         }
 
+        if !reach.can_reach(event.source, event.target) {
+            continue;
+        }
+
         event.add_interaction(InteractionOption {
             text: "Pick Up".into(),
             interaction: Box::new(PickupInteraction::new()),
@@ -661,12 +786,15 @@ fn prepare_pickup_interaction(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn pickup_interaction(
     mut query: Query<(Entity, &mut PickupInteraction, &mut ActiveInteraction)>,
     items: Query<&Item>,
     hands: Query<&Hands>,
     hand_query: Query<(Entity, &Hand, &Container)>,
     mut item_moves: ResMut<Tasks<MoveItem>>,
+    mut actions: EventWriter<ActorActionEvent>,
+    mut feedback: Feedback,
 ) {
     for (source, mut interaction, mut active) in query.iter_mut() {
         if interaction.move_task.is_some() {
@@ -689,6 +817,7 @@ fn pickup_interaction(
         };
 
         if !hand_container.is_empty() {
+            feedback.send_to_creature(source, FeedbackKind::HandsFull, "hands.full", &[]);
             active.status = InteractionStatus::Canceled;
             continue;
         }
@@ -703,14 +832,26 @@ fn pickup_interaction(
     }
 
     // Check for completed container moves
-    for (_, interaction, mut active) in query.iter_mut() {
+    for (source, interaction, mut active) in query.iter_mut() {
         let Some(task) = interaction.move_task else {
             continue;
         };
         if let Some(result) = item_moves.result(task) {
             active.status = if result.was_success() {
+                actions.send(ActorActionEvent {
+                    actor: source,
+                    action: ActorAction::Pickup,
+                });
                 InteractionStatus::Completed
             } else {
+                if let Some(failure) = result.failure() {
+                    feedback.send_to_creature(
+                        source,
+                        FeedbackKind::WontFit,
+                        failure.text_key(),
+                        &[],
+                    );
+                }
                 InteractionStatus::Canceled
             };
         }
@@ -828,7 +969,7 @@ fn drop_interaction(
 
 #[derive(Component, Reflect, Default)]
 #[reflect(Component)]
-struct Cutting {}
+pub struct Cutting {}
 
 #[derive(Component, Reflect, Default)]
 #[reflect(Component)]
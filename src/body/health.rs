@@ -1,12 +1,35 @@
-use bevy::{ecs::query::Has, prelude::*};
-use networking::is_server;
-
-use crate::combat::damage::*;
-
-use super::Body;
-
+use bevy::{
+    ecs::{query::Has, system::SystemParam},
+    prelude::*,
+};
+use networking::{identity::NetworkIdentity, is_server};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    combat::damage::*,
+    communication::EmoteEvent,
+    decals::{DecalKind, DecalSender},
+    flash::EyeDamageEvent,
+    items::{
+        armor::{limb_protection, Armor},
+        clothes::ClothingHolder,
+        Item,
+    },
+    movement::FallEvent,
+    replay::{self, ChecksumSet, Checksums},
+    temperature::ThermalDamageEvent,
+};
+
+use self::treatment::Sutured;
+use super::{self_or_ancestor, variant::BodyVariant, Body, Limb};
+
+pub use death::Dead;
+
+mod death;
 mod items;
+pub mod metabolism;
 mod scanner;
+pub mod treatment;
 mod ui;
 
 pub struct HealthPlugin;
@@ -17,24 +40,46 @@ impl Plugin for HealthPlugin {
             .register_type::<OrganicBodyPart>()
             .register_type::<OrganicLung>()
             .register_type::<OrganicHeart>()
-            .register_type::<OrganicBrain>();
+            .register_type::<OrganicBrain>()
+            .register_type::<OrganicEyes>();
         if is_server(app) {
             app.add_event::<HeartBeat>()
                 .add_event::<BrainStateEvent>()
+                .add_event::<BasicAidEvent>()
+                .add_event::<LethalDamageEvent>()
                 .add_systems(
                     Update,
                     (
                         (heart_beat, adjust_heart_rate).chain(),
                         breathing,
                         lung_gas_exchange,
-                        receive_damage,
+                        // Damage is applied in a fixed order. Body part integrity is clamped and lethal
+                        // damage checks whether the brain was already dead, so a different order can end
+                        // with different values and the same round wouldn't play out the same again.
+                        (
+                            receive_fall_damage,
+                            receive_damage,
+                            receive_thermal_damage,
+                            receive_eye_damage,
+                            receive_lethal_damage,
+                        )
+                            .chain(),
                         brain_live,
+                        basic_aid,
+                        apply_variant_integrity,
                     ),
-                );
+                )
+                .add_plugins(death::DeathPlugin);
+
+            if replay::is_active(app) {
+                app.add_systems(Last, health_checksum.in_set(ChecksumSet));
+            }
         }
         app.add_plugins((
             scanner::HealthScannerPlugin,
             items::HealthItemsPlugin,
+            metabolism::MetabolismPlugin,
+            treatment::TreatmentPlugin,
             ui::HealthUiPlugin,
         ));
     }
@@ -94,6 +139,8 @@ struct OrganicBodyPart {
     /// How damaged the body part is.
     /// 1 is fully capable, 0 is unusable
     integrity: f32,
+    /// Integrity of the unharmed body part, set by the body variant
+    max_integrity: f32,
 }
 
 impl FromWorld for OrganicBodyPart {
@@ -102,6 +149,7 @@ impl FromWorld for OrganicBodyPart {
             oxygen_consumed: 0.0,
             oxygen_capacity: 0.0015,
             integrity: 1.0,
+            max_integrity: 1.0,
         }
     }
 }
@@ -298,7 +346,7 @@ fn heart_beat(
     mut bodies: Query<(&Body, &mut OrganicBody)>,
     mut body_parts: Query<(&Parent, &mut OrganicBodyPart)>,
     mut event: EventWriter<HeartBeat>,
-    lacerations: Query<(&OrganicLaceration, &Parent)>,
+    lacerations: Query<(&OrganicLaceration, &Parent), Without<Sutured>>,
     parents: Query<&Parent>,
     time: Res<Time>,
 ) {
@@ -390,12 +438,13 @@ fn heart_beat(
         );
 
         for (laceration, parent) in lacerations.iter() {
-            let Ok((body_part_parent, _)) = body_parts.get(parent.get()) else {
+            // Wounds are on a limb or body part attached to the body
+            let Ok(limb_parent) = parents.get(parent.get()) else {
                 continue;
             };
 
             // TODO: Can we make this more efficient?
-            if body_entity != body_part_parent.get() {
+            if body_entity != limb_parent.get() {
                 continue;
             }
 
@@ -533,7 +582,7 @@ struct OrganicLaceration {
     size: LacerationSize,
 }
 
-#[allow(dead_code)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 enum LacerationSize {
     Small,
     Medium,
@@ -541,6 +590,19 @@ enum LacerationSize {
 }
 
 impl LacerationSize {
+    /// The wound left by an impact with some kinetic energy in joules, if any
+    fn from_energy(energy: f32) -> Option<Self> {
+        if energy < 200.0 {
+            None
+        } else if energy < 2000.0 {
+            Some(LacerationSize::Small)
+        } else if energy < 12000.0 {
+            Some(LacerationSize::Medium)
+        } else {
+            Some(LacerationSize::Large)
+        }
+    }
+
     fn blood_loss_ratio(&self) -> f32 {
         match self {
             LacerationSize::Small => 0.05,
@@ -548,6 +610,15 @@ impl LacerationSize {
             LacerationSize::Large => 0.40,
         }
     }
+
+    /// Width in meters of the blood splatter left on the floor
+    fn splatter_size(&self) -> f32 {
+        match self {
+            LacerationSize::Small => 0.4,
+            LacerationSize::Medium => 0.7,
+            LacerationSize::Large => 1.0,
+        }
+    }
 }
 
 impl std::fmt::Display for LacerationSize {
@@ -560,24 +631,355 @@ impl std::fmt::Display for LacerationSize {
     }
 }
 
-fn receive_damage(
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn receive_damage(
     attacks: Query<(Entity, &AffectedEntity, &KineticDamage), Added<Attack>>,
-    body_parts: Query<&OrganicBodyPart>,
+    body_parts: Query<(), Or<(With<OrganicBodyPart>, With<Limb>)>>,
+    children: Query<&Children>,
+    holders: Query<(), With<ClothingHolder>>,
+    armor: Query<&Armor>,
+    transforms: Query<&GlobalTransform>,
+    mut decals: DecalSender,
     mut commands: Commands,
 ) {
-    for (attack_entity, affected_entity, _kinetic) in attacks.iter() {
-        let Ok(_) = body_parts.get(affected_entity.0) else {
+    for (attack_entity, affected_entity, kinetic) in attacks.iter() {
+        if !body_parts.contains(affected_entity.0) {
             continue;
-        };
+        }
 
-        bevy::log::debug!("Received wound");
-        // TODO: Clothing/armor, hitting organs, arteries
+        // TODO: Hitting organs, arteries
         commands.entity(attack_entity).despawn();
+        // TODO: Consider the shape of the impact
+        let energy = 0.5 * kinetic.mass * kinetic.velocity.powi(2) * kinetic.scale;
+        // Armor worn on the limb absorbs part of the hit
+        let energy =
+            energy * (1.0 - limb_protection(affected_entity.0, &children, &holders, &armor));
+        let Some(size) = LacerationSize::from_energy(energy) else {
+            bevy::log::debug!("Attack too weak to wound");
+            continue;
+        };
+
+        bevy::log::debug!("Received {} wound", size);
+        if let Ok(transform) = transforms.get(affected_entity.0) {
+            decals.send(
+                DecalKind::Blood,
+                transform.translation(),
+                None,
+                size.splatter_size(),
+            );
+        }
         commands
-            .spawn(OrganicLaceration {
-                // TODO: Consider kinetic profile
-                size: LacerationSize::Medium,
-            })
+            .spawn(OrganicLaceration { size })
             .set_parent(affected_entity.0);
     }
 }
+
+/// Burns and frostbite hurt every body part equally.
+fn receive_thermal_damage(
+    mut events: EventReader<ThermalDamageEvent>,
+    bodies: Query<(&Body, Option<&BodyVariant>)>,
+    mut body_parts: Query<&mut OrganicBodyPart>,
+) {
+    for event in events.iter() {
+        let Ok((body, variant)) = bodies.get(event.body) else {
+            continue;
+        };
+        let parts_count = body_parts.iter_many(&body.limbs).count();
+        if parts_count == 0 {
+            continue;
+        }
+
+        let vulnerability = variant.map_or(1.0, |v| v.vulnerability(event.kind));
+        let per_part = event.amount * vulnerability / parts_count as f32;
+        bevy::log::debug!("{:?} damage {} per body part", event.kind, per_part);
+        let mut iter = body_parts.iter_many_mut(&body.limbs);
+        while let Some(mut part) = iter.fetch_next() {
+            part.damage(per_part);
+        }
+    }
+}
+
+/// Body parts of a creature start with the integrity of its body variant.
+fn apply_variant_integrity(
+    mut body_parts: Query<(Entity, &mut OrganicBodyPart), Added<OrganicBodyPart>>,
+    parents: Query<&Parent>,
+    variants: Query<&BodyVariant>,
+) {
+    for (entity, mut part) in body_parts.iter_mut() {
+        let Some(variant) = parents
+            .iter_ancestors(entity)
+            .find_map(|ancestor| variants.get(ancestor).ok())
+        else {
+            continue;
+        };
+        part.max_integrity = variant.max_integrity();
+        part.integrity = part.max_integrity;
+    }
+}
+
+/// Eyes get hurt by bright flashes. The damage adds up and doesn't heal on its own.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+struct OrganicEyes;
+
+fn receive_eye_damage(
+    mut events: EventReader<EyeDamageEvent>,
+    bodies: Query<&Body>,
+    mut eyes: Query<&mut OrganicBodyPart, With<OrganicEyes>>,
+) {
+    for event in events.iter() {
+        let Ok(body) = bodies.get(event.body) else {
+            continue;
+        };
+        let mut iter = eyes.iter_many_mut(&body.limbs);
+        while let Some(mut part) = iter.fetch_next() {
+            part.damage(event.amount);
+            bevy::log::debug!("Eyes damaged, integrity {}", part.integrity);
+        }
+    }
+}
+
+/// How many body parts take the impact of a fall
+const FALL_IMPACT_PARTS: usize = 2;
+/// Mass in kg hitting the floor with each of those body parts
+const FALL_IMPACT_MASS: f32 = 35.0;
+
+/// Falls hit the body parts the creature lands on, usually the feet.
+fn receive_fall_damage(
+    mut events: EventReader<FallEvent>,
+    bodies: Query<&Body>,
+    body_parts: Query<&GlobalTransform, With<OrganicBodyPart>>,
+    mut commands: Commands,
+) {
+    for event in events.iter() {
+        let Ok(body) = bodies.get(event.body) else {
+            continue;
+        };
+        let mut parts: Vec<_> = body
+            .limbs
+            .iter()
+            .filter_map(|&limb| Some((limb, body_parts.get(limb).ok()?.translation().y)))
+            .collect();
+        parts.sort_unstable_by(|a, b| a.1.total_cmp(&b.1));
+
+        let velocity = (2.0 * 9.81 * event.height).sqrt();
+        bevy::log::debug!("Fell {} m, landing at {} m/s", event.height, velocity);
+        for &(part, _) in parts.iter().take(FALL_IMPACT_PARTS) {
+            commands.spawn((
+                Attack,
+                AffectedEntity(part),
+                KineticDamage {
+                    velocity,
+                    mass: FALL_IMPACT_MASS,
+                    shape: KineticShape::Blunt,
+                    scale: 1.0,
+                },
+            ));
+        }
+    }
+}
+
+/// Damage no creature survives, like falling into the void.
+#[derive(Event)]
+pub struct LethalDamageEvent {
+    pub body: Entity,
+}
+
+/// Destroys every body part. The brain dies with them, so the usual death handling follows.
+fn receive_lethal_damage(
+    mut events: EventReader<LethalDamageEvent>,
+    bodies: Query<&Body>,
+    mut body_parts: Query<(Entity, &mut OrganicBodyPart, Has<OrganicBrain>)>,
+    mut state_events: EventWriter<BrainStateEvent>,
+) {
+    for event in events.iter() {
+        let Ok(body) = bodies.get(event.body) else {
+            continue;
+        };
+        let mut iter = body_parts.iter_many_mut(&body.limbs);
+        while let Some((entity, mut part, is_brain)) = iter.fetch_next() {
+            // Brains that are already dead have already been handled
+            let was_dead = part.unusable();
+            part.damage(part.integrity);
+            if is_brain && !was_dead {
+                state_events.send(BrainStateEvent {
+                    brain: entity,
+                    new_state: BrainState::Dead,
+                });
+            }
+        }
+    }
+}
+
+/// Hashes the state of every body part for replays, so a simulation notices damage being applied differently.
+/// Parts without a network identity are told apart by the identity of their creature.
+fn health_checksum(
+    bodies: Query<(&NetworkIdentity, &OrganicBody)>,
+    body_parts: Query<(Entity, &OrganicBodyPart)>,
+    identities: Query<&NetworkIdentity>,
+    parents: Query<&Parent>,
+    mut checksums: ResMut<Checksums>,
+) {
+    let blood = replay::unordered_hash(bodies.iter().map(|(&identity, body)| {
+        (
+            identity,
+            body.blood.to_bits(),
+            body.oxygen_in_blood.to_bits(),
+        )
+    }));
+    let parts = replay::unordered_hash(body_parts.iter().map(|(entity, part)| {
+        let identity = self_or_ancestor(&parents, entity, |e| identities.contains(e))
+            .and_then(|e| identities.get(e).ok());
+        (
+            identity,
+            part.integrity.to_bits(),
+            part.oxygen_consumed.to_bits(),
+        )
+    }));
+    checksums.add("blood", blood);
+    checksums.add("health", parts);
+}
+
+/// A rough summary of how a creature is doing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VitalStatus {
+    Healthy,
+    Injured,
+    Unconscious,
+    Dead,
+}
+
+impl std::fmt::Display for VitalStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VitalStatus::Healthy => write!(f, "Healthy"),
+            VitalStatus::Injured => write!(f, "Injured"),
+            VitalStatus::Unconscious => write!(f, "Unconscious"),
+            VitalStatus::Dead => write!(f, "Dead"),
+        }
+    }
+}
+
+#[derive(SystemParam)]
+pub struct Vitals<'w, 's> {
+    bodies: Query<'w, 's, &'static Body>,
+    brains: Query<'w, 's, (&'static OrganicBrain, Option<&'static OrganicBodyPart>)>,
+    limbs: Query<'w, 's, &'static Children, With<OrganicBodyPart>>,
+    lacerations: Query<'w, 's, (), With<OrganicLaceration>>,
+    parts: Query<
+        'w,
+        's,
+        (
+            &'static OrganicBodyPart,
+            Option<&'static Item>,
+            Option<&'static Children>,
+        ),
+    >,
+}
+
+/// The condition of a single body part, as shown by medical machines.
+pub struct LimbCondition {
+    pub name: String,
+    /// 1 is fully capable, 0 is unusable
+    pub integrity: f32,
+    pub wounds: usize,
+}
+
+impl<'w, 's> Vitals<'w, 's> {
+    /// The status of a body, `None` if it isn't one
+    pub fn status(&self, body: Entity) -> Option<VitalStatus> {
+        let body = self.bodies.get(body).ok()?;
+
+        let mut unconscious = false;
+        for (brain, part) in self.brains.iter_many(&body.limbs) {
+            if part.is_some_and(|p| p.unusable()) {
+                return Some(VitalStatus::Dead);
+            }
+            unconscious |= brain.unconcious;
+        }
+        if unconscious {
+            return Some(VitalStatus::Unconscious);
+        }
+
+        let wounded = self
+            .limbs
+            .iter_many(&body.limbs)
+            .flat_map(|children| children.iter())
+            .any(|&child| self.lacerations.contains(child));
+        Some(if wounded {
+            VitalStatus::Injured
+        } else {
+            VitalStatus::Healthy
+        })
+    }
+
+    /// The condition of every organic body part of a body, `None` if it isn't one
+    pub fn limbs(&self, body: Entity) -> Option<Vec<LimbCondition>> {
+        let body = self.bodies.get(body).ok()?;
+        let mut limbs: Vec<_> = self
+            .parts
+            .iter_many(&body.limbs)
+            .map(|(part, item, children)| LimbCondition {
+                name: item.map(|i| i.name.clone()).unwrap_or_default(),
+                integrity: part.integrity,
+                wounds: children
+                    .into_iter()
+                    .flat_map(|c| c.iter())
+                    .filter(|&&child| self.lacerations.contains(child))
+                    .count(),
+            })
+            .collect();
+        limbs.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+        Some(limbs)
+    }
+}
+
+/// A creature helping another without any medical items.
+#[derive(Event)]
+pub struct BasicAidEvent {
+    pub helper: Entity,
+    pub patient: Entity,
+}
+
+/// Pressure on a wound makes it bleed like a smaller one, otherwise it's just a hug
+fn basic_aid(
+    mut events: EventReader<BasicAidEvent>,
+    bodies: Query<&Body>,
+    limbs: Query<&Children, With<OrganicBodyPart>>,
+    mut lacerations: Query<&mut OrganicLaceration>,
+    mut emotes: EventWriter<EmoteEvent>,
+) {
+    for event in events.iter() {
+        let Ok(body) = bodies.get(event.patient) else {
+            continue;
+        };
+
+        let worst_wound = limbs
+            .iter_many(&body.limbs)
+            .flat_map(|children| children.iter())
+            .filter_map(|&child| lacerations.get(child).ok().map(|l| (child, l.size)))
+            .filter(|(_, size)| !matches!(size, LacerationSize::Small))
+            .max_by_key(|(_, size)| match size {
+                LacerationSize::Small => 0,
+                LacerationSize::Medium => 1,
+                LacerationSize::Large => 2,
+            })
+            .map(|(entity, _)| entity);
+
+        let text = if let Some(mut wound) = worst_wound.and_then(|w| lacerations.get_mut(w).ok()) {
+            wound.size = match wound.size {
+                LacerationSize::Large => LacerationSize::Medium,
+                _ => LacerationSize::Small,
+            };
+            "applies pressure to {target}'s wounds."
+        } else {
+            "hugs {target}."
+        };
+
+        emotes.send(EmoteEvent {
+            actor: event.helper,
+            target: Some(event.patient),
+            text: text.into(),
+        });
+    }
+}
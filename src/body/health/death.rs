@@ -0,0 +1,440 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+use networking::{spawning::ClientControls, time::ServerNetworkTime, Players};
+
+use crate::{
+    body::{self_or_ancestor, Body},
+    combat::damage::*,
+    communication::{AnnouncementEvent, SystemMessageEvent},
+    interaction::{
+        ActiveInteraction, GenerateInteractionList, InteractionListEvents, InteractionOption,
+        InteractionSpecificity, InteractionStatus,
+    },
+    items::Item,
+    round::RoundState,
+    temperature::{ThermalDamageEvent, ThermalDamageKind},
+};
+
+use super::{
+    receive_damage, scanner::HealthScanner, BrainState, BrainStateEvent, LacerationSize,
+    LethalDamageEvent,
+};
+
+/// Records what hurt a body, to find out how it died.
+pub(super) struct DeathPlugin;
+
+impl Plugin for DeathPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<AutopsyInteraction>()
+            .init_resource::<RoundDeaths>()
+            .add_systems(
+                Update,
+                (
+                    (
+                        setup_damage_logs,
+                        log_kinetic_damage.after(receive_damage),
+                        log_thermal_damage,
+                        log_lethal_damage,
+                        mark_dead,
+                    )
+                        .chain(),
+                    prepare_autopsy_interaction.in_set(GenerateInteractionList),
+                    autopsy_interaction,
+                ),
+            )
+            .add_systems(OnEnter(RoundState::Ended), announce_deaths);
+    }
+}
+
+/// How many damage records are kept per body
+const DAMAGE_LOG_LENGTH: usize = 16;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DamageKind {
+    Brute,
+    Burn,
+    Cold,
+}
+
+impl DamageKind {
+    fn describe(self) -> &'static str {
+        match self {
+            DamageKind::Brute => "brute trauma",
+            DamageKind::Burn => "burns",
+            DamageKind::Cold => "frostbite",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Severity {
+    Minor,
+    Moderate,
+    Severe,
+    Critical,
+}
+
+impl Severity {
+    fn from_laceration(size: Option<LacerationSize>) -> Self {
+        match size {
+            None => Severity::Minor,
+            Some(LacerationSize::Small) => Severity::Moderate,
+            Some(LacerationSize::Medium) => Severity::Severe,
+            Some(LacerationSize::Large) => Severity::Critical,
+        }
+    }
+
+    /// Severity of damage to the integrity of every body part
+    fn from_integrity_loss(amount: f32) -> Self {
+        if amount < 0.02 {
+            Severity::Minor
+        } else if amount < 0.1 {
+            Severity::Moderate
+        } else if amount < 0.3 {
+            Severity::Severe
+        } else {
+            Severity::Critical
+        }
+    }
+
+    fn describe(self) -> &'static str {
+        match self {
+            Severity::Minor => "minor",
+            Severity::Moderate => "moderate",
+            Severity::Severe => "severe",
+            Severity::Critical => "critical",
+        }
+    }
+}
+
+/// Damage a body took at some point.
+pub struct DamageRecord {
+    pub kind: DamageKind,
+    pub severity: Severity,
+    /// Energy in joules for brute damage, integrity lost for temperature damage
+    pub amount: f32,
+    pub tick: u32,
+    /// The body part that was hit, if not the whole body
+    pub zone: Option<String>,
+    /// The creature responsible, following the attack back to who started it
+    pub source: Option<Entity>,
+    /// Name of the responsible creature and its player at the time
+    pub source_name: Option<String>,
+    pub source_player: Option<String>,
+}
+
+impl DamageRecord {
+    fn describe(&self) -> String {
+        let mut text = format!("{} {}", self.severity.describe(), self.kind.describe());
+        match &self.zone {
+            Some(zone) => text += &format!(" to the {}", zone.to_lowercase()),
+            None => text += " all over the body",
+        }
+        text
+    }
+}
+
+/// The most recent damage a body took. Only kept on the server.
+#[derive(Component, Default)]
+pub struct DamageLog {
+    records: VecDeque<DamageRecord>,
+}
+
+impl DamageLog {
+    fn push(&mut self, record: DamageRecord) {
+        if self.records.len() >= DAMAGE_LOG_LENGTH {
+            self.records.pop_front();
+        }
+        self.records.push_back(record);
+    }
+
+    /// The record that most likely killed the body
+    fn cause(&self) -> Option<&DamageRecord> {
+        // Later damage wins ties, as it was the last straw
+        self.records.iter().max_by(|a, b| {
+            a.severity
+                .cmp(&b.severity)
+                .then(a.amount.total_cmp(&b.amount))
+        })
+    }
+}
+
+/// Added to bodies when they die.
+#[derive(Component)]
+pub struct Dead {
+    /// Readable summary of what killed the body
+    pub cause: String,
+    /// The creature responsible for the death, if another creature caused it
+    pub killer: Option<Entity>,
+}
+
+/// Everyone that died this round, for the summary at the end.
+#[derive(Resource, Default)]
+struct RoundDeaths {
+    deaths: Vec<(String, String)>,
+}
+
+/// Who is responsible for an attack, following it back through whoever set it in motion.
+fn responsible(
+    source: &AttackSource,
+    names: &Query<&Name>,
+    controls: &ClientControls,
+    players: &Players,
+) -> (Option<Entity>, Option<String>, Option<String>) {
+    let entity = source.instigator.unwrap_or(source.attacker);
+    let name = names.get(entity).ok().map(|n| n.as_str().to_owned());
+    let player = controls
+        .controlling_player(entity)
+        .and_then(|id| players.get_connection(&id))
+        .and_then(|connection| players.get(connection))
+        .map(|player| player.username.clone());
+    (Some(entity), name, player)
+}
+
+fn setup_damage_logs(bodies: Query<Entity, Added<Body>>, mut commands: Commands) {
+    for body in bodies.iter() {
+        commands.entity(body).insert(DamageLog::default());
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn log_kinetic_damage(
+    attacks: Query<(&AffectedEntity, &KineticDamage, Option<&AttackSource>), Added<Attack>>,
+    bodies: Query<(), With<Body>>,
+    parents: Query<&Parent>,
+    items: Query<&Item>,
+    names: Query<&Name>,
+    mut logs: Query<&mut DamageLog>,
+    controls: Res<ClientControls>,
+    players: Res<Players>,
+    network_time: Res<ServerNetworkTime>,
+) {
+    for (affected, kinetic, source) in attacks.iter() {
+        let Some(body) = self_or_ancestor(&parents, affected.0, |e| bodies.contains(e)) else {
+            continue;
+        };
+
+        // Same energy as used for the wound
+        let energy = 0.5 * kinetic.mass * kinetic.velocity.powi(2) * kinetic.scale;
+        let (source, source_name, source_player) = source
+            .map(|source| responsible(source, &names, &controls, &players))
+            .unwrap_or_default();
+        let record = DamageRecord {
+            kind: DamageKind::Brute,
+            severity: Severity::from_laceration(LacerationSize::from_energy(energy)),
+            amount: energy,
+            tick: network_time.current_tick(),
+            zone: (affected.0 != body)
+                .then(|| items.get(affected.0).ok().map(|item| item.name.clone()))
+                .flatten(),
+            source,
+            source_name,
+            source_player,
+        };
+
+        if let Ok(mut log) = logs.get_mut(body) {
+            log.push(record);
+        }
+    }
+}
+
+fn log_thermal_damage(
+    mut events: EventReader<ThermalDamageEvent>,
+    mut logs: Query<&mut DamageLog>,
+    bodies: Query<(), With<Body>>,
+    network_time: Res<ServerNetworkTime>,
+) {
+    for event in events.iter() {
+        if !bodies.contains(event.body) {
+            continue;
+        }
+        let record = DamageRecord {
+            kind: match event.kind {
+                ThermalDamageKind::Burn => DamageKind::Burn,
+                ThermalDamageKind::Cold => DamageKind::Cold,
+            },
+            severity: Severity::from_integrity_loss(event.amount),
+            amount: event.amount,
+            tick: network_time.current_tick(),
+            zone: None,
+            source: None,
+            source_name: None,
+            source_player: None,
+        };
+
+        if let Ok(mut log) = logs.get_mut(event.body) {
+            log.push(record);
+        }
+    }
+}
+
+fn log_lethal_damage(
+    mut events: EventReader<LethalDamageEvent>,
+    mut logs: Query<&mut DamageLog>,
+    network_time: Res<ServerNetworkTime>,
+) {
+    for event in events.iter() {
+        let Ok(mut log) = logs.get_mut(event.body) else {
+            continue;
+        };
+        log.push(DamageRecord {
+            kind: DamageKind::Brute,
+            severity: Severity::Critical,
+            // Outweighs any other critical damage as the cause of death
+            amount: f32::MAX,
+            tick: network_time.current_tick(),
+            zone: None,
+            source: None,
+            source_name: None,
+            source_player: None,
+        });
+    }
+}
+
+fn mark_dead(
+    mut events: EventReader<BrainStateEvent>,
+    bodies: Query<(Option<&DamageLog>, Option<&Name>), (With<Body>, Without<Dead>)>,
+    parents: Query<&Parent>,
+    mut deaths: ResMut<RoundDeaths>,
+    mut commands: Commands,
+) {
+    for event in events.iter() {
+        if event.new_state != BrainState::Dead {
+            continue;
+        }
+        let Some(body) = parents
+            .iter_ancestors(event.brain)
+            .find(|e| bodies.contains(*e))
+        else {
+            continue;
+        };
+        let (log, name) = bodies.get(body).unwrap();
+
+        let cause_record = log.and_then(DamageLog::cause);
+        let cause = match cause_record {
+            Some(record) => match &record.source_name {
+                Some(source) => format!("{}, caused by {}", record.describe(), source),
+                None => record.describe(),
+            },
+            // Brains only die by themselves from a lack of oxygen
+            None => "oxygen deprivation".to_owned(),
+        };
+
+        let name = name.map(|n| n.as_str()).unwrap_or("Unknown");
+        info!(
+            ?body,
+            name,
+            cause = cause.as_str(),
+            killer = ?cause_record.and_then(|r| r.source),
+            killer_player = cause_record.and_then(|r| r.source_player.as_deref()),
+            "Creature died"
+        );
+        deaths.deaths.push((name.to_owned(), cause.clone()));
+        commands.entity(body).insert(Dead {
+            cause,
+            killer: cause_record.and_then(|r| r.source),
+        });
+    }
+}
+
+fn announce_deaths(deaths: Res<RoundDeaths>, mut announcements: EventWriter<AnnouncementEvent>) {
+    let text = if deaths.deaths.is_empty() {
+        "Round summary: nobody died.".to_owned()
+    } else {
+        let lines: Vec<_> = deaths
+            .deaths
+            .iter()
+            .map(|(name, cause)| format!("{} died of {}", name, cause))
+            .collect();
+        format!("Round summary: {}.", lines.join("; "))
+    };
+    info!(text = text.as_str(), "Round ended");
+    announcements.send(AnnouncementEvent::custom(text));
+}
+
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+#[component(storage = "SparseSet")]
+struct AutopsyInteraction;
+
+fn prepare_autopsy_interaction(
+    list: Res<InteractionListEvents>,
+    scanners: Query<(), With<HealthScanner>>,
+    corpses: Query<(), (With<Body>, With<Dead>)>,
+) {
+    for event in list.events.iter() {
+        if !event
+            .item_in_hand
+            .is_some_and(|item| scanners.contains(item))
+        {
+            continue;
+        }
+        if !corpses.contains(event.target) {
+            continue;
+        }
+
+        event.add_interaction(InteractionOption {
+            text: "Autopsy".into(),
+            interaction: Box::new(AutopsyInteraction),
+            specificity: InteractionSpecificity::Specific,
+        });
+    }
+}
+
+/// Time it takes to examine a corpse
+const AUTOPSY_TIME: std::time::Duration = std::time::Duration::from_secs(3);
+
+fn describe_age(seconds: f64) -> String {
+    if seconds < 60.0 {
+        "moments ago".to_owned()
+    } else {
+        let minutes = (seconds / 60.0).round() as u32;
+        if minutes == 1 {
+            "approx. 1 minute ago".to_owned()
+        } else {
+            format!("approx. {} minutes ago", minutes)
+        }
+    }
+}
+
+fn autopsy_interaction(
+    mut query: Query<(Entity, &mut ActiveInteraction), With<AutopsyInteraction>>,
+    corpses: Query<(&Dead, Option<&DamageLog>)>,
+    controls: Res<ClientControls>,
+    players: Res<Players>,
+    network_time: Res<ServerNetworkTime>,
+    time: Res<Time>,
+    mut messages: EventWriter<SystemMessageEvent>,
+) {
+    for (source, mut active) in query.iter_mut() {
+        active.set_initial_duration(AUTOPSY_TIME);
+        let Ok((dead, log)) = corpses.get(active.target) else {
+            active.status = InteractionStatus::Canceled;
+            continue;
+        };
+        if active.start_time() + AUTOPSY_TIME.as_secs_f32() > time.elapsed_seconds() {
+            continue;
+        }
+        active.status = InteractionStatus::Completed;
+
+        let Some(connection) = controls
+            .controlling_player(source)
+            .and_then(|p| players.get_connection(&p))
+        else {
+            continue;
+        };
+
+        let mut lines = vec![format!("Cause of death: {}.", dead.cause)];
+        let now = network_time.current_tick();
+        for record in log.into_iter().flat_map(|log| log.records.iter().rev()) {
+            let seconds = now.saturating_sub(record.tick) as f64 * network_time.tick_in_seconds();
+            lines.push(format!("{}, {}.", record.describe(), describe_age(seconds)));
+        }
+        let text = lines.join("\n");
+        messages.send(SystemMessageEvent {
+            receiver: connection,
+            text,
+        });
+    }
+}
@@ -270,7 +270,7 @@ fn defibrillate_interaction(
                 if let (Some(_), Some(mut body_part)) = (brain, body_part) {
                     // Heal brain damage
                     // I know this makes no sense, sue me
-                    body_part.integrity = 1.0;
+                    body_part.integrity = body_part.max_integrity;
                 }
             }
         }
@@ -0,0 +1,630 @@
+use std::time::Duration;
+
+use bevy::{ecs::query::Has, prelude::*, time::common_conditions::on_timer, utils::HashMap};
+use networking::{
+    is_server,
+    messaging::{AppExt, MessageReceivers, MessageSender},
+    spawning::ClientControls,
+    ConnectionId, Players,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    body::{variant::BodyVariant, Body},
+    combat::{CombatMode, GrabbedBy},
+    config::ServerConfig,
+    interaction::{
+        ActiveInteraction, GenerateInteractionList, InteractionListEvents, InteractionOption,
+        InteractionSpecificity, InteractionStatus, Reach,
+    },
+    movement::speed::SpeedModifiers,
+};
+
+#[cfg(feature = "client")]
+use {
+    crate::ui::has_window,
+    bevy_egui::{egui, EguiContexts},
+    networking::messaging::MessageEvent,
+};
+
+use super::{OrganicBodyPart, OrganicBrain};
+
+/// Hunger and thirst of player bodies, and eating food to keep them up.
+pub struct MetabolismPlugin;
+
+impl Plugin for MetabolismPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Nutrition>()
+            .add_network_message::<MetabolismMessage>("MetabolismMessage");
+
+        if is_server(app) {
+            let config = app
+                .world
+                .get_resource::<ServerConfig>()
+                .map(|config| config.metabolism.clone())
+                .unwrap_or_default();
+            // Casual servers can turn it off, bodies then never get hungry and can't eat
+            if !config.enabled {
+                return;
+            }
+            app.insert_resource(config)
+                .register_type::<EatInteraction>()
+                .add_systems(
+                    Update,
+                    (
+                        add_metabolism,
+                        tick_metabolism
+                            .run_if(on_timer(Duration::from_secs_f32(METABOLISM_INTERVAL))),
+                        send_metabolism,
+                        starving_speed,
+                        prepare_eat_interaction.in_set(GenerateInteractionList),
+                        eat_interaction,
+                    ),
+                );
+        } else {
+            #[cfg(feature = "client")]
+            app.init_resource::<OwnMetabolism>().add_systems(
+                Update,
+                (receive_metabolism, metabolism_ui.run_if(has_window)).chain(),
+            );
+        }
+    }
+}
+
+/// Seconds between metabolism updates
+const METABOLISM_INTERVAL: f32 = 1.0;
+/// Hunger and thirst of a creature that just ate and drank
+pub const MAX_SATIATION: f32 = 100.0;
+/// Movement speed while starving or dehydrated
+const STARVING_SPEED_MULTIPLIER: f32 = 0.85;
+
+/// How fast creatures get hungry and thirsty.
+#[derive(Deserialize, Resource, Clone)]
+pub struct MetabolismConfig {
+    #[serde(default = "MetabolismConfig::default_enabled")]
+    pub enabled: bool,
+    /// Hunger lost per minute, out of [`MAX_SATIATION`]
+    #[serde(default = "MetabolismConfig::default_hunger_per_minute")]
+    pub hunger_per_minute: f32,
+    /// Thirst lost per minute, out of [`MAX_SATIATION`]
+    #[serde(default = "MetabolismConfig::default_thirst_per_minute")]
+    pub thirst_per_minute: f32,
+    /// Integrity every body part loses per minute while starving or dehydrated
+    #[serde(default = "MetabolismConfig::default_starving_damage_per_minute")]
+    pub starving_damage_per_minute: f32,
+}
+
+impl MetabolismConfig {
+    fn default_enabled() -> bool {
+        true
+    }
+
+    fn default_hunger_per_minute() -> f32 {
+        1.0
+    }
+
+    fn default_thirst_per_minute() -> f32 {
+        1.5
+    }
+
+    fn default_starving_damage_per_minute() -> f32 {
+        0.01
+    }
+}
+
+impl Default for MetabolismConfig {
+    fn default() -> Self {
+        Self {
+            enabled: Self::default_enabled(),
+            hunger_per_minute: Self::default_hunger_per_minute(),
+            thirst_per_minute: Self::default_thirst_per_minute(),
+            starving_damage_per_minute: Self::default_starving_damage_per_minute(),
+        }
+    }
+}
+
+/// How full a creature is, coarse enough that players don't see exact numbers.
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum SatiationTier {
+    #[default]
+    Full,
+    Fed,
+    Hungry,
+    Starving,
+}
+
+impl SatiationTier {
+    fn from_value(value: f32) -> Self {
+        if value > 75.0 {
+            SatiationTier::Full
+        } else if value > 40.0 {
+            SatiationTier::Fed
+        } else if value > 10.0 {
+            SatiationTier::Hungry
+        } else {
+            SatiationTier::Starving
+        }
+    }
+}
+
+/// Hunger and thirst of a creature, between 0 (starving) and [`MAX_SATIATION`].
+/// Only known to the server, the controlling player is sent [`SatiationTier`]s.
+#[derive(Component)]
+pub struct Metabolism {
+    pub hunger: f32,
+    pub thirst: f32,
+}
+
+impl Default for Metabolism {
+    fn default() -> Self {
+        Self {
+            hunger: MAX_SATIATION,
+            thirst: MAX_SATIATION,
+        }
+    }
+}
+
+impl Metabolism {
+    pub fn hunger_tier(&self) -> SatiationTier {
+        SatiationTier::from_value(self.hunger)
+    }
+
+    pub fn thirst_tier(&self) -> SatiationTier {
+        SatiationTier::from_value(self.thirst)
+    }
+
+    /// Starving or dehydrated creatures are slowed and slowly take damage
+    pub fn is_starving(&self) -> bool {
+        self.hunger_tier() == SatiationTier::Starving
+            || self.thirst_tier() == SatiationTier::Starving
+    }
+
+    /// Uses up food and water for the given amount of seconds.
+    pub fn tick(&mut self, seconds: f32, config: &MetabolismConfig) {
+        let minutes = seconds / 60.0;
+        self.hunger = (self.hunger - config.hunger_per_minute * minutes).max(0.0);
+        self.thirst = (self.thirst - config.thirst_per_minute * minutes).max(0.0);
+    }
+
+    pub fn restore(&mut self, hunger: f32, thirst: f32) {
+        self.hunger = (self.hunger + hunger).clamp(0.0, MAX_SATIATION);
+        self.thirst = (self.thirst + thirst).clamp(0.0, MAX_SATIATION);
+    }
+}
+
+fn add_metabolism(
+    bodies: Query<(Entity, Option<&BodyVariant>), Added<Body>>,
+    mut commands: Commands,
+) {
+    for (entity, variant) in bodies.iter() {
+        // Bodies like robots never get hungry
+        if variant.is_some_and(|v| !v.has_metabolism()) {
+            continue;
+        }
+        commands.entity(entity).insert(Metabolism::default());
+    }
+}
+
+fn tick_metabolism(
+    mut bodies: Query<(&Body, &mut Metabolism)>,
+    mut body_parts: Query<(&mut OrganicBodyPart, Has<OrganicBrain>)>,
+    config: Res<MetabolismConfig>,
+) {
+    for (body, mut metabolism) in bodies.iter_mut() {
+        // Dead bodies don't get any hungrier
+        let dead = body_parts
+            .iter_many(&body.limbs)
+            .any(|(part, brain)| brain && part.unusable());
+        if dead {
+            continue;
+        }
+
+        metabolism.tick(METABOLISM_INTERVAL, &config);
+        if !metabolism.is_starving() {
+            continue;
+        }
+
+        let damage = config.starving_damage_per_minute * METABOLISM_INTERVAL / 60.0;
+        let mut parts = body_parts.iter_many_mut(&body.limbs);
+        while let Some((mut part, _)) = parts.fetch_next() {
+            part.damage(damage);
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn starving_speed(
+    mut bodies: Query<
+        (&Metabolism, &mut SpeedModifiers),
+        Or<(Changed<Metabolism>, Added<SpeedModifiers>)>,
+    >,
+) {
+    for (metabolism, mut modifiers) in bodies.iter_mut() {
+        let multiplier = if metabolism.is_starving() {
+            STARVING_SPEED_MULTIPLIER
+        } else {
+            1.0
+        };
+        modifiers.set_multiplier("starving", multiplier);
+    }
+}
+
+/// Server message with how hungry and thirsty the controlled creature is.
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq)]
+struct MetabolismMessage {
+    hunger: SatiationTier,
+    thirst: SatiationTier,
+}
+
+/// Sends every player the tiers of the creature they control whenever they change.
+fn send_metabolism(
+    players: Res<Players>,
+    controls: Res<ClientControls>,
+    bodies: Query<&Metabolism>,
+    mut sent: Local<HashMap<ConnectionId, MetabolismMessage>>,
+    mut sender: MessageSender,
+) {
+    let mut current = HashMap::default();
+    for (&connection, player) in players.players().iter() {
+        let message = controls
+            .controlled_entity(player.id)
+            .and_then(|entity| bodies.get(entity).ok())
+            .map(|metabolism| MetabolismMessage {
+                hunger: metabolism.hunger_tier(),
+                thirst: metabolism.thirst_tier(),
+            })
+            .unwrap_or_default();
+        current.insert(connection, message);
+    }
+
+    for (&connection, message) in current.iter() {
+        let last = sent.get(&connection);
+        if last == Some(message) || (last.is_none() && message == &MetabolismMessage::default()) {
+            continue;
+        }
+        sender.send(message, MessageReceivers::Single(connection));
+    }
+    *sent = current;
+}
+
+/// How hungry and thirsty the creature this client controls is.
+#[cfg(feature = "client")]
+#[derive(Resource, Default)]
+struct OwnMetabolism {
+    hunger: SatiationTier,
+    thirst: SatiationTier,
+}
+
+#[cfg(feature = "client")]
+fn receive_metabolism(
+    mut messages: EventReader<MessageEvent<MetabolismMessage>>,
+    mut own: ResMut<OwnMetabolism>,
+) {
+    if let Some(event) = messages.iter().last() {
+        own.hunger = event.message.hunger;
+        own.thirst = event.message.thirst;
+    }
+}
+
+#[cfg(feature = "client")]
+fn metabolism_ui(mut contexts: EguiContexts, own: Res<OwnMetabolism>) {
+    let hunger = match own.hunger {
+        SatiationTier::Hungry => Some("HUNGRY"),
+        SatiationTier::Starving => Some("STARVING"),
+        _ => None,
+    };
+    let thirst = match own.thirst {
+        SatiationTier::Hungry => Some("THIRSTY"),
+        SatiationTier::Starving => Some("DEHYDRATED"),
+        _ => None,
+    };
+    if hunger.is_none() && thirst.is_none() {
+        return;
+    }
+
+    egui::Area::new("metabolism_indicator")
+        .anchor(egui::Align2::CENTER_BOTTOM, egui::vec2(0.0, -135.0))
+        .show(contexts.ctx_mut(), |ui| {
+            ui.horizontal(|ui| {
+                for label in [hunger, thirst].into_iter().flatten() {
+                    ui.label(
+                        egui::RichText::new(label)
+                            .color(egui::Rgba::from_rgb(0.9, 0.75, 0.3))
+                            .size(16.0),
+                    );
+                }
+            });
+        });
+}
+
+/// Food or drink that can be eaten, one bite at a time.
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct Nutrition {
+    /// Hunger restored per bite
+    pub hunger_restore: f32,
+    /// Thirst restored per bite
+    pub thirst_restore: f32,
+    /// Seconds a bite takes
+    pub eat_time: f32,
+    /// Bites left before the item is used up
+    pub bites: u32,
+}
+
+impl Default for Nutrition {
+    fn default() -> Self {
+        Self {
+            hunger_restore: 0.0,
+            thirst_restore: 0.0,
+            eat_time: 2.0,
+            bites: 1,
+        }
+    }
+}
+
+impl Nutrition {
+    fn is_drink(&self) -> bool {
+        self.thirst_restore > self.hunger_restore
+    }
+}
+
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+#[component(storage = "SparseSet")]
+struct EatInteraction {
+    item: Entity,
+}
+
+impl FromWorld for EatInteraction {
+    fn from_world(_: &mut World) -> Self {
+        // Dummy default for Reflect
+        Self {
+            item: Entity::PLACEHOLDER,
+        }
+    }
+}
+
+/// Other players can only be fed if they aren't fighting back or are held tightly.
+fn accepts_feeding(
+    target: Entity,
+    combat_modes: &Query<&CombatMode>,
+    grabbed: &Query<&GrabbedBy>,
+) -> bool {
+    let willing = combat_modes.get(target).map_or(true, |m| !m.is_enabled());
+    let restrained = grabbed.get(target).is_ok_and(|g| g.restrains_hands());
+    willing || restrained
+}
+
+fn prepare_eat_interaction(
+    interaction_list: Res<InteractionListEvents>,
+    food: Query<&Nutrition>,
+    bodies: Query<(), With<Metabolism>>,
+    combat_modes: Query<&CombatMode>,
+    grabbed: Query<&GrabbedBy>,
+    reach: Reach,
+) {
+    for event in interaction_list.events.iter() {
+        let Some(item) = event.item_in_hand else {
+            continue;
+        };
+        let Ok(nutrition) = food.get(item) else {
+            continue;
+        };
+        if !bodies.contains(event.target) {
+            continue;
+        }
+
+        let text = if event.target == event.source {
+            if nutrition.is_drink() {
+                "Drink"
+            } else {
+                "Eat"
+            }
+        } else {
+            if !reach.can_reach(event.source, event.target)
+                || !accepts_feeding(event.target, &combat_modes, &grabbed)
+            {
+                continue;
+            }
+            if nutrition.is_drink() {
+                "Give drink"
+            } else {
+                "Feed"
+            }
+        };
+
+        event.add_interaction(InteractionOption {
+            text: text.into(),
+            interaction: Box::new(EatInteraction { item }),
+            specificity: InteractionSpecificity::Specific,
+        });
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn eat_interaction(
+    mut query: Query<(Entity, &EatInteraction, &mut ActiveInteraction)>,
+    mut food: Query<&mut Nutrition>,
+    mut bodies: Query<&mut Metabolism>,
+    combat_modes: Query<&CombatMode>,
+    grabbed: Query<&GrabbedBy>,
+    reach: Reach,
+    time: Res<Time>,
+    mut commands: Commands,
+) {
+    for (entity, interaction, mut active) in query.iter_mut() {
+        let Ok(mut nutrition) = food.get_mut(interaction.item) else {
+            active.status = InteractionStatus::Canceled;
+            continue;
+        };
+
+        active.set_initial_duration(Duration::from_secs_f32(nutrition.eat_time));
+        if active.start_time() + nutrition.eat_time > time.elapsed_seconds() {
+            continue;
+        }
+
+        // The other player could have walked away or started resisting in the meantime
+        let target = active.target;
+        if target != entity
+            && (!reach.can_reach(entity, target)
+                || !accepts_feeding(target, &combat_modes, &grabbed))
+        {
+            active.status = InteractionStatus::Canceled;
+            continue;
+        }
+        let Ok(mut metabolism) = bodies.get_mut(target) else {
+            active.status = InteractionStatus::Canceled;
+            continue;
+        };
+
+        metabolism.restore(nutrition.hunger_restore, nutrition.thirst_restore);
+        nutrition.bites = nutrition.bites.saturating_sub(1);
+        if nutrition.bites == 0 {
+            commands.entity(interaction.item).despawn_recursive();
+        }
+        active.status = InteractionStatus::Completed;
+    }
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use bevy::time::TimeUpdateStrategy;
+    use utils::task::Tasks;
+
+    use super::*;
+    use crate::{interaction::ExecuteInteraction, testing::server_app};
+
+    const FRAME: Duration = Duration::from_millis(100);
+
+    fn update(app: &mut App, frames: u32) {
+        for _ in 0..frames {
+            app.update();
+        }
+    }
+
+    fn app() -> App {
+        let mut app = server_app(ServerConfig::default());
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(FRAME));
+        app
+    }
+
+    /// A body at `position` that is starving.
+    fn starving_body(app: &mut App, position: Vec3) -> Entity {
+        let body = app
+            .world
+            .spawn((
+                Body::default(),
+                SpatialBundle::from_transform(Transform::from_translation(position)),
+            ))
+            .id();
+        update(app, 1);
+        *app.world.get_mut::<Metabolism>(body).unwrap() = Metabolism {
+            hunger: 5.0,
+            thirst: 80.0,
+        };
+        update(app, 1);
+        body
+    }
+
+    fn food(app: &mut App) -> Entity {
+        app.world
+            .spawn(Nutrition {
+                hunger_restore: 40.0,
+                thirst_restore: 0.0,
+                eat_time: 0.5,
+                bites: 1,
+            })
+            .id()
+    }
+
+    fn eat(app: &mut App, entity: Entity, target: Entity, item: Entity) {
+        app.world
+            .resource_mut::<Tasks<ExecuteInteraction>>()
+            .create_ignore(ExecuteInteraction {
+                entity,
+                target,
+                interaction: Box::new(EatInteraction { item }),
+            });
+        update(app, 10);
+        assert!(app.world.get::<ActiveInteraction>(entity).is_none());
+    }
+
+    fn is_slowed(app: &App, body: Entity) -> bool {
+        app.world
+            .get::<SpeedModifiers>(body)
+            .unwrap()
+            .iter()
+            .any(|(name, _)| name == "starving")
+    }
+
+    #[test]
+    fn tick_uses_up_food_and_water_per_minute() {
+        let config = MetabolismConfig::default();
+        let mut metabolism = Metabolism::default();
+        metabolism.tick(30.0, &config);
+        assert_eq!(metabolism.hunger, 99.5);
+        assert_eq!(metabolism.thirst, 99.25);
+
+        // 10 minutes of thirst are left, but the tiers only tell players roughly
+        metabolism.hunger = 60.0;
+        metabolism.thirst = 15.0;
+        assert_eq!(metabolism.hunger_tier(), SatiationTier::Fed);
+        assert_eq!(metabolism.thirst_tier(), SatiationTier::Hungry);
+        assert!(!metabolism.is_starving());
+
+        metabolism.tick(240.0, &config);
+        assert_eq!(metabolism.hunger, 56.0);
+        assert_eq!(metabolism.thirst, 9.0);
+        assert!(metabolism.is_starving());
+
+        metabolism.tick(3600.0, &config);
+        assert_eq!(metabolism.hunger, 0.0);
+        assert_eq!(metabolism.thirst, 0.0);
+    }
+
+    #[test]
+    fn restoring_stops_at_full() {
+        let mut metabolism = Metabolism {
+            hunger: 90.0,
+            thirst: 20.0,
+        };
+        metabolism.restore(30.0, 30.0);
+        assert_eq!(metabolism.hunger, MAX_SATIATION);
+        assert_eq!(metabolism.thirst, 50.0);
+    }
+
+    #[test]
+    fn eating_while_starving_clears_the_status() {
+        let mut app = app();
+        let body = starving_body(&mut app, Vec3::ZERO);
+        assert!(app.world.get::<Metabolism>(body).unwrap().is_starving());
+        assert!(is_slowed(&app, body));
+
+        let item = food(&mut app);
+        eat(&mut app, body, body, item);
+
+        let metabolism = app.world.get::<Metabolism>(body).unwrap();
+        assert!(!metabolism.is_starving());
+        assert_eq!(metabolism.hunger_tier(), SatiationTier::Fed);
+        assert!(!is_slowed(&app, body));
+        assert!(app.world.get_entity(item).is_none());
+    }
+
+    #[test]
+    fn feeding_someone_else_needs_them_close() {
+        let mut app = app();
+        let feeder = starving_body(&mut app, Vec3::ZERO);
+        let near = starving_body(&mut app, Vec3::new(1.0, 0.0, 0.0));
+        let far = starving_body(&mut app, Vec3::new(5.0, 0.0, 0.0));
+
+        let item = food(&mut app);
+        eat(&mut app, feeder, far, item);
+        assert!(app.world.get::<Metabolism>(far).unwrap().is_starving());
+        assert!(app.world.get_entity(item).is_some());
+
+        eat(&mut app, feeder, near, item);
+        assert!(!app.world.get::<Metabolism>(near).unwrap().is_starving());
+        assert!(app.world.get::<Metabolism>(feeder).unwrap().is_starving());
+        assert!(app.world.get_entity(item).is_none());
+    }
+}
@@ -1,10 +1,9 @@
 use bevy::{prelude::*, reflect::TypeUuid};
-use bevy_egui::{egui, EguiContexts};
 use networking::{
     component::AppExt as ComponentExt,
     identity::{NetworkIdentities, NetworkIdentity},
     is_server,
-    messaging::{AppExt, MessageEvent, MessageReceivers, MessageSender},
+    messaging::{AppExt, MessageReceivers, MessageSender},
     spawning::ClientControls,
     variable::{NetworkVar, ServerVar},
     Networked, Players,
@@ -17,7 +16,13 @@ use crate::{
         ActiveInteraction, GenerateInteractionList, InteractionListEvents, InteractionOption,
         InteractionSpecificity, InteractionStatus,
     },
-    ui::has_window,
+};
+
+#[cfg(feature = "client")]
+use {
+    crate::ui::has_window,
+    bevy_egui::{egui, EguiContexts},
+    networking::messaging::MessageEvent,
 };
 
 use super::{OrganicBody, OrganicBodyPart, OrganicBrain, OrganicHeart, MAX_BLOOD_OXYGEN};
@@ -28,7 +33,7 @@ impl Plugin for HealthScannerPlugin {
     fn build(&self, app: &mut App) {
         app.register_type::<HealthScanner>()
             .add_networked_component::<HealthScanner, HealthScannerClient>()
-            .add_network_message::<OpenHealthScannerMessage>();
+            .add_network_message::<OpenHealthScannerMessage>("OpenHealthScannerMessage");
         if is_server(app) {
             app.register_type::<HealthScanInteraction>().add_systems(
                 Update,
@@ -39,6 +44,7 @@ impl Plugin for HealthScannerPlugin {
                 ),
             );
         } else {
+            #[cfg(feature = "client")]
             app.add_systems(Update, health_scanner_ui.run_if(has_window));
         }
     }
@@ -65,7 +71,7 @@ pub(crate) struct HealthScannerClient {
     open: bool,
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 struct Vitals {
     bpm: u32,
     blood: f32,
@@ -127,6 +133,7 @@ fn collect_vitals(
     }
 }
 
+#[cfg(feature = "client")]
 fn health_scanner_ui(
     mut contexts: EguiContexts,
     mut scanners: Query<(Entity, &mut HealthScannerClient)>,
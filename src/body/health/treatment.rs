@@ -0,0 +1,958 @@
+use std::time::Duration;
+
+use bevy::{ecs::query::Has, prelude::*, reflect::TypeUuid, utils::HashMap};
+use networking::{
+    component::AppExt as ComponentAppExt,
+    is_server,
+    messaging::{AppExt, MessageEvent, MessageReceivers, MessageSender},
+    spawning::ClientControls,
+    variable::{NetworkVar, ServerVar},
+    ConnectionId, Networked, Players,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    body::{variant::BodyVariant, Body},
+    combat::GrabbedBy,
+    communication::{EmoteEvent, SpeechName, SystemMessageEvent},
+    interaction::{
+        ActiveInteraction, GenerateInteractionList, InteractionListEvents, InteractionOption,
+        InteractionSpecificity, InteractionStatus, Reach,
+    },
+    items::Item,
+    movement::speed::SpeedModifiers,
+};
+
+#[cfg(feature = "client")]
+use {
+    crate::ui::has_window,
+    bevy_egui::{egui, EguiContexts},
+};
+
+use super::{LacerationSize, OrganicLaceration, VitalStatus, Vitals};
+
+/// Splints for wounded legs and sutures for bleeding wounds, applied to the limb that was clicked.
+pub struct TreatmentPlugin;
+
+impl Plugin for TreatmentPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Leg>()
+            .register_type::<Splint>()
+            .register_type::<Sutures>()
+            .add_networked_component::<Limp, LimpClient>()
+            .add_network_message::<ConsentRequestMessage>("ConsentRequestMessage")
+            .add_network_message::<ConsentResponseMessage>("ConsentResponseMessage");
+
+        if is_server(app) {
+            app.init_resource::<ConsentRequests>()
+                .register_type::<TreatLimbInteraction>()
+                .register_type::<ExamineBodyInteraction>()
+                .add_systems(
+                    Update,
+                    (
+                        prepare_treatment_interaction.in_set(GenerateInteractionList),
+                        prepare_examine_interaction.in_set(GenerateInteractionList),
+                        receive_consent_responses,
+                        treat_limb_interaction,
+                        examine_body_interaction,
+                        loosen_splints,
+                        (add_limp, update_limp, limp_speed).chain(),
+                    ),
+                );
+        } else {
+            #[cfg(feature = "client")]
+            app.init_resource::<ConsentPrompts>().add_systems(
+                Update,
+                (receive_consent_requests, consent_ui.run_if(has_window)).chain(),
+            );
+        }
+    }
+}
+
+/// Seconds the patient has to answer before it counts as declining
+const CONSENT_TIMEOUT: f32 = 15.0;
+/// Wounds this size or bigger knock a splint loose
+const SPLINT_BREAK_SIZE: LacerationSize = LacerationSize::Medium;
+/// Movement speed for each wounded leg that isn't splinted
+const LIMP_SPEED_MULTIPLIER: f32 = 0.7;
+
+/// A leg, which slows its creature down while wounded unless it is splinted.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct Leg;
+
+/// Holds a wounded leg in place, so walking on it isn't slowed down.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct Splint;
+
+/// Stitches a limb's wounds shut, so they stop bleeding.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct Sutures;
+
+/// A leg that was splinted. It stays wounded, but the creature walks normally.
+#[derive(Component)]
+pub struct Splinted;
+
+/// A wound that was stitched shut and doesn't bleed.
+#[derive(Component)]
+pub struct Sutured;
+
+#[derive(Reflect, Default, Clone, Copy, PartialEq, Eq, Debug)]
+enum Treatment {
+    #[default]
+    Splint,
+    Suture,
+}
+
+impl Treatment {
+    fn verb(self) -> &'static str {
+        match self {
+            Treatment::Splint => "splint",
+            Treatment::Suture => "stitch up",
+        }
+    }
+
+    /// Shown in the interaction list
+    fn label(self) -> &'static str {
+        match self {
+            Treatment::Splint => "Splint",
+            Treatment::Suture => "Stitch up",
+        }
+    }
+
+    /// Shown in chat once done
+    fn action(self) -> &'static str {
+        match self {
+            Treatment::Splint => "splints",
+            Treatment::Suture => "stitches up",
+        }
+    }
+
+    fn duration(self) -> Duration {
+        match self {
+            Treatment::Splint => Duration::from_secs(4),
+            Treatment::Suture => Duration::from_secs(6),
+        }
+    }
+}
+
+/// How a limb is called in messages, like "human leg left"
+fn limb_name(items: &Query<&Item>, limb: Entity) -> String {
+    items
+        .get(limb)
+        .map(|item| item.name.to_lowercase())
+        .unwrap_or_else(|_| "limb".into())
+}
+
+/// Patients that are out cold or held tightly are treated without asking.
+fn needs_consent(patient: Entity, vitals: &Vitals, grabbed: &Query<&GrabbedBy>) -> bool {
+    let helpless = matches!(
+        vitals.status(patient),
+        Some(VitalStatus::Unconscious | VitalStatus::Dead)
+    );
+    let restrained = grabbed.get(patient).is_ok_and(|g| g.restrains_hands());
+    !helpless && !restrained
+}
+
+/// Server message asking a player to allow a treatment.
+#[derive(Serialize, Deserialize, Clone)]
+struct ConsentRequestMessage {
+    id: u32,
+    text: String,
+}
+
+/// Client answer to a [`ConsentRequestMessage`].
+#[derive(Serialize, Deserialize)]
+struct ConsentResponseMessage {
+    id: u32,
+    accept: bool,
+}
+
+struct PendingConsent {
+    patient: ConnectionId,
+    asked_at: f32,
+    answer: Option<bool>,
+}
+
+/// Treatments waiting on the patient to agree.
+#[derive(Resource, Default)]
+struct ConsentRequests {
+    next_id: u32,
+    pending: HashMap<u32, PendingConsent>,
+}
+
+enum ConsentAnswer {
+    Waiting,
+    Accepted,
+    Declined,
+}
+
+impl ConsentRequests {
+    fn ask(&mut self, patient: ConnectionId, now: f32) -> u32 {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        self.pending.insert(
+            id,
+            PendingConsent {
+                patient,
+                asked_at: now,
+                answer: None,
+            },
+        );
+        id
+    }
+
+    /// The answer to a request. Requests that ran out of time count as declined.
+    fn answer(&mut self, id: u32, now: f32) -> ConsentAnswer {
+        let answer = match self.pending.get(&id) {
+            Some(PendingConsent {
+                answer: Some(true), ..
+            }) => ConsentAnswer::Accepted,
+            Some(PendingConsent {
+                answer: None,
+                asked_at,
+                ..
+            }) if now - asked_at < CONSENT_TIMEOUT => return ConsentAnswer::Waiting,
+            _ => ConsentAnswer::Declined,
+        };
+        self.pending.remove(&id);
+        answer
+    }
+}
+
+fn receive_consent_responses(
+    mut messages: EventReader<MessageEvent<ConsentResponseMessage>>,
+    mut requests: ResMut<ConsentRequests>,
+) {
+    for event in messages.iter() {
+        // Only the patient that was asked can answer
+        if let Some(pending) = requests
+            .pending
+            .get_mut(&event.message.id)
+            .filter(|p| p.patient == event.connection)
+        {
+            pending.answer = Some(event.message.accept);
+        }
+    }
+}
+
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+#[component(storage = "SparseSet")]
+struct TreatLimbInteraction {
+    item: Entity,
+    limb: Entity,
+    treatment: Treatment,
+    /// Request sent to the patient, if it had to be asked
+    consent: Option<u32>,
+    consented: bool,
+    /// When the treatment itself started, which is after the patient agreed
+    started: Option<f32>,
+}
+
+impl FromWorld for TreatLimbInteraction {
+    fn from_world(_: &mut World) -> Self {
+        // Dummy default for Reflect
+        Self {
+            item: Entity::PLACEHOLDER,
+            limb: Entity::PLACEHOLDER,
+            treatment: Treatment::Splint,
+            consent: None,
+            consented: false,
+            started: None,
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn prepare_treatment_interaction(
+    interaction_list: Res<InteractionListEvents>,
+    splints: Query<(), With<Splint>>,
+    sutures: Query<(), With<Sutures>>,
+    bodies: Query<&Body>,
+    transforms: Query<&GlobalTransform>,
+    items: Query<&Item>,
+    legs: Query<(), With<Leg>>,
+    splinted: Query<(), With<Splinted>>,
+    children: Query<&Children>,
+    lacerations: Query<Has<Sutured>, With<OrganicLaceration>>,
+    reach: Reach,
+) {
+    // If the treatment can help a limb right now
+    let applies = |treatment: Treatment, limb: Entity| {
+        let mut wounds = children
+            .get(limb)
+            .into_iter()
+            .flat_map(|c| c.iter())
+            .filter_map(|&child| lacerations.get(child).ok());
+        match treatment {
+            Treatment::Splint => {
+                legs.contains(limb) && !splinted.contains(limb) && wounds.next().is_some()
+            }
+            Treatment::Suture => wounds.any(|sutured| !sutured),
+        }
+    };
+
+    for event in interaction_list.events.iter() {
+        let Some(item) = event.item_in_hand else {
+            continue;
+        };
+        let treatment = if splints.contains(item) {
+            Treatment::Splint
+        } else if sutures.contains(item) {
+            Treatment::Suture
+        } else {
+            continue;
+        };
+        let Ok(body) = bodies.get(event.target) else {
+            continue;
+        };
+        if event.target != event.source && !reach.can_reach(event.source, event.target) {
+            continue;
+        }
+
+        // The limb closest to where the body was clicked, so players can pick which leg to treat
+        let distance = |limb: Entity| {
+            event
+                .point
+                .zip(transforms.get(limb).ok())
+                .map_or(0.0, |(point, t)| t.translation().distance_squared(point))
+        };
+        let Some(limb) = body
+            .limbs
+            .iter()
+            .copied()
+            .filter(|&limb| applies(treatment, limb))
+            .min_by(|&a, &b| distance(a).total_cmp(&distance(b)))
+        else {
+            continue;
+        };
+
+        event.add_interaction(InteractionOption {
+            text: format!("{} {}", treatment.label(), limb_name(&items, limb)),
+            interaction: Box::new(TreatLimbInteraction {
+                item,
+                limb,
+                treatment,
+                consent: None,
+                consented: false,
+                started: None,
+            }),
+            specificity: InteractionSpecificity::Specific,
+        });
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn treat_limb_interaction(
+    mut query: Query<(Entity, &mut TreatLimbInteraction, &mut ActiveInteraction)>,
+    bodies: Query<&Body>,
+    items: Query<&Item>,
+    names: Query<&SpeechName>,
+    children: Query<&Children>,
+    lacerations: Query<Entity, (With<OrganicLaceration>, Without<Sutured>)>,
+    grabbed: Query<&GrabbedBy>,
+    vitals: Vitals,
+    reach: Reach,
+    controls: Res<ClientControls>,
+    players: Res<Players>,
+    mut requests: ResMut<ConsentRequests>,
+    time: Res<Time>,
+    mut sender: MessageSender,
+    mut emotes: EventWriter<EmoteEvent>,
+    mut messages: EventWriter<SystemMessageEvent>,
+    mut commands: Commands,
+) {
+    let now = time.elapsed_seconds();
+    // Requests of treatments that were canceled are never answered
+    requests
+        .pending
+        .retain(|_, pending| now - pending.asked_at < CONSENT_TIMEOUT * 2.0);
+    let connection_of = |entity: Entity| {
+        controls
+            .controlling_player(entity)
+            .and_then(|p| players.get_connection(&p))
+    };
+
+    for (helper, mut interaction, mut active) in query.iter_mut() {
+        let patient = active.target;
+        // Either side could have walked away or the limb could have come off
+        let attached = bodies
+            .get(patient)
+            .is_ok_and(|body| body.limbs.contains(&interaction.limb));
+        if !items.contains(interaction.item)
+            || !attached
+            || (helper != patient && !reach.can_reach(helper, patient))
+        {
+            active.status = InteractionStatus::Canceled;
+            continue;
+        }
+
+        if !interaction.consented {
+            let patient_connection = connection_of(patient);
+            match (interaction.consent, patient_connection) {
+                _ if helper == patient || !needs_consent(patient, &vitals, &grabbed) => {
+                    interaction.consented = true;
+                }
+                // Nobody controls the patient, so nobody can object
+                (None, None) => interaction.consented = true,
+                (None, Some(connection)) => {
+                    let id = requests.ask(connection, now);
+                    interaction.consent = Some(id);
+                    let helper_name = names.get(helper).map_or("Someone", |name| name.0.as_str());
+                    let text = format!(
+                        "{} wants to {} your {}.",
+                        helper_name,
+                        interaction.treatment.verb(),
+                        limb_name(&items, interaction.limb)
+                    );
+                    sender.send(
+                        &ConsentRequestMessage { id, text },
+                        MessageReceivers::Single(connection),
+                    );
+                }
+                (Some(id), _) => match requests.answer(id, now) {
+                    ConsentAnswer::Waiting => {}
+                    ConsentAnswer::Accepted => interaction.consented = true,
+                    ConsentAnswer::Declined => {
+                        active.status = InteractionStatus::Canceled;
+                        if let Some(connection) = connection_of(helper) {
+                            messages.send(SystemMessageEvent {
+                                receiver: connection,
+                                text: "The patient didn't agree to the treatment.".into(),
+                            });
+                        }
+                    }
+                },
+            }
+            if !interaction.consented {
+                continue;
+            }
+        }
+
+        let duration = interaction.treatment.duration();
+        let started = *interaction.started.get_or_insert(now);
+        active.set_initial_duration(duration);
+        if started + duration.as_secs_f32() > now {
+            continue;
+        }
+
+        match interaction.treatment {
+            Treatment::Splint => {
+                commands.entity(interaction.limb).insert(Splinted);
+            }
+            Treatment::Suture => {
+                let wounds = children
+                    .get(interaction.limb)
+                    .into_iter()
+                    .flat_map(|c| c.iter())
+                    .filter_map(|&child| lacerations.get(child).ok());
+                for wound in wounds {
+                    commands.entity(wound).insert(Sutured);
+                }
+            }
+        }
+        commands.entity(interaction.item).despawn_recursive();
+        emotes.send(EmoteEvent {
+            actor: helper,
+            target: Some(patient),
+            text: format!(
+                "{} {{target}}'s {}.",
+                interaction.treatment.action(),
+                limb_name(&items, interaction.limb)
+            ),
+        });
+        active.status = InteractionStatus::Completed;
+    }
+}
+
+/// Big new wounds on a splinted leg knock the splint loose.
+fn loosen_splints(
+    new_wounds: Query<(&OrganicLaceration, &Parent), Added<OrganicLaceration>>,
+    splinted: Query<(), With<Splinted>>,
+    mut commands: Commands,
+) {
+    for (laceration, parent) in new_wounds.iter() {
+        let limb = parent.get();
+        if laceration.size >= SPLINT_BREAK_SIZE && splinted.contains(limb) {
+            debug!(?limb, "Splint knocked loose by a {} wound", laceration.size);
+            commands.entity(limb).remove::<Splinted>();
+        }
+    }
+}
+
+/// How many legs of a creature are wounded and not splinted.
+#[derive(Component, Networked)]
+#[networked(client = "LimpClient")]
+pub struct Limp {
+    legs: NetworkVar<u8>,
+}
+
+#[derive(Component, Default, TypeUuid, Networked)]
+#[uuid = "03684d04-6c0b-4174-bd01-d3b43789cbde"]
+#[networked(server = "Limp")]
+pub struct LimpClient {
+    legs: ServerVar<u8>,
+}
+
+fn add_limp(bodies: Query<Entity, Added<Body>>, mut commands: Commands) {
+    for entity in bodies.iter() {
+        commands.entity(entity).insert(Limp {
+            legs: Default::default(),
+        });
+    }
+}
+
+/// Recounts the wounded legs of every body when a wound or splint came or went.
+#[allow(clippy::type_complexity)]
+fn update_limp(
+    changed: Query<(), Or<(Added<OrganicLaceration>, Added<Splinted>, Added<Limp>)>>,
+    mut removed_wounds: RemovedComponents<OrganicLaceration>,
+    mut removed_splints: RemovedComponents<Splinted>,
+    mut bodies: Query<(&Body, &mut Limp)>,
+    legs: Query<Option<&Children>, (With<Leg>, Without<Splinted>)>,
+    lacerations: Query<(), With<OrganicLaceration>>,
+) {
+    let any_removed = removed_wounds.iter().count() + removed_splints.iter().count() > 0;
+    if changed.is_empty() && !any_removed {
+        return;
+    }
+
+    for (body, mut limp) in bodies.iter_mut() {
+        let wounded = legs
+            .iter_many(&body.limbs)
+            .filter(|children| {
+                children
+                    .into_iter()
+                    .flat_map(|c| c.iter())
+                    .any(|&child| lacerations.contains(child))
+            })
+            .count() as u8;
+        if *limp.legs != wounded {
+            *limp.legs = wounded;
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn limp_speed(
+    mut bodies: Query<(&Limp, &mut SpeedModifiers), Or<(Changed<Limp>, Added<SpeedModifiers>)>>,
+) {
+    for (limp, mut modifiers) in bodies.iter_mut() {
+        modifiers.set_multiplier("limp", LIMP_SPEED_MULTIPLIER.powi(*limp.legs as i32));
+    }
+}
+
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+#[component(storage = "SparseSet")]
+struct ExamineBodyInteraction {
+    user: Entity,
+}
+
+impl FromWorld for ExamineBodyInteraction {
+    fn from_world(_: &mut World) -> Self {
+        // Dummy default for Reflect
+        Self {
+            user: Entity::PLACEHOLDER,
+        }
+    }
+}
+
+fn prepare_examine_interaction(
+    interaction_list: Res<InteractionListEvents>,
+    bodies: Query<(), With<Body>>,
+) {
+    for event in interaction_list.events.iter() {
+        if !bodies.contains(event.target) {
+            continue;
+        }
+        event.add_interaction(InteractionOption {
+            text: "Examine".into(),
+            interaction: Box::new(ExamineBodyInteraction { user: event.source }),
+            specificity: InteractionSpecificity::Generic,
+        });
+    }
+}
+
+/// Describes the wounds and treatments that can be seen on a body.
+#[allow(clippy::too_many_arguments)]
+fn examine_body_interaction(
+    mut query: Query<(&ExamineBodyInteraction, &mut ActiveInteraction)>,
+    bodies: Query<(&Body, Option<&BodyVariant>)>,
+    items: Query<&Item>,
+    children: Query<&Children>,
+    splinted: Query<(), With<Splinted>>,
+    lacerations: Query<Has<Sutured>, With<OrganicLaceration>>,
+    controls: Res<ClientControls>,
+    players: Res<Players>,
+    mut messages: EventWriter<SystemMessageEvent>,
+) {
+    for (interaction, mut active) in query.iter_mut() {
+        let Ok((body, variant)) = bodies.get(active.target) else {
+            active.status = InteractionStatus::Canceled;
+            continue;
+        };
+        active.status = InteractionStatus::Completed;
+
+        let mut limbs: Vec<_> = body.limbs.iter().copied().collect();
+        limbs.sort_unstable_by_key(|&limb| limb_name(&items, limb));
+        let mut lines = Vec::new();
+        for limb in limbs {
+            let name = limb_name(&items, limb);
+            let (mut open, mut stitched) = (0, 0);
+            for sutured in children
+                .get(limb)
+                .into_iter()
+                .flat_map(|c| c.iter())
+                .filter_map(|&child| lacerations.get(child).ok())
+            {
+                if sutured {
+                    stitched += 1;
+                } else {
+                    open += 1;
+                }
+            }
+            if open > 0 {
+                lines.push(format!("The {} has {} open wound(s).", name, open));
+            }
+            if stitched > 0 {
+                lines.push(format!("The {} has {} stitched wound(s).", name, stitched));
+            }
+            if splinted.contains(limb) {
+                lines.push(format!("The {} is splinted.", name));
+            }
+        }
+        if lines.is_empty() {
+            lines.push("You see no injuries.".into());
+        }
+        if let Some(variant) = variant {
+            lines.insert(0, format!("Body type: {}", variant.name()));
+        }
+
+        let Some(connection) = controls
+            .controlling_player(interaction.user)
+            .and_then(|p| players.get_connection(&p))
+        else {
+            continue;
+        };
+        messages.send(SystemMessageEvent {
+            receiver: connection,
+            text: lines.join("\n"),
+        });
+    }
+}
+
+/// Treatments this client was asked to allow, with the time they were received.
+#[cfg(feature = "client")]
+#[derive(Resource, Default)]
+struct ConsentPrompts(Vec<(ConsentRequestMessage, f32)>);
+
+#[cfg(feature = "client")]
+fn receive_consent_requests(
+    mut messages: EventReader<MessageEvent<ConsentRequestMessage>>,
+    mut prompts: ResMut<ConsentPrompts>,
+    time: Res<Time>,
+) {
+    for event in messages.iter() {
+        prompts
+            .0
+            .push((event.message.clone(), time.elapsed_seconds()));
+    }
+}
+
+#[cfg(feature = "client")]
+fn consent_ui(
+    mut contexts: EguiContexts,
+    mut prompts: ResMut<ConsentPrompts>,
+    time: Res<Time>,
+    mut sender: MessageSender,
+) {
+    let now = time.elapsed_seconds();
+    // The server takes no answer as a refusal
+    prompts
+        .0
+        .retain(|(_, received)| now - received < CONSENT_TIMEOUT);
+
+    let mut answered = Vec::new();
+    for (request, _) in prompts.0.iter() {
+        egui::Window::new("Treatment")
+            .id(egui::Id::new(("consent", request.id)))
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::vec2(0.0, -100.0))
+            .show(contexts.ctx_mut(), |ui| {
+                ui.label(&request.text);
+                ui.horizontal(|ui| {
+                    if ui.button("Accept").clicked() {
+                        answered.push((request.id, true));
+                    }
+                    if ui.button("Decline").clicked() {
+                        answered.push((request.id, false));
+                    }
+                });
+            });
+    }
+
+    for (id, accept) in answered {
+        sender.send_to_server(&ConsentResponseMessage { id, accept });
+        prompts.0.retain(|(request, _)| request.id != id);
+    }
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use bevy::{ecs::system::SystemState, time::TimeUpdateStrategy};
+    use networking::{loopback::LinkConditions, testing, NetworkRole};
+    use utils::task::Tasks;
+
+    use super::*;
+    use crate::{
+        body::health::OrganicBrain, config::ServerConfig, interaction::ExecuteInteraction,
+        movement::speed::SpeedModifier, testing::server_app,
+    };
+
+    const FRAME: Duration = Duration::from_millis(100);
+    /// Frames until a splint is on, once the patient agreed
+    const SPLINT_FRAMES: u32 = 43;
+
+    fn update(app: &mut App, frames: u32) {
+        for _ in 0..frames {
+            app.update();
+        }
+    }
+
+    /// A creature with two legs, returning the creature and its left leg.
+    fn creature(app: &mut App, extra_limbs: &[Entity]) -> (Entity, Entity) {
+        let left = app.world.spawn(Leg).id();
+        let right = app.world.spawn(Leg).id();
+        let limbs = [left, right].into_iter().chain(extra_limbs.iter().copied());
+        let creature = app
+            .world
+            .spawn((Body::with_limbs(limbs), SpatialBundle::default()))
+            .id();
+        (creature, left)
+    }
+
+    fn wound(app: &mut App, limb: Entity, size: LacerationSize) {
+        app.world.spawn(OrganicLaceration { size }).set_parent(limb);
+    }
+
+    fn limp(app: &App, creature: Entity) -> Option<SpeedModifier> {
+        app.world
+            .get::<SpeedModifiers>(creature)
+            .unwrap()
+            .iter()
+            .find_map(|(name, modifier)| (name == "limp").then_some(modifier))
+    }
+
+    /// Starts splinting the leg of the patient, returning the splint.
+    fn start_splint(app: &mut App, helper: Entity, patient: Entity, leg: Entity) -> Entity {
+        let item = app.world.spawn((Item::default(), Splint)).id();
+        app.world
+            .resource_mut::<Tasks<ExecuteInteraction>>()
+            .create_ignore(ExecuteInteraction {
+                entity: helper,
+                target: patient,
+                interaction: Box::new(TreatLimbInteraction {
+                    item,
+                    limb: leg,
+                    treatment: Treatment::Splint,
+                    consent: None,
+                    consented: false,
+                    started: None,
+                }),
+            });
+        item
+    }
+
+    #[test]
+    fn splint_removes_the_limp_until_the_leg_is_hurt_again() {
+        let mut app = server_app(ServerConfig::default());
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(FRAME));
+        let (creature, leg) = creature(&mut app, &[]);
+        update(&mut app, 1);
+        assert_eq!(limp(&app, creature), None);
+
+        wound(&mut app, leg, LacerationSize::Small);
+        update(&mut app, 2);
+        let limping = Some(SpeedModifier::Multiply(LIMP_SPEED_MULTIPLIER));
+        assert_eq!(limp(&app, creature), limping);
+
+        // Splinting your own leg doesn't need anyone's consent
+        let splint = start_splint(&mut app, creature, creature, leg);
+        update(&mut app, SPLINT_FRAMES);
+        assert!(app.world.get::<Splinted>(leg).is_some());
+        assert!(app.world.get_entity(splint).is_none());
+        assert_eq!(limp(&app, creature), None);
+
+        wound(&mut app, leg, LacerationSize::Small);
+        update(&mut app, 2);
+        assert!(app.world.get::<Splinted>(leg).is_some());
+        assert_eq!(limp(&app, creature), None);
+
+        wound(&mut app, leg, LacerationSize::Medium);
+        update(&mut app, 3);
+        assert!(app.world.get::<Splinted>(leg).is_none());
+        assert_eq!(limp(&app, creature), limping);
+    }
+
+    #[derive(Resource, Default)]
+    struct Prompts(Vec<ConsentRequestMessage>);
+
+    fn record_prompts(
+        mut messages: EventReader<MessageEvent<ConsentRequestMessage>>,
+        mut prompts: ResMut<Prompts>,
+    ) {
+        prompts
+            .0
+            .extend(messages.iter().map(|event| event.message.clone()));
+    }
+
+    struct Scene {
+        server: App,
+        client: App,
+        helper: Entity,
+        patient: Entity,
+        leg: Entity,
+    }
+
+    impl Scene {
+        fn update(&mut self, frames: u32) {
+            testing::update(&mut self.server, &mut [&mut self.client], frames);
+        }
+
+        fn prompts(&self) -> &[ConsentRequestMessage] {
+            &self.client.world.resource::<Prompts>().0
+        }
+
+        fn answer(&mut self, accept: bool) {
+            let id = self.prompts()[0].id;
+            let mut state = SystemState::<MessageSender>::new(&mut self.client.world);
+            state
+                .get_mut(&mut self.client.world)
+                .send_to_server(&ConsentResponseMessage { id, accept });
+        }
+
+        fn is_treating(&self) -> bool {
+            self.server
+                .world
+                .get::<ActiveInteraction>(self.helper)
+                .is_some()
+        }
+
+        fn is_splinted(&self) -> bool {
+            self.server.world.get::<Splinted>(self.leg).is_some()
+        }
+    }
+
+    /// A patient controlled by a connected player, being splinted by someone else.
+    fn splint_player(unconscious: bool) -> Scene {
+        let mut server = server_app(ServerConfig::default());
+        let mut client = testing::app(NetworkRole::Client);
+        client
+            .add_network_message::<ConsentRequestMessage>("ConsentRequestMessage")
+            .add_network_message::<ConsentResponseMessage>("ConsentResponseMessage")
+            .init_resource::<Prompts>()
+            .add_systems(Update, record_prompts);
+        let connector = testing::listen(&mut server);
+        testing::join(&mut client, &connector, LinkConditions::default());
+        testing::connect(&mut server, &mut [&mut client], 200);
+        server.insert_resource(TimeUpdateStrategy::ManualDuration(FRAME));
+
+        let brain = server
+            .world
+            .spawn(OrganicBrain {
+                unconcious: unconscious,
+                // Never thinks, so it stays in the state it starts in
+                last_think: f32::MAX,
+                ..Default::default()
+            })
+            .id();
+        let (patient, leg) = creature(&mut server, &[brain]);
+        let helper = server.world.spawn(SpatialBundle::default()).id();
+        let player = server
+            .world
+            .resource::<Players>()
+            .players()
+            .values()
+            .next()
+            .unwrap()
+            .id;
+        server
+            .world
+            .resource_mut::<ClientControls>()
+            .give_control(player, patient);
+        start_splint(&mut server, helper, patient, leg);
+
+        let mut scene = Scene {
+            server,
+            client,
+            helper,
+            patient,
+            leg,
+        };
+        scene.update(3);
+        scene
+    }
+
+    #[test]
+    fn accepted_treatment_goes_ahead() {
+        let mut scene = splint_player(false);
+        assert_eq!(scene.prompts().len(), 1);
+        assert_eq!(
+            scene.prompts()[0].text,
+            "Someone wants to splint your limb."
+        );
+        // Nothing happens while the patient thinks about it
+        scene.update(SPLINT_FRAMES);
+        assert!(scene.is_treating());
+        assert!(!scene.is_splinted());
+
+        scene.answer(true);
+        scene.update(SPLINT_FRAMES + 3);
+        assert!(!scene.is_treating());
+        assert!(scene.is_splinted());
+    }
+
+    #[test]
+    fn declined_treatment_is_canceled() {
+        let mut scene = splint_player(false);
+        scene.answer(false);
+        scene.update(3);
+        assert!(!scene.is_treating());
+
+        scene.update(SPLINT_FRAMES);
+        assert!(!scene.is_splinted());
+    }
+
+    #[test]
+    fn unanswered_request_times_out_as_declined() {
+        let mut scene = splint_player(false);
+        let timeout_frames = (CONSENT_TIMEOUT / FRAME.as_secs_f32()) as u32;
+        scene.update(timeout_frames - 10);
+        assert!(scene.is_treating());
+
+        scene.update(12);
+        assert!(!scene.is_treating());
+        assert!(!scene.is_splinted());
+        assert_eq!(scene.prompts().len(), 1);
+    }
+
+    #[test]
+    fn unconscious_patient_is_treated_without_asking() {
+        let mut scene = splint_player(true);
+        let patient = scene.patient;
+        let mut state = SystemState::<Vitals>::new(&mut scene.server.world);
+        assert_eq!(
+            state.get(&scene.server.world).status(patient),
+            Some(VitalStatus::Unconscious)
+        );
+
+        scene.update(SPLINT_FRAMES);
+        assert!(scene.is_splinted());
+        assert!(scene.prompts().is_empty());
+    }
+}
@@ -1,10 +1,9 @@
 use bevy::{prelude::*, reflect::TypeUuid, utils::HashMap};
-use bevy_egui::{egui, EguiContexts};
 use networking::{
     component::AppExt,
     identity::{EntityCommandsExt, NetworkIdentities, NetworkIdentity},
     is_server,
-    messaging::{AppExt as MessageAppExt, MessageEvent, MessageSender},
+    messaging::{AppExt as MessageAppExt, MessageEvent},
     spawning::ClientControls,
     variable::{NetworkVar, ServerVar},
     visibility::AlwaysVisible,
@@ -14,17 +13,29 @@ use serde::{Deserialize, Serialize};
 use utils::task::Tasks;
 
 use crate::{
-    body::{Body, ClientHeldItem, Limb},
+    body::{Body, Limb},
     interaction::{
         ActiveInteraction, ExecuteInteraction, GenerateInteractionList, InteractionListEvents,
         InteractionOption, InteractionSpecificity, InteractionStatus,
     },
     items::Item,
-    ui::{has_window, CloseUiMessage, NetworkUi},
+    ui::NetworkUi,
+};
+
+#[cfg(feature = "client")]
+use {
+    super::items::HealingItem,
+    crate::{
+        body::ClientHeldItem,
+        ui::{has_window, CloseUiMessage},
+    },
+    bevy_egui::{egui, EguiContexts},
+    networking::messaging::MessageSender,
 };
 
 use super::{
-    items::{ApplyMedicineInteraction, HealingItem},
+    items::ApplyMedicineInteraction,
+    treatment::{Splinted, Sutured},
     OrganicLaceration,
 };
 
@@ -33,7 +44,7 @@ pub struct HealthUiPlugin;
 impl Plugin for HealthUiPlugin {
     fn build(&self, app: &mut App) {
         app.add_networked_component::<HealthUi, HealthUiClient>()
-            .add_network_message::<ApplyMedicineMessage>();
+            .add_network_message::<ApplyMedicineMessage>("ApplyMedicineMessage");
         if is_server(app) {
             app.register_type::<InspectVitalsInteraction>().add_systems(
                 Update,
@@ -45,6 +56,7 @@ impl Plugin for HealthUiPlugin {
                 ),
             );
         } else {
+            #[cfg(feature = "client")]
             app.add_systems(Update, vitals_ui.run_if(has_window));
         }
     }
@@ -56,6 +68,8 @@ pub struct HealthUi {
     last_update: f32,
     target: NetworkVar<NetworkIdentity>,
     injuries: NetworkVar<HashMap<String, Vec<Injury>>>,
+    /// Names of the limbs that are splinted
+    splinted: NetworkVar<Vec<String>>,
 }
 
 #[derive(Component, Default, TypeUuid, Networked)]
@@ -64,12 +78,14 @@ pub struct HealthUi {
 pub(crate) struct HealthUiClient {
     target: ServerVar<NetworkIdentity>,
     injuries: ServerVar<HashMap<String, Vec<Injury>>>,
+    splinted: ServerVar<Vec<String>>,
 }
 
-#[derive(Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
 struct Injury {
     server_entity: Entity,
     name: String,
+    sutured: bool,
 }
 
 #[derive(Component, Reflect)]
@@ -125,6 +141,7 @@ fn vitals_interaction(
                     last_update: 0.0,
                     target: network_id.into(),
                     injuries: Default::default(),
+                    splinted: Default::default(),
                 },
                 AlwaysVisible::single(interaction.viewer),
             ))
@@ -138,9 +155,9 @@ const COLLECT_VITALS_INTERVAL: f32 = 0.1;
 fn collect_vitals(
     mut uis: Query<(Entity, &mut HealthUi)>,
     bodies: Query<&Body>,
-    limbs: Query<(&Children, &Item), With<Limb>>,
+    limbs: Query<(&Children, &Item, Has<Splinted>), With<Limb>>,
     identities: Res<NetworkIdentities>,
-    injuries: Query<(Entity, AnyOf<(&OrganicLaceration, ())>)>,
+    injuries: Query<(Entity, AnyOf<(&OrganicLaceration, ())>, Has<Sutured>)>,
     time: Res<Time>,
     mut commands: Commands,
 ) {
@@ -163,18 +180,23 @@ fn collect_vitals(
 
         // TODO: Reduce allocations
         let mut all_injuries = HashMap::default();
+        let mut all_splinted = Vec::new();
         for &limb in body.limbs.iter() {
-            let Ok((children, item)) = limbs.get(limb) else {
+            let Ok((children, item, splinted)) = limbs.get(limb) else {
                 continue;
             };
 
             let mut limb_injuries = Vec::default();
             let name = &item.name;
-            for (entity, (organic_laceration, _)) in injuries.iter_many(children) {
+            if splinted {
+                all_splinted.push(name.clone());
+            }
+            for (entity, (organic_laceration, _), sutured) in injuries.iter_many(children) {
                 if let Some(injury) = organic_laceration {
                     limb_injuries.push(Injury {
                         server_entity: entity,
                         name: format!("{} Laceration", injury.size),
+                        sutured,
                     });
                 }
             }
@@ -188,6 +210,10 @@ fn collect_vitals(
         if *ui.injuries != all_injuries {
             *ui.injuries = all_injuries;
         }
+        all_splinted.sort_unstable();
+        if *ui.splinted != all_splinted {
+            *ui.splinted = all_splinted;
+        }
     }
 }
 
@@ -224,6 +250,7 @@ fn handle_apply_medicine(
     }
 }
 
+#[cfg(feature = "client")]
 fn vitals_ui(
     mut contexts: EguiContexts,
     uis: Query<(Entity, &NetworkIdentity, &HealthUiClient)>,
@@ -246,11 +273,18 @@ fn vitals_ui(
                 }
 
                 for (body_part, injuries) in health_ui.injuries.iter() {
-                    ui.label(body_part);
+                    if health_ui.splinted.contains(body_part) {
+                        ui.label(format!("{} (splinted)", body_part));
+                    } else {
+                        ui.label(body_part);
+                    }
                     ui.indent("idk", |ui| {
                         for injury in injuries.iter() {
                             ui.horizontal(|ui| {
                                 ui.label(injury.name.as_str());
+                                if injury.sutured {
+                                    ui.label("(sutured)");
+                                }
                                 if let Some(&medicine) = held_entity {
                                     ui.spacing();
                                     if ui.button("Apply").clicked() {
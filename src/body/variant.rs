@@ -0,0 +1,318 @@
+use bevy::{
+    prelude::*,
+    reflect::{TypePath, TypeUuid},
+    utils::HashMap,
+};
+use bevy_common_assets::ron::RonAssetPlugin;
+use networking::{
+    component::AppExt as ComponentAppExt,
+    is_server,
+    variable::{NetworkVar, ServerVar},
+    Networked,
+};
+use serde::Deserialize;
+
+use crate::{temperature::ThermalDamageKind, Player};
+
+/// Body types players pick in their character profile, like lanky crew members or robots.
+pub(super) struct BodyVariantPlugin;
+
+impl Plugin for BodyVariantPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(RonAssetPlugin::<BodyTemplate>::new(&["body.ron"]))
+            .add_networked_component::<BodyVariant, BodyVariantClient>()
+            .add_systems(Startup, load_assets);
+        if !is_server(app) {
+            app.add_systems(Update, apply_variant_speed);
+        }
+    }
+}
+
+/// The limbs and stats of a body type, loaded from `assets/bodies`.
+#[derive(Deserialize, TypeUuid, TypePath)]
+#[uuid = "5b0f6a2e-93c4-4d1e-8f27-c1a9e4b37d52"]
+pub struct BodyTemplate {
+    pub id: String,
+    pub name: String,
+    /// Walking speed in m/s
+    pub speed: f32,
+    /// Integrity of every body part when unharmed, humans have 1
+    #[serde(default = "BodyTemplate::default_max_integrity")]
+    pub max_integrity: f32,
+    /// If the creature gets hungry and thirsty
+    #[serde(default = "BodyTemplate::default_metabolism")]
+    pub metabolism: bool,
+    /// Multiplier for temperature damage, by kind. Kinds that aren't listed do normal damage.
+    #[serde(default)]
+    pub vulnerabilities: HashMap<ThermalDamageKind, f32>,
+    /// Scene in `assets/creatures` for the creature itself
+    pub scene: String,
+    /// The limb all other limbs and organs are attached to
+    pub root: LimbTemplate,
+}
+
+impl BodyTemplate {
+    fn default_max_integrity() -> f32 {
+        1.0
+    }
+
+    fn default_metabolism() -> bool {
+        true
+    }
+
+    /// Finds the loaded template with the given id.
+    pub fn find<'a>(templates: &'a Assets<BodyTemplate>, id: &str) -> Option<&'a BodyTemplate> {
+        templates
+            .iter()
+            .map(|(_, template)| template)
+            .find(|template| template.id == id)
+    }
+}
+
+/// A limb of a body template and the limbs and organs attached to it.
+#[derive(Deserialize)]
+pub struct LimbTemplate {
+    /// Scene in `assets/creatures`
+    pub scene: String,
+    #[serde(default)]
+    pub children: Vec<LimbTemplate>,
+}
+
+#[derive(Resource)]
+struct BodyTemplateAssets {
+    // Used to keep templates loaded
+    #[allow(dead_code)]
+    templates: Vec<Handle<BodyTemplate>>,
+}
+
+fn load_assets(mut commands: Commands, server: Res<AssetServer>) {
+    let assets = BodyTemplateAssets {
+        templates: server
+            .load_folder("bodies")
+            .expect("assets/bodies is missing")
+            .into_iter()
+            .map(HandleUntyped::typed)
+            .collect(),
+    };
+    commands.insert_resource(assets);
+}
+
+/// Which body templates players can pick on this server.
+#[derive(Deserialize)]
+pub struct BodyConfig {
+    /// Ids of the body templates players can spawn with
+    #[serde(default = "BodyConfig::default_allowed")]
+    pub allowed: Vec<String>,
+    /// Id of the body used when a player picked one that isn't allowed
+    #[serde(default = "BodyConfig::default_fallback")]
+    pub fallback: String,
+}
+
+impl Default for BodyConfig {
+    fn default() -> Self {
+        Self {
+            allowed: Self::default_allowed(),
+            fallback: Self::default_fallback(),
+        }
+    }
+}
+
+impl BodyConfig {
+    fn default_allowed() -> Vec<String> {
+        vec!["human".into(), "lanky".into(), "stocky".into()]
+    }
+
+    fn default_fallback() -> String {
+        "human".into()
+    }
+
+    /// Checks if a player can spawn with the body they picked.
+    /// Returns why not, so the player can be told which body they got instead.
+    pub fn check(&self, requested: &str, templates: &Assets<BodyTemplate>) -> Result<(), String> {
+        let fallback = BodyTemplate::find(templates, &self.fallback)
+            .map_or(self.fallback.as_str(), |t| t.name.as_str());
+        let Some(template) = BodyTemplate::find(templates, requested) else {
+            return Err(format!(
+                "This server doesn't have the body you picked, you will spawn as {}",
+                fallback
+            ));
+        };
+        if !self.allowed.contains(&template.id) {
+            return Err(format!(
+                "The {} body is not allowed on this server, you will spawn as {}",
+                template.name, fallback
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// The body template a creature was created from.
+/// Health, movement and metabolism read their base values from here.
+#[derive(Component, Networked)]
+#[networked(client = "BodyVariantClient")]
+pub struct BodyVariant {
+    name: NetworkVar<String>,
+    speed: NetworkVar<f32>,
+    max_integrity: f32,
+    metabolism: bool,
+    vulnerabilities: HashMap<ThermalDamageKind, f32>,
+}
+
+impl BodyVariant {
+    pub fn new(template: &BodyTemplate) -> Self {
+        Self {
+            name: template.name.clone().into(),
+            speed: template.speed.into(),
+            max_integrity: template.max_integrity,
+            metabolism: template.metabolism,
+            vulnerabilities: template.vulnerabilities.clone(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn max_integrity(&self) -> f32 {
+        self.max_integrity
+    }
+
+    pub fn has_metabolism(&self) -> bool {
+        self.metabolism
+    }
+
+    /// Multiplier for temperature damage of the given kind.
+    pub fn vulnerability(&self, kind: ThermalDamageKind) -> f32 {
+        self.vulnerabilities.get(&kind).copied().unwrap_or(1.0)
+    }
+}
+
+#[derive(Component, Default, Networked, TypeUuid)]
+#[uuid = "e8d41c7a-2b6f-4a95-b3d0-7f1c59a26e84"]
+#[networked(server = "BodyVariant")]
+pub struct BodyVariantClient {
+    name: ServerVar<String>,
+    speed: ServerVar<f32>,
+}
+
+/// Movement is simulated on the client, so it needs the walking speed of the body.
+fn apply_variant_speed(
+    mut creatures: Query<
+        (&BodyVariantClient, &mut Player),
+        Or<(Changed<BodyVariantClient>, Added<Player>)>,
+    >,
+) {
+    for (variant, mut player) in creatures.iter_mut() {
+        player.max_velocity = *variant.speed;
+    }
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use bevy::asset::{AssetPath, AssetPathId};
+    use utils::task::Tasks;
+
+    use super::*;
+    use crate::{body::SpawnCreature, config::ServerConfig, testing::server_app};
+
+    const TEMPLATES: [(&str, &str); 4] = [
+        ("human", include_str!("../../assets/bodies/human.body.ron")),
+        ("lanky", include_str!("../../assets/bodies/lanky.body.ron")),
+        (
+            "stocky",
+            include_str!("../../assets/bodies/stocky.body.ron"),
+        ),
+        ("robot", include_str!("../../assets/bodies/robot.body.ron")),
+    ];
+
+    fn add_templates(assets: &mut Assets<BodyTemplate>) {
+        for (id, text) in TEMPLATES {
+            let path = format!("bodies/{}.body.ron", id);
+            let template: BodyTemplate = ron::from_str(text).unwrap();
+            assets.set_untracked(AssetPathId::from(AssetPath::from(path.as_str())), template);
+        }
+    }
+
+    /// Spawns a creature with every body template.
+    fn spawned_variants() -> HashMap<String, (f32, f32, bool, f32)> {
+        let mut app = server_app(ServerConfig::default());
+        add_templates(&mut app.world.resource_mut::<Assets<BodyTemplate>>());
+        for (id, _) in TEMPLATES {
+            app.world
+                .resource_mut::<Tasks<SpawnCreature>>()
+                .create_ignore(SpawnCreature {
+                    archetype: id.into(),
+                });
+        }
+        app.update();
+
+        let mut variants = app.world.query::<&BodyVariant>();
+        variants
+            .iter(&app.world)
+            .map(|variant| {
+                (
+                    variant.name().to_owned(),
+                    (
+                        *variant.speed,
+                        variant.max_integrity(),
+                        variant.has_metabolism(),
+                        variant.vulnerability(ThermalDamageKind::Burn),
+                    ),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn spawned_bodies_have_the_stats_of_their_template() {
+        let variants = spawned_variants();
+        assert_eq!(variants.len(), TEMPLATES.len());
+
+        let (human_speed, human_integrity, ..) = variants["Human"];
+        assert_eq!(variants["Human"], (human_speed, 1.0, true, 1.0));
+
+        let (speed, integrity, metabolism, _) = variants["Lanky"];
+        assert!(speed > human_speed);
+        assert!(integrity < human_integrity);
+        assert!(metabolism);
+
+        let (speed, integrity, metabolism, _) = variants["Stocky"];
+        assert!(speed < human_speed);
+        assert!(integrity > human_integrity);
+        assert!(metabolism);
+
+        let (_, integrity, metabolism, burn) = variants["Test Robot"];
+        assert_eq!(integrity, human_integrity);
+        assert!(!metabolism);
+        assert!(burn > 1.0);
+    }
+
+    #[test]
+    fn robot_only_differs_in_listed_vulnerabilities() {
+        let (_, text) = TEMPLATES[3];
+        let robot = BodyVariant::new(&ron::from_str(text).unwrap());
+        assert!(robot.vulnerability(ThermalDamageKind::Burn) > 1.0);
+        assert_eq!(robot.vulnerability(ThermalDamageKind::Cold), 1.0);
+    }
+
+    #[test]
+    fn bodies_outside_the_allow_list_are_refused() {
+        let mut app = server_app(ServerConfig::default());
+        add_templates(&mut app.world.resource_mut::<Assets<BodyTemplate>>());
+        let templates = app.world.resource::<Assets<BodyTemplate>>();
+        let config = BodyConfig::default();
+
+        assert_eq!(config.check("lanky", templates), Ok(()));
+        assert_eq!(
+            config.check("robot", templates),
+            Err(
+                "The Test Robot body is not allowed on this server, you will spawn as Human".into()
+            )
+        );
+        assert_eq!(
+            config.check("dragon", templates),
+            Err("This server doesn't have the body you picked, you will spawn as Human".into())
+        );
+    }
+}
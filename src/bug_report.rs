@@ -0,0 +1,375 @@
+use std::io::Write;
+
+use bevy::{log::Level, prelude::*, utils::HashMap};
+use networking::{
+    is_server,
+    messaging::{AppExt, MessageEvent, MessageReceivers, MessageSender},
+    time::ServerNetworkTime,
+    ConnectionId,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::logging::RecentLogs;
+
+#[cfg(feature = "client")]
+use {
+    crate::{logging::LogLine, ui::has_window, GameState},
+    bevy::{render::view::screenshot::ScreenshotManager, window::PrimaryWindow},
+    bevy_egui::{egui, EguiContexts},
+    networking::{
+        diagnostics::{ConnectionStats, UnresolvedIdentities},
+        identity::NetworkIdentity,
+        messaging::MessageTypes,
+        ClientState,
+    },
+    std::{
+        fs::{create_dir_all, File},
+        io::Cursor,
+        path::{Path, PathBuf},
+        sync::{Arc, Mutex},
+        time::SystemTime,
+    },
+};
+
+/// Lets players save a bug report with information about the client and server state.
+pub struct BugReportPlugin;
+
+impl Plugin for BugReportPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_network_message::<BugReportContextRequest>("BugReportContextRequest")
+            .add_network_message::<BugReportContext>("BugReportContext");
+
+        if is_server(app) {
+            app.init_resource::<ContextCooldowns>()
+                .add_systems(Update, handle_context_request);
+        } else {
+            #[cfg(feature = "client")]
+            app.add_systems(
+                Update,
+                (
+                    start_bug_report,
+                    receive_server_context,
+                    finish_bug_report,
+                    report_toast.run_if(has_window),
+                )
+                    .chain(),
+            );
+        }
+    }
+}
+
+#[cfg(feature = "client")]
+const REPORT_KEY: KeyCode = KeyCode::F12;
+#[cfg(feature = "client")]
+const REPORT_DIRECTORY: &str = "reports";
+/// How many lines of the client log are included
+#[cfg(feature = "client")]
+const CLIENT_LOG_LINES: usize = 200;
+/// How long to wait for the screenshot and server before saving what we have
+#[cfg(feature = "client")]
+const REPORT_TIMEOUT: f32 = 5.0;
+#[cfg(feature = "client")]
+const TOAST_DURATION: f32 = 6.0;
+/// Limits for the server context, so it can't be used to send large amounts of data
+const MAX_CONTEXT_LINES: usize = 50;
+const MAX_CONTEXT_LINE_LENGTH: usize = 300;
+const MAX_CONTEXT_SIZE: usize = 8 * 1024;
+/// Seconds a player has to wait between requesting server context
+const CONTEXT_COOLDOWN: f32 = 60.0;
+
+/// Client request for server information to include in a bug report.
+/// Any player can request this, it only contains information that isn't private.
+#[derive(Serialize, Deserialize)]
+struct BugReportContextRequest;
+
+#[derive(Serialize, Deserialize)]
+struct BugReportContext {
+    tick: u32,
+    /// Recent warnings and errors logged by the server
+    warnings: Vec<String>,
+}
+
+#[derive(Resource, Default)]
+struct ContextCooldowns {
+    last_request: HashMap<ConnectionId, f32>,
+}
+
+fn handle_context_request(
+    mut requests: EventReader<MessageEvent<BugReportContextRequest>>,
+    mut cooldowns: ResMut<ContextCooldowns>,
+    logs: Res<RecentLogs>,
+    network_time: Res<ServerNetworkTime>,
+    time: Res<Time>,
+    mut sender: MessageSender,
+) {
+    for event in requests.iter() {
+        let now = time.elapsed_seconds();
+        if let Some(last) = cooldowns.last_request.get(&event.connection) {
+            if now - last < CONTEXT_COOLDOWN {
+                debug!(connection = ?event.connection, "Bug report context request rate limited");
+                continue;
+            }
+        }
+        cooldowns.last_request.insert(event.connection, now);
+
+        // Newest lines are kept if the size limit is hit
+        let mut size = 0;
+        let mut warnings: Vec<String> = logs
+            .last(usize::MAX)
+            .iter()
+            .rev()
+            .filter(|line| line.level <= Level::WARN && !line.private)
+            .take(MAX_CONTEXT_LINES)
+            .map(|line| {
+                line.to_string()
+                    .chars()
+                    .take(MAX_CONTEXT_LINE_LENGTH)
+                    .collect()
+            })
+            .take_while(|line: &String| {
+                size += line.len();
+                size <= MAX_CONTEXT_SIZE
+            })
+            .collect();
+        warnings.reverse();
+
+        sender.send(
+            &BugReportContext {
+                tick: network_time.current_tick(),
+                warnings,
+            },
+            MessageReceivers::Single(event.connection),
+        );
+    }
+}
+
+/// A report that is waiting for the screenshot or server context.
+#[cfg(feature = "client")]
+#[derive(Resource)]
+struct PendingReport {
+    started: f32,
+    /// Files to put into the archive
+    files: Vec<(&'static str, Vec<u8>)>,
+    /// Filled with the encoded PNG once the screenshot was taken
+    screenshot: Arc<Mutex<Option<Vec<u8>>>>,
+    waiting_for_screenshot: bool,
+    waiting_for_server: bool,
+}
+
+/// Tells the player where the last report was saved.
+#[cfg(feature = "client")]
+#[derive(Resource)]
+struct ReportToast {
+    text: String,
+    until: f32,
+}
+
+#[cfg(feature = "client")]
+#[allow(clippy::too_many_arguments)]
+fn start_bug_report(
+    keys: Res<Input<KeyCode>>,
+    pending: Option<Res<PendingReport>>,
+    logs: Res<RecentLogs>,
+    game_state: Res<State<GameState>>,
+    client_state: Res<State<ClientState>>,
+    message_types: Res<MessageTypes>,
+    stats: Option<Res<ConnectionStats>>,
+    unresolved: Res<UnresolvedIdentities>,
+    replicated: Query<(), With<NetworkIdentity>>,
+    windows: Query<Entity, With<PrimaryWindow>>,
+    mut screenshots: ResMut<ScreenshotManager>,
+    mut sender: MessageSender,
+    time: Res<Time>,
+    mut commands: Commands,
+) {
+    if !keys.just_pressed(REPORT_KEY) || pending.is_some() {
+        return;
+    }
+
+    let connected = *client_state.get() == ClientState::Connected;
+    let mut info = String::new();
+    info.push_str(&format!("Version: {}\n", env!("CARGO_PKG_VERSION")));
+    info.push_str(&format!(
+        "Protocol hash: {:016x}\n",
+        message_types.protocol_hash()
+    ));
+    info.push_str(&format!("Game state: {:?}\n", game_state.get()));
+    info.push_str(&format!("Client state: {:?}\n", client_state.get()));
+    info.push_str(&format!(
+        "Replicated entities: {}\n",
+        replicated.iter().count()
+    ));
+    info.push_str(&format!(
+        "Unresolved network identities: {}\n",
+        unresolved.count()
+    ));
+    for (source, identities) in unresolved.iter() {
+        info.push_str(&format!("  {}: {:?}\n", source, identities));
+    }
+    if let Some(stats) = stats.filter(|_| connected) {
+        info.push_str(&format!("Connection: {:#?}\n", *stats));
+    }
+
+    let client_log = logs
+        .last(CLIENT_LOG_LINES)
+        .iter()
+        .map(LogLine::to_string)
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let screenshot = Arc::new(Mutex::new(None));
+    if let Ok(window) = windows.get_single() {
+        let slot = screenshot.clone();
+        let result = screenshots.take_screenshot(window, move |screen| {
+            let png = screen.try_into_dynamic().ok().and_then(|dynamic| {
+                let mut bytes = Cursor::new(Vec::new());
+                dynamic
+                    .to_rgb8()
+                    .write_to(&mut bytes, image::ImageOutputFormat::Png)
+                    .ok()?;
+                Some(bytes.into_inner())
+            });
+            // Empty if the screenshot could not be encoded
+            *slot.lock().unwrap() = Some(png.unwrap_or_default());
+        });
+        if let Err(err) = result {
+            warn!(error = %err, "Could not take bug report screenshot");
+            *screenshot.lock().unwrap() = Some(Vec::new());
+        }
+    } else {
+        *screenshot.lock().unwrap() = Some(Vec::new());
+    }
+
+    if connected {
+        sender.send_to_server(&BugReportContextRequest);
+    }
+
+    info!("Capturing bug report");
+    commands.insert_resource(PendingReport {
+        started: time.elapsed_seconds(),
+        files: vec![
+            ("info.txt", info.into_bytes()),
+            ("client.log", client_log.into_bytes()),
+        ],
+        screenshot,
+        waiting_for_screenshot: true,
+        waiting_for_server: connected,
+    });
+}
+
+#[cfg(feature = "client")]
+fn receive_server_context(
+    mut messages: EventReader<MessageEvent<BugReportContext>>,
+    pending: Option<ResMut<PendingReport>>,
+) {
+    let Some(mut pending) = pending else {
+        messages.clear();
+        return;
+    };
+
+    for event in messages.iter() {
+        let context = &event.message;
+        let mut text = format!("Server tick: {}\n\n", context.tick);
+        for line in context.warnings.iter() {
+            text.push_str(line);
+            text.push('\n');
+        }
+        pending.files.push(("server.txt", text.into_bytes()));
+        pending.waiting_for_server = false;
+    }
+}
+
+#[cfg(feature = "client")]
+fn finish_bug_report(
+    pending: Option<ResMut<PendingReport>>,
+    time: Res<Time>,
+    mut commands: Commands,
+) {
+    let Some(mut pending) = pending else {
+        return;
+    };
+
+    let screenshot = pending.screenshot.lock().unwrap().take();
+    if let Some(png) = screenshot {
+        if !png.is_empty() {
+            pending.files.push(("screenshot.png", png));
+        }
+        pending.waiting_for_screenshot = false;
+    }
+
+    let now = time.elapsed_seconds();
+    if pending.waiting_for_screenshot || pending.waiting_for_server {
+        if now - pending.started <= REPORT_TIMEOUT {
+            return;
+        }
+        warn!(
+            screenshot = pending.waiting_for_screenshot,
+            server = pending.waiting_for_server,
+            "Bug report timed out, saving without missing parts"
+        );
+    }
+
+    commands.remove_resource::<PendingReport>();
+    let text = match write_report(&pending.files) {
+        Ok(path) => {
+            info!(path = %path.display(), "Saved bug report");
+            format!("Bug report saved to {}", path.display())
+        }
+        Err(err) => {
+            error!(error = %err, "Could not save bug report");
+            format!("Could not save bug report: {}", err)
+        }
+    };
+    commands.insert_resource(ReportToast {
+        text,
+        until: now + TOAST_DURATION,
+    });
+}
+
+#[cfg(feature = "client")]
+fn write_report(files: &[(&str, Vec<u8>)]) -> Result<PathBuf, String> {
+    let directory = Path::new(REPORT_DIRECTORY);
+    create_dir_all(directory).map_err(|e| e.to_string())?;
+    let timestamp = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let path = directory.join(format!("report-{}.zip", timestamp));
+
+    let file = File::create(&path).map_err(|e| e.to_string())?;
+    let mut archive = zip::ZipWriter::new(file);
+    for (name, content) in files {
+        archive
+            .start_file(*name, zip::write::FileOptions::default())
+            .map_err(|e| e.to_string())?;
+        archive.write_all(content).map_err(|e| e.to_string())?;
+    }
+    archive.finish().map_err(|e| e.to_string())?;
+
+    Ok(path)
+}
+
+#[cfg(feature = "client")]
+fn report_toast(
+    toast: Option<Res<ReportToast>>,
+    mut contexts: EguiContexts,
+    time: Res<Time>,
+    mut commands: Commands,
+) {
+    let Some(toast) = toast else {
+        return;
+    };
+    if time.elapsed_seconds() > toast.until {
+        commands.remove_resource::<ReportToast>();
+        return;
+    }
+
+    egui::Area::new("bug_report_toast")
+        .anchor(egui::Align2::CENTER_BOTTOM, egui::vec2(0.0, -40.0))
+        .order(egui::Order::Foreground)
+        .show(contexts.ctx_mut(), |ui| {
+            egui::Frame::popup(ui.style()).show(ui, |ui| {
+                ui.label(&toast.text);
+            });
+        });
+}
@@ -0,0 +1,209 @@
+use std::{path::Path, time::Duration};
+
+use bevy::{prelude::*, utils::HashMap};
+use networking::{is_server, scene::NetworkSceneBundle};
+use serde::Deserialize;
+
+use crate::{
+    body::{health::Dead, Body, Cutting},
+    config::ServerConfig,
+    decals::{DecalKind, DecalSender},
+    effects::{EffectKind, EffectSender},
+    feedback::{Feedback, FeedbackKind},
+    interaction::{
+        ActiveInteraction, GenerateInteractionList, InteractionListEvents, InteractionOption,
+        InteractionSpecificity, InteractionStatus, Reach,
+    },
+};
+
+/// Cutting up dead mobs with a sharp item for meat and trophies.
+pub struct ButcheringPlugin;
+
+impl Plugin for ButcheringPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Butcherable>();
+
+        if is_server(app) {
+            let mut config = app
+                .world
+                .get_resource::<ServerConfig>()
+                .map(|config| config.butchering.clone())
+                .unwrap_or_default();
+            config.human_yield.retain(|item| {
+                let exists = prefab_exists(item);
+                if !exists {
+                    error!(
+                        item = item.as_str(),
+                        "Unknown item in butchering.human_yield"
+                    );
+                }
+                exists
+            });
+            app.insert_resource(config)
+                .register_type::<ButcherInteraction>()
+                .add_systems(
+                    Update,
+                    (
+                        validate_butcher_yields,
+                        prepare_butcher_interaction.in_set(GenerateInteractionList),
+                        butcher_interaction,
+                    ),
+                );
+        }
+    }
+}
+
+/// Time it takes to butcher a corpse
+const BUTCHER_TIME: Duration = Duration::from_secs(5);
+/// Meters a corpse can be moved while it's being butchered, like by being pulled, before it's interrupted
+const BUTCHER_TOLERANCE: f32 = 0.4;
+
+#[derive(Deserialize, Resource, Clone)]
+#[serde(default)]
+pub struct ButcheringConfig {
+    /// If human corpses can be butchered, for servers that want that kind of horror
+    pub allow_humans: bool,
+    /// Items a human corpse is cut into, by prefab name in `assets/items`
+    pub human_yield: Vec<String>,
+}
+
+impl Default for ButcheringConfig {
+    fn default() -> Self {
+        Self {
+            allow_humans: false,
+            human_yield: vec!["raw_meat".into(); 3],
+        }
+    }
+}
+
+/// What a mob is cut into once it's dead. Set in the mob's scene.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct Butcherable {
+    /// Items the corpse is replaced with, by prefab name in `assets/items`.
+    /// Listing an item several times spawns several of it.
+    pub butcher_yield: Vec<String>,
+}
+
+/// A dead mob that stays around until it's butchered or cleaned up.
+#[derive(Component)]
+pub struct Corpse;
+
+fn prefab_path(item: &str) -> String {
+    format!("items/{}.scn.ron", item)
+}
+
+fn prefab_exists(item: &str) -> bool {
+    Path::new("assets").join(prefab_path(item)).exists()
+}
+
+/// Drops yield items that don't exist when a mob is loaded, so butchering it can't fail later.
+fn validate_butcher_yields(
+    mut mobs: Query<(Entity, &mut Butcherable), Added<Butcherable>>,
+    mut known: Local<HashMap<String, bool>>,
+) {
+    for (entity, mut butcherable) in mobs.iter_mut() {
+        butcherable.butcher_yield.retain(|item| {
+            *known.entry(item.clone()).or_insert_with(|| {
+                let exists = prefab_exists(item);
+                if !exists {
+                    error!(mob = ?entity, item = item.as_str(), "Unknown item in butcher yield");
+                }
+                exists
+            })
+        });
+    }
+}
+
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+#[component(storage = "SparseSet")]
+struct ButcherInteraction {
+    /// Where the corpse was when butchering started
+    origin: Option<Vec3>,
+}
+
+fn prepare_butcher_interaction(
+    list: Res<InteractionListEvents>,
+    sharp_items: Query<(), With<Cutting>>,
+    mobs: Query<(), (With<Corpse>, With<Butcherable>)>,
+    humans: Query<(), (With<Body>, With<Dead>)>,
+) {
+    for event in list.events.iter() {
+        if !event
+            .item_in_hand
+            .is_some_and(|item| sharp_items.contains(item))
+        {
+            continue;
+        }
+        // Human corpses are offered too, so players are told why they can't
+        if !mobs.contains(event.target) && !humans.contains(event.target) {
+            continue;
+        }
+
+        event.add_interaction(InteractionOption {
+            text: "Butcher".into(),
+            interaction: Box::<ButcherInteraction>::default(),
+            specificity: InteractionSpecificity::Specific,
+        });
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn butcher_interaction(
+    mut query: Query<(Entity, &mut ButcherInteraction, &mut ActiveInteraction)>,
+    corpses: Query<(&GlobalTransform, Option<&Butcherable>)>,
+    config: Res<ButcheringConfig>,
+    reach: Reach,
+    time: Res<Time>,
+    asset_server: Res<AssetServer>,
+    mut feedback: Feedback,
+    mut decals: DecalSender,
+    mut effects: EffectSender,
+    mut commands: Commands,
+) {
+    for (entity, mut interaction, mut active) in query.iter_mut() {
+        let Ok((transform, butcherable)) = corpses.get(active.target) else {
+            active.status = InteractionStatus::Canceled;
+            continue;
+        };
+        let items = match butcherable {
+            Some(butcherable) => &butcherable.butcher_yield,
+            None if config.allow_humans => &config.human_yield,
+            None => {
+                feedback.send_to_creature(entity, FeedbackKind::Blocked, "butcher.refused", &[]);
+                active.status = InteractionStatus::Canceled;
+                continue;
+            }
+        };
+
+        active.set_initial_duration(BUTCHER_TIME);
+        let position = transform.translation();
+        let origin = *interaction.origin.get_or_insert(position);
+        // Pulled away or carried off in the meantime
+        if position.distance(origin) > BUTCHER_TOLERANCE || !reach.can_reach(entity, active.target)
+        {
+            feedback.send_to_creature(entity, FeedbackKind::Blocked, "butcher.moved", &[]);
+            active.status = InteractionStatus::Canceled;
+            continue;
+        }
+        if active.start_time() + BUTCHER_TIME.as_secs_f32() > time.elapsed_seconds() {
+            continue;
+        }
+        active.status = InteractionStatus::Completed;
+
+        for (i, item) in items.iter().enumerate() {
+            // Side by side, so they don't spawn inside each other
+            let offset = Vec3::new((i as f32 - (items.len() - 1) as f32 / 2.0) * 0.2, 0.2, 0.0);
+            commands.spawn(NetworkSceneBundle {
+                scene: asset_server.load(prefab_path(item)).into(),
+                transform: Transform::from_translation(position + offset),
+                ..Default::default()
+            });
+        }
+        decals.send(DecalKind::Blood, position, None, 1.5);
+        effects.send(EffectKind::Gore, position, 1.0);
+        commands.entity(active.target).despawn_recursive();
+        debug!(corpse = ?active.target, butcher = ?entity, items = items.len(), "Butchered corpse");
+    }
+}
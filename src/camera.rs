@@ -1,6 +1,16 @@
-use bevy::{input::mouse::MouseWheel, prelude::*};
+use bevy::{
+    ecs::query::Has, input::mouse::MouseWheel, prelude::*, utils::Uuid, window::PrimaryWindow,
+};
+use bevy_egui::EguiContexts;
+use networking::{
+    identity::NetworkIdentities, messaging::MessageSender, spawning::ClientControlled,
+};
 
-use crate::movement::MovementSystem;
+use crate::{
+    movement::MovementSystem,
+    spectator::{ClientSpectatorTargets, SpectateRequest, Spectator},
+    GameState,
+};
 
 #[derive(Component)]
 pub struct MainCamera;
@@ -14,6 +24,8 @@ pub struct TopDownCamera {
     target_zoom: f32,
     closest_offset: Vec3,
     farthest_offset: Vec3,
+    /// Height the camera looks at, following the target's height with a delay
+    current_height: f32,
 }
 
 impl TopDownCamera {
@@ -27,6 +39,7 @@ impl TopDownCamera {
             target_zoom: 0.5,
             closest_offset: Vec3::new(0.0, 5.0, 0.0),
             farthest_offset: Vec3::new(0.0, 15.0, 0.0),
+            current_height: 0.0,
         }
     }
 
@@ -60,6 +73,9 @@ pub fn top_down_camera_input_system(
     }
 }
 
+/// How quickly the camera follows the target up steps and down ledges
+const HEIGHT_SMOOTHING: f32 = 8.0;
+
 pub fn top_down_camera_update_system(
     time: Res<Time>,
     mut camera_query: Query<(&mut TopDownCamera, &mut Transform)>,
@@ -87,11 +103,164 @@ pub fn top_down_camera_update_system(
                 .closest_offset
                 .lerp(camera.farthest_offset, camera.current_zoom),
         );
-        transform.translation = target_transform.translation + offset;
-        transform.look_at(target_transform.translation, Vec3::Y);
+        // Stepping up is instant for the target, so the height is eased to avoid jerking the view
+        let target = target_transform.translation;
+        let height_interpolate = 1.0 - (-time.delta_seconds() * HEIGHT_SMOOTHING).exp();
+        camera.current_height += (target.y - camera.current_height) * height_interpolate;
+        let focus = Vec3::new(target.x, camera.current_height, target.z);
+        transform.translation = focus + offset;
+        transform.look_at(focus, Vec3::Y);
+    }
+}
+
+/// Camera controller for spectators.
+/// Works like the [`TopDownCamera`] until it follows another player.
+#[derive(Component, Default)]
+pub struct SpectatorCamera {
+    following: Option<Uuid>,
+    /// Position of the followed player in the target list, to continue from if they disappear
+    index: usize,
+    position: Vec3,
+    focus: Vec3,
+}
+
+impl SpectatorCamera {
+    pub fn following(&self) -> Option<Uuid> {
+        self.following
     }
 }
 
+/// Where the camera is relative to a followed player
+const FOLLOW_OFFSET: Vec3 = Vec3::new(0.0, 7.0, 5.0);
+/// How quickly the camera catches up to a followed player
+const FOLLOW_SMOOTHING: f32 = 5.0;
+
+/// Switches the camera controller depending on if the controlled creature is a spectator
+pub fn spectator_camera_role_system(
+    controlled: Query<Has<Spectator>, With<ClientControlled>>,
+    cameras: Query<(Entity, Has<SpectatorCamera>), With<MainCamera>>,
+    mut commands: Commands,
+) {
+    let is_spectator = controlled.get_single().unwrap_or(false);
+    for (entity, has_controller) in cameras.iter() {
+        if is_spectator && !has_controller {
+            commands.entity(entity).insert(SpectatorCamera::default());
+        } else if !is_spectator && has_controller {
+            commands.entity(entity).remove::<SpectatorCamera>();
+        }
+    }
+}
+
+pub fn spectator_camera_input_system(
+    mut cameras: Query<(&mut SpectatorCamera, &Transform)>,
+    keyboard_input: Res<Input<KeyCode>>,
+    targets: Res<ClientSpectatorTargets>,
+    identities: Res<NetworkIdentities>,
+    mut sender: MessageSender,
+) {
+    for (mut camera, transform) in cameras.iter_mut() {
+        let previous = camera.following;
+        let current_lost = previous.is_some_and(|p| !targets.is_valid(p, &identities));
+
+        let direction = if keyboard_input.just_pressed(KeyCode::BracketRight) || current_lost {
+            Some(true)
+        } else if keyboard_input.just_pressed(KeyCode::BracketLeft) {
+            Some(false)
+        } else {
+            None
+        };
+
+        if let Some(forward) = direction {
+            // A lost target falls through to whoever took its place in the list
+            let current = if current_lost { None } else { previous };
+            match targets.cycle(current, camera.index, forward, &identities) {
+                Some((index, target)) => {
+                    camera.following = Some(target.player);
+                    camera.index = index;
+                }
+                None => camera.following = None,
+            }
+        }
+        if keyboard_input.just_pressed(KeyCode::Backslash) {
+            camera.following = None;
+        }
+
+        if camera.following == previous {
+            continue;
+        }
+        if previous.is_none() {
+            // Start moving from wherever the camera currently is
+            camera.position = transform.translation;
+            camera.focus = transform.translation + transform.forward() * FOLLOW_OFFSET.length();
+        }
+        sender.send_to_server(&SpectateRequest {
+            target: camera.following,
+        });
+    }
+}
+
+pub fn spectator_camera_update_system(
+    time: Res<Time>,
+    mut cameras: Query<(&mut SpectatorCamera, &mut Transform)>,
+    targets: Res<ClientSpectatorTargets>,
+    identities: Res<NetworkIdentities>,
+    target_query: Query<&GlobalTransform>,
+) {
+    for (mut camera, mut transform) in cameras.iter_mut() {
+        let Some(target_position) = camera
+            .following
+            .and_then(|player| targets.get(player))
+            .and_then(|target| identities.get_entity(target.entity))
+            .and_then(|entity| target_query.get(entity).ok())
+            .map(|t| t.translation())
+        else {
+            continue;
+        };
+
+        let interpolate = 1.0 - (-time.delta_seconds() * FOLLOW_SMOOTHING).exp();
+        camera.position = camera
+            .position
+            .lerp(target_position + FOLLOW_OFFSET, interpolate);
+        camera.focus = camera.focus.lerp(target_position, interpolate);
+        transform.translation = camera.position;
+        transform.look_at(camera.focus, Vec3::Y);
+    }
+}
+
+/// The point on the floor under the cursor.
+/// Keeps the last position while the cursor is over the UI, so commands typed in chat can refer to it.
+#[derive(Resource, Default)]
+pub struct WorldCursor(pub Option<Vec3>);
+
+fn update_world_cursor(
+    mut cursor: ResMut<WorldCursor>,
+    mut contexts: EguiContexts,
+    windows: Query<(Entity, &Window), With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+) {
+    let Ok((window_entity, window)) = windows.get_single() else {
+        return;
+    };
+    if contexts
+        .try_ctx_for_window_mut(window_entity)
+        .is_some_and(|c| c.is_pointer_over_area())
+    {
+        return;
+    }
+    let (Some(position), Ok((camera, camera_transform))) =
+        (window.cursor_position(), cameras.get_single())
+    else {
+        return;
+    };
+    let Some(ray) = camera.viewport_to_world(camera_transform, position) else {
+        return;
+    };
+    let Some(distance) = ray.intersect_plane(Vec3::ZERO, Vec3::Y) else {
+        return;
+    };
+    cursor.0 = Some(ray.get_point(distance));
+}
+
 pub struct CameraPlugin;
 
 impl Plugin for CameraPlugin {
@@ -103,6 +272,23 @@ impl Plugin for CameraPlugin {
                 top_down_camera_update_system.after(MovementSystem::Update),
             )
                 .chain(),
+        )
+        .add_systems(
+            Update,
+            (
+                spectator_camera_role_system,
+                apply_deferred,
+                spectator_camera_input_system,
+                spectator_camera_update_system,
+            )
+                .chain()
+                .after(top_down_camera_update_system)
+                .run_if(in_state(GameState::Game)),
+        )
+        .init_resource::<WorldCursor>()
+        .add_systems(
+            Update,
+            update_world_cursor.after(spectator_camera_update_system),
         );
     }
 }
@@ -0,0 +1,931 @@
+use std::time::Duration;
+
+use bevy::{
+    ecs::{query::Has, system::SystemParam},
+    prelude::*,
+    reflect::TypeUuid,
+    time::common_conditions::on_timer,
+    utils::{HashMap, HashSet},
+};
+use maps::{tile_neighbours, world_to_tile, TileEntity, TileLayer, TileMap};
+use networking::{
+    component::AppExt as ComponentAppExt,
+    identity::EntityCommandsExt,
+    is_server,
+    transform::NetworkTransform,
+    variable::{NetworkVar, ServerVar},
+    Networked,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    body::Body,
+    console::{
+        ArgumentKind, CommandContext, CommandResult, ConsoleAppExt, ConsoleCommand, PermissionLevel,
+    },
+    construction::Welder,
+    decals::{DecalKind, DecalSender},
+    diffusion::{tile_kind, TileKind},
+    door::{Door, DoorState},
+    effects::{EffectKind, EffectSender},
+    gravity::Weightless,
+    interaction::{
+        ActiveInteraction, GenerateInteractionList, InteractionListEvents, InteractionOption,
+        InteractionSpecificity, InteractionStatus,
+    },
+    items::durability::Broken,
+    movement::{speed::SpeedModifiers, Stunned},
+    rng::GameRng,
+    temperature::{Airtight, HeatSource, TemperatureGrid},
+};
+
+#[cfg(feature = "client")]
+use crate::GameState;
+
+/// Liquids spilled on floors. They spread out a little, slow down or trip whoever walks through them,
+/// and dry up over time or when mopped.
+pub struct CoatingPlugin;
+
+impl Plugin for CoatingPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<CoatingKind>()
+            .register_type::<Spill>()
+            .register_type::<Mop>()
+            .add_networked_component::<TileCoating, TileCoatingClient>();
+
+        if is_server(app) {
+            app.init_resource::<TileCoatings>()
+                .add_event::<SpillEvent>()
+                .register_type::<MopInteraction>()
+                .register_type::<PourInteraction>()
+                .add_console_command(ConsoleCommand {
+                    name: "spill",
+                    description: "Spills water, blood, lube or plasma at your cursor",
+                    parameters: &[
+                        ("kind", ArgumentKind::Word),
+                        ("radius", ArgumentKind::Integer),
+                    ],
+                    permission: PermissionLevel::Admin,
+                    handler: spill_command,
+                })
+                .add_systems(
+                    Update,
+                    (
+                        (
+                            spill_broken_items,
+                            start_spills,
+                            spread_spills
+                                .run_if(on_timer(Duration::from_secs_f32(SPREAD_INTERVAL))),
+                        )
+                            .chain(),
+                        evaporate_coatings.run_if(on_timer(Duration::from_secs(1))),
+                        ignite_plasma.run_if(on_timer(Duration::from_secs_f32(IGNITE_INTERVAL))),
+                        walk_through_coatings,
+                        prepare_coating_interactions.in_set(GenerateInteractionList),
+                        mop_interaction,
+                        pour_interaction,
+                    ),
+                );
+        } else {
+            #[cfg(feature = "client")]
+            app.add_systems(
+                Update,
+                attach_coating_decals.run_if(in_state(GameState::Game)),
+            );
+        }
+    }
+}
+
+/// Coated tiles are capped, the oldest coating dries up first to make room for new ones
+const MAX_COATED_TILES: usize = 512;
+/// Seconds between a spill reaching one ring of tiles and the next
+const SPREAD_INTERVAL: f32 = 0.2;
+/// Walking speed on wet floor, as a fraction of normal speed
+const WATER_SPEED_MULTIPLIER: f32 = 0.8;
+/// Chance of slipping on every tile of lube stepped on, crouching or not
+const LUBE_SLIP_CHANCE: f32 = 0.9;
+const SLIP_STUN_SECONDS: f32 = 2.0;
+/// Tiles a creature leaves bloody footprints on after stepping in blood
+const FOOTPRINT_TILES: u8 = 5;
+const IGNITE_INTERVAL: f32 = 0.5;
+/// Heat sources and tiles at least this hot set plasma on fire, in kelvin
+const IGNITION_TEMPERATURE: f32 = 573.0;
+/// Temperature of a tile right after the plasma on it burned
+const BURN_TEMPERATURE: f32 = 900.0;
+
+/// What a floor is coated with.
+#[derive(Reflect, Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum CoatingKind {
+    /// Slows walking. Will also carry electric shocks once there are any.
+    #[default]
+    Water,
+    /// Leaves footprints behind whoever walks through it
+    Blood,
+    /// Almost everyone slips on it
+    Lube,
+    /// Burns away when a fire source crosses it
+    Plasma,
+}
+
+impl CoatingKind {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "water" => Some(CoatingKind::Water),
+            "blood" => Some(CoatingKind::Blood),
+            "lube" => Some(CoatingKind::Lube),
+            "plasma" => Some(CoatingKind::Plasma),
+            _ => None,
+        }
+    }
+
+    /// Amount lost every second, a full puddle has an amount of 1
+    fn evaporation(self) -> f32 {
+        match self {
+            CoatingKind::Water => 1.0 / 120.0,
+            CoatingKind::Blood => 1.0 / 600.0,
+            CoatingKind::Lube => 1.0 / 300.0,
+            CoatingKind::Plasma => 1.0 / 180.0,
+        }
+    }
+}
+
+/// Liquid an item holds. It is spilled when the item breaks or is poured out.
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct Spill {
+    pub kind: CoatingKind,
+    /// Amount on the tile it is spilled on, 1 is a full puddle
+    pub amount: f32,
+    /// How many tiles the spill spreads out
+    pub radius: u32,
+}
+
+impl Default for Spill {
+    fn default() -> Self {
+        Self {
+            kind: CoatingKind::Water,
+            amount: 1.0,
+            radius: 1,
+        }
+    }
+}
+
+/// Cleans coated floors.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct Mop;
+
+/// Liquid spilled onto a tile, spreading to the open tiles around it over the next few ticks.
+#[derive(Event)]
+pub struct SpillEvent {
+    pub tile: UVec2,
+    pub kind: CoatingKind,
+    pub amount: f32,
+    pub radius: u32,
+}
+
+/// The coating of one tile.
+#[derive(Component, Networked)]
+#[networked(client = "TileCoatingClient")]
+pub struct TileCoating {
+    kind: NetworkVar<CoatingKind>,
+    /// Quarters of a full puddle, rounded up. Only updated when it changes, to save traffic.
+    quarters: NetworkVar<u8>,
+    amount: f32,
+    tile: UVec2,
+    /// Elapsed seconds when the tile was coated
+    since: f32,
+}
+
+impl TileCoating {
+    fn set_amount(&mut self, amount: f32) {
+        self.amount = amount.min(1.0);
+        let quarters = (self.amount * 4.0).ceil().clamp(1.0, 4.0) as u8;
+        if *self.quarters != quarters {
+            *self.quarters = quarters;
+        }
+    }
+}
+
+#[derive(Component, Default, TypeUuid, Networked)]
+#[uuid = "3c8f1a67-9d2e-4b54-a1f0-7e6c2d9b4a83"]
+#[networked(server = "TileCoating")]
+pub struct TileCoatingClient {
+    kind: ServerVar<CoatingKind>,
+    quarters: ServerVar<u8>,
+}
+
+/// Coated tiles, so floors without a coating cost nothing.
+// TODO: Support multiple maps
+#[derive(Resource, Default)]
+pub struct TileCoatings {
+    tiles: HashMap<UVec2, (Entity, CoatingKind)>,
+    spreading: Vec<Spreading>,
+    /// Plasma tiles set on fire by a neighbour, burning at the next step
+    burning: HashSet<UVec2>,
+}
+
+impl TileCoatings {
+    pub fn kind_at(&self, tile: UVec2) -> Option<CoatingKind> {
+        self.tiles.get(&tile).map(|&(_, kind)| kind)
+    }
+}
+
+/// A spill that hasn't reached its full radius yet.
+struct Spreading {
+    kind: CoatingKind,
+    amount: f32,
+    radius: u32,
+    ring: u32,
+    frontier: Vec<UVec2>,
+    reached: HashSet<UVec2>,
+}
+
+/// Adds and removes coatings, keeping [`TileCoatings`] up to date.
+#[derive(SystemParam)]
+struct Coater<'w, 's> {
+    coatings: ResMut<'w, TileCoatings>,
+    existing: Query<'w, 's, &'static mut TileCoating>,
+    time: Res<'w, Time>,
+    commands: Commands<'w, 's>,
+}
+
+impl<'w, 's> Coater<'w, 's> {
+    fn coat(&mut self, tile: UVec2, kind: CoatingKind, amount: f32) {
+        if let Some(&(entity, current)) = self.coatings.tiles.get(&tile) {
+            // Spawned this frame, the next spill step will find it
+            let Ok(mut coating) = self.existing.get_mut(entity) else {
+                return;
+            };
+            if current == kind {
+                let total = coating.amount + amount;
+                coating.set_amount(total);
+            } else {
+                // Liquids wash away what was there
+                *coating.kind = kind;
+                coating.set_amount(amount);
+                self.coatings.tiles.insert(tile, (entity, kind));
+            }
+            return;
+        }
+
+        if self.coatings.tiles.len() >= MAX_COATED_TILES {
+            if let Some(oldest) = self
+                .existing
+                .iter()
+                .min_by(|a, b| a.since.total_cmp(&b.since))
+                .map(|c| c.tile)
+            {
+                self.clean(oldest);
+            }
+        }
+
+        let mut coating = TileCoating {
+            kind: kind.into(),
+            quarters: 0.into(),
+            amount: 0.0,
+            tile,
+            since: self.time.elapsed_seconds(),
+        };
+        coating.set_amount(amount);
+        let entity = self
+            .commands
+            .spawn((
+                coating,
+                SpatialBundle::from_transform(Transform::from_xyz(
+                    tile.x as f32,
+                    0.0,
+                    tile.y as f32,
+                )),
+                NetworkTransform::default(),
+            ))
+            .networked()
+            .id();
+        self.coatings.tiles.insert(tile, (entity, kind));
+    }
+
+    fn clean(&mut self, tile: UVec2) {
+        if let Some((entity, _)) = self.coatings.tiles.remove(&tile) {
+            self.commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+/// Tiles liquid can flow onto.
+#[derive(SystemParam)]
+struct OpenTiles<'w, 's> {
+    maps: Query<'w, 's, &'static TileMap>,
+    airtight: Query<'w, 's, (), With<Airtight>>,
+    doors: Query<'w, 's, &'static DoorState, With<Door>>,
+}
+
+impl<'w, 's> OpenTiles<'w, 's> {
+    fn is_open(&self, tile: UVec2) -> bool {
+        self.maps
+            .get_single()
+            .is_ok_and(|map| tile_kind(map, tile, &self.airtight, &self.doors) == TileKind::Open)
+    }
+}
+
+fn spill_broken_items(
+    broken: Query<(Entity, &Spill, &GlobalTransform), Added<Broken>>,
+    mut spills: EventWriter<SpillEvent>,
+    mut commands: Commands,
+) {
+    for (item, spill, transform) in broken.iter() {
+        if let Some(tile) = world_to_tile(transform.translation()) {
+            spills.send(SpillEvent {
+                tile,
+                kind: spill.kind,
+                amount: spill.amount,
+                radius: spill.radius,
+            });
+        }
+        commands.entity(item).remove::<Spill>();
+    }
+}
+
+fn start_spills(mut events: EventReader<SpillEvent>, open: OpenTiles, mut coater: Coater) {
+    for event in events.iter() {
+        if !open.is_open(event.tile) {
+            continue;
+        }
+        coater.coat(event.tile, event.kind, event.amount);
+        if event.radius > 0 {
+            coater.coatings.spreading.push(Spreading {
+                kind: event.kind,
+                amount: event.amount,
+                radius: event.radius,
+                ring: 0,
+                frontier: vec![event.tile],
+                reached: HashSet::from_iter([event.tile]),
+            });
+        }
+    }
+}
+
+/// Moves every spill one tile further out, getting thinner the further it goes.
+fn spread_spills(open: OpenTiles, mut coater: Coater) {
+    let mut spreading = std::mem::take(&mut coater.coatings.spreading);
+    for spill in spreading.iter_mut() {
+        spill.ring += 1;
+        let mut next = Vec::new();
+        for &tile in spill.frontier.iter() {
+            for (_, neighbour) in tile_neighbours(tile) {
+                if open.is_open(neighbour) && spill.reached.insert(neighbour) {
+                    next.push(neighbour);
+                }
+            }
+        }
+        let amount = spill.amount * (1.0 - spill.ring as f32 / (spill.radius + 1) as f32);
+        for &tile in next.iter() {
+            coater.coat(tile, spill.kind, amount);
+        }
+        spill.frontier = next;
+    }
+    spreading.retain(|spill| spill.ring < spill.radius && !spill.frontier.is_empty());
+    coater.coatings.spreading = spreading;
+}
+
+fn evaporate_coatings(mut coater: Coater) {
+    let mut dried = Vec::new();
+    for mut coating in coater.existing.iter_mut() {
+        let amount = coating.amount - coating.kind.evaporation();
+        if amount <= 0.0 {
+            dried.push(coating.tile);
+        } else {
+            coating.set_amount(amount);
+        }
+    }
+    for tile in dried {
+        coater.clean(tile);
+    }
+}
+
+/// Burns plasma touched by a fire source, which sets the plasma next to it on fire too.
+/// Fire sources are hot heat sources, welders (even carried ones) and hot tiles.
+fn ignite_plasma(
+    sources: Query<(&HeatSource, &GlobalTransform)>,
+    welders: Query<&GlobalTransform, With<Welder>>,
+    mut temperature: ResMut<TemperatureGrid>,
+    mut effects: EffectSender,
+    mut decals: DecalSender,
+    mut coater: Coater,
+) {
+    let mut fire: HashSet<UVec2> = sources
+        .iter()
+        .filter(|(source, _)| source.target >= IGNITION_TEMPERATURE)
+        .map(|(_, transform)| transform)
+        .chain(welders.iter())
+        .filter_map(|transform| world_to_tile(transform.translation()))
+        .collect();
+    fire.extend(coater.coatings.burning.drain());
+
+    let plasma: Vec<UVec2> = coater
+        .coatings
+        .tiles
+        .iter()
+        .filter(|(_, &(_, kind))| kind == CoatingKind::Plasma)
+        .map(|(&tile, _)| tile)
+        .filter(|&tile| fire.contains(&tile) || temperature.get(tile) >= IGNITION_TEMPERATURE)
+        .collect();
+    for tile in plasma {
+        coater.clean(tile);
+        temperature.heat(tile, BURN_TEMPERATURE);
+        let position = Vec3::new(tile.x as f32, 0.1, tile.y as f32);
+        effects.send(EffectKind::Sparks, position, 2.0);
+        decals.send(DecalKind::Scorch, position, None, 1.0);
+        coater
+            .coatings
+            .burning
+            .extend(tile_neighbours(tile).map(|(_, neighbour)| neighbour));
+    }
+}
+
+/// A creature that stepped in blood, leaving footprints on the next few tiles.
+#[derive(Component)]
+struct BloodyFeet {
+    tiles_left: u8,
+}
+
+#[allow(clippy::type_complexity)]
+fn walk_through_coatings(
+    mut walkers: Query<
+        (
+            Entity,
+            &GlobalTransform,
+            &mut SpeedModifiers,
+            Option<&Weightless>,
+            Option<&mut BloodyFeet>,
+            Has<Stunned>,
+        ),
+        With<Body>,
+    >,
+    mut last_tiles: Local<HashMap<Entity, UVec2>>,
+    mut rng: ResMut<GameRng>,
+    mut decals: DecalSender,
+    mut coater: Coater,
+) {
+    last_tiles.retain(|entity, _| walkers.contains(*entity));
+    let now = coater.time.elapsed_seconds();
+
+    for (entity, transform, mut modifiers, weightless, bloody, stunned) in walkers.iter_mut() {
+        let tile = world_to_tile(transform.translation());
+        // Floating creatures don't touch the floor
+        let kind = tile
+            .filter(|_| !weightless.is_some_and(|w| w.is_floating()))
+            .and_then(|tile| coater.coatings.kind_at(tile));
+        if kind == Some(CoatingKind::Water) {
+            modifiers.set_multiplier("coating", WATER_SPEED_MULTIPLIER);
+        } else {
+            modifiers.remove("coating");
+        }
+
+        let Some(tile) = tile else {
+            continue;
+        };
+        let Some(previous) = last_tiles.insert(entity, tile) else {
+            continue;
+        };
+        if previous == tile || weightless.is_some_and(|w| w.is_floating()) {
+            continue;
+        }
+
+        match kind {
+            Some(CoatingKind::Lube) => {
+                if !stunned && rng.stream("slips").f32() < LUBE_SLIP_CHANCE {
+                    coater
+                        .commands
+                        .entity(entity)
+                        .insert(Stunned::new(SLIP_STUN_SECONDS, now));
+                }
+            }
+            Some(CoatingKind::Blood) => {
+                coater.commands.entity(entity).insert(BloodyFeet {
+                    tiles_left: FOOTPRINT_TILES,
+                });
+            }
+            None => {
+                let Some(mut bloody) = bloody else {
+                    continue;
+                };
+                let step = tile.as_vec2() - previous.as_vec2();
+                decals.send(
+                    DecalKind::Footprints,
+                    Vec3::new(tile.x as f32, 0.0, tile.y as f32),
+                    Some(step.x.atan2(step.y)),
+                    1.0,
+                );
+                bloody.tiles_left = bloody.tiles_left.saturating_sub(1);
+                if bloody.tiles_left == 0 {
+                    coater.commands.entity(entity).remove::<BloodyFeet>();
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+#[component(storage = "SparseSet")]
+struct MopInteraction {
+    tile: UVec2,
+}
+
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+#[component(storage = "SparseSet")]
+struct PourInteraction {
+    tile: UVec2,
+    item: Entity,
+}
+
+// Dummy default for Reflect
+impl Default for PourInteraction {
+    fn default() -> Self {
+        Self {
+            tile: UVec2::ZERO,
+            item: Entity::from_raw(0),
+        }
+    }
+}
+
+const MOP_TIME: Duration = Duration::from_secs(2);
+
+fn prepare_coating_interactions(
+    list: Res<InteractionListEvents>,
+    mops: Query<(), With<Mop>>,
+    spills: Query<&Spill>,
+    floors: Query<&TileEntity>,
+    coatings: Res<TileCoatings>,
+) {
+    for event in list.events.iter() {
+        let Some(item_in_hand) = event.item_in_hand else {
+            continue;
+        };
+        let Some(tile) = floors
+            .get(event.target)
+            .ok()
+            .filter(|tile| tile.layer() == TileLayer::Turf)
+            .map(|tile| tile.position())
+        else {
+            continue;
+        };
+
+        if mops.contains(item_in_hand) && coatings.kind_at(tile).is_some() {
+            event.add_interaction(InteractionOption {
+                text: "Mop floor".into(),
+                interaction: Box::new(MopInteraction { tile }),
+                specificity: InteractionSpecificity::Specific,
+            });
+        }
+        if spills.contains(item_in_hand) {
+            event.add_interaction(InteractionOption {
+                text: "Pour out".into(),
+                interaction: Box::new(PourInteraction {
+                    tile,
+                    item: item_in_hand,
+                }),
+                specificity: InteractionSpecificity::Common,
+            });
+        }
+    }
+}
+
+fn mop_interaction(
+    mut query: Query<(&MopInteraction, &mut ActiveInteraction)>,
+    mut decals: DecalSender,
+    mut coater: Coater,
+) {
+    let now = coater.time.elapsed_seconds();
+    for (interaction, mut active) in query.iter_mut() {
+        active.set_initial_duration(MOP_TIME);
+
+        // Dried up or mopped by someone else
+        if coater.coatings.kind_at(interaction.tile).is_none() {
+            active.status = InteractionStatus::Canceled;
+            continue;
+        }
+        if active.start_time() + MOP_TIME.as_secs_f32() > now {
+            continue;
+        }
+
+        coater.clean(interaction.tile);
+        decals.clear(interaction.tile);
+        active.status = InteractionStatus::Completed;
+    }
+}
+
+fn pour_interaction(
+    mut query: Query<(&PourInteraction, &mut ActiveInteraction)>,
+    items: Query<&Spill>,
+    mut spills: EventWriter<SpillEvent>,
+    mut commands: Commands,
+) {
+    for (interaction, mut active) in query.iter_mut() {
+        let Ok(spill) = items.get(interaction.item) else {
+            active.status = InteractionStatus::Canceled;
+            continue;
+        };
+        spills.send(SpillEvent {
+            tile: interaction.tile,
+            kind: spill.kind,
+            amount: spill.amount,
+            radius: spill.radius,
+        });
+        // Nothing left to pour
+        commands.entity(interaction.item).remove::<Spill>();
+        active.status = InteractionStatus::Completed;
+    }
+}
+
+fn spill_command(world: &mut World, context: &CommandContext) -> CommandResult {
+    let kind = CoatingKind::parse(context.text(0))
+        .ok_or_else(|| format!("{} can't be spilled", context.text(0)))?;
+    let radius = u32::try_from(context.integer(1))
+        .ok()
+        .filter(|&r| r <= 5)
+        .ok_or_else(|| "radius must be between 0 and 5".to_string())?;
+    let Some(tile) = context.cursor.and_then(world_to_tile) else {
+        return Err("Your cursor isn't over the map".into());
+    };
+    world.resource_mut::<Events<SpillEvent>>().send(SpillEvent {
+        tile,
+        kind,
+        amount: 1.0,
+        radius,
+    });
+    Ok(format!("Spilled {:?} at {},{}", kind, tile.x, tile.y))
+}
+
+/// Height coatings are drawn at, just above the floor and decals
+#[cfg(feature = "client")]
+const DECAL_HEIGHT: f32 = 0.01;
+
+/// The decal drawn for a coating.
+#[cfg(feature = "client")]
+#[derive(Component)]
+struct CoatingDecal(Entity);
+
+#[cfg(feature = "client")]
+#[allow(clippy::type_complexity)]
+fn attach_coating_decals(
+    coatings: Query<
+        (Entity, &TileCoatingClient, Option<&CoatingDecal>),
+        Changed<TileCoatingClient>,
+    >,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut mesh_handles: Local<Option<Handle<Mesh>>>,
+    mut material_handles: Local<HashMap<(CoatingKind, u8), Handle<StandardMaterial>>>,
+    mut commands: Commands,
+) {
+    for (entity, coating, decal) in coatings.iter() {
+        if let Some(decal) = decal {
+            if let Some(old) = commands.get_entity(decal.0) {
+                old.despawn_recursive();
+            }
+        }
+
+        let puddle = mesh_handles
+            .get_or_insert_with(|| meshes.add(shape::Plane::from_size(0.9).into()))
+            .clone();
+        let kind = *coating.kind;
+        let quarters = *coating.quarters;
+        let material = material_handles
+            .entry((kind, quarters))
+            .or_insert_with(|| {
+                let color = match kind {
+                    CoatingKind::Water => Color::rgb(0.45, 0.6, 0.9),
+                    CoatingKind::Blood => Color::rgb(0.45, 0.02, 0.02),
+                    CoatingKind::Lube => Color::rgb(0.6, 0.9, 0.8),
+                    CoatingKind::Plasma => Color::rgb(0.7, 0.3, 0.9),
+                };
+                materials.add(StandardMaterial {
+                    base_color: color.with_a(0.2 + 0.15 * quarters as f32),
+                    alpha_mode: AlphaMode::Blend,
+                    perceptual_roughness: 0.1,
+                    ..Default::default()
+                })
+            })
+            .clone();
+
+        let decal = commands
+            .spawn(PbrBundle {
+                mesh: puddle,
+                material,
+                transform: Transform::from_xyz(0.0, DECAL_HEIGHT, 0.0),
+                ..Default::default()
+            })
+            .set_parent(entity)
+            .id();
+        commands.entity(entity).insert(CoatingDecal(decal));
+    }
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use bevy::time::TimeUpdateStrategy;
+    use maps::{TileReference, CHUNK_SIZE};
+    use utils::task::Tasks;
+
+    use super::*;
+    use crate::{config::ServerConfig, interaction::ExecuteInteraction, testing::server_app};
+
+    const FRAME: Duration = Duration::from_millis(100);
+    const SPILL: UVec2 = UVec2::new(4, 4);
+
+    /// A server with floor on every tile of the first chunk.
+    fn setup() -> App {
+        let mut app = server_app(ServerConfig::default());
+        // The test brings its own map
+        app.update();
+        app.world.remove_resource::<crate::Map>();
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(FRAME));
+
+        let floor = app.world.spawn_empty().id();
+        let mut map = TileMap::new(UVec2::ONE);
+        for position in (0..CHUNK_SIZE).flat_map(|x| (0..CHUNK_SIZE).map(move |y| UVec2::new(x, y)))
+        {
+            let tile = TileReference {
+                turf: Some(floor),
+                ..Default::default()
+            };
+            map.set_tile(position, tile).unwrap();
+        }
+        app.world.spawn((map, SpatialBundle::default()));
+        app
+    }
+
+    fn update(app: &mut App, frames: u32) {
+        for _ in 0..frames {
+            app.update();
+        }
+    }
+
+    /// Spills a full puddle and waits until it reached its radius.
+    fn spill(app: &mut App, tile: UVec2, kind: CoatingKind, radius: u32) {
+        app.world.send_event(SpillEvent {
+            tile,
+            kind,
+            amount: 1.0,
+            radius,
+        });
+        update(app, 10);
+    }
+
+    fn amount(app: &App, tile: UVec2) -> f32 {
+        let (entity, _) = app.world.resource::<TileCoatings>().tiles[&tile];
+        app.world.get::<TileCoating>(entity).unwrap().amount
+    }
+
+    fn kind_at(app: &App, tile: UVec2) -> Option<CoatingKind> {
+        app.world.resource::<TileCoatings>().kind_at(tile)
+    }
+
+    fn position(tile: UVec2) -> Vec3 {
+        Vec3::new(tile.x as f32, 0.0, tile.y as f32)
+    }
+
+    fn spawn_walker(app: &mut App, tile: UVec2) -> Entity {
+        let walker = app
+            .world
+            .spawn((
+                Body::default(),
+                SpatialBundle::from_transform(Transform::from_translation(position(tile))),
+            ))
+            .id();
+        // Lets the body get its speed modifiers and remember where it stands
+        update(app, 2);
+        walker
+    }
+
+    /// Moves the walker onto the tile, as if it walked there.
+    fn step(app: &mut App, walker: Entity, tile: UVec2) {
+        let translation = position(tile);
+        let mut entity = app.world.entity_mut(walker);
+        entity.get_mut::<Transform>().unwrap().translation = translation;
+        *entity.get_mut::<GlobalTransform>().unwrap() =
+            GlobalTransform::from_translation(translation);
+        app.update();
+    }
+
+    fn has_coating_modifier(app: &App, walker: Entity) -> bool {
+        app.world
+            .get::<SpeedModifiers>(walker)
+            .unwrap()
+            .iter()
+            .any(|(name, _)| name == "coating")
+    }
+
+    #[test]
+    fn spill_spreads_thinner_around_its_tile() {
+        let mut app = setup();
+        spill(&mut app, SPILL, CoatingKind::Water, 1);
+
+        assert_eq!(kind_at(&app, SPILL), Some(CoatingKind::Water));
+        for (_, neighbour) in tile_neighbours(SPILL) {
+            assert_eq!(kind_at(&app, neighbour), Some(CoatingKind::Water));
+            assert!(amount(&app, neighbour) < amount(&app, SPILL));
+        }
+        // Only one ring out
+        assert_eq!(kind_at(&app, SPILL + UVec2::new(2, 0)), None);
+        assert_eq!(kind_at(&app, SPILL + UVec2::ONE), None);
+        assert_eq!(app.world.resource::<TileCoatings>().tiles.len(), 5);
+    }
+
+    #[test]
+    fn spill_doesnt_flow_into_space() {
+        let mut app = setup();
+        spill(&mut app, UVec2::new(CHUNK_SIZE, 0), CoatingKind::Water, 1);
+        assert!(app.world.resource::<TileCoatings>().tiles.is_empty());
+    }
+
+    #[test]
+    fn walking_on_lube_slips() {
+        let mut app = setup();
+        spill(&mut app, SPILL, CoatingKind::Lube, 1);
+        let walker = spawn_walker(&mut app, SPILL - UVec2::new(2, 0));
+
+        for x in 1..=3 {
+            step(
+                &mut app,
+                walker,
+                SPILL - UVec2::new(2, 0) + UVec2::new(x, 0),
+            );
+        }
+        assert!(app.world.get::<Stunned>(walker).is_some());
+    }
+
+    #[test]
+    fn walking_on_water_slows_without_slipping() {
+        let mut app = setup();
+        spill(&mut app, SPILL, CoatingKind::Water, 1);
+        let walker = spawn_walker(&mut app, SPILL - UVec2::new(2, 0));
+        assert!(!has_coating_modifier(&app, walker));
+
+        for x in 1..=3 {
+            step(
+                &mut app,
+                walker,
+                SPILL - UVec2::new(2, 0) + UVec2::new(x, 0),
+            );
+            assert!(has_coating_modifier(&app, walker));
+        }
+        assert!(app.world.get::<Stunned>(walker).is_none());
+
+        step(&mut app, walker, SPILL + UVec2::new(2, 0));
+        assert!(!has_coating_modifier(&app, walker));
+    }
+
+    #[test]
+    fn mopping_cleans_the_tile() {
+        let mut app = setup();
+        spill(&mut app, SPILL, CoatingKind::Blood, 0);
+        let janitor = spawn_walker(&mut app, SPILL);
+        let floor = app.world.spawn_empty().id();
+
+        app.world
+            .resource_mut::<Tasks<ExecuteInteraction>>()
+            .create_ignore(ExecuteInteraction {
+                entity: janitor,
+                target: floor,
+                interaction: Box::new(MopInteraction { tile: SPILL }),
+            });
+        update(&mut app, 5);
+        // Mopping takes a while
+        assert_eq!(kind_at(&app, SPILL), Some(CoatingKind::Blood));
+
+        update(
+            &mut app,
+            (MOP_TIME.as_secs_f32() / FRAME.as_secs_f32()) as u32,
+        );
+        assert_eq!(kind_at(&app, SPILL), None);
+        assert!(app
+            .world
+            .query::<&TileCoating>()
+            .iter(&app.world)
+            .next()
+            .is_none());
+    }
+
+    #[test]
+    fn footprints_trail_for_a_few_tiles() {
+        let mut app = setup();
+        spill(&mut app, SPILL, CoatingKind::Blood, 0);
+        let start = SPILL - UVec2::X;
+        let walker = spawn_walker(&mut app, start);
+
+        let mut footprints = 0;
+        for x in 1..=FOOTPRINT_TILES as u32 + 5 {
+            let bloody = app.world.get::<BloodyFeet>(walker).is_some();
+            step(&mut app, walker, start + UVec2::new(x, 0));
+            // Every clean tile stepped on with bloody feet gets a footprint
+            if bloody {
+                footprints += 1;
+            }
+        }
+        assert_eq!(footprints, FOOTPRINT_TILES as u32);
+    }
+}
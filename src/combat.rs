@@ -1,41 +1,83 @@
-use bevy::{ecs::system::SystemParam, prelude::*, reflect::TypeUuid, window::PrimaryWindow};
-use bevy_egui::{egui, EguiContexts};
+use bevy::{prelude::*, reflect::TypeUuid};
 use networking::{
     component::AppExt,
     is_server,
-    messaging::{AppExt as MessageExt, MessageEvent, MessageSender},
-    spawning::{ClientControlled, ClientControls},
+    messaging::{AppExt as MessageExt, Finite, InvalidMessage, MessageEvent},
+    spawning::ClientControls,
     variable::{NetworkVar, ServerVar},
     Networked, Players,
 };
 use serde::{Deserialize, Serialize};
 
 use crate::{
+    actions::{direction_towards, ActorAction, ActorActionEvent},
     body::{Hand, Hands},
-    camera::MainCamera,
-    items::containers::Container,
-    ui::has_window,
+    feedback::{Feedback, FeedbackKind},
+    items::{containers::Container, durability::ItemDamageEvent},
+    movement::{
+        stamina::{Stamina, StaminaConfig},
+        Stunned,
+    },
 };
 
-use self::ranged::RangedPlugin;
+#[cfg(feature = "client")]
+use {
+    crate::{
+        accessibility::{AccessibilitySettings, UiColor},
+        camera::MainCamera,
+        cooldown::{paint_sweep, CooldownKey, OwnCooldowns},
+        ui::has_window,
+    },
+    bevy::{ecs::system::SystemParam, window::PrimaryWindow},
+    bevy_egui::{egui, EguiContexts},
+    networking::{messaging::MessageSender, spawning::ClientControlled, time::ClientNetworkTime},
+};
+
+use self::{
+    config::CombatConfigPlugin,
+    grab::GrabPlugin,
+    intents::IntentPlugin,
+    penetration::PenetrationPlugin,
+    ranged::RangedPlugin,
+    rewind::{LagCompensation, RewindPlugin},
+};
+
+pub use self::grab::{GrabbedBy, GrabbedByClient};
 
+mod config;
 pub mod damage;
+mod grab;
+mod intents;
+mod penetration;
 mod ranged;
+mod rewind;
 pub struct CombatPlugin;
 
 impl Plugin for CombatPlugin {
     fn build(&self, app: &mut App) {
-        app.add_network_message::<UpdateCombatModeRequest>()
-            .add_network_message::<CombatInput>()
+        app.add_network_message::<UpdateCombatModeRequest>("UpdateCombatModeRequest")
+            .add_network_message::<UpdateIntentRequest>("UpdateIntentRequest")
+            .add_network_message::<CombatInput>("CombatInput")
             .add_networked_component::<CombatMode, CombatModeClient>();
         if is_server(app) {
-            app.add_event::<CombatInputEvent>()
-                .add_systems(Update, (receive_combat_mode_request, handle_attack_request));
+            app.add_plugins(RewindPlugin)
+                .add_event::<CombatInputEvent>()
+                .add_event::<IntentInputEvent>()
+                .add_systems(
+                    Update,
+                    (
+                        receive_combat_mode_request,
+                        receive_intent_request,
+                        handle_attack_request,
+                        announce_melee_swings.after(handle_attack_request),
+                    ),
+                );
         } else {
+            #[cfg(feature = "client")]
             app.add_systems(
                 Update,
                 (
-                    client_toggle_combat_mode,
+                    (client_toggle_combat_mode, client_cycle_intent),
                     (
                         (client_calculate_aim, client_combat_input).chain(),
                         client_combat_mode_ui.run_if(has_window),
@@ -44,7 +86,64 @@ impl Plugin for CombatPlugin {
                     .chain(),
             );
         }
-        app.add_plugins(RangedPlugin);
+        app.add_plugins((
+            RangedPlugin,
+            IntentPlugin,
+            GrabPlugin,
+            CombatConfigPlugin,
+            PenetrationPlugin,
+        ));
+    }
+}
+
+/// What a creature attempts to do when clicking in combat mode.
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Intent {
+    #[default]
+    Help,
+    Disarm,
+    Grab,
+    Harm,
+}
+
+impl Intent {
+    fn next(self) -> Self {
+        match self {
+            Intent::Help => Intent::Disarm,
+            Intent::Disarm => Intent::Grab,
+            Intent::Grab => Intent::Harm,
+            Intent::Harm => Intent::Help,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Intent::Help => "HELP",
+            Intent::Disarm => "DISARM",
+            Intent::Grab => "GRAB",
+            Intent::Harm => "HARM",
+        }
+    }
+
+    /// The cooldown shown on the intent indicator
+    #[cfg(feature = "client")]
+    fn cooldown_key(self) -> Option<CooldownKey> {
+        match self {
+            Intent::Help => Some(intents::HELP_COOLDOWN_KEY),
+            Intent::Disarm => Some(intents::DISARM_COOLDOWN_KEY),
+            Intent::Grab => Some(grab::GRAB_COOLDOWN_KEY),
+            Intent::Harm => None,
+        }
+    }
+
+    #[cfg(feature = "client")]
+    fn color(self, accessibility: &AccessibilitySettings) -> egui::Color32 {
+        match self {
+            Intent::Help => accessibility.color(UiColor::Safe),
+            Intent::Disarm => egui::Color32::BLUE,
+            Intent::Grab => accessibility.color(UiColor::Warning),
+            Intent::Harm => accessibility.color(UiColor::Danger),
+        }
     }
 }
 
@@ -52,12 +151,27 @@ impl Plugin for CombatPlugin {
 #[networked(client = "CombatModeClient")]
 pub struct CombatMode {
     enabled: NetworkVar<bool>,
+    intent: NetworkVar<Intent>,
 }
 
 impl CombatMode {
     pub fn set(&mut self, enabled: bool) {
         *self.enabled = enabled;
     }
+
+    pub fn is_enabled(&self) -> bool {
+        *self.enabled
+    }
+
+    pub fn intent(&self) -> Intent {
+        *self.intent
+    }
+
+    pub fn set_intent(&mut self, intent: Intent) {
+        if *self.intent != intent {
+            *self.intent = intent;
+        }
+    }
 }
 
 #[derive(Component, Networked, TypeUuid, Default)]
@@ -65,14 +179,17 @@ impl CombatMode {
 #[uuid = "bfe1d314-6e1a-4e9d-b871-d8e9879e27ea"]
 pub struct CombatModeClient {
     enabled: ServerVar<bool>,
+    intent: ServerVar<Intent>,
     pub aim: Aim,
 }
 
+#[cfg(feature = "client")]
 #[derive(SystemParam)]
 pub struct ClientCombatModeStatus<'w, 's> {
     controlled: Query<'w, 's, &'static CombatModeClient, With<ClientControlled>>,
 }
 
+#[cfg(feature = "client")]
 impl<'w, 's> ClientCombatModeStatus<'w, 's> {
     pub fn is_enabled(&self) -> bool {
         self.controlled
@@ -80,6 +197,13 @@ impl<'w, 's> ClientCombatModeStatus<'w, 's> {
             .map(|mode| *mode.enabled)
             .unwrap_or(false)
     }
+
+    pub fn intent(&self) -> Intent {
+        self.controlled
+            .get_single()
+            .map(|mode| *mode.intent)
+            .unwrap_or_default()
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -106,12 +230,50 @@ fn receive_combat_mode_request(
         } else if event.message.enabled {
             commands.entity(entity).insert(CombatMode {
                 enabled: true.into(),
+                ..Default::default()
+            });
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct UpdateIntentRequest {
+    intent: Intent,
+}
+
+fn receive_intent_request(
+    mut messages: EventReader<MessageEvent<UpdateIntentRequest>>,
+    players: Res<Players>,
+    controlled: Res<ClientControls>,
+    mut modes: Query<&mut CombatMode>,
+    mut commands: Commands,
+) {
+    for event in messages.iter() {
+        let Some(player) = players.get(event.connection) else {
+            continue;
+        };
+        let Some(entity) = controlled.controlled_entity(player.id) else {
+            continue;
+        };
+        if let Ok(mut mode) = modes.get_mut(entity) {
+            mode.set_intent(event.message.intent);
+        } else {
+            commands.entity(entity).insert(CombatMode {
+                intent: event.message.intent.into(),
+                ..Default::default()
             });
         }
     }
 }
 
-fn client_combat_mode_ui(mut contexts: EguiContexts, status: ClientCombatModeStatus) {
+#[cfg(feature = "client")]
+fn client_combat_mode_ui(
+    mut contexts: EguiContexts,
+    status: ClientCombatModeStatus,
+    cooldowns: Res<OwnCooldowns>,
+    network_time: Res<ClientNetworkTime>,
+    accessibility: Res<AccessibilitySettings>,
+) {
     // Show UI only if combat mode is enabled
     if !status.is_enabled() {
         return;
@@ -122,13 +284,26 @@ fn client_combat_mode_ui(mut contexts: EguiContexts, status: ClientCombatModeSta
             ui.vertical_centered_justified(|ui| {
                 ui.label(
                     egui::RichText::new("COMBAT MODE")
-                        .color(egui::Rgba::RED)
+                        .color(accessibility.color(UiColor::Danger))
                         .size(21.0),
                 );
+                let intent = status.intent();
+                let label = ui.label(
+                    egui::RichText::new(intent.label())
+                        .color(intent.color(&accessibility))
+                        .size(16.0),
+                );
+                let remaining = intent
+                    .cooldown_key()
+                    .and_then(|key| cooldowns.remaining(key, network_time.interpolated_tick()));
+                if let Some(remaining) = remaining {
+                    paint_sweep(ui, label.rect, remaining);
+                }
             });
         });
 }
 
+#[cfg(feature = "client")]
 fn client_toggle_combat_mode(
     keys: Res<Input<KeyCode>>,
     status: ClientCombatModeStatus,
@@ -145,6 +320,21 @@ fn client_toggle_combat_mode(
     });
 }
 
+#[cfg(feature = "client")]
+fn client_cycle_intent(
+    keys: Res<Input<KeyCode>>,
+    status: ClientCombatModeStatus,
+    mut sender: MessageSender,
+) {
+    if !keys.just_pressed(KeyCode::G) {
+        return;
+    }
+
+    sender.send_to_server(&UpdateIntentRequest {
+        intent: status.intent().next(),
+    });
+}
+
 #[derive(Default, Clone, Copy, Serialize, Deserialize)]
 pub struct Aim {
     pub target_position: Vec3,
@@ -152,10 +342,17 @@ pub struct Aim {
     pub origin: Vec3,
 }
 
+impl Finite for Aim {
+    fn is_finite(&self) -> bool {
+        self.target_position.is_finite() && self.origin.is_finite()
+    }
+}
+
 /// At what height ranged weapons are aimed.
 // TODO: Replace with height depending on character
 const RANGED_AIM_HEIGHT: f32 = 0.85;
 
+#[cfg(feature = "client")]
 fn client_calculate_aim(
     mut players: Query<(&mut CombatModeClient, &GlobalTransform), With<ClientControlled>>,
     windows: Query<&Window, With<PrimaryWindow>>,
@@ -195,13 +392,19 @@ fn client_calculate_aim(
 #[derive(Clone, Copy, Serialize, Deserialize)]
 struct CombatInput {
     aim: Aim,
+    /// The intent the client had selected when clicking
+    intent: Intent,
     primary_attack: bool,
+    /// The server tick the client was displaying when clicking, hits are checked against it
+    view_tick: f32,
 }
 
+#[cfg(feature = "client")]
 fn client_combat_input(
     combat_mode: ClientCombatModeStatus,
     buttons: Res<Input<MouseButton>>,
     players: Query<&CombatModeClient, With<ClientControlled>>,
+    network_time: Res<ClientNetworkTime>,
     mut sender: MessageSender,
 ) {
     if !buttons.just_pressed(MouseButton::Left) {
@@ -217,13 +420,15 @@ fn client_combat_input(
     // TODO: Should be unreliable and buffered, including prediction
     sender.send_to_server(&CombatInput {
         aim: combat.aim,
+        intent: *combat.intent,
         primary_attack: true,
+        view_tick: network_time.interpolated_tick(),
     });
 }
 
+/// A harmful attack by a creature
 #[derive(Event)]
 struct CombatInputEvent {
-    #[allow(dead_code)]
     actor: Entity,
     input: CombatInput,
     wielded_weapon: Option<Entity>,
@@ -231,35 +436,147 @@ struct CombatInputEvent {
     used_hand: Option<Entity>,
 }
 
+/// A non-harmful combat click by a creature
+#[derive(Event)]
+struct IntentInputEvent {
+    actor: Entity,
+    intent: Intent,
+    aim: Aim,
+    held_item: Option<Entity>,
+    /// The clamped tick to check the target at
+    view_tick: f32,
+}
+
+/// Lets other players see attacks that aren't made with a gun, and wears down the weapon used.
+/// Every swing also tires out the attacker.
+#[allow(clippy::too_many_arguments)]
+fn announce_melee_swings(
+    mut input: EventReader<CombatInputEvent>,
+    guns: Query<(), With<ranged::Gun>>,
+    mut stamina: Query<&mut Stamina>,
+    config: Res<StaminaConfig>,
+    time: Res<Time>,
+    mut actions: EventWriter<ActorActionEvent>,
+    mut damage: EventWriter<ItemDamageEvent>,
+) {
+    for event in input.iter() {
+        if !event.input.primary_attack || event.wielded_weapon.is_some_and(|w| guns.contains(w)) {
+            continue;
+        }
+
+        if let Ok(mut stamina) = stamina.get_mut(event.actor) {
+            stamina.drain(config.swing_cost, time.elapsed_seconds());
+        }
+
+        actions.send(ActorActionEvent {
+            actor: event.actor,
+            action: ActorAction::Swing {
+                direction: direction_towards(
+                    event.input.aim.origin,
+                    event.input.aim.target_position,
+                ),
+            },
+        });
+        if let Some(weapon) = event.wielded_weapon {
+            damage.send(ItemDamageEvent::used(weapon, event.actor));
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 fn handle_attack_request(
     mut events: EventReader<MessageEvent<CombatInput>>,
     players: Res<Players>,
     controls: Res<ClientControls>,
+    modes: Query<&CombatMode>,
     bodies: Query<&Hands>,
     hand_query: Query<(Entity, &Container), With<Hand>>,
     mut attack_event: EventWriter<CombatInputEvent>,
+    mut intent_event: EventWriter<IntentInputEvent>,
+    grabbed: Query<&GrabbedBy>,
+    stunned: Query<(), With<Stunned>>,
+    stamina: Query<&Stamina>,
+    lag: LagCompensation,
+    mut invalid: EventWriter<InvalidMessage>,
+    mut feedback: Feedback,
 ) {
     for event in events.iter() {
+        // The aim is also used as the target of throws
+        if !event.message.aim.is_finite() || !event.message.view_tick.is_finite() {
+            invalid.send(InvalidMessage {
+                connection: event.connection,
+                reason: "non-finite aim",
+            });
+            continue;
+        }
         let Some(player) = players.get(event.connection).map(|p| p.id) else {
             continue;
         };
         let Some(player_entity) = controls.controlled_entity(player) else {
             continue;
         };
+        let Ok(mode) = modes.get(player_entity) else {
+            continue;
+        };
+
+        // Ignore clicks made before the client knew about an intent change
+        let intent = mode.intent();
+        if intent != event.message.intent {
+            debug!(connection = ?event.connection, ?intent, "Combat input with outdated intent");
+            continue;
+        }
+
+        // Attacks aren't queued like interactions, a click during a stun would be a free hit after it
+        if intent != Intent::Help && stunned.contains(player_entity) {
+            feedback.send(
+                event.connection,
+                FeedbackKind::Blocked,
+                "combat.stunned",
+                &[],
+            );
+            continue;
+        }
+        if intent != Intent::Help && stamina.get(player_entity).is_ok_and(|s| s.is_exhausted()) {
+            feedback.send(
+                event.connection,
+                FeedbackKind::Blocked,
+                "combat.exhausted",
+                &[],
+            );
+            continue;
+        }
 
         let hand = bodies
             .get(player_entity)
             .ok()
             .and_then(|hands| hand_query.get(hands.active_hand()).ok());
-        let wielded_weapon =
-            hand.and_then(|(_, container)| container.iter().next().map(|(_, item)| *item));
+        // Held items can't be used while held tightly
+        let restrained = grabbed
+            .get(player_entity)
+            .is_ok_and(|g| g.restrains_hands());
+        let held_item = hand
+            .filter(|_| !restrained)
+            .and_then(|(_, container)| container.iter().next().map(|(_, item)| *item));
         let used_hand = hand.unzip().0;
+        let view_tick = lag.clamp_tick(event.message.view_tick, player_entity);
 
-        attack_event.send(CombatInputEvent {
-            actor: player_entity,
-            input: event.message,
-            wielded_weapon,
-            used_hand,
-        });
+        match intent {
+            Intent::Harm => attack_event.send(CombatInputEvent {
+                actor: player_entity,
+                input: CombatInput {
+                    view_tick,
+                    ..event.message
+                },
+                wielded_weapon: held_item,
+                used_hand,
+            }),
+            Intent::Help | Intent::Disarm | Intent::Grab => intent_event.send(IntentInputEvent {
+                actor: player_entity,
+                intent,
+                aim: event.message.aim,
+                held_item,
+                view_tick,
+            }),
+        }
     }
 }
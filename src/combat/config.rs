@@ -0,0 +1,528 @@
+use std::fmt;
+
+use bevy::{prelude::*, utils::HashMap};
+use networking::is_server;
+use serde::Deserialize;
+
+use crate::{
+    body::{health::receive_damage, self_or_ancestor},
+    config::ServerConfig,
+    console::{CommandContext, CommandResult, ConsoleAppExt, ConsoleCommand, PermissionLevel},
+    job::Affiliation,
+    replay::{read_config_file, RecordedFiles},
+    rng::GameRng,
+};
+
+use super::{
+    damage::{AffectedEntity, Attack, AttackSource, KineticDamage},
+    penetration::ShotMaterial,
+};
+
+/// Scales damage by weapon falloff, variance and friendly fire before it is applied.
+pub(super) struct CombatConfigPlugin;
+
+impl Plugin for CombatConfigPlugin {
+    fn build(&self, app: &mut App) {
+        if !is_server(app) {
+            return;
+        }
+
+        let path = app
+            .world
+            .get_resource::<ServerConfig>()
+            .and_then(|config| config.combat.clone());
+        let recorded = app.world.get_resource::<RecordedFiles>();
+        let config = match path
+            .as_deref()
+            .map(|path| CombatConfig::load(recorded, path))
+        {
+            Some(Ok(config)) => config,
+            Some(Err(err)) => {
+                error!("Error loading combat config: {}", err);
+                CombatConfig::default()
+            }
+            None => CombatConfig::default(),
+        };
+
+        app.insert_resource(config)
+            .add_console_command(ConsoleCommand {
+                name: "reloadconfig",
+                description: "Reloads the combat configuration",
+                parameters: &[],
+                permission: PermissionLevel::Admin,
+                handler: reload_command,
+            })
+            .add_systems(Update, scale_damage.before(receive_damage));
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FriendlyFirePolicy {
+    /// Attacks on the same team do no damage
+    Off,
+    /// Attacks on the same team are multiplied by `friendly_fire_multiplier`
+    Reduced,
+    #[default]
+    On,
+}
+
+/// Combat tuning loaded from the file referenced in the server config.
+#[derive(Deserialize, Resource)]
+pub struct CombatConfig {
+    #[serde(default)]
+    pub friendly_fire: FriendlyFirePolicy,
+    #[serde(default = "CombatConfig::default_friendly_fire_multiplier")]
+    pub friendly_fire_multiplier: f32,
+    /// Settings for weapons by their id
+    #[serde(default)]
+    pub weapons: HashMap<String, WeaponConfig>,
+    #[serde(default)]
+    pub penetration: PenetrationConfig,
+    #[serde(default)]
+    pub cover: CoverConfig,
+}
+
+impl Default for CombatConfig {
+    fn default() -> Self {
+        Self {
+            friendly_fire: Default::default(),
+            friendly_fire_multiplier: Self::default_friendly_fire_multiplier(),
+            weapons: Default::default(),
+            penetration: Default::default(),
+            cover: Default::default(),
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
+pub struct WeaponConfig {
+    pub falloff: Option<Falloff>,
+    /// Damage is multiplied by a random value in this range
+    pub variance: Option<(f32, f32)>,
+    pub accuracy: Option<Accuracy>,
+    /// Penetration budget of the projectiles, uses `penetration.default_budget` if not set
+    pub penetration: Option<f32>,
+    /// Stamina taken from creatures by every hit, like disabler shots
+    pub stamina_damage: Option<f32>,
+    /// Hits only take stamina and don't wound
+    #[serde(default)]
+    pub nonlethal: bool,
+}
+
+/// Full damage up to `full_until` meters, then linearly less until none at `zero_at`.
+#[derive(Deserialize, Clone, Copy)]
+pub struct Falloff {
+    pub full_until: f32,
+    pub zero_at: f32,
+}
+
+impl Falloff {
+    pub fn multiplier(&self, distance: f32) -> f32 {
+        if distance <= self.full_until {
+            1.0
+        } else if distance >= self.zero_at {
+            0.0
+        } else {
+            1.0 - (distance - self.full_until) / (self.zero_at - self.full_until)
+        }
+    }
+}
+
+/// How far shots stray from where a gun is aimed.
+/// Spreads are the full angle of the cone in degrees.
+#[derive(Deserialize, Clone, Copy)]
+pub struct Accuracy {
+    /// Spread right after starting to aim at something
+    pub base_spread: f32,
+    /// Spread after aiming at the same point for `steady_seconds`
+    pub min_spread: f32,
+    /// Spread is never larger than this, no matter the movement and recoil
+    pub max_spread: f32,
+    pub steady_seconds: f32,
+    /// Spread added per meter per second the shooter is moving
+    pub movement_spread: f32,
+    /// Spread added by each shot
+    pub recoil: f32,
+    /// Degrees of recoil recovered per second
+    pub recoil_recovery: f32,
+}
+
+impl Accuracy {
+    /// The spread of a shot after aiming steadily for some time while moving at a speed.
+    pub fn spread(&self, steady_time: f32, speed: f32, recoil: f32) -> f32 {
+        let steadiness = if self.steady_seconds > 0.0 {
+            (steady_time / self.steady_seconds).clamp(0.0, 1.0)
+        } else {
+            1.0
+        };
+        let aimed = self.base_spread + (self.min_spread - self.base_spread) * steadiness;
+        (aimed + speed * self.movement_spread + recoil).clamp(self.min_spread, self.max_spread)
+    }
+
+    /// Recoil left after recovering for some time.
+    pub fn recover(&self, recoil: f32, seconds: f32) -> f32 {
+        (recoil - self.recoil_recovery * seconds).max(0.0)
+    }
+}
+
+/// How projectiles pass through thin obstacles like windows, tables and bodies.
+/// Every obstacle uses up some of the projectile's budget, it stops once the budget runs out.
+#[derive(Deserialize, Clone, Copy)]
+#[serde(default)]
+pub struct PenetrationConfig {
+    /// Budget of weapons that don't set their own
+    pub default_budget: f32,
+    pub glass: MaterialPenetration,
+    pub reinforced_glass: MaterialPenetration,
+    pub grille: MaterialPenetration,
+    pub furniture: MaterialPenetration,
+    pub flesh: MaterialPenetration,
+}
+
+impl Default for PenetrationConfig {
+    fn default() -> Self {
+        Self {
+            default_budget: 1.0,
+            glass: MaterialPenetration::new(0.3, 0.3),
+            reinforced_glass: MaterialPenetration::new(0.8, 0.5),
+            grille: MaterialPenetration::new(0.1, 0.1),
+            furniture: MaterialPenetration::new(0.4, 0.4),
+            flesh: MaterialPenetration::new(0.6, 0.5),
+        }
+    }
+}
+
+impl PenetrationConfig {
+    pub fn material(&self, material: ShotMaterial) -> MaterialPenetration {
+        match material {
+            ShotMaterial::Glass => self.glass,
+            ShotMaterial::ReinforcedGlass => self.reinforced_glass,
+            ShotMaterial::Grille => self.grille,
+            ShotMaterial::Furniture => self.furniture,
+            ShotMaterial::Flesh => self.flesh,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Copy)]
+pub struct MaterialPenetration {
+    /// Budget used up by passing through
+    pub cost: f32,
+    /// Fraction of the remaining damage lost by passing through
+    pub damage_reduction: f32,
+}
+
+impl MaterialPenetration {
+    fn new(cost: f32, damage_reduction: f32) -> Self {
+        Self {
+            cost,
+            damage_reduction,
+        }
+    }
+}
+
+/// Crouching behind cover makes shots coming from the other side miss sometimes.
+#[derive(Deserialize, Clone, Copy)]
+#[serde(default)]
+pub struct CoverConfig {
+    /// Chance a shot crossing the cover misses, between 0 and 1
+    pub miss_chance: f32,
+    /// How far in meters behind the cover a creature can be and still be covered
+    pub max_distance: f32,
+}
+
+impl Default for CoverConfig {
+    fn default() -> Self {
+        Self {
+            miss_chance: 0.5,
+            max_distance: 1.5,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum CombatConfigError {
+    Io(std::io::Error),
+    Parse(toml::de::Error),
+    Invalid(String),
+}
+
+impl fmt::Display for CombatConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CombatConfigError::Io(err) => write!(f, "could not read file: {}", err),
+            CombatConfigError::Parse(err) => write!(f, "{}", err),
+            CombatConfigError::Invalid(reason) => f.write_str(reason),
+        }
+    }
+}
+
+impl CombatConfig {
+    fn default_friendly_fire_multiplier() -> f32 {
+        0.5
+    }
+
+    /// Loads the config file, or its copy in the recording when simulating a round.
+    pub fn load(recorded: Option<&RecordedFiles>, path: &str) -> Result<Self, CombatConfigError> {
+        let text = read_config_file(recorded, path).map_err(CombatConfigError::Io)?;
+        Self::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, CombatConfigError> {
+        let config: CombatConfig = toml::from_str(text).map_err(CombatConfigError::Parse)?;
+        config.validate().map_err(CombatConfigError::Invalid)?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if !(0.0..=1.0).contains(&self.friendly_fire_multiplier) {
+            return Err(format!(
+                "friendly_fire_multiplier ({}) must be between 0 and 1",
+                self.friendly_fire_multiplier
+            ));
+        }
+
+        let penetration = &self.penetration;
+        if penetration.default_budget < 0.0 {
+            return Err(format!(
+                "penetration.default_budget ({}) must not be negative",
+                penetration.default_budget
+            ));
+        }
+        for (name, material) in [
+            ("glass", penetration.glass),
+            ("reinforced_glass", penetration.reinforced_glass),
+            ("grille", penetration.grille),
+            ("furniture", penetration.furniture),
+            ("flesh", penetration.flesh),
+        ] {
+            if material.cost < 0.0 || !(0.0..=1.0).contains(&material.damage_reduction) {
+                return Err(format!(
+                    "penetration.{}: cost ({}) must not be negative and damage_reduction ({}) must be between 0 and 1",
+                    name, material.cost, material.damage_reduction
+                ));
+            }
+        }
+        if !(0.0..=1.0).contains(&self.cover.miss_chance) || self.cover.max_distance < 0.0 {
+            return Err(format!(
+                "cover: miss_chance ({}) must be between 0 and 1 and max_distance ({}) must not be negative",
+                self.cover.miss_chance, self.cover.max_distance
+            ));
+        }
+
+        for (id, weapon) in self.weapons.iter() {
+            if let Some(falloff) = weapon.falloff {
+                if falloff.full_until < 0.0 {
+                    return Err(format!(
+                        "weapons.{}.falloff: full_until ({}) must not be negative",
+                        id, falloff.full_until
+                    ));
+                }
+                if falloff.zero_at < falloff.full_until {
+                    return Err(format!(
+                        "weapons.{}.falloff: zero_at ({}) must not be less than full_until ({})",
+                        id, falloff.zero_at, falloff.full_until
+                    ));
+                }
+            }
+            if let Some((min, max)) = weapon.variance {
+                if min < 0.0 || max < min {
+                    return Err(format!(
+                        "weapons.{}.variance: ({}, {}) must be a non-negative range with min <= max",
+                        id, min, max
+                    ));
+                }
+            }
+            if weapon.penetration.is_some_and(|budget| budget < 0.0) {
+                return Err(format!("weapons.{}.penetration must not be negative", id));
+            }
+            if weapon.stamina_damage.is_some_and(|damage| damage < 0.0) {
+                return Err(format!(
+                    "weapons.{}.stamina_damage must not be negative",
+                    id
+                ));
+            }
+            if let Some(accuracy) = weapon.accuracy {
+                if accuracy.min_spread < 0.0
+                    || accuracy.base_spread < accuracy.min_spread
+                    || accuracy.max_spread < accuracy.base_spread
+                    || accuracy.max_spread > 180.0
+                {
+                    return Err(format!(
+                        "weapons.{}.accuracy: spreads must satisfy 0 <= min_spread ({}) <= base_spread ({}) <= max_spread ({}) <= 180",
+                        id, accuracy.min_spread, accuracy.base_spread, accuracy.max_spread
+                    ));
+                }
+                let rates = [
+                    accuracy.steady_seconds,
+                    accuracy.movement_spread,
+                    accuracy.recoil,
+                    accuracy.recoil_recovery,
+                ];
+                if rates.iter().any(|&value| value < 0.0) {
+                    return Err(format!(
+                        "weapons.{}.accuracy: steady_seconds, movement_spread, recoil and recoil_recovery must not be negative",
+                        id
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The accuracy settings of a weapon, if it has any.
+    pub fn accuracy(&self, weapon_id: &str) -> Option<Accuracy> {
+        self.weapons.get(weapon_id).and_then(|w| w.accuracy)
+    }
+
+    /// The penetration budget of a weapon's projectiles.
+    pub fn penetration_budget(&self, weapon_id: &str) -> f32 {
+        self.weapons
+            .get(weapon_id)
+            .and_then(|w| w.penetration)
+            .unwrap_or(self.penetration.default_budget)
+    }
+
+    /// Stamina taken by every hit of a weapon, and if its hits don't wound.
+    pub fn stamina_damage(&self, weapon_id: &str) -> (f32, bool) {
+        self.weapons.get(weapon_id).map_or((0.0, false), |w| {
+            (w.stamina_damage.unwrap_or_default(), w.nonlethal)
+        })
+    }
+
+    /// How much of an attack's damage is dealt to the target.
+    pub fn damage_multiplier(
+        &self,
+        source: &AttackSource,
+        same_team: bool,
+        rng: &mut fastrand::Rng,
+    ) -> f32 {
+        let mut multiplier = 1.0;
+
+        if let Some(weapon) = source.weapon.as_ref().and_then(|id| self.weapons.get(id)) {
+            if let (Some(falloff), Some(distance)) = (weapon.falloff, source.distance) {
+                multiplier *= falloff.multiplier(distance);
+            }
+            if let Some((min, max)) = weapon.variance {
+                multiplier *= min + (max - min) * rng.f32();
+            }
+        }
+
+        if same_team {
+            multiplier *= match self.friendly_fire {
+                FriendlyFirePolicy::Off => 0.0,
+                FriendlyFirePolicy::Reduced => self.friendly_fire_multiplier,
+                FriendlyFirePolicy::On => 1.0,
+            };
+        }
+
+        multiplier
+    }
+}
+
+fn reload_command(world: &mut World, _: &CommandContext) -> CommandResult {
+    let path = world
+        .resource::<ServerConfig>()
+        .combat
+        .clone()
+        .ok_or_else(|| "No combat config is set in the server config".to_owned())?;
+    let config = CombatConfig::load(world.get_resource::<RecordedFiles>(), &path)
+        .map_err(|err| format!("{}: {}", path, err))?;
+    world.insert_resource(config);
+    info!(path = %path, "Reloaded combat config");
+    Ok(format!("Reloaded {}", path))
+}
+
+fn scale_damage(
+    mut attacks: Query<(&AffectedEntity, &AttackSource, &mut KineticDamage), Added<Attack>>,
+    affiliations: Query<&Affiliation>,
+    parents: Query<&Parent>,
+    config: Res<CombatConfig>,
+    mut rng: ResMut<GameRng>,
+) {
+    for (affected, source, mut damage) in attacks.iter_mut() {
+        let target_team = self_or_ancestor(&parents, affected.0, |e| affiliations.contains(e))
+            .and_then(|e| affiliations.get(e).ok());
+        let same_team = target_team
+            .zip(affiliations.get(source.attacker).ok())
+            .is_some_and(|(target, attacker)| target.team == attacker.team);
+
+        let multiplier = config.damage_multiplier(source, same_team, rng.stream("damage"));
+        damage.scale *= multiplier;
+        debug!(
+            weapon = ?source.weapon,
+            distance = ?source.distance,
+            same_team,
+            multiplier,
+            "Scaled attack damage"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+friendly_fire = "reduced"
+friendly_fire_multiplier = 0.25
+
+[weapons.rifle]
+falloff = { full_until = 10.0, zero_at = 40.0 }
+"#;
+
+    fn shot(distance: f32) -> AttackSource {
+        AttackSource {
+            attacker: Entity::from_raw(0),
+            instigator: None,
+            weapon: Some("rifle".into()),
+            distance: Some(distance),
+        }
+    }
+
+    #[test]
+    fn damage_falls_off_with_distance() {
+        let config = CombatConfig::parse(CONFIG).unwrap();
+        let mut rng = fastrand::Rng::with_seed(0);
+
+        assert_eq!(config.damage_multiplier(&shot(2.0), false, &mut rng), 1.0);
+        // 20 of the 30 meters between full and no damage
+        let far = config.damage_multiplier(&shot(30.0), false, &mut rng);
+        assert!((far - 1.0 / 3.0).abs() < 1e-5, "multiplier {}", far);
+        assert_eq!(config.damage_multiplier(&shot(50.0), false, &mut rng), 0.0);
+    }
+
+    #[test]
+    fn reduced_friendly_fire_scales_damage() {
+        let config = CombatConfig::parse(CONFIG).unwrap();
+        let mut rng = fastrand::Rng::with_seed(0);
+
+        assert_eq!(config.damage_multiplier(&shot(2.0), true, &mut rng), 0.25);
+        // Applies on top of the falloff
+        let far = config.damage_multiplier(&shot(30.0), true, &mut rng);
+        assert!((far - 0.25 / 3.0).abs() < 1e-5, "multiplier {}", far);
+
+        let off = CombatConfig::parse(r#"friendly_fire = "off""#).unwrap();
+        assert_eq!(off.damage_multiplier(&shot(2.0), true, &mut rng), 0.0);
+        assert_eq!(off.damage_multiplier(&shot(2.0), false, &mut rng), 1.0);
+    }
+
+    #[test]
+    fn inverted_falloff_is_rejected() {
+        let result = CombatConfig::parse(
+            r#"
+[weapons.pistol]
+falloff = { full_until = 20.0, zero_at = 5.0 }
+"#,
+        );
+        let Err(err) = result else {
+            panic!("inverted falloff was accepted");
+        };
+        assert_eq!(
+            err.to_string(),
+            "weapons.pistol.falloff: zero_at (5) must not be less than full_until (20)"
+        );
+    }
+}
@@ -14,6 +14,8 @@ pub struct KineticDamage {
     /// Object mass in kg
     pub mass: f32,
     pub shape: KineticShape,
+    /// Multiplier for the damage dealt, adjusted by falloff and friendly fire
+    pub scale: f32,
 }
 
 /// Marker component for entities representing an attack / impact
@@ -22,3 +24,16 @@ pub struct Attack;
 
 #[derive(Component)]
 pub struct AffectedEntity(pub Entity);
+
+/// Who made an attack and with what
+#[derive(Component)]
+pub struct AttackSource {
+    pub attacker: Entity,
+    /// Who is responsible if the attacker acted for someone else,
+    /// like the player that primed a grenade or threw an item
+    pub instigator: Option<Entity>,
+    /// Weapon id used to look up its combat config
+    pub weapon: Option<String>,
+    /// Distance in meters from where the attack started, for ranged attacks
+    pub distance: Option<f32>,
+}
@@ -0,0 +1,765 @@
+use bevy::{math::Vec3Swizzles, prelude::*, reflect::TypeUuid};
+use bevy_rapier3d::prelude::{CollisionGroups, QueryFilter, RapierContext};
+use maps::{world_to_tile, TileMap};
+use networking::{
+    component::AppExt,
+    is_server,
+    messaging::{MessageReceivers, MessageSender},
+    spawning::ClientControls,
+    time::ServerNetworkTime,
+    variable::{NetworkVar, ServerVar},
+    Networked, Players,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    actions::direction_towards,
+    body::{
+        health::{BrainState, BrainStateEvent},
+        Body,
+    },
+    communication::EmoteEvent,
+    cooldown::{CooldownKey, Cooldowns},
+    movement::{speed::SpeedModifiers, ForcePositionMessage, Stunned},
+    safe_zone::Safety,
+};
+
+#[cfg(feature = "client")]
+use {
+    crate::ui::has_window,
+    bevy_egui::{egui, EguiContexts},
+    networking::spawning::ClientControlled,
+};
+
+use super::{
+    intents::{find_target, Pulling, INTENT_TARGET_RADIUS},
+    rewind::LagCompensation,
+    CombatInputEvent, Intent, IntentInputEvent,
+};
+
+/// Grabbing creatures, tightening the grip and throwing them.
+pub(super) struct GrabPlugin;
+
+impl Plugin for GrabPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Table>()
+            .add_networked_component::<Grabbing, GrabbingClient>()
+            .add_networked_component::<GrabbedBy, GrabbedByClient>();
+        if is_server(app) {
+            app.add_event::<ThrowEvent>().add_systems(
+                Update,
+                (
+                    grab_intent,
+                    throw_grabbed,
+                    resist_escalation,
+                    progress_escalation,
+                    release_on_death,
+                    release_broken_grabs,
+                    grab_speed,
+                )
+                    .chain(),
+            );
+        } else {
+            #[cfg(feature = "client")]
+            app.add_systems(Update, grab_status_ui.run_if(has_window));
+        }
+    }
+}
+
+const GRAB_COOLDOWN: f32 = 1.0;
+pub(super) const GRAB_COOLDOWN_KEY: CooldownKey = "intent.grab";
+/// Speed of creatures held in an aggressive grab, relative to their normal speed
+const GRABBED_SPEED_MULTIPLIER: f32 = 0.4;
+/// How close the target has to stay while the grip is tightened
+const ESCALATION_REACH: f32 = 1.5;
+/// How far a lifted creature is thrown, unless a wall is in the way
+const THROW_DISTANCE: f32 = 4.0;
+/// Space kept between a thrown creature and the wall it hits
+const THROW_WALL_MARGIN: f32 = 0.5;
+const THROW_STUN_SECONDS: f32 = 2.0;
+const SLAM_STUN_SECONDS: f32 = 3.0;
+
+/// How tightly a creature is held.
+#[derive(Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Serialize, Deserialize)]
+pub enum GrabStage {
+    /// Pulled along, but free to act
+    #[default]
+    Passive,
+    /// Slowed and can't use held items
+    Aggressive,
+    /// Can be thrown or slammed onto a table
+    Lifted,
+}
+
+impl GrabStage {
+    fn next(self) -> Option<Self> {
+        match self {
+            GrabStage::Passive => Some(GrabStage::Aggressive),
+            GrabStage::Aggressive => Some(GrabStage::Lifted),
+            GrabStage::Lifted => None,
+        }
+    }
+
+    /// Seconds it takes to tighten the grip to this stage
+    fn escalation_seconds(self) -> f32 {
+        match self {
+            GrabStage::Passive => 0.0,
+            GrabStage::Aggressive => 1.5,
+            GrabStage::Lifted => 3.0,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            GrabStage::Passive => "PASSIVE",
+            GrabStage::Aggressive => "AGGRESSIVE",
+            GrabStage::Lifted => "LIFTED",
+        }
+    }
+}
+
+/// Marks furniture creatures can be slammed onto.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct Table;
+
+/// A creature holding another creature.
+#[derive(Component, Networked)]
+#[networked(client = "GrabbingClient")]
+pub struct Grabbing {
+    target: Entity,
+    stage: NetworkVar<GrabStage>,
+    /// The grip is being tightened
+    escalating: NetworkVar<bool>,
+    /// When the grip reaches the next stage
+    escalation_end: f32,
+}
+
+#[derive(Component, Default, TypeUuid, Networked)]
+#[uuid = "9b3f6c52-1d7e-4f0a-8c25-6e4b1a7d3f08"]
+#[networked(server = "Grabbing")]
+pub struct GrabbingClient {
+    stage: ServerVar<GrabStage>,
+    escalating: ServerVar<bool>,
+}
+
+/// A creature being held by another creature.
+#[derive(Component, Networked)]
+#[networked(client = "GrabbedByClient")]
+pub struct GrabbedBy {
+    grabber: Entity,
+    stage: NetworkVar<GrabStage>,
+}
+
+impl GrabbedBy {
+    /// If movement is slowed by the grab
+    pub fn slows_movement(&self) -> bool {
+        *self.stage >= GrabStage::Aggressive
+    }
+
+    /// If the creature is held too tightly to use items
+    pub fn restrains_hands(&self) -> bool {
+        *self.stage >= GrabStage::Aggressive
+    }
+}
+
+#[derive(Component, Default, TypeUuid, Networked)]
+#[uuid = "4e8a2d71-93c5-4b6f-a0d4-7f1c5e9b2a63"]
+#[networked(server = "GrabbedBy")]
+pub struct GrabbedByClient {
+    stage: ServerVar<GrabStage>,
+}
+
+impl GrabbedByClient {
+    /// If the creature is held too tightly to use items
+    pub fn restrains_hands(&self) -> bool {
+        *self.stage >= GrabStage::Aggressive
+    }
+}
+
+/// Ends a grab on both sides.
+fn release(commands: &mut Commands, grabber: Entity, target: Entity) {
+    if let Some(mut grabber) = commands.get_entity(grabber) {
+        grabber.remove::<(Grabbing, Pulling)>();
+    }
+    if let Some(mut target) = commands.get_entity(target) {
+        target.remove::<GrabbedBy>();
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn grab_intent(
+    mut events: EventReader<IntentInputEvent>,
+    bodies: Query<(Entity, &GlobalTransform), With<Body>>,
+    lag: LagCompensation,
+    mut cooldowns: Query<&mut Cooldowns>,
+    mut grabbing: Query<&mut Grabbing>,
+    grabbed: Query<&GrabbedBy>,
+    mut emotes: EventWriter<EmoteEvent>,
+    mut throws: EventWriter<ThrowEvent>,
+    safety: Safety,
+    time: Res<Time>,
+    network_time: Res<ServerNetworkTime>,
+    mut commands: Commands,
+) {
+    let now = time.elapsed_seconds();
+    for event in events.iter().filter(|e| e.intent == Intent::Grab) {
+        // Grabbing needs a free hand
+        if event.held_item.is_some() {
+            continue;
+        }
+        if !cooldowns
+            .get_mut(event.actor)
+            .is_ok_and(|mut c| c.try_start(GRAB_COOLDOWN_KEY, GRAB_COOLDOWN, &network_time))
+        {
+            continue;
+        }
+        let target = find_target(event, &bodies, &lag).filter(|t| !safety.is_protected(*t));
+
+        if let Ok(mut grab) = grabbing.get_mut(event.actor) {
+            // A lifted creature is thrown wherever the grabber is aiming
+            if *grab.stage == GrabStage::Lifted {
+                throws.send(ThrowEvent {
+                    grabber: event.actor,
+                    target: grab.target,
+                    aim: event.aim.target_position,
+                });
+                continue;
+            }
+
+            if target == Some(grab.target) {
+                // Grabbing the same creature again tightens the grip
+                if *grab.escalating {
+                    continue;
+                }
+                let Some(next) = grab.stage.next() else {
+                    continue;
+                };
+                *grab.escalating = true;
+                grab.escalation_end = now + next.escalation_seconds();
+                emotes.send(EmoteEvent {
+                    actor: event.actor,
+                    target: Some(grab.target),
+                    text: "starts tightening their grip on {target}!".into(),
+                });
+                continue;
+            }
+
+            // Grabbing anything else lets go first
+            let previous = grab.target;
+            release(&mut commands, event.actor, previous);
+            emotes.send(EmoteEvent {
+                actor: event.actor,
+                target: Some(previous),
+                text: "lets go of {target}.".into(),
+            });
+        }
+
+        let Some(target) = target else {
+            continue;
+        };
+        // Only one creature can hold another
+        if grabbed.contains(target) {
+            continue;
+        }
+
+        commands.entity(event.actor).insert((
+            Pulling { target },
+            Grabbing {
+                target,
+                stage: GrabStage::Passive.into(),
+                escalating: false.into(),
+                escalation_end: 0.0,
+            },
+        ));
+        commands.entity(target).insert(GrabbedBy {
+            grabber: event.actor,
+            stage: GrabStage::Passive.into(),
+        });
+        emotes.send(EmoteEvent {
+            actor: event.actor,
+            target: Some(target),
+            text: "grabs {target}.".into(),
+        });
+    }
+}
+
+/// The grabbed creature interrupts a tightening grip by attacking the grabber.
+fn resist_escalation(
+    mut attacks: EventReader<CombatInputEvent>,
+    mut intents: EventReader<IntentInputEvent>,
+    grabbed: Query<&GrabbedBy>,
+    mut grabbing: Query<&mut Grabbing>,
+    transforms: Query<&GlobalTransform>,
+    mut emotes: EventWriter<EmoteEvent>,
+) {
+    let resisting = attacks
+        .iter()
+        .map(|e| (e.actor, e.input.aim.target_position))
+        .chain(
+            intents
+                .iter()
+                .filter(|e| matches!(e.intent, Intent::Disarm | Intent::Harm))
+                .map(|e| (e.actor, e.aim.target_position)),
+        );
+
+    for (victim, aimed) in resisting {
+        let Ok(grabbed_by) = grabbed.get(victim) else {
+            continue;
+        };
+        let Ok(mut grab) = grabbing.get_mut(grabbed_by.grabber) else {
+            continue;
+        };
+        if !*grab.escalating {
+            continue;
+        }
+        let Ok(grabber_transform) = transforms.get(grabbed_by.grabber) else {
+            continue;
+        };
+        if grabber_transform.translation().xz().distance(aimed.xz()) > INTENT_TARGET_RADIUS {
+            continue;
+        }
+
+        *grab.escalating = false;
+        emotes.send(EmoteEvent {
+            actor: victim,
+            target: Some(grabbed_by.grabber),
+            text: "struggles against {target}'s grip!".into(),
+        });
+    }
+}
+
+fn progress_escalation(
+    mut grabbing: Query<(Entity, &mut Grabbing, &GlobalTransform)>,
+    mut grabbed: Query<(&mut GrabbedBy, &GlobalTransform)>,
+    mut emotes: EventWriter<EmoteEvent>,
+    time: Res<Time>,
+) {
+    let now = time.elapsed_seconds();
+    for (grabber, mut grab, transform) in grabbing.iter_mut() {
+        if !*grab.escalating {
+            continue;
+        }
+        let Ok((mut grabbed_by, target_transform)) = grabbed.get_mut(grab.target) else {
+            continue;
+        };
+
+        // Moving away before the grip is tight escapes the escalation
+        let distance = transform
+            .translation()
+            .xz()
+            .distance(target_transform.translation().xz());
+        if distance > ESCALATION_REACH {
+            *grab.escalating = false;
+            emotes.send(EmoteEvent {
+                actor: grab.target,
+                target: Some(grabber),
+                text: "pulls away from {target}'s grip!".into(),
+            });
+            continue;
+        }
+
+        if now < grab.escalation_end {
+            continue;
+        }
+        let Some(next) = grab.stage.next() else {
+            *grab.escalating = false;
+            continue;
+        };
+        *grab.stage = next;
+        *grab.escalating = false;
+        *grabbed_by.stage = next;
+        emotes.send(EmoteEvent {
+            actor: grabber,
+            target: Some(grab.target),
+            text: match next {
+                GrabStage::Aggressive => "grabs {target} aggressively!",
+                _ => "lifts {target} up!",
+            }
+            .into(),
+        });
+    }
+}
+
+/// Dead creatures can't hold on or be held.
+fn release_on_death(
+    mut events: EventReader<BrainStateEvent>,
+    parents: Query<&Parent>,
+    grabbing: Query<&Grabbing>,
+    grabbed: Query<&GrabbedBy>,
+    mut commands: Commands,
+) {
+    for event in events.iter().filter(|e| e.new_state == BrainState::Dead) {
+        for creature in parents.iter_ancestors(event.brain) {
+            if let Ok(grab) = grabbing.get(creature) {
+                release(&mut commands, creature, grab.target);
+            }
+            if let Ok(grabbed_by) = grabbed.get(creature) {
+                release(&mut commands, grabbed_by.grabber, creature);
+            }
+        }
+    }
+}
+
+/// Resets the grab when the pull it's built on breaks or either creature disappears.
+fn release_broken_grabs(
+    grabbing: Query<(Entity, &Grabbing, Option<&Pulling>)>,
+    grabbed: Query<(Entity, &GrabbedBy)>,
+    mut commands: Commands,
+) {
+    for (grabber, grab, pulling) in grabbing.iter() {
+        let pulled = pulling.map(|p| p.target) == Some(grab.target);
+        if !pulled || !grabbed.contains(grab.target) {
+            release(&mut commands, grabber, grab.target);
+        }
+    }
+    for (target, grabbed_by) in grabbed.iter() {
+        if !grabbing.contains(grabbed_by.grabber) {
+            commands.entity(target).remove::<GrabbedBy>();
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn grab_speed(
+    mut grabbed: Query<
+        (&GrabbedBy, &mut SpeedModifiers),
+        Or<(Changed<GrabbedBy>, Added<SpeedModifiers>)>,
+    >,
+    mut released: RemovedComponents<GrabbedBy>,
+    mut free: Query<&mut SpeedModifiers, Without<GrabbedBy>>,
+) {
+    for (grabbed_by, mut modifiers) in grabbed.iter_mut() {
+        let multiplier = if grabbed_by.slows_movement() {
+            GRABBED_SPEED_MULTIPLIER
+        } else {
+            1.0
+        };
+        modifiers.set_multiplier("grabbed", multiplier);
+    }
+    for entity in released.iter() {
+        if let Ok(mut modifiers) = free.get_mut(entity) {
+            modifiers.remove("grabbed");
+        }
+    }
+}
+
+/// A lifted creature is thrown towards where the grabber aimed.
+#[derive(Event)]
+struct ThrowEvent {
+    grabber: Entity,
+    target: Entity,
+    aim: Vec3,
+}
+
+#[allow(clippy::too_many_arguments)]
+fn throw_grabbed(
+    mut events: EventReader<ThrowEvent>,
+    mut transforms: Query<&mut Transform>,
+    maps: Query<&TileMap>,
+    tables: Query<(), With<Table>>,
+    rapier: Res<RapierContext>,
+    controls: Res<ClientControls>,
+    players: Res<Players>,
+    mut emotes: EventWriter<EmoteEvent>,
+    mut sender: MessageSender,
+    time: Res<Time>,
+    mut commands: Commands,
+) {
+    for event in events.iter() {
+        let Ok(grabber_position) = transforms.get(event.grabber).map(|t| t.translation) else {
+            continue;
+        };
+        let Ok(mut target_transform) = transforms.get_mut(event.target) else {
+            continue;
+        };
+        let direction = direction_towards(grabber_position, event.aim);
+        if direction == Vec2::ZERO {
+            continue;
+        }
+        let direction = Vec3::new(direction.x, 0.0, direction.y);
+
+        release(&mut commands, event.grabber, event.target);
+
+        let table_position = table_in_direction(grabber_position, direction, &maps, &tables);
+        let (landing, stun, text) = if let Some(table_position) = table_position {
+            (
+                Vec3::new(
+                    table_position.x,
+                    target_transform.translation.y,
+                    table_position.z,
+                ),
+                SLAM_STUN_SECONDS,
+                "slams {target} onto the table!",
+            )
+        } else {
+            // Stop in front of walls
+            let filter = QueryFilter::only_fixed().groups(CollisionGroups::new(
+                physics::RAYCASTING_GROUP,
+                physics::OBSTACLE_GROUPS,
+            ));
+            let origin = target_transform.translation;
+            let distance = rapier
+                .cast_ray(origin, direction, THROW_DISTANCE, true, filter)
+                .map(|(_, toi)| (toi - THROW_WALL_MARGIN).max(0.0))
+                .unwrap_or(THROW_DISTANCE);
+            (
+                origin + direction * distance,
+                THROW_STUN_SECONDS,
+                "throws {target}!",
+            )
+        };
+
+        // TODO: Animate the flight and damage the thrown creature on impact
+        target_transform.translation = landing;
+        commands
+            .entity(event.target)
+            .insert(Stunned::new(stun, time.elapsed_seconds()));
+        emotes.send(EmoteEvent {
+            actor: event.grabber,
+            target: Some(event.target),
+            text: text.into(),
+        });
+
+        // Movement is client authoritative, so the client has to be told
+        if let Some(connection) = controls
+            .controlling_player(event.target)
+            .and_then(|p| players.get_connection(&p))
+        {
+            sender.send(
+                &ForcePositionMessage {
+                    position: landing,
+                    rotation: target_transform.rotation,
+                },
+                MessageReceivers::Single(connection),
+            );
+        }
+    }
+}
+
+/// Finds a table on the tile next to a position, in the given direction.
+/// Returns the world position of the table's tile.
+fn table_in_direction(
+    position: Vec3,
+    direction: Vec3,
+    maps: &Query<&TileMap>,
+    tables: &Query<(), With<Table>>,
+) -> Option<Vec3> {
+    // TODO: Support multiple maps
+    let map = maps.get_single().ok()?;
+    let start = world_to_tile(position)?;
+    // Snap to one of the eight neighbouring tiles
+    let neighbour = (position + direction).round();
+    let tile = world_to_tile(neighbour)?;
+    if tile == start {
+        return None;
+    }
+
+    let furniture = map.tile_at(neighbour)?.furniture?;
+    tables
+        .contains(furniture)
+        .then(|| Vec3::new(tile.x as f32, position.y, tile.y as f32))
+}
+
+#[cfg(feature = "client")]
+fn grab_status_ui(
+    mut contexts: EguiContexts,
+    grabbing: Query<&GrabbingClient, With<ClientControlled>>,
+    grabbed: Query<&GrabbedByClient, With<ClientControlled>>,
+) {
+    let mut lines = Vec::new();
+    if let Ok(grab) = grabbing.get_single() {
+        let mut text = format!("GRABBING: {}", grab.stage.label());
+        if *grab.escalating {
+            text += " (tightening)";
+        }
+        lines.push(text);
+    }
+    if let Ok(grabbed) = grabbed.get_single() {
+        lines.push(format!("GRABBED: {}", grabbed.stage.label()));
+    }
+    if lines.is_empty() {
+        return;
+    }
+
+    egui::Area::new("grab_indicator")
+        .anchor(egui::Align2::CENTER_BOTTOM, egui::vec2(0.0, -80.0))
+        .show(contexts.ctx_mut(), |ui| {
+            for line in lines {
+                ui.label(
+                    egui::RichText::new(line)
+                        .color(egui::Rgba::from_rgb(1.0, 0.85, 0.0))
+                        .size(16.0),
+                );
+            }
+        });
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::{ecs::system::SystemState, time::TimeUpdateStrategy};
+    use maps::TileReference;
+
+    use super::*;
+    use crate::{combat::Aim, config::ServerConfig, testing::server_app};
+
+    const FRAME: Duration = Duration::from_millis(100);
+    const GRABBER: Vec3 = Vec3::new(2.0, 0.0, 2.0);
+
+    fn update(app: &mut App, frames: u32) {
+        for _ in 0..frames {
+            app.update();
+        }
+    }
+
+    /// A passive grab with the grip being tightened. Returns the grabber and the target.
+    fn tightening(app: &mut App) -> (Entity, Entity) {
+        let now = app.world.resource::<Time>().elapsed_seconds();
+        let target = app
+            .world
+            .spawn(TransformBundle::from_transform(
+                Transform::from_translation(GRABBER + Vec3::X),
+            ))
+            .id();
+        let grabber = app
+            .world
+            .spawn((
+                TransformBundle::from_transform(Transform::from_translation(GRABBER)),
+                Pulling { target },
+                Grabbing {
+                    target,
+                    stage: GrabStage::Passive.into(),
+                    escalating: true.into(),
+                    escalation_end: now + GrabStage::Aggressive.escalation_seconds(),
+                },
+            ))
+            .id();
+        app.world.entity_mut(target).insert(GrabbedBy {
+            grabber,
+            stage: GrabStage::Passive.into(),
+        });
+        (grabber, target)
+    }
+
+    fn setup() -> (App, Entity, Entity) {
+        let mut app = server_app(ServerConfig::default());
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(FRAME));
+        app.update();
+        let (grabber, target) = tightening(&mut app);
+        update(&mut app, 2);
+        (app, grabber, target)
+    }
+
+    fn stages(app: &App, grabber: Entity, target: Entity) -> (GrabStage, bool, GrabStage) {
+        let grab = app.world.get::<Grabbing>(grabber).unwrap();
+        let grabbed_by = app.world.get::<GrabbedBy>(target).unwrap();
+        (*grab.stage, *grab.escalating, *grabbed_by.stage)
+    }
+
+    fn resist(app: &mut App, target: Entity) {
+        let origin = app.world.get::<Transform>(target).unwrap().translation;
+        app.world.send_event(IntentInputEvent {
+            actor: target,
+            intent: Intent::Disarm,
+            aim: Aim {
+                target_position: GRABBER,
+                origin,
+            },
+            held_item: None,
+            view_tick: 0.0,
+        });
+    }
+
+    #[test]
+    fn grip_tightens_when_not_interrupted() {
+        let (mut app, grabber, target) = setup();
+        assert_eq!(
+            stages(&app, grabber, target),
+            (GrabStage::Passive, true, GrabStage::Passive)
+        );
+
+        update(&mut app, 20);
+        assert_eq!(
+            stages(&app, grabber, target),
+            (GrabStage::Aggressive, false, GrabStage::Aggressive)
+        );
+        assert!(app
+            .world
+            .get::<GrabbedBy>(target)
+            .unwrap()
+            .restrains_hands());
+
+        // Resisting once the grip is tight doesn't loosen it
+        resist(&mut app, target);
+        update(&mut app, 1);
+        assert_eq!(
+            stages(&app, grabber, target),
+            (GrabStage::Aggressive, false, GrabStage::Aggressive)
+        );
+    }
+
+    #[test]
+    fn moving_away_interrupts_tightening() {
+        let (mut app, grabber, target) = setup();
+        app.world.get_mut::<Transform>(target).unwrap().translation =
+            GRABBER + Vec3::X * (ESCALATION_REACH + 1.0);
+        update(&mut app, 2);
+        assert_eq!(
+            stages(&app, grabber, target),
+            (GrabStage::Passive, false, GrabStage::Passive)
+        );
+
+        // The grab itself holds, but the grip stays loose
+        update(&mut app, 20);
+        assert_eq!(
+            stages(&app, grabber, target),
+            (GrabStage::Passive, false, GrabStage::Passive)
+        );
+    }
+
+    #[test]
+    fn attacking_the_grabber_interrupts_tightening() {
+        let (mut app, grabber, target) = setup();
+        resist(&mut app, target);
+        update(&mut app, 1);
+        assert_eq!(
+            stages(&app, grabber, target),
+            (GrabStage::Passive, false, GrabStage::Passive)
+        );
+
+        update(&mut app, 20);
+        assert_eq!(stages(&app, grabber, target).0, GrabStage::Passive);
+    }
+
+    #[test]
+    fn slams_only_onto_an_adjacent_table() {
+        let mut world = World::new();
+        let table = world.spawn(Table).id();
+        let chair = world.spawn_empty().id();
+        let mut map = TileMap::new(UVec2::ONE);
+        for (position, furniture) in [(UVec2::new(3, 2), table), (UVec2::new(2, 3), chair)] {
+            let tile = TileReference {
+                furniture: Some(furniture),
+                ..Default::default()
+            };
+            map.set_tile(position, tile).unwrap();
+        }
+        world.spawn(map);
+
+        let mut state = SystemState::<(Query<&TileMap>, Query<(), With<Table>>)>::new(&mut world);
+        let (maps, tables) = state.get(&world);
+        let slam = |direction: Vec3| table_in_direction(GRABBER, direction, &maps, &tables);
+        assert_eq!(slam(Vec3::X), Some(Vec3::new(3.0, 0.0, 2.0)));
+        // Other furniture isn't a table
+        assert_eq!(slam(Vec3::Z), None);
+        assert_eq!(slam(Vec3::NEG_X), None);
+        // Diagonals snap to the corner tile
+        assert_eq!(slam(Vec3::new(1.0, 0.0, 1.0).normalize()), None);
+        assert_eq!(
+            slam(Vec3::new(1.0, 0.0, 0.2).normalize()),
+            Some(Vec3::new(3.0, 0.0, 2.0))
+        );
+    }
+}
@@ -0,0 +1,293 @@
+use bevy::{math::Vec3Swizzles, prelude::*};
+use bevy_rapier3d::prelude::Velocity;
+use networking::{is_server, time::ServerNetworkTime};
+use utils::task::Tasks;
+
+use crate::{
+    body::{health::BasicAidEvent, Body, HeldItem},
+    communication::EmoteEvent,
+    construction::AnchorState,
+    cooldown::{CooldownKey, Cooldowns},
+    feedback::{Feedback, FeedbackKind},
+    items::containers::MoveItem,
+    movement::stamina::{Stamina, StaminaConfig},
+    rng::GameRng,
+    safe_zone::Safety,
+};
+
+use super::{rewind::LagCompensation, Intent, IntentInputEvent};
+
+pub(super) struct IntentPlugin;
+
+impl Plugin for IntentPlugin {
+    fn build(&self, app: &mut App) {
+        if is_server(app) {
+            app.add_systems(Update, ((help_intent, disarm_intent), pull_targets).chain());
+        }
+    }
+}
+
+/// How far away a creature can be reached with an empty hand
+const INTENT_REACH: f32 = 1.5;
+/// How close to the aimed position a creature has to be to be targeted
+pub(super) const INTENT_TARGET_RADIUS: f32 = 0.6;
+
+const HELP_COOLDOWN: f32 = 1.0;
+const DISARM_COOLDOWN: f32 = 1.5;
+pub(super) const HELP_COOLDOWN_KEY: CooldownKey = "intent.help";
+pub(super) const DISARM_COOLDOWN_KEY: CooldownKey = "intent.disarm";
+/// Chance that a disarm knocks the item out of the hand
+const DISARM_CHANCE: f32 = 0.4;
+
+/// A creature dragging another object behind it.
+#[derive(Component)]
+pub(super) struct Pulling {
+    pub target: Entity,
+}
+
+/// Find the creature closest to where the actor is aiming, if it's in reach.
+pub(super) fn find_target(
+    event: &IntentInputEvent,
+    bodies: &Query<(Entity, &GlobalTransform), With<Body>>,
+    lag: &LagCompensation,
+) -> Option<Entity> {
+    let (_, actor_transform) = bodies.get(event.actor).ok()?;
+    let actor_position = actor_transform.translation().xz();
+    let aimed = event.aim.target_position.xz();
+
+    aimed_creatures(event, bodies, lag)
+        .filter(|(_, position)| position.distance(actor_position) <= INTENT_REACH)
+        .map(|(entity, position)| (entity, position.distance(aimed)))
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(entity, _)| entity)
+}
+
+/// If the actor aimed at a creature that is too far away to reach.
+pub(super) fn aimed_out_of_reach(
+    event: &IntentInputEvent,
+    bodies: &Query<(Entity, &GlobalTransform), With<Body>>,
+    lag: &LagCompensation,
+) -> bool {
+    let Ok((_, actor_transform)) = bodies.get(event.actor) else {
+        return false;
+    };
+    let actor_position = actor_transform.translation().xz();
+    aimed_creatures(event, bodies, lag)
+        .any(|(_, position)| position.distance(actor_position) > INTENT_REACH)
+}
+
+/// Other creatures close to where the actor is aiming, with their horizontal position
+fn aimed_creatures<'a>(
+    event: &'a IntentInputEvent,
+    bodies: &'a Query<(Entity, &GlobalTransform), With<Body>>,
+    lag: &'a LagCompensation,
+) -> impl Iterator<Item = (Entity, Vec2)> + 'a {
+    let aimed = event.aim.target_position.xz();
+    // Targets are checked where the client saw them
+    bodies
+        .iter()
+        .filter(move |(entity, _)| *entity != event.actor)
+        .map(move |(entity, transform)| {
+            let position = lag
+                .position_at(entity, event.view_tick)
+                .unwrap_or_else(|| transform.translation());
+            (entity, position.xz())
+        })
+        .filter(move |(_, position)| position.distance(aimed) <= INTENT_TARGET_RADIUS)
+}
+
+fn help_intent(
+    mut events: EventReader<IntentInputEvent>,
+    bodies: Query<(Entity, &GlobalTransform), With<Body>>,
+    lag: LagCompensation,
+    mut cooldowns: Query<&mut Cooldowns>,
+    mut aid: EventWriter<BasicAidEvent>,
+    mut feedback: Feedback,
+    time: Res<ServerNetworkTime>,
+) {
+    for event in events.iter().filter(|e| e.intent == Intent::Help) {
+        // Helping is done with an empty hand
+        if event.held_item.is_some() {
+            continue;
+        }
+        let Some(target) = find_target(event, &bodies, &lag) else {
+            if aimed_out_of_reach(event, &bodies, &lag) {
+                feedback.send_to_creature(
+                    event.actor,
+                    FeedbackKind::OutOfReach,
+                    "combat.out_of_reach",
+                    &[],
+                );
+            }
+            continue;
+        };
+        if !cooldowns
+            .get_mut(event.actor)
+            .is_ok_and(|mut c| c.try_start(HELP_COOLDOWN_KEY, HELP_COOLDOWN, &time))
+        {
+            continue;
+        }
+
+        aid.send(BasicAidEvent {
+            helper: event.actor,
+            patient: target,
+        });
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn disarm_intent(
+    mut events: EventReader<IntentInputEvent>,
+    bodies: Query<(Entity, &GlobalTransform), With<Body>>,
+    lag: LagCompensation,
+    mut cooldowns: Query<&mut Cooldowns>,
+    held_item: HeldItem,
+    mut move_items: ResMut<Tasks<MoveItem>>,
+    mut emotes: EventWriter<EmoteEvent>,
+    safety: Safety,
+    mut stamina: Query<&mut Stamina>,
+    stamina_config: Res<StaminaConfig>,
+    mut rng: ResMut<GameRng>,
+    mut feedback: Feedback,
+    network_time: Res<ServerNetworkTime>,
+    time: Res<Time>,
+) {
+    for event in events.iter().filter(|e| e.intent == Intent::Disarm) {
+        let Some(target) = find_target(event, &bodies, &lag).filter(|t| !safety.is_protected(*t))
+        else {
+            if aimed_out_of_reach(event, &bodies, &lag) {
+                feedback.send_to_creature(
+                    event.actor,
+                    FeedbackKind::OutOfReach,
+                    "combat.out_of_reach",
+                    &[],
+                );
+            }
+            continue;
+        };
+        if !cooldowns
+            .get_mut(event.actor)
+            .is_ok_and(|mut c| c.try_start(DISARM_COOLDOWN_KEY, DISARM_COOLDOWN, &network_time))
+        {
+            continue;
+        }
+        if let Ok(mut stamina) = stamina.get_mut(event.actor) {
+            stamina.drain(stamina_config.disarm_cost, time.elapsed_seconds());
+        }
+
+        // TODO: Contest with stats of both creatures
+        let item = held_item.get(target);
+        let success = item.is_some() && rng.stream("disarm").f32() < DISARM_CHANCE;
+        if let (true, Some(item)) = (success, item) {
+            move_items.create_ignore(MoveItem {
+                item,
+                container: None,
+                position: None,
+            });
+        }
+
+        emotes.send(EmoteEvent {
+            actor: event.actor,
+            target: Some(target),
+            text: if success {
+                "disarms {target}!"
+            } else {
+                "attempts to disarm {target}."
+            }
+            .into(),
+        });
+    }
+}
+
+/// Distance at which a pulled object starts following
+const PULL_DISTANCE: f32 = 1.0;
+/// Distance at which the pull is broken
+const PULL_BREAK_DISTANCE: f32 = 3.0;
+/// How fast pulled objects catch up per meter of distance
+const PULL_STRENGTH: f32 = 4.0;
+
+fn pull_targets(
+    pullers: Query<(Entity, &Pulling, &GlobalTransform)>,
+    mut targets: Query<(
+        &GlobalTransform,
+        Option<&mut Velocity>,
+        Option<&AnchorState>,
+    )>,
+    mut commands: Commands,
+) {
+    for (entity, pulling, transform) in pullers.iter() {
+        let Ok((target_transform, velocity, anchor)) = targets.get_mut(pulling.target) else {
+            commands.entity(entity).remove::<Pulling>();
+            continue;
+        };
+        // Bolted down objects can't be dragged along
+        if anchor.is_some_and(|a| a.anchored()) {
+            commands.entity(entity).remove::<Pulling>();
+            continue;
+        }
+
+        let offset = transform.translation().xz() - target_transform.translation().xz();
+        let distance = offset.length();
+        if distance > PULL_BREAK_DISTANCE {
+            commands.entity(entity).remove::<Pulling>();
+            continue;
+        }
+
+        // TODO: Client controlled creatures move themselves, so this is overriden by their client
+        let Some(mut velocity) = velocity else {
+            continue;
+        };
+        if distance > PULL_DISTANCE {
+            let pull = offset / distance * (distance - PULL_DISTANCE) * PULL_STRENGTH;
+            velocity.linvel.x = pull.x;
+            velocity.linvel.z = pull.y;
+        }
+    }
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use super::*;
+    use crate::{config::ServerConfig, construction::Anchorable, testing::server_app};
+
+    /// Pulls an object two meters away for a few frames. Returns if it's still pulled and its velocity.
+    fn pull(anchored: bool) -> (bool, Vec3) {
+        let mut app = server_app(ServerConfig::default());
+        app.update();
+        let target = app
+            .world
+            .spawn((
+                Anchorable { anchored },
+                Velocity::zero(),
+                SpatialBundle::from_transform(Transform::from_xyz(2.0, 0.0, 0.0)),
+            ))
+            .id();
+        // Lets the object be bolted down first
+        app.update();
+        let puller = app
+            .world
+            .spawn((Pulling { target }, SpatialBundle::default()))
+            .id();
+        for _ in 0..3 {
+            app.update();
+        }
+        (
+            app.world.get::<Pulling>(puller).is_some(),
+            app.world.get::<Velocity>(target).unwrap().linvel,
+        )
+    }
+
+    #[test]
+    fn loose_objects_are_pulled() {
+        let (pulled, velocity) = pull(false);
+        assert!(pulled);
+        assert!(velocity.x < 0.0);
+    }
+
+    #[test]
+    fn anchored_objects_break_the_pull() {
+        let (pulled, velocity) = pull(true);
+        assert!(!pulled);
+        assert_eq!(velocity.x, 0.0);
+    }
+}
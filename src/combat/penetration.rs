@@ -0,0 +1,327 @@
+use bevy::{ecs::system::SystemParam, prelude::*};
+use bevy_rapier3d::prelude::{CollisionGroups, QueryFilter, RapierContext};
+use maps::TileMap;
+
+use crate::{movement::Crouching, rng::GameRng};
+
+use super::{config::CombatConfig, rewind::LagCompensation};
+
+/// Lets projectiles pass through thin obstacles and creatures take cover behind furniture.
+pub(super) struct PenetrationPlugin;
+
+impl Plugin for PenetrationPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<ShotMaterial>()
+            .register_type::<Penetrable>()
+            .register_type::<Cover>();
+    }
+}
+
+/// The most obstacles a single shot is traced through
+const MAX_SEGMENTS: usize = 8;
+/// How far past an obstacle the ray is cast again, so it doesn't hit the same surface
+const RECAST_OFFSET: f32 = 0.01;
+/// Distance between the points checked for cover behind a creature
+const COVER_STEP: f32 = 0.25;
+
+/// What an obstacle is made of, which decides how much it slows down projectiles.
+/// The costs are set in the combat config.
+#[derive(Reflect, Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ShotMaterial {
+    #[default]
+    Glass,
+    ReinforcedGlass,
+    Grille,
+    Furniture,
+    /// Creatures are always flesh, they don't need a component
+    Flesh,
+}
+
+/// An obstacle projectiles can pass through if they have enough penetration left.
+/// Anything hit without it, like a wall, stops the projectile.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct Penetrable {
+    pub material: ShotMaterial,
+}
+
+/// Furniture creatures can crouch behind to get missed by shots from the other side.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct Cover;
+
+/// A projectile about to be traced.
+pub(super) struct Shot {
+    pub origin: Vec3,
+    pub direction: Vec3,
+    pub max_distance: f32,
+    /// Tick the shooter saw, creatures are hit where they were at this tick
+    pub view_tick: f32,
+    pub budget: f32,
+}
+
+/// Something a projectile hit on its way.
+pub(super) struct ShotHit {
+    pub entity: Entity,
+    /// Meters from the origin of the shot
+    pub distance: f32,
+    /// Damage multiplier left after the obstacles passed before this one
+    pub scale: f32,
+}
+
+pub(super) struct ShotPath {
+    /// Everything hit, closest first
+    pub hits: Vec<ShotHit>,
+    /// Where the projectile stopped
+    pub end: Vec3,
+}
+
+/// Traces projectiles through the world and the rewound creatures.
+#[derive(SystemParam)]
+pub(super) struct ShotTracer<'w, 's> {
+    rapier: Res<'w, RapierContext>,
+    lag: LagCompensation<'w, 's>,
+    materials: Query<'w, 's, &'static Penetrable>,
+    parents: Query<'w, 's, &'static Parent>,
+    cover: Query<'w, 's, (), With<Cover>>,
+    crouching: Query<'w, 's, &'static Crouching>,
+    maps: Query<'w, 's, &'static TileMap>,
+}
+
+impl<'w, 's> ShotTracer<'w, 's> {
+    /// Follows a shot until it hits something it can't pass through, runs out of range
+    /// or has passed through too many obstacles.
+    pub fn trace(&self, shot: &Shot, config: &CombatConfig, rng: &mut GameRng) -> ShotPath {
+        // Creatures are hit where the shooter saw them, everything else where it is now
+        let groups = CollisionGroups::new(
+            physics::RAYCASTING_GROUP,
+            physics::OBSTACLE_GROUPS | physics::LIMB_GROUP,
+        );
+
+        let mut hits = Vec::new();
+        let mut passed_colliders = Vec::new();
+        let mut passed_creatures = Vec::new();
+        let mut origin = shot.origin;
+        let mut traveled = 0.0;
+        let mut budget = shot.budget;
+        let mut scale = 1.0;
+
+        for _ in 0..MAX_SEGMENTS {
+            let remaining = shot.max_distance - traveled;
+            if remaining <= 0.0 {
+                break;
+            }
+
+            let not_passed = |entity: Entity| {
+                !self.lag.is_rewound(entity) && !passed_colliders.contains(&entity)
+            };
+            let filter = QueryFilter::new().groups(groups).predicate(&not_passed);
+            let world_hit = self
+                .rapier
+                .cast_ray(origin, shot.direction, remaining, false, filter);
+            let creature_hit = self.lag.cast_ray(
+                shot.view_tick,
+                origin,
+                shot.direction,
+                remaining,
+                groups,
+                &passed_creatures,
+            );
+            let (hit, creature) = match (world_hit, creature_hit) {
+                (Some(world), Some(creature)) if creature.1 < world.1 => (creature, true),
+                (None, Some(creature)) => (creature, true),
+                (Some(world), _) => (world, false),
+                (None, None) => {
+                    return ShotPath {
+                        hits,
+                        end: origin + shot.direction * remaining,
+                    }
+                }
+            };
+
+            let (entity, toi) = hit;
+            let position = origin + shot.direction * toi;
+            let distance = traveled + toi;
+            origin = position + shot.direction * RECAST_OFFSET;
+            traveled = distance + RECAST_OFFSET;
+
+            let creature = creature.then(|| self.lag.creature_of(entity)).flatten();
+            if let Some(creature) = creature {
+                if self.is_covered(creature, position, shot.direction, config)
+                    && rng.stream("cover").f32() < config.cover.miss_chance
+                {
+                    debug!(?creature, "Shot missed a creature behind cover");
+                    passed_creatures.push(creature);
+                    continue;
+                }
+            }
+
+            hits.push(ShotHit {
+                entity,
+                distance,
+                scale,
+            });
+
+            let material = match creature {
+                Some(_) => Some(ShotMaterial::Flesh),
+                None => self.material_of(entity),
+            };
+            let Some(material) = material else {
+                return ShotPath {
+                    hits,
+                    end: position,
+                };
+            };
+            let penetration = config.penetration.material(material);
+            if penetration.cost > budget {
+                return ShotPath {
+                    hits,
+                    end: position,
+                };
+            }
+            budget -= penetration.cost;
+            scale *= 1.0 - penetration.damage_reduction;
+            match creature {
+                Some(creature) => passed_creatures.push(creature),
+                None => passed_colliders.push(entity),
+            }
+        }
+
+        ShotPath { hits, end: origin }
+    }
+
+    /// Colliders are usually children of the object they belong to
+    fn material_of(&self, collider: Entity) -> Option<ShotMaterial> {
+        std::iter::once(collider)
+            .chain(self.parents.iter_ancestors(collider))
+            .find_map(|entity| self.materials.get(entity).ok())
+            .map(|penetrable| penetrable.material)
+    }
+
+    /// If a crouching creature has cover between it and where the shot is coming from.
+    fn is_covered(
+        &self,
+        creature: Entity,
+        hit_position: Vec3,
+        direction: Vec3,
+        config: &CombatConfig,
+    ) -> bool {
+        if !self
+            .crouching
+            .get(creature)
+            .is_ok_and(|crouching| crouching.is_crouched())
+        {
+            return false;
+        }
+
+        let steps = (config.cover.max_distance / COVER_STEP).ceil() as usize;
+        (0..=steps).any(|step| {
+            let position = hit_position - direction * (step as f32 * COVER_STEP);
+            self.maps
+                .iter()
+                .find_map(|map| map.tile_at(position))
+                .and_then(|tile| tile.furniture)
+                .is_some_and(|furniture| self.cover.contains(furniture))
+        })
+    }
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use bevy::ecs::system::SystemState;
+    use bevy_rapier3d::prelude::{Collider, Group, RigidBody};
+    use networking::time::ServerNetworkTime;
+
+    use super::*;
+    use crate::{body::Body, config::ServerConfig, testing::server_app};
+
+    /// An obstacle across the path of the shots, `z` meters in front of the shooter.
+    fn obstacle(app: &mut App, z: f32, material: Option<ShotMaterial>) -> Entity {
+        let mut obstacle = app.world.spawn((
+            TransformBundle::from(Transform::from_xyz(0.0, 1.0, z)),
+            RigidBody::Fixed,
+            Collider::cuboid(1.0, 1.0, 0.05),
+            CollisionGroups::new(physics::STATIC_GROUP, Group::ALL),
+        ));
+        if let Some(material) = material {
+            obstacle.insert(Penetrable { material });
+        }
+        obstacle.id()
+    }
+
+    fn person(app: &mut App, z: f32) -> Entity {
+        app.world
+            .spawn((
+                Body::default(),
+                TransformBundle::from(Transform::from_xyz(0.0, 1.0, z)),
+                Collider::capsule_y(0.5, 0.3),
+            ))
+            .id()
+    }
+
+    /// Fires a shot with the default budget along the z axis.
+    fn shoot(app: &mut App) -> ShotPath {
+        // Lets the physics world and the position history catch up
+        for _ in 0..3 {
+            app.update();
+        }
+        let config = CombatConfig::default();
+        let view_tick = app.world.resource::<ServerNetworkTime>().current_tick() as f32;
+        let mut state = SystemState::<ShotTracer>::new(&mut app.world);
+        state.get(&app.world).trace(
+            &Shot {
+                origin: Vec3::new(0.0, 1.0, 0.0),
+                direction: Vec3::Z,
+                max_distance: 50.0,
+                view_tick,
+                budget: config.penetration.default_budget,
+            },
+            &config,
+            &mut GameRng::new(0),
+        )
+    }
+
+    #[test]
+    fn shot_through_a_window_hits_the_person_behind_it() {
+        let mut app = server_app(ServerConfig::default());
+        let window = obstacle(&mut app, 2.0, Some(ShotMaterial::Glass));
+        let person = person(&mut app, 4.0);
+        let wall = obstacle(&mut app, 8.0, None);
+
+        let path = shoot(&mut app);
+        let hits: Vec<_> = path
+            .hits
+            .iter()
+            .map(|hit| (hit.entity, hit.scale))
+            .collect();
+        let penetration = CombatConfig::default().penetration;
+        let after_window = 1.0 - penetration.glass.damage_reduction;
+        assert_eq!(
+            hits,
+            [
+                (window, 1.0),
+                (person, after_window),
+                (
+                    wall,
+                    after_window * (1.0 - penetration.flesh.damage_reduction)
+                ),
+            ]
+        );
+        assert!((path.hits[1].distance - 3.7).abs() < 0.01);
+        assert!((path.end.z - 7.95).abs() < 0.01);
+    }
+
+    #[test]
+    fn wall_stops_everything() {
+        let mut app = server_app(ServerConfig::default());
+        let wall = obstacle(&mut app, 2.0, None);
+        person(&mut app, 4.0);
+        obstacle(&mut app, 6.0, Some(ShotMaterial::Glass));
+
+        let path = shoot(&mut app);
+        assert_eq!(path.hits.len(), 1);
+        assert_eq!(path.hits[0].entity, wall);
+        assert_eq!(path.hits[0].scale, 1.0);
+        assert!((path.end.z - 1.95).abs() < 0.01);
+    }
+}
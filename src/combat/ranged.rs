@@ -1,22 +1,46 @@
 use std::time::Duration;
 
-use bevy::{prelude::*, reflect::TypeUuid};
-use bevy_rapier3d::prelude::{CollisionGroups, QueryFilter, RapierContext};
+use bevy::{math::Vec3Swizzles, prelude::*, reflect::TypeUuid};
 use networking::{
     component::AppExt,
     is_server,
-    messaging::{AppExt as MessageAppExt, MessageEvent, MessageReceivers, MessageSender},
+    messaging::{
+        AppExt as MessageAppExt, Finite, InvalidMessage, MessageEvent, MessageReceivers,
+        MessageSender,
+    },
+    spawning::ClientControls,
     variable::{NetworkVar, ServerVar},
-    Networked,
+    Networked, Players,
 };
 use serde::{Deserialize, Serialize};
 
 use crate::{
+    actions::{ActorAction, ActorActionEvent},
+    body::{Hand, Hands},
     combat::{damage::*, RANGED_AIM_HEIGHT},
-    GameState,
+    flash::{Flashed, FLASHED_SPREAD_MULTIPLIER},
+    items::{
+        containers::ItemLeftContainer,
+        durability::{Broken, ItemDamageEvent},
+    },
+    movement::stamina::StaminaDamageEvent,
+    rng::GameRng,
 };
 
-use super::CombatInputEvent;
+use super::{
+    config::{Accuracy, CombatConfig},
+    penetration::{Shot, ShotTracer},
+    Aim, CombatInputEvent,
+};
+
+#[cfg(feature = "client")]
+use {
+    super::{ClientCombatModeStatus, CombatModeClient},
+    crate::{body::ClientHeldItem, camera::MainCamera, ui::has_window, GameState},
+    bevy::time::common_conditions::on_timer,
+    bevy_egui::{egui, EguiContexts},
+    networking::spawning::ClientControlled,
+};
 
 pub struct RangedPlugin;
 
@@ -24,14 +48,22 @@ impl Plugin for RangedPlugin {
     fn build(&self, app: &mut App) {
         app.register_type::<Gun>()
             .add_networked_component::<Gun, GunClient>()
-            .add_network_message::<GunShotMessage>();
+            .add_network_message::<GunShotMessage>("GunShotMessage")
+            .add_network_message::<AimUpdate>("AimUpdate")
+            .add_network_message::<AccuracyMessage>("AccuracyMessage");
 
         if is_server(app) {
-            app.add_systems(Update, shoot_gun);
+            app.add_systems(Update, (reset_aim, track_aim, shoot_gun).chain());
         } else {
-            app.add_systems(
+            #[cfg(feature = "client")]
+            app.init_resource::<ClientAccuracy>().add_systems(
                 Update,
-                client_handle_gun_shot_effects.run_if(in_state(GameState::Game)),
+                (
+                    client_handle_gun_shot_effects,
+                    client_send_aim.run_if(on_timer(Duration::from_secs_f32(AIM_UPDATE_INTERVAL))),
+                    (receive_accuracy, crosshair_ui.run_if(has_window)).chain(),
+                )
+                    .run_if(in_state(GameState::Game)),
             );
         }
     }
@@ -41,8 +73,10 @@ impl Plugin for RangedPlugin {
 #[derive(Component, Reflect, Networked)]
 #[reflect(Component)]
 #[networked(client = "GunClient")]
-struct Gun {
+pub(super) struct Gun {
     time_between_shots: Duration,
+    /// Identifies the weapon in the combat config
+    weapon_id: String,
 
     #[reflect(ignore)]
     next_shot_time: NetworkVar<f32>,
@@ -52,18 +86,208 @@ impl Default for Gun {
     fn default() -> Self {
         Self {
             time_between_shots: Duration::from_secs_f32(0.1),
+            weapon_id: String::new(),
             next_shot_time: NetworkVar::from_default(0.0),
         }
     }
 }
 
+/// How far shots travel in meters
+const MAX_SHOT_DISTANCE: f32 = 20.0;
+/// Seconds between aim updates sent by clients holding a gun in combat mode
+#[cfg(feature = "client")]
+const AIM_UPDATE_INTERVAL: f32 = 0.1;
+/// How far the aim can wander in meters while still counting as aiming at the same point
+const STEADY_AIM_TOLERANCE: f32 = 0.5;
+/// Samples closer together than this don't change the measured speed
+const MIN_SPEED_SAMPLE_SECONDS: f32 = 0.05;
+
+/// Where a creature has been aiming, which makes its shots more or less accurate.
+#[derive(Component)]
+struct AimTracker {
+    target: Vec3,
+    steady_since: f32,
+    /// Where the creature was at the last sample, to measure how fast it is moving
+    position: Vec3,
+    sampled_at: f32,
+    speed: f32,
+    recoil: f32,
+    last_shot: f32,
+}
+
+impl AimTracker {
+    fn new(target: Vec3, position: Vec3, now: f32) -> Self {
+        Self {
+            target,
+            steady_since: now,
+            position,
+            sampled_at: now,
+            speed: 0.0,
+            recoil: 0.0,
+            last_shot: now,
+        }
+    }
+
+    fn sample(&mut self, target: Vec3, position: Vec3, now: f32) {
+        if target.distance(self.target) > STEADY_AIM_TOLERANCE {
+            self.target = target;
+            self.steady_since = now;
+        }
+
+        // Movement is client authoritative, so the velocity on the server can't be used
+        let elapsed = now - self.sampled_at;
+        if elapsed >= MIN_SPEED_SAMPLE_SECONDS {
+            self.speed = position.xz().distance(self.position.xz()) / elapsed;
+            self.position = position;
+            self.sampled_at = now;
+        }
+    }
+
+    fn spread(&self, accuracy: &Accuracy, now: f32) -> f32 {
+        let recoil = accuracy.recover(self.recoil, now - self.last_shot);
+        accuracy.spread(now - self.steady_since, self.speed, recoil)
+    }
+
+    fn add_recoil(&mut self, accuracy: &Accuracy, now: f32) {
+        self.recoil = accuracy.recover(self.recoil, now - self.last_shot) + accuracy.recoil;
+        self.last_shot = now;
+    }
+}
+
+/// Client message with where the player is aiming, so steady aim can be rewarded.
+#[derive(Serialize, Deserialize, Clone, Copy)]
+struct AimUpdate(Aim);
+
+/// Server message with the spread shots of the held gun currently have, in degrees.
+#[derive(Serialize, Deserialize, Clone, Copy)]
+struct AccuracyMessage {
+    spread: f32,
+}
+
+fn send_accuracy(
+    creature: Entity,
+    spread: f32,
+    controls: &ClientControls,
+    players: &Players,
+    sender: &mut MessageSender,
+) {
+    let Some(connection) = controls
+        .controlling_player(creature)
+        .and_then(|player| players.get_connection(&player))
+    else {
+        return;
+    };
+    sender.send(
+        &AccuracyMessage { spread },
+        MessageReceivers::Single(connection),
+    );
+}
+
+/// Steady aim and recoil are lost with the gun, like when it's put away or taken by a disarm.
+fn reset_aim(
+    mut left: EventReader<ItemLeftContainer>,
+    guns: Query<(), With<Gun>>,
+    hands: Query<(), With<Hand>>,
+    parents: Query<&Parent>,
+    trackers: Query<(), With<AimTracker>>,
+    mut commands: Commands,
+) {
+    for event in left.iter() {
+        if !guns.contains(event.item) || !hands.contains(event.container) {
+            continue;
+        }
+        if let Some(creature) = parents
+            .iter_ancestors(event.container)
+            .find(|&entity| trackers.contains(entity))
+        {
+            commands.entity(creature).remove::<AimTracker>();
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn track_aim(
+    mut messages: EventReader<MessageEvent<AimUpdate>>,
+    players: Res<Players>,
+    controls: Res<ClientControls>,
+    mut trackers: Query<(&GlobalTransform, Option<&mut AimTracker>)>,
+    bodies: Query<&Hands>,
+    children: Query<&Children>,
+    guns: Query<&Gun>,
+    config: Res<CombatConfig>,
+    time: Res<Time>,
+    mut invalid: EventWriter<InvalidMessage>,
+    mut sender: MessageSender,
+    mut commands: Commands,
+) {
+    let now = time.elapsed_seconds();
+    for event in messages.iter() {
+        let AimUpdate(aim) = event.message;
+        if !aim.is_finite() {
+            invalid.send(InvalidMessage {
+                connection: event.connection,
+                reason: "non-finite aim",
+            });
+            continue;
+        }
+        let Some(creature) = players
+            .get(event.connection)
+            .and_then(|player| controls.controlled_entity(player.id))
+        else {
+            continue;
+        };
+        let Ok((transform, tracker)) = trackers.get_mut(creature) else {
+            continue;
+        };
+
+        let position = transform.translation();
+        let tracker = match tracker {
+            Some(mut tracker) => {
+                tracker.sample(aim.target_position, position, now);
+                tracker
+            }
+            None => {
+                commands.entity(creature).insert(AimTracker::new(
+                    aim.target_position,
+                    position,
+                    now,
+                ));
+                continue;
+            }
+        };
+
+        // Only the gun in the active hand counts
+        let Some(accuracy) = bodies
+            .get(creature)
+            .ok()
+            .and_then(|hands| children.get(hands.active_hand()).ok())
+            .and_then(|items| guns.iter_many(items.iter()).next())
+            .and_then(|gun| config.accuracy(&gun.weapon_id))
+        else {
+            continue;
+        };
+        let spread = tracker.spread(&accuracy, now);
+        send_accuracy(creature, spread, &controls, &players, &mut sender);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 fn shoot_gun(
     mut input: EventReader<CombatInputEvent>,
-    mut guns: Query<&mut Gun>,
+    mut guns: Query<&mut Gun, Without<Broken>>,
+    mut trackers: Query<(&GlobalTransform, Option<&mut AimTracker>)>,
+    flashed: Query<(), With<Flashed>>,
+    config: Res<CombatConfig>,
+    mut rng: ResMut<GameRng>,
+    controls: Res<ClientControls>,
+    players: Res<Players>,
     time: Res<Time>,
-    rapier: Res<RapierContext>,
+    tracer: ShotTracer,
     mut commands: Commands,
     mut sender: MessageSender,
+    mut actions: EventWriter<ActorActionEvent>,
+    mut damage: EventWriter<ItemDamageEvent>,
+    mut stamina_damage: EventWriter<StaminaDamageEvent>,
 ) {
     for event in input.iter() {
         if !event.input.primary_attack {
@@ -90,40 +314,100 @@ fn shoot_gun(
         let mut direction = (target_position - origin).normalize_or_zero();
         // Don't aim up or down for now
         direction.y = 0.;
-        // Prevent player from hitting themselves
-        origin += direction * 0.5;
 
-        let filter = QueryFilter::new().groups(CollisionGroups::new(
-            physics::RAYCASTING_GROUP,
-            physics::DEFAULT_GROUP | physics::LIMB_GROUP,
-        ));
-        if let Some((hit_entity, toi)) = rapier.cast_ray(origin, direction, 20.0, false, filter) {
-            let position = origin + direction * toi;
+        if let Some(accuracy) = config.accuracy(&gun.weapon_id) {
+            let spread = match trackers.get_mut(event.actor) {
+                Ok((transform, Some(mut tracker))) => {
+                    tracker.sample(target_position, transform.translation(), elapsed);
+                    let spread = tracker.spread(&accuracy, elapsed);
+                    tracker.add_recoil(&accuracy, elapsed);
+                    spread
+                }
+                Ok((transform, None)) => {
+                    let mut tracker =
+                        AimTracker::new(target_position, transform.translation(), elapsed);
+                    let spread = tracker.spread(&accuracy, elapsed);
+                    tracker.add_recoil(&accuracy, elapsed);
+                    commands.entity(event.actor).insert(tracker);
+                    spread
+                }
+                Err(_) => accuracy.base_spread,
+            };
+            // Blinded shooters can't aim properly
+            let spread = if flashed.contains(event.actor) {
+                spread * FLASHED_SPREAD_MULTIPLIER
+            } else {
+                spread
+            };
 
+            let angle = (rng.stream("gun_spread").f32() - 0.5) * spread.to_radians();
+            direction = Quat::from_rotation_y(angle) * direction;
+            send_accuracy(event.actor, spread, &controls, &players, &mut sender);
+        }
+
+        // Prevent player from hitting themselves
+        const MUZZLE_OFFSET: f32 = 0.5;
+        origin += direction * MUZZLE_OFFSET;
+
+        let path = tracer.trace(
+            &Shot {
+                origin,
+                direction,
+                max_distance: MAX_SHOT_DISTANCE,
+                view_tick: event.input.view_tick,
+                budget: config.penetration_budget(&gun.weapon_id),
+            },
+            &config,
+            &mut rng,
+        );
+        let (stamina, nonlethal) = config.stamina_damage(&gun.weapon_id);
+        for hit in path.hits.iter() {
+            if stamina > 0.0 {
+                stamina_damage.send(StaminaDamageEvent {
+                    target: hit.entity,
+                    amount: stamina * hit.scale,
+                });
+            }
+            if nonlethal {
+                continue;
+            }
             commands.spawn((
                 Attack,
-                AffectedEntity(hit_entity),
+                AffectedEntity(hit.entity),
                 // TODO: Grab from weapon and ammo used
                 KineticDamage {
                     mass: 0.115,
                     velocity: 400.0,
                     shape: KineticShape::Point,
+                    scale: hit.scale,
+                },
+                AttackSource {
+                    attacker: event.actor,
+                    instigator: None,
+                    weapon: Some(gun.weapon_id.clone()).filter(|id| !id.is_empty()),
+                    distance: Some(hit.distance + MUZZLE_OFFSET),
                 },
             ));
             // TODO: Attacks are not yet automatically deleted
-
+        }
+        if !path.hits.is_empty() {
             // TODO: Maybe handle with entity?
             // TODO: Don't send to all players, only in range
             sender.send(
                 &GunShotMessage {
                     origin,
-                    hit: position,
+                    hit: path.end,
                 },
                 MessageReceivers::AllPlayers,
             );
         }
 
+        actions.send(ActorActionEvent {
+            actor: event.actor,
+            action: ActorAction::Fire,
+        });
         *gun.next_shot_time = elapsed + gun.time_between_shots.as_secs_f32();
+        damage.send(ItemDamageEvent::used(wielded_weapon, event.actor));
     }
 }
 
@@ -148,8 +432,10 @@ struct GunShotMessage {
     hit: Vec3,
 }
 
+#[cfg(feature = "client")]
 const BULLET_TRACER_VISIBLE_SECONDS: f32 = 0.5;
 
+#[cfg(feature = "client")]
 fn client_handle_gun_shot_effects(
     mut messages: EventReader<MessageEvent<GunShotMessage>>,
     mut current: Local<Vec<(f32, GunShotMessage)>>,
@@ -168,3 +454,168 @@ fn client_handle_gun_shot_effects(
         gizmos.line(message.origin, message.hit, Color::RED);
     }
 }
+
+#[cfg(feature = "client")]
+fn client_send_aim(
+    combat_mode: ClientCombatModeStatus,
+    players: Query<&CombatModeClient, With<ClientControlled>>,
+    held_item: ClientHeldItem,
+    guns: Query<(), With<GunClient>>,
+    mut sender: MessageSender,
+) {
+    if !combat_mode.is_enabled() || !held_item.get().is_some_and(|item| guns.contains(item)) {
+        return;
+    }
+    let Ok(combat) = players.get_single() else {
+        return;
+    };
+    sender.send_to_server(&AimUpdate(combat.aim));
+}
+
+/// The last spread the server reported for the held gun
+#[cfg(feature = "client")]
+#[derive(Resource, Default)]
+struct ClientAccuracy {
+    spread: f32,
+    received_at: Option<f32>,
+}
+
+#[cfg(feature = "client")]
+fn receive_accuracy(
+    mut messages: EventReader<MessageEvent<AccuracyMessage>>,
+    mut accuracy: ResMut<ClientAccuracy>,
+    time: Res<Time>,
+) {
+    if let Some(event) = messages.iter().last() {
+        accuracy.spread = event.message.spread;
+        accuracy.received_at = Some(time.elapsed_seconds());
+    }
+}
+
+/// The crosshair disappears when the server stops sending the spread, for example after putting the gun away
+#[cfg(feature = "client")]
+const CROSSHAIR_TIMEOUT_SECONDS: f32 = 0.5;
+
+#[cfg(feature = "client")]
+/// Draws a circle around the cursor covering where shots could land.
+fn crosshair_ui(
+    mut contexts: EguiContexts,
+    accuracy: Res<ClientAccuracy>,
+    combat_mode: ClientCombatModeStatus,
+    players: Query<&CombatModeClient, With<ClientControlled>>,
+    camera: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    time: Res<Time>,
+) {
+    let Some(received_at) = accuracy.received_at else {
+        return;
+    };
+    if time.elapsed_seconds() - received_at > CROSSHAIR_TIMEOUT_SECONDS || !combat_mode.is_enabled()
+    {
+        return;
+    }
+    let (Ok(combat), Ok((camera, camera_transform))) = (players.get_single(), camera.get_single())
+    else {
+        return;
+    };
+
+    let target = combat.aim.target_position;
+    let origin = combat.aim.origin + Vec3::new(0.0, RANGED_AIM_HEIGHT, 0.0);
+    let offset = (target - origin).xz();
+    let distance = offset.length();
+    if distance < 0.01 {
+        return;
+    }
+    // Half of the cone's width at the distance of the cursor
+    let width = distance * (accuracy.spread.to_radians() / 2.0).tan();
+    let side = Vec3::new(-offset.y, 0.0, offset.x) / distance * width;
+    let (Some(center), Some(edge)) = (
+        camera.world_to_viewport(camera_transform, target),
+        camera.world_to_viewport(camera_transform, target + side),
+    ) else {
+        return;
+    };
+
+    let painter = contexts.ctx_mut().layer_painter(egui::LayerId::new(
+        egui::Order::Foreground,
+        egui::Id::new("crosshair"),
+    ));
+    painter.circle_stroke(
+        egui::pos2(center.x, center.y),
+        center.distance(edge).max(4.0),
+        egui::Stroke::new(1.5, egui::Color32::from_rgb(255, 80, 80)),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ACCURACY: Accuracy = Accuracy {
+        base_spread: 10.0,
+        min_spread: 2.0,
+        max_spread: 30.0,
+        steady_seconds: 2.0,
+        movement_spread: 4.0,
+        recoil: 3.0,
+        recoil_recovery: 6.0,
+    };
+    /// Seconds between aim updates from the client
+    const SAMPLE: f32 = 0.1;
+
+    #[test]
+    fn steady_aim_converges_to_min_spread() {
+        let target = Vec3::new(5.0, 0.0, 0.0);
+        let mut tracker = AimTracker::new(target, Vec3::ZERO, 0.0);
+        assert_eq!(tracker.spread(&ACCURACY, 0.0), ACCURACY.base_spread);
+
+        let mut previous = f32::MAX;
+        for step in 1..=40 {
+            let now = step as f32 * SAMPLE;
+            // Small hand movements stay within the tolerance
+            let wobble = Vec3::Z * 0.1 * (step % 3) as f32;
+            tracker.sample(target + wobble, Vec3::ZERO, now);
+            let spread = tracker.spread(&ACCURACY, now);
+            assert!(spread <= previous, "spread grew to {} at {}s", spread, now);
+            previous = spread;
+        }
+        assert_eq!(previous, ACCURACY.min_spread);
+
+        // Aiming somewhere else starts over
+        tracker.sample(Vec3::new(-5.0, 0.0, 0.0), Vec3::ZERO, 4.1);
+        assert_eq!(tracker.spread(&ACCURACY, 4.1), ACCURACY.base_spread);
+    }
+
+    #[test]
+    fn moving_shooter_hits_the_cap() {
+        let target = Vec3::new(5.0, 0.0, 0.0);
+        let mut tracker = AimTracker::new(target, Vec3::ZERO, 0.0);
+        for step in 1..=40 {
+            let now = step as f32 * SAMPLE;
+            // Running at 10 m/s
+            tracker.sample(target, Vec3::X * now * 10.0, now);
+        }
+        assert!(
+            (tracker.speed - 10.0).abs() < 0.01,
+            "speed {}",
+            tracker.speed
+        );
+        assert_eq!(tracker.spread(&ACCURACY, 4.0), ACCURACY.max_spread);
+    }
+
+    #[test]
+    fn recoil_builds_up_within_a_burst() {
+        let target = Vec3::new(5.0, 0.0, 0.0);
+        let mut tracker = AimTracker::new(target, Vec3::ZERO, 0.0);
+        tracker.sample(target, Vec3::ZERO, 3.0);
+        let steady = tracker.spread(&ACCURACY, 3.0);
+
+        tracker.add_recoil(&ACCURACY, 3.0);
+        tracker.add_recoil(&ACCURACY, 3.1);
+        // Two shots of recoil, minus what was recovered in between
+        let burst = tracker.spread(&ACCURACY, 3.1);
+        assert!((burst - (steady + 2.0 * ACCURACY.recoil - 0.6)).abs() < 1e-4);
+
+        // Fully recovered after a pause
+        assert_eq!(tracker.spread(&ACCURACY, 5.0), steady);
+    }
+}
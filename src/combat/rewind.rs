@@ -0,0 +1,340 @@
+use std::collections::VecDeque;
+
+use bevy::{ecs::system::SystemParam, prelude::*, transform::TransformSystem};
+use bevy_rapier3d::prelude::{Collider, CollisionGroups};
+use networking::time::ServerNetworkTime;
+
+use crate::body::Body;
+
+/// Keeps a short history of where creatures were, so hits can be checked against
+/// what a client saw when it clicked instead of where the creatures are now.
+pub(super) struct RewindPlugin;
+
+impl Plugin for RewindPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PostUpdate,
+            record_positions.after(TransformSystem::TransformPropagate),
+        );
+    }
+}
+
+/// How many seconds into the past hit checks can be rewound
+const HISTORY_SECONDS: f64 = 0.5;
+
+struct PositionSample {
+    tick: u32,
+    translation: Vec3,
+    rotation: Quat,
+}
+
+/// Recent positions of a creature, one sample per server tick.
+#[derive(Component, Default)]
+pub(super) struct PositionHistory {
+    samples: VecDeque<PositionSample>,
+}
+
+impl PositionHistory {
+    /// Where the entity was at a (fractional) tick, interpolating between samples.
+    /// Ticks outside the history use the closest sample.
+    fn at(&self, tick: f32) -> Option<(Vec3, Quat)> {
+        let first = self.samples.front()?;
+        if tick <= first.tick as f32 {
+            return Some((first.translation, first.rotation));
+        }
+
+        for (before, after) in self.samples.iter().zip(self.samples.iter().skip(1)) {
+            if tick > after.tick as f32 {
+                continue;
+            }
+            let t = (tick - before.tick as f32) / (after.tick - before.tick).max(1) as f32;
+            return Some((
+                before.translation.lerp(after.translation, t),
+                before.rotation.slerp(after.rotation, t),
+            ));
+        }
+
+        let last = self.samples.back()?;
+        Some((last.translation, last.rotation))
+    }
+}
+
+fn history_ticks(network_time: &ServerNetworkTime) -> u32 {
+    (HISTORY_SECONDS / network_time.tick_in_seconds()).ceil() as u32
+}
+
+fn record_positions(
+    new_bodies: Query<Entity, (With<Body>, Without<PositionHistory>)>,
+    mut bodies: Query<(&GlobalTransform, &mut PositionHistory)>,
+    network_time: Res<ServerNetworkTime>,
+    mut commands: Commands,
+) {
+    for entity in new_bodies.iter() {
+        commands.entity(entity).insert(PositionHistory::default());
+    }
+
+    let tick = network_time.current_tick();
+    let oldest = tick.saturating_sub(history_ticks(&network_time));
+    for (transform, mut history) in bodies.iter_mut() {
+        let (_, rotation, translation) = transform.to_scale_rotation_translation();
+        history.samples.push_back(PositionSample {
+            tick,
+            translation,
+            rotation,
+        });
+        while history.samples.front().is_some_and(|s| s.tick < oldest) {
+            history.samples.pop_front();
+        }
+    }
+}
+
+/// Checks hits against the recorded positions of creatures.
+/// Rewinding never moves anything in the physics world, it only affects the hit check.
+#[derive(SystemParam)]
+pub(super) struct LagCompensation<'w, 's> {
+    network_time: Res<'w, ServerNetworkTime>,
+    histories: Query<'w, 's, (Entity, &'static PositionHistory, &'static GlobalTransform)>,
+    children: Query<'w, 's, &'static Children>,
+    parents: Query<'w, 's, &'static Parent>,
+    colliders: Query<
+        'w,
+        's,
+        (
+            &'static Collider,
+            &'static GlobalTransform,
+            Option<&'static CollisionGroups>,
+        ),
+    >,
+}
+
+impl<'w, 's> LagCompensation<'w, 's> {
+    /// Limits the tick a client claims to have seen to the recorded history.
+    /// Clients can't look further into the past than the history, or into the future.
+    pub fn clamp_tick(&self, claimed: f32, actor: Entity) -> f32 {
+        let now = self.network_time.current_tick() as f32;
+        let oldest = now - history_ticks(&self.network_time) as f32;
+        let tick = claimed.clamp(oldest, now);
+        if tick != claimed {
+            warn!(
+                ?actor,
+                claimed,
+                current = now,
+                "Hit check claimed a tick outside the rewind history"
+            );
+        }
+        debug!(?actor, delta = now - tick, "Rewinding hit check");
+        tick
+    }
+
+    /// Where an entity was at a tick, if its position is recorded.
+    pub fn position_at(&self, entity: Entity, tick: f32) -> Option<Vec3> {
+        let (_, history, _) = self.histories.get(entity).ok()?;
+        history.at(tick).map(|(translation, _)| translation)
+    }
+
+    /// If the collider belongs to a creature that is checked at its past position.
+    pub fn is_rewound(&self, collider: Entity) -> bool {
+        self.creature_of(collider).is_some()
+    }
+
+    /// The creature a rewound collider belongs to.
+    pub fn creature_of(&self, collider: Entity) -> Option<Entity> {
+        if self.histories.contains(collider) {
+            return Some(collider);
+        }
+        self.parents
+            .get(collider)
+            .ok()
+            .map(|parent| parent.get())
+            .filter(|&parent| self.histories.contains(parent))
+    }
+
+    /// Casts a ray against the colliders of creatures at their position at the given tick.
+    /// Creatures in `exclude` are ignored, like ones a projectile already passed through.
+    /// Returns the hit entity and the time of impact.
+    pub fn cast_ray(
+        &self,
+        tick: f32,
+        origin: Vec3,
+        direction: Vec3,
+        max_toi: f32,
+        groups: CollisionGroups,
+        exclude: &[Entity],
+    ) -> Option<(Entity, f32)> {
+        let mut closest: Option<(Entity, f32)> = None;
+        for (root, history, root_transform) in self.histories.iter() {
+            if exclude.contains(&root) {
+                continue;
+            }
+            let Some((translation, rotation)) = history.at(tick) else {
+                continue;
+            };
+            // Moves colliders from where the creature is now to where it was
+            let rewind = Transform::from_translation(translation)
+                .with_rotation(rotation)
+                .compute_matrix()
+                * root_transform.compute_matrix().inverse();
+
+            let children = self.children.get(root).into_iter().flatten().copied();
+            for entity in std::iter::once(root).chain(children) {
+                let Ok((collider, transform, collider_groups)) = self.colliders.get(entity) else {
+                    continue;
+                };
+                if !groups_match(groups, collider_groups.copied().unwrap_or_default()) {
+                    continue;
+                }
+
+                let (_, rotation, translation) =
+                    (rewind * transform.compute_matrix()).to_scale_rotation_translation();
+                let Some(toi) =
+                    collider.cast_ray(translation, rotation, origin, direction, max_toi, false)
+                else {
+                    continue;
+                };
+                if closest.map_or(true, |(_, closest)| toi < closest) {
+                    closest = Some((entity, toi));
+                }
+            }
+        }
+        closest
+    }
+}
+
+/// Same rule rapier uses to decide if a query can hit a collider.
+fn groups_match(query: CollisionGroups, collider: CollisionGroups) -> bool {
+    query.memberships.intersects(collider.filters) && collider.memberships.intersects(query.filters)
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use bevy::ecs::system::SystemState;
+
+    use super::*;
+    use crate::{config::ServerConfig, testing::server_app};
+
+    /// The tick the server is on during the tests
+    const NOW: u32 = 1000;
+    /// How far back the creature in the tests was at the origin
+    const TICKS_AGO: u32 = 5;
+
+    fn app() -> App {
+        let mut app = server_app(ServerConfig::default());
+        app.world
+            .resource_mut::<ServerNetworkTime>()
+            .set_current_tick(NOW);
+        app
+    }
+
+    fn history(samples: &[(u32, f32)]) -> PositionHistory {
+        PositionHistory {
+            samples: samples
+                .iter()
+                .map(|&(tick, x)| PositionSample {
+                    tick,
+                    translation: Vec3::new(x, 0.0, 0.0),
+                    rotation: Quat::IDENTITY,
+                })
+                .collect(),
+        }
+    }
+
+    /// A creature that moved from the origin to `x = 5` over the last few ticks.
+    fn spawn_moved_creature(app: &mut App) -> Entity {
+        app.world
+            .spawn((
+                history(&[(NOW - TICKS_AGO, 0.0), (NOW, 5.0)]),
+                Collider::ball(0.5),
+                GlobalTransform::from_xyz(5.0, 0.0, 0.0),
+            ))
+            .id()
+    }
+
+    #[test]
+    fn history_interpolates_between_ticks() {
+        let history = history(&[(10, 0.0), (12, 2.0), (13, 4.0)]);
+        assert_eq!(history.at(11.0).unwrap().0.x, 1.0);
+        assert_eq!(history.at(12.5).unwrap().0.x, 3.0);
+        // Outside the history the closest sample is used
+        assert_eq!(history.at(3.0).unwrap().0.x, 0.0);
+        assert_eq!(history.at(20.0).unwrap().0.x, 4.0);
+        assert!(PositionHistory::default().at(10.0).is_none());
+    }
+
+    #[test]
+    fn claimed_ticks_are_clamped_to_the_history() {
+        let mut app = app();
+        let actor = app.world.spawn_empty().id();
+        let mut state = SystemState::<LagCompensation>::new(&mut app.world);
+        let lag = state.get(&app.world);
+        let oldest = (NOW - history_ticks(&lag.network_time)) as f32;
+
+        let recent = NOW as f32 - 1.5;
+        assert_eq!(lag.clamp_tick(recent, actor), recent);
+        assert_eq!(lag.clamp_tick(0.0, actor), oldest);
+        assert_eq!(lag.clamp_tick(oldest - 0.5, actor), oldest);
+        assert_eq!(lag.clamp_tick(NOW as f32 + 10.0, actor), NOW as f32);
+    }
+
+    #[test]
+    fn rays_hit_where_the_creature_was() {
+        let mut app = app();
+        let creature = spawn_moved_creature(&mut app);
+        let mut state = SystemState::<LagCompensation>::new(&mut app.world);
+        let lag = state.get(&app.world);
+
+        let origin = Vec3::new(0.0, 0.0, -5.0);
+        let groups = CollisionGroups::default();
+        let past = (NOW - TICKS_AGO) as f32;
+        let (hit, toi) = lag
+            .cast_ray(past, origin, Vec3::Z, 10.0, groups, &[])
+            .unwrap();
+        assert_eq!(hit, creature);
+        assert!((toi - 4.5).abs() < 0.01, "hit at {}", toi);
+        assert_eq!(lag.position_at(creature, past), Some(Vec3::ZERO));
+        assert!(lag.is_rewound(creature));
+
+        // The creature isn't there anymore
+        assert!(lag
+            .cast_ray(NOW as f32, origin, Vec3::Z, 10.0, groups, &[])
+            .is_none());
+        assert!(lag
+            .cast_ray(past, origin, Vec3::Z, 10.0, groups, &[creature])
+            .is_none());
+    }
+
+    #[test]
+    fn rewound_positions_can_be_clamped_into() {
+        let mut app = app();
+        let creature = spawn_moved_creature(&mut app);
+        let actor = app.world.spawn_empty().id();
+        let mut state = SystemState::<LagCompensation>::new(&mut app.world);
+        let lag = state.get(&app.world);
+
+        // A claim from the future checks where the creature is now
+        let tick = lag.clamp_tick(NOW as f32 + 100.0, actor);
+        assert_eq!(lag.position_at(creature, tick), Some(Vec3::X * 5.0));
+    }
+
+    #[test]
+    fn body_positions_are_recorded() {
+        let mut app = server_app(ServerConfig::default());
+        let body = app
+            .world
+            .spawn((
+                Body::default(),
+                SpatialBundle::from_transform(Transform::from_xyz(1.0, 0.0, 2.0)),
+            ))
+            .id();
+        for _ in 0..3 {
+            app.update();
+        }
+
+        let history = app.world.get::<PositionHistory>(body).unwrap();
+        let last = history.samples.back().unwrap();
+        assert_eq!(last.translation, Vec3::new(1.0, 0.0, 2.0));
+        assert_eq!(
+            last.tick,
+            app.world.resource::<ServerNetworkTime>().current_tick()
+        );
+    }
+}
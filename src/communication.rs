@@ -1,44 +1,77 @@
 use std::ops::Range;
 
-use bevy::{prelude::*, utils::HashMap};
-use bevy_egui::{egui, EguiContexts};
+use bevy::prelude::*;
 use networking::{
     identity::{NetworkIdentities, NetworkIdentity},
     is_server,
     messaging::{AppExt, MessageEvent, MessageReceivers, MessageSender},
     spawning::ClientControls,
-    Players,
+    visibility::NetworkVisibilities,
+    ConnectionId, Players,
 };
 use serde::{Deserialize, Serialize};
 
-use crate::{camera::MainCamera, ui::has_window, GameState};
+use crate::{
+    console::{CommandSource, ConsoleInputEvent},
+    text_filter::{PlayerText, TextContext},
+};
+
+pub use self::announcement::{AnnouncementAudience, AnnouncementEvent};
+use self::{
+    announcement::AnnouncementPlugin,
+    radio::{parse_radio_prefix, RadioChannel, RadioPlugin, Radios},
+};
+
+mod announcement;
+mod radio;
+
+#[cfg(feature = "client")]
+use {
+    crate::{
+        camera::{MainCamera, WorldCursor},
+        ui::has_window,
+        GameState,
+    },
+    bevy::utils::HashMap,
+    bevy_egui::{egui, EguiContexts},
+    std::collections::VecDeque,
+};
 
 pub struct CommunicationPlugin;
 
+/// Largest serialized chat message a client can send
+const MAX_SPEAK_MESSAGE_SIZE: u64 = 2048;
+
 impl Plugin for CommunicationPlugin {
     fn build(&self, app: &mut App) {
-        app.add_network_message::<SpeakMessage>()
-            .add_network_message::<SpeechMessage>();
+        app.add_network_message_with_limit::<SpeakMessage>("SpeakMessage", MAX_SPEAK_MESSAGE_SIZE)
+            .add_network_message::<SpeechMessage>("SpeechMessage")
+            .add_event::<EmoteEvent>()
+            .add_event::<SystemMessageEvent>()
+            .add_plugins((RadioPlugin, AnnouncementPlugin));
 
         if is_server(app) {
-            app.add_systems(Update, handle_speech);
-        } else {
-            app.init_resource::<ClientChat>().add_systems(
+            app.add_systems(
                 Update,
-                (
-                    (client_chat_box, client_speech_bubbles)
-                        .run_if(has_window)
-                        .run_if(in_state(GameState::Game)),
-                    client_handle_chat,
-                ),
+                (handle_speech, handle_emotes, handle_system_messages),
             );
+        } else {
+            #[cfg(feature = "client")]
+            app.init_resource::<ClientChat>()
+                .init_resource::<ChatSettings>()
+                .add_systems(
+                    Update,
+                    (
+                        (client_chat_box, client_speech_bubbles)
+                            .run_if(has_window)
+                            .run_if(in_state(GameState::Game)),
+                        client_handle_chat,
+                    ),
+                );
         }
     }
 }
 
-#[derive(Serialize, Deserialize)]
-struct RadioChannel(pub u32);
-
 #[derive(Serialize, Deserialize)]
 enum ChatKind {
     Local,
@@ -70,6 +103,7 @@ struct ChatFormat {
     bold: bool,
 }
 
+#[cfg(feature = "client")]
 impl From<ChatFormat> for egui::TextFormat {
     fn from(value: ChatFormat) -> Self {
         egui::TextFormat {
@@ -120,21 +154,28 @@ impl ChatMessage {
         self.spoken_range = Some(start..self.text.len());
     }
 
-    fn append_to(&self, layout: &mut egui::text::LayoutJob) {
+    /// Adds the message to the chat history, optionally in a different color
+    #[cfg(feature = "client")]
+    fn append_to(&self, layout: &mut egui::text::LayoutJob, color: Option<egui::Color32>) {
         Self::add_newline(layout);
 
         let base_index = layout.text.len();
         layout.text += self.text.as_str();
 
         for section in &self.sections {
+            let mut format: egui::TextFormat = section.format.into();
+            if let Some(color) = color {
+                format.color = color;
+            }
             layout.sections.push(egui::text::LayoutSection {
                 leading_space: 0.0,
                 byte_range: (base_index + section.range.start)..(base_index + section.range.end),
-                format: section.format.into(),
+                format,
             });
         }
     }
 
+    #[cfg(feature = "client")]
     fn append_spoken_part(&self, layout: &mut egui::text::LayoutJob) -> Option<()> {
         let range = self.spoken_range.clone()?;
         let spoken = &self.text[range.clone()];
@@ -165,6 +206,7 @@ impl ChatMessage {
         Some(())
     }
 
+    #[cfg(feature = "client")]
     fn add_newline(layout: &mut egui::text::LayoutJob) {
         if !layout.sections.is_empty() {
             layout.append("\n", 0.0, egui::TextFormat::default());
@@ -181,6 +223,8 @@ pub struct SpeechName(pub String);
 struct SpeakMessage {
     text: String,
     kind: ChatKind,
+    /// Where the cursor points in the world, only sent with commands
+    cursor: Option<Vec3>,
 }
 
 /// Server message when someone said something
@@ -188,14 +232,21 @@ struct SpeakMessage {
 struct SpeechMessage {
     message: ChatMessage,
     speaker: Option<NetworkIdentity>,
+    /// The radio channel the message was sent on, if it wasn't spoken out loud
+    channel: Option<RadioChannel>,
 }
 
+#[allow(clippy::too_many_arguments)]
 fn handle_speech(
     mut messages: EventReader<MessageEvent<SpeakMessage>>,
     players: Res<Players>,
     controlled: Res<ClientControls>,
     identities: Res<NetworkIdentities>,
     names: Query<AnyOf<(&SpeechName, &Name)>>,
+    radios: Radios,
+    mut console: EventWriter<ConsoleInputEvent>,
+    mut system_messages: EventWriter<SystemMessageEvent>,
+    mut player_text: PlayerText,
     mut sender: MessageSender,
 ) {
     for event in messages.iter() {
@@ -203,6 +254,16 @@ fn handle_speech(
             continue;
         };
 
+        // Commands are never spoken, even by players without a body
+        if let Some(line) = event.message.text.strip_prefix('/') {
+            console.send(ConsoleInputEvent {
+                source: CommandSource::Player(event.connection),
+                line: line.to_owned(),
+                cursor: event.message.cursor.filter(|c| c.is_finite()),
+            });
+            continue;
+        }
+
         let Some(player_entity) = controlled.controlled_entity(player.id) else {
             continue;
         };
@@ -214,11 +275,51 @@ fn handle_speech(
             _ => "Unknown".to_owned(),
         };
 
-        let text = event.message.text.as_str();
+        let Some(text) =
+            player_text.accept(event.connection, TextContext::Chat, &event.message.text)
+        else {
+            continue;
+        };
+        if text.is_empty() {
+            continue;
+        }
+        // A prefix in the text wins over the channel picked in the chat window
+        let (prefix_channel, text) = parse_radio_prefix(text.as_str());
+        if text.is_empty() {
+            continue;
+        }
+        let requested = prefix_channel.or(match event.message.kind {
+            ChatKind::Radio(channel) => Some(channel),
+            _ => None,
+        });
 
         // TODO: Use chat kind (ex. OOC)
 
+        let mut channel = None;
+        if let Some(requested) = requested {
+            match radios.worn_headset(player_entity) {
+                None => system_messages.send(SystemMessageEvent {
+                    receiver: event.connection,
+                    text: "You aren't wearing a headset, so you say it out loud.".into(),
+                }),
+                Some(headset) if !headset.has_channel(requested) => {
+                    system_messages.send(SystemMessageEvent {
+                        receiver: event.connection,
+                        text: format!(
+                            "Your headset has no encryption key for the {} channel.",
+                            requested.name()
+                        ),
+                    });
+                    continue;
+                }
+                Some(_) => channel = Some(requested),
+            }
+        }
+
         let mut message = ChatMessage::default();
+        if let Some(channel) = channel {
+            message.section(&format!("[{}] ", channel.name()), Default::default());
+        }
         message.section(
             &name,
             ChatFormat {
@@ -232,38 +333,189 @@ fn handle_speech(
 
         info!(
             player = player.id.to_string().as_str(),
-            text, "Chat message"
+            text,
+            channel = channel.map(|c| c.name()),
+            "Chat message"
         );
 
+        let (speaker, receivers) = match channel {
+            // Radio messages aren't heard around the speaker, so there's no speech bubble
+            Some(channel) => (
+                None,
+                MessageReceivers::Set(radios.listeners(channel, &players, &controlled)),
+            ),
+            // TODO: Respect local chat, only send to nearby & hearing players
+            None => (
+                identities.get_identity(player_entity),
+                MessageReceivers::AllPlayers,
+            ),
+        };
         sender.send(
             &SpeechMessage {
                 message,
-                speaker: identities.get_identity(player_entity),
+                speaker,
+                channel,
             },
-            // TODO: Respect local chat, only send to nearby & hearing players
-            MessageReceivers::AllPlayers,
+            receivers,
+        );
+    }
+}
+
+/// Sends a message only a single player can see, like command output.
+#[derive(Event)]
+pub struct SystemMessageEvent {
+    pub receiver: ConnectionId,
+    pub text: String,
+}
+
+fn handle_system_messages(mut events: EventReader<SystemMessageEvent>, mut sender: MessageSender) {
+    for event in events.iter() {
+        let mut message = ChatMessage::default();
+        message.section(
+            &event.text,
+            ChatFormat {
+                italics: true,
+                ..Default::default()
+            },
+        );
+
+        sender.send(
+            &SpeechMessage {
+                message,
+                speaker: None,
+                channel: None,
+            },
+            MessageReceivers::Single(event.receiver),
         );
     }
 }
 
+/// Tells players about something a creature did.
+/// `{target}` in the text is replaced with the name of the target.
+#[derive(Event)]
+pub struct EmoteEvent {
+    pub actor: Entity,
+    pub target: Option<Entity>,
+    pub text: String,
+}
+
+fn handle_emotes(
+    mut events: EventReader<EmoteEvent>,
+    names: Query<AnyOf<(&SpeechName, &Name)>>,
+    identities: Query<&NetworkIdentity>,
+    visibilities: Res<NetworkVisibilities>,
+    mut sender: MessageSender,
+) {
+    let get_name = |entity| match names.get(entity) {
+        Ok((Some(speech_name), _)) => speech_name.0.clone(),
+        Ok((_, Some(name))) => name.as_str().to_owned(),
+        _ => "Unknown".to_owned(),
+    };
+
+    for event in events.iter() {
+        let mut text = event.text.clone();
+        if let Some(target) = event.target {
+            text = text.replace("{target}", &get_name(target));
+        }
+
+        let mut message = ChatMessage::default();
+        message.section(
+            &get_name(event.actor),
+            ChatFormat {
+                bold: true,
+                ..Default::default()
+            },
+        );
+        message.section(
+            &format!(" {}", text),
+            ChatFormat {
+                italics: true,
+                ..Default::default()
+            },
+        );
+
+        // Only players that can see the actor see the emote
+        let receivers = identities
+            .get(event.actor)
+            .ok()
+            .and_then(|&identity| visibilities.get(identity))
+            .map(|visibility| MessageReceivers::Set(visibility.observers().copied().collect()))
+            .unwrap_or(MessageReceivers::AllPlayers);
+        sender.send(
+            &SpeechMessage {
+                message,
+                speaker: None,
+                channel: None,
+            },
+            receivers,
+        );
+    }
+}
+
+/// Where spoken messages are shown.
+#[cfg(feature = "client")]
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub enum ChatDisplay {
+    /// Only above the speaker's head
+    Bubbles,
+    /// Only in the chat window
+    ChatOnly,
+    #[default]
+    Both,
+}
+
+#[cfg(feature = "client")]
+impl ChatDisplay {
+    pub const ALL: [Self; 3] = [Self::Both, Self::Bubbles, Self::ChatOnly];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ChatDisplay::Bubbles => "Speech bubbles",
+            ChatDisplay::ChatOnly => "Chat window",
+            ChatDisplay::Both => "Both",
+        }
+    }
+
+    fn shows_bubbles(self) -> bool {
+        !matches!(self, ChatDisplay::ChatOnly)
+    }
+
+    fn shows_chat_window(self) -> bool {
+        !matches!(self, ChatDisplay::Bubbles)
+    }
+}
+
+/// Player preferences for the chat.
+#[cfg(feature = "client")]
+#[derive(Resource, Default)]
+pub struct ChatSettings {
+    pub display: ChatDisplay,
+}
+
+#[cfg(feature = "client")]
 #[derive(Resource, Default)]
 struct ClientChat {
     input_chat: String,
+    /// Radio channel messages are sent on without a prefix, spoken out loud if `None`
+    channel: Option<RadioChannel>,
     history: egui::text::LayoutJob,
-    bubbles: HashMap<NetworkIdentity, SpeechBubble>,
-    bubble_id: usize,
+    /// Recent messages of every speaker, oldest first
+    bubbles: HashMap<NetworkIdentity, VecDeque<SpeechBubble>>,
 }
 
+#[cfg(feature = "client")]
 struct SpeechBubble {
-    id: usize,
     text: egui::text::LayoutJob,
     when: f32,
+    duration: f32,
 }
 
+#[cfg(feature = "client")]
 fn client_chat_box(
     mut contexts: EguiContexts,
     mut data: ResMut<ClientChat>,
     mut keyboard: ResMut<Input<KeyCode>>,
+    cursor: Res<WorldCursor>,
     mut sender: MessageSender,
 ) {
     egui::Window::new("Chat")
@@ -276,11 +528,29 @@ fn client_chat_box(
                 ui.label(data.history.clone());
             });
 
-            let response = egui::TextEdit::singleline(&mut data.input_chat)
-                .hint_text("Talk")
-                .id_source("chat_input")
-                .show(ui)
-                .response;
+            let data = &mut *data;
+            let response = ui
+                .horizontal(|ui| {
+                    egui::ComboBox::from_id_source("chat_channel")
+                        .width(80.0)
+                        .selected_text(data.channel.map_or("Local", |c| c.name()))
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut data.channel, None, "Local");
+                            for channel in RadioChannel::ALL {
+                                ui.selectable_value(
+                                    &mut data.channel,
+                                    Some(channel),
+                                    egui::RichText::new(channel.name()).color(channel.color()),
+                                );
+                            }
+                        });
+                    egui::TextEdit::singleline(&mut data.input_chat)
+                        .hint_text("Talk")
+                        .id_source("chat_input")
+                        .show(ui)
+                        .response
+                })
+                .inner;
 
             // Focus chat if chat key is pressed
             if keyboard.clear_just_pressed(KeyCode::T) {
@@ -292,9 +562,11 @@ fn client_chat_box(
                     .input(|input| input.key_pressed(egui::Key::Enter))
             {
                 if !data.input_chat.trim().is_empty() {
+                    let is_command = data.input_chat.starts_with('/');
                     sender.send_to_server(&SpeakMessage {
                         text: std::mem::take(&mut data.input_chat),
-                        kind: ChatKind::Local,
+                        kind: data.channel.map_or(ChatKind::Local, ChatKind::Radio),
+                        cursor: cursor.0.filter(|_| is_command),
                     });
                 }
                 data.input_chat.clear();
@@ -302,90 +574,262 @@ fn client_chat_box(
         });
 }
 
+#[cfg(feature = "client")]
 fn client_handle_chat(
     mut messages: EventReader<MessageEvent<SpeechMessage>>,
     mut data: ResMut<ClientChat>,
+    settings: Res<ChatSettings>,
     time: Res<Time>,
 ) {
     for event in messages.iter() {
-        let data = &mut *data;
-        event.message.message.append_to(&mut data.history);
+        let message = &event.message.message;
+        let speaker = event
+            .message
+            .speaker
+            .filter(|_| message.spoken_range.is_some());
+
+        // Messages that can't be shown as a bubble always go in the chat window
+        if speaker.is_none() || settings.display.shows_chat_window() {
+            let color = event.message.channel.map(RadioChannel::color);
+            message.append_to(&mut data.history, color);
+        }
 
-        // Check if we should add a speech bubble
-        let Some(speaker) = event.message.speaker else {
+        let Some(speaker) = speaker else {
             continue;
         };
-
-        if event.message.message.spoken_range.is_none() {
+        if !settings.display.shows_bubbles() {
             continue;
         }
 
-        let bubble = data
-            .bubbles
-            .entry(speaker)
-            .and_modify(|bubble| bubble.when = time.elapsed_seconds())
-            .or_insert_with(|| {
-                let id = data.bubble_id.wrapping_add(1);
-                data.bubble_id = id;
-                SpeechBubble {
-                    id,
-                    text: Default::default(),
-                    when: time.elapsed_seconds(),
-                }
-            });
-        event.message.message.append_spoken_part(&mut bubble.text);
+        let mut text = egui::text::LayoutJob::default();
+        message.append_spoken_part(&mut text);
+        let duration = speech_bubble_duration(&text.text);
+        let bubbles = data.bubbles.entry(speaker).or_default();
+        bubbles.push_back(SpeechBubble {
+            text,
+            when: time.elapsed_seconds(),
+            duration,
+        });
+        if bubbles.len() > MAX_SPEECH_BUBBLES {
+            bubbles.pop_front();
+        }
     }
 }
 
-// TODO: Duration depending on text length
-// TODO: Add accessibility setting
-const SPEECH_BUBBLE_DURATION: f32 = 4.0;
+/// How many messages are stacked above a speaker at once
+#[cfg(feature = "client")]
+const MAX_SPEECH_BUBBLES: usize = 3;
+/// Speech bubbles are shown for at least this many seconds
+#[cfg(feature = "client")]
+const SPEECH_BUBBLE_MIN_DURATION: f32 = 2.5;
+/// Additional seconds a speech bubble is shown for each character
+#[cfg(feature = "client")]
+const SPEECH_BUBBLE_CHARACTER_DURATION: f32 = 0.06;
+#[cfg(feature = "client")]
+const SPEECH_BUBBLE_MAX_DURATION: f32 = 10.0;
+/// Seconds a speech bubble takes to fade out at the end of its duration
+#[cfg(feature = "client")]
+const SPEECH_BUBBLE_FADE: f32 = 0.5;
+/// Speakers further away from the camera don't show speech bubbles
+#[cfg(feature = "client")]
+const SPEECH_BUBBLE_MAX_DISTANCE: f32 = 25.0;
+#[cfg(feature = "client")]
+const SPEECH_BUBBLE_WIDTH: f32 = 220.0;
+#[cfg(feature = "client")]
+const SPEECH_BUBBLE_PADDING: f32 = 4.0;
+
+#[cfg(feature = "client")]
+fn speech_bubble_duration(text: &str) -> f32 {
+    (SPEECH_BUBBLE_MIN_DURATION + text.chars().count() as f32 * SPEECH_BUBBLE_CHARACTER_DURATION)
+        .min(SPEECH_BUBBLE_MAX_DURATION)
+}
 
+#[cfg(feature = "client")]
 fn client_speech_bubbles(
     mut contexts: EguiContexts,
     mut data: ResMut<ClientChat>,
+    settings: Res<ChatSettings>,
     camera: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
     transforms: Query<&GlobalTransform>,
     identities: Res<NetworkIdentities>,
     time: Res<Time>,
 ) {
+    let now = time.elapsed_seconds();
+    data.bubbles.retain(|_, bubbles| {
+        bubbles.retain(|bubble| bubble.when + bubble.duration > now);
+        !bubbles.is_empty()
+    });
+
+    if !settings.display.shows_bubbles() {
+        return;
+    }
+
+    // The camera can be missing while it is being switched
     let Ok((camera, camera_transform)) = camera.get_single() else {
         return;
     };
 
-    data.bubbles.retain(|&speaker, bubble| {
-        if bubble.when + SPEECH_BUBBLE_DURATION < time.elapsed_seconds() {
-            return false;
+    let ctx = contexts.ctx_mut();
+    let painter = ctx.layer_painter(egui::LayerId::new(
+        egui::Order::Background,
+        egui::Id::new("speech_bubbles"),
+    ));
+
+    for (&speaker, bubbles) in data.bubbles.iter() {
+        // The speaker may have despawned, their bubbles will expire on their own
+        let Some(transform) = identities
+            .get_entity(speaker)
+            .and_then(|entity| transforms.get(entity).ok())
+        else {
+            continue;
+        };
+
+        // TODO: Calculate offset from character bounding box
+        let head = transform.translation() + Vec3::Y * 1.8;
+        if head.distance(camera_transform.translation()) > SPEECH_BUBBLE_MAX_DISTANCE {
+            continue;
         }
 
-        let Some(entity) = identities.get_entity(speaker) else {
-            return true;
+        // Skip speakers outside the view frustum
+        let Some(ndc) = camera.world_to_ndc(camera_transform, head) else {
+            continue;
         };
-
-        let Ok(transform) = transforms.get(entity) else {
-            return true;
+        if ndc.x.abs() > 1.0 || ndc.y.abs() > 1.0 || !(0.0..=1.0).contains(&ndc.z) {
+            continue;
+        }
+        let Some(screen_position) = camera.world_to_viewport(camera_transform, head) else {
+            continue;
         };
 
-        // TODO: Calculate offset from character bounding box
-        let offset = Vec3::Y * 1.8;
+        // Newest message is at the bottom, older ones are pushed up
+        let mut bottom = screen_position.y;
+        for bubble in bubbles.iter().rev() {
+            let alpha =
+                ((bubble.when + bubble.duration - now) / SPEECH_BUBBLE_FADE).clamp(0.0, 1.0);
+
+            // TODO: Cache the galley instead of laying out every frame
+            let mut job = bubble.text.clone();
+            job.wrap.max_width = SPEECH_BUBBLE_WIDTH;
+            job.halign = egui::Align::Center;
+            let galley = ctx.fonts(|fonts| fonts.layout_job(job));
+
+            let rect = egui::Align2::CENTER_BOTTOM.anchor_rect(egui::Rect::from_min_size(
+                egui::pos2(screen_position.x, bottom),
+                galley.size(),
+            ));
+            let background = rect.expand(SPEECH_BUBBLE_PADDING);
+            painter.rect_filled(
+                background,
+                SPEECH_BUBBLE_PADDING,
+                egui::Color32::from_black_alpha(180).gamma_multiply(alpha),
+            );
+            let mut shape = egui::epaint::TextShape::new(rect.center_top(), galley);
+            shape.override_text_color = Some(egui::Color32::WHITE.gamma_multiply(alpha));
+            painter.add(shape);
 
-        let Some(screen_position) =
-            camera.world_to_viewport(camera_transform, transform.translation() + offset)
-        else {
-            return true;
-        };
+            bottom = background.min.y - SPEECH_BUBBLE_PADDING;
+        }
+    }
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use bevy::ecs::system::SystemState;
+    use networking::{loopback::LinkConditions, testing, NetworkRole};
+
+    use super::*;
+    use crate::{config::ServerConfig, testing::server_app};
+
+    #[derive(Resource, Default)]
+    struct Heard(Vec<String>);
+
+    fn record_speech(
+        mut messages: EventReader<MessageEvent<SpeechMessage>>,
+        mut heard: ResMut<Heard>,
+    ) {
+        heard.0.extend(
+            messages
+                .iter()
+                .map(|event| event.message.message.text.clone()),
+        );
+    }
 
-        egui::Window::new("")
-            .id(egui::Id::new("speech_bubble").with(bubble.id))
-            .title_bar(false)
-            .resizable(false)
-            .fixed_pos(egui::pos2(screen_position.x, screen_position.y))
-            .pivot(egui::Align2::CENTER_BOTTOM)
-            .show(contexts.ctx_mut(), |ui| {
-                // TODO: Use a non-allocating Galley instead
-                ui.label(bubble.text.clone());
+    fn client() -> App {
+        let mut client = testing::app(NetworkRole::Client);
+        client
+            .add_network_message::<SpeakMessage>("SpeakMessage")
+            .add_network_message::<SpeechMessage>("SpeechMessage")
+            .init_resource::<Heard>()
+            .add_systems(Update, record_speech);
+        client
+    }
+
+    fn say(client: &mut App, text: &str) {
+        let mut state = SystemState::<MessageSender>::new(&mut client.world);
+        state
+            .get_mut(&mut client.world)
+            .send_to_server(&SpeakMessage {
+                text: text.into(),
+                kind: ChatKind::Local,
+                cursor: None,
             });
+    }
 
-        true
-    });
+    /// A server that replaces a word, with a speaking and a listening client.
+    fn setup() -> (App, App, App) {
+        let mut config = ServerConfig::default();
+        config
+            .text_filter
+            .replacements
+            .insert("heck".into(), "****".into());
+        let mut server = server_app(config);
+        let mut speaker = client();
+        let mut listener = client();
+        let connector = testing::listen(&mut server);
+        testing::join(&mut speaker, &connector, LinkConditions::default());
+        testing::connect(&mut server, &mut [&mut speaker], 200);
+
+        // The speaker joins first, so it's the only player yet
+        let player = server
+            .world
+            .resource::<Players>()
+            .players()
+            .values()
+            .next()
+            .unwrap()
+            .id;
+        let creature = server.world.spawn(Name::new("Alice")).id();
+        server
+            .world
+            .resource_mut::<ClientControls>()
+            .give_control(player, creature);
+
+        testing::join(&mut listener, &connector, LinkConditions::default());
+        testing::connect(&mut server, &mut [&mut speaker, &mut listener], 200);
+        (server, speaker, listener)
+    }
+
+    #[test]
+    fn speech_is_filtered_before_it_is_broadcast() {
+        let (mut server, mut speaker, mut listener) = setup();
+        say(&mut speaker, "what the heck");
+        testing::update(&mut server, &mut [&mut speaker, &mut listener], 10);
+
+        let expected = ["Alice says, \"what the ****\""];
+        assert_eq!(listener.world.resource::<Heard>().0, expected);
+        assert_eq!(speaker.world.resource::<Heard>().0, expected);
+    }
+
+    #[test]
+    fn rejected_speech_only_reaches_the_speaker() {
+        let (mut server, mut speaker, mut listener) = setup();
+        say(&mut speaker, &"abcdefghij".repeat(60));
+        testing::update(&mut server, &mut [&mut speaker, &mut listener], 10);
+
+        assert!(listener.world.resource::<Heard>().0.is_empty());
+        assert_eq!(
+            speaker.world.resource::<Heard>().0,
+            ["Your message was not accepted: too long, the limit is 512 characters"]
+        );
+    }
 }
@@ -0,0 +1,455 @@
+use bevy::{
+    prelude::*,
+    utils::{HashMap, Uuid},
+};
+use networking::{
+    is_server,
+    messaging::{AppExt, MessageReceivers, MessageSender},
+    spawning::ClientControls,
+    ConnectionId, Players, ServerEvent,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    job::{Affiliation, JobDefinition},
+    round::RoundState,
+};
+
+use super::SpeechName;
+
+#[cfg(feature = "client")]
+use {
+    super::{ChatFormat, ChatMessage, ClientChat},
+    crate::{
+        camera::MainCamera,
+        locale::Locale,
+        sound::{PlaySoundMessage, SoundId},
+        ui::has_window,
+        GameState,
+    },
+    bevy_egui::{egui, EguiContexts},
+    networking::messaging::MessageEvent,
+};
+
+/// Station announcements, shown as a banner and in the chat with a sound.
+/// Sent by the round, the timeline, arrivals and departures and command consoles.
+pub struct AnnouncementPlugin;
+
+impl Plugin for AnnouncementPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_network_message::<AnnouncementMessage>("AnnouncementMessage")
+            .add_event::<AnnouncementEvent>();
+
+        if is_server(app) {
+            app.add_systems(Update, (announce_departures, handle_announcements).chain());
+        } else {
+            #[cfg(feature = "client")]
+            app.init_resource::<AnnouncementBanner>().add_systems(
+                Update,
+                (
+                    receive_announcements,
+                    announcement_banner.run_if(has_window),
+                )
+                    .chain()
+                    .run_if(in_state(GameState::Game)),
+            );
+        }
+    }
+}
+
+/// Who hears an announcement.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug, Default)]
+pub enum AnnouncementAudience {
+    #[default]
+    Everyone,
+    /// Only creatures whose job belongs to the department, see [`JobDefinition::department`]
+    Department(String),
+}
+
+/// Sends an announcement. The text is looked up by key on the client, see [`crate::locale`].
+#[derive(Event)]
+pub struct AnnouncementEvent {
+    pub text_key: String,
+    pub args: Vec<String>,
+    pub audience: AnnouncementAudience,
+}
+
+impl AnnouncementEvent {
+    pub fn new(text_key: &str, args: &[&str], audience: AnnouncementAudience) -> Self {
+        Self {
+            text_key: text_key.to_owned(),
+            args: args.iter().map(|&a| a.to_owned()).collect(),
+            audience,
+        }
+    }
+
+    /// Text that isn't looked up, like announcements put together from a list of players or typed by an admin.
+    pub fn custom(text: String) -> Self {
+        Self {
+            text_key: "announcement.custom".into(),
+            args: vec![text],
+            audience: AnnouncementAudience::Everyone,
+        }
+    }
+}
+
+/// Server message with an announcement for the player.
+#[derive(Serialize, Deserialize, Clone)]
+struct AnnouncementMessage {
+    text_key: String,
+    args: Vec<String>,
+    /// Set if only a department hears it
+    department: Option<String>,
+}
+
+fn handle_announcements(
+    mut events: EventReader<AnnouncementEvent>,
+    players: Res<Players>,
+    controls: Res<ClientControls>,
+    affiliations: Query<&Affiliation>,
+    mut sender: MessageSender,
+) {
+    for event in events.iter() {
+        info!(
+            key = event.text_key.as_str(),
+            args = ?event.args,
+            audience = ?event.audience,
+            "Announcement"
+        );
+
+        let (receivers, department) = match &event.audience {
+            AnnouncementAudience::Everyone => (MessageReceivers::AllPlayers, None),
+            AnnouncementAudience::Department(department) => {
+                let connections =
+                    department_members(department, &players, &controls, &affiliations);
+                if connections.is_empty() {
+                    continue;
+                }
+                (
+                    MessageReceivers::Set(connections.into_iter().collect()),
+                    Some(department.clone()),
+                )
+            }
+        };
+        sender.send(
+            &AnnouncementMessage {
+                text_key: event.text_key.clone(),
+                args: event.args.clone(),
+                department,
+            },
+            receivers,
+        );
+    }
+}
+
+/// Players controlling a creature of the department. Spectators and ghosts have no department.
+fn department_members(
+    department: &str,
+    players: &Players,
+    controls: &ClientControls,
+    affiliations: &Query<&Affiliation>,
+) -> Vec<ConnectionId> {
+    players
+        .players()
+        .iter()
+        .filter(|(_, player)| {
+            controls
+                .controlled_entity(player.id)
+                .and_then(|entity| affiliations.get(entity).ok())
+                .is_some_and(|affiliation| affiliation.department == department)
+        })
+        .map(|(&connection, _)| connection)
+        .collect()
+}
+
+/// Announces crew leaving the server while the round is running.
+/// Arrivals are announced when late joiners finish spawning.
+#[allow(clippy::too_many_arguments)]
+fn announce_departures(
+    mut events: EventReader<ServerEvent>,
+    players: Res<Players>,
+    controls: Res<ClientControls>,
+    creatures: Query<(&Affiliation, Option<&SpeechName>)>,
+    jobs: Res<Assets<JobDefinition>>,
+    round: Res<State<RoundState>>,
+    mut connected: Local<HashMap<ConnectionId, (Uuid, String)>>,
+    mut announcements: EventWriter<AnnouncementEvent>,
+) {
+    for event in events.iter() {
+        match *event {
+            ServerEvent::PlayerConnected(connection) => {
+                if let Some(player) = players.get(connection) {
+                    connected.insert(connection, (player.id, player.username.clone()));
+                }
+            }
+            ServerEvent::PlayerDisconnected(connection) => {
+                let Some((player, username)) = connected.remove(&connection) else {
+                    continue;
+                };
+                if *round.get() != RoundState::Running {
+                    continue;
+                }
+                // Only crew with a body leave the station, spectators just stop watching
+                let Some((affiliation, name)) = controls
+                    .controlled_entity(player)
+                    .and_then(|entity| creatures.get(entity).ok())
+                else {
+                    continue;
+                };
+                let name = name.map_or(username.as_str(), |n| n.0.as_str());
+                let job = jobs
+                    .iter()
+                    .find(|(_, job)| job.id == affiliation.job)
+                    .map_or(affiliation.job.as_str(), |(_, job)| job.name.as_str());
+                announcements.send(AnnouncementEvent::new(
+                    "announcement.departure",
+                    &[name, job],
+                    AnnouncementAudience::Everyone,
+                ));
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Seconds an announcement banner is shown
+#[cfg(feature = "client")]
+const BANNER_DURATION: f32 = 8.0;
+/// Seconds the banner takes to slide in, and to fade out at the end
+#[cfg(feature = "client")]
+const BANNER_SLIDE: f32 = 0.4;
+#[cfg(feature = "client")]
+const ANNOUNCEMENT_COLOR: egui::Color32 = egui::Color32::from_rgb(255, 200, 90);
+
+/// Announcements waiting to be shown as a banner, the first one is on screen.
+#[cfg(feature = "client")]
+#[derive(Resource, Default)]
+struct AnnouncementBanner {
+    queue: std::collections::VecDeque<(String, String)>,
+    /// When the first banner in the queue was shown
+    shown_at: Option<f32>,
+}
+
+#[cfg(feature = "client")]
+fn receive_announcements(
+    mut messages: EventReader<MessageEvent<AnnouncementMessage>>,
+    mut chat: ResMut<ClientChat>,
+    mut banner: ResMut<AnnouncementBanner>,
+    locale: Locale,
+    listener: Query<&GlobalTransform, With<MainCamera>>,
+    mut sounds: EventWriter<PlaySoundMessage>,
+) {
+    for event in messages.iter() {
+        let announcement = &event.message;
+        let text = locale.text(&announcement.text_key, &announcement.args);
+        let title = match &announcement.department {
+            Some(department) => format!("{} announcement", capitalize(department)),
+            None => "Station announcement".to_owned(),
+        };
+
+        let mut message = ChatMessage::default();
+        message.section(
+            &format!("{}: ", title),
+            ChatFormat {
+                bold: true,
+                underline: true,
+                ..Default::default()
+            },
+        );
+        message.section(&text, Default::default());
+        message.append_to(&mut chat.history, Some(ANNOUNCEMENT_COLOR));
+
+        banner.queue.push_back((title, text));
+        sounds.send(PlaySoundMessage {
+            sound: SoundId::Announcement,
+            position: listener
+                .get_single()
+                .map_or(Vec3::ZERO, |t| t.translation()),
+            surface: None,
+            impact: None,
+        });
+    }
+}
+
+#[cfg(feature = "client")]
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    chars
+        .next()
+        .map(|first| first.to_uppercase().chain(chars).collect())
+        .unwrap_or_default()
+}
+
+/// Slides the banner in at the top of the screen and dismisses it after a few seconds.
+/// Announcements arriving in the meantime are shown one after another.
+#[cfg(feature = "client")]
+fn announcement_banner(
+    mut contexts: EguiContexts,
+    mut banner: ResMut<AnnouncementBanner>,
+    time: Res<Time>,
+) {
+    let now = time.elapsed_seconds();
+    let banner = banner.as_mut();
+    if banner.queue.is_empty() {
+        banner.shown_at = None;
+        return;
+    }
+    let shown_at = *banner.shown_at.get_or_insert(now);
+    if now - shown_at > BANNER_DURATION {
+        banner.queue.pop_front();
+        banner.shown_at = None;
+        return;
+    }
+    let Some((title, text)) = banner.queue.front() else {
+        return;
+    };
+
+    let elapsed = now - shown_at;
+    let slide = (elapsed / BANNER_SLIDE).min(1.0);
+    let alpha = ((BANNER_DURATION - elapsed) / BANNER_SLIDE).min(1.0);
+    // Eases out, starting above the screen
+    let offset = -80.0 + 100.0 * (1.0 - (1.0 - slide).powi(3));
+
+    egui::Area::new("announcement_banner")
+        .anchor(egui::Align2::CENTER_TOP, egui::vec2(0.0, offset))
+        .order(egui::Order::Foreground)
+        .interactable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            egui::Frame::none()
+                .fill(egui::Color32::from_black_alpha(200).gamma_multiply(alpha))
+                .stroke(egui::Stroke::new(
+                    2.0,
+                    ANNOUNCEMENT_COLOR.gamma_multiply(alpha),
+                ))
+                .rounding(4.0)
+                .inner_margin(egui::Margin::symmetric(16.0, 8.0))
+                .show(ui, |ui| {
+                    ui.set_max_width(480.0);
+                    ui.vertical_centered(|ui| {
+                        ui.label(
+                            egui::RichText::new(title)
+                                .strong()
+                                .size(18.0)
+                                .color(ANNOUNCEMENT_COLOR.gamma_multiply(alpha)),
+                        );
+                        ui.label(
+                            egui::RichText::new(text)
+                                .color(egui::Color32::WHITE.gamma_multiply(alpha)),
+                        );
+                    });
+                });
+        });
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use networking::{
+        loopback::LinkConditions,
+        messaging::{AppExt, MessageEvent},
+        testing, NetworkRole,
+    };
+
+    use super::*;
+    use crate::{config::ServerConfig, testing::server_app};
+
+    #[derive(Resource, Default)]
+    struct Heard(u32);
+
+    fn count_announcements(
+        mut messages: EventReader<MessageEvent<AnnouncementMessage>>,
+        mut heard: ResMut<Heard>,
+    ) {
+        heard.0 += messages.iter().count() as u32;
+    }
+
+    fn listener() -> App {
+        let mut client = testing::app(NetworkRole::Client);
+        client
+            .add_network_message::<AnnouncementMessage>("AnnouncementMessage")
+            .init_resource::<Heard>()
+            .add_systems(Update, count_announcements);
+        client
+    }
+
+    fn affiliation(department: &str) -> Affiliation {
+        Affiliation {
+            team: "crew".into(),
+            job: department.into(),
+            department: department.into(),
+        }
+    }
+
+    /// A server with a security officer, a doctor and a spectator connected, in that order.
+    /// Clients join one by one, so every new player gets the next body.
+    fn setup() -> (App, Vec<App>) {
+        let mut server = server_app(ServerConfig::default());
+        let connector = testing::listen(&mut server);
+        let mut clients = Vec::new();
+        for department in [Some("security"), Some("medical"), None] {
+            let mut client = listener();
+            testing::join(&mut client, &connector, LinkConditions::default());
+            clients.push(client);
+            let mut joined: Vec<_> = clients.iter_mut().collect();
+            testing::connect(&mut server, &mut joined, 200);
+
+            let Some(department) = department else {
+                continue;
+            };
+            let creature = server.world.spawn(affiliation(department)).id();
+            let world = &mut server.world;
+            let player = world
+                .resource::<Players>()
+                .players()
+                .values()
+                .map(|player| player.id)
+                .find(|&player| {
+                    world
+                        .resource::<ClientControls>()
+                        .controlled_entity(player)
+                        .is_none()
+                })
+                .unwrap();
+            world
+                .resource_mut::<ClientControls>()
+                .give_control(player, creature);
+        }
+        (server, clients)
+    }
+
+    /// Sends an announcement and returns how many announcements each client heard.
+    fn announce(server: &mut App, clients: &mut [App], audience: AnnouncementAudience) -> Vec<u32> {
+        server.world.send_event(AnnouncementEvent::new(
+            "announcement.custom",
+            &["Test"],
+            audience,
+        ));
+        let mut clients: Vec<_> = clients.iter_mut().collect();
+        testing::update(server, &mut clients, 10);
+        clients
+            .iter()
+            .map(|client| client.world.resource::<Heard>().0)
+            .collect()
+    }
+
+    #[test]
+    fn everyone_hears_station_announcements() {
+        let (mut server, mut clients) = setup();
+        assert_eq!(
+            announce(&mut server, &mut clients, AnnouncementAudience::Everyone),
+            [1, 1, 1]
+        );
+    }
+
+    #[test]
+    fn department_announcements_exclude_other_clients() {
+        let (mut server, mut clients) = setup();
+        assert_eq!(
+            announce(
+                &mut server,
+                &mut clients,
+                AnnouncementAudience::Department("security".into())
+            ),
+            [1, 0, 0]
+        );
+    }
+}
@@ -0,0 +1,544 @@
+use bevy::{ecs::system::SystemParam, prelude::*, utils::HashSet};
+use networking::{
+    is_server, scene::NetworkSceneBundle, spawning::ClientControls, ConnectionId, Players,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    construction::Screwdriver,
+    interaction::{
+        ActiveInteraction, GenerateInteractionList, InteractionListEvents, InteractionOption,
+        InteractionSpecificity, InteractionStatus,
+    },
+    items::{
+        clothes::Wearers,
+        containers::{ItemEnteredContainer, ItemLeftContainer},
+        durability::Broken,
+    },
+};
+
+#[cfg(feature = "client")]
+use bevy_egui::egui;
+
+/// Headsets worn on the ear that send and receive department radio channels.
+pub struct RadioPlugin;
+
+impl Plugin for RadioPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<RadioChannel>()
+            .register_type::<Vec<RadioChannel>>()
+            .register_type::<Headset>()
+            .register_type::<EncryptionKey>();
+
+        if is_server(app) {
+            app.register_type::<ToggleHeadsetInteraction>()
+                .register_type::<InsertKeyInteraction>()
+                .register_type::<RemoveKeysInteraction>()
+                .add_systems(
+                    Update,
+                    (
+                        track_worn_headsets,
+                        prepare_headset_interactions.in_set(GenerateInteractionList),
+                        toggle_headset_interaction,
+                        insert_key_interaction,
+                        remove_keys_interaction,
+                    ),
+                );
+        }
+    }
+}
+
+/// How many encryption keys fit in a headset
+const MAX_KEYS: usize = 2;
+
+/// Radio channels. Every headset can use the common channel, the others need an encryption key.
+#[derive(Reflect, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub enum RadioChannel {
+    #[default]
+    Common,
+    Security,
+    Engineering,
+    Medical,
+    Command,
+}
+
+impl RadioChannel {
+    pub const ALL: [Self; 5] = [
+        Self::Common,
+        Self::Security,
+        Self::Engineering,
+        Self::Medical,
+        Self::Command,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            RadioChannel::Common => "Common",
+            RadioChannel::Security => "Security",
+            RadioChannel::Engineering => "Engineering",
+            RadioChannel::Medical => "Medical",
+            RadioChannel::Command => "Command",
+        }
+    }
+
+    /// The letter after `:` that selects a department channel
+    fn from_key(key: char) -> Option<Self> {
+        match key.to_ascii_lowercase() {
+            's' => Some(RadioChannel::Security),
+            'e' => Some(RadioChannel::Engineering),
+            'm' => Some(RadioChannel::Medical),
+            'c' => Some(RadioChannel::Command),
+            _ => None,
+        }
+    }
+
+    /// Prefab of the encryption key for the channel, the common channel doesn't need one
+    fn key_prefab(self) -> Option<&'static str> {
+        match self {
+            RadioChannel::Common => None,
+            RadioChannel::Security => Some("items/encryption_key_security.scn.ron"),
+            RadioChannel::Engineering => Some("items/encryption_key_engineering.scn.ron"),
+            RadioChannel::Medical => Some("items/encryption_key_medical.scn.ron"),
+            RadioChannel::Command => Some("items/encryption_key_command.scn.ron"),
+        }
+    }
+
+    #[cfg(feature = "client")]
+    pub fn color(self) -> egui::Color32 {
+        match self {
+            RadioChannel::Common => egui::Color32::from_rgb(80, 200, 80),
+            RadioChannel::Security => egui::Color32::from_rgb(220, 70, 70),
+            RadioChannel::Engineering => egui::Color32::from_rgb(230, 160, 50),
+            RadioChannel::Medical => egui::Color32::from_rgb(90, 170, 230),
+            RadioChannel::Command => egui::Color32::from_rgb(220, 200, 70),
+        }
+    }
+}
+
+/// Splits the radio prefix off a chat message.
+/// `;` is the common channel and `:` followed by a letter is a department, like `:s` for security.
+/// Unknown letters aren't a prefix, so the message is spoken like any other.
+pub(super) fn parse_radio_prefix(text: &str) -> (Option<RadioChannel>, &str) {
+    if let Some(rest) = text.strip_prefix(';') {
+        return (Some(RadioChannel::Common), rest.trim_start());
+    }
+    let mut chars = text.chars();
+    if chars.next() == Some(':') {
+        if let Some(channel) = chars.next().and_then(RadioChannel::from_key) {
+            return (Some(channel), chars.as_str().trim_start());
+        }
+    }
+    (None, text)
+}
+
+/// A radio worn on the ear.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct Headset {
+    /// Department channels of the encryption keys inside
+    pub keys: Vec<RadioChannel>,
+    /// Keys can only be taken out or put in while the headset is screwed open
+    pub open: bool,
+}
+
+impl Headset {
+    pub fn has_channel(&self, channel: RadioChannel) -> bool {
+        channel == RadioChannel::Common || self.keys.contains(&channel)
+    }
+}
+
+/// Lets a headset use a department channel once put inside.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct EncryptionKey {
+    pub channel: RadioChannel,
+}
+
+/// The headset a creature is wearing.
+#[derive(Component)]
+struct WornHeadset(Entity);
+
+/// Keeps track of the headsets creatures put on and take off. Headsets in hands or bags don't count.
+fn track_worn_headsets(
+    mut left: EventReader<ItemLeftContainer>,
+    mut entered: EventReader<ItemEnteredContainer>,
+    headsets: Query<(), With<Headset>>,
+    worn: Query<&WornHeadset>,
+    wearers: Wearers,
+    mut commands: Commands,
+) {
+    for event in left.iter().filter(|e| headsets.contains(e.item)) {
+        let Some(creature) = wearers.wearer(event.container) else {
+            continue;
+        };
+        if worn.get(creature).is_ok_and(|worn| worn.0 == event.item) {
+            commands.entity(creature).remove::<WornHeadset>();
+        }
+    }
+    for event in entered.iter().filter(|e| headsets.contains(e.item)) {
+        if let Some(creature) = wearers.wearer(event.container) {
+            commands.entity(creature).insert(WornHeadset(event.item));
+        }
+    }
+}
+
+/// Finds the headsets players are wearing.
+#[derive(SystemParam)]
+pub(super) struct Radios<'w, 's> {
+    worn: Query<'w, 's, &'static WornHeadset>,
+    headsets: Query<'w, 's, &'static Headset>,
+}
+
+impl<'w, 's> Radios<'w, 's> {
+    /// The headset worn by a creature.
+    pub fn worn_headset(&self, creature: Entity) -> Option<&Headset> {
+        let worn = self.worn.get(creature).ok()?;
+        self.headsets.get(worn.0).ok()
+    }
+
+    /// Players whose creature wears a headset that receives the channel.
+    pub fn listeners(
+        &self,
+        channel: RadioChannel,
+        players: &Players,
+        controls: &ClientControls,
+    ) -> HashSet<ConnectionId> {
+        players
+            .players()
+            .iter()
+            .filter(|(_, player)| {
+                controls
+                    .controlled_entity(player.id)
+                    .and_then(|creature| self.worn_headset(creature))
+                    .is_some_and(|headset| headset.has_channel(channel))
+            })
+            .map(|(&connection, _)| connection)
+            .collect()
+    }
+}
+
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+#[component(storage = "SparseSet")]
+struct ToggleHeadsetInteraction;
+
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+#[component(storage = "SparseSet")]
+struct InsertKeyInteraction {
+    key: Entity,
+}
+
+impl FromWorld for InsertKeyInteraction {
+    fn from_world(_: &mut World) -> Self {
+        // Dummy default for Reflect
+        Self {
+            key: Entity::PLACEHOLDER,
+        }
+    }
+}
+
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+#[component(storage = "SparseSet")]
+struct RemoveKeysInteraction;
+
+fn prepare_headset_interactions(
+    interaction_list: Res<InteractionListEvents>,
+    headsets: Query<&Headset>,
+    keys: Query<(), With<EncryptionKey>>,
+    screwdrivers: Query<(), (With<Screwdriver>, Without<Broken>)>,
+) {
+    for event in interaction_list.events.iter() {
+        let Ok(headset) = headsets.get(event.target) else {
+            continue;
+        };
+
+        let option = match event.item_in_hand {
+            Some(item) if screwdrivers.contains(item) => InteractionOption {
+                text: if headset.open {
+                    "Close headset"
+                } else {
+                    "Open headset"
+                }
+                .into(),
+                interaction: Box::new(ToggleHeadsetInteraction),
+                specificity: InteractionSpecificity::Specific,
+            },
+            Some(item) if headset.open && keys.contains(item) && headset.keys.len() < MAX_KEYS => {
+                InteractionOption {
+                    text: "Insert encryption key".into(),
+                    interaction: Box::new(InsertKeyInteraction { key: item }),
+                    specificity: InteractionSpecificity::Specific,
+                }
+            }
+            None if headset.open && !headset.keys.is_empty() => InteractionOption {
+                text: "Remove encryption keys".into(),
+                interaction: Box::new(RemoveKeysInteraction),
+                specificity: InteractionSpecificity::Specific,
+            },
+            _ => continue,
+        };
+        event.add_interaction(option);
+    }
+}
+
+fn toggle_headset_interaction(
+    mut query: Query<&mut ActiveInteraction, With<ToggleHeadsetInteraction>>,
+    mut headsets: Query<&mut Headset>,
+) {
+    for mut active in query.iter_mut() {
+        let Ok(mut headset) = headsets.get_mut(active.target) else {
+            active.status = InteractionStatus::Canceled;
+            continue;
+        };
+        headset.open = !headset.open;
+        active.status = InteractionStatus::Completed;
+    }
+}
+
+fn insert_key_interaction(
+    mut query: Query<(&InsertKeyInteraction, &mut ActiveInteraction)>,
+    mut headsets: Query<&mut Headset>,
+    keys: Query<&EncryptionKey>,
+    mut commands: Commands,
+) {
+    for (interaction, mut active) in query.iter_mut() {
+        let (Ok(mut headset), Ok(key)) =
+            (headsets.get_mut(active.target), keys.get(interaction.key))
+        else {
+            active.status = InteractionStatus::Canceled;
+            continue;
+        };
+        if !headset.open || headset.keys.len() >= MAX_KEYS {
+            active.status = InteractionStatus::Canceled;
+            continue;
+        }
+
+        headset.keys.push(key.channel);
+        commands.entity(interaction.key).despawn_recursive();
+        active.status = InteractionStatus::Completed;
+    }
+}
+
+fn remove_keys_interaction(
+    mut query: Query<&mut ActiveInteraction, With<RemoveKeysInteraction>>,
+    mut headsets: Query<(&mut Headset, &GlobalTransform)>,
+    asset_server: Res<AssetServer>,
+    mut commands: Commands,
+) {
+    for mut active in query.iter_mut() {
+        let Ok((mut headset, transform)) = headsets.get_mut(active.target) else {
+            active.status = InteractionStatus::Canceled;
+            continue;
+        };
+        if !headset.open {
+            active.status = InteractionStatus::Canceled;
+            continue;
+        }
+
+        let position = transform.translation();
+        for prefab in headset.keys.drain(..).filter_map(RadioChannel::key_prefab) {
+            commands.spawn(NetworkSceneBundle {
+                scene: asset_server.load(prefab).into(),
+                transform: Transform::from_translation(position + Vec3::Y * 0.2),
+                ..Default::default()
+            });
+        }
+        active.status = InteractionStatus::Completed;
+    }
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use bevy::ecs::system::SystemState;
+    use networking::{
+        loopback::LinkConditions,
+        messaging::{AppExt, MessageEvent, MessageSender},
+        testing, NetworkRole, UserData,
+    };
+    use utils::task::Tasks;
+
+    use super::*;
+    use crate::{
+        communication::{ChatKind, SpeakMessage, SpeechMessage},
+        config::ServerConfig,
+        interaction::ExecuteInteraction,
+        testing::server_app,
+    };
+
+    #[test]
+    fn prefix_selects_the_channel() {
+        assert_eq!(
+            parse_radio_prefix(";hello"),
+            (Some(RadioChannel::Common), "hello")
+        );
+        assert_eq!(
+            parse_radio_prefix(":s  suspect in the hallway"),
+            (Some(RadioChannel::Security), "suspect in the hallway")
+        );
+        assert_eq!(
+            parse_radio_prefix(":Ehelp"),
+            (Some(RadioChannel::Engineering), "help")
+        );
+        // Nothing left to say, which isn't sent at all
+        assert_eq!(parse_radio_prefix(":m"), (Some(RadioChannel::Medical), ""));
+    }
+
+    #[test]
+    fn text_without_a_known_prefix_is_spoken() {
+        for text in [
+            ":x hello",
+            ":",
+            ":: hi",
+            "hi ;there",
+            " :s hi",
+            ":\u{e9} hi",
+        ] {
+            assert_eq!(parse_radio_prefix(text), (None, text));
+        }
+        assert_eq!(parse_radio_prefix(""), (None, ""));
+    }
+
+    #[derive(Resource, Default)]
+    struct Heard(Vec<String>);
+
+    fn record_speech(
+        mut messages: EventReader<MessageEvent<SpeechMessage>>,
+        mut heard: ResMut<Heard>,
+    ) {
+        heard.0.extend(
+            messages
+                .iter()
+                .map(|event| event.message.message.text.clone()),
+        );
+    }
+
+    fn client() -> App {
+        let mut client = testing::app(NetworkRole::Client);
+        client
+            .add_network_message::<SpeakMessage>("SpeakMessage")
+            .add_network_message::<SpeechMessage>("SpeechMessage")
+            .init_resource::<Heard>()
+            .add_systems(Update, record_speech);
+        client
+    }
+
+    fn say(client: &mut App, text: &str) {
+        let mut state = SystemState::<MessageSender>::new(&mut client.world);
+        state
+            .get_mut(&mut client.world)
+            .send_to_server(&SpeakMessage {
+                text: text.into(),
+                kind: ChatKind::Local,
+                cursor: None,
+            });
+    }
+
+    /// Gives the player a creature wearing a headset with the given keys.
+    fn wear_headset(server: &mut App, username: &str, keys: Vec<RadioChannel>) -> (Entity, Entity) {
+        let player = server
+            .world
+            .resource::<Players>()
+            .players()
+            .values()
+            .find(|player| player.username == username)
+            .unwrap()
+            .id;
+        let headset = server
+            .world
+            .spawn((Headset { keys, open: false }, SpatialBundle::default()))
+            .id();
+        let creature = server
+            .world
+            .spawn((
+                Name::new(username.to_owned()),
+                WornHeadset(headset),
+                SpatialBundle::default(),
+            ))
+            .id();
+        server
+            .world
+            .resource_mut::<ClientControls>()
+            .give_control(player, creature);
+        (creature, headset)
+    }
+
+    fn interact(
+        server: &mut App,
+        creature: Entity,
+        headset: Entity,
+        interaction: Box<dyn Reflect>,
+    ) {
+        server
+            .world
+            .resource_mut::<Tasks<ExecuteInteraction>>()
+            .create_ignore(ExecuteInteraction {
+                entity: creature,
+                target: headset,
+                interaction,
+            });
+        server.update();
+        server.update();
+        assert!(server.world.get::<ActiveInteraction>(creature).is_none());
+    }
+
+    #[test]
+    fn keys_decide_who_can_use_a_department_channel() {
+        let mut server = server_app(ServerConfig::default());
+        let mut engineer = client();
+        let mut officer = client();
+        let connector = testing::listen(&mut server);
+        // Joining names every client "Test", the name is only sent once connected
+        for (client, username) in [(&mut engineer, "Engineer"), (&mut officer, "Officer")] {
+            testing::join(client, &connector, LinkConditions::default());
+            client.insert_resource(UserData {
+                username: username.into(),
+            });
+        }
+        testing::connect(&mut server, &mut [&mut engineer, &mut officer], 200);
+        let (creature, headset) = wear_headset(&mut server, "Engineer", vec![]);
+        wear_headset(&mut server, "Officer", vec![RadioChannel::Security]);
+
+        say(&mut engineer, ":s anyone there?");
+        testing::update(&mut server, &mut [&mut engineer, &mut officer], 10);
+        assert!(officer.world.resource::<Heard>().0.is_empty());
+        assert_eq!(
+            engineer.world.resource::<Heard>().0,
+            ["Your headset has no encryption key for the Security channel."]
+        );
+
+        // Move a security key into the engineer's headset
+        let key = server
+            .world
+            .spawn(EncryptionKey {
+                channel: RadioChannel::Security,
+            })
+            .id();
+        interact(
+            &mut server,
+            creature,
+            headset,
+            Box::new(ToggleHeadsetInteraction),
+        );
+        interact(
+            &mut server,
+            creature,
+            headset,
+            Box::new(InsertKeyInteraction { key }),
+        );
+        assert!(server.world.get_entity(key).is_none());
+        assert!(server
+            .world
+            .get::<Headset>(headset)
+            .unwrap()
+            .has_channel(RadioChannel::Security));
+
+        engineer.world.resource_mut::<Heard>().0.clear();
+        say(&mut engineer, ":s anyone there?");
+        testing::update(&mut server, &mut [&mut engineer, &mut officer], 10);
+        let expected = ["[Security] Engineer says, \"anyone there?\""];
+        assert_eq!(officer.world.resource::<Heard>().0, expected);
+        assert_eq!(engineer.world.resource::<Heard>().0, expected);
+    }
+}
@@ -1,18 +1,87 @@
-use std::{fs::read_to_string, time::Duration};
+use std::path::PathBuf;
 
-use async_compat::Compat;
-use bevy::{
-    prelude::{error, Res, Resource},
-    tasks::IoTaskPool,
+use bevy::{prelude::Resource, utils::Uuid};
+use networking::quality::LinkQualityConfig;
+use serde::Deserialize;
+
+use crate::{
+    access::AccessGrants, admin::moderation::ModerationConfig, autosave::AutosaveConfig,
+    body::health::metabolism::MetabolismConfig, body::variant::BodyConfig,
+    butchering::ButcheringConfig, escape_pod::EscapePodConfig,
+    items::encumbrance::EncumbranceConfig, job::JobConfig, movement::stamina::StaminaConfig,
+    physics_tuning::PhysicsConfig, random_event::carp::CarpMigrationConfig,
+    resource_packs::ResourcePackConfig, round::modes::GameModeConfig, safe_zone::SafetyConfig,
+    status::StatusConfig, text_filter::TextFilterConfig, void::VoidConfig,
+    waypoint::WaypointConfig,
 };
-use serde::{Deserialize, Serialize};
-use tokio::time::{interval, MissedTickBehavior};
 
-use crate::{ArgCommands, Args};
+#[cfg(feature = "server")]
+use {
+    crate::{ArgCommands, Args},
+    async_compat::Compat,
+    bevy::{
+        prelude::{error, Res},
+        tasks::IoTaskPool,
+    },
+    serde::Serialize,
+    std::{fs::read_to_string, time::Duration},
+    tokio::time::{interval, MissedTickBehavior},
+};
 
 #[derive(Default, Deserialize, Resource)]
 pub struct ServerConfig {
     pub registration: Option<ServerRegistration>,
+    pub autosave: Option<AutosaveConfig>,
+    #[serde(default)]
+    pub safety: SafetyConfig,
+    /// Player ids that can use admin commands
+    #[serde(default)]
+    pub admins: Vec<Uuid>,
+    /// Seed for gameplay randomness, random if not set
+    pub seed: Option<u64>,
+    #[serde(default)]
+    pub encumbrance: EncumbranceConfig,
+    /// Path to the combat config file, like `combat.toml`
+    pub combat: Option<String>,
+    /// Accesses that can be given at ID card consoles, by the access of the authorizing card
+    #[serde(default)]
+    pub access_grants: AccessGrants,
+    #[serde(default)]
+    pub jobs: JobConfig,
+    #[serde(default)]
+    pub text_filter: TextFilterConfig,
+    #[serde(default)]
+    pub game_mode: GameModeConfig,
+    #[serde(default)]
+    pub resource_packs: ResourcePackConfig,
+    #[serde(default)]
+    pub moderation: ModerationConfig,
+    #[serde(default)]
+    pub metabolism: MetabolismConfig,
+    #[serde(default)]
+    pub bodies: BodyConfig,
+    #[serde(default)]
+    pub waypoints: WaypointConfig,
+    #[serde(default)]
+    pub void: VoidConfig,
+    #[serde(default)]
+    pub physics: PhysicsConfig,
+    /// Folder in `textures/turfs/themes` turf textures are taken from, the default textures if not set
+    pub map_theme: Option<String>,
+    /// When clients on bad connections get fewer updates
+    #[serde(default)]
+    pub link_quality: LinkQualityConfig,
+    #[serde(default)]
+    pub escape_pods: EscapePodConfig,
+    /// The HTTP endpoint monitoring tools read the server status from
+    #[serde(default)]
+    pub status: StatusConfig,
+    #[serde(default)]
+    pub carp_migration: CarpMigrationConfig,
+    #[serde(default)]
+    pub butchering: ButcheringConfig,
+    #[serde(default)]
+    pub stamina: StaminaConfig,
 }
 
 #[derive(Deserialize, Clone)]
@@ -21,28 +90,55 @@ pub struct ServerRegistration {
     pub private_key: [u8; 32],
 }
 
+/// Directory of the user's own files, like character profiles.
+/// The working directory is used if the platform doesn't have one.
+pub fn user_config_directory() -> PathBuf {
+    let home = || std::env::var_os("HOME").map(PathBuf::from);
+    let base = if cfg!(windows) {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        home().map(|home| home.join("Library/Application Support"))
+    } else {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|directory| !directory.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| home().map(|home| home.join(".config")))
+    };
+    base.map_or_else(PathBuf::new, |base| base.join("ssnt"))
+}
+
+#[cfg(feature = "server")]
 const DEFAULT_SERVER_CONFIG_FILE: &str = "server-config.toml";
 
+/// The text of the server config file, if there is one.
+#[cfg(feature = "server")]
+pub fn read_server_config() -> Option<String> {
+    read_to_string(DEFAULT_SERVER_CONFIG_FILE).ok()
+}
+
+#[cfg(feature = "server")]
 pub fn load_server_config() -> Result<ServerConfig, toml::de::Error> {
-    let text = match read_to_string(DEFAULT_SERVER_CONFIG_FILE) {
-        Ok(t) => t,
-        Err(_) => return Ok(ServerConfig::default()),
-    };
-    toml::from_str(&text)
+    match read_server_config() {
+        Some(text) => toml::from_str(&text),
+        None => Ok(ServerConfig::default()),
+    }
 }
 
+#[cfg(feature = "server")]
 const SERVER_PING_MUTATION: &str = "mutation ping($privateKey: [Int!], $port: Int!) {
   serverPing(input: {privateKey: $privateKey, port: $port}) {
     id
   }
 }";
 
+#[cfg(feature = "server")]
 #[derive(Serialize)]
 struct ServerPingMutation {
     query: &'static str,
     variables: ServerPingMutationVariables,
 }
 
+#[cfg(feature = "server")]
 impl ServerPingMutation {
     fn new(private_key: [u8; 32], port: u16) -> Self {
         Self {
@@ -52,6 +148,7 @@ impl ServerPingMutation {
     }
 }
 
+#[cfg(feature = "server")]
 #[derive(Serialize)]
 struct ServerPingMutationVariables {
     port: u16,
@@ -59,11 +156,14 @@ struct ServerPingMutationVariables {
     private_key: [u8; 32],
 }
 
+#[cfg(feature = "server")]
 pub(crate) fn server_startup(config: Res<ServerConfig>, args: Res<Args>) {
     if let Some(registration) = config.registration.as_ref().cloned() {
         let client = reqwest::Client::new();
         let port = match args.command {
             Some(ArgCommands::Host { bind_address, .. }) => bind_address.port(),
+            #[cfg(feature = "client")]
+            Some(ArgCommands::HostAndPlay { bind_address, .. }) => bind_address.port(),
             _ => panic!(),
         };
 
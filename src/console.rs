@@ -0,0 +1,432 @@
+use std::{
+    io::{stdin, BufRead},
+    sync::{
+        mpsc::{channel, Receiver},
+        Mutex,
+    },
+};
+
+use bevy::{prelude::*, utils::HashMap};
+use networking::{is_server, ConnectionId, Players};
+
+use crate::{communication::SystemMessageEvent, config::ServerConfig};
+
+/// Runs text commands typed into the server console or sent in chat with a leading `/`.
+pub struct ConsolePlugin;
+
+impl Plugin for ConsolePlugin {
+    fn build(&self, app: &mut App) {
+        if !is_server(app) {
+            return;
+        }
+
+        app.add_event::<ConsoleInputEvent>()
+            .init_resource::<CommandRegistry>()
+            .add_console_command(ConsoleCommand {
+                name: "help",
+                description: "Lists the commands you can use",
+                parameters: &[],
+                permission: PermissionLevel::Player,
+                handler: help_command,
+            })
+            .add_systems(Startup, start_stdin_reader)
+            .add_systems(Update, (read_stdin, run_commands).chain());
+    }
+}
+
+/// Who is allowed to run a command. Higher levels can run everything lower levels can.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Default)]
+pub enum PermissionLevel {
+    #[default]
+    Player,
+    Admin,
+    /// Only the server console
+    Console,
+}
+
+/// Where a command was typed. Output is sent back there.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CommandSource {
+    Console,
+    Player(ConnectionId),
+}
+
+impl CommandSource {
+    fn permission(&self, world: &World) -> PermissionLevel {
+        let connection = match self {
+            CommandSource::Console => return PermissionLevel::Console,
+            CommandSource::Player(c) => *c,
+        };
+        let is_admin = world
+            .resource::<Players>()
+            .get(connection)
+            .map(|player| world.resource::<ServerConfig>().admins.contains(&player.id))
+            .unwrap_or(false);
+        if is_admin {
+            PermissionLevel::Admin
+        } else {
+            PermissionLevel::Player
+        }
+    }
+}
+
+/// A line of text to run as a command, without the leading `/`.
+#[derive(Event)]
+pub struct ConsoleInputEvent {
+    pub source: CommandSource,
+    pub line: String,
+    /// Where in the world the player's cursor was when they sent the command
+    pub cursor: Option<Vec3>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum ArgumentKind {
+    /// Username of a connected player
+    Player,
+    Integer,
+    /// A tile position written as `x,y`
+    Tile,
+    /// A single word, read with [`CommandContext::text`]
+    Word,
+    /// The rest of the line. Must be the last parameter.
+    Text,
+}
+
+impl ArgumentKind {
+    fn describe(self) -> &'static str {
+        match self {
+            ArgumentKind::Player => "player name",
+            ArgumentKind::Integer => "integer",
+            ArgumentKind::Tile => "tile coordinate (x,y)",
+            ArgumentKind::Word => "word",
+            ArgumentKind::Text => "text",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Argument {
+    Player(ConnectionId),
+    Integer(i64),
+    Tile(UVec2),
+    Text(String),
+}
+
+/// Output shown to whoever ran the command
+pub type CommandResult = Result<String, String>;
+pub type CommandHandler = fn(&mut World, &CommandContext) -> CommandResult;
+
+#[derive(Clone, Copy)]
+pub struct ConsoleCommand {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// Names and types of the arguments, in order
+    pub parameters: &'static [(&'static str, ArgumentKind)],
+    pub permission: PermissionLevel,
+    pub handler: CommandHandler,
+}
+
+impl ConsoleCommand {
+    fn usage(&self) -> String {
+        let mut usage = format!("/{}", self.name);
+        for (name, _) in self.parameters {
+            usage += &format!(" <{}>", name);
+        }
+        usage
+    }
+}
+
+/// Arguments passed to a command handler.
+/// The accessors panic if the index doesn't match the command's parameters.
+pub struct CommandContext {
+    pub source: CommandSource,
+    pub cursor: Option<Vec3>,
+    arguments: Vec<Argument>,
+}
+
+impl CommandContext {
+    pub fn player(&self, index: usize) -> ConnectionId {
+        match &self.arguments[index] {
+            Argument::Player(connection) => *connection,
+            other => panic!("Argument {} is not a player: {:?}", index, other),
+        }
+    }
+
+    pub fn integer(&self, index: usize) -> i64 {
+        match &self.arguments[index] {
+            Argument::Integer(value) => *value,
+            other => panic!("Argument {} is not an integer: {:?}", index, other),
+        }
+    }
+
+    pub fn tile(&self, index: usize) -> UVec2 {
+        match &self.arguments[index] {
+            Argument::Tile(position) => *position,
+            other => panic!("Argument {} is not a tile: {:?}", index, other),
+        }
+    }
+
+    pub fn text(&self, index: usize) -> &str {
+        match &self.arguments[index] {
+            Argument::Text(text) => text,
+            other => panic!("Argument {} is not text: {:?}", index, other),
+        }
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct CommandRegistry {
+    commands: HashMap<&'static str, ConsoleCommand>,
+}
+
+impl CommandRegistry {
+    pub fn register(&mut self, command: ConsoleCommand) {
+        if self.commands.insert(command.name, command).is_some() {
+            warn!(name = command.name, "Console command registered twice");
+        }
+    }
+
+    /// Commands a permission level can see and run, sorted by name.
+    fn available(&self, permission: PermissionLevel) -> Vec<&ConsoleCommand> {
+        let mut commands: Vec<_> = self
+            .commands
+            .values()
+            .filter(|c| c.permission <= permission)
+            .collect();
+        commands.sort_unstable_by_key(|c| c.name);
+        commands
+    }
+}
+
+pub trait ConsoleAppExt {
+    fn add_console_command(&mut self, command: ConsoleCommand) -> &mut Self;
+}
+
+impl ConsoleAppExt for App {
+    fn add_console_command(&mut self, command: ConsoleCommand) -> &mut Self {
+        self.world
+            .get_resource_or_insert_with(CommandRegistry::default)
+            .register(command);
+        self
+    }
+}
+
+struct Token {
+    text: String,
+    /// Byte offset in the line, used to take the rest of the line as text
+    start: usize,
+}
+
+/// Splits a line at whitespace. Double quotes group words and `\` escapes the next character.
+fn tokenize(line: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = line.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        let mut text = String::new();
+        let mut quoted = false;
+        while let Some(&(_, c)) = chars.peek() {
+            if c.is_whitespace() && !quoted {
+                break;
+            }
+            chars.next();
+            match c {
+                '"' => quoted = !quoted,
+                '\\' => match chars.next() {
+                    Some((_, escaped)) => text.push(escaped),
+                    None => return Err("nothing to escape at the end of the line".into()),
+                },
+                c => text.push(c),
+            }
+        }
+        if quoted {
+            return Err(format!("missing closing quote after '{}'", text));
+        }
+        tokens.push(Token { text, start });
+    }
+    Ok(tokens)
+}
+
+fn parse_argument(kind: ArgumentKind, text: &str, players: &Players) -> Result<Argument, String> {
+    let invalid = || format!("expected {}, got '{}'", kind.describe(), text);
+    match kind {
+        ArgumentKind::Player => players
+            .players()
+            .iter()
+            .find(|(_, p)| p.username.eq_ignore_ascii_case(text))
+            .map(|(connection, _)| Argument::Player(*connection))
+            .ok_or_else(invalid),
+        ArgumentKind::Integer => text.parse().map(Argument::Integer).map_err(|_| invalid()),
+        ArgumentKind::Tile => {
+            let (x, y) = text.split_once(',').ok_or_else(invalid)?;
+            let x = x.trim().parse().map_err(|_| invalid())?;
+            let y = y.trim().parse().map_err(|_| invalid())?;
+            Ok(Argument::Tile(UVec2::new(x, y)))
+        }
+        ArgumentKind::Word | ArgumentKind::Text => Ok(Argument::Text(text.to_owned())),
+    }
+}
+
+/// Parses the arguments of a command line. The first token is the command name.
+fn parse_arguments(
+    command: &ConsoleCommand,
+    line: &str,
+    tokens: &[Token],
+    players: &Players,
+) -> Result<Vec<Argument>, String> {
+    let mut arguments = Vec::with_capacity(command.parameters.len());
+    let mut remaining = tokens.get(1..).unwrap_or_default();
+    for &(name, kind) in command.parameters {
+        let Some(token) = remaining.first() else {
+            return Err(format!("missing <{}>. Usage: {}", name, command.usage()));
+        };
+
+        let text = match kind {
+            // Keep the spacing of multiple words. A single word may be quoted to keep leading spaces.
+            ArgumentKind::Text if remaining.len() > 1 => {
+                let text = line[token.start..].trim_end();
+                remaining = &[];
+                text
+            }
+            _ => {
+                remaining = &remaining[1..];
+                token.text.as_str()
+            }
+        };
+        arguments.push(parse_argument(kind, text, players)?);
+    }
+
+    if let Some(extra) = remaining.first() {
+        return Err(format!(
+            "unexpected argument '{}'. Usage: {}",
+            extra.text,
+            command.usage()
+        ));
+    }
+    Ok(arguments)
+}
+
+fn execute(
+    world: &mut World,
+    source: CommandSource,
+    line: &str,
+    cursor: Option<Vec3>,
+) -> CommandResult {
+    let tokens = tokenize(line)?;
+    let Some(name) = tokens.first().map(|t| t.text.to_lowercase()) else {
+        return Err("No command given. Use /help to list commands.".into());
+    };
+
+    // Commands above the caller's level are reported as unknown, so their existence isn't leaked
+    let permission = source.permission(world);
+    let Some(command) = world
+        .resource::<CommandRegistry>()
+        .commands
+        .get(name.as_str())
+        .filter(|c| c.permission <= permission)
+        .copied()
+    else {
+        return Err(format!(
+            "Unknown command '{}'. Use /help to list commands.",
+            name
+        ));
+    };
+
+    let arguments = parse_arguments(&command, line, &tokens, world.resource::<Players>())?;
+    if let CommandSource::Player(connection) = source {
+        info!(connection = ?connection, line, "Running command");
+    }
+    (command.handler)(
+        world,
+        &CommandContext {
+            source,
+            cursor,
+            arguments,
+        },
+    )
+}
+
+fn run_commands(world: &mut World) {
+    let inputs: Vec<_> = world
+        .resource_mut::<Events<ConsoleInputEvent>>()
+        .drain()
+        .collect();
+
+    for input in inputs {
+        let text = match execute(world, input.source, &input.line, input.cursor) {
+            Ok(output) => output,
+            Err(error) => format!("Error: {}", error),
+        };
+        if text.is_empty() {
+            continue;
+        }
+
+        match input.source {
+            CommandSource::Console => println!("{}", text),
+            CommandSource::Player(receiver) => {
+                world
+                    .resource_mut::<Events<SystemMessageEvent>>()
+                    .send(SystemMessageEvent { receiver, text });
+            }
+        }
+    }
+}
+
+/// Lines read from stdin on a separate thread, so waiting for input doesn't block the server.
+#[derive(Resource)]
+struct ConsoleLines(Mutex<Receiver<String>>);
+
+fn start_stdin_reader(mut commands: Commands) {
+    let (sender, receiver) = channel();
+    let result = std::thread::Builder::new()
+        .name("console input".into())
+        .spawn(move || {
+            for line in stdin().lock().lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+    if let Err(err) = result {
+        error!(error = %err, "Could not start console input thread");
+        return;
+    }
+    commands.insert_resource(ConsoleLines(Mutex::new(receiver)));
+}
+
+fn read_stdin(lines: Option<Res<ConsoleLines>>, mut events: EventWriter<ConsoleInputEvent>) {
+    let Some(lines) = lines else {
+        return;
+    };
+    let receiver = lines.0.lock().unwrap();
+    for line in receiver.try_iter() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        // The slash is optional on the console
+        events.send(ConsoleInputEvent {
+            source: CommandSource::Console,
+            line: line.strip_prefix('/').unwrap_or(line).to_owned(),
+            cursor: None,
+        });
+    }
+}
+
+fn help_command(world: &mut World, context: &CommandContext) -> CommandResult {
+    let permission = context.source.permission(world);
+    let registry = world.resource::<CommandRegistry>();
+    let lines: Vec<_> = registry
+        .available(permission)
+        .into_iter()
+        .map(|c| format!("{} - {}", c.usage(), c.description))
+        .collect();
+    Ok(lines.join("\n"))
+}
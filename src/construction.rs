@@ -1,40 +1,152 @@
 use std::time::Duration;
 
-use bevy::prelude::*;
+use bevy::{ecs::system::SystemParam, prelude::*, reflect::TypeUuid};
+use bevy_rapier3d::prelude::{LockedAxes, RigidBody as RapierRigidBody};
 use maps::MapCommandsExt;
-use networking::is_server;
+use networking::{
+    component::AppExt,
+    is_server,
+    scene::NetworkSceneBundle,
+    variable::{NetworkVar, ServerVar},
+    Networked,
+};
+use serde::Deserialize;
 
-use crate::interaction::{
-    ActiveInteraction, GenerateInteractionList, InteractionListEvents, InteractionOption,
-    InteractionSpecificity, InteractionStatus,
+use crate::{
+    decals::{DecalKind, DecalSender},
+    effects::{EffectKind, EffectSender},
+    feedback::{Feedback, FeedbackKind},
+    interaction::{
+        ActiveInteraction, GenerateInteractionList, InteractionListEvents, InteractionOption,
+        InteractionSpecificity, InteractionStatus,
+    },
+    items::{
+        containers::DropContainedItems,
+        durability::{Broken, ItemDamageEvent},
+        surface::DropSurfaceItems,
+    },
+    sound::ImpactMaterial,
 };
 
+use self::stages::StagesPlugin;
+
+pub mod stages;
+
 pub struct ConstructionPlugin;
 
 impl Plugin for ConstructionPlugin {
     fn build(&self, app: &mut App) {
         app.register_type::<Wrench>()
+            .register_type::<Screwdriver>()
+            .register_type::<Wirecutters>()
+            .register_type::<Multitool>()
+            .register_type::<Welder>()
+            .register_type::<ToolKind>()
+            .register_type::<Vec<ToolKind>>()
+            .register_type::<Vec<String>>()
+            .register_type::<Anchorable>()
+            .register_type::<Deconstructable>()
             .register_type::<WrenchDeconstructable>()
-            .register_type::<WrenchDeconstructInteraction>();
+            .register_type::<WrenchDeconstructInteraction>()
+            .add_networked_component::<AnchorState, AnchorStateClient>()
+            .add_plugins(StagesPlugin);
         if is_server(app) {
-            app.add_systems(
-                Update,
-                (
-                    prepare_deconstruct_wrench_interaction.in_set(GenerateInteractionList),
-                    execute_deconstruct_wrench_interaction,
-                ),
-            );
+            app.register_type::<AnchorInteraction>()
+                .register_type::<DeconstructStepInteraction>()
+                .register_type::<WrongToolInteraction>()
+                .add_systems(
+                    Update,
+                    (
+                        prepare_deconstruct_wrench_interaction.in_set(GenerateInteractionList),
+                        execute_deconstruct_wrench_interaction,
+                        setup_anchorables,
+                        apply_anchoring.after(setup_anchorables),
+                        prepare_anchor_interaction.in_set(GenerateInteractionList),
+                        anchor_interaction,
+                        prepare_deconstruct_interactions.in_set(GenerateInteractionList),
+                        deconstruct_step_interaction,
+                        wrong_tool_interaction,
+                    ),
+                );
         }
     }
 }
 
 const DECONSTRUCT_TIME: Duration = Duration::from_secs(2);
+const ANCHOR_TIME: Duration = Duration::from_secs(2);
+const DECONSTRUCT_STEP_TIME: Duration = Duration::from_secs(3);
 
 /// Marks an object as a wrench tool.
 #[derive(Component, Reflect, Default)]
 #[reflect(Component)]
 struct Wrench;
 
+/// Marks an object as a screwdriver tool.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct Screwdriver;
+
+/// Marks an object as a wirecutter tool.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct Wirecutters;
+
+/// Marks an object as a multitool.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct Multitool;
+
+/// Marks an object as a welding tool.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct Welder;
+
+/// The tools used to take objects apart.
+#[derive(Reflect, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum ToolKind {
+    #[default]
+    Wrench,
+    Screwdriver,
+    Welder,
+    Wirecutters,
+}
+
+impl ToolKind {
+    fn name(self) -> &'static str {
+        match self {
+            ToolKind::Wrench => "a wrench",
+            ToolKind::Screwdriver => "a screwdriver",
+            ToolKind::Welder => "a welder",
+            ToolKind::Wirecutters => "wirecutters",
+        }
+    }
+}
+
+/// Finds out which tool an item is. Broken tools can't be used.
+#[derive(SystemParam)]
+struct Tools<'w, 's> {
+    wrenches: Query<'w, 's, (), (With<Wrench>, Without<Broken>)>,
+    screwdrivers: Query<'w, 's, (), (With<Screwdriver>, Without<Broken>)>,
+    welders: Query<'w, 's, (), (With<Welder>, Without<Broken>)>,
+    wirecutters: Query<'w, 's, (), (With<Wirecutters>, Without<Broken>)>,
+}
+
+impl<'w, 's> Tools<'w, 's> {
+    fn kind(&self, item: Entity) -> Option<ToolKind> {
+        if self.wrenches.contains(item) {
+            Some(ToolKind::Wrench)
+        } else if self.screwdrivers.contains(item) {
+            Some(ToolKind::Screwdriver)
+        } else if self.welders.contains(item) {
+            Some(ToolKind::Welder)
+        } else if self.wirecutters.contains(item) {
+            Some(ToolKind::Wirecutters)
+        } else {
+            None
+        }
+    }
+}
+
 #[derive(Component, Reflect, Default)]
 #[reflect(Component)]
 struct WrenchDeconstructable;
@@ -44,6 +156,7 @@ struct WrenchDeconstructable;
 #[component(storage = "SparseSet")]
 struct WrenchDeconstructInteraction {
     target: Entity,
+    tool: Entity,
 }
 
 // Dummy default for Reflect
@@ -51,13 +164,14 @@ impl Default for WrenchDeconstructInteraction {
     fn default() -> Self {
         Self {
             target: Entity::from_raw(0),
+            tool: Entity::from_raw(0),
         }
     }
 }
 
 fn prepare_deconstruct_wrench_interaction(
     list: Res<InteractionListEvents>,
-    wrenches: Query<(), With<Wrench>>,
+    wrenches: Query<(), (With<Wrench>, Without<Broken>)>,
     deconstructables: Query<(), With<WrenchDeconstructable>>,
 ) {
     for event in list.events.iter() {
@@ -77,22 +191,39 @@ fn prepare_deconstruct_wrench_interaction(
             text: "Deconstruct".into(),
             interaction: Box::new(WrenchDeconstructInteraction {
                 target: event.target,
+                tool: item_in_hand,
             }),
             specificity: InteractionSpecificity::Specific,
         });
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn execute_deconstruct_wrench_interaction(
-    mut query: Query<(&WrenchDeconstructInteraction, &mut ActiveInteraction)>,
-    deconstructables: Query<(), With<WrenchDeconstructable>>,
+    mut query: Query<(
+        Entity,
+        &WrenchDeconstructInteraction,
+        &mut ActiveInteraction,
+    )>,
+    deconstructables: Query<
+        (&GlobalTransform, Option<&ImpactMaterial>),
+        With<WrenchDeconstructable>,
+    >,
+    broken: Query<(), With<Broken>>,
     time: Res<Time>,
+    mut effects: EffectSender,
+    mut decals: DecalSender,
+    mut damage: EventWriter<ItemDamageEvent>,
     mut commands: Commands,
 ) {
-    for (interaction, mut active) in query.iter_mut() {
+    for (source, interaction, mut active) in query.iter_mut() {
         active.set_initial_duration(DECONSTRUCT_TIME);
 
-        if !deconstructables.contains(active.target) {
+        let Ok((transform, material)) = deconstructables.get(active.target) else {
+            active.status = InteractionStatus::Canceled;
+            continue;
+        };
+        if broken.contains(interaction.tool) {
             active.status = InteractionStatus::Canceled;
             continue;
         }
@@ -101,7 +232,555 @@ fn execute_deconstruct_wrench_interaction(
             continue;
         }
 
+        effects.send(
+            EffectKind::breaking(material.copied()),
+            transform.translation(),
+            1.0,
+        );
+        decals.send(DecalKind::Dirt, transform.translation(), None, 0.8);
+        commands.add(DropSurfaceItems {
+            surface: interaction.target,
+        });
+        commands.add(DropContainedItems {
+            entity: interaction.target,
+        });
         commands.despawn_tile_entity(interaction.target);
+        damage.send(ItemDamageEvent::used(interaction.tool, source));
         active.status = InteractionStatus::Completed;
     }
 }
+
+/// An object that can be bolted to the floor with a wrench.
+/// Anchored objects don't move, unanchored objects are pushed around by physics.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct Anchorable {
+    /// If the object starts out anchored
+    pub anchored: bool,
+}
+
+// TODO: Unregister power consumers when unanchored, once there is a power grid
+#[derive(Component, Networked)]
+#[networked(client = "AnchorStateClient")]
+pub struct AnchorState {
+    anchored: NetworkVar<bool>,
+}
+
+impl AnchorState {
+    pub fn anchored(&self) -> bool {
+        *self.anchored
+    }
+}
+
+/// Lets clients show if an object is bolted down.
+#[derive(Component, Default, TypeUuid, Networked)]
+#[uuid = "3c9e1f72-8d4b-4a06-b5e1-7f2a9c6d0e48"]
+#[networked(server = "AnchorState")]
+pub struct AnchorStateClient {
+    anchored: ServerVar<bool>,
+}
+
+impl AnchorStateClient {
+    #[allow(dead_code)]
+    pub fn anchored(&self) -> bool {
+        *self.anchored
+    }
+}
+
+fn setup_anchorables(
+    anchorables: Query<(Entity, &Anchorable), Added<Anchorable>>,
+    mut commands: Commands,
+) {
+    for (entity, anchorable) in anchorables.iter() {
+        commands.entity(entity).insert(AnchorState {
+            anchored: anchorable.anchored.into(),
+        });
+    }
+}
+
+fn apply_anchoring(
+    changed: Query<(Entity, &AnchorState), Changed<AnchorState>>,
+    mut commands: Commands,
+) {
+    for (entity, state) in changed.iter() {
+        if state.anchored() {
+            commands.entity(entity).insert(RapierRigidBody::Fixed);
+        } else {
+            commands
+                .entity(entity)
+                .insert((RapierRigidBody::Dynamic, LockedAxes::ROTATION_LOCKED));
+        }
+    }
+}
+
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+#[component(storage = "SparseSet")]
+struct AnchorInteraction {
+    target: Entity,
+    tool: Entity,
+}
+
+// Dummy default for Reflect
+impl Default for AnchorInteraction {
+    fn default() -> Self {
+        Self {
+            target: Entity::from_raw(0),
+            tool: Entity::from_raw(0),
+        }
+    }
+}
+
+fn prepare_anchor_interaction(
+    list: Res<InteractionListEvents>,
+    wrenches: Query<(), (With<Wrench>, Without<Broken>)>,
+    anchorables: Query<&AnchorState>,
+) {
+    for event in list.events.iter() {
+        let Some(item_in_hand) = event.item_in_hand else {
+            continue;
+        };
+        if !wrenches.contains(item_in_hand) {
+            continue;
+        }
+        let Ok(state) = anchorables.get(event.target) else {
+            continue;
+        };
+
+        event.add_interaction(InteractionOption {
+            text: if state.anchored() {
+                "Unanchor"
+            } else {
+                "Anchor"
+            }
+            .into(),
+            interaction: Box::new(AnchorInteraction {
+                target: event.target,
+                tool: item_in_hand,
+            }),
+            specificity: InteractionSpecificity::Specific,
+        });
+    }
+}
+
+fn anchor_interaction(
+    mut query: Query<(Entity, &AnchorInteraction, &mut ActiveInteraction)>,
+    mut anchorables: Query<&mut AnchorState>,
+    broken: Query<(), With<Broken>>,
+    time: Res<Time>,
+    mut damage: EventWriter<ItemDamageEvent>,
+) {
+    for (source, interaction, mut active) in query.iter_mut() {
+        active.set_initial_duration(ANCHOR_TIME);
+
+        let Ok(mut state) = anchorables.get_mut(interaction.target) else {
+            active.status = InteractionStatus::Canceled;
+            continue;
+        };
+        if broken.contains(interaction.tool) {
+            active.status = InteractionStatus::Canceled;
+            continue;
+        }
+
+        if active.start_time() + ANCHOR_TIME.as_secs_f32() > time.elapsed_seconds() {
+            continue;
+        }
+
+        *state.anchored = !*state.anchored;
+        damage.send(ItemDamageEvent::used(interaction.tool, source));
+        active.status = InteractionStatus::Completed;
+    }
+}
+
+/// An object that is taken apart by using tools on it in a fixed order.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct Deconstructable {
+    /// Tools to use, in order
+    pub steps: Vec<ToolKind>,
+    /// Prefabs dropped on the tile when the object is taken apart
+    pub materials: Vec<String>,
+}
+
+/// How many deconstruction steps have been done on an object.
+/// Only advanced when a step finishes, so interrupted steps can be started again.
+#[derive(Component, Default)]
+struct DeconstructProgress(usize);
+
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+#[component(storage = "SparseSet")]
+struct DeconstructStepInteraction {
+    target: Entity,
+    /// The step this interaction completes
+    step: usize,
+    tool: Entity,
+}
+
+// Dummy default for Reflect
+impl Default for DeconstructStepInteraction {
+    fn default() -> Self {
+        Self {
+            target: Entity::from_raw(0),
+            step: 0,
+            tool: Entity::from_raw(0),
+        }
+    }
+}
+
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+#[component(storage = "SparseSet")]
+struct WrongToolInteraction {
+    user: Entity,
+    needed: ToolKind,
+}
+
+// Dummy default for Reflect
+impl Default for WrongToolInteraction {
+    fn default() -> Self {
+        Self {
+            user: Entity::from_raw(0),
+            needed: ToolKind::default(),
+        }
+    }
+}
+
+fn prepare_deconstruct_interactions(
+    list: Res<InteractionListEvents>,
+    tools: Tools,
+    deconstructables: Query<(&Deconstructable, Option<&DeconstructProgress>)>,
+) {
+    for event in list.events.iter() {
+        let Some((item, tool)) = event
+            .item_in_hand
+            .and_then(|item| Some((item, tools.kind(item)?)))
+        else {
+            continue;
+        };
+        let Ok((deconstructable, progress)) = deconstructables.get(event.target) else {
+            continue;
+        };
+        if let Some(option) = deconstruct_option(
+            deconstructable,
+            progress,
+            event.source,
+            event.target,
+            item,
+            tool,
+        ) {
+            event.add_interaction(option);
+        }
+    }
+}
+
+/// What using `tool` on the object does, given how far it was taken apart.
+/// Using the wrong tool is offered too, so players find out what's needed.
+fn deconstruct_option(
+    deconstructable: &Deconstructable,
+    progress: Option<&DeconstructProgress>,
+    user: Entity,
+    target: Entity,
+    item: Entity,
+    tool: ToolKind,
+) -> Option<InteractionOption> {
+    let step = progress.map(|p| p.0).unwrap_or_default();
+    let &needed = deconstructable.steps.get(step)?;
+    Some(if tool == needed {
+        InteractionOption {
+            text: "Deconstruct".into(),
+            interaction: Box::new(DeconstructStepInteraction {
+                target,
+                step,
+                tool: item,
+            }),
+            specificity: InteractionSpecificity::Specific,
+        }
+    } else {
+        InteractionOption {
+            text: format!("Deconstruct (needs {})", needed.name()),
+            interaction: Box::new(WrongToolInteraction { user, needed }),
+            specificity: InteractionSpecificity::Common,
+        }
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+fn deconstruct_step_interaction(
+    mut query: Query<(Entity, &DeconstructStepInteraction, &mut ActiveInteraction)>,
+    deconstructables: Query<(
+        &Deconstructable,
+        Option<&DeconstructProgress>,
+        &GlobalTransform,
+        Option<&ImpactMaterial>,
+    )>,
+    broken: Query<(), With<Broken>>,
+    asset_server: Res<AssetServer>,
+    time: Res<Time>,
+    mut effects: EffectSender,
+    mut decals: DecalSender,
+    mut damage: EventWriter<ItemDamageEvent>,
+    mut commands: Commands,
+) {
+    for (source, interaction, mut active) in query.iter_mut() {
+        active.set_initial_duration(DECONSTRUCT_STEP_TIME);
+
+        let Ok((deconstructable, progress, transform, impact)) =
+            deconstructables.get(interaction.target)
+        else {
+            active.status = InteractionStatus::Canceled;
+            continue;
+        };
+        // Someone else finished this step first
+        let step = progress.map(|p| p.0).unwrap_or_default();
+        if step != interaction.step || broken.contains(interaction.tool) {
+            active.status = InteractionStatus::Canceled;
+            continue;
+        }
+
+        if active.start_time() + DECONSTRUCT_STEP_TIME.as_secs_f32() > time.elapsed_seconds() {
+            continue;
+        }
+        active.status = InteractionStatus::Completed;
+        damage.send(ItemDamageEvent::used(interaction.tool, source));
+
+        let position = transform.translation();
+        if deconstructable.steps.get(step) == Some(&ToolKind::Welder) {
+            effects.send(EffectKind::Sparks, position, 0.5);
+        }
+
+        let step = step + 1;
+        if step < deconstructable.steps.len() {
+            commands
+                .entity(interaction.target)
+                .insert(DeconstructProgress(step));
+            continue;
+        }
+
+        effects.send(EffectKind::breaking(impact.copied()), position, 1.0);
+        decals.send(DecalKind::Dirt, position, None, 0.8);
+        for material in deconstructable.materials.iter() {
+            commands.spawn(NetworkSceneBundle {
+                scene: asset_server.load(material.as_str()).into(),
+                transform: Transform::from_translation(position),
+                ..Default::default()
+            });
+        }
+        commands.add(DropSurfaceItems {
+            surface: interaction.target,
+        });
+        commands.add(DropContainedItems {
+            entity: interaction.target,
+        });
+        commands.entity(interaction.target).despawn_recursive();
+    }
+}
+
+/// Tells the player which tool the next step needs.
+fn wrong_tool_interaction(
+    mut query: Query<(&WrongToolInteraction, &mut ActiveInteraction)>,
+    mut feedback: Feedback,
+) {
+    for (interaction, mut active) in query.iter_mut() {
+        active.status = InteractionStatus::Canceled;
+        feedback.send_to_creature(
+            interaction.user,
+            FeedbackKind::WrongTool,
+            "construction.wrong_tool",
+            &[interaction.needed.name()],
+        );
+    }
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use bevy::time::TimeUpdateStrategy;
+    use networking::{
+        loopback::LinkConditions,
+        messaging::{AppExt, MessageEvent},
+        spawning::ClientControls,
+        testing, NetworkRole, Players,
+    };
+    use utils::task::Tasks;
+
+    use super::*;
+    use crate::{
+        config::ServerConfig, feedback::ActionFeedback, interaction::ExecuteInteraction,
+        testing::server_app,
+    };
+
+    const FRAME: Duration = Duration::from_millis(100);
+    /// Frames a deconstruction step takes, with a few to spare
+    const STEP_FRAMES: u32 = 35;
+
+    fn update(app: &mut App, frames: u32) {
+        for _ in 0..frames {
+            app.update();
+        }
+    }
+
+    fn locker() -> Deconstructable {
+        Deconstructable {
+            steps: vec![ToolKind::Wrench, ToolKind::Welder],
+            materials: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn tools_are_checked_in_order() {
+        let deconstructable = locker();
+        let entity = Entity::from_raw(1);
+        let option = |tool, done| {
+            deconstruct_option(
+                &deconstructable,
+                Some(&DeconstructProgress(done)),
+                entity,
+                entity,
+                entity,
+                tool,
+            )
+        };
+
+        let first = option(ToolKind::Wrench, 0).unwrap();
+        assert_eq!(first.text, "Deconstruct");
+        assert!(matches!(
+            first
+                .interaction
+                .downcast_ref::<DeconstructStepInteraction>(),
+            Some(DeconstructStepInteraction { step: 0, .. })
+        ));
+        let wrong = option(ToolKind::Welder, 0).unwrap();
+        assert_eq!(wrong.text, "Deconstruct (needs a wrench)");
+        assert!(matches!(
+            wrong.interaction.downcast_ref::<WrongToolInteraction>(),
+            Some(WrongToolInteraction {
+                needed: ToolKind::Wrench,
+                ..
+            })
+        ));
+
+        assert!(option(ToolKind::Welder, 1)
+            .unwrap()
+            .interaction
+            .is::<DeconstructStepInteraction>());
+        assert!(option(ToolKind::Wrench, 1)
+            .unwrap()
+            .interaction
+            .is::<WrongToolInteraction>());
+        // Nothing is left to take apart
+        assert!(option(ToolKind::Wrench, 2).is_none());
+    }
+
+    #[derive(Resource, Default)]
+    struct Received(Vec<String>);
+
+    fn record_feedback(
+        mut messages: EventReader<MessageEvent<ActionFeedback>>,
+        mut received: ResMut<Received>,
+    ) {
+        received
+            .0
+            .extend(messages.iter().map(|event| event.message.text_key.clone()));
+    }
+
+    #[test]
+    fn wrong_tool_tells_the_user() {
+        let mut server = server_app(ServerConfig::default());
+        let mut client = testing::app(NetworkRole::Client);
+        client
+            .add_network_message::<ActionFeedback>("ActionFeedback")
+            .init_resource::<Received>()
+            .add_systems(Update, record_feedback);
+        let connector = testing::listen(&mut server);
+        testing::join(&mut client, &connector, LinkConditions::default());
+        testing::connect(&mut server, &mut [&mut client], 200);
+
+        let user = server.world.spawn(SpatialBundle::default()).id();
+        let target = server
+            .world
+            .spawn((locker(), SpatialBundle::default()))
+            .id();
+        let player = server
+            .world
+            .resource::<Players>()
+            .players()
+            .values()
+            .next()
+            .unwrap()
+            .id;
+        server
+            .world
+            .resource_mut::<ClientControls>()
+            .give_control(player, user);
+
+        server
+            .world
+            .resource_mut::<Tasks<ExecuteInteraction>>()
+            .create_ignore(ExecuteInteraction {
+                entity: user,
+                target,
+                interaction: Box::new(WrongToolInteraction {
+                    user,
+                    needed: ToolKind::Wrench,
+                }),
+            });
+        testing::update(&mut server, &mut [&mut client], 10);
+
+        assert_eq!(
+            client.world.resource::<Received>().0,
+            ["construction.wrong_tool"]
+        );
+        assert!(server.world.get::<ActiveInteraction>(user).is_none());
+        assert!(server.world.get::<DeconstructProgress>(target).is_none());
+    }
+
+    /// Starts a deconstruction step with a wrench.
+    fn start_step(app: &mut App, user: Entity, target: Entity, step: usize) {
+        let tool = app.world.spawn(Wrench).id();
+        app.world
+            .resource_mut::<Tasks<ExecuteInteraction>>()
+            .create_ignore(ExecuteInteraction {
+                entity: user,
+                target,
+                interaction: Box::new(DeconstructStepInteraction { target, step, tool }),
+            });
+    }
+
+    fn progress(app: &App, target: Entity) -> usize {
+        app.world
+            .get::<DeconstructProgress>(target)
+            .map_or(0, |p| p.0)
+    }
+
+    #[test]
+    fn interrupted_step_keeps_the_object_where_it_was() {
+        let mut app = server_app(ServerConfig::default());
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(FRAME));
+        app.update();
+        let user = app.world.spawn(SpatialBundle::default()).id();
+        let target = app.world.spawn((locker(), SpatialBundle::default())).id();
+
+        start_step(&mut app, user, target, 0);
+        update(&mut app, 10);
+        assert!(app.world.get::<ActiveInteraction>(user).is_some());
+        app.world.get_mut::<ActiveInteraction>(user).unwrap().status = InteractionStatus::Canceled;
+        update(&mut app, STEP_FRAMES);
+        assert!(app.world.get::<ActiveInteraction>(user).is_none());
+        assert_eq!(progress(&app, target), 0);
+
+        // The step starts over and finishes
+        start_step(&mut app, user, target, 0);
+        update(&mut app, STEP_FRAMES);
+        assert_eq!(progress(&app, target), 1);
+
+        // A step someone else already finished is dropped
+        start_step(&mut app, user, target, 0);
+        update(&mut app, 2);
+        assert!(app.world.get::<ActiveInteraction>(user).is_none());
+        assert_eq!(progress(&app, target), 1);
+
+        start_step(&mut app, user, target, 1);
+        update(&mut app, STEP_FRAMES);
+        assert!(app.world.get_entity(target).is_none());
+    }
+}
@@ -0,0 +1,905 @@
+use std::{fmt, fs::read_to_string, iter, path::Path, time::Duration};
+
+use bevy::{prelude::*, utils::HashMap};
+use maps::{MapCommandsExt, TileEntity, TileLayer, TileMap};
+use networking::{is_server, scene::NetworkSceneBundle, spawning::ClientControls, Players};
+use serde::Deserialize;
+
+use crate::{
+    communication::SystemMessageEvent,
+    effects::{EffectKind, EffectSender},
+    interaction::{
+        ActiveInteraction, GenerateInteractionList, InteractionListEvents, InteractionOption,
+        InteractionSpecificity, InteractionStatus,
+    },
+    items::durability::{Broken, ItemDamageEvent},
+    machines::processing::MaterialStack,
+    navigation::BlocksTile,
+    sound::ImpactMaterial,
+};
+
+use super::{ToolKind, Tools, WrongToolInteraction, DECONSTRUCT_STEP_TIME};
+
+/// Objects that are built up in stages, like walls from girders and airlocks from frames.
+/// The stages and what it takes to get from one to the next are read from a data file.
+pub(super) struct StagesPlugin;
+
+impl Plugin for StagesPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<ConstructionStage>()
+            .register_type::<ConstructionPart>();
+
+        if is_server(app) {
+            app.register_type::<BuildStepInteraction>()
+                .register_type::<UndoStepInteraction>()
+                .register_type::<ExamineStageInteraction>()
+                .add_systems(Startup, load_stage_graph)
+                .add_systems(
+                    Update,
+                    (
+                        prepare_build_interactions.in_set(GenerateInteractionList),
+                        prepare_undo_interactions.in_set(GenerateInteractionList),
+                        prepare_examine_interaction.in_set(GenerateInteractionList),
+                        build_step_interaction,
+                        undo_step_interaction,
+                        examine_stage_interaction,
+                        set_refund_amounts,
+                    ),
+                );
+        }
+    }
+}
+
+const STAGE_GRAPH_FILE: &str = "assets/construction.ron";
+
+/// Which stage of construction an object is in. Set in the prefab of every stage.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct ConstructionStage {
+    /// Key of the stage in the stage graph
+    pub id: String,
+}
+
+/// An item that is built into objects, like cable or door electronics.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct ConstructionPart {
+    pub kind: String,
+}
+
+/// All construction stages and the steps between them, loaded from `assets/construction.ron`.
+#[derive(Resource, Deserialize, Default)]
+struct StageGraph {
+    /// Turf put down when a turf stage is taken apart, like a wall into a girder
+    floor: String,
+    stages: HashMap<String, Stage>,
+    steps: Vec<Step>,
+}
+
+#[derive(Deserialize)]
+struct Stage {
+    /// Shown to players, like "girder"
+    name: String,
+    placement: Placement,
+    /// Shown when examining the object, followed by what can be done next
+    examine: String,
+}
+
+/// Where a stage is put on its tile.
+#[derive(Deserialize)]
+enum Placement {
+    /// A prefab in the furniture layer, like `tilemap/furniture/girder`
+    Furniture(String),
+    /// A turf by name, like `wall`
+    Turf(String),
+    /// A prefab in the pipe layer under the floor, like `tilemap/pipes/pipe`
+    Pipe(String),
+}
+
+impl Placement {
+    fn layer(&self) -> TileLayer {
+        match self {
+            Placement::Furniture(_) => TileLayer::Furniture,
+            Placement::Turf(_) => TileLayer::Turf,
+            Placement::Pipe(_) => TileLayer::Pipe,
+        }
+    }
+
+    fn scene_path(&self) -> String {
+        match self {
+            Placement::Furniture(prefab) | Placement::Pipe(prefab) => {
+                format!("{}.scn.ron", prefab)
+            }
+            Placement::Turf(name) => turf_path(name),
+        }
+    }
+}
+
+fn turf_path(name: &str) -> String {
+    format!("tilemap/turfs/{}.scn.ron", name)
+}
+
+/// Turns one stage into the next. Undoing the step with a tool goes back to the stage before.
+#[derive(Deserialize)]
+struct Step {
+    /// Stage the step is done on. `None` starts a new object on an empty floor.
+    from: Option<String>,
+    to: String,
+    input: StageInput,
+    seconds: f32,
+    /// Tool that undoes the step
+    undo: ToolKind,
+    /// Items given back when the step is undone
+    #[serde(default)]
+    refund: Vec<StageInput>,
+}
+
+#[derive(Deserialize)]
+enum StageInput {
+    /// An amount taken from a held stack of material sheets
+    Material { material: String, amount: u32 },
+    /// A single item with a [`ConstructionPart`] of this kind
+    Part(String),
+}
+
+impl StageInput {
+    /// The item prefab the input is refunded as
+    fn prefab_path(&self) -> String {
+        match self {
+            StageInput::Material { material, .. } => format!("items/{}_sheets.scn.ron", material),
+            StageInput::Part(kind) => format!("items/{}.scn.ron", kind),
+        }
+    }
+
+    fn matches(&self, stack: Option<&MaterialStack>, part: Option<&ConstructionPart>) -> bool {
+        match self {
+            StageInput::Material { material, amount } => {
+                stack.is_some_and(|s| &s.material == material && s.amount >= *amount)
+            }
+            StageInput::Part(kind) => part.is_some_and(|p| &p.kind == kind),
+        }
+    }
+}
+
+impl fmt::Display for StageInput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StageInput::Material { material, amount } => write!(f, "{} {}", amount, material),
+            StageInput::Part(kind) => write!(f, "{}", kind.replace('_', " ")),
+        }
+    }
+}
+
+impl StageGraph {
+    fn steps_from<'a>(
+        &'a self,
+        from: Option<&'a str>,
+    ) -> impl Iterator<Item = (usize, &'a Step)> + 'a {
+        self.steps
+            .iter()
+            .enumerate()
+            .filter(move |(_, step)| step.from.as_deref() == from)
+    }
+
+    /// The step that builds a stage, which is undone to take it apart.
+    fn step_into(&self, stage: &str) -> Option<(usize, &Step)> {
+        self.steps
+            .iter()
+            .enumerate()
+            .find(|(_, step)| step.to == stage)
+    }
+
+    fn stage_name<'a>(&'a self, id: &'a str) -> &'a str {
+        self.stages.get(id).map_or(id, |stage| stage.name.as_str())
+    }
+
+    /// The layer the stage a step builds is put in.
+    fn step_layer(&self, step: &Step) -> TileLayer {
+        self.stages
+            .get(&step.to)
+            .map_or(TileLayer::Furniture, |stage| stage.placement.layer())
+    }
+
+    /// Checks that every stage, prefab, turf and item the graph refers to exists.
+    /// Returns all problems instead of stopping at the first, so they can be fixed in one go.
+    fn validate(&self) -> Vec<String> {
+        let exists = |path: &str| Path::new("assets").join(path).exists();
+        let mut errors = Vec::new();
+
+        if !exists(&turf_path(&self.floor)) {
+            errors.push(format!("floor: unknown turf {}", self.floor));
+        }
+
+        for (id, stage) in self.stages.iter() {
+            if exists(&stage.placement.scene_path()) {
+                continue;
+            }
+            errors.push(match &stage.placement {
+                Placement::Furniture(prefab) | Placement::Pipe(prefab) => {
+                    format!("stages.{}: unknown prefab {}", id, prefab)
+                }
+                Placement::Turf(name) => format!("stages.{}: unknown turf {}", id, name),
+            });
+        }
+
+        for (index, step) in self.steps.iter().enumerate() {
+            for stage in step.from.iter().chain(iter::once(&step.to)) {
+                if !self.stages.contains_key(stage) {
+                    errors.push(format!("steps[{}]: unknown stage {}", index, stage));
+                }
+            }
+            // Taking a stage apart has to lead back to exactly one stage
+            if self.steps[..index].iter().any(|other| other.to == step.to) {
+                errors.push(format!(
+                    "steps[{}]: stage {} is already built by an earlier step",
+                    index, step.to
+                ));
+            }
+            if step.seconds < 0.0 {
+                errors.push(format!(
+                    "steps[{}]: seconds ({}) must not be negative",
+                    index, step.seconds
+                ));
+            }
+            for input in iter::once(&step.input).chain(step.refund.iter()) {
+                if !exists(&input.prefab_path()) {
+                    errors.push(format!(
+                        "steps[{}]: unknown item kind {} (no prefab at {})",
+                        index,
+                        input,
+                        input.prefab_path()
+                    ));
+                }
+            }
+        }
+        errors
+    }
+}
+
+fn load_stage_graph(mut commands: Commands) {
+    commands.init_resource::<StageGraph>();
+
+    let text = match read_to_string(STAGE_GRAPH_FILE) {
+        Ok(t) => t,
+        Err(err) => {
+            warn!(error = %err, "Could not read {}", STAGE_GRAPH_FILE);
+            return;
+        }
+    };
+
+    let graph: StageGraph = match ron::from_str(&text) {
+        Ok(g) => g,
+        Err(err) => {
+            error!(error = %err, "Error parsing {}", STAGE_GRAPH_FILE);
+            return;
+        }
+    };
+
+    // A partial graph could leave objects that can't be finished or taken apart, so nothing is built
+    let errors = graph.validate();
+    if !errors.is_empty() {
+        for err in errors.iter() {
+            error!(error = err.as_str(), "Invalid construction stage");
+        }
+        error!(
+            errors = errors.len(),
+            "Construction stages are disabled, fix {}", STAGE_GRAPH_FILE
+        );
+        return;
+    }
+
+    info!(
+        stages = graph.stages.len(),
+        steps = graph.steps.len(),
+        "Loaded construction stages"
+    );
+    commands.insert_resource(graph);
+}
+
+/// Floor turfs, where new objects can be started.
+type Floors<'w, 's> = Query<'w, 's, &'static TileEntity, Without<BlocksTile>>;
+
+/// If the entity is a floor with nothing in the layer a new object would be put in.
+fn is_empty_floor(
+    entity: Entity,
+    layer: TileLayer,
+    floors: &Floors,
+    maps: &Query<&TileMap>,
+) -> bool {
+    let Ok(tile) = floors.get(entity) else {
+        return false;
+    };
+    tile.layer() == TileLayer::Turf
+        && maps
+            .get(tile.tilemap())
+            .ok()
+            .and_then(|map| map.tile(tile.position()))
+            .is_some_and(|t| match layer {
+                TileLayer::Pipe => t.pipe.is_none(),
+                _ => t.furniture.is_none(),
+            })
+}
+
+/// Replaces an object with another stage, or removes it if there is none.
+/// The target is the floor when a new object is started.
+fn change_stage(
+    commands: &mut Commands,
+    graph: &StageGraph,
+    asset_server: &AssetServer,
+    target: Entity,
+    tile: &TileEntity,
+    from: Option<&str>,
+    to: Option<&str>,
+) {
+    let from = from.and_then(|id| graph.stages.get(id));
+    let to = to.and_then(|id| graph.stages.get(id));
+    let (tilemap, position) = (tile.tilemap(), tile.position());
+
+    if from.is_some() {
+        commands.despawn_tile_entity(target);
+    }
+    // Turf stages take the place of the floor, which has to come back when they are taken apart
+    let is_turf =
+        |stage: Option<&Stage>| stage.is_some_and(|s| s.placement.layer() == TileLayer::Turf);
+    if is_turf(from) && !is_turf(to) {
+        commands.spawn_tile_entity(
+            tilemap,
+            position,
+            TileLayer::Turf,
+            asset_server.load(turf_path(&graph.floor)),
+        );
+    }
+    if let Some(to) = to {
+        commands.spawn_tile_entity(
+            tilemap,
+            position,
+            to.placement.layer(),
+            asset_server.load(to.placement.scene_path()),
+        );
+    }
+}
+
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+#[component(storage = "SparseSet")]
+struct BuildStepInteraction {
+    /// The object, or the floor for steps that start a new one
+    target: Entity,
+    step: usize,
+    item: Entity,
+}
+
+// Dummy default for Reflect
+impl Default for BuildStepInteraction {
+    fn default() -> Self {
+        Self {
+            target: Entity::from_raw(0),
+            step: 0,
+            item: Entity::from_raw(0),
+        }
+    }
+}
+
+fn prepare_build_interactions(
+    list: Res<InteractionListEvents>,
+    graph: Res<StageGraph>,
+    items: Query<(Option<&MaterialStack>, Option<&ConstructionPart>)>,
+    stages: Query<&ConstructionStage>,
+    floors: Floors,
+    maps: Query<&TileMap>,
+) {
+    for event in list.events.iter() {
+        let Some((item, (stack, part))) = event
+            .item_in_hand
+            .and_then(|item| Some((item, items.get(item).ok()?)))
+        else {
+            continue;
+        };
+        let from = match stages.get(event.target) {
+            Ok(stage) => Some(stage.id.as_str()),
+            Err(_) if floors.contains(event.target) => None,
+            Err(_) => continue,
+        };
+
+        for (index, step) in graph.steps_from(from) {
+            if !step.input.matches(stack, part) {
+                continue;
+            }
+            if from.is_none()
+                && !is_empty_floor(event.target, graph.step_layer(step), &floors, &maps)
+            {
+                continue;
+            }
+            event.add_interaction(InteractionOption {
+                text: format!("Build {}", graph.stage_name(&step.to)),
+                interaction: Box::new(BuildStepInteraction {
+                    target: event.target,
+                    step: index,
+                    item,
+                }),
+                specificity: InteractionSpecificity::Specific,
+            });
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn build_step_interaction(
+    mut query: Query<(&BuildStepInteraction, &mut ActiveInteraction)>,
+    graph: Res<StageGraph>,
+    stages: Query<&ConstructionStage>,
+    tiles: Query<&TileEntity>,
+    floors: Floors,
+    maps: Query<&TileMap>,
+    mut items: Query<(Option<&mut MaterialStack>, Option<&ConstructionPart>)>,
+    asset_server: Res<AssetServer>,
+    time: Res<Time>,
+    mut commands: Commands,
+) {
+    for (interaction, mut active) in query.iter_mut() {
+        let Some(step) = graph.steps.get(interaction.step) else {
+            active.status = InteractionStatus::Canceled;
+            continue;
+        };
+        active.set_initial_duration(Duration::from_secs_f32(step.seconds));
+
+        // Someone else changed the object or used up the item first
+        let Ok(tile) = tiles.get(interaction.target) else {
+            active.status = InteractionStatus::Canceled;
+            continue;
+        };
+        let current = stages.get(interaction.target).ok().map(|s| s.id.as_str());
+        let still_there = match current {
+            Some(_) => current == step.from.as_deref(),
+            None => {
+                step.from.is_none()
+                    && is_empty_floor(interaction.target, graph.step_layer(step), &floors, &maps)
+            }
+        };
+        let usable = items
+            .get(interaction.item)
+            .is_ok_and(|(stack, part)| step.input.matches(stack, part));
+        if !still_there || !usable {
+            active.status = InteractionStatus::Canceled;
+            continue;
+        }
+
+        if active.start_time() + step.seconds > time.elapsed_seconds() {
+            continue;
+        }
+
+        match &step.input {
+            StageInput::Material { amount, .. } => {
+                if let Ok((Some(mut stack), _)) = items.get_mut(interaction.item) {
+                    stack.amount -= amount;
+                    if stack.amount == 0 {
+                        commands.entity(interaction.item).despawn_recursive();
+                    }
+                }
+            }
+            StageInput::Part(_) => commands.entity(interaction.item).despawn_recursive(),
+        }
+        change_stage(
+            &mut commands,
+            &graph,
+            &asset_server,
+            interaction.target,
+            tile,
+            step.from.as_deref(),
+            Some(&step.to),
+        );
+        debug!(stage = step.to, position = ?tile.position(), "Built construction stage");
+        active.status = InteractionStatus::Completed;
+    }
+}
+
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+#[component(storage = "SparseSet")]
+struct UndoStepInteraction {
+    target: Entity,
+    step: usize,
+    tool: Entity,
+}
+
+// Dummy default for Reflect
+impl Default for UndoStepInteraction {
+    fn default() -> Self {
+        Self {
+            target: Entity::from_raw(0),
+            step: 0,
+            tool: Entity::from_raw(0),
+        }
+    }
+}
+
+fn prepare_undo_interactions(
+    list: Res<InteractionListEvents>,
+    graph: Res<StageGraph>,
+    tools: Tools,
+    stages: Query<&ConstructionStage>,
+) {
+    for event in list.events.iter() {
+        let Some((item, tool)) = event
+            .item_in_hand
+            .and_then(|item| Some((item, tools.kind(item)?)))
+        else {
+            continue;
+        };
+        let Some((index, step)) = stages
+            .get(event.target)
+            .ok()
+            .and_then(|stage| graph.step_into(&stage.id))
+        else {
+            continue;
+        };
+
+        // Using the wrong tool is offered too, so players find out what's needed
+        let option = if tool == step.undo {
+            InteractionOption {
+                text: "Deconstruct".into(),
+                interaction: Box::new(UndoStepInteraction {
+                    target: event.target,
+                    step: index,
+                    tool: item,
+                }),
+                specificity: InteractionSpecificity::Specific,
+            }
+        } else {
+            InteractionOption {
+                text: format!("Deconstruct (needs {})", step.undo.name()),
+                interaction: Box::new(WrongToolInteraction {
+                    user: event.source,
+                    needed: step.undo,
+                }),
+                specificity: InteractionSpecificity::Common,
+            }
+        };
+        event.add_interaction(option);
+    }
+}
+
+/// Sets the amount of refunded material once its prefab has spawned.
+#[derive(Component)]
+struct RefundAmount(u32);
+
+#[allow(clippy::too_many_arguments)]
+fn undo_step_interaction(
+    mut query: Query<(Entity, &UndoStepInteraction, &mut ActiveInteraction)>,
+    graph: Res<StageGraph>,
+    objects: Query<(
+        &ConstructionStage,
+        &TileEntity,
+        &GlobalTransform,
+        Option<&ImpactMaterial>,
+    )>,
+    broken: Query<(), With<Broken>>,
+    asset_server: Res<AssetServer>,
+    time: Res<Time>,
+    mut effects: EffectSender,
+    mut damage: EventWriter<ItemDamageEvent>,
+    mut commands: Commands,
+) {
+    for (source, interaction, mut active) in query.iter_mut() {
+        active.set_initial_duration(DECONSTRUCT_STEP_TIME);
+
+        let Some(step) = graph.steps.get(interaction.step) else {
+            active.status = InteractionStatus::Canceled;
+            continue;
+        };
+        let Ok((stage, tile, transform, impact)) = objects.get(interaction.target) else {
+            active.status = InteractionStatus::Canceled;
+            continue;
+        };
+        // Someone else changed the object first
+        if stage.id != step.to || broken.contains(interaction.tool) {
+            active.status = InteractionStatus::Canceled;
+            continue;
+        }
+
+        if active.start_time() + DECONSTRUCT_STEP_TIME.as_secs_f32() > time.elapsed_seconds() {
+            continue;
+        }
+        active.status = InteractionStatus::Completed;
+        damage.send(ItemDamageEvent::used(interaction.tool, source));
+
+        let position = transform.translation();
+        if step.undo == ToolKind::Welder {
+            effects.send(EffectKind::Sparks, position, 0.5);
+        }
+        if step.from.is_none() {
+            effects.send(EffectKind::breaking(impact.copied()), position, 1.0);
+        }
+
+        for refund in step.refund.iter() {
+            let mut item = commands.spawn(NetworkSceneBundle {
+                scene: asset_server.load(refund.prefab_path()).into(),
+                transform: Transform::from_translation(position + Vec3::Y * 0.5),
+                ..Default::default()
+            });
+            if let StageInput::Material { amount, .. } = refund {
+                item.insert(RefundAmount(*amount));
+            }
+        }
+        change_stage(
+            &mut commands,
+            &graph,
+            &asset_server,
+            interaction.target,
+            tile,
+            Some(&step.to),
+            step.from.as_deref(),
+        );
+        debug!(stage = step.to, position = ?tile.position(), "Took apart construction stage");
+    }
+}
+
+fn set_refund_amounts(
+    mut stacks: Query<(Entity, &RefundAmount, &mut MaterialStack)>,
+    mut commands: Commands,
+) {
+    for (entity, refund, mut stack) in stacks.iter_mut() {
+        stack.amount = refund.0;
+        commands.entity(entity).remove::<RefundAmount>();
+    }
+}
+
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+#[component(storage = "SparseSet")]
+struct ExamineStageInteraction {
+    user: Entity,
+}
+
+// Dummy default for Reflect
+impl Default for ExamineStageInteraction {
+    fn default() -> Self {
+        Self {
+            user: Entity::from_raw(0),
+        }
+    }
+}
+
+fn prepare_examine_interaction(
+    list: Res<InteractionListEvents>,
+    stages: Query<(), With<ConstructionStage>>,
+) {
+    for event in list.events.iter() {
+        if !stages.contains(event.target) {
+            continue;
+        }
+        event.add_interaction(InteractionOption {
+            text: "Examine".into(),
+            interaction: Box::new(ExamineStageInteraction { user: event.source }),
+            specificity: InteractionSpecificity::Common,
+        });
+    }
+}
+
+/// Describes the stage and lists what can be done with it next.
+fn examine_stage_interaction(
+    mut query: Query<(&ExamineStageInteraction, &mut ActiveInteraction)>,
+    stages: Query<&ConstructionStage>,
+    graph: Res<StageGraph>,
+    controls: Res<ClientControls>,
+    players: Res<Players>,
+    mut messages: EventWriter<SystemMessageEvent>,
+) {
+    for (interaction, mut active) in query.iter_mut() {
+        let Some((id, stage)) = stages
+            .get(active.target)
+            .ok()
+            .and_then(|s| Some((s.id.as_str(), graph.stages.get(&s.id)?)))
+        else {
+            active.status = InteractionStatus::Canceled;
+            continue;
+        };
+        active.status = InteractionStatus::Completed;
+
+        let mut text = stage.examine.clone();
+        for (_, step) in graph.steps_from(Some(id)) {
+            text.push_str(&format!(
+                " Add {} to build the {}.",
+                step.input,
+                graph.stage_name(&step.to)
+            ));
+        }
+        if let Some((_, step)) = graph.step_into(id) {
+            text.push_str(&format!(
+                " It can be taken apart with {}.",
+                step.undo.name()
+            ));
+        }
+        let Some(connection) = controls
+            .controlling_player(interaction.user)
+            .and_then(|p| players.get_connection(&p))
+        else {
+            continue;
+        };
+        messages.send(SystemMessageEvent {
+            receiver: connection,
+            text,
+        });
+    }
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use bevy::{
+        ecs::system::{Command, SystemState},
+        time::TimeUpdateStrategy,
+    };
+    use networking::identity::NetworkCommand;
+    use utils::task::Tasks;
+
+    use super::*;
+    use crate::{
+        config::ServerConfig, door::Door, interaction::ExecuteInteraction, testing::server_app,
+    };
+
+    const FRAME: Duration = Duration::from_millis(100);
+    const POSITION: UVec2 = UVec2::new(2, 2);
+
+    fn update(app: &mut App, frames: u32) {
+        for _ in 0..frames {
+            app.update();
+        }
+    }
+
+    /// A map with a single floor tile, returning the map and the floor.
+    fn setup() -> (App, Entity, Entity) {
+        let mut app = server_app(ServerConfig::default());
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(FRAME));
+        let map = app
+            .world
+            .spawn((TileMap::new(UVec2::ONE), SpatialBundle::default()))
+            .id();
+        NetworkCommand { entity: map }.apply(&mut app.world);
+
+        let mut state = SystemState::<(Commands, Res<AssetServer>)>::new(&mut app.world);
+        let (mut commands, asset_server) = state.get_mut(&mut app.world);
+        let floor = commands.spawn_tile_entity(
+            map,
+            POSITION,
+            TileLayer::Turf,
+            asset_server.load(turf_path("plating")),
+        );
+        state.apply(&mut app.world);
+        update(&mut app, 1);
+        (app, map, floor)
+    }
+
+    /// Does the step that builds `stage`, returning the object once its prefab has loaded.
+    fn build(app: &mut App, map: Entity, target: Entity, stage: &str, item: Entity) -> Entity {
+        let graph = app.world.resource::<StageGraph>();
+        let (step, seconds) = graph
+            .step_into(stage)
+            .map(|(index, step)| (index, step.seconds))
+            .unwrap();
+        let user = app.world.spawn(SpatialBundle::default()).id();
+        app.world
+            .resource_mut::<Tasks<ExecuteInteraction>>()
+            .create_ignore(ExecuteInteraction {
+                entity: user,
+                target,
+                interaction: Box::new(BuildStepInteraction { target, step, item }),
+            });
+        update(app, (seconds / FRAME.as_secs_f32()) as u32 + 3);
+        assert!(app.world.get::<ActiveInteraction>(user).is_none());
+
+        for _ in 0..200 {
+            let furniture = app
+                .world
+                .get::<TileMap>(map)
+                .and_then(|map| map.tile(POSITION))
+                .and_then(|tile| tile.furniture);
+            if let Some(object) = furniture.filter(|&object| {
+                app.world
+                    .get::<ConstructionStage>(object)
+                    .is_some_and(|s| s.id == stage)
+            }) {
+                return object;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+            app.update();
+        }
+        panic!("{} was not built", stage);
+    }
+
+    #[test]
+    fn airlock_is_built_in_stages() {
+        let (mut app, map, floor) = setup();
+        // The graph is read when the app starts
+        assert!(!app.world.resource::<StageGraph>().steps.is_empty());
+
+        let metal = app
+            .world
+            .spawn(MaterialStack {
+                material: "metal".into(),
+                amount: 5,
+            })
+            .id();
+        let frame = build(&mut app, map, floor, "airlock_frame", metal);
+        assert_eq!(app.world.get::<MaterialStack>(metal).unwrap().amount, 1);
+        assert!(app.world.get::<Door>(frame).is_none());
+        // Frames go on top of the floor instead of replacing it
+        assert_eq!(
+            app.world
+                .get::<TileMap>(map)
+                .unwrap()
+                .tile(POSITION)
+                .unwrap()
+                .turf,
+            Some(floor)
+        );
+
+        let cable = app
+            .world
+            .spawn(ConstructionPart {
+                kind: "cable_coil".into(),
+            })
+            .id();
+        let wired = build(&mut app, map, frame, "airlock_frame_wired", cable);
+        assert!(app.world.get_entity(frame).is_none());
+        assert!(app.world.get_entity(cable).is_none());
+        assert!(app.world.get::<Door>(wired).is_none());
+
+        let electronics = app
+            .world
+            .spawn(ConstructionPart {
+                kind: "airlock_electronics".into(),
+            })
+            .id();
+        let airlock = build(&mut app, map, wired, "airlock", electronics);
+        assert!(app.world.get_entity(wired).is_none());
+        assert!(app.world.get_entity(electronics).is_none());
+        assert!(app.world.get::<Door>(airlock).is_some());
+    }
+
+    #[test]
+    fn wrong_part_cancels_the_step() {
+        let (mut app, map, floor) = setup();
+        let metal = app
+            .world
+            .spawn(MaterialStack {
+                material: "metal".into(),
+                amount: 4,
+            })
+            .id();
+        let frame = build(&mut app, map, floor, "airlock_frame", metal);
+
+        // Electronics can only go into a wired frame
+        let electronics = app
+            .world
+            .spawn(ConstructionPart {
+                kind: "airlock_electronics".into(),
+            })
+            .id();
+        let step = app
+            .world
+            .resource::<StageGraph>()
+            .step_into("airlock")
+            .unwrap()
+            .0;
+        let user = app.world.spawn(SpatialBundle::default()).id();
+        app.world
+            .resource_mut::<Tasks<ExecuteInteraction>>()
+            .create_ignore(ExecuteInteraction {
+                entity: user,
+                target: frame,
+                interaction: Box::new(BuildStepInteraction {
+                    target: frame,
+                    step,
+                    item: electronics,
+                }),
+            });
+        update(&mut app, 40);
+
+        assert!(app.world.get::<ActiveInteraction>(user).is_none());
+        assert!(app.world.get_entity(electronics).is_some());
+        assert_eq!(
+            app.world.get::<ConstructionStage>(frame).unwrap().id,
+            "airlock_frame"
+        );
+    }
+}
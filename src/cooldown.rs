@@ -0,0 +1,319 @@
+use bevy::{prelude::*, utils::HashMap};
+use networking::{
+    is_server,
+    messaging::{AppExt, MessageReceivers, MessageSender},
+    spawning::ClientControls,
+    time::ServerNetworkTime,
+    ConnectionId, Players,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{body::Body, spectator::Spectator};
+
+#[cfg(feature = "client")]
+use {bevy_egui::egui, networking::messaging::MessageEvent};
+
+/// Actions creatures have to wait for before using them again.
+/// Every player is told about the cooldowns of the creature they control, so the UI can show them.
+pub struct CooldownPlugin;
+
+impl Plugin for CooldownPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_network_message::<CooldownsMessage>("CooldownsMessage");
+
+        if is_server(app) {
+            app.add_systems(Update, (add_cooldowns, send_cooldowns));
+        } else {
+            #[cfg(feature = "client")]
+            app.init_resource::<OwnCooldowns>()
+                .add_systems(Update, receive_cooldowns);
+        }
+    }
+}
+
+/// Names a cooldown. Keys start with the feature they belong to, like `intent.disarm`.
+pub type CooldownKey = &'static str;
+
+/// The running cooldowns of a player's creature or spectator, with the server tick they started and end at.
+#[derive(Component, Default)]
+pub struct Cooldowns {
+    running: HashMap<CooldownKey, (u32, u32)>,
+}
+
+impl Cooldowns {
+    /// Starts the cooldown and returns true, or returns false if it's still running.
+    /// Actions should only be done if this returns true.
+    pub fn try_start(&mut self, key: CooldownKey, seconds: f32, time: &ServerNetworkTime) -> bool {
+        let now = time.current_tick();
+        self.running.retain(|_, &mut (_, end)| end > now);
+        if self.running.contains_key(key) {
+            return false;
+        }
+        let ticks = (seconds as f64 / time.tick_in_seconds()).ceil() as u32;
+        self.running.insert(key, (now, now + ticks.max(1)));
+        true
+    }
+}
+
+/// Spectators get cooldowns too, they can still point at things.
+fn add_cooldowns(
+    added: Query<Entity, (Or<(Added<Body>, Added<Spectator>)>, Without<Cooldowns>)>,
+    mut commands: Commands,
+) {
+    for entity in added.iter() {
+        commands.entity(entity).insert(Cooldowns::default());
+    }
+}
+
+/// Running cooldowns of the creature a player controls.
+#[derive(Serialize, Deserialize, Default, Clone, PartialEq)]
+struct CooldownsMessage {
+    /// Key, start tick and end tick, sorted by key
+    cooldowns: Vec<(String, u32, u32)>,
+}
+
+/// Sends every player the cooldowns of the creature they control whenever they change.
+fn send_cooldowns(
+    players: Res<Players>,
+    controls: Res<ClientControls>,
+    bodies: Query<&Cooldowns>,
+    time: Res<ServerNetworkTime>,
+    mut sent: Local<HashMap<ConnectionId, CooldownsMessage>>,
+    mut sender: MessageSender,
+) {
+    let now = time.current_tick();
+    let mut current = HashMap::default();
+    for (&connection, player) in players.players().iter() {
+        let mut cooldowns: Vec<_> = controls
+            .controlled_entity(player.id)
+            .and_then(|entity| bodies.get(entity).ok())
+            .into_iter()
+            .flat_map(|c| c.running.iter())
+            .filter(|(_, &(_, end))| end > now)
+            .map(|(&key, &(start, end))| (key.to_owned(), start, end))
+            .collect();
+        cooldowns.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        current.insert(connection, CooldownsMessage { cooldowns });
+    }
+
+    for (&connection, message) in current.iter() {
+        let last = sent.get(&connection);
+        if last == Some(message) || (last.is_none() && message == &CooldownsMessage::default()) {
+            continue;
+        }
+        sender.send(message, MessageReceivers::Single(connection));
+    }
+    *sent = current;
+}
+
+/// Cooldowns of the creature this client controls.
+#[cfg(feature = "client")]
+#[derive(Resource, Default)]
+pub struct OwnCooldowns {
+    running: HashMap<String, (u32, u32)>,
+}
+
+#[cfg(feature = "client")]
+impl OwnCooldowns {
+    /// How much of the cooldown is left at the server tick, from 1 right after it started to 0.
+    /// Ticks come from the synced clock, so the value changes smoothly between updates.
+    pub fn remaining(&self, key: CooldownKey, tick: f32) -> Option<f32> {
+        let &(start, end) = self.running.get(key)?;
+        let (start, end) = (start as f32, end as f32);
+        (tick < end).then(|| ((end - tick) / (end - start)).min(1.0))
+    }
+}
+
+#[cfg(feature = "client")]
+fn receive_cooldowns(
+    mut messages: EventReader<MessageEvent<CooldownsMessage>>,
+    mut own: ResMut<OwnCooldowns>,
+) {
+    if let Some(event) = messages.iter().last() {
+        own.running = event
+            .message
+            .cooldowns
+            .iter()
+            .map(|(key, start, end)| (key.clone(), (*start, *end)))
+            .collect();
+    }
+}
+
+/// Triangles the full circle of a sweep is made of
+#[cfg(feature = "client")]
+const SWEEP_SEGMENTS: usize = 32;
+
+/// Darkens the part of the rectangle that is still cooling down, like a clock hand going around.
+#[cfg(feature = "client")]
+pub fn paint_sweep(ui: &egui::Ui, rect: egui::Rect, remaining: f32) {
+    if remaining <= 0.0 {
+        return;
+    }
+    let painter = ui.painter_at(rect);
+    let center = rect.center();
+    // Large enough to reach the corners, the painter cuts off the rest
+    let radius = rect.size().length() / 2.0;
+    let point = |angle: f32| center + radius * egui::vec2(angle.sin(), -angle.cos());
+    let color = egui::Color32::from_black_alpha(150);
+
+    let covered = std::f32::consts::TAU * remaining.min(1.0);
+    let start = std::f32::consts::TAU - covered;
+    let segments = ((SWEEP_SEGMENTS as f32 * remaining).ceil() as usize).max(1);
+    for i in 0..segments {
+        let from = start + covered * i as f32 / segments as f32;
+        let to = start + covered * (i + 1) as f32 / segments as f32;
+        painter.add(egui::Shape::convex_polygon(
+            vec![center, point(from), point(to)],
+            color,
+            egui::Stroke::NONE,
+        ));
+    }
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::time::TimeUpdateStrategy;
+    use networking::{loopback::LinkConditions, messaging::MessageEvent, testing, NetworkRole};
+
+    use super::*;
+    use crate::{config::ServerConfig, testing::server_app};
+
+    const FRAME: Duration = Duration::from_millis(100);
+    const DISARM: CooldownKey = "intent.disarm";
+
+    #[derive(Resource, Default)]
+    struct Received(Vec<CooldownsMessage>);
+
+    fn record_messages(
+        mut messages: ResMut<Events<MessageEvent<CooldownsMessage>>>,
+        mut received: ResMut<Received>,
+    ) {
+        received
+            .0
+            .extend(messages.drain().map(|event| event.message));
+    }
+
+    fn try_start(world: &mut World, entity: Entity, key: CooldownKey, seconds: f32) -> bool {
+        world.resource_scope(|world, time: Mut<ServerNetworkTime>| {
+            world
+                .get_mut::<Cooldowns>(entity)
+                .unwrap()
+                .try_start(key, seconds, &time)
+        })
+    }
+
+    fn set_tick(world: &mut World, tick: u32) {
+        world
+            .resource_mut::<ServerNetworkTime>()
+            .set_current_tick(tick);
+    }
+
+    #[test]
+    fn early_reuse_is_rejected_until_the_cooldown_ends() {
+        let mut server = server_app(ServerConfig::default());
+        let entity = server.world.spawn(Cooldowns::default()).id();
+        set_tick(&mut server.world, 100);
+
+        assert!(try_start(&mut server.world, entity, DISARM, 1.0));
+        let &(start, end) = server
+            .world
+            .get::<Cooldowns>(entity)
+            .unwrap()
+            .running
+            .get(DISARM)
+            .unwrap();
+        let tick_seconds = server
+            .world
+            .resource::<ServerNetworkTime>()
+            .tick_in_seconds();
+        assert_eq!(start, 100);
+        assert_eq!(end, 100 + (1.0 / tick_seconds).ceil() as u32);
+
+        assert!(!try_start(&mut server.world, entity, DISARM, 1.0));
+        // Other cooldowns are independent
+        assert!(try_start(&mut server.world, entity, "pointing.point", 1.0));
+        set_tick(&mut server.world, end - 1);
+        assert!(!try_start(&mut server.world, entity, DISARM, 1.0));
+        set_tick(&mut server.world, end);
+        assert!(try_start(&mut server.world, entity, DISARM, 1.0));
+    }
+
+    #[test]
+    fn tiny_cooldowns_last_at_least_a_tick() {
+        let mut server = server_app(ServerConfig::default());
+        let entity = server.world.spawn(Cooldowns::default()).id();
+
+        assert!(try_start(&mut server.world, entity, DISARM, 0.0));
+        assert!(!try_start(&mut server.world, entity, DISARM, 0.0));
+        let now = server.world.resource::<ServerNetworkTime>().current_tick();
+        set_tick(&mut server.world, now + 1);
+        assert!(try_start(&mut server.world, entity, DISARM, 0.0));
+    }
+
+    #[test]
+    fn controlling_player_receives_the_server_expiry() {
+        let mut server = server_app(ServerConfig::default());
+        let mut client = testing::app(NetworkRole::Client);
+        client
+            .add_network_message::<CooldownsMessage>("CooldownsMessage")
+            .init_resource::<Received>()
+            .add_systems(Update, record_messages);
+        let connector = testing::listen(&mut server);
+        testing::join(&mut client, &connector, LinkConditions::default());
+        testing::connect(&mut server, &mut [&mut client], 200);
+        server.insert_resource(TimeUpdateStrategy::ManualDuration(FRAME));
+
+        let player = server
+            .world
+            .resource::<Players>()
+            .players()
+            .values()
+            .next()
+            .unwrap()
+            .id;
+        let controlled = server.world.spawn(Cooldowns::default()).id();
+        let uncontrolled = server.world.spawn(Cooldowns::default()).id();
+        server
+            .world
+            .resource_mut::<ClientControls>()
+            .give_control(player, controlled);
+        testing::update(&mut server, &mut [&mut client], 5);
+        assert!(
+            client.world.resource::<Received>().0.is_empty(),
+            "nothing is sent while no cooldown runs"
+        );
+
+        assert!(try_start(&mut server.world, uncontrolled, DISARM, 1.0));
+        testing::update(&mut server, &mut [&mut client], 5);
+        assert!(
+            client.world.resource::<Received>().0.is_empty(),
+            "cooldowns of other creatures aren't sent"
+        );
+
+        assert!(try_start(&mut server.world, controlled, DISARM, 1.0));
+        let &(start, end) = server
+            .world
+            .get::<Cooldowns>(controlled)
+            .unwrap()
+            .running
+            .get(DISARM)
+            .unwrap();
+        testing::update(&mut server, &mut [&mut client], 3);
+        let received = std::mem::take(&mut client.world.resource_mut::<Received>().0);
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].cooldowns, vec![(DISARM.to_owned(), start, end)]);
+        assert!(!try_start(&mut server.world, controlled, DISARM, 1.0));
+
+        let now = server.world.resource::<ServerNetworkTime>().current_tick();
+        testing::update(&mut server, &mut [&mut client], end - now + 2);
+        let received = std::mem::take(&mut client.world.resource_mut::<Received>().0);
+        assert_eq!(
+            received.last().map(|message| message.cooldowns.len()),
+            Some(0),
+            "the client is told once the cooldown ran out"
+        );
+        assert!(try_start(&mut server.world, controlled, DISARM, 1.0));
+    }
+}
@@ -0,0 +1,211 @@
+use std::time::Duration;
+
+use bevy::{prelude::*, time::common_conditions::on_timer, window::PrimaryWindow};
+use bevy_egui::{egui, EguiContexts};
+use bevy_rapier3d::prelude::RapierContext;
+use networking::spawning::ClientControlled;
+
+use crate::{
+    body::Body,
+    camera::MainCamera,
+    combat::ClientCombatModeStatus,
+    door::Door,
+    interaction::Reach,
+    items::{surface::SurfacePicker, Item},
+    ui::has_window,
+    GameState,
+};
+
+/// Changes the cursor to show what clicking would do, like attacking or picking something up.
+/// Only uses what the client already knows about the hovered object, so it doesn't cost any traffic.
+pub struct CursorPlugin;
+
+impl Plugin for CursorPlugin {
+    fn build(&self, app: &mut App) {
+        let server = app.world.resource::<AssetServer>();
+        let icons = CursorIcons {
+            attack: server.load("textures/cursors/attack.png"),
+            grab: server.load("textures/cursors/grab.png"),
+            interact: server.load("textures/cursors/interact.png"),
+        };
+        app.insert_resource(icons)
+            .init_resource::<CursorSettings>()
+            .init_resource::<HoverTarget>()
+            .add_systems(
+                Update,
+                (
+                    update_hover_target.run_if(on_timer(Duration::from_secs_f32(HOVER_INTERVAL))),
+                    update_cursor.run_if(has_window),
+                )
+                    .chain()
+                    .run_if(in_state(GameState::Game)),
+            )
+            .add_systems(OnExit(GameState::Game), restore_cursor);
+    }
+}
+
+/// Seconds between raycasts for the object under the cursor
+const HOVER_INTERVAL: f32 = 0.1;
+/// Size of the cursor icons in points
+const ICON_SIZE: f32 = 32.0;
+
+/// Player preferences for the cursor.
+#[derive(Resource)]
+pub struct CursorSettings {
+    /// Show what clicking would do instead of always using the normal cursor
+    pub contextual: bool,
+}
+
+impl Default for CursorSettings {
+    fn default() -> Self {
+        Self { contextual: true }
+    }
+}
+
+#[derive(Resource)]
+struct CursorIcons {
+    attack: Handle<Image>,
+    grab: Handle<Image>,
+    interact: Handle<Image>,
+}
+
+/// The object under the cursor, updated a few times a second.
+#[derive(Resource, Default)]
+pub struct HoverTarget {
+    /// The hit entity and where it was hit
+    pub hit: Option<(Entity, Vec3)>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum CursorAction {
+    Attack,
+    Grab,
+    Interact,
+}
+
+fn update_hover_target(
+    mut hover: ResMut<HoverTarget>,
+    rapier_context: Res<RapierContext>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    surface_picker: SurfacePicker,
+) {
+    let ray = windows
+        .get_single()
+        .ok()
+        .and_then(|window| window.cursor_position())
+        .zip(cameras.iter().next())
+        .and_then(|(cursor, (camera, transform))| camera.viewport_to_world(transform, cursor));
+    hover.hit = ray.and_then(|ray| {
+        let (entity, toi) =
+            rapier_context.cast_ray(ray.origin, ray.direction, 100.0, true, Default::default())?;
+        let point = ray.origin + ray.direction * toi;
+        // Items on tables have no colliders, so they are picked by where the table was hovered
+        Some((
+            surface_picker.item_at(entity, point).unwrap_or(entity),
+            point,
+        ))
+    });
+}
+
+#[allow(clippy::too_many_arguments)]
+fn update_cursor(
+    mut contexts: EguiContexts,
+    mut windows: Query<&mut Window, With<PrimaryWindow>>,
+    hover: Res<HoverTarget>,
+    settings: Res<CursorSettings>,
+    icons: Res<CursorIcons>,
+    combat_status: ClientCombatModeStatus,
+    controlled: Query<Entity, With<ClientControlled>>,
+    parents: Query<&Parent>,
+    creatures: Query<(), With<Body>>,
+    items: Query<(), With<Item>>,
+    doors: Query<(), With<Door>>,
+    reach: Reach,
+) {
+    let Ok(mut window) = windows.get_single_mut() else {
+        return;
+    };
+    let ctx = contexts.ctx_mut();
+    // Panels and windows show egui's own cursor
+    let over_ui = ctx.is_pointer_over_area();
+    let cursor_position = ctx.input(|input| input.pointer.hover_pos());
+
+    let action = match (
+        settings.contextual && !over_ui,
+        hover.hit,
+        controlled.get_single(),
+    ) {
+        (true, Some((hit, point)), Ok(actor)) => {
+            let hovered: Vec<_> = std::iter::once(hit)
+                .chain(parents.iter_ancestors(hit))
+                .collect();
+            // Hovering yourself or something you carry doesn't do anything special
+            if hovered.contains(&actor) {
+                None
+            } else {
+                let action = if combat_status.is_enabled() {
+                    hovered
+                        .iter()
+                        .find(|&&e| creatures.contains(e))
+                        .map(|&e| (CursorAction::Attack, e))
+                } else {
+                    hovered
+                        .iter()
+                        .find(|&&e| items.contains(e))
+                        .map(|&e| (CursorAction::Grab, e))
+                        .or_else(|| {
+                            hovered
+                                .iter()
+                                .find(|&&e| doors.contains(e))
+                                .map(|&e| (CursorAction::Interact, e))
+                        })
+                };
+                action.map(|(action, target)| {
+                    (action, reach.can_reach_point(actor, point, Some(target)))
+                })
+            }
+        }
+        _ => None,
+    };
+
+    let (Some((action, in_reach)), Some(position)) = (action, cursor_position) else {
+        if !window.cursor.visible {
+            window.cursor.visible = true;
+        }
+        return;
+    };
+    if window.cursor.visible {
+        window.cursor.visible = false;
+    }
+
+    let icon = match action {
+        CursorAction::Attack => &icons.attack,
+        CursorAction::Grab => &icons.grab,
+        CursorAction::Interact => &icons.interact,
+    };
+    let texture = contexts.add_image(icon.clone_weak());
+    let tint = if in_reach {
+        egui::Color32::WHITE
+    } else {
+        egui::Color32::from_gray(110)
+    };
+    contexts
+        .ctx_mut()
+        .layer_painter(egui::LayerId::new(
+            egui::Order::Tooltip,
+            egui::Id::new("contextual_cursor"),
+        ))
+        .image(
+            texture,
+            egui::Rect::from_center_size(position, egui::Vec2::splat(ICON_SIZE)),
+            egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0)),
+            tint,
+        );
+}
+
+fn restore_cursor(mut windows: Query<&mut Window, With<PrimaryWindow>>) {
+    for mut window in windows.iter_mut() {
+        window.cursor.visible = true;
+    }
+}
@@ -2,8 +2,13 @@ use bevy::prelude::*;
 use bevy_egui::{egui, EguiContexts};
 use bevy_inspector_egui::quick::WorldInspectorPlugin;
 use bevy_rapier3d::render::DebugRenderContext;
+use networking::diagnostics::{ConnectionStats, SpawnAuditStats};
 
-use crate::{ui::has_window, GameState};
+use crate::{
+    occlusion::{OcclusionSettings, OcclusionStats},
+    ui::has_window,
+    GameState,
+};
 
 pub(crate) struct DebugPlugin;
 
@@ -33,10 +38,31 @@ fn debug_menu(
     mut contexts: EguiContexts,
     mut rapier_debug: ResMut<DebugRenderContext>,
     mut state: ResMut<DebugState>,
+    mut occlusion: ResMut<OcclusionSettings>,
+    occlusion_stats: Res<OcclusionStats>,
+    connection: Res<ConnectionStats>,
+    spawn_audit: Res<SpawnAuditStats>,
 ) {
     egui::Window::new("Debug Menu").show(contexts.ctx_mut(), |ui| {
         ui.checkbox(&mut state.inspector_enabled, "World inspector");
         ui.checkbox(&mut rapier_debug.enabled, "Show physics objects");
+        ui.checkbox(&mut occlusion.show_flood_fill, "Show occlusion flood fill");
+        let stats = &*occlusion_stats;
+        let percent = stats.hidden_meshes as f32 / stats.total_meshes.max(1) as f32 * 100.0;
+        ui.label(format!(
+            "Occlusion hides {} of {} meshes ({:.0}%)",
+            stats.hidden_meshes, stats.total_meshes, percent
+        ));
+        ui.label(format!(
+            "Connection: {:.0} ms, {:.1}% loss, {} link",
+            connection.rtt,
+            connection.packet_loss * 100.0,
+            connection.link_tier
+        ));
+        ui.label(format!(
+            "Spawn audit: {} stale entities removed and {} missing requested in {} reconciliations",
+            spawn_audit.orphans_removed, spawn_audit.missing_requested, spawn_audit.reconciliations
+        ));
     });
 }
 
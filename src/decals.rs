@@ -0,0 +1,524 @@
+use bevy::{
+    ecs::system::{SystemParam, SystemState},
+    prelude::*,
+    utils::HashSet,
+};
+use networking::{
+    is_server,
+    messaging::{AppExt, MessageReceivers, MessageSender},
+    spawning::ClientControls,
+    ConnectionId, Players,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::console::{
+    CommandContext, CommandResult, ConsoleAppExt, ConsoleCommand, PermissionLevel,
+};
+
+#[cfg(feature = "client")]
+use {crate::GameState, bevy::time::common_conditions::on_timer, std::time::Duration};
+
+/// Marks left on floors, like blood splatters, scorch marks, footprints and dirt.
+/// The client keeps a fixed number of them and draws every chunk's decals as a single mesh.
+pub struct DecalPlugin;
+
+impl Plugin for DecalPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_network_message::<DecalMessage>("DecalMessage")
+            .add_network_message::<ClearDecalsMessage>("ClearDecalsMessage");
+
+        if is_server(app) {
+            app.add_console_command(ConsoleCommand {
+                name: "cleardecals",
+                description: "Removes every blood splatter, scorch mark and footprint",
+                parameters: &[],
+                permission: PermissionLevel::Admin,
+                handler: clear_decals_command,
+            });
+        } else {
+            #[cfg(feature = "client")]
+            app.init_resource::<DecalSettings>()
+                .init_resource::<client::Decals>()
+                .add_systems(Startup, client::setup_decal_assets)
+                .add_systems(
+                    Update,
+                    (
+                        client::receive_decals,
+                        client::drop_decals_without_turf,
+                        client::expire_decals.run_if(on_timer(Duration::from_secs(1))),
+                        client::rebuild_chunk_meshes,
+                    )
+                        .chain()
+                        .run_if(in_state(GameState::Game)),
+                );
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecalKind {
+    Blood,
+    Scorch,
+    Footprints,
+    Dirt,
+}
+
+/// Server message to leave a decal on the floor.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+struct DecalMessage {
+    kind: DecalKind,
+    /// Position on the floor, x and z in world space
+    position: Vec2,
+    /// Direction the decal points in, in radians around the vertical axis.
+    /// Picked at random on the client if not set.
+    heading: Option<f32>,
+    /// Width in meters
+    size: f32,
+}
+
+/// Server message to remove the decals on a tile, or every decal.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+struct ClearDecalsMessage {
+    tile: Option<UVec2>,
+}
+
+/// Players further away than this from a decal aren't sent it
+const DECAL_RANGE: f32 = 20.0;
+
+/// Sends decals to the players close enough to see them.
+#[derive(SystemParam)]
+pub struct DecalSender<'w, 's> {
+    players: Res<'w, Players>,
+    controls: Res<'w, ClientControls>,
+    transforms: Query<'w, 's, &'static GlobalTransform>,
+    sender: MessageSender<'w, 's>,
+}
+
+impl<'w, 's> DecalSender<'w, 's> {
+    pub fn send(&mut self, kind: DecalKind, position: Vec3, heading: Option<f32>, size: f32) {
+        let Some(receivers) = self.receivers(position) else {
+            return;
+        };
+        self.sender.send(
+            &DecalMessage {
+                kind,
+                position: Vec2::new(position.x, position.z),
+                heading,
+                size,
+            },
+            MessageReceivers::Set(receivers),
+        );
+    }
+
+    /// Removes the decals on a tile, for example after it was mopped.
+    pub fn clear(&mut self, tile: UVec2) {
+        let Some(receivers) = self.receivers(Vec3::new(tile.x as f32, 0.0, tile.y as f32)) else {
+            return;
+        };
+        self.sender.send(
+            &ClearDecalsMessage { tile: Some(tile) },
+            MessageReceivers::Set(receivers),
+        );
+    }
+
+    fn receivers(&self, position: Vec3) -> Option<HashSet<ConnectionId>> {
+        let receivers: HashSet<_> = self
+            .players
+            .players()
+            .iter()
+            .filter(|(_, player)| {
+                self.controls
+                    .controlled_entity(player.id)
+                    .and_then(|e| self.transforms.get(e).ok())
+                    .is_some_and(|t| t.translation().distance(position) <= DECAL_RANGE)
+            })
+            .map(|(&connection, _)| connection)
+            .collect();
+        (!receivers.is_empty()).then_some(receivers)
+    }
+}
+
+fn clear_decals_command(world: &mut World, _: &CommandContext) -> CommandResult {
+    let mut state = SystemState::<MessageSender>::new(world);
+    state.get_mut(world).send(
+        &ClearDecalsMessage { tile: None },
+        MessageReceivers::AllPlayers,
+    );
+    state.apply(world);
+    Ok("Cleared all decals".into())
+}
+
+/// Player preferences for decals.
+#[cfg(feature = "client")]
+#[derive(Resource)]
+pub struct DecalSettings {
+    /// Most decals kept at the same time, the oldest ones are removed first. 0 turns them off.
+    pub capacity: usize,
+}
+
+#[cfg(feature = "client")]
+impl Default for DecalSettings {
+    fn default() -> Self {
+        Self { capacity: 1024 }
+    }
+}
+
+#[cfg(feature = "client")]
+mod client {
+    use std::collections::VecDeque;
+
+    use bevy::{
+        prelude::*,
+        render::{
+            mesh::Indices,
+            render_resource::{Extent3d, PrimitiveTopology, TextureDimension, TextureFormat},
+        },
+        utils::{HashMap, HashSet},
+    };
+    use maps::{world_to_tile, TileMapClient, CHUNK_SIZE};
+    use networking::{messaging::MessageEvent, spawning::NetworkedEntityEvent};
+
+    use super::{ClearDecalsMessage, DecalKind, DecalMessage, DecalSettings};
+
+    /// Height decals are drawn at, just above the floor and below coatings
+    const DECAL_HEIGHT: f32 = 0.005;
+    /// Width and height in pixels of one decal in the atlas
+    const CELL_SIZE: u32 = 64;
+    const ATLAS_CELLS: u32 = 4;
+
+    impl DecalKind {
+        /// Column of the decal in the atlas
+        fn cell(self) -> u32 {
+            match self {
+                DecalKind::Blood => 0,
+                DecalKind::Scorch => 1,
+                DecalKind::Footprints => 2,
+                DecalKind::Dirt => 3,
+            }
+        }
+
+        /// Seconds until the decal has faded away, if it doesn't stay until it's recycled
+        fn lifetime(self) -> Option<f32> {
+            match self {
+                DecalKind::Footprints => Some(150.0),
+                _ => None,
+            }
+        }
+    }
+
+    struct Decal {
+        kind: DecalKind,
+        position: Vec2,
+        rotation: f32,
+        size: f32,
+        tile: UVec2,
+        created: f32,
+    }
+
+    /// Decals in the order they were left, the oldest first.
+    #[derive(Resource, Default)]
+    pub(super) struct Decals {
+        decals: VecDeque<Decal>,
+        /// Chunks whose mesh doesn't match their decals anymore
+        dirty: HashSet<UVec2>,
+        /// Entity drawing the decals of each chunk
+        chunks: HashMap<UVec2, Entity>,
+    }
+
+    impl Decals {
+        fn remove_where(&mut self, mut remove: impl FnMut(&Decal) -> bool) {
+            let dirty = &mut self.dirty;
+            self.decals.retain(|decal| {
+                let removed = remove(decal);
+                if removed {
+                    dirty.insert(chunk(decal.tile));
+                }
+                !removed
+            });
+        }
+
+        /// Drops the oldest decals until there are at most `capacity` left.
+        fn recycle(&mut self, capacity: usize) {
+            while self.decals.len() > capacity {
+                if let Some(oldest) = self.decals.pop_front() {
+                    self.dirty.insert(chunk(oldest.tile));
+                }
+            }
+        }
+    }
+
+    fn chunk(tile: UVec2) -> UVec2 {
+        tile / UVec2::splat(CHUNK_SIZE)
+    }
+
+    fn has_turf(map: &TileMapClient, tile: UVec2) -> bool {
+        map.tile(tile).is_some_and(|t| t.turf.is_some())
+    }
+
+    #[derive(Resource)]
+    pub(super) struct DecalAssets {
+        material: Handle<StandardMaterial>,
+    }
+
+    pub(super) fn setup_decal_assets(
+        mut images: ResMut<Assets<Image>>,
+        mut materials: ResMut<Assets<StandardMaterial>>,
+        mut commands: Commands,
+    ) {
+        let atlas = images.add(decal_atlas());
+        commands.insert_resource(DecalAssets {
+            material: materials.add(StandardMaterial {
+                base_color_texture: Some(atlas),
+                alpha_mode: AlphaMode::Blend,
+                perceptual_roughness: 0.9,
+                ..Default::default()
+            }),
+        });
+    }
+
+    /// Draws every kind of decal next to each other into one texture, so all decals share a material.
+    fn decal_atlas() -> Image {
+        let width = CELL_SIZE * ATLAS_CELLS;
+        let mut data = Vec::with_capacity((width * CELL_SIZE * 4) as usize);
+        for y in 0..CELL_SIZE {
+            for x in 0..width {
+                let cell = x / CELL_SIZE;
+                // -1 to 1 across the cell
+                let point = Vec2::new(
+                    ((x % CELL_SIZE) as f32 + 0.5) / CELL_SIZE as f32 * 2.0 - 1.0,
+                    (y as f32 + 0.5) / CELL_SIZE as f32 * 2.0 - 1.0,
+                );
+                let noise = hash(x, y);
+                let (color, alpha) = match cell {
+                    0 => (Vec3::new(0.45, 0.02, 0.02), blood_splatter(point)),
+                    1 => (Vec3::new(0.08, 0.07, 0.06), scorch_ring(point)),
+                    2 => (Vec3::new(0.45, 0.02, 0.02), footprints(point)),
+                    _ => (
+                        Vec3::new(0.3, 0.27, 0.22),
+                        (1.0 - point.length()).clamp(0.0, 1.0) * noise,
+                    ),
+                };
+                let color = color * (0.85 + 0.3 * noise);
+                data.extend(
+                    [color.x, color.y, color.z, alpha.clamp(0.0, 1.0) * 0.85]
+                        .map(|c| (c.clamp(0.0, 1.0) * 255.0) as u8),
+                );
+            }
+        }
+        Image::new(
+            Extent3d {
+                width,
+                height: CELL_SIZE,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            data,
+            TextureFormat::Rgba8UnormSrgb,
+        )
+    }
+
+    fn hash(x: u32, y: u32) -> f32 {
+        let mut h = x.wrapping_mul(374_761_393) ^ y.wrapping_mul(668_265_263);
+        h = (h ^ (h >> 13)).wrapping_mul(1_274_126_177);
+        (h ^ (h >> 16)) as f32 / u32::MAX as f32
+    }
+
+    fn blood_splatter(point: Vec2) -> f32 {
+        let angle = point.y.atan2(point.x);
+        let radius = 0.5 + 0.12 * (3.0 * angle).sin() + 0.06 * (7.0 * angle + 1.0).sin();
+        let blob = ((radius - point.length()) * 12.0).clamp(0.0, 1.0);
+        let drops = [
+            (Vec2::new(0.7, 0.3), 0.09),
+            (Vec2::new(-0.6, -0.55), 0.07),
+            (Vec2::new(-0.2, 0.78), 0.05),
+        ]
+        .into_iter()
+        .map(|(center, size)| ((size - point.distance(center)) * 40.0).clamp(0.0, 1.0))
+        .fold(0.0, f32::max);
+        blob.max(drops)
+    }
+
+    fn scorch_ring(point: Vec2) -> f32 {
+        let distance = point.length();
+        let ring = (-((distance - 0.6) / 0.2).powi(2)).exp();
+        let inside = if distance < 0.6 { 0.4 } else { 0.0 };
+        ring.max(inside) * (1.0 - distance).clamp(0.0, 1.0).sqrt()
+    }
+
+    /// Two shoe prints, the same as a left and right foot stepping along the y axis.
+    fn footprints(point: Vec2) -> f32 {
+        [Vec2::new(-0.2, -0.2), Vec2::new(0.2, 0.2)]
+            .into_iter()
+            .map(|center| {
+                let offset = (point - center) / Vec2::new(0.12, 0.25);
+                ((1.0 - offset.length()) * 6.0).clamp(0.0, 1.0)
+            })
+            .fold(0.0, f32::max)
+    }
+
+    pub(super) fn receive_decals(
+        mut messages: EventReader<MessageEvent<DecalMessage>>,
+        mut clears: EventReader<MessageEvent<ClearDecalsMessage>>,
+        mut decals: ResMut<Decals>,
+        settings: Res<DecalSettings>,
+        maps: Query<&TileMapClient>,
+        time: Res<Time>,
+    ) {
+        let now = time.elapsed_seconds();
+        let map = maps.get_single().ok();
+        for event in messages.iter() {
+            if settings.capacity == 0 {
+                continue;
+            }
+            let message = event.message;
+            let position = Vec3::new(message.position.x, 0.0, message.position.y);
+            // Nothing to draw on in space
+            let Some(tile) =
+                world_to_tile(position).filter(|&t| map.is_some_and(|m| has_turf(m, t)))
+            else {
+                continue;
+            };
+            decals.recycle(settings.capacity - 1);
+            decals.dirty.insert(chunk(tile));
+            decals.decals.push_back(Decal {
+                kind: message.kind,
+                position: message.position,
+                // Cosmetic, so it doesn't matter that every client sees them turned differently
+                rotation: message
+                    .heading
+                    .unwrap_or_else(|| fastrand::f32() * std::f32::consts::TAU),
+                size: message.size.clamp(0.1, 4.0),
+                tile,
+                created: now,
+            });
+        }
+
+        for event in clears.iter() {
+            match event.message.tile {
+                Some(tile) => decals.remove_where(|decal| decal.tile == tile),
+                None => decals.recycle(0),
+            }
+        }
+
+        if settings.is_changed() {
+            decals.recycle(settings.capacity);
+        }
+    }
+
+    /// Drops decals on turfs that were destroyed or left the area the server sends.
+    pub(super) fn drop_decals_without_turf(
+        mut events: EventReader<NetworkedEntityEvent>,
+        mut decals: ResMut<Decals>,
+        maps: Query<&TileMapClient>,
+    ) {
+        let despawned = events
+            .iter()
+            .filter(|e| matches!(e, NetworkedEntityEvent::Despawned(_)))
+            .count();
+        if despawned == 0 {
+            return;
+        }
+        match maps.get_single() {
+            Ok(map) => decals.remove_where(|decal| !has_turf(map, decal.tile)),
+            Err(_) => decals.recycle(0),
+        }
+    }
+
+    /// Fades out decals that don't last, and removes them once they're gone.
+    pub(super) fn expire_decals(mut decals: ResMut<Decals>, time: Res<Time>) {
+        let now = time.elapsed_seconds();
+        let decals = decals.as_mut();
+        decals.remove_where(|decal| {
+            decal
+                .kind
+                .lifetime()
+                .is_some_and(|lifetime| now - decal.created >= lifetime)
+        });
+        for decal in decals.decals.iter() {
+            if decal.kind.lifetime().is_some() {
+                decals.dirty.insert(chunk(decal.tile));
+            }
+        }
+    }
+
+    /// Replaces the mesh of every chunk whose decals changed, so each chunk is drawn at once.
+    pub(super) fn rebuild_chunk_meshes(
+        mut decals: ResMut<Decals>,
+        assets: Res<DecalAssets>,
+        time: Res<Time>,
+        mut meshes: ResMut<Assets<Mesh>>,
+        mut commands: Commands,
+    ) {
+        if decals.dirty.is_empty() {
+            return;
+        }
+        let now = time.elapsed_seconds();
+        let decals = decals.as_mut();
+        for dirty in decals.dirty.drain() {
+            // Spawned again instead of swapping the mesh, so its bounds are calculated again
+            if let Some(entity) = decals.chunks.remove(&dirty) {
+                commands.entity(entity).despawn();
+            }
+            let Some(mesh) =
+                chunk_mesh(decals.decals.iter().filter(|d| chunk(d.tile) == dirty), now)
+            else {
+                continue;
+            };
+            let entity = commands
+                .spawn(PbrBundle {
+                    mesh: meshes.add(mesh),
+                    material: assets.material.clone(),
+                    ..Default::default()
+                })
+                .id();
+            decals.chunks.insert(dirty, entity);
+        }
+    }
+
+    /// One quad for every decal, newer decals on top of older ones.
+    fn chunk_mesh<'a>(decals: impl Iterator<Item = &'a Decal>, now: f32) -> Option<Mesh> {
+        let mut positions = Vec::new();
+        let mut uvs = Vec::new();
+        let mut colors = Vec::new();
+        let mut indices = Vec::new();
+        for (layer, decal) in decals.enumerate() {
+            let alpha = decal.kind.lifetime().map_or(1.0, |lifetime| {
+                (1.0 - (now - decal.created) / lifetime).clamp(0.0, 1.0)
+            });
+            let rotation = Mat2::from_angle(decal.rotation);
+            let height = DECAL_HEIGHT + layer as f32 * 0.00001;
+            let cell = decal.kind.cell() as f32;
+            let start = positions.len() as u32;
+            for corner in [
+                Vec2::new(-0.5, -0.5),
+                Vec2::new(0.5, -0.5),
+                Vec2::new(0.5, 0.5),
+                Vec2::new(-0.5, 0.5),
+            ] {
+                let offset = rotation * (corner * decal.size);
+                positions.push([
+                    decal.position.x + offset.x,
+                    height,
+                    decal.position.y + offset.y,
+                ]);
+                uvs.push([(cell + corner.x + 0.5) / ATLAS_CELLS as f32, corner.y + 0.5]);
+                colors.push([1.0, 1.0, 1.0, alpha]);
+            }
+            indices.extend([start, start + 2, start + 1, start, start + 3, start + 2]);
+        }
+        if positions.is_empty() {
+            return None;
+        }
+
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.insert_attribute(
+            Mesh::ATTRIBUTE_NORMAL,
+            vec![[0.0, 1.0, 0.0]; positions.len()],
+        );
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+        mesh.set_indices(Some(Indices::U32(indices)));
+        Some(mesh)
+    }
+}
@@ -0,0 +1,492 @@
+use std::collections::VecDeque;
+
+use bevy::{
+    math::Vec3Swizzles,
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+use maps::{world_to_tile, TileEntity, TileLayer};
+use networking::{is_server, ConnectionId};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    atmos::pipes::{PipeDevice, PipeDeviceState},
+    body::Body,
+    console::{
+        ArgumentKind, CommandContext, CommandResult, CommandSource, ConsoleAppExt, ConsoleCommand,
+        PermissionLevel,
+    },
+    door::{door_obstructed, Door, DoorState},
+    interaction::{
+        ActiveInteraction, GenerateInteractionList, InteractionListEvents, InteractionOption,
+        InteractionSpecificity, InteractionStatus, Reach,
+    },
+    lights::{LightBehavior, LightFixture, LightState},
+};
+
+/// Lets map objects send signals to each other over named channels, like a button opening a door.
+pub struct DeviceLinkPlugin;
+
+impl Plugin for DeviceLinkPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<DeviceLink>()
+            .register_type::<SignalKind>()
+            .register_type::<SignalButton>()
+            .register_type::<SignalTrigger>()
+            .register_type::<SignalRelay>();
+
+        if is_server(app) {
+            app.init_resource::<LinkRegistry>()
+                .init_resource::<LinkingTool>()
+                .init_resource::<PendingTileLinks>()
+                .add_event::<DeviceSignal>()
+                .add_event::<ReceivedSignal>()
+                .register_type::<PressButtonInteraction>()
+                .add_console_command(ConsoleCommand {
+                    name: "link",
+                    description: "Run on a source and then on a target to link the target to the source's channel",
+                    parameters: &[],
+                    permission: PermissionLevel::Admin,
+                    handler: link_command,
+                })
+                .add_console_command(ConsoleCommand {
+                    name: "channel",
+                    description: "Sets the signal channel of the object at your cursor",
+                    parameters: &[("channel", ArgumentKind::Text)],
+                    permission: PermissionLevel::Admin,
+                    handler: channel_command,
+                })
+                .add_console_command(ConsoleCommand {
+                    name: "unlink",
+                    description: "Removes the object at your cursor from its signal channel",
+                    parameters: &[],
+                    permission: PermissionLevel::Admin,
+                    handler: unlink_command,
+                })
+                .add_systems(
+                    Update,
+                    (
+                        apply_pending_tile_links,
+                        update_registry,
+                        prepare_button_interaction.in_set(GenerateInteractionList),
+                        (
+                            press_button_interaction,
+                            walkover_triggers,
+                            propagate_signals,
+                            (doors_receive, lights_receive, pipe_devices_receive),
+                        )
+                            .chain()
+                            .after(update_registry),
+                    ),
+                );
+        }
+    }
+}
+
+/// Most relays a signal can pass through. Stops relays that are linked in a loop.
+const MAX_SIGNAL_DEPTH: u8 = 8;
+/// Most signals delivered in a frame, in case a loop branches out faster than the depth cap stops it
+const MAX_DELIVERIES_PER_TICK: usize = 1024;
+/// How far from the cursor the linking commands look for objects, in meters
+const PICK_DISTANCE: f32 = 0.75;
+
+/// Connects an object to a signal channel.
+/// Sources send signals on their channel, and receivers react to every signal sent on theirs.
+#[derive(Component, Reflect, Default, Clone)]
+#[reflect(Component)]
+pub struct DeviceLink {
+    pub channel: String,
+}
+
+#[derive(Reflect, Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SignalKind {
+    #[default]
+    Toggle,
+    On,
+    Off,
+    /// A short activation, like a button press. Receivers without one treat it like a toggle.
+    Pulse,
+}
+
+impl std::fmt::Display for SignalKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            SignalKind::Toggle => "toggle",
+            SignalKind::On => "on",
+            SignalKind::Off => "off",
+            SignalKind::Pulse => "pulse",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl SignalKind {
+    /// The state a receiver ends up in, from the state it is in now.
+    fn apply(self, on: bool) -> bool {
+        match self {
+            SignalKind::On => true,
+            SignalKind::Off => false,
+            SignalKind::Toggle | SignalKind::Pulse => !on,
+        }
+    }
+}
+
+/// Sent by signal sources to everything linked to a channel.
+#[derive(Event, Clone, Debug)]
+pub struct DeviceSignal {
+    pub channel: String,
+    pub kind: SignalKind,
+}
+
+/// A signal arriving at a receiver, after it went through any relays.
+#[derive(Event)]
+struct ReceivedSignal {
+    receiver: Entity,
+    kind: SignalKind,
+}
+
+/// Sends a signal on its channel when pressed.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct SignalButton {
+    pub kind: SignalKind,
+}
+
+/// Sends a signal on its channel when a creature steps onto its tile.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct SignalTrigger {
+    pub kind: SignalKind,
+}
+
+/// Passes signals arriving on its channel on to another channel.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct SignalRelay {
+    pub output: String,
+    /// Replaces the kind of relayed signals, if set
+    pub kind: Option<SignalKind>,
+}
+
+/// Every linked object by channel.
+#[derive(Resource, Default)]
+pub struct LinkRegistry {
+    channels: HashMap<String, HashSet<Entity>>,
+    /// Channel of every linked object, to find them again when they are removed
+    linked: HashMap<Entity, String>,
+}
+
+impl LinkRegistry {
+    pub fn linked(&self, channel: &str) -> impl Iterator<Item = Entity> + '_ {
+        self.channels.get(channel).into_iter().flatten().copied()
+    }
+
+    fn insert(&mut self, entity: Entity, channel: &str) {
+        self.remove(entity);
+        self.channels.entry_ref(channel).or_default().insert(entity);
+        self.linked.insert(entity, channel.to_owned());
+    }
+
+    fn remove(&mut self, entity: Entity) {
+        let Some(channel) = self.linked.remove(&entity) else {
+            return;
+        };
+        if let Some(entities) = self.channels.get_mut(&channel) {
+            entities.remove(&entity);
+            if entities.is_empty() {
+                self.channels.remove(&channel);
+            }
+        }
+    }
+}
+
+/// Channels for tile objects that haven't spawned yet, from an imported map or an autosave.
+#[derive(Resource, Default)]
+pub struct PendingTileLinks(pub HashMap<(UVec2, TileLayer), String>);
+
+fn apply_pending_tile_links(
+    mut pending: ResMut<PendingTileLinks>,
+    all_tiles: Query<(Entity, &TileEntity)>,
+    new_tiles: Query<(Entity, &TileEntity), Added<TileEntity>>,
+    mut commands: Commands,
+) {
+    if pending.0.is_empty() {
+        return;
+    }
+    // Objects that already exist only have to be checked when new links are added
+    let tiles: Vec<_> = if pending.is_changed() {
+        all_tiles.iter().collect()
+    } else {
+        new_tiles.iter().collect()
+    };
+    let pending = pending.bypass_change_detection();
+    for (entity, tile) in tiles {
+        if let Some(channel) = pending.0.remove(&(tile.position(), tile.layer())) {
+            commands.entity(entity).insert(DeviceLink { channel });
+        }
+    }
+}
+
+fn update_registry(
+    links: Query<(Entity, &DeviceLink), Changed<DeviceLink>>,
+    mut removed: RemovedComponents<DeviceLink>,
+    mut registry: ResMut<LinkRegistry>,
+) {
+    for entity in removed.iter() {
+        registry.remove(entity);
+    }
+    for (entity, link) in links.iter() {
+        if link.channel.is_empty() {
+            registry.remove(entity);
+        } else {
+            registry.insert(entity, &link.channel);
+        }
+    }
+}
+
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+#[component(storage = "SparseSet")]
+struct PressButtonInteraction;
+
+fn prepare_button_interaction(
+    list: Res<InteractionListEvents>,
+    buttons: Query<(), (With<SignalButton>, With<DeviceLink>)>,
+    reach: Reach,
+) {
+    for event in list.events.iter() {
+        if !buttons.contains(event.target) || !reach.can_reach(event.source, event.target) {
+            continue;
+        }
+        event.add_restricted_interaction(InteractionOption {
+            text: "Press".into(),
+            interaction: Box::<PressButtonInteraction>::default(),
+            specificity: InteractionSpecificity::Specific,
+        });
+    }
+}
+
+fn press_button_interaction(
+    mut query: Query<&mut ActiveInteraction, With<PressButtonInteraction>>,
+    buttons: Query<(&SignalButton, &DeviceLink)>,
+    mut signals: EventWriter<DeviceSignal>,
+) {
+    for mut active in query.iter_mut() {
+        let Ok((button, link)) = buttons.get(active.target) else {
+            active.status = InteractionStatus::Canceled;
+            continue;
+        };
+        signals.send(DeviceSignal {
+            channel: link.channel.clone(),
+            kind: button.kind,
+        });
+        active.status = InteractionStatus::Completed;
+    }
+}
+
+fn walkover_triggers(
+    bodies: Query<(Entity, &GlobalTransform), (With<Body>, Changed<GlobalTransform>)>,
+    triggers: Query<(&SignalTrigger, &DeviceLink, &GlobalTransform)>,
+    mut last_tiles: Local<HashMap<Entity, UVec2>>,
+    mut signals: EventWriter<DeviceSignal>,
+) {
+    if triggers.is_empty() {
+        return;
+    }
+    for (body, transform) in bodies.iter() {
+        let Some(tile) = world_to_tile(transform.translation()) else {
+            continue;
+        };
+        if last_tiles.insert(body, tile) == Some(tile) {
+            continue;
+        }
+        for (trigger, link, trigger_transform) in triggers.iter() {
+            if world_to_tile(trigger_transform.translation()) == Some(tile) {
+                signals.send(DeviceSignal {
+                    channel: link.channel.clone(),
+                    kind: trigger.kind,
+                });
+            }
+        }
+    }
+}
+
+/// Delivers signals to their receivers, passing them through relays within the same frame.
+fn propagate_signals(
+    mut signals: EventReader<DeviceSignal>,
+    registry: Res<LinkRegistry>,
+    relays: Query<&SignalRelay>,
+    mut received: EventWriter<ReceivedSignal>,
+) {
+    let mut queue: VecDeque<(String, SignalKind, u8)> = signals
+        .iter()
+        .map(|signal| (signal.channel.clone(), signal.kind, 0))
+        .collect();
+    let mut delivered = 0;
+    while let Some((channel, kind, depth)) = queue.pop_front() {
+        for entity in registry.linked(&channel) {
+            let Ok(relay) = relays.get(entity) else {
+                received.send(ReceivedSignal {
+                    receiver: entity,
+                    kind,
+                });
+                delivered += 1;
+                continue;
+            };
+            if depth >= MAX_SIGNAL_DEPTH {
+                warn!(
+                    channel = channel.as_str(),
+                    output = relay.output.as_str(),
+                    "Dropped signal that went through too many relays, they may be linked in a loop"
+                );
+                continue;
+            }
+            queue.push_back((relay.output.clone(), relay.kind.unwrap_or(kind), depth + 1));
+        }
+        if delivered >= MAX_DELIVERIES_PER_TICK {
+            warn!(
+                remaining = queue.len(),
+                "Dropped signals after too many deliveries in one frame"
+            );
+            break;
+        }
+    }
+}
+
+fn doors_receive(
+    mut signals: EventReader<ReceivedSignal>,
+    mut doors: Query<(&mut DoorState, &GlobalTransform), With<Door>>,
+    creatures: Query<&GlobalTransform, With<Body>>,
+) {
+    for signal in signals.iter() {
+        let Ok((mut state, transform)) = doors.get_mut(signal.receiver) else {
+            continue;
+        };
+        if !state.can_move() {
+            continue;
+        }
+        let open = signal.kind.apply(state.is_open());
+        if !open && door_obstructed(transform.translation(), &state, &creatures) {
+            continue;
+        }
+        state.set_open(open);
+    }
+}
+
+fn lights_receive(
+    mut signals: EventReader<ReceivedSignal>,
+    mut lights: Query<(&mut LightState, &LightFixture)>,
+) {
+    for signal in signals.iter() {
+        let Ok((mut state, fixture)) = lights.get_mut(signal.receiver) else {
+            continue;
+        };
+        let on = signal.kind.apply(state.behavior() != LightBehavior::Off);
+        // Lights that are turned back on go back to what they did when they were spawned
+        state.set_behavior(if on {
+            fixture.behavior
+        } else {
+            LightBehavior::Off
+        });
+    }
+}
+
+fn pipe_devices_receive(
+    mut signals: EventReader<ReceivedSignal>,
+    mut devices: Query<&mut PipeDeviceState, With<PipeDevice>>,
+) {
+    for signal in signals.iter() {
+        if let Ok(mut state) = devices.get_mut(signal.receiver) {
+            let on = signal.kind.apply(state.is_on());
+            state.set_on(on);
+        }
+    }
+}
+
+/// Sources admins picked with the `link` command, waiting for a target.
+#[derive(Resource, Default)]
+struct LinkingTool(HashMap<ConnectionId, Entity>);
+
+/// The linkable object closest to the player's cursor.
+fn object_at_cursor(world: &mut World, context: &CommandContext) -> Result<Entity, String> {
+    let Some(cursor) = context.cursor else {
+        return Err("Your cursor isn't over the map".into());
+    };
+    let mut query = world.query_filtered::<(Entity, &GlobalTransform), Or<(
+        With<DeviceLink>,
+        With<SignalButton>,
+        With<SignalTrigger>,
+        With<SignalRelay>,
+        With<Door>,
+        With<LightFixture>,
+        With<PipeDevice>,
+    )>>();
+    query
+        .iter(world)
+        .map(|(entity, transform)| {
+            let distance = transform.translation().xz().distance(cursor.xz());
+            (entity, distance)
+        })
+        .filter(|(_, distance)| *distance <= PICK_DISTANCE)
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(entity, _)| entity)
+        .ok_or_else(|| "There is nothing to link at your cursor".into())
+}
+
+fn link_command(world: &mut World, context: &CommandContext) -> CommandResult {
+    let CommandSource::Player(connection) = context.source else {
+        return Err("Objects can only be picked by players".into());
+    };
+    let target = object_at_cursor(world, context)?;
+
+    let picked = world.resource_mut::<LinkingTool>().0.remove(&connection);
+    let Some(source) = picked else {
+        world
+            .resource_mut::<LinkingTool>()
+            .0
+            .insert(connection, target);
+        return Ok("Source picked, run /link again on the target".into());
+    };
+    if world.get_entity(source).is_none() {
+        return Err("The source no longer exists, run /link on a new one".into());
+    }
+
+    // Sources without a channel get a new one
+    let channel = match world.get::<DeviceLink>(source) {
+        Some(link) if !link.channel.is_empty() => link.channel.clone(),
+        _ => {
+            let channel = format!("link-{}", source.index());
+            world.entity_mut(source).insert(DeviceLink {
+                channel: channel.clone(),
+            });
+            channel
+        }
+    };
+    world.entity_mut(target).insert(DeviceLink {
+        channel: channel.clone(),
+    });
+    info!(
+        ?source,
+        ?target,
+        channel = channel.as_str(),
+        "Linked devices"
+    );
+    Ok(format!("Linked to channel {}", channel))
+}
+
+fn channel_command(world: &mut World, context: &CommandContext) -> CommandResult {
+    let target = object_at_cursor(world, context)?;
+    let channel = context.text(0).trim().to_owned();
+    world.entity_mut(target).insert(DeviceLink {
+        channel: channel.clone(),
+    });
+    Ok(format!("Set channel to {}", channel))
+}
+
+fn unlink_command(world: &mut World, context: &CommandContext) -> CommandResult {
+    let target = object_at_cursor(world, context)?;
+    if world.entity_mut(target).take::<DeviceLink>().is_none() {
+        return Err("The object isn't linked".into());
+    }
+    Ok("Unlinked".into())
+}
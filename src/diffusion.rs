@@ -0,0 +1,223 @@
+use std::marker::PhantomData;
+
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+use maps::{tile_neighbours, world_to_tile, TileMap, CHUNK_SIZE};
+
+use crate::{
+    door::{Door, DoorState},
+    temperature::Airtight,
+};
+
+/// Seconds between simulation steps
+pub const SIMULATION_INTERVAL: f32 = 0.25;
+/// Fraction of the difference to a neighbour exchanged per simulation step
+const DIFFUSION_RATE: f32 = 0.2;
+/// Tiles changing less than this per step stop being simulated
+const ACTIVE_THRESHOLD: f32 = 0.01;
+
+/// Something that spreads between open tiles, like heat or air.
+/// Each one has its own [`DiffusionGrid`], simulated by the systems in this module.
+pub trait Diffusing: Send + Sync + 'static {
+    /// Value of tiles on the station when a map is loaded
+    const INITIAL: f32;
+    /// Value of space tiles, which never changes
+    const SPACE: f32;
+}
+
+/// Value of every tile on the map.
+/// Only tiles that are still changing are simulated.
+// TODO: Support multiple maps
+#[derive(Resource)]
+pub struct DiffusionGrid<T: Diffusing> {
+    /// Size in tiles
+    size: UVec2,
+    values: Vec<f32>,
+    active: HashSet<UVec2>,
+    last_step: f32,
+    quantity: PhantomData<T>,
+}
+
+impl<T: Diffusing> Default for DiffusionGrid<T> {
+    fn default() -> Self {
+        Self {
+            size: UVec2::ZERO,
+            values: Vec::new(),
+            active: HashSet::default(),
+            last_step: 0.0,
+            quantity: PhantomData,
+        }
+    }
+}
+
+impl<T: Diffusing> DiffusionGrid<T> {
+    fn index(&self, position: UVec2) -> Option<usize> {
+        (position.x < self.size.x && position.y < self.size.y)
+            .then(|| (position.y * self.size.x + position.x) as usize)
+    }
+
+    /// If the tile is on the map
+    pub fn contains(&self, position: UVec2) -> bool {
+        self.index(position).is_some()
+    }
+
+    /// Value of a tile, tiles outside the map are space.
+    pub fn get(&self, position: UVec2) -> f32 {
+        self.index(position)
+            .map(|i| self.values[i])
+            .unwrap_or(T::SPACE)
+    }
+
+    pub(crate) fn set(&mut self, position: UVec2, value: f32) {
+        if let Some(index) = self.index(position) {
+            self.values[index] = value;
+        }
+    }
+
+    /// Makes a tile and its neighbours simulate again, for example after a wall was removed.
+    pub fn disturb(&mut self, position: UVec2) {
+        self.active.insert(position);
+        self.active
+            .extend(tile_neighbours(position).map(|(_, neighbour)| neighbour));
+    }
+
+    /// Fills the grid for a newly loaded map.
+    fn reset(&mut self, map: &TileMap) {
+        let size = map.size() * CHUNK_SIZE;
+        let tiles = (0..size.x).flat_map(|x| (0..size.y).map(move |y| UVec2::new(x, y)));
+        let is_space = |p: UVec2| map.tile(p).map_or(true, |t| t.turf.is_none());
+        self.size = size;
+        self.values = vec![T::INITIAL; (size.x * size.y) as usize];
+        for position in tiles.clone().filter(|&p| is_space(p)) {
+            self.set(position, T::SPACE);
+        }
+        // Everything next to space has to settle
+        self.active = tiles
+            .filter(|&p| is_space(p))
+            .flat_map(|p| tile_neighbours(p).map(|(_, n)| n))
+            .collect();
+    }
+
+    /// Exchanges values between active tiles and their open neighbours.
+    fn step(
+        &mut self,
+        map: &TileMap,
+        airtight: &Query<(), With<Airtight>>,
+        doors: &Query<&DoorState, With<Door>>,
+    ) {
+        let active = std::mem::take(&mut self.active);
+        let mut changes = Vec::with_capacity(active.len());
+        for &position in active.iter() {
+            if tile_kind(map, position, airtight, doors) != TileKind::Open {
+                continue;
+            }
+
+            let value = self.get(position);
+            let mut change = 0.0;
+            for (_, neighbour) in tile_neighbours(position) {
+                let neighbour_value = match tile_kind(map, neighbour, airtight, doors) {
+                    TileKind::Blocked => continue,
+                    TileKind::Space => T::SPACE,
+                    TileKind::Open => self.get(neighbour),
+                };
+                change += (neighbour_value - value) * DIFFUSION_RATE / 4.0;
+            }
+            changes.push((position, value + change, change.abs() > ACTIVE_THRESHOLD));
+        }
+
+        // Applied afterwards, so the result doesn't depend on the order tiles are visited in
+        for (position, value, still_changing) in changes {
+            self.set(position, value);
+            if still_changing {
+                self.disturb(position);
+            }
+        }
+    }
+}
+
+/// How a tile takes part in the simulation.
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub(crate) enum TileKind {
+    Space,
+    Blocked,
+    Open,
+}
+
+pub(crate) fn tile_kind(
+    map: &TileMap,
+    position: UVec2,
+    airtight: &Query<(), With<Airtight>>,
+    doors: &Query<&DoorState, With<Door>>,
+) -> TileKind {
+    let Some(tile) = map.tile(position) else {
+        return TileKind::Space;
+    };
+    let Some(turf) = tile.turf else {
+        return TileKind::Space;
+    };
+    let closed_door = tile
+        .furniture
+        .and_then(|f| doors.get(f).ok())
+        .is_some_and(|door| !door.is_open());
+    if airtight.contains(turf) || closed_door {
+        TileKind::Blocked
+    } else {
+        TileKind::Open
+    }
+}
+
+pub(crate) fn resize_grid<T: Diffusing>(
+    maps: Query<&TileMap, Added<TileMap>>,
+    mut grid: ResMut<DiffusionGrid<T>>,
+) {
+    if let Ok(map) = maps.get_single() {
+        grid.reset(map);
+    }
+}
+
+/// Wakes up tiles around doors that opened or closed and airtight objects that were built or removed.
+pub(crate) fn disturb_changed_tiles<T: Diffusing>(
+    doors: Query<&GlobalTransform, Changed<DoorState>>,
+    // Transforms only get their final position after propagation, so this also catches new objects
+    moved: Query<(Entity, &GlobalTransform), (With<Airtight>, Changed<GlobalTransform>)>,
+    mut removed: RemovedComponents<Airtight>,
+    mut airtight_positions: Local<HashMap<Entity, UVec2>>,
+    mut grid: ResMut<DiffusionGrid<T>>,
+) {
+    for transform in doors.iter() {
+        if let Some(position) = world_to_tile(transform.translation()) {
+            grid.disturb(position);
+        }
+    }
+    for (entity, transform) in moved.iter() {
+        if let Some(position) = world_to_tile(transform.translation()) {
+            airtight_positions.insert(entity, position);
+            grid.disturb(position);
+        }
+    }
+    for entity in removed.iter() {
+        if let Some(position) = airtight_positions.remove(&entity) {
+            grid.disturb(position);
+        }
+    }
+}
+
+pub(crate) fn diffuse<T: Diffusing>(
+    mut grid: ResMut<DiffusionGrid<T>>,
+    maps: Query<&TileMap>,
+    airtight: Query<(), With<Airtight>>,
+    doors: Query<&DoorState, With<Door>>,
+    time: Res<Time>,
+) {
+    let now = time.elapsed_seconds();
+    if now - grid.last_step < SIMULATION_INTERVAL {
+        return;
+    }
+    grid.last_step = now;
+    let Ok(map) = maps.get_single() else {
+        return;
+    };
+    grid.step(map, &airtight, &doors);
+}
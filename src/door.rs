@@ -0,0 +1,302 @@
+use bevy::{math::Vec3Swizzles, prelude::*, reflect::TypeUuid};
+use bevy_rapier3d::prelude::CollisionGroups;
+use networking::{
+    component::AppExt,
+    is_server,
+    variable::{NetworkVar, ServerVar},
+    Networked,
+};
+use physics::ColliderGroup;
+
+use crate::{
+    body::{self_or_ancestor, Body},
+    combat::damage::{AffectedEntity, Attack, KineticDamage},
+    interaction::{
+        ActiveInteraction, GenerateInteractionList, InteractionAppExt, InteractionListEvents,
+        InteractionOption, InteractionSpecificity, InteractionStatus,
+    },
+};
+
+use self::wires::WirePlugin;
+
+pub mod wires;
+
+pub struct DoorPlugin;
+
+impl Plugin for DoorPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Door>()
+            .register_type::<DoorCollider>()
+            .register_queueable_interaction::<DoorInteraction>()
+            .add_networked_component::<DoorState, DoorStateClient>()
+            .add_plugins(WirePlugin);
+
+        if is_server(app) {
+            app.add_systems(
+                Update,
+                (
+                    initialize_doors,
+                    prepare_door_interaction.in_set(GenerateInteractionList),
+                    execute_door_interaction,
+                    damage_doors,
+                    update_door_colliders::<DoorState>,
+                ),
+            );
+        } else {
+            app.add_systems(
+                Update,
+                (
+                    update_door_colliders::<DoorStateClient>,
+                    update_door_visibility,
+                ),
+            );
+        }
+    }
+}
+
+/// Marks an object as a door. The state is kept in [`DoorState`].
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct Door;
+
+/// Marks the collider that blocks movement when a door is closed.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct DoorCollider;
+
+#[derive(Component, Networked)]
+#[networked(client = "DoorStateClient")]
+pub struct DoorState {
+    open: NetworkVar<bool>,
+    bolted: NetworkVar<bool>,
+    /// If the door has power to move
+    pub powered: bool,
+    /// If the area the door is in has power, controlled by its APC
+    pub area_powered: bool,
+    /// If the door checks for obstacles before closing
+    pub safety: bool,
+    /// Joules of damage the door takes before it breaks open
+    pub integrity: f32,
+}
+
+impl Default for DoorState {
+    fn default() -> Self {
+        Self {
+            open: false.into(),
+            bolted: false.into(),
+            powered: true,
+            area_powered: true,
+            safety: true,
+            integrity: 3000.0,
+        }
+    }
+}
+
+impl DoorState {
+    pub fn is_open(&self) -> bool {
+        *self.open
+    }
+
+    pub fn set_open(&mut self, open: bool) {
+        if *self.open != open {
+            *self.open = open;
+        }
+    }
+
+    pub fn is_bolted(&self) -> bool {
+        *self.bolted
+    }
+
+    pub fn set_bolted(&mut self, bolted: bool) {
+        if *self.bolted != bolted {
+            *self.bolted = bolted;
+        }
+    }
+
+    /// If the door is able to open or close by itself.
+    pub fn can_move(&self) -> bool {
+        self.powered && self.area_powered && !self.is_bolted() && !self.is_broken()
+    }
+
+    /// If attacks broke the door, which leaves it stuck open
+    pub fn is_broken(&self) -> bool {
+        self.integrity <= 0.0
+    }
+}
+
+#[derive(Component, Networked, TypeUuid, Default)]
+#[uuid = "3c9d6bb2-8a0e-4a66-bb24-7b5f0c7c27a1"]
+#[networked(server = "DoorState")]
+pub struct DoorStateClient {
+    open: ServerVar<bool>,
+    bolted: ServerVar<bool>,
+}
+
+impl DoorStateClient {
+    pub fn is_open(&self) -> bool {
+        *self.open
+    }
+}
+
+/// Common access to the open state on server and client
+pub(crate) trait DoorOpen: Component {
+    fn open(&self) -> bool;
+}
+
+impl DoorOpen for DoorState {
+    fn open(&self) -> bool {
+        self.is_open()
+    }
+}
+
+impl DoorOpen for DoorStateClient {
+    fn open(&self) -> bool {
+        *self.open
+    }
+}
+
+fn initialize_doors(new_doors: Query<Entity, Added<Door>>, mut commands: Commands) {
+    for entity in new_doors.iter() {
+        commands.entity(entity).insert(DoorState::default());
+    }
+}
+
+/// Lets creatures walk through open doors, while still allowing them to be clicked
+fn update_door_colliders<T: DoorOpen>(
+    doors: Query<(Entity, &T), Changed<T>>,
+    children: Query<&Children>,
+    colliders: Query<(), With<DoorCollider>>,
+    mut commands: Commands,
+) {
+    for (entity, state) in doors.iter() {
+        let group = if state.open() {
+            ColliderGroup::Passable
+        } else {
+            ColliderGroup::Default
+        };
+
+        for child in children.iter_descendants(entity) {
+            if colliders.contains(child) {
+                commands.entity(child).insert(CollisionGroups::from(group));
+            }
+        }
+    }
+}
+
+fn update_door_visibility(
+    mut doors: Query<(&DoorStateClient, &mut Visibility), Changed<DoorStateClient>>,
+) {
+    for (state, mut visibility) in doors.iter_mut() {
+        // TODO: Animate door opening
+        *visibility = if state.open() {
+            Visibility::Hidden
+        } else {
+            Visibility::Inherited
+        };
+    }
+}
+
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+#[component(storage = "SparseSet")]
+struct DoorInteraction {
+    target: Entity,
+}
+
+// Dummy default for Reflect
+impl Default for DoorInteraction {
+    fn default() -> Self {
+        Self {
+            target: Entity::from_raw(0),
+        }
+    }
+}
+
+fn prepare_door_interaction(list: Res<InteractionListEvents>, doors: Query<&DoorState>) {
+    for event in list.events.iter() {
+        let Ok(state) = doors.get(event.target) else {
+            continue;
+        };
+
+        if !state.can_move() {
+            continue;
+        }
+
+        event.add_restricted_interaction(InteractionOption {
+            text: if state.is_open() { "Close" } else { "Open" }.into(),
+            interaction: Box::new(DoorInteraction {
+                target: event.target,
+            }),
+            specificity: InteractionSpecificity::Common,
+        });
+    }
+}
+
+/// How close a creature can be to a door before it refuses to close
+const DOOR_OBSTACLE_DISTANCE: f32 = 0.75;
+
+/// Checks if a door is able to close without crushing anything.
+/// Doors with cut safety wires will close anyway.
+pub fn door_obstructed(
+    door_position: Vec3,
+    state: &DoorState,
+    creatures: &Query<&GlobalTransform, With<Body>>,
+) -> bool {
+    state.safety
+        && creatures
+            .iter()
+            .any(|t| t.translation().xz().distance(door_position.xz()) < DOOR_OBSTACLE_DISTANCE)
+}
+
+fn execute_door_interaction(
+    mut query: Query<(&DoorInteraction, &mut ActiveInteraction)>,
+    mut doors: Query<(&mut DoorState, &GlobalTransform)>,
+    creatures: Query<&GlobalTransform, With<Body>>,
+) {
+    for (interaction, mut active) in query.iter_mut() {
+        let Ok((mut state, transform)) = doors.get_mut(interaction.target) else {
+            active.status = InteractionStatus::Canceled;
+            continue;
+        };
+
+        if !state.can_move() {
+            active.status = InteractionStatus::Canceled;
+            continue;
+        }
+
+        let open = !state.is_open();
+        if !open && door_obstructed(transform.translation(), &state, &creatures) {
+            active.status = InteractionStatus::Canceled;
+            continue;
+        }
+
+        state.set_open(open);
+        active.status = InteractionStatus::Completed;
+    }
+}
+
+/// Attacks wear doors down until they break, which leaves them open for good.
+fn damage_doors(
+    attacks: Query<(Entity, &AffectedEntity, &KineticDamage), Added<Attack>>,
+    parents: Query<&Parent>,
+    mut doors: Query<&mut DoorState>,
+    mut commands: Commands,
+) {
+    for (attack, affected, kinetic) in attacks.iter() {
+        // Attacks hit the collider, which is a child of the door
+        let Some(door) = self_or_ancestor(&parents, affected.0, |e| doors.contains(e)) else {
+            continue;
+        };
+        commands.entity(attack).despawn();
+
+        let mut state = doors.get_mut(door).unwrap();
+        if state.is_broken() {
+            continue;
+        }
+        state.integrity -= 0.5 * kinetic.mass * kinetic.velocity.powi(2) * kinetic.scale;
+        if state.is_broken() {
+            state.set_open(true);
+            debug!(door = ?door, "Door broken open");
+        }
+    }
+}
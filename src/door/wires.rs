@@ -0,0 +1,695 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+use networking::{
+    identity::{NetworkIdentities, NetworkIdentity},
+    is_server,
+    messaging::{AppExt, MessageEvent, MessageReceivers, MessageSender},
+    spawning::ClientControls,
+    ConnectionId, Players,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    body::{Body, HeldItem},
+    construction::{Multitool, Screwdriver, Wirecutters},
+    effects::{EffectKind, EffectSender},
+    interaction::{
+        ActiveInteraction, GenerateInteractionList, InteractionListEvents, InteractionOption,
+        InteractionSpecificity, InteractionStatus,
+    },
+    rng::GameRng,
+};
+
+#[cfg(feature = "client")]
+use {
+    crate::{
+        accessibility::{AccessibilitySettings, Palette},
+        ui::has_window,
+        GameState,
+    },
+    bevy_egui::{egui, EguiContexts},
+};
+
+use super::{door_obstructed, Door, DoorState};
+
+pub(super) struct WirePlugin;
+
+impl Plugin for WirePlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<TogglePanelInteraction>()
+            .register_type::<InspectWiresInteraction>()
+            .add_network_message::<WirePanelMessage>("WirePanelMessage")
+            .add_network_message::<WireActionRequest>("WireActionRequest");
+
+        if is_server(app) {
+            app.init_resource::<PendingWireActions>().add_systems(
+                Update,
+                (
+                    add_wire_panels,
+                    prepare_panel_interactions.in_set(GenerateInteractionList),
+                    execute_toggle_panel_interaction,
+                    execute_inspect_wires_interaction,
+                    handle_wire_action_requests,
+                    process_wire_actions,
+                ),
+            );
+        } else {
+            #[cfg(feature = "client")]
+            app.init_resource::<ClientWirePanel>().add_systems(
+                Update,
+                (
+                    client_receive_wire_panel,
+                    client_wire_panel_ui
+                        .run_if(in_state(GameState::Game))
+                        .run_if(has_window),
+                ),
+            );
+        }
+    }
+}
+
+/// What a wire in a door does.
+/// This is never sent to clients, players have to find out by experimenting.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum WireRole {
+    Power,
+    Bolts,
+    Open,
+    Safety,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+enum WireColor {
+    Red,
+    Blue,
+    Green,
+    Yellow,
+    Orange,
+    Purple,
+}
+
+impl WireColor {
+    const ALL: [WireColor; 6] = [
+        WireColor::Red,
+        WireColor::Blue,
+        WireColor::Green,
+        WireColor::Yellow,
+        WireColor::Orange,
+        WireColor::Purple,
+    ];
+
+    #[cfg(feature = "client")]
+    fn egui_color(&self, palette: Palette) -> egui::Color32 {
+        if palette == Palette::Standard {
+            return match self {
+                WireColor::Red => egui::Color32::RED,
+                WireColor::Blue => egui::Color32::LIGHT_BLUE,
+                WireColor::Green => egui::Color32::GREEN,
+                WireColor::Yellow => egui::Color32::YELLOW,
+                WireColor::Orange => egui::Color32::from_rgb(255, 165, 0),
+                WireColor::Purple => egui::Color32::from_rgb(160, 32, 240),
+            };
+        }
+        // Colors that stay apart for most kinds of colorblindness
+        match self {
+            WireColor::Red => egui::Color32::from_rgb(213, 94, 0),
+            WireColor::Blue => egui::Color32::from_rgb(0, 114, 178),
+            WireColor::Green => egui::Color32::from_rgb(0, 158, 115),
+            WireColor::Yellow => egui::Color32::from_rgb(240, 228, 66),
+            WireColor::Orange => egui::Color32::from_rgb(230, 159, 0),
+            WireColor::Purple => egui::Color32::from_rgb(204, 121, 167),
+        }
+    }
+
+    /// Shown next to the color for players that can't rely on it.
+    /// Belongs to the color and not the role, so it tells players nothing more than the color does.
+    #[cfg(feature = "client")]
+    fn symbol(&self) -> &'static str {
+        match self {
+            WireColor::Red => "●",
+            WireColor::Blue => "■",
+            WireColor::Green => "▲",
+            WireColor::Yellow => "◆",
+            WireColor::Orange => "★",
+            WireColor::Purple => "✚",
+        }
+    }
+}
+
+struct Wire {
+    color: WireColor,
+    /// Wires without a role are decoys
+    role: Option<WireRole>,
+    cut: bool,
+}
+
+/// The maintenance panel of a door, containing its wires.
+#[derive(Component)]
+pub struct WirePanel {
+    open: bool,
+    wires: Vec<Wire>,
+}
+
+impl WirePanel {
+    fn generate(rng: &mut fastrand::Rng) -> Self {
+        let mut roles = vec![
+            Some(WireRole::Power),
+            Some(WireRole::Bolts),
+            Some(WireRole::Open),
+            Some(WireRole::Safety),
+        ];
+        roles.resize(WireColor::ALL.len(), None);
+        rng.shuffle(&mut roles);
+
+        let mut colors = WireColor::ALL;
+        rng.shuffle(&mut colors);
+
+        Self {
+            open: false,
+            wires: colors
+                .into_iter()
+                .zip(roles)
+                .map(|(color, role)| Wire {
+                    color,
+                    role,
+                    cut: false,
+                })
+                .collect(),
+        }
+    }
+
+    /// Colors and roles of the wires, for comparing panels in tests
+    #[cfg(test)]
+    pub(crate) fn layout(&self) -> Vec<String> {
+        self.wires
+            .iter()
+            .map(|w| format!("{:?} {:?}", w.color, w.role))
+            .collect()
+    }
+
+    /// The information players can observe by looking at the panel
+    fn to_client(&self) -> Vec<WireClient> {
+        self.wires
+            .iter()
+            .map(|w| WireClient {
+                color: w.color,
+                cut: w.cut,
+            })
+            .collect()
+    }
+}
+
+fn add_wire_panels(
+    new_doors: Query<Entity, Added<Door>>,
+    rng: Res<GameRng>,
+    mut commands: Commands,
+) {
+    for entity in new_doors.iter() {
+        // Derive the layout from the entity, so every door is different
+        let mut rng = rng.for_entity(entity, "wires");
+        commands
+            .entity(entity)
+            .insert(WirePanel::generate(&mut rng));
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+struct WireClient {
+    color: WireColor,
+    cut: bool,
+}
+
+/// Server message with the visible state of a wire panel.
+#[derive(Serialize, Deserialize)]
+struct WirePanelMessage {
+    door: NetworkIdentity,
+    /// `None` if the panel was closed
+    wires: Option<Vec<WireClient>>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+enum WireAction {
+    Cut,
+    Mend,
+    Pulse,
+}
+
+/// Client message to do something with a wire.
+#[derive(Serialize, Deserialize)]
+struct WireActionRequest {
+    door: NetworkIdentity,
+    wire: usize,
+    action: WireAction,
+}
+
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+#[component(storage = "SparseSet")]
+struct TogglePanelInteraction {
+    target: Entity,
+}
+
+// Dummy default for Reflect
+impl Default for TogglePanelInteraction {
+    fn default() -> Self {
+        Self {
+            target: Entity::from_raw(0),
+        }
+    }
+}
+
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+#[component(storage = "SparseSet")]
+struct InspectWiresInteraction {
+    target: Entity,
+}
+
+// Dummy default for Reflect
+impl Default for InspectWiresInteraction {
+    fn default() -> Self {
+        Self {
+            target: Entity::from_raw(0),
+        }
+    }
+}
+
+fn prepare_panel_interactions(
+    list: Res<InteractionListEvents>,
+    screwdrivers: Query<(), With<Screwdriver>>,
+    panels: Query<&WirePanel>,
+) {
+    for event in list.events.iter() {
+        let Ok(panel) = panels.get(event.target) else {
+            continue;
+        };
+
+        let holds_screwdriver = event
+            .item_in_hand
+            .map(|item| screwdrivers.contains(item))
+            .unwrap_or(false);
+        if holds_screwdriver {
+            event.add_interaction(InteractionOption {
+                text: if panel.open {
+                    "Close panel"
+                } else {
+                    "Open panel"
+                }
+                .into(),
+                interaction: Box::new(TogglePanelInteraction {
+                    target: event.target,
+                }),
+                specificity: InteractionSpecificity::Specific,
+            });
+        }
+
+        if panel.open {
+            event.add_interaction(InteractionOption {
+                text: "Inspect wires".into(),
+                interaction: Box::new(InspectWiresInteraction {
+                    target: event.target,
+                }),
+                specificity: InteractionSpecificity::Specific,
+            });
+        }
+    }
+}
+
+const PANEL_TIME: Duration = Duration::from_secs(1);
+
+fn execute_toggle_panel_interaction(
+    mut query: Query<(Entity, &TogglePanelInteraction, &mut ActiveInteraction)>,
+    mut panels: Query<(&mut WirePanel, &mut DoorState)>,
+    identities: Res<NetworkIdentities>,
+    controls: Res<ClientControls>,
+    players: Res<Players>,
+    time: Res<Time>,
+    mut sender: MessageSender,
+) {
+    for (entity, interaction, mut active) in query.iter_mut() {
+        active.set_initial_duration(PANEL_TIME);
+
+        let Ok((mut panel, mut state)) = panels.get_mut(interaction.target) else {
+            active.status = InteractionStatus::Canceled;
+            continue;
+        };
+
+        if active.start_time() + PANEL_TIME.as_secs_f32() > time.elapsed_seconds() {
+            continue;
+        }
+
+        panel.open = !panel.open;
+        if !panel.open {
+            // Reassembling the panel fixes all the wiring
+            for wire in panel.wires.iter_mut() {
+                wire.cut = false;
+            }
+            state.powered = true;
+            state.safety = true;
+            state.set_bolted(false);
+        }
+
+        // Close the wire window for the player
+        if let (false, Some(connection), Some(door)) = (
+            panel.open,
+            player_connection(entity, &controls, &players),
+            identities.get_identity(interaction.target),
+        ) {
+            sender.send(
+                &WirePanelMessage { door, wires: None },
+                MessageReceivers::Single(connection),
+            );
+        }
+
+        active.status = InteractionStatus::Completed;
+    }
+}
+
+fn execute_inspect_wires_interaction(
+    mut query: Query<(Entity, &InspectWiresInteraction, &mut ActiveInteraction)>,
+    panels: Query<&WirePanel>,
+    identities: Res<NetworkIdentities>,
+    controls: Res<ClientControls>,
+    players: Res<Players>,
+    mut sender: MessageSender,
+) {
+    for (entity, interaction, mut active) in query.iter_mut() {
+        let (Ok(panel), Some(door), Some(connection)) = (
+            panels.get(interaction.target),
+            identities.get_identity(interaction.target),
+            player_connection(entity, &controls, &players),
+        ) else {
+            active.status = InteractionStatus::Canceled;
+            continue;
+        };
+
+        if !panel.open {
+            active.status = InteractionStatus::Canceled;
+            continue;
+        }
+
+        sender.send(
+            &WirePanelMessage {
+                door,
+                wires: Some(panel.to_client()),
+            },
+            MessageReceivers::Single(connection),
+        );
+        active.status = InteractionStatus::Completed;
+    }
+}
+
+fn player_connection(
+    entity: Entity,
+    controls: &ClientControls,
+    players: &Players,
+) -> Option<ConnectionId> {
+    controls
+        .controlling_player(entity)
+        .and_then(|player| players.get_connection(&player))
+}
+
+/// How long it takes to cut, mend or pulse a wire
+const WIRE_ACTION_TIME: Duration = Duration::from_millis(1500);
+/// How far away a player can be from a door to work on its wires
+const WIRE_ACTION_RANGE: f32 = 1.5;
+
+struct PendingWireAction {
+    connection: ConnectionId,
+    door: Entity,
+    wire: usize,
+    action: WireAction,
+    started: f32,
+}
+
+#[derive(Resource, Default)]
+struct PendingWireActions(Vec<PendingWireAction>);
+
+/// Checks if the player can currently do a wire action.
+fn validate_wire_action(
+    connection: ConnectionId,
+    door: Entity,
+    panel: &WirePanel,
+    wire: usize,
+    action: WireAction,
+    world: &WireActionWorld,
+) -> bool {
+    let Some(player) = world
+        .players
+        .get(connection)
+        .and_then(|p| world.controls.controlled_entity(p.id))
+    else {
+        return false;
+    };
+
+    let Some(wire) = panel.wires.get(wire) else {
+        return false;
+    };
+    if !panel.open {
+        return false;
+    }
+
+    let valid_state = match action {
+        WireAction::Cut | WireAction::Pulse => !wire.cut,
+        WireAction::Mend => wire.cut,
+    };
+    if !valid_state {
+        return false;
+    }
+
+    let (Ok(player_transform), Ok(door_transform)) =
+        (world.transforms.get(player), world.transforms.get(door))
+    else {
+        return false;
+    };
+    if player_transform
+        .translation()
+        .distance(door_transform.translation())
+        > WIRE_ACTION_RANGE
+    {
+        return false;
+    }
+
+    // Check the required tool is in the active hand
+    let Some(item) = world.held_item.get(player) else {
+        return false;
+    };
+    match action {
+        WireAction::Cut | WireAction::Mend => world.wirecutters.contains(item),
+        WireAction::Pulse => world.multitools.contains(item),
+    }
+}
+
+#[derive(bevy::ecs::system::SystemParam)]
+struct WireActionWorld<'w, 's> {
+    players: Res<'w, Players>,
+    controls: Res<'w, ClientControls>,
+    transforms: Query<'w, 's, &'static GlobalTransform>,
+    held_item: HeldItem<'w, 's>,
+    wirecutters: Query<'w, 's, (), With<Wirecutters>>,
+    multitools: Query<'w, 's, (), With<Multitool>>,
+}
+
+fn handle_wire_action_requests(
+    mut messages: EventReader<MessageEvent<WireActionRequest>>,
+    mut pending: ResMut<PendingWireActions>,
+    identities: Res<NetworkIdentities>,
+    panels: Query<&WirePanel>,
+    world: WireActionWorld,
+    time: Res<Time>,
+) {
+    for event in messages.iter() {
+        let Some(door) = identities.get_entity(event.message.door) else {
+            continue;
+        };
+        let Ok(panel) = panels.get(door) else {
+            continue;
+        };
+
+        let request = &event.message;
+        if !validate_wire_action(
+            event.connection,
+            door,
+            panel,
+            request.wire,
+            request.action,
+            &world,
+        ) {
+            debug!(connection = ?event.connection, action = ?request.action, "Invalid wire action");
+            continue;
+        }
+
+        // Only one action at a time per player
+        pending.0.retain(|a| a.connection != event.connection);
+        pending.0.push(PendingWireAction {
+            connection: event.connection,
+            door,
+            wire: request.wire,
+            action: request.action,
+            started: time.elapsed_seconds(),
+        });
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn process_wire_actions(
+    mut pending: ResMut<PendingWireActions>,
+    mut doors: Query<(&mut WirePanel, &mut DoorState, &GlobalTransform)>,
+    creatures: Query<&GlobalTransform, With<Body>>,
+    identities: Res<NetworkIdentities>,
+    world: WireActionWorld,
+    time: Res<Time>,
+    mut effects: EffectSender,
+    mut sender: MessageSender,
+) {
+    pending.0.retain(|action| {
+        if action.started + WIRE_ACTION_TIME.as_secs_f32() > time.elapsed_seconds() {
+            return true;
+        }
+
+        let Ok((mut panel, mut state, transform)) = doors.get_mut(action.door) else {
+            return false;
+        };
+
+        // The player may have moved away or swapped tools in the meantime
+        if !validate_wire_action(
+            action.connection,
+            action.door,
+            &panel,
+            action.wire,
+            action.action,
+            &world,
+        ) {
+            return false;
+        }
+
+        let wire = &mut panel.wires[action.wire];
+        // Messing with a live power wire sparks
+        if wire.role == Some(WireRole::Power)
+            && state.powered
+            && matches!(action.action, WireAction::Cut | WireAction::Pulse)
+        {
+            effects.send(EffectKind::Sparks, transform.translation(), 0.5);
+        }
+        match action.action {
+            WireAction::Cut => {
+                wire.cut = true;
+                match wire.role {
+                    Some(WireRole::Power) => state.powered = false,
+                    Some(WireRole::Bolts) => state.set_bolted(true),
+                    Some(WireRole::Safety) => state.safety = false,
+                    Some(WireRole::Open) | None => {}
+                }
+            }
+            WireAction::Mend => {
+                wire.cut = false;
+                match wire.role {
+                    Some(WireRole::Power) => state.powered = true,
+                    Some(WireRole::Safety) => state.safety = true,
+                    // Bolts stay down until raised
+                    Some(WireRole::Bolts) | Some(WireRole::Open) | None => {}
+                }
+            }
+            WireAction::Pulse => match wire.role {
+                Some(WireRole::Bolts) if state.powered => {
+                    let bolted = state.is_bolted();
+                    state.set_bolted(!bolted);
+                }
+                Some(WireRole::Open) if state.can_move() => {
+                    let open = !state.is_open();
+                    if open || !door_obstructed(transform.translation(), &state, &creatures) {
+                        state.set_open(open);
+                    }
+                }
+                _ => {}
+            },
+        }
+
+        info!(connection = ?action.connection, door = ?action.door, action = ?action.action, "Wire action completed");
+
+        if let Some(door) = identities.get_identity(action.door) {
+            sender.send(
+                &WirePanelMessage {
+                    door,
+                    wires: Some(panel.to_client()),
+                },
+                MessageReceivers::Single(action.connection),
+            );
+        }
+
+        false
+    });
+}
+
+#[cfg(feature = "client")]
+#[derive(Resource, Default)]
+struct ClientWirePanel {
+    door: Option<NetworkIdentity>,
+    wires: Vec<WireClient>,
+}
+
+#[cfg(feature = "client")]
+fn client_receive_wire_panel(
+    mut messages: EventReader<MessageEvent<WirePanelMessage>>,
+    mut panel: ResMut<ClientWirePanel>,
+) {
+    for event in messages.iter() {
+        match &event.message.wires {
+            Some(wires) => {
+                panel.door = Some(event.message.door);
+                panel.wires = wires.clone();
+            }
+            None => {
+                if panel.door == Some(event.message.door) {
+                    panel.door = None;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "client")]
+fn client_wire_panel_ui(
+    mut contexts: EguiContexts,
+    mut panel: ResMut<ClientWirePanel>,
+    accessibility: Res<AccessibilitySettings>,
+    mut sender: MessageSender,
+) {
+    let Some(door) = panel.door else {
+        return;
+    };
+
+    let mut open = true;
+    egui::Window::new("Wires")
+        .open(&mut open)
+        .show(contexts.ctx_mut(), |ui| {
+            for (index, wire) in panel.wires.iter().enumerate() {
+                ui.horizontal(|ui| {
+                    let color = wire.color.egui_color(accessibility.palette);
+                    if accessibility.symbols() {
+                        ui.colored_label(color, wire.color.symbol());
+                    }
+                    ui.colored_label(color, format!("{:?}", wire.color));
+                    let actions: &[(WireAction, &str)] = if wire.cut {
+                        &[(WireAction::Mend, "Mend")]
+                    } else {
+                        &[(WireAction::Cut, "Cut"), (WireAction::Pulse, "Pulse")]
+                    };
+                    for &(action, text) in actions {
+                        if ui.button(text).clicked() {
+                            sender.send_to_server(&WireActionRequest {
+                                door,
+                                wire: index,
+                                action,
+                            });
+                        }
+                    }
+                });
+            }
+        });
+
+    if !open {
+        panel.door = None;
+    }
+}
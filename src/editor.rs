@@ -0,0 +1,622 @@
+use std::{collections::VecDeque, path::PathBuf};
+
+use bevy::{
+    asset::LoadState,
+    prelude::*,
+    utils::{HashMap, HashSet},
+    window::PrimaryWindow,
+};
+use bevy_egui::{egui, EguiContexts};
+use maps::{
+    cursor_tile,
+    save::{MapSave, SavedTile},
+    FootstepMaterial, HighlightRequest, HighlightTarget, LocalTileCommandsExt, TileLayer,
+    TileMapClient,
+};
+
+use crate::{
+    camera::{MainCamera, TopDownCamera},
+    map_validation::{self, MapIssue, Severity, TileDefinitions},
+    ui::has_window,
+    ArgCommands, Args, GameState,
+};
+
+/// Edits maps without a server, started with the `editor` command.
+/// Maps are saved in the [`MapSave`] format, which the server loads with `--map-save`.
+pub struct EditorPlugin;
+
+impl Plugin for EditorPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(GameState::Editor), setup_editor)
+            .add_systems(
+                Update,
+                (
+                    move_camera,
+                    editor_ui.run_if(has_window),
+                    shortcuts,
+                    paint,
+                    spawn_changed_tiles,
+                )
+                    .chain()
+                    .run_if(in_state(GameState::Editor)),
+            );
+    }
+}
+
+/// Size in tiles of maps created by the editor
+const NEW_MAP_SIZE: UVec2 = UVec2::new(64, 64);
+/// How many strokes can be undone
+const MAX_UNDO_STEPS: usize = 100;
+/// Camera speed in tiles per second
+const CAMERA_SPEED: f32 = 10.0;
+const TURF_FOLDER: &str = "tilemap/turfs";
+
+#[derive(Clone, PartialEq, Eq)]
+enum Tool {
+    /// Paints the turf with this asset path
+    Turf(String),
+    /// Removes everything on a tile except its area
+    Eraser,
+    /// Assigns tiles to the area with this name. An empty name removes the area.
+    Area(String),
+}
+
+/// A tile before and after it was edited.
+#[derive(Clone, Copy)]
+struct TileEdit {
+    position: UVec2,
+    before: SavedTile,
+    after: SavedTile,
+}
+
+#[derive(Resource)]
+struct Editor {
+    map: MapSave,
+    tilemap: Entity,
+    /// Where the map is saved. Not set if the file couldn't be loaded, so it isn't overwritten.
+    path: Option<PathBuf>,
+    /// Text of the save as field
+    save_as_path: String,
+    unsaved: bool,
+    status: String,
+    tool: Tool,
+    /// Asset paths of all turfs
+    turfs: Vec<String>,
+    /// Keeps the tile definitions loaded
+    definitions: Vec<HandleUntyped>,
+    /// Edits of the stroke that is being painted, added to the undo stack when the mouse is released
+    stroke: Vec<TileEdit>,
+    undo: VecDeque<Vec<TileEdit>>,
+    redo: Vec<Vec<TileEdit>>,
+    /// Tiles whose objects need to be spawned again
+    changed: HashSet<UVec2>,
+    /// Spawned objects of each tile
+    objects: HashMap<UVec2, Vec<Entity>>,
+    hovered: Option<UVec2>,
+    /// Problems found the last time the map was validated
+    issues: Vec<MapIssue>,
+}
+
+impl Editor {
+    fn set_tile(&mut self, position: UVec2, tile: SavedTile) {
+        let Some(current) = self.map.tile_mut(position) else {
+            return;
+        };
+        if *current == tile {
+            return;
+        }
+
+        self.stroke.push(TileEdit {
+            position,
+            before: *current,
+            after: tile,
+        });
+        *current = tile;
+        self.changed.insert(position);
+        self.unsaved = true;
+    }
+
+    /// Adds the current stroke to the undo history.
+    fn finish_stroke(&mut self) {
+        if self.stroke.is_empty() {
+            return;
+        }
+        self.undo.push_back(std::mem::take(&mut self.stroke));
+        if self.undo.len() > MAX_UNDO_STEPS {
+            self.undo.pop_front();
+        }
+        self.redo.clear();
+    }
+
+    fn apply_edits(&mut self, edits: &[TileEdit], undo: bool) {
+        let ordered: Box<dyn Iterator<Item = &TileEdit>> = match undo {
+            true => Box::new(edits.iter().rev()),
+            false => Box::new(edits.iter()),
+        };
+        for edit in ordered {
+            if let Some(tile) = self.map.tile_mut(edit.position) {
+                *tile = if undo { edit.before } else { edit.after };
+                self.changed.insert(edit.position);
+            }
+        }
+        self.unsaved = true;
+    }
+
+    fn undo(&mut self) {
+        self.finish_stroke();
+        if let Some(edits) = self.undo.pop_back() {
+            self.apply_edits(&edits, true);
+            self.redo.push(edits);
+        }
+    }
+
+    fn redo(&mut self) {
+        if let Some(edits) = self.redo.pop() {
+            self.apply_edits(&edits, false);
+            self.undo.push_back(edits);
+        }
+    }
+
+    fn save(&mut self) {
+        let Some(path) = self.path.as_ref() else {
+            self.status = "Choose a file with save as".into();
+            return;
+        };
+        match self.map.save(path) {
+            Ok(()) => {
+                self.unsaved = false;
+                self.status = format!("Saved to {}", path.display());
+                info!(path = %path.display(), "Saved map");
+            }
+            Err(err) => {
+                self.status = format!("Error saving: {}", err);
+                error!(path = %path.display(), error = %err, "Error saving map");
+            }
+        }
+    }
+
+    /// What a tile looks like after using the current tool on it.
+    /// Runs the same checks the server does when the map is loaded.
+    fn validate(&mut self) {
+        let report = map_validation::validate(&self.map.to_data(), &TileDefinitions::load());
+        self.status = if report.issues.is_empty() {
+            "No issues found".into()
+        } else {
+            format!("Found {} issues", report.issues.len())
+        };
+        self.issues = report.issues;
+    }
+
+    fn tool_result(&mut self, mut tile: SavedTile) -> SavedTile {
+        match self.tool.clone() {
+            Tool::Turf(path) => {
+                tile.turf = Some(self.map.palette_index(&path));
+                tile.footstep_material = footstep_material(&path);
+            }
+            Tool::Eraser => {
+                tile = SavedTile {
+                    area: tile.area,
+                    ..Default::default()
+                };
+            }
+            Tool::Area(name) if name.trim().is_empty() => tile.area = None,
+            Tool::Area(name) => tile.area = Some(self.map.area_index(name.trim())),
+        }
+        tile
+    }
+}
+
+/// The name of a tile object from its asset path, like `wood floor` for `tilemap/turfs/wood floor.scn.ron`.
+fn object_name(path: &str) -> &str {
+    let file = path.rsplit('/').next().unwrap_or(path);
+    file.split('.').next().unwrap_or(file)
+}
+
+fn footstep_material(turf_path: &str) -> Option<FootstepMaterial> {
+    let name = object_name(turf_path);
+    if name.contains("wood") {
+        Some(FootstepMaterial::Wood)
+    } else if name.contains("plating") || name.contains("catwalk") || name.contains("stairs") {
+        Some(FootstepMaterial::Metal)
+    } else if name.contains("floor") {
+        Some(FootstepMaterial::Tile)
+    } else {
+        None
+    }
+}
+
+#[derive(Component)]
+struct EditorCameraTarget;
+
+fn setup_editor(
+    args: Res<Args>,
+    asset_server: Res<AssetServer>,
+    mut cameras: Query<&mut TopDownCamera>,
+    mut commands: Commands,
+) {
+    let Some(ArgCommands::Editor { path }) = &args.command else {
+        return;
+    };
+
+    let (map, path, status) = if path.exists() {
+        match MapSave::load(path) {
+            Ok(map) => (
+                map,
+                Some(path.clone()),
+                format!("Opened {}", path.display()),
+            ),
+            Err(err) => {
+                error!(path = %path.display(), error = %err, "Error loading map");
+                (
+                    MapSave::new(NEW_MAP_SIZE),
+                    None,
+                    format!("Error loading {}: {}", path.display(), err),
+                )
+            }
+        }
+    } else {
+        (
+            MapSave::new(NEW_MAP_SIZE),
+            Some(path.clone()),
+            format!("Created {}", path.display()),
+        )
+    };
+
+    let definitions = asset_server
+        .load_folder("tilemap")
+        .expect("assets/tilemap is missing");
+    let mut turfs: Vec<_> = definitions
+        .iter()
+        .filter_map(|h| asset_server.get_handle_path(h))
+        .map(|p| p.path().to_string_lossy().replace('\\', "/"))
+        .filter(|p| p.starts_with(TURF_FOLDER))
+        .collect();
+    turfs.sort();
+
+    let tilemap = commands
+        .spawn((TileMapClient::local(map.size), SpatialBundle::default()))
+        .id();
+
+    // Start looking at the middle of the map
+    let center = map.size.as_vec2() / 2.0;
+    let target = commands
+        .spawn((
+            TransformBundle::from(Transform::from_xyz(center.x, 0.0, center.y)),
+            EditorCameraTarget,
+        ))
+        .id();
+    for mut camera in cameras.iter_mut() {
+        camera.target = target;
+    }
+
+    let changed = (0..map.size.y)
+        .flat_map(|y| (0..map.size.x).map(move |x| UVec2::new(x, y)))
+        .collect();
+    commands.insert_resource(Editor {
+        save_as_path: path
+            .as_ref()
+            .map(|p| p.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        map,
+        tilemap,
+        path,
+        unsaved: false,
+        status,
+        tool: turfs
+            .first()
+            .cloned()
+            .map(Tool::Turf)
+            .unwrap_or(Tool::Eraser),
+        turfs,
+        definitions,
+        stroke: Vec::new(),
+        undo: VecDeque::new(),
+        redo: Vec::new(),
+        changed,
+        objects: HashMap::default(),
+        hovered: None,
+        issues: Vec::new(),
+    });
+}
+
+fn move_camera(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut contexts: EguiContexts,
+    cameras: Query<&TopDownCamera>,
+    mut targets: Query<&mut Transform, With<EditorCameraTarget>>,
+    time: Res<Time>,
+) {
+    let typing = contexts.ctx_mut().wants_keyboard_input();
+    let shortcut = keyboard_input.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
+    if typing || shortcut {
+        return;
+    }
+    let Ok(camera) = cameras.get_single() else {
+        return;
+    };
+    let Ok(mut target) = targets.get_single_mut() else {
+        return;
+    };
+
+    let axis = |positive, negative| {
+        f32::from(keyboard_input.pressed(positive)) - f32::from(keyboard_input.pressed(negative))
+    };
+    // Same axes as creature movement, so the camera moves the way the keys point on screen
+    let direction = Quat::from_euler(bevy::math::EulerRot::XYZ, 0.0, camera.current_angle(), 0.0)
+        .mul_vec3(Vec3::new(
+            axis(KeyCode::W, KeyCode::S),
+            0.0,
+            axis(KeyCode::D, KeyCode::A),
+        ));
+    target.translation += direction.normalize_or_zero() * CAMERA_SPEED * time.delta_seconds();
+}
+
+fn editor_ui(
+    mut contexts: EguiContexts,
+    editor: Option<ResMut<Editor>>,
+    mut targets: Query<&mut Transform, With<EditorCameraTarget>>,
+) {
+    let Some(mut editor) = editor else {
+        return;
+    };
+    let editor = editor.as_mut();
+    let mut jump_to = None;
+
+    egui::Window::new("Map editor").show(contexts.ctx_mut(), |ui| {
+        let title = match &editor.path {
+            Some(path) => path.display().to_string(),
+            None => "Unsaved map".to_owned(),
+        };
+        ui.heading(if editor.unsaved {
+            format!("{} *", title)
+        } else {
+            title
+        });
+
+        ui.horizontal(|ui| {
+            if ui.button("Save").clicked() {
+                editor.save();
+            }
+            ui.text_edit_singleline(&mut editor.save_as_path);
+            if ui.button("Save as").clicked() && !editor.save_as_path.trim().is_empty() {
+                editor.path = Some(editor.save_as_path.trim().into());
+                editor.save();
+            }
+        });
+
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(!editor.undo.is_empty(), egui::Button::new("Undo"))
+                .clicked()
+            {
+                editor.undo();
+            }
+            if ui
+                .add_enabled(!editor.redo.is_empty(), egui::Button::new("Redo"))
+                .clicked()
+            {
+                editor.redo();
+            }
+            if ui.button("Validate").clicked() {
+                editor.validate();
+            }
+        });
+
+        ui.separator();
+        ui.label("Turfs");
+        let mut selected = None;
+        for turf in editor.turfs.iter() {
+            let active = editor.tool == Tool::Turf(turf.clone());
+            if ui.selectable_label(active, object_name(turf)).clicked() {
+                selected = Some(Tool::Turf(turf.clone()));
+            }
+        }
+        if ui
+            .selectable_label(editor.tool == Tool::Eraser, "Eraser")
+            .clicked()
+        {
+            selected = Some(Tool::Eraser);
+        }
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            let mut area = match &editor.tool {
+                Tool::Area(name) => name.clone(),
+                _ => String::new(),
+            };
+            let active = matches!(editor.tool, Tool::Area(_));
+            if ui.selectable_label(active, "Area").clicked() {
+                selected = Some(Tool::Area(area.clone()));
+            }
+            if ui.text_edit_singleline(&mut area).changed() {
+                selected = Some(Tool::Area(area));
+            }
+        });
+        if let Some(tool) = selected {
+            editor.tool = tool;
+        }
+
+        ui.separator();
+        if let Some(position) = editor.hovered {
+            let tile = editor.map.tile(position).copied().unwrap_or_default();
+            let turf = tile.turf.and_then(|t| editor.map.path(t)).map(object_name);
+            let area = tile
+                .area
+                .and_then(|a| editor.map.areas.get(a as usize))
+                .map(String::as_str);
+            ui.label(format!(
+                "{},{}: {} ({})",
+                position.x,
+                position.y,
+                turf.unwrap_or("empty"),
+                area.unwrap_or("no area")
+            ));
+        }
+        ui.label(&editor.status);
+
+        if !editor.issues.is_empty() {
+            ui.separator();
+            egui::ScrollArea::vertical()
+                .max_height(200.0)
+                .show(ui, |ui| {
+                    for issue in editor.issues.iter() {
+                        let color = match issue.severity {
+                            Severity::Error => egui::Color32::LIGHT_RED,
+                            Severity::Warning => egui::Color32::YELLOW,
+                        };
+                        let text = egui::RichText::new(issue.to_string()).color(color);
+                        if ui.selectable_label(false, text).clicked() {
+                            jump_to = issue.position;
+                        }
+                    }
+                });
+        }
+    });
+
+    if let Some(position) = jump_to {
+        for mut target in targets.iter_mut() {
+            target.translation = Vec3::new(position.x as f32, 0.0, position.y as f32);
+        }
+    }
+}
+
+fn shortcuts(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut contexts: EguiContexts,
+    editor: Option<ResMut<Editor>>,
+) {
+    let Some(mut editor) = editor else {
+        return;
+    };
+    if contexts.ctx_mut().wants_keyboard_input()
+        || !keyboard_input.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight])
+    {
+        return;
+    }
+
+    let shift = keyboard_input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    if keyboard_input.just_pressed(KeyCode::S) {
+        editor.save();
+    } else if keyboard_input.just_pressed(KeyCode::Y)
+        || (shift && keyboard_input.just_pressed(KeyCode::Z))
+    {
+        editor.redo();
+    } else if keyboard_input.just_pressed(KeyCode::Z) {
+        editor.undo();
+    }
+}
+
+fn paint(
+    buttons: Res<Input<MouseButton>>,
+    mut contexts: EguiContexts,
+    windows: Query<(Entity, &Window), With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    tilemaps: Query<(&TileMapClient, &GlobalTransform)>,
+    mut highlight: ResMut<HighlightRequest>,
+    editor: Option<ResMut<Editor>>,
+) {
+    let Some(mut editor) = editor else {
+        return;
+    };
+    if buttons.just_released(MouseButton::Left) {
+        editor.finish_stroke();
+    }
+    editor.hovered = None;
+
+    let Ok((window_entity, window)) = windows.get_single() else {
+        return;
+    };
+    if contexts
+        .try_ctx_for_window_mut(window_entity)
+        .map(|c| c.wants_pointer_input())
+        == Some(true)
+    {
+        return;
+    }
+    let Some((camera, camera_transform)) = cameras.iter().next() else {
+        return;
+    };
+    let Ok((map, map_transform)) = tilemaps.get(editor.tilemap) else {
+        return;
+    };
+    let Some(position) = cursor_tile(window, camera, camera_transform, map, map_transform) else {
+        return;
+    };
+
+    editor.hovered = Some(position);
+    highlight.target = Some(HighlightTarget {
+        tilemap: editor.tilemap,
+        position,
+        valid: true,
+    });
+
+    // Holding the button paints every tile the cursor moves over
+    if !buttons.pressed(MouseButton::Left) {
+        return;
+    }
+    let Some(tile) = editor.map.tile(position).copied() else {
+        return;
+    };
+    let tile = editor.tool_result(tile);
+    editor.set_tile(position, tile);
+}
+
+/// Replaces the objects of edited tiles.
+fn spawn_changed_tiles(
+    editor: Option<ResMut<Editor>>,
+    asset_server: Res<AssetServer>,
+    mut commands: Commands,
+) {
+    let Some(mut editor) = editor else {
+        return;
+    };
+    if editor.changed.is_empty() {
+        return;
+    }
+    // Tiles spawned before their scene is loaded wouldn't get their meshes set up
+    let load_state = asset_server.get_group_load_state(editor.definitions.iter().map(|h| h.id()));
+    if load_state != LoadState::Loaded {
+        return;
+    }
+
+    let editor = editor.as_mut();
+    for position in editor.changed.drain() {
+        for entity in editor.objects.remove(&position).into_iter().flatten() {
+            commands.despawn_local_tile(entity);
+        }
+
+        let Some(tile) = editor.map.tile(position) else {
+            continue;
+        };
+        let layers = [
+            (TileLayer::Turf, None, tile.turf),
+            (TileLayer::Furniture, None, tile.furniture),
+            (TileLayer::Pipe, None, tile.pipe),
+        ]
+        .into_iter()
+        .chain(
+            tile.high_mounts
+                .iter()
+                .enumerate()
+                .map(|(i, object)| (TileLayer::HighMount, Some(i as u8), *object)),
+        );
+
+        let mut objects = Vec::new();
+        for (layer, index_in_layer, object) in layers {
+            let Some(path) = object.and_then(|o| editor.map.path(o)) else {
+                continue;
+            };
+            let scene = asset_server.get_handle(path);
+            objects.push(commands.spawn_local_tile(
+                editor.tilemap,
+                position,
+                layer,
+                index_in_layer,
+                scene,
+            ));
+        }
+        if !objects.is_empty() {
+            editor.objects.insert(position, objects);
+        }
+    }
+}
@@ -0,0 +1,405 @@
+use bevy::{
+    ecs::system::{SystemParam, SystemState},
+    prelude::*,
+    utils::HashSet,
+};
+use networking::{
+    is_server,
+    messaging::{AppExt, MessageReceivers, MessageSender},
+    spawning::ClientControls,
+    Players,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    console::{
+        ArgumentKind, CommandContext, CommandResult, ConsoleAppExt, ConsoleCommand, PermissionLevel,
+    },
+    sound::ImpactMaterial,
+};
+
+#[cfg(feature = "client")]
+use crate::GameState;
+
+/// Short visual effects for things breaking, like sparks and debris.
+pub struct EffectsPlugin;
+
+impl Plugin for EffectsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_network_message::<DestructionEffect>("DestructionEffect");
+
+        if is_server(app) {
+            app.add_console_command(ConsoleCommand {
+                name: "effect",
+                description: "Shows a destruction effect at your cursor",
+                parameters: &[("kind", ArgumentKind::Text)],
+                permission: PermissionLevel::Admin,
+                handler: effect_command,
+            });
+        } else {
+            #[cfg(feature = "client")]
+            app.add_systems(Startup, client::setup_effect_assets)
+                .add_systems(
+                    Update,
+                    (
+                        (client::spawn_effects, client::update_particles).chain(),
+                        client::play_effect_sounds,
+                    )
+                        .run_if(in_state(GameState::Game)),
+                );
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EffectKind {
+    Sparks,
+    Debris,
+    Dust,
+    WoodBreak,
+    MetalBreak,
+    GlassBreak,
+    /// Something being cut apart, like a butchered corpse
+    Gore,
+}
+
+impl EffectKind {
+    const ALL: [EffectKind; 7] = [
+        EffectKind::Sparks,
+        EffectKind::Debris,
+        EffectKind::Dust,
+        EffectKind::WoodBreak,
+        EffectKind::MetalBreak,
+        EffectKind::GlassBreak,
+        EffectKind::Gore,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            EffectKind::Sparks => "sparks",
+            EffectKind::Debris => "debris",
+            EffectKind::Dust => "dust",
+            EffectKind::WoodBreak => "wood",
+            EffectKind::MetalBreak => "metal",
+            EffectKind::GlassBreak => "glass",
+            EffectKind::Gore => "gore",
+        }
+    }
+
+    /// The effect of an object made of a material falling apart.
+    pub fn breaking(material: Option<ImpactMaterial>) -> Self {
+        match material {
+            Some(ImpactMaterial::Metal) | None => EffectKind::MetalBreak,
+            Some(ImpactMaterial::Wood) => EffectKind::WoodBreak,
+            Some(ImpactMaterial::Plastic) => EffectKind::Debris,
+            Some(ImpactMaterial::Soft) => EffectKind::Dust,
+            Some(ImpactMaterial::Glass) => EffectKind::GlassBreak,
+        }
+    }
+}
+
+/// Server message to show an effect at a position.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct DestructionEffect {
+    pub position: Vec3,
+    pub kind: EffectKind,
+    /// How big the effect is, 1 being a single object breaking
+    pub magnitude: f32,
+}
+
+/// Players further away than this from an effect aren't sent it
+const EFFECT_RANGE: f32 = 20.0;
+
+/// Sends effects to the players close enough to see them.
+#[derive(SystemParam)]
+pub struct EffectSender<'w, 's> {
+    players: Res<'w, Players>,
+    controls: Res<'w, ClientControls>,
+    transforms: Query<'w, 's, &'static GlobalTransform>,
+    sender: MessageSender<'w, 's>,
+}
+
+impl<'w, 's> EffectSender<'w, 's> {
+    pub fn send(&mut self, kind: EffectKind, position: Vec3, magnitude: f32) {
+        let receivers: HashSet<_> = self
+            .players
+            .players()
+            .iter()
+            .filter(|(_, player)| {
+                self.controls
+                    .controlled_entity(player.id)
+                    .and_then(|e| self.transforms.get(e).ok())
+                    .is_some_and(|t| t.translation().distance(position) <= EFFECT_RANGE)
+            })
+            .map(|(&connection, _)| connection)
+            .collect();
+        if receivers.is_empty() {
+            return;
+        }
+
+        self.sender.send(
+            &DestructionEffect {
+                position,
+                kind,
+                magnitude,
+            },
+            MessageReceivers::Set(receivers),
+        );
+    }
+}
+
+fn effect_command(world: &mut World, context: &CommandContext) -> CommandResult {
+    let name = context.text(0);
+    let Some(kind) = EffectKind::ALL.into_iter().find(|k| k.name() == name) else {
+        let names: Vec<_> = EffectKind::ALL.iter().map(|k| k.name()).collect();
+        return Err(format!("Unknown effect, use one of: {}", names.join(", ")));
+    };
+    let Some(position) = context.cursor else {
+        return Err("Your cursor isn't over the world".into());
+    };
+
+    let mut state = SystemState::<EffectSender>::new(world);
+    state.get_mut(world).send(kind, position, 1.0);
+    state.apply(world);
+    Ok(format!("Showing {} at {}", kind.name(), position))
+}
+
+#[cfg(feature = "client")]
+mod client {
+    use bevy::prelude::*;
+    use networking::messaging::MessageEvent;
+
+    use super::{DestructionEffect, EffectKind};
+    use crate::camera::MainCamera;
+
+    /// Most particles alive at the same time, new effects are skipped while at the limit
+    const MAX_PARTICLES: usize = 300;
+    /// Effects further from the camera than this aren't shown
+    const CULL_DISTANCE: f32 = 25.0;
+    const GRAVITY: f32 = 9.81;
+
+    #[derive(Resource)]
+    pub(super) struct EffectAssets {
+        quad: Handle<Mesh>,
+        sparks: Handle<StandardMaterial>,
+        debris: Handle<StandardMaterial>,
+        dust: Handle<StandardMaterial>,
+        wood: Handle<StandardMaterial>,
+        metal: Handle<StandardMaterial>,
+        glass: Handle<StandardMaterial>,
+        gore: Handle<StandardMaterial>,
+    }
+
+    /// How the particles of an effect look and move
+    struct ParticleStyle {
+        count: f32,
+        size: f32,
+        speed: f32,
+        lifetime: f32,
+        falls: bool,
+    }
+
+    impl EffectKind {
+        fn style(self) -> ParticleStyle {
+            match self {
+                EffectKind::Sparks => ParticleStyle {
+                    count: 12.0,
+                    size: 0.03,
+                    speed: 3.0,
+                    lifetime: 0.4,
+                    falls: true,
+                },
+                EffectKind::Debris => ParticleStyle {
+                    count: 8.0,
+                    size: 0.06,
+                    speed: 2.0,
+                    lifetime: 0.8,
+                    falls: true,
+                },
+                EffectKind::Dust => ParticleStyle {
+                    count: 6.0,
+                    size: 0.2,
+                    speed: 0.4,
+                    lifetime: 1.2,
+                    falls: false,
+                },
+                EffectKind::WoodBreak | EffectKind::MetalBreak => ParticleStyle {
+                    count: 10.0,
+                    size: 0.08,
+                    speed: 2.5,
+                    lifetime: 0.8,
+                    falls: true,
+                },
+                EffectKind::GlassBreak => ParticleStyle {
+                    count: 16.0,
+                    size: 0.05,
+                    speed: 3.0,
+                    lifetime: 0.6,
+                    falls: true,
+                },
+                EffectKind::Gore => ParticleStyle {
+                    count: 10.0,
+                    size: 0.06,
+                    speed: 2.0,
+                    lifetime: 0.7,
+                    falls: true,
+                },
+            }
+        }
+
+        fn material(self, assets: &EffectAssets) -> Handle<StandardMaterial> {
+            match self {
+                EffectKind::Sparks => assets.sparks.clone(),
+                EffectKind::Debris => assets.debris.clone(),
+                EffectKind::Dust => assets.dust.clone(),
+                EffectKind::WoodBreak => assets.wood.clone(),
+                EffectKind::MetalBreak => assets.metal.clone(),
+                EffectKind::GlassBreak => assets.glass.clone(),
+                EffectKind::Gore => assets.gore.clone(),
+            }
+        }
+    }
+
+    #[derive(Component)]
+    pub(super) struct Particle {
+        velocity: Vec3,
+        falls: bool,
+        expires: f32,
+    }
+
+    pub(super) fn setup_effect_assets(
+        mut meshes: ResMut<Assets<Mesh>>,
+        mut materials: ResMut<Assets<StandardMaterial>>,
+        mut commands: Commands,
+    ) {
+        let mut material = |color: Color| {
+            materials.add(StandardMaterial {
+                base_color: color,
+                unlit: true,
+                alpha_mode: AlphaMode::Blend,
+                ..Default::default()
+            })
+        };
+        commands.insert_resource(EffectAssets {
+            quad: meshes.add(shape::Quad::new(Vec2::ONE).into()),
+            sparks: material(Color::rgb(1.0, 0.85, 0.3)),
+            debris: material(Color::rgb(0.4, 0.4, 0.4)),
+            dust: material(Color::rgba(0.7, 0.68, 0.62, 0.5)),
+            wood: material(Color::rgb(0.55, 0.35, 0.17)),
+            metal: material(Color::rgb(0.6, 0.62, 0.66)),
+            glass: material(Color::rgba(0.75, 0.9, 1.0, 0.6)),
+            gore: material(Color::rgb(0.55, 0.05, 0.05)),
+        });
+    }
+
+    pub(super) fn spawn_effects(
+        mut messages: EventReader<MessageEvent<DestructionEffect>>,
+        particles: Query<(), With<Particle>>,
+        camera: Query<&GlobalTransform, With<MainCamera>>,
+        assets: Res<EffectAssets>,
+        time: Res<Time>,
+        mut commands: Commands,
+    ) {
+        let camera_position = camera.get_single().ok().map(|t| t.translation());
+        let mut alive = particles.iter().count();
+        let now = time.elapsed_seconds();
+
+        for event in messages.iter() {
+            let effect = event.message;
+            if camera_position
+                .is_some_and(|camera| camera.distance(effect.position) > CULL_DISTANCE)
+            {
+                continue;
+            }
+
+            let style = effect.kind.style();
+            let count = (style.count * effect.magnitude.clamp(0.1, 4.0)).round() as usize;
+            let count = count.min(MAX_PARTICLES.saturating_sub(alive));
+            alive += count;
+            for _ in 0..count {
+                // Cosmetic, so it doesn't matter that every client sees different particles
+                let direction = Vec3::new(
+                    fastrand::f32() * 2.0 - 1.0,
+                    fastrand::f32(),
+                    fastrand::f32() * 2.0 - 1.0,
+                )
+                .normalize_or_zero();
+                let speed = style.speed * (0.5 + fastrand::f32());
+                commands.spawn((
+                    PbrBundle {
+                        mesh: assets.quad.clone(),
+                        material: effect.kind.material(&assets),
+                        transform: Transform::from_translation(effect.position)
+                            .with_scale(Vec3::splat(style.size)),
+                        ..Default::default()
+                    },
+                    Particle {
+                        velocity: direction * speed,
+                        falls: style.falls,
+                        expires: now + style.lifetime * (0.75 + fastrand::f32() * 0.5),
+                    },
+                ));
+            }
+        }
+    }
+
+    /// Moves particles and turns them towards the camera.
+    pub(super) fn update_particles(
+        mut particles: Query<(Entity, &mut Particle, &mut Transform)>,
+        camera: Query<&GlobalTransform, With<MainCamera>>,
+        time: Res<Time>,
+        mut commands: Commands,
+    ) {
+        let now = time.elapsed_seconds();
+        let delta = time.delta_seconds();
+        let camera_rotation = camera
+            .get_single()
+            .ok()
+            .map(|t| t.compute_transform().rotation);
+
+        for (entity, mut particle, mut transform) in particles.iter_mut() {
+            if particle.expires <= now {
+                commands.entity(entity).despawn();
+                continue;
+            }
+
+            if particle.falls {
+                particle.velocity.y -= GRAVITY * delta;
+            }
+            transform.translation += particle.velocity * delta;
+            // Stop on the floor
+            if transform.translation.y < 0.0 {
+                transform.translation.y = 0.0;
+                particle.velocity = Vec3::ZERO;
+            }
+            if let Some(rotation) = camera_rotation {
+                transform.rotation = rotation;
+            }
+        }
+    }
+
+    pub(super) fn play_effect_sounds(
+        mut messages: EventReader<MessageEvent<DestructionEffect>>,
+        mut sounds: EventWriter<crate::sound::PlaySoundMessage>,
+    ) {
+        use crate::sound::{ImpactMaterial, PlaySoundMessage, SoundId};
+
+        for event in messages.iter() {
+            let effect = event.message;
+            let (sound, impact) = match effect.kind {
+                EffectKind::Sparks => (SoundId::Sparks, None),
+                EffectKind::Debris | EffectKind::Dust => (SoundId::Debris, None),
+                EffectKind::WoodBreak => (SoundId::Break, Some(ImpactMaterial::Wood)),
+                EffectKind::MetalBreak => (SoundId::Break, Some(ImpactMaterial::Metal)),
+                EffectKind::GlassBreak => (SoundId::Break, Some(ImpactMaterial::Glass)),
+                EffectKind::Gore => (SoundId::Gore, None),
+            };
+            sounds.send(PlaySoundMessage {
+                sound,
+                position: effect.position,
+                surface: None,
+                impact,
+            });
+        }
+    }
+}
@@ -0,0 +1,487 @@
+use std::time::Duration;
+
+use bevy::{
+    prelude::*,
+    utils::{HashSet, Uuid},
+};
+use maps::{world_to_tile, MapCommandsExt, TileMap};
+use networking::{
+    is_server,
+    messaging::{AppExt, MessageReceivers, MessageSender},
+    scene::NetworkSceneBundle,
+    spawning::ClientControls,
+    visibility::{NetworkObserver, NetworkObserverBundle},
+    ConnectionId, Players,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    body::{
+        health::{VitalStatus, Vitals},
+        Body,
+    },
+    communication::AnnouncementEvent,
+    config::ServerConfig,
+    feedback::{ActionFeedback, Feedback, FeedbackKind},
+    interaction::{
+        ActiveInteraction, GenerateInteractionList, InteractionListEvents, InteractionOption,
+        InteractionSpecificity, InteractionStatus,
+    },
+    items::Item,
+    movement::ForcePositionMessage,
+    round::RoundState,
+    shuttle::ShuttleGrid,
+};
+
+#[cfg(feature = "client")]
+use {
+    crate::ui::has_window,
+    bevy_egui::{egui, EguiContexts},
+    networking::messaging::MessageEvent,
+};
+
+/// Escape pods placed by the map, a way off the station for those that miss the shuttle.
+/// Everyone in the pod's tiles when the countdown ends leaves the round as a survivor.
+pub struct EscapePodPlugin;
+
+impl Plugin for EscapePodPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<EscapePod>()
+            .add_network_message::<PodCountdown>("PodCountdown");
+
+        if is_server(app) {
+            let config = app
+                .world
+                .get_resource::<ServerConfig>()
+                .map(|config| config.escape_pods.clone())
+                .unwrap_or_default();
+            app.insert_resource(config)
+                .init_resource::<Escapes>()
+                .register_type::<LaunchPodInteraction>()
+                .add_systems(OnEnter(RoundState::Running), reset_escapes)
+                .add_systems(OnEnter(RoundState::Ended), announce_escapes)
+                .add_systems(
+                    Update,
+                    (
+                        prepare_launch_interaction.in_set(GenerateInteractionList),
+                        launch_interaction,
+                        update_launches.run_if(in_state(RoundState::Running)),
+                    ),
+                );
+        } else {
+            #[cfg(feature = "client")]
+            app.init_resource::<ClientPodCountdown>().add_systems(
+                Update,
+                (receive_countdown, countdown_ui.run_if(has_window)).chain(),
+            );
+        }
+    }
+}
+
+/// Seconds it takes to press the launch button
+const LAUNCH_INTERACTION_TIME: Duration = Duration::from_secs(1);
+
+#[derive(Resource, Deserialize, Clone)]
+#[serde(default)]
+pub struct EscapePodConfig {
+    /// If pods can only launch once the evacuation shuttle has been called
+    pub require_evacuation: bool,
+}
+
+impl Default for EscapePodConfig {
+    fn default() -> Self {
+        Self {
+            require_evacuation: true,
+        }
+    }
+}
+
+/// The launch console of an escape pod. The pod is a rectangle of tiles next to it.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct EscapePod {
+    /// Width and depth of the pod in tiles
+    pub size: UVec2,
+    /// Position of the pod's first tile relative to the console's tile
+    pub offset: IVec2,
+    /// How many bodies the pod carries
+    pub capacity: u32,
+    /// Seconds between pressing launch and leaving
+    pub countdown: f32,
+}
+
+impl EscapePod {
+    fn contains(&self, console_tile: UVec2, tile: UVec2) -> bool {
+        let local = tile.as_ivec2() - console_tile.as_ivec2() - self.offset;
+        local.cmpge(IVec2::ZERO).all() && local.cmplt(self.size.as_ivec2()).all()
+    }
+}
+
+/// A pod counting down to its launch.
+#[derive(Component)]
+struct PodLaunch {
+    launches_at: f32,
+    /// Connections that were told about the countdown
+    notified: HashSet<ConnectionId>,
+}
+
+/// Tells a player in a pod when it launches, or that they aren't leaving with it anymore.
+#[derive(Serialize, Deserialize)]
+struct PodCountdown {
+    launches_in: Option<f32>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EscapeRoute {
+    Pod,
+}
+
+pub struct Escapee {
+    pub name: String,
+    pub player: Option<Uuid>,
+    pub route: EscapeRoute,
+    /// Names of the items they took along
+    pub carried: Vec<String>,
+}
+
+/// Everyone that left the station alive this round, other than on the shuttle.
+/// Shuttle passengers are still in the world at the end of the round, so they are counted there.
+#[derive(Resource, Default)]
+pub struct Escapes {
+    escapees: Vec<Escapee>,
+}
+
+impl Escapes {
+    /// How a player escaped, if they did.
+    pub fn get(&self, player: Uuid) -> Option<&Escapee> {
+        self.escapees.iter().find(|e| e.player == Some(player))
+    }
+}
+
+fn reset_escapes(mut escapes: ResMut<Escapes>) {
+    escapes.escapees.clear();
+}
+
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+#[component(storage = "SparseSet")]
+struct LaunchPodInteraction {
+    target: Entity,
+}
+
+// Dummy default for Reflect
+impl Default for LaunchPodInteraction {
+    fn default() -> Self {
+        Self {
+            target: Entity::from_raw(0),
+        }
+    }
+}
+
+fn prepare_launch_interaction(
+    list: Res<InteractionListEvents>,
+    pods: Query<(), (With<EscapePod>, Without<PodLaunch>)>,
+) {
+    for event in list.events.iter() {
+        if !pods.contains(event.target) {
+            continue;
+        }
+
+        event.add_interaction(InteractionOption {
+            text: "Launch pod".into(),
+            interaction: Box::new(LaunchPodInteraction {
+                target: event.target,
+            }),
+            specificity: InteractionSpecificity::Specific,
+        });
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn launch_interaction(
+    mut query: Query<(Entity, &LaunchPodInteraction, &mut ActiveInteraction)>,
+    pods: Query<(&EscapePod, &GlobalTransform), Without<PodLaunch>>,
+    bodies: Query<&GlobalTransform, With<Body>>,
+    shuttles: Query<&ShuttleGrid>,
+    config: Res<EscapePodConfig>,
+    time: Res<Time>,
+    mut feedback: Feedback,
+    mut commands: Commands,
+) {
+    for (source, interaction, mut active) in query.iter_mut() {
+        active.set_initial_duration(LAUNCH_INTERACTION_TIME);
+
+        let Ok((pod, pod_transform)) = pods.get(interaction.target) else {
+            active.status = InteractionStatus::Canceled;
+            continue;
+        };
+        if active.start_time() + LAUNCH_INTERACTION_TIME.as_secs_f32() > time.elapsed_seconds() {
+            continue;
+        }
+
+        let Some(console_tile) = world_to_tile(pod_transform.translation()) else {
+            active.status = InteractionStatus::Canceled;
+            continue;
+        };
+        let inside = |transform: &GlobalTransform| {
+            world_to_tile(transform.translation()).is_some_and(|t| pod.contains(console_tile, t))
+        };
+        let rejection = if !bodies.get(source).is_ok_and(inside) {
+            Some(("pod.not_inside", Vec::new()))
+        } else if bodies.iter().filter(|t| inside(*t)).count() > pod.capacity as usize {
+            Some(("pod.full", vec![pod.capacity.to_string()]))
+        } else if config.require_evacuation && !shuttles.iter().any(|s| s.evacuation_called()) {
+            Some(("pod.no_evacuation", Vec::new()))
+        } else {
+            None
+        };
+        if let Some((key, args)) = rejection {
+            let args: Vec<_> = args.iter().map(String::as_str).collect();
+            feedback.send_to_creature(source, FeedbackKind::Blocked, key, &args);
+            active.status = InteractionStatus::Canceled;
+            continue;
+        }
+
+        info!(pod = ?interaction.target, countdown = pod.countdown, "Escape pod launch started");
+        commands.entity(interaction.target).insert(PodLaunch {
+            launches_at: time.elapsed_seconds() + pod.countdown,
+            notified: HashSet::default(),
+        });
+        active.status = InteractionStatus::Completed;
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn update_launches(
+    mut pods: Query<(Entity, &EscapePod, &mut PodLaunch, &GlobalTransform)>,
+    bodies: Query<(Entity, &GlobalTransform, Option<&Name>), With<Body>>,
+    maps: Query<&TileMap>,
+    children: Query<&Children>,
+    items: Query<&Item>,
+    vitals: Vitals,
+    players: Res<Players>,
+    mut controls: ResMut<ClientControls>,
+    mut escapes: ResMut<Escapes>,
+    asset_server: Res<AssetServer>,
+    time: Res<Time>,
+    mut sender: MessageSender,
+    mut commands: Commands,
+) {
+    let now = time.elapsed_seconds();
+    for (pod_entity, pod, mut launch, pod_transform) in pods.iter_mut() {
+        let Some(console_tile) = world_to_tile(pod_transform.translation()) else {
+            continue;
+        };
+        // Whoever stepped out during the countdown stays behind
+        let occupants: Vec<_> = bodies
+            .iter()
+            .filter(|(_, transform, _)| {
+                world_to_tile(transform.translation())
+                    .is_some_and(|t| pod.contains(console_tile, t))
+            })
+            .collect();
+        let connections: HashSet<_> = occupants
+            .iter()
+            .filter_map(|(body, ..)| controls.controlling_player(*body))
+            .filter_map(|player| players.get_connection(&player))
+            .collect();
+
+        let launching = now >= launch.launches_at;
+        let overloaded = occupants.len() > pod.capacity as usize;
+        for &connection in launch.notified.difference(&connections) {
+            sender.send(
+                &PodCountdown { launches_in: None },
+                MessageReceivers::Single(connection),
+            );
+        }
+        if launching && overloaded {
+            for &connection in connections.iter() {
+                sender.send(
+                    &PodCountdown { launches_in: None },
+                    MessageReceivers::Single(connection),
+                );
+                sender.send(
+                    &ActionFeedback {
+                        kind: FeedbackKind::Blocked,
+                        text_key: "pod.full".into(),
+                        args: vec![pod.capacity.to_string()],
+                    },
+                    MessageReceivers::Single(connection),
+                );
+            }
+            info!(pod = ?pod_entity, occupants = occupants.len(), "Escape pod launch aborted");
+            commands.entity(pod_entity).remove::<PodLaunch>();
+            continue;
+        }
+        if !launching {
+            for &connection in connections.difference(&launch.notified) {
+                sender.send(
+                    &PodCountdown {
+                        launches_in: Some(launch.launches_at - now),
+                    },
+                    MessageReceivers::Single(connection),
+                );
+            }
+            launch.notified = connections;
+            continue;
+        }
+
+        let mut survivors = 0;
+        for &(body, transform, name) in occupants.iter() {
+            let player = controls.controlling_player(body);
+            // Dead bodies are carried off, but nobody survived in them
+            if vitals.status(body).is_some_and(|s| s != VitalStatus::Dead) {
+                survivors += 1;
+                escapes.escapees.push(Escapee {
+                    name: name.map(|n| n.as_str()).unwrap_or("Unknown").to_owned(),
+                    player,
+                    route: EscapeRoute::Pod,
+                    carried: children
+                        .iter_descendants(body)
+                        .filter_map(|e| items.get(e).ok())
+                        .map(|item| item.name.clone())
+                        .collect(),
+                });
+            }
+            commands.entity(body).despawn_recursive();
+
+            let Some(player) = player else {
+                continue;
+            };
+            let position = transform.translation();
+            let ghost = commands
+                .spawn((
+                    NetworkSceneBundle {
+                        scene: asset_server.load("creatures/ghost.scn.ron").into(),
+                        transform: Transform::from_translation(position),
+                        ..Default::default()
+                    },
+                    NetworkObserverBundle {
+                        observer: NetworkObserver {
+                            range: 1,
+                            player_id: player,
+                        },
+                        cells: Default::default(),
+                    },
+                    networking::transform::ClientMovement,
+                ))
+                .id();
+            controls.give_control(player, ghost);
+            if let Some(connection) = players.get_connection(&player) {
+                sender.send(
+                    &PodCountdown { launches_in: None },
+                    MessageReceivers::Single(connection),
+                );
+                sender.send_with_priority(
+                    &ForcePositionMessage {
+                        position,
+                        rotation: Quat::IDENTITY,
+                    },
+                    MessageReceivers::Single(connection),
+                    10,
+                );
+            }
+        }
+
+        // The pod's seats and the console leave with it
+        for map in maps.iter() {
+            for x in 0..pod.size.x {
+                for y in 0..pod.size.y {
+                    let tile =
+                        console_tile.as_ivec2() + pod.offset + IVec2::new(x as i32, y as i32);
+                    if tile.min_element() < 0 {
+                        continue;
+                    }
+                    if let Some(furniture) = map.tile(tile.as_uvec2()).and_then(|t| t.furniture) {
+                        commands.despawn_tile_entity(furniture);
+                    }
+                }
+            }
+        }
+        commands.despawn_tile_entity(pod_entity);
+        info!(
+            pod = ?pod_entity,
+            occupants = occupants.len(),
+            survivors,
+            "Escape pod launched"
+        );
+    }
+}
+
+fn announce_escapes(
+    escapes: Res<Escapes>,
+    bodies: Query<Entity, With<Body>>,
+    parents: Query<&Parent>,
+    shuttles: Query<(), With<ShuttleGrid>>,
+    vitals: Vitals,
+    mut announcements: EventWriter<AnnouncementEvent>,
+) {
+    let shuttle = bodies
+        .iter()
+        .filter(|&body| {
+            vitals.status(body).is_some_and(|s| s != VitalStatus::Dead)
+                && parents.iter_ancestors(body).any(|e| shuttles.contains(e))
+        })
+        .count();
+    let pods: Vec<_> = escapes
+        .escapees
+        .iter()
+        .filter(|e| e.route == EscapeRoute::Pod)
+        .map(|e| e.name.as_str())
+        .collect();
+
+    let text = if pods.is_empty() {
+        format!(
+            "Escapes: {} on the shuttle, nobody in escape pods.",
+            shuttle
+        )
+    } else {
+        format!(
+            "Escapes: {} on the shuttle, {} in escape pods ({}).",
+            shuttle,
+            pods.len(),
+            pods.join(", ")
+        )
+    };
+    info!(text = text.as_str(), "Escape summary");
+    announcements.send(AnnouncementEvent::custom(text));
+}
+
+/// When the pod the player is in launches, in client time.
+#[cfg(feature = "client")]
+#[derive(Resource, Default)]
+struct ClientPodCountdown {
+    launches_at: Option<f32>,
+}
+
+#[cfg(feature = "client")]
+fn receive_countdown(
+    mut messages: EventReader<MessageEvent<PodCountdown>>,
+    mut countdown: ResMut<ClientPodCountdown>,
+    time: Res<Time>,
+) {
+    for event in messages.iter() {
+        countdown.launches_at = event
+            .message
+            .launches_in
+            .map(|seconds| time.elapsed_seconds() + seconds);
+    }
+}
+
+#[cfg(feature = "client")]
+fn countdown_ui(mut contexts: EguiContexts, countdown: Res<ClientPodCountdown>, time: Res<Time>) {
+    let Some(launches_at) = countdown.launches_at else {
+        return;
+    };
+
+    let remaining = (launches_at - time.elapsed_seconds()).max(0.0);
+    egui::Area::new("escape_pod_countdown")
+        .anchor(egui::Align2::CENTER_TOP, egui::vec2(0.0, 60.0))
+        .interactable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.colored_label(
+                egui::Color32::from_rgb(255, 170, 80),
+                format!("Escape pod launching in {:.0}", remaining.ceil()),
+            );
+        });
+}
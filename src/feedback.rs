@@ -0,0 +1,222 @@
+use bevy::{ecs::system::SystemParam, prelude::*};
+use networking::{
+    messaging::{AppExt, MessageReceivers, MessageSender},
+    spawning::ClientControls,
+    ConnectionId, Players,
+};
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "client")]
+use {
+    crate::{
+        camera::MainCamera,
+        locale::Locale,
+        sound::{PlaySoundMessage, SoundId},
+        ui::has_window,
+    },
+    bevy_egui::{egui, EguiContexts},
+    networking::{is_server, messaging::MessageEvent},
+};
+
+/// Tells players why an action they tried didn't work, instead of it silently doing nothing.
+/// The client shows a short toast above the hands and may play a sound for it.
+pub struct FeedbackPlugin;
+
+impl Plugin for FeedbackPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_network_message::<ActionFeedback>("ActionFeedback");
+
+        #[cfg(feature = "client")]
+        if !is_server(app) {
+            app.init_resource::<FeedbackToasts>().add_systems(
+                Update,
+                (receive_feedback, feedback_toasts.run_if(has_window)).chain(),
+            );
+        }
+    }
+}
+
+/// What kind of action was rejected. Toasts of the same kind replace each other instead of stacking.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FeedbackKind {
+    OutOfReach,
+    AccessDenied,
+    HandsFull,
+    NoPower,
+    /// An item doesn't fit into a container
+    WontFit,
+    WrongTool,
+    /// Anything else that prevents the action
+    Blocked,
+    /// The action wasn't rejected, it runs once the creature is free again
+    Queued,
+}
+
+impl FeedbackKind {
+    #[cfg(feature = "client")]
+    fn sound(self) -> Option<SoundId> {
+        match self {
+            FeedbackKind::AccessDenied | FeedbackKind::NoPower => Some(SoundId::Denied),
+            _ => None,
+        }
+    }
+}
+
+/// Server message telling a player why their action failed.
+/// The text is looked up by key on the client, see [`crate::locale`].
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ActionFeedback {
+    pub kind: FeedbackKind,
+    pub text_key: String,
+    pub args: Vec<String>,
+}
+
+/// Sends [`ActionFeedback`] to players.
+#[derive(SystemParam)]
+pub struct Feedback<'w, 's> {
+    sender: MessageSender<'w, 's>,
+    controls: Res<'w, ClientControls>,
+    players: Res<'w, Players>,
+}
+
+impl<'w, 's> Feedback<'w, 's> {
+    pub fn send(&mut self, connection: ConnectionId, kind: FeedbackKind, key: &str, args: &[&str]) {
+        self.sender.send(
+            &ActionFeedback {
+                kind,
+                text_key: key.to_owned(),
+                args: args.iter().map(|&a| a.to_owned()).collect(),
+            },
+            MessageReceivers::Single(connection),
+        );
+    }
+
+    /// Sends feedback to the player controlling a creature. Does nothing for creatures without a player.
+    pub fn send_to_creature(
+        &mut self,
+        creature: Entity,
+        kind: FeedbackKind,
+        key: &str,
+        args: &[&str],
+    ) {
+        if let Some(connection) = self
+            .controls
+            .controlling_player(creature)
+            .and_then(|player| self.players.get_connection(&player))
+        {
+            self.send(connection, kind, key, args);
+        }
+    }
+}
+
+/// Seconds a toast stays visible
+#[cfg(feature = "client")]
+const TOAST_DURATION: f32 = 2.5;
+/// At most this many toasts are shown, older ones are dropped
+#[cfg(feature = "client")]
+const MAX_TOASTS: usize = 3;
+/// A sound for the same kind of feedback isn't played again within this many seconds
+#[cfg(feature = "client")]
+const SOUND_COOLDOWN: f32 = 0.5;
+
+#[cfg(feature = "client")]
+#[derive(Resource, Default)]
+struct FeedbackToasts {
+    toasts: Vec<Toast>,
+}
+
+#[cfg(feature = "client")]
+struct Toast {
+    kind: FeedbackKind,
+    text: String,
+    /// How often the feedback was received while shown
+    count: u32,
+    shown_at: f32,
+    until: f32,
+}
+
+#[cfg(feature = "client")]
+fn receive_feedback(
+    mut messages: EventReader<MessageEvent<ActionFeedback>>,
+    mut toasts: ResMut<FeedbackToasts>,
+    locale: Locale,
+    time: Res<Time>,
+    listener: Query<&GlobalTransform, With<MainCamera>>,
+    mut sounds: EventWriter<PlaySoundMessage>,
+) {
+    let now = time.elapsed_seconds();
+    toasts.toasts.retain(|t| t.until > now);
+
+    for event in messages.iter() {
+        let feedback = &event.message;
+        let text = locale.text(&feedback.text_key, &feedback.args);
+
+        let previous = toasts.toasts.iter().position(|t| t.kind == feedback.kind);
+        let play_sound =
+            previous.map_or(true, |i| now - toasts.toasts[i].shown_at > SOUND_COOLDOWN);
+        match previous {
+            Some(index) => {
+                let toast = &mut toasts.toasts[index];
+                toast.count = if toast.text == text {
+                    toast.count + 1
+                } else {
+                    1
+                };
+                toast.text = text;
+                toast.shown_at = now;
+                toast.until = now + TOAST_DURATION;
+            }
+            None => {
+                if toasts.toasts.len() >= MAX_TOASTS {
+                    toasts.toasts.remove(0);
+                }
+                toasts.toasts.push(Toast {
+                    kind: feedback.kind,
+                    text,
+                    count: 1,
+                    shown_at: now,
+                    until: now + TOAST_DURATION,
+                });
+            }
+        }
+
+        if let (true, Some(sound)) = (play_sound, feedback.kind.sound()) {
+            sounds.send(PlaySoundMessage {
+                sound,
+                position: listener
+                    .get_single()
+                    .map_or(Vec3::ZERO, |t| t.translation()),
+                surface: None,
+                impact: None,
+            });
+        }
+    }
+}
+
+#[cfg(feature = "client")]
+fn feedback_toasts(mut contexts: EguiContexts, toasts: Res<FeedbackToasts>, time: Res<Time>) {
+    let now = time.elapsed_seconds();
+    if toasts.toasts.iter().all(|t| t.until <= now) {
+        return;
+    }
+
+    egui::Area::new("action_feedback")
+        .anchor(egui::Align2::CENTER_BOTTOM, egui::vec2(0.0, -160.0))
+        .order(egui::Order::Foreground)
+        .interactable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            for toast in toasts.toasts.iter().filter(|t| t.until > now) {
+                // Fades out during the last half second
+                let alpha = ((toast.until - now) / 0.5).min(1.0);
+                let text = if toast.count > 1 {
+                    format!("{} (x{})", toast.text, toast.count)
+                } else {
+                    toast.text.clone()
+                };
+                ui.colored_label(
+                    egui::Color32::from_rgba_unmultiplied(255, 210, 120, (alpha * 255.0) as u8),
+                    text,
+                );
+            }
+        });
+}
@@ -0,0 +1,582 @@
+use bevy::{math::Vec3Swizzles, prelude::*, reflect::TypeUuid, utils::HashMap};
+use networking::{
+    component::AppExt as ComponentAppExt,
+    is_server,
+    variable::{NetworkVar, ServerVar},
+    Networked,
+};
+
+use crate::{
+    body::{Body, HeldItem},
+    construction::Welder,
+    decals::{DecalKind, DecalSender},
+    effects::{EffectKind, EffectSender},
+    interaction::{
+        ActiveInteraction, GenerateInteractionList, InteractionListEvents, InteractionOption,
+        InteractionSpecificity, InteractionStatus,
+    },
+    items::{clothes::ClothingHolder, durability::Broken},
+    vision::LineOfSight,
+};
+
+#[cfg(feature = "client")]
+use {
+    crate::{accessibility::AccessibilitySettings, ui::has_window},
+    bevy_egui::{egui, EguiContexts},
+    networking::spawning::ClientControlled,
+};
+
+/// Bright flashes from welding and flashbangs. Creatures looking at one are blinded for a while
+/// and hurt their eyes, unless they wear enough flash protection.
+pub struct FlashPlugin;
+
+impl Plugin for FlashPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<FlashProtection>()
+            .register_type::<Flashbang>()
+            .add_networked_component::<Flashed, FlashedClient>();
+
+        if is_server(app) {
+            app.register_type::<PrimeFlashbangInteraction>()
+                .add_event::<FlashSource>()
+                .add_event::<EyeDamageEvent>()
+                .add_systems(
+                    Update,
+                    (
+                        prepare_prime_interaction.in_set(GenerateInteractionList),
+                        prime_interaction,
+                        (welding_flashes, detonate_flashbangs, resolve_flashes).chain(),
+                        expire_flashed,
+                    ),
+                );
+        } else {
+            #[cfg(feature = "client")]
+            app.add_systems(Update, flash_overlay.run_if(has_window));
+        }
+    }
+}
+
+/// Height of the eyes above a creature's origin
+const EYE_HEIGHT: f32 = 1.5;
+/// Flashes this close are seen even while looking the other way
+const POINT_BLANK_DISTANCE: f32 = 0.3;
+/// How far to either side of where a creature is facing a flash can still be seen.
+/// 0 is a half circle in front of them.
+const MIN_FACING_DOT: f32 = 0.0;
+/// Eye damage for every second a flash blinds someone
+const EYE_DAMAGE_PER_SECOND: f32 = 0.01;
+/// Being blinded again extends the effect, up to this many seconds
+const MAX_FLASHED_SECONDS: f32 = 15.0;
+/// Seconds between flashes while welding
+const WELDING_FLASH_INTERVAL: f32 = 1.0;
+const WELDING_FLASH_RANGE: f32 = 3.0;
+const WELDING_FLASH_STRENGTH: f32 = 1.5;
+/// How much wider shots spread while blinded
+pub const FLASHED_SPREAD_MULTIPLIER: f32 = 4.0;
+/// Seconds the white overlay takes to fade out at the end
+#[cfg(feature = "client")]
+const OVERLAY_FADE_SECONDS: f32 = 2.0;
+
+/// Something bright went off.
+#[derive(Event)]
+pub struct FlashSource {
+    pub position: Vec3,
+    /// Creatures further away don't see it
+    pub range: f32,
+    /// Seconds someone looking at it from up close is blinded for
+    pub strength: f32,
+}
+
+/// A flash hurt a creature's eyes.
+#[derive(Event)]
+pub struct EyeDamageEvent {
+    pub body: Entity,
+    pub amount: f32,
+}
+
+/// Worn clothing that shields the eyes from flashes.
+/// The protection of everything worn is added up, 1 or more blocks flashes completely.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct FlashProtection {
+    pub amount: f32,
+}
+
+/// A creature that can't see much after a flash.
+#[derive(Component, Networked)]
+#[networked(client = "FlashedClient")]
+pub struct Flashed {
+    ends_at: f32,
+    /// Seconds left when the effect was last extended
+    remaining: NetworkVar<f32>,
+}
+
+#[derive(Component, Default, TypeUuid, Networked)]
+#[uuid = "3f9b6d21-8c4e-4a7f-b15d-e2a7c09f4b63"]
+#[networked(server = "Flashed")]
+pub struct FlashedClient {
+    remaining: ServerVar<f32>,
+}
+
+/// How strongly a creature at `eye` facing `facing` sees a flash, or `None` if it doesn't.
+/// Line of sight is checked separately.
+fn exposure(eye: Vec3, facing: Vec3, flash: Vec3, range: f32) -> Option<f32> {
+    let offset = flash - eye;
+    let distance = offset.length();
+    if distance > range {
+        return None;
+    }
+    if distance > POINT_BLANK_DISTANCE {
+        let direction = offset.xz().normalize_or_zero();
+        if facing.xz().normalize_or_zero().dot(direction) < MIN_FACING_DOT {
+            return None;
+        }
+    }
+    // Half as strong at the edge of the range
+    Some(1.0 - 0.5 * distance / range)
+}
+
+/// Blinds creatures that see a flash and hurts their eyes.
+#[allow(clippy::too_many_arguments)]
+fn resolve_flashes(
+    mut events: EventReader<FlashSource>,
+    creatures: Query<(Entity, &GlobalTransform), With<Body>>,
+    mut flashed: Query<&mut Flashed>,
+    children: Query<&Children>,
+    protection: Query<(&Parent, &FlashProtection)>,
+    holders: Query<(), With<ClothingHolder>>,
+    sight: LineOfSight,
+    time: Res<Time>,
+    mut eye_damage: EventWriter<EyeDamageEvent>,
+    mut commands: Commands,
+) {
+    // Several flashes in the same frame add up to one longer effect
+    let mut blinded = HashMap::<Entity, f32>::default();
+    for event in events.iter() {
+        for (creature, transform) in creatures.iter() {
+            let eye = transform.translation() + Vec3::Y * EYE_HEIGHT;
+            // Creatures face along their local Z axis
+            let Some(seen) = exposure(eye, transform.back(), event.position, event.range) else {
+                continue;
+            };
+            // Walls block the light, windows let it through
+            if !sight.clear(eye, event.position) {
+                continue;
+            }
+
+            // Only worn clothing protects
+            let shielded: f32 = children
+                .iter_descendants(creature)
+                .filter_map(|e| protection.get(e).ok())
+                .filter(|(parent, _)| holders.contains(parent.get()))
+                .map(|(_, protection)| protection.amount)
+                .sum();
+            let seconds = event.strength * seen * (1.0 - shielded).max(0.0);
+            if seconds <= 0.0 {
+                continue;
+            }
+
+            eye_damage.send(EyeDamageEvent {
+                body: creature,
+                amount: seconds * EYE_DAMAGE_PER_SECOND,
+            });
+            *blinded.entry(creature).or_default() += seconds;
+        }
+    }
+
+    let now = time.elapsed_seconds();
+    for (creature, seconds) in blinded {
+        match flashed.get_mut(creature) {
+            Ok(mut flashed) => {
+                flashed.ends_at =
+                    (flashed.ends_at.max(now) + seconds).min(now + MAX_FLASHED_SECONDS);
+                *flashed.remaining = flashed.ends_at - now;
+            }
+            Err(_) => {
+                let seconds = seconds.min(MAX_FLASHED_SECONDS);
+                commands.entity(creature).insert(Flashed {
+                    ends_at: now + seconds,
+                    remaining: seconds.into(),
+                });
+            }
+        }
+        debug!(creature = ?creature, seconds, "Flashed");
+    }
+}
+
+fn expire_flashed(flashed: Query<(Entity, &Flashed)>, time: Res<Time>, mut commands: Commands) {
+    let now = time.elapsed_seconds();
+    for (entity, flashed) in flashed.iter() {
+        if flashed.ends_at <= now {
+            commands.entity(entity).remove::<Flashed>();
+        }
+    }
+}
+
+/// Welding flashes every now and then, right in front of the welder.
+fn welding_flashes(
+    users: Query<(Entity, &ActiveInteraction, &GlobalTransform)>,
+    targets: Query<&GlobalTransform>,
+    welders: Query<(), (With<Welder>, Without<Broken>)>,
+    held_item: HeldItem,
+    time: Res<Time>,
+    mut last_flash: Local<HashMap<Entity, f32>>,
+    mut flashes: EventWriter<FlashSource>,
+) {
+    let now = time.elapsed_seconds();
+    let mut welding = HashMap::default();
+    for (user, active, transform) in users.iter() {
+        if !active.is_timed() || !held_item.get(user).is_some_and(|i| welders.contains(i)) {
+            continue;
+        }
+        if let Some(&last) = last_flash
+            .get(&user)
+            .filter(|&&t| now - t < WELDING_FLASH_INTERVAL)
+        {
+            welding.insert(user, last);
+            continue;
+        }
+        welding.insert(user, now);
+
+        // Just in front of the welder, so the wall being welded doesn't block the light
+        let origin = transform.translation();
+        let toward = targets
+            .get(active.target)
+            .map(|t| (t.translation() - origin).xz().normalize_or_zero())
+            .unwrap_or_else(|_| transform.back().xz());
+        flashes.send(FlashSource {
+            position: origin + Vec3::new(toward.x * 0.3, 1.0, toward.y * 0.3),
+            range: WELDING_FLASH_RANGE,
+            strength: WELDING_FLASH_STRENGTH,
+        });
+    }
+    *last_flash = welding;
+}
+
+/// A grenade that goes off with a blinding flash.
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct Flashbang {
+    /// Seconds between priming and going off
+    fuse: f32,
+    range: f32,
+    strength: f32,
+}
+
+impl Default for Flashbang {
+    fn default() -> Self {
+        Self {
+            fuse: 3.0,
+            range: 7.0,
+            strength: 6.0,
+        }
+    }
+}
+
+/// A flashbang that will go off.
+#[derive(Component)]
+struct Primed {
+    detonates_at: f32,
+}
+
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+#[component(storage = "SparseSet")]
+struct PrimeFlashbangInteraction {
+    flashbang: Entity,
+}
+
+// Dummy implementation for reflection
+impl Default for PrimeFlashbangInteraction {
+    fn default() -> Self {
+        Self {
+            flashbang: Entity::from_raw(0),
+        }
+    }
+}
+
+fn prepare_prime_interaction(
+    list: Res<InteractionListEvents>,
+    flashbangs: Query<(), (With<Flashbang>, Without<Primed>)>,
+    held_item: HeldItem,
+) {
+    for event in list.events.iter() {
+        if !flashbangs.contains(event.target)
+            || !held_item.all(event.source).any(|i| i == event.target)
+        {
+            continue;
+        }
+
+        event.add_interaction(InteractionOption {
+            text: "Prime".into(),
+            interaction: Box::new(PrimeFlashbangInteraction {
+                flashbang: event.target,
+            }),
+            specificity: InteractionSpecificity::Specific,
+        });
+    }
+}
+
+fn prime_interaction(
+    mut query: Query<(&PrimeFlashbangInteraction, &mut ActiveInteraction)>,
+    flashbangs: Query<&Flashbang, Without<Primed>>,
+    time: Res<Time>,
+    mut commands: Commands,
+) {
+    for (interaction, mut active) in query.iter_mut() {
+        let Ok(flashbang) = flashbangs.get(interaction.flashbang) else {
+            active.status = InteractionStatus::Canceled;
+            continue;
+        };
+        commands.entity(interaction.flashbang).insert(Primed {
+            detonates_at: time.elapsed_seconds() + flashbang.fuse,
+        });
+        active.status = InteractionStatus::Completed;
+    }
+}
+
+fn detonate_flashbangs(
+    flashbangs: Query<(Entity, &Flashbang, &Primed, &GlobalTransform)>,
+    time: Res<Time>,
+    mut flashes: EventWriter<FlashSource>,
+    mut effects: EffectSender,
+    mut decals: DecalSender,
+    mut commands: Commands,
+) {
+    for (entity, flashbang, primed, transform) in flashbangs.iter() {
+        if primed.detonates_at > time.elapsed_seconds() {
+            continue;
+        }
+        let position = transform.translation();
+        flashes.send(FlashSource {
+            position,
+            range: flashbang.range,
+            strength: flashbang.strength,
+        });
+        effects.send(EffectKind::Sparks, position, 2.0);
+        decals.send(DecalKind::Scorch, position, None, 1.2);
+        info!(entity = ?entity, "Flashbang went off");
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// Whites out the screen of the local player while they are blinded.
+#[cfg(feature = "client")]
+fn flash_overlay(
+    mut contexts: EguiContexts,
+    flashed: Query<Ref<FlashedClient>, With<ClientControlled>>,
+    accessibility: Res<AccessibilitySettings>,
+    time: Res<Time>,
+    mut effect: Local<Option<(f32, f32)>>,
+) {
+    let now = time.elapsed_seconds();
+    match flashed.get_single() {
+        // Extending the effect restarts the countdown from the new length
+        Ok(flashed) if flashed.is_changed() => {
+            *effect = Some((now, *flashed.remaining));
+        }
+        Ok(_) => {}
+        Err(_) => *effect = None,
+    }
+    let Some((started, length)) = *effect else {
+        return;
+    };
+
+    let Some(color) = overlay_color(length - (now - started), &accessibility) else {
+        return;
+    };
+    let ctx = contexts.ctx_mut();
+    ctx.layer_painter(egui::LayerId::new(
+        egui::Order::Foreground,
+        egui::Id::new("flash_overlay"),
+    ))
+    .rect_filled(ctx.screen_rect(), 0.0, color);
+}
+
+/// The overlay with `left` seconds of the effect to go, `None` once it faded out.
+/// With reduced flashing the screen goes dark instead, which hides just as much.
+#[cfg(feature = "client")]
+fn overlay_color(left: f32, accessibility: &AccessibilitySettings) -> Option<egui::Color32> {
+    let alpha = (left / OVERLAY_FADE_SECONDS).clamp(0.0, 1.0);
+    if alpha <= 0.0 {
+        return None;
+    }
+    let alpha = (alpha * 255.0) as u8;
+    Some(if accessibility.reduce_flashing {
+        egui::Color32::from_rgba_unmultiplied(20, 20, 24, alpha)
+    } else {
+        egui::Color32::from_white_alpha(alpha)
+    })
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::time::TimeUpdateStrategy;
+
+    use super::*;
+    use crate::{config::ServerConfig, testing::server_app};
+
+    const FRAME: Duration = Duration::from_millis(100);
+    /// Two meters in front of a creature at the origin, at eye height
+    const IN_FRONT: Vec3 = Vec3::new(0.0, EYE_HEIGHT, 2.0);
+    const BEHIND: Vec3 = Vec3::new(0.0, EYE_HEIGHT, -2.0);
+    /// Seconds a flash of strength 4 and range 4 blinds from two meters away
+    const BLINDED_SECONDS: f32 = 3.0;
+
+    #[derive(Resource, Default)]
+    struct EyeDamage(f32);
+
+    fn record_eye_damage(mut events: EventReader<EyeDamageEvent>, mut damage: ResMut<EyeDamage>) {
+        damage.0 += events.iter().map(|event| event.amount).sum::<f32>();
+    }
+
+    /// A creature at the origin, facing along +Z.
+    fn setup() -> (App, Entity) {
+        let mut app = server_app(ServerConfig::default());
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(FRAME))
+            .init_resource::<EyeDamage>()
+            .add_systems(Update, record_eye_damage.after(resolve_flashes));
+        let creature = app
+            .world
+            .spawn((Body::default(), SpatialBundle::default()))
+            .id();
+        app.update();
+        (app, creature)
+    }
+
+    fn flash(app: &mut App, position: Vec3) {
+        app.world.send_event(FlashSource {
+            position,
+            range: 4.0,
+            strength: 4.0,
+        });
+        app.update();
+    }
+
+    fn remaining(app: &App, creature: Entity) -> Option<f32> {
+        app.world
+            .get::<Flashed>(creature)
+            .map(|flashed| *flashed.remaining)
+    }
+
+    /// Puts on something that protects the eyes.
+    fn wear(app: &mut App, creature: Entity, amount: f32) {
+        let holder = ClothingHolder::from_world(&mut app.world);
+        let holder = app.world.spawn(holder).set_parent(creature).id();
+        app.world
+            .spawn(FlashProtection { amount })
+            .set_parent(holder);
+    }
+
+    #[test]
+    fn flash_behind_does_nothing() {
+        let (mut app, creature) = setup();
+        flash(&mut app, BEHIND);
+        assert_eq!(remaining(&app, creature), None);
+        assert_eq!(app.world.resource::<EyeDamage>().0, 0.0);
+
+        flash(&mut app, IN_FRONT);
+        let seconds = remaining(&app, creature).unwrap();
+        assert!((seconds - BLINDED_SECONDS).abs() < 1e-4, "{}", seconds);
+        let damage = app.world.resource::<EyeDamage>().0;
+        assert!((damage - BLINDED_SECONDS * EYE_DAMAGE_PER_SECOND).abs() < 1e-6);
+    }
+
+    #[test]
+    fn point_blank_flash_is_seen_from_behind() {
+        let (mut app, creature) = setup();
+        flash(&mut app, Vec3::new(0.0, EYE_HEIGHT, -0.2));
+        assert!(remaining(&app, creature).is_some());
+    }
+
+    #[test]
+    fn protection_negates_the_flash() {
+        let (mut app, creature) = setup();
+        // Pieces add up, going over full protection is fine
+        wear(&mut app, creature, 0.75);
+        wear(&mut app, creature, 0.5);
+        flash(&mut app, IN_FRONT);
+
+        assert_eq!(remaining(&app, creature), None);
+        assert_eq!(app.world.resource::<EyeDamage>().0, 0.0);
+    }
+
+    #[test]
+    fn partial_protection_shortens_the_flash() {
+        let (mut app, creature) = setup();
+        wear(&mut app, creature, 0.5);
+        // Protection that isn't worn doesn't count
+        app.world
+            .spawn(FlashProtection { amount: 1.0 })
+            .set_parent(creature);
+        flash(&mut app, IN_FRONT);
+
+        let seconds = remaining(&app, creature).unwrap();
+        assert!(
+            (seconds - BLINDED_SECONDS / 2.0).abs() < 1e-4,
+            "{}",
+            seconds
+        );
+    }
+
+    #[test]
+    fn flashes_extend_the_effect() {
+        let (mut app, creature) = setup();
+        flash(&mut app, IN_FRONT);
+        let first = remaining(&app, creature).unwrap();
+        flash(&mut app, IN_FRONT);
+
+        // Still one effect, now lasting for both flashes minus the frame in between
+        let seconds = remaining(&app, creature).unwrap();
+        let expected = first + BLINDED_SECONDS - FRAME.as_secs_f32();
+        assert!((seconds - expected).abs() < 1e-3, "{}", seconds);
+
+        // But never longer than the cap
+        for _ in 0..10 {
+            flash(&mut app, IN_FRONT);
+        }
+        let seconds = remaining(&app, creature).unwrap();
+        assert!(seconds <= MAX_FLASHED_SECONDS + 1e-4, "{}", seconds);
+
+        // And it wears off on its own
+        for _ in 0..((MAX_FLASHED_SECONDS / FRAME.as_secs_f32()) as u32 + 2) {
+            app.update();
+        }
+        assert_eq!(remaining(&app, creature), None);
+    }
+
+    #[cfg(feature = "client")]
+    #[test]
+    fn reduced_flashing_darkens_the_screen_for_just_as_long() {
+        let (mut app, creature) = setup();
+        flash(&mut app, IN_FRONT);
+
+        // The setting only exists on the client, the server blinds everyone the same
+        assert!(app.world.get_resource::<AccessibilitySettings>().is_none());
+        let seconds = remaining(&app, creature).unwrap();
+        assert!((seconds - BLINDED_SECONDS).abs() < 1e-4, "{}", seconds);
+        let damage = app.world.resource::<EyeDamage>().0;
+        assert!((damage - BLINDED_SECONDS * EYE_DAMAGE_PER_SECOND).abs() < 1e-6);
+
+        let standard = AccessibilitySettings::default();
+        let reduced = AccessibilitySettings {
+            reduce_flashing: true,
+            ..Default::default()
+        };
+        assert_eq!(overlay_color(seconds, &standard).map(|c| c.a()), Some(255));
+        for step in 0..=40 {
+            let left = seconds - step as f32 * FRAME.as_secs_f32();
+            let white = overlay_color(left, &standard);
+            let dark = overlay_color(left, &reduced);
+            // Hides the screen just as much and just as long, only the color differs
+            assert_eq!(white.map(|c| c.a()), dark.map(|c| c.a()), "{}s left", left);
+            if let (Some(white), Some(dark)) = (white, dark) {
+                assert_eq!(white.r(), white.a());
+                assert!(dark.r().max(dark.g()).max(dark.b()) <= 24, "{:?}", dark);
+            }
+        }
+        assert_eq!(overlay_color(0.0, &reduced), None);
+    }
+}
@@ -0,0 +1,347 @@
+use std::time::Duration;
+
+use bevy::{ecs::query::Has, prelude::*, reflect::TypeUuid, time::common_conditions::on_timer};
+use bevy_rapier3d::prelude::{GravityScale, RigidBody, Velocity};
+use maps::TileMap;
+use networking::{
+    component::AppExt as ComponentAppExt,
+    is_server,
+    resource::AppExt as ResourceAppExt,
+    spawning::ClientControlled,
+    variable::{NetworkVar, ServerVar},
+    Networked,
+};
+
+use crate::{
+    console::{
+        ArgumentKind, CommandContext, CommandResult, ConsoleAppExt, ConsoleCommand, PermissionLevel,
+    },
+    items::{
+        clothes::Wearers,
+        containers::{ItemEnteredContainer, ItemLeftContainer},
+    },
+};
+
+#[cfg(feature = "client")]
+use {
+    crate::{navigation::BlocksTile, ui::has_window},
+    bevy::{ecs::system::SystemParam, math::Vec3Swizzles},
+    bevy_egui::{egui, EguiContexts},
+    maps::{tile_neighbours, world_to_tile, TileMapClient},
+};
+
+/// Station gravity, which can be turned off for the whole station or single areas.
+/// Creatures and loose items without gravity float, unless a creature wears magnetized boots.
+pub struct GravityPlugin;
+
+impl Plugin for GravityPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Magnetized>()
+            .add_networked_resource::<Gravity, GravityClient>()
+            .add_networked_component::<Weightless, WeightlessClient>();
+
+        if is_server(app) {
+            app.init_resource::<Gravity>()
+                .add_console_command(ConsoleCommand {
+                    name: "gravity",
+                    description:
+                        "Turns gravity on or off, for everything or an area (\"off /area/engine\")",
+                    parameters: &[("state", ArgumentKind::Text)],
+                    permission: PermissionLevel::Admin,
+                    handler: gravity_command,
+                })
+                .add_systems(
+                    Update,
+                    (
+                        track_magboots,
+                        update_weightless
+                            .run_if(on_timer(Duration::from_secs_f32(GRAVITY_CHECK_INTERVAL))),
+                    ),
+                );
+        } else {
+            app.add_systems(Update, apply_client_gravity);
+            #[cfg(feature = "client")]
+            app.add_systems(Update, gravity_hud.run_if(has_window));
+        }
+    }
+}
+
+/// Seconds between checks for bodies moving into or out of gravity
+const GRAVITY_CHECK_INTERVAL: f32 = 0.2;
+
+/// Fastest a floating object can still be moving when gravity comes back.
+/// Objects drift for a long time without gravity, so they would otherwise slam into the floor.
+const SETTLE_MAX_SPEED: f32 = 2.0;
+const SETTLE_MAX_SPIN: f32 = 3.0;
+
+/// Where gravity is working.
+#[derive(Resource, Networked)]
+#[networked(client = "GravityClient")]
+pub struct Gravity {
+    /// If the station generator is on
+    enabled: NetworkVar<bool>,
+    /// Area paths without gravity even while the generator is on.
+    /// Sub-areas of a listed area are included.
+    disabled_areas: NetworkVar<Vec<String>>,
+}
+
+impl Default for Gravity {
+    fn default() -> Self {
+        Self {
+            enabled: true.into(),
+            disabled_areas: Vec::new().into(),
+        }
+    }
+}
+
+impl Gravity {
+    /// Turns gravity on or off in an area, or on the whole station if there is no area.
+    pub fn set(&mut self, area: Option<&str>, enabled: bool) {
+        let Some(area) = area else {
+            *self.enabled = enabled;
+            return;
+        };
+        let listed = self.disabled_areas.iter().any(|a| a == area);
+        if enabled && listed {
+            self.disabled_areas.retain(|a| a != area);
+        } else if !enabled && !listed {
+            self.disabled_areas.push(area.to_owned());
+        }
+    }
+
+    pub fn has_gravity(&self, area: Option<&str>) -> bool {
+        if !*self.enabled {
+            return false;
+        }
+        let Some(area) = area else {
+            return true;
+        };
+        !self.disabled_areas.iter().any(|prefix| {
+            area.strip_prefix(prefix.as_str())
+                .map(|rest| rest.is_empty() || rest.starts_with('/'))
+                .unwrap_or(false)
+        })
+    }
+}
+
+#[derive(Default, TypeUuid, Networked, Resource)]
+#[uuid = "8b4e2f17-6c9a-4d03-a5e1-3f7d9c2b6a48"]
+#[networked(server = "Gravity")]
+pub struct GravityClient {
+    enabled: ServerVar<bool>,
+    disabled_areas: ServerVar<Vec<String>>,
+}
+
+impl GravityClient {
+    /// If the station generator is on. Single areas can still be without gravity.
+    pub fn enabled(&self) -> bool {
+        *self.enabled
+    }
+}
+
+/// Boots that keep a creature on the floor without gravity.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct Magnetized;
+
+/// A creature wearing magnetized boots.
+#[derive(Component)]
+struct Anchored;
+
+/// A physics object in an area without gravity.
+#[derive(Component, Networked)]
+#[networked(client = "WeightlessClient")]
+pub struct Weightless {
+    /// Creatures wearing magnetized boots move normally
+    magnetized: NetworkVar<bool>,
+}
+
+impl Weightless {
+    /// If the object is actually floating around
+    pub fn is_floating(&self) -> bool {
+        !*self.magnetized
+    }
+}
+
+#[derive(Component, Default, TypeUuid, Networked)]
+#[uuid = "e61a9c3d-2f5b-4e87-9d40-7b1c8a5f3e26"]
+#[networked(server = "Weightless")]
+pub struct WeightlessClient {
+    magnetized: ServerVar<bool>,
+}
+
+impl WeightlessClient {
+    pub fn is_floating(&self) -> bool {
+        !*self.magnetized
+    }
+}
+
+/// Marks dynamic bodies as weightless when they are somewhere without gravity.
+#[allow(clippy::too_many_arguments)]
+fn update_weightless(
+    mut objects: Query<(
+        Entity,
+        &RigidBody,
+        &GlobalTransform,
+        Option<&mut Weightless>,
+        Option<&mut Velocity>,
+        Has<Anchored>,
+    )>,
+    maps: Query<&TileMap>,
+    gravity: Res<Gravity>,
+    mut commands: Commands,
+) {
+    // TODO: Support multiple maps
+    let map = maps.get_single().ok();
+    for (entity, body, transform, weightless, velocity, magnetized) in objects.iter_mut() {
+        if *body != RigidBody::Dynamic {
+            continue;
+        }
+
+        let area = map.and_then(|m| m.area_at(transform.translation()));
+        if gravity.has_gravity(area) {
+            if weightless.is_none() {
+                continue;
+            }
+            commands
+                .entity(entity)
+                .remove::<(Weightless, GravityScale)>();
+            // Let floating objects come down gently instead of keeping all their momentum
+            if let Some(mut velocity) = velocity {
+                velocity.linvel = velocity.linvel.clamp_length_max(SETTLE_MAX_SPEED);
+                velocity.angvel = velocity.angvel.clamp_length_max(SETTLE_MAX_SPIN);
+            }
+            continue;
+        }
+
+        match weightless {
+            Some(mut weightless) if *weightless.magnetized != magnetized => {
+                *weightless.magnetized = magnetized;
+            }
+            Some(_) => continue,
+            None => {
+                commands.entity(entity).insert(Weightless {
+                    magnetized: magnetized.into(),
+                });
+            }
+        }
+        if magnetized {
+            commands.entity(entity).remove::<GravityScale>();
+        } else {
+            commands.entity(entity).insert(GravityScale(0.0));
+        }
+    }
+}
+
+/// Anchors creatures as they put on magnetized boots. Boots in hands or bags don't count.
+fn track_magboots(
+    mut left: EventReader<ItemLeftContainer>,
+    mut entered: EventReader<ItemEnteredContainer>,
+    boots: Query<(), With<Magnetized>>,
+    wearers: Wearers,
+    mut commands: Commands,
+) {
+    // Taking boots off comes first, so swapping them in the same frame keeps the creature anchored
+    for event in left.iter().filter(|e| boots.contains(e.item)) {
+        if let Some(creature) = wearers.wearer(event.container) {
+            commands.entity(creature).remove::<Anchored>();
+        }
+    }
+    for event in entered.iter().filter(|e| boots.contains(e.item)) {
+        if let Some(creature) = wearers.wearer(event.container) {
+            commands.entity(creature).insert(Anchored);
+        }
+    }
+}
+
+/// The client simulates its own creature, so it turns off gravity for it as well.
+fn apply_client_gravity(
+    creatures: Query<
+        (Entity, Option<&WeightlessClient>, Option<&GravityScale>),
+        With<ClientControlled>,
+    >,
+    mut commands: Commands,
+) {
+    for (entity, weightless, scale) in creatures.iter() {
+        let floating = weightless.is_some_and(|w| w.is_floating());
+        match (floating, scale) {
+            (true, None) => {
+                commands.entity(entity).insert(GravityScale(0.0));
+            }
+            (false, Some(_)) => {
+                commands.entity(entity).remove::<GravityScale>();
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Checks the tiles around a position for walls a floating creature can push off from.
+#[cfg(feature = "client")]
+#[derive(SystemParam)]
+pub struct WallContact<'w, 's> {
+    maps: Query<'w, 's, &'static TileMapClient>,
+    blockers: Query<'w, 's, (), With<BlocksTile>>,
+}
+
+#[cfg(feature = "client")]
+impl<'w, 's> WallContact<'w, 's> {
+    pub fn near_wall(&self, position: Vec3) -> bool {
+        let Some(tile) = world_to_tile(position) else {
+            return false;
+        };
+        self.maps.iter().any(|map| {
+            tile_neighbours(tile)
+                .filter_map(|(_, neighbour)| map.tile(neighbour))
+                .flat_map(|t| [t.turf, t.furniture])
+                .flatten()
+                .any(|entity| self.blockers.contains(entity))
+        })
+    }
+}
+
+fn gravity_command(world: &mut World, context: &CommandContext) -> CommandResult {
+    let mut words = context.text(0).split_whitespace();
+    let enabled = match words.next() {
+        Some("on") => true,
+        Some("off") => false,
+        _ => return Err("expected \"on\" or \"off\", optionally followed by an area".into()),
+    };
+    let area = words.next();
+    world.resource_mut::<Gravity>().set(area, enabled);
+
+    let state = if enabled { "on" } else { "off" };
+    info!(area = ?area, enabled, "Changed gravity");
+    Ok(match area {
+        Some(area) => format!("Gravity {} in {}", state, area),
+        None => format!("Gravity {} on the station", state),
+    })
+}
+
+#[cfg(feature = "client")]
+fn gravity_hud(
+    mut contexts: EguiContexts,
+    weightless: Query<(&WeightlessClient, &Velocity), With<ClientControlled>>,
+    gravity: Option<Res<GravityClient>>,
+) {
+    let Ok((weightless, velocity)) = weightless.get_single() else {
+        return;
+    };
+    let label = if !weightless.is_floating() {
+        "MAGBOOTS ENGAGED".to_owned()
+    } else if gravity.is_some_and(|g| !g.enabled()) {
+        format!("GRAVITY OFFLINE ({:.1} m/s)", velocity.linvel.xz().length())
+    } else {
+        format!("NO GRAVITY ({:.1} m/s)", velocity.linvel.xz().length())
+    };
+
+    egui::Area::new("gravity_indicator")
+        .anchor(egui::Align2::CENTER_BOTTOM, egui::vec2(0.0, -130.0))
+        .show(contexts.ctx_mut(), |ui| {
+            ui.label(
+                egui::RichText::new(label)
+                    .color(egui::Rgba::from_rgb(0.5, 0.7, 1.0))
+                    .size(16.0),
+            );
+        });
+}
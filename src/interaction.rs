@@ -1,17 +1,24 @@
 use std::{sync::Mutex, time::Duration};
 
 use bevy::{
-    ecs::query::QuerySingleError, prelude::*, reflect::TypeUuid, utils::HashMap,
-    window::PrimaryWindow,
+    ecs::system::SystemParam,
+    math::Vec3Swizzles,
+    prelude::*,
+    reflect::TypeUuid,
+    utils::{HashMap, HashSet},
+};
+use bevy_rapier3d::prelude::{CollisionGroups, QueryFilter, RapierContext};
+use maps::{
+    chunk_colliders::{self, ChunkCollider},
+    TileEntity,
 };
-use bevy_egui::{egui, EguiContexts};
-use bevy_rapier3d::prelude::RapierContext;
 use networking::{
     component::AppExt as ComponentAppExt,
+    diagnostics::DebugNames,
     identity::{NetworkIdentities, NetworkIdentity},
     is_server,
-    messaging::{AppExt, MessageEvent, MessageReceivers, MessageSender},
-    spawning::{ClientControlled, ClientControls},
+    messaging::{AppExt, InvalidMessage, MessageEvent, MessageReceivers, MessageSender},
+    spawning::ClientControls,
     variable::{NetworkVar, ServerVar},
     ConnectionId, Networked, Players,
 };
@@ -19,28 +26,68 @@ use serde::{Deserialize, Serialize};
 use utils::task::{Task, Tasks};
 
 use crate::{
+    access::AccessReader,
+    actions::{ActorAction, ActorActionEvent},
     body::{Hand, Hands},
-    camera::MainCamera,
-    combat::ClientCombatModeStatus,
+    combat::{CombatMode, Intent},
+    feedback::{Feedback, FeedbackKind},
+    flash::Flashed,
     items::containers::Container,
-    ui::has_window,
+    movement::Stunned,
+};
+
+#[cfg(feature = "client")]
+use {
+    crate::{
+        camera::MainCamera,
+        combat::ClientCombatModeStatus,
+        items::surface::{SurfaceDrag, SurfacePicker},
+        ui::has_window,
+    },
+    bevy::{ecs::query::QuerySingleError, window::PrimaryWindow},
+    bevy_egui::{egui, EguiContexts},
+    networking::spawning::ClientControlled,
 };
 
+pub use self::bump::BumpInteractable;
+pub use self::queue::InteractionAppExt;
+#[cfg(feature = "client")]
+pub use self::radial::InteractionSettings;
+#[cfg(feature = "client")]
+use self::radial::{RadialMenu, RadialMenuPlugin};
+
+mod bump;
+mod queue;
+#[cfg(feature = "client")]
+mod radial;
+
 pub struct InteractionPlugin;
 
 impl Plugin for InteractionPlugin {
     fn build(&self, app: &mut App) {
-        app.add_network_message::<InteractionListRequest>()
-            .add_network_message::<InteractionListClient>()
-            .add_network_message::<InteractionExecuteRequest>()
-            .add_network_message::<InteractionExecuteDefaultRequest>()
+        app.register_type::<LowObstacle>()
+            .register_type::<BumpInteractable>()
+            .add_network_message::<InteractionListRequest>("InteractionListRequest")
+            .add_network_message::<InteractionListClient>("InteractionListClient")
+            .add_network_message::<InteractionExecuteRequest>("InteractionExecuteRequest")
+            .add_network_message::<InteractionExecuteDefaultRequest>(
+                "InteractionExecuteDefaultRequest",
+            )
+            .add_network_message::<queue::QueuedInteractionMessage>("QueuedInteractionMessage")
+            .add_network_message::<queue::CancelQueuedInteractionRequest>(
+                "CancelQueuedInteractionRequest",
+            )
             .add_networked_component::<ActiveInteraction, ActiveInteractionClient>()
-            .add_event::<InteractionListOrder>();
+            .add_event::<InteractionListOrder>()
+            .add_event::<DefaultInteractionOrder>();
 
         if is_server(app) {
-            app.init_resource::<SentInteractionLists>()
+            app.add_event::<bump::Bump>()
+                .init_resource::<SentInteractionLists>()
                 .init_resource::<InteractionListEvents>()
                 .init_resource::<Tasks<ExecuteInteraction>>()
+                .init_resource::<queue::QueueableInteractions>()
+                .init_resource::<queue::InteractionQueue>()
                 .configure_sets(
                     Update,
                     (GenerateInteractionList
@@ -56,33 +103,48 @@ impl Plugin for InteractionPlugin {
                         (
                             handle_interaction_list_request,
                             handle_default_interaction_request,
+                            (bump::find_bumps, bump::bump_interactions).chain(),
                         ),
+                        order_default_interaction_lists,
                         begin_interaction_list,
                         handle_completed_interaction_list,
                         handle_default_interaction_request_execution,
+                        queue::handle_cancel_requests,
                         handle_interaction_execute_request,
+                        queue::drain_interaction_queue,
                         run_interactions,
+                        announce_channeling,
                         clear_completed_interactions,
                     )
                         .chain(),
                 );
         } else {
-            app.init_resource::<ClientInteractionUi>().add_systems(
-                Update,
-                (
-                    client_request_interaction_list.in_set(InteractionSystem::Input),
+            #[cfg(feature = "client")]
+            app.add_plugins(RadialMenuPlugin)
+                .init_resource::<ClientInteractionUi>()
+                .init_resource::<queue::ClientQueuedInteraction>()
+                .add_systems(
+                    Update,
                     (
-                        client_receive_interactions,
-                        client_interaction_selection_ui.run_if(has_window),
-                    )
-                        .chain(),
-                    client_progress_ui,
-                ),
-            );
+                        client_request_interaction_list.in_set(InteractionSystem::Input),
+                        (
+                            client_receive_interactions,
+                            client_interaction_selection_ui.run_if(has_window),
+                        )
+                            .chain(),
+                        client_progress_ui,
+                        (
+                            queue::receive_queued_interaction,
+                            queue::queued_interaction_indicator.run_if(has_window),
+                        )
+                            .chain(),
+                    ),
+                );
         }
     }
 }
 
+#[cfg(feature = "client")]
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, SystemSet)]
 pub enum InteractionSystem {
     Input,
@@ -93,9 +155,19 @@ pub enum InteractionSystem {
 struct InteractionListOrder {
     connection: ConnectionId,
     target: NetworkIdentity,
+    point: Option<Vec3>,
     send_to_client: bool,
 }
 
+/// Event to run the first interaction of a target, once its list is built.
+/// Sent for validated client requests and by the server itself, like for bumping into doors.
+#[derive(Event, Clone, Copy)]
+pub(crate) struct DefaultInteractionOrder {
+    pub connection: ConnectionId,
+    pub target: NetworkIdentity,
+    pub point: Option<Vec3>,
+}
+
 pub struct InteractionListEvent {
     /// If we should send the result of this list to the client
     send_to_client: bool,
@@ -104,14 +176,26 @@ pub struct InteractionListEvent {
     pub target: Entity,
     pub used_hand: Option<Entity>,
     pub item_in_hand: Option<Entity>,
+    /// Where the target was clicked in world space, if the client knows
+    pub point: Option<Vec3>,
+    /// The combat intent of the source creature
+    pub intent: Intent,
     // Behind a mutex to allow concurrent execution of interaction systems
     interactions: Mutex<Vec<InteractionOption>>,
+    /// Only offered if the source has the access the target requires
+    restricted: Mutex<Vec<InteractionOption>>,
 }
 
 impl InteractionListEvent {
     pub fn add_interaction(&self, interaction: InteractionOption) {
         self.interactions.lock().unwrap().push(interaction);
     }
+
+    /// Adds an interaction that is only available if the source passes the
+    /// [`RequiresAccess`](crate::access::RequiresAccess) of the target.
+    pub fn add_restricted_interaction(&self, interaction: InteractionOption) {
+        self.restricted.lock().unwrap().push(interaction);
+    }
 }
 
 #[derive(Resource, Default)]
@@ -142,6 +226,8 @@ struct SentInteractionLists {
 #[derive(Serialize, Deserialize)]
 pub struct InteractionListRequest {
     pub target: NetworkIdentity,
+    /// Where the target was clicked in world space
+    pub point: Option<Vec3>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Copy)]
@@ -152,11 +238,103 @@ struct InteractionExecuteRequest {
 #[derive(Serialize, Deserialize, Clone, Copy)]
 pub struct InteractionExecuteDefaultRequest {
     pub target: NetworkIdentity,
+    /// Where the target was clicked in world space
+    pub point: Option<Vec3>,
+}
+
+/// Furniture that blocks movement, but can be reached over (like tables).
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct LowObstacle;
+
+/// How far away objects can be interacted with, measured on the floor
+pub const INTERACTION_REACH: f32 = 1.75;
+/// Height reach is checked at, above the top of low obstacles
+const REACH_HEIGHT: f32 = 1.0;
+
+/// Lets a creature skip reach and access checks. Targets still have to exist.
+/// Only given to the ghost of an admin in aghost mode.
+#[derive(Component)]
+pub struct ValidationBypass;
+
+/// Checks if a creature can reach an object with its hands.
+#[derive(SystemParam)]
+pub struct Reach<'w, 's> {
+    transforms: Query<'w, 's, &'static GlobalTransform>,
+    bypass: Query<'w, 's, (), With<ValidationBypass>>,
+    parents: Query<'w, 's, &'static Parent>,
+    low_obstacles: Query<'w, 's, (), With<LowObstacle>>,
+    tiles: Query<'w, 's, &'static TileEntity>,
+    chunk_colliders: Query<'w, 's, (), With<ChunkCollider>>,
+    rapier: Res<'w, RapierContext>,
+}
+
+impl<'w, 's> Reach<'w, 's> {
+    pub fn can_reach(&self, actor: Entity, target: Entity) -> bool {
+        let Ok(target_position) = self.transforms.get(target).map(|t| t.translation()) else {
+            return false;
+        };
+        self.can_reach_point(actor, target_position, Some(target))
+    }
+
+    /// If a position is close enough and not behind a wall.
+    /// Colliders of `target` and low obstacles don't block.
+    pub fn can_reach_point(&self, actor: Entity, position: Vec3, target: Option<Entity>) -> bool {
+        let Ok(actor_position) = self.transforms.get(actor).map(|t| t.translation()) else {
+            return false;
+        };
+        if self.bypass.contains(actor) {
+            return true;
+        }
+        let offset = (position - actor_position).xz();
+        let distance = offset.length();
+        if distance > INTERACTION_REACH {
+            return false;
+        }
+        if distance < 0.01 {
+            return true;
+        }
+
+        let is_ignored = |entity: Entity| {
+            std::iter::once(entity)
+                .chain(self.parents.iter_ancestors(entity))
+                .any(|e| Some(e) == target || self.low_obstacles.contains(e))
+        };
+        let filter = QueryFilter::only_fixed()
+            .groups(CollisionGroups::new(
+                physics::RAYCASTING_GROUP,
+                physics::OBSTACLE_GROUPS,
+            ))
+            .predicate(&|entity| !is_ignored(entity));
+        let origin = Vec3::new(
+            actor_position.x,
+            actor_position.y + REACH_HEIGHT,
+            actor_position.z,
+        );
+        let direction = Vec3::new(offset.x, 0.0, offset.y) / distance;
+        match self
+            .rapier
+            .cast_ray(origin, direction, distance, true, filter)
+        {
+            None => true,
+            // Walls on the server are merged per chunk, so the tile that was hit has to be looked up
+            Some((hit, toi)) if self.chunk_colliders.contains(hit) => {
+                let target_tile = target
+                    .and_then(|target| self.tiles.get(target).ok())
+                    .map(|tile| tile.position());
+                target_tile.is_some()
+                    && chunk_colliders::hit_tile(origin, direction, toi) == target_tile
+            }
+            Some(_) => false,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone)]
 struct InteractionOptionClient {
     text: String,
+    /// Options with a higher priority are shown first
+    priority: u8,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -183,6 +361,16 @@ pub enum InteractionSpecificity {
     Generic,
 }
 
+impl InteractionSpecificity {
+    fn priority(&self) -> u8 {
+        match self {
+            InteractionSpecificity::Specific => 2,
+            InteractionSpecificity::Common => 1,
+            InteractionSpecificity::Generic => 0,
+        }
+    }
+}
+
 /// Contains information about the interaction an entity is currently executing.
 #[derive(Component, Networked)]
 #[component(storage = "SparseSet")]
@@ -201,6 +389,11 @@ impl ActiveInteraction {
         self.started
     }
 
+    /// If the interaction takes time, like using a tool on something.
+    pub fn is_timed(&self) -> bool {
+        self.estimate_duration.is_some()
+    }
+
     pub fn set_initial_duration(&mut self, duration: Duration) {
         if self.estimate_duration.is_none() {
             *self.estimate_duration = Some(duration.as_secs_f32());
@@ -230,6 +423,7 @@ impl Task for ExecuteInteraction {
     type Result = ();
 }
 
+#[allow(clippy::too_many_arguments)]
 fn begin_interaction_list(
     mut orders: EventReader<InteractionListOrder>,
     mut interaction_lists: ResMut<InteractionListEvents>,
@@ -238,11 +432,12 @@ fn begin_interaction_list(
     controls: Res<ClientControls>,
     bodies: Query<&Hands>,
     hand_query: Query<(Entity, &Container), With<Hand>>,
+    combat_modes: Query<&CombatMode>,
 ) {
     for event in orders.iter() {
         let connection = event.connection;
         let Some(target) = identities.get_entity(event.target) else {
-            warn!(connection=?connection, "Interaction list attempted for non-existent identity {:?}", event.target);
+            warn!(connection=?connection, "Interaction list attempted for non-existent identity {}", event.target);
             continue;
         };
         let Some(player) = players.get(connection).map(|p| p.id) else {
@@ -270,7 +465,13 @@ fn begin_interaction_list(
             target,
             used_hand,
             item_in_hand,
+            point: event.point,
+            intent: combat_modes
+                .get(player_entity)
+                .map(|mode| mode.intent())
+                .unwrap_or_default(),
             interactions: Default::default(),
+            restricted: Default::default(),
         });
 
         debug!(connection=?connection, target=?target, "Interaction list build started");
@@ -281,10 +482,28 @@ fn handle_completed_interaction_list(
     mut interaction_lists: ResMut<InteractionListEvents>,
     mut sent: ResMut<SentInteractionLists>,
     identities: Res<NetworkIdentities>,
+    access: AccessReader,
     mut sender: MessageSender,
+    mut feedback: Feedback,
 ) {
     for event in interaction_lists.events.drain(..) {
         let mut interactions = event.interactions.into_inner().unwrap();
+        let restricted = event.restricted.into_inner().unwrap();
+        if !restricted.is_empty() {
+            if access.can_access(event.source, event.target) {
+                interactions.extend(restricted);
+            } else {
+                debug!(connection=?event.connection, target=?event.target, "Interactions hidden by missing access");
+                if event.send_to_client {
+                    feedback.send(
+                        event.connection,
+                        FeedbackKind::AccessDenied,
+                        "access.denied",
+                        &[],
+                    );
+                }
+            }
+        }
         // Sort interactions by specificity and name
         // TODO: Add another criteria to sort by (name is probably not a good criteria)
         interactions
@@ -299,6 +518,7 @@ fn handle_completed_interaction_list(
                         .iter()
                         .map(|i| InteractionOptionClient {
                             text: i.text.clone(),
+                            priority: i.specificity.priority(),
                         })
                         .collect(),
                 },
@@ -316,11 +536,20 @@ fn handle_completed_interaction_list(
 fn handle_interaction_list_request(
     mut messages: EventReader<MessageEvent<InteractionListRequest>>,
     mut orders: EventWriter<InteractionListOrder>,
+    mut invalid: EventWriter<InvalidMessage>,
 ) {
     for event in messages.iter() {
+        if event.message.point.is_some_and(|p| !p.is_finite()) {
+            invalid.send(InvalidMessage {
+                connection: event.connection,
+                reason: "non-finite interaction point",
+            });
+            continue;
+        }
         orders.send(InteractionListOrder {
             connection: event.connection,
             target: event.message.target,
+            point: event.message.point,
             send_to_client: true,
         });
         debug!(connection=?event.connection, target=?event.message.target, "Interaction list requested");
@@ -329,25 +558,47 @@ fn handle_interaction_list_request(
 
 fn handle_default_interaction_request(
     mut messages: EventReader<MessageEvent<InteractionExecuteDefaultRequest>>,
-    mut orders: EventWriter<InteractionListOrder>,
+    mut orders: EventWriter<DefaultInteractionOrder>,
+    mut invalid: EventWriter<InvalidMessage>,
 ) {
     for event in messages.iter() {
-        orders.send(InteractionListOrder {
+        if event.message.point.is_some_and(|p| !p.is_finite()) {
+            invalid.send(InvalidMessage {
+                connection: event.connection,
+                reason: "non-finite interaction point",
+            });
+            continue;
+        }
+        orders.send(DefaultInteractionOrder {
             connection: event.connection,
             target: event.message.target,
-            send_to_client: false,
+            point: event.message.point,
         });
         debug!(connection=?event.connection, target=?event.message.target, "Default interaction requested");
     }
 }
 
+fn order_default_interaction_lists(
+    mut defaults: EventReader<DefaultInteractionOrder>,
+    mut orders: EventWriter<InteractionListOrder>,
+) {
+    for order in defaults.iter() {
+        orders.send(InteractionListOrder {
+            connection: order.connection,
+            target: order.target,
+            point: order.point,
+            send_to_client: false,
+        });
+    }
+}
+
 fn handle_default_interaction_request_execution(
-    mut messages: EventReader<MessageEvent<InteractionExecuteDefaultRequest>>,
+    mut orders: EventReader<DefaultInteractionOrder>,
     lists: Res<SentInteractionLists>,
     mut events: EventWriter<MessageEvent<InteractionExecuteRequest>>,
 ) {
-    for event in messages.iter() {
-        let connection = event.connection;
+    for order in orders.iter() {
+        let connection = order.connection;
         let Some((_, interactions)) = lists.map.get(&connection) else {
             continue;
         };
@@ -364,12 +615,21 @@ fn handle_default_interaction_request_execution(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn handle_interaction_execute_request(
     mut messages: EventReader<MessageEvent<InteractionExecuteRequest>>,
     mut sent_interactions: ResMut<SentInteractionLists>,
     controls: Res<ClientControls>,
     players: Res<Players>,
     mut execute: ResMut<Tasks<ExecuteInteraction>>,
+    flashed: Query<(), With<Flashed>>,
+    busy: Query<(), Or<(With<ActiveInteraction>, With<Stunned>)>>,
+    queueable: Res<queue::QueueableInteractions>,
+    mut queued: ResMut<queue::InteractionQueue>,
+    time: Res<Time>,
+    names: DebugNames,
+    mut feedback: Feedback,
+    mut sender: MessageSender,
 ) {
     for event in messages.iter() {
         let Some((_, (target, mut options))) =
@@ -381,7 +641,7 @@ fn handle_interaction_execute_request(
 
         let index = event.message.index;
         if index >= options.len() {
-            warn!(connection=?event.connection, index=event.message.index, "Received interaction execute request with out of bounds index");
+            warn!(connection=?event.connection, index=event.message.index, target=%names.debug_name(target), "Received interaction execute request with out of bounds index");
             continue;
         }
 
@@ -390,8 +650,9 @@ fn handle_interaction_execute_request(
         let option = options.swap_remove(index);
 
         debug!(
-            "Client wants to execute interaction \"{}\" on {:?}",
-            &option.text, target
+            "Client wants to execute interaction \"{}\" on {}",
+            &option.text,
+            names.debug_name(target)
         );
 
         let connection = event.connection;
@@ -403,7 +664,44 @@ fn handle_interaction_execute_request(
             warn!(connection=?connection, player=?player, "Received interaction execute request from player without controlled entity");
             continue;
         };
+        // Blinded creatures can't see what they're doing
+        if flashed.contains(player_entity) {
+            debug!(connection=?connection, "Interaction ignored while flashed");
+            feedback.send(
+                connection,
+                FeedbackKind::Blocked,
+                "interaction.flashed",
+                &[],
+            );
+            continue;
+        }
+        // Quick interactions wait for the current one or a stun to end, anything else is rejected
+        if busy.contains(player_entity) {
+            if !queueable.contains(option.interaction.as_ref()) {
+                feedback.send(connection, FeedbackKind::Blocked, "interaction.busy", &[]);
+                continue;
+            }
+            debug!(connection=?connection, interaction=option.text.as_str(), "Interaction queued while busy");
+            feedback.send(
+                connection,
+                FeedbackKind::Queued,
+                "interaction.queued",
+                &[&option.text],
+            );
+            queued.push(
+                player_entity,
+                connection,
+                target,
+                option.interaction,
+                option.text,
+                time.elapsed_seconds(),
+                &mut sender,
+            );
+            continue;
+        }
 
+        // Doing something else replaces the queued interaction
+        queued.cancel(player_entity, &mut sender);
         execute.create_ignore(ExecuteInteraction {
             entity: player_entity,
             target,
@@ -412,7 +710,7 @@ fn handle_interaction_execute_request(
     }
 }
 
-fn run_interactions(world: &mut World) {
+pub(crate) fn run_interactions(world: &mut World) {
     let started = world.resource::<Time>().elapsed_seconds();
 
     world.resource_scope(|world, mut tasks: Mut<Tasks<ExecuteInteraction>>| {
@@ -449,7 +747,40 @@ fn run_interactions(world: &mut World) {
     });
 }
 
-fn clear_completed_interactions(
+/// Lets other players see who is busy with a timed interaction
+fn announce_channeling(
+    interactions: Query<(Entity, &ActiveInteraction)>,
+    mut channeling: Local<HashSet<Entity>>,
+    mut actions: EventWriter<ActorActionEvent>,
+) {
+    for (entity, interaction) in interactions.iter() {
+        let Some(duration) = *interaction.estimate_duration else {
+            continue;
+        };
+        if channeling.insert(entity) {
+            actions.send(ActorActionEvent {
+                actor: entity,
+                action: ActorAction::Channel { duration },
+            });
+        }
+    }
+
+    channeling.retain(|&entity| {
+        let running = interactions
+            .get(entity)
+            .map(|(_, i)| matches!(i.status, InteractionStatus::Running))
+            .unwrap_or(false);
+        if !running {
+            actions.send(ActorActionEvent {
+                actor: entity,
+                action: ActorAction::StopChannel,
+            });
+        }
+        running
+    });
+}
+
+pub(crate) fn clear_completed_interactions(
     world: &mut World,
     query: &mut QueryState<(Entity, &ActiveInteraction), Changed<ActiveInteraction>>,
 ) {
@@ -476,9 +807,11 @@ fn clear_completed_interactions(
     }
 }
 
+#[cfg(feature = "client")]
 #[allow(clippy::too_many_arguments)]
 fn client_request_interaction_list(
     buttons: Res<Input<MouseButton>>,
+    keys: Res<Input<KeyCode>>,
     mut contexts: EguiContexts,
     rapier_context: Res<RapierContext>,
     windows: Query<(Entity, &Window), With<PrimaryWindow>>,
@@ -486,6 +819,11 @@ fn client_request_interaction_list(
     parents: Query<&Parent>,
     identities: Res<NetworkIdentities>,
     combat_status: ClientCombatModeStatus,
+    mut radial: ResMut<RadialMenu>,
+    settings: Res<InteractionSettings>,
+    surface_picker: SurfacePicker,
+    mut surface_drag: ResMut<SurfaceDrag>,
+    time: Res<Time>,
     mut sender: MessageSender,
 ) {
     let execute_default = buttons.just_pressed(MouseButton::Left);
@@ -494,6 +832,11 @@ fn client_request_interaction_list(
         return;
     }
 
+    // Alt-clicks list everything on the tile instead
+    if keys.any_pressed([KeyCode::AltLeft, KeyCode::AltRight]) {
+        return;
+    }
+
     // We prevent interaction with the world while fighting
     // so we can reuse the same mouse buttons for attacking
     if combat_status.is_enabled() {
@@ -523,11 +866,16 @@ fn client_request_interaction_list(
         return;
     };
 
-    let Some((entity, _)) =
+    let Some((hit_entity, toi)) =
         rapier_context.cast_ray(ray.origin, ray.direction, 100.0, true, Default::default())
     else {
         return;
     };
+    let point = ray.origin + ray.direction * toi;
+
+    // Items on tables have no colliders, so they are picked by where the table was clicked
+    let surface_item = surface_picker.item_at(hit_entity, point);
+    let entity = surface_item.unwrap_or(hit_entity);
 
     // Get network identity on hit or parents
     let target = identities.get_identity(entity).or_else(|| {
@@ -541,20 +889,37 @@ fn client_request_interaction_list(
     };
 
     if execute_default {
-        sender.send_to_server(&InteractionExecuteDefaultRequest { target });
+        // Releasing decides between using and moving the item
+        if let Some(item) = surface_item {
+            surface_drag.start(item, target, cursor_position);
+            return;
+        }
+        sender.send_to_server(&InteractionExecuteDefaultRequest {
+            target,
+            point: Some(point),
+        });
     } else {
-        sender.send_to_server(&InteractionListRequest { target });
+        sender.send_to_server(&InteractionListRequest {
+            target,
+            point: Some(point),
+        });
+        if settings.radial_menu {
+            radial.start(cursor_position, time.elapsed_seconds());
+        }
     }
 }
 
+#[cfg(feature = "client")]
 #[derive(Resource, Default)]
 struct ClientInteractionUi {
     current: Option<InteractionListClient>,
 }
 
+#[cfg(feature = "client")]
 fn client_receive_interactions(
     mut messages: EventReader<MessageEvent<InteractionListClient>>,
     mut state: ResMut<ClientInteractionUi>,
+    mut radial: ResMut<RadialMenu>,
 ) {
     let Some(event) = messages.iter().last() else {
         return;
@@ -563,11 +928,13 @@ fn client_receive_interactions(
     if event.message.interactions.is_empty() {
         // Ensures possible existing dialog disappears
         state.current = None;
-    } else {
-        state.current = Some(event.message.clone());
+        *radial = RadialMenu::Closed;
+    } else if let Some(list) = radial.receive(&event.message) {
+        state.current = Some(list);
     }
 }
 
+#[cfg(feature = "client")]
 fn client_interaction_selection_ui(
     mut contexts: EguiContexts,
     mut state: ResMut<ClientInteractionUi>,
@@ -606,6 +973,7 @@ fn client_interaction_selection_ui(
     }
 }
 
+#[cfg(feature = "client")]
 fn client_progress_ui(
     mut contexts: EguiContexts,
     mut interactions: Query<
@@ -639,7 +1007,8 @@ fn client_progress_ui(
     };
 
     // TODO: This may be inaccurate with high RTT, use interpolated tick instead
-    if interaction.started.is_none() {
+    // Interactions can wait on something before their timed part begins
+    if interaction.started.is_none() && interaction.estimate_duration.is_some() {
         interaction.started = Some(time.elapsed_seconds());
     }
 
@@ -0,0 +1,271 @@
+use bevy::{prelude::*, utils::HashMap};
+use bevy_rapier3d::prelude::RapierContext;
+use networking::{
+    identity::NetworkIdentity, spawning::ClientControls, transform::ClientMovement, Players,
+};
+
+use crate::{
+    access::AccessReader,
+    body::Body,
+    combat::{
+        damage::{AffectedEntity, Attack, AttackSource, KineticDamage, KineticShape},
+        CombatMode, Intent,
+    },
+    feedback::{Feedback, FeedbackKind},
+};
+
+use super::DefaultInteractionOrder;
+
+/// Objects that run their default interaction when a creature walks into them, like doors.
+/// Doors without it (like high-security ones) have to be clicked.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct BumpInteractable;
+
+/// A creature touching a [`BumpInteractable`]. Sent every frame they touch.
+#[derive(Event, Clone, Copy)]
+pub(super) struct Bump {
+    pub creature: Entity,
+    pub target: Entity,
+}
+
+/// Seconds before a creature bumping into the same object tries again
+const BUMP_COOLDOWN: f32 = 1.0;
+/// Speed in m/s of the shoulder a creature rams into things with on harm intent
+const BUMP_ATTACK_VELOCITY: f32 = 4.0;
+/// Mass in kg behind a harm intent bump
+const BUMP_ATTACK_MASS: f32 = 2.0;
+
+/// Finds creatures touching a [`BumpInteractable`] through physics contacts.
+pub(super) fn find_bumps(
+    rapier: Res<RapierContext>,
+    bumpables: Query<Entity, With<BumpInteractable>>,
+    children: Query<&Children>,
+    parents: Query<&Parent>,
+    creatures: Query<(), (With<Body>, With<ClientMovement>)>,
+    mut bumps: EventWriter<Bump>,
+) {
+    let rapier = &*rapier;
+    for target in bumpables.iter() {
+        // Colliders are usually children of the object
+        let touching = std::iter::once(target)
+            .chain(children.iter_descendants(target))
+            .flat_map(|collider| {
+                rapier
+                    .contacts_with(collider)
+                    .filter(|pair| pair.has_any_active_contacts())
+                    .map(move |pair| {
+                        if pair.collider1() == collider {
+                            pair.collider2()
+                        } else {
+                            pair.collider1()
+                        }
+                    })
+            })
+            .filter_map(|other| {
+                std::iter::once(other)
+                    .chain(parents.iter_ancestors(other))
+                    .find(|&e| creatures.contains(e))
+            });
+
+        for creature in touching {
+            bumps.send(Bump { creature, target });
+        }
+    }
+}
+
+/// Starts the default interaction for creatures bumping into an object.
+/// Uses the same path as a click, so access checks and feedback stay the same.
+/// Creatures looking for a fight attack the object instead.
+#[allow(clippy::too_many_arguments)]
+pub(super) fn bump_interactions(
+    mut bumps: EventReader<Bump>,
+    bumpables: Query<&NetworkIdentity, With<BumpInteractable>>,
+    creatures: Query<Option<&CombatMode>, (With<Body>, With<ClientMovement>)>,
+    controls: Res<ClientControls>,
+    players: Res<Players>,
+    access: AccessReader,
+    time: Res<Time>,
+    mut cooldowns: Local<HashMap<(Entity, Entity), f32>>,
+    mut orders: EventWriter<DefaultInteractionOrder>,
+    mut feedback: Feedback,
+    mut commands: Commands,
+) {
+    let now = time.elapsed_seconds();
+    cooldowns.retain(|_, until| *until > now);
+
+    for &Bump { creature, target } in bumps.iter() {
+        let (Ok(&identity), Ok(mode)) = (bumpables.get(target), creatures.get(creature)) else {
+            continue;
+        };
+        if cooldowns.contains_key(&(creature, target)) {
+            continue;
+        }
+        cooldowns.insert((creature, target), now + BUMP_COOLDOWN);
+
+        if mode.is_some_and(|mode| mode.is_enabled() && mode.intent() == Intent::Harm) {
+            commands.spawn((
+                Attack,
+                AffectedEntity(target),
+                KineticDamage {
+                    velocity: BUMP_ATTACK_VELOCITY,
+                    mass: BUMP_ATTACK_MASS,
+                    shape: KineticShape::Blunt,
+                    scale: 1.0,
+                },
+                AttackSource {
+                    attacker: creature,
+                    instigator: None,
+                    weapon: None,
+                    distance: None,
+                },
+            ));
+            debug!(creature = ?creature, target = ?target, "Rammed into interactable");
+            continue;
+        }
+
+        let Some(connection) = controls
+            .controlling_player(creature)
+            .and_then(|player| players.get_connection(&player))
+        else {
+            continue;
+        };
+
+        // Default interactions don't explain missing access, clicks show the list instead
+        if !access.can_access(creature, target) {
+            feedback.send(connection, FeedbackKind::AccessDenied, "access.denied", &[]);
+            continue;
+        }
+
+        orders.send(DefaultInteractionOrder {
+            connection,
+            target: identity,
+            point: None,
+        });
+        debug!(connection = ?connection, target = ?target, "Bumped into interactable");
+    }
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::{ecs::system::Command, time::TimeUpdateStrategy};
+    use networking::{
+        identity::NetworkCommand,
+        loopback::LinkConditions,
+        messaging::{AppExt, MessageEvent},
+        testing, NetworkRole,
+    };
+
+    use super::*;
+    use crate::{
+        access::RequiresAccess,
+        config::ServerConfig,
+        door::{Door, DoorState},
+        feedback::ActionFeedback,
+        testing::server_app,
+    };
+
+    const FRAME: Duration = Duration::from_millis(100);
+
+    #[derive(Resource, Default)]
+    struct Denials(u32);
+
+    fn count_denials(
+        mut messages: EventReader<MessageEvent<ActionFeedback>>,
+        mut denials: ResMut<Denials>,
+    ) {
+        denials.0 += messages
+            .iter()
+            .filter(|event| event.message.kind == FeedbackKind::AccessDenied)
+            .count() as u32;
+    }
+
+    /// A server with a door and a creature controlled by a connected client.
+    fn setup() -> (App, App, Entity, Entity) {
+        let mut server = server_app(ServerConfig::default());
+        let mut client = testing::app(NetworkRole::Client);
+        client
+            .add_network_message::<ActionFeedback>("ActionFeedback")
+            .init_resource::<Denials>()
+            .add_systems(Update, count_denials);
+        let connector = testing::listen(&mut server);
+        testing::join(&mut client, &connector, LinkConditions::default());
+        testing::connect(&mut server, &mut [&mut client], 200);
+        server.insert_resource(TimeUpdateStrategy::ManualDuration(FRAME));
+
+        let door = server
+            .world
+            .spawn((Door, BumpInteractable, SpatialBundle::default()))
+            .id();
+        NetworkCommand { entity: door }.apply(&mut server.world);
+        let creature = server
+            .world
+            .spawn((
+                Body::default(),
+                ClientMovement,
+                CombatMode::default(),
+                SpatialBundle::from_transform(Transform::from_xyz(1.0, 0.0, 0.0)),
+            ))
+            .id();
+        NetworkCommand { entity: creature }.apply(&mut server.world);
+        let player = server
+            .world
+            .resource::<Players>()
+            .players()
+            .values()
+            .next()
+            .unwrap()
+            .id;
+        server
+            .world
+            .resource_mut::<ClientControls>()
+            .give_control(player, creature);
+        // Lets the door get its state
+        testing::update(&mut server, &mut [&mut client], 1);
+        (server, client, door, creature)
+    }
+
+    /// Bumps every frame, like a creature walking into the object.
+    fn bump(server: &mut App, client: &mut App, creature: Entity, target: Entity, frames: u32) {
+        for _ in 0..frames {
+            server.world.send_event(Bump { creature, target });
+            testing::update(server, &mut [&mut *client], 1);
+        }
+    }
+
+    #[test]
+    fn accessible_door_opens() {
+        let (mut server, mut client, door, creature) = setup();
+        bump(&mut server, &mut client, creature, door, 5);
+        assert!(server.world.get::<DoorState>(door).unwrap().is_open());
+    }
+
+    #[test]
+    fn inaccessible_door_denies_once_per_cooldown() {
+        let (mut server, mut client, door, creature) = setup();
+        server.world.entity_mut(door).insert(RequiresAccess {
+            accesses: vec!["command".into()],
+        });
+
+        // Spans a bit more than one cooldown
+        bump(&mut server, &mut client, creature, door, 15);
+        testing::update(&mut server, &mut [&mut client], 5);
+        assert!(!server.world.get::<DoorState>(door).unwrap().is_open());
+        assert_eq!(client.world.resource::<Denials>().0, 2);
+    }
+
+    #[test]
+    fn combat_mode_bump_damages_door() {
+        let (mut server, mut client, door, creature) = setup();
+        let mut mode = server.world.get_mut::<CombatMode>(creature).unwrap();
+        mode.set(true);
+        mode.set_intent(Intent::Harm);
+
+        bump(&mut server, &mut client, creature, door, 3);
+        let state = server.world.get::<DoorState>(door).unwrap();
+        assert!(!state.is_open());
+        assert!(state.integrity < DoorState::default().integrity);
+    }
+}
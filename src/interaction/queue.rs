@@ -0,0 +1,484 @@
+use bevy::{
+    prelude::*,
+    reflect::GetTypeRegistration,
+    utils::{HashMap, HashSet},
+};
+use networking::{
+    messaging::{MessageEvent, MessageReceivers, MessageSender},
+    spawning::ClientControls,
+    ConnectionId, Players,
+};
+use serde::{Deserialize, Serialize};
+use utils::task::Tasks;
+
+use crate::{
+    feedback::{Feedback, FeedbackKind},
+    flash::Flashed,
+    movement::Stunned,
+};
+
+use super::{ActiveInteraction, ExecuteInteraction, Reach};
+
+#[cfg(feature = "client")]
+use bevy_egui::{egui, EguiContexts};
+
+/// Seconds a queued interaction waits for its creature to be free before it is dropped
+const QUEUE_TTL: f32 = 3.0;
+
+/// Interactions that can be queued while their creature is busy, by type name.
+/// Mostly quick ones, like opening something or picking it up.
+#[derive(Resource, Default)]
+pub(super) struct QueueableInteractions(HashSet<&'static str>);
+
+pub trait InteractionAppExt {
+    /// Registers an interaction component that can be queued while the creature is busy with
+    /// another interaction or stunned. Replaces `app.register_type::<T>()` for it.
+    fn register_queueable_interaction<T>(&mut self) -> &mut Self
+    where
+        T: GetTypeRegistration;
+}
+
+impl InteractionAppExt for App {
+    fn register_queueable_interaction<T>(&mut self) -> &mut Self
+    where
+        T: GetTypeRegistration,
+    {
+        self.register_type::<T>();
+        self.world
+            .get_resource_or_insert_with(QueueableInteractions::default)
+            .0
+            .insert(std::any::type_name::<T>());
+        self
+    }
+}
+
+/// An interaction waiting for its creature to finish what it's doing.
+pub(super) struct QueuedInteraction {
+    connection: ConnectionId,
+    target: Entity,
+    interaction: Box<dyn Reflect>,
+    text: String,
+    expires: f32,
+}
+
+/// The next interaction of every busy creature. A new one replaces the old one.
+#[derive(Resource, Default)]
+pub(super) struct InteractionQueue {
+    queued: HashMap<Entity, QueuedInteraction>,
+}
+
+impl QueueableInteractions {
+    pub(super) fn contains(&self, interaction: &dyn Reflect) -> bool {
+        self.0.contains(interaction.type_name())
+    }
+}
+
+impl InteractionQueue {
+    /// Queues the interaction, replacing the one that was queued before.
+    #[allow(clippy::too_many_arguments)]
+    pub(super) fn push(
+        &mut self,
+        creature: Entity,
+        connection: ConnectionId,
+        target: Entity,
+        interaction: Box<dyn Reflect>,
+        text: String,
+        now: f32,
+        sender: &mut MessageSender,
+    ) {
+        send_queued(sender, connection, Some(text.clone()));
+        self.queued.insert(
+            creature,
+            QueuedInteraction {
+                connection,
+                target,
+                interaction,
+                text,
+                expires: now + QUEUE_TTL,
+            },
+        );
+    }
+
+    /// Drops the queued interaction of a creature, for example because it did something else.
+    pub(super) fn cancel(&mut self, creature: Entity, sender: &mut MessageSender) {
+        if let Some(queued) = self.queued.remove(&creature) {
+            send_queued(sender, queued.connection, None);
+        }
+    }
+}
+
+/// Server message with the interaction queued for the player's creature, `None` once it ran or was dropped.
+#[derive(Serialize, Deserialize)]
+pub(super) struct QueuedInteractionMessage {
+    text: Option<String>,
+}
+
+/// Client message to drop the queued interaction.
+#[derive(Serialize, Deserialize)]
+pub(super) struct CancelQueuedInteractionRequest;
+
+fn send_queued(sender: &mut MessageSender, connection: ConnectionId, text: Option<String>) {
+    sender.send(
+        &QueuedInteractionMessage { text },
+        MessageReceivers::Single(connection),
+    );
+}
+
+pub(super) fn handle_cancel_requests(
+    mut messages: EventReader<MessageEvent<CancelQueuedInteractionRequest>>,
+    players: Res<Players>,
+    controls: Res<ClientControls>,
+    mut queue: ResMut<InteractionQueue>,
+    mut sender: MessageSender,
+) {
+    for event in messages.iter() {
+        if let Some(creature) = players
+            .get(event.connection)
+            .and_then(|player| controls.controlled_entity(player.id))
+        {
+            queue.cancel(creature, &mut sender);
+        }
+    }
+}
+
+/// Starts queued interactions once their creature is free again.
+/// The target is checked again, as it may have moved away in the meantime.
+#[allow(clippy::too_many_arguments)]
+pub(super) fn drain_interaction_queue(
+    mut queue: ResMut<InteractionQueue>,
+    busy: Query<(), Or<(With<ActiveInteraction>, With<Stunned>)>>,
+    flashed: Query<(), With<Flashed>>,
+    entities: Query<()>,
+    reach: Reach,
+    time: Res<Time>,
+    mut execute: ResMut<Tasks<ExecuteInteraction>>,
+    mut feedback: Feedback,
+    mut sender: MessageSender,
+) {
+    if queue.queued.is_empty() {
+        return;
+    }
+
+    let now = time.elapsed_seconds();
+    let ready: Vec<Entity> = queue
+        .queued
+        .iter()
+        .filter(|(&creature, queued)| queued.expires <= now || !busy.contains(creature))
+        .map(|(&creature, _)| creature)
+        .collect();
+
+    for creature in ready {
+        let queued = queue.queued.remove(&creature).unwrap();
+        send_queued(&mut sender, queued.connection, None);
+
+        if queued.expires <= now {
+            debug!(creature = ?creature, interaction = queued.text.as_str(), "Queued interaction expired");
+            continue;
+        }
+        if !entities.contains(creature) || flashed.contains(creature) {
+            continue;
+        }
+        if !entities.contains(queued.target)
+            || (queued.target != creature && !reach.can_reach(creature, queued.target))
+        {
+            debug!(creature = ?creature, interaction = queued.text.as_str(), "Queued interaction target out of reach");
+            feedback.send(
+                queued.connection,
+                FeedbackKind::OutOfReach,
+                "item.out_of_reach",
+                &[],
+            );
+            continue;
+        }
+
+        execute.create_ignore(ExecuteInteraction {
+            entity: creature,
+            target: queued.target,
+            interaction: queued.interaction,
+        });
+    }
+}
+
+/// The interaction queued on the server for the controlled creature.
+#[cfg(feature = "client")]
+#[derive(Resource, Default)]
+pub(super) struct ClientQueuedInteraction(Option<String>);
+
+#[cfg(feature = "client")]
+pub(super) fn receive_queued_interaction(
+    mut messages: EventReader<MessageEvent<QueuedInteractionMessage>>,
+    mut queued: ResMut<ClientQueuedInteraction>,
+) {
+    if let Some(event) = messages.iter().last() {
+        queued.0 = event.message.text.clone();
+    }
+}
+
+/// Shows the queued interaction above the hands. Escape drops it.
+#[cfg(feature = "client")]
+pub(super) fn queued_interaction_indicator(
+    mut contexts: EguiContexts,
+    keys: Res<Input<KeyCode>>,
+    mut queued: ResMut<ClientQueuedInteraction>,
+    mut sender: MessageSender,
+) {
+    let Some(text) = &queued.0 else {
+        return;
+    };
+    if keys.just_pressed(KeyCode::Escape) {
+        sender.send_to_server(&CancelQueuedInteractionRequest);
+        queued.0 = None;
+        return;
+    }
+
+    egui::Area::new("queued_interaction")
+        .anchor(egui::Align2::CENTER_BOTTOM, egui::vec2(0.0, -190.0))
+        .order(egui::Order::Foreground)
+        .interactable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.label(
+                egui::RichText::new(format!("Next: {} (Esc to cancel)", text))
+                    .small()
+                    .color(egui::Color32::from_gray(200)),
+            );
+        });
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::{ecs::system::Command, time::TimeUpdateStrategy};
+    use networking::{
+        identity::{NetworkCommand, NetworkIdentities},
+        loopback::LinkConditions,
+        messaging::AppExt,
+        testing, NetworkRole,
+    };
+
+    use super::*;
+    use crate::{
+        config::ServerConfig,
+        feedback::ActionFeedback,
+        interaction::InteractionExecuteDefaultRequest,
+        machines::{Machine, MachineViewers},
+        testing::server_app,
+    };
+
+    const FRAME: Duration = Duration::from_millis(100);
+
+    #[derive(Resource, Default)]
+    struct Received {
+        queued: Vec<Option<String>>,
+        feedback: Vec<String>,
+    }
+
+    fn record_messages(
+        mut queued: EventReader<MessageEvent<QueuedInteractionMessage>>,
+        mut feedback: EventReader<MessageEvent<ActionFeedback>>,
+        mut received: ResMut<Received>,
+    ) {
+        received
+            .queued
+            .extend(queued.iter().map(|event| event.message.text.clone()));
+        received
+            .feedback
+            .extend(feedback.iter().map(|event| event.message.text_key.clone()));
+    }
+
+    struct Scene {
+        server: App,
+        client: App,
+        connection: ConnectionId,
+        body: Entity,
+    }
+
+    impl Scene {
+        /// A player controlling a creature standing at the origin.
+        fn new() -> Self {
+            let mut server = server_app(ServerConfig::default());
+            let mut client = testing::app(NetworkRole::Client);
+            client
+                .add_network_message::<QueuedInteractionMessage>("QueuedInteractionMessage")
+                .add_network_message::<ActionFeedback>("ActionFeedback")
+                .init_resource::<Received>()
+                .add_systems(Update, record_messages);
+            let connector = testing::listen(&mut server);
+            testing::join(&mut client, &connector, LinkConditions::default());
+            testing::connect(&mut server, &mut [&mut client], 200);
+            server.insert_resource(TimeUpdateStrategy::ManualDuration(FRAME));
+
+            let (&connection, player) = server
+                .world
+                .resource::<Players>()
+                .players()
+                .iter()
+                .next()
+                .unwrap();
+            let player = player.id;
+            let body = server.world.spawn(SpatialBundle::default()).id();
+            server
+                .world
+                .resource_mut::<ClientControls>()
+                .give_control(player, body);
+
+            let mut scene = Self {
+                server,
+                client,
+                connection,
+                body,
+            };
+            scene.update(2);
+            scene
+        }
+
+        fn update(&mut self, frames: u32) {
+            testing::update(&mut self.server, &mut [&mut self.client], frames);
+        }
+
+        /// A machine within reach of the creature.
+        fn spawn_machine(&mut self, name: &str, position: Vec3) -> Entity {
+            let machine = self
+                .server
+                .world
+                .spawn((
+                    SpatialBundle::from_transform(Transform::from_translation(position)),
+                    Machine { name: name.into() },
+                ))
+                .id();
+            NetworkCommand { entity: machine }.apply(&mut self.server.world);
+            self.update(1);
+            machine
+        }
+
+        fn stun(&mut self, seconds: f32) {
+            let now = self.server.world.resource::<Time>().elapsed_seconds();
+            self.server
+                .world
+                .entity_mut(self.body)
+                .insert(Stunned::new(seconds, now));
+        }
+
+        fn use_machine(&mut self, machine: Entity) {
+            let target = self
+                .server
+                .world
+                .resource::<NetworkIdentities>()
+                .get_identity(machine)
+                .unwrap();
+            self.server.world.send_event(MessageEvent {
+                message: InteractionExecuteDefaultRequest {
+                    target,
+                    point: None,
+                },
+                connection: self.connection,
+            });
+            self.update(3);
+        }
+
+        fn is_open(&self, machine: Entity) -> bool {
+            let identity = self
+                .server
+                .world
+                .resource::<NetworkIdentities>()
+                .get_identity(machine)
+                .unwrap();
+            self.server
+                .world
+                .resource::<MachineViewers>()
+                .get(self.connection, identity)
+                .is_some()
+        }
+
+        /// Queue updates received since the last call.
+        fn queued(&mut self) -> Vec<Option<String>> {
+            std::mem::take(&mut self.client.world.resource_mut::<Received>().queued)
+        }
+
+        fn feedback(&mut self) -> Vec<String> {
+            std::mem::take(&mut self.client.world.resource_mut::<Received>().feedback)
+        }
+    }
+
+    #[test]
+    fn queued_interaction_runs_when_the_stun_ends() {
+        let mut scene = Scene::new();
+        let locker = scene.spawn_machine("Locker", Vec3::new(1.0, 0.0, 0.0));
+        scene.stun(1.0);
+
+        scene.use_machine(locker);
+
+        assert!(!scene.is_open(locker));
+        assert_eq!(scene.queued(), [Some("Use Locker".to_owned())]);
+        assert!(scene.feedback().contains(&"interaction.queued".to_owned()));
+
+        scene.update(12);
+        assert!(scene.server.world.get::<Stunned>(scene.body).is_none());
+        assert!(scene.is_open(locker));
+        assert_eq!(scene.queued(), [None]);
+    }
+
+    #[test]
+    fn newer_interaction_replaces_the_queued_one() {
+        let mut scene = Scene::new();
+        let locker = scene.spawn_machine("Locker", Vec3::new(1.0, 0.0, 0.0));
+        let closet = scene.spawn_machine("Closet", Vec3::new(0.0, 0.0, 1.0));
+        scene.stun(1.0);
+
+        scene.use_machine(locker);
+        scene.use_machine(closet);
+        assert_eq!(
+            scene.queued(),
+            [Some("Use Locker".to_owned()), Some("Use Closet".to_owned())]
+        );
+
+        scene.update(12);
+        assert!(scene.is_open(closet));
+        assert!(!scene.is_open(locker));
+    }
+
+    #[test]
+    fn queued_interaction_expires_during_a_long_stun() {
+        let mut scene = Scene::new();
+        let locker = scene.spawn_machine("Locker", Vec3::new(1.0, 0.0, 0.0));
+        scene.stun(5.0);
+
+        scene.use_machine(locker);
+        assert_eq!(scene.queued(), [Some("Use Locker".to_owned())]);
+
+        // Dropped after QUEUE_TTL, while still stunned
+        scene.update(32);
+        assert!(scene.server.world.get::<Stunned>(scene.body).is_some());
+        assert_eq!(scene.queued(), [None]);
+
+        scene.update(23);
+        assert!(scene.server.world.get::<Stunned>(scene.body).is_none());
+        assert!(!scene.is_open(locker));
+    }
+
+    #[test]
+    fn target_moved_out_of_reach_is_not_used() {
+        let mut scene = Scene::new();
+        let locker = scene.spawn_machine("Locker", Vec3::new(1.0, 0.0, 0.0));
+        scene.stun(1.0);
+        scene.use_machine(locker);
+        scene.feedback();
+
+        scene
+            .server
+            .world
+            .get_mut::<Transform>(locker)
+            .unwrap()
+            .translation = Vec3::new(6.0, 0.0, 0.0);
+        scene.update(12);
+
+        assert!(!scene.is_open(locker));
+        assert!(scene
+            .server
+            .world
+            .get::<ActiveInteraction>(scene.body)
+            .is_none());
+        assert_eq!(scene.queued(), [None]);
+        assert_eq!(scene.feedback(), ["item.out_of_reach"]);
+    }
+}
@@ -0,0 +1,296 @@
+use std::f32::consts::TAU;
+
+use bevy::{
+    input::{mouse::MouseButtonInput, ButtonState},
+    prelude::*,
+    window::PrimaryWindow,
+};
+use bevy_egui::{egui, EguiContexts};
+use networking::messaging::MessageSender;
+
+use crate::ui::{has_window, InputBlocks};
+
+use super::{
+    ClientInteractionUi, InteractionExecuteRequest, InteractionListClient, InteractionOptionClient,
+    InteractionSystem,
+};
+
+/// Shows interactions on a wheel around the cursor while the interact button is held.
+pub(super) struct RadialMenuPlugin;
+
+impl Plugin for RadialMenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RadialMenu>()
+            .init_resource::<InteractionSettings>()
+            .add_systems(
+                Update,
+                (
+                    radial_input.after(InteractionSystem::Input),
+                    radial_ui.run_if(has_window),
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// Player preferences for interacting.
+#[derive(Resource)]
+pub struct InteractionSettings {
+    /// Show interactions on a wheel instead of a list while the button is held
+    pub radial_menu: bool,
+}
+
+impl Default for InteractionSettings {
+    fn default() -> Self {
+        Self { radial_menu: true }
+    }
+}
+
+const INTERACT_BUTTON: MouseButton = MouseButton::Right;
+/// The most options that fit on the wheel, larger lists are shown as a list
+const MAX_SEGMENTS: usize = 8;
+/// Releasing the button before this many seconds counts as a click, which shows the list
+const HOLD_THRESHOLD: f32 = 0.25;
+/// Distance from the center of the wheel at which labels are drawn
+const RADIUS: f32 = 90.0;
+/// The cursor has to be this far from the center to select something
+const DEAD_ZONE: f32 = 25.0;
+const INPUT_BLOCK: &str = "radial menu";
+
+#[derive(Resource, Default)]
+pub(super) enum RadialMenu {
+    #[default]
+    Closed,
+    /// The button is held and the options haven't arrived yet
+    Waiting { center: Vec2, pressed_at: f32 },
+    /// The button was clicked, so the options are shown as a list when they arrive
+    ShowList,
+    /// The button was released before the options arrived, they are ignored
+    Canceled,
+    Open {
+        center: Vec2,
+        pressed_at: f32,
+        list: InteractionListClient,
+        /// Indices into the list, in the order of the segments
+        segments: Vec<usize>,
+        selected: Option<usize>,
+    },
+}
+
+impl RadialMenu {
+    /// Called when the interaction list was requested with the interact button.
+    pub(super) fn start(&mut self, cursor: Vec2, now: f32) {
+        *self = RadialMenu::Waiting {
+            center: cursor,
+            pressed_at: now,
+        };
+    }
+
+    /// Takes the received options if they should be shown on the wheel.
+    /// Returns the list if it should be shown as a list instead.
+    pub(super) fn receive(
+        &mut self,
+        list: &InteractionListClient,
+    ) -> Option<InteractionListClient> {
+        match std::mem::take(self) {
+            RadialMenu::Canceled => None,
+            RadialMenu::Waiting { center, pressed_at }
+                if list.interactions.len() <= MAX_SEGMENTS =>
+            {
+                let mut segments: Vec<_> = (0..list.interactions.len()).collect();
+                // Highest priority goes on top, the sort is stable so the server order is kept otherwise
+                segments.sort_by_key(|&i| std::cmp::Reverse(list.interactions[i].priority));
+                *self = RadialMenu::Open {
+                    center,
+                    pressed_at,
+                    list: list.clone(),
+                    segments,
+                    selected: None,
+                };
+                None
+            }
+            _ => Some(list.clone()),
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        matches!(self, RadialMenu::Waiting { .. } | RadialMenu::Open { .. })
+    }
+}
+
+/// Which segment a direction points to. Segment 0 is at the top, the rest follow clockwise.
+fn segment_at(direction: Vec2, count: usize) -> usize {
+    let step = TAU / count as f32;
+    // Screen coordinates point down, so this angle goes clockwise from the top
+    let angle = direction.x.atan2(-direction.y);
+    (((angle + step / 2.0).rem_euclid(TAU)) / step) as usize % count
+}
+
+/// Direction of a segment's center in screen coordinates.
+fn segment_direction(index: usize, count: usize) -> Vec2 {
+    let angle = index as f32 * TAU / count as f32;
+    Vec2::new(angle.sin(), -angle.cos())
+}
+
+/// Direction selected with the arrow keys or a gamepad's d-pad.
+fn pad_direction(
+    keys: &Input<KeyCode>,
+    gamepads: &Gamepads,
+    gamepad_buttons: &Input<GamepadButton>,
+) -> Vec2 {
+    let mut direction = Vec2::ZERO;
+    for (key, button, offset) in [
+        (KeyCode::Up, GamepadButtonType::DPadUp, Vec2::NEG_Y),
+        (KeyCode::Down, GamepadButtonType::DPadDown, Vec2::Y),
+        (KeyCode::Left, GamepadButtonType::DPadLeft, Vec2::NEG_X),
+        (KeyCode::Right, GamepadButtonType::DPadRight, Vec2::X),
+    ] {
+        let gamepad_pressed = gamepads
+            .iter()
+            .any(|gamepad| gamepad_buttons.pressed(GamepadButton::new(gamepad, button)));
+        if keys.pressed(key) || gamepad_pressed {
+            direction += offset;
+        }
+    }
+    direction
+}
+
+#[allow(clippy::too_many_arguments)]
+fn radial_input(
+    mut radial: ResMut<RadialMenu>,
+    mut buttons: EventReader<MouseButtonInput>,
+    keys: Res<Input<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut list_ui: ResMut<ClientInteractionUi>,
+    mut blocks: ResMut<InputBlocks>,
+    mut sender: MessageSender,
+    time: Res<Time>,
+) {
+    let now = time.elapsed_seconds();
+    // Read the raw events, as the button state is reset while the wheel blocks input
+    let released = buttons
+        .iter()
+        .any(|e| e.button == INTERACT_BUTTON && e.state == ButtonState::Released);
+
+    if keys.just_pressed(KeyCode::Escape) && radial.is_active() {
+        *radial = RadialMenu::Canceled;
+    }
+
+    match &mut *radial {
+        RadialMenu::Waiting { pressed_at, .. } if released => {
+            *radial = if now - *pressed_at < HOLD_THRESHOLD {
+                RadialMenu::ShowList
+            } else {
+                RadialMenu::Canceled
+            };
+        }
+        RadialMenu::Open {
+            center,
+            pressed_at,
+            list,
+            segments,
+            selected,
+        } => {
+            let pad = pad_direction(&keys, &gamepads, &gamepad_buttons);
+            let cursor = windows
+                .get_single()
+                .ok()
+                .and_then(|w| w.cursor_position())
+                .map(|p| p - *center)
+                .unwrap_or_default();
+            // The selection stays when the pad is let go, so it doesn't have to be held while releasing
+            if pad != Vec2::ZERO {
+                *selected = Some(segment_at(pad, segments.len()));
+            } else if cursor.length() >= DEAD_ZONE {
+                *selected = Some(segment_at(cursor, segments.len()));
+            }
+
+            if released {
+                match *selected {
+                    Some(segment) => {
+                        sender.send_to_server(&InteractionExecuteRequest {
+                            index: segments[segment],
+                        });
+                    }
+                    // A click shows the usual list
+                    None if now - *pressed_at < HOLD_THRESHOLD => {
+                        list_ui.current = Some(list.clone());
+                    }
+                    None => {}
+                }
+                *radial = RadialMenu::Closed;
+            }
+        }
+        _ => {}
+    }
+
+    blocks.set(INPUT_BLOCK, radial.is_active());
+}
+
+fn radial_ui(mut contexts: EguiContexts, radial: Res<RadialMenu>, time: Res<Time>) {
+    let ctx = contexts.ctx_mut();
+    match &*radial {
+        RadialMenu::Waiting { center, pressed_at } => {
+            // Don't flash a spinner on clicks
+            if time.elapsed_seconds() - pressed_at < HOLD_THRESHOLD {
+                return;
+            }
+            egui::Area::new("radial menu spinner")
+                .fixed_pos(egui::pos2(center.x - 8.0, center.y - 8.0))
+                .order(egui::Order::Foreground)
+                .show(ctx, |ui| {
+                    ui.spinner();
+                });
+        }
+        RadialMenu::Open {
+            center,
+            list,
+            segments,
+            selected,
+            ..
+        } => {
+            let painter = ctx.layer_painter(egui::LayerId::new(
+                egui::Order::Foreground,
+                egui::Id::new("radial menu"),
+            ));
+            let center_pos = egui::pos2(center.x, center.y);
+            let visuals = ctx.style().visuals.clone();
+            painter.circle_stroke(
+                center_pos,
+                DEAD_ZONE,
+                egui::Stroke::new(2.0, visuals.widgets.noninteractive.fg_stroke.color),
+            );
+
+            for (segment, &index) in segments.iter().enumerate() {
+                let InteractionOptionClient { text, .. } = &list.interactions[index];
+                let direction = segment_direction(segment, segments.len()) * RADIUS;
+                let position = center_pos + egui::vec2(direction.x, direction.y);
+                let highlighted = *selected == Some(segment);
+
+                let galley = painter.layout_no_wrap(
+                    text.clone(),
+                    egui::FontId::proportional(15.0),
+                    if highlighted {
+                        visuals.strong_text_color()
+                    } else {
+                        visuals.text_color()
+                    },
+                );
+                let rect = egui::Rect::from_center_size(position, galley.size()).expand(6.0);
+                painter.rect_filled(
+                    rect,
+                    4.0,
+                    if highlighted {
+                        visuals.selection.bg_fill
+                    } else {
+                        visuals.window_fill
+                    },
+                );
+                painter.galley(rect.min + egui::vec2(6.0, 6.0), galley);
+            }
+        }
+        _ => {}
+    }
+}
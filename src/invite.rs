@@ -0,0 +1,450 @@
+//! Invite codes let players join a server without exchanging addresses.
+//!
+//! A host registers the addresses it can be reached at with a broker and gets a short code back.
+//! Players look the code up and try each address until one works.
+
+use std::net::SocketAddr;
+
+use async_compat::Compat;
+use bevy::{prelude::*, tasks::IoTaskPool};
+use futures_lite::future::Boxed;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "server")]
+use {
+    crate::{config::ServerConfig, ArgCommands, Args},
+    std::{
+        net::{IpAddr, Ipv4Addr, UdpSocket},
+        time::Duration,
+    },
+    tokio::time::{interval, MissedTickBehavior},
+};
+#[cfg(feature = "client")]
+use {
+    crate::{ui::has_window, GameState},
+    bevy::{ecs::event::ManualEventReader, tasks::Task},
+    futures_lite::future,
+    networking::{ClientEvent, TargetServer},
+    std::collections::VecDeque,
+};
+
+pub struct InvitePlugin;
+
+impl Plugin for InvitePlugin {
+    fn build(&self, app: &mut App) {
+        if networking::is_server(app) {
+            #[cfg(feature = "server")]
+            app.add_systems(Startup, register_invite);
+        } else {
+            #[cfg(feature = "client")]
+            app.add_event::<JoinByCode>().add_systems(
+                Update,
+                (
+                    lookup_invite_code,
+                    start_invite_join,
+                    try_next_candidate,
+                    invite_status_ui
+                        .run_if(in_state(GameState::Joining))
+                        .run_if(has_window),
+                ),
+            );
+        }
+    }
+}
+
+#[cfg(feature = "client")]
+/// Environment variable with the broker clients look up codes with
+const BROKER_ENV: &str = "SSNT_INVITE_BROKER";
+#[cfg(feature = "server")]
+/// Registrations are refreshed this often relative to their time to live
+const REFRESH_FRACTION: u32 = 2;
+#[cfg(feature = "server")]
+const MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum AddressKind {
+    /// Reachable from the same network as the host
+    Lan,
+    /// Reachable from the internet
+    Public,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct InviteAddress {
+    pub address: SocketAddr,
+    pub kind: AddressKind,
+}
+
+/// Sent by a host to register or refresh its invite code.
+#[cfg(feature = "server")]
+#[derive(Serialize, Clone, Debug)]
+pub struct InviteRegistration {
+    /// The code from the last registration, so it stays the same when refreshing
+    pub code: Option<String>,
+    pub addresses: Vec<InviteAddress>,
+    /// If the server only accepts connect tokens, which can't be created from an invite
+    pub authenticated: bool,
+    pub ttl_seconds: u64,
+}
+
+#[cfg(feature = "server")]
+#[derive(Deserialize, Clone, Debug)]
+pub struct RegisteredInvite {
+    pub code: String,
+    pub expires_in_seconds: u64,
+}
+
+/// The addresses a code points to.
+/// Brokers should add the address the registration came from as a public address.
+#[cfg(feature = "client")]
+#[derive(Deserialize, Clone, Debug)]
+pub struct InviteLookup {
+    pub addresses: Vec<InviteAddress>,
+    #[serde(default)]
+    pub authenticated: bool,
+}
+
+/// A service that stores invite codes.
+pub trait InviteBroker: Send + Sync {
+    #[cfg(feature = "server")]
+    fn register(&self, registration: InviteRegistration)
+        -> Boxed<Result<RegisteredInvite, String>>;
+
+    #[cfg(feature = "client")]
+    fn lookup(&self, code: &str) -> Boxed<Result<InviteLookup, String>>;
+}
+
+/// A broker using a small JSON API:
+/// - `POST {url}/register` with an [`InviteRegistration`], returning a [`RegisteredInvite`]
+/// - `GET {url}/lookup/{code}` returning an [`InviteLookup`], or 404 if the code is unknown or expired
+pub struct HttpBroker {
+    url: String,
+    client: reqwest::Client,
+}
+
+impl HttpBroker {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.trim_end_matches('/').to_owned(),
+            client: reqwest::Client::new(),
+        }
+    }
+}
+
+impl InviteBroker for HttpBroker {
+    #[cfg(feature = "server")]
+    fn register(
+        &self,
+        registration: InviteRegistration,
+    ) -> Boxed<Result<RegisteredInvite, String>> {
+        let request = self
+            .client
+            .post(format!("{}/register", self.url))
+            .json(&registration);
+        Box::pin(async move {
+            request
+                .send()
+                .await
+                .and_then(|r| r.error_for_status())
+                .map_err(|e| e.to_string())?
+                .json()
+                .await
+                .map_err(|e| e.to_string())
+        })
+    }
+
+    #[cfg(feature = "client")]
+    fn lookup(&self, code: &str) -> Boxed<Result<InviteLookup, String>> {
+        let request = self.client.get(format!("{}/lookup/{}", self.url, code));
+        Box::pin(async move {
+            let response = request.send().await.map_err(|e| e.to_string())?;
+            if response.status() == reqwest::StatusCode::NOT_FOUND {
+                return Err("Unknown or expired invite code".into());
+            }
+            response
+                .error_for_status()
+                .map_err(|e| e.to_string())?
+                .json()
+                .await
+                .map_err(|e| e.to_string())
+        })
+    }
+}
+
+#[cfg(feature = "server")]
+/// The address of this machine in the local network.
+/// Connecting a UDP socket doesn't send anything, but makes the OS pick the interface used for the internet.
+fn local_address() -> Option<IpAddr> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).ok()?;
+    socket.connect((Ipv4Addr::new(1, 1, 1, 1), 80)).ok()?;
+    Some(socket.local_addr().ok()?.ip())
+}
+
+#[cfg(feature = "server")]
+fn register_invite(args: Res<Args>, config: Res<ServerConfig>) {
+    let Some(ArgCommands::Host {
+        bind_address,
+        public_address,
+        invite_broker: Some(broker_url),
+        invite_ttl,
+        ..
+    }) = &args.command
+    else {
+        return;
+    };
+
+    let port = bind_address.port();
+    let lan_ip = if bind_address.ip().is_unspecified() {
+        local_address()
+    } else {
+        Some(bind_address.ip())
+    };
+    let mut addresses: Vec<_> = lan_ip
+        .map(|ip| InviteAddress {
+            address: SocketAddr::new(ip, port),
+            kind: AddressKind::Lan,
+        })
+        .into_iter()
+        .collect();
+    if let Some(ip) = public_address {
+        addresses.push(InviteAddress {
+            address: SocketAddr::new(*ip, port),
+            kind: AddressKind::Public,
+        });
+    }
+
+    let ttl = Duration::from_secs(invite_ttl * 60);
+    let broker = HttpBroker::new(broker_url);
+    let authenticated = config.registration.is_some();
+    let broker_url = broker_url.clone();
+
+    // Refresh the registration before it expires
+    let refresh_future = async move {
+        let mut interval = interval((ttl / REFRESH_FRACTION).max(MIN_REFRESH_INTERVAL));
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        let mut code: Option<String> = None;
+        loop {
+            interval.tick().await;
+            let registration = InviteRegistration {
+                code: code.clone(),
+                addresses: addresses.clone(),
+                authenticated,
+                ttl_seconds: ttl.as_secs(),
+            };
+            match broker.register(registration).await {
+                Ok(invite) => {
+                    if code.as_ref() != Some(&invite.code) {
+                        info!(
+                            code = invite.code.as_str(),
+                            broker = broker_url.as_str(),
+                            expires_in_seconds = invite.expires_in_seconds,
+                            "Invite code: {} (join with {}@{})",
+                            invite.code,
+                            invite.code,
+                            broker_url
+                        );
+                    }
+                    code = Some(invite.code);
+                }
+                Err(err) => {
+                    error!("Error registering invite code: {}", err);
+                }
+            }
+        }
+    };
+    IoTaskPool::get()
+        .spawn(Compat::new(refresh_future))
+        .detach();
+}
+
+#[cfg(feature = "client")]
+/// Sent by the main menu to join a server using an invite code.
+/// The code can contain the broker to use as `code@url`.
+#[derive(Event)]
+pub struct JoinByCode(pub String);
+
+#[cfg(feature = "client")]
+/// Progress of joining a server through an invite code.
+#[derive(Resource)]
+pub enum InviteJoin {
+    LookingUp(Task<Result<InviteLookup, String>>),
+    Connecting {
+        /// Addresses that haven't been tried yet
+        remaining: VecDeque<InviteAddress>,
+        current: InviteAddress,
+        /// Addresses that failed and why
+        failed: Vec<(InviteAddress, String)>,
+        total: usize,
+    },
+    /// Every address failed, or the code couldn't be looked up
+    Failed(String),
+}
+
+#[cfg(feature = "client")]
+fn lookup_invite_code(mut events: EventReader<JoinByCode>, mut commands: Commands) {
+    let Some(JoinByCode(input)) = events.iter().last() else {
+        return;
+    };
+
+    let (code, broker_url) = match input.trim().split_once('@') {
+        Some((code, url)) => (code.to_owned(), Some(url.to_owned())),
+        None => (input.trim().to_owned(), std::env::var(BROKER_ENV).ok()),
+    };
+    let Some(broker_url) = broker_url else {
+        commands.insert_resource(InviteJoin::Failed(format!(
+            "No invite broker set, use code@broker or set {}",
+            BROKER_ENV
+        )));
+        return;
+    };
+
+    let broker = HttpBroker::new(&broker_url);
+    let task = IoTaskPool::get().spawn(Compat::new(broker.lookup(&code)));
+    commands.insert_resource(InviteJoin::LookingUp(task));
+}
+
+#[cfg(feature = "client")]
+fn start_invite_join(
+    invite: Option<ResMut<InviteJoin>>,
+    mut client_events: EventWriter<ClientEvent>,
+) {
+    let Some(mut invite) = invite else {
+        return;
+    };
+    let InviteJoin::LookingUp(task) = &mut *invite else {
+        return;
+    };
+    let Some(result) = future::block_on(future::poll_once(task)) else {
+        return;
+    };
+
+    let lookup = match result {
+        Ok(lookup) if lookup.authenticated => {
+            *invite = InviteJoin::Failed(
+                "This server requires an account, join it from the server list".into(),
+            );
+            return;
+        }
+        Ok(lookup) => lookup,
+        Err(err) => {
+            *invite = InviteJoin::Failed(err);
+            return;
+        }
+    };
+
+    // LAN addresses are tried first, they also work when the router doesn't support hairpinning
+    let mut remaining: VecDeque<_> = lookup
+        .addresses
+        .iter()
+        .filter(|a| a.kind == AddressKind::Lan)
+        .chain(
+            lookup
+                .addresses
+                .iter()
+                .filter(|a| a.kind == AddressKind::Public),
+        )
+        .copied()
+        .collect();
+    let total = remaining.len();
+    let Some(current) = remaining.pop_front() else {
+        *invite = InviteJoin::Failed("The invite has no addresses".into());
+        return;
+    };
+
+    info!(address = %current.address, kind = ?current.kind, "Joining through invite");
+    client_events.send(ClientEvent::Join(TargetServer::Raw(current.address)));
+    *invite = InviteJoin::Connecting {
+        remaining,
+        current,
+        failed: Vec::new(),
+        total,
+    };
+}
+
+#[cfg(feature = "client")]
+/// Moves on to the next address when joining fails, and reports every failure at once.
+fn try_next_candidate(
+    invite: Option<ResMut<InviteJoin>>,
+    mut reader: Local<ManualEventReader<ClientEvent>>,
+    mut events: ResMut<Events<ClientEvent>>,
+    mut commands: Commands,
+) {
+    // Read with a manual reader, because this system also sends join events
+    let received: Vec<_> = reader.iter(&events).cloned().collect();
+    let Some(mut invite) = invite else {
+        return;
+    };
+    let InviteJoin::Connecting {
+        remaining,
+        current,
+        failed,
+        ..
+    } = &mut *invite
+    else {
+        return;
+    };
+
+    for event in received {
+        let reason = match event {
+            ClientEvent::Joined => {
+                info!(address = %current.address, kind = ?current.kind, "Joined through invite");
+                commands.remove_resource::<InviteJoin>();
+                return;
+            }
+            ClientEvent::JoinFailed(reason) => reason,
+            _ => continue,
+        };
+
+        warn!(address = %current.address, reason = reason.as_str(), "Could not join invite address");
+        failed.push((*current, reason));
+        match remaining.pop_front() {
+            Some(next) => {
+                info!(address = %next.address, kind = ?next.kind, "Trying next invite address");
+                *current = next;
+                events.send(ClientEvent::Join(TargetServer::Raw(next.address)));
+            }
+            None => {
+                let tried = failed
+                    .iter()
+                    .map(|(a, reason)| format!("{} ({:?}): {}", a.address, a.kind, reason))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                *invite = InviteJoin::Failed(format!("Could not reach the host:\n{}", tried));
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(feature = "client")]
+fn invite_status_ui(invite: Option<Res<InviteJoin>>, mut contexts: bevy_egui::EguiContexts) {
+    let Some(invite) = invite else {
+        return;
+    };
+    let InviteJoin::Connecting {
+        current,
+        failed,
+        total,
+        ..
+    } = &*invite
+    else {
+        return;
+    };
+
+    bevy_egui::egui::Area::new("invite status")
+        .anchor(
+            bevy_egui::egui::Align2::CENTER_CENTER,
+            bevy_egui::egui::Vec2::ZERO,
+        )
+        .show(contexts.ctx_mut(), |ui| {
+            ui.label(format!(
+                "Trying {:?} address {} ({} of {})",
+                current.kind,
+                current.address,
+                failed.len() + 1,
+                total
+            ));
+        });
+}
@@ -0,0 +1,579 @@
+use bevy::{prelude::*, reflect::TypeUuid, utils::HashMap};
+use networking::{
+    component::AppExt as ComponentAppExt,
+    identity::{NetworkIdentities, NetworkIdentity},
+    is_server,
+    messaging::{AppExt, MessageEvent},
+    spawning::ClientControls,
+    variable::{NetworkVar, ServerVar},
+    Networked, Players,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    flash::FlashProtection,
+    movement::speed::{SpeedModifier, SpeedModifiers},
+};
+
+use super::{clothes::ClothingHolder, StoredItem, StoredItemClient};
+
+/// Worn armor that absorbs hits and slows its wearer down, and helmet visors that can be raised.
+pub struct ArmorPlugin;
+
+impl Plugin for ArmorPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Armor>()
+            .register_type::<ArmorLayer>()
+            .register_type::<Visor>()
+            .add_networked_component::<VisorState, VisorStateClient>()
+            .add_network_message::<ToggleVisorMessage>("ToggleVisorMessage");
+
+        if is_server(app) {
+            app.add_systems(
+                Update,
+                (
+                    add_visor_state,
+                    toggle_visor.run_if(on_event::<MessageEvent<ToggleVisorMessage>>()),
+                    armor_speed,
+                ),
+            );
+        } else {
+            app.add_systems(
+                Update,
+                (attach_armor_visuals, cleanup_armor_proxies).chain(),
+            );
+        }
+    }
+}
+
+/// Placeholder shown on the body of a creature wearing armor.
+#[derive(Reflect, Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ArmorLayer {
+    /// Nothing is shown besides the item itself
+    #[default]
+    None,
+    Vest,
+    Helmet,
+}
+
+/// Clothing that protects the limb it's worn on.
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct Armor {
+    /// Fraction of the energy of hits on the limb that is absorbed, between 0 and 1
+    pub protection: f32,
+    /// Walking speed lost while it's worn, in m/s
+    pub movement_penalty: f32,
+    pub visual_layer: ArmorLayer,
+    /// Color of the placeholder
+    pub tint: Color,
+}
+
+impl Default for Armor {
+    fn default() -> Self {
+        Self {
+            protection: 0.0,
+            movement_penalty: 0.0,
+            visual_layer: ArmorLayer::None,
+            tint: Color::GRAY,
+        }
+    }
+}
+
+/// Combined protection of the armor worn on a limb, between 0 and 1.
+pub fn limb_protection(
+    limb: Entity,
+    children: &Query<&Children>,
+    holders: &Query<(), With<ClothingHolder>>,
+    armor: &Query<&Armor>,
+) -> f32 {
+    let worn = children
+        .get(limb)
+        .into_iter()
+        .flat_map(|c| c.iter())
+        .filter(|&&e| holders.contains(e))
+        .filter_map(|&holder| children.get(holder).ok())
+        .flat_map(|c| armor.iter_many(c));
+    // Every layer absorbs part of what got through the one above it
+    let passed: f32 = worn
+        .map(|armor| 1.0 - armor.protection.clamp(0.0, 1.0))
+        .product();
+    1.0 - passed
+}
+
+/// Slows bodies down by the penalty of all the armor they wear.
+#[allow(clippy::type_complexity)]
+fn armor_speed(
+    changed: Query<(), (With<Armor>, Or<(Changed<StoredItem>, Added<Armor>)>)>,
+    new_bodies: Query<(), Added<SpeedModifiers>>,
+    mut removed: RemovedComponents<StoredItem>,
+    armor: Query<(Entity, &Armor, &Parent)>,
+    holders: Query<(), With<ClothingHolder>>,
+    parents: Query<&Parent>,
+    mut bodies: Query<(Entity, &mut SpeedModifiers)>,
+) {
+    let any_removed = removed.iter().count() > 0;
+    if changed.is_empty() && new_bodies.is_empty() && !any_removed {
+        return;
+    }
+
+    let mut penalties: HashMap<Entity, f32> = HashMap::default();
+    for (entity, armor, parent) in armor.iter() {
+        // Only worn armor slows down, not armor that's carried around
+        if !holders.contains(parent.get()) {
+            continue;
+        }
+        let Some(body) = parents.iter_ancestors(entity).find(|&e| bodies.contains(e)) else {
+            continue;
+        };
+        *penalties.entry(body).or_default() += armor.movement_penalty;
+    }
+
+    for (entity, mut modifiers) in bodies.iter_mut() {
+        match penalties.get(&entity) {
+            Some(&penalty) if penalty > 0.0 => {
+                modifiers.set("armor", SpeedModifier::Add(-penalty));
+            }
+            _ => modifiers.remove("armor"),
+        }
+    }
+}
+
+/// A helmet visor that only protects from flashes while it's lowered.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct Visor {
+    pub flash_protection: f32,
+}
+
+#[derive(Component, Networked)]
+#[networked(client = "VisorStateClient")]
+pub struct VisorState {
+    down: NetworkVar<bool>,
+}
+
+#[derive(Component, Default, TypeUuid, Networked)]
+#[uuid = "7d2e9b54-1a6c-4f83-b0d7-e5c3a9f1826b"]
+#[networked(server = "VisorState")]
+pub struct VisorStateClient {
+    down: ServerVar<bool>,
+}
+
+impl VisorStateClient {
+    pub fn is_down(&self) -> bool {
+        *self.down
+    }
+}
+
+/// Visors start lowered.
+fn add_visor_state(visors: Query<(Entity, &Visor), Added<Visor>>, mut commands: Commands) {
+    for (entity, visor) in visors.iter() {
+        commands.entity(entity).insert((
+            VisorState { down: true.into() },
+            FlashProtection {
+                amount: visor.flash_protection,
+            },
+        ));
+    }
+}
+
+/// Client request to raise or lower the visor of a worn helmet.
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub(super) struct ToggleVisorMessage {
+    pub clothing: NetworkIdentity,
+}
+
+fn toggle_visor(
+    mut messages: EventReader<MessageEvent<ToggleVisorMessage>>,
+    mut visors: Query<(&Visor, &mut VisorState, &mut FlashProtection)>,
+    parents: Query<&Parent>,
+    players: Res<Players>,
+    controlled: Res<ClientControls>,
+    identities: Res<NetworkIdentities>,
+) {
+    for event in messages.iter() {
+        let Some(entity) = identities.get_entity(event.message.clothing) else {
+            continue;
+        };
+        let Some(controlled_entity) = players
+            .get(event.connection)
+            .and_then(|player| controlled.controlled_entity(player.id))
+        else {
+            continue;
+        };
+        // Players can only flip visors of helmets they wear or carry
+        if !parents
+            .iter_ancestors(entity)
+            .any(|e| e == controlled_entity)
+        {
+            continue;
+        }
+        let Ok((visor, mut state, mut protection)) = visors.get_mut(entity) else {
+            continue;
+        };
+
+        *state.down = !*state.down;
+        protection.amount = if *state.down {
+            visor.flash_protection
+        } else {
+            0.0
+        };
+    }
+}
+
+/// Client state of worn armor shown on the body.
+#[derive(Component)]
+struct ArmorVisual {
+    holder: Entity,
+    visor_down: bool,
+    proxy: Entity,
+}
+
+/// The placeholder of a worn armor item.
+#[derive(Component)]
+struct ArmorProxy {
+    item: Entity,
+}
+
+/// Offset of a placeholder from the clothing holder, which sits at the feet of the body.
+const VEST_OFFSET: Vec3 = Vec3::new(0.0, 1.0, 0.0);
+const HELMET_OFFSET: Vec3 = Vec3::new(0.0, 1.5, 0.0);
+/// Visor position relative to the helmet, creatures face along their local Z axis
+const VISOR_OFFSET: Vec3 = Vec3::new(0.0, -0.03, 0.14);
+
+#[allow(clippy::type_complexity)]
+fn attach_armor_visuals(
+    armor: Query<(
+        Entity,
+        &Armor,
+        Option<&StoredItemClient>,
+        Option<&VisorStateClient>,
+        Option<&ArmorVisual>,
+    )>,
+    holders: Query<(), With<ClothingHolder>>,
+    identities: Res<NetworkIdentities>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut commands: Commands,
+) {
+    for (item, armor, stored, visor, visual) in armor.iter() {
+        let holder = stored
+            .and_then(|s| identities.get_entity(*s.container))
+            .filter(|&e| holders.contains(e));
+        let visor_down = visor.is_some_and(|v| v.is_down());
+
+        if let Some(visual) = visual {
+            let unchanged = Some(visual.holder) == holder && visual.visor_down == visor_down;
+            if unchanged && commands.get_entity(visual.proxy).is_some() {
+                continue;
+            }
+            // Taken off, or the visor was flipped
+            if let Some(proxy) = commands.get_entity(visual.proxy) {
+                proxy.despawn_recursive();
+            }
+            commands.entity(item).remove::<ArmorVisual>();
+        }
+
+        let Some(holder) = holder else {
+            continue;
+        };
+        let (mesh, offset) = match armor.visual_layer {
+            ArmorLayer::None => continue,
+            ArmorLayer::Vest => (Mesh::from(shape::Box::new(0.45, 0.6, 0.3)), VEST_OFFSET),
+            ArmorLayer::Helmet => (
+                Mesh::from(shape::UVSphere {
+                    radius: 0.16,
+                    ..Default::default()
+                }),
+                HELMET_OFFSET,
+            ),
+        };
+        let proxy = commands
+            .spawn((
+                PbrBundle {
+                    mesh: meshes.add(mesh),
+                    material: materials.add(armor.tint.into()),
+                    transform: Transform::from_translation(offset),
+                    ..Default::default()
+                },
+                ArmorProxy { item },
+            ))
+            .set_parent(holder)
+            .id();
+        if visor_down {
+            commands
+                .spawn(PbrBundle {
+                    mesh: meshes.add(Mesh::from(shape::Box::new(0.22, 0.08, 0.04))),
+                    material: materials.add(Color::rgb(0.1, 0.1, 0.12).into()),
+                    transform: Transform::from_translation(VISOR_OFFSET),
+                    ..Default::default()
+                })
+                .set_parent(proxy);
+        }
+
+        commands.entity(item).insert(ArmorVisual {
+            holder,
+            visor_down,
+            proxy,
+        });
+    }
+}
+
+/// Removes placeholders of armor that was despawned, for example when the wearer left interest range.
+fn cleanup_armor_proxies(
+    proxies: Query<(Entity, &ArmorProxy)>,
+    items: Query<(), With<ArmorVisual>>,
+    mut commands: Commands,
+) {
+    for (proxy, armor) in proxies.iter() {
+        if !items.contains(armor.item) {
+            commands.entity(proxy).despawn_recursive();
+        }
+    }
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use bevy::ecs::system::Command;
+    use networking::{identity::NetworkCommand, loopback::LinkConditions, testing, NetworkRole};
+    use utils::task::Tasks;
+
+    use super::*;
+    use crate::{
+        body::Body,
+        config::ServerConfig,
+        items::{
+            containers::{Container, MoveItem},
+            encumbrance::EncumbranceTier,
+            Item,
+        },
+        testing::server_app,
+    };
+
+    /// A creature with a torso and a head slot.
+    fn creature(app: &mut App) -> (Entity, Entity, Entity) {
+        let creature = app
+            .world
+            .spawn((Body::default(), SpatialBundle::default()))
+            .id();
+        let mut slot = || {
+            let bundle = (
+                ClothingHolder::from_world(&mut app.world),
+                Container::from_world(&mut app.world),
+                SpatialBundle::default(),
+            );
+            app.world.spawn(bundle).set_parent(creature).id()
+        };
+        let (torso, head) = (slot(), slot());
+        app.update();
+        (creature, torso, head)
+    }
+
+    fn armor(app: &mut App, movement_penalty: f32) -> Entity {
+        app.world
+            .spawn((
+                Item::default(),
+                Armor {
+                    protection: 0.5,
+                    movement_penalty,
+                    ..Default::default()
+                },
+                SpatialBundle::default(),
+            ))
+            .id()
+    }
+
+    fn move_item(app: &mut App, item: Entity, container: Option<Entity>) {
+        app.world
+            .resource_mut::<Tasks<MoveItem>>()
+            .create_ignore(MoveItem {
+                item,
+                container,
+                position: None,
+            });
+        // Moving, then the weight and armor penalty, then the encumbrance modifier
+        for _ in 0..3 {
+            app.update();
+        }
+    }
+
+    /// Active speed modifiers, sorted by name.
+    fn modifiers(app: &App, creature: Entity) -> Vec<(&str, SpeedModifier)> {
+        let mut modifiers: Vec<_> = app
+            .world
+            .get::<SpeedModifiers>(creature)
+            .unwrap()
+            .iter()
+            .collect();
+        modifiers.sort_by_key(|(name, _)| *name);
+        modifiers
+    }
+
+    #[test]
+    fn armor_stacks_with_encumbrance_and_statuses() {
+        let mut app = server_app(ServerConfig::default());
+        let (creature, torso, head) = creature(&mut app);
+        // Stands in for any status effect
+        app.world
+            .get_mut::<SpeedModifiers>(creature)
+            .unwrap()
+            .set_multiplier("slowed", 0.75);
+        let slowed = ("slowed", SpeedModifier::Multiply(0.75));
+        let vest = armor(&mut app, 0.5);
+        // Heavy enough to encumber on its own
+        app.world.get_mut::<Item>(vest).unwrap().weight = 20.0;
+        let helmet = armor(&mut app, 0.25);
+
+        // Armor lying around doesn't slow anyone down
+        app.update();
+        assert_eq!(modifiers(&app, creature), [slowed]);
+
+        move_item(&mut app, vest, Some(torso));
+        move_item(&mut app, helmet, Some(head));
+        let encumbered = (
+            "encumbrance",
+            SpeedModifier::Multiply(EncumbranceTier::Medium.speed_multiplier()),
+        );
+        assert_eq!(
+            modifiers(&app, creature),
+            [("armor", SpeedModifier::Add(-0.75)), encumbered, slowed]
+        );
+
+        // Taking armor off removes its part of the modifiers, and nothing else
+        move_item(&mut app, vest, None);
+        assert_eq!(
+            modifiers(&app, creature),
+            [("armor", SpeedModifier::Add(-0.25)), slowed]
+        );
+        move_item(&mut app, helmet, None);
+        assert_eq!(modifiers(&app, creature), [slowed]);
+    }
+
+    #[test]
+    fn raising_the_visor_removes_flash_protection() {
+        let mut server = server_app(ServerConfig::default());
+        let mut client = testing::app(NetworkRole::Client);
+        let connector = testing::listen(&mut server);
+        testing::join(&mut client, &connector, LinkConditions::default());
+        testing::connect(&mut server, &mut [&mut client], 200);
+        let (&connection, player) = server
+            .world
+            .resource::<Players>()
+            .players()
+            .iter()
+            .next()
+            .unwrap();
+        let player = player.id;
+
+        let (creature, _, head) = creature(&mut server);
+        server
+            .world
+            .resource_mut::<ClientControls>()
+            .give_control(player, creature);
+        let helmet = armor(&mut server, 0.0);
+        server.world.entity_mut(helmet).insert(Visor {
+            flash_protection: 1.0,
+        });
+        NetworkCommand { entity: helmet }.apply(&mut server.world);
+        move_item(&mut server, helmet, Some(head));
+        let state = |server: &App| {
+            (
+                *server.world.get::<VisorState>(helmet).unwrap().down,
+                server.world.get::<FlashProtection>(helmet).unwrap().amount,
+            )
+        };
+        assert_eq!(state(&server), (true, 1.0));
+
+        let clothing = *server.world.get::<NetworkIdentity>(helmet).unwrap();
+        for expected in [(false, 0.0), (true, 1.0)] {
+            server.world.send_event(MessageEvent {
+                message: ToggleVisorMessage { clothing },
+                connection,
+            });
+            testing::update(&mut server, &mut [&mut client], 1);
+            assert_eq!(state(&server), expected);
+        }
+    }
+
+    /// The client side of a worn helmet, in a bare app with just the visual systems.
+    fn client_visuals() -> (App, Entity, Entity) {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default()))
+            .add_asset::<Mesh>()
+            .add_asset::<StandardMaterial>()
+            .init_resource::<NetworkIdentities>()
+            .add_systems(
+                Update,
+                (attach_armor_visuals, cleanup_armor_proxies).chain(),
+            );
+        let holder = app
+            .world
+            .spawn(ClothingHolder::from_world(&mut app.world))
+            .id();
+        let holder_identity = NetworkIdentity::from_raw(1);
+        app.world
+            .resource_mut::<NetworkIdentities>()
+            .set_identity(holder, holder_identity);
+        let helmet = app
+            .world
+            .spawn((
+                Armor {
+                    visual_layer: ArmorLayer::Helmet,
+                    ..Default::default()
+                },
+                StoredItemClient {
+                    container: ServerVar::from_default(holder_identity),
+                    slot: ServerVar::from_default(UVec2::ZERO),
+                    visible: ServerVar::from_default(true),
+                },
+                VisorStateClient {
+                    down: ServerVar::from_default(true),
+                },
+            ))
+            .id();
+        app.update();
+        (app, holder, helmet)
+    }
+
+    /// The placeholders of an item and if it shows a visor.
+    fn proxies(app: &mut App, item: Entity) -> Vec<(Entity, bool)> {
+        let mut proxies = app
+            .world
+            .query::<(Entity, &ArmorProxy, Option<&Children>)>();
+        proxies
+            .iter(&app.world)
+            .filter(|(_, proxy, _)| proxy.item == item)
+            .map(|(entity, _, children)| (entity, children.is_some()))
+            .collect()
+    }
+
+    #[test]
+    fn worn_helmet_is_shown_with_its_visor() {
+        let (mut app, holder, helmet) = client_visuals();
+
+        let shown = proxies(&mut app, helmet);
+        assert_eq!(shown.len(), 1);
+        let (proxy, visor) = shown[0];
+        assert!(visor);
+        assert_eq!(app.world.get::<Parent>(proxy).unwrap().get(), holder);
+
+        *app.world.get_mut::<VisorStateClient>(helmet).unwrap().down = false;
+        app.update();
+        let shown = proxies(&mut app, helmet);
+        assert_eq!(shown.len(), 1);
+        assert!(!shown[0].1);
+        assert!(app.world.get_entity(proxy).is_none());
+    }
+
+    #[test]
+    fn taking_armor_off_removes_its_placeholder() {
+        let (mut app, _, helmet) = client_visuals();
+        assert_eq!(proxies(&mut app, helmet).len(), 1);
+
+        app.world.entity_mut(helmet).remove::<StoredItemClient>();
+        app.update();
+
+        assert!(proxies(&mut app, helmet).is_empty());
+        assert!(app.world.get::<ArmorVisual>(helmet).is_none());
+    }
+}
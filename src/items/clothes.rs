@@ -1,26 +1,34 @@
 #![allow(clippy::too_many_arguments)]
 
-use bevy::{prelude::*, utils::HashMap};
-use bevy_egui::{egui, EguiContexts};
+use bevy::{ecs::system::SystemParam, prelude::*, utils::HashMap};
 use networking::{
     identity::{NetworkIdentities, NetworkIdentity},
     is_server,
-    messaging::{AppExt, MessageEvent, MessageSender},
-    spawning::{ClientControlled, ClientControls},
+    messaging::{AppExt, MessageEvent},
+    spawning::ClientControls,
     Players,
 };
 use serde::{Deserialize, Serialize};
 use utils::task::{Task, TaskId, TaskStatus, Tasks};
 
-use crate::{
-    body::{ClientHeldItem, Hands},
-    ui::has_window,
-    GameState,
+use crate::body::{Body, Hands};
+
+#[cfg(feature = "client")]
+use {
+    super::{
+        armor::{ToggleVisorMessage, VisorStateClient},
+        durability::ItemConditionClient,
+        labels::{display_name, ItemLabelClient},
+        Item, StoredItemClient,
+    },
+    crate::{body::ClientHeldItem, ui::has_window, GameState},
+    bevy_egui::{egui, EguiContexts},
+    networking::{messaging::MessageSender, spawning::ClientControlled},
 };
 
 use super::{
     containers::{Container, MoveItem},
-    Item, StoredItem, StoredItemClient,
+    StoredItem,
 };
 
 pub struct ClothingPlugin;
@@ -29,8 +37,8 @@ impl Plugin for ClothingPlugin {
     fn build(&self, app: &mut App) {
         app.register_type::<Clothing>()
             .register_type::<ClothingHolder>()
-            .add_network_message::<EquipClothingMessage>()
-            .add_network_message::<UnequipClothingMessage>();
+            .add_network_message::<EquipClothingMessage>("EquipClothingMessage")
+            .add_network_message::<UnequipClothingMessage>("UnequipClothingMessage");
 
         if is_server(app) {
             app.init_resource::<Tasks<EquipClothing>>().add_systems(
@@ -44,6 +52,7 @@ impl Plugin for ClothingPlugin {
                 ),
             );
         } else {
+            #[cfg(feature = "client")]
             app.add_systems(
                 Update,
                 client_clothing_ui
@@ -86,6 +95,26 @@ impl FromWorld for ClothingHolder {
     }
 }
 
+/// Finds who wears the clothing in a container, for reacting to clothes being put on and taken off.
+#[derive(SystemParam)]
+pub struct Wearers<'w, 's> {
+    holders: Query<'w, 's, (), With<ClothingHolder>>,
+    parents: Query<'w, 's, &'static Parent>,
+    bodies: Query<'w, 's, (), With<Body>>,
+}
+
+impl<'w, 's> Wearers<'w, 's> {
+    /// The creature wearing what is in the container, `None` if it isn't a clothing slot.
+    pub fn wearer(&self, container: Entity) -> Option<Entity> {
+        if !self.holders.contains(container) {
+            return None;
+        }
+        self.parents
+            .iter_ancestors(container)
+            .find(|&entity| self.bodies.contains(entity))
+    }
+}
+
 pub struct EquipClothing {
     pub creature: Entity,
     pub clothing: Entity,
@@ -179,12 +208,23 @@ struct UnequipClothingMessage {
     clothing: NetworkIdentity,
 }
 
+#[cfg(feature = "client")]
 fn client_clothing_ui(
     mut contexts: EguiContexts,
     bodies: Query<Entity, With<ClientControlled>>,
     child_query: Query<&Children>,
     clothing_holders: Query<(&NetworkIdentity, &ClothingHolder, Option<&Children>)>,
-    clothing: Query<(&Clothing, &Item, &NetworkIdentity), With<StoredItemClient>>,
+    clothing: Query<
+        (
+            &Clothing,
+            &Item,
+            Option<&ItemLabelClient>,
+            Option<&ItemConditionClient>,
+            &NetworkIdentity,
+            Option<&VisorStateClient>,
+        ),
+        With<StoredItemClient>,
+    >,
     held_item: ClientHeldItem,
     mut sender: MessageSender,
 ) {
@@ -211,23 +251,36 @@ fn client_clothing_ui(
                     ui.label(format!(
                         "{} - {}",
                         holder.clothing_type,
-                        if let Some((_, item, _)) = clothing_in_slot {
-                            &item.name
+                        if let Some((_, item, label, condition, _, _)) = clothing_in_slot {
+                            display_name(item, label, condition)
                         } else {
-                            "empty"
+                            "empty".into()
                         }
                     ));
 
-                    if let Some((_, _, &clothing_id)) = clothing_in_slot {
+                    if let Some((_, _, _, _, &clothing_id, visor)) = clothing_in_slot {
                         // Button to unequip worn clothing
                         if held_item.is_none() && ui.button("Unequip").clicked() {
                             sender.send_to_server(&UnequipClothingMessage {
                                 clothing: clothing_id,
                             });
                         }
+                        // Helmets with a visor can have it raised and lowered while worn
+                        if let Some(visor) = visor {
+                            let text = if visor.is_down() {
+                                "Raise visor"
+                            } else {
+                                "Lower visor"
+                            };
+                            if ui.button(text).clicked() {
+                                sender.send_to_server(&ToggleVisorMessage {
+                                    clothing: clothing_id,
+                                });
+                            }
+                        }
                     } else {
                         // Button to equip held clothing
-                        if let Some((clothing, _, &clothing_id)) = held_clothing {
+                        if let Some((clothing, _, _, _, &clothing_id, _)) = held_clothing {
                             if clothing.clothing_type == holder.clothing_type
                                 && ui.button("Equip").clicked()
                             {
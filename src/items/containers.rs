@@ -1,4 +1,5 @@
 use bevy::{
+    ecs::system::Command,
     prelude::*,
     utils::{HashMap, HashSet},
 };
@@ -9,13 +10,16 @@ use networking::{
     visibility::{NetworkVisibilities, VisibilitySystem},
     NetworkSet, Players,
 };
-use physics::PhysicsEntityCommands;
+use physics::{PhysicsEntityCommands, SetPhysicsCommand};
 use utils::task::{Task, Tasks};
 
-use super::{Item, StoredItem};
+use super::{Item, ItemSize, StoredItem};
 
 mod ui;
 
+#[cfg(feature = "client")]
+pub(crate) use ui::MoveItemMessage;
+
 pub struct ContainerPlugin;
 
 impl Plugin for ContainerPlugin {
@@ -25,6 +29,8 @@ impl Plugin for ContainerPlugin {
         if is_server(app) {
             app.init_resource::<Tasks<MoveItem>>()
                 .init_resource::<ContainerItems>()
+                .add_event::<ItemLeftContainer>()
+                .add_event::<ItemEnteredContainer>()
                 .add_systems(
                     PreUpdate,
                     item_in_container_visibility
@@ -48,6 +54,12 @@ pub struct Container {
     pub relative_position: Vec3,
     /// The items remain visible when stored
     pub items_visible: bool,
+    /// The largest size class that can be stored
+    pub max_item_size: ItemSize,
+    /// Total capacity units of the stored items, unlimited if `None`
+    pub capacity: Option<u32>,
+    /// Multiplier for the weight of stored items, for containers that make carrying easier
+    pub weight_factor: f32,
 }
 
 impl FromWorld for Container {
@@ -58,16 +70,38 @@ impl FromWorld for Container {
             attach_to: None,
             relative_position: Vec3::ZERO,
             items_visible: false,
+            max_item_size: ItemSize::Huge,
+            capacity: None,
+            weight_factor: 1.0,
+        }
+    }
+}
+
+/// Why an item could not be moved into a container.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveItemFailure {
+    TooLarge,
+    Full,
+    NoSpace,
+}
+
+impl MoveItemFailure {
+    /// Locale key of the text telling the player about the failure
+    pub fn text_key(self) -> &'static str {
+        match self {
+            MoveItemFailure::TooLarge => "container.too_large",
+            MoveItemFailure::Full | MoveItemFailure::NoSpace => "container.full",
         }
     }
 }
 
 impl Container {
-    pub fn insert_item_unchecked(&mut self, entity: Entity, position: UVec2) {
+    // Only `MoveItem` adds and removes items, so no container transition goes unnoticed
+    fn insert_item_unchecked(&mut self, entity: Entity, position: UVec2) {
         self.items.insert(position, entity);
     }
 
-    pub fn remove_item(&mut self, entity: Entity) {
+    fn remove_item(&mut self, entity: Entity) {
         let entry = self.items.iter().find(|(_, v)| v == &&entity);
         if let Some((&k, _)) = entry {
             self.items.remove(&k);
@@ -78,12 +112,22 @@ impl Container {
         self.items.is_empty()
     }
 
-    fn can_fit(&self, items_query: &Query<&Item>, item: &Item, position: UVec2) -> bool {
+    /// Checks if the item fits at the position. The item itself is ignored, so it can be moved within the container.
+    fn can_fit(
+        &self,
+        items_query: &Query<&Item>,
+        item_entity: Entity,
+        item: &Item,
+        position: UVec2,
+    ) -> bool {
         if position.x + item.size.x > self.size.x || position.y + item.size.y > self.size.y {
             return false;
         }
 
         for (&other_position, &entity) in self.items.iter() {
+            if entity == item_entity {
+                continue;
+            }
             let other_item = items_query.get(entity).unwrap();
 
             let x_overlap = (other_position.x..(other_position.x + other_item.size.x))
@@ -100,11 +144,16 @@ impl Container {
         true
     }
 
-    fn find_space(&self, items_query: &Query<&Item>, item: &Item) -> Option<UVec2> {
+    fn find_space(
+        &self,
+        items_query: &Query<&Item>,
+        item_entity: Entity,
+        item: &Item,
+    ) -> Option<UVec2> {
         let mut current = UVec2::ZERO;
         while current.x < self.size.x && current.y < self.size.y {
             // TODO: This is very inefficient <3
-            if self.can_fit(items_query, item, current) {
+            if self.can_fit(items_query, item_entity, item, current) {
                 return Some(current);
             }
 
@@ -121,6 +170,33 @@ impl Container {
     pub fn iter(&self) -> impl Iterator<Item = (&UVec2, &Entity)> {
         self.items.iter()
     }
+
+    /// Checks the size class and capacity limits, without looking for a free slot.
+    pub fn accepts(
+        &self,
+        items_query: &Query<&Item>,
+        item_entity: Entity,
+        item: &Item,
+    ) -> Result<(), MoveItemFailure> {
+        if item.size_class > self.max_item_size {
+            return Err(MoveItemFailure::TooLarge);
+        }
+
+        if let Some(capacity) = self.capacity {
+            let used: u32 = self
+                .items
+                .values()
+                .filter(|&&e| e != item_entity)
+                .filter_map(|&e| items_query.get(e).ok())
+                .map(|i| i.size_class.capacity_units())
+                .sum();
+            if used + item.size_class.capacity_units() > capacity {
+                return Err(MoveItemFailure::Full);
+            }
+        }
+
+        Ok(())
+    }
 }
 
 /// A component on containers which show their contents to everyone in the area.
@@ -143,20 +219,61 @@ pub struct MoveItem {
     pub position: Option<UVec2>,
 }
 
+/// An item was put into a container, by any means.
+/// Sent after the `ItemLeftContainer` of its previous container, if it had one.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct ItemEnteredContainer {
+    pub item: Entity,
+    pub container: Entity,
+    pub previous: Option<Entity>,
+}
+
+/// An item left a container, because it was moved, dropped or its container was destroyed.
+/// Moving an item within the same container sends neither event.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct ItemLeftContainer {
+    pub item: Entity,
+    /// May already be despawned
+    pub container: Entity,
+    /// The container the item went into, `None` if it ended up in the world or was despawned
+    pub next: Option<Entity>,
+}
+
 impl Task for MoveItem {
     type Result = MoveItemResult;
 }
 
 pub struct MoveItemResult {
     success: bool,
+    failure: Option<MoveItemFailure>,
 }
 
 impl MoveItemResult {
+    fn success() -> Self {
+        Self {
+            success: true,
+            failure: None,
+        }
+    }
+
+    fn failed(failure: Option<MoveItemFailure>) -> Self {
+        Self {
+            success: false,
+            failure,
+        }
+    }
+
     pub fn was_success(&self) -> bool {
         self.success
     }
+
+    /// Why the item could not be stored, if it was a limit of the container
+    pub fn failure(&self) -> Option<MoveItemFailure> {
+        self.failure
+    }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn do_item_move(
     mut tasks: ResMut<Tasks<MoveItem>>,
     mut containers: Query<&mut Container>,
@@ -164,34 +281,66 @@ fn do_item_move(
     mut container_items: ResMut<ContainerItems>,
     global_transforms: Query<&GlobalTransform>,
     only_items: Query<&Item>,
+    mut left: EventWriter<ItemLeftContainer>,
+    mut entered: EventWriter<ItemEnteredContainer>,
     mut commands: Commands,
 ) {
     tasks.process(|data| {
         let Ok((item_entity, item, mut stored)) = items.get_mut(data.item) else {
             warn!(task = ?data, "Failed to move item because it does not have an item component");
-            return MoveItemResult { success: false };
+            return MoveItemResult::failed(None);
         };
 
         if data.container == Some(data.item) {
             error!(task = ?data, "Tried to store a container inside itself");
-            return MoveItemResult { success: false };
+            return MoveItemResult::failed(None);
         }
 
+        // Check the target before the item leaves its old container
+        let target = match data.container {
+            Some(container_entity) => {
+                let Ok(container) = containers.get(container_entity) else {
+                    warn!(task = ?data, "Failed to move item because target is not a container");
+                    return MoveItemResult::failed(None);
+                };
+                if let Err(failure) = container.accepts(&only_items, item_entity, item) {
+                    debug!(task = ?data, ?failure, "Item does not fit in the container");
+                    return MoveItemResult::failed(Some(failure));
+                }
+                let position = data.position.unwrap_or_else(|| {
+                    container
+                        .find_space(&only_items, item_entity, item)
+                        .unwrap_or_default()
+                });
+                if !container.can_fit(&only_items, item_entity, item, position) {
+                    warn!(task = ?data, "Failed to move item because it does not fit in the container");
+                    return MoveItemResult::failed(Some(MoveItemFailure::NoSpace));
+                }
+                Some((container_entity, position))
+            }
+            None => None,
+        };
+
         // Remove from old container if it exists
-        if let Some(stored) = stored.as_mut() {
-            let mut container = containers.get_mut(*stored.container).unwrap();
+        let previous = stored.as_ref().map(|stored| *stored.container);
+        if let Some(previous) = previous {
+            let mut container = containers.get_mut(previous).unwrap();
 
             // Remove from container
             container.remove_item(item_entity);
-            if let Some(items) = container_items
-                .containers_to_items
-                .get_mut(&*stored.container)
-            {
+            if let Some(items) = container_items.containers_to_items.get_mut(&previous) {
                 items.remove(&item_entity);
             }
+            if data.container != Some(previous) {
+                left.send(ItemLeftContainer {
+                    item: item_entity,
+                    container: previous,
+                    next: data.container,
+                });
+            }
         }
 
-        let Some(container_entity) = data.container else {
+        let Some((container_entity, position)) = target else {
             // If we're putting it back into the world
             if stored.is_some() {
                 let mut entity_commands = commands.entity(item_entity);
@@ -206,22 +355,10 @@ fn do_item_move(
 
                 container_items.items_to_container.remove(&item_entity);
             }
-            return MoveItemResult { success: true };
-        };
-
-        let Ok(mut container) = containers.get_mut(container_entity) else {
-            warn!(task = ?data, "Failed to move item because target is not a container");
-            return MoveItemResult { success: false };
+            return MoveItemResult::success();
         };
 
-        let position = data
-            .position
-            .unwrap_or_else(|| container.find_space(&only_items, item).unwrap_or_default());
-        if !container.can_fit(&only_items, item, position) {
-            warn!(task = ?data, "Failed to move item because it does not fit in the container");
-            return MoveItemResult { success: false };
-        }
-
+        let mut container = containers.get_mut(container_entity).unwrap();
         container.insert_item_unchecked(data.item, position);
         if let Some(stored) = stored.as_mut() {
             *stored.container = container_entity;
@@ -254,21 +391,97 @@ fn do_item_move(
             .insert(Transform::default())
             .disable_physics();
 
-        MoveItemResult { success: true }
+        if previous != Some(container_entity) {
+            entered.send(ItemEnteredContainer {
+                item: item_entity,
+                container: container_entity,
+                previous,
+            });
+        }
+
+        MoveItemResult::success()
     });
 }
 
+/// Command that moves the items stored in an entity's containers to the floor, used before the entity is destroyed.
+/// Sends an `ItemLeftContainer` for each of them.
+/// Containers of child entities are emptied as well, but stored items keep their contents.
+pub struct DropContainedItems {
+    pub entity: Entity,
+}
+
+impl Command for DropContainedItems {
+    fn apply(self, world: &mut World) {
+        let mut containers = vec![self.entity];
+        let mut index = 0;
+        while let Some(&entity) = containers.get(index) {
+            if let Some(children) = world.get::<Children>(entity) {
+                containers.extend(
+                    children
+                        .iter()
+                        .copied()
+                        .filter(|&child| world.get::<StoredItem>(child).is_none()),
+                );
+            }
+            index += 1;
+        }
+
+        let mut items = Vec::new();
+        for entity in containers {
+            if let Some(mut container) = world.get_mut::<Container>(entity) {
+                items.extend(container.items.drain().map(|(_, item)| (entity, item)));
+            }
+        }
+        let mut container_items = world.resource_mut::<ContainerItems>();
+        for &(container, item) in items.iter() {
+            container_items.items_to_container.remove(&item);
+            container_items.containers_to_items.remove(&container);
+        }
+        for (container, item) in items {
+            world
+                .resource_mut::<Events<ItemLeftContainer>>()
+                .send(ItemLeftContainer {
+                    item,
+                    container,
+                    next: None,
+                });
+            let transform = world
+                .get::<GlobalTransform>(item)
+                .map(|global| global.compute_transform())
+                .unwrap_or_default();
+            world
+                .entity_mut(item)
+                .remove::<StoredItem>()
+                .remove_parent()
+                .insert(transform);
+            SetPhysicsCommand {
+                entity: item,
+                enabled: true,
+                disable_colliders: true,
+                new_group: None,
+            }
+            .apply(world);
+        }
+    }
+}
+
 fn cleanup_deleted_entities(
     mut deleted_items: RemovedComponents<StoredItem>,
     mut deleted_containers: RemovedComponents<Container>,
     mut container_items: ResMut<ContainerItems>,
     mut containers: Query<&mut Container>,
+    mut left: EventWriter<ItemLeftContainer>,
 ) {
     // Clean when item was deleted
     for item_entity in deleted_items.iter() {
         let Some(container_entity) = container_items.items_to_container.remove(&item_entity) else {
             continue;
         };
+        left.send(ItemLeftContainer {
+            item: item_entity,
+            container: container_entity,
+            next: None,
+        });
 
         let Ok(mut container) = containers.get_mut(container_entity) else {
             continue;
@@ -284,10 +497,24 @@ fn cleanup_deleted_entities(
 
     // Clean when container was deleted
     for container_entity in deleted_containers.iter() {
-        container_items
+        let Some(items) = container_items
             .containers_to_items
-            .remove(&container_entity);
+            .remove(&container_entity)
+        else {
+            continue;
+        };
         // TODO: Do we need to do anything to the items? I assume the container was removed using `despawn_recursive`
+        for item in items {
+            // Items deleted with the container were handled above
+            if container_items.items_to_container.get(&item) == Some(&container_entity) {
+                container_items.items_to_container.remove(&item);
+                left.send(ItemLeftContainer {
+                    item,
+                    container: container_entity,
+                    next: None,
+                });
+            }
+        }
     }
 }
 
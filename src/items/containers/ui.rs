@@ -1,10 +1,9 @@
-use bevy::{prelude::*, reflect::TypeUuid, utils::HashMap};
-use bevy_egui::{egui, EguiContexts};
+use bevy::{prelude::*, reflect::TypeUuid};
 use networking::{
     component::AppExt as _,
     identity::{EntityCommandsExt as _, NetworkIdentities, NetworkIdentity},
     is_server,
-    messaging::{AppExt, MessageEvent, MessageSender},
+    messaging::{AppExt, MessageEvent},
     variable::{NetworkVar, ServerVar},
     visibility::AlwaysVisible,
     Networked,
@@ -13,12 +12,30 @@ use serde::{Deserialize, Serialize};
 use utils::task::Tasks;
 
 use crate::{
+    feedback::{Feedback, FeedbackKind},
     interaction::{
-        ActiveInteraction, GenerateInteractionList, InteractionListEvents, InteractionOption,
-        InteractionSpecificity, InteractionStatus,
+        ActiveInteraction, GenerateInteractionList, InteractionAppExt, InteractionListEvents,
+        InteractionOption, InteractionSpecificity, InteractionStatus,
     },
-    items::{Item, StoredItemClient},
-    ui::{has_window, CloseUiMessage, NetworkUi},
+    items::Item,
+    ui::NetworkUi,
+};
+
+#[cfg(feature = "client")]
+use {
+    crate::{
+        accessibility::{AccessibilitySettings, UiColor},
+        items::{
+            drag::{DraggedItem, DropTargets, SLOT_SIZE},
+            durability::ItemConditionClient,
+            labels::{display_name, ItemLabelClient},
+            StoredItemClient,
+        },
+        ui::{has_window, CloseUiMessage},
+    },
+    bevy::utils::HashMap,
+    bevy_egui::{egui, EguiContexts},
+    networking::messaging::MessageSender,
 };
 
 use super::{Container, MoveItem};
@@ -28,9 +45,9 @@ pub struct ContainerUiPlugin;
 impl Plugin for ContainerUiPlugin {
     fn build(&self, app: &mut App) {
         app.add_networked_component::<ContainerUi, ContainerUiClient>()
-            .add_network_message::<MoveItemMessage>();
+            .add_network_message::<MoveItemMessage>("MoveItemMessage");
         if is_server(app) {
-            app.register_type::<ViewContainerInteraction>()
+            app.register_queueable_interaction::<ViewContainerInteraction>()
                 .register_type::<InsertItemInteraction>()
                 .add_systems(
                     Update,
@@ -43,8 +60,8 @@ impl Plugin for ContainerUiPlugin {
                     ),
                 );
         } else {
-            app.init_resource::<DraggedItem>()
-                .add_systems(Update, container_ui.run_if(has_window));
+            #[cfg(feature = "client")]
+            app.add_systems(Update, container_ui.in_set(DropTargets).run_if(has_window));
         }
     }
 }
@@ -62,28 +79,23 @@ struct ContainerUiClient {
     container: ServerVar<NetworkIdentity>,
 }
 
-#[derive(Resource, Default)]
-struct DraggedItem {
-    info: Option<DragInfo>,
-}
-
-struct DragInfo {
-    entity: Entity,
-    size: UVec2,
-    over_anything: bool,
-    just_dropped: bool,
-}
-
-const SLOT_SIZE: egui::Vec2 = egui::vec2(36.0, 36.0);
-
+#[cfg(feature = "client")]
 #[allow(clippy::too_many_arguments)]
 fn container_ui(
     mut contexts: EguiContexts,
     uis: Query<(Entity, &NetworkIdentity, &ContainerUiClient)>,
-    mut items: Query<(Entity, &NetworkIdentity, &Item, &mut StoredItemClient)>,
+    mut items: Query<(
+        Entity,
+        &NetworkIdentity,
+        &Item,
+        Option<&ItemLabelClient>,
+        Option<&ItemConditionClient>,
+        &mut StoredItemClient,
+    )>,
     containers: Query<(&Container, &Children)>,
     identities: Res<NetworkIdentities>,
     mut dragged: ResMut<DraggedItem>,
+    accessibility: Res<AccessibilitySettings>,
     mut sender: MessageSender,
     mut commands: Commands,
 ) {
@@ -97,7 +109,10 @@ fn container_ui(
 
         let stored: HashMap<_, _> = items
             .iter_many(children)
-            .map(|(entity, _, item, stored)| (*stored.slot, (entity, item.name.clone(), item.size)))
+            .map(|(entity, &item_identity, item, label, condition, stored)| {
+                let name = display_name(item, label, condition);
+                (*stored.slot, (entity, item_identity, name, item.size))
+            })
             .collect();
 
         let mut keep_open = true;
@@ -105,11 +120,6 @@ fn container_ui(
             .id(egui::Id::new(("container", ui_entity)))
             .open(&mut keep_open)
             .show(contexts.ctx_mut(), |ui| {
-                let anything_dragged = ui.memory(|mem| mem.is_anything_being_dragged());
-                if !anything_dragged {
-                    dragged.info = None;
-                }
-
                 let (grid_rect, grid_response) = ui.allocate_at_least(
                     egui::vec2(
                         SLOT_SIZE.x * container.size.x as f32,
@@ -120,7 +130,7 @@ fn container_ui(
 
                 // Find where dragged item would be dropped
                 let mut drop_item = None;
-                if let Some(info) = dragged.info.as_ref() {
+                if let Some(info) = dragged.get() {
                     if let Some(hover_pos) = grid_response.hover_pos() {
                         let offset = hover_pos
                             - egui::vec2(
@@ -155,12 +165,12 @@ fn container_ui(
 
                 // Draw dragged item preview in container
                 if let Some((entity, position, size)) = drop_item {
+                    dragged.hover_target();
+
                     let x_slots_to_draw = size.x.min(container.size.x - position.x);
                     let y_slots_to_draw = size.y.min(container.size.y - position.y);
                     let out_of_bounds = x_slots_to_draw != size.x || y_slots_to_draw != size.y;
                     if x_slots_to_draw != 0 && y_slots_to_draw != 0 {
-                        dragged.info.as_mut().unwrap().over_anything = true;
-
                         let item_rect = egui::Rect::from_min_size(
                             egui::pos2(
                                 position.x as f32 * SLOT_SIZE.x,
@@ -175,12 +185,13 @@ fn container_ui(
                         ui.painter().rect(
                             item_rect,
                             0.,
-                            if out_of_bounds {
-                                egui::Color32::RED
-                            } else {
-                                egui::Color32::GREEN
-                            }
-                            .gamma_multiply(0.25),
+                            accessibility
+                                .color(if out_of_bounds {
+                                    UiColor::Danger
+                                } else {
+                                    UiColor::Safe
+                                })
+                                .gamma_multiply(0.25),
                             egui::Stroke::NONE,
                         );
                     }
@@ -188,7 +199,8 @@ fn container_ui(
                     if !out_of_bounds {
                         // Drop if pointer released
                         if ui.input(|i| i.pointer.any_released()) {
-                            if let Ok((item_entity, &identity, _, mut item)) = items.get_mut(entity)
+                            if let Ok((item_entity, &identity, _, _, _, mut item)) =
+                                items.get_mut(entity)
                             {
                                 // Tell server to move it
                                 sender.send_to_server(&MoveItemMessage {
@@ -202,16 +214,15 @@ fn container_ui(
                                 item.container.set(*container_ui.container);
                                 commands.entity(item_entity).set_parent(container_entity);
 
-                                dragged.info.as_mut().unwrap().just_dropped = true;
+                                dragged.dropped();
                             }
                         }
                     }
                 }
 
                 // Paint all items in container
-                for (position, (item_entity, name, size)) in stored.iter() {
+                for (position, (item_entity, item_identity, name, size)) in stored.iter() {
                     let id = egui::Id::new(item_entity).with(container_entity);
-                    let is_being_dragged = ui.memory(|mem| mem.is_being_dragged(id));
                     let item_rect = egui::Rect::from_min_size(
                         egui::pos2(
                             position.x as f32 * SLOT_SIZE.x,
@@ -220,28 +231,18 @@ fn container_ui(
                         egui::vec2(SLOT_SIZE.x * size.x as f32, SLOT_SIZE.y * size.y as f32),
                     )
                     .translate(grid_rect.left_top().to_vec2());
-                    if is_being_dragged {
-                        if dragged.info.is_none() {
-                            dragged.info = Some(DragInfo {
-                                entity: *item_entity,
-                                size: *size,
-                                over_anything: false,
-                                just_dropped: false,
-                            });
-                        }
-                        ui.ctx().set_cursor_icon(egui::CursorIcon::Grabbing);
-
-                        let layer_id = egui::LayerId::new(egui::Order::Tooltip, id);
-                        ui.with_layer_id(layer_id, |ui| {
-                            draw_item(ui, item_rect, name);
-                        });
-
-                        if let Some(pointer_pos) = ui.ctx().pointer_interact_pos() {
-                            let delta = pointer_pos - item_rect.center();
-                            ui.ctx().translate_layer(layer_id, delta);
-                        }
+                    let response = ui.interact(item_rect, id, egui::Sense::drag());
+                    if response.drag_started() {
+                        dragged.start(*item_entity, *item_identity, name.clone(), *size);
+                    }
+                    // The dragged item follows the cursor, only its outline stays behind
+                    if response.dragged() {
+                        ui.painter().rect_stroke(
+                            item_rect,
+                            0.,
+                            egui::Stroke::new(1.0, egui::Color32::from_gray(120)),
+                        );
                     } else {
-                        ui.interact(item_rect, id, egui::Sense::drag());
                         draw_item(ui, item_rect, name);
                     }
                 }
@@ -251,34 +252,9 @@ fn container_ui(
             sender.send_to_server(&CloseUiMessage { ui: *identity });
         }
     }
-
-    // Dropping over empty space
-    if let Some(info) = dragged.info.as_ref() {
-        if !info.over_anything
-            && !info.just_dropped
-            && contexts.ctx_mut().input(|i| i.pointer.any_released())
-        {
-            let Ok((_, &item, ..)) = items.get(info.entity) else {
-                return;
-            };
-            sender.send_to_server(&MoveItemMessage {
-                item,
-                to_container: None,
-                to_slot: UVec2::ZERO,
-            });
-        }
-    }
-
-    // Remove old drag info
-    if let Some(info) = dragged.info.as_mut() {
-        if info.just_dropped {
-            dragged.info = None;
-        } else {
-            info.over_anything = false;
-        }
-    }
 }
 
+#[cfg(feature = "client")]
 fn draw_item(ui: &mut egui::Ui, item_rect: egui::Rect, name: &str) {
     ui.painter().rect(
         item_rect,
@@ -292,17 +268,21 @@ fn draw_item(ui: &mut egui::Ui, item_rect: egui::Rect, name: &str) {
     ui.put(item_rect, egui::Label::new(styled_text));
 }
 
+/// Asks the server to move an item into a container slot, or out of its container if there is none.
 #[derive(Serialize, Deserialize)]
-struct MoveItemMessage {
-    item: NetworkIdentity,
-    to_container: Option<NetworkIdentity>,
-    to_slot: UVec2,
+pub(crate) struct MoveItemMessage {
+    pub item: NetworkIdentity,
+    pub to_container: Option<NetworkIdentity>,
+    pub to_slot: UVec2,
 }
 
 fn handle_move_message(
     mut messages: EventReader<MessageEvent<MoveItemMessage>>,
     identities: Res<NetworkIdentities>,
+    containers: Query<&Container>,
+    items: Query<&Item>,
     mut item_moves: ResMut<Tasks<MoveItem>>,
+    mut feedback: Feedback,
 ) {
     for event in messages.iter() {
         let message = &event.message;
@@ -310,6 +290,23 @@ fn handle_move_message(
             continue;
         };
         let container_entity = message.to_container.and_then(|i| identities.get_entity(i));
+
+        // Tell the player why the item can't go there
+        if let (Some(container), Ok(item)) = (
+            container_entity.and_then(|c| containers.get(c).ok()),
+            items.get(item_entity),
+        ) {
+            if let Err(failure) = container.accepts(&items, item_entity, item) {
+                feedback.send(
+                    event.connection,
+                    FeedbackKind::WontFit,
+                    failure.text_key(),
+                    &[],
+                );
+                continue;
+            }
+        }
+
         item_moves.create_ignore(MoveItem {
             item: item_entity,
             container: container_entity,
@@ -412,15 +409,28 @@ fn prepare_insert_interaction(
 
 fn insert_interaction(
     mut query: Query<(Entity, &mut InsertItemInteraction, &mut ActiveInteraction)>,
-    containers: Query<Entity, With<Container>>,
+    containers: Query<(Entity, &Container)>,
+    items: Query<&Item>,
     mut move_tasks: ResMut<Tasks<MoveItem>>,
+    mut feedback: Feedback,
 ) {
-    for (_, interaction, mut active) in query.iter_mut() {
-        let Ok(container) = containers.get(active.target) else {
+    for (source, interaction, mut active) in query.iter_mut() {
+        let Ok((container_entity, container)) = containers.get(active.target) else {
+            active.status = InteractionStatus::Canceled;
+            continue;
+        };
+        let Ok(item) = items.get(interaction.item) else {
             active.status = InteractionStatus::Canceled;
             continue;
         };
 
+        if let Err(failure) = container.accepts(&items, interaction.item, item) {
+            feedback.send_to_creature(source, FeedbackKind::WontFit, failure.text_key(), &[]);
+            active.status = InteractionStatus::Canceled;
+            continue;
+        }
+        let container = container_entity;
+
         move_tasks.create_ignore(MoveItem {
             item: interaction.item,
             container: Some(container),
@@ -429,3 +439,90 @@ fn insert_interaction(
         active.status = InteractionStatus::Completed;
     }
 }
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use bevy::ecs::system::Command;
+    use networking::{
+        identity::NetworkCommand, loopback::LinkConditions, testing, NetworkRole, Players,
+    };
+
+    use super::*;
+    use crate::{
+        config::ServerConfig, feedback::ActionFeedback, items::ItemSize, testing::server_app,
+    };
+
+    #[derive(Resource, Default)]
+    struct Received(Vec<String>);
+
+    fn record_feedback(
+        mut messages: EventReader<MessageEvent<ActionFeedback>>,
+        mut received: ResMut<Received>,
+    ) {
+        received
+            .0
+            .extend(messages.iter().map(|event| event.message.text_key.clone()));
+    }
+
+    fn client() -> App {
+        let mut client = testing::app(NetworkRole::Client);
+        client
+            .add_network_message::<ActionFeedback>("ActionFeedback")
+            .init_resource::<Received>()
+            .add_systems(Update, record_feedback);
+        client
+    }
+
+    #[test]
+    fn rejected_move_tells_only_the_mover() {
+        let mut server = server_app(ServerConfig::default());
+        let connector = testing::listen(&mut server);
+        let mut mover = client();
+        testing::join(&mut mover, &connector, LinkConditions::default());
+        testing::connect(&mut server, &mut [&mut mover], 200);
+        // The mover is the only player yet
+        let connection = *server
+            .world
+            .resource::<Players>()
+            .players()
+            .keys()
+            .next()
+            .unwrap();
+        let mut bystander = client();
+        testing::join(&mut bystander, &connector, LinkConditions::default());
+        testing::connect(&mut server, &mut [&mut mover, &mut bystander], 200);
+
+        let mut container = Container::from_world(&mut server.world);
+        container.max_item_size = ItemSize::Small;
+        let container = server.world.spawn(container).id();
+        let item = server
+            .world
+            .spawn(Item {
+                size_class: ItemSize::Huge,
+                ..Default::default()
+            })
+            .id();
+        for entity in [container, item] {
+            NetworkCommand { entity }.apply(&mut server.world);
+        }
+        let identity = |entity| *server.world.get::<NetworkIdentity>(entity).unwrap();
+        let message = MoveItemMessage {
+            item: identity(item),
+            to_container: Some(identity(container)),
+            to_slot: UVec2::ZERO,
+        };
+
+        server.world.send_event(MessageEvent {
+            message,
+            connection,
+        });
+        testing::update(&mut server, &mut [&mut mover, &mut bystander], 10);
+
+        assert_eq!(
+            mover.world.resource::<Received>().0,
+            ["container.too_large"]
+        );
+        assert!(bystander.world.resource::<Received>().0.is_empty());
+        assert!(server.world.get::<Container>(container).unwrap().is_empty());
+    }
+}
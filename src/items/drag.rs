@@ -0,0 +1,325 @@
+//! Dragging items between container windows, hand slots and the world.
+//!
+//! Windows and hand slots that accept the dragged item are drop targets and send the move themselves.
+//! Releasing anywhere else over the world drops the item where the cursor points.
+
+use bevy::{prelude::*, utils::HashMap};
+use networking::{
+    identity::{NetworkIdentities, NetworkIdentity},
+    is_server,
+    messaging::{AppExt, InvalidMessage, MessageEvent},
+    spawning::ClientControls,
+    Players,
+};
+use serde::{Deserialize, Serialize};
+use utils::task::{TaskId, Tasks};
+
+use crate::{
+    body::health::{VitalStatus, Vitals},
+    feedback::{Feedback, FeedbackKind},
+    interaction::Reach,
+    movement::Stunned,
+};
+
+use super::{containers::MoveItem, StoredItem};
+
+#[cfg(feature = "client")]
+use {
+    crate::{
+        body::Body,
+        camera::MainCamera,
+        movement::StunnedClient,
+        status_hud::{Condition, VisibleConditionClient},
+        ui::{has_window, InputBlocks},
+    },
+    bevy::{ecs::query::Has, window::PrimaryWindow},
+    bevy_egui::{egui, EguiContexts},
+    bevy_rapier3d::prelude::RapierContext,
+    networking::{messaging::MessageSender, spawning::ClientControlled},
+};
+
+pub struct DragPlugin;
+
+impl Plugin for DragPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_network_message::<DropItemMessage>("DropItemMessage");
+
+        if is_server(app) {
+            app.init_resource::<PendingDrops>()
+                .add_systems(Update, (handle_drop_message, place_dropped_items));
+        } else {
+            #[cfg(feature = "client")]
+            app.init_resource::<DraggedItem>()
+                .add_systems(Update, drag_overlay.after(DropTargets).run_if(has_window));
+        }
+    }
+}
+
+/// Meters above the clicked point dropped items appear at, so they don't start inside the floor
+const DROP_HEIGHT: f32 = 0.1;
+
+/// Size of an inventory slot in egui points
+#[cfg(feature = "client")]
+pub(crate) const SLOT_SIZE: egui::Vec2 = egui::vec2(36.0, 36.0);
+#[cfg(feature = "client")]
+const INPUT_BLOCK: &str = "item drag";
+
+/// Systems drawing UI that items can be dragged from or dropped onto.
+/// They run before the drag is resolved, so a release over them isn't taken as a drop into the world.
+#[cfg(feature = "client")]
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct DropTargets;
+
+/// The item being dragged on the client, shared by all inventory windows.
+#[cfg(feature = "client")]
+#[derive(Resource, Default)]
+pub(crate) struct DraggedItem {
+    info: Option<DragInfo>,
+}
+
+#[cfg(feature = "client")]
+pub(crate) struct DragInfo {
+    pub entity: Entity,
+    pub identity: NetworkIdentity,
+    pub name: String,
+    pub size: UVec2,
+    /// A drop target is under the pointer this frame
+    over_target: bool,
+    /// A drop target took the item
+    dropped: bool,
+}
+
+#[cfg(feature = "client")]
+impl DraggedItem {
+    /// Starts dragging an item, unless one is already dragged.
+    pub(crate) fn start(
+        &mut self,
+        entity: Entity,
+        identity: NetworkIdentity,
+        name: String,
+        size: UVec2,
+    ) {
+        self.info.get_or_insert(DragInfo {
+            entity,
+            identity,
+            name,
+            size,
+            over_target: false,
+            dropped: false,
+        });
+    }
+
+    pub(crate) fn get(&self) -> Option<&DragInfo> {
+        self.info.as_ref().filter(|info| !info.dropped)
+    }
+
+    /// Called by drop targets while the pointer is over them.
+    pub(crate) fn hover_target(&mut self) {
+        if let Some(info) = self.info.as_mut() {
+            info.over_target = true;
+        }
+    }
+
+    /// Called by the drop target that took the item.
+    pub(crate) fn dropped(&mut self) {
+        if let Some(info) = self.info.as_mut() {
+            info.dropped = true;
+        }
+    }
+}
+
+/// Asks the server to drop an item out of an inventory at a position in the world.
+#[derive(Serialize, Deserialize)]
+struct DropItemMessage {
+    item: NetworkIdentity,
+    position: Vec3,
+}
+
+/// Items being taken out of their container, placed at the position once they are.
+#[derive(Resource, Default)]
+struct PendingDrops(HashMap<TaskId<MoveItem>, (Entity, Vec3)>);
+
+#[allow(clippy::too_many_arguments)]
+fn handle_drop_message(
+    mut messages: EventReader<MessageEvent<DropItemMessage>>,
+    players: Res<Players>,
+    controls: Res<ClientControls>,
+    identities: Res<NetworkIdentities>,
+    stored: Query<(), With<StoredItem>>,
+    parents: Query<&Parent>,
+    stunned: Query<(), With<Stunned>>,
+    vitals: Vitals,
+    reach: Reach,
+    mut item_moves: ResMut<Tasks<MoveItem>>,
+    mut pending: ResMut<PendingDrops>,
+    mut feedback: Feedback,
+    mut invalid: EventWriter<InvalidMessage>,
+) {
+    for event in messages.iter() {
+        let message = &event.message;
+        if !message.position.is_finite() {
+            invalid.send(InvalidMessage {
+                connection: event.connection,
+                reason: "non-finite drop position",
+            });
+            continue;
+        }
+        let Some(body) = players
+            .get(event.connection)
+            .and_then(|player| controls.controlled_entity(player.id))
+        else {
+            continue;
+        };
+        if stunned.contains(body)
+            || !matches!(
+                vitals.status(body),
+                Some(VitalStatus::Healthy | VitalStatus::Injured)
+            )
+        {
+            continue;
+        }
+        let Some(item) = identities.get_entity(message.item) else {
+            continue;
+        };
+        if !stored.contains(item) {
+            continue;
+        }
+
+        // Carried items can always be dropped, others have to be in a container within reach
+        let carried = parents.iter_ancestors(item).any(|e| e == body);
+        if !carried && !reach.can_reach(body, item) {
+            feedback.send(
+                event.connection,
+                FeedbackKind::OutOfReach,
+                "item.out_of_reach",
+                &[],
+            );
+            continue;
+        }
+        if !reach.can_reach_point(body, message.position, None) {
+            feedback.send(
+                event.connection,
+                FeedbackKind::OutOfReach,
+                "item.drop_out_of_reach",
+                &[],
+            );
+            continue;
+        }
+
+        let task = item_moves.create(MoveItem {
+            item,
+            container: None,
+            position: None,
+        });
+        pending.0.insert(task, (item, message.position));
+    }
+}
+
+/// Items leave containers where the container is, this moves them to where they were dropped.
+fn place_dropped_items(
+    mut pending: ResMut<PendingDrops>,
+    item_moves: Res<Tasks<MoveItem>>,
+    mut commands: Commands,
+) {
+    pending.0.retain(|&task, &mut (item, position)| {
+        let Some(result) = item_moves.result(task) else {
+            return true;
+        };
+        if result.was_success() {
+            if let Some(mut entity) = commands.get_entity(item) {
+                entity.insert(Transform::from_translation(
+                    position + Vec3::Y * DROP_HEIGHT,
+                ));
+            }
+        }
+        false
+    });
+}
+
+/// Draws the dragged item at the cursor and resolves releases that no drop target took.
+#[cfg(feature = "client")]
+#[allow(clippy::too_many_arguments)]
+fn drag_overlay(
+    mut contexts: EguiContexts,
+    mut dragged: ResMut<DraggedItem>,
+    mut blocks: ResMut<InputBlocks>,
+    keys: Res<Input<KeyCode>>,
+    controlled: Query<
+        (Has<StunnedClient>, Option<&VisibleConditionClient>),
+        (With<ClientControlled>, With<Body>),
+    >,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    rapier: Res<RapierContext>,
+    mut sender: MessageSender,
+) {
+    let ctx = contexts.ctx_mut();
+    let released = ctx.input(|i| i.pointer.any_released());
+    let still_dragging = ctx.memory(|m| m.is_anything_being_dragged()) || released;
+    // Dead, unconscious or stunned characters can't move items around
+    let can_act = controlled.get_single().is_ok_and(|(stunned, condition)| {
+        !stunned && condition.map_or(true, |c| c.condition() == Condition::Awake)
+    });
+
+    let Some(mut info) = dragged.info.take() else {
+        blocks.set(INPUT_BLOCK, false);
+        return;
+    };
+
+    if info.dropped || !still_dragging {
+        // A drop target took the item, or egui lost the drag
+    } else if !can_act || keys.just_pressed(KeyCode::Escape) {
+        ctx.memory_mut(|m| m.stop_dragging());
+    } else if released {
+        // Releasing over a window that doesn't take the item, or over nothing, cancels the drag
+        let target = (!info.over_target && !ctx.is_pointer_over_area())
+            .then(|| {
+                let window = windows.get_single().ok()?;
+                let (camera, camera_transform) = cameras.iter().next()?;
+                let ray = camera.viewport_to_world(camera_transform, window.cursor_position()?)?;
+                let (_, toi) =
+                    rapier.cast_ray(ray.origin, ray.direction, 100.0, true, Default::default())?;
+                Some(ray.origin + ray.direction * toi)
+            })
+            .flatten();
+        if let Some(position) = target {
+            sender.send_to_server(&DropItemMessage {
+                item: info.identity,
+                position,
+            });
+        }
+    } else {
+        if let Some(pointer) = ctx.pointer_interact_pos() {
+            let rect = egui::Rect::from_center_size(
+                pointer,
+                egui::vec2(
+                    SLOT_SIZE.x * info.size.x as f32,
+                    SLOT_SIZE.y * info.size.y as f32,
+                ),
+            );
+            let painter = ctx.layer_painter(egui::LayerId::new(
+                egui::Order::Tooltip,
+                egui::Id::new("dragged_item"),
+            ));
+            painter.rect(
+                rect,
+                0.,
+                egui::Color32::from_white_alpha(24),
+                egui::Stroke::new(1.0, egui::Color32::WHITE),
+            );
+            painter.text(
+                rect.center(),
+                egui::Align2::CENTER_CENTER,
+                &info.name,
+                egui::FontId::proportional(11.0),
+                egui::Color32::WHITE,
+            );
+            ctx.set_cursor_icon(egui::CursorIcon::Grabbing);
+        }
+
+        info.over_target = false;
+        dragged.info = Some(info);
+    }
+
+    blocks.set(INPUT_BLOCK, dragged.info.is_some());
+}
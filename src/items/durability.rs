@@ -0,0 +1,498 @@
+use std::time::Duration;
+
+use bevy::{prelude::*, reflect::TypeUuid, time::common_conditions::on_timer};
+use maps::world_to_tile;
+use networking::{
+    component::AppExt,
+    is_server,
+    spawning::ClientControls,
+    variable::{NetworkVar, ServerVar},
+    Networked, Players,
+};
+
+use crate::{
+    body::HeldItem,
+    communication::SystemMessageEvent,
+    construction::Welder,
+    interaction::{
+        ActiveInteraction, GenerateInteractionList, InteractionListEvents, InteractionOption,
+        InteractionSpecificity, InteractionStatus,
+    },
+    temperature::TemperatureGrid,
+};
+
+use super::Item;
+
+pub struct DurabilityPlugin;
+
+impl Plugin for DurabilityPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Durability>()
+            .add_networked_component::<ItemCondition, ItemConditionClient>();
+
+        if is_server(app) {
+            app.register_type::<RepairInteraction>()
+                .add_event::<ItemDamageEvent>()
+                .add_systems(
+                    Update,
+                    (
+                        (setup_durability, apply_item_damage, update_condition).chain(),
+                        burn_items
+                            .before(apply_item_damage)
+                            .run_if(on_timer(Duration::from_secs(1))),
+                        prepare_repair_interaction.in_set(GenerateInteractionList),
+                        repair_interaction.before(update_condition),
+                    ),
+                );
+        }
+    }
+}
+
+/// How much use an item can take before it breaks.
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct Durability {
+    pub current: f32,
+    pub max: f32,
+    /// Durability lost every time the item is used
+    pub wear: f32,
+}
+
+impl Default for Durability {
+    fn default() -> Self {
+        Self {
+            current: 100.0,
+            max: 100.0,
+            wear: 1.0,
+        }
+    }
+}
+
+impl Durability {
+    /// Quarters of durability left, rounded up. Zero means the item is broken.
+    fn quarters(&self) -> u8 {
+        if self.current <= 0.0 || self.max <= 0.0 {
+            return 0;
+        }
+        (self.current / self.max * 4.0).ceil().clamp(1.0, 4.0) as u8
+    }
+}
+
+/// Marks an item that has no durability left and can't be used until repaired.
+#[derive(Component)]
+pub struct Broken;
+
+/// How worn an item looks. Only updated when the durability crosses a quarter, to save traffic.
+#[derive(Component, Networked)]
+#[networked(client = "ItemConditionClient")]
+pub struct ItemCondition {
+    quarters: NetworkVar<u8>,
+}
+
+#[derive(Component, Default, Networked, TypeUuid)]
+#[uuid = "5b8d2e64-1f3a-4c97-8e0b-6a4d9f2c7e15"]
+#[networked(server = "ItemCondition")]
+pub struct ItemConditionClient {
+    quarters: ServerVar<u8>,
+}
+
+impl ItemConditionClient {
+    /// Text put in front of the item name, if the item is in bad shape.
+    pub fn prefix(&self) -> Option<&'static str> {
+        match *self.quarters {
+            0 => Some("Broken"),
+            1 => Some("Worn"),
+            _ => None,
+        }
+    }
+}
+
+/// Wears down an item or damages it.
+#[derive(Event)]
+pub struct ItemDamageEvent {
+    pub item: Entity,
+    /// The creature using the item, told when it breaks
+    pub user: Option<Entity>,
+    pub kind: ItemDamage,
+}
+
+pub enum ItemDamage {
+    /// The item was used once and loses its wear rate
+    Use,
+    /// The item was damaged by the environment, like a fire
+    Damage(f32),
+}
+
+impl ItemDamageEvent {
+    pub fn used(item: Entity, user: Entity) -> Self {
+        Self {
+            item,
+            user: Some(user),
+            kind: ItemDamage::Use,
+        }
+    }
+}
+
+fn setup_durability(
+    mut items: Query<(Entity, &mut Durability), Added<Durability>>,
+    mut commands: Commands,
+) {
+    for (entity, mut durability) in items.iter_mut() {
+        durability.current = durability.current.clamp(0.0, durability.max);
+        let quarters = durability.quarters();
+        let mut entity = commands.entity(entity);
+        entity.insert(ItemCondition {
+            quarters: quarters.into(),
+        });
+        if quarters == 0 {
+            entity.insert(Broken);
+        }
+    }
+}
+
+fn apply_item_damage(
+    mut events: EventReader<ItemDamageEvent>,
+    mut items: Query<(&mut Durability, &Item)>,
+    controls: Res<ClientControls>,
+    players: Res<Players>,
+    mut messages: EventWriter<SystemMessageEvent>,
+) {
+    for event in events.iter() {
+        let Ok((mut durability, item)) = items.get_mut(event.item) else {
+            continue;
+        };
+        if durability.current <= 0.0 {
+            continue;
+        }
+
+        let amount = match event.kind {
+            ItemDamage::Use => durability.wear,
+            ItemDamage::Damage(amount) => amount,
+        };
+        durability.current = (durability.current - amount).max(0.0);
+        if durability.current > 0.0 {
+            continue;
+        }
+
+        debug!(item = ?event.item, name = item.name.as_str(), "Item broke");
+        let Some(connection) = event
+            .user
+            .and_then(|user| controls.controlling_player(user))
+            .and_then(|player| players.get_connection(&player))
+        else {
+            continue;
+        };
+        messages.send(SystemMessageEvent {
+            receiver: connection,
+            text: format!("The {} snaps!", item.name.to_lowercase()),
+        });
+    }
+}
+
+fn update_condition(
+    mut items: Query<(Entity, &Durability, &mut ItemCondition), Changed<Durability>>,
+    mut commands: Commands,
+) {
+    for (entity, durability, mut condition) in items.iter_mut() {
+        let quarters = durability.quarters();
+        if *condition.quarters == quarters {
+            continue;
+        }
+
+        *condition.quarters = quarters;
+        if quarters == 0 {
+            commands.entity(entity).insert(Broken);
+        } else {
+            commands.entity(entity).remove::<Broken>();
+        }
+    }
+}
+
+/// Temperature in kelvin above which items start taking damage
+const ITEM_BURN_TEMPERATURE: f32 = 500.0;
+/// Durability lost per second and kelvin above the burn temperature
+const ITEM_BURN_RATE: f32 = 0.05;
+
+/// Damages items lying in fires.
+fn burn_items(
+    items: Query<(Entity, &GlobalTransform, &Durability)>,
+    grid: Res<TemperatureGrid>,
+    mut damage: EventWriter<ItemDamageEvent>,
+) {
+    for (item, transform, durability) in items.iter() {
+        if durability.current <= 0.0 {
+            continue;
+        }
+        let Some(position) = world_to_tile(transform.translation()) else {
+            continue;
+        };
+        let temperature = grid.get(position);
+        if temperature <= ITEM_BURN_TEMPERATURE {
+            continue;
+        }
+        damage.send(ItemDamageEvent {
+            item,
+            user: None,
+            kind: ItemDamage::Damage((temperature - ITEM_BURN_TEMPERATURE) * ITEM_BURN_RATE),
+        });
+    }
+}
+
+const REPAIR_TIME: Duration = Duration::from_secs(4);
+/// Part of the maximum durability restored by one repair
+const REPAIR_AMOUNT: f32 = 0.25;
+
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+#[component(storage = "SparseSet")]
+struct RepairInteraction {
+    tool: Entity,
+}
+
+// Dummy default for Reflect
+impl Default for RepairInteraction {
+    fn default() -> Self {
+        Self {
+            tool: Entity::from_raw(0),
+        }
+    }
+}
+
+fn prepare_repair_interaction(
+    list: Res<InteractionListEvents>,
+    welders: Query<(), (With<Welder>, Without<Broken>)>,
+    items: Query<&Durability>,
+) {
+    for event in list.events.iter() {
+        let Some(tool) = event.item_in_hand.filter(|&item| welders.contains(item)) else {
+            continue;
+        };
+        if tool == event.target {
+            continue;
+        }
+        let Ok(durability) = items.get(event.target) else {
+            continue;
+        };
+        if durability.current >= durability.max {
+            continue;
+        }
+
+        event.add_interaction(InteractionOption {
+            text: "Repair".into(),
+            interaction: Box::new(RepairInteraction { tool }),
+            specificity: InteractionSpecificity::Specific,
+        });
+    }
+}
+
+fn repair_interaction(
+    mut query: Query<(Entity, &RepairInteraction, &mut ActiveInteraction)>,
+    mut items: Query<&mut Durability>,
+    broken: Query<(), With<Broken>>,
+    held_item: HeldItem,
+    time: Res<Time>,
+    mut damage: EventWriter<ItemDamageEvent>,
+) {
+    for (source, interaction, mut active) in query.iter_mut() {
+        active.set_initial_duration(REPAIR_TIME);
+
+        // The welder could break or be put away while repairing
+        if broken.contains(interaction.tool) || held_item.get(source) != Some(interaction.tool) {
+            active.status = InteractionStatus::Canceled;
+            continue;
+        }
+        if !items.contains(active.target) {
+            active.status = InteractionStatus::Canceled;
+            continue;
+        }
+
+        if active.start_time() + REPAIR_TIME.as_secs_f32() > time.elapsed_seconds() {
+            continue;
+        }
+
+        let mut durability = items.get_mut(active.target).unwrap();
+        durability.current =
+            (durability.current + durability.max * REPAIR_AMOUNT).min(durability.max);
+        damage.send(ItemDamageEvent::used(interaction.tool, source));
+        active.status = InteractionStatus::Completed;
+    }
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use bevy::time::TimeUpdateStrategy;
+    use utils::task::Tasks;
+
+    use super::*;
+    use crate::{
+        body::{Body, Hand},
+        config::ServerConfig,
+        interaction::ExecuteInteraction,
+        items::containers::{Container, MoveItem},
+        testing::server_app,
+    };
+
+    const FRAME: Duration = Duration::from_millis(100);
+
+    fn update(app: &mut App, frames: u32) {
+        for _ in 0..frames {
+            app.update();
+        }
+    }
+
+    fn app() -> App {
+        let mut app = server_app(ServerConfig::default());
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(FRAME));
+        app
+    }
+
+    fn item(app: &mut App, current: f32, wear: f32) -> Entity {
+        app.world
+            .spawn((
+                Item::default(),
+                Durability {
+                    current,
+                    max: 100.0,
+                    wear,
+                },
+                SpatialBundle::default(),
+            ))
+            .id()
+    }
+
+    fn quarters(app: &App, item: Entity) -> u8 {
+        *app.world.get::<ItemCondition>(item).unwrap().quarters
+    }
+
+    fn current(app: &App, item: Entity) -> f32 {
+        app.world.get::<Durability>(item).unwrap().current
+    }
+
+    /// A creature holding a welder, repairing `target`.
+    fn start_repair(app: &mut App, welder_durability: f32, target: Entity) -> (Entity, Entity) {
+        let welder = item(app, welder_durability, 1.0);
+        app.world.entity_mut(welder).insert(Welder);
+        let bundle = (
+            Hand::from_world(&mut app.world),
+            Container::from_world(&mut app.world),
+        );
+        let hand = app.world.spawn(bundle).id();
+        let creature = app
+            .world
+            .spawn((Body::with_limbs([hand]), SpatialBundle::default()))
+            .id();
+        app.world
+            .resource_mut::<Tasks<MoveItem>>()
+            .create_ignore(MoveItem {
+                item: welder,
+                container: Some(hand),
+                position: None,
+            });
+        update(app, 2);
+
+        app.world
+            .resource_mut::<Tasks<ExecuteInteraction>>()
+            .create_ignore(ExecuteInteraction {
+                entity: creature,
+                target,
+                interaction: Box::new(RepairInteraction { tool: welder }),
+            });
+        update(app, 1);
+        assert!(app.world.get::<ActiveInteraction>(creature).is_some());
+        (creature, welder)
+    }
+
+    #[test]
+    fn wear_accumulates_until_the_item_breaks() {
+        let mut app = app();
+        let user = app.world.spawn_empty().id();
+        let wrench = item(&mut app, 100.0, 10.0);
+        update(&mut app, 1);
+        assert_eq!(quarters(&app, wrench), 4);
+
+        for _ in 0..3 {
+            app.world.send_event(ItemDamageEvent::used(wrench, user));
+        }
+        update(&mut app, 1);
+        assert_eq!(current(&app, wrench), 70.0);
+        assert_eq!(quarters(&app, wrench), 3);
+        assert!(app.world.get::<Broken>(wrench).is_none());
+
+        // More uses than it has left
+        for _ in 0..10 {
+            app.world.send_event(ItemDamageEvent::used(wrench, user));
+        }
+        update(&mut app, 2);
+        assert_eq!(current(&app, wrench), 0.0);
+        assert_eq!(quarters(&app, wrench), 0);
+        assert!(app.world.get::<Broken>(wrench).is_some());
+    }
+
+    #[test]
+    fn small_wear_keeps_the_condition_until_a_quarter_is_crossed() {
+        let mut app = app();
+        let user = app.world.spawn_empty().id();
+        let wrench = item(&mut app, 80.0, 1.0);
+        update(&mut app, 1);
+
+        for _ in 0..4 {
+            app.world.send_event(ItemDamageEvent::used(wrench, user));
+            update(&mut app, 1);
+            assert_eq!(quarters(&app, wrench), 4);
+        }
+        app.world.send_event(ItemDamageEvent::used(wrench, user));
+        update(&mut app, 1);
+        assert_eq!(current(&app, wrench), 75.0);
+        assert_eq!(quarters(&app, wrench), 3);
+    }
+
+    #[test]
+    fn repair_stops_at_max_durability() {
+        let mut app = app();
+        let target = item(&mut app, 90.0, 1.0);
+        let (creature, welder) = start_repair(&mut app, 100.0, target);
+        update(&mut app, 50);
+
+        assert!(app.world.get::<ActiveInteraction>(creature).is_none());
+        assert_eq!(current(&app, target), 100.0);
+        assert_eq!(quarters(&app, target), 4);
+        // Repairing wears down the welder
+        assert_eq!(current(&app, welder), 99.0);
+    }
+
+    #[test]
+    fn repair_restores_a_quarter_of_a_broken_item() {
+        let mut app = app();
+        let target = item(&mut app, 0.0, 1.0);
+        update(&mut app, 1);
+        assert!(app.world.get::<Broken>(target).is_some());
+
+        start_repair(&mut app, 100.0, target);
+        update(&mut app, 50);
+
+        assert_eq!(current(&app, target), 25.0);
+        assert_eq!(quarters(&app, target), 1);
+        assert!(app.world.get::<Broken>(target).is_none());
+    }
+
+    #[test]
+    fn welder_breaking_mid_repair_cancels_it() {
+        let mut app = app();
+        let target = item(&mut app, 50.0, 1.0);
+        let (creature, welder) = start_repair(&mut app, 1.0, target);
+        update(&mut app, 10);
+        assert!(app.world.get::<ActiveInteraction>(creature).is_some());
+
+        // Caught in a fire halfway through
+        app.world.send_event(ItemDamageEvent {
+            item: welder,
+            user: None,
+            kind: ItemDamage::Damage(5.0),
+        });
+        update(&mut app, 50);
+
+        assert!(app.world.get::<Broken>(welder).is_some());
+        assert!(app.world.get::<ActiveInteraction>(creature).is_none());
+        assert_eq!(current(&app, target), 50.0);
+    }
+}
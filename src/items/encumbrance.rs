@@ -0,0 +1,226 @@
+use bevy::{prelude::*, reflect::TypeUuid, utils::HashMap};
+use networking::{
+    component::AppExt,
+    is_server,
+    variable::{NetworkVar, ServerVar},
+    Networked,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{body::Body, config::ServerConfig, movement::speed::SpeedModifiers};
+
+#[cfg(feature = "client")]
+use {
+    crate::ui::has_window,
+    bevy_egui::{egui, EguiContexts},
+    networking::spawning::ClientControlled,
+};
+
+use super::{containers::Container, Item, StoredItem};
+
+/// Slows creatures down when they carry too much weight.
+pub struct EncumbrancePlugin;
+
+impl Plugin for EncumbrancePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_networked_component::<Encumbrance, EncumbranceClient>();
+        if is_server(app) {
+            let config = app
+                .world
+                .get_resource::<ServerConfig>()
+                .map(|config| config.encumbrance.clone())
+                .unwrap_or_default();
+            app.insert_resource(config).add_systems(
+                Update,
+                (add_encumbrance, update_encumbrance, encumbrance_speed).chain(),
+            );
+        } else {
+            #[cfg(feature = "client")]
+            app.add_systems(Update, encumbrance_ui.run_if(has_window));
+        }
+    }
+}
+
+/// Carried weight in kg at which each encumbrance tier starts.
+#[derive(Deserialize, Resource, Clone)]
+pub struct EncumbranceConfig {
+    #[serde(default = "EncumbranceConfig::default_medium")]
+    pub medium: f32,
+    #[serde(default = "EncumbranceConfig::default_heavy")]
+    pub heavy: f32,
+    #[serde(default = "EncumbranceConfig::default_overloaded")]
+    pub overloaded: f32,
+}
+
+impl EncumbranceConfig {
+    fn default_medium() -> f32 {
+        15.0
+    }
+
+    fn default_heavy() -> f32 {
+        30.0
+    }
+
+    fn default_overloaded() -> f32 {
+        45.0
+    }
+
+    fn tier(&self, weight: f32) -> EncumbranceTier {
+        if weight >= self.overloaded {
+            EncumbranceTier::Overloaded
+        } else if weight >= self.heavy {
+            EncumbranceTier::Heavy
+        } else if weight >= self.medium {
+            EncumbranceTier::Medium
+        } else {
+            EncumbranceTier::Light
+        }
+    }
+}
+
+impl Default for EncumbranceConfig {
+    fn default() -> Self {
+        Self {
+            medium: Self::default_medium(),
+            heavy: Self::default_heavy(),
+            overloaded: Self::default_overloaded(),
+        }
+    }
+}
+
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum EncumbranceTier {
+    #[default]
+    Light,
+    Medium,
+    Heavy,
+    Overloaded,
+}
+
+impl EncumbranceTier {
+    /// Movement speed relative to an unencumbered creature
+    pub fn speed_multiplier(&self) -> f32 {
+        match self {
+            EncumbranceTier::Light => 1.0,
+            EncumbranceTier::Medium => 0.8,
+            EncumbranceTier::Heavy => 0.6,
+            EncumbranceTier::Overloaded => 0.35,
+        }
+    }
+
+    fn label(&self) -> Option<&'static str> {
+        match self {
+            EncumbranceTier::Light => None,
+            EncumbranceTier::Medium => Some("ENCUMBERED"),
+            EncumbranceTier::Heavy => Some("HEAVILY ENCUMBERED"),
+            EncumbranceTier::Overloaded => Some("OVERLOADED"),
+        }
+    }
+}
+
+/// How much a creature is carrying.
+#[derive(Component, Networked)]
+#[networked(client = "EncumbranceClient")]
+pub struct Encumbrance {
+    tier: NetworkVar<EncumbranceTier>,
+    /// Total weight in kg, only known to the server
+    weight: f32,
+}
+
+#[derive(Component, Default, TypeUuid, Networked)]
+#[uuid = "c2a7e5d9-3f18-4b6a-9e04-8d1b7f6a2c35"]
+#[networked(server = "Encumbrance")]
+pub struct EncumbranceClient {
+    tier: ServerVar<EncumbranceTier>,
+}
+
+fn add_encumbrance(bodies: Query<Entity, Added<Body>>, mut commands: Commands) {
+    for entity in bodies.iter() {
+        commands.entity(entity).insert(Encumbrance {
+            tier: Default::default(),
+            weight: 0.0,
+        });
+    }
+}
+
+/// Recalculates the carried weight of every body when any stored item moved.
+fn update_encumbrance(
+    changed: Query<(), Or<(Changed<StoredItem>, Changed<Container>, Added<Encumbrance>)>>,
+    mut removed: RemovedComponents<StoredItem>,
+    stored_items: Query<(Entity, &Item, &StoredItem)>,
+    containers: Query<(&Container, Option<&StoredItem>)>,
+    parents: Query<&Parent>,
+    mut bodies: Query<(Entity, &mut Encumbrance)>,
+    config: Res<EncumbranceConfig>,
+) {
+    let any_removed = removed.iter().count() > 0;
+    if changed.is_empty() && !any_removed {
+        return;
+    }
+
+    let mut weights: HashMap<Entity, f32> = HashMap::default();
+    for (item_entity, item, stored) in stored_items.iter() {
+        let Some(body) = parents
+            .iter_ancestors(item_entity)
+            .find(|&e| bodies.contains(e))
+        else {
+            continue;
+        };
+
+        // Containers inside containers each apply their factor
+        let mut factor = 1.0;
+        let mut container_entity = *stored.container;
+        while let Ok((container, parent_stored)) = containers.get(container_entity) {
+            factor *= container.weight_factor;
+            match parent_stored {
+                Some(parent_stored) => container_entity = *parent_stored.container,
+                None => break,
+            }
+        }
+
+        *weights.entry(body).or_default() += item.weight * factor;
+    }
+
+    for (entity, mut encumbrance) in bodies.iter_mut() {
+        let weight = weights.get(&entity).copied().unwrap_or_default();
+        let tier = config.tier(weight);
+        if encumbrance.weight != weight {
+            encumbrance.weight = weight;
+        }
+        if *encumbrance.tier != tier {
+            *encumbrance.tier = tier;
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn encumbrance_speed(
+    mut bodies: Query<
+        (&Encumbrance, &mut SpeedModifiers),
+        Or<(Changed<Encumbrance>, Added<SpeedModifiers>)>,
+    >,
+) {
+    for (encumbrance, mut modifiers) in bodies.iter_mut() {
+        modifiers.set_multiplier("encumbrance", encumbrance.tier.speed_multiplier());
+    }
+}
+
+#[cfg(feature = "client")]
+fn encumbrance_ui(
+    mut contexts: EguiContexts,
+    encumbrance: Query<&EncumbranceClient, With<ClientControlled>>,
+) {
+    let Some(label) = encumbrance.get_single().ok().and_then(|e| e.tier.label()) else {
+        return;
+    };
+
+    egui::Area::new("encumbrance_indicator")
+        .anchor(egui::Align2::CENTER_BOTTOM, egui::vec2(0.0, -110.0))
+        .show(contexts.ctx_mut(), |ui| {
+            ui.label(
+                egui::RichText::new(label)
+                    .color(egui::Rgba::from_rgb(1.0, 0.6, 0.2))
+                    .size(16.0),
+            );
+        });
+}
@@ -0,0 +1,244 @@
+use bevy::prelude::*;
+use networking::{identity::NetworkIdentities, is_server};
+
+use crate::body::{Body, Hand, HandsClient, LimbSide};
+
+use super::{client_update_item_visibility, StoredItemClient};
+
+/// Shows items held in hands on the character holding them.
+pub struct HeldItemPlugin;
+
+impl Plugin for HeldItemPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<HeldOffset>()
+            .register_type::<TwoHanded>()
+            .register_type::<HoldSocket>()
+            .register_type::<HoldSocketKind>();
+
+        if !is_server(app) {
+            app.add_systems(
+                Update,
+                (attach_held_items, restore_released_items, cleanup_proxies)
+                    .chain()
+                    .after(client_update_item_visibility),
+            );
+        }
+    }
+}
+
+/// How an item is positioned relative to the hand socket holding it.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct HeldOffset {
+    pub translation: Vec3,
+    pub rotation: Quat,
+}
+
+/// An item that is held with both hands when it's in the active hand.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct TwoHanded;
+
+#[derive(Reflect, Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum HoldSocketKind {
+    #[default]
+    Left,
+    Right,
+    /// Used for two handed items
+    Wield,
+}
+
+/// A point on a body model that held items are attached to.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct HoldSocket {
+    pub kind: HoldSocketKind,
+}
+
+/// Client state of an item that is shown on a socket.
+/// The item itself is hidden and a copy of its meshes is spawned on the socket,
+/// so networked transform updates of the item don't fight with the attachment.
+#[derive(Component)]
+struct HeldVisual {
+    socket: Entity,
+    proxy: Entity,
+}
+
+/// The copy of a held item shown on a socket.
+#[derive(Component)]
+struct HeldProxy {
+    item: Entity,
+}
+
+/// The socket an item in a hand should be shown on.
+/// Falls back to the hand itself if the hand is not attached to a body with sockets.
+#[allow(clippy::too_many_arguments)]
+fn find_socket(
+    hand_entity: Entity,
+    hand: &Hand,
+    two_handed: bool,
+    parents: &Query<&Parent>,
+    children: &Query<&Children>,
+    bodies: &Query<Option<&HandsClient>, With<Body>>,
+    sockets: &Query<&HoldSocket>,
+    identities: &NetworkIdentities,
+) -> Entity {
+    let Some((body, hands)) = parents
+        .iter_ancestors(hand_entity)
+        .find_map(|e| bodies.get(e).ok().map(|hands| (e, hands)))
+    else {
+        return hand_entity;
+    };
+
+    let active = hands
+        .and_then(|h| identities.get_entity(h.active_hand()))
+        .is_some_and(|active| active == hand_entity);
+    let kind = match hand.side {
+        _ if two_handed && active => HoldSocketKind::Wield,
+        LimbSide::Left => HoldSocketKind::Left,
+        LimbSide::Right => HoldSocketKind::Right,
+    };
+
+    children
+        .iter_descendants(body)
+        .find(|e| sockets.get(*e).is_ok_and(|s| s.kind == kind))
+        .unwrap_or(hand_entity)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn attach_held_items(
+    items: Query<(
+        Entity,
+        &StoredItemClient,
+        Option<&HeldOffset>,
+        Has<TwoHanded>,
+        Option<&HeldVisual>,
+    )>,
+    released: Query<(Entity, &HeldVisual), Without<StoredItemClient>>,
+    hands: Query<&Hand>,
+    parents: Query<&Parent>,
+    children: Query<&Children>,
+    bodies: Query<Option<&HandsClient>, With<Body>>,
+    sockets: Query<&HoldSocket>,
+    meshes: Query<(&Handle<Mesh>, &Handle<StandardMaterial>)>,
+    transforms: Query<&Transform>,
+    identities: Res<NetworkIdentities>,
+    mut visibilities: Query<&mut Visibility>,
+    mut commands: Commands,
+) {
+    // Taken out of a container, for example by being dropped or thrown
+    for (item, visual) in released.iter() {
+        release(&mut commands, item, visual);
+    }
+
+    for (item, stored, offset, two_handed, visual) in items.iter() {
+        let hand = identities
+            .get_entity(*stored.container)
+            .and_then(|e| hands.get(e).ok().map(|hand| (e, hand)));
+        let Some((hand_entity, hand)) = hand else {
+            // Moved into a container that isn't a hand
+            if let Some(visual) = visual {
+                release(&mut commands, item, visual);
+            }
+            continue;
+        };
+
+        let socket = find_socket(
+            hand_entity,
+            hand,
+            two_handed,
+            &parents,
+            &children,
+            &bodies,
+            &sockets,
+            &identities,
+        );
+        // Container changes can make the item visible again
+        if let Ok(mut visibility) = visibilities.get_mut(item) {
+            if *visibility != Visibility::Hidden {
+                *visibility = Visibility::Hidden;
+            }
+        }
+
+        if let Some(visual) = visual {
+            // Sockets are resolved every frame, so switching hands or losing the body moves the visual
+            if visual.socket == socket && commands.get_entity(visual.proxy).is_some() {
+                continue;
+            }
+            release(&mut commands, item, visual);
+        }
+
+        // Copy the meshes of the item onto the socket
+        let proxy = commands
+            .spawn((
+                SpatialBundle::from_transform(
+                    offset
+                        .map(|o| {
+                            Transform::from_translation(o.translation).with_rotation(o.rotation)
+                        })
+                        .unwrap_or_default(),
+                ),
+                HeldProxy { item },
+            ))
+            .set_parent(socket)
+            .id();
+        for entity in std::iter::once(item).chain(children.iter_descendants(item)) {
+            let Ok((mesh, material)) = meshes.get(entity) else {
+                continue;
+            };
+            let transform = if entity == item {
+                Transform::default()
+            } else {
+                transforms.get(entity).copied().unwrap_or_default()
+            };
+            commands
+                .spawn(PbrBundle {
+                    mesh: mesh.clone(),
+                    material: material.clone(),
+                    transform,
+                    ..Default::default()
+                })
+                .set_parent(proxy);
+        }
+
+        commands.entity(item).insert(HeldVisual { socket, proxy });
+    }
+}
+
+fn release(commands: &mut Commands, item: Entity, visual: &HeldVisual) {
+    if let Some(proxy) = commands.get_entity(visual.proxy) {
+        proxy.despawn_recursive();
+    }
+    commands.entity(item).remove::<HeldVisual>();
+}
+
+/// Shows the item again when it leaves a hand, for example by being dropped or thrown.
+fn restore_released_items(
+    mut items: Query<(&mut Visibility, Option<&StoredItemClient>), Without<HeldVisual>>,
+    mut removed: RemovedComponents<HeldVisual>,
+) {
+    for item in removed.iter() {
+        let Ok((mut visibility, stored)) = items.get_mut(item) else {
+            continue;
+        };
+        let visible = stored.map(|s| *s.visible).unwrap_or(true);
+        *visibility = if visible {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+    }
+}
+
+/// Removes copies of items that were despawned, for example when the holder left interest range.
+fn cleanup_proxies(
+    proxies: Query<(Entity, &HeldProxy)>,
+    items: Query<(), With<HeldVisual>>,
+    mut commands: Commands,
+) {
+    for (proxy, held) in proxies.iter() {
+        if !items.contains(held.item) {
+            commands.entity(proxy).despawn_recursive();
+        }
+    }
+}
@@ -0,0 +1,372 @@
+use bevy::{prelude::*, reflect::TypeUuid, utils::HashMap};
+use networking::{
+    component::AppExt as ComponentAppExt,
+    identity::{NetworkIdentities, NetworkIdentity},
+    is_server,
+    messaging::{AppExt, MessageEvent, MessageReceivers, MessageSender},
+    spawning::ClientControls,
+    variable::{NetworkVar, ServerVar},
+    ConnectionId, Networked, Players,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    body::HeldItem,
+    interaction::{
+        ActiveInteraction, GenerateInteractionList, InteractionListEvents, InteractionOption,
+        InteractionSpecificity, InteractionStatus,
+    },
+    text_filter::{PlayerText, TextContext},
+};
+
+#[cfg(feature = "client")]
+use {
+    super::durability::ItemConditionClient,
+    crate::{ui::has_window, GameState},
+    bevy_egui::{egui, EguiContexts},
+};
+
+use super::Item;
+
+pub struct LabelPlugin;
+
+impl Plugin for LabelPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Pen>()
+            .register_type::<LabelInteraction>()
+            .register_type::<RemoveLabelInteraction>()
+            .add_networked_component::<ItemLabel, ItemLabelClient>()
+            .add_network_message::<OpenLabelDialog>("OpenLabelDialog")
+            .add_network_message::<SetLabelRequest>("SetLabelRequest");
+
+        if is_server(app) {
+            app.init_resource::<LabelCooldowns>().add_systems(
+                Update,
+                (
+                    prepare_label_interactions.in_set(GenerateInteractionList),
+                    execute_label_interaction,
+                    execute_remove_label_interaction,
+                    handle_set_label_request,
+                ),
+            );
+        } else {
+            #[cfg(feature = "client")]
+            app.init_resource::<LabelDialog>().add_systems(
+                Update,
+                (
+                    client_open_label_dialog,
+                    label_dialog_ui
+                        .run_if(in_state(GameState::Game))
+                        .run_if(has_window),
+                ),
+            );
+        }
+    }
+}
+
+/// Marks an object as a pen, which can write labels.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct Pen;
+
+/// Text a player has written on an item.
+#[derive(Component, Networked)]
+#[networked(client = "ItemLabelClient")]
+pub struct ItemLabel {
+    text: NetworkVar<String>,
+}
+
+impl ItemLabel {
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+#[derive(Component, Default, Networked, TypeUuid)]
+#[uuid = "c2f1a3a4-5d8e-4b7f-9a61-0f3e2d9b8c17"]
+#[networked(server = "ItemLabel")]
+pub struct ItemLabelClient {
+    text: ServerVar<String>,
+}
+
+/// The name of an item as it should be displayed to players, including any label and wear.
+#[cfg(feature = "client")]
+pub fn display_name(
+    item: &Item,
+    label: Option<&ItemLabelClient>,
+    condition: Option<&ItemConditionClient>,
+) -> String {
+    let name = match condition.and_then(|c| c.prefix()) {
+        Some(prefix) => format!("{} {}", prefix, item.name.to_lowercase()),
+        None => item.name.clone(),
+    };
+    match label {
+        Some(label) => format!("{} ({})", name, *label.text),
+        None => name,
+    }
+}
+
+/// The longest label that can be written
+pub const MAX_LABEL_LENGTH: usize = 24;
+/// Seconds a player has to wait between writing labels
+const LABEL_COOLDOWN: f32 = 2.0;
+/// How far away an item can be labeled from
+const LABEL_RANGE: f32 = 1.5;
+
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+#[component(storage = "SparseSet")]
+struct LabelInteraction {
+    target: Entity,
+}
+
+// Dummy default for Reflect
+impl Default for LabelInteraction {
+    fn default() -> Self {
+        Self {
+            target: Entity::from_raw(0),
+        }
+    }
+}
+
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+#[component(storage = "SparseSet")]
+struct RemoveLabelInteraction {
+    target: Entity,
+}
+
+// Dummy default for Reflect
+impl Default for RemoveLabelInteraction {
+    fn default() -> Self {
+        Self {
+            target: Entity::from_raw(0),
+        }
+    }
+}
+
+/// Server message to show the label input to a player
+#[derive(Serialize, Deserialize)]
+struct OpenLabelDialog {
+    target: NetworkIdentity,
+}
+
+/// Client message to write a label on an item
+#[derive(Serialize, Deserialize)]
+struct SetLabelRequest {
+    target: NetworkIdentity,
+    text: String,
+}
+
+#[derive(Resource, Default)]
+struct LabelCooldowns {
+    last_label: HashMap<ConnectionId, f32>,
+}
+
+fn prepare_label_interactions(
+    list: Res<InteractionListEvents>,
+    pens: Query<(), With<Pen>>,
+    items: Query<Option<&ItemLabel>, With<Item>>,
+) {
+    for event in list.events.iter() {
+        let Ok(label) = items.get(event.target) else {
+            continue;
+        };
+
+        let holds_pen = event
+            .item_in_hand
+            .map(|item| pens.contains(item))
+            .unwrap_or(false);
+        if holds_pen && event.item_in_hand != Some(event.target) {
+            event.add_interaction(InteractionOption {
+                text: "Label".into(),
+                interaction: Box::new(LabelInteraction {
+                    target: event.target,
+                }),
+                specificity: InteractionSpecificity::Specific,
+            });
+        }
+
+        if label.is_some() {
+            event.add_interaction(InteractionOption {
+                text: "Remove label".into(),
+                interaction: Box::new(RemoveLabelInteraction {
+                    target: event.target,
+                }),
+                specificity: InteractionSpecificity::Common,
+            });
+        }
+    }
+}
+
+fn execute_label_interaction(
+    mut query: Query<(Entity, &LabelInteraction, &mut ActiveInteraction)>,
+    identities: Res<NetworkIdentities>,
+    controls: Res<ClientControls>,
+    players: Res<Players>,
+    mut sender: MessageSender,
+) {
+    for (entity, interaction, mut active) in query.iter_mut() {
+        let (Some(target), Some(connection)) = (
+            identities.get_identity(interaction.target),
+            controls
+                .controlling_player(entity)
+                .and_then(|p| players.get_connection(&p)),
+        ) else {
+            active.status = InteractionStatus::Canceled;
+            continue;
+        };
+
+        // The label is written once the player sends the text
+        sender.send(
+            &OpenLabelDialog { target },
+            MessageReceivers::Single(connection),
+        );
+        active.status = InteractionStatus::Completed;
+    }
+}
+
+fn execute_remove_label_interaction(
+    mut query: Query<(&RemoveLabelInteraction, &mut ActiveInteraction)>,
+    mut commands: Commands,
+) {
+    for (interaction, mut active) in query.iter_mut() {
+        if let Some(mut entity) = commands.get_entity(interaction.target) {
+            entity.remove::<ItemLabel>();
+        }
+        active.status = InteractionStatus::Completed;
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn handle_set_label_request(
+    mut messages: EventReader<MessageEvent<SetLabelRequest>>,
+    mut cooldowns: ResMut<LabelCooldowns>,
+    identities: Res<NetworkIdentities>,
+    controls: Res<ClientControls>,
+    players: Res<Players>,
+    held_item: HeldItem,
+    pens: Query<(), With<Pen>>,
+    items: Query<(), With<Item>>,
+    transforms: Query<&GlobalTransform>,
+    time: Res<Time>,
+    mut player_text: PlayerText,
+    mut commands: Commands,
+) {
+    for event in messages.iter() {
+        let connection = event.connection;
+        let Some(player) = players
+            .get(connection)
+            .and_then(|p| controls.controlled_entity(p.id))
+        else {
+            continue;
+        };
+        let Some(target) = identities.get_entity(event.message.target) else {
+            continue;
+        };
+        if !items.contains(target) {
+            continue;
+        }
+
+        // Must be holding a pen
+        if !held_item
+            .get(player)
+            .map(|item| pens.contains(item))
+            .unwrap_or(false)
+        {
+            continue;
+        }
+
+        // Must be in reach
+        let (Ok(player_transform), Ok(target_transform)) =
+            (transforms.get(player), transforms.get(target))
+        else {
+            continue;
+        };
+        if player_transform
+            .translation()
+            .distance(target_transform.translation())
+            > LABEL_RANGE
+        {
+            continue;
+        }
+
+        let now = time.elapsed_seconds();
+        if let Some(last) = cooldowns.last_label.get(&connection) {
+            if now - last < LABEL_COOLDOWN {
+                debug!(connection = ?connection, "Label request rate limited");
+                continue;
+            }
+        }
+        cooldowns.last_label.insert(connection, now);
+
+        let Some(text) = player_text.accept(connection, TextContext::Label, &event.message.text)
+        else {
+            continue;
+        };
+        if text.is_empty() {
+            commands.entity(target).remove::<ItemLabel>();
+        } else {
+            commands
+                .entity(target)
+                .insert(ItemLabel { text: text.into() });
+        }
+    }
+}
+
+#[cfg(feature = "client")]
+#[derive(Resource, Default)]
+struct LabelDialog {
+    target: Option<NetworkIdentity>,
+    text: String,
+}
+
+#[cfg(feature = "client")]
+fn client_open_label_dialog(
+    mut messages: EventReader<MessageEvent<OpenLabelDialog>>,
+    mut dialog: ResMut<LabelDialog>,
+) {
+    for event in messages.iter() {
+        dialog.target = Some(event.message.target);
+        dialog.text.clear();
+    }
+}
+
+#[cfg(feature = "client")]
+fn label_dialog_ui(
+    mut contexts: EguiContexts,
+    mut dialog: ResMut<LabelDialog>,
+    mut sender: MessageSender,
+) {
+    let Some(target) = dialog.target else {
+        return;
+    };
+
+    let dialog = &mut *dialog;
+    let mut open = true;
+    let mut submitted = false;
+    egui::Window::new("Label")
+        .open(&mut open)
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+        .show(contexts.ctx_mut(), |ui| {
+            egui::TextEdit::singleline(&mut dialog.text)
+                .char_limit(MAX_LABEL_LENGTH)
+                .hint_text("Label text")
+                .show(ui);
+            if ui.button("Write").clicked() {
+                submitted = true;
+            }
+        });
+
+    if submitted {
+        sender.send_to_server(&SetLabelRequest {
+            target,
+            text: std::mem::take(&mut dialog.text),
+        });
+    }
+    if submitted || !open {
+        dialog.target = None;
+    }
+}
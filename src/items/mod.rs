@@ -7,16 +7,31 @@ use networking::{
     NetworkManager, Networked,
 };
 
-use self::{clothes::ClothingPlugin, containers::ContainerPlugin};
+use self::{
+    armor::ArmorPlugin, clothes::ClothingPlugin, containers::ContainerPlugin, drag::DragPlugin,
+    durability::DurabilityPlugin, encumbrance::EncumbrancePlugin, held::HeldItemPlugin,
+    labels::LabelPlugin, paper::PaperPlugin, surface::SurfacePlugin,
+    tile_contents::TileContentsPlugin,
+};
 
+pub mod armor;
 pub mod clothes;
 pub mod containers;
+pub mod drag;
+pub mod durability;
+pub mod encumbrance;
+pub mod held;
+pub mod labels;
+pub mod paper;
+pub mod surface;
+mod tile_contents;
 
 pub struct ItemPlugin;
 
 impl Plugin for ItemPlugin {
     fn build(&self, app: &mut App) {
         app.register_type::<Item>()
+            .register_type::<ItemSize>()
             .add_networked_component::<StoredItem, StoredItemClient>()
             .add_systems(Startup, load_item_assets);
 
@@ -29,7 +44,19 @@ impl Plugin for ItemPlugin {
                 ),
             );
         }
-        app.add_plugins((ContainerPlugin, ClothingPlugin));
+        app.add_plugins((
+            ContainerPlugin,
+            ClothingPlugin,
+            ArmorPlugin,
+            LabelPlugin,
+            PaperPlugin,
+            HeldItemPlugin,
+            EncumbrancePlugin,
+            SurfacePlugin,
+            DurabilityPlugin,
+            DragPlugin,
+            TileContentsPlugin,
+        ));
     }
 }
 
@@ -38,6 +65,10 @@ impl Plugin for ItemPlugin {
 pub struct Item {
     pub name: String,
     pub size: UVec2,
+    /// How bulky the item is, limits which containers it fits in
+    pub size_class: ItemSize,
+    /// Weight in kg
+    pub weight: f32,
 }
 
 impl Default for Item {
@@ -45,6 +76,32 @@ impl Default for Item {
         Self {
             name: "Default item name".to_string(),
             size: UVec2::ONE,
+            size_class: ItemSize::Normal,
+            weight: 1.0,
+        }
+    }
+}
+
+/// Size classes of items, from pocket sized to barely carryable.
+#[derive(Reflect, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum ItemSize {
+    Tiny,
+    Small,
+    #[default]
+    Normal,
+    Bulky,
+    Huge,
+}
+
+impl ItemSize {
+    /// How much of a container's capacity the item uses
+    pub fn capacity_units(&self) -> u32 {
+        match self {
+            ItemSize::Tiny => 1,
+            ItemSize::Small => 2,
+            ItemSize::Normal => 4,
+            ItemSize::Bulky => 8,
+            ItemSize::Huge => 16,
         }
     }
 }
@@ -0,0 +1,581 @@
+use bevy::{prelude::*, utils::HashMap};
+use networking::{
+    identity::{NetworkIdentities, NetworkIdentity},
+    is_server,
+    messaging::{AppExt, MessageEvent, MessageReceivers, MessageSender},
+    spawning::ClientControls,
+    ConnectionId, Players,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    body::HeldItem,
+    interaction::{
+        ActiveInteraction, GenerateInteractionList, InteractionListEvents, InteractionOption,
+        InteractionSpecificity, InteractionStatus,
+    },
+    text_filter::{PlayerText, TextContext},
+};
+
+#[cfg(feature = "client")]
+use {
+    crate::{ui::has_window, GameState},
+    bevy_egui::{egui, EguiContexts},
+};
+
+use super::{
+    labels::{ItemLabel, Pen},
+    Item,
+};
+
+pub struct PaperPlugin;
+
+impl Plugin for PaperPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Paper>()
+            .register_type::<WritePaperInteraction>()
+            .register_type::<ReadPaperInteraction>()
+            .add_network_message::<OpenPaperEditor>("OpenPaperEditor")
+            .add_network_message::<WritePaperRequest>("WritePaperRequest")
+            .add_network_message::<PaperContentMessage>("PaperContentMessage");
+
+        if is_server(app) {
+            app.init_resource::<WriteCooldowns>().add_systems(
+                Update,
+                (
+                    prepare_paper_interactions.in_set(GenerateInteractionList),
+                    execute_write_interaction,
+                    execute_read_interaction,
+                    handle_write_request,
+                ),
+            );
+        } else {
+            #[cfg(feature = "client")]
+            app.init_resource::<PaperWindows>().add_systems(
+                Update,
+                (
+                    client_receive_paper_messages,
+                    paper_ui
+                        .run_if(in_state(GameState::Game))
+                        .run_if(has_window),
+                )
+                    .chain(),
+            );
+        }
+    }
+}
+
+/// An item that can be written on with a pen.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct Paper;
+
+/// What has been written on a paper.
+/// Only kept on the server and sent to players reading it, so papers don't cost anything to replicate.
+#[derive(Component, Default)]
+pub struct PaperContent(pub String);
+
+/// The longest text that can be written at once
+pub const MAX_WRITE_LENGTH: usize = 1000;
+/// The most text a paper can hold, including author lines
+const MAX_PAPER_LENGTH: usize = 5000;
+/// Seconds a player has to wait between writing
+const WRITE_COOLDOWN: f32 = 2.0;
+
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+#[component(storage = "SparseSet")]
+struct WritePaperInteraction {
+    target: Entity,
+}
+
+// Dummy default for Reflect
+impl Default for WritePaperInteraction {
+    fn default() -> Self {
+        Self {
+            target: Entity::from_raw(0),
+        }
+    }
+}
+
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+#[component(storage = "SparseSet")]
+struct ReadPaperInteraction {
+    target: Entity,
+}
+
+// Dummy default for Reflect
+impl Default for ReadPaperInteraction {
+    fn default() -> Self {
+        Self {
+            target: Entity::from_raw(0),
+        }
+    }
+}
+
+/// Server message to show the paper editor to a player
+#[derive(Serialize, Deserialize)]
+struct OpenPaperEditor {
+    target: NetworkIdentity,
+}
+
+/// Client message to add text to a paper
+#[derive(Serialize, Deserialize)]
+struct WritePaperRequest {
+    target: NetworkIdentity,
+    text: String,
+}
+
+/// Server message with the content of a paper a player is reading
+#[derive(Serialize, Deserialize)]
+struct PaperContentMessage {
+    target: NetworkIdentity,
+    title: String,
+    content: String,
+}
+
+#[derive(Resource, Default)]
+struct WriteCooldowns {
+    last_write: HashMap<ConnectionId, f32>,
+}
+
+fn prepare_paper_interactions(
+    list: Res<InteractionListEvents>,
+    pens: Query<(), With<Pen>>,
+    papers: Query<(), With<Paper>>,
+    held_item: HeldItem,
+) {
+    for event in list.events.iter() {
+        if !papers.contains(event.target) {
+            continue;
+        }
+
+        event.add_interaction(InteractionOption {
+            text: "Read".into(),
+            interaction: Box::new(ReadPaperInteraction {
+                target: event.target,
+            }),
+            specificity: InteractionSpecificity::Common,
+        });
+
+        // Writing needs the paper in one hand and a pen in the other
+        let holds_pen = event
+            .item_in_hand
+            .map(|item| pens.contains(item))
+            .unwrap_or(false);
+        let holds_paper = held_item.all(event.source).any(|i| i == event.target);
+        if holds_pen && holds_paper {
+            event.add_interaction(InteractionOption {
+                text: "Write".into(),
+                interaction: Box::new(WritePaperInteraction {
+                    target: event.target,
+                }),
+                specificity: InteractionSpecificity::Specific,
+            });
+        }
+    }
+}
+
+fn execute_write_interaction(
+    mut query: Query<(Entity, &WritePaperInteraction, &mut ActiveInteraction)>,
+    identities: Res<NetworkIdentities>,
+    controls: Res<ClientControls>,
+    players: Res<Players>,
+    mut sender: MessageSender,
+) {
+    for (entity, interaction, mut active) in query.iter_mut() {
+        let (Some(target), Some(connection)) = (
+            identities.get_identity(interaction.target),
+            controls
+                .controlling_player(entity)
+                .and_then(|p| players.get_connection(&p)),
+        ) else {
+            active.status = InteractionStatus::Canceled;
+            continue;
+        };
+
+        // The text is added once the player sends it
+        sender.send(
+            &OpenPaperEditor { target },
+            MessageReceivers::Single(connection),
+        );
+        active.status = InteractionStatus::Completed;
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn execute_read_interaction(
+    mut query: Query<(Entity, &ReadPaperInteraction, &mut ActiveInteraction)>,
+    papers: Query<(&Item, Option<&PaperContent>), With<Paper>>,
+    labels: Query<&ItemLabel>,
+    identities: Res<NetworkIdentities>,
+    controls: Res<ClientControls>,
+    players: Res<Players>,
+    mut sender: MessageSender,
+) {
+    for (entity, interaction, mut active) in query.iter_mut() {
+        let (Ok((item, content)), Some(target), Some(connection)) = (
+            papers.get(interaction.target),
+            identities.get_identity(interaction.target),
+            controls
+                .controlling_player(entity)
+                .and_then(|p| players.get_connection(&p)),
+        ) else {
+            active.status = InteractionStatus::Canceled;
+            continue;
+        };
+
+        let title = match labels.get(interaction.target) {
+            Ok(label) => format!("{} ({})", item.name, label.text()),
+            Err(_) => item.name.clone(),
+        };
+        sender.send(
+            &PaperContentMessage {
+                target,
+                title,
+                content: content.map(|c| c.0.clone()).unwrap_or_default(),
+            },
+            MessageReceivers::Single(connection),
+        );
+        active.status = InteractionStatus::Completed;
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn handle_write_request(
+    mut messages: EventReader<MessageEvent<WritePaperRequest>>,
+    mut cooldowns: ResMut<WriteCooldowns>,
+    identities: Res<NetworkIdentities>,
+    controls: Res<ClientControls>,
+    players: Res<Players>,
+    held_item: HeldItem,
+    pens: Query<(), With<Pen>>,
+    mut papers: Query<Option<&mut PaperContent>, With<Paper>>,
+    names: Query<&Name>,
+    time: Res<Time>,
+    mut player_text: PlayerText,
+    mut commands: Commands,
+) {
+    // Requests are handled one after another, so writes from several players never overwrite each other.
+    // Papers written on for the first time only get their content once the commands run.
+    let mut new_contents: HashMap<Entity, String> = HashMap::default();
+    for event in messages.iter() {
+        let connection = event.connection;
+        let Some(player) = players
+            .get(connection)
+            .and_then(|p| controls.controlled_entity(p.id))
+        else {
+            continue;
+        };
+        let Some(target) = identities.get_entity(event.message.target) else {
+            continue;
+        };
+        let Ok(content) = papers.get_mut(target) else {
+            continue;
+        };
+
+        // Must be holding the paper and a pen
+        let (mut holds_paper, mut holds_pen) = (false, false);
+        for item in held_item.all(player) {
+            holds_paper |= item == target;
+            holds_pen |= pens.contains(item);
+        }
+        if !holds_paper || !holds_pen {
+            continue;
+        }
+
+        let now = time.elapsed_seconds();
+        if let Some(last) = cooldowns.last_write.get(&connection) {
+            if now - last < WRITE_COOLDOWN {
+                debug!(connection = ?connection, "Paper write request rate limited");
+                continue;
+            }
+        }
+
+        let Some(text) = player_text.accept(connection, TextContext::Paper, &event.message.text)
+        else {
+            continue;
+        };
+        if text.is_empty() {
+            continue;
+        }
+        cooldowns.last_write.insert(connection, now);
+
+        let author = names
+            .get(player)
+            .map(|n| n.as_str().to_owned())
+            .unwrap_or_else(|_| "Unknown".to_owned());
+        let minutes = (now / 60.0) as u32;
+        let entry = format!(
+            "{}\n- {}, {:02}:{:02}\n",
+            text,
+            author,
+            minutes / 60,
+            minutes % 60
+        );
+
+        let appended = match content {
+            Some(mut content) => append_entry(&mut content.0, &entry),
+            None => append_entry(new_contents.entry(target).or_default(), &entry),
+        };
+        if !appended {
+            debug!(connection = ?connection, "Paper is full");
+        }
+    }
+
+    for (target, content) in new_contents {
+        commands.entity(target).insert(PaperContent(content));
+    }
+}
+
+/// Adds an entry below the existing text of a paper. Returns false if the paper is full.
+fn append_entry(content: &mut String, entry: &str) -> bool {
+    if content.len() + entry.len() > MAX_PAPER_LENGTH {
+        return false;
+    }
+    if !content.is_empty() {
+        content.push('\n');
+    }
+    content.push_str(entry);
+    true
+}
+
+#[cfg(feature = "client")]
+#[derive(Default)]
+struct PaperEditor {
+    target: Option<NetworkIdentity>,
+    text: String,
+}
+
+/// Papers the player has open.
+#[cfg(feature = "client")]
+#[derive(Resource, Default)]
+struct PaperWindows {
+    editor: PaperEditor,
+    /// Title and content of papers being read
+    reading: HashMap<NetworkIdentity, (String, String)>,
+}
+
+#[cfg(feature = "client")]
+fn client_receive_paper_messages(
+    mut open_editor: EventReader<MessageEvent<OpenPaperEditor>>,
+    mut contents: EventReader<MessageEvent<PaperContentMessage>>,
+    mut windows: ResMut<PaperWindows>,
+) {
+    for event in open_editor.iter() {
+        windows.editor.target = Some(event.message.target);
+        windows.editor.text.clear();
+    }
+    for event in contents.iter() {
+        let message = &event.message;
+        windows.reading.insert(
+            message.target,
+            (message.title.clone(), message.content.clone()),
+        );
+    }
+}
+
+#[cfg(feature = "client")]
+fn paper_ui(
+    mut contexts: EguiContexts,
+    mut windows: ResMut<PaperWindows>,
+    mut sender: MessageSender,
+) {
+    let windows = &mut *windows;
+    let ctx = contexts.ctx_mut();
+
+    windows.reading.retain(|target, (title, content)| {
+        let mut open = true;
+        egui::Window::new(title.as_str())
+            .id(egui::Id::new(("paper", *target)))
+            .open(&mut open)
+            .default_size(egui::vec2(320.0, 400.0))
+            .show(ctx, |ui| {
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    if content.is_empty() {
+                        ui.weak("The paper is blank.");
+                    } else {
+                        ui.label(content.as_str());
+                    }
+                });
+            });
+        open
+    });
+
+    let Some(target) = windows.editor.target else {
+        return;
+    };
+    let editor = &mut windows.editor;
+    let mut open = true;
+    let mut submitted = false;
+    egui::Window::new("Write")
+        .open(&mut open)
+        .collapsible(false)
+        .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+        .show(ctx, |ui| {
+            egui::TextEdit::multiline(&mut editor.text)
+                .char_limit(MAX_WRITE_LENGTH)
+                .desired_rows(10)
+                .hint_text("Write something")
+                .show(ui);
+            ui.label(format!(
+                "{}/{}",
+                editor.text.chars().count(),
+                MAX_WRITE_LENGTH
+            ));
+            if ui.button("Write").clicked() {
+                submitted = true;
+            }
+        });
+
+    if submitted {
+        sender.send_to_server(&WritePaperRequest {
+            target,
+            text: std::mem::take(&mut editor.text),
+        });
+    }
+    if submitted || !open {
+        editor.target = None;
+    }
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use bevy::ecs::system::{Command, SystemState};
+    use networking::{identity::NetworkCommand, loopback::LinkConditions, testing, NetworkRole};
+    use utils::task::Tasks;
+
+    use super::*;
+    use crate::{
+        body::{Body, Hand},
+        config::ServerConfig,
+        items::containers::{Container, MoveItem},
+        testing::server_app,
+    };
+
+    fn hand(world: &mut World) -> Entity {
+        let bundle = (Hand::from_world(world), Container::from_world(world));
+        world.spawn(bundle).id()
+    }
+
+    fn put_in(world: &mut World, item: Entity, container: Entity) {
+        world
+            .resource_mut::<Tasks<MoveItem>>()
+            .create_ignore(MoveItem {
+                item,
+                container: Some(container),
+                position: None,
+            });
+    }
+
+    fn write(client: &mut App, target: NetworkIdentity, text: &str) {
+        let mut state = SystemState::<MessageSender>::new(&mut client.world);
+        state
+            .get_mut(&mut client.world)
+            .send_to_server(&WritePaperRequest {
+                target,
+                text: text.into(),
+            });
+    }
+
+    #[test]
+    fn concurrent_writes_interleave() {
+        let mut server = server_app(ServerConfig::default());
+        let mut alice = testing::app(NetworkRole::Client);
+        let mut bob = testing::app(NetworkRole::Client);
+        for client in [&mut alice, &mut bob] {
+            client.add_network_message::<WritePaperRequest>("WritePaperRequest");
+        }
+        let connector = testing::listen(&mut server);
+        testing::join(&mut alice, &connector, LinkConditions::default());
+        testing::join(&mut bob, &connector, LinkConditions::default());
+        testing::connect(&mut server, &mut [&mut alice, &mut bob], 200);
+
+        let paper = server
+            .world
+            .spawn((Paper, Item::default(), SpatialBundle::default()))
+            .id();
+        NetworkCommand { entity: paper }.apply(&mut server.world);
+        let target = server
+            .world
+            .resource::<NetworkIdentities>()
+            .get_identity(paper)
+            .unwrap();
+
+        // Both creatures reach into the hand holding the paper, so their writes can land in the same frame
+        let paper_hand = hand(&mut server.world);
+        put_in(&mut server.world, paper, paper_hand);
+        let players: Vec<_> = server
+            .world
+            .resource::<Players>()
+            .players()
+            .iter()
+            .map(|(&connection, player)| (connection, player.id))
+            .collect();
+        let mut authors = HashMap::default();
+        for (index, (connection, player)) in players.into_iter().enumerate() {
+            let pen_hand = hand(&mut server.world);
+            let pen = server
+                .world
+                .spawn((Pen, Item::default(), SpatialBundle::default()))
+                .id();
+            put_in(&mut server.world, pen, pen_hand);
+            let name = format!("Writer {}", index);
+            let creature = server
+                .world
+                .spawn((
+                    Name::new(name.clone()),
+                    Body::with_limbs([pen_hand, paper_hand]),
+                ))
+                .id();
+            server
+                .world
+                .resource_mut::<ClientControls>()
+                .give_control(player, creature);
+            authors.insert(connection, name);
+        }
+        testing::update(&mut server, &mut [&mut alice, &mut bob], 5);
+
+        // The paper has no content yet, so both entries are added before it gets any
+        write(&mut alice, target, "First line from one side");
+        write(&mut bob, target, "Second line from the other");
+        testing::update(&mut server, &mut [&mut alice, &mut bob], 5);
+
+        let content = &server.world.get::<PaperContent>(paper).unwrap().0;
+        let entries: Vec<_> = content.split("\n\n").collect();
+        assert_eq!(entries.len(), 2, "{:?}", content);
+        for text in ["First line from one side", "Second line from the other"] {
+            let entry = entries
+                .iter()
+                .find(|entry| entry.starts_with(text))
+                .unwrap_or_else(|| panic!("{:?} is missing from {:?}", text, content));
+            // Every entry is directly followed by the line of its own author
+            let author = entry.lines().nth(1).unwrap();
+            assert!(
+                authors
+                    .values()
+                    .any(|name| author.starts_with(&format!("- {},", name))),
+                "{:?}",
+                author
+            );
+        }
+        assert_ne!(
+            entries[0].lines().nth(1),
+            entries[1].lines().nth(1),
+            "both entries have the same author"
+        );
+    }
+
+    #[test]
+    fn full_paper_rejects_entries() {
+        let mut content = String::new();
+        assert!(append_entry(&mut content, "first\n"));
+        assert!(append_entry(&mut content, "second\n"));
+        assert_eq!(content, "first\n\nsecond\n");
+
+        let long = "a".repeat(MAX_PAPER_LENGTH);
+        assert!(!append_entry(&mut content, &long));
+        assert_eq!(content, "first\n\nsecond\n");
+    }
+}
@@ -0,0 +1,415 @@
+use bevy::{ecs::system::Command, prelude::*};
+use networking::{
+    identity::{NetworkIdentities, NetworkIdentity},
+    is_server,
+    messaging::{AppExt, InvalidMessage, MessageEvent},
+    spawning::ClientControls,
+    Players,
+};
+use physics::{PhysicsEntityCommands, SetPhysicsCommand};
+use serde::{Deserialize, Serialize};
+use utils::task::{TaskId, Tasks};
+
+use crate::{
+    body::HeldItem,
+    interaction::{
+        ActiveInteraction, InteractionListEvents, InteractionOption, InteractionSpecificity,
+        InteractionStatus, Reach,
+    },
+};
+
+use super::containers::MoveItem;
+
+#[cfg(feature = "client")]
+use {
+    super::Item,
+    crate::{camera::MainCamera, interaction::InteractionExecuteDefaultRequest, GameState},
+    bevy::{ecs::system::SystemParam, math::Vec3Swizzles, window::PrimaryWindow},
+    networking::messaging::MessageSender,
+};
+
+/// Lets items be placed on furniture like tables.
+pub struct SurfacePlugin;
+
+impl Plugin for SurfacePlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Surface>()
+            .add_network_message::<MoveOnSurfaceRequest>("MoveOnSurfaceRequest");
+
+        if is_server(app) {
+            app.register_type::<PlaceOnSurfaceInteraction>()
+                .add_systems(
+                    Update,
+                    (
+                        prepare_place_interaction
+                            .in_set(crate::interaction::GenerateInteractionList),
+                        place_interaction,
+                        move_on_surface,
+                        leave_surface,
+                    ),
+                );
+        } else {
+            #[cfg(feature = "client")]
+            app.init_resource::<SurfaceDrag>().add_systems(
+                Update,
+                client_surface_drag.run_if(in_state(GameState::Game)),
+            );
+        }
+    }
+}
+
+/// Height above the tabletop placed items are put at, so they don't clip into it
+const SURFACE_OFFSET: f32 = 0.02;
+/// How close to a stored item a click on the surface has to be to select it
+#[cfg(feature = "client")]
+const PICK_RADIUS: f32 = 0.2;
+/// How far the cursor has to move in pixels before a click becomes a drag
+#[cfg(feature = "client")]
+const DRAG_THRESHOLD: f32 = 6.0;
+
+/// Furniture that items can be put on.
+/// Items on the surface are its children, positioned relative to it.
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct Surface {
+    /// Half the size of the top on the X and Z axis
+    pub half_size: Vec2,
+    /// Height of the top relative to the entity
+    pub height: f32,
+}
+
+impl FromWorld for Surface {
+    fn from_world(_: &mut World) -> Self {
+        Self {
+            half_size: Vec2::splat(0.45),
+            height: 0.7,
+        }
+    }
+}
+
+impl Surface {
+    /// Turns a world position into a position on the top of the surface.
+    /// Returns `None` if it's outside of the top.
+    fn local_point(&self, surface_transform: &GlobalTransform, point: Vec3) -> Option<Vec3> {
+        let local = surface_transform.affine().inverse().transform_point3(point);
+        if local.x.abs() > self.half_size.x || local.z.abs() > self.half_size.y {
+            return None;
+        }
+        Some(Vec3::new(local.x, self.height + SURFACE_OFFSET, local.z))
+    }
+}
+
+/// An item that has been placed on a [`Surface`].
+#[derive(Component)]
+pub struct OnSurface {
+    surface: Entity,
+}
+
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+#[component(storage = "SparseSet")]
+struct PlaceOnSurfaceInteraction {
+    /// Where the item goes, relative to the surface
+    position: Vec3,
+    #[reflect(ignore)]
+    move_task: Option<(Entity, TaskId<MoveItem>)>,
+}
+
+// Dummy implementation for reflection
+impl FromWorld for PlaceOnSurfaceInteraction {
+    fn from_world(_: &mut World) -> Self {
+        Self {
+            position: Vec3::ZERO,
+            move_task: None,
+        }
+    }
+}
+
+fn prepare_place_interaction(
+    interaction_list: Res<InteractionListEvents>,
+    surfaces: Query<(&Surface, &GlobalTransform)>,
+    reach: Reach,
+) {
+    for event in interaction_list.events.iter() {
+        let Ok((surface, transform)) = surfaces.get(event.target) else {
+            continue;
+        };
+        if event.item_in_hand.is_none() {
+            continue;
+        }
+        let Some(position) = event
+            .point
+            .and_then(|point| surface.local_point(transform, point))
+        else {
+            continue;
+        };
+        if !reach.can_reach(event.source, event.target) {
+            continue;
+        }
+
+        event.add_interaction(InteractionOption {
+            text: "Place".into(),
+            interaction: Box::new(PlaceOnSurfaceInteraction {
+                position,
+                move_task: None,
+            }),
+            specificity: InteractionSpecificity::Specific,
+        });
+    }
+}
+
+fn place_interaction(
+    mut query: Query<(
+        Entity,
+        &mut PlaceOnSurfaceInteraction,
+        &mut ActiveInteraction,
+    )>,
+    surfaces: Query<(), With<Surface>>,
+    held_item: HeldItem,
+    mut item_moves: ResMut<Tasks<MoveItem>>,
+    mut commands: Commands,
+) {
+    for (source, mut interaction, mut active) in query.iter_mut() {
+        if !surfaces.contains(active.target) {
+            active.status = InteractionStatus::Canceled;
+            continue;
+        }
+
+        let Some((item, task)) = interaction.move_task else {
+            // Take the item out of the hand first, so the container forgets about it
+            let Some(item) = held_item.get(source) else {
+                active.status = InteractionStatus::Canceled;
+                continue;
+            };
+            let task = item_moves.create(MoveItem {
+                item,
+                container: None,
+                position: None,
+            });
+            interaction.move_task = Some((item, task));
+            continue;
+        };
+
+        let Some(result) = item_moves.result(task) else {
+            continue;
+        };
+        if !result.was_success() {
+            active.status = InteractionStatus::Canceled;
+            continue;
+        }
+
+        commands
+            .entity(item)
+            .insert((
+                Transform::from_translation(interaction.position),
+                OnSurface {
+                    surface: active.target,
+                },
+            ))
+            .set_parent(active.target)
+            .disable_physics();
+        active.status = InteractionStatus::Completed;
+    }
+}
+
+/// Client message to move an item to another place on its surface.
+#[derive(Serialize, Deserialize)]
+struct MoveOnSurfaceRequest {
+    item: NetworkIdentity,
+    /// The new position in world space
+    point: Vec3,
+}
+
+#[allow(clippy::too_many_arguments)]
+fn move_on_surface(
+    mut messages: EventReader<MessageEvent<MoveOnSurfaceRequest>>,
+    mut items: Query<(&OnSurface, &mut Transform)>,
+    surfaces: Query<(&Surface, &GlobalTransform)>,
+    identities: Res<NetworkIdentities>,
+    players: Res<Players>,
+    controls: Res<ClientControls>,
+    reach: Reach,
+    mut invalid: EventWriter<InvalidMessage>,
+) {
+    for event in messages.iter() {
+        if !event.message.point.is_finite() {
+            invalid.send(InvalidMessage {
+                connection: event.connection,
+                reason: "non-finite surface position",
+            });
+            continue;
+        }
+        let Some(creature) = players
+            .get(event.connection)
+            .and_then(|player| controls.controlled_entity(player.id))
+        else {
+            continue;
+        };
+        let Some(item_entity) = identities.get_entity(event.message.item) else {
+            continue;
+        };
+        let Ok((on_surface, mut transform)) = items.get_mut(item_entity) else {
+            continue;
+        };
+        let Ok((surface, surface_transform)) = surfaces.get(on_surface.surface) else {
+            continue;
+        };
+
+        // Has to stay on the same surface, which has to be in reach
+        let Some(position) = surface.local_point(surface_transform, event.message.point) else {
+            debug!(connection = ?event.connection, "Surface move out of bounds");
+            continue;
+        };
+        if !reach.can_reach(creature, on_surface.surface) || !reach.can_reach(creature, item_entity)
+        {
+            continue;
+        }
+
+        transform.translation = position;
+    }
+}
+
+/// Forgets about surfaces once an item is taken off them, for example by being picked up.
+fn leave_surface(
+    items: Query<(Entity, &OnSurface, Option<&Parent>), Changed<Parent>>,
+    removed_parents: Query<Entity, (With<OnSurface>, Without<Parent>)>,
+    mut commands: Commands,
+) {
+    for (entity, on_surface, parent) in items.iter() {
+        if parent.map(|p| p.get()) != Some(on_surface.surface) {
+            commands.entity(entity).remove::<OnSurface>();
+        }
+    }
+    for entity in removed_parents.iter() {
+        commands.entity(entity).remove::<OnSurface>();
+    }
+}
+
+/// Command that moves the items on a surface to the floor, used before the surface is destroyed.
+pub struct DropSurfaceItems {
+    pub surface: Entity,
+}
+
+impl Command for DropSurfaceItems {
+    fn apply(self, world: &mut World) {
+        let Some(children) = world.get::<Children>(self.surface) else {
+            return;
+        };
+        let items: Vec<Entity> = children
+            .iter()
+            .copied()
+            .filter(|&child| world.get::<OnSurface>(child).is_some())
+            .collect();
+
+        for item in items {
+            let transform = world
+                .get::<GlobalTransform>(item)
+                .map(|global| global.compute_transform())
+                .unwrap_or_default();
+            world
+                .entity_mut(item)
+                .remove_parent()
+                .remove::<OnSurface>()
+                .insert(transform);
+            SetPhysicsCommand {
+                entity: item,
+                enabled: true,
+                disable_colliders: true,
+                new_group: None,
+            }
+            .apply(world);
+        }
+    }
+}
+
+/// Finds items on surfaces on the client, as their colliders are disabled.
+#[cfg(feature = "client")]
+#[derive(SystemParam)]
+pub struct SurfacePicker<'w, 's> {
+    surfaces: Query<'w, 's, (&'static GlobalTransform, Option<&'static Children>), With<Surface>>,
+    items: Query<'w, 's, &'static GlobalTransform, With<Item>>,
+    parents: Query<'w, 's, &'static Parent>,
+}
+
+#[cfg(feature = "client")]
+impl<'w, 's> SurfacePicker<'w, 's> {
+    /// The item closest to a clicked point, if a surface was clicked
+    pub fn item_at(&self, hit: Entity, point: Vec3) -> Option<Entity> {
+        let surface = std::iter::once(hit)
+            .chain(self.parents.iter_ancestors(hit))
+            .find(|&e| self.surfaces.contains(e))?;
+        let (_, children) = self.surfaces.get(surface).ok()?;
+
+        children?
+            .iter()
+            .filter_map(|&child| {
+                let transform = self.items.get(child).ok()?;
+                Some((child, transform.translation().xz().distance(point.xz())))
+            })
+            .filter(|(_, distance)| *distance <= PICK_RADIUS)
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(entity, _)| entity)
+    }
+}
+
+/// An item on a surface that was clicked, but the mouse button wasn't released yet.
+#[cfg(feature = "client")]
+#[derive(Resource, Default)]
+pub struct SurfaceDrag {
+    pressed: Option<(Entity, NetworkIdentity, Vec2)>,
+}
+
+#[cfg(feature = "client")]
+impl SurfaceDrag {
+    pub fn start(&mut self, item: Entity, identity: NetworkIdentity, cursor: Vec2) {
+        self.pressed = Some((item, identity, cursor));
+    }
+}
+
+#[cfg(feature = "client")]
+/// Releasing where an item was clicked uses it, releasing elsewhere moves it.
+fn client_surface_drag(
+    buttons: Res<Input<MouseButton>>,
+    mut drag: ResMut<SurfaceDrag>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    transforms: Query<&GlobalTransform>,
+    mut sender: MessageSender,
+) {
+    if !buttons.just_released(MouseButton::Left) {
+        return;
+    }
+    let Some((item, identity, start)) = drag.pressed.take() else {
+        return;
+    };
+    let Some(cursor) = windows.get_single().ok().and_then(|w| w.cursor_position()) else {
+        return;
+    };
+
+    if cursor.distance(start) < DRAG_THRESHOLD {
+        sender.send_to_server(&InteractionExecuteDefaultRequest {
+            target: identity,
+            point: None,
+        });
+        return;
+    }
+
+    // Keep the item at its height on the surface
+    let Ok(item_height) = transforms.get(item).map(|t| t.translation().y) else {
+        return;
+    };
+    let Some((camera, camera_transform)) = cameras.iter().next() else {
+        return;
+    };
+    let Some(ray) = camera.viewport_to_world(camera_transform, cursor) else {
+        return;
+    };
+    let Some(toi) = ray.intersect_plane(Vec3::new(0.0, item_height, 0.0), Vec3::Y) else {
+        return;
+    };
+
+    sender.send_to_server(&MoveOnSurfaceRequest {
+        item: identity,
+        point: ray.origin + ray.direction * toi,
+    });
+}
@@ -0,0 +1,684 @@
+use std::time::Duration;
+
+use bevy::{
+    ecs::system::SystemParam, math::Vec3Swizzles, prelude::*, time::common_conditions::on_timer,
+    utils::HashMap,
+};
+use networking::{
+    identity::NetworkIdentity,
+    is_server,
+    messaging::{AppExt, MessageEvent, MessageReceivers, MessageSender},
+    spawning::ClientControls,
+    ConnectionId, Players,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::communication::SpeechName;
+
+use super::{labels::ItemLabel, Item, StoredItem};
+
+#[cfg(feature = "client")]
+use {
+    crate::{
+        camera::MainCamera,
+        interaction::{InteractionListRequest, InteractionSystem},
+        ui::has_window,
+        GameState,
+    },
+    bevy::window::PrimaryWindow,
+    bevy_egui::{egui, EguiContexts},
+    bevy_rapier3d::prelude::RapierContext,
+};
+
+/// Lists everything lying on a tile when it is alt-clicked, so items in a pile can be picked out.
+pub(super) struct TileContentsPlugin;
+
+impl Plugin for TileContentsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_network_message::<TileContentsRequest>("TileContentsRequest")
+            .add_network_message::<TileContentsClose>("TileContentsClose")
+            .add_network_message::<TileContentsMessage>("TileContentsMessage")
+            .add_network_message::<TileContentsClosed>("TileContentsClosed");
+
+        if is_server(app) {
+            app.init_resource::<TileContentsViewers>().add_systems(
+                Update,
+                (
+                    handle_tile_contents_requests,
+                    update_tile_contents.run_if(on_timer(Duration::from_secs_f32(UPDATE_INTERVAL))),
+                )
+                    .chain(),
+            );
+        } else {
+            #[cfg(feature = "client")]
+            app.init_resource::<ClientTileContents>().add_systems(
+                Update,
+                (
+                    request_tile_contents.in_set(InteractionSystem::Input),
+                    (receive_tile_contents, tile_contents_ui.run_if(has_window)).chain(),
+                )
+                    .run_if(in_state(GameState::Game)),
+            );
+        }
+    }
+}
+
+/// How far from the tile center a player can be while looking at its contents
+const TILE_CONTENTS_RANGE: f32 = 2.5;
+/// Entries sent at once, larger piles are split into pages
+const PAGE_SIZE: usize = 50;
+/// Seconds between checking open lists for changes
+const UPDATE_INTERVAL: f32 = 0.25;
+
+/// Client message to start watching a tile, or to switch to another page of it.
+#[derive(Serialize, Deserialize)]
+struct TileContentsRequest {
+    tile: UVec2,
+    page: u32,
+}
+
+/// Client message that the contents window was closed.
+#[derive(Serialize, Deserialize)]
+struct TileContentsClose;
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+struct TileContentsEntry {
+    identity: NetworkIdentity,
+    name: String,
+}
+
+/// Server message with changes to the watched page since the last message.
+/// The first message for a tile or page contains all of its entries as added.
+#[derive(Serialize, Deserialize)]
+struct TileContentsMessage {
+    tile: UVec2,
+    page: u32,
+    pages: u32,
+    added: Vec<TileContentsEntry>,
+    removed: Vec<NetworkIdentity>,
+}
+
+/// Server message that the player can't see the tile anymore.
+#[derive(Serialize, Deserialize)]
+struct TileContentsClosed;
+
+struct TileContentsViewer {
+    tile: UVec2,
+    page: u32,
+    pages: u32,
+    /// The entries the client has, so only changes are sent
+    sent: Vec<TileContentsEntry>,
+}
+
+/// Players watching the contents of a tile.
+#[derive(Resource, Default)]
+struct TileContentsViewers {
+    viewers: HashMap<ConnectionId, TileContentsViewer>,
+}
+
+/// Finds the objects on a tile that are worth listing: loose items and creatures.
+#[derive(SystemParam)]
+struct TileObjects<'w, 's> {
+    items: Query<
+        'w,
+        's,
+        (
+            &'static NetworkIdentity,
+            &'static GlobalTransform,
+            &'static Item,
+            Option<&'static ItemLabel>,
+        ),
+        Without<StoredItem>,
+    >,
+    creatures: Query<
+        'w,
+        's,
+        (
+            &'static NetworkIdentity,
+            &'static GlobalTransform,
+            &'static SpeechName,
+        ),
+    >,
+}
+
+impl<'w, 's> TileObjects<'w, 's> {
+    fn on_tile(&self, tile: UVec2) -> Vec<TileContentsEntry> {
+        let items = self
+            .items
+            .iter()
+            .filter(|(_, transform, ..)| maps::world_to_tile(transform.translation()) == Some(tile))
+            .map(|(&identity, _, item, label)| TileContentsEntry {
+                identity,
+                name: match label {
+                    Some(label) => format!("{} ({})", item.name, label.text()),
+                    None => item.name.clone(),
+                },
+            });
+        let creatures = self
+            .creatures
+            .iter()
+            .filter(|(_, transform, _)| maps::world_to_tile(transform.translation()) == Some(tile))
+            .map(|(&identity, _, name)| TileContentsEntry {
+                identity,
+                name: name.0.clone(),
+            });
+
+        let mut entries: Vec<_> = creatures.chain(items).collect();
+        // Stable order, so pages don't shuffle while the pile changes
+        entries.sort_by(|a, b| a.name.cmp(&b.name).then(a.identity.cmp(&b.identity)));
+        entries
+    }
+}
+
+/// Finds the position of the creature a connection controls.
+fn viewer_position(
+    connection: ConnectionId,
+    players: &Players,
+    controls: &ClientControls,
+    transforms: &Query<&GlobalTransform>,
+) -> Option<Vec3> {
+    let player = players.get(connection)?.id;
+    let creature = controls.controlled_entity(player)?;
+    transforms.get(creature).ok().map(|t| t.translation())
+}
+
+fn in_range(position: Vec3, tile: UVec2) -> bool {
+    position.xz().distance(tile.as_vec2()) <= TILE_CONTENTS_RANGE
+}
+
+fn handle_tile_contents_requests(
+    mut requests: EventReader<MessageEvent<TileContentsRequest>>,
+    mut closes: EventReader<MessageEvent<TileContentsClose>>,
+    mut viewers: ResMut<TileContentsViewers>,
+    players: Res<Players>,
+    controls: Res<ClientControls>,
+    transforms: Query<&GlobalTransform>,
+) {
+    for event in closes.iter() {
+        viewers.viewers.remove(&event.connection);
+    }
+
+    for event in requests.iter() {
+        let (tile, page) = (event.message.tile, event.message.page);
+        let Some(position) = viewer_position(event.connection, &players, &controls, &transforms)
+        else {
+            continue;
+        };
+        if !in_range(position, tile) {
+            debug!(connection = ?event.connection, ?tile, "Tile contents requested out of range");
+            continue;
+        }
+
+        // Anything sent for another tile or page is replaced on the client
+        viewers.viewers.insert(
+            event.connection,
+            TileContentsViewer {
+                tile,
+                page,
+                pages: 0,
+                sent: Vec::new(),
+            },
+        );
+    }
+}
+
+/// Sends changes of watched tiles and closes lists of players that walked away.
+fn update_tile_contents(
+    mut viewers: ResMut<TileContentsViewers>,
+    players: Res<Players>,
+    controls: Res<ClientControls>,
+    transforms: Query<&GlobalTransform>,
+    objects: TileObjects,
+    mut sender: MessageSender,
+) {
+    viewers.viewers.retain(|&connection, viewer| {
+        let Some(position) = viewer_position(connection, &players, &controls, &transforms) else {
+            // Disconnected or not in the round anymore
+            return false;
+        };
+        if !in_range(position, viewer.tile) {
+            sender.send(&TileContentsClosed, MessageReceivers::Single(connection));
+            return false;
+        }
+
+        let entries = objects.on_tile(viewer.tile);
+        let pages = ((entries.len() + PAGE_SIZE - 1) / PAGE_SIZE).max(1) as u32;
+        let page = viewer.page.min(pages - 1);
+        let current: Vec<_> = entries
+            .into_iter()
+            .skip(page as usize * PAGE_SIZE)
+            .take(PAGE_SIZE)
+            .collect();
+
+        // A clamped page is a different page, the client starts over
+        if page != viewer.page {
+            viewer.page = page;
+            viewer.sent.clear();
+        }
+        let added: Vec<_> = current
+            .iter()
+            .filter(|e| !viewer.sent.contains(e))
+            .cloned()
+            .collect();
+        let removed: Vec<_> = viewer
+            .sent
+            .iter()
+            .filter(|e| !current.contains(e))
+            .map(|e| e.identity)
+            .collect();
+        // Empty tiles still get a message the first time, so the window opens
+        let first = viewer.pages == 0;
+        if added.is_empty() && removed.is_empty() && pages == viewer.pages && !first {
+            return true;
+        }
+
+        sender.send(
+            &TileContentsMessage {
+                tile: viewer.tile,
+                page,
+                pages,
+                added,
+                removed,
+            },
+            MessageReceivers::Single(connection),
+        );
+        viewer.pages = pages;
+        viewer.sent = current;
+        true
+    });
+}
+
+/// The tile contents window, if it's open.
+#[cfg(feature = "client")]
+#[derive(Resource, Default)]
+struct ClientTileContents {
+    open: Option<(UVec2, u32)>,
+    pages: u32,
+    entries: Vec<TileContentsEntry>,
+}
+
+#[cfg(feature = "client")]
+#[allow(clippy::too_many_arguments)]
+fn request_tile_contents(
+    buttons: Res<Input<MouseButton>>,
+    keys: Res<Input<KeyCode>>,
+    mut contexts: EguiContexts,
+    rapier_context: Res<RapierContext>,
+    windows: Query<(Entity, &Window), With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    mut sender: MessageSender,
+) {
+    if !buttons.just_pressed(MouseButton::Left)
+        || !keys.any_pressed([KeyCode::AltLeft, KeyCode::AltRight])
+    {
+        return;
+    }
+
+    let Ok((window_entity, window)) = windows.get_single() else {
+        return;
+    };
+    if contexts
+        .try_ctx_for_window_mut(window_entity)
+        .is_some_and(|c| c.is_pointer_over_area())
+    {
+        return;
+    }
+
+    let Some(ray) = window
+        .cursor_position()
+        .zip(cameras.iter().next())
+        .and_then(|(cursor, (camera, transform))| camera.viewport_to_world(transform, cursor))
+    else {
+        return;
+    };
+    let Some((_, toi)) =
+        rapier_context.cast_ray(ray.origin, ray.direction, 100.0, true, Default::default())
+    else {
+        return;
+    };
+    let Some(tile) = maps::world_to_tile(ray.origin + ray.direction * toi) else {
+        return;
+    };
+
+    sender.send_to_server(&TileContentsRequest { tile, page: 0 });
+}
+
+#[cfg(feature = "client")]
+fn receive_tile_contents(
+    mut messages: EventReader<MessageEvent<TileContentsMessage>>,
+    mut closed: EventReader<MessageEvent<TileContentsClosed>>,
+    mut contents: ResMut<ClientTileContents>,
+) {
+    for event in messages.iter() {
+        let message = &event.message;
+        if contents.open != Some((message.tile, message.page)) {
+            contents.open = Some((message.tile, message.page));
+            contents.entries.clear();
+        }
+        contents.pages = message.pages;
+        contents
+            .entries
+            .retain(|e| !message.removed.contains(&e.identity));
+        contents.entries.extend(message.added.iter().cloned());
+        contents
+            .entries
+            .sort_by(|a, b| a.name.cmp(&b.name).then(a.identity.cmp(&b.identity)));
+    }
+
+    if closed.iter().count() > 0 {
+        *contents = Default::default();
+    }
+}
+
+#[cfg(feature = "client")]
+fn tile_contents_ui(
+    mut contexts: EguiContexts,
+    mut contents: ResMut<ClientTileContents>,
+    mut sender: MessageSender,
+) {
+    let Some((tile, page)) = contents.open else {
+        return;
+    };
+
+    let mut open = true;
+    egui::Window::new("Tile contents")
+        .open(&mut open)
+        .resizable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            if contents.entries.is_empty() {
+                ui.label("Nothing here");
+            }
+            egui::ScrollArea::vertical()
+                .max_height(300.0)
+                .show(ui, |ui| {
+                    egui::Grid::new("tile_contents").show(ui, |ui| {
+                        for entry in contents.entries.iter() {
+                            ui.label(&entry.name);
+                            // Shows the same options as right clicking it, like picking it up
+                            if ui.button("Interact").clicked() {
+                                sender.send_to_server(&InteractionListRequest {
+                                    target: entry.identity,
+                                    point: None,
+                                });
+                            }
+                            ui.end_row();
+                        }
+                    });
+                });
+
+            if contents.pages > 1 {
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(page > 0, egui::Button::new("Previous"))
+                        .clicked()
+                    {
+                        sender.send_to_server(&TileContentsRequest {
+                            tile,
+                            page: page - 1,
+                        });
+                    }
+                    ui.label(format!("Page {} of {}", page + 1, contents.pages));
+                    if ui
+                        .add_enabled(page + 1 < contents.pages, egui::Button::new("Next"))
+                        .clicked()
+                    {
+                        sender.send_to_server(&TileContentsRequest {
+                            tile,
+                            page: page + 1,
+                        });
+                    }
+                });
+            }
+        });
+
+    if !open {
+        *contents = Default::default();
+        sender.send_to_server(&TileContentsClose);
+    }
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use bevy::{
+        ecs::system::{Command, SystemState},
+        time::TimeUpdateStrategy,
+    };
+    use networking::{identity::NetworkCommand, loopback::LinkConditions, testing, NetworkRole};
+
+    use super::*;
+    use crate::{config::ServerConfig, testing::server_app};
+
+    const FRAME: Duration = Duration::from_millis(100);
+    const TILE: UVec2 = UVec2::new(3, 2);
+
+    #[derive(Resource, Default)]
+    struct Received {
+        messages: Vec<TileContentsMessage>,
+        closed: usize,
+    }
+
+    fn record_messages(
+        mut messages: ResMut<Events<MessageEvent<TileContentsMessage>>>,
+        mut closed: EventReader<MessageEvent<TileContentsClosed>>,
+        mut received: ResMut<Received>,
+    ) {
+        received
+            .messages
+            .extend(messages.drain().map(|event| event.message));
+        received.closed += closed.iter().count();
+    }
+
+    struct Scene {
+        server: App,
+        client: App,
+        viewer: Entity,
+    }
+
+    impl Scene {
+        /// A player standing next to [`TILE`].
+        fn new() -> Self {
+            let mut server = server_app(ServerConfig::default());
+            let mut client = testing::app(NetworkRole::Client);
+            client
+                .add_network_message::<TileContentsRequest>("TileContentsRequest")
+                .add_network_message::<TileContentsClose>("TileContentsClose")
+                .add_network_message::<TileContentsMessage>("TileContentsMessage")
+                .add_network_message::<TileContentsClosed>("TileContentsClosed")
+                .init_resource::<Received>()
+                .add_systems(Update, record_messages);
+            let connector = testing::listen(&mut server);
+            testing::join(&mut client, &connector, LinkConditions::default());
+            testing::connect(&mut server, &mut [&mut client], 200);
+            server.insert_resource(TimeUpdateStrategy::ManualDuration(FRAME));
+
+            let player = server
+                .world
+                .resource::<Players>()
+                .players()
+                .values()
+                .next()
+                .unwrap()
+                .id;
+            let viewer = server
+                .world
+                .spawn(TransformBundle::from_transform(Transform::from_xyz(
+                    2.0, 0.0, 2.0,
+                )))
+                .id();
+            server
+                .world
+                .resource_mut::<ClientControls>()
+                .give_control(player, viewer);
+
+            let mut scene = Self {
+                server,
+                client,
+                viewer,
+            };
+            scene.update(1);
+            scene
+        }
+
+        fn update(&mut self, frames: u32) {
+            testing::update(&mut self.server, &mut [&mut self.client], frames);
+        }
+
+        fn send<T: 'static + Serialize + Send + Sync>(&mut self, message: &T) {
+            let mut state = SystemState::<MessageSender>::new(&mut self.client.world);
+            state
+                .get_mut(&mut self.client.world)
+                .send_to_server(message);
+        }
+
+        fn spawn_item(&mut self, name: &str, tile: UVec2) -> (Entity, NetworkIdentity) {
+            let item = self
+                .server
+                .world
+                .spawn((
+                    Item {
+                        name: name.into(),
+                        ..Default::default()
+                    },
+                    TransformBundle::from_transform(Transform::from_xyz(
+                        tile.x as f32,
+                        0.0,
+                        tile.y as f32,
+                    )),
+                ))
+                .id();
+            NetworkCommand { entity: item }.apply(&mut self.server.world);
+            let identity = *self.server.world.get::<NetworkIdentity>(item).unwrap();
+            (item, identity)
+        }
+
+        /// Messages received since the last call.
+        fn received(&mut self) -> Vec<TileContentsMessage> {
+            std::mem::take(&mut self.client.world.resource_mut::<Received>().messages)
+        }
+
+        fn closed(&self) -> usize {
+            self.client.world.resource::<Received>().closed
+        }
+
+        fn watching(&self) -> bool {
+            !self
+                .server
+                .world
+                .resource::<TileContentsViewers>()
+                .viewers
+                .is_empty()
+        }
+    }
+
+    fn names(entries: &[TileContentsEntry]) -> Vec<&str> {
+        entries.iter().map(|e| e.name.as_str()).collect()
+    }
+
+    #[test]
+    fn subscription_sends_changes_until_closed() {
+        let mut scene = Scene::new();
+        let (crowbar, crowbar_identity) = scene.spawn_item("Crowbar", TILE);
+        scene.spawn_item("Elsewhere", TILE + UVec2::X);
+
+        scene.send(&TileContentsRequest {
+            tile: TILE,
+            page: 0,
+        });
+        scene.update(5);
+        let received = scene.received();
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].tile, TILE);
+        assert_eq!(received[0].pages, 1);
+        assert_eq!(names(&received[0].added), ["Crowbar"]);
+        assert!(received[0].removed.is_empty());
+
+        // Nothing changed, nothing is sent
+        scene.update(5);
+        assert!(scene.received().is_empty());
+
+        // Only the differences are sent
+        scene.spawn_item("Wrench", TILE);
+        scene.update(5);
+        let received = scene.received();
+        assert_eq!(received.len(), 1);
+        assert_eq!(names(&received[0].added), ["Wrench"]);
+        assert!(received[0].removed.is_empty());
+
+        scene.server.world.despawn(crowbar);
+        scene.update(5);
+        let received = scene.received();
+        assert_eq!(received.len(), 1);
+        assert!(received[0].added.is_empty());
+        assert_eq!(received[0].removed, [crowbar_identity]);
+
+        scene.send(&TileContentsClose);
+        scene.update(5);
+        assert!(!scene.watching());
+        scene.spawn_item("Screwdriver", TILE);
+        scene.update(5);
+        assert!(scene.received().is_empty());
+        assert_eq!(scene.closed(), 0);
+    }
+
+    #[test]
+    fn walking_away_closes_the_list() {
+        let mut scene = Scene::new();
+        scene.spawn_item("Crowbar", TILE);
+        scene.send(&TileContentsRequest {
+            tile: TILE,
+            page: 0,
+        });
+        scene.update(5);
+        assert_eq!(scene.received().len(), 1);
+
+        scene
+            .server
+            .world
+            .get_mut::<Transform>(scene.viewer)
+            .unwrap()
+            .translation = Vec3::new(10.0, 0.0, 10.0);
+        scene.update(5);
+        assert_eq!(scene.closed(), 1);
+        assert!(!scene.watching());
+
+        // Changes aren't sent to players who walked away
+        scene.spawn_item("Wrench", TILE);
+        scene.update(5);
+        assert!(scene.received().is_empty());
+        assert_eq!(scene.closed(), 1);
+    }
+
+    #[test]
+    fn out_of_range_tiles_cant_be_watched() {
+        let mut scene = Scene::new();
+        scene.spawn_item("Crowbar", UVec2::new(9, 9));
+
+        scene.send(&TileContentsRequest {
+            tile: UVec2::new(9, 9),
+            page: 0,
+        });
+        scene.update(5);
+
+        assert!(!scene.watching());
+        assert!(scene.received().is_empty());
+    }
+
+    #[test]
+    fn large_piles_are_paged() {
+        let mut scene = Scene::new();
+        for i in 0..PAGE_SIZE + 10 {
+            scene.spawn_item(&format!("Item {:03}", i), TILE);
+        }
+
+        scene.send(&TileContentsRequest {
+            tile: TILE,
+            page: 1,
+        });
+        scene.update(5);
+        let received = scene.received();
+        assert_eq!(received.len(), 1);
+        assert_eq!((received[0].page, received[0].pages), (1, 2));
+        assert_eq!(received[0].added.len(), 10);
+        assert_eq!(received[0].added[0].name, format!("Item {:03}", PAGE_SIZE));
+    }
+}
@@ -1,29 +1,63 @@
 use bevy::{
-    asset::AssetPathId,
+    asset::{AssetPathId, HandleId},
+    ecs::system::SystemParam,
     prelude::*,
     reflect::{TypePath, TypeUuid},
-    utils::HashMap,
+    utils::{HashMap, HashSet, Uuid},
 };
 use bevy_common_assets::ron::RonAssetPlugin;
-use maps::TileMap;
+use maps::{TileMap, ARRIVALS_LANDMARK};
 use networking::{
     is_server,
-    messaging::{AppExt, MessageEvent},
+    messaging::{AppExt, MessageEvent, MessageReceivers, MessageSender},
     spawning::ClientControls,
     ConnectionId, Players,
 };
 use serde::{Deserialize, Serialize};
 
+use crate::{
+    admin::role_bans::RoleBans,
+    body::{
+        health::{BrainState, BrainStateEvent},
+        Body,
+    },
+    config::ServerConfig,
+    console::{CommandContext, CommandResult, ConsoleAppExt, ConsoleCommand, PermissionLevel},
+    round::modes::TRAITOR_ROLE,
+    stats::PlayerStats,
+    status_hud::HudKind,
+};
+
 pub struct JobPlugin;
 
 impl Plugin for JobPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins(RonAssetPlugin::<JobDefinition>::new(&["job.ron"]))
-            .add_network_message::<SelectJobMessage>()
+            .add_network_message::<SelectJobMessage>("SelectJobMessage")
+            .add_network_message::<JobSlotsRequest>("JobSlotsRequest")
+            .add_network_message::<JobSlotsMessage>("JobSlotsMessage")
             .add_systems(Startup, load_assets);
         if is_server(app) {
             app.init_resource::<SelectedJobs>()
-                .add_systems(Update, handle_job_selection);
+                .init_resource::<JobSlots>()
+                .add_console_command(ConsoleCommand {
+                    name: "reloadjobs",
+                    description: "Reloads the job definitions, including new job files",
+                    parameters: &[],
+                    permission: PermissionLevel::Admin,
+                    handler: reload_command,
+                })
+                .add_systems(
+                    Update,
+                    (
+                        handle_job_selection,
+                        send_job_slots,
+                        release_slots_on_death.run_if(on_event::<BrainStateEvent>()),
+                    ),
+                );
+        } else {
+            app.init_resource::<ClientJobSlots>()
+                .add_systems(Update, receive_job_slots);
         }
     }
 }
@@ -35,6 +69,106 @@ pub struct JobDefinition {
     pub name: String,
     pub description: String,
     pub clothing: Vec<String>,
+    /// Creatures on the same team are affected by the friendly fire policy
+    #[serde(default = "JobDefinition::default_team")]
+    pub team: String,
+    /// Department that hears announcements meant for it, the job id if not set
+    #[serde(default)]
+    department: Option<String>,
+    /// How many players can have this job in a round, unlimited if not set
+    #[serde(default)]
+    pub max_slots: Option<u32>,
+    /// HUDs the job sees without wearing them
+    #[serde(default)]
+    pub huds: Vec<HudKind>,
+    /// Starting clothing the player picks from a few alternatives, equipped after `clothing`
+    #[serde(default)]
+    pub loadout: Vec<LoadoutSlot>,
+}
+
+impl JobDefinition {
+    fn default_team() -> String {
+        "crew".into()
+    }
+
+    pub fn department(&self) -> &str {
+        self.department.as_deref().unwrap_or(&self.id)
+    }
+
+    /// The item picked for every loadout slot, from the item ids the player chose.
+    /// Slots without a valid choice, or whose choice `lock` rejects, use their default.
+    pub fn loadout_items<'a>(
+        &'a self,
+        choices: &[String],
+        mut lock: impl FnMut(&LoadoutOption) -> Option<String>,
+    ) -> Vec<&'a str> {
+        self.loadout
+            .iter()
+            .filter_map(|slot| {
+                let default = slot.default_option()?;
+                let Some(chosen) = slot.chosen(choices) else {
+                    return Some(default.item.as_str());
+                };
+                if let Some(reason) = lock(chosen) {
+                    warn!(
+                        job = self.id.as_str(),
+                        slot = slot.id.as_str(),
+                        item = chosen.item.as_str(),
+                        reason = reason.as_str(),
+                        "Loadout choice is locked, using the default"
+                    );
+                    return Some(default.item.as_str());
+                }
+                Some(chosen.item.as_str())
+            })
+            .collect()
+    }
+
+    /// If the item is an option of any loadout slot of the job.
+    pub fn offers(&self, item: &str) -> bool {
+        self.loadout
+            .iter()
+            .any(|slot| slot.options.iter().any(|o| o.item == item))
+    }
+}
+
+/// A piece of starting clothing with a few alternatives, like a backpack or a satchel.
+#[derive(Deserialize, Clone)]
+pub struct LoadoutSlot {
+    pub id: String,
+    pub name: String,
+    /// The first option is the default and should not have a playtime requirement
+    pub options: Vec<LoadoutOption>,
+}
+
+impl LoadoutSlot {
+    pub fn default_option(&self) -> Option<&LoadoutOption> {
+        self.options.first()
+    }
+
+    /// The option the player chose for this slot, if any of the chosen items is in its pool.
+    pub fn chosen(&self, choices: &[String]) -> Option<&LoadoutOption> {
+        self.options.iter().find(|o| choices.contains(&o.item))
+    }
+}
+
+#[derive(Deserialize, Clone)]
+pub struct LoadoutOption {
+    /// Name of the item scene, equipped like the job's clothing
+    pub item: String,
+    pub name: String,
+    /// Time the player has to spend as a job before they can pick this option
+    #[serde(default)]
+    pub playtime: Option<PlaytimeRequirement>,
+}
+
+/// Which team a creature belongs to, set from its job when spawning.
+#[derive(Component)]
+pub struct Affiliation {
+    pub team: String,
+    /// Id of the job the creature spawned as
+    pub job: String,
+    pub department: String,
 }
 
 #[derive(Resource)]
@@ -56,6 +190,25 @@ fn load_assets(mut commands: Commands, server: ResMut<AssetServer>) {
     commands.insert_resource(assets);
 }
 
+fn reload_command(world: &mut World, _: &CommandContext) -> CommandResult {
+    let server = world.resource::<AssetServer>().clone();
+    let handles = server
+        .load_folder("jobs")
+        .map_err(|err| format!("Error loading assets/jobs: {}", err))?;
+    // Loading the folder only picks up new files, existing ones have to be read again
+    for handle in handles.iter() {
+        if let Some(path) = server.get_handle_path(handle) {
+            server.reload_asset(path);
+        }
+    }
+    let count = handles.len();
+    world.insert_resource(JobAssets {
+        definitions: handles.into_iter().map(HandleUntyped::typed).collect(),
+    });
+    info!(jobs = count, "Reloading job definitions");
+    Ok(format!("Reloading {} job definitions", count))
+}
+
 #[derive(Default, Resource)]
 pub struct SelectedJobs {
     selected: HashMap<ConnectionId, AssetPathId>,
@@ -81,6 +234,352 @@ impl SelectedJobs {
             .get(&connection)
             .and_then(|id| assets.get(&assets.get_handle(*id)))
     }
+
+    pub fn select(&mut self, connection: ConnectionId, job: AssetPathId) {
+        self.selected.insert(connection, job);
+    }
+}
+
+#[derive(Deserialize)]
+pub struct JobConfig {
+    /// If a job slot opens up again when the player holding it dies
+    #[serde(default)]
+    pub free_slot_on_death: bool,
+    /// Id of the job players get when their selected job is full.
+    /// Without one, players can only join a full job as an observer.
+    #[serde(default = "JobConfig::default_overflow_job")]
+    pub overflow_job: Option<String>,
+    /// Jobs players can only take after playing another job for a while, by job id
+    #[serde(default)]
+    pub playtime_requirements: HashMap<String, PlaytimeRequirement>,
+}
+
+/// Time a player has to spend as one job before they can pick another.
+#[derive(Deserialize, Clone)]
+pub struct PlaytimeRequirement {
+    /// Id of the job the time has to be spent as
+    pub job: String,
+    pub hours: f32,
+}
+
+impl JobConfig {
+    fn default_overflow_job() -> Option<String> {
+        Some("assistant".into())
+    }
+}
+
+impl Default for JobConfig {
+    fn default() -> Self {
+        Self {
+            free_slot_on_death: false,
+            overflow_job: Self::default_overflow_job(),
+            playtime_requirements: HashMap::default(),
+        }
+    }
+}
+
+/// Which players hold a slot of each job this round.
+/// Roundstart and latejoin spawns both take their slots from here.
+#[derive(Resource, Default)]
+pub struct JobSlots {
+    taken: HashMap<String, HashSet<Uuid>>,
+    /// Job and player of spawned bodies, to free the slot when they die
+    bodies: HashMap<Entity, (String, Uuid)>,
+}
+
+impl JobSlots {
+    /// How many slots of the job are still free, `None` if it is unlimited.
+    pub fn open(&self, job: &JobDefinition) -> Option<u32> {
+        let taken = self.taken.get(&job.id).map_or(0, |t| t.len()) as u32;
+        job.max_slots.map(|max| max.saturating_sub(taken))
+    }
+
+    /// Takes a slot of the job for the player. Returns false if the job is full.
+    /// A player that already holds a slot of the job keeps it.
+    pub fn claim(&mut self, job: &JobDefinition, player: Uuid) -> bool {
+        let taken = self.taken.entry_ref(&job.id).or_default();
+        if taken.contains(&player) {
+            return true;
+        }
+        if job.max_slots.is_some_and(|max| taken.len() as u32 >= max) {
+            return false;
+        }
+        taken.insert(player);
+        true
+    }
+
+    /// Remembers which slot a spawned body holds.
+    pub fn track_body(&mut self, body: Entity, job: &JobDefinition, player: Uuid) {
+        self.bodies.insert(body, (job.id.clone(), player));
+    }
+
+    fn release(&mut self, body: Entity) {
+        let Some((job, player)) = self.bodies.remove(&body) else {
+            return;
+        };
+        if let Some(taken) = self.taken.get_mut(&job) {
+            taken.remove(&player);
+        }
+    }
+}
+
+/// Gives players the job they selected, if they may take it and it has a free slot.
+/// Roundstart and latejoin spawns both assign jobs with this.
+#[derive(SystemParam)]
+pub struct JobAssigner<'w> {
+    selected: ResMut<'w, SelectedJobs>,
+    jobs: Res<'w, Assets<JobDefinition>>,
+    slots: ResMut<'w, JobSlots>,
+    config: Res<'w, ServerConfig>,
+    stats: Res<'w, PlayerStats>,
+    role_bans: Res<'w, RoleBans>,
+}
+
+impl<'w> JobAssigner<'w> {
+    /// Connections of players that selected a job.
+    pub fn connections(&self) -> Vec<ConnectionId> {
+        self.selected
+            .selected(&self.jobs)
+            .map(|(connection, _)| connection)
+            .collect()
+    }
+
+    /// The job the player selected.
+    pub fn selected(&self, connection: ConnectionId) -> Option<&JobDefinition> {
+        self.selected.get(connection, &self.jobs)
+    }
+
+    /// Claims a slot in the player's selected job, moving them to the overflow job if it is full.
+    /// Returns the job the player spawns as, or why they can't spawn.
+    pub fn assign(
+        &mut self,
+        connection: ConnectionId,
+        player: Uuid,
+    ) -> Result<&JobDefinition, JobRejection> {
+        let assets: &Assets<JobDefinition> = &self.jobs;
+        let config = &self.config.jobs;
+        let job = self
+            .selected
+            .selected
+            .get(&connection)
+            .and_then(|&asset_id| assets.get(&assets.get_handle(asset_id)))
+            .ok_or(JobRejection::NoJob)?;
+        if let Some(ban) = self.role_bans.job_ban(player, &job.id) {
+            return Err(JobRejection::Banned(job.name.clone(), ban.reason.clone()));
+        }
+        if let Some(reason) = self.stats.job_lock(player, job, config, assets) {
+            return Err(JobRejection::Locked(reason));
+        }
+        if self.slots.claim(job, player) {
+            return Ok(job);
+        }
+
+        let full = || JobRejection::Full(job.name.clone());
+        let (handle, overflow) = config
+            .overflow_job
+            .as_deref()
+            .and_then(|overflow_id| assets.iter().find(|(_, j)| j.id == overflow_id))
+            .ok_or_else(full)?;
+        let HandleId::AssetPathId(overflow_asset) = handle else {
+            return Err(full());
+        };
+        if self
+            .stats
+            .job_lock(player, overflow, config, assets)
+            .is_some()
+            || self.role_bans.job_ban(player, &overflow.id).is_some()
+            || !self.slots.claim(overflow, player)
+        {
+            return Err(full());
+        }
+        info!(
+            player = ?player,
+            full = job.id.as_str(),
+            overflow = overflow.id.as_str(),
+            "Job is full, using overflow job"
+        );
+        self.selected.select(connection, overflow_asset);
+        Ok(overflow)
+    }
+}
+
+/// Why a player couldn't get a job.
+#[derive(Debug)]
+pub enum JobRejection {
+    /// The player hasn't picked a job
+    NoJob,
+    /// The job and the overflow job have no open slots
+    Full(String),
+    /// The player hasn't played enough to take the job
+    Locked(String),
+    /// An admin banned the player from the job, with the reason
+    Banned(String, String),
+}
+
+impl std::fmt::Display for JobRejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JobRejection::NoJob => write!(f, "Pick a job first."),
+            JobRejection::Full(job) => write!(
+                f,
+                "There are no open {} slots. Pick another job or join as an observer.",
+                job
+            ),
+            JobRejection::Locked(reason) => write!(f, "{}", reason),
+            JobRejection::Banned(job, reason) => {
+                write!(f, "You are banned from playing {}: {}", job, reason)
+            }
+        }
+    }
+}
+
+fn release_slots_on_death(
+    mut brain_events: EventReader<BrainStateEvent>,
+    mut slots: ResMut<JobSlots>,
+    parents: Query<&Parent>,
+    bodies: Query<(), With<Body>>,
+    config: Res<ServerConfig>,
+) {
+    for event in brain_events.iter() {
+        if event.new_state != BrainState::Dead || !config.jobs.free_slot_on_death {
+            continue;
+        }
+
+        let Some(body) = parents
+            .iter_ancestors(event.brain)
+            .find(|e| bodies.contains(*e))
+        else {
+            continue;
+        };
+        slots.release(body);
+    }
+}
+
+/// Client message asking for the open job slots, sent while in the lobby.
+#[derive(Serialize, Deserialize)]
+pub struct JobSlotsRequest;
+
+/// Open slots of limited jobs by job id. Jobs that aren't listed are unlimited.
+#[derive(Serialize, Deserialize)]
+struct JobSlotsMessage {
+    open: Vec<(String, u32)>,
+    /// Jobs the receiving player can't take yet, with the reason
+    locked: Vec<(String, String)>,
+    /// Loadout options the receiving player can't pick yet, as job id, item and reason
+    locked_options: Vec<(String, String, String)>,
+    /// Jobs the receiving player is banned from, with the ban
+    banned: Vec<(String, String)>,
+    /// Why the receiving player won't be picked as an antagonist, if they are banned
+    antagonist_ban: Option<String>,
+}
+
+#[allow(clippy::too_many_arguments)]
+fn send_job_slots(
+    mut requests: EventReader<MessageEvent<JobSlotsRequest>>,
+    slots: Res<JobSlots>,
+    jobs: Res<Assets<JobDefinition>>,
+    players: Res<Players>,
+    stats: Res<PlayerStats>,
+    config: Res<ServerConfig>,
+    role_bans: Res<RoleBans>,
+    mut sender: MessageSender,
+) {
+    for request in requests.iter() {
+        let open = jobs
+            .iter()
+            .filter_map(|(_, job)| slots.open(job).map(|open| (job.id.clone(), open)))
+            .collect();
+        let mut locked = Vec::new();
+        let mut locked_options = Vec::new();
+        // Only the player's own bans are sent, other players' bans stay on the server
+        let mut banned = Vec::new();
+        let mut antagonist_ban = None;
+        if let Some(player) = players.get(request.connection) {
+            antagonist_ban = role_bans
+                .antagonist_ban(player.id, TRAITOR_ROLE)
+                .map(|ban| ban.describe());
+            for (_, job) in jobs.iter() {
+                if let Some(ban) = role_bans.job_ban(player.id, &job.id) {
+                    banned.push((job.id.clone(), ban.describe()));
+                }
+                if let Some(reason) = stats.job_lock(player.id, job, &config.jobs, &jobs) {
+                    locked.push((job.id.clone(), reason));
+                }
+                for option in job.loadout.iter().flat_map(|slot| slot.options.iter()) {
+                    if let Some(reason) = stats.loadout_lock(player.id, option, &jobs) {
+                        locked_options.push((job.id.clone(), option.item.clone(), reason));
+                    }
+                }
+            }
+        }
+        sender.send(
+            &JobSlotsMessage {
+                open,
+                locked,
+                locked_options,
+                banned,
+                antagonist_ban,
+            },
+            MessageReceivers::Single(request.connection),
+        );
+    }
+}
+
+/// Open slots of limited jobs, as last received from the server.
+#[derive(Resource, Default)]
+pub struct ClientJobSlots {
+    open: HashMap<String, u32>,
+    locked: HashMap<String, String>,
+    locked_options: HashMap<(String, String), String>,
+    banned: HashMap<String, String>,
+    antagonist_ban: Option<String>,
+}
+
+impl ClientJobSlots {
+    /// How many slots of the job are still free, `None` if it is unlimited.
+    pub fn open(&self, job: &JobDefinition) -> Option<u32> {
+        self.open.get(&job.id).copied()
+    }
+
+    /// Why the local player can't take the job yet, `None` if they can.
+    pub fn locked(&self, job: &JobDefinition) -> Option<&str> {
+        self.locked.get(&job.id).map(|reason| reason.as_str())
+    }
+
+    /// The local player's ban from the job, `None` if they aren't banned.
+    pub fn banned(&self, job: &JobDefinition) -> Option<&str> {
+        self.banned.get(&job.id).map(|ban| ban.as_str())
+    }
+
+    /// The local player's ban from antagonist roles, `None` if they aren't banned.
+    pub fn antagonist_ban(&self) -> Option<&str> {
+        self.antagonist_ban.as_deref()
+    }
+
+    /// Why the local player can't pick the loadout option yet, `None` if they can.
+    pub fn option_locked(&self, job: &JobDefinition, option: &LoadoutOption) -> Option<&str> {
+        self.locked_options
+            .get(&(job.id.clone(), option.item.clone()))
+            .map(|reason| reason.as_str())
+    }
+}
+
+fn receive_job_slots(
+    mut messages: EventReader<MessageEvent<JobSlotsMessage>>,
+    mut slots: ResMut<ClientJobSlots>,
+) {
+    for event in messages.iter() {
+        slots.open = event.message.open.iter().cloned().collect();
+        slots.locked = event.message.locked.iter().cloned().collect();
+        slots.locked_options = event
+            .message
+            .locked_options
+            .iter()
+            .map(|(job, item, reason)| ((job.clone(), item.clone()), reason.clone()))
+            .collect();
+        slots.banned = event.message.banned.iter().cloned().collect();
+        slots.antagonist_ban = event.message.antagonist_ban.clone();
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -122,3 +621,290 @@ pub fn get_spawn_position(map: &TileMap, job: &JobDefinition) -> Vec3 {
         .unwrap_or_default();
     Vec3::new(spawn_tile.x as f32, 1.0, spawn_tile.y as f32)
 }
+
+/// Where players joining a running round arrive.
+/// Falls back to the job spawn, or any spawn point, if the map has no arrivals landmark.
+pub fn get_arrivals_position(map: &TileMap, job: &JobDefinition) -> Vec3 {
+    if let Some(tile) = map
+        .job_spawn_positions
+        .get(ARRIVALS_LANDMARK)
+        .and_then(|p| p.first())
+    {
+        return Vec3::new(tile.x as f32, 1.0, tile.y as f32);
+    }
+
+    warn!("Map has no arrivals landmark, spawning latejoin at a job spawn");
+    if map.job_spawn_positions.contains_key(&job.id) {
+        return get_spawn_position(map, job);
+    }
+    let spawn_tile = map
+        .job_spawn_positions
+        .values()
+        .find_map(|p| p.first().copied())
+        .unwrap_or_default();
+    Vec3::new(spawn_tile.x as f32, 1.0, spawn_tile.y as f32)
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use bevy::{
+        asset::AssetPath,
+        ecs::{schedule::ExecutorKind, system::SystemState},
+    };
+    use networking::{loopback::LinkConditions, testing, NetworkRole};
+
+    use super::*;
+    use crate::{
+        profile::{CharacterProfiles, ProfileMessage},
+        testing::{capture_warnings, server_app},
+    };
+
+    fn job(id: &str, max_slots: Option<u32>) -> JobDefinition {
+        ron::from_str(&format!(
+            r#"(id: "{}", name: "{}", description: "", clothing: [], max_slots: {:?})"#,
+            id, id, max_slots
+        ))
+        .unwrap()
+    }
+
+    /// Security with a choice of weapon and bag.
+    fn loadout_job() -> JobDefinition {
+        ron::from_str(
+            r#"(
+                id: "security",
+                name: "Security",
+                description: "",
+                clothing: [],
+                loadout: [
+                    (id: "weapon", name: "Weapon", options: [
+                        (item: "baton", name: "Baton"),
+                        (item: "disabler", name: "Disabler"),
+                    ]),
+                    (id: "bag", name: "Bag", options: [
+                        (item: "backpack", name: "Backpack"),
+                        (item: "satchel", name: "Satchel"),
+                    ]),
+                ],
+            )"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn out_of_pool_choice_is_dropped_for_the_default() {
+        let mut server = server_app(ServerConfig::default());
+        // Warnings are only captured on the test thread
+        server.edit_schedule(Update, |schedule| {
+            schedule.set_executor_kind(ExecutorKind::SingleThreaded);
+        });
+        let mut client = testing::app(NetworkRole::Client);
+        let connector = testing::listen(&mut server);
+        testing::join(&mut client, &connector, LinkConditions::default());
+        testing::connect(&mut server, &mut [&mut client], 200);
+        let security_id = AssetPathId::from(AssetPath::from("jobs/security.job.ron"));
+        server
+            .world
+            .resource_mut::<Assets<JobDefinition>>()
+            .set_untracked(security_id, loadout_job());
+
+        let connection = *server
+            .world
+            .resource::<Players>()
+            .players()
+            .keys()
+            .next()
+            .unwrap();
+        server.world.send_event(MessageEvent {
+            message: ProfileMessage {
+                name: "Alice".into(),
+                job_preferences: vec!["security".into()],
+                loadout: vec!["energy_sword".into(), "satchel".into()],
+                body: String::new(),
+            },
+            connection,
+        });
+        let (_, warnings) = capture_warnings(|| server.update());
+        assert!(warnings
+            .iter()
+            .any(|w| w == "Player chose a loadout item no job offers, it will be ignored"));
+
+        let choices = server
+            .world
+            .resource::<CharacterProfiles>()
+            .loadout(connection);
+        assert_eq!(choices, ["satchel"]);
+        let security = server
+            .world
+            .resource::<Assets<JobDefinition>>()
+            .get(&Handle::weak(HandleId::AssetPathId(security_id)))
+            .unwrap();
+        // The weapon slot has no valid choice, so it gets its default
+        assert_eq!(
+            security.loadout_items(choices, |_| None),
+            ["baton", "satchel"]
+        );
+    }
+
+    #[test]
+    fn locked_choice_is_replaced_by_the_default() {
+        let security = loadout_job();
+        let choices = ["disabler".to_owned(), "backpack".to_owned()];
+        let (items, warnings) = capture_warnings(|| {
+            security.loadout_items(&choices, |option| {
+                (option.item == "disabler").then(|| "Play security for 5 more hours".into())
+            })
+        });
+        assert_eq!(items, ["baton", "backpack"]);
+        assert_eq!(warnings, ["Loadout choice is locked, using the default"]);
+    }
+
+    #[test]
+    fn slots_run_out() {
+        let security = job("security", Some(2));
+        let mut slots = JobSlots::default();
+        assert_eq!(slots.open(&security), Some(2));
+
+        let players: Vec<_> = (1..=3).map(Uuid::from_u128).collect();
+        assert!(slots.claim(&security, players[0]));
+        assert!(slots.claim(&security, players[1]));
+        assert_eq!(slots.open(&security), Some(0));
+        assert!(!slots.claim(&security, players[2]));
+        // Claiming again doesn't take another slot
+        assert!(slots.claim(&security, players[0]));
+
+        // Unlimited jobs never fill up
+        let assistant = job("assistant", None);
+        for &player in players.iter() {
+            assert!(slots.claim(&assistant, player));
+        }
+        assert_eq!(slots.open(&assistant), None);
+    }
+
+    #[test]
+    fn dead_players_keep_their_slot_unless_released() {
+        let security = job("security", Some(1));
+        let mut slots = JobSlots::default();
+        let (player, body) = (Uuid::from_u128(1), Entity::from_raw(1));
+        assert!(slots.claim(&security, player));
+        slots.track_body(body, &security, player);
+        assert_eq!(slots.open(&security), Some(0));
+
+        slots.release(body);
+        assert_eq!(slots.open(&security), Some(1));
+        assert!(slots.claim(&security, Uuid::from_u128(2)));
+    }
+
+    /// Two connected clients that both selected the last security slot.
+    fn race(config: ServerConfig) -> App {
+        let mut server = server_app(config);
+        let mut first = testing::app(NetworkRole::Client);
+        let mut second = testing::app(NetworkRole::Client);
+        let connector = testing::listen(&mut server);
+        testing::join(&mut first, &connector, LinkConditions::default());
+        testing::join(&mut second, &connector, LinkConditions::default());
+        testing::connect(&mut server, &mut [&mut first, &mut second], 200);
+
+        let mut assets = server.world.resource_mut::<Assets<JobDefinition>>();
+        let security_id = AssetPathId::from(AssetPath::from("jobs/security.job.ron"));
+        assets.set_untracked(security_id, job("security", Some(1)));
+        assets.set_untracked(
+            AssetPathId::from(AssetPath::from("jobs/assistant.job.ron")),
+            job("assistant", None),
+        );
+        let connections: Vec<_> = server
+            .world
+            .resource::<Players>()
+            .players()
+            .keys()
+            .copied()
+            .collect();
+        let mut selected = server.world.resource_mut::<SelectedJobs>();
+        for connection in connections {
+            selected.select(connection, security_id);
+        }
+        server
+    }
+
+    /// Assigns jobs like a roundstart does, returning the job id or rejection of each player.
+    fn assign_all(server: &mut App) -> Vec<Result<String, String>> {
+        let players: HashMap<_, _> = server
+            .world
+            .resource::<Players>()
+            .players()
+            .iter()
+            .map(|(&connection, player)| (connection, player.id))
+            .collect();
+        let mut state = SystemState::<JobAssigner>::new(&mut server.world);
+        let mut jobs = state.get_mut(&mut server.world);
+        let mut results = Vec::new();
+        for connection in jobs.connections() {
+            let result = jobs
+                .assign(connection, players[&connection])
+                .map(|job| job.id.clone())
+                .map_err(|rejection| rejection.to_string());
+            results.push(result);
+        }
+        results
+    }
+
+    #[test]
+    fn racing_for_the_last_slot_overflows_the_loser() {
+        let mut server = race(ServerConfig::default());
+        let mut jobs: Vec<String> = assign_all(&mut server)
+            .into_iter()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        jobs.sort();
+        assert_eq!(jobs, ["assistant", "security"]);
+
+        // The loser now has the overflow job selected
+        let mut state = SystemState::<JobAssigner>::new(&mut server.world);
+        let jobs = state.get_mut(&mut server.world);
+        let mut selected: Vec<_> = jobs
+            .connections()
+            .into_iter()
+            .map(|connection| jobs.selected(connection).unwrap().id.clone())
+            .collect();
+        selected.sort();
+        assert_eq!(selected, ["assistant", "security"]);
+    }
+
+    #[test]
+    fn racing_for_the_last_slot_without_overflow_rejects_the_loser() {
+        let mut config = ServerConfig::default();
+        config.jobs.overflow_job = None;
+        let mut server = race(config);
+        let results = assign_all(&mut server);
+
+        assert_eq!(results.iter().filter(|r| r.is_ok()).count(), 1);
+        assert!(results.contains(&Ok("security".into())));
+        assert!(results.contains(&Err(JobRejection::Full("security".into()).to_string())));
+    }
+
+    #[test]
+    fn latejoin_falls_back_without_arrivals() {
+        let security = job("security", None);
+        let mut map = TileMap::new(UVec2::ONE);
+        map.job_spawn_positions
+            .insert("engineer".into(), vec![UVec2::new(7, 8)]);
+        // No spawn for the job either, so any spawn point is used
+        assert_eq!(
+            get_arrivals_position(&map, &security),
+            Vec3::new(7.0, 1.0, 8.0)
+        );
+
+        map.job_spawn_positions
+            .insert("security".into(), vec![UVec2::new(2, 3)]);
+        assert_eq!(
+            get_arrivals_position(&map, &security),
+            Vec3::new(2.0, 1.0, 3.0)
+        );
+
+        map.job_spawn_positions
+            .insert(ARRIVALS_LANDMARK.into(), vec![UVec2::new(10, 4)]);
+        assert_eq!(
+            get_arrivals_position(&map, &security),
+            Vec3::new(10.0, 1.0, 4.0)
+        );
+    }
+}
@@ -0,0 +1,727 @@
+use std::{collections::VecDeque, time::Duration};
+
+use bevy::{
+    prelude::*,
+    reflect::TypeUuid,
+    utils::{HashMap, HashSet},
+};
+use maps::{world_to_tile, TileMap};
+use networking::{
+    component::AppExt,
+    is_server,
+    variable::{NetworkVar, ServerVar},
+    Networked,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    body::self_or_ancestor,
+    combat::damage::{AffectedEntity, Attack},
+    console::{
+        ArgumentKind, CommandContext, CommandResult, ConsoleAppExt, ConsoleCommand, PermissionLevel,
+    },
+    construction::Screwdriver,
+    interaction::{
+        ActiveInteraction, GenerateInteractionList, InteractionListEvents, InteractionOption,
+        InteractionSpecificity, InteractionStatus,
+    },
+};
+
+#[cfg(feature = "client")]
+use {
+    crate::{accessibility::AccessibilitySettings, camera::MainCamera},
+    bevy::time::common_conditions::on_timer,
+};
+
+/// Animates light fixtures and lets them break, be repaired and show alarms.
+pub struct LightsPlugin;
+
+impl Plugin for LightsPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<LightFixture>()
+            .register_type::<LightBehavior>()
+            .register_type::<Opaque>()
+            .add_networked_component::<LightState, LightStateClient>();
+
+        if is_server(app) {
+            app.init_resource::<FireAlarms>()
+                .init_resource::<LightMap>()
+                .register_type::<RepairLightInteraction>()
+                .add_console_command(ConsoleCommand {
+                    name: "firealarm",
+                    description: "Turns the fire alarm of an area on or off",
+                    parameters: &[("area", ArgumentKind::Text)],
+                    permission: PermissionLevel::Admin,
+                    handler: fire_alarm_command,
+                })
+                .add_console_command(ConsoleCommand {
+                    name: "lit",
+                    description: "Shows how many lights reach the tile at your cursor",
+                    parameters: &[],
+                    permission: PermissionLevel::Admin,
+                    handler: lit_command,
+                })
+                .add_systems(
+                    Update,
+                    (
+                        setup_fixtures,
+                        break_damaged_fixtures,
+                        prepare_repair_interaction.in_set(GenerateInteractionList),
+                        repair_interaction,
+                        apply_fire_alarms.after(setup_fixtures),
+                        (queue_light_map_updates, update_light_map).chain(),
+                    ),
+                );
+        } else {
+            #[cfg(feature = "client")]
+            app.add_systems(
+                Update,
+                (
+                    (store_base_lights, animate_lights).chain(),
+                    limit_shadow_casters
+                        .after(store_base_lights)
+                        .run_if(on_timer(Duration::from_secs_f32(SHADOW_UPDATE_INTERVAL))),
+                ),
+            );
+        }
+    }
+}
+
+const REPAIR_TIME: Duration = Duration::from_secs(3);
+/// How often a broken light gets a chance to flash, in seconds
+#[cfg(feature = "client")]
+const BROKEN_FLASH_STEP: f32 = 0.1;
+/// Chance of a broken light flashing every step
+#[cfg(feature = "client")]
+const BROKEN_FLASH_CHANCE: f32 = 0.03;
+const FIRE_ALARM_PERIOD: f32 = 1.5;
+/// How many tiles away a fixture lights up
+const LIGHT_RADIUS: i32 = 6;
+/// Most fixtures that get their lit tiles recomputed in a frame
+const LIGHT_MAP_UPDATES_PER_TICK: usize = 16;
+/// Only this many fixtures closest to the camera cast shadows
+#[cfg(feature = "client")]
+const MAX_SHADOW_CASTERS: usize = 8;
+#[cfg(feature = "client")]
+const SHADOW_UPDATE_INTERVAL: f32 = 0.5;
+
+/// How the brightness of a light changes over time.
+#[derive(Serialize, Deserialize, Reflect, Clone, Copy, PartialEq, Debug, Default)]
+pub enum LightBehavior {
+    #[default]
+    Steady,
+    /// Jumps to random brightness factors in the range, changing `frequency` times per second
+    Flicker {
+        intensity_range: (f32, f32),
+        frequency: f32,
+    },
+    /// Fades out and in again every `period` seconds
+    Pulse { period: f32 },
+    /// Dark, with rare short flashes
+    Broken,
+    /// Completely dark
+    Off,
+}
+
+impl LightBehavior {
+    /// Brightness factor at a time. `seed` offsets random behaviors, so lights don't flicker in sync.
+    #[cfg(feature = "client")]
+    fn brightness(&self, time: f32, seed: u32) -> f32 {
+        match *self {
+            LightBehavior::Steady => 1.0,
+            LightBehavior::Flicker {
+                intensity_range: (min, max),
+                frequency,
+            } => {
+                let step = (time * frequency.max(0.0)) as u32;
+                min + (max - min) * noise(seed, step)
+            }
+            LightBehavior::Pulse { period } => {
+                let phase = time / period.max(f32::EPSILON) * std::f32::consts::TAU;
+                0.2 + 0.8 * (0.5 + 0.5 * phase.cos())
+            }
+            LightBehavior::Broken => {
+                let step = (time / BROKEN_FLASH_STEP) as u32;
+                if noise(seed, step) < BROKEN_FLASH_CHANCE {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            LightBehavior::Off => 0.0,
+        }
+    }
+
+    /// The behavior without flickering or flashing, for players that reduce flashing.
+    /// Lights that are mostly on stay on and lights that are mostly off stay off.
+    #[cfg(feature = "client")]
+    fn without_flashing(self) -> Self {
+        match self {
+            LightBehavior::Flicker { .. } | LightBehavior::Pulse { .. } => LightBehavior::Steady,
+            LightBehavior::Broken => LightBehavior::Off,
+            behavior => behavior,
+        }
+    }
+}
+
+/// Deterministic value between 0 and 1, so every client sees the same pattern for a light.
+#[cfg(feature = "client")]
+fn noise(seed: u32, step: u32) -> f32 {
+    let mut x = seed ^ step.wrapping_mul(0x9E37_79B9);
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7FEB_352D);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846C_A68B);
+    x ^= x >> 16;
+    x as f32 / u32::MAX as f32
+}
+
+/// A wall mounted light. The behavior is what the light does when it's spawned.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct LightFixture {
+    pub behavior: LightBehavior,
+}
+
+/// What is temporarily changing how a light looks.
+/// Clients pick the color for it, so players can change it to one they can see.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum OverrideSource {
+    FireAlarm,
+    NoPower,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct LightOverride {
+    pub source: OverrideSource,
+    pub behavior: LightBehavior,
+}
+
+/// The current behavior of a fixture.
+/// Overrides are shown on top of the behavior without replacing it, the last one added is visible.
+#[derive(Component, Networked)]
+#[networked(client = "LightStateClient")]
+pub struct LightState {
+    behavior: NetworkVar<LightBehavior>,
+    overrides: NetworkVar<Vec<LightOverride>>,
+}
+
+impl LightState {
+    fn new(behavior: LightBehavior) -> Self {
+        Self {
+            behavior: behavior.into(),
+            overrides: Vec::new().into(),
+        }
+    }
+
+    pub fn behavior(&self) -> LightBehavior {
+        *self.behavior
+    }
+
+    pub fn set_behavior(&mut self, behavior: LightBehavior) {
+        if *self.behavior != behavior {
+            *self.behavior = behavior;
+        }
+    }
+
+    /// Adds an override, replacing an existing one from the same source.
+    pub fn push_override(&mut self, light_override: LightOverride) {
+        if self.overrides.last() == Some(&light_override) {
+            return;
+        }
+        self.overrides.retain(|o| o.source != light_override.source);
+        self.overrides.push(light_override);
+    }
+
+    pub fn remove_override(&mut self, source: OverrideSource) {
+        if self.overrides.iter().any(|o| o.source == source) {
+            self.overrides.retain(|o| o.source != source);
+        }
+    }
+}
+
+#[derive(Component, Default, TypeUuid, Networked)]
+#[uuid = "b7e4c2a9-3f1d-4e8a-a6c5-1d9f0e2b7a34"]
+#[networked(server = "LightState")]
+pub struct LightStateClient {
+    behavior: ServerVar<LightBehavior>,
+    overrides: ServerVar<Vec<LightOverride>>,
+}
+
+fn setup_fixtures(
+    fixtures: Query<(Entity, &LightFixture), Added<LightFixture>>,
+    mut commands: Commands,
+) {
+    for (entity, fixture) in fixtures.iter() {
+        commands
+            .entity(entity)
+            .insert(LightState::new(fixture.behavior));
+    }
+}
+
+/// Any hit breaks a light.
+fn break_damaged_fixtures(
+    attacks: Query<(Entity, &AffectedEntity), Added<Attack>>,
+    parents: Query<&Parent>,
+    mut fixtures: Query<&mut LightState>,
+    mut commands: Commands,
+) {
+    for (attack, affected) in attacks.iter() {
+        // Attacks hit the collider, which is a child of the fixture
+        let Some(fixture) = self_or_ancestor(&parents, affected.0, |e| fixtures.contains(e)) else {
+            continue;
+        };
+
+        fixtures
+            .get_mut(fixture)
+            .unwrap()
+            .set_behavior(LightBehavior::Broken);
+        commands.entity(attack).despawn();
+    }
+}
+
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+#[component(storage = "SparseSet")]
+struct RepairLightInteraction {
+    target: Entity,
+}
+
+// Dummy default for Reflect
+impl Default for RepairLightInteraction {
+    fn default() -> Self {
+        Self {
+            target: Entity::from_raw(0),
+        }
+    }
+}
+
+fn prepare_repair_interaction(
+    list: Res<InteractionListEvents>,
+    screwdrivers: Query<(), With<Screwdriver>>,
+    fixtures: Query<&LightState>,
+) {
+    for event in list.events.iter() {
+        let Some(item_in_hand) = event.item_in_hand else {
+            continue;
+        };
+        if !screwdrivers.contains(item_in_hand) {
+            continue;
+        }
+        let Ok(state) = fixtures.get(event.target) else {
+            continue;
+        };
+        if state.behavior() != LightBehavior::Broken {
+            continue;
+        }
+
+        event.add_interaction(InteractionOption {
+            text: "Repair light".into(),
+            interaction: Box::new(RepairLightInteraction {
+                target: event.target,
+            }),
+            specificity: InteractionSpecificity::Specific,
+        });
+    }
+}
+
+fn repair_interaction(
+    mut query: Query<(&RepairLightInteraction, &mut ActiveInteraction)>,
+    mut fixtures: Query<&mut LightState>,
+    time: Res<Time>,
+) {
+    for (interaction, mut active) in query.iter_mut() {
+        active.set_initial_duration(REPAIR_TIME);
+
+        let Ok(mut state) = fixtures.get_mut(interaction.target) else {
+            active.status = InteractionStatus::Canceled;
+            continue;
+        };
+        if state.behavior() != LightBehavior::Broken {
+            active.status = InteractionStatus::Canceled;
+            continue;
+        }
+
+        if active.start_time() + REPAIR_TIME.as_secs_f32() > time.elapsed_seconds() {
+            continue;
+        }
+
+        state.set_behavior(LightBehavior::Steady);
+        active.status = InteractionStatus::Completed;
+    }
+}
+
+/// Names of areas with an active fire alarm.
+#[derive(Resource, Default)]
+pub struct FireAlarms(pub HashSet<String>);
+
+fn fire_alarm_command(world: &mut World, context: &CommandContext) -> CommandResult {
+    let area = context.text(0);
+    let mut alarms = world.resource_mut::<FireAlarms>();
+    if alarms.0.remove(area) {
+        Ok(format!("Fire alarm in {} is off", area))
+    } else {
+        alarms.0.insert(area.to_owned());
+        Ok(format!("Fire alarm in {} is on", area))
+    }
+}
+
+fn lit_command(world: &mut World, context: &CommandContext) -> CommandResult {
+    let Some(tile) = context.cursor.and_then(world_to_tile) else {
+        return Err("Your cursor isn't over the map".into());
+    };
+    let light_map = world.resource::<LightMap>();
+    if light_map.is_lit(tile) {
+        Ok(format!(
+            "Tile {} is lit by {} lights",
+            tile,
+            light_map.lights_at(tile)
+        ))
+    } else {
+        Ok(format!("Tile {} is dark", tile))
+    }
+}
+
+/// Makes lights in areas with a fire alarm pulse red.
+fn apply_fire_alarms(
+    alarms: Res<FireAlarms>,
+    mut fixtures: Query<(&mut LightState, &GlobalTransform)>,
+    new_fixtures: Query<(), Added<LightState>>,
+    maps: Query<&TileMap>,
+) {
+    if !alarms.is_changed() && new_fixtures.is_empty() {
+        return;
+    }
+    // TODO: Support multiple maps
+    let Ok(map) = maps.get_single() else {
+        return;
+    };
+
+    for (mut state, transform) in fixtures.iter_mut() {
+        let alarm = map
+            .area_at(transform.translation())
+            .is_some_and(|area| alarms.0.contains(area));
+        if alarm {
+            state.push_override(LightOverride {
+                source: OverrideSource::FireAlarm,
+                behavior: LightBehavior::Pulse {
+                    period: FIRE_ALARM_PERIOD,
+                },
+            });
+        } else {
+            state.remove_override(OverrideSource::FireAlarm);
+        }
+    }
+}
+
+/// Blocks light, like walls. Windows let light through.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct Opaque;
+
+/// The tiles lit by each fixture, using line of sight on the tile grid.
+/// Coarse, but cheap enough for gameplay systems to ask about any tile.
+#[derive(Resource, Default)]
+pub struct LightMap {
+    fixtures: HashMap<Entity, LitArea>,
+    /// How many fixtures light each tile
+    tiles: HashMap<UVec2, u32>,
+    /// Fixtures waiting to be recomputed, in the order they were changed
+    queue: VecDeque<Entity>,
+    queued: HashSet<Entity>,
+}
+
+struct LitArea {
+    origin: UVec2,
+    tiles: Vec<UVec2>,
+}
+
+impl LightMap {
+    /// If any working fixture reaches the tile.
+    pub fn is_lit(&self, tile: UVec2) -> bool {
+        self.tiles.contains_key(&tile)
+    }
+
+    /// How many working fixtures reach the tile.
+    pub fn lights_at(&self, tile: UVec2) -> u32 {
+        self.tiles.get(&tile).copied().unwrap_or_default()
+    }
+
+    fn queue(&mut self, fixture: Entity) {
+        if self.queued.insert(fixture) {
+            self.queue.push_back(fixture);
+        }
+    }
+
+    fn remove(&mut self, fixture: Entity) {
+        let Some(area) = self.fixtures.remove(&fixture) else {
+            return;
+        };
+        for tile in area.tiles {
+            if let Some(count) = self.tiles.get_mut(&tile) {
+                *count -= 1;
+                if *count == 0 {
+                    self.tiles.remove(&tile);
+                }
+            }
+        }
+    }
+
+    fn insert(&mut self, fixture: Entity, area: LitArea) {
+        self.remove(fixture);
+        for &tile in area.tiles.iter() {
+            *self.tiles.entry(tile).or_default() += 1;
+        }
+        self.fixtures.insert(fixture, area);
+    }
+}
+
+fn is_opaque(map: &TileMap, position: UVec2, opaque: &Query<(), With<Opaque>>) -> bool {
+    map.tile(position).is_some_and(|tile| {
+        tile.turf
+            .into_iter()
+            .chain(tile.furniture)
+            .any(|e| opaque.contains(e))
+    })
+}
+
+/// Tiles visible from the origin within the light radius.
+/// Opaque tiles are lit themselves, but block everything behind them.
+fn lit_tiles(map: &TileMap, origin: UVec2, opaque: &Query<(), With<Opaque>>) -> Vec<UVec2> {
+    const STEPS_PER_TILE: i32 = 4;
+
+    let start = origin.as_ivec2();
+    let mut tiles = Vec::new();
+    for y in -LIGHT_RADIUS..=LIGHT_RADIUS {
+        for x in -LIGHT_RADIUS..=LIGHT_RADIUS {
+            let offset = IVec2::new(x, y);
+            if offset.length_squared() > LIGHT_RADIUS * LIGHT_RADIUS {
+                continue;
+            }
+            let target = start + offset;
+            if target.min_element() < 0 {
+                continue;
+            }
+
+            // Walk along the line between the tile centers
+            let steps = offset.abs().max_element() * STEPS_PER_TILE;
+            let blocked = (1..steps).any(|step| {
+                let point = start.as_vec2() + offset.as_vec2() * (step as f32 / steps as f32);
+                let tile = point.round().as_ivec2();
+                tile != start && tile != target && is_opaque(map, tile.as_uvec2(), opaque)
+            });
+            if !blocked {
+                tiles.push(target.as_uvec2());
+            }
+        }
+    }
+    tiles
+}
+
+/// The tile a fixture shines from. Wall mounts sit on the edge of their tile, so prefer the open side.
+fn fixture_origin(
+    map: &TileMap,
+    position: Vec3,
+    opaque: &Query<(), With<Opaque>>,
+) -> Option<UVec2> {
+    let tile = world_to_tile(position)?;
+    if !is_opaque(map, tile, opaque) {
+        return Some(tile);
+    }
+    let neighbours = [IVec2::X, IVec2::NEG_X, IVec2::Y, IVec2::NEG_Y]
+        .map(|offset| tile.as_ivec2() + offset)
+        .into_iter()
+        .filter(|t| t.min_element() >= 0)
+        .map(|t| t.as_uvec2())
+        .filter(|&t| !is_opaque(map, t, opaque));
+    neighbours
+        .min_by(|a, b| {
+            let distance = |t: &UVec2| t.as_vec2().distance_squared(position.xz());
+            distance(a).total_cmp(&distance(b))
+        })
+        .or(Some(tile))
+}
+
+/// Finds fixtures whose lit tiles may have changed.
+/// Only fixtures within the light radius of a changed wall are recomputed.
+fn queue_light_map_updates(
+    fixtures: Query<
+        Entity,
+        (
+            With<LightFixture>,
+            Or<(Changed<GlobalTransform>, Changed<LightState>)>,
+        ),
+    >,
+    mut removed_fixtures: RemovedComponents<LightFixture>,
+    // Transforms only get their final position after propagation, so this also catches new walls
+    moved: Query<(Entity, &GlobalTransform), (With<Opaque>, Changed<GlobalTransform>)>,
+    mut removed: RemovedComponents<Opaque>,
+    mut opaque_positions: Local<HashMap<Entity, UVec2>>,
+    mut light_map: ResMut<LightMap>,
+) {
+    for fixture in removed_fixtures.iter() {
+        light_map.remove(fixture);
+    }
+    for fixture in fixtures.iter() {
+        light_map.queue(fixture);
+    }
+
+    let mut changed_tiles = Vec::new();
+    for (entity, transform) in moved.iter() {
+        if let Some(position) = world_to_tile(transform.translation()) {
+            if let Some(old) = opaque_positions.insert(entity, position) {
+                changed_tiles.push(old);
+            }
+            changed_tiles.push(position);
+        }
+    }
+    for entity in removed.iter() {
+        if let Some(position) = opaque_positions.remove(&entity) {
+            changed_tiles.push(position);
+        }
+    }
+    if changed_tiles.is_empty() {
+        return;
+    }
+
+    let affected: Vec<Entity> = light_map
+        .fixtures
+        .iter()
+        .filter(|(_, area)| {
+            changed_tiles.iter().any(|tile| {
+                (tile.as_ivec2() - area.origin.as_ivec2()).length_squared()
+                    <= (LIGHT_RADIUS + 1) * (LIGHT_RADIUS + 1)
+            })
+        })
+        .map(|(&fixture, _)| fixture)
+        .collect();
+    for fixture in affected {
+        light_map.queue(fixture);
+    }
+}
+
+/// Recomputes queued fixtures, a limited number per frame.
+fn update_light_map(
+    fixtures: Query<(&GlobalTransform, &LightState)>,
+    opaque: Query<(), With<Opaque>>,
+    maps: Query<&TileMap>,
+    mut light_map: ResMut<LightMap>,
+) {
+    // TODO: Support multiple maps
+    let Ok(map) = maps.get_single() else {
+        return;
+    };
+
+    for _ in 0..LIGHT_MAP_UPDATES_PER_TICK {
+        let Some(fixture) = light_map.queue.pop_front() else {
+            break;
+        };
+        light_map.queued.remove(&fixture);
+
+        let Ok((transform, state)) = fixtures.get(fixture) else {
+            light_map.remove(fixture);
+            continue;
+        };
+        let Some(origin) = fixture_origin(map, transform.translation(), &opaque) else {
+            light_map.remove(fixture);
+            continue;
+        };
+        // Dark fixtures are kept, so wall changes near them are still noticed
+        let dark = matches!(state.behavior(), LightBehavior::Off | LightBehavior::Broken);
+        let tiles = if dark {
+            Vec::new()
+        } else {
+            lit_tiles(map, origin, &opaque)
+        };
+        light_map.insert(fixture, LitArea { origin, tiles });
+    }
+}
+
+/// How a light looks without any behavior applied.
+#[cfg(feature = "client")]
+#[derive(Component)]
+struct BaseLight {
+    intensity: f32,
+    color: Color,
+}
+
+#[cfg(feature = "client")]
+fn store_base_lights(
+    lights: Query<(Entity, &PointLight), Without<BaseLight>>,
+    mut commands: Commands,
+) {
+    for (entity, light) in lights.iter() {
+        commands.entity(entity).insert(BaseLight {
+            intensity: light.intensity,
+            color: light.color,
+        });
+    }
+}
+
+/// Offsets the flicker of a light fixture.
+/// Seeded by position, as that's the same for every client and every run of a map.
+#[cfg(any(feature = "client", test))]
+pub(crate) fn flicker_seed(translation: Vec3) -> u32 {
+    let position = translation.round().as_ivec3();
+    (position.x as u32).wrapping_mul(73_856_093) ^ (position.z as u32).wrapping_mul(19_349_663)
+}
+
+#[cfg(feature = "client")]
+fn animate_lights(
+    fixtures: Query<(Entity, &LightStateClient, &GlobalTransform)>,
+    children: Query<&Children>,
+    mut lights: Query<(&mut PointLight, &BaseLight)>,
+    accessibility: Res<AccessibilitySettings>,
+    time: Res<Time>,
+) {
+    let now = time.elapsed_seconds();
+    for (entity, state, transform) in fixtures.iter() {
+        let seed = flicker_seed(transform.translation());
+        let (mut behavior, color) = match state.overrides.last() {
+            Some(light_override) => (
+                light_override.behavior,
+                match light_override.source {
+                    OverrideSource::FireAlarm => Some(accessibility.alarm_light()),
+                    OverrideSource::NoPower => None,
+                },
+            ),
+            None => (*state.behavior, None),
+        };
+        if accessibility.reduce_flashing {
+            behavior = behavior.without_flashing();
+        }
+        let brightness = behavior.brightness(now, seed);
+
+        for child in children.iter_descendants(entity) {
+            let Ok((mut light, base)) = lights.get_mut(child) else {
+                continue;
+            };
+            light.intensity = base.intensity * brightness;
+            light.color = color.unwrap_or(base.color);
+        }
+    }
+}
+
+#[cfg(feature = "client")]
+/// Shadows are expensive, so only the fixtures closest to the camera cast them.
+fn limit_shadow_casters(
+    mut lights: Query<(&GlobalTransform, &mut PointLight), With<BaseLight>>,
+    camera: Query<&GlobalTransform, With<MainCamera>>,
+) {
+    let Ok(camera) = camera.get_single() else {
+        return;
+    };
+    let camera = camera.translation();
+
+    let mut by_distance: Vec<_> = lights
+        .iter_mut()
+        .map(|(transform, light)| (transform.translation().distance_squared(camera), light))
+        .collect();
+    by_distance.sort_unstable_by(|(a, _), (b, _)| a.total_cmp(b));
+    for (index, (_, mut light)) in by_distance.into_iter().enumerate() {
+        let shadows = index < MAX_SHADOW_CASTERS;
+        if light.shadows_enabled != shadows {
+            light.shadows_enabled = shadows;
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use bevy::prelude::*;
+use networking::is_server;
+
+#[cfg(feature = "client")]
+use {
+    bevy::{
+        ecs::system::SystemParam,
+        reflect::{TypePath, TypeUuid},
+        utils::HashMap,
+    },
+    bevy_common_assets::ron::RonAssetPlugin,
+    serde::Deserialize,
+};
+
+/// Player-facing text looked up by key, so it can be translated (or replaced by a resource pack) without code changes.
+/// The server sends keys and arguments, the client picks the text.
+pub struct LocalePlugin;
+
+impl Plugin for LocalePlugin {
+    fn build(&self, app: &mut App) {
+        if !is_server(app) {
+            #[cfg(feature = "client")]
+            app.add_plugins(RonAssetPlugin::<LocaleTexts>::new(&["locale.ron"]))
+                .add_systems(Startup, load_locale);
+        }
+    }
+}
+
+/// Texts shown to the player
+#[cfg(feature = "client")]
+const LOCALE_FILE: &str = "locale/en.locale.ron";
+
+/// Texts by key. `{0}`, `{1}` and so on are replaced by the arguments.
+#[cfg(feature = "client")]
+#[derive(Deserialize, TypeUuid, TypePath)]
+#[uuid = "5d1b8a3e-7f0c-4b2e-9a61-3c8e2f4d7b90"]
+pub struct LocaleTexts {
+    texts: HashMap<String, String>,
+}
+
+#[cfg(feature = "client")]
+#[derive(Resource)]
+struct LocaleHandle(Handle<LocaleTexts>);
+
+#[cfg(feature = "client")]
+fn load_locale(asset_server: Res<AssetServer>, mut commands: Commands) {
+    commands.insert_resource(LocaleHandle(asset_server.load(LOCALE_FILE)));
+}
+
+#[cfg(feature = "client")]
+#[derive(SystemParam)]
+pub struct Locale<'w> {
+    handle: Option<Res<'w, LocaleHandle>>,
+    texts: Res<'w, Assets<LocaleTexts>>,
+}
+
+#[cfg(feature = "client")]
+impl<'w> Locale<'w> {
+    /// The text for a key with the arguments filled in. Unknown keys are shown as is, so missing texts are easy to spot.
+    pub fn text(&self, key: &str, args: &[String]) -> String {
+        let template = self
+            .handle
+            .as_ref()
+            .and_then(|handle| self.texts.get(&handle.0))
+            .and_then(|locale| locale.texts.get(key));
+        let Some(template) = template else {
+            return key.to_owned();
+        };
+
+        let mut text = template.clone();
+        for (index, arg) in args.iter().enumerate() {
+            text = text.replace(&format!("{{{}}}", index), arg);
+        }
+        text
+    }
+}
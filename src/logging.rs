@@ -0,0 +1,154 @@
+use std::{
+    collections::VecDeque,
+    fmt::{self, Write},
+    sync::{Arc, Mutex, OnceLock},
+    time::SystemTime,
+};
+
+use bevy::{
+    prelude::*,
+    utils::tracing::{
+        field::{Field, Visit},
+        Event, Level, Subscriber,
+    },
+};
+use tracing_subscriber::{
+    layer::Context, prelude::*, util::SubscriberInitExt, EnvFilter, Layer, Registry,
+};
+
+/// Sets up logging like bevy's `LogPlugin`, but also keeps recent lines in [`RecentLogs`].
+pub struct LogCapturePlugin;
+
+impl Plugin for LogCapturePlugin {
+    fn build(&self, app: &mut App) {
+        // The logger is global, so a server and client in the same process share it
+        let logs = PROCESS_LOGS
+            .get_or_init(|| {
+                let logs = RecentLogs::default();
+                let filter = EnvFilter::try_from_default_env()
+                    .or_else(|_| EnvFilter::try_new(DEFAULT_FILTER))
+                    .unwrap();
+                let result = Registry::default()
+                    .with(filter)
+                    .with(tracing_subscriber::fmt::Layer::default().with_writer(std::io::stderr))
+                    .with(CaptureLayer { logs: logs.clone() })
+                    .try_init();
+                if let Err(err) = result {
+                    eprintln!("Could not set up logging: {}", err);
+                }
+                logs
+            })
+            .clone();
+
+        app.insert_resource(logs);
+    }
+}
+
+/// Lines captured by the logger of this process
+static PROCESS_LOGS: OnceLock<RecentLogs> = OnceLock::new();
+
+/// Same as the default of bevy's `LogPlugin`
+const DEFAULT_FILTER: &str = "info,wgpu=error,naga=warn";
+/// How many lines are kept in memory
+const CAPTURED_LINES: usize = 500;
+/// Fields that may contain information about a specific player
+const PRIVATE_FIELDS: &[&str] = &[
+    "connection",
+    "client_id",
+    "username",
+    "id",
+    "player",
+    "text",
+];
+/// Modules that log what players say or do privately
+const PRIVATE_TARGETS: &[&str] = &["ssnt::communication", "ssnt::admin"];
+
+/// A line that was logged.
+#[derive(Clone, Debug)]
+pub struct LogLine {
+    pub level: Level,
+    pub target: String,
+    pub text: String,
+    /// Seconds since the unix epoch
+    pub timestamp: f64,
+    /// The line could contain information about a player, so it should only be shown to admins
+    pub private: bool,
+}
+
+impl fmt::Display for LogLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:.3} {} {}: {}",
+            self.timestamp, self.level, self.target, self.text
+        )
+    }
+}
+
+/// The most recent log lines.
+#[derive(Resource, Clone, Default)]
+pub struct RecentLogs(Arc<Mutex<VecDeque<LogLine>>>);
+
+impl RecentLogs {
+    /// The last `count` lines, oldest first.
+    pub fn last(&self, count: usize) -> Vec<LogLine> {
+        let lines = self.0.lock().unwrap();
+        lines
+            .iter()
+            .skip(lines.len().saturating_sub(count))
+            .cloned()
+            .collect()
+    }
+
+    fn push(&self, line: LogLine) {
+        let mut lines = self.0.lock().unwrap();
+        if lines.len() >= CAPTURED_LINES {
+            lines.pop_front();
+        }
+        lines.push_back(line);
+    }
+}
+
+struct CaptureLayer {
+    logs: RecentLogs,
+}
+
+impl<S: Subscriber> Layer<S> for CaptureLayer {
+    fn on_event(&self, event: &Event<'_>, _: Context<'_, S>) {
+        let metadata = event.metadata();
+        let mut visitor = LineVisitor::default();
+        event.record(&mut visitor);
+
+        let target = metadata.target();
+        self.logs.push(LogLine {
+            level: *metadata.level(),
+            target: target.to_owned(),
+            text: visitor.message + &visitor.fields,
+            timestamp: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map(|d| d.as_secs_f64())
+                .unwrap_or_default(),
+            private: visitor.private || PRIVATE_TARGETS.iter().any(|t| target.starts_with(t)),
+        });
+    }
+}
+
+#[derive(Default)]
+struct LineVisitor {
+    message: String,
+    fields: String,
+    private: bool,
+}
+
+impl Visit for LineVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        let name = field.name();
+        if name == "message" {
+            let _ = write!(self.message, "{:?}", value);
+            return;
+        }
+
+        self.private |= PRIVATE_FIELDS.contains(&name);
+        let _ = write!(self.fields, " {}={:?}", name, value);
+    }
+}
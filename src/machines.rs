@@ -0,0 +1,334 @@
+use bevy::prelude::*;
+use networking::{
+    identity::{NetworkIdentities, NetworkIdentity},
+    is_server,
+    messaging::{AppExt, MessageEvent, MessageReceivers, MessageSender},
+    spawning::ClientControls,
+    ConnectionId, Players,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    access::AccessReader,
+    interaction::{
+        ActiveInteraction, GenerateInteractionList, InteractionAppExt, InteractionListEvents,
+        InteractionOption, InteractionSpecificity, InteractionStatus, Reach,
+    },
+};
+
+use self::{
+    apc::ApcPlugin, command::CommandConsolePlugin, id_card::IdCardConsolePlugin,
+    medical::MedicalScannerPlugin, processing::ProcessingPlugin, security::SecurityConsolePlugin,
+};
+
+pub mod apc;
+pub mod command;
+pub mod id_card;
+pub mod medical;
+pub mod processing;
+pub mod security;
+
+/// Consoles that open a window for the player using them.
+pub struct MachinesPlugin;
+
+impl Plugin for MachinesPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Machine>()
+            .add_network_message::<CloseMachineRequest>("CloseMachineRequest")
+            .add_network_message::<MachineClosedMessage>("MachineClosedMessage")
+            .add_plugins((
+                SecurityConsolePlugin,
+                ApcPlugin,
+                MedicalScannerPlugin,
+                IdCardConsolePlugin,
+                ProcessingPlugin,
+                CommandConsolePlugin,
+            ));
+
+        if is_server(app) {
+            app.init_resource::<MachineViewers>()
+                .register_queueable_interaction::<UseMachineInteraction>()
+                .add_systems(
+                    Update,
+                    (
+                        prepare_use_machine_interaction.in_set(GenerateInteractionList),
+                        use_machine_interaction,
+                        (
+                            handle_close_requests,
+                            close_invalid_viewers,
+                            schedule_updates,
+                        )
+                            .chain()
+                            .before(SendMachineUpdates),
+                    ),
+                );
+        }
+    }
+}
+
+/// Seconds between updates sent to a player with a machine window open
+const UPDATE_INTERVAL: f32 = 1.0;
+
+/// The set in which machines send their state to viewers that are due an update.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, SystemSet)]
+pub struct SendMachineUpdates;
+
+/// An object with a window that players can open by using it.
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct Machine {
+    pub name: String,
+}
+
+impl FromWorld for Machine {
+    fn from_world(_: &mut World) -> Self {
+        Self {
+            name: "Machine".into(),
+        }
+    }
+}
+
+/// A player with the window of a machine open.
+pub struct MachineViewer {
+    pub connection: ConnectionId,
+    pub creature: Entity,
+    pub machine: Entity,
+    pub identity: NetworkIdentity,
+    /// When the viewer last got an update
+    last_update: f32,
+    /// Refreshes wait a frame, so changes made with commands are applied first
+    refresh: bool,
+    refresh_next: bool,
+    due: bool,
+}
+
+impl MachineViewer {
+    fn new(
+        connection: ConnectionId,
+        creature: Entity,
+        machine: Entity,
+        identity: NetworkIdentity,
+    ) -> Self {
+        Self {
+            connection,
+            creature,
+            machine,
+            identity,
+            last_update: f32::NEG_INFINITY,
+            refresh: false,
+            refresh_next: false,
+            due: false,
+        }
+    }
+}
+
+/// Who has which machine open. Machine state is only sent to these players.
+#[derive(Resource, Default)]
+pub struct MachineViewers {
+    viewers: Vec<MachineViewer>,
+}
+
+impl MachineViewers {
+    /// Viewers that should be sent the state of their machine this frame.
+    pub fn due(&self) -> impl Iterator<Item = &MachineViewer> {
+        self.viewers.iter().filter(|v| v.due)
+    }
+
+    /// The viewer of a machine, if the connection has it open.
+    pub fn get(
+        &self,
+        connection: ConnectionId,
+        machine: NetworkIdentity,
+    ) -> Option<&MachineViewer> {
+        self.viewers
+            .iter()
+            .find(|v| v.connection == connection && v.identity == machine)
+    }
+
+    /// Sends an update to everyone viewing a machine soon, for example after it changed.
+    pub fn refresh(&mut self, machine: Entity) {
+        for viewer in self.viewers.iter_mut().filter(|v| v.machine == machine) {
+            viewer.refresh = true;
+        }
+    }
+
+    /// Sends an update to everyone viewing any machine, for when shared state changed.
+    pub fn refresh_all(&mut self) {
+        for viewer in self.viewers.iter_mut() {
+            viewer.refresh = true;
+        }
+    }
+
+    /// Closes the window of everyone viewing a machine.
+    pub fn close(&mut self, machine: Entity, sender: &mut MessageSender) {
+        self.viewers.retain(|viewer| {
+            if viewer.machine != machine {
+                return true;
+            }
+            sender.send(
+                &MachineClosedMessage {
+                    machine: viewer.identity,
+                },
+                MessageReceivers::Single(viewer.connection),
+            );
+            false
+        });
+    }
+
+    fn open(&mut self, viewer: MachineViewer) -> Option<MachineViewer> {
+        let previous = self
+            .viewers
+            .iter()
+            .position(|v| v.connection == viewer.connection)
+            .map(|index| self.viewers.swap_remove(index));
+        self.viewers.push(viewer);
+        previous
+    }
+}
+
+/// Client message to stop getting updates for a machine.
+#[derive(Serialize, Deserialize)]
+struct CloseMachineRequest {
+    machine: NetworkIdentity,
+}
+
+/// Server message that a machine window was closed, because the player walked away or similar.
+#[derive(Serialize, Deserialize)]
+pub struct MachineClosedMessage {
+    pub machine: NetworkIdentity,
+}
+
+/// Tells the server that the client closed a machine window, so it stops sending updates.
+#[cfg(feature = "client")]
+pub fn close_machine_window(machine: NetworkIdentity, sender: &mut MessageSender) {
+    sender.send_to_server(&CloseMachineRequest { machine });
+}
+
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+#[component(storage = "SparseSet")]
+struct UseMachineInteraction {
+    target: Entity,
+}
+
+// Dummy default for Reflect
+impl Default for UseMachineInteraction {
+    fn default() -> Self {
+        Self {
+            target: Entity::from_raw(0),
+        }
+    }
+}
+
+fn prepare_use_machine_interaction(
+    list: Res<InteractionListEvents>,
+    machines: Query<&Machine>,
+    reach: Reach,
+) {
+    for event in list.events.iter() {
+        let Ok(machine) = machines.get(event.target) else {
+            continue;
+        };
+        if !reach.can_reach(event.source, event.target) {
+            continue;
+        }
+
+        event.add_restricted_interaction(InteractionOption {
+            text: format!("Use {}", machine.name),
+            interaction: Box::new(UseMachineInteraction {
+                target: event.target,
+            }),
+            specificity: InteractionSpecificity::Specific,
+        });
+    }
+}
+
+fn use_machine_interaction(
+    mut query: Query<(Entity, &UseMachineInteraction, &mut ActiveInteraction)>,
+    machines: Query<&NetworkIdentity, With<Machine>>,
+    mut viewers: ResMut<MachineViewers>,
+    controls: Res<ClientControls>,
+    players: Res<Players>,
+    mut sender: MessageSender,
+) {
+    for (creature, interaction, mut active) in query.iter_mut() {
+        let (Ok(&identity), Some(connection)) = (
+            machines.get(interaction.target),
+            controls
+                .controlling_player(creature)
+                .and_then(|player| players.get_connection(&player)),
+        ) else {
+            active.status = InteractionStatus::Canceled;
+            continue;
+        };
+
+        // Players only have one machine open at a time
+        let previous = viewers.open(MachineViewer::new(
+            connection,
+            creature,
+            interaction.target,
+            identity,
+        ));
+        if let Some(previous) = previous.filter(|p| p.identity != identity) {
+            sender.send(
+                &MachineClosedMessage {
+                    machine: previous.identity,
+                },
+                MessageReceivers::Single(connection),
+            );
+        }
+        active.status = InteractionStatus::Completed;
+    }
+}
+
+fn handle_close_requests(
+    mut messages: EventReader<MessageEvent<CloseMachineRequest>>,
+    mut viewers: ResMut<MachineViewers>,
+) {
+    for event in messages.iter() {
+        viewers
+            .viewers
+            .retain(|v| v.connection != event.connection || v.identity != event.message.machine);
+    }
+}
+
+/// Closes windows of players that can't use their machine anymore
+fn close_invalid_viewers(
+    mut viewers: ResMut<MachineViewers>,
+    identities: Res<NetworkIdentities>,
+    controls: Res<ClientControls>,
+    players: Res<Players>,
+    reach: Reach,
+    access: AccessReader,
+    mut sender: MessageSender,
+) {
+    viewers.viewers.retain(|viewer| {
+        let valid = identities.get_entity(viewer.identity) == Some(viewer.machine)
+            && players
+                .get(viewer.connection)
+                .and_then(|player| controls.controlled_entity(player.id))
+                == Some(viewer.creature)
+            && reach.can_reach(viewer.creature, viewer.machine)
+            && access.can_access(viewer.creature, viewer.machine);
+        if !valid {
+            sender.send(
+                &MachineClosedMessage {
+                    machine: viewer.identity,
+                },
+                MessageReceivers::Single(viewer.connection),
+            );
+        }
+        valid
+    });
+}
+
+fn schedule_updates(mut viewers: ResMut<MachineViewers>, time: Res<Time>) {
+    let now = time.elapsed_seconds();
+    for viewer in viewers.viewers.iter_mut() {
+        viewer.due = viewer.refresh_next || viewer.last_update + UPDATE_INTERVAL <= now;
+        viewer.refresh_next = std::mem::take(&mut viewer.refresh);
+        if viewer.due {
+            viewer.last_update = now;
+        }
+    }
+}
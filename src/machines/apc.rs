@@ -0,0 +1,267 @@
+use bevy::{prelude::*, utils::HashSet};
+use maps::TileMap;
+use networking::{
+    identity::NetworkIdentity,
+    is_server,
+    messaging::{AppExt, MessageEvent, MessageReceivers, MessageSender},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    access::AccessReader,
+    door::DoorState,
+    lights::{LightBehavior, LightOverride, LightState, OverrideSource},
+};
+
+#[cfg(feature = "client")]
+use {
+    super::{close_machine_window, MachineClosedMessage},
+    crate::{ui::has_window, GameState},
+    bevy_egui::{egui, EguiContexts},
+};
+
+use super::{MachineViewers, SendMachineUpdates};
+
+/// Lets engineering turn the power of an area on and off.
+pub(super) struct ApcPlugin;
+
+impl Plugin for ApcPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Apc>()
+            .register_type::<PowerConsumer>()
+            .add_network_message::<ApcMessage>("ApcMessage")
+            .add_network_message::<SetAreaPowerRequest>("SetAreaPowerRequest");
+
+        if is_server(app) {
+            app.init_resource::<UnpoweredAreas>().add_systems(
+                Update,
+                (
+                    send_apc_state.in_set(SendMachineUpdates),
+                    handle_set_power_requests.before(SendMachineUpdates),
+                    apply_area_power,
+                ),
+            );
+        } else {
+            #[cfg(feature = "client")]
+            app.init_resource::<ClientApc>().add_systems(
+                Update,
+                (receive_apc_state, apc_ui.run_if(has_window))
+                    .chain()
+                    .run_if(in_state(GameState::Game)),
+            );
+        }
+    }
+}
+
+/// Controls the power of the area it is in.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct Apc;
+
+/// Machines that stop working when the power of their area is turned off.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct PowerConsumer;
+
+/// Names of areas with their power turned off.
+#[derive(Resource, Default)]
+pub struct UnpoweredAreas(pub HashSet<String>);
+
+impl UnpoweredAreas {
+    /// If something at the position has power. Positions outside of any area always do.
+    pub fn is_powered(&self, map: Option<&TileMap>, position: Vec3) -> bool {
+        map.and_then(|map| map.area_at(position))
+            .map_or(true, |area| !self.0.contains(area))
+    }
+}
+
+/// Server message with the state of an APC.
+#[derive(Serialize, Deserialize, Clone)]
+struct ApcMessage {
+    machine: NetworkIdentity,
+    /// `None` if the APC isn't in an area
+    area: Option<String>,
+    powered: bool,
+    lights: usize,
+    doors: usize,
+}
+
+/// Client message to turn the power of an APC's area on or off.
+#[derive(Serialize, Deserialize)]
+struct SetAreaPowerRequest {
+    machine: NetworkIdentity,
+    powered: bool,
+}
+
+fn area_of<'a>(map: Option<&'a TileMap>, transform: &GlobalTransform) -> Option<&'a str> {
+    map.and_then(|map| map.area_at(transform.translation()))
+}
+
+fn send_apc_state(
+    viewers: Res<MachineViewers>,
+    apcs: Query<&GlobalTransform, With<Apc>>,
+    lights: Query<&GlobalTransform, With<LightState>>,
+    doors: Query<&GlobalTransform, With<DoorState>>,
+    unpowered: Res<UnpoweredAreas>,
+    maps: Query<&TileMap>,
+    mut sender: MessageSender,
+) {
+    // TODO: Support multiple maps
+    let map = maps.get_single().ok();
+    for viewer in viewers.due() {
+        let Ok(transform) = apcs.get(viewer.machine) else {
+            continue;
+        };
+        let area = area_of(map, transform);
+        let in_area = |t: &GlobalTransform| area.is_some() && area_of(map, t) == area;
+
+        sender.send(
+            &ApcMessage {
+                machine: viewer.identity,
+                area: area.map(str::to_owned),
+                powered: area.map_or(true, |a| !unpowered.0.contains(a)),
+                lights: lights.iter().filter(|t| in_area(t)).count(),
+                doors: doors.iter().filter(|t| in_area(t)).count(),
+            },
+            MessageReceivers::Single(viewer.connection),
+        );
+    }
+}
+
+fn handle_set_power_requests(
+    mut messages: EventReader<MessageEvent<SetAreaPowerRequest>>,
+    mut viewers: ResMut<MachineViewers>,
+    apcs: Query<&GlobalTransform, With<Apc>>,
+    maps: Query<&TileMap>,
+    access: AccessReader,
+    mut unpowered: ResMut<UnpoweredAreas>,
+) {
+    for event in messages.iter() {
+        let Some(viewer) = viewers.get(event.connection, event.message.machine) else {
+            continue;
+        };
+        if !access.can_access(viewer.creature, viewer.machine) {
+            continue;
+        }
+        let Ok(transform) = apcs.get(viewer.machine) else {
+            continue;
+        };
+        let Some(area) = area_of(maps.get_single().ok(), transform) else {
+            continue;
+        };
+
+        let changed = if event.message.powered {
+            unpowered.0.remove(area)
+        } else {
+            unpowered.0.insert(area.to_owned())
+        };
+        if changed {
+            info!(connection = ?event.connection, area, powered = event.message.powered, "Area power changed");
+            // Other APCs could be in the same area
+            viewers.refresh_all();
+        }
+    }
+}
+
+/// Turns off lights and doors in areas without power.
+fn apply_area_power(
+    unpowered: Res<UnpoweredAreas>,
+    mut fixtures: Query<(&mut LightState, &GlobalTransform)>,
+    mut doors: Query<(&mut DoorState, &GlobalTransform)>,
+    new_fixtures: Query<(), Added<LightState>>,
+    new_doors: Query<(), Added<DoorState>>,
+    maps: Query<&TileMap>,
+) {
+    if !unpowered.is_changed() && new_fixtures.is_empty() && new_doors.is_empty() {
+        return;
+    }
+    // TODO: Support multiple maps
+    let Ok(map) = maps.get_single() else {
+        return;
+    };
+    let is_powered = |transform: &GlobalTransform| {
+        map.area_at(transform.translation())
+            .map_or(true, |area| !unpowered.0.contains(area))
+    };
+
+    for (mut state, transform) in fixtures.iter_mut() {
+        if is_powered(transform) {
+            state.remove_override(OverrideSource::NoPower);
+        } else {
+            state.push_override(LightOverride {
+                source: OverrideSource::NoPower,
+                behavior: LightBehavior::Off,
+            });
+        }
+    }
+
+    for (mut state, transform) in doors.iter_mut() {
+        let powered = is_powered(transform);
+        if state.area_powered != powered {
+            state.area_powered = powered;
+        }
+    }
+}
+
+#[cfg(feature = "client")]
+#[derive(Resource, Default)]
+struct ClientApc {
+    state: Option<ApcMessage>,
+}
+
+#[cfg(feature = "client")]
+fn receive_apc_state(
+    mut messages: EventReader<MessageEvent<ApcMessage>>,
+    mut closed: EventReader<MessageEvent<MachineClosedMessage>>,
+    mut apc: ResMut<ClientApc>,
+) {
+    for event in messages.iter() {
+        apc.state = Some(event.message.clone());
+    }
+    for event in closed.iter() {
+        if apc.state.as_ref().map(|s| s.machine) == Some(event.message.machine) {
+            apc.state = None;
+        }
+    }
+}
+
+#[cfg(feature = "client")]
+fn apc_ui(mut contexts: EguiContexts, mut apc: ResMut<ClientApc>, mut sender: MessageSender) {
+    let Some(state) = apc.state.as_ref() else {
+        return;
+    };
+    let machine = state.machine;
+
+    let mut open = true;
+    egui::Window::new("APC")
+        .open(&mut open)
+        .resizable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            let Some(area) = &state.area else {
+                ui.label("Not connected to an area");
+                return;
+            };
+            ui.heading(area);
+            ui.label(format!("Lights: {}", state.lights));
+            ui.label(format!("Doors: {}", state.doors));
+            ui.horizontal(|ui| {
+                ui.label(if state.powered {
+                    "Power: On"
+                } else {
+                    "Power: Off"
+                });
+                let text = if state.powered { "Turn off" } else { "Turn on" };
+                if ui.button(text).clicked() {
+                    sender.send_to_server(&SetAreaPowerRequest {
+                        machine,
+                        powered: !state.powered,
+                    });
+                }
+            });
+        });
+
+    if !open {
+        apc.state = None;
+        close_machine_window(machine, &mut sender);
+    }
+}
@@ -0,0 +1,398 @@
+use bevy::prelude::*;
+use networking::{
+    identity::NetworkIdentity,
+    is_server,
+    messaging::{AppExt, MessageEvent, MessageReceivers, MessageSender},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    access::AccessReader,
+    communication::{AnnouncementAudience, AnnouncementEvent, SpeechName},
+    feedback::{Feedback, FeedbackKind},
+    job::Affiliation,
+    text_filter::{PlayerText, TextContext},
+};
+
+#[cfg(feature = "client")]
+use {
+    super::{close_machine_window, MachineClosedMessage},
+    crate::{ui::has_window, GameState},
+    bevy_egui::{egui, EguiContexts},
+};
+
+use super::{MachineViewers, SendMachineUpdates};
+
+/// Lets command staff announce things to the whole station or their department.
+pub(super) struct CommandConsolePlugin;
+
+impl Plugin for CommandConsolePlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<CommandConsole>()
+            .add_network_message::<CommandConsoleMessage>("CommandConsoleMessage")
+            .add_network_message_with_limit::<AnnounceRequest>(
+                "AnnounceRequest",
+                MAX_ANNOUNCE_MESSAGE_SIZE,
+            );
+
+        if is_server(app) {
+            app.add_systems(
+                Update,
+                (
+                    handle_announce_requests.before(SendMachineUpdates),
+                    send_command_console_state.in_set(SendMachineUpdates),
+                ),
+            );
+        } else {
+            #[cfg(feature = "client")]
+            app.init_resource::<ClientCommandConsole>().add_systems(
+                Update,
+                (
+                    receive_command_console_state,
+                    command_console_ui.run_if(has_window),
+                )
+                    .chain()
+                    .run_if(in_state(GameState::Game)),
+            );
+        }
+    }
+}
+
+/// Longest announcement in characters
+pub const MAX_ANNOUNCEMENT_LENGTH: usize = 300;
+/// Largest serialized announcement request a client can send
+const MAX_ANNOUNCE_MESSAGE_SIZE: u64 = 2048;
+/// Seconds a console has to wait between announcements
+const ANNOUNCEMENT_COOLDOWN: f32 = 60.0;
+
+/// A console that sends announcements typed by its user.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct CommandConsole;
+
+/// When a command console can announce again, in seconds since startup.
+#[derive(Component)]
+struct AnnouncementCooldown {
+    ready_at: f32,
+}
+
+/// Server message with the state of a command console.
+#[derive(Serialize, Deserialize)]
+struct CommandConsoleMessage {
+    machine: NetworkIdentity,
+    /// The department of the user, if they have one to announce to
+    department: Option<String>,
+    /// Seconds until the console can announce again
+    ready_in: f32,
+}
+
+/// Client message to announce something.
+#[derive(Serialize, Deserialize)]
+struct AnnounceRequest {
+    machine: NetworkIdentity,
+    text: String,
+    /// Only the department of the user hears it
+    department_only: bool,
+}
+
+fn send_command_console_state(
+    viewers: Res<MachineViewers>,
+    consoles: Query<Option<&AnnouncementCooldown>, With<CommandConsole>>,
+    affiliations: Query<&Affiliation>,
+    time: Res<Time>,
+    mut sender: MessageSender,
+) {
+    let now = time.elapsed_seconds();
+    for viewer in viewers.due() {
+        let Ok(cooldown) = consoles.get(viewer.machine) else {
+            continue;
+        };
+        sender.send(
+            &CommandConsoleMessage {
+                machine: viewer.identity,
+                department: affiliations
+                    .get(viewer.creature)
+                    .ok()
+                    .map(|a| a.department.clone()),
+                ready_in: cooldown.map_or(0.0, |c| (c.ready_at - now).max(0.0)),
+            },
+            MessageReceivers::Single(viewer.connection),
+        );
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn handle_announce_requests(
+    mut messages: EventReader<MessageEvent<AnnounceRequest>>,
+    mut viewers: ResMut<MachineViewers>,
+    consoles: Query<Option<&AnnouncementCooldown>, With<CommandConsole>>,
+    creatures: Query<(Option<&SpeechName>, Option<&Affiliation>)>,
+    access: AccessReader,
+    time: Res<Time>,
+    mut player_text: PlayerText,
+    mut feedback: Feedback,
+    mut announcements: EventWriter<AnnouncementEvent>,
+    mut commands: Commands,
+) {
+    let now = time.elapsed_seconds();
+    for event in messages.iter() {
+        let Some(viewer) = viewers.get(event.connection, event.message.machine) else {
+            continue;
+        };
+        let (machine, creature) = (viewer.machine, viewer.creature);
+        let Ok(cooldown) = consoles.get(machine) else {
+            continue;
+        };
+        if !access.can_access(creature, machine) {
+            continue;
+        }
+        if let Some(cooldown) = cooldown.filter(|c| c.ready_at > now) {
+            let seconds = format!("{:.0}", (cooldown.ready_at - now).ceil());
+            feedback.send(
+                event.connection,
+                FeedbackKind::Blocked,
+                "announcement.cooldown",
+                &[&seconds],
+            );
+            continue;
+        }
+
+        let Ok((name, affiliation)) = creatures.get(creature) else {
+            continue;
+        };
+        let audience = if event.message.department_only {
+            let Some(affiliation) = affiliation else {
+                feedback.send(
+                    event.connection,
+                    FeedbackKind::Blocked,
+                    "announcement.no_department",
+                    &[],
+                );
+                continue;
+            };
+            AnnouncementAudience::Department(affiliation.department.clone())
+        } else {
+            AnnouncementAudience::Everyone
+        };
+
+        let Some(text) = player_text.accept(
+            event.connection,
+            TextContext::Announcement,
+            &event.message.text,
+        ) else {
+            continue;
+        };
+        if text.is_empty() {
+            continue;
+        }
+
+        let name = name.map_or("Unknown", |n| n.0.as_str());
+        info!(connection = ?event.connection, console = ?machine, name, text = text.as_str(), "Console announcement");
+        announcements.send(AnnouncementEvent::new(
+            "announcement.console",
+            &[name, &text],
+            audience,
+        ));
+        commands.entity(machine).insert(AnnouncementCooldown {
+            ready_at: now + ANNOUNCEMENT_COOLDOWN,
+        });
+        viewers.refresh(machine);
+    }
+}
+
+#[cfg(feature = "client")]
+#[derive(Resource, Default)]
+struct ClientCommandConsole {
+    machine: Option<NetworkIdentity>,
+    department: Option<String>,
+    /// When the console can announce again, in client time
+    ready_at: f32,
+    text: String,
+    department_only: bool,
+}
+
+#[cfg(feature = "client")]
+fn receive_command_console_state(
+    mut messages: EventReader<MessageEvent<CommandConsoleMessage>>,
+    mut closed: EventReader<MessageEvent<MachineClosedMessage>>,
+    mut console: ResMut<ClientCommandConsole>,
+    time: Res<Time>,
+) {
+    for event in messages.iter() {
+        if console.machine != Some(event.message.machine) {
+            console.text.clear();
+            console.department_only = false;
+        }
+        console.machine = Some(event.message.machine);
+        console.department = event.message.department.clone();
+        console.ready_at = time.elapsed_seconds() + event.message.ready_in;
+    }
+    for event in closed.iter() {
+        if console.machine == Some(event.message.machine) {
+            console.machine = None;
+        }
+    }
+}
+
+#[cfg(feature = "client")]
+fn command_console_ui(
+    mut contexts: EguiContexts,
+    mut console: ResMut<ClientCommandConsole>,
+    time: Res<Time>,
+    mut sender: MessageSender,
+) {
+    let Some(machine) = console.machine else {
+        return;
+    };
+
+    let console = console.as_mut();
+    let mut open = true;
+    egui::Window::new("Command Console")
+        .open(&mut open)
+        .resizable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut console.text)
+                    .hint_text("Announcement")
+                    .char_limit(MAX_ANNOUNCEMENT_LENGTH),
+            );
+            match &console.department {
+                Some(department) => {
+                    ui.checkbox(
+                        &mut console.department_only,
+                        format!("Only to {}", department),
+                    );
+                }
+                None => console.department_only = false,
+            }
+
+            let ready_in = console.ready_at - time.elapsed_seconds();
+            if ready_in > 0.0 {
+                ui.label(format!("Ready again in {:.0} seconds", ready_in.ceil()));
+            }
+            let can_send = ready_in <= 0.0 && !console.text.trim().is_empty();
+            if ui
+                .add_enabled(can_send, egui::Button::new("Announce"))
+                .clicked()
+            {
+                sender.send_to_server(&AnnounceRequest {
+                    machine,
+                    text: std::mem::take(&mut console.text),
+                    department_only: console.department_only,
+                });
+            }
+        });
+
+    if !open {
+        console.machine = None;
+        close_machine_window(machine, &mut sender);
+    }
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::{
+        ecs::{event::ManualEventReader, system::Command},
+        time::TimeUpdateStrategy,
+    };
+    use networking::{
+        identity::NetworkCommand, loopback::LinkConditions, spawning::ClientControls, testing,
+        NetworkRole, Players,
+    };
+
+    use super::*;
+    use crate::{config::ServerConfig, machines::MachineViewer, testing::server_app};
+
+    const FRAME: Duration = Duration::from_millis(100);
+
+    /// A server with a player standing at a command console they have open.
+    fn setup() -> (App, App, NetworkIdentity) {
+        let mut server = server_app(ServerConfig::default());
+        let mut client = testing::app(NetworkRole::Client);
+        let connector = testing::listen(&mut server);
+        testing::join(&mut client, &connector, LinkConditions::default());
+        testing::connect(&mut server, &mut [&mut client], 200);
+        server.insert_resource(TimeUpdateStrategy::ManualDuration(FRAME));
+
+        let console = server
+            .world
+            .spawn((CommandConsole, SpatialBundle::default()))
+            .id();
+        NetworkCommand { entity: console }.apply(&mut server.world);
+        let creature = server
+            .world
+            .spawn((SpeechName("Captain".into()), SpatialBundle::default()))
+            .id();
+        let (&connection, player) = server
+            .world
+            .resource::<Players>()
+            .players()
+            .iter()
+            .next()
+            .unwrap();
+        let player = player.id;
+        server
+            .world
+            .resource_mut::<ClientControls>()
+            .give_control(player, creature);
+
+        let identity = *server.world.get::<NetworkIdentity>(console).unwrap();
+        server
+            .world
+            .resource_mut::<MachineViewers>()
+            .open(MachineViewer::new(connection, creature, console, identity));
+        testing::update(&mut server, &mut [&mut client], 1);
+        (server, client, identity)
+    }
+
+    /// Asks to announce from the console and counts the console announcements sent.
+    fn announce(server: &mut App, client: &mut App, machine: NetworkIdentity) -> usize {
+        let connection = *server
+            .world
+            .resource::<Players>()
+            .players()
+            .keys()
+            .next()
+            .unwrap();
+        server.world.send_event(MessageEvent {
+            message: AnnounceRequest {
+                machine,
+                text: "Meeting on the bridge".into(),
+                department_only: false,
+            },
+            connection,
+        });
+        let mut reader = ManualEventReader::<AnnouncementEvent>::default();
+        // Skips the announcements of earlier calls
+        reader
+            .iter(server.world.resource::<Events<AnnouncementEvent>>())
+            .count();
+        testing::update(server, &mut [&mut *client], 1);
+        reader
+            .iter(server.world.resource::<Events<AnnouncementEvent>>())
+            .filter(|event| event.text_key == "announcement.console")
+            .count()
+    }
+
+    #[test]
+    fn console_announces() {
+        let (mut server, mut client, console) = setup();
+        assert_eq!(announce(&mut server, &mut client, console), 1);
+    }
+
+    #[test]
+    fn console_waits_between_announcements() {
+        let (mut server, mut client, console) = setup();
+        assert_eq!(announce(&mut server, &mut client, console), 1);
+        assert_eq!(announce(&mut server, &mut client, console), 0);
+
+        server.insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f32(
+            ANNOUNCEMENT_COOLDOWN,
+        )));
+        testing::update(&mut server, &mut [&mut client], 1);
+        server.insert_resource(TimeUpdateStrategy::ManualDuration(FRAME));
+        assert_eq!(announce(&mut server, &mut client, console), 1);
+    }
+}
@@ -0,0 +1,536 @@
+use bevy::prelude::*;
+use networking::{
+    identity::NetworkIdentity,
+    is_server,
+    messaging::{AppExt, MessageEvent, MessageReceivers, MessageSender},
+};
+use serde::{Deserialize, Serialize};
+use utils::task::{TaskId, Tasks};
+
+use crate::{
+    access::{AccessGrants, IdCard},
+    body::HeldItem,
+    interaction::{
+        ActiveInteraction, GenerateInteractionList, InteractionListEvents, InteractionOption,
+        InteractionSpecificity, InteractionStatus, Reach,
+    },
+    items::{
+        containers::{Container, MoveItem},
+        Item,
+    },
+};
+
+#[cfg(feature = "client")]
+use {
+    super::{close_machine_window, MachineClosedMessage},
+    crate::{ui::has_window, GameState},
+    bevy_egui::{egui, EguiContexts},
+};
+
+use super::{MachineViewers, SendMachineUpdates};
+
+/// Lets heads of staff change the accesses and job title of ID cards.
+pub(super) struct IdCardConsolePlugin;
+
+impl Plugin for IdCardConsolePlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<IdCardConsole>()
+            .register_type::<IdCardSlot>()
+            .add_network_message::<IdCardConsoleMessage>("IdCardConsoleMessage")
+            .add_network_message::<EjectCardRequest>("EjectCardRequest")
+            .add_network_message::<SetCardAccessRequest>("SetCardAccessRequest")
+            .add_network_message_with_limit::<SetCardTitleRequest>(
+                "SetCardTitleRequest",
+                MAX_TITLE_MESSAGE_SIZE,
+            );
+
+        if is_server(app) {
+            app.register_type::<InsertCardInteraction>().add_systems(
+                Update,
+                (
+                    prepare_insert_card_interaction.in_set(GenerateInteractionList),
+                    insert_card_interaction,
+                    refresh_changed_slots.before(SendMachineUpdates),
+                    (handle_eject_requests, handle_edit_requests).before(SendMachineUpdates),
+                    send_id_console_state.in_set(SendMachineUpdates),
+                ),
+            );
+        } else {
+            #[cfg(feature = "client")]
+            app.init_resource::<ClientIdCardConsole>().add_systems(
+                Update,
+                (receive_id_console_state, id_console_ui.run_if(has_window))
+                    .chain()
+                    .run_if(in_state(GameState::Game)),
+            );
+        }
+    }
+}
+
+/// Longest job title that can be written on a card
+const MAX_TITLE_LENGTH: usize = 32;
+const MAX_TITLE_MESSAGE_SIZE: u64 = 256;
+
+/// A console with slots for two ID cards, where the first one authorizes changes to the second.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct IdCardConsole;
+
+/// A single card container on an ID card console, as a child of the console.
+#[derive(
+    Component, Reflect, Default, Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize,
+)]
+#[reflect(Component)]
+pub enum IdCardSlot {
+    /// The card of whoever is making changes
+    #[default]
+    Authorizer,
+    /// The card being changed
+    Target,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct CardInfo {
+    name: String,
+    title: String,
+    accesses: Vec<String>,
+}
+
+/// Server message with the cards in an ID card console.
+#[derive(Serialize, Deserialize, Clone)]
+struct IdCardConsoleMessage {
+    machine: NetworkIdentity,
+    authorizer: Option<CardInfo>,
+    target: Option<CardInfo>,
+    /// Accesses the authorizing card can give to the target
+    grantable: Vec<String>,
+}
+
+/// Client message to take a card out of a console.
+#[derive(Serialize, Deserialize)]
+struct EjectCardRequest {
+    machine: NetworkIdentity,
+    slot: IdCardSlot,
+}
+
+/// Client message to give or take an access from the target card.
+#[derive(Serialize, Deserialize)]
+struct SetCardAccessRequest {
+    machine: NetworkIdentity,
+    access: String,
+    enabled: bool,
+}
+
+/// Client message to change the job title of the target card.
+#[derive(Serialize, Deserialize)]
+struct SetCardTitleRequest {
+    machine: NetworkIdentity,
+    title: String,
+}
+
+/// The slots of a console and the cards in them
+struct ConsoleSlots {
+    authorizer: Option<(Entity, Option<Entity>)>,
+    target: Option<(Entity, Option<Entity>)>,
+}
+
+impl ConsoleSlots {
+    fn find(
+        console: Entity,
+        children: &Query<&Children>,
+        slots: &Query<(&IdCardSlot, &Container)>,
+    ) -> Self {
+        let mut found = Self {
+            authorizer: None,
+            target: None,
+        };
+        for &child in children
+            .get(console)
+            .map(|c| c.iter())
+            .into_iter()
+            .flatten()
+        {
+            let Ok((slot, container)) = slots.get(child) else {
+                continue;
+            };
+            let card = container.iter().next().map(|(_, &item)| item);
+            match slot {
+                IdCardSlot::Authorizer => found.authorizer = Some((child, card)),
+                IdCardSlot::Target => found.target = Some((child, card)),
+            }
+        }
+        found
+    }
+
+    fn slot(&self, slot: IdCardSlot) -> Option<(Entity, Option<Entity>)> {
+        match slot {
+            IdCardSlot::Authorizer => self.authorizer,
+            IdCardSlot::Target => self.target,
+        }
+    }
+
+    fn card(&self, slot: IdCardSlot) -> Option<Entity> {
+        self.slot(slot).and_then(|(_, card)| card)
+    }
+}
+
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+#[component(storage = "SparseSet")]
+struct InsertCardInteraction {
+    slot: Entity,
+    #[reflect(ignore)]
+    move_task: Option<TaskId<MoveItem>>,
+}
+
+// Dummy default for Reflect
+impl Default for InsertCardInteraction {
+    fn default() -> Self {
+        Self {
+            slot: Entity::from_raw(0),
+            move_task: None,
+        }
+    }
+}
+
+fn prepare_insert_card_interaction(
+    list: Res<InteractionListEvents>,
+    consoles: Query<(), With<IdCardConsole>>,
+    children: Query<&Children>,
+    slots: Query<(&IdCardSlot, &Container)>,
+    cards: Query<(), With<IdCard>>,
+    reach: Reach,
+) {
+    for event in list.events.iter() {
+        if !consoles.contains(event.target)
+            || !event.item_in_hand.is_some_and(|item| cards.contains(item))
+            || !reach.can_reach(event.source, event.target)
+        {
+            continue;
+        }
+
+        // The authorizing card goes in first
+        let console = ConsoleSlots::find(event.target, &children, &slots);
+        let (slot, text) = match (console.authorizer, console.target) {
+            (Some((slot, None)), _) => (slot, "Insert authorizing ID"),
+            (Some((_, Some(_))), Some((slot, None))) => (slot, "Insert ID to modify"),
+            _ => continue,
+        };
+
+        event.add_interaction(InteractionOption {
+            text: text.into(),
+            interaction: Box::new(InsertCardInteraction {
+                slot,
+                move_task: None,
+            }),
+            specificity: InteractionSpecificity::Specific,
+        });
+    }
+}
+
+fn insert_card_interaction(
+    mut query: Query<(Entity, &mut InsertCardInteraction, &mut ActiveInteraction)>,
+    slots: Query<&Container, With<IdCardSlot>>,
+    cards: Query<(), With<IdCard>>,
+    held_item: HeldItem,
+    mut item_moves: ResMut<Tasks<MoveItem>>,
+) {
+    for (source, mut interaction, mut active) in query.iter_mut() {
+        let Some(task) = interaction.move_task else {
+            let (Ok(slot), Some(card)) = (slots.get(interaction.slot), held_item.get(source))
+            else {
+                active.status = InteractionStatus::Canceled;
+                continue;
+            };
+            // Someone else could have filled the slot in the meantime
+            if !slot.is_empty() || !cards.contains(card) {
+                active.status = InteractionStatus::Canceled;
+                continue;
+            }
+            interaction.move_task = Some(item_moves.create(MoveItem {
+                item: card,
+                container: Some(interaction.slot),
+                position: None,
+            }));
+            continue;
+        };
+
+        let Some(result) = item_moves.result(task) else {
+            continue;
+        };
+        active.status = if result.was_success() {
+            InteractionStatus::Completed
+        } else {
+            InteractionStatus::Canceled
+        };
+    }
+}
+
+/// Updates open windows when a card is inserted or taken out.
+fn refresh_changed_slots(
+    slots: Query<&Parent, (With<IdCardSlot>, Changed<Container>)>,
+    mut viewers: ResMut<MachineViewers>,
+) {
+    for parent in slots.iter() {
+        viewers.refresh(parent.get());
+    }
+}
+
+fn card_info(card: Option<Entity>, cards: &Query<(&IdCard, &Item)>) -> Option<CardInfo> {
+    let (id, item) = cards.get(card?).ok()?;
+    Some(CardInfo {
+        name: item.name.clone(),
+        title: id.title.clone(),
+        accesses: id.accesses.clone(),
+    })
+}
+
+fn send_id_console_state(
+    viewers: Res<MachineViewers>,
+    consoles: Query<(), With<IdCardConsole>>,
+    children: Query<&Children>,
+    slots: Query<(&IdCardSlot, &Container)>,
+    cards: Query<(&IdCard, &Item)>,
+    grants: Res<AccessGrants>,
+    mut sender: MessageSender,
+) {
+    for viewer in viewers.due().filter(|v| consoles.contains(v.machine)) {
+        let console = ConsoleSlots::find(viewer.machine, &children, &slots);
+        let authorizer = card_info(console.card(IdCardSlot::Authorizer), &cards);
+        let target = card_info(console.card(IdCardSlot::Target), &cards);
+        let grantable = authorizer
+            .as_ref()
+            .map(|card| grants.grantable(card.accesses.iter()))
+            .unwrap_or_default();
+
+        sender.send(
+            &IdCardConsoleMessage {
+                machine: viewer.identity,
+                authorizer,
+                target,
+                grantable,
+            },
+            MessageReceivers::Single(viewer.connection),
+        );
+    }
+}
+
+fn handle_eject_requests(
+    mut messages: EventReader<MessageEvent<EjectCardRequest>>,
+    mut viewers: ResMut<MachineViewers>,
+    consoles: Query<(), With<IdCardConsole>>,
+    children: Query<&Children>,
+    slots: Query<(&IdCardSlot, &Container)>,
+    mut item_moves: ResMut<Tasks<MoveItem>>,
+    mut sender: MessageSender,
+) {
+    for event in messages.iter() {
+        let Some(machine) = viewers
+            .get(event.connection, event.message.machine)
+            .map(|v| v.machine)
+            .filter(|&m| consoles.contains(m))
+        else {
+            continue;
+        };
+        let console = ConsoleSlots::find(machine, &children, &slots);
+        let Some(card) = console.card(event.message.slot) else {
+            continue;
+        };
+
+        // The card drops out in front of the console
+        item_moves.create_ignore(MoveItem {
+            item: card,
+            container: None,
+            position: None,
+        });
+        info!(connection = ?event.connection, console = ?machine, slot = ?event.message.slot, "Ejected ID card");
+        viewers.close(machine, &mut sender);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn handle_edit_requests(
+    mut access_requests: EventReader<MessageEvent<SetCardAccessRequest>>,
+    mut title_requests: EventReader<MessageEvent<SetCardTitleRequest>>,
+    mut viewers: ResMut<MachineViewers>,
+    consoles: Query<(), With<IdCardConsole>>,
+    children: Query<&Children>,
+    slots: Query<(&IdCardSlot, &Container)>,
+    mut cards: Query<&mut IdCard>,
+    grants: Res<AccessGrants>,
+) {
+    // Finds the authorizing and target cards of the console the connection is using
+    let mut session = |connection, machine| {
+        let machine = viewers
+            .get(connection, machine)
+            .map(|v| v.machine)
+            .filter(|&m| consoles.contains(m))?;
+        let console = ConsoleSlots::find(machine, &children, &slots);
+        let authorizer = console.card(IdCardSlot::Authorizer)?;
+        let target = console.card(IdCardSlot::Target)?;
+        // Cards can't authorize changes to themselves
+        (authorizer != target).then_some((machine, authorizer, target))
+    };
+
+    let mut changed = Vec::new();
+    for event in access_requests.iter() {
+        let Some((machine, authorizer, target)) = session(event.connection, event.message.machine)
+        else {
+            continue;
+        };
+        let Ok(authorizer_card) = cards.get(authorizer) else {
+            continue;
+        };
+        let access = &event.message.access;
+        if !grants
+            .grantable(authorizer_card.accesses.iter())
+            .contains(access)
+        {
+            debug!(connection = ?event.connection, access, "Access can't be granted by this card");
+            continue;
+        }
+
+        let Ok(mut card) = cards.get_mut(target) else {
+            continue;
+        };
+        let has_access = card.accesses.contains(access);
+        if event.message.enabled && !has_access {
+            card.accesses.push(access.clone());
+        } else if !event.message.enabled && has_access {
+            card.accesses.retain(|a| a != access);
+        } else {
+            continue;
+        }
+        info!(connection = ?event.connection, card = ?target, access, enabled = event.message.enabled, "ID card access changed");
+        changed.push(machine);
+    }
+
+    for event in title_requests.iter() {
+        let Some((machine, _, target)) = session(event.connection, event.message.machine) else {
+            continue;
+        };
+        let title = event.message.title.trim();
+        if title.chars().count() > MAX_TITLE_LENGTH {
+            continue;
+        }
+        let Ok(mut card) = cards.get_mut(target) else {
+            continue;
+        };
+        if card.title == title {
+            continue;
+        }
+        card.title = title.to_owned();
+        info!(connection = ?event.connection, card = ?target, title, "ID card title changed");
+        changed.push(machine);
+    }
+
+    for machine in changed {
+        viewers.refresh(machine);
+    }
+}
+
+#[cfg(feature = "client")]
+#[derive(Resource, Default)]
+struct ClientIdCardConsole {
+    state: Option<IdCardConsoleMessage>,
+    /// The title being typed, sent when the field loses focus
+    title: String,
+}
+
+#[cfg(feature = "client")]
+fn receive_id_console_state(
+    mut messages: EventReader<MessageEvent<IdCardConsoleMessage>>,
+    mut closed: EventReader<MessageEvent<MachineClosedMessage>>,
+    mut console: ResMut<ClientIdCardConsole>,
+) {
+    for event in messages.iter() {
+        console.title = event
+            .message
+            .target
+            .as_ref()
+            .map(|card| card.title.clone())
+            .unwrap_or_default();
+        console.state = Some(event.message.clone());
+    }
+    for event in closed.iter() {
+        if console.state.as_ref().map(|s| s.machine) == Some(event.message.machine) {
+            console.state = None;
+        }
+    }
+}
+
+#[cfg(feature = "client")]
+fn id_console_ui(
+    mut contexts: EguiContexts,
+    mut console: ResMut<ClientIdCardConsole>,
+    mut sender: MessageSender,
+) {
+    let console = &mut *console;
+    let Some(state) = console.state.as_ref() else {
+        return;
+    };
+    let machine = state.machine;
+
+    let mut open = true;
+    egui::Window::new("ID Card Console")
+        .open(&mut open)
+        .resizable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            let eject = |ui: &mut egui::Ui, sender: &mut MessageSender, slot| {
+                if ui.button("Eject").clicked() {
+                    sender.send_to_server(&EjectCardRequest { machine, slot });
+                }
+            };
+
+            let Some(authorizer) = &state.authorizer else {
+                ui.label("Insert an authorizing ID to begin");
+                return;
+            };
+            ui.horizontal(|ui| {
+                ui.label(format!("Authorized by: {}", authorizer.name));
+                eject(ui, &mut sender, IdCardSlot::Authorizer);
+            });
+            ui.separator();
+
+            let Some(target) = &state.target else {
+                ui.label("Insert the ID to modify");
+                return;
+            };
+            ui.horizontal(|ui| {
+                ui.label(&target.name);
+                eject(ui, &mut sender, IdCardSlot::Target);
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Job title:");
+                let response = ui.add(
+                    egui::TextEdit::singleline(&mut console.title).char_limit(MAX_TITLE_LENGTH),
+                );
+                if response.lost_focus() && console.title != target.title {
+                    sender.send_to_server(&SetCardTitleRequest {
+                        machine,
+                        title: console.title.clone(),
+                    });
+                }
+            });
+
+            if state.grantable.is_empty() {
+                ui.label("This ID can't grant any access");
+            }
+            for access in state.grantable.iter() {
+                let mut enabled = target.accesses.contains(access);
+                if ui.checkbox(&mut enabled, access).changed() {
+                    sender.send_to_server(&SetCardAccessRequest {
+                        machine,
+                        access: access.clone(),
+                        enabled,
+                    });
+                }
+            }
+        });
+
+    if !open {
+        console.state = None;
+        close_machine_window(machine, &mut sender);
+    }
+}
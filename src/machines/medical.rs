@@ -0,0 +1,246 @@
+use std::fmt::Write;
+
+use bevy::prelude::*;
+use networking::{
+    identity::{NetworkIdentities, NetworkIdentity},
+    is_server,
+    messaging::{AppExt, MessageEvent, MessageReceivers, MessageSender},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    access::AccessReader,
+    body::{health::Vitals, Body},
+    communication::{SpeechName, SystemMessageEvent},
+};
+
+#[cfg(feature = "client")]
+use {
+    super::{close_machine_window, MachineClosedMessage},
+    crate::{ui::has_window, GameState},
+    bevy_egui::{egui, EguiContexts},
+};
+
+use super::{MachineViewers, SendMachineUpdates};
+
+/// Lets medical staff get a detailed report of a patient next to the scanner.
+pub(super) struct MedicalScannerPlugin;
+
+impl Plugin for MedicalScannerPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<MedicalScanner>()
+            .add_network_message::<MedicalScannerMessage>("MedicalScannerMessage")
+            .add_network_message::<ScanPatientRequest>("ScanPatientRequest");
+
+        if is_server(app) {
+            app.add_systems(
+                Update,
+                (
+                    send_scanner_patients.in_set(SendMachineUpdates),
+                    handle_scan_requests,
+                ),
+            );
+        } else {
+            #[cfg(feature = "client")]
+            app.init_resource::<ClientMedicalScanner>().add_systems(
+                Update,
+                (
+                    receive_scanner_patients,
+                    medical_scanner_ui.run_if(has_window),
+                )
+                    .chain()
+                    .run_if(in_state(GameState::Game)),
+            );
+        }
+    }
+}
+
+/// How far a body can be from the scanner to be scanned
+const SCAN_RANGE: f32 = 1.5;
+
+/// A machine that scans the bodies next to it.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct MedicalScanner;
+
+#[derive(Serialize, Deserialize, Clone)]
+struct Patient {
+    body: NetworkIdentity,
+    name: String,
+}
+
+/// Server message with the bodies a medical scanner can scan.
+#[derive(Serialize, Deserialize)]
+struct MedicalScannerMessage {
+    machine: NetworkIdentity,
+    patients: Vec<Patient>,
+}
+
+/// Client message to print the report of a patient.
+#[derive(Serialize, Deserialize)]
+struct ScanPatientRequest {
+    machine: NetworkIdentity,
+    body: NetworkIdentity,
+}
+
+type Bodies<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static NetworkIdentity,
+        &'static GlobalTransform,
+        Option<&'static SpeechName>,
+    ),
+    With<Body>,
+>;
+
+/// Bodies close enough to a scanner, with their names
+fn adjacent_bodies(scanner: &GlobalTransform, bodies: &Bodies) -> Vec<(Entity, Patient)> {
+    bodies
+        .iter()
+        .filter(|(_, _, transform, _)| {
+            transform.translation().distance(scanner.translation()) <= SCAN_RANGE
+        })
+        .map(|(entity, &body, _, name)| {
+            let name = name
+                .map(|n| n.0.clone())
+                .unwrap_or_else(|| "Unknown".to_owned());
+            (entity, Patient { body, name })
+        })
+        .collect()
+}
+
+fn send_scanner_patients(
+    viewers: Res<MachineViewers>,
+    scanners: Query<&GlobalTransform, With<MedicalScanner>>,
+    bodies: Bodies,
+    mut sender: MessageSender,
+) {
+    for viewer in viewers.due() {
+        let Ok(scanner) = scanners.get(viewer.machine) else {
+            continue;
+        };
+
+        let patients = adjacent_bodies(scanner, &bodies)
+            .into_iter()
+            .map(|(_, patient)| patient)
+            .collect();
+        sender.send(
+            &MedicalScannerMessage {
+                machine: viewer.identity,
+                patients,
+            },
+            MessageReceivers::Single(viewer.connection),
+        );
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn handle_scan_requests(
+    mut messages: EventReader<MessageEvent<ScanPatientRequest>>,
+    viewers: Res<MachineViewers>,
+    scanners: Query<&GlobalTransform, With<MedicalScanner>>,
+    bodies: Bodies,
+    identities: Res<NetworkIdentities>,
+    vitals: Vitals,
+    access: AccessReader,
+    mut system_messages: EventWriter<SystemMessageEvent>,
+) {
+    for event in messages.iter() {
+        let Some(viewer) = viewers.get(event.connection, event.message.machine) else {
+            continue;
+        };
+        if !access.can_access(viewer.creature, viewer.machine) {
+            continue;
+        }
+        let (Ok(scanner), Some(patient_entity)) = (
+            scanners.get(viewer.machine),
+            identities.get_entity(event.message.body),
+        ) else {
+            continue;
+        };
+        let Some((body, patient)) = adjacent_bodies(scanner, &bodies)
+            .into_iter()
+            .find(|(entity, _)| *entity == patient_entity)
+        else {
+            continue;
+        };
+        let (Some(status), Some(limbs)) = (vitals.status(body), vitals.limbs(body)) else {
+            continue;
+        };
+
+        let mut text = format!("Scan of {}: {}", patient.name, status);
+        for limb in limbs {
+            let _ = write!(text, "\n{}: {:.0}%", limb.name, limb.integrity * 100.0);
+            if limb.wounds > 0 {
+                let _ = write!(text, ", {} wound(s)", limb.wounds);
+            }
+        }
+        system_messages.send(SystemMessageEvent {
+            receiver: event.connection,
+            text,
+        });
+    }
+}
+
+#[cfg(feature = "client")]
+#[derive(Resource, Default)]
+struct ClientMedicalScanner {
+    machine: Option<NetworkIdentity>,
+    patients: Vec<Patient>,
+}
+
+#[cfg(feature = "client")]
+fn receive_scanner_patients(
+    mut messages: EventReader<MessageEvent<MedicalScannerMessage>>,
+    mut closed: EventReader<MessageEvent<MachineClosedMessage>>,
+    mut scanner: ResMut<ClientMedicalScanner>,
+) {
+    for event in messages.iter() {
+        scanner.machine = Some(event.message.machine);
+        scanner.patients = event.message.patients.clone();
+    }
+    for event in closed.iter() {
+        if scanner.machine == Some(event.message.machine) {
+            scanner.machine = None;
+        }
+    }
+}
+
+#[cfg(feature = "client")]
+fn medical_scanner_ui(
+    mut contexts: EguiContexts,
+    mut scanner: ResMut<ClientMedicalScanner>,
+    mut sender: MessageSender,
+) {
+    let Some(machine) = scanner.machine else {
+        return;
+    };
+
+    let mut open = true;
+    egui::Window::new("Medical Scanner")
+        .open(&mut open)
+        .resizable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            if scanner.patients.is_empty() {
+                ui.label("No patient next to the scanner");
+            }
+            for patient in scanner.patients.iter() {
+                ui.horizontal(|ui| {
+                    ui.label(&patient.name);
+                    if ui.button("Scan").clicked() {
+                        sender.send_to_server(&ScanPatientRequest {
+                            machine,
+                            body: patient.body,
+                        });
+                    }
+                });
+            }
+        });
+
+    if !open {
+        scanner.machine = None;
+        close_machine_window(machine, &mut sender);
+    }
+}
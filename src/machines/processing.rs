@@ -0,0 +1,937 @@
+use bevy::{
+    prelude::*,
+    reflect::{TypePath, TypeUuid},
+    utils::HashSet,
+};
+use bevy_common_assets::ron::RonAssetPlugin;
+use maps::TileMap;
+use networking::{
+    component::AppExt as ComponentAppExt,
+    identity::NetworkIdentity,
+    is_server,
+    messaging::{AppExt, MessageEvent, MessageReceivers, MessageSender},
+    scene::NetworkSceneBundle,
+    variable::{NetworkVar, ServerVar},
+    Networked,
+};
+use serde::{Deserialize, Serialize};
+use utils::task::{TaskId, Tasks};
+
+use crate::{
+    feedback::{Feedback, FeedbackKind},
+    interaction::{
+        ActiveInteraction, GenerateInteractionList, InteractionListEvents, InteractionOption,
+        InteractionSpecificity, InteractionStatus, Reach,
+    },
+    items::{
+        containers::{Container, MoveItem},
+        Item,
+    },
+};
+
+#[cfg(feature = "client")]
+use {
+    super::{close_machine_window, MachineClosedMessage},
+    crate::{ui::has_window, GameState},
+    bevy_egui::{egui, EguiContexts},
+    networking::identity::NetworkIdentities,
+};
+
+use super::{
+    apc::{PowerConsumer, UnpoweredAreas},
+    Machine, MachineViewers, SendMachineUpdates,
+};
+
+/// Machines that turn the items put into them into something else, like microwaves and autolathes.
+pub(super) struct ProcessingPlugin;
+
+impl Plugin for ProcessingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(RonAssetPlugin::<RecipeBook>::new(&["recipes.ron"]))
+            .register_type::<ProcessingMachine>()
+            .register_type::<ProcessingInput>()
+            .register_type::<InputFilter>()
+            .register_type::<OutputTarget>()
+            .register_type::<Ingredient>()
+            .register_type::<MaterialStack>()
+            .add_networked_component::<ProcessingState, ProcessingStateClient>()
+            .add_network_message::<ProcessingMachineMessage>("ProcessingMachineMessage")
+            .add_network_message::<SelectRecipeRequest>("SelectRecipeRequest")
+            .add_network_message::<EjectContentsRequest>("EjectContentsRequest");
+
+        if is_server(app) {
+            app.register_type::<InsertInputInteraction>()
+                .register_type::<StartProcessingInteraction>()
+                .add_systems(Startup, load_recipes)
+                .add_systems(
+                    Update,
+                    (
+                        setup_processing_machines,
+                        prepare_insert_input_interaction.in_set(GenerateInteractionList),
+                        insert_input_interaction,
+                        prepare_start_interaction.in_set(GenerateInteractionList),
+                        start_interaction,
+                        run_processes,
+                        store_outputs,
+                        refresh_changed_inputs.before(SendMachineUpdates),
+                        (handle_select_requests, handle_eject_requests).before(SendMachineUpdates),
+                        send_processing_state.in_set(SendMachineUpdates),
+                    ),
+                );
+        } else {
+            #[cfg(feature = "client")]
+            app.init_resource::<ClientProcessingMachine>().add_systems(
+                Update,
+                (receive_processing_state, processing_ui.run_if(has_window))
+                    .chain()
+                    .run_if(in_state(GameState::Game)),
+            );
+        }
+    }
+}
+
+/// A machine that runs a timed process on the items in its input container and produces new items.
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct ProcessingMachine {
+    /// Id of the recipe book the machine uses
+    pub recipes: String,
+    /// Which items can be put into the machine
+    pub accepts: InputFilter,
+    /// Where produced items go
+    pub output: OutputTarget,
+    /// If the player picks what to make, instead of the first recipe that matches the inputs
+    pub select_recipe: bool,
+    #[reflect(ignore)]
+    selected: Option<String>,
+}
+
+impl FromWorld for ProcessingMachine {
+    fn from_world(_: &mut World) -> Self {
+        Self {
+            recipes: String::new(),
+            accepts: InputFilter::Ingredients,
+            output: OutputTarget::Floor,
+            select_recipe: false,
+            selected: None,
+        }
+    }
+}
+
+#[derive(Reflect, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum InputFilter {
+    Ingredients,
+    Materials,
+}
+
+#[derive(Reflect, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum OutputTarget {
+    /// Produced items are stored in the input container
+    Container,
+    /// Produced items are dropped in front of the machine
+    Floor,
+}
+
+/// The container child of a processing machine that holds its inputs.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct ProcessingInput;
+
+/// An item that recipes can use, like food.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct Ingredient {
+    pub kind: String,
+}
+
+/// A stack of material sheets that recipes take an amount from.
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct MaterialStack {
+    pub material: String,
+    pub amount: u32,
+}
+
+impl Default for MaterialStack {
+    fn default() -> Self {
+        Self {
+            material: String::new(),
+            amount: 1,
+        }
+    }
+}
+
+impl InputFilter {
+    fn accepts(&self, ingredient: Option<&Ingredient>, stack: Option<&MaterialStack>) -> bool {
+        match self {
+            InputFilter::Ingredients => ingredient.is_some(),
+            InputFilter::Materials => stack.is_some(),
+        }
+    }
+}
+
+/// The recipes of a type of machine, loaded from `assets/recipes`.
+#[derive(Deserialize, TypeUuid, TypePath)]
+#[uuid = "5b0f6c2e-7a41-4d8b-9e35-2c1d8f4a6b97"]
+pub struct RecipeBook {
+    pub id: String,
+    pub recipes: Vec<Recipe>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct Recipe {
+    pub id: String,
+    pub name: String,
+    pub inputs: Vec<RecipeInput>,
+    /// Prefab name of the produced item, in `assets/items`
+    pub output: String,
+    pub seconds: f32,
+}
+
+#[derive(Deserialize, Clone)]
+pub enum RecipeInput {
+    /// One item with an [`Ingredient`] of this kind
+    Ingredient(String),
+    /// An amount of material, taken from any stacks of it
+    Material { material: String, amount: u32 },
+}
+
+impl std::fmt::Display for RecipeInput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RecipeInput::Ingredient(kind) => write!(f, "{}", kind.replace('_', " ")),
+            RecipeInput::Material { material, amount } => write!(f, "{} {}", amount, material),
+        }
+    }
+}
+
+#[derive(Resource)]
+struct RecipeAssets {
+    // Used to keep recipe books loaded
+    #[allow(dead_code)]
+    books: Vec<Handle<RecipeBook>>,
+}
+
+fn load_recipes(mut commands: Commands, server: Res<AssetServer>) {
+    commands.insert_resource(RecipeAssets {
+        books: server
+            .load_folder("recipes")
+            .expect("assets/recipes is missing")
+            .into_iter()
+            .map(HandleUntyped::typed)
+            .collect(),
+    });
+}
+
+fn find_book<'a>(books: &'a Assets<RecipeBook>, id: &str) -> Option<&'a RecipeBook> {
+    books
+        .iter()
+        .map(|(_, book)| book)
+        .find(|book| book.id == id)
+}
+
+/// How much of an input item a recipe uses up.
+struct Consume {
+    item: Entity,
+    /// `None` uses up the whole item
+    amount: Option<u32>,
+}
+
+type Inputs<'w, 's> = Query<'w, 's, (Option<&'static Ingredient>, Option<&'static MaterialStack>)>;
+
+/// Which inputs a recipe would use up, if the container has everything it needs.
+fn match_recipe(recipe: &Recipe, container: &Container, inputs: &Inputs) -> Option<Vec<Consume>> {
+    let mut contents: Vec<Entity> = container.iter().map(|(_, &item)| item).collect();
+    // Same matches every time
+    contents.sort();
+
+    let mut used = HashSet::new();
+    let mut plan = Vec::new();
+    for input in recipe.inputs.iter() {
+        match input {
+            RecipeInput::Ingredient(kind) => {
+                let item = contents.iter().copied().find(|item| {
+                    !used.contains(item)
+                        && inputs
+                            .get(*item)
+                            .is_ok_and(|(i, _)| i.is_some_and(|i| &i.kind == kind))
+                })?;
+                used.insert(item);
+                plan.push(Consume { item, amount: None });
+            }
+            RecipeInput::Material { material, amount } => {
+                let mut missing = *amount;
+                for &item in contents.iter() {
+                    if missing == 0 {
+                        break;
+                    }
+                    if used.contains(&item) {
+                        continue;
+                    }
+                    let Ok((_, Some(stack))) = inputs.get(item) else {
+                        continue;
+                    };
+                    if &stack.material != material {
+                        continue;
+                    }
+                    used.insert(item);
+                    let taken = stack.amount.min(missing);
+                    missing -= taken;
+                    plan.push(Consume {
+                        item,
+                        amount: (taken < stack.amount).then_some(taken),
+                    });
+                }
+                if missing > 0 {
+                    return None;
+                }
+            }
+        }
+    }
+    Some(plan)
+}
+
+/// The input container of a machine.
+fn input_container(
+    machine: Entity,
+    children: &Query<&Children>,
+    containers: &Query<&Container, With<ProcessingInput>>,
+) -> Option<Entity> {
+    children
+        .get(machine)
+        .ok()?
+        .iter()
+        .copied()
+        .find(|&child| containers.contains(child))
+}
+
+/// Progress of the process a machine is running, visible to everyone near it.
+#[derive(Component, Networked)]
+#[networked(client = "ProcessingStateClient")]
+struct ProcessingState {
+    /// Between 0 and 1, `None` when the machine isn't running
+    progress: NetworkVar<Option<f32>>,
+    /// The process is waiting for power
+    paused: NetworkVar<bool>,
+}
+
+#[derive(Component, Default, TypeUuid, Networked)]
+#[uuid = "a3d27e58-96c1-4f0b-8d4e-1b7c5f92e630"]
+#[networked(server = "ProcessingState")]
+pub struct ProcessingStateClient {
+    progress: ServerVar<Option<f32>>,
+    paused: ServerVar<bool>,
+}
+
+/// How much progress has to be made before clients are updated
+const PROGRESS_STEP: f32 = 0.05;
+
+/// The process a machine is running. Its inputs can't be changed until it is done.
+#[derive(Component)]
+#[component(storage = "SparseSet")]
+struct ActiveProcess {
+    recipe: Recipe,
+    elapsed: f32,
+}
+
+fn setup_processing_machines(
+    machines: Query<Entity, Added<ProcessingMachine>>,
+    mut commands: Commands,
+) {
+    for entity in machines.iter() {
+        commands.entity(entity).insert(ProcessingState {
+            progress: None.into(),
+            paused: false.into(),
+        });
+    }
+}
+
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+#[component(storage = "SparseSet")]
+struct InsertInputInteraction {
+    item: Entity,
+    #[reflect(ignore)]
+    move_task: Option<TaskId<MoveItem>>,
+}
+
+// Dummy default for Reflect
+impl Default for InsertInputInteraction {
+    fn default() -> Self {
+        Self {
+            item: Entity::from_raw(0),
+            move_task: None,
+        }
+    }
+}
+
+fn prepare_insert_input_interaction(
+    list: Res<InteractionListEvents>,
+    machines: Query<(&ProcessingMachine, &Machine), Without<ActiveProcess>>,
+    inputs: Inputs,
+    reach: Reach,
+) {
+    for event in list.events.iter() {
+        let Ok((processing, machine)) = machines.get(event.target) else {
+            continue;
+        };
+        let Some(item) = event.item_in_hand else {
+            continue;
+        };
+        let Ok((ingredient, stack)) = inputs.get(item) else {
+            continue;
+        };
+        if !processing.accepts.accepts(ingredient, stack)
+            || !reach.can_reach(event.source, event.target)
+        {
+            continue;
+        }
+
+        event.add_interaction(InteractionOption {
+            text: format!("Put in {}", machine.name),
+            interaction: Box::new(InsertInputInteraction {
+                item,
+                move_task: None,
+            }),
+            specificity: InteractionSpecificity::Specific,
+        });
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn insert_input_interaction(
+    mut query: Query<(Entity, &mut InsertInputInteraction, &mut ActiveInteraction)>,
+    machines: Query<(), (With<ProcessingMachine>, Without<ActiveProcess>)>,
+    children: Query<&Children>,
+    containers: Query<&Container, With<ProcessingInput>>,
+    items: Query<&Item>,
+    mut item_moves: ResMut<Tasks<MoveItem>>,
+    mut feedback: Feedback,
+) {
+    for (source, mut interaction, mut active) in query.iter_mut() {
+        let Some(task) = interaction.move_task else {
+            // The machine could have been started in the meantime
+            let (true, Some(container_entity)) = (
+                machines.contains(active.target),
+                input_container(active.target, &children, &containers),
+            ) else {
+                active.status = InteractionStatus::Canceled;
+                continue;
+            };
+            let (Ok(container), Ok(item)) = (
+                containers.get(container_entity),
+                items.get(interaction.item),
+            ) else {
+                active.status = InteractionStatus::Canceled;
+                continue;
+            };
+            if let Err(failure) = container.accepts(&items, interaction.item, item) {
+                feedback.send_to_creature(source, FeedbackKind::WontFit, failure.text_key(), &[]);
+                active.status = InteractionStatus::Canceled;
+                continue;
+            }
+
+            interaction.move_task = Some(item_moves.create(MoveItem {
+                item: interaction.item,
+                container: Some(container_entity),
+                position: None,
+            }));
+            continue;
+        };
+
+        let Some(result) = item_moves.result(task) else {
+            continue;
+        };
+        if let Some(failure) = result.failure() {
+            feedback.send_to_creature(source, FeedbackKind::WontFit, failure.text_key(), &[]);
+        }
+        active.status = if result.was_success() {
+            InteractionStatus::Completed
+        } else {
+            InteractionStatus::Canceled
+        };
+    }
+}
+
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+#[component(storage = "SparseSet")]
+struct StartProcessingInteraction;
+
+fn prepare_start_interaction(
+    list: Res<InteractionListEvents>,
+    machines: Query<&Machine, (With<ProcessingMachine>, Without<ActiveProcess>)>,
+    reach: Reach,
+) {
+    for event in list.events.iter() {
+        let Ok(machine) = machines.get(event.target) else {
+            continue;
+        };
+        if !reach.can_reach(event.source, event.target) {
+            continue;
+        }
+
+        event.add_interaction(InteractionOption {
+            text: format!("Start {}", machine.name),
+            interaction: Box::<StartProcessingInteraction>::default(),
+            specificity: InteractionSpecificity::Specific,
+        });
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn start_interaction(
+    mut query: Query<(Entity, &mut ActiveInteraction), With<StartProcessingInteraction>>,
+    machines: Query<
+        (&ProcessingMachine, &GlobalTransform, Option<&PowerConsumer>),
+        Without<ActiveProcess>,
+    >,
+    children: Query<&Children>,
+    containers: Query<&Container, With<ProcessingInput>>,
+    inputs: Inputs,
+    books: Res<Assets<RecipeBook>>,
+    unpowered: Res<UnpoweredAreas>,
+    maps: Query<&TileMap>,
+    mut viewers: ResMut<MachineViewers>,
+    mut feedback: Feedback,
+    mut commands: Commands,
+) {
+    for (source, mut active) in query.iter_mut() {
+        let machine_entity = active.target;
+        let (Ok((machine, transform, consumer)), Some(container)) = (
+            machines.get(machine_entity),
+            input_container(machine_entity, &children, &containers)
+                .and_then(|c| containers.get(c).ok()),
+        ) else {
+            active.status = InteractionStatus::Canceled;
+            continue;
+        };
+        active.status = InteractionStatus::Completed;
+
+        let mut fail = |kind: FeedbackKind, key: &str| {
+            feedback.send_to_creature(source, kind, key, &[]);
+        };
+
+        if consumer.is_some()
+            && !unpowered.is_powered(maps.get_single().ok(), transform.translation())
+        {
+            fail(FeedbackKind::NoPower, "machine.no_power");
+            continue;
+        }
+
+        let Some(book) = find_book(&books, &machine.recipes) else {
+            warn!(machine = ?machine_entity, recipes = machine.recipes, "Missing recipe book");
+            continue;
+        };
+
+        let recipe = if machine.select_recipe {
+            let Some(selected) = book
+                .recipes
+                .iter()
+                .find(|r| machine.selected.as_ref() == Some(&r.id))
+            else {
+                fail(FeedbackKind::Blocked, "machine.select_recipe");
+                continue;
+            };
+            if match_recipe(selected, container, &inputs).is_none() {
+                fail(FeedbackKind::Blocked, "machine.missing_materials");
+                continue;
+            }
+            selected
+        } else {
+            let Some(recipe) = book
+                .recipes
+                .iter()
+                .find(|r| match_recipe(r, container, &inputs).is_some())
+            else {
+                fail(FeedbackKind::Blocked, "machine.no_recipe");
+                continue;
+            };
+            recipe
+        };
+
+        info!(machine = ?machine_entity, recipe = recipe.id, "Started processing");
+        commands.entity(machine_entity).insert(ActiveProcess {
+            recipe: recipe.clone(),
+            elapsed: 0.0,
+        });
+        viewers.refresh(machine_entity);
+    }
+}
+
+/// Output that is moved into the machine once its prefab has spawned.
+#[derive(Component)]
+struct PendingOutput {
+    container: Entity,
+}
+
+#[allow(clippy::too_many_arguments)]
+fn run_processes(
+    mut machines: Query<(
+        Entity,
+        &ProcessingMachine,
+        &mut ActiveProcess,
+        &mut ProcessingState,
+        &GlobalTransform,
+        Option<&PowerConsumer>,
+    )>,
+    children: Query<&Children>,
+    containers: Query<&Container, With<ProcessingInput>>,
+    mut inputs: ParamSet<(Inputs, Query<&mut MaterialStack>)>,
+    unpowered: Res<UnpoweredAreas>,
+    maps: Query<&TileMap>,
+    time: Res<Time>,
+    asset_server: Res<AssetServer>,
+    mut viewers: ResMut<MachineViewers>,
+    mut commands: Commands,
+) {
+    let map = maps.get_single().ok();
+    for (entity, machine, mut process, mut state, transform, consumer) in machines.iter_mut() {
+        // Losing power pauses the process until it comes back
+        let paused = consumer.is_some() && !unpowered.is_powered(map, transform.translation());
+        if *state.paused != paused {
+            *state.paused = paused;
+        }
+        if paused {
+            continue;
+        }
+
+        process.elapsed += time.delta_seconds();
+        let progress = (process.elapsed / process.recipe.seconds).min(1.0);
+        let last = state.progress.unwrap_or(0.0);
+        if state.progress.is_none() || progress >= 1.0 || progress - last >= PROGRESS_STEP {
+            *state.progress = Some(progress);
+        }
+        if progress < 1.0 {
+            continue;
+        }
+
+        commands.entity(entity).remove::<ActiveProcess>();
+        *state.progress = None;
+        viewers.refresh(entity);
+
+        let Some(container_entity) = input_container(entity, &children, &containers) else {
+            continue;
+        };
+        let Some(plan) = containers
+            .get(container_entity)
+            .ok()
+            .and_then(|container| match_recipe(&process.recipe, container, &inputs.p0()))
+        else {
+            warn!(machine = ?entity, recipe = process.recipe.id, "Inputs changed while processing");
+            continue;
+        };
+
+        for consume in plan {
+            match consume.amount {
+                Some(amount) => {
+                    if let Ok(mut stack) = inputs.p1().get_mut(consume.item) {
+                        stack.amount -= amount;
+                    }
+                }
+                None => commands.entity(consume.item).despawn_recursive(),
+            }
+        }
+
+        let scene = asset_server
+            .load(format!("items/{}.scn.ron", process.recipe.output))
+            .into();
+        match machine.output {
+            OutputTarget::Container => {
+                commands.spawn((
+                    NetworkSceneBundle {
+                        scene,
+                        transform: Transform::from_translation(transform.translation()),
+                        ..Default::default()
+                    },
+                    PendingOutput {
+                        container: container_entity,
+                    },
+                ));
+            }
+            OutputTarget::Floor => {
+                let position = transform.translation() + transform.forward();
+                commands.spawn(NetworkSceneBundle {
+                    scene,
+                    transform: Transform::from_translation(position.round() + Vec3::Y * 0.5),
+                    ..Default::default()
+                });
+            }
+        }
+        info!(machine = ?entity, recipe = process.recipe.id, "Finished processing");
+    }
+}
+
+/// Moves produced items into their machine once they have spawned.
+fn store_outputs(
+    outputs: Query<(Entity, &PendingOutput), With<Item>>,
+    mut item_moves: ResMut<Tasks<MoveItem>>,
+    mut commands: Commands,
+) {
+    for (entity, output) in outputs.iter() {
+        item_moves.create_ignore(MoveItem {
+            item: entity,
+            container: Some(output.container),
+            position: None,
+        });
+        commands.entity(entity).remove::<PendingOutput>();
+    }
+}
+
+/// Updates open windows when the inputs of a machine change.
+fn refresh_changed_inputs(
+    inputs: Query<&Parent, (With<ProcessingInput>, Changed<Container>)>,
+    mut viewers: ResMut<MachineViewers>,
+) {
+    for parent in inputs.iter() {
+        viewers.refresh(parent.get());
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct RecipeInfo {
+    id: String,
+    name: String,
+    cost: String,
+}
+
+/// Server message with the state of a processing machine.
+#[derive(Serialize, Deserialize, Clone)]
+struct ProcessingMachineMessage {
+    machine: NetworkIdentity,
+    name: String,
+    contents: Vec<String>,
+    /// Recipes the player can choose from, empty if the machine picks on its own
+    recipes: Vec<RecipeInfo>,
+    selected: Option<String>,
+    running: bool,
+}
+
+/// Client message to pick what a machine makes.
+#[derive(Serialize, Deserialize)]
+struct SelectRecipeRequest {
+    machine: NetworkIdentity,
+    recipe: String,
+}
+
+/// Client message to take everything out of a machine.
+#[derive(Serialize, Deserialize)]
+struct EjectContentsRequest {
+    machine: NetworkIdentity,
+}
+
+fn send_processing_state(
+    viewers: Res<MachineViewers>,
+    machines: Query<(&Machine, &ProcessingMachine, Option<&ActiveProcess>)>,
+    children: Query<&Children>,
+    containers: Query<&Container, With<ProcessingInput>>,
+    items: Query<(&Item, Option<&MaterialStack>)>,
+    books: Res<Assets<RecipeBook>>,
+    mut sender: MessageSender,
+) {
+    for viewer in viewers.due() {
+        let Ok((machine, processing, running)) = machines.get(viewer.machine) else {
+            continue;
+        };
+
+        let mut contents: Vec<String> = input_container(viewer.machine, &children, &containers)
+            .and_then(|c| containers.get(c).ok())
+            .into_iter()
+            .flat_map(|container| container.iter())
+            .filter_map(|(_, &item)| items.get(item).ok())
+            .map(|(item, stack)| match stack {
+                Some(stack) => format!("{} ({})", item.name, stack.amount),
+                None => item.name.clone(),
+            })
+            .collect();
+        contents.sort();
+
+        let recipes = processing
+            .select_recipe
+            .then(|| find_book(&books, &processing.recipes))
+            .flatten()
+            .map(|book| {
+                book.recipes
+                    .iter()
+                    .map(|recipe| RecipeInfo {
+                        id: recipe.id.clone(),
+                        name: recipe.name.clone(),
+                        cost: recipe
+                            .inputs
+                            .iter()
+                            .map(|i| i.to_string())
+                            .collect::<Vec<_>>()
+                            .join(", "),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        sender.send(
+            &ProcessingMachineMessage {
+                machine: viewer.identity,
+                name: machine.name.clone(),
+                contents,
+                recipes,
+                selected: processing.selected.clone(),
+                running: running.is_some(),
+            },
+            MessageReceivers::Single(viewer.connection),
+        );
+    }
+}
+
+fn handle_select_requests(
+    mut messages: EventReader<MessageEvent<SelectRecipeRequest>>,
+    mut viewers: ResMut<MachineViewers>,
+    mut machines: Query<&mut ProcessingMachine, Without<ActiveProcess>>,
+    books: Res<Assets<RecipeBook>>,
+) {
+    for event in messages.iter() {
+        let Some(machine_entity) = viewers
+            .get(event.connection, event.message.machine)
+            .map(|v| v.machine)
+        else {
+            continue;
+        };
+        let Ok(mut machine) = machines.get_mut(machine_entity) else {
+            continue;
+        };
+        let known = find_book(&books, &machine.recipes)
+            .is_some_and(|book| book.recipes.iter().any(|r| r.id == event.message.recipe));
+        if !machine.select_recipe || !known {
+            continue;
+        }
+
+        machine.selected = Some(event.message.recipe.clone());
+        viewers.refresh(machine_entity);
+    }
+}
+
+fn handle_eject_requests(
+    mut messages: EventReader<MessageEvent<EjectContentsRequest>>,
+    viewers: Res<MachineViewers>,
+    machines: Query<(), (With<ProcessingMachine>, Without<ActiveProcess>)>,
+    children: Query<&Children>,
+    containers: Query<&Container, With<ProcessingInput>>,
+    mut item_moves: ResMut<Tasks<MoveItem>>,
+) {
+    for event in messages.iter() {
+        let Some(machine) = viewers
+            .get(event.connection, event.message.machine)
+            .map(|v| v.machine)
+            .filter(|&m| machines.contains(m))
+        else {
+            continue;
+        };
+        let Some(container) =
+            input_container(machine, &children, &containers).and_then(|c| containers.get(c).ok())
+        else {
+            continue;
+        };
+
+        for (_, &item) in container.iter() {
+            item_moves.create_ignore(MoveItem {
+                item,
+                container: None,
+                position: None,
+            });
+        }
+        info!(connection = ?event.connection, ?machine, "Ejected machine contents");
+    }
+}
+
+#[cfg(feature = "client")]
+#[derive(Resource, Default)]
+struct ClientProcessingMachine {
+    state: Option<ProcessingMachineMessage>,
+}
+
+#[cfg(feature = "client")]
+fn receive_processing_state(
+    mut messages: EventReader<MessageEvent<ProcessingMachineMessage>>,
+    mut closed: EventReader<MessageEvent<MachineClosedMessage>>,
+    mut machine: ResMut<ClientProcessingMachine>,
+) {
+    for event in messages.iter() {
+        machine.state = Some(event.message.clone());
+    }
+    for event in closed.iter() {
+        if machine.state.as_ref().map(|s| s.machine) == Some(event.message.machine) {
+            machine.state = None;
+        }
+    }
+}
+
+#[cfg(feature = "client")]
+fn processing_ui(
+    mut contexts: EguiContexts,
+    mut machine: ResMut<ClientProcessingMachine>,
+    identities: Res<NetworkIdentities>,
+    progress: Query<&ProcessingStateClient>,
+    mut sender: MessageSender,
+) {
+    let Some(state) = machine.state.as_ref() else {
+        return;
+    };
+    let identity = state.machine;
+    let progress = identities
+        .get_entity(identity)
+        .and_then(|entity| progress.get(entity).ok());
+
+    let mut open = true;
+    egui::Window::new(&state.name)
+        .open(&mut open)
+        .resizable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.label("Contents:");
+            if state.contents.is_empty() {
+                ui.label("Empty");
+            }
+            for item in state.contents.iter() {
+                ui.label(item);
+            }
+
+            if !state.recipes.is_empty() {
+                ui.separator();
+                for recipe in state.recipes.iter() {
+                    let selected = state.selected.as_ref() == Some(&recipe.id);
+                    let response = ui.add_enabled(
+                        !state.running,
+                        egui::RadioButton::new(
+                            selected,
+                            format!("{} ({})", recipe.name, recipe.cost),
+                        ),
+                    );
+                    if response.clicked() && !selected {
+                        sender.send_to_server(&SelectRecipeRequest {
+                            machine: identity,
+                            recipe: recipe.id.clone(),
+                        });
+                    }
+                }
+            }
+
+            ui.separator();
+            match progress.and_then(|p| p.progress.map(|value| (value, *p.paused))) {
+                Some((_, true)) => {
+                    ui.label("Paused: no power");
+                }
+                Some((value, false)) => {
+                    ui.add(egui::ProgressBar::new(value).show_percentage());
+                }
+                None => {}
+            }
+            if ui
+                .add_enabled(!state.running, egui::Button::new("Eject contents"))
+                .clicked()
+            {
+                sender.send_to_server(&EjectContentsRequest { machine: identity });
+            }
+        });
+
+    if !open {
+        machine.state = None;
+        close_machine_window(identity, &mut sender);
+    }
+}
@@ -0,0 +1,238 @@
+use bevy::{ecs::query::Has, prelude::*};
+use networking::{
+    identity::{NetworkIdentities, NetworkIdentity},
+    is_server,
+    messaging::{AppExt, MessageEvent, MessageReceivers, MessageSender},
+    spawning::ClientControls,
+    Players,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{access::AccessReader, communication::SpeechName};
+
+#[cfg(feature = "client")]
+use {
+    super::{close_machine_window, MachineClosedMessage},
+    crate::{ui::has_window, GameState},
+    bevy_egui::{egui, EguiContexts},
+};
+
+use super::{MachineViewers, SendMachineUpdates};
+
+/// Lets security mark players for arrest, which shows up on security HUDs.
+pub(super) struct SecurityConsolePlugin;
+
+impl Plugin for SecurityConsolePlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<SecurityConsole>()
+            .register_type::<SecurityHud>()
+            .add_network_message::<SecurityRecordsMessage>("SecurityRecordsMessage")
+            .add_network_message::<SetArrestRequest>("SetArrestRequest");
+
+        if is_server(app) {
+            app.add_systems(
+                Update,
+                (
+                    send_security_records.in_set(SendMachineUpdates),
+                    handle_set_arrest_requests.before(SendMachineUpdates),
+                ),
+            );
+        } else {
+            #[cfg(feature = "client")]
+            app.init_resource::<ClientSecurityConsole>().add_systems(
+                Update,
+                (
+                    receive_security_records,
+                    security_console_ui.run_if(has_window),
+                )
+                    .chain()
+                    .run_if(in_state(GameState::Game)),
+            );
+        }
+    }
+}
+
+/// A console listing the crew, where players can be flagged for arrest.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct SecurityConsole;
+
+/// Clothing that shows arrest flags above players while worn.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct SecurityHud;
+
+/// A creature that security should arrest.
+#[derive(Component)]
+pub struct ArrestFlag;
+
+#[derive(Serialize, Deserialize, Clone)]
+struct SecurityRecord {
+    creature: NetworkIdentity,
+    name: String,
+    arrest: bool,
+}
+
+/// Server message with the records shown on a security console.
+#[derive(Serialize, Deserialize)]
+struct SecurityRecordsMessage {
+    machine: NetworkIdentity,
+    records: Vec<SecurityRecord>,
+}
+
+/// Client message to flag or unflag a creature for arrest.
+#[derive(Serialize, Deserialize)]
+struct SetArrestRequest {
+    machine: NetworkIdentity,
+    creature: NetworkIdentity,
+    arrest: bool,
+}
+
+fn send_security_records(
+    viewers: Res<MachineViewers>,
+    consoles: Query<(), With<SecurityConsole>>,
+    players: Res<Players>,
+    controls: Res<ClientControls>,
+    creatures: Query<(&NetworkIdentity, Option<&SpeechName>, Has<ArrestFlag>)>,
+    mut sender: MessageSender,
+) {
+    let mut records = None;
+    for viewer in viewers.due().filter(|v| consoles.contains(v.machine)) {
+        // Everyone with a body in the round has a record
+        let records = records.get_or_insert_with(|| {
+            let mut records: Vec<_> = players
+                .players()
+                .values()
+                .filter_map(|player| {
+                    let entity = controls.controlled_entity(player.id)?;
+                    let (&creature, name, arrest) = creatures.get(entity).ok()?;
+                    Some(SecurityRecord {
+                        creature,
+                        name: name
+                            .map(|n| n.0.clone())
+                            .unwrap_or_else(|| player.username.clone()),
+                        arrest,
+                    })
+                })
+                .collect();
+            records.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+            records
+        });
+
+        sender.send(
+            &SecurityRecordsMessage {
+                machine: viewer.identity,
+                records: records.clone(),
+            },
+            MessageReceivers::Single(viewer.connection),
+        );
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn handle_set_arrest_requests(
+    mut messages: EventReader<MessageEvent<SetArrestRequest>>,
+    mut viewers: ResMut<MachineViewers>,
+    consoles: Query<(), With<SecurityConsole>>,
+    creatures: Query<Has<ArrestFlag>>,
+    identities: Res<NetworkIdentities>,
+    controls: Res<ClientControls>,
+    access: AccessReader,
+    mut commands: Commands,
+) {
+    for event in messages.iter() {
+        let Some(viewer) = viewers.get(event.connection, event.message.machine) else {
+            continue;
+        };
+        if !consoles.contains(viewer.machine) || !access.can_access(viewer.creature, viewer.machine)
+        {
+            continue;
+        }
+        // Only player creatures have records
+        let Some(creature) = identities
+            .get_entity(event.message.creature)
+            .filter(|&e| controls.controlling_player(e).is_some())
+        else {
+            continue;
+        };
+        let Ok(flagged) = creatures.get(creature) else {
+            continue;
+        };
+        if flagged == event.message.arrest {
+            continue;
+        }
+
+        if event.message.arrest {
+            commands.entity(creature).insert(ArrestFlag);
+        } else {
+            commands.entity(creature).remove::<ArrestFlag>();
+        }
+        info!(connection = ?event.connection, creature = ?creature, arrest = event.message.arrest, "Arrest flag changed");
+        // Every security console shows the same records
+        viewers.refresh_all();
+    }
+}
+
+#[cfg(feature = "client")]
+#[derive(Resource, Default)]
+struct ClientSecurityConsole {
+    machine: Option<NetworkIdentity>,
+    records: Vec<SecurityRecord>,
+}
+
+#[cfg(feature = "client")]
+fn receive_security_records(
+    mut messages: EventReader<MessageEvent<SecurityRecordsMessage>>,
+    mut closed: EventReader<MessageEvent<MachineClosedMessage>>,
+    mut console: ResMut<ClientSecurityConsole>,
+) {
+    for event in messages.iter() {
+        console.machine = Some(event.message.machine);
+        console.records = event.message.records.clone();
+    }
+    for event in closed.iter() {
+        if console.machine == Some(event.message.machine) {
+            console.machine = None;
+        }
+    }
+}
+
+#[cfg(feature = "client")]
+fn security_console_ui(
+    mut contexts: EguiContexts,
+    mut console: ResMut<ClientSecurityConsole>,
+    mut sender: MessageSender,
+) {
+    let Some(machine) = console.machine else {
+        return;
+    };
+
+    let mut open = true;
+    egui::Window::new("Security Records")
+        .open(&mut open)
+        .resizable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            if console.records.is_empty() {
+                ui.label("No crew records");
+            }
+            egui::Grid::new("security_records").show(ui, |ui| {
+                for record in console.records.iter() {
+                    ui.label(&record.name);
+                    let mut arrest = record.arrest;
+                    if ui.checkbox(&mut arrest, "Arrest").changed() {
+                        sender.send_to_server(&SetArrestRequest {
+                            machine,
+                            creature: record.creature,
+                            arrest,
+                        });
+                    }
+                    ui.end_row();
+                }
+            });
+        });
+
+    if !open {
+        console.machine = None;
+        close_machine_window(machine, &mut sender);
+    }
+}
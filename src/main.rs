@@ -1,41 +1,96 @@
 #![allow(clippy::type_complexity)]
 
+mod access;
+#[cfg(feature = "client")]
+mod accessibility;
+mod actions;
 mod admin;
+mod atmos;
+mod autosave;
 mod body;
+mod bug_report;
+mod butchering;
+#[cfg(feature = "client")]
 mod camera;
+mod coating;
 mod combat;
 mod communication;
 mod components;
 mod config;
+mod console;
 mod construction;
+mod cooldown;
+#[cfg(feature = "client")]
+mod cursor;
+#[cfg(feature = "client")]
 mod debug;
+mod decals;
+mod device_link;
+mod diffusion;
+mod door;
+#[cfg(feature = "client")]
+mod editor;
+mod effects;
+mod escape_pod;
+mod feedback;
+mod flash;
+mod gravity;
 mod interaction;
+mod invite;
 mod items;
 mod job;
+mod lights;
+mod locale;
+mod logging;
+mod machines;
+mod map_objects;
+mod map_theme;
+mod map_validation;
 mod movement;
+mod navigation;
+#[cfg(feature = "client")]
+mod occlusion;
+mod physics_tuning;
+mod pointing;
+mod profile;
+mod random_event;
+mod replay;
+mod resource_packs;
+mod rng;
 mod round;
+mod safe_zone;
 mod scene;
+mod security_camera;
+mod shuttle;
+mod sound;
+mod spectator;
+mod stats;
+mod status;
+mod status_hud;
+mod temperature;
+#[cfg(all(test, feature = "server"))]
+mod testing;
+mod text_filter;
+mod timeline;
 mod ui;
+mod vision;
+mod void;
+mod waypoint;
+mod windows;
+
+#[cfg(not(any(feature = "client", feature = "server")))]
+compile_error!("At least one of the `client` and `server` features must be enabled");
 
-use std::net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4};
-use std::time::Duration;
+use std::net::SocketAddr;
+use std::path::PathBuf;
 
 use admin::AdminPlugin;
-use bevy::app::ScheduleRunnerPlugin;
-use bevy::asset::AssetPlugin;
-use bevy::log::LogPlugin;
 use bevy::prelude::*;
-use bevy::scene::ScenePlugin;
-use bevy::tasks::{AsyncComputeTaskPool, Task};
 use bevy_rapier3d::plugin::{NoUserData, RapierPhysicsPlugin};
 use bevy_rapier3d::prelude::Collider;
-use byond::tgm::TgmLoader;
 use clap::{Parser, Subcommand};
-use config::ServerConfig;
-use futures_lite::future;
-use maps::TileMapData;
-use networking::identity::EntityCommandsExt as NetworkingEntityCommandsExt;
-use networking::{NetworkRole, NetworkingPlugin, ServerAuthentication};
+use maps::save::MapSave;
+use networking::{NetworkRole, NetworkingPlugin};
 
 #[cfg(feature = "client")]
 use {
@@ -45,17 +100,41 @@ use {
     networking::{ClientEvent, ConnectToken, TargetServer, UserData},
 };
 
+#[cfg(all(feature = "client", feature = "server"))]
+use networking::loopback::LoopbackConnector;
+
+#[cfg(feature = "server")]
+use {
+    bevy::app::ScheduleRunnerPlugin,
+    bevy::asset::AssetPlugin,
+    bevy::scene::ScenePlugin,
+    bevy::tasks::{AsyncComputeTaskPool, Task},
+    byond::tgm::{
+        conversion::{ObjectPlacement, TileObjectTag},
+        TgmLoader,
+    },
+    config::ServerConfig,
+    futures_lite::future,
+    maps::TileMapData,
+    networking::identity::EntityCommandsExt as NetworkingEntityCommandsExt,
+    networking::ServerAuthentication,
+    std::net::{IpAddr, Ipv4Addr, SocketAddrV4},
+    std::time::Duration,
+};
+
 /// How many ticks the server runs per second
+#[cfg(feature = "server")]
 const SERVER_TPS: u32 = 60;
 
-#[derive(Parser, Resource)]
+#[derive(Parser, Resource, Clone)]
 struct Args {
     #[clap(subcommand)]
     command: Option<ArgCommands>,
 }
 
-#[derive(Subcommand)]
+#[derive(Subcommand, Clone)]
 enum ArgCommands {
+    #[cfg(feature = "server")]
     /// host a server
     Host {
         #[clap(default_value_t = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(0, 0, 0, 0), 33998u16)))]
@@ -64,6 +143,36 @@ enum ArgCommands {
         /// set this when hosting behind NAT (ex. a home router)
         #[clap(long)]
         public_address: Option<IpAddr>,
+        /// boot from an autosave instead of a fresh map
+        #[clap(long)]
+        recover: Option<PathBuf>,
+        /// start from a map saved with the map editor instead of the default map
+        #[clap(long)]
+        map_save: Option<PathBuf>,
+        /// register an invite code with this broker, so players can join without the address
+        #[clap(long)]
+        invite_broker: Option<String>,
+        /// minutes an invite code stays valid without being refreshed
+        #[clap(long, default_value_t = 120)]
+        invite_ttl: u64,
+        /// record everything players send to this file, so the round can be simulated again
+        #[clap(long)]
+        record_inputs: Option<PathBuf>,
+        /// ticks between the world checksums saved in the recording
+        #[clap(long, default_value_t = 60)]
+        checkpoint_interval: u64,
+    },
+    #[cfg(feature = "server")]
+    /// re-run a round recorded with --record-inputs without networking and report where it diverges
+    Simulate { path: PathBuf },
+    #[cfg(all(feature = "client", feature = "server"))]
+    /// host a server and play on it. other players can join as usual
+    HostAndPlay {
+        #[clap(default_value_t = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(0, 0, 0, 0), 33998u16)))]
+        bind_address: SocketAddr,
+        /// name of your player
+        #[clap(long, default_value = "Host")]
+        name: String,
     },
     #[cfg(feature = "client")]
     /// join a game
@@ -74,71 +183,61 @@ enum ArgCommands {
         /// base64 encoded connection token
         token: String,
     },
+    #[cfg(feature = "client")]
+    /// edit a map without a server. creates the map if the file doesn't exist
+    Editor { path: PathBuf },
 }
 
 fn main() {
     let args = Args::parse();
     let role = match args.command {
-        Some(ArgCommands::Host { .. }) => NetworkRole::Server,
+        #[cfg(feature = "server")]
+        Some(ArgCommands::Host { .. } | ArgCommands::Simulate { .. }) => NetworkRole::Server,
         _ => NetworkRole::Client,
     };
     let networking_plugin = NetworkingPlugin { role };
 
     let mut app = App::new();
-    app.register_type::<Player>();
+    resource_packs::install_asset_io(&mut app);
 
     match role {
         NetworkRole::Server => {
-            match config::load_server_config() {
-                Ok(config) => app.insert_resource(config),
-                Err(err) => {
-                    error!("Error loading server configuration: {}", err);
-                    return;
-                }
-            };
-
-            let runner =
-                ScheduleRunnerPlugin::run_loop(Duration::from_secs_f64(1f64 / SERVER_TPS as f64));
-            app.add_plugins((
-                MinimalPlugins.set(runner),
-                TransformPlugin,
-                AssetPlugin::default(),
-                LogPlugin::default(),
-                ScenePlugin,
-                HierarchyPlugin,
-                networking_plugin,
-            ))
-            .add_asset::<byond::tgm::TileMap>()
-            .add_asset::<Mesh>() // TODO: remove once no longer needed by rapier
-            .add_asset::<Scene>() // TODO: remove once no longer needed by rapier
-            // Register types used in scenes manually.
-            // The server will not do anything with them, but needs it so it can load scene files.
-            .register_type::<bevy::pbr::PointLight>()
-            .register_type::<bevy::pbr::CubemapVisibleEntities>()
-            .register_type::<bevy::render::primitives::CubemapFrusta>()
-            .register_type::<bevy::render::view::Visibility>()
-            .register_type::<bevy::render::view::ComputedVisibility>()
-            .register_type::<Handle<bevy::pbr::StandardMaterial>>()
-            .register_type::<bevy::pbr::NotShadowCaster>()
-            .register_type::<Vec<Entity>>()
-            .add_asset_loader(TgmLoader)
-            .add_systems(Startup, (setup_server, config::server_startup))
-            .add_systems(Update, (convert_tgm_map, create_tilemap_from_converted));
+            #[cfg(feature = "server")]
+            if !build_server(&mut app, &args, networking_plugin) {
+                return;
+            }
+            #[cfg(not(feature = "server"))]
+            panic!("Compiled without server support");
         }
         NetworkRole::Client => {
+            #[cfg(all(feature = "client", feature = "server"))]
+            if let Some(ArgCommands::HostAndPlay { .. }) = &args.command {
+                let Some(connector) = start_local_server(&args) else {
+                    error!("The local server could not be started");
+                    return;
+                };
+                app.insert_resource(LocalServer(connector));
+            }
             #[cfg(feature = "client")]
             app.add_plugins((
-                DefaultPlugins.set(WindowPlugin {
-                    primary_window: Some(Window {
-                        title: "Space Station Nanotrasen".to_owned(),
+                logging::LogCapturePlugin,
+                DefaultPlugins
+                    .set(WindowPlugin {
+                        primary_window: Some(Window {
+                            title: "Space Station Nanotrasen".to_owned(),
+                            ..Default::default()
+                        }),
                         ..Default::default()
-                    }),
-                    ..Default::default()
-                }),
+                    })
+                    .disable::<LogPlugin>(),
                 networking_plugin,
                 camera::CameraPlugin,
                 EguiPlugin,
                 debug::DebugPlugin,
+                editor::EditorPlugin,
+                occlusion::OcclusionPlugin,
+                cursor::CursorPlugin,
+                accessibility::AccessibilityPlugin,
             ))
             .insert_resource(ClearColor(Color::rgb(
                 44.0 / 255.0,
@@ -152,26 +251,242 @@ fn main() {
             panic!("Compiled without client support");
         }
     };
+    add_game_plugins(&mut app);
+    app.insert_resource(args).run();
+}
+
+/// Adds the plugins used by both the client and the server.
+fn add_game_plugins(app: &mut App) {
+    app.register_type::<Player>()
+        .add_plugins((
+            RapierPhysicsPlugin::<NoUserData>::default(),
+            physics::PhysicsPlugin,
+            scene::ScenePlugin,
+            movement::MovementPlugin,
+            maps::MapPlugin,
+            AdminPlugin,
+            items::ItemPlugin,
+            body::BodyPlugin,
+            round::RoundPlugin,
+            job::JobPlugin,
+            interaction::InteractionPlugin,
+            construction::ConstructionPlugin,
+            combat::CombatPlugin,
+            communication::CommunicationPlugin,
+        ))
+        .add_plugins((
+            ui::UiPlugin,
+            sound::SoundPlugin,
+            profile::ProfilePlugin,
+            timeline::TimelinePlugin,
+            door::DoorPlugin,
+            security_camera::SecurityCameraPlugin,
+            map_objects::MapObjectsPlugin,
+            autosave::AutosavePlugin,
+            vision::VisionPlugin,
+            actions::ActionsPlugin,
+            safe_zone::SafeZonePlugin,
+            console::ConsolePlugin,
+            lights::LightsPlugin,
+            bug_report::BugReportPlugin,
+            invite::InvitePlugin,
+        ))
+        .add_plugins((
+            temperature::TemperaturePlugin,
+            atmos::AtmosPlugin,
+            rng::RngPlugin,
+            shuttle::ShuttlePlugin,
+            spectator::SpectatorPlugin,
+            access::AccessPlugin,
+            machines::MachinesPlugin,
+            effects::EffectsPlugin,
+            navigation::NavigationPlugin,
+            gravity::GravityPlugin,
+            text_filter::TextFilterPlugin,
+            status_hud::StatusHudPlugin,
+            flash::FlashPlugin,
+            map_validation::MapValidationPlugin,
+            cooldown::CooldownPlugin,
+        ))
+        .add_plugins((
+            resource_packs::ResourcePackPlugin,
+            locale::LocalePlugin,
+            feedback::FeedbackPlugin,
+            device_link::DeviceLinkPlugin,
+            pointing::PointingPlugin,
+            waypoint::WaypointPlugin,
+            void::VoidPlugin,
+            physics_tuning::PhysicsTuningPlugin,
+            replay::ReplayPlugin,
+            stats::StatsPlugin,
+            map_theme::MapThemePlugin,
+            escape_pod::EscapePodPlugin,
+            status::StatusPlugin,
+            random_event::RandomEventPlugin,
+            windows::WindowsPlugin,
+        ))
+        .add_plugins((
+            coating::CoatingPlugin,
+            decals::DecalPlugin,
+            butchering::ButcheringPlugin,
+        ))
+        .add_systems(Startup, setup_shared);
+}
+
+/// Runs the server of a host-and-play game on its own thread.
+/// Returns the connector the local player joins with, or `None` if the server didn't start.
+#[cfg(all(feature = "client", feature = "server"))]
+fn start_local_server(args: &Args) -> Option<LoopbackConnector> {
+    let (sender, receiver) = std::sync::mpsc::sync_channel(1);
+    let args = args.clone();
+    std::thread::Builder::new()
+        .name("server".into())
+        .spawn(move || {
+            let mut app = App::new();
+            resource_packs::install_asset_io(&mut app);
+            let networking_plugin = NetworkingPlugin {
+                role: NetworkRole::Server,
+            };
+            if !build_server(&mut app, &args, networking_plugin) {
+                return;
+            }
+            add_game_plugins(&mut app);
+            app.insert_resource(LocalServerStarted(sender))
+                .insert_resource(args)
+                .run();
+        })
+        .expect("Could not start the server thread");
+    // The sender is dropped without sending if the server fails to start
+    receiver.recv().ok()
+}
+
+/// Connects the player of a host-and-play game to the server in the same process.
+#[cfg(all(feature = "client", feature = "server"))]
+#[derive(Resource)]
+struct LocalServer(LoopbackConnector);
+
+/// Passes the connector of a host-and-play server to the client once it's listening.
+#[cfg(all(feature = "client", feature = "server"))]
+#[derive(Resource)]
+struct LocalServerStarted(std::sync::mpsc::SyncSender<LoopbackConnector>);
+
+/// Adds the plugins only the server uses. Returns false if the server can't start.
+#[cfg(feature = "server")]
+fn build_server(app: &mut App, args: &Args, networking_plugin: NetworkingPlugin) -> bool {
+    // Simulations use the config and map the recorded round used
+    let mut map_save = match &args.command {
+        Some(ArgCommands::Host { map_save, .. }) => map_save.clone(),
+        _ => None,
+    };
+    let config = if let Some(ArgCommands::Simulate { path }) = &args.command {
+        let simulation = match replay::Simulation::load(path) {
+            Ok(simulation) => simulation,
+            Err(err) => {
+                error!("Error loading recording {}: {}", path.display(), err);
+                return false;
+            }
+        };
+        let config = match simulation.server_config() {
+            Ok(config) => config,
+            Err(err) => {
+                error!("Error loading recorded server configuration: {}", err);
+                return false;
+            }
+        };
+        map_save = simulation.map_save().map(Into::into);
+        app.insert_resource(simulation.recorded_files())
+            .insert_resource(simulation);
+        config
+    } else {
+        match config::load_server_config() {
+            Ok(config) => config,
+            Err(err) => {
+                error!("Error loading server configuration: {}", err);
+                return false;
+            }
+        }
+    };
+
+    if let Some(ArgCommands::Host {
+        record_inputs: Some(path),
+        checkpoint_interval,
+        recover,
+        ..
+    }) = &args.command
+    {
+        // The simulation would start from the map instead of the recovered world
+        if recover.is_some() {
+            error!("Inputs can't be recorded when recovering from an autosave");
+            return false;
+        }
+        let recorder = replay::InputRecorder::create(
+            path,
+            &config,
+            config::read_server_config(),
+            map_save.clone(),
+            *checkpoint_interval,
+        );
+        match recorder {
+            Ok(recorder) => app.insert_resource(recorder),
+            Err(err) => {
+                error!("Error creating input recording {}: {}", path.display(), err);
+                return false;
+            }
+        };
+    }
+
+    app.insert_resource(config.link_quality.clone())
+        .insert_resource(config);
+
+    if let Some(ArgCommands::Host {
+        recover: Some(path),
+        ..
+    }) = &args.command
+    {
+        match autosave::load_snapshot(path) {
+            Ok(snapshot) => app.insert_resource(autosave::RecoveredWorld(snapshot)),
+            Err(err) => {
+                error!("Error loading autosave {}: {}", path.display(), err);
+                return false;
+            }
+        };
+    }
+
+    if let Some(path) = &map_save {
+        match MapSave::load(path) {
+            Ok(save) => app.insert_resource(SavedMap(save)),
+            Err(err) => {
+                error!("Error loading map save {}: {}", path.display(), err);
+                return false;
+            }
+        };
+    }
+
+    add_server_plugins(app, networking_plugin);
+    app.add_asset_loader(TgmLoader)
+        .add_systems(Startup, (setup_server, config::server_startup))
+        .add_systems(Update, (convert_tgm_map, create_tilemap_from_converted));
+    true
+}
+
+/// Adds the engine plugins a headless server needs.
+#[cfg(feature = "server")]
+fn add_server_plugins(app: &mut App, networking_plugin: NetworkingPlugin) {
+    let runner = ScheduleRunnerPlugin::run_loop(Duration::from_secs_f64(1f64 / SERVER_TPS as f64));
     app.add_plugins((
-        RapierPhysicsPlugin::<NoUserData>::default(),
-        physics::PhysicsPlugin,
-        scene::ScenePlugin,
-        movement::MovementPlugin,
-        maps::MapPlugin,
-        AdminPlugin,
-        items::ItemPlugin,
-        body::BodyPlugin,
-        round::RoundPlugin,
-        job::JobPlugin,
-        interaction::InteractionPlugin,
-        construction::ConstructionPlugin,
-        combat::CombatPlugin,
-        communication::CommunicationPlugin,
+        MinimalPlugins.set(runner),
+        TransformPlugin,
+        AssetPlugin::default(),
+        logging::LogCapturePlugin,
+        ScenePlugin,
+        HierarchyPlugin,
+        networking_plugin,
     ))
-    .add_plugins((ui::UiPlugin,))
-    .insert_resource(args)
-    .add_systems(Startup, setup_shared)
-    .run();
+    .add_asset::<byond::tgm::TileMap>()
+    .add_asset::<Mesh>() // TODO: remove once no longer needed by rapier
+    .add_asset::<Scene>() // TODO: remove once no longer needed by rapier
+    .add_plugins(scene::server_scene_compat::ServerSceneCompatPlugin)
+    .register_type::<Vec<Entity>>();
 }
 
 #[derive(Clone, Eq, PartialEq, Debug, Default, Hash, States)]
@@ -181,6 +496,8 @@ enum GameState {
     MainMenu,
     Joining,
     Game,
+    /// Editing a map locally, see [`editor`]
+    Editor,
 }
 
 /// A component that prevents an entity from being deleted when joining or leaving a server.
@@ -216,6 +533,10 @@ pub struct Map {
     pub spawned: bool,
 }
 
+/// A map loaded from a map editor save, used instead of the default map.
+#[derive(Resource)]
+pub struct SavedMap(pub MapSave);
+
 fn setup_shared(mut commands: Commands) {
     // Spawn ground plane
     commands.spawn((
@@ -225,26 +546,43 @@ fn setup_shared(mut commands: Commands) {
     ));
 }
 
+#[cfg(feature = "server")]
 fn setup_server(args: Res<Args>, server_config: Res<ServerConfig>, mut commands: Commands) {
     match args.command.as_ref().unwrap() {
         &ArgCommands::Host {
             bind_address,
             public_address,
+            ..
         } => {
-            let authentication = match &server_config.registration {
-                Some(registration) => {
-                    info!("Running server in authenticated mode");
-                    ServerAuthentication::Secure {
-                        private_key: registration.private_key,
-                    }
-                }
-                None => {
-                    info!("No server registration configured, running in unauthenticated mode (users are not verified)");
-                    ServerAuthentication::Unsecure
+            let (server, transport) = networking::create_server(
+                bind_address,
+                public_address,
+                server_authentication(&server_config),
+            );
+            commands.insert_resource(server);
+            commands.insert_resource(transport);
+            info!(address = %bind_address, "Server listening");
+        }
+        #[cfg(feature = "client")]
+        &ArgCommands::HostAndPlay { bind_address, .. } => {
+            let (server, transport, loopback, connector) = networking::create_listen_server(
+                bind_address,
+                None,
+                server_authentication(&server_config),
+            );
+            commands.insert_resource(server);
+            commands.insert_resource(transport);
+            commands.insert_resource(loopback);
+            commands.add(move |world: &mut World| {
+                if let Some(started) = world.remove_resource::<LocalServerStarted>() {
+                    let _ = started.0.send(connector);
                 }
-            };
-            let (server, transport) =
-                networking::create_server(bind_address, public_address, authentication);
+            });
+            info!(address = %bind_address, "Server listening, joining as local player");
+        }
+        ArgCommands::Simulate { .. } => {
+            // Nobody connects, so the simulation doesn't need to open a socket
+            let (server, transport, _) = networking::create_local_server();
             commands.insert_resource(server);
             commands.insert_resource(transport);
         }
@@ -253,12 +591,29 @@ fn setup_server(args: Res<Args>, server_config: Res<ServerConfig>, mut commands:
     };
 }
 
+#[cfg(feature = "server")]
+fn server_authentication(server_config: &ServerConfig) -> ServerAuthentication {
+    match &server_config.registration {
+        Some(registration) => {
+            info!("Running server in authenticated mode");
+            ServerAuthentication::Secure {
+                private_key: registration.private_key,
+            }
+        }
+        None => {
+            info!("No server registration configured, running in unauthenticated mode (users are not verified)");
+            ServerAuthentication::Unsecure
+        }
+    }
+}
+
 #[cfg(feature = "client")]
 fn setup_client(
     mut commands: Commands,
     args: Res<Args>,
     mut client_events: EventWriter<ClientEvent>,
     mut state: ResMut<NextState<GameState>>,
+    #[cfg(feature = "server")] local_server: Option<Res<LocalServer>>,
 ) {
     // TODO: Replace with on-station lights
     commands.insert_resource(AmbientLight {
@@ -278,6 +633,10 @@ fn setup_client(
         KeepOnServerChange,
     ));
 
+    if let Some(ArgCommands::Editor { .. }) = &args.command {
+        state.set(GameState::Editor);
+    }
+
     // Connect with IP
     if let Some(ArgCommands::Join { address, name }) = &args.command {
         state.set(GameState::MainMenu);
@@ -287,6 +646,18 @@ fn setup_client(
         });
     }
 
+    // Host-and-play joins the server running in this process
+    #[cfg(feature = "server")]
+    if let Some(ArgCommands::HostAndPlay { name, .. }) = &args.command {
+        state.set(GameState::MainMenu);
+        client_events.send(ClientEvent::Join(TargetServer::Loopback(
+            local_server.unwrap().0.clone(),
+        )));
+        commands.insert_resource(UserData {
+            username: name.clone(),
+        });
+    }
+
     // Connect with a token from the central server
     if let Some(ArgCommands::JoinToken { token }) = &args.command {
         state.set(GameState::MainMenu);
@@ -341,9 +712,11 @@ fn set_camera_target(
     }
 }
 
+#[cfg(feature = "server")]
 #[derive(Component)]
-struct ConvertByondMap(Task<TileMapData>);
+struct ConvertByondMap(Task<(TileMapData, Vec<ObjectPlacement>, Vec<TileObjectTag>)>);
 
+#[cfg(feature = "server")]
 fn convert_tgm_map(
     mut commands: Commands,
     map_resource: Option<ResMut<Map>>,
@@ -353,8 +726,13 @@ fn convert_tgm_map(
         if let Some(map) = tilemaps.get(&res.handle) {
             let map_copy = byond::tgm::TileMap::clone(map);
             let thread_pool = AsyncComputeTaskPool::get();
-            let task =
-                thread_pool.spawn(async move { byond::tgm::conversion::to_map_data(&map_copy) });
+            let task = thread_pool.spawn(async move {
+                (
+                    byond::tgm::conversion::to_map_data(&map_copy),
+                    byond::tgm::conversion::object_placements(&map_copy),
+                    byond::tgm::conversion::tile_object_tags(&map_copy),
+                )
+            });
             let new_entity = commands.spawn(ConvertByondMap(task)).id();
             info!("Scheduled tgm map conversion (entity={:?})", new_entity);
             commands.remove_resource::<Map>();
@@ -362,16 +740,24 @@ fn convert_tgm_map(
     }
 }
 
+#[cfg(feature = "server")]
 fn create_tilemap_from_converted(
     mut commands: Commands,
     mut map_tasks: Query<(Entity, &mut ConvertByondMap)>,
 ) {
     for (entity, mut map_task) in map_tasks.iter_mut() {
-        if let Some(map_data) = future::block_on(future::poll_once(&mut map_task.0)) {
+        if let Some((map_data, objects, tags)) =
+            future::block_on(future::poll_once(&mut map_task.0))
+        {
             commands
                 .entity(entity)
                 .remove::<ConvertByondMap>()
-                .insert((map_data, SpatialBundle::default()))
+                .insert((
+                    map_data,
+                    SpatialBundle::default(),
+                    map_objects::PendingMapObjects(objects),
+                    map_objects::PendingTileTags(tags),
+                ))
                 .networked();
             info!("Map conversion finished and applied (entity={:?})", entity);
         }
@@ -0,0 +1,265 @@
+use std::fs::read_to_string;
+
+use bevy::{ecs::system::EntityCommands, prelude::*, utils::HashMap};
+use byond::tgm::conversion::{ObjectPlacement, TileObjectTag};
+use maps::TileMap;
+use networking::{is_server, scene::NetworkSceneBundle};
+use serde::Deserialize;
+
+use crate::{
+    device_link::{DeviceLink, PendingTileLinks},
+    rng::GameRng,
+};
+
+/// Spawns BYOND map objects that aren't part of the tilemap as prefabs.
+pub struct MapObjectsPlugin;
+
+impl Plugin for MapObjectsPlugin {
+    fn build(&self, app: &mut App) {
+        if is_server(app) {
+            app.init_resource::<UnmappedObjects>()
+                .add_systems(Startup, (load_object_mapping, load_loot_tables))
+                .add_systems(Update, spawn_map_objects);
+        }
+    }
+}
+
+const OBJECT_MAPPING_FILE: &str = "assets/maps/byond_objects.ron";
+const LOOT_TABLES_FILE: &str = "assets/maps/loot_tables.ron";
+
+/// Objects waiting to be spawned once their tilemap exists.
+#[derive(Component)]
+pub struct PendingMapObjects(pub Vec<ObjectPlacement>);
+
+/// Links of objects converted into the tilemap, applied once they have spawned.
+#[derive(Component)]
+pub struct PendingTileTags(pub Vec<TileObjectTag>);
+
+/// Set when the map objects are restored from elsewhere, so they aren't spawned twice.
+#[derive(Resource)]
+pub struct SkipMapObjects;
+
+/// An object that was spawned as part of a map.
+#[derive(Component)]
+pub struct MapObject;
+
+/// Maps BYOND path prefixes to prefab scenes.
+#[derive(Resource, Default)]
+pub(crate) struct ObjectMapping {
+    prefabs: HashMap<String, String>,
+}
+
+impl ObjectMapping {
+    /// Finds the prefab for a path. The most specific prefix wins.
+    pub fn prefab(&self, byond_path: &str) -> Option<&str> {
+        longest_prefix(&self.prefabs, byond_path).map(String::as_str)
+    }
+}
+
+/// Finds the value of the most specific path prefix of a BYOND path.
+fn longest_prefix<'a, T>(values: &'a HashMap<String, T>, byond_path: &str) -> Option<&'a T> {
+    values
+        .iter()
+        .filter(|(prefix, _)| {
+            byond_path
+                .strip_prefix(prefix.as_str())
+                .map(|rest| rest.is_empty() || rest.starts_with('/'))
+                .unwrap_or(false)
+        })
+        .max_by_key(|(prefix, _)| prefix.len())
+        .map(|(_, value)| value)
+}
+
+/// Random items a BYOND loot spawner turns into.
+#[derive(Deserialize)]
+pub(crate) struct LootTable {
+    /// How many items are picked
+    rolls: u32,
+    entries: Vec<LootEntry>,
+}
+
+#[derive(Deserialize)]
+struct LootEntry {
+    /// `None` for rolls that spawn nothing
+    prefab: Option<String>,
+    weight: u32,
+}
+
+impl LootTable {
+    /// Picks the prefabs to spawn, each roll is weighted by the entries.
+    pub fn roll<'a>(&'a self, rng: &'a mut fastrand::Rng) -> impl Iterator<Item = &'a str> + 'a {
+        let total: u32 = self.entries.iter().map(|e| e.weight).sum();
+        (0..self.rolls).filter_map(move |_| {
+            if total == 0 {
+                return None;
+            }
+            let mut pick = rng.u32(..total);
+            self.entries
+                .iter()
+                .find(|entry| {
+                    if pick < entry.weight {
+                        return true;
+                    }
+                    pick -= entry.weight;
+                    false
+                })
+                .and_then(|entry| entry.prefab.as_deref())
+        })
+    }
+}
+
+/// Maps BYOND path prefixes of loot spawners to their tables.
+#[derive(Resource, Default)]
+pub(crate) struct LootTables {
+    tables: HashMap<String, LootTable>,
+}
+
+impl LootTables {
+    /// Finds the table for a path. The most specific prefix wins.
+    pub fn table(&self, byond_path: &str) -> Option<&LootTable> {
+        longest_prefix(&self.tables, byond_path)
+    }
+}
+
+/// Seeds the rolls of a loot spawner, so a map rolls the same items every time it's loaded with a seed.
+/// `index` tells apart spawners on the same tile.
+fn loot_key(position: UVec2, index: u64) -> u64 {
+    ((position.x as u64) << 40) | ((position.y as u64) << 16) | index
+}
+
+/// How often each BYOND path without a prefab appeared in the last loaded map.
+#[derive(Resource, Default)]
+pub(crate) struct UnmappedObjects {
+    pub counts: HashMap<String, usize>,
+}
+
+fn load_object_mapping(mut commands: Commands) {
+    let text = match read_to_string(OBJECT_MAPPING_FILE) {
+        Ok(t) => t,
+        Err(err) => {
+            warn!(error = %err, "Could not read {}", OBJECT_MAPPING_FILE);
+            commands.init_resource::<ObjectMapping>();
+            return;
+        }
+    };
+
+    let prefabs = match ron::from_str(&text) {
+        Ok(p) => p,
+        Err(err) => {
+            error!(error = %err, "Error parsing {}", OBJECT_MAPPING_FILE);
+            HashMap::default()
+        }
+    };
+    commands.insert_resource(ObjectMapping { prefabs });
+}
+
+fn load_loot_tables(mut commands: Commands) {
+    let text = match read_to_string(LOOT_TABLES_FILE) {
+        Ok(t) => t,
+        Err(err) => {
+            warn!(error = %err, "Could not read {}", LOOT_TABLES_FILE);
+            commands.init_resource::<LootTables>();
+            return;
+        }
+    };
+
+    let tables = match ron::from_str(&text) {
+        Ok(t) => t,
+        Err(err) => {
+            error!(error = %err, "Error parsing {}", LOOT_TABLES_FILE);
+            HashMap::default()
+        }
+    };
+    commands.insert_resource(LootTables { tables });
+}
+
+#[allow(clippy::too_many_arguments)]
+fn spawn_map_objects(
+    maps: Query<(Entity, &PendingMapObjects, Option<&PendingTileTags>), With<TileMap>>,
+    mapping: Res<ObjectMapping>,
+    loot: Res<LootTables>,
+    rng: Res<GameRng>,
+    mut tile_links: ResMut<PendingTileLinks>,
+    mut unmapped: ResMut<UnmappedObjects>,
+    asset_server: Res<AssetServer>,
+    skip: Option<Res<SkipMapObjects>>,
+    mut commands: Commands,
+) {
+    for (map_entity, pending, tags) in maps.iter() {
+        commands
+            .entity(map_entity)
+            .remove::<(PendingMapObjects, PendingTileTags)>();
+        if skip.is_some() {
+            continue;
+        }
+        unmapped.counts.clear();
+
+        for tag in tags.iter().flat_map(|t| t.0.iter()) {
+            tile_links
+                .0
+                .insert((tag.tile_position, tag.layer), tag.id_tag.clone());
+        }
+
+        let mut spawned = 0;
+        let mut looted = 0;
+        let mut spawners_on_tile: HashMap<UVec2, u64> = HashMap::default();
+        for placement in pending.0.iter() {
+            let transform = Transform {
+                translation: Vec3::new(
+                    placement.tile_position.x as f32,
+                    0.0,
+                    placement.tile_position.y as f32,
+                ),
+                rotation: placement.direction.rotation(),
+                ..Default::default()
+            };
+            if let Some(prefab) = mapping.prefab(&placement.byond_path) {
+                let mut object = spawn_prefab(&mut commands, &asset_server, prefab, transform);
+                if let Some(channel) = placement.id_tag.clone() {
+                    object.insert(DeviceLink { channel });
+                }
+                spawned += 1;
+            } else if let Some(table) = loot.table(&placement.byond_path) {
+                let index = spawners_on_tile.entry(placement.tile_position).or_default();
+                let mut rolls = rng.for_key(loot_key(placement.tile_position, *index), "loot");
+                *index += 1;
+                for prefab in table.roll(&mut rolls) {
+                    spawn_prefab(&mut commands, &asset_server, prefab, transform);
+                    looted += 1;
+                }
+            } else {
+                *unmapped
+                    .counts
+                    .entry(placement.byond_path.clone())
+                    .or_default() += 1;
+            }
+        }
+
+        for (path, count) in unmapped.counts.iter() {
+            debug!(path = path.as_str(), count, "No prefab for map object");
+        }
+        info!(
+            spawned,
+            looted,
+            unmapped = unmapped.counts.values().sum::<usize>(),
+            unmapped_paths = unmapped.counts.len(),
+            "Spawned map objects"
+        );
+    }
+}
+
+fn spawn_prefab<'w, 's, 'a>(
+    commands: &'a mut Commands<'w, 's>,
+    asset_server: &AssetServer,
+    prefab: &str,
+    transform: Transform,
+) -> EntityCommands<'w, 's, 'a> {
+    commands.spawn((
+        NetworkSceneBundle {
+            scene: asset_server.load(format!("{}.scn.ron", prefab)).into(),
+            transform,
+            ..Default::default()
+        },
+        MapObject,
+    ))
+}
@@ -0,0 +1,63 @@
+use bevy::prelude::*;
+use maps::{MapStyle, TileMap};
+use networking::is_server;
+
+use crate::{
+    config::ServerConfig,
+    console::{
+        ArgumentKind, CommandContext, CommandResult, ConsoleAppExt, ConsoleCommand, PermissionLevel,
+    },
+    rng::GameRng,
+};
+
+/// Applies the turf theme from the server config and seeds turf variants with the round seed.
+pub struct MapThemePlugin;
+
+impl Plugin for MapThemePlugin {
+    fn build(&self, app: &mut App) {
+        if !is_server(app) {
+            return;
+        }
+
+        let theme = app
+            .world
+            .get_resource::<ServerConfig>()
+            .and_then(|config| config.map_theme.clone());
+        let variant_seed = app
+            .world
+            .get_resource_mut::<GameRng>()
+            .map(|mut rng| rng.stream("turf_variants").u64(..))
+            .unwrap_or_default();
+        app.insert_resource(MapStyle {
+            theme,
+            variant_seed,
+        })
+        .add_console_command(ConsoleCommand {
+            name: "theme",
+            description: "Changes the turf textures of the map, \"default\" removes the theme",
+            parameters: &[("theme", ArgumentKind::Word)],
+            permission: PermissionLevel::Admin,
+            handler: theme_command,
+        });
+    }
+}
+
+fn theme_command(world: &mut World, context: &CommandContext) -> CommandResult {
+    let theme = match context.text(0) {
+        "default" => None,
+        // Themes are folder names, so don't let them point elsewhere
+        theme if !theme.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') => {
+            return Err(format!("{} is not a valid theme name", theme));
+        }
+        theme => Some(theme.to_owned()),
+    };
+    world.resource_mut::<MapStyle>().theme = theme.clone();
+    let mut maps = world.query::<&mut TileMap>();
+    for mut map in maps.iter_mut(world) {
+        map.set_theme(theme.clone());
+    }
+    Ok(match theme {
+        Some(theme) => format!("Turfs now use the {} theme", theme),
+        None => "Turfs now use the default textures".into(),
+    })
+}
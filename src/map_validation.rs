@@ -0,0 +1,615 @@
+use std::{fmt, fs, path::Path};
+
+use bevy::{app::AppExit, asset::AssetPathId, prelude::*, utils::HashMap};
+use maps::{TileMapData, CARP_SPAWN_LANDMARK};
+use networking::is_server;
+
+use crate::{
+    map_objects::{ObjectMapping, PendingMapObjects},
+    navigation::{NavGrid, NavTile},
+};
+
+/// Checks maps for mistakes when they are loaded, before anyone plays on them.
+/// Maps with errors stop the server, warnings are logged and written to [`REPORT_FILE`].
+pub struct MapValidationPlugin;
+
+impl Plugin for MapValidationPlugin {
+    fn build(&self, app: &mut App) {
+        if is_server(app) {
+            app.add_systems(Update, validate_loaded_maps);
+        }
+    }
+}
+
+const TILE_DEFINITION_FOLDER: &str = "assets/tilemap";
+const REPORT_FILE: &str = "map_report.txt";
+/// Areas with this prefix are outside the station and don't have to be enclosed
+const SPACE_AREA_PREFIX: &str = "/area/space";
+/// BYOND objects that are doors, which are spawned from the object mapping
+const DOOR_OBJECT_PREFIX: &str = "/obj/machinery/door";
+
+/// What matters about a tile scene for validation.
+struct TileDefinition {
+    /// Creatures can't walk over it
+    blocks: bool,
+    door: bool,
+}
+
+/// All tile scenes in the tilemap asset folder, read from disk so maps can be checked before they spawn.
+pub struct TileDefinitions(HashMap<AssetPathId, TileDefinition>);
+
+impl TileDefinitions {
+    pub fn load() -> Self {
+        let mut definitions = HashMap::default();
+        read_definitions(Path::new(TILE_DEFINITION_FOLDER), &mut definitions);
+        Self(definitions)
+    }
+}
+
+fn read_definitions(folder: &Path, definitions: &mut HashMap<AssetPathId, TileDefinition>) {
+    let entries = match fs::read_dir(folder) {
+        Ok(e) => e,
+        Err(err) => {
+            warn!(error = %err, "Could not read tile definitions in {}", folder.display());
+            return;
+        }
+    };
+
+    for path in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+        if path.is_dir() {
+            read_definitions(&path, definitions);
+            continue;
+        }
+        let Some(asset_path) = path
+            .strip_prefix("assets")
+            .ok()
+            .map(|p| p.to_string_lossy().replace('\\', "/"))
+            .filter(|p| p.ends_with(".scn.ron"))
+        else {
+            continue;
+        };
+        let text = match fs::read_to_string(&path) {
+            Ok(t) => t,
+            Err(err) => {
+                warn!(error = %err, "Could not read tile definition {}", path.display());
+                continue;
+            }
+        };
+        definitions.insert(
+            asset_path.as_str().into(),
+            TileDefinition {
+                blocks: text.contains("\"ssnt::navigation::BlocksTile\""),
+                door: text.contains("\"ssnt::door::Door\""),
+            },
+        );
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Severity {
+    /// The map can't be played on
+    Error,
+    Warning,
+}
+
+#[derive(Clone, Debug)]
+pub struct MapIssue {
+    pub severity: Severity,
+    pub message: String,
+    /// The tile the issue is on, if it's about a specific place
+    pub position: Option<UVec2>,
+}
+
+impl fmt::Display for MapIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(f, "{}: {}", severity, self.message)?;
+        if let Some(position) = self.position {
+            write!(f, " at ({}, {})", position.x, position.y)?;
+        }
+        Ok(())
+    }
+}
+
+/// Everything wrong with a map.
+#[derive(Default)]
+pub struct MapReport {
+    pub issues: Vec<MapIssue>,
+}
+
+impl MapReport {
+    fn add(&mut self, severity: Severity, message: String, position: Option<UVec2>) {
+        self.issues.push(MapIssue {
+            severity,
+            message,
+            position,
+        });
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.issues.iter().any(|i| i.severity == Severity::Error)
+    }
+
+    fn write(&self, path: &str) -> std::io::Result<()> {
+        let mut text = String::new();
+        if self.issues.is_empty() {
+            text.push_str("No issues found\n");
+        }
+        for issue in self.issues.iter() {
+            text.push_str(&issue.to_string());
+            text.push('\n');
+        }
+        fs::write(path, text)
+    }
+}
+
+/// Checks the tiles, spawn points and areas of a map.
+pub fn validate(data: &TileMapData, definitions: &TileDefinitions) -> MapReport {
+    let mut report = MapReport::default();
+    let position = |index: usize| {
+        let index = index as u32;
+        UVec2::new(index % data.size.x, index / data.size.x)
+    };
+    let tile_index = |position: UVec2| {
+        (position.x < data.size.x && position.y < data.size.y)
+            .then(|| (position.y * data.size.x + position.x) as usize)
+    };
+
+    // Missing definitions are reported once per scene, there are usually many tiles using it
+    let mut missing: HashMap<(AssetPathId, &str), (UVec2, usize)> = HashMap::default();
+    for (index, tile) in data.tiles.iter().enumerate() {
+        let layers = [
+            (tile.turf, "turf"),
+            (tile.furniture, "furniture"),
+            (tile.pipe, "pipe"),
+        ]
+        .into_iter()
+        .chain(tile.high_mounts.iter().map(|&m| (m, "wall mount")));
+        for (id, layer) in layers {
+            let Some(id) = id.filter(|id| !definitions.0.contains_key(id)) else {
+                continue;
+            };
+            missing.entry((id, layer)).or_insert((position(index), 0)).1 += 1;
+        }
+    }
+    let mut missing: Vec<_> = missing.into_iter().collect();
+    missing.sort_by_key(|(_, (first, _))| (first.y, first.x));
+    for ((_, layer), (first, count)) in missing {
+        // Without a turf the tile would be space where the map wants a floor or wall
+        let severity = if layer == "turf" {
+            Severity::Error
+        } else {
+            Severity::Warning
+        };
+        report.add(
+            severity,
+            format!("{} tiles use a {} that doesn't exist", count, layer),
+            Some(first),
+        );
+    }
+
+    let definition = |id: Option<AssetPathId>| id.and_then(|id| definitions.0.get(&id));
+    let tiles = data
+        .tiles
+        .iter()
+        .map(|tile| {
+            let turf = definition(tile.turf);
+            let furniture = definition(tile.furniture);
+            if tile.turf.is_none() {
+                NavTile::Blocked
+            } else if furniture.is_some_and(|f| f.door) {
+                NavTile::Door {
+                    entity: Entity::PLACEHOLDER,
+                    open: false,
+                    bolted: false,
+                }
+            } else if turf.is_some_and(|t| t.blocks) || furniture.is_some_and(|f| f.blocks) {
+                NavTile::Blocked
+            } else {
+                NavTile::Walkable
+            }
+        })
+        .collect();
+    let grid = NavGrid::from_tiles(data.size, tiles);
+    let is_space = |position: UVec2| {
+        tile_index(position)
+            .and_then(|index| data.tiles.get(index))
+            .map_or(true, |tile| tile.turf.is_none())
+    };
+
+    let spawns: Vec<_> = data
+        .job_spawn_positions
+        .iter()
+        .filter(|(landmark, _)| landmark.as_str() != CARP_SPAWN_LANDMARK)
+        .flat_map(|(_, positions)| positions.iter().copied())
+        .collect();
+    if spawns.is_empty() {
+        report.add(Severity::Error, "The map has no spawn points".into(), None);
+    }
+    let mut landmarks: Vec<_> = data
+        .job_spawn_positions
+        .iter()
+        .filter(|(landmark, _)| landmark.as_str() != CARP_SPAWN_LANDMARK)
+        .collect();
+    landmarks.sort_by_key(|(landmark, _)| landmark.as_str());
+    for (landmark, positions) in landmarks {
+        for &spawn in positions.iter() {
+            if tile_index(spawn).is_none() {
+                report.add(
+                    Severity::Warning,
+                    format!("Spawn point {} is outside the map", landmark),
+                    Some(spawn),
+                );
+            } else if is_space(spawn) {
+                report.add(
+                    Severity::Warning,
+                    format!("Spawn point {} is in space", landmark),
+                    Some(spawn),
+                );
+            } else if grid.get(spawn) == NavTile::Blocked {
+                report.add(
+                    Severity::Warning,
+                    format!("Spawn point {} is on a blocked tile", landmark),
+                    Some(spawn),
+                );
+            }
+        }
+    }
+
+    let mut area_tiles = vec![Vec::new(); data.areas.len()];
+    for (index, tile) in data.tiles.iter().enumerate() {
+        if let Some(tiles) = tile.area.and_then(|a| area_tiles.get_mut(a as usize)) {
+            tiles.push(position(index));
+        }
+    }
+    let is_station_area = |area: usize| !data.areas[area].starts_with(SPACE_AREA_PREFIX);
+    let area_of = |position: UVec2| {
+        tile_index(position)
+            .and_then(|index| data.tiles[index].area)
+            .map(usize::from)
+    };
+
+    for (area, tiles) in area_tiles.iter().enumerate() {
+        if tiles.is_empty() && is_station_area(area) {
+            report.add(
+                Severity::Warning,
+                format!("Area {} has no tiles", data.areas[area]),
+                None,
+            );
+        }
+    }
+
+    // Air escapes through everything that can be walked over, except closed doors.
+    // Flooding from space reaches the tiles next to the hole in an area first.
+    let edge = |position: UVec2| {
+        position.x == 0
+            || position.y == 0
+            || position.x + 1 == data.size.x
+            || position.y + 1 == data.size.y
+    };
+    let leak_starts = (0..data.tiles.len())
+        .map(position)
+        .filter(|&p| is_space(p) || (edge(p) && grid.get(p) == NavTile::Walkable));
+    let leaked = grid.flood_fill(leak_starts, |position, tile| {
+        tile == NavTile::Walkable || is_space(position)
+    });
+    let mut leaks = vec![None; data.areas.len()];
+    for &position in leaked.iter().filter(|&&p| !is_space(p)) {
+        if let Some(leak) = area_of(position).and_then(|a| leaks.get_mut(a)) {
+            leak.get_or_insert(position);
+        }
+    }
+    for (area, leak) in leaks.into_iter().enumerate() {
+        if let Some(leak) = leak.filter(|_| is_station_area(area)) {
+            report.add(
+                Severity::Warning,
+                format!(
+                    "Area {} is not enclosed by walls and leaks into space",
+                    data.areas[area]
+                ),
+                Some(leak),
+            );
+        }
+    }
+
+    // Doors can be opened by someone, so only walls make a room unreachable
+    if !spawns.is_empty() {
+        let reached = grid.flood_fill(spawns.iter().copied(), |_, tile| tile != NavTile::Blocked);
+        let mut reachable = vec![false; data.areas.len()];
+        for area in reached.into_iter().filter_map(area_of) {
+            if let Some(reachable) = reachable.get_mut(area) {
+                *reachable = true;
+            }
+        }
+        for (area, tiles) in area_tiles.iter().enumerate() {
+            if reachable[area] || !is_station_area(area) {
+                continue;
+            }
+            // Areas made of nothing but walls can't be walked into anyway
+            let Some(&walkable) = tiles.iter().find(|&&t| grid.get(t) != NavTile::Blocked) else {
+                continue;
+            };
+            report.add(
+                Severity::Warning,
+                format!(
+                    "Area {} can't be reached from any spawn point",
+                    data.areas[area]
+                ),
+                Some(walkable),
+            );
+        }
+    }
+
+    report
+}
+
+/// Warns about doors that won't spawn because there's no prefab for them.
+fn check_door_objects(
+    report: &mut MapReport,
+    objects: &PendingMapObjects,
+    mapping: &ObjectMapping,
+) {
+    let mut unmapped: HashMap<&str, (UVec2, usize)> = HashMap::default();
+    for placement in objects.0.iter() {
+        if placement.byond_path.starts_with(DOOR_OBJECT_PREFIX)
+            && mapping.prefab(&placement.byond_path).is_none()
+        {
+            unmapped
+                .entry(placement.byond_path.as_str())
+                .or_insert((placement.tile_position, 0))
+                .1 += 1;
+        }
+    }
+    let mut unmapped: Vec<_> = unmapped.into_iter().collect();
+    unmapped.sort_by_key(|(path, _)| *path);
+    for (path, (first, count)) in unmapped {
+        report.add(
+            Severity::Warning,
+            format!("{} doors of type {} have no prefab", count, path),
+            Some(first),
+        );
+    }
+}
+
+fn validate_loaded_maps(
+    maps: Query<(&TileMapData, Option<&PendingMapObjects>), Added<TileMapData>>,
+    mapping: Res<ObjectMapping>,
+    mut exit: EventWriter<AppExit>,
+) {
+    for (data, objects) in maps.iter() {
+        let mut report = validate(data, &TileDefinitions::load());
+        if let Some(objects) = objects {
+            check_door_objects(&mut report, objects, &mapping);
+        }
+
+        for issue in report.issues.iter() {
+            match issue.severity {
+                Severity::Error => error!("Map {}", issue),
+                Severity::Warning => warn!("Map {}", issue),
+            }
+        }
+        if let Err(err) = report.write(REPORT_FILE) {
+            warn!(error = %err, "Could not write {}", REPORT_FILE);
+        }
+
+        if report.has_errors() {
+            error!(
+                "The map can't be played on, see the errors above or in {}. Stopping the server.",
+                REPORT_FILE
+            );
+            exit.send(AppExit);
+        } else {
+            info!(
+                warnings = report.issues.len(),
+                "Map validated, details are in {}", REPORT_FILE
+            );
+        }
+    }
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use byond::tgm::conversion::ObjectPlacement;
+    use maps::{Direction, TileData};
+
+    use super::*;
+
+    const FLOOR: &str = "tilemap/turfs/floor.scn.ron";
+    const WALL: &str = "tilemap/turfs/wall.scn.ron";
+    const DOOR: &str = "tilemap/furniture/airlock.scn.ron";
+    const MISSING: &str = "tilemap/turfs/missing.scn.ron";
+    const HALL: &str = "/area/station/hall";
+    const STORAGE: &str = "/area/station/storage";
+
+    fn definitions() -> TileDefinitions {
+        let definition =
+            |path: &str, blocks, door| (AssetPathId::from(path), TileDefinition { blocks, door });
+        TileDefinitions(HashMap::from_iter([
+            definition(FLOOR, false, false),
+            definition(WALL, true, false),
+            definition(DOOR, true, true),
+        ]))
+    }
+
+    /// Builds a map from rows of tiles, the first row is y = 0.
+    /// `#` is a wall, `.` floor of the hall, `,` floor of the storage room, `D` a door in the hall,
+    /// `S` a spawn point in the hall, `?` a turf that doesn't exist and a space is space.
+    fn map(rows: &[&str]) -> TileMapData {
+        let size = UVec2::new(rows[0].len() as u32, rows.len() as u32);
+        let mut tiles = Vec::new();
+        let mut spawns = Vec::new();
+        for (y, row) in rows.iter().enumerate() {
+            for (x, symbol) in row.chars().enumerate() {
+                let (turf, furniture, area) = match symbol {
+                    '#' => (Some(WALL), None, None),
+                    '.' => (Some(FLOOR), None, Some(0)),
+                    ',' => (Some(FLOOR), None, Some(1)),
+                    'D' => (Some(FLOOR), Some(DOOR), Some(0)),
+                    'S' => {
+                        spawns.push(UVec2::new(x as u32, y as u32));
+                        (Some(FLOOR), None, Some(0))
+                    }
+                    '?' => (Some(MISSING), None, Some(0)),
+                    _ => (None, None, None),
+                };
+                tiles.push(TileData {
+                    turf: turf.map(AssetPathId::from),
+                    furniture: furniture.map(AssetPathId::from),
+                    area,
+                    ..Default::default()
+                });
+            }
+        }
+        // The storage room only exists if the map has tiles of it
+        let mut areas = vec![HALL.to_owned()];
+        if rows.iter().any(|row| row.contains(',')) {
+            areas.push(STORAGE.into());
+        }
+        let mut job_spawn_positions = HashMap::default();
+        if !spawns.is_empty() {
+            job_spawn_positions.insert("assistant".to_owned(), spawns);
+        }
+        TileMapData {
+            size,
+            tiles,
+            job_spawn_positions,
+            areas,
+        }
+    }
+
+    fn issues(report: &MapReport) -> Vec<String> {
+        report.issues.iter().map(|i| i.to_string()).collect()
+    }
+
+    #[test]
+    fn enclosed_reachable_map_has_no_issues() {
+        let data = map(&[
+            "#####", //
+            "#S..#", "##D##", "#,,,#", "#####",
+        ]);
+
+        let report = validate(&data, &definitions());
+        assert!(report.issues.is_empty(), "{:?}", issues(&report));
+    }
+
+    #[test]
+    fn missing_spawns_and_turfs_are_errors() {
+        let data = map(&[
+            "#####", //
+            "#..?#", "##D##", "#,?,#", "#####",
+        ]);
+
+        let report = validate(&data, &definitions());
+        assert!(report.has_errors());
+        assert_eq!(
+            issues(&report),
+            [
+                "error: 2 tiles use a turf that doesn't exist at (3, 1)",
+                "error: The map has no spawn points",
+            ]
+        );
+    }
+
+    #[test]
+    fn misplaced_spawn_points_are_warnings() {
+        let mut data = map(&[
+            "       ", //
+            " ##### ", " #S..# ", " ##### ", "       ",
+        ]);
+        data.job_spawn_positions.insert(
+            "security".into(),
+            vec![UVec2::new(1, 1), UVec2::new(0, 0), UVec2::new(9, 9)],
+        );
+        // Carp are meant to spawn in space
+        data.job_spawn_positions
+            .insert(CARP_SPAWN_LANDMARK.into(), vec![UVec2::new(0, 4)]);
+
+        let report = validate(&data, &definitions());
+        assert!(!report.has_errors());
+        assert_eq!(
+            issues(&report),
+            [
+                "warning: Spawn point security is on a blocked tile at (1, 1)",
+                "warning: Spawn point security is in space at (0, 0)",
+                "warning: Spawn point security is outside the map at (9, 9)",
+            ]
+        );
+    }
+
+    #[test]
+    fn area_with_a_hole_to_space_leaks() {
+        let data = map(&[
+            "       ", //
+            " ##### ", " #S..  ", " ##### ", "       ",
+        ]);
+
+        let report = validate(&data, &definitions());
+        assert_eq!(
+            issues(&report),
+            ["warning: Area /area/station/hall is not enclosed by walls and leaks into space at (4, 2)"]
+        );
+    }
+
+    #[test]
+    fn walled_off_and_empty_areas_are_warnings() {
+        let mut data = map(&[
+            "#########", //
+            "#S..#,,,#",
+            "#########",
+        ]);
+        data.areas.push("/area/station/unused".into());
+        // Space areas are expected to be empty
+        data.areas.push("/area/space/nearstation".into());
+
+        let report = validate(&data, &definitions());
+        assert_eq!(
+            issues(&report),
+            [
+                "warning: Area /area/station/unused has no tiles",
+                "warning: Area /area/station/storage can't be reached from any spawn point at (5, 1)",
+            ]
+        );
+    }
+
+    #[test]
+    fn doors_without_a_prefab_are_reported_once_per_type() {
+        let placement = |byond_path: &str, x| ObjectPlacement {
+            byond_path: byond_path.into(),
+            tile_position: UVec2::new(x, 0),
+            direction: Direction::North,
+            id_tag: None,
+        };
+        let objects = PendingMapObjects(vec![
+            placement("/obj/machinery/door/airlock/engineering", 3),
+            placement("/obj/item/wrench", 4),
+            placement("/obj/machinery/door/airlock/engineering", 5),
+        ]);
+
+        let mut report = MapReport::default();
+        check_door_objects(&mut report, &objects, &ObjectMapping::default());
+        assert_eq!(
+            issues(&report),
+            ["warning: 2 doors of type /obj/machinery/door/airlock/engineering have no prefab at (3, 0)"]
+        );
+    }
+
+    #[test]
+    fn report_lists_every_issue() {
+        let data = map(&["#?#"]);
+        let report = validate(&data, &definitions());
+        let path = std::env::temp_dir().join(format!("ssnt-map-report-{}.txt", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        report.write(path).unwrap();
+        let text = fs::read_to_string(path).unwrap();
+        assert_eq!(text.lines().collect::<Vec<_>>(), issues(&report));
+        fs::remove_file(path).unwrap();
+
+        MapReport::default().write(path).unwrap();
+        assert_eq!(fs::read_to_string(path).unwrap(), "No issues found\n");
+        fs::remove_file(path).unwrap();
+    }
+}
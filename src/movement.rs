@@ -1,24 +1,50 @@
-use std::time::Duration;
+use bevy::{ecs::query::Has, prelude::*, reflect::TypeUuid};
+use bevy_rapier3d::prelude::{
+    Collider, CollisionGroups, Damping, LockedAxes, RigidBodyDisabled, Velocity,
+};
+use maps::FloorHeights;
+use networking::{
+    component::AppExt as ComponentAppExt,
+    messaging::{AppExt, Finite, InvalidMessage, MessageEvent, MessageReceivers, MessageSender},
+    spawning::ClientControls,
+    transform::ClientMovement,
+    variable::{NetworkVar, ServerVar},
+    NetworkManager, NetworkSet, Networked, Players, ServerEvent,
+};
+use physics::ColliderGroup;
+use serde::{Deserialize, Serialize};
 
 use crate::{
     body::{
         health::{BrainState, BrainStateEvent},
         Body,
     },
-    camera::{MainCamera, TopDownCamera},
-    combat::{ClientCombatModeStatus, CombatModeClient},
-    Player,
+    gravity::Weightless,
 };
-use bevy::{ecs::query::Has, math::Vec3Swizzles, prelude::*, time::common_conditions::on_timer};
-use bevy_rapier3d::prelude::{ExternalForce, ReadMassProperties, Velocity};
-use networking::{
-    messaging::{AppExt, MessageEvent, MessageReceivers, MessageSender},
-    spawning::{ClientControlled, ClientControls},
-    transform::{ClientMovement, ClientMovementClient},
-    NetworkManager, NetworkSet, Players, ServerEvent,
+
+#[cfg(feature = "client")]
+use {
+    crate::{
+        camera::{MainCamera, TopDownCamera},
+        combat::{ClientCombatModeStatus, CombatModeClient},
+        gravity::{WallContact, WeightlessClient},
+        Player,
+    },
+    bevy::{math::Vec3Swizzles, time::common_conditions::on_timer},
+    bevy_rapier3d::prelude::{ExternalForce, ReadMassProperties},
+    networking::{spawning::ClientControlled, transform::ClientMovementClient},
+    std::time::Duration,
 };
-use serde::{Deserialize, Serialize};
 
+use self::speed::SpeedModifiers;
+#[cfg(feature = "client")]
+use self::speed::SpeedModifiersClient;
+
+pub mod speed;
+pub mod stamina;
+
+#[cfg(feature = "client")]
+#[allow(clippy::too_many_arguments)]
 pub fn movement_system(
     time: Res<Time>,
     keyboard_input: Res<Input<KeyCode>>,
@@ -26,19 +52,36 @@ pub fn movement_system(
         (
             Entity,
             &mut Player,
+            &GlobalTransform,
             &Velocity,
             Option<&mut ExternalForce>,
             &ReadMassProperties,
             Has<ClientMovementClient>,
+            Has<StunnedClient>,
+            Option<&SpeedModifiersClient>,
+            Option<&WeightlessClient>,
         ),
         With<ClientControlled>,
     >,
     camera_query: Query<&TopDownCamera, With<MainCamera>>,
+    walls: WallContact,
     mut commands: Commands,
 ) {
-    for (entity, mut player, velocity, forces, mass_properties, can_move) in query.iter_mut() {
+    for (
+        entity,
+        mut player,
+        transform,
+        velocity,
+        forces,
+        mass_properties,
+        can_move,
+        stunned,
+        modifiers,
+        weightless,
+    ) in query.iter_mut()
+    {
         // Reset force if we can't move
-        if !can_move {
+        if !can_move || stunned {
             if let Some(mut forces) = forces {
                 forces.force = Vec3::ZERO;
             }
@@ -59,22 +102,16 @@ pub fn movement_system(
             .xz();
         player.target_direction = target_direction;
 
-        // What is our ideal speed
-        let mut ideal_speed: Vec2 = target_direction * player.max_velocity;
-
-        // Prevent diagonal movement being twice as fast
-        if target_direction.length_squared() > f32::EPSILON {
-            ideal_speed /= target_direction.length();
-        }
-
-        // Move target velocity towards ideal speed, by acceleration
-        let difference: Vec2 = ideal_speed - player.target_velocity;
-        let step: f32 = player.acceleration * time.delta_seconds();
-        let difference_magnitude = difference.length();
-        if difference_magnitude < step || difference_magnitude < f32::EPSILON {
-            player.target_velocity = ideal_speed;
+        // Without gravity there is nothing to walk on, unless there's a wall to push off from
+        let drifting = weightless.is_some_and(|w| w.is_floating())
+            && !walls.near_wall(transform.translation());
+        if drifting {
+            player.target_velocity =
+                drift_velocity(velocity.linvel.xz(), target_direction, time.delta_seconds());
         } else {
-            player.target_velocity += difference / difference_magnitude * step;
+            // Movement is simulated here, so the server only tells us what changes our speed
+            let speed = modifiers.map_or(player.max_velocity, |m| m.speed(player.max_velocity));
+            walk_towards(&mut player, target_direction, speed, time.delta_seconds());
         }
 
         // Calculate needed force to reach target velocity in one frame
@@ -99,9 +136,369 @@ pub fn movement_system(
     }
 }
 
+/// Moves the target velocity of a walking creature towards where it wants to go.
+#[cfg(feature = "client")]
+fn walk_towards(player: &mut Player, target_direction: Vec2, speed: f32, delta_seconds: f32) {
+    // What is our ideal speed
+    let mut ideal_speed: Vec2 = target_direction * speed;
+
+    // Prevent diagonal movement being twice as fast
+    if target_direction.length_squared() > f32::EPSILON {
+        ideal_speed /= target_direction.length();
+    }
+
+    // Move target velocity towards ideal speed, by acceleration
+    let difference: Vec2 = ideal_speed - player.target_velocity;
+    // Slowed creatures also take longer to get up to speed
+    let speed_multiplier = if player.max_velocity > f32::EPSILON {
+        speed / player.max_velocity
+    } else {
+        1.0
+    };
+    let step: f32 = player.acceleration * speed_multiplier * delta_seconds;
+    let difference_magnitude = difference.length();
+    if difference_magnitude < step || difference_magnitude < f32::EPSILON {
+        player.target_velocity = ideal_speed;
+    } else {
+        player.target_velocity += difference / difference_magnitude * step;
+    }
+}
+
+/// Velocity of a creature floating without gravity.
+/// Inputs only give it a small push and nothing slows it down again.
+#[cfg(feature = "client")]
+fn drift_velocity(current: Vec2, direction: Vec2, delta_seconds: f32) -> Vec2 {
+    let push = direction.normalize_or_zero() * DRIFT_ACCELERATION * delta_seconds;
+    (current + push).clamp_length_max(DRIFT_MAX_SPEED)
+}
+
+/// How quickly a floating creature can change its velocity, in m/s²
+#[cfg(feature = "client")]
+const DRIFT_ACCELERATION: f32 = 0.8;
+/// Fastest a floating creature can push itself
+#[cfg(feature = "client")]
+const DRIFT_MAX_SPEED: f32 = 2.5;
+
+/// Highest difference in floor height a creature can walk up, in meters.
+/// Anything higher blocks it like a wall, and paths don't lead over it.
+pub const MAX_STEP_HEIGHT: f32 = 0.35;
+/// How far ahead of a walking creature the floor is checked for steps
+#[cfg(feature = "client")]
+const STEP_PROBE_DISTANCE: f32 = 0.25;
+/// Steps lower than this are left to the physics engine
+#[cfg(feature = "client")]
+const MIN_STEP_HEIGHT: f32 = 0.01;
+
+/// Lifts walking creatures onto raised floors in front of them, like the step offset of a character controller.
+/// The capsule would otherwise get stuck on the edge of every catwalk.
+#[cfg(feature = "client")]
+fn step_up_system(
+    mut query: Query<
+        (
+            &Player,
+            &mut Transform,
+            &mut Velocity,
+            Option<&WeightlessClient>,
+        ),
+        (
+            With<ClientControlled>,
+            With<ClientMovementClient>,
+            Without<StunnedClient>,
+        ),
+    >,
+    floors: FloorHeights,
+) {
+    for (player, mut transform, mut velocity, weightless) in query.iter_mut() {
+        let direction = player.target_direction.normalize_or_zero();
+        if direction == Vec2::ZERO || weightless.is_some_and(|w| w.is_floating()) {
+            continue;
+        }
+
+        // Only creatures standing on the floor can step, not ones already falling
+        let feet = transform.translation;
+        if feet.y - floors.height_at(feet) > MIN_STEP_HEIGHT {
+            continue;
+        }
+
+        let ahead = feet + Vec3::new(direction.x, 0.0, direction.y) * STEP_PROBE_DISTANCE;
+        let step = floors.height_at(ahead) - feet.y;
+        if step > MIN_STEP_HEIGHT && step <= MAX_STEP_HEIGHT {
+            transform.translation.y += step;
+            velocity.linvel.y = velocity.linvel.y.max(0.0);
+        }
+    }
+}
+
+/// A creature landed after falling from high enough to get hurt.
+#[derive(Event)]
+pub struct FallEvent {
+    pub body: Entity,
+    /// How far it fell in meters
+    pub height: f32,
+}
+
+/// Falls from lower than this are harmless
+const FALL_DAMAGE_HEIGHT: f32 = 1.5;
+/// How far above the floor a creature can be while still standing on it
+const GROUNDED_DISTANCE: f32 = 0.05;
+
+/// Highest point a creature reached since it last stood on the floor.
+#[derive(Component)]
+#[component(storage = "SparseSet")]
+struct Airborne {
+    peak: f32,
+}
+
+/// Watches creatures leave the floor and reports how far they fell when they land.
+fn detect_falls(
+    mut bodies: Query<(Entity, &Transform, Option<&mut Airborne>, Has<Weightless>), With<Body>>,
+    floors: FloorHeights,
+    mut falls: EventWriter<FallEvent>,
+    mut commands: Commands,
+) {
+    for (entity, transform, airborne, weightless) in bodies.iter_mut() {
+        let position = transform.translation;
+        let grounded = position.y - floors.height_at(position) < GROUNDED_DISTANCE;
+        match airborne {
+            // Floating creatures come down slowly when gravity returns
+            Some(_) if weightless => {
+                commands.entity(entity).remove::<Airborne>();
+            }
+            Some(mut airborne) if !grounded => {
+                airborne.peak = airborne.peak.max(position.y);
+            }
+            Some(airborne) => {
+                commands.entity(entity).remove::<Airborne>();
+                let height = airborne.peak - position.y;
+                if height > FALL_DAMAGE_HEIGHT {
+                    falls.send(FallEvent {
+                        body: entity,
+                        height,
+                    });
+                }
+            }
+            None if !grounded && !weightless => {
+                commands
+                    .entity(entity)
+                    .insert(Airborne { peak: position.y });
+            }
+            None => {}
+        }
+    }
+}
+
+/// A creature that was knocked down and can't move for a while.
+#[derive(Component, Networked)]
+#[networked(client = "StunnedClient")]
+pub struct Stunned {
+    duration: NetworkVar<f32>,
+    started: f32,
+}
+
+impl Stunned {
+    pub fn new(duration: f32, now: f32) -> Self {
+        Self {
+            duration: duration.into(),
+            started: now,
+        }
+    }
+}
+
+#[derive(Component, Default, TypeUuid, Networked)]
+#[uuid = "c7e2a915-5b3d-4f86-9e1a-2d8c6b4f0e37"]
+#[networked(server = "Stunned")]
+pub struct StunnedClient {
+    duration: ServerVar<f32>,
+}
+
+fn expire_stuns(stunned: Query<(Entity, &Stunned)>, time: Res<Time>, mut commands: Commands) {
+    let now = time.elapsed_seconds();
+    for (entity, stun) in stunned.iter() {
+        if now - stun.started >= *stun.duration {
+            commands.entity(entity).remove::<Stunned>();
+        }
+    }
+}
+
+/// Crouched creatures walk at this fraction of their speed
+const CROUCH_SPEED_MULTIPLIER: f32 = 0.5;
+
+/// A creature ducking down. It is half as tall and slow, and can hide behind cover.
+#[derive(Component, Default, Networked)]
+#[networked(client = "CrouchingClient")]
+pub struct Crouching {
+    crouched: NetworkVar<bool>,
+}
+
+impl Crouching {
+    pub fn is_crouched(&self) -> bool {
+        *self.crouched
+    }
+}
+
+#[derive(Component, Default, TypeUuid, Networked)]
+#[uuid = "6e1d8b42-3c9f-4a57-b0e8-5f2a7c4d9b13"]
+#[networked(server = "Crouching")]
+pub struct CrouchingClient {
+    crouched: ServerVar<bool>,
+}
+
+impl CrouchingClient {
+    pub fn is_crouched(&self) -> bool {
+        *self.crouched
+    }
+}
+
+/// Lets the collider system work with the crouch state on both sides.
+trait CrouchState: Component {
+    fn is_crouched(&self) -> bool;
+}
+
+impl CrouchState for Crouching {
+    fn is_crouched(&self) -> bool {
+        Crouching::is_crouched(self)
+    }
+}
+
+impl CrouchState for CrouchingClient {
+    fn is_crouched(&self) -> bool {
+        CrouchingClient::is_crouched(self)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct CrouchRequest {
+    crouched: bool,
+}
+
+fn receive_crouch_request(
+    mut messages: EventReader<MessageEvent<CrouchRequest>>,
+    players: Res<Players>,
+    controlled: Res<ClientControls>,
+    mut crouching: Query<&mut Crouching>,
+    mut commands: Commands,
+) {
+    for event in messages.iter() {
+        let Some(entity) = players
+            .get(event.connection)
+            .and_then(|player| controlled.controlled_entity(player.id))
+        else {
+            continue;
+        };
+        if let Ok(mut crouching) = crouching.get_mut(entity) {
+            if *crouching.crouched != event.message.crouched {
+                *crouching.crouched = event.message.crouched;
+            }
+        } else if event.message.crouched {
+            commands.entity(entity).insert(Crouching {
+                crouched: true.into(),
+            });
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn crouch_speed(
+    mut creatures: Query<
+        (&Crouching, &mut SpeedModifiers),
+        Or<(Changed<Crouching>, Added<SpeedModifiers>)>,
+    >,
+) {
+    for (crouching, mut modifiers) in creatures.iter_mut() {
+        let multiplier = if crouching.is_crouched() {
+            CROUCH_SPEED_MULTIPLIER
+        } else {
+            1.0
+        };
+        modifiers.set_multiplier("crouching", multiplier);
+    }
+}
+
+#[cfg(feature = "client")]
+fn client_toggle_crouch(
+    keys: Res<Input<KeyCode>>,
+    crouching: Query<&CrouchingClient, With<ClientControlled>>,
+    mut sender: MessageSender,
+) {
+    if !keys.just_pressed(KeyCode::C) {
+        return;
+    }
+
+    let crouched = crouching
+        .get_single()
+        .is_ok_and(|crouching| crouching.is_crouched());
+    sender.send_to_server(&CrouchRequest {
+        crouched: !crouched,
+    });
+}
+
+/// Shape of a character capsule while standing, kept to stand up again.
+#[derive(Component)]
+struct StandingCapsule {
+    half_height: f32,
+    center: f32,
+}
+
+/// Halves the height of character capsules while crouched, keeping their bottom on the floor.
+/// Runs on both sides so clients predict their own movement against the same colliders.
+fn update_crouch_colliders<T: CrouchState>(
+    changed: Query<(Entity, &T), Changed<T>>,
+    children: Query<&Children>,
+    mut colliders: Query<(
+        &mut Collider,
+        &mut Transform,
+        &CollisionGroups,
+        Option<&StandingCapsule>,
+    )>,
+    mut commands: Commands,
+) {
+    let upright = CollisionGroups::from(ColliderGroup::CharacterColliders);
+    for (body, state) in changed.iter() {
+        let crouched = state.is_crouched();
+        for child in children.iter_descendants(body) {
+            let Ok((mut collider, mut transform, groups, standing)) = colliders.get_mut(child)
+            else {
+                continue;
+            };
+            if *groups != upright {
+                continue;
+            }
+            match (crouched, standing) {
+                (true, None) => {
+                    let Some(capsule) = collider.as_capsule() else {
+                        continue;
+                    };
+                    let (half_height, radius) = (capsule.half_height(), capsule.radius());
+                    // Half of the full height, which includes the rounded ends
+                    let crouched_half_height = ((half_height - radius) / 2.0).max(0.0);
+                    commands.entity(child).insert(StandingCapsule {
+                        half_height,
+                        center: transform.translation.y,
+                    });
+                    *collider = Collider::capsule_y(crouched_half_height, radius);
+                    transform.translation.y -= half_height - crouched_half_height;
+                }
+                (false, Some(standing)) => {
+                    let Some(capsule) = collider.as_capsule() else {
+                        continue;
+                    };
+                    let radius = capsule.radius();
+                    *collider = Collider::capsule_y(standing.half_height, radius);
+                    transform.translation.y = standing.center;
+                    commands.entity(child).remove::<StandingCapsule>();
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(feature = "client")]
 const NORMAL_ROTATION_RADIANS_PER_SECOND: f32 = 5.0;
+#[cfg(feature = "client")]
 const COMBAT_ROTATION_RADIANS_PER_SECOND: f32 = 10.0;
 
+#[cfg(feature = "client")]
 fn character_rotation_system(
     time: Res<Time>,
     mut query: Query<
@@ -153,6 +550,7 @@ fn character_rotation_system(
     }
 }
 
+#[cfg(feature = "client")]
 fn movement_axis(input: &Res<Input<KeyCode>>, plus: KeyCode, minus: KeyCode) -> f32 {
     let mut axis = 0.0;
     if input.pressed(plus) {
@@ -164,6 +562,7 @@ fn movement_axis(input: &Res<Input<KeyCode>>, plus: KeyCode, minus: KeyCode) ->
     axis
 }
 
+#[cfg(feature = "client")]
 fn send_movement_update(
     // Require client control and already having a position from the server
     query: Query<
@@ -192,9 +591,17 @@ fn handle_movement_message(
     controls: Res<ClientControls>,
     players: Res<Players>,
     mut messages: EventReader<MessageEvent<MovementMessage>>,
+    mut invalid: EventWriter<InvalidMessage>,
     mut commands: Commands,
 ) {
     for event in messages.iter() {
+        if !event.message.is_finite() {
+            invalid.send(InvalidMessage {
+                connection: event.connection,
+                reason: "non-finite movement",
+            });
+            continue;
+        }
         let player = match players.get(event.connection) {
             Some(p) => p,
             None => continue,
@@ -224,6 +631,7 @@ fn handle_movement_message(
 
 // HACK: forces the client to be at a position
 // The code needs to die.
+#[cfg(feature = "client")]
 fn handle_force_position_client(
     mut query: Query<Entity, (With<ClientControlled>, With<Transform>)>,
     mut messages: EventReader<MessageEvent<ForcePositionMessage>>,
@@ -287,6 +695,12 @@ struct MovementMessage {
     rotation: Quat,
 }
 
+impl Finite for MovementMessage {
+    fn is_finite(&self) -> bool {
+        self.position.is_finite() && self.rotation.is_finite()
+    }
+}
+
 // TODO: Remove once movement is server authoritative
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ForcePositionMessage {
@@ -294,6 +708,7 @@ pub struct ForcePositionMessage {
     pub rotation: Quat,
 }
 
+#[cfg(feature = "client")]
 #[derive(Component)]
 #[component(storage = "SparseSet")]
 struct ForcePositionReceived;
@@ -314,10 +729,30 @@ fn restore_client_position(
     }
 }
 
+/// A body that went limp and falls over instead of staying upright.
+#[derive(Component, Networked)]
+#[networked(client = "RagdollClient")]
+pub struct Ragdoll {
+    dead: NetworkVar<bool>,
+}
+
+#[derive(Component, Default, TypeUuid, Networked)]
+#[uuid = "3f9b6d21-8a4e-4c57-b1d2-7e05a9c3f864"]
+#[networked(server = "Ragdoll")]
+pub struct RagdollClient {
+    dead: ServerVar<bool>,
+}
+
+/// Slows down limp bodies, so they settle and fall asleep instead of sliding around
+const RAGDOLL_DAMPING: Damping = Damping {
+    linear_damping: 2.0,
+    angular_damping: 4.0,
+};
+
 #[allow(clippy::too_many_arguments)]
 fn prevent_movement_when_unconcious(
     mut reader: EventReader<BrainStateEvent>,
-    bodies: Query<&Body>,
+    bodies: Query<(&Body, Has<RigidBodyDisabled>)>,
     mut transforms: Query<&mut Transform>,
     parents: Query<&Parent>,
     controls: Res<ClientControls>,
@@ -326,9 +761,9 @@ fn prevent_movement_when_unconcious(
     mut commands: Commands,
 ) {
     for event in reader.iter() {
-        let Some(body_entity) = parents
+        let Some((body_entity, frozen)) = parents
             .iter_ancestors(event.brain)
-            .find(|e| bodies.contains(*e))
+            .find_map(|e| bodies.get(e).ok().map(|(_, frozen)| (e, frozen)))
         else {
             continue;
         };
@@ -338,10 +773,9 @@ fn prevent_movement_when_unconcious(
         let mut entity = commands.entity(body_entity);
         match event.new_state {
             BrainState::Conscious => {
-                entity.insert((
+                entity.remove::<Ragdoll>().insert((
                     ClientMovement,
-                    bevy_rapier3d::prelude::LockedAxes::ROTATION_LOCKED_X
-                        | bevy_rapier3d::prelude::LockedAxes::ROTATION_LOCKED_Z,
+                    LockedAxes::ROTATION_LOCKED_X | LockedAxes::ROTATION_LOCKED_Z,
                 ));
                 transform.rotation = Quat::IDENTITY;
 
@@ -359,14 +793,64 @@ fn prevent_movement_when_unconcious(
                 }
             }
             BrainState::Unconscious | BrainState::Dead => {
-                entity
-                    .remove::<ClientMovement>()
-                    .insert(bevy_rapier3d::prelude::LockedAxes::default());
+                entity.remove::<ClientMovement>().insert(Ragdoll {
+                    dead: (event.new_state == BrainState::Dead).into(),
+                });
+                // Bodies held in place stay where they are instead of falling over
+                if !frozen {
+                    entity.insert(LockedAxes::default());
+                }
             }
         };
     }
 }
 
+/// Lets living characters walk over limp bodies, and gives the bodies back their collisions when they get up.
+/// Runs on both sides so clients predict their own movement against the same colliders.
+fn update_ragdoll_colliders<T: Component>(
+    added: Query<Entity, Added<T>>,
+    mut removed: RemovedComponents<T>,
+    still_limp: Query<(), With<T>>,
+    children: Query<&Children>,
+    mut colliders: Query<&mut CollisionGroups>,
+    mut commands: Commands,
+) {
+    let upright = CollisionGroups::from(ColliderGroup::CharacterColliders);
+    let limp = CollisionGroups::from(ColliderGroup::Corpse);
+
+    let changes = added.iter().map(|e| (e, true)).chain(
+        removed
+            .iter()
+            .filter(|&e| !still_limp.contains(e))
+            .map(|e| (e, false)),
+    );
+    for (body, is_limp) in changes.collect::<Vec<_>>() {
+        let Some(mut entity) = commands.get_entity(body) else {
+            continue;
+        };
+        if is_limp {
+            entity.insert(RAGDOLL_DAMPING);
+        } else {
+            entity.remove::<Damping>();
+        }
+
+        // Only the character capsule changes, limbs keep their own group
+        let (from, to) = if is_limp {
+            (upright, limp)
+        } else {
+            (limp, upright)
+        };
+        for child in children.iter_descendants(body) {
+            if let Ok(mut groups) = colliders.get_mut(child) {
+                if *groups == from {
+                    *groups = to;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "client")]
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, SystemSet)]
 pub enum MovementSystem {
     Update,
@@ -376,8 +860,13 @@ pub struct MovementPlugin;
 
 impl Plugin for MovementPlugin {
     fn build(&self, app: &mut App) {
-        app.add_network_message::<MovementMessage>()
-            .add_network_message::<ForcePositionMessage>();
+        app.add_network_message::<MovementMessage>("MovementMessage")
+            .add_network_message::<ForcePositionMessage>("ForcePositionMessage")
+            .add_networked_component::<Stunned, StunnedClient>()
+            .add_networked_component::<Ragdoll, RagdollClient>()
+            .add_network_message::<CrouchRequest>("CrouchRequest")
+            .add_networked_component::<Crouching, CrouchingClient>()
+            .add_plugins((speed::SpeedPlugin, stamina::StaminaPlugin));
 
         if app
             .world
@@ -385,35 +874,47 @@ impl Plugin for MovementPlugin {
             .unwrap()
             .is_client()
         {
+            #[cfg(feature = "client")]
             app.add_systems(
                 Update,
                 (
                     (
                         movement_system,
+                        step_up_system,
                         character_rotation_system,
                         send_movement_update.run_if(on_timer(Duration::from_millis(30))),
                     )
                         .chain()
                         .in_set(MovementSystem::Update),
                     handle_force_position_client,
+                    update_ragdoll_colliders::<RagdollClient>,
+                    client_toggle_crouch,
+                    update_crouch_colliders::<CrouchingClient>,
                 ),
             );
         } else {
-            app.add_systems(
-                Update,
-                (
-                    handle_movement_message,
-                    force_position_on_rejoin,
-                    prevent_movement_when_unconcious.run_if(on_event::<BrainStateEvent>()),
-                ),
-            )
-            .add_systems(
-                PostUpdate,
-                // To prevent server physics simulation messing up the position before sending
-                restore_client_position
-                    .after(bevy_rapier3d::plugin::PhysicsSet::Writeback)
-                    .before(NetworkSet::ServerSyncPhysics),
-            );
+            app.add_event::<FallEvent>()
+                .add_systems(
+                    Update,
+                    (
+                        handle_movement_message,
+                        force_position_on_rejoin,
+                        expire_stuns,
+                        detect_falls.after(handle_movement_message),
+                        prevent_movement_when_unconcious.run_if(on_event::<BrainStateEvent>()),
+                        update_ragdoll_colliders::<Ragdoll>,
+                        receive_crouch_request,
+                        update_crouch_colliders::<Crouching>,
+                        crouch_speed,
+                    ),
+                )
+                .add_systems(
+                    PostUpdate,
+                    // To prevent server physics simulation messing up the position before sending
+                    restore_client_position
+                        .after(bevy_rapier3d::plugin::PhysicsSet::Writeback)
+                        .before(NetworkSet::ServerSyncPhysics),
+                );
         }
     }
 }
@@ -0,0 +1,169 @@
+use std::fmt;
+
+use bevy::{prelude::*, reflect::TypeUuid};
+use networking::{
+    component::AppExt as ComponentAppExt,
+    is_server,
+    variable::{NetworkVar, ServerVar},
+    Networked,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::body::Body;
+
+/// Collects everything that makes a creature walk faster or slower.
+/// Features set a named modifier instead of changing the speed themselves, so their effects stack.
+pub struct SpeedPlugin;
+
+impl Plugin for SpeedPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_networked_component::<SpeedModifiers, SpeedModifiersClient>();
+        if is_server(app) {
+            app.add_systems(Update, add_speed_modifiers);
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum SpeedModifier {
+    /// Scales the speed, 0.5 halves it
+    Multiply(f32),
+    /// Added to the base speed in m/s, before any multipliers
+    Add(f32),
+}
+
+impl fmt::Display for SpeedModifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpeedModifier::Multiply(multiplier) => write!(f, "x{:.2}", multiplier),
+            SpeedModifier::Add(speed) => write!(f, "{:+.2} m/s", speed),
+        }
+    }
+}
+
+/// The active speed modifiers of a creature, by the name of what caused them.
+#[derive(Component, Default, Networked)]
+#[networked(client = "SpeedModifiersClient")]
+pub struct SpeedModifiers {
+    modifiers: NetworkVar<Vec<(String, SpeedModifier)>>,
+}
+
+impl SpeedModifiers {
+    /// Adds the modifier, or replaces the one with the same name.
+    pub fn set(&mut self, name: &str, modifier: SpeedModifier) {
+        match self.modifiers.iter().position(|(n, _)| n == name) {
+            // Don't send an update if nothing changed
+            Some(index) if self.modifiers[index].1 == modifier => {}
+            Some(index) => self.modifiers[index].1 = modifier,
+            None => self.modifiers.push((name.to_owned(), modifier)),
+        }
+    }
+
+    /// Sets a multiplier, or removes it if it wouldn't change the speed.
+    pub fn set_multiplier(&mut self, name: &str, multiplier: f32) {
+        if multiplier == 1.0 {
+            self.remove(name);
+        } else {
+            self.set(name, SpeedModifier::Multiply(multiplier));
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        if let Some(index) = self.modifiers.iter().position(|(n, _)| n == name) {
+            self.modifiers.remove(index);
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, SpeedModifier)> {
+        self.modifiers
+            .iter()
+            .map(|(name, modifier)| (name.as_str(), *modifier))
+    }
+}
+
+#[derive(Component, Default, TypeUuid, Networked)]
+#[uuid = "a4c7e2d9-6b13-4f58-8e0a-3d9f1b5c7a26"]
+#[networked(server = "SpeedModifiers")]
+pub struct SpeedModifiersClient {
+    modifiers: ServerVar<Vec<(String, SpeedModifier)>>,
+}
+
+#[cfg(feature = "client")]
+impl SpeedModifiersClient {
+    /// Walking speed of the creature if it would normally walk at `base` m/s.
+    /// Additions are summed up first and the result is multiplied by every multiplier,
+    /// so the order modifiers were set in doesn't matter.
+    pub fn speed(&self, base: f32) -> f32 {
+        let mut added = 0.0;
+        let mut multiplier = 1.0;
+        for (_, modifier) in self.modifiers.iter() {
+            match modifier {
+                SpeedModifier::Multiply(m) => multiplier *= m,
+                SpeedModifier::Add(a) => added += a,
+            }
+        }
+        ((base + added) * multiplier).max(0.0)
+    }
+}
+
+fn add_speed_modifiers(
+    bodies: Query<Entity, (Added<Body>, Without<SpeedModifiers>)>,
+    mut commands: Commands,
+) {
+    for entity in bodies.iter() {
+        commands.entity(entity).insert(SpeedModifiers::default());
+    }
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn setting_a_modifier_again_replaces_it() {
+        let mut modifiers = SpeedModifiers::default();
+        modifiers.set("armor", SpeedModifier::Add(-0.5));
+        modifiers.set("armor", SpeedModifier::Add(-0.75));
+        modifiers.set_multiplier("starving", 0.8);
+        assert_eq!(
+            modifiers.iter().collect::<Vec<_>>(),
+            [
+                ("armor", SpeedModifier::Add(-0.75)),
+                ("starving", SpeedModifier::Multiply(0.8))
+            ]
+        );
+
+        // A multiplier of 1 doesn't do anything, so it's not kept around
+        modifiers.set_multiplier("starving", 1.0);
+        modifiers.remove("armor");
+        assert_eq!(modifiers.iter().count(), 0);
+    }
+
+    #[cfg(feature = "client")]
+    #[test]
+    fn armor_encumbrance_and_statuses_compose_in_any_order() {
+        let armor = ("armor".to_owned(), SpeedModifier::Add(-1.0));
+        let encumbrance = ("encumbrance".to_owned(), SpeedModifier::Multiply(0.75));
+        let starving = ("starving".to_owned(), SpeedModifier::Multiply(0.8));
+        let speed = |modifiers: Vec<(String, SpeedModifier)>| {
+            SpeedModifiersClient {
+                modifiers: ServerVar::from_default(modifiers),
+            }
+            .speed(5.0)
+        };
+
+        // The penalty comes off the base speed, then every multiplier scales the rest
+        let expected = (5.0 - 1.0) * 0.75 * 0.8;
+        assert_eq!(
+            speed(vec![armor.clone(), encumbrance.clone(), starving.clone()]),
+            expected
+        );
+        assert_eq!(
+            speed(vec![starving.clone(), armor.clone(), encumbrance.clone()]),
+            expected
+        );
+        assert_eq!(speed(vec![]), 5.0);
+        // Never walking backwards
+        assert_eq!(speed(vec![("armor".into(), SpeedModifier::Add(-9.0))]), 0.0);
+    }
+}
@@ -0,0 +1,582 @@
+use std::time::Duration;
+
+use bevy::{
+    ecs::query::Has, math::Vec3Swizzles, prelude::*, time::common_conditions::on_timer,
+    utils::HashMap,
+};
+use networking::{
+    is_server,
+    messaging::{AppExt, MessageEvent, MessageReceivers, MessageSender},
+    spawning::ClientControls,
+    ConnectionId, Players,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    body::{self_or_ancestor, Body},
+    communication::EmoteEvent,
+    config::ServerConfig,
+};
+
+use super::{speed::SpeedModifiers, Stunned};
+
+#[cfg(feature = "client")]
+use {
+    crate::ui::has_window,
+    bevy_egui::{egui, EguiContexts},
+};
+
+/// Exhaustion, kept apart from health. Sprinting, swinging weapons and disarming use up stamina,
+/// and running out of it slows a creature down and stops it from fighting until it caught its breath.
+pub struct StaminaPlugin;
+
+impl Plugin for StaminaPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_network_message::<SprintRequest>("SprintRequest")
+            .add_network_message::<StaminaMessage>("StaminaMessage");
+
+        if is_server(app) {
+            let config = app
+                .world
+                .get_resource::<ServerConfig>()
+                .map(|config| config.stamina.clone())
+                .unwrap_or_default();
+            app.insert_resource(config)
+                .add_event::<StaminaDamageEvent>()
+                .add_systems(
+                    Update,
+                    (
+                        add_stamina,
+                        receive_sprint_request,
+                        update_stamina.run_if(on_timer(Duration::from_secs_f32(STAMINA_INTERVAL))),
+                        apply_stamina_damage,
+                        sprint_speed,
+                        send_stamina,
+                    )
+                        .chain(),
+                );
+        } else {
+            #[cfg(feature = "client")]
+            app.init_resource::<OwnStamina>().add_systems(
+                Update,
+                (
+                    client_sprint,
+                    (receive_stamina, stamina_ui.run_if(has_window)).chain(),
+                ),
+            );
+        }
+    }
+}
+
+/// Seconds between stamina updates
+const STAMINA_INTERVAL: f32 = 0.25;
+/// Creatures moving slower than this in m/s don't use up stamina while sprinting,
+/// so holding the key while standing still doesn't tire anyone out
+const MIN_SPRINT_SPEED: f32 = 1.0;
+/// How many steps the stamina bar has, the client isn't told the exact value
+const STAMINA_BAR_STEPS: u8 = 20;
+
+/// How fast stamina is used up and comes back.
+#[derive(Deserialize, Resource, Clone)]
+#[serde(default)]
+pub struct StaminaConfig {
+    /// Stamina of a rested creature
+    pub max: f32,
+    /// Stamina regained per second
+    pub regen_per_second: f32,
+    /// Seconds after stamina was last used up before it starts coming back
+    pub regen_delay: f32,
+    /// Movement speed while sprinting, relative to walking
+    pub sprint_speed_multiplier: f32,
+    /// Stamina used up per second of sprinting
+    pub sprint_drain_per_second: f32,
+    /// Stamina used up by every melee swing
+    pub swing_cost: f32,
+    /// Stamina used up by every disarm attempt
+    pub disarm_cost: f32,
+    /// Fraction of `max` an exhausted creature has to recover to before it can sprint and fight again
+    pub recovery_threshold: f32,
+    /// Seconds a creature collapses for when stamina damage takes its last stamina
+    pub collapse_seconds: f32,
+}
+
+impl Default for StaminaConfig {
+    fn default() -> Self {
+        Self {
+            max: 100.0,
+            regen_per_second: 15.0,
+            regen_delay: 1.5,
+            sprint_speed_multiplier: 1.5,
+            sprint_drain_per_second: 12.0,
+            swing_cost: 10.0,
+            disarm_cost: 15.0,
+            recovery_threshold: 0.3,
+            collapse_seconds: 3.0,
+        }
+    }
+}
+
+/// How much energy a creature has left for sprinting and fighting.
+#[derive(Component)]
+pub struct Stamina {
+    pub current: f32,
+    pub max: f32,
+    /// Stamina regained per second
+    pub regen_rate: f32,
+    /// When stamina was last used up, it only comes back after a delay
+    last_drain: f32,
+    /// Set when stamina hits zero, until it recovered above the threshold
+    exhausted: bool,
+    /// Where the creature was at the last update, to tell if it's moving
+    position: Vec3,
+}
+
+impl Stamina {
+    /// If the creature ran out of stamina and hasn't recovered yet.
+    pub fn is_exhausted(&self) -> bool {
+        self.exhausted
+    }
+
+    /// Uses up stamina. Returns true if none is left.
+    pub fn drain(&mut self, amount: f32, now: f32) -> bool {
+        if amount <= 0.0 {
+            return false;
+        }
+        self.current = (self.current - amount).max(0.0);
+        self.last_drain = now;
+        if self.current <= 0.0 {
+            self.exhausted = true;
+        }
+        self.current <= 0.0
+    }
+
+    fn regenerate(&mut self, seconds: f32, now: f32, config: &StaminaConfig) {
+        if now - self.last_drain < config.regen_delay || self.current >= self.max {
+            return;
+        }
+        self.current = (self.current + self.regen_rate * seconds).min(self.max);
+        if self.exhausted && self.current >= self.max * config.recovery_threshold {
+            self.exhausted = false;
+        }
+    }
+}
+
+/// Takes stamina instead of health, like a disabler shot.
+/// The target can be a creature or one of its body parts.
+#[derive(Event)]
+pub struct StaminaDamageEvent {
+    pub target: Entity,
+    pub amount: f32,
+}
+
+/// A creature the player wants to sprint with.
+#[derive(Component)]
+struct Sprinting {
+    sprinting: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SprintRequest {
+    sprinting: bool,
+}
+
+fn add_stamina(
+    bodies: Query<(Entity, &GlobalTransform), Added<Body>>,
+    config: Res<StaminaConfig>,
+    mut commands: Commands,
+) {
+    for (entity, transform) in bodies.iter() {
+        commands.entity(entity).insert(Stamina {
+            current: config.max,
+            max: config.max,
+            regen_rate: config.regen_per_second,
+            last_drain: 0.0,
+            exhausted: false,
+            position: transform.translation(),
+        });
+    }
+}
+
+fn receive_sprint_request(
+    mut messages: EventReader<MessageEvent<SprintRequest>>,
+    players: Res<Players>,
+    controlled: Res<ClientControls>,
+    mut sprinting: Query<&mut Sprinting>,
+    mut commands: Commands,
+) {
+    for event in messages.iter() {
+        let Some(entity) = players
+            .get(event.connection)
+            .and_then(|player| controlled.controlled_entity(player.id))
+        else {
+            continue;
+        };
+        if let Ok(mut sprinting) = sprinting.get_mut(entity) {
+            if sprinting.sprinting != event.message.sprinting {
+                sprinting.sprinting = event.message.sprinting;
+            }
+        } else if event.message.sprinting {
+            commands
+                .entity(entity)
+                .insert(Sprinting { sprinting: true });
+        }
+    }
+}
+
+/// Uses up stamina of sprinting creatures and regenerates it for everyone else.
+/// Movement is client authoritative, so how far the creature got since the last update tells if it's moving.
+fn update_stamina(
+    mut creatures: Query<(&mut Stamina, &GlobalTransform, Option<&Sprinting>)>,
+    config: Res<StaminaConfig>,
+    time: Res<Time>,
+) {
+    let now = time.elapsed_seconds();
+    for (mut stamina, transform, sprinting) in creatures.iter_mut() {
+        let position = transform.translation();
+        let speed = position.xz().distance(stamina.position.xz()) / STAMINA_INTERVAL;
+        stamina.bypass_change_detection().position = position;
+
+        let sprinting = sprinting.is_some_and(|s| s.sprinting) && !stamina.exhausted;
+        if sprinting && speed >= MIN_SPRINT_SPEED {
+            stamina.drain(config.sprint_drain_per_second * STAMINA_INTERVAL, now);
+        } else if stamina.current < stamina.max {
+            stamina.regenerate(STAMINA_INTERVAL, now, &config);
+        }
+    }
+}
+
+/// Creatures left without stamina by it collapse for a moment.
+fn apply_stamina_damage(
+    mut events: EventReader<StaminaDamageEvent>,
+    mut creatures: Query<(&mut Stamina, Has<Stunned>)>,
+    parents: Query<&Parent>,
+    config: Res<StaminaConfig>,
+    time: Res<Time>,
+    mut emotes: EventWriter<EmoteEvent>,
+    mut commands: Commands,
+) {
+    let now = time.elapsed_seconds();
+    for event in events.iter() {
+        let Some(creature) = self_or_ancestor(&parents, event.target, |e| creatures.contains(e))
+        else {
+            continue;
+        };
+        let (mut stamina, stunned) = creatures.get_mut(creature).unwrap();
+        if !stamina.drain(event.amount, now) || stunned {
+            continue;
+        }
+
+        commands
+            .entity(creature)
+            .insert(Stunned::new(config.collapse_seconds, now));
+        emotes.send(EmoteEvent {
+            actor: creature,
+            target: None,
+            text: "collapses from exhaustion!".into(),
+        });
+    }
+}
+
+/// Sprinting is only faster while the creature has stamina left.
+#[allow(clippy::type_complexity)]
+fn sprint_speed(
+    mut creatures: Query<
+        (&Sprinting, &Stamina, &mut SpeedModifiers),
+        Or<(Changed<Sprinting>, Changed<Stamina>, Added<SpeedModifiers>)>,
+    >,
+    config: Res<StaminaConfig>,
+) {
+    for (sprinting, stamina, mut modifiers) in creatures.iter_mut() {
+        let multiplier = if sprinting.sprinting && !stamina.exhausted {
+            config.sprint_speed_multiplier
+        } else {
+            1.0
+        };
+        modifiers.set_multiplier("sprinting", multiplier);
+    }
+}
+
+/// Server message with the stamina of the controlled creature.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+struct StaminaMessage {
+    /// Stamina between 0 and [`STAMINA_BAR_STEPS`]
+    level: u8,
+    exhausted: bool,
+}
+
+impl Default for StaminaMessage {
+    fn default() -> Self {
+        Self {
+            level: STAMINA_BAR_STEPS,
+            exhausted: false,
+        }
+    }
+}
+
+/// Sends every player the stamina of the creature they control whenever the bar changes.
+fn send_stamina(
+    players: Res<Players>,
+    controls: Res<ClientControls>,
+    creatures: Query<&Stamina>,
+    mut sent: Local<HashMap<ConnectionId, StaminaMessage>>,
+    mut sender: MessageSender,
+) {
+    let mut current = HashMap::default();
+    for (&connection, player) in players.players().iter() {
+        let message = controls
+            .controlled_entity(player.id)
+            .and_then(|entity| creatures.get(entity).ok())
+            .map(|stamina| StaminaMessage {
+                level: (stamina.current / stamina.max * STAMINA_BAR_STEPS as f32).ceil() as u8,
+                exhausted: stamina.exhausted,
+            })
+            .unwrap_or_default();
+        current.insert(connection, message);
+    }
+
+    for (&connection, message) in current.iter() {
+        let last = sent.get(&connection);
+        if last == Some(message) || (last.is_none() && message == &StaminaMessage::default()) {
+            continue;
+        }
+        sender.send(message, MessageReceivers::Single(connection));
+    }
+    *sent = current;
+}
+
+#[cfg(feature = "client")]
+fn client_sprint(keys: Res<Input<KeyCode>>, mut sender: MessageSender) {
+    if keys.just_pressed(KeyCode::ShiftLeft) {
+        sender.send_to_server(&SprintRequest { sprinting: true });
+    } else if keys.just_released(KeyCode::ShiftLeft) {
+        sender.send_to_server(&SprintRequest { sprinting: false });
+    }
+}
+
+/// The stamina of the creature this client controls.
+#[cfg(feature = "client")]
+#[derive(Resource, Default)]
+struct OwnStamina(StaminaMessage);
+
+#[cfg(feature = "client")]
+fn receive_stamina(
+    mut messages: EventReader<MessageEvent<StaminaMessage>>,
+    mut own: ResMut<OwnStamina>,
+) {
+    if let Some(event) = messages.iter().last() {
+        own.0 = event.message;
+    }
+}
+
+/// Shows a bar above the hands while stamina isn't full.
+#[cfg(feature = "client")]
+fn stamina_ui(mut contexts: EguiContexts, own: Res<OwnStamina>) {
+    let StaminaMessage { level, exhausted } = own.0;
+    if level >= STAMINA_BAR_STEPS && !exhausted {
+        return;
+    }
+
+    let color = if exhausted {
+        egui::Color32::from_rgb(230, 90, 60)
+    } else {
+        egui::Color32::from_rgb(120, 200, 80)
+    };
+    egui::Area::new("stamina_bar")
+        .anchor(egui::Align2::CENTER_BOTTOM, egui::vec2(0.0, -98.0))
+        .interactable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            let (rect, _) = ui.allocate_exact_size(egui::vec2(120.0, 6.0), egui::Sense::hover());
+            let painter = ui.painter();
+            painter.rect_filled(rect, 2.0, egui::Color32::from_black_alpha(180));
+            let mut filled = rect;
+            filled.set_width(rect.width() * level as f32 / STAMINA_BAR_STEPS as f32);
+            painter.rect_filled(filled, 2.0, color);
+        });
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use bevy::time::TimeUpdateStrategy;
+
+    use super::*;
+    use crate::{
+        body::{health::treatment::Leg, Limb},
+        combat::damage::{AffectedEntity, Attack, KineticDamage, KineticShape},
+        movement::speed::SpeedModifier,
+        testing::server_app,
+    };
+
+    /// Same as the stamina interval, so stamina is updated every frame
+    const FRAME: Duration = Duration::from_millis(250);
+
+    fn update(app: &mut App, frames: u32) {
+        for _ in 0..frames {
+            app.update();
+        }
+    }
+
+    fn app() -> App {
+        let mut app = server_app(ServerConfig::default());
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(FRAME));
+        app
+    }
+
+    /// A creature with one leg, returning the creature and the leg.
+    fn creature(app: &mut App) -> (Entity, Entity) {
+        let limb = Limb::from_world(&mut app.world);
+        let leg = app.world.spawn((Leg, limb, SpatialBundle::default())).id();
+        let creature = app
+            .world
+            .spawn((Body::with_limbs([leg]), SpatialBundle::default()))
+            .id();
+        app.world.entity_mut(leg).set_parent(creature);
+        update(app, 1);
+        (creature, leg)
+    }
+
+    fn stamina(app: &App, creature: Entity) -> &Stamina {
+        app.world.get::<Stamina>(creature).unwrap()
+    }
+
+    fn modifier(app: &App, creature: Entity, name: &str) -> Option<SpeedModifier> {
+        app.world
+            .get::<SpeedModifiers>(creature)
+            .unwrap()
+            .iter()
+            .find_map(|(n, modifier)| (n == name).then_some(modifier))
+    }
+
+    /// Moves the creature by a meter every frame until the condition holds, returning how many frames that took.
+    fn run_until(app: &mut App, creature: Entity, condition: impl Fn(&Stamina) -> bool) -> u32 {
+        for frame in 1..=100 {
+            app.world
+                .get_mut::<Transform>(creature)
+                .unwrap()
+                .translation
+                .x += 1.0;
+            app.update();
+            if condition(stamina(app, creature)) {
+                return frame;
+            }
+        }
+        panic!("stamina never reached the expected state");
+    }
+
+    #[test]
+    fn regen_delay_restarts_with_every_drain() {
+        let config = StaminaConfig::default();
+        let mut stamina = Stamina {
+            current: 50.0,
+            max: config.max,
+            regen_rate: config.regen_per_second,
+            last_drain: 0.0,
+            exhausted: false,
+            position: Vec3::ZERO,
+        };
+
+        assert!(!stamina.drain(10.0, 0.0));
+        stamina.regenerate(1.0, 1.0, &config);
+        assert_eq!(stamina.current, 40.0);
+
+        // Long past the delay of the first drain, but the second one started it over
+        stamina.drain(config.swing_cost, 1.25);
+        stamina.regenerate(1.0, 1.25 + config.regen_delay - 0.25, &config);
+        assert_eq!(stamina.current, 30.0);
+
+        stamina.regenerate(1.0, 1.25 + config.regen_delay, &config);
+        assert_eq!(stamina.current, 30.0 + config.regen_per_second);
+    }
+
+    #[test]
+    fn sprinting_runs_out_and_recovers_past_the_threshold() {
+        let mut app = app();
+        let (creature, _) = creature(&mut app);
+        let config = StaminaConfig::default();
+        app.world
+            .entity_mut(creature)
+            .insert(Sprinting { sprinting: true });
+        update(&mut app, 1);
+        let sprinting = Some(SpeedModifier::Multiply(config.sprint_speed_multiplier));
+        assert_eq!(modifier(&app, creature, "sprinting"), sprinting);
+
+        // 3 stamina every update at the default rates
+        let drain_frames =
+            (config.max / (config.sprint_drain_per_second * STAMINA_INTERVAL)).ceil() as u32;
+        let frames = run_until(&mut app, creature, |s| s.is_exhausted());
+        assert!(
+            (drain_frames - 1..=drain_frames + 1).contains(&frames),
+            "exhausted after {} frames",
+            frames
+        );
+        assert_eq!(stamina(&app, creature).current, 0.0);
+        update(&mut app, 1);
+        assert_eq!(modifier(&app, creature, "sprinting"), None);
+
+        // Still running, but exhausted creatures don't use up stamina
+        let delay_frames = (config.regen_delay / STAMINA_INTERVAL) as u32;
+        let regen_frames = (config.max * config.recovery_threshold
+            / (config.regen_per_second * STAMINA_INTERVAL))
+            .ceil() as u32;
+        let frames = run_until(&mut app, creature, |s| !s.is_exhausted());
+        let expected = delay_frames + regen_frames - 1;
+        assert!(
+            (expected - 1..=expected + 1).contains(&frames),
+            "recovered after {} frames, expected {}",
+            frames,
+            expected
+        );
+        assert!(stamina(&app, creature).current >= config.max * config.recovery_threshold);
+        update(&mut app, 1);
+        assert_eq!(modifier(&app, creature, "sprinting"), sprinting);
+    }
+
+    #[test]
+    fn stamina_damage_and_wounds_stack_independently() {
+        let mut app = app();
+        let (creature, leg) = creature(&mut app);
+        let config = StaminaConfig::default();
+
+        // Hits on a body part tire out the creature
+        app.world.send_event(StaminaDamageEvent {
+            target: leg,
+            amount: 40.0,
+        });
+        update(&mut app, 1);
+        app.world.send_event(StaminaDamageEvent {
+            target: leg,
+            amount: 40.0,
+        });
+        update(&mut app, 1);
+        assert_eq!(stamina(&app, creature).current, config.max - 80.0);
+        assert_eq!(modifier(&app, creature, "limp"), None);
+
+        app.world.spawn((
+            Attack,
+            AffectedEntity(leg),
+            KineticDamage {
+                mass: 0.115,
+                velocity: 400.0,
+                shape: KineticShape::Point,
+                scale: 1.0,
+            },
+        ));
+        update(&mut app, 2);
+        assert!(modifier(&app, creature, "limp").is_some());
+        assert_eq!(stamina(&app, creature).current, config.max - 80.0);
+        assert!(app.world.get::<Stunned>(creature).is_none());
+
+        // Taking the last stamina knocks the creature down for a moment
+        app.world.send_event(StaminaDamageEvent {
+            target: leg,
+            amount: 40.0,
+        });
+        update(&mut app, 2);
+        assert_eq!(stamina(&app, creature).current, 0.0);
+        assert!(stamina(&app, creature).is_exhausted());
+        assert!(app.world.get::<Stunned>(creature).is_some());
+
+        let collapse_frames = (config.collapse_seconds / STAMINA_INTERVAL) as u32;
+        update(&mut app, collapse_frames);
+        assert!(app.world.get::<Stunned>(creature).is_none());
+    }
+}
@@ -0,0 +1,561 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, VecDeque},
+    time::Duration,
+};
+
+use bevy::{
+    ecs::system::SystemState,
+    prelude::*,
+    time::common_conditions::on_timer,
+    utils::{HashMap, HashSet},
+};
+use maps::{
+    tile_neighbours, tile_to_world, world_to_tile, FloorHeight, TileEntity, TileMap, TileMapClient,
+    TileOverlay, TileReference, CHUNK_SIZE,
+};
+use networking::{
+    is_server,
+    messaging::{AppExt, MessageEvent, MessageReceivers, MessageSender},
+    spawning::ClientControls,
+    ConnectionId, Players,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    access::AccessReader,
+    console::{
+        CommandContext, CommandResult, CommandSource, ConsoleAppExt, ConsoleCommand,
+        PermissionLevel,
+    },
+    door::DoorState,
+    movement::MAX_STEP_HEIGHT,
+};
+
+/// Keeps track of which tiles creatures can walk on and finds paths between them.
+pub struct NavigationPlugin;
+
+impl Plugin for NavigationPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<BlocksTile>()
+            .add_network_message::<NavGridDebugMessage>("NavGridDebugMessage");
+
+        if is_server(app) {
+            app.init_resource::<NavGrid>()
+                .init_resource::<NavDebugViewers>()
+                .add_console_command(ConsoleCommand {
+                    name: "navgrid",
+                    description: "Toggles the navigation grid overlay around you",
+                    parameters: &[],
+                    permission: PermissionLevel::Admin,
+                    handler: navgrid_command,
+                })
+                .add_console_command(ConsoleCommand {
+                    name: "navpath",
+                    description: "Finds a path from your body to the tile at your cursor",
+                    parameters: &[],
+                    permission: PermissionLevel::Admin,
+                    handler: navpath_command,
+                })
+                .add_systems(
+                    Update,
+                    (
+                        update_nav_grid,
+                        send_nav_debug
+                            .after(update_nav_grid)
+                            .run_if(on_timer(Duration::from_secs_f32(NAV_DEBUG_INTERVAL))),
+                    ),
+                );
+        } else {
+            app.init_resource::<ClientNavPaths>()
+                .add_systems(Update, (receive_nav_debug, draw_nav_paths).chain());
+        }
+    }
+}
+
+/// Stops creatures from walking over the tile the entity is part of, like walls.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct BlocksTile;
+
+/// If and how a tile can be walked over.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum NavTile {
+    #[default]
+    Blocked,
+    Walkable,
+    /// Only passable if the door is open, or the creature can open it
+    Door {
+        entity: Entity,
+        open: bool,
+        bolted: bool,
+    },
+}
+
+impl NavTile {
+    /// If anything can move over the tile without opening a door.
+    pub fn is_walkable(self) -> bool {
+        matches!(self, NavTile::Walkable | NavTile::Door { open: true, .. })
+    }
+}
+
+/// Walkability of every tile on the map.
+/// Updated when tiles or doors change, so queries don't have to look at the map.
+// TODO: Support multiple maps
+#[derive(Resource, Default)]
+pub struct NavGrid {
+    /// Size in tiles
+    size: UVec2,
+    tiles: Vec<NavTile>,
+    /// Floor height of every tile, creatures can't walk between tiles too far apart
+    heights: Vec<f32>,
+}
+
+/// Paths longer than this many searched tiles are given up on
+const MAX_PATH_SEARCH: usize = 10_000;
+
+impl NavGrid {
+    pub fn get(&self, position: UVec2) -> NavTile {
+        self.index(position)
+            .map(|index| self.tiles[index])
+            .unwrap_or_default()
+    }
+
+    /// Creates a grid of tiles that aren't spawned yet, like a map that is being checked.
+    /// All floors are at the same height.
+    pub fn from_tiles(size: UVec2, tiles: Vec<NavTile>) -> Self {
+        Self {
+            size,
+            heights: vec![0.0; tiles.len()],
+            tiles,
+        }
+    }
+
+    fn set(&mut self, position: UVec2, tile: NavTile, height: f32) {
+        if let Some(index) = self.index(position) {
+            self.tiles[index] = tile;
+            self.heights[index] = height;
+        }
+    }
+
+    fn index(&self, position: UVec2) -> Option<usize> {
+        (position.x < self.size.x && position.y < self.size.y)
+            .then(|| (position.y * self.size.x + position.x) as usize)
+    }
+
+    fn position(&self, index: usize) -> UVec2 {
+        UVec2::new(index as u32 % self.size.x, index as u32 / self.size.x)
+    }
+
+    /// Finds the closest walkable tile within a square of `max_distance` tiles around the position.
+    pub fn nearest_walkable(&self, position: UVec2, max_distance: u32) -> Option<UVec2> {
+        let min = position.saturating_sub(UVec2::splat(max_distance));
+        let max = (position + UVec2::splat(max_distance)).min(self.size.saturating_sub(UVec2::ONE));
+        (min.y..=max.y)
+            .flat_map(|y| (min.x..=max.x).map(move |x| UVec2::new(x, y)))
+            .filter(|&tile| self.get(tile).is_walkable())
+            .min_by_key(|tile| (tile.as_ivec2() - position.as_ivec2()).length_squared())
+    }
+
+    /// If a creature could step from one tile onto its neighbour, ignoring what is on it.
+    fn can_step(&self, from: usize, to: usize) -> bool {
+        (self.heights[to] - self.heights[from]).abs() <= MAX_STEP_HEIGHT
+    }
+
+    /// Finds every tile connected to the starting tiles over `passable` tiles, closest ones first.
+    /// The starting tiles are included even if they aren't passable.
+    pub fn flood_fill(
+        &self,
+        starts: impl IntoIterator<Item = UVec2>,
+        passable: impl Fn(UVec2, NavTile) -> bool,
+    ) -> Vec<UVec2> {
+        let mut visited = vec![false; self.tiles.len()];
+        let mut queue = VecDeque::new();
+        for index in starts.into_iter().filter_map(|start| self.index(start)) {
+            if !visited[index] {
+                visited[index] = true;
+                queue.push_back(index);
+            }
+        }
+
+        let mut reached = Vec::new();
+        while let Some(index) = queue.pop_front() {
+            let position = self.position(index);
+            reached.push(position);
+            for (_, neighbour) in tile_neighbours(position) {
+                let Some(neighbour_index) = self.index(neighbour) else {
+                    continue;
+                };
+                if visited[neighbour_index] || !self.can_step(index, neighbour_index) {
+                    continue;
+                }
+                if !passable(neighbour, self.get(neighbour)) {
+                    continue;
+                }
+                visited[neighbour_index] = true;
+                queue.push_back(neighbour_index);
+            }
+        }
+        reached
+    }
+
+    /// Finds the shortest path between two tiles with A*, including both ends.
+    /// `passable` decides which tiles the path can go over, which lets each creature
+    /// decide which closed doors it is able to open.
+    pub fn find_path(
+        &self,
+        start: UVec2,
+        goal: UVec2,
+        passable: impl Fn(UVec2, NavTile) -> bool,
+    ) -> Option<Vec<UVec2>> {
+        let start_index = self.index(start)?;
+        let goal_index = self.index(goal)?;
+        if !passable(goal, self.get(goal)) {
+            return None;
+        }
+
+        let heuristic = |position: UVec2| {
+            let difference = position.as_ivec2() - goal.as_ivec2();
+            difference.x.unsigned_abs() + difference.y.unsigned_abs()
+        };
+
+        let mut open = BinaryHeap::new();
+        let mut costs: HashMap<usize, u32> = HashMap::default();
+        let mut came_from: HashMap<usize, usize> = HashMap::default();
+        open.push(Reverse((heuristic(start), start_index)));
+        costs.insert(start_index, 0);
+
+        let mut searched = 0;
+        while let Some(Reverse((_, index))) = open.pop() {
+            if index == goal_index {
+                let mut path = vec![goal];
+                let mut current = index;
+                while let Some(&previous) = came_from.get(&current) {
+                    path.push(self.position(previous));
+                    current = previous;
+                }
+                path.reverse();
+                return Some(path);
+            }
+
+            searched += 1;
+            if searched > MAX_PATH_SEARCH {
+                return None;
+            }
+
+            let cost = costs[&index] + 1;
+            for (_, neighbour) in tile_neighbours(self.position(index)) {
+                let Some(neighbour_index) = self.index(neighbour) else {
+                    continue;
+                };
+                if !self.can_step(index, neighbour_index) {
+                    continue;
+                }
+                if costs.get(&neighbour_index).is_some_and(|&c| c <= cost) {
+                    continue;
+                }
+                if !passable(neighbour, self.get(neighbour)) {
+                    continue;
+                }
+                costs.insert(neighbour_index, cost);
+                came_from.insert(neighbour_index, index);
+                open.push(Reverse((cost + heuristic(neighbour), neighbour_index)));
+            }
+        }
+
+        None
+    }
+}
+
+fn nav_tile(
+    tile: Option<&TileReference>,
+    blockers: &Query<(), With<BlocksTile>>,
+    doors: &Query<&DoorState>,
+) -> NavTile {
+    // Space has no turf
+    let Some(turf) = tile.and_then(|t| t.turf) else {
+        return NavTile::Blocked;
+    };
+
+    for entity in [Some(turf), tile.and_then(|t| t.furniture)]
+        .into_iter()
+        .flatten()
+    {
+        if let Ok(door) = doors.get(entity) {
+            return NavTile::Door {
+                entity,
+                open: door.is_open(),
+                bolted: door.is_bolted(),
+            };
+        }
+        if blockers.contains(entity) {
+            return NavTile::Blocked;
+        }
+    }
+
+    NavTile::Walkable
+}
+
+fn floor_height(tile: Option<&TileReference>, heights: &Query<&FloorHeight>) -> f32 {
+    tile.and_then(|t| t.turf)
+        .and_then(|turf| heights.get(turf).ok())
+        .map_or(0.0, |floor| floor.height)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn update_nav_grid(
+    mut grid: ResMut<NavGrid>,
+    maps: Query<&TileMap>,
+    // Transforms only get their final position after propagation, so this also catches new tiles
+    moved: Query<(Entity, &GlobalTransform), (With<TileEntity>, Changed<GlobalTransform>)>,
+    mut removed: RemovedComponents<TileEntity>,
+    changed_doors: Query<&GlobalTransform, (With<TileEntity>, Changed<DoorState>)>,
+    mut tile_positions: Local<HashMap<Entity, UVec2>>,
+    blockers: Query<(), With<BlocksTile>>,
+    doors: Query<&DoorState>,
+    heights: Query<&FloorHeight>,
+) {
+    let Ok(map) = maps.get_single() else {
+        return;
+    };
+
+    let size = map.size() * CHUNK_SIZE;
+    if grid.size != size {
+        grid.size = size;
+        grid.tiles = vec![NavTile::Blocked; (size.x * size.y) as usize];
+        grid.heights = vec![0.0; grid.tiles.len()];
+        for index in 0..grid.tiles.len() {
+            let position = grid.position(index);
+            let tile = map.tile(position);
+            grid.tiles[index] = nav_tile(tile, &blockers, &doors);
+            grid.heights[index] = floor_height(tile, &heights);
+        }
+        return;
+    }
+
+    let mut changed_tiles = HashSet::new();
+    for (entity, transform) in moved.iter() {
+        if let Some(position) = world_to_tile(transform.translation()) {
+            if let Some(old) = tile_positions.insert(entity, position) {
+                changed_tiles.insert(old);
+            }
+            changed_tiles.insert(position);
+        }
+    }
+    for entity in removed.iter() {
+        if let Some(position) = tile_positions.remove(&entity) {
+            changed_tiles.insert(position);
+        }
+    }
+    for transform in changed_doors.iter() {
+        changed_tiles.extend(world_to_tile(transform.translation()));
+    }
+
+    for position in changed_tiles {
+        let tile = map.tile(position);
+        grid.set(
+            position,
+            nav_tile(tile, &blockers, &doors),
+            floor_height(tile, &heights),
+        );
+    }
+}
+
+/// The tiles a creature is walking along, shown by the navigation overlay.
+#[derive(Component, Default)]
+pub struct NavPath {
+    pub tiles: Vec<UVec2>,
+}
+
+/// Admins that see the navigation grid around them.
+#[derive(Resource, Default)]
+struct NavDebugViewers {
+    viewers: HashSet<ConnectionId>,
+    /// Admins that turned the overlay off and still need to have it cleared
+    hidden: Vec<ConnectionId>,
+}
+
+/// Seconds between navigation grid updates sent to admins viewing it
+const NAV_DEBUG_INTERVAL: f32 = 1.0;
+
+/// The navigation grid of the chunk around the player, sent to admins debugging it.
+#[derive(Serialize, Deserialize)]
+struct NavGridDebugMessage {
+    /// First tile of the chunk
+    origin: UVec2,
+    /// One entry per tile in the chunk, row by row. Empty when the overlay is turned off.
+    walkable: Vec<bool>,
+    /// Paths of creatures passing through the chunk
+    paths: Vec<Vec<UVec2>>,
+}
+
+fn navgrid_command(world: &mut World, context: &CommandContext) -> CommandResult {
+    let CommandSource::Player(connection) = context.source else {
+        return Err("The overlay can only be shown to players".into());
+    };
+
+    let mut viewers = world.resource_mut::<NavDebugViewers>();
+    if viewers.viewers.remove(&connection) {
+        viewers.hidden.push(connection);
+        Ok("Navigation overlay hidden".into())
+    } else {
+        viewers.viewers.insert(connection);
+        Ok("Navigation overlay shown".into())
+    }
+}
+
+fn navpath_command(world: &mut World, context: &CommandContext) -> CommandResult {
+    let CommandSource::Player(connection) = context.source else {
+        return Err("Only players have a body to find a path for".into());
+    };
+    let Some(goal) = context.cursor.and_then(world_to_tile) else {
+        return Err("Your cursor isn't over the map".into());
+    };
+    let Some(body) = world
+        .resource::<Players>()
+        .get(connection)
+        .and_then(|player| {
+            world
+                .resource::<ClientControls>()
+                .controlled_entity(player.id)
+        })
+    else {
+        return Err("You don't control a body".into());
+    };
+    let Some(start) = world
+        .get::<GlobalTransform>(body)
+        .and_then(|transform| world_to_tile(transform.translation()))
+    else {
+        return Err("Your body isn't on the map".into());
+    };
+
+    let mut state = SystemState::<(Res<NavGrid>, AccessReader)>::new(world);
+    let (grid, access) = state.get(world);
+    let goal = grid.nearest_walkable(goal, 1).unwrap_or(goal);
+    // The body can pass closed doors it has access to
+    let path = grid.find_path(start, goal, |_, tile| match tile {
+        NavTile::Door {
+            entity,
+            open: false,
+            bolted,
+        } => !bolted && access.can_access(body, entity),
+        tile => tile.is_walkable(),
+    });
+    let Some(path) = path else {
+        return Err(format!("No path from {} to {}", start, goal));
+    };
+    let length = path.len();
+    world.entity_mut(body).insert(NavPath { tiles: path });
+    Ok(format!("Found a path with {} tiles", length))
+}
+
+fn send_nav_debug(
+    mut viewers: ResMut<NavDebugViewers>,
+    grid: Res<NavGrid>,
+    players: Res<Players>,
+    controls: Res<ClientControls>,
+    transforms: Query<&GlobalTransform>,
+    paths: Query<&NavPath>,
+    mut sender: MessageSender,
+) {
+    for connection in viewers.hidden.drain(..) {
+        sender.send(
+            &NavGridDebugMessage {
+                origin: UVec2::ZERO,
+                walkable: Vec::new(),
+                paths: Vec::new(),
+            },
+            MessageReceivers::Single(connection),
+        );
+    }
+
+    // Viewers that left are forgotten
+    viewers
+        .viewers
+        .retain(|connection| players.get(*connection).is_some());
+
+    for &connection in viewers.viewers.iter() {
+        let Some(tile) = players
+            .get(connection)
+            .and_then(|player| controls.controlled_entity(player.id))
+            .and_then(|entity| transforms.get(entity).ok())
+            .and_then(|transform| world_to_tile(transform.translation()))
+        else {
+            continue;
+        };
+
+        let origin = tile / CHUNK_SIZE * CHUNK_SIZE;
+        let walkable = (0..CHUNK_SIZE)
+            .flat_map(|y| (0..CHUNK_SIZE).map(move |x| origin + UVec2::new(x, y)))
+            .map(|position| grid.get(position).is_walkable())
+            .collect();
+        let in_chunk = |position: &UVec2| {
+            position.x >= origin.x
+                && position.y >= origin.y
+                && position.x < origin.x + CHUNK_SIZE
+                && position.y < origin.y + CHUNK_SIZE
+        };
+        let paths = paths
+            .iter()
+            .filter(|path| path.tiles.iter().any(in_chunk))
+            .map(|path| path.tiles.clone())
+            .collect();
+
+        sender.send(
+            &NavGridDebugMessage {
+                origin,
+                walkable,
+                paths,
+            },
+            MessageReceivers::Single(connection),
+        );
+    }
+}
+
+/// Creature paths received from the server for the navigation overlay.
+#[derive(Resource, Default)]
+struct ClientNavPaths(Vec<Vec<UVec2>>);
+
+fn receive_nav_debug(
+    mut messages: EventReader<MessageEvent<NavGridDebugMessage>>,
+    tilemaps: Query<Entity, With<TileMapClient>>,
+    mut overlay: ResMut<TileOverlay>,
+    mut paths: ResMut<ClientNavPaths>,
+) {
+    let Some(event) = messages.iter().last() else {
+        return;
+    };
+    let message = &event.message;
+
+    overlay.tilemap = tilemaps.get_single().ok();
+    overlay.tiles = message
+        .walkable
+        .iter()
+        .enumerate()
+        .map(|(index, &walkable)| {
+            let offset = UVec2::new(index as u32 % CHUNK_SIZE, index as u32 / CHUNK_SIZE);
+            (message.origin + offset, walkable)
+        })
+        .collect();
+    paths.0 = message.paths.clone();
+}
+
+/// Height above the floor paths are drawn at
+const PATH_HEIGHT: f32 = 0.1;
+
+fn draw_nav_paths(
+    paths: Res<ClientNavPaths>,
+    tilemaps: Query<&GlobalTransform, With<TileMapClient>>,
+    mut gizmos: Gizmos,
+) {
+    let Ok(map_transform) = tilemaps.get_single() else {
+        return;
+    };
+    for path in paths.0.iter() {
+        gizmos.linestrip(
+            path.iter()
+                .map(|&tile| tile_to_world(map_transform, tile) + Vec3::Y * PATH_HEIGHT),
+            Color::CYAN,
+        );
+    }
+}
@@ -0,0 +1,304 @@
+use std::{collections::VecDeque, time::Duration};
+
+use bevy::{prelude::*, time::common_conditions::on_timer, utils::HashSet};
+use maps::{tile_neighbours, tile_to_world, world_to_tile, TileMapClient, CHUNK_SIZE};
+use networking::{identity::NetworkIdentity, spawning::ClientControlled};
+
+use crate::{
+    camera::{MainCamera, TopDownCamera},
+    door::DoorStateClient,
+    lights::Opaque,
+    GameState,
+};
+
+/// Hides objects in parts of the station the player can't see into, like rooms behind walls
+/// when the camera is over space. Only changes what is drawn, the objects are still replicated.
+pub struct OcclusionPlugin;
+
+impl Plugin for OcclusionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<OcclusionSettings>()
+            .init_resource::<VisibleTiles>()
+            .init_resource::<OcclusionStats>()
+            .add_systems(
+                Update,
+                (
+                    (update_visible_tiles, apply_occlusion).chain(),
+                    update_stats.run_if(on_timer(Duration::from_secs_f32(STATS_INTERVAL))),
+                    draw_visible_tiles
+                        .run_if(|settings: Res<OcclusionSettings>| settings.show_flood_fill),
+                )
+                    .run_if(in_state(GameState::Game)),
+            );
+    }
+}
+
+/// The most tiles visited when looking for visible tiles.
+/// Tiles are visited closest first, so this limits how far away anything is shown.
+const MAX_FLOOD_TILES: usize = 4096;
+/// Seconds after which visible tiles are found again anyway, to notice new walls and tiles
+const REFRESH_INTERVAL: f32 = 1.0;
+/// Seconds between counting how many meshes are hidden
+const STATS_INTERVAL: f32 = 0.5;
+const OVERLAY_HEIGHT: f32 = 0.05;
+
+/// Player preferences for hiding objects out of sight.
+#[derive(Resource)]
+pub struct OcclusionSettings {
+    pub enabled: bool,
+    /// Draw the tiles that are considered visible, to tune the flood fill
+    pub show_flood_fill: bool,
+}
+
+impl Default for OcclusionSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            show_flood_fill: false,
+        }
+    }
+}
+
+/// Tiles that can be seen from the camera target, and the chunks containing any of them.
+#[derive(Resource, Default)]
+struct VisibleTiles {
+    tiles: HashSet<UVec2>,
+    chunks: HashSet<UVec2>,
+    /// Chunk the camera target was in when the tiles were last found
+    origin_chunk: Option<UVec2>,
+    /// Elapsed seconds when the tiles were last found
+    last_update: f32,
+    /// The tiles changed since objects were last hidden or shown
+    changed: bool,
+    /// Objects hidden by occlusion, only these are shown again
+    hidden: HashSet<Entity>,
+}
+
+impl VisibleTiles {
+    fn clear(&mut self) {
+        self.tiles.clear();
+        self.chunks.clear();
+        self.origin_chunk = None;
+        self.changed = true;
+    }
+}
+
+/// How much occlusion saves, shown in the debug menu.
+#[derive(Resource, Default)]
+pub struct OcclusionStats {
+    pub hidden_meshes: usize,
+    pub total_meshes: usize,
+}
+
+fn chunk_of(tile: UVec2) -> UVec2 {
+    tile / CHUNK_SIZE
+}
+
+/// Walls and closed doors block sight. Windows and open doors don't.
+fn blocks_sight(
+    map: &TileMapClient,
+    position: UVec2,
+    opaque: &Query<(), With<Opaque>>,
+    doors: &Query<&DoorStateClient>,
+) -> bool {
+    map.tile(position).is_some_and(|tile| {
+        tile.turf
+            .into_iter()
+            .chain(tile.furniture)
+            .any(|e| opaque.contains(e) || doors.get(e).is_ok_and(|door| !door.is_open()))
+    })
+}
+
+/// Flood fills from the origin through tiles that don't block sight.
+/// Blocking tiles are visible themselves, but nothing is visited behind them.
+fn flood_visible_tiles(
+    map: &TileMapClient,
+    origin: UVec2,
+    opaque: &Query<(), With<Opaque>>,
+    doors: &Query<&DoorStateClient>,
+) -> HashSet<UVec2> {
+    let bounds = map.bounds();
+    let mut visited = HashSet::default();
+    let mut queue = VecDeque::from([origin]);
+    visited.insert(origin);
+    while let Some(position) = queue.pop_front() {
+        if position != origin && blocks_sight(map, position, opaque, doors) {
+            continue;
+        }
+        for (_, neighbour) in tile_neighbours(position) {
+            if visited.len() >= MAX_FLOOD_TILES {
+                return visited;
+            }
+            // Space around the map doesn't have to be visited
+            if neighbour.x > bounds.x || neighbour.y > bounds.y {
+                continue;
+            }
+            if visited.insert(neighbour) {
+                queue.push_back(neighbour);
+            }
+        }
+    }
+    visited
+}
+
+/// Finds the visible tiles again when the camera target enters another chunk
+/// or a door next to a visible tile changes.
+#[allow(clippy::too_many_arguments)]
+fn update_visible_tiles(
+    settings: Res<OcclusionSettings>,
+    cameras: Query<&TopDownCamera, With<MainCamera>>,
+    transforms: Query<&GlobalTransform>,
+    maps: Query<(&TileMapClient, &GlobalTransform)>,
+    opaque: Query<(), With<Opaque>>,
+    doors: Query<&DoorStateClient>,
+    changed_doors: Query<&GlobalTransform, Changed<DoorStateClient>>,
+    time: Res<Time>,
+    mut visible: ResMut<VisibleTiles>,
+) {
+    if !settings.enabled {
+        if visible.origin_chunk.is_some() || !visible.hidden.is_empty() {
+            visible.clear();
+        }
+        return;
+    }
+
+    let Ok((map, map_transform)) = maps.get_single() else {
+        return;
+    };
+    let Some(origin) = cameras
+        .get_single()
+        .ok()
+        .and_then(|camera| transforms.get(camera.target).ok())
+        .and_then(|target| world_tile(map_transform, target.translation()))
+    else {
+        if visible.origin_chunk.is_some() {
+            visible.clear();
+        }
+        return;
+    };
+
+    let chunk = chunk_of(origin);
+    let door_changed = changed_doors.iter().any(|door| {
+        world_tile(map_transform, door.translation()).is_some_and(|tile| {
+            visible.tiles.contains(&tile)
+                || tile_neighbours(tile).any(|(_, n)| visible.tiles.contains(&n))
+        })
+    });
+    let now = time.elapsed_seconds();
+    if visible.origin_chunk == Some(chunk)
+        && !door_changed
+        && now - visible.last_update < REFRESH_INTERVAL
+    {
+        return;
+    }
+
+    let tiles = flood_visible_tiles(map, origin, &opaque, &doors);
+    visible.chunks = tiles.iter().map(|&tile| chunk_of(tile)).collect();
+    visible.chunks.insert(chunk);
+    visible.tiles = tiles;
+    visible.origin_chunk = Some(chunk);
+    visible.last_update = now;
+    visible.changed = true;
+}
+
+/// The tile a world position is on, in the coordinates of the map.
+fn world_tile(map_transform: &GlobalTransform, position: Vec3) -> Option<UVec2> {
+    world_to_tile(map_transform.affine().inverse().transform_point3(position))
+}
+
+/// Hides objects in chunks that aren't visible and shows them again once they are.
+/// Tile objects are children of the map, everything else has to be a root to be hidden.
+/// The controlled creature and the camera target are never hidden, and neither is
+/// anything they hold, as held items are their children.
+fn apply_occlusion(
+    mut visible: ResMut<VisibleTiles>,
+    mut objects: Query<
+        (
+            Entity,
+            Ref<GlobalTransform>,
+            &mut Visibility,
+            Option<&Parent>,
+        ),
+        (
+            With<NetworkIdentity>,
+            Without<ClientControlled>,
+            Without<TileMapClient>,
+            // Doors hide themselves while open, which would be undone when shown again
+            Without<DoorStateClient>,
+        ),
+    >,
+    maps: Query<(Entity, &GlobalTransform), With<TileMapClient>>,
+    cameras: Query<&TopDownCamera, With<MainCamera>>,
+) {
+    let visible = visible.as_mut();
+    let everything = std::mem::take(&mut visible.changed);
+    let map = maps.get_single().ok();
+    let camera_target = cameras.get_single().ok().map(|camera| camera.target);
+
+    for (entity, transform, mut visibility, parent) in objects.iter_mut() {
+        if !everything && !transform.is_changed() {
+            continue;
+        }
+
+        let is_candidate = match parent {
+            None => true,
+            Some(parent) => map.is_some_and(|(map, _)| parent.get() == map),
+        };
+        let hide = is_candidate
+            && Some(entity) != camera_target
+            && visible.origin_chunk.is_some()
+            && map.is_some_and(|(_, map_transform)| {
+                world_tile(map_transform, transform.translation())
+                    .is_some_and(|tile| !visible.chunks.contains(&chunk_of(tile)))
+            });
+
+        if hide {
+            if *visibility == Visibility::Inherited {
+                *visibility = Visibility::Hidden;
+                visible.hidden.insert(entity);
+            }
+        } else if visible.hidden.remove(&entity) && *visibility == Visibility::Hidden {
+            *visibility = Visibility::Inherited;
+        }
+    }
+
+    if everything {
+        visible.hidden.retain(|&entity| objects.contains(entity));
+    }
+}
+
+fn update_stats(
+    visible: Res<VisibleTiles>,
+    meshes: Query<(), With<Handle<Mesh>>>,
+    children: Query<&Children>,
+    mut stats: ResMut<OcclusionStats>,
+) {
+    let hidden_meshes = visible
+        .hidden
+        .iter()
+        .flat_map(|&root| std::iter::once(root).chain(children.iter_descendants(root)))
+        .filter(|&entity| meshes.contains(entity))
+        .count();
+    *stats = OcclusionStats {
+        hidden_meshes,
+        total_meshes: meshes.iter().count(),
+    };
+}
+
+fn draw_visible_tiles(
+    visible: Res<VisibleTiles>,
+    maps: Query<&GlobalTransform, With<TileMapClient>>,
+    mut gizmos: Gizmos,
+) {
+    let Ok(map_transform) = maps.get_single() else {
+        return;
+    };
+    for &tile in visible.tiles.iter() {
+        gizmos.rect(
+            tile_to_world(map_transform, tile) + Vec3::Y * OVERLAY_HEIGHT,
+            Quat::from_rotation_x(std::f32::consts::FRAC_PI_2),
+            Vec2::splat(0.8),
+            Color::rgba(0.2, 0.8, 1.0, 0.5),
+        );
+    }
+}
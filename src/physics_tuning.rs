@@ -0,0 +1,276 @@
+use bevy::prelude::*;
+use bevy_rapier3d::{
+    plugin::{PhysicsSet, RapierContext},
+    prelude::{Ccd, RigidBody, Velocity},
+};
+use networking::is_server;
+use serde::Deserialize;
+
+use crate::{config::ServerConfig, items::Item};
+
+/// Applies the physics settings from the server config and measures how long physics steps take.
+pub struct PhysicsTuningPlugin;
+
+impl Plugin for PhysicsTuningPlugin {
+    fn build(&self, app: &mut App) {
+        if !is_server(app) {
+            return;
+        }
+
+        let config = app
+            .world
+            .get_resource::<ServerConfig>()
+            .map(|config| config.physics.clone())
+            .unwrap_or_default();
+        app.insert_resource(config)
+            .init_resource::<PhysicsTimings>()
+            .add_systems(Startup, apply_integration_parameters)
+            .add_systems(Update, toggle_item_ccd)
+            .add_systems(
+                PostUpdate,
+                record_physics_timings.after(PhysicsSet::StepSimulation),
+            );
+    }
+}
+
+/// How much a new step counts towards the averaged timings
+const TIMING_SMOOTHING: f64 = 0.05;
+
+#[derive(Deserialize, Resource, Clone)]
+pub struct PhysicsConfig {
+    /// Solver iterations per step. More make stacked objects steadier, but cost time
+    #[serde(default = "PhysicsConfig::default_velocity_iterations")]
+    pub velocity_iterations: usize,
+    /// Iterations spent pushing overlapping objects apart
+    #[serde(default = "PhysicsConfig::default_stabilization_iterations")]
+    pub stabilization_iterations: usize,
+    /// Meters apart two colliders start being checked for contact
+    #[serde(default = "PhysicsConfig::default_prediction_distance")]
+    pub prediction_distance: f32,
+    /// Items faster than this in meters per second use continuous collision detection,
+    /// so they don't pass through walls when thrown
+    #[serde(default = "PhysicsConfig::default_ccd_speed")]
+    pub ccd_speed: f32,
+}
+
+impl PhysicsConfig {
+    fn default_velocity_iterations() -> usize {
+        4
+    }
+
+    fn default_stabilization_iterations() -> usize {
+        1
+    }
+
+    fn default_prediction_distance() -> f32 {
+        0.002
+    }
+
+    fn default_ccd_speed() -> f32 {
+        10.0
+    }
+}
+
+impl Default for PhysicsConfig {
+    fn default() -> Self {
+        Self {
+            velocity_iterations: Self::default_velocity_iterations(),
+            stabilization_iterations: Self::default_stabilization_iterations(),
+            prediction_distance: Self::default_prediction_distance(),
+            ccd_speed: Self::default_ccd_speed(),
+        }
+    }
+}
+
+/// Averaged milliseconds spent in the stages of a physics step.
+#[derive(Resource, Default, Clone)]
+pub struct PhysicsTimings {
+    pub step: f64,
+    pub broad_phase: f64,
+    pub narrow_phase: f64,
+    pub solver: f64,
+}
+
+fn apply_integration_parameters(config: Res<PhysicsConfig>, mut rapier: ResMut<RapierContext>) {
+    let parameters = &mut rapier.integration_parameters;
+    parameters.max_velocity_iterations = config.velocity_iterations;
+    parameters.max_stabilization_iterations = config.stabilization_iterations;
+    parameters.prediction_distance = config.prediction_distance;
+    rapier.pipeline.counters.enable();
+}
+
+/// Continuous collision detection is expensive, so only items moving fast enough to skip
+/// through a wall in one step use it. It is turned off again once they slowed down well below that.
+fn toggle_item_ccd(
+    items: Query<
+        (Entity, &Velocity, Option<&Ccd>),
+        (With<Item>, With<RigidBody>, Changed<Velocity>),
+    >,
+    config: Res<PhysicsConfig>,
+    mut commands: Commands,
+) {
+    for (entity, velocity, ccd) in items.iter() {
+        let speed = velocity.linvel.length();
+        let enabled = ccd.is_some_and(|ccd| ccd.enabled);
+        if !enabled && speed > config.ccd_speed {
+            commands.entity(entity).insert(Ccd::enabled());
+        } else if enabled && speed < config.ccd_speed * 0.5 {
+            commands.entity(entity).insert(Ccd::disabled());
+        }
+    }
+}
+
+fn record_physics_timings(rapier: Res<RapierContext>, mut timings: ResMut<PhysicsTimings>) {
+    let counters = &rapier.pipeline.counters;
+    let smooth = |average: &mut f64, value: f64| {
+        *average += (value - *average) * TIMING_SMOOTHING;
+    };
+    smooth(&mut timings.step, counters.step_time.time());
+    smooth(
+        &mut timings.broad_phase,
+        counters.cd.broad_phase_time.time(),
+    );
+    smooth(
+        &mut timings.narrow_phase,
+        counters.cd.narrow_phase_time.time(),
+    );
+    smooth(&mut timings.solver, counters.stages.solver_time.time());
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use bevy::{
+        ecs::system::{Command, CommandQueue},
+        time::TimeUpdateStrategy,
+    };
+    use bevy_rapier3d::prelude::{Collider, ColliderDisabled, LockedAxes};
+    use maps::{chunk_colliders::ChunkCollider, MapCommandsExt, TileLayer, TileMap, CHUNK_SIZE};
+    use networking::{identity::NetworkCommand, scene::NetworkSceneSpawner};
+
+    use super::*;
+    use crate::{testing::server_app, SERVER_TPS};
+
+    /// Size of the station in chunks, about as large as the maps that are played
+    const STATION_CHUNKS: u32 = 10;
+    /// Tiles between the walls of the rooms
+    const ROOM_SIZE: u32 = 10;
+    const MOVING_BODIES: usize = 50;
+    /// Ticks measured after everything has spawned
+    const MEASURED_TICKS: u32 = 120;
+    /// CI machines are slower and tests run unoptimized, so the tick may take a few times its budget
+    const BUDGET_MARGIN: f64 = 3.0;
+    const MAX_LOADING_FRAMES: u32 = 2000;
+
+    fn tick() -> Duration {
+        Duration::from_secs_f64(1.0 / SERVER_TPS as f64)
+    }
+
+    /// Walls around rooms filling the whole station, with a window in the middle of every wall.
+    fn spawn_station(app: &mut App) -> usize {
+        let map = app
+            .world
+            .spawn((
+                TileMap::new(UVec2::splat(STATION_CHUNKS)),
+                SpatialBundle::default(),
+            ))
+            .id();
+        NetworkCommand { entity: map }.apply(&mut app.world);
+
+        let asset_server = app.world.resource::<AssetServer>().clone();
+        let wall = asset_server.load("tilemap/turfs/wall.scn.ron");
+        let window = asset_server.load("tilemap/turfs/window.scn.ron");
+        let size = STATION_CHUNKS * CHUNK_SIZE;
+        let mut queue = CommandQueue::default();
+        let mut commands = Commands::new(&mut queue, &app.world);
+        let mut turfs = 0;
+        for position in (0..size).flat_map(|x| (0..size).map(move |y| UVec2::new(x, y))) {
+            let on_wall = |v: u32| v % ROOM_SIZE == 0;
+            let in_middle = |v: u32| v % ROOM_SIZE == ROOM_SIZE / 2;
+            let scene = match (on_wall(position.x), on_wall(position.y)) {
+                (true, _) if in_middle(position.y) => window.clone(),
+                (_, true) if in_middle(position.x) => window.clone(),
+                (true, _) | (_, true) => wall.clone(),
+                _ => continue,
+            };
+            commands.spawn_tile_entity(map, position, TileLayer::Turf, scene);
+            turfs += 1;
+        }
+        queue.apply(&mut app.world);
+        turfs
+    }
+
+    /// Bodies running around in the rooms, bouncing off the walls.
+    fn spawn_moving_bodies(app: &mut App) {
+        let rooms = STATION_CHUNKS * CHUNK_SIZE / ROOM_SIZE;
+        for i in 0..MOVING_BODIES as u32 {
+            let room = UVec2::new(i % rooms, i / rooms) * ROOM_SIZE;
+            let center = room.as_vec2() + ROOM_SIZE as f32 / 2.0;
+            let angle = i as f32 * 0.7;
+            app.world.spawn((
+                TransformBundle::from(Transform::from_xyz(center.x, 1.0, center.y)),
+                RigidBody::Dynamic,
+                Collider::capsule_y(0.5, 0.3),
+                LockedAxes::ROTATION_LOCKED,
+                Velocity::linear(Vec3::new(angle.cos(), 0.0, angle.sin()) * 4.0),
+            ));
+        }
+    }
+
+    #[test]
+    fn full_station_tick_stays_within_budget() {
+        let mut app = server_app(ServerConfig::default());
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(tick()));
+        app.update();
+        let turfs = spawn_station(&mut app);
+        spawn_moving_bodies(&mut app);
+
+        // Wait for the turf scenes to spawn and the chunk colliders to be built
+        let mut loaded = false;
+        for _ in 0..MAX_LOADING_FRAMES {
+            app.update();
+            let chunks = app.world.query::<&ChunkCollider>().iter(&app.world).count();
+            if chunks > 0 && app.world.resource::<NetworkSceneSpawner>().is_idle() {
+                loaded = true;
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        assert!(loaded, "The station didn't finish spawning");
+        // One more frame to build chunks of the last turfs
+        app.update();
+
+        let chunks = app.world.query::<&ChunkCollider>().iter(&app.world).count();
+        assert!(chunks <= (STATION_CHUNKS * STATION_CHUNKS) as usize);
+        let disabled = app
+            .world
+            .query_filtered::<(), With<ColliderDisabled>>()
+            .iter(&app.world)
+            .count();
+        assert!(
+            disabled > turfs / 2,
+            "Only {} of {} turf colliders were batched",
+            disabled,
+            turfs
+        );
+
+        let start = Instant::now();
+        for _ in 0..MEASURED_TICKS {
+            app.update();
+        }
+        let average = start.elapsed() / MEASURED_TICKS;
+        let budget = tick().mul_f64(BUDGET_MARGIN);
+        let timings = app.world.resource::<PhysicsTimings>();
+        assert!(
+            average < budget,
+            "Ticks took {:?} on average, the budget is {:?}. Physics step {:.2}ms, broad phase {:.2}ms, narrow phase {:.2}ms, solver {:.2}ms",
+            average,
+            budget,
+            timings.step,
+            timings.broad_phase,
+            timings.narrow_phase,
+            timings.solver
+        );
+    }
+}
@@ -0,0 +1,359 @@
+use bevy::prelude::*;
+use maps::{tile_to_world, TileMap};
+use networking::{
+    identity::{NetworkIdentities, NetworkIdentity},
+    is_server,
+    messaging::{AppExt, MessageEvent, MessageReceivers, MessageSender},
+    spawning::ClientControls,
+    time::ServerNetworkTime,
+    visibility::NetworkVisibilities,
+    Players,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    communication::{EmoteEvent, SpeechName},
+    construction::stages::ConstructionStage,
+    cooldown::{CooldownKey, Cooldowns},
+    door::Door,
+    items::Item,
+    vision::LineOfSight,
+};
+
+#[cfg(feature = "client")]
+use {
+    crate::{
+        camera::{MainCamera, WorldCursor},
+        ui::has_window,
+        GameState,
+    },
+    bevy::window::PrimaryWindow,
+    bevy_egui::EguiContexts,
+    bevy_rapier3d::prelude::RapierContext,
+    maps::{world_to_tile, TileMapClient},
+};
+
+/// Lets players point at things, which shows an arrow above them to everyone nearby.
+pub struct PointingPlugin;
+
+impl Plugin for PointingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_network_message::<PointRequest>("PointRequest")
+            .add_network_message::<PointedMessage>("PointedMessage");
+
+        if is_server(app) {
+            app.add_systems(Update, handle_point_requests);
+        } else {
+            #[cfg(feature = "client")]
+            app.init_resource::<PointArrows>().add_systems(
+                Update,
+                (
+                    client_point.run_if(has_window),
+                    (receive_pointed, draw_point_arrows).chain(),
+                )
+                    .run_if(in_state(GameState::Game)),
+            );
+        }
+    }
+}
+
+/// Seconds a creature has to wait between pointing
+const POINT_COOLDOWN: f32 = 1.0;
+const POINT_COOLDOWN_KEY: CooldownKey = "pointing.point";
+/// Things further away can't be made out well enough to point at them
+const MAX_POINT_DISTANCE: f32 = 20.0;
+/// Rays are cast at roughly eye level, so low furniture doesn't block sight
+const EYE_HEIGHT: f32 = 1.5;
+/// Height the line of sight to a target is checked at
+const TARGET_HEIGHT: f32 = 0.5;
+/// Walls and other tile objects block the ray themselves, so hits this close to the target count
+const TILE_TOLERANCE: f32 = 0.75;
+/// How long the arrow stays above the target
+#[cfg(feature = "client")]
+const ARROW_SECONDS: f32 = 3.0;
+#[cfg(feature = "client")]
+const ARROW_HEIGHT: f32 = 2.0;
+#[cfg(feature = "client")]
+const ARROW_LENGTH: f32 = 0.6;
+
+/// Something a player can point at.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub enum PointTarget {
+    Entity(NetworkIdentity),
+    Tile(UVec2),
+}
+
+/// Client message to point at something.
+#[derive(Serialize, Deserialize)]
+struct PointRequest {
+    target: PointTarget,
+}
+
+/// Server message that a creature pointed at something, sent to the players that can see it.
+#[derive(Serialize, Deserialize)]
+struct PointedMessage {
+    actor: NetworkIdentity,
+    target: PointTarget,
+}
+
+/// How the target is called in chat, like "the airlock".
+fn describe(
+    entity: Entity,
+    names: &Query<(
+        Option<&SpeechName>,
+        Option<&Name>,
+        Option<&Item>,
+        Option<&ConstructionStage>,
+        Has<Door>,
+    )>,
+) -> String {
+    match names.get(entity) {
+        Ok((Some(speech_name), ..)) => speech_name.0.clone(),
+        Ok((_, Some(name), ..)) => name.as_str().to_owned(),
+        Ok((_, _, Some(item), ..)) => format!("the {}", item.name.to_lowercase()),
+        Ok((_, _, _, _, true)) => "the airlock".into(),
+        Ok((_, _, _, Some(stage), _)) => format!("the {}", stage.id.replace('_', " ")),
+        _ => "something".into(),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn handle_point_requests(
+    mut messages: EventReader<MessageEvent<PointRequest>>,
+    players: Res<Players>,
+    controls: Res<ClientControls>,
+    identities: Res<NetworkIdentities>,
+    visibilities: Res<NetworkVisibilities>,
+    transforms: Query<&GlobalTransform>,
+    parents: Query<&Parent>,
+    maps: Query<(&TileMap, &GlobalTransform)>,
+    names: Query<(
+        Option<&SpeechName>,
+        Option<&Name>,
+        Option<&Item>,
+        Option<&ConstructionStage>,
+        Has<Door>,
+    )>,
+    sight: LineOfSight,
+    time: Res<ServerNetworkTime>,
+    mut cooldowns: Query<&mut Cooldowns>,
+    mut emotes: EventWriter<EmoteEvent>,
+    mut sender: MessageSender,
+) {
+    for event in messages.iter() {
+        let Some(actor) = players
+            .get(event.connection)
+            .and_then(|player| controls.controlled_entity(player.id))
+        else {
+            continue;
+        };
+        let (Some(actor_identity), Ok(actor_transform)) =
+            (identities.get_identity(actor), transforms.get(actor))
+        else {
+            continue;
+        };
+
+        let target = event.message.target;
+        let (target_entity, target_position) = match target {
+            PointTarget::Entity(identity) => {
+                let Some(entity) = identities.get_entity(identity) else {
+                    continue;
+                };
+                let Ok(transform) = transforms.get(entity) else {
+                    continue;
+                };
+                (Some(entity), transform.translation())
+            }
+            PointTarget::Tile(tile) => {
+                let Ok((map, map_transform)) = maps.get_single() else {
+                    continue;
+                };
+                // Pointing at a tile with furniture is pointing at the furniture
+                let furniture = map.tile(tile).and_then(|tile| tile.furniture);
+                (furniture, tile_to_world(map_transform, tile))
+            }
+        };
+
+        // Reach doesn't matter, but the target has to be in sight
+        let eye = actor_transform.translation() + Vec3::Y * EYE_HEIGHT;
+        let offset = target_position + Vec3::Y * TARGET_HEIGHT - eye;
+        let distance = offset.length();
+        if distance > MAX_POINT_DISTANCE {
+            continue;
+        }
+        let in_sight = distance <= f32::EPSILON
+            || match sight.cast(eye, offset / distance, distance) {
+                None => true,
+                Some((hit, toi)) => {
+                    toi >= distance - TILE_TOLERANCE
+                        || target_entity.is_some_and(|target| {
+                            hit == target || parents.iter_ancestors(hit).any(|e| e == target)
+                        })
+                }
+            };
+        if !in_sight {
+            continue;
+        }
+        if !cooldowns
+            .get_mut(actor)
+            .is_ok_and(|mut c| c.try_start(POINT_COOLDOWN_KEY, POINT_COOLDOWN, &time))
+        {
+            continue;
+        }
+
+        let description = match target_entity {
+            Some(entity) => describe(entity, &names),
+            None => "the floor".into(),
+        };
+        emotes.send(EmoteEvent {
+            actor,
+            target: None,
+            text: format!("points at {}", description),
+        });
+
+        // Only players that can see the actor need the arrow
+        let Some(visibility) = visibilities.get(actor_identity) else {
+            continue;
+        };
+        sender.send(
+            &PointedMessage {
+                actor: actor_identity,
+                target,
+            },
+            MessageReceivers::Set(visibility.observers().copied().collect()),
+        );
+    }
+}
+
+/// Arrows currently shown, with the time they appeared.
+#[cfg(feature = "client")]
+#[derive(Resource, Default)]
+struct PointArrows(Vec<(ArrowTarget, f32)>);
+
+#[cfg(feature = "client")]
+enum ArrowTarget {
+    Entity(Entity),
+    Position(Vec3),
+}
+
+/// Points at what is under the cursor when the point key is pressed.
+#[cfg(feature = "client")]
+#[allow(clippy::too_many_arguments)]
+fn client_point(
+    keys: Res<Input<KeyCode>>,
+    mut contexts: EguiContexts,
+    rapier: Res<RapierContext>,
+    windows: Query<(Entity, &Window), With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    parents: Query<&Parent>,
+    identities: Res<NetworkIdentities>,
+    cursor: Res<WorldCursor>,
+    maps: Query<&GlobalTransform, With<TileMapClient>>,
+    mut sender: MessageSender,
+) {
+    if !keys.just_pressed(KeyCode::P) {
+        return;
+    }
+
+    let Ok((window_entity, window)) = windows.get_single() else {
+        return;
+    };
+    if contexts
+        .try_ctx_for_window_mut(window_entity)
+        .is_some_and(|c| c.is_pointer_over_area())
+    {
+        return;
+    }
+
+    let hovered = window
+        .cursor_position()
+        .zip(cameras.get_single().ok())
+        .and_then(|(position, (camera, camera_transform))| {
+            camera.viewport_to_world(camera_transform, position)
+        })
+        .and_then(|ray| rapier.cast_ray(ray.origin, ray.direction, 100.0, true, default()))
+        .and_then(|(entity, _)| {
+            std::iter::once(entity)
+                .chain(parents.iter_ancestors(entity))
+                .find_map(|e| identities.get_identity(e))
+        });
+
+    let target = match hovered {
+        Some(identity) => PointTarget::Entity(identity),
+        None => {
+            let Some(tile) =
+                cursor
+                    .0
+                    .zip(maps.get_single().ok())
+                    .and_then(|(position, map_transform)| {
+                        world_to_tile(map_transform.affine().inverse().transform_point3(position))
+                    })
+            else {
+                return;
+            };
+            PointTarget::Tile(tile)
+        }
+    };
+    sender.send_to_server(&PointRequest { target });
+}
+
+#[cfg(feature = "client")]
+fn receive_pointed(
+    mut messages: EventReader<MessageEvent<PointedMessage>>,
+    identities: Res<NetworkIdentities>,
+    maps: Query<&GlobalTransform, With<TileMapClient>>,
+    mut arrows: ResMut<PointArrows>,
+    time: Res<Time>,
+) {
+    for event in messages.iter() {
+        let target = match event.message.target {
+            PointTarget::Entity(identity) => {
+                let Some(entity) = identities.get_entity(identity) else {
+                    continue;
+                };
+                ArrowTarget::Entity(entity)
+            }
+            PointTarget::Tile(tile) => {
+                let Ok(map_transform) = maps.get_single() else {
+                    continue;
+                };
+                ArrowTarget::Position(tile_to_world(map_transform, tile))
+            }
+        };
+        arrows.0.push((target, time.elapsed_seconds()));
+    }
+}
+
+#[cfg(feature = "client")]
+fn draw_point_arrows(
+    mut arrows: ResMut<PointArrows>,
+    transforms: Query<&GlobalTransform>,
+    time: Res<Time>,
+    mut gizmos: Gizmos,
+) {
+    let now = time.elapsed_seconds();
+    arrows.0.retain(|(target, started)| {
+        now - started < ARROW_SECONDS
+            && match target {
+                ArrowTarget::Entity(entity) => transforms.contains(*entity),
+                ArrowTarget::Position(_) => true,
+            }
+    });
+
+    for (target, started) in arrows.0.iter() {
+        let base = match target {
+            ArrowTarget::Entity(entity) => transforms.get(*entity).unwrap().translation(),
+            ArrowTarget::Position(position) => *position,
+        };
+        // Bob up and down to catch the eye
+        let bob = ((now - started) * 6.0).sin() * 0.1;
+        let tip = base + Vec3::Y * (ARROW_HEIGHT + bob);
+        let tail = tip + Vec3::Y * ARROW_LENGTH;
+        let color = Color::YELLOW;
+        gizmos.line(tail, tip, color);
+        for side in [Vec3::X, Vec3::Z] {
+            gizmos.line(tip, tip + (Vec3::Y + side) * 0.15, color);
+            gizmos.line(tip, tip + (Vec3::Y - side) * 0.15, color);
+        }
+    }
+}
@@ -0,0 +1,469 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use bevy::{asset::HandleId, prelude::*, utils::HashMap};
+use networking::{
+    is_server,
+    messaging::{AppExt, MessageEvent, MessageSender},
+    spawning::ClientControls,
+    ClientEvent, ConnectionId, Players, ServerEvent, UserData,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::user_config_directory,
+    job::{JobDefinition, SelectedJobs},
+    text_filter::{PlayerText, TextContext},
+};
+
+pub struct ProfilePlugin;
+
+impl Plugin for ProfilePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_network_message::<ProfileMessage>("ProfileMessage");
+        if is_server(app) {
+            app.init_resource::<CharacterProfiles>()
+                .add_systems(Update, (handle_profile_message, remove_disconnected));
+        } else {
+            app.insert_resource(Profiles::load(&profile_directory()))
+                .add_systems(Update, (apply_selected_profile, send_profile_on_join));
+        }
+    }
+}
+
+/// Directory in the user config directory the client stores character profiles in
+const PROFILE_DIRECTORY: &str = "profiles";
+/// The newest version of the profile format
+const PROFILE_VERSION: u32 = 1;
+/// The longest character name the server accepts
+pub const MAX_CHARACTER_NAME_LENGTH: usize = 32;
+/// The most loadout choices the server keeps per player
+const MAX_LOADOUT_CHOICES: usize = 32;
+
+/// A character saved on the client.
+///
+/// All fields must have a default value, so profiles saved by older versions can still be loaded.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct CharacterProfile {
+    /// Format version the profile was saved with.
+    /// Profiles from before versioning have none, they are version 0.
+    #[serde(default)]
+    pub version: u32,
+    pub name: String,
+    /// Job ids ordered by preference
+    pub job_preferences: Vec<String>,
+    /// Item ids the player prefers to spawn with
+    pub loadout: Vec<String>,
+    /// Id of the body template the character spawns with
+    pub body: String,
+    // TODO: Store appearance once characters can be customized
+}
+
+impl Default for CharacterProfile {
+    fn default() -> Self {
+        Self {
+            version: PROFILE_VERSION,
+            name: "New Character".into(),
+            job_preferences: Vec::new(),
+            loadout: Vec::new(),
+            body: "human".into(),
+        }
+    }
+}
+
+impl CharacterProfile {
+    /// Upgrades a profile saved with an older format version.
+    fn migrate(&mut self) {
+        // Version 0 profiles were saved before versioning, they only differ by missing fields
+        if self.version < PROFILE_VERSION {
+            self.version = PROFILE_VERSION;
+        }
+    }
+}
+
+/// Where the client keeps character profiles.
+/// Profiles in the working directory, where older versions kept them, are copied over once.
+fn profile_directory() -> PathBuf {
+    let directory = user_config_directory().join(PROFILE_DIRECTORY);
+    let legacy = Path::new(PROFILE_DIRECTORY);
+    if directory.exists() || !legacy.is_dir() || directory == legacy {
+        return directory;
+    }
+
+    let result = fs::create_dir_all(&directory).and_then(|_| {
+        for entry in fs::read_dir(legacy)? {
+            let path = entry?.path();
+            if let Some(name) = path.file_name() {
+                fs::copy(&path, directory.join(name))?;
+            }
+        }
+        Ok(())
+    });
+    if let Err(err) = result {
+        error!(directory = ?directory, error = %err, "Error copying character profiles, using the old directory");
+        return legacy.to_owned();
+    }
+    info!(directory = ?directory, "Copied character profiles to the config directory");
+    directory
+}
+
+/// The character profiles available on this client.
+#[derive(Resource)]
+pub struct Profiles {
+    directory: PathBuf,
+    profiles: Vec<(PathBuf, CharacterProfile)>,
+    selected: usize,
+    /// Messages about profiles that could not be loaded
+    pub notices: Vec<String>,
+}
+
+impl Profiles {
+    fn load(directory: &Path) -> Self {
+        let mut profiles = Self {
+            directory: directory.to_owned(),
+            profiles: Vec::new(),
+            selected: 0,
+            notices: Vec::new(),
+        };
+
+        let entries = match fs::read_dir(directory) {
+            Ok(e) => e,
+            Err(_) => {
+                profiles.create(CharacterProfile::default());
+                return profiles;
+            }
+        };
+
+        let mut paths: Vec<_> = entries
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| p.extension().map(|e| e == "toml").unwrap_or(false))
+            .collect();
+        paths.sort();
+
+        for path in paths {
+            let parsed = fs::read_to_string(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|text| {
+                    toml::from_str::<CharacterProfile>(&text).map_err(|e| e.to_string())
+                });
+            match parsed {
+                Ok(mut profile) => {
+                    let version = profile.version;
+                    profile.migrate();
+                    profiles.profiles.push((path.clone(), profile));
+                    if version != PROFILE_VERSION {
+                        profiles.save(profiles.profiles.len() - 1);
+                    }
+                }
+                Err(err) => {
+                    // Move corrupt files out of the way, so the player can recover them manually
+                    let mut quarantined = path.clone().into_os_string();
+                    quarantined.push(".broken");
+                    let _ = fs::rename(&path, &quarantined);
+                    warn!(path = ?path, error = %err, "Quarantined broken character profile");
+                    profiles.notices.push(format!(
+                        "Profile {} could not be loaded and was renamed to {}",
+                        path.display(),
+                        Path::new(&quarantined).display()
+                    ));
+                }
+            }
+        }
+
+        if profiles.profiles.is_empty() {
+            profiles.create(CharacterProfile::default());
+        }
+
+        profiles
+    }
+
+    fn save(&mut self, index: usize) {
+        let (path, profile) = &self.profiles[index];
+        let result = fs::create_dir_all(&self.directory)
+            .map_err(|e| e.to_string())
+            .and_then(|_| toml::to_string(profile).map_err(|e| e.to_string()))
+            .and_then(|text| fs::write(path, text).map_err(|e| e.to_string()));
+        if let Err(err) = result {
+            error!(path = ?path, error = %err, "Error saving character profile");
+            self.notices
+                .push(format!("Profile {} could not be saved", path.display()));
+        }
+    }
+
+    fn free_path(&self) -> PathBuf {
+        (0..)
+            .map(|i| self.directory.join(format!("profile_{}.toml", i)))
+            .find(|p| !p.exists() && self.profiles.iter().all(|(other, _)| other != p))
+            .unwrap()
+    }
+
+    /// Adds a new profile and selects it.
+    pub fn create(&mut self, profile: CharacterProfile) {
+        let path = self.free_path();
+        self.profiles.push((path, profile));
+        self.selected = self.profiles.len() - 1;
+        self.save(self.selected);
+    }
+
+    /// Creates a copy of the selected profile.
+    pub fn duplicate_selected(&mut self) {
+        let mut profile = self.selected().clone();
+        profile.name.push_str(" (copy)");
+        self.create(profile);
+    }
+
+    /// Deletes the selected profile. The last profile can not be deleted.
+    pub fn delete_selected(&mut self) {
+        if self.profiles.len() <= 1 {
+            return;
+        }
+
+        let (path, _) = self.profiles.remove(self.selected);
+        if let Err(err) = fs::remove_file(&path) {
+            warn!(path = ?path, error = %err, "Error deleting character profile");
+        }
+        self.selected = self.selected.min(self.profiles.len() - 1);
+    }
+
+    /// Applies changes to the selected profile and saves it.
+    pub fn edit_selected(&mut self, edit: impl FnOnce(&mut CharacterProfile)) {
+        edit(&mut self.profiles[self.selected].1);
+        self.save(self.selected);
+    }
+
+    pub fn select(&mut self, index: usize) {
+        if index < self.profiles.len() {
+            self.selected = index;
+        }
+    }
+
+    pub fn selected_index(&self) -> usize {
+        self.selected
+    }
+
+    pub fn selected(&self) -> &CharacterProfile {
+        &self.profiles[self.selected].1
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &CharacterProfile> {
+        self.profiles.iter().map(|(_, p)| p)
+    }
+}
+
+/// Sent by the client after joining to tell the server about the selected character.
+#[derive(Serialize, Deserialize)]
+pub struct ProfileMessage {
+    pub name: String,
+    pub job_preferences: Vec<String>,
+    /// Item ids picked for job loadout slots
+    pub loadout: Vec<String>,
+    /// Id of the body template, checked against the allowed bodies when spawning
+    pub body: String,
+}
+
+impl From<&CharacterProfile> for ProfileMessage {
+    fn from(profile: &CharacterProfile) -> Self {
+        Self {
+            name: profile.name.clone(),
+            job_preferences: profile.job_preferences.clone(),
+            loadout: profile.loadout.clone(),
+            body: profile.body.clone(),
+        }
+    }
+}
+
+/// Use the selected profile name when connecting
+fn apply_selected_profile(
+    profiles: Res<Profiles>,
+    user_data: Option<Res<UserData>>,
+    mut commands: Commands,
+) {
+    if !profiles.is_changed() {
+        return;
+    }
+
+    // A name passed on the command line takes priority at startup
+    if profiles.is_added() && user_data.is_some() {
+        return;
+    }
+
+    commands.insert_resource(UserData {
+        username: profiles.selected().name.clone(),
+    });
+}
+
+fn send_profile_on_join(
+    mut events: EventReader<ClientEvent>,
+    profiles: Res<Profiles>,
+    mut sender: MessageSender,
+) {
+    for event in events.iter() {
+        if event != &ClientEvent::Joined {
+            continue;
+        }
+
+        sender.send_to_server(&ProfileMessage::from(profiles.selected()));
+    }
+}
+
+/// Validated character information sent by players.
+#[derive(Resource, Default)]
+pub struct CharacterProfiles {
+    names: HashMap<ConnectionId, String>,
+    /// Loadout choices that are offered by at least one job
+    loadouts: HashMap<ConnectionId, Vec<String>>,
+    bodies: HashMap<ConnectionId, String>,
+}
+
+impl CharacterProfiles {
+    pub fn name(&self, connection: ConnectionId) -> Option<&str> {
+        self.names.get(&connection).map(|n| n.as_str())
+    }
+
+    /// Item ids the player picked for loadout slots. They still have to be checked against the job.
+    pub fn loadout(&self, connection: ConnectionId) -> &[String] {
+        self.loadouts
+            .get(&connection)
+            .map_or(&[], |choices| choices.as_slice())
+    }
+
+    /// Id of the body the player picked. It still has to be checked against the allowed bodies.
+    pub fn body(&self, connection: ConnectionId) -> Option<&str> {
+        self.bodies.get(&connection).map(|b| b.as_str())
+    }
+}
+
+/// Checks if a player provided character name is acceptable.
+pub fn validate_character_name(name: &str) -> Option<String> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > MAX_CHARACTER_NAME_LENGTH {
+        return None;
+    }
+
+    let allowed = name
+        .chars()
+        .all(|c| c.is_alphanumeric() || matches!(c, ' ' | '-' | '\'' | '.'));
+    allowed.then(|| name.to_owned())
+}
+
+fn handle_profile_message(
+    mut messages: EventReader<MessageEvent<ProfileMessage>>,
+    mut profiles: ResMut<CharacterProfiles>,
+    mut selected_jobs: ResMut<SelectedJobs>,
+    jobs: Res<Assets<JobDefinition>>,
+    players: Res<Players>,
+    controls: Res<ClientControls>,
+    mut player_text: PlayerText,
+) {
+    for event in messages.iter() {
+        let Some(player) = players.get(event.connection) else {
+            continue;
+        };
+
+        // Only allow changing the character if not already in the game
+        if controls.controlled_entity(player.id).is_some() {
+            continue;
+        }
+
+        let name = player_text
+            .accept(
+                event.connection,
+                TextContext::CharacterName,
+                &event.message.name,
+            )
+            .and_then(|name| validate_character_name(&name));
+        match name {
+            Some(name) => {
+                profiles.names.insert(event.connection, name);
+            }
+            None => {
+                warn!(connection = ?event.connection, "Player sent invalid character name");
+            }
+        }
+
+        let loadout = event
+            .message
+            .loadout
+            .iter()
+            .take(MAX_LOADOUT_CHOICES)
+            .filter(|item| {
+                let offered = jobs.iter().any(|(_, job)| job.offers(item));
+                if !offered {
+                    warn!(
+                        connection = ?event.connection,
+                        item = item.as_str(),
+                        "Player chose a loadout item no job offers, it will be ignored"
+                    );
+                }
+                offered
+            })
+            .cloned()
+            .collect();
+        profiles.loadouts.insert(event.connection, loadout);
+        profiles
+            .bodies
+            .insert(event.connection, event.message.body.clone());
+
+        // Select the most preferred job, unless the player already picked one
+        if selected_jobs.get(event.connection, &jobs).is_some() {
+            continue;
+        }
+        let preferred = event.message.job_preferences.iter().find_map(|id| {
+            jobs.iter().find_map(|(handle, job)| match handle {
+                HandleId::AssetPathId(path) if &job.id == id => Some(path),
+                _ => None,
+            })
+        });
+        if let Some(job) = preferred {
+            selected_jobs.select(event.connection, job);
+        }
+    }
+}
+
+fn remove_disconnected(
+    mut events: EventReader<ServerEvent>,
+    mut profiles: ResMut<CharacterProfiles>,
+) {
+    for event in events.iter() {
+        if let ServerEvent::PlayerDisconnected(connection) = event {
+            profiles.names.remove(connection);
+            profiles.loadouts.remove(connection);
+            profiles.bodies.remove(connection);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unversioned_profile_is_migrated() {
+        let directory = std::env::temp_dir().join(format!("ssnt-profiles-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("profile_0.toml");
+        fs::write(
+            &path,
+            "name = \"Old Timer\"\njob_preferences = [\"engineer\"]\n",
+        )
+        .unwrap();
+
+        let profile: CharacterProfile =
+            toml::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(profile.version, 0);
+
+        let profiles = Profiles::load(&directory);
+        assert_eq!(profiles.selected().name, "Old Timer");
+        assert_eq!(profiles.selected().version, PROFILE_VERSION);
+        // The upgraded profile was written back
+        let saved: CharacterProfile = toml::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved.version, PROFILE_VERSION);
+        assert_eq!(saved.job_preferences, ["engineer"]);
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
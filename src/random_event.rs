@@ -0,0 +1,90 @@
+use bevy::{ecs::system::Command, prelude::*};
+
+use self::carp::CarpMigrationPlugin;
+
+pub mod carp;
+
+/// Keeps the events that can happen during a round, started by the timeline or by admins.
+pub struct RandomEventPlugin;
+
+impl Plugin for RandomEventPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RandomEvents>()
+            .add_plugins(CarpMigrationPlugin);
+    }
+}
+
+/// Something that disrupts the round, like a wave of hostile creatures.
+pub struct RandomEvent {
+    pub id: &'static str,
+    pub name: &'static str,
+    pub description: &'static str,
+    /// Starts the event, or returns why it can't start right now
+    pub start: fn(&mut World) -> Result<(), String>,
+}
+
+/// Every registered event. Registered on both sides, so clients can list them without asking.
+#[derive(Resource, Default)]
+pub struct RandomEvents {
+    events: Vec<RandomEvent>,
+}
+
+impl RandomEvents {
+    fn register(&mut self, event: RandomEvent) {
+        if self.get(event.id).is_some() {
+            panic!("Random event {} registered twice", event.id);
+        }
+        self.events.push(event);
+    }
+
+    pub fn get(&self, id: &str) -> Option<&RandomEvent> {
+        self.events.iter().find(|e| e.id == id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &RandomEvent> {
+        self.events.iter()
+    }
+}
+
+pub trait RandomEventAppExt {
+    fn add_random_event(&mut self, event: RandomEvent) -> &mut Self;
+}
+
+impl RandomEventAppExt for App {
+    fn add_random_event(&mut self, event: RandomEvent) -> &mut Self {
+        self.world
+            .get_resource_or_insert_with(RandomEvents::default)
+            .register(event);
+        self
+    }
+}
+
+/// Starts a registered event by its id, logging why if it couldn't.
+pub struct StartRandomEvent {
+    pub id: String,
+}
+
+impl Command for StartRandomEvent {
+    fn apply(self, world: &mut World) {
+        let Some(start) = world
+            .resource::<RandomEvents>()
+            .get(&self.id)
+            .map(|event| event.start)
+        else {
+            warn!(
+                event = self.id.as_str(),
+                "Tried to start unknown random event"
+            );
+            return;
+        };
+
+        match start(world) {
+            Ok(()) => info!(event = self.id.as_str(), "Started random event"),
+            Err(err) => warn!(
+                event = self.id.as_str(),
+                error = err.as_str(),
+                "Random event could not start"
+            ),
+        }
+    }
+}
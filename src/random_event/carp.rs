@@ -0,0 +1,470 @@
+//! Space carp migrating through the station, the first random event.
+//!
+//! Carp arrive in waves at the carp landmarks, hunt the closest crew member and swim off when the event ends.
+
+use std::time::Duration;
+
+use bevy::{prelude::*, time::common_conditions::on_timer};
+use bevy_rapier3d::prelude::{LockedAxes, Velocity};
+use maps::{world_to_tile, TileMap, CARP_SPAWN_LANDMARK};
+use networking::{is_server, scene::NetworkSceneBundle, spawning::ClientControls};
+use serde::Deserialize;
+
+use crate::{
+    body::{
+        health::{receive_damage, VitalStatus, Vitals},
+        self_or_ancestor, Body,
+    },
+    butchering::Corpse,
+    combat::damage::{AffectedEntity, Attack, AttackSource, KineticDamage, KineticShape},
+    communication::{AnnouncementAudience, AnnouncementEvent},
+    config::ServerConfig,
+    job::Affiliation,
+    navigation::{NavGrid, NavPath},
+    rng::GameRng,
+    round::RoundState,
+    stats::PlayerStats,
+};
+
+use super::{RandomEvent, RandomEventAppExt};
+
+pub(super) struct CarpMigrationPlugin;
+
+impl Plugin for CarpMigrationPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Carp>().add_random_event(RandomEvent {
+            id: "carp_migration",
+            name: "Carp migration",
+            description: "Waves of space carp swim into the station and attack the crew",
+            start: start_migration,
+        });
+
+        if is_server(app) {
+            let config = app
+                .world
+                .get_resource::<ServerConfig>()
+                .map(|config| config.carp_migration.clone())
+                .unwrap_or_default();
+            app.insert_resource(config)
+                .init_resource::<CarpMigration>()
+                .add_systems(OnExit(RoundState::Running), end_migration)
+                .add_systems(
+                    Update,
+                    (
+                        setup_carp,
+                        run_migration,
+                        carp_ai.run_if(on_timer(Duration::from_secs_f32(AI_INTERVAL))),
+                        hurt_carp.after(receive_damage),
+                    )
+                        .run_if(in_state(RoundState::Running)),
+                );
+        }
+    }
+}
+
+const CARP_SCENE: &str = "mobs/carp.scn.ron";
+/// Seconds between carp deciding where to swim
+const AI_INTERVAL: f32 = 0.25;
+/// Paths searched per AI update at most, the other carp swim straight until their turn
+const MAX_PATH_SEARCHES: usize = 4;
+/// Meters within which carp notice crew
+const AGGRO_RANGE: f32 = 20.0;
+/// Meters from which carp can bite
+const BITE_RANGE: f32 = 1.2;
+/// Seconds between bites of a carp
+const BITE_COOLDOWN: f32 = 1.5;
+const BITE_MASS: f32 = 4.0;
+const BITE_VELOCITY: f32 = 15.0;
+/// Health carp lose for every joule of an impact
+const HEALTH_PER_JOULE: f32 = 0.01;
+
+#[derive(Resource, Deserialize, Clone)]
+#[serde(default)]
+pub struct CarpMigrationConfig {
+    /// Landmarks carp arrive at, `carp_spawn` is converted from the map's carp spawn points
+    pub landmarks: Vec<String>,
+    pub waves: u32,
+    /// Seconds between waves
+    pub wave_interval: f32,
+    /// Carp in a wave for every living crew member
+    pub carp_per_crew: f32,
+    pub min_wave_size: u32,
+    pub max_wave_size: u32,
+    /// Most carp alive at the same time, later waves are smaller if it's reached
+    pub max_carp: u32,
+    /// Seconds after the start when surviving carp leave
+    pub duration: f32,
+}
+
+impl Default for CarpMigrationConfig {
+    fn default() -> Self {
+        Self {
+            landmarks: vec![CARP_SPAWN_LANDMARK.into()],
+            waves: 3,
+            wave_interval: 40.0,
+            carp_per_crew: 0.5,
+            min_wave_size: 2,
+            max_wave_size: 8,
+            max_carp: 24,
+            duration: 240.0,
+        }
+    }
+}
+
+/// A hostile fish that bites the crew.
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct Carp {
+    pub health: f32,
+    /// Meters per second
+    pub speed: f32,
+}
+
+impl Default for Carp {
+    fn default() -> Self {
+        Self {
+            health: 100.0,
+            speed: 3.0,
+        }
+    }
+}
+
+/// What a carp is hunting.
+#[derive(Component, Default)]
+struct CarpBrain {
+    target: Option<Entity>,
+    target_tile: Option<UVec2>,
+    /// A path to the target tile was already searched for
+    searched: bool,
+    last_bite: f32,
+}
+
+#[derive(Resource, Default)]
+struct CarpMigration {
+    active: Option<Migration>,
+}
+
+struct Migration {
+    started: f32,
+    waves_spawned: u32,
+    spawn_points: Vec<Vec3>,
+}
+
+fn start_migration(world: &mut World) -> Result<(), String> {
+    if world.resource::<State<RoundState>>().get() != &RoundState::Running {
+        return Err("the round isn't running".into());
+    }
+    if world.resource::<CarpMigration>().active.is_some() {
+        return Err("a carp migration is already happening".into());
+    }
+
+    let landmarks = world.resource::<CarpMigrationConfig>().landmarks.clone();
+    let spawn_points: Vec<_> = world
+        .query::<&TileMap>()
+        .iter(world)
+        .flat_map(|map| {
+            landmarks
+                .iter()
+                .filter_map(|landmark| map.job_spawn_positions.get(landmark))
+                .flatten()
+                .map(|p| Vec3::new(p.x as f32, 1.0, p.y as f32))
+        })
+        .collect();
+    if spawn_points.is_empty() {
+        return Err(format!(
+            "the map has none of the landmarks {}",
+            landmarks.join(", ")
+        ));
+    }
+
+    let started = world.resource::<Time>().elapsed_seconds();
+    world.resource_mut::<CarpMigration>().active = Some(Migration {
+        started,
+        waves_spawned: 0,
+        spawn_points,
+    });
+    world
+        .resource_mut::<Events<AnnouncementEvent>>()
+        .send(AnnouncementEvent::new(
+            "announcement.carp_arrived",
+            &[],
+            AnnouncementAudience::Everyone,
+        ));
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn run_migration(
+    mut migration: ResMut<CarpMigration>,
+    config: Res<CarpMigrationConfig>,
+    time: Res<Time>,
+    crew: Query<Entity, (With<Body>, With<Affiliation>)>,
+    vitals: Vitals,
+    carp: Query<Entity, With<Carp>>,
+    asset_server: Res<AssetServer>,
+    mut rng: ResMut<GameRng>,
+    mut announcements: EventWriter<AnnouncementEvent>,
+    mut commands: Commands,
+) {
+    let Some(active) = migration.active.as_mut() else {
+        return;
+    };
+
+    let elapsed = time.elapsed_seconds() - active.started;
+    if elapsed >= config.duration {
+        // Survivors swim off
+        for entity in carp.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+        migration.active = None;
+        announcements.send(AnnouncementEvent::new(
+            "announcement.carp_left",
+            &[],
+            AnnouncementAudience::Everyone,
+        ));
+        info!("Carp migration ended");
+        return;
+    }
+
+    if active.waves_spawned >= config.waves
+        || elapsed < active.waves_spawned as f32 * config.wave_interval
+    {
+        return;
+    }
+    active.waves_spawned += 1;
+
+    let living_crew = crew
+        .iter()
+        .filter(|&body| {
+            matches!(
+                vitals.status(body),
+                Some(VitalStatus::Healthy | VitalStatus::Injured)
+            )
+        })
+        .count();
+    let wave_size = ((living_crew as f32 * config.carp_per_crew).ceil() as u32)
+        .max(config.min_wave_size)
+        .min(config.max_wave_size)
+        .min(config.max_carp.saturating_sub(carp.iter().count() as u32));
+
+    let rng = rng.stream("carp_migration");
+    for _ in 0..wave_size {
+        let point = active.spawn_points[rng.usize(..active.spawn_points.len())];
+        // Spread carp over the tile, so a wave doesn't start inside each other
+        let offset = Vec3::new(rng.f32() - 0.5, 0.0, rng.f32() - 0.5);
+        commands.spawn(NetworkSceneBundle {
+            scene: asset_server.load(CARP_SCENE).into(),
+            transform: Transform::from_translation(point + offset),
+            ..Default::default()
+        });
+    }
+    info!(
+        wave = active.waves_spawned,
+        carp = wave_size,
+        living_crew,
+        "Spawned carp wave"
+    );
+}
+
+fn end_migration(
+    mut migration: ResMut<CarpMigration>,
+    carp: Query<Entity, With<Carp>>,
+    mut commands: Commands,
+) {
+    migration.active = None;
+    for entity in carp.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn setup_carp(carp: Query<Entity, Added<Carp>>, mut commands: Commands) {
+    for entity in carp.iter() {
+        commands.entity(entity).insert((
+            CarpBrain::default(),
+            NavPath::default(),
+            Velocity::zero(),
+            LockedAxes::ROTATION_LOCKED,
+        ));
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn carp_ai(
+    mut carp: Query<(
+        Entity,
+        &Carp,
+        &GlobalTransform,
+        &mut CarpBrain,
+        &mut NavPath,
+        &mut Velocity,
+    )>,
+    crew: Query<(Entity, &Body, &GlobalTransform), With<Affiliation>>,
+    vitals: Vitals,
+    nav: Res<NavGrid>,
+    time: Res<Time>,
+    mut rng: ResMut<GameRng>,
+    mut commands: Commands,
+) {
+    // Carp keep biting crew that went down, but leave the dead alone
+    let prey: Vec<_> = crew
+        .iter()
+        .filter(|&(body, ..)| {
+            matches!(
+                vitals.status(body),
+                Some(VitalStatus::Healthy | VitalStatus::Injured | VitalStatus::Unconscious)
+            )
+        })
+        .map(|(entity, body, transform)| (entity, body, transform.translation()))
+        .collect();
+    let now = time.elapsed_seconds();
+    let mut path_searches = 0;
+
+    for (entity, carp, transform, mut brain, mut path, mut velocity) in carp.iter_mut() {
+        let position = transform.translation();
+        let closest = prey
+            .iter()
+            .map(|&(target, body, target_position)| {
+                (
+                    target,
+                    body,
+                    target_position,
+                    position.distance(target_position),
+                )
+            })
+            .filter(|&(.., distance)| distance <= AGGRO_RANGE)
+            .min_by(|a, b| a.3.total_cmp(&b.3));
+        let Some((target, body, target_position, _)) = closest else {
+            brain.target = None;
+            path.tiles.clear();
+            velocity.linvel.x = 0.0;
+            velocity.linvel.z = 0.0;
+            continue;
+        };
+
+        let offset = target_position - position;
+        if Vec2::new(offset.x, offset.z).length() <= BITE_RANGE {
+            velocity.linvel.x = 0.0;
+            velocity.linvel.z = 0.0;
+            if now - brain.last_bite >= BITE_COOLDOWN {
+                brain.last_bite = now;
+                bite(entity, body, &mut rng, &mut commands);
+            }
+            continue;
+        }
+
+        let target_tile = world_to_tile(target_position);
+        if brain.target != Some(target) || brain.target_tile != target_tile {
+            brain.target = Some(target);
+            brain.target_tile = target_tile;
+            brain.searched = false;
+            path.tiles.clear();
+        }
+
+        // Inside the station carp follow the corridors, in space they swim straight at their prey
+        let own_tile = world_to_tile(position);
+        if !brain.searched && path_searches < MAX_PATH_SEARCHES {
+            if let (Some(start), Some(goal)) = (own_tile, target_tile) {
+                if nav.get(start).is_walkable() {
+                    path_searches += 1;
+                    path.tiles = nav
+                        .find_path(start, goal, |_, tile| tile.is_walkable())
+                        .unwrap_or_default();
+                }
+            }
+            brain.searched = true;
+        }
+        while path
+            .tiles
+            .first()
+            .is_some_and(|&tile| Some(tile) == own_tile)
+        {
+            path.tiles.remove(0);
+        }
+
+        let waypoint = path.tiles.first().map_or(target_position, |tile| {
+            Vec3::new(tile.x as f32, position.y, tile.y as f32)
+        });
+        let direction =
+            Vec3::new(waypoint.x - position.x, 0.0, waypoint.z - position.z).normalize_or_zero();
+        // Falling is left to physics, carp only swim sideways
+        velocity.linvel.x = direction.x * carp.speed;
+        velocity.linvel.z = direction.z * carp.speed;
+    }
+}
+
+/// Bites a random limb of the body.
+fn bite(carp: Entity, body: &Body, rng: &mut GameRng, commands: &mut Commands) {
+    let mut limbs: Vec<_> = body.limbs().collect();
+    if limbs.is_empty() {
+        return;
+    }
+    // Sorted so the same seed bites the same limbs
+    limbs.sort_unstable();
+    let limb = limbs[rng.stream("carp_bite").usize(..limbs.len())];
+    commands.spawn((
+        Attack,
+        AffectedEntity(limb),
+        KineticDamage {
+            velocity: BITE_VELOCITY,
+            mass: BITE_MASS,
+            shape: KineticShape::Sharp,
+            scale: 1.0,
+        },
+        AttackSource {
+            attacker: carp,
+            instigator: None,
+            weapon: Some("carp_bite".into()),
+            distance: None,
+        },
+    ));
+}
+
+/// Attacks on carp take away their health, and the one who finishes a carp off gets the kill.
+fn hurt_carp(
+    attacks: Query<
+        (
+            Entity,
+            &AffectedEntity,
+            &KineticDamage,
+            Option<&AttackSource>,
+        ),
+        Added<Attack>,
+    >,
+    parents: Query<&Parent>,
+    mut carps: Query<&mut Carp>,
+    controls: Res<ClientControls>,
+    mut stats: ResMut<PlayerStats>,
+    mut commands: Commands,
+) {
+    for (attack, affected, kinetic, source) in attacks.iter() {
+        // Shots hit the collider, which is a child of the carp
+        let Some(carp_entity) = self_or_ancestor(&parents, affected.0, |e| carps.contains(e))
+        else {
+            continue;
+        };
+        commands.entity(attack).despawn();
+
+        let Ok(mut carp) = carps.get_mut(carp_entity) else {
+            continue;
+        };
+        // Already killed by an earlier attack this frame
+        if carp.health <= 0.0 {
+            continue;
+        }
+        let energy = 0.5 * kinetic.mass * kinetic.velocity.powi(2) * kinetic.scale;
+        carp.health -= energy * HEALTH_PER_JOULE;
+        if carp.health > 0.0 {
+            continue;
+        }
+
+        if let Some(source) = source {
+            stats.count_kill(source.instigator.unwrap_or(source.attacker), &controls);
+        }
+        // The corpse stays around to be butchered, no longer counted as a living carp
+        commands
+            .entity(carp_entity)
+            .remove::<(Carp, CarpBrain, NavPath)>()
+            .insert((Corpse, Velocity::zero()));
+        debug!(carp = ?carp_entity, "Carp killed");
+    }
+}
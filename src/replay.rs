@@ -0,0 +1,671 @@
+//! Recording what players send to the server, and simulating the round again from the recording.
+//!
+//! Both runs save checksums of the world at the same ticks, so the first tick where they differ
+//! points at server logic that isn't deterministic. Systems that change gameplay state shouldn't depend on
+//! the iteration order of `std` hash maps, on wall clock time or on the order of systems that aren't ordered
+//! with each other when that order changes the result (see how damage is applied in [`crate::body::health`]).
+
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    fs::{read_to_string, File},
+    hash::{Hash, Hasher},
+    io::{self, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use bevy::{
+    app::AppExit,
+    ecs::{
+        event::ManualEventReader,
+        schedule::{ExecutorKind, ScheduleLabel},
+    },
+    prelude::*,
+    time::TimeUpdateStrategy,
+};
+use maps::{TileMap, CHUNK_SIZE};
+use networking::{
+    identity::NetworkIdentity,
+    messaging::{IncomingMessage, MessageTypes, ReadMessagesSet},
+    scene::NetworkSceneSpawner,
+    time::ServerNetworkTime,
+    ConnectionId, ServerEvent, SimulatedDisconnect,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{config::ServerConfig, map_objects::PendingMapObjects, rng::GameRng, timeline};
+
+/// Records inputs when the server is started with `--record-inputs`, and simulates them with `simulate`.
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        let checkpoint_interval = if let Some(recorder) = app.world.get_resource::<InputRecorder>()
+        {
+            recorder.header.checkpoint_interval
+        } else if let Some(simulation) = app.world.get_resource::<Simulation>() {
+            simulation.header.checkpoint_interval
+        } else {
+            return;
+        };
+
+        // Systems that aren't ordered with each other run in the same order every tick,
+        // instead of whichever happens to be ready first
+        single_threaded(app, First);
+        single_threaded(app, PreUpdate);
+        single_threaded(app, StateTransition);
+        single_threaded(app, FixedUpdate);
+        single_threaded(app, Update);
+        single_threaded(app, PostUpdate);
+        single_threaded(app, Last);
+
+        app.insert_resource(ReplayClock {
+            tick: None,
+            checkpoint_interval,
+        })
+        .init_resource::<Checksums>()
+        // Game time stands still until the map is ready, then moves by exactly one tick per frame
+        .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::ZERO))
+        .configure_set(Last, ChecksumSet.run_if(checkpoint_due))
+        .add_systems(
+            Last,
+            (entity_checksum, tile_checksum, rng_checksum).in_set(ChecksumSet),
+        );
+
+        if app.world.contains_resource::<InputRecorder>() {
+            app.add_systems(Startup, write_header).add_systems(
+                Last,
+                (record_tick, advance_clock).chain().after(ChecksumSet),
+            );
+        } else {
+            app.set_runner(run_unpaced)
+                .add_systems(Startup, check_protocol)
+                .add_systems(
+                    PreUpdate,
+                    send_recorded_inputs.in_set(ReadMessagesSet::ReadChannel),
+                )
+                .add_systems(
+                    Last,
+                    (compare_checksums, advance_clock)
+                        .chain()
+                        .after(ChecksumSet),
+                );
+        }
+    }
+}
+
+/// Version of the recording format, increased when it changes
+const RECORDING_VERSION: u32 = 3;
+
+fn single_threaded(app: &mut App, label: impl ScheduleLabel) {
+    app.edit_schedule(label, |schedule| {
+        schedule.set_executor_kind(ExecutorKind::SingleThreaded);
+    });
+}
+
+/// True if the server is recording or simulating a round.
+pub fn is_active(app: &App) -> bool {
+    app.world.contains_resource::<InputRecorder>() || app.world.contains_resource::<Simulation>()
+}
+
+/// Systems adding to [`Checksums`]. They only run on checkpoint ticks.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ChecksumSet;
+
+/// Hashes of the world state on a checkpoint tick, by category.
+#[derive(Resource, Default)]
+pub struct Checksums(BTreeMap<String, u64>);
+
+impl Checksums {
+    pub fn add(&mut self, category: &str, value: u64) {
+        self.0.insert(category.to_owned(), value);
+    }
+}
+
+/// FNV-1a, which gives the same hashes in every build.
+/// The `std` hashers are free to change between Rust versions, so recordings couldn't be compared.
+pub struct StableHasher(u64);
+
+impl Default for StableHasher {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for StableHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Hashes every item on its own and adds the hashes up, so the order of queries doesn't matter.
+/// Items should be keyed by something both runs agree on, like a [`NetworkIdentity`], instead of an [`Entity`].
+pub fn unordered_hash<T: Hash>(items: impl IntoIterator<Item = T>) -> u64 {
+    items.into_iter().fold(0u64, |sum, item| {
+        let mut hasher = StableHasher::default();
+        item.hash(&mut hasher);
+        sum.wrapping_add(hasher.finish())
+    })
+}
+
+/// Config files besides the server config that change how the round plays out.
+fn config_file_paths(config: &ServerConfig) -> Vec<String> {
+    config
+        .combat
+        .iter()
+        .cloned()
+        .chain([timeline::DEFAULT_TIMELINE_FILE.to_owned()])
+        .collect()
+}
+
+/// Copies of the config files a recorded round used, see [`read_config_file`].
+#[derive(Resource, Default)]
+pub struct RecordedFiles(BTreeMap<String, Option<String>>);
+
+/// Reads a config file the round depends on.
+/// Recordings keep a copy of these, which simulations read instead of the file on disk.
+pub fn read_config_file(recorded: Option<&RecordedFiles>, path: &str) -> io::Result<String> {
+    let Some(recorded) = recorded else {
+        return read_to_string(path);
+    };
+    recorded.0.get(path).cloned().flatten().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            "the file didn't exist when the round was recorded",
+        )
+    })
+}
+
+/// Counts the ticks since the map was ready, which is when recording starts.
+/// Loading takes a different amount of ticks every time, so ticks before that can't be compared.
+#[derive(Resource)]
+struct ReplayClock {
+    tick: Option<u64>,
+    checkpoint_interval: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RecordingHeader {
+    version: u32,
+    /// A build with different message types may not be able to read the inputs
+    protocol_hash: u64,
+    seed: u64,
+    /// The text of the server config file
+    config: Option<String>,
+    /// The text of other config files by path, `None` for missing ones
+    files: BTreeMap<String, Option<String>>,
+    map_save: Option<PathBuf>,
+    checkpoint_interval: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RecordedTick {
+    tick: u64,
+    /// The network tick, which player messages refer to
+    server_tick: u32,
+    inputs: Vec<IncomingMessage>,
+    disconnects: Vec<ConnectionId>,
+    checksums: Option<BTreeMap<String, u64>>,
+}
+
+/// Writes the inputs of the round to a file.
+#[derive(Resource)]
+pub struct InputRecorder {
+    writer: BufWriter<File>,
+    /// Completed with the seed and message types on startup, then written
+    header: RecordingHeader,
+    failed: bool,
+}
+
+impl InputRecorder {
+    pub fn create(
+        path: &Path,
+        server_config: &ServerConfig,
+        config: Option<String>,
+        map_save: Option<PathBuf>,
+        checkpoint_interval: u64,
+    ) -> Result<Self, String> {
+        let file = File::create(path).map_err(|e| e.to_string())?;
+        let files = config_file_paths(server_config)
+            .into_iter()
+            .map(|path| {
+                let text = read_to_string(&path).ok();
+                (path, text)
+            })
+            .collect();
+        Ok(Self {
+            writer: BufWriter::new(file),
+            header: RecordingHeader {
+                version: RECORDING_VERSION,
+                protocol_hash: 0,
+                seed: 0,
+                config,
+                files,
+                map_save,
+                checkpoint_interval: checkpoint_interval.max(1),
+            },
+            failed: false,
+        })
+    }
+
+    fn write(&mut self, value: &impl Serialize, flush: bool) {
+        if self.failed {
+            return;
+        }
+        let result = bincode::serialize_into(&mut self.writer, value).map_err(|e| e.to_string());
+        let result = result.and_then(|_| {
+            if flush {
+                self.writer.flush().map_err(|e| e.to_string())
+            } else {
+                Ok(())
+            }
+        });
+        if let Err(err) = result {
+            error!("Error writing input recording, recording stopped: {}", err);
+            self.failed = true;
+        }
+    }
+}
+
+/// A recorded round being simulated.
+#[derive(Resource)]
+pub struct Simulation {
+    header: RecordingHeader,
+    ticks: VecDeque<RecordedTick>,
+    checkpoints: usize,
+    /// The first tick that differed from the recording, and the categories that did
+    divergence: Option<(u64, Vec<String>)>,
+}
+
+impl Simulation {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let mut reader = BufReader::new(File::open(path).map_err(|e| e.to_string())?);
+        let header: RecordingHeader =
+            bincode::deserialize_from(&mut reader).map_err(|e| e.to_string())?;
+        if header.version != RECORDING_VERSION {
+            return Err(format!(
+                "recording version {} is not supported",
+                header.version
+            ));
+        }
+
+        // Recordings of servers that were stopped can end in the middle of a tick
+        let mut ticks = VecDeque::new();
+        while let Ok(tick) = bincode::deserialize_from::<_, RecordedTick>(&mut reader) {
+            ticks.push_back(tick);
+        }
+        Ok(Self {
+            header,
+            ticks,
+            checkpoints: 0,
+            divergence: None,
+        })
+    }
+
+    /// Copies of the config files the recorded server read, to be inserted next to the simulation.
+    pub fn recorded_files(&self) -> RecordedFiles {
+        RecordedFiles(self.header.files.clone())
+    }
+
+    /// If all recorded ticks were simulated
+    pub fn is_finished(&self) -> bool {
+        self.ticks.is_empty()
+    }
+
+    /// How many checkpoints were compared so far
+    pub fn checkpoints(&self) -> usize {
+        self.checkpoints
+    }
+
+    pub fn divergence(&self) -> Option<&(u64, Vec<String>)> {
+        self.divergence.as_ref()
+    }
+
+    /// The config the recorded server used, with its seed.
+    pub fn server_config(&self) -> Result<ServerConfig, String> {
+        let mut config: ServerConfig = match &self.header.config {
+            Some(text) => toml::from_str(text).map_err(|e| e.to_string())?,
+            None => ServerConfig::default(),
+        };
+        config.seed = Some(self.header.seed);
+        // The simulation shouldn't be visible to anyone or write over the saves of the real server
+        config.registration = None;
+        config.autosave = None;
+        config.status.bind_address = None;
+        Ok(config)
+    }
+
+    pub fn map_save(&self) -> Option<&Path> {
+        self.header.map_save.as_deref()
+    }
+}
+
+/// Runs ticks back to back, a simulation doesn't wait for anyone.
+fn run_unpaced(mut app: App) {
+    let mut exit_reader = ManualEventReader::<AppExit>::default();
+    loop {
+        app.update();
+        if let Some(exits) = app.world.get_resource::<Events<AppExit>>() {
+            if exit_reader.iter(exits).next().is_some() {
+                return;
+            }
+        }
+    }
+}
+
+fn checkpoint_due(clock: Res<ReplayClock>) -> bool {
+    clock
+        .tick
+        .is_some_and(|tick| tick % clock.checkpoint_interval == 0)
+}
+
+fn write_header(mut recorder: ResMut<InputRecorder>, rng: Res<GameRng>, types: Res<MessageTypes>) {
+    let recorder = &mut *recorder;
+    recorder.header.seed = rng.seed();
+    recorder.header.protocol_hash = types.protocol_hash();
+    if let Err(err) = bincode::serialize_into(&mut recorder.writer, &recorder.header) {
+        error!("Error writing input recording, recording stopped: {}", err);
+        recorder.failed = true;
+    }
+}
+
+fn check_protocol(
+    simulation: Res<Simulation>,
+    types: Res<MessageTypes>,
+    mut exit: EventWriter<AppExit>,
+) {
+    if simulation.header.protocol_hash != types.protocol_hash() {
+        error!("The recording was made by a different version of the game and can't be simulated");
+        exit.send(AppExit);
+    } else {
+        info!(
+            ticks = simulation.ticks.len(),
+            seed = simulation.header.seed,
+            "Simulating recording"
+        );
+    }
+}
+
+fn advance_clock(
+    mut clock: ResMut<ReplayClock>,
+    maps: Query<(), (With<TileMap>, Without<PendingMapObjects>)>,
+    scenes: Res<NetworkSceneSpawner>,
+    simulation: Option<Res<Simulation>>,
+    mut network_time: ResMut<ServerNetworkTime>,
+    mut time_strategy: ResMut<TimeUpdateStrategy>,
+) {
+    match clock.tick {
+        Some(tick) => clock.tick = Some(tick + 1),
+        None if !maps.is_empty() && scenes.is_idle() => {
+            clock.tick = Some(0);
+            *time_strategy = TimeUpdateStrategy::ManualDuration(Duration::from_secs_f64(
+                network_time.tick_in_seconds(),
+            ));
+            // Player messages refer to network ticks, so the simulation counts them like the recorded server
+            if let Some(first) = simulation.as_ref().and_then(|s| s.ticks.front()) {
+                network_time.set_current_tick(first.server_tick.wrapping_sub(1));
+            }
+            info!("Map is ready, replay ticks start now");
+        }
+        None => {}
+    }
+}
+
+fn record_tick(
+    mut recorder: ResMut<InputRecorder>,
+    clock: Res<ReplayClock>,
+    mut messages: EventReader<IncomingMessage>,
+    mut server_events: EventReader<ServerEvent>,
+    mut checksums: ResMut<Checksums>,
+    network_time: Res<ServerNetworkTime>,
+) {
+    let inputs: Vec<_> = messages.iter().cloned().collect();
+    let disconnects: Vec<_> = server_events
+        .iter()
+        .filter_map(|event| match event {
+            ServerEvent::PlayerDisconnected(connection) => Some(*connection),
+            _ => None,
+        })
+        .collect();
+    let Some(tick) = clock.tick else {
+        if !inputs.is_empty() {
+            warn!("Messages received before the map was ready aren't recorded, the simulation may differ");
+        }
+        return;
+    };
+
+    let checksums = (!checksums.0.is_empty()).then(|| std::mem::take(&mut checksums.0));
+    // Flushing on checkpoints keeps most of the recording if the server is killed
+    let flush = checksums.is_some();
+    recorder.write(
+        &RecordedTick {
+            tick,
+            server_tick: network_time.current_tick(),
+            inputs,
+            disconnects,
+            checksums,
+        },
+        flush,
+    );
+}
+
+fn send_recorded_inputs(
+    simulation: Res<Simulation>,
+    clock: Res<ReplayClock>,
+    mut messages: EventWriter<IncomingMessage>,
+    mut disconnects: EventWriter<SimulatedDisconnect>,
+) {
+    let Some(recorded) = simulation
+        .ticks
+        .front()
+        .filter(|recorded| Some(recorded.tick) == clock.tick)
+    else {
+        return;
+    };
+    messages.send_batch(recorded.inputs.iter().cloned());
+    disconnects.send_batch(
+        recorded
+            .disconnects
+            .iter()
+            .copied()
+            .map(SimulatedDisconnect),
+    );
+}
+
+fn compare_checksums(
+    mut simulation: ResMut<Simulation>,
+    clock: Res<ReplayClock>,
+    mut checksums: ResMut<Checksums>,
+    mut exit: EventWriter<AppExit>,
+) {
+    let Some(tick) = clock.tick else {
+        return;
+    };
+    let ours = std::mem::take(&mut checksums.0);
+    let Some(recorded) = simulation.ticks.pop_front() else {
+        warn!("The recording doesn't contain any ticks");
+        exit.send(AppExit);
+        return;
+    };
+
+    if let Some(expected) = recorded.checksums {
+        simulation.checkpoints += 1;
+        let differing: Vec<_> = expected
+            .keys()
+            .chain(ours.keys())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .filter(|&category| expected.get(category) != ours.get(category))
+            .map(String::as_str)
+            .collect();
+        if !differing.is_empty() {
+            error!(
+                tick,
+                categories = differing.join(", ").as_str(),
+                "Simulation diverged from the recording"
+            );
+            simulation.divergence = Some((tick, differing.into_iter().map(Into::into).collect()));
+            exit.send(AppExit);
+            return;
+        }
+    }
+
+    if simulation.ticks.is_empty() {
+        info!(
+            ticks = tick + 1,
+            checkpoints = simulation.checkpoints,
+            "Simulation matched the recording"
+        );
+        exit.send(AppExit);
+    }
+}
+
+fn entity_checksum(entities: Query<Entity>, mut checksums: ResMut<Checksums>) {
+    checksums.add("entities", entities.iter().count() as u64);
+}
+
+/// Turfs are hashed by their network identity, entities can be numbered differently in a simulation.
+fn tile_checksum(
+    maps: Query<(&NetworkIdentity, &TileMap)>,
+    identities: Query<&NetworkIdentity>,
+    mut checksums: ResMut<Checksums>,
+) {
+    let turfs = maps.iter().flat_map(|(&map_identity, map)| {
+        let size = map.size() * CHUNK_SIZE;
+        (0..size.y)
+            .flat_map(move |y| (0..size.x).map(move |x| UVec2::new(x, y)))
+            .filter_map(move |position| {
+                let turf = map.tile(position)?.turf?;
+                Some((
+                    map_identity,
+                    position.x,
+                    position.y,
+                    identities.get(turf).ok(),
+                ))
+            })
+    });
+    checksums.add("turfs", unordered_hash(turfs));
+}
+
+fn rng_checksum(rng: Res<GameRng>, mut checksums: ResMut<Checksums>) {
+    checksums.add("rng", rng.checksum());
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use std::fs;
+
+    use bevy::ecs::system::Command;
+    use maps::TileReference;
+    use networking::identity::NetworkCommand;
+
+    use super::*;
+    use crate::{testing::server_app_with, SERVER_TPS};
+
+    /// Ticks in five minutes of a round
+    const FIVE_MINUTES: u64 = 5 * 60 * SERVER_TPS as u64;
+    /// Ticks the map may take to be ready
+    const LOADING_TICKS: u64 = 100;
+
+    /// A small map of networked floor tiles, spawned the same way in both runs.
+    fn spawn_map(app: &mut App) {
+        let mut map = TileMap::new(UVec2::ONE);
+        for position in (0..4).flat_map(|x| (0..4).map(move |y| UVec2::new(x, y))) {
+            let turf = app.world.spawn(SpatialBundle::default()).id();
+            NetworkCommand { entity: turf }.apply(&mut app.world);
+            let tile = TileReference {
+                turf: Some(turf),
+                ..Default::default()
+            };
+            map.set_tile(position, tile).unwrap();
+        }
+        let map = app.world.spawn((map, SpatialBundle::default())).id();
+        NetworkCommand { entity: map }.apply(&mut app.world);
+    }
+
+    fn replay_tick(app: &App) -> Option<u64> {
+        app.world.resource::<ReplayClock>().tick
+    }
+
+    #[test]
+    fn five_minute_recording_simulates_without_divergence() {
+        let directory = std::env::temp_dir().join(format!("ssnt-replay-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let recording = directory.join("inputs.bin");
+        let combat = directory.join("combat.toml");
+        fs::write(&combat, "friendly_fire_multiplier = 0.25\n").unwrap();
+        let config_text = format!("seed = 7\ncombat = {:?}\n", combat.to_str().unwrap());
+
+        let config: ServerConfig = toml::from_str(&config_text).unwrap();
+        let recorder = InputRecorder::create(
+            &recording,
+            &config,
+            Some(config_text),
+            None,
+            SERVER_TPS as u64,
+        )
+        .unwrap();
+        let mut app = server_app_with(config, |app| {
+            app.insert_resource(recorder);
+        });
+        spawn_map(&mut app);
+        for _ in 0..FIVE_MINUTES + LOADING_TICKS {
+            app.update();
+            if replay_tick(&app).is_some_and(|tick| tick >= FIVE_MINUTES) {
+                break;
+            }
+        }
+        assert!(replay_tick(&app).is_some_and(|tick| tick >= FIVE_MINUTES));
+        // Flushes the recording
+        drop(app);
+        // The simulation has to use the copy in the recording
+        fs::remove_file(&combat).unwrap();
+
+        let simulation = Simulation::load(&recording).unwrap();
+        let config = simulation.server_config().unwrap();
+        let mut app = server_app_with(config, |app| {
+            app.insert_resource(simulation.recorded_files())
+                .insert_resource(simulation);
+        });
+        spawn_map(&mut app);
+        let mut exits = ManualEventReader::<AppExit>::default();
+        for _ in 0..FIVE_MINUTES + LOADING_TICKS {
+            app.update();
+            if exits
+                .iter(app.world.resource::<Events<AppExit>>())
+                .next()
+                .is_some()
+            {
+                break;
+            }
+        }
+        fs::remove_dir_all(&directory).unwrap();
+
+        let simulation = app.world.resource::<Simulation>();
+        assert_eq!(simulation.divergence(), None);
+        assert!(simulation.is_finished());
+        assert!(simulation.checkpoints() as u64 >= FIVE_MINUTES / SERVER_TPS as u64);
+        let combat_config = read_config_file(
+            Some(app.world.resource::<RecordedFiles>()),
+            combat.to_str().unwrap(),
+        )
+        .unwrap();
+        assert!(combat_config.contains("0.25"));
+    }
+
+    #[test]
+    fn unordered_hash_ignores_order() {
+        assert_eq!(
+            unordered_hash([(1u32, 2u64), (3, 4), (5, 6)]),
+            unordered_hash([(5u32, 6u64), (1, 2), (3, 4)])
+        );
+        assert_ne!(unordered_hash([1u32, 2]), unordered_hash([1u32, 3]));
+    }
+}
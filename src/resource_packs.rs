@@ -0,0 +1,749 @@
+use std::{
+    collections::VecDeque,
+    fs::{create_dir_all, read, read_dir, read_to_string, write},
+    io::{Cursor, Read, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use bevy::{
+    asset::{AssetPath, LoadState},
+    prelude::*,
+    tasks::{IoTaskPool, Task},
+};
+use futures_lite::future;
+use networking::{
+    is_server,
+    messaging::{AppExt, MessageEvent, MessageReceivers, MessageSender},
+    quality::LinkQuality,
+    ClientEvent, ConnectionId, DisconnectPlayer, ServerEvent,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::config::ServerConfig;
+
+use self::asset_io::{MountedPack, PackAssetIo, PackMounts, PACK_META_FILE};
+
+#[cfg(feature = "client")]
+use {
+    crate::ui::has_window,
+    bevy_egui::{egui, EguiContexts},
+};
+
+pub mod asset_io;
+
+/// Lets servers replace assets with their own, without changing the game.
+/// Packs are listed in the server config, sent to clients that don't have them cached and mounted over the default assets.
+pub struct ResourcePackPlugin;
+
+impl Plugin for ResourcePackPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_network_message::<PackManifest>("PackManifest")
+            .add_network_message::<RequestPack>("RequestPack")
+            .add_network_message::<RefusePack>("RefusePack")
+            .add_network_message::<PackChunk>("PackChunk");
+
+        if is_server(app) {
+            let config = app
+                .world
+                .get_resource::<ServerConfig>()
+                .map(|config| config.resource_packs.clone())
+                .unwrap_or_default();
+            let packs = load_server_packs(&config, app.world.resource::<PackMounts>());
+            app.insert_resource(packs)
+                .init_resource::<PackTransfers>()
+                .add_systems(
+                    Update,
+                    (send_manifest, handle_pack_requests, send_pack_chunks).chain(),
+                );
+        } else {
+            app.insert_resource(load_client_settings())
+                .init_resource::<ClientPacks>()
+                .add_systems(
+                    Update,
+                    (reset_packs, receive_manifest, receive_chunks, update_packs).chain(),
+                );
+            #[cfg(feature = "client")]
+            app.add_systems(Update, pack_window.after(update_packs).run_if(has_window));
+        }
+    }
+}
+
+/// Makes the asset server look in mounted packs before the asset folder.
+/// Must be called before the asset plugin is added, which otherwise creates its own asset server.
+pub fn install_asset_io(app: &mut App) {
+    let mounts = PackMounts::default();
+    app.insert_resource(AssetServer::with_boxed_io(Box::new(PackAssetIo::new(
+        mounts.clone(),
+    ))))
+    .insert_resource(mounts);
+}
+
+/// Size of the pieces packs are sent in
+const CHUNK_SIZE: usize = 16 * 1024;
+/// Where clients keep downloaded packs, named by their hash
+const CACHE_DIRECTORY: &str = "pack_cache";
+const CLIENT_CONFIG_FILE: &str = "client-config.toml";
+
+#[derive(Deserialize, Clone)]
+pub struct ResourcePackConfig {
+    /// Packs in the order they are applied, later packs win if they replace the same file
+    #[serde(default)]
+    pub packs: Vec<PackSource>,
+    /// Upload rate for pack downloads of a single player
+    #[serde(default = "ResourcePackConfig::default_bytes_per_second")]
+    pub bytes_per_second: u32,
+    /// Upload rate for pack downloads of all players together
+    #[serde(default = "ResourcePackConfig::default_total_bytes_per_second")]
+    pub total_bytes_per_second: u32,
+}
+
+impl ResourcePackConfig {
+    fn default_bytes_per_second() -> u32 {
+        256 * 1024
+    }
+
+    fn default_total_bytes_per_second() -> u32 {
+        2 * 1024 * 1024
+    }
+}
+
+impl Default for ResourcePackConfig {
+    fn default() -> Self {
+        Self {
+            packs: Vec::new(),
+            bytes_per_second: Self::default_bytes_per_second(),
+            total_bytes_per_second: Self::default_total_bytes_per_second(),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+pub struct PackSource {
+    /// A folder laid out like the asset folder, or a zip of one. Both contain a `pack.ron` with the name and version.
+    pub path: PathBuf,
+    /// Players that can't or won't download the pack are disconnected.
+    /// Otherwise they play with the default assets instead.
+    #[serde(default)]
+    pub required: bool,
+}
+
+/// Contents of `pack.ron`
+#[derive(Deserialize)]
+struct PackMeta {
+    name: String,
+    version: String,
+}
+
+/// Client settings for downloading packs, read from the `[resource_packs]` table of `client-config.toml`.
+#[derive(Resource, Deserialize, Clone)]
+pub struct ResourcePackSettings {
+    /// If packs are downloaded at all. Servers that require a pack can't be joined without.
+    #[serde(default = "ResourcePackSettings::default_allow_downloads")]
+    pub allow_downloads: bool,
+    /// Packs larger than this many megabytes are not downloaded
+    #[serde(default = "ResourcePackSettings::default_max_download_mb")]
+    pub max_download_mb: u64,
+}
+
+impl ResourcePackSettings {
+    fn default_allow_downloads() -> bool {
+        true
+    }
+
+    fn default_max_download_mb() -> u64 {
+        200
+    }
+}
+
+impl Default for ResourcePackSettings {
+    fn default() -> Self {
+        Self {
+            allow_downloads: Self::default_allow_downloads(),
+            max_download_mb: Self::default_max_download_mb(),
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
+struct ClientConfig {
+    #[serde(default)]
+    resource_packs: ResourcePackSettings,
+}
+
+fn load_client_settings() -> ResourcePackSettings {
+    let Ok(text) = read_to_string(CLIENT_CONFIG_FILE) else {
+        return Default::default();
+    };
+    match toml::from_str::<ClientConfig>(&text) {
+        Ok(config) => config.resource_packs,
+        Err(err) => {
+            warn!(error = %err, "Invalid client config, using default resource pack settings");
+            Default::default()
+        }
+    }
+}
+
+/// Server message listing the packs of the server, sent when a player connects.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct PackManifest {
+    pub packs: Vec<PackInfo>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PackInfo {
+    pub name: String,
+    pub version: String,
+    /// CRC32 of the zipped pack
+    pub hash: u32,
+    /// Size of the zipped pack in bytes
+    pub size: u64,
+    pub required: bool,
+    /// Asset paths the pack replaces or adds
+    pub overrides: Vec<String>,
+}
+
+/// Client message asking for the pack at this position of the manifest.
+#[derive(Serialize, Deserialize)]
+pub struct RequestPack {
+    pub index: usize,
+}
+
+/// Client message saying the pack at this position of the manifest won't be used.
+#[derive(Serialize, Deserialize)]
+pub struct RefusePack {
+    pub index: usize,
+}
+
+/// Server message with a part of a zipped pack. Parts are sent in order over the bulk channel.
+#[derive(Serialize, Deserialize)]
+pub struct PackChunk {
+    pub index: usize,
+    pub offset: u64,
+    pub data: Vec<u8>,
+}
+
+/// The packs the server mounted, zipped as they are sent to clients.
+#[derive(Resource, Default)]
+struct ServerPacks {
+    manifest: PackManifest,
+    data: Vec<Arc<[u8]>>,
+}
+
+fn load_server_packs(config: &ResourcePackConfig, mounts: &PackMounts) -> ServerPacks {
+    let mut packs = ServerPacks::default();
+    for source in config.packs.iter() {
+        let order = packs.data.len();
+        let loaded = load_pack(&source.path).and_then(|(meta, data)| {
+            MountedPack::from_zip(meta.name.clone(), order, &data)
+                .map(|mounted| (meta, data, mounted))
+        });
+        let (meta, data, mounted) = match loaded {
+            Ok(loaded) => loaded,
+            Err(err) => {
+                error!(path = %source.path.display(), error = err.as_str(), "Could not load resource pack");
+                continue;
+            }
+        };
+
+        let mut overrides: Vec<_> = mounted
+            .files
+            .keys()
+            .map(|path| path.to_string_lossy().replace('\\', "/"))
+            .collect();
+        overrides.sort();
+        let info = PackInfo {
+            name: meta.name,
+            version: meta.version,
+            hash: crc32fast::hash(&data),
+            size: data.len() as u64,
+            required: source.required,
+            overrides,
+        };
+        info!(
+            name = info.name.as_str(),
+            version = info.version.as_str(),
+            files = info.overrides.len(),
+            required = info.required,
+            "Loaded resource pack"
+        );
+
+        mounts.mount(mounted);
+        packs.manifest.packs.push(info);
+        packs.data.push(data.into());
+    }
+    packs
+}
+
+/// Reads a zipped pack, or zips a pack folder in memory.
+fn load_pack(path: &Path) -> Result<(PackMeta, Vec<u8>), String> {
+    let data = if path.is_dir() {
+        zip_directory(path)?
+    } else {
+        read(path).map_err(|e| e.to_string())?
+    };
+
+    let mut text = String::new();
+    zip::ZipArchive::new(Cursor::new(&data))
+        .map_err(|e| e.to_string())?
+        .by_name(PACK_META_FILE)
+        .map_err(|_| format!("missing {}", PACK_META_FILE))?
+        .read_to_string(&mut text)
+        .map_err(|e| e.to_string())?;
+    let meta = ron::from_str(&text).map_err(|e| e.to_string())?;
+
+    Ok((meta, data))
+}
+
+fn zip_directory(root: &Path) -> Result<Vec<u8>, String> {
+    let mut files = Vec::new();
+    collect_files(root, &mut files).map_err(|e| e.to_string())?;
+    // Same order every time, so an unchanged folder keeps its hash and stays cached on clients
+    files.sort();
+
+    let mut archive = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    for file in files {
+        let relative = file.strip_prefix(root).map_err(|e| e.to_string())?;
+        let name: Vec<_> = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect();
+        archive
+            .start_file(name.join("/"), zip::write::FileOptions::default())
+            .map_err(|e| e.to_string())?;
+        archive
+            .write_all(&read(&file).map_err(|e| e.to_string())?)
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(archive.finish().map_err(|e| e.to_string())?.into_inner())
+}
+
+fn collect_files(directory: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in read_dir(directory)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Packs that are being sent to players.
+#[derive(Resource, Default)]
+struct PackTransfers {
+    connections: Vec<ConnectionTransfers>,
+    /// Bytes that can still be sent to all players together
+    total_allowance: f32,
+    /// Rotates which player is served first, so nobody gets all of the total rate
+    next: usize,
+}
+
+struct ConnectionTransfers {
+    connection: ConnectionId,
+    /// Positions in the manifest and how much of them was sent
+    queue: VecDeque<(usize, usize)>,
+    /// Bytes that can still be sent to this player
+    allowance: f32,
+}
+
+impl PackTransfers {
+    fn queue(&mut self, connection: ConnectionId, index: usize) {
+        let transfers = match self
+            .connections
+            .iter_mut()
+            .position(|t| t.connection == connection)
+        {
+            Some(position) => &mut self.connections[position],
+            None => {
+                self.connections.push(ConnectionTransfers {
+                    connection,
+                    queue: VecDeque::new(),
+                    allowance: 0.0,
+                });
+                self.connections.last_mut().unwrap()
+            }
+        };
+        if !transfers.queue.iter().any(|&(i, _)| i == index) {
+            transfers.queue.push_back((index, 0));
+        }
+    }
+}
+
+fn send_manifest(
+    mut events: EventReader<ServerEvent>,
+    packs: Res<ServerPacks>,
+    mut transfers: ResMut<PackTransfers>,
+    mut sender: MessageSender,
+) {
+    for event in events.iter() {
+        match event {
+            ServerEvent::PlayerConnected(connection) => {
+                if !packs.manifest.packs.is_empty() {
+                    sender.send(&packs.manifest, MessageReceivers::Single(*connection));
+                }
+            }
+            ServerEvent::PlayerDisconnected(connection) => {
+                transfers
+                    .connections
+                    .retain(|t| t.connection != *connection);
+            }
+        }
+    }
+}
+
+fn handle_pack_requests(
+    mut requests: EventReader<MessageEvent<RequestPack>>,
+    mut refusals: EventReader<MessageEvent<RefusePack>>,
+    packs: Res<ServerPacks>,
+    mut transfers: ResMut<PackTransfers>,
+    mut disconnect: EventWriter<DisconnectPlayer>,
+) {
+    for event in requests.iter() {
+        if event.message.index >= packs.data.len() {
+            warn!(connection = ?event.connection, index = event.message.index, "Requested resource pack doesn't exist");
+            continue;
+        }
+        transfers.queue(event.connection, event.message.index);
+    }
+
+    for event in refusals.iter() {
+        let Some(info) = packs.manifest.packs.get(event.message.index) else {
+            continue;
+        };
+        if info.required {
+            info!(connection = ?event.connection, pack = info.name.as_str(), "Player refused a required resource pack");
+            disconnect.send(DisconnectPlayer(event.connection));
+        }
+    }
+}
+
+/// Sends queued packs in chunks, limited per player and in total so joining players don't starve gameplay traffic.
+fn send_pack_chunks(
+    time: Res<Time>,
+    config: Res<ServerConfig>,
+    quality: Res<LinkQuality>,
+    packs: Res<ServerPacks>,
+    mut transfers: ResMut<PackTransfers>,
+    mut sender: MessageSender,
+) {
+    let PackTransfers {
+        connections,
+        total_allowance,
+        next,
+    } = &mut *transfers;
+    connections.retain(|t| !t.queue.is_empty());
+    if connections.is_empty() {
+        *total_allowance = 0.0;
+        return;
+    }
+
+    let delta = time.delta_seconds();
+    // Unused rate carries over for at most a second, enough for a chunk even at low rates
+    let rate = config.resource_packs.bytes_per_second as f32;
+    let total_rate = config.resource_packs.total_bytes_per_second as f32;
+    *total_allowance =
+        (*total_allowance + total_rate * delta).min(total_rate.max(CHUNK_SIZE as f32));
+
+    let count = connections.len();
+    let start = *next % count;
+    *next = next.wrapping_add(1);
+    for offset in 0..count {
+        let transfers = &mut connections[(start + offset) % count];
+        // Players on bad connections need their bandwidth for the game
+        let rate = rate * quality.rate_factor(transfers.connection, &config.link_quality);
+        transfers.allowance = (transfers.allowance + rate * delta).min(rate.max(CHUNK_SIZE as f32));
+
+        while let Some((index, sent)) = transfers.queue.front_mut() {
+            let data = &packs.data[*index];
+            let length = CHUNK_SIZE.min(data.len() - *sent);
+            let cost = length as f32;
+            if transfers.allowance < cost || *total_allowance < cost {
+                break;
+            }
+
+            sender.send_bulk(
+                &PackChunk {
+                    index: *index,
+                    offset: *sent as u64,
+                    data: data[*sent..*sent + length].to_vec(),
+                },
+                MessageReceivers::Single(transfers.connection),
+            );
+            transfers.allowance -= cost;
+            *total_allowance -= cost;
+            *sent += length;
+            if *sent >= data.len() {
+                transfers.queue.pop_front();
+            }
+        }
+    }
+}
+
+/// The packs of the server the client is on.
+#[derive(Resource, Default)]
+pub struct ClientPacks {
+    packs: Vec<ClientPack>,
+    /// Why the client can't play on this server, if it refused a required pack
+    refused: Option<String>,
+}
+
+struct ClientPack {
+    info: PackInfo,
+    state: PackState,
+}
+
+enum PackState {
+    /// Looking for the pack in the local cache
+    Checking(Task<Option<MountedPack>>),
+    Downloading(Vec<u8>),
+    /// Checking the hash of a download and unpacking it
+    Verifying(Task<Result<MountedPack, String>>),
+    Mounted,
+    /// Not used, the default assets are shown instead
+    Skipped,
+}
+
+impl ClientPack {
+    #[cfg(feature = "client")]
+    fn is_pending(&self) -> bool {
+        !matches!(self.state, PackState::Mounted | PackState::Skipped)
+    }
+}
+
+fn cache_path(hash: u32) -> PathBuf {
+    Path::new(CACHE_DIRECTORY).join(format!("{:08x}.zip", hash))
+}
+
+/// Removes the packs of the previous server when joining or leaving one.
+fn reset_packs(
+    mut events: EventReader<ClientEvent>,
+    mut packs: ResMut<ClientPacks>,
+    mounts: Res<PackMounts>,
+    asset_server: Res<AssetServer>,
+) {
+    let mut joining = false;
+    let mut changed = false;
+    for event in events.iter() {
+        joining |= matches!(event, ClientEvent::Join(_));
+        changed |= event != &ClientEvent::Joined;
+    }
+    if !changed {
+        return;
+    }
+
+    // The reason stays around after being disconnected, so it can be shown in the menu
+    let refused = packs.refused.take().filter(|_| !joining);
+    *packs = ClientPacks {
+        refused,
+        ..Default::default()
+    };
+    reload_loaded(&asset_server, mounts.unmount_all().iter());
+}
+
+fn receive_manifest(
+    mut messages: EventReader<MessageEvent<PackManifest>>,
+    mut packs: ResMut<ClientPacks>,
+) {
+    let Some(event) = messages.iter().last() else {
+        return;
+    };
+
+    packs.packs = event
+        .message
+        .packs
+        .iter()
+        .enumerate()
+        .map(|(order, info)| {
+            let (name, hash, size) = (info.name.clone(), info.hash, info.size);
+            let task = IoTaskPool::get().spawn(async move {
+                let data = read(cache_path(hash)).ok()?;
+                if data.len() as u64 != size || crc32fast::hash(&data) != hash {
+                    return None;
+                }
+                MountedPack::from_zip(name, order, &data).ok()
+            });
+            ClientPack {
+                info: info.clone(),
+                state: PackState::Checking(task),
+            }
+        })
+        .collect();
+}
+
+fn receive_chunks(
+    mut messages: EventReader<MessageEvent<PackChunk>>,
+    mut packs: ResMut<ClientPacks>,
+) {
+    for event in messages.iter() {
+        let chunk = &event.message;
+        let Some(pack) = packs.packs.get_mut(chunk.index) else {
+            continue;
+        };
+        let PackState::Downloading(data) = &mut pack.state else {
+            continue;
+        };
+        if chunk.offset != data.len() as u64
+            || (data.len() + chunk.data.len()) as u64 > pack.info.size
+        {
+            warn!(
+                pack = pack.info.name.as_str(),
+                "Received resource pack chunk out of order"
+            );
+            continue;
+        }
+
+        data.extend_from_slice(&chunk.data);
+        if data.len() as u64 == pack.info.size {
+            let data = std::mem::take(data);
+            let (name, hash, order) = (pack.info.name.clone(), pack.info.hash, chunk.index);
+            let task = IoTaskPool::get().spawn(async move {
+                if crc32fast::hash(&data) != hash {
+                    return Err("the download is corrupted".to_owned());
+                }
+                let mounted = MountedPack::from_zip(name, order, &data)?;
+                if let Err(err) =
+                    create_dir_all(CACHE_DIRECTORY).and_then(|_| write(cache_path(hash), &data))
+                {
+                    warn!(error = %err, "Could not cache resource pack");
+                }
+                Ok(mounted)
+            });
+            pack.state = PackState::Verifying(task);
+        }
+    }
+}
+
+/// Mounts packs once they are found in the cache or downloaded, and downloads or refuses the rest.
+fn update_packs(
+    mut packs: ResMut<ClientPacks>,
+    settings: Res<ResourcePackSettings>,
+    mounts: Res<PackMounts>,
+    asset_server: Res<AssetServer>,
+    mut sender: MessageSender,
+) {
+    let ClientPacks { packs, refused } = &mut *packs;
+    for (index, pack) in packs.iter_mut().enumerate() {
+        let result = match &mut pack.state {
+            PackState::Checking(task) => {
+                let Some(cached) = future::block_on(future::poll_once(task)) else {
+                    continue;
+                };
+                cached.ok_or_else(|| {
+                    if !settings.allow_downloads {
+                        Some("downloads are disabled".to_owned())
+                    } else if pack.info.size > settings.max_download_mb * 1024 * 1024 {
+                        Some(format!("it is larger than {} MB", settings.max_download_mb))
+                    } else {
+                        None
+                    }
+                })
+            }
+            PackState::Verifying(task) => {
+                let Some(result) = future::block_on(future::poll_once(task)) else {
+                    continue;
+                };
+                result.map_err(Some)
+            }
+            _ => continue,
+        };
+
+        match result {
+            Ok(mounted) => {
+                info!(pack = pack.info.name.as_str(), "Mounted resource pack");
+                let paths: Vec<_> = mounted.files.keys().cloned().collect();
+                mounts.mount(mounted);
+                reload_loaded(&asset_server, paths.iter());
+                pack.state = PackState::Mounted;
+            }
+            Err(None) => {
+                sender.send_to_server(&RequestPack { index });
+                pack.state = PackState::Downloading(Vec::with_capacity(pack.info.size as usize));
+            }
+            Err(Some(reason)) => {
+                sender.send_to_server(&RefusePack { index });
+                pack.state = PackState::Skipped;
+                if pack.info.required {
+                    warn!(
+                        pack = pack.info.name.as_str(),
+                        reason = reason.as_str(),
+                        "Refused a required resource pack"
+                    );
+                    *refused = Some(format!(
+                        "The server requires the resource pack {}, but {}.",
+                        pack.info.name, reason
+                    ));
+                } else {
+                    info!(
+                        pack = pack.info.name.as_str(),
+                        reason = reason.as_str(),
+                        "Using default assets instead of resource pack"
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Reloads assets that are already loaded, so they are read again from the current packs.
+fn reload_loaded<'a>(asset_server: &AssetServer, paths: impl Iterator<Item = &'a PathBuf>) {
+    for path in paths {
+        let asset_path = AssetPath::from(path.as_path());
+        if asset_server.get_load_state(asset_path.clone()) != LoadState::NotLoaded {
+            asset_server.reload_asset(asset_path);
+        }
+    }
+}
+
+/// Shows the progress of pack downloads, or why the server can't be joined.
+#[cfg(feature = "client")]
+fn pack_window(mut contexts: EguiContexts, mut packs: ResMut<ClientPacks>) {
+    let mut dismissed = false;
+    if packs.refused.is_none() && !packs.packs.iter().any(ClientPack::is_pending) {
+        return;
+    }
+
+    egui::Window::new("Resource packs")
+        .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+        .collapsible(false)
+        .resizable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            for pack in packs.packs.iter() {
+                ui.horizontal(|ui| {
+                    ui.label(format!("{} {}", pack.info.name, pack.info.version));
+                    match &pack.state {
+                        PackState::Checking(_) => {
+                            ui.label("Checking cache...");
+                        }
+                        PackState::Downloading(data) => {
+                            let progress = data.len() as f32 / pack.info.size.max(1) as f32;
+                            ui.add(egui::ProgressBar::new(progress).text(format!(
+                                "{} / {} KiB",
+                                data.len() / 1024,
+                                pack.info.size / 1024
+                            )));
+                        }
+                        PackState::Verifying(_) => {
+                            ui.label("Verifying...");
+                        }
+                        PackState::Mounted => {
+                            ui.label("Ready");
+                        }
+                        PackState::Skipped => {
+                            ui.label("Using default assets");
+                        }
+                    }
+                });
+            }
+
+            if let Some(reason) = packs.refused.as_ref() {
+                ui.colored_label(egui::Color32::RED, reason);
+                dismissed = ui.button("Dismiss").clicked();
+            }
+        });
+
+    if dismissed {
+        packs.refused = None;
+    }
+}
@@ -0,0 +1,166 @@
+use std::{
+    collections::HashMap,
+    io::{Cursor, Read},
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+
+use bevy::{
+    asset::{AssetIo, AssetIoError, BoxedFuture, ChangeWatcher, FileAssetIo, FileType, Metadata},
+    prelude::Resource,
+};
+
+/// File in a pack describing it, not an asset itself
+pub const PACK_META_FILE: &str = "pack.ron";
+
+/// A resource pack whose files are served instead of the default assets.
+pub struct MountedPack {
+    pub name: String,
+    /// Position in the server manifest. Packs later in the manifest win if they override the same file.
+    pub order: usize,
+    pub files: HashMap<PathBuf, Arc<[u8]>>,
+}
+
+impl MountedPack {
+    /// Reads all files of a zipped pack into memory.
+    pub fn from_zip(name: String, order: usize, data: &[u8]) -> Result<Self, String> {
+        let mut archive = zip::ZipArchive::new(Cursor::new(data)).map_err(|e| e.to_string())?;
+        let mut files = HashMap::new();
+        for index in 0..archive.len() {
+            let mut file = archive.by_index(index).map_err(|e| e.to_string())?;
+            if !file.is_file() {
+                continue;
+            }
+            // Entries trying to escape the asset folder are ignored
+            let Some(path) = file.enclosed_name().map(Path::to_path_buf) else {
+                continue;
+            };
+            if path == Path::new(PACK_META_FILE) {
+                continue;
+            }
+            let mut content = Vec::with_capacity(file.size() as usize);
+            file.read_to_end(&mut content).map_err(|e| e.to_string())?;
+            files.insert(path, content.into());
+        }
+        Ok(Self { name, order, files })
+    }
+}
+
+/// The packs currently mounted over the default assets.
+/// Shared with the asset server, so mounting takes effect for the next load of an asset.
+#[derive(Resource, Clone, Default)]
+pub struct PackMounts(Arc<RwLock<Vec<MountedPack>>>);
+
+impl PackMounts {
+    pub fn mount(&self, pack: MountedPack) {
+        let mut packs = self.0.write().unwrap();
+        let position = packs
+            .iter()
+            .position(|p| p.order > pack.order)
+            .unwrap_or(packs.len());
+        packs.insert(position, pack);
+    }
+
+    /// Removes all packs, returning the paths they overrode.
+    pub fn unmount_all(&self) -> Vec<PathBuf> {
+        let mut packs = self.0.write().unwrap();
+        packs
+            .drain(..)
+            .flat_map(|pack| pack.files.into_keys())
+            .collect()
+    }
+
+    fn find(&self, path: &Path) -> Option<Arc<[u8]>> {
+        let packs = self.0.read().unwrap();
+        packs
+            .iter()
+            .rev()
+            .find_map(|pack| pack.files.get(path).cloned())
+    }
+
+    /// Direct children of a directory that only exist in packs, and if the path is a file in a pack.
+    fn entries(&self, directory: &Path) -> (Vec<PathBuf>, bool) {
+        let packs = self.0.read().unwrap();
+        let mut children = Vec::new();
+        let mut is_file = false;
+        for path in packs.iter().flat_map(|pack| pack.files.keys()) {
+            is_file |= path == directory;
+            let Ok(relative) = path.strip_prefix(directory) else {
+                continue;
+            };
+            if let Some(first) = relative.components().next() {
+                let child = directory.join(first);
+                if !children.contains(&child) {
+                    children.push(child);
+                }
+            }
+        }
+        (children, is_file)
+    }
+}
+
+/// Reads assets from mounted packs first and falls back to the asset folder.
+pub struct PackAssetIo {
+    default_io: FileAssetIo,
+    mounts: PackMounts,
+}
+
+impl PackAssetIo {
+    pub fn new(mounts: PackMounts) -> Self {
+        Self {
+            default_io: FileAssetIo::new("assets", &None),
+            mounts,
+        }
+    }
+}
+
+impl AssetIo for PackAssetIo {
+    fn load_path<'a>(&'a self, path: &'a Path) -> BoxedFuture<'a, Result<Vec<u8>, AssetIoError>> {
+        match self.mounts.find(path) {
+            Some(content) => Box::pin(async move { Ok(content.to_vec()) }),
+            None => self.default_io.load_path(path),
+        }
+    }
+
+    fn read_directory(
+        &self,
+        path: &Path,
+    ) -> Result<Box<dyn Iterator<Item = PathBuf>>, AssetIoError> {
+        let (pack_entries, _) = self.mounts.entries(path);
+        let mut entries: Vec<_> = match self.default_io.read_directory(path) {
+            Ok(entries) => entries.collect(),
+            // Packs can add folders that don't exist in the default assets
+            Err(_) if !pack_entries.is_empty() => Vec::new(),
+            Err(err) => return Err(err),
+        };
+        for entry in pack_entries {
+            if !entries.contains(&entry) {
+                entries.push(entry);
+            }
+        }
+        Ok(Box::new(entries.into_iter()))
+    }
+
+    fn get_metadata(&self, path: &Path) -> Result<Metadata, AssetIoError> {
+        let (children, is_file) = self.mounts.entries(path);
+        if is_file {
+            return Ok(Metadata::new(FileType::File));
+        }
+        match self.default_io.get_metadata(path) {
+            Err(_) if !children.is_empty() => Ok(Metadata::new(FileType::Directory)),
+            result => result,
+        }
+    }
+
+    fn watch_path_for_changes(
+        &self,
+        to_watch: &Path,
+        to_reload: Option<PathBuf>,
+    ) -> Result<(), AssetIoError> {
+        self.default_io.watch_path_for_changes(to_watch, to_reload)
+    }
+
+    fn watch_for_changes(&self, configuration: &ChangeWatcher) -> Result<(), AssetIoError> {
+        self.default_io.watch_for_changes(configuration)
+    }
+}
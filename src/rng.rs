@@ -0,0 +1,259 @@
+use std::hash::{Hash, Hasher};
+
+use bevy::{prelude::*, utils::HashMap};
+use networking::is_server;
+
+use crate::{config::ServerConfig, replay::StableHasher};
+
+/// Provides [`GameRng`], the source of randomness for everything that affects gameplay.
+pub struct RngPlugin;
+
+impl Plugin for RngPlugin {
+    fn build(&self, app: &mut App) {
+        if is_server(app) {
+            let seed = app
+                .world
+                .get_resource::<ServerConfig>()
+                .and_then(|config| config.seed)
+                .unwrap_or_else(|| fastrand::u64(..));
+            app.insert_resource(GameRng::new(seed))
+                .add_systems(Startup, log_seed);
+        }
+    }
+}
+
+/// Seeded randomness for the round.
+/// Systems get their own streams, so using randomness in one system doesn't change the results of another.
+/// Cosmetic randomness on the client doesn't need to use this.
+#[derive(Resource)]
+pub struct GameRng {
+    seed: u64,
+    streams: HashMap<&'static str, fastrand::Rng>,
+}
+
+impl GameRng {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            streams: Default::default(),
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// A sequence for a purpose, continuing where the last call left off.
+    pub fn stream(&mut self, purpose: &'static str) -> &mut fastrand::Rng {
+        let seed = self.seed;
+        self.streams
+            .entry(purpose)
+            .or_insert_with(|| fastrand::Rng::with_seed(derive_seed(seed, purpose, 0)))
+    }
+
+    /// Hashes how far every stream has advanced, so two runs can tell whether they used the same randomness.
+    pub fn checksum(&self) -> u64 {
+        let mut streams: Vec<_> = self
+            .streams
+            .iter()
+            .map(|(&purpose, rng)| (purpose, rng.get_seed()))
+            .collect();
+        streams.sort_unstable();
+        let mut hasher = StableHasher::default();
+        (self.seed, streams).hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// A generator for a purpose on a single entity.
+    /// Returns the same sequence every time it's called with the same arguments.
+    pub fn for_entity(&self, entity: Entity, purpose: &str) -> fastrand::Rng {
+        self.for_key(entity.to_bits(), purpose)
+    }
+
+    /// Like [`GameRng::for_entity`], for things that aren't entities yet, like objects in a map file.
+    pub fn for_key(&self, key: u64, purpose: &str) -> fastrand::Rng {
+        fastrand::Rng::with_seed(derive_seed(self.seed, purpose, key))
+    }
+}
+
+/// Mixes the purpose and a value into the seed (FNV-1a followed by a splitmix64 finalizer).
+fn derive_seed(seed: u64, purpose: &str, value: u64) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+    for byte in purpose.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+
+    let mut x = seed ^ hash ^ value.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+/// The seed is logged so a round can be reproduced
+fn log_seed(rng: Res<GameRng>) {
+    info!(seed = rng.seed(), "Round random seed");
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use bevy::ecs::system::Command;
+    use byond::tgm::conversion::ObjectPlacement;
+    use maps::{Direction, TileMap};
+    use networking::{identity::NetworkCommand, scene::NetworkScene};
+
+    use super::*;
+    use crate::{
+        door::{wires::WirePanel, Door},
+        lights::{flicker_seed, LightFixture},
+        logging::RecentLogs,
+        map_objects::{MapObject, PendingMapObjects},
+        testing::server_app,
+    };
+
+    const MAINTENANCE_LOOT: &str = "/obj/effect/spawner/lootdrop/maintenance";
+
+    /// What the randomness of a round decided, compared between runs.
+    #[derive(PartialEq, Debug)]
+    struct RoundOutcome {
+        wires: Vec<Vec<String>>,
+        loot: Vec<(IVec3, String)>,
+        flicker_seeds: Vec<u32>,
+        checksum: u64,
+    }
+
+    /// Plays the same short round with a seed: doors, lights and a map full of loot spawners.
+    fn play_round(seed: u64) -> RoundOutcome {
+        let mut app = server_app(ServerConfig {
+            seed: Some(seed),
+            ..Default::default()
+        });
+
+        let doors: Vec<_> = (0..4)
+            .map(|x| {
+                let door = app
+                    .world
+                    .spawn((
+                        Door,
+                        SpatialBundle::from_transform(Transform::from_xyz(x as f32, 0.0, 0.0)),
+                    ))
+                    .id();
+                NetworkCommand { entity: door }.apply(&mut app.world);
+                door
+            })
+            .collect();
+        let lights: Vec<_> = (0..4)
+            .map(|z| {
+                app.world
+                    .spawn((
+                        LightFixture::default(),
+                        SpatialBundle::from_transform(Transform::from_xyz(0.0, 2.0, z as f32)),
+                    ))
+                    .id()
+            })
+            .collect();
+        let placements = (0..8)
+            .flat_map(|x| (0..8).map(move |y| UVec2::new(x, y)))
+            .map(|tile_position| ObjectPlacement {
+                byond_path: format!("{}/two", MAINTENANCE_LOOT),
+                tile_position,
+                direction: Direction::South,
+                id_tag: None,
+            })
+            .collect();
+        app.world.spawn((
+            TileMap::new(UVec2::ONE),
+            PendingMapObjects(placements),
+            SpatialBundle::default(),
+        ));
+
+        for _ in 0..3 {
+            app.update();
+        }
+
+        let asset_server = app.world.resource::<AssetServer>().clone();
+        let mut loot: Vec<_> = app
+            .world
+            .query_filtered::<(&NetworkScene, &Transform), With<MapObject>>()
+            .iter(&app.world)
+            .map(|(scene, transform)| {
+                let path = asset_server
+                    .get_handle_path(scene.handle())
+                    .map(|p| p.path().display().to_string())
+                    .unwrap_or_default();
+                (transform.translation.as_ivec3(), path)
+            })
+            .collect();
+        loot.sort();
+
+        RoundOutcome {
+            wires: doors
+                .iter()
+                .map(|&door| app.world.get::<WirePanel>(door).unwrap().layout())
+                .collect(),
+            loot,
+            flicker_seeds: lights
+                .iter()
+                .map(|&light| {
+                    flicker_seed(
+                        app.world
+                            .get::<GlobalTransform>(light)
+                            .unwrap()
+                            .translation(),
+                    )
+                })
+                .collect(),
+            checksum: app.world.resource::<GameRng>().checksum(),
+        }
+    }
+
+    #[test]
+    fn same_seed_plays_the_same_round() {
+        let first = play_round(1234);
+        assert!(!first.loot.is_empty());
+        assert_eq!(first, play_round(1234));
+    }
+
+    #[test]
+    fn different_seeds_roll_differently() {
+        let first = play_round(1);
+        let second = play_round(2);
+        assert_ne!(first.wires, second.wires);
+        assert_ne!(first.loot, second.loot);
+    }
+
+    #[test]
+    fn seed_is_in_the_round_log() {
+        let mut app = server_app(ServerConfig {
+            seed: Some(987_654_321),
+            ..Default::default()
+        });
+        app.update();
+
+        let logs = app.world.resource::<RecentLogs>().last(usize::MAX);
+        assert!(
+            logs.iter()
+                .any(|line| line.text.contains("Round random seed")
+                    && line.text.contains("seed=987654321")),
+            "seed missing from the log"
+        );
+    }
+
+    #[test]
+    fn entity_streams_repeat_and_differ() {
+        let rng = GameRng::new(5);
+        let entity = Entity::from_raw(3);
+        assert_eq!(
+            rng.for_entity(entity, "wires").u64(..),
+            rng.for_entity(entity, "wires").u64(..)
+        );
+        assert_ne!(
+            rng.for_entity(entity, "wires").u64(..),
+            rng.for_entity(entity, "loot").u64(..)
+        );
+        assert_ne!(
+            rng.for_entity(entity, "wires").u64(..),
+            GameRng::new(6).for_entity(entity, "wires").u64(..)
+        );
+    }
+}
@@ -5,6 +5,7 @@ use bevy::{
 };
 use maps::TileMap;
 use networking::{
+    identity::EntityCommandsExt,
     is_client, is_server,
     messaging::{AppExt, MessageEvent, MessageReceivers, MessageSender},
     resource::AppExt as ResAppExt,
@@ -13,25 +14,39 @@ use networking::{
     time::ServerNetworkTime,
     variable::{NetworkVar, ServerVar},
     visibility::{NetworkObserver, NetworkObserverBundle},
-    Networked, Players,
+    ConnectionId, Networked, Players,
 };
 use serde::{Deserialize, Serialize};
 use utils::task::*;
 
 use crate::{
-    body::SpawnCreature,
+    autosave::RecoveredWorld,
+    body::{variant::BodyTemplate, SpawnCreature},
+    communication::{AnnouncementAudience, AnnouncementEvent, SystemMessageEvent},
+    config::ServerConfig,
     items::clothes::{EquipClothing, EquipClothingSystem},
-    job::{JobDefinition, SelectedJobs},
+    job::{JobAssigner, JobDefinition, JobSlots, SelectedJobs},
     movement::ForcePositionMessage,
+    profile::CharacterProfiles,
+    safe_zone::SpawnProtected,
+    stats::PlayerStats,
+    SavedMap,
 };
 
+use self::{modes::GameModePlugin, traitor::TraitorPlugin};
+
+pub mod modes;
+pub(crate) mod traitor;
+
 pub struct RoundPlugin;
 
 impl Plugin for RoundPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
-        app.add_network_message::<StartRoundRequest>()
-            .add_network_message::<RequestJoin>()
-            .add_networked_resource::<RoundData, RoundDataClient>();
+        app.add_network_message::<StartRoundRequest>("StartRoundRequest")
+            .add_network_message::<RequestJoin>("RequestJoin")
+            .add_network_message::<RequestObserve>("RequestObserve")
+            .add_networked_resource::<RoundData, RoundDataClient>()
+            .add_plugins((GameModePlugin, TraitorPlugin));
         if is_server(app) {
             app.add_state::<RoundState>()
                 .insert_resource(RoundData {
@@ -42,14 +57,19 @@ impl Plugin for RoundPlugin {
                 .add_systems(OnEnter(RoundState::Loading), load_map)
                 .add_systems(
                     OnEnter(RoundState::Running),
-                    (spawn_players_roundstart, start_round_timer),
+                    (
+                        spawn_players_roundstart,
+                        start_round_timer,
+                        announce_round_start,
+                    ),
                 )
                 .add_systems(
                     Update,
                     (
                         set_ready.run_if(in_state(RoundState::Loading)),
                         handle_start_round_request.run_if(in_state(RoundState::Ready)),
-                        spawn_player_latejoin.run_if(in_state(RoundState::Running)),
+                        (spawn_player_latejoin, spawn_observer)
+                            .run_if(in_state(RoundState::Running)),
                         update_round_data.run_if(state_changed::<RoundState>()),
                         (
                             handle_player_body_spawned.after(EquipClothingSystem),
@@ -86,12 +106,19 @@ pub enum RoundState {
 
 #[derive(Networked, Resource)]
 #[networked(client = "RoundDataClient")]
-struct RoundData {
+pub(crate) struct RoundData {
     state: NetworkVar<RoundState>,
     /// The server tick the round was started.
     start: NetworkVar<Option<u32>>,
 }
 
+impl RoundData {
+    /// The server tick the round was started, if it has started.
+    pub(crate) fn start(&self) -> Option<u32> {
+        *self.start
+    }
+}
+
 #[derive(Default, TypeUuid, Networked, Resource)]
 #[uuid = "0db42b69-f2bd-4b28-96a2-e8123e51f45a"]
 #[networked(server = "RoundData")]
@@ -113,9 +140,29 @@ impl RoundDataClient {
 #[derive(Serialize, Deserialize)]
 pub struct StartRoundRequest;
 
-fn load_map(mut commands: Commands, server: Res<AssetServer>) {
+fn load_map(
+    mut commands: Commands,
+    server: Res<AssetServer>,
+    recovered: Option<Res<RecoveredWorld>>,
+    saved_map: Option<Res<SavedMap>>,
+) {
+    // Editor saves are already tile data and don't need to be converted
+    // TODO: Remember the save in autosaves, they currently recover the default map
+    if let Some(saved_map) = saved_map {
+        let entity = commands
+            .spawn((saved_map.0.to_data(), SpatialBundle::default()))
+            .networked()
+            .id();
+        info!(entity = ?entity, "Spawning map from save");
+        return;
+    }
+
     // TODO: Make map selection configurable
-    let handle = server.load("maps/BoxStation.dmm");
+    let path = recovered
+        .as_ref()
+        .map(|r| r.map())
+        .unwrap_or("maps/BoxStation.dmm");
+    let handle = server.load(path);
     commands.insert_resource(crate::Map {
         handle,
         spawned: false,
@@ -148,6 +195,14 @@ fn start_round_timer(mut round_data: ResMut<RoundData>, server_time: Res<ServerN
     *round_data.start = Some(server_time.current_tick());
 }
 
+fn announce_round_start(mut announcements: EventWriter<AnnouncementEvent>) {
+    announcements.send(AnnouncementEvent::new(
+        "announcement.round_start",
+        &[],
+        AnnouncementAudience::Everyone,
+    ));
+}
+
 #[derive(Resource)]
 struct PlayerAssets {
     #[allow(dead_code)]
@@ -158,30 +213,85 @@ struct PlayerAssets {
     ghost_model: Option<Handle<Scene>>,
 }
 
+#[derive(Clone, Copy)]
+struct PlayerSpawn {
+    player: Uuid,
+    /// If the player joined after the round started
+    latejoin: bool,
+}
+
 #[derive(Resource, Default)]
 struct SpawnsInProgress {
-    spawn_tasks: HashMap<TaskId<SpawnCreature>, Uuid>,
-    clothing_tasks: Vec<(Vec<TaskId<EquipClothing>>, Uuid, Entity)>,
+    spawn_tasks: HashMap<TaskId<SpawnCreature>, PlayerSpawn>,
+    clothing_tasks: Vec<(Vec<TaskId<EquipClothing>>, PlayerSpawn, Entity)>,
 }
 
+#[allow(clippy::too_many_arguments)]
 fn spawn_players_roundstart(
-    selected_jobs: Res<SelectedJobs>,
-    job_data: Res<Assets<JobDefinition>>,
+    mut jobs: JobAssigner,
     players: Res<Players>,
+    config: Res<ServerConfig>,
+    profiles: Res<CharacterProfiles>,
+    body_templates: Res<Assets<BodyTemplate>>,
     mut spawns: ResMut<SpawnsInProgress>,
     mut spawning: ResMut<Tasks<SpawnCreature>>,
+    mut system_messages: EventWriter<SystemMessageEvent>,
 ) {
-    for (connection, _) in selected_jobs.selected(&job_data) {
+    for connection in jobs.connections() {
         let player = match players.get(connection) {
             Some(p) => p,
             None => continue,
         };
 
-        let spawn_id = spawning.create(SpawnCreature {
-            archetype: "human".into(),
-        });
+        if let Err(rejection) = jobs.assign(connection, player.id) {
+            info!(player = ?player.id, reason = %rejection, "No job at roundstart");
+            system_messages.send(SystemMessageEvent {
+                receiver: connection,
+                text: rejection.to_string(),
+            });
+            continue;
+        }
+
+        let archetype = player_body(
+            connection,
+            &profiles,
+            &config,
+            &body_templates,
+            &mut system_messages,
+        );
+        let spawn_id = spawning.create(SpawnCreature { archetype });
+
+        spawns.spawn_tasks.insert(
+            spawn_id,
+            PlayerSpawn {
+                player: player.id,
+                latejoin: false,
+            },
+        );
+    }
+}
 
-        spawns.spawn_tasks.insert(spawn_id, player.id);
+/// The body a player spawns with. Players are told when the one they picked can't be used.
+fn player_body(
+    connection: ConnectionId,
+    profiles: &CharacterProfiles,
+    config: &ServerConfig,
+    templates: &Assets<BodyTemplate>,
+    system_messages: &mut EventWriter<SystemMessageEvent>,
+) -> String {
+    let Some(requested) = profiles.body(connection) else {
+        return config.bodies.fallback.clone();
+    };
+    match config.bodies.check(requested, templates) {
+        Ok(()) => requested.to_owned(),
+        Err(rejection) => {
+            info!(connection = ?connection, body = requested, reason = %rejection, "Body not allowed");
+            system_messages.send(SystemMessageEvent {
+                receiver: connection,
+                text: rejection,
+            });
+            config.bodies.fallback.clone()
+        }
     }
 }
 
@@ -191,26 +301,123 @@ pub struct RequestJoin;
 #[allow(clippy::too_many_arguments)]
 fn spawn_player_latejoin(
     mut messages: EventReader<MessageEvent<RequestJoin>>,
-    selected_jobs: Res<SelectedJobs>,
-    job_data: Res<Assets<JobDefinition>>,
+    mut jobs: JobAssigner,
     players: Res<Players>,
+    controls: Res<ClientControls>,
+    config: Res<ServerConfig>,
+    profiles: Res<CharacterProfiles>,
+    body_templates: Res<Assets<BodyTemplate>>,
     mut spawns: ResMut<SpawnsInProgress>,
     mut spawning: ResMut<Tasks<SpawnCreature>>,
+    mut system_messages: EventWriter<SystemMessageEvent>,
 ) {
     for event in messages.iter() {
         let Some(player) = players.get(event.connection) else {
             continue;
         };
 
-        if selected_jobs.get(event.connection, &job_data).is_none() {
+        // Already in the round, or still spawning
+        if controls.controlled_entity(player.id).is_some()
+            || spawns.spawn_tasks.values().any(|s| s.player == player.id)
+        {
             continue;
         }
 
-        let spawn_id = spawning.create(SpawnCreature {
-            archetype: "human".into(),
-        });
+        if jobs.selected(event.connection).is_none() {
+            continue;
+        }
+
+        if let Err(rejection) = jobs.assign(event.connection, player.id) {
+            system_messages.send(SystemMessageEvent {
+                receiver: event.connection,
+                text: rejection.to_string(),
+            });
+            continue;
+        }
+
+        let archetype = player_body(
+            event.connection,
+            &profiles,
+            &config,
+            &body_templates,
+            &mut system_messages,
+        );
+        let spawn_id = spawning.create(SpawnCreature { archetype });
+
+        spawns.spawn_tasks.insert(
+            spawn_id,
+            PlayerSpawn {
+                player: player.id,
+                latejoin: true,
+            },
+        );
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RequestObserve;
+
+/// Lets players that don't want to (or can't) play watch the round as a ghost.
+#[allow(clippy::too_many_arguments)]
+fn spawn_observer(
+    mut messages: EventReader<MessageEvent<RequestObserve>>,
+    players: Res<Players>,
+    mut controls: ResMut<ClientControls>,
+    selected_jobs: Res<SelectedJobs>,
+    job_data: Res<Assets<JobDefinition>>,
+    maps: Query<&TileMap>,
+    asset_server: Res<AssetServer>,
+    mut commands: Commands,
+    mut sender: MessageSender,
+) {
+    for event in messages.iter() {
+        let Some(player) = players.get(event.connection) else {
+            continue;
+        };
+        if controls.controlled_entity(player.id).is_some() {
+            continue;
+        }
+        let Ok(main_map) = maps.get_single() else {
+            continue;
+        };
+
+        let position = match selected_jobs.get(event.connection, &job_data) {
+            Some(job) => crate::job::get_arrivals_position(main_map, job),
+            None => main_map
+                .job_spawn_positions
+                .get(maps::ARRIVALS_LANDMARK)
+                .and_then(|p| p.first())
+                .map(|tile| Vec3::new(tile.x as f32, 1.0, tile.y as f32))
+                .unwrap_or(Vec3::Y),
+        };
 
-        spawns.spawn_tasks.insert(spawn_id, player.id);
+        let ghost = commands
+            .spawn((
+                NetworkSceneBundle {
+                    scene: asset_server.load("creatures/ghost.scn.ron").into(),
+                    transform: Transform::from_translation(position),
+                    ..Default::default()
+                },
+                NetworkObserverBundle {
+                    observer: NetworkObserver {
+                        range: 1,
+                        player_id: player.id,
+                    },
+                    cells: Default::default(),
+                },
+                networking::transform::ClientMovement,
+            ))
+            .id();
+        controls.give_control(player.id, ghost);
+
+        sender.send_with_priority(
+            &ForcePositionMessage {
+                position,
+                rotation: Quat::IDENTITY,
+            },
+            MessageReceivers::Single(event.connection),
+            10,
+        );
     }
 }
 
@@ -219,6 +426,8 @@ fn handle_player_body_spawned(
     players: Res<Players>,
     selected_jobs: Res<SelectedJobs>,
     job_data: Res<Assets<JobDefinition>>,
+    profiles: Res<CharacterProfiles>,
+    stats: Res<PlayerStats>,
     mut spawns: ResMut<SpawnsInProgress>,
     mut spawning: ResMut<Tasks<SpawnCreature>>,
     mut clothing_equip: ResMut<Tasks<EquipClothing>>,
@@ -226,12 +435,12 @@ fn handle_player_body_spawned(
     mut commands: Commands,
 ) {
     let spawns = &mut *spawns;
-    spawns.spawn_tasks.retain(|&task, &mut player_id| {
+    spawns.spawn_tasks.retain(|&task, &mut spawn| {
         let Some(result) = spawning.result(task) else {
             return true;
         };
 
-        let Some(connection) = players.get_connection(&player_id) else {
+        let Some(connection) = players.get_connection(&spawn.player) else {
             return false;
         };
 
@@ -239,9 +448,15 @@ fn handle_player_body_spawned(
             return false;
         };
 
+        // Loadout choices are checked against the job's pools again, they may have been reloaded
+        let loadout = job.loadout_items(profiles.loadout(connection), |option| {
+            stats.loadout_lock(spawn.player, option, &job_data)
+        });
         let clothing_tasks: Vec<_> = job
             .clothing
             .iter()
+            .map(String::as_str)
+            .chain(loadout)
             .map(|clothing| {
                 let clothing_entity = commands
                     .spawn(NetworkSceneBundle {
@@ -261,7 +476,7 @@ fn handle_player_body_spawned(
 
         spawns
             .clothing_tasks
-            .push((clothing_tasks, player_id, result.root));
+            .push((clothing_tasks, spawn, result.root));
         false
     });
 }
@@ -269,18 +484,24 @@ fn handle_player_body_spawned(
 #[allow(clippy::too_many_arguments)]
 fn finalise_player_spawn(
     players: Res<Players>,
+    profiles: Res<CharacterProfiles>,
     maps: Query<&TileMap>,
     selected_jobs: Res<SelectedJobs>,
     job_data: Res<Assets<JobDefinition>>,
     mut spawns: ResMut<SpawnsInProgress>,
     mut clothing: ResMut<Tasks<EquipClothing>>,
     mut controls: ResMut<ClientControls>,
+    mut slots: ResMut<JobSlots>,
+    config: Res<ServerConfig>,
+    time: Res<Time>,
     mut commands: Commands,
     mut sender: MessageSender,
+    mut announcements: EventWriter<AnnouncementEvent>,
 ) {
     spawns
         .clothing_tasks
-        .retain(|(tasks, player_id, player_entity)| {
+        .retain(|(tasks, spawn, player_entity)| {
+            let player_id = &spawn.player;
             let mut clothing_finished = true;
             for &task_id in tasks.iter() {
                 if let Some(result) = clothing.result(task_id) {
@@ -303,10 +524,11 @@ fn finalise_player_spawn(
                 return false;
             };
 
-            let Some(name) = players
-                .players()
-                .get(&connection)
-                .map(|p| p.username.clone())
+            // Prefer the character name, but fall back to the username
+            let Some(name) = profiles
+                .name(connection)
+                .map(|n| n.to_owned())
+                .or_else(|| players.get(connection).map(|p| p.username.clone()))
             else {
                 return false;
             };
@@ -320,7 +542,11 @@ fn finalise_player_spawn(
                 return false;
             };
 
-            let spawn_position = crate::job::get_spawn_position(main_map, job);
+            let spawn_position = if spawn.latejoin {
+                crate::job::get_arrivals_position(main_map, job)
+            } else {
+                crate::job::get_spawn_position(main_map, job)
+            };
 
             // Add some player specific components
             commands.entity(*player_entity).insert((
@@ -332,11 +558,39 @@ fn finalise_player_spawn(
                     cells: Default::default(),
                 },
                 Transform::from_translation(spawn_position),
-                crate::communication::SpeechName(name),
+                crate::communication::SpeechName(name.clone()),
                 networking::transform::ClientMovement,
+                crate::job::Affiliation {
+                    team: job.team.clone(),
+                    job: job.id.clone(),
+                    department: job.department().to_owned(),
+                },
             ));
+            if !job.huds.is_empty() {
+                commands
+                    .entity(*player_entity)
+                    .insert(crate::status_hud::JobHuds(job.huds.clone()));
+            }
+
+            let protection = config.safety.spawn_protection_seconds;
+            if protection > 0.0 {
+                commands.entity(*player_entity).insert(SpawnProtected::new(
+                    protection,
+                    time.elapsed_seconds(),
+                    main_map.area_at(spawn_position).map(str::to_owned),
+                ));
+            }
 
             controls.give_control(*player_id, *player_entity);
+            slots.track_body(*player_entity, job, *player_id);
+
+            if spawn.latejoin {
+                announcements.send(AnnouncementEvent::new(
+                    "announcement.arrival",
+                    &[&name, &job.name],
+                    AnnouncementAudience::Everyone,
+                ));
+            }
 
             // Force client to accept new position (unless they cheat lol)
             sender.send_with_priority(
@@ -351,3 +605,160 @@ fn finalise_player_spawn(
             false
         });
 }
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use bevy::{
+        asset::{AssetPath, AssetPathId},
+        ecs::{event::ManualEventReader, system::SystemState},
+    };
+    use networking::{loopback::LinkConditions, messaging::MessageEvent, testing, NetworkRole};
+
+    use super::*;
+    use crate::{profile::ProfileMessage, testing::server_app};
+
+    /// Finishes spawning the body of a connected player and counts the arrival announcements.
+    fn arrivals(latejoin: bool) -> usize {
+        let mut server = server_app(ServerConfig::default());
+        // The test brings its own map
+        server.update();
+        server.world.remove_resource::<crate::Map>();
+        let mut client = testing::app(NetworkRole::Client);
+        let connector = testing::listen(&mut server);
+        testing::join(&mut client, &connector, LinkConditions::default());
+        testing::connect(&mut server, &mut [&mut client], 200);
+
+        server
+            .world
+            .spawn((TileMap::new(UVec2::ONE), SpatialBundle::default()));
+        let job: JobDefinition =
+            ron::from_str(r#"(id: "assistant", name: "Assistant", description: "", clothing: [])"#)
+                .unwrap();
+        let job_id = AssetPathId::from(AssetPath::from("jobs/assistant.job.ron"));
+        server
+            .world
+            .resource_mut::<Assets<JobDefinition>>()
+            .set_untracked(job_id, job);
+        let (&connection, player) = server
+            .world
+            .resource::<Players>()
+            .players()
+            .iter()
+            .next()
+            .unwrap();
+        let player = player.id;
+        server
+            .world
+            .resource_mut::<SelectedJobs>()
+            .select(connection, job_id);
+
+        // A body without starting clothing is done spawning right away
+        let body = server.world.spawn_empty().id();
+        server
+            .world
+            .resource_mut::<SpawnsInProgress>()
+            .clothing_tasks
+            .push((Vec::new(), PlayerSpawn { player, latejoin }, body));
+        testing::update(&mut server, &mut [&mut client], 1);
+
+        assert_eq!(
+            server
+                .world
+                .resource::<ClientControls>()
+                .controlled_entity(player),
+            Some(body)
+        );
+        let events = server.world.resource::<Events<AnnouncementEvent>>();
+        ManualEventReader::<AnnouncementEvent>::default()
+            .iter(events)
+            .filter(|event| event.text_key == "announcement.arrival")
+            .count()
+    }
+
+    #[test]
+    fn latejoin_is_announced() {
+        assert_eq!(arrivals(true), 1);
+    }
+
+    #[test]
+    fn roundstart_spawn_is_not_announced() {
+        assert_eq!(arrivals(false), 0);
+    }
+
+    /// The body spawned for a player who picked `body`, and what they were told about it.
+    fn picked_body(body: &str) -> (String, Vec<String>) {
+        let mut server = server_app(ServerConfig::default());
+        let mut client = testing::app(NetworkRole::Client);
+        let connector = testing::listen(&mut server);
+        testing::join(&mut client, &connector, LinkConditions::default());
+        testing::connect(&mut server, &mut [&mut client], 200);
+
+        for (id, text) in [
+            ("human", include_str!("../assets/bodies/human.body.ron")),
+            ("robot", include_str!("../assets/bodies/robot.body.ron")),
+        ] {
+            let template: BodyTemplate = ron::from_str(text).unwrap();
+            let path = format!("bodies/{}.body.ron", id);
+            server
+                .world
+                .resource_mut::<Assets<BodyTemplate>>()
+                .set_untracked(AssetPathId::from(AssetPath::from(path.as_str())), template);
+        }
+        let connection = *server
+            .world
+            .resource::<Players>()
+            .players()
+            .keys()
+            .next()
+            .unwrap();
+        server.world.send_event(MessageEvent {
+            message: ProfileMessage {
+                name: "Tess Ter".into(),
+                job_preferences: Vec::new(),
+                loadout: Vec::new(),
+                body: body.into(),
+            },
+            connection,
+        });
+        testing::update(&mut server, &mut [&mut client], 1);
+
+        let mut state = SystemState::<(
+            Res<CharacterProfiles>,
+            Res<ServerConfig>,
+            Res<Assets<BodyTemplate>>,
+            EventWriter<SystemMessageEvent>,
+        )>::new(&mut server.world);
+        let (profiles, config, templates, mut system_messages) = state.get_mut(&mut server.world);
+        let spawned = player_body(
+            connection,
+            &profiles,
+            &config,
+            &templates,
+            &mut system_messages,
+        );
+
+        let events = server.world.resource::<Events<SystemMessageEvent>>();
+        let mut reader = ManualEventReader::<SystemMessageEvent>::default();
+        let told = reader
+            .iter(events)
+            .filter(|event| event.receiver == connection)
+            .map(|event| event.text.clone())
+            .collect();
+        (spawned, told)
+    }
+
+    #[test]
+    fn allowed_body_is_spawned() {
+        assert_eq!(picked_body("human"), ("human".into(), Vec::new()));
+    }
+
+    #[test]
+    fn disallowed_body_falls_back_with_a_lobby_message() {
+        let (spawned, told) = picked_body("robot");
+        assert_eq!(spawned, "human");
+        assert_eq!(
+            told,
+            ["The Test Robot body is not allowed on this server, you will spawn as Human"]
+        );
+    }
+}
@@ -0,0 +1,302 @@
+use bevy::{prelude::*, utils::HashMap};
+use networking::{
+    is_server,
+    messaging::{AppExt, MessageEvent, MessageReceivers, MessageSender},
+    ConnectionId, ServerEvent,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    communication::{AnnouncementAudience, AnnouncementEvent},
+    config::ServerConfig,
+    GameState,
+};
+
+use super::RoundState;
+
+#[cfg(feature = "client")]
+use {
+    crate::ui::has_window,
+    bevy_egui::{egui, EguiContexts},
+};
+
+/// Picks the game mode when the round starts, from the lobby vote or the server config.
+/// Each mode adds its own systems, which only run while it is the current mode.
+pub(super) struct GameModePlugin;
+
+impl Plugin for GameModePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_network_message::<GameModeVote>("GameModeVote")
+            .add_network_message::<GameModeTally>("GameModeTally")
+            .add_network_message::<AntagonistBriefing>("AntagonistBriefing");
+
+        if is_server(app) {
+            let default_mode = app
+                .world
+                .get_resource::<ServerConfig>()
+                .map(|config| config.game_mode.default_mode)
+                .unwrap_or_default();
+            app.insert_resource(CurrentGameMode(default_mode))
+                .init_resource::<ModeVotes>()
+                .add_systems(
+                    OnEnter(RoundState::Running),
+                    select_game_mode.in_set(GameModeSelection),
+                )
+                .add_systems(
+                    Update,
+                    (
+                        handle_mode_votes.run_if(in_state(RoundState::Ready)),
+                        update_voters,
+                    ),
+                );
+        } else {
+            app.init_resource::<ClientModeVotes>()
+                .init_resource::<ClientBriefing>()
+                .add_systems(
+                    Update,
+                    (receive_tally, receive_briefing).run_if(in_state(GameState::Game)),
+                );
+            #[cfg(feature = "client")]
+            app.add_systems(
+                Update,
+                briefing_window
+                    .after(receive_briefing)
+                    .run_if(in_state(GameState::Game))
+                    .run_if(has_window),
+            );
+        }
+    }
+}
+
+/// Runs when the round starts and sets [`CurrentGameMode`].
+/// Modes that set up the round (like picking antagonists) run after it.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct GameModeSelection;
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub enum GameMode {
+    /// No antagonists, the round lasts until the shuttle leaves
+    #[default]
+    Extended,
+    /// Some of the crew secretly work against the station
+    Traitor,
+}
+
+impl GameMode {
+    pub const ALL: [GameMode; 2] = [GameMode::Extended, GameMode::Traitor];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            GameMode::Extended => "Extended",
+            GameMode::Traitor => "Traitor",
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+pub struct GameModeConfig {
+    /// The mode used if nobody votes, or on a tie that includes it
+    #[serde(default)]
+    pub default_mode: GameMode,
+    /// Share of the players in the round that become traitors. At least one is picked.
+    #[serde(default = "GameModeConfig::default_traitor_fraction")]
+    pub traitor_fraction: f32,
+    /// Item prefab put in the backpack of every traitor
+    #[serde(default = "GameModeConfig::default_traitor_item")]
+    pub traitor_item: String,
+    /// Item names traitors can be told to steal
+    #[serde(default = "GameModeConfig::default_steal_targets")]
+    pub steal_targets: Vec<String>,
+}
+
+impl GameModeConfig {
+    fn default_traitor_fraction() -> f32 {
+        0.2
+    }
+
+    fn default_traitor_item() -> String {
+        "enforcer".into()
+    }
+
+    fn default_steal_targets() -> Vec<String> {
+        vec![
+            "Captain's ID Card".into(),
+            "Defibrillator".into(),
+            "Magboots".into(),
+        ]
+    }
+}
+
+impl Default for GameModeConfig {
+    fn default() -> Self {
+        Self {
+            default_mode: Default::default(),
+            traitor_fraction: Self::default_traitor_fraction(),
+            traitor_item: Self::default_traitor_item(),
+            steal_targets: Self::default_steal_targets(),
+        }
+    }
+}
+
+/// The mode of the running round.
+#[derive(Resource)]
+pub struct CurrentGameMode(pub GameMode);
+
+/// Run condition for systems that belong to a game mode.
+pub fn game_mode_is(mode: GameMode) -> impl Fn(Option<Res<CurrentGameMode>>) -> bool + Clone {
+    move |current| current.is_some_and(|c| c.0 == mode)
+}
+
+/// Client message voting for the mode of the next round.
+#[derive(Serialize, Deserialize)]
+pub struct GameModeVote(pub GameMode);
+
+/// Server message with how many votes each mode has, sent to everyone when it changes.
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct GameModeTally {
+    pub votes: Vec<(GameMode, usize)>,
+}
+
+#[derive(Resource, Default)]
+struct ModeVotes {
+    votes: HashMap<ConnectionId, GameMode>,
+}
+
+impl ModeVotes {
+    fn tally(&self) -> GameModeTally {
+        GameModeTally {
+            votes: GameMode::ALL
+                .into_iter()
+                .map(|mode| (mode, self.votes.values().filter(|&&m| m == mode).count()))
+                .collect(),
+        }
+    }
+
+    /// The mode with the most votes. Ties go to the default, then to the first mode listed.
+    fn winner(&self, default: GameMode) -> GameMode {
+        let tally = self.tally();
+        let most = tally.votes.iter().map(|&(_, n)| n).max().unwrap_or(0);
+        if most == 0 || tally.votes.contains(&(default, most)) {
+            return default;
+        }
+        tally
+            .votes
+            .into_iter()
+            .find(|&(_, n)| n == most)
+            .map_or(default, |(mode, _)| mode)
+    }
+}
+
+fn handle_mode_votes(
+    mut messages: EventReader<MessageEvent<GameModeVote>>,
+    mut votes: ResMut<ModeVotes>,
+    mut sender: MessageSender,
+) {
+    let mut changed = false;
+    for event in messages.iter() {
+        changed |= votes.votes.insert(event.connection, event.message.0) != Some(event.message.0);
+    }
+    if changed {
+        sender.send(&votes.tally(), MessageReceivers::AllPlayers);
+    }
+}
+
+/// Drops the votes of players that left and tells new players the current tally.
+fn update_voters(
+    mut events: EventReader<ServerEvent>,
+    mut votes: ResMut<ModeVotes>,
+    mut sender: MessageSender,
+) {
+    for event in events.iter() {
+        match event {
+            ServerEvent::PlayerConnected(connection) => {
+                sender.send(&votes.tally(), MessageReceivers::Single(*connection));
+            }
+            ServerEvent::PlayerDisconnected(connection) => {
+                if votes.votes.remove(connection).is_some() {
+                    sender.send(&votes.tally(), MessageReceivers::AllPlayers);
+                }
+            }
+        }
+    }
+}
+
+fn select_game_mode(
+    mut votes: ResMut<ModeVotes>,
+    config: Res<ServerConfig>,
+    mut current: ResMut<CurrentGameMode>,
+    mut announcements: EventWriter<AnnouncementEvent>,
+    mut sender: MessageSender,
+) {
+    let mode = votes.winner(config.game_mode.default_mode);
+    votes.votes.clear();
+    current.0 = mode;
+    info!(mode = mode.name(), "Selected game mode");
+    announcements.send(AnnouncementEvent::new(
+        "announcement.game_mode",
+        &[mode.name()],
+        AnnouncementAudience::Everyone,
+    ));
+    sender.send(&votes.tally(), MessageReceivers::AllPlayers);
+}
+
+/// Id of the traitor role, used by role bans.
+pub const TRAITOR_ROLE: &str = "traitor";
+
+/// Server message telling a player they are an antagonist and what they have to do.
+/// Only ever sent to that player, nobody else can find out who the antagonists are.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct AntagonistBriefing {
+    pub role: String,
+    pub objectives: Vec<String>,
+}
+
+#[derive(Resource, Default)]
+pub struct ClientModeVotes {
+    pub tally: GameModeTally,
+}
+
+fn receive_tally(
+    mut messages: EventReader<MessageEvent<GameModeTally>>,
+    mut votes: ResMut<ClientModeVotes>,
+) {
+    if let Some(event) = messages.iter().last() {
+        votes.tally = event.message.clone();
+    }
+}
+
+/// The antagonist briefing of the local player, if they are one.
+#[derive(Resource, Default)]
+pub struct ClientBriefing {
+    pub briefing: Option<AntagonistBriefing>,
+    /// If the objectives window is shown
+    pub open: bool,
+}
+
+fn receive_briefing(
+    mut messages: EventReader<MessageEvent<AntagonistBriefing>>,
+    mut briefing: ResMut<ClientBriefing>,
+) {
+    if let Some(event) = messages.iter().last() {
+        briefing.briefing = Some(event.message.clone());
+        briefing.open = true;
+    }
+}
+
+#[cfg(feature = "client")]
+fn briefing_window(mut contexts: EguiContexts, mut briefing: ResMut<ClientBriefing>) {
+    let briefing = &mut *briefing;
+    let Some(current) = briefing.briefing.as_ref() else {
+        return;
+    };
+
+    egui::Window::new("Objectives")
+        .open(&mut briefing.open)
+        .resizable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.label(egui::RichText::new(format!("You are a {}.", current.role)).strong());
+            for (index, objective) in current.objectives.iter().enumerate() {
+                ui.label(format!("{}. {}", index + 1, objective));
+            }
+        });
+}
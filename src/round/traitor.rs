@@ -0,0 +1,353 @@
+use std::time::Duration;
+
+use bevy::{ecs::system::SystemParam, prelude::*, time::common_conditions::on_timer, utils::Uuid};
+use networking::{
+    is_server,
+    messaging::{MessageReceivers, MessageSender},
+    scene::NetworkSceneBundle,
+    spawning::ClientControls,
+    Players,
+};
+use utils::task::Tasks;
+
+use crate::{
+    admin::role_bans::RoleBans,
+    body::health::{VitalStatus, Vitals},
+    communication::{AnnouncementAudience, AnnouncementEvent},
+    config::ServerConfig,
+    escape_pod::Escapes,
+    items::{
+        clothes::{Clothing, ClothingHolder},
+        containers::{Container, MoveItem},
+        Item,
+    },
+    job::{Affiliation, JobDefinition, SelectedJobs},
+    profile::CharacterProfiles,
+    rng::GameRng,
+    shuttle::ShuttleGrid,
+};
+
+use super::{
+    modes::{game_mode_is, AntagonistBriefing, GameMode, GameModeSelection, TRAITOR_ROLE},
+    RoundState,
+};
+
+/// Traitor mode: a share of the crew is secretly told to steal something and escape alive.
+/// Who is a traitor is only known to the server and the traitors themselves until the round ends.
+pub(super) struct TraitorPlugin;
+
+impl Plugin for TraitorPlugin {
+    fn build(&self, app: &mut App) {
+        if !is_server(app) {
+            return;
+        }
+
+        app.init_resource::<Traitors>()
+            .add_systems(
+                OnEnter(RoundState::Running),
+                select_traitors
+                    .after(GameModeSelection)
+                    .run_if(game_mode_is(GameMode::Traitor)),
+            )
+            .add_systems(
+                Update,
+                (
+                    give_traitor_items,
+                    store_traitor_items,
+                    check_crew_wiped_out
+                        .run_if(on_timer(Duration::from_secs_f32(VICTORY_CHECK_INTERVAL))),
+                )
+                    .run_if(in_state(RoundState::Running))
+                    .run_if(game_mode_is(GameMode::Traitor)),
+            )
+            .add_systems(
+                OnEnter(RoundState::Ended),
+                traitor_summary.run_if(game_mode_is(GameMode::Traitor)),
+            );
+    }
+}
+
+/// Seconds between checks if the traitors killed everyone else
+const VICTORY_CHECK_INTERVAL: f32 = 1.0;
+
+/// The traitors of this round. Kept on the server only, nothing about it is replicated.
+#[derive(Resource, Default)]
+struct Traitors {
+    traitors: Vec<Traitor>,
+}
+
+struct Traitor {
+    player: Uuid,
+    name: String,
+    objectives: Vec<Objective>,
+    /// The creature the traitor spawned as, once they got their starting item
+    body: Option<Entity>,
+}
+
+enum Objective {
+    /// Have an item with this name on you when the round ends
+    Steal(String),
+    /// Be alive on the evacuation shuttle when the round ends
+    EscapeAlive,
+}
+
+impl Objective {
+    fn describe(&self) -> String {
+        match self {
+            Objective::Steal(item) => format!("Steal the {}.", item),
+            Objective::EscapeAlive => {
+                "Escape alive on the evacuation shuttle or an escape pod.".into()
+            }
+        }
+    }
+}
+
+/// Picks the share of candidates that become traitors.
+/// Players banned from the role are never drawn, and the same rng state always picks the same players.
+pub(crate) fn draw_traitors<T>(
+    mut candidates: Vec<(Uuid, T)>,
+    role_bans: &RoleBans,
+    fraction: f32,
+    rng: &mut fastrand::Rng,
+) -> Vec<(Uuid, T)> {
+    candidates.retain(|(player, _)| role_bans.antagonist_ban(*player, TRAITOR_ROLE).is_none());
+    if candidates.is_empty() {
+        return candidates;
+    }
+    // Same order every time, so the seed decides who is picked
+    candidates.sort_by_key(|(player, _)| *player);
+    rng.shuffle(&mut candidates);
+
+    let count = ((candidates.len() as f32 * fraction).round() as usize).clamp(1, candidates.len());
+    candidates.truncate(count);
+    candidates
+}
+
+#[allow(clippy::too_many_arguments)]
+fn select_traitors(
+    selected_jobs: Res<SelectedJobs>,
+    job_data: Res<Assets<JobDefinition>>,
+    players: Res<Players>,
+    profiles: Res<CharacterProfiles>,
+    config: Res<ServerConfig>,
+    role_bans: Res<RoleBans>,
+    mut rng: ResMut<GameRng>,
+    mut traitors: ResMut<Traitors>,
+    mut sender: MessageSender,
+) {
+    traitors.traitors.clear();
+
+    let candidates = selected_jobs
+        .selected(&job_data)
+        .filter_map(|(connection, _)| players.get(connection).map(|p| (p.id, (connection, p))))
+        .collect();
+    let config = &config.game_mode;
+    let rng = rng.stream("traitors");
+    let drawn = draw_traitors(candidates, &role_bans, config.traitor_fraction, rng);
+    for (_, (connection, player)) in drawn {
+        let mut objectives = Vec::new();
+        if !config.steal_targets.is_empty() {
+            let target = &config.steal_targets[rng.usize(..config.steal_targets.len())];
+            objectives.push(Objective::Steal(target.clone()));
+        }
+        objectives.push(Objective::EscapeAlive);
+
+        sender.send(
+            &AntagonistBriefing {
+                role: "Traitor".into(),
+                objectives: objectives.iter().map(Objective::describe).collect(),
+            },
+            MessageReceivers::Single(connection),
+        );
+        info!(player = ?player.id, "Selected traitor");
+
+        traitors.traitors.push(Traitor {
+            player: player.id,
+            name: profiles
+                .name(connection)
+                .map_or_else(|| player.username.clone(), str::to_owned),
+            objectives,
+            body: None,
+        });
+    }
+}
+
+/// A traitor item that is moved into a backpack once its prefab has spawned.
+#[derive(Component)]
+struct PendingTraitorItem {
+    container: Entity,
+}
+
+/// Spawns the starting item of traitors once they are in control of a body wearing a backpack.
+#[allow(clippy::too_many_arguments)]
+fn give_traitor_items(
+    mut traitors: ResMut<Traitors>,
+    controls: Res<ClientControls>,
+    children: Query<&Children>,
+    backpacks: Query<&Parent, (With<Container>, With<Clothing>)>,
+    holders: Query<(), With<ClothingHolder>>,
+    transforms: Query<&GlobalTransform>,
+    config: Res<ServerConfig>,
+    asset_server: Res<AssetServer>,
+    mut commands: Commands,
+) {
+    for traitor in traitors.traitors.iter_mut().filter(|t| t.body.is_none()) {
+        let Some(creature) = controls.controlled_entity(traitor.player) else {
+            continue;
+        };
+        let Some(backpack) = children.iter_descendants(creature).find(|&e| {
+            backpacks
+                .get(e)
+                .is_ok_and(|parent| holders.contains(parent.get()))
+        }) else {
+            continue;
+        };
+        let position = transforms
+            .get(creature)
+            .map_or(Vec3::ZERO, |t| t.translation());
+
+        commands.spawn((
+            NetworkSceneBundle {
+                scene: asset_server
+                    .load(format!("items/{}.scn.ron", config.game_mode.traitor_item))
+                    .into(),
+                transform: Transform::from_translation(position),
+                ..Default::default()
+            },
+            PendingTraitorItem {
+                container: backpack,
+            },
+        ));
+        traitor.body = Some(creature);
+    }
+}
+
+fn store_traitor_items(
+    items: Query<(Entity, &PendingTraitorItem), With<Item>>,
+    mut item_moves: ResMut<Tasks<MoveItem>>,
+    mut commands: Commands,
+) {
+    for (entity, pending) in items.iter() {
+        item_moves.create_ignore(MoveItem {
+            item: entity,
+            container: Some(pending.container),
+            position: None,
+        });
+        commands.entity(entity).remove::<PendingTraitorItem>();
+    }
+}
+
+/// Ends the round if every crew member that isn't a traitor is dead while a traitor lives.
+fn check_crew_wiped_out(
+    traitors: Res<Traitors>,
+    crew: Query<Entity, With<Affiliation>>,
+    vitals: Vitals,
+    mut round_state: ResMut<NextState<RoundState>>,
+    mut announcements: EventWriter<AnnouncementEvent>,
+) {
+    if traitors.traitors.is_empty() {
+        return;
+    }
+
+    let (mut crew_seen, mut crew_alive, mut traitor_alive) = (false, false, false);
+    for creature in crew.iter() {
+        let alive = vitals
+            .status(creature)
+            .is_some_and(|s| s != VitalStatus::Dead);
+        if traitors.traitors.iter().any(|t| t.body == Some(creature)) {
+            traitor_alive |= alive;
+        } else {
+            crew_seen = true;
+            crew_alive |= alive;
+        }
+    }
+
+    if crew_seen && !crew_alive && traitor_alive {
+        info!("Traitors killed the crew");
+        announcements.send(AnnouncementEvent::new(
+            "announcement.traitors_won",
+            &[],
+            AnnouncementAudience::Everyone,
+        ));
+        round_state.set(RoundState::Ended);
+    }
+}
+
+/// Checks the objectives of a traitor at the end of the round.
+#[derive(SystemParam)]
+struct ObjectiveCheck<'w, 's> {
+    vitals: Vitals<'w, 's>,
+    children: Query<'w, 's, &'static Children>,
+    parents: Query<'w, 's, &'static Parent>,
+    items: Query<'w, 's, &'static Item>,
+    shuttles: Query<'w, 's, (), With<ShuttleGrid>>,
+    escapes: Res<'w, Escapes>,
+}
+
+impl<'w, 's> ObjectiveCheck<'w, 's> {
+    fn completed(&self, objective: &Objective, traitor: &Traitor) -> bool {
+        // Pod escapees left the world along with everything they carried
+        if let Some(escapee) = self.escapes.get(traitor.player) {
+            return match objective {
+                Objective::Steal(name) => escapee.carried.contains(name),
+                Objective::EscapeAlive => true,
+            };
+        }
+        let Some(body) = traitor.body else {
+            return false;
+        };
+        match objective {
+            Objective::Steal(name) => self
+                .children
+                .iter_descendants(body)
+                .filter_map(|e| self.items.get(e).ok())
+                .any(|item| &item.name == name),
+            Objective::EscapeAlive => {
+                self.vitals
+                    .status(body)
+                    .is_some_and(|s| s != VitalStatus::Dead)
+                    && self
+                        .parents
+                        .iter_ancestors(body)
+                        .any(|e| self.shuttles.contains(e))
+            }
+        }
+    }
+}
+
+fn traitor_summary(
+    traitors: Res<Traitors>,
+    check: ObjectiveCheck,
+    mut announcements: EventWriter<AnnouncementEvent>,
+) {
+    let text = if traitors.traitors.is_empty() {
+        "There were no traitors this round.".to_owned()
+    } else {
+        let lines: Vec<_> = traitors
+            .traitors
+            .iter()
+            .map(|traitor| {
+                let objectives: Vec<_> = traitor
+                    .objectives
+                    .iter()
+                    .map(|objective| {
+                        let result = if check.completed(objective, traitor) {
+                            "success"
+                        } else {
+                            "failed"
+                        };
+                        format!(
+                            "{} ({})",
+                            objective.describe().trim_end_matches('.'),
+                            result
+                        )
+                    })
+                    .collect();
+                format!("{} was a traitor: {}", traitor.name, objectives.join(", "))
+            })
+            .collect();
+        format!("Traitors: {}.", lines.join("; "))
+    };
+    info!(text = text.as_str(), "Traitor summary");
+    announcements.send(AnnouncementEvent::custom(text));
+}
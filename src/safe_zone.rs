@@ -0,0 +1,248 @@
+use bevy::{ecs::system::SystemParam, prelude::*, reflect::TypeUuid};
+use maps::TileMap;
+use networking::{
+    component::AppExt,
+    is_server,
+    variable::{NetworkVar, ServerVar},
+    Networked,
+};
+use serde::Deserialize;
+
+use crate::{
+    body::{health::receive_damage, self_or_ancestor, Body},
+    combat::damage::{AffectedEntity, Attack, AttackSource},
+    config::ServerConfig,
+    feedback::{Feedback, FeedbackKind},
+};
+
+#[cfg(feature = "client")]
+use {
+    crate::ui::has_window,
+    bevy_egui::{egui, EguiContexts},
+    networking::spawning::ClientControlled,
+};
+
+/// Protects creatures in safe areas and players that just spawned from being attacked.
+pub struct SafeZonePlugin;
+
+impl Plugin for SafeZonePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_networked_component::<SpawnProtected, SpawnProtectedClient>();
+        if is_server(app) {
+            app.add_systems(Startup, load_safe_areas).add_systems(
+                Update,
+                (
+                    expire_spawn_protection,
+                    // Blocked attacks have to be gone before damage is applied
+                    (block_protected_attacks, apply_deferred)
+                        .chain()
+                        .before(receive_damage),
+                ),
+            );
+        } else {
+            #[cfg(feature = "client")]
+            app.add_systems(Update, spawn_protection_ui.run_if(has_window));
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct SafetyConfig {
+    /// Area paths where creatures can't attack or be attacked.
+    /// Sub-areas of a listed area are included.
+    #[serde(default = "SafetyConfig::default_safe_areas")]
+    pub safe_areas: Vec<String>,
+    /// Seconds a new body is protected after spawning. 0 disables spawn protection.
+    #[serde(default = "SafetyConfig::default_spawn_protection")]
+    pub spawn_protection_seconds: f32,
+}
+
+impl SafetyConfig {
+    fn default_safe_areas() -> Vec<String> {
+        Vec::new()
+    }
+
+    fn default_spawn_protection() -> f32 {
+        10.0
+    }
+}
+
+impl Default for SafetyConfig {
+    fn default() -> Self {
+        Self {
+            safe_areas: Self::default_safe_areas(),
+            spawn_protection_seconds: Self::default_spawn_protection(),
+        }
+    }
+}
+
+/// Area path prefixes that are safe zones.
+#[derive(Resource, Default)]
+struct SafeAreas(Vec<String>);
+
+impl SafeAreas {
+    fn contains(&self, area: &str) -> bool {
+        self.0.iter().any(|prefix| {
+            area.strip_prefix(prefix.as_str())
+                .map(|rest| rest.is_empty() || rest.starts_with('/'))
+                .unwrap_or(false)
+        })
+    }
+}
+
+fn load_safe_areas(config: Res<ServerConfig>, mut commands: Commands) {
+    commands.insert_resource(SafeAreas(config.safety.safe_areas.clone()));
+}
+
+/// A recently spawned body that can't attack or be attacked.
+/// Removed when it runs out, the creature attacks, or it leaves the area it spawned in.
+#[derive(Component, Networked)]
+#[networked(client = "SpawnProtectedClient")]
+pub struct SpawnProtected {
+    duration: NetworkVar<f32>,
+    started: f32,
+    /// The area the body spawned in
+    area: Option<String>,
+}
+
+impl SpawnProtected {
+    pub fn new(duration: f32, now: f32, area: Option<String>) -> Self {
+        Self {
+            duration: duration.into(),
+            started: now,
+            area,
+        }
+    }
+}
+
+#[derive(Component, Default, TypeUuid, Networked)]
+#[uuid = "5d1f0a4e-7c2b-4b8e-9a63-2f4e8c1b7d90"]
+#[networked(server = "SpawnProtected")]
+pub struct SpawnProtectedClient {
+    duration: ServerVar<f32>,
+    /// Local time the protection was received, used for the countdown
+    #[cfg(feature = "client")]
+    received: Option<f32>,
+}
+
+/// Checks if creatures are allowed to take part in combat.
+#[derive(SystemParam)]
+pub struct Safety<'w, 's> {
+    protected: Query<'w, 's, (), With<SpawnProtected>>,
+    transforms: Query<'w, 's, &'static GlobalTransform>,
+    maps: Query<'w, 's, &'static TileMap>,
+    safe_areas: Res<'w, SafeAreas>,
+}
+
+impl<'w, 's> Safety<'w, 's> {
+    /// If the creature is spawn protected or standing in a safe zone.
+    pub fn is_protected(&self, creature: Entity) -> bool {
+        self.protected.contains(creature) || self.in_safe_zone(creature)
+    }
+
+    pub fn in_safe_zone(&self, creature: Entity) -> bool {
+        let Ok(transform) = self.transforms.get(creature) else {
+            return false;
+        };
+        // TODO: Support multiple maps
+        let Ok(map) = self.maps.get_single() else {
+            return false;
+        };
+        map.area_at(transform.translation())
+            .map(|area| self.safe_areas.contains(area))
+            .unwrap_or(false)
+    }
+
+    fn spawn_protected(&self, creature: Entity) -> bool {
+        self.protected.contains(creature)
+    }
+}
+
+fn expire_spawn_protection(
+    protected: Query<(Entity, &SpawnProtected, &GlobalTransform)>,
+    maps: Query<&TileMap>,
+    time: Res<Time>,
+    mut commands: Commands,
+) {
+    let now = time.elapsed_seconds();
+    let map = maps.get_single().ok();
+    for (entity, protection, transform) in protected.iter() {
+        let expired = now - protection.started >= *protection.duration;
+        let left_area = map
+            .map(|m| m.area_at(transform.translation()) != protection.area.as_deref())
+            .unwrap_or(false);
+        if expired || left_area {
+            commands.entity(entity).remove::<SpawnProtected>();
+        }
+    }
+}
+
+/// Removes attacks on or by protected creatures before they cause any damage.
+/// This is the only place attacks are checked, so it covers every source of damage that creates an [`Attack`],
+/// like melee, shots, thrown items and explosions.
+fn block_protected_attacks(
+    attacks: Query<(Entity, &AffectedEntity, Option<&AttackSource>), Added<Attack>>,
+    bodies: Query<(), With<Body>>,
+    parents: Query<&Parent>,
+    safety: Safety,
+    mut feedback: Feedback,
+    mut commands: Commands,
+) {
+    let creature_of = |entity| self_or_ancestor(&parents, entity, |e| bodies.contains(e));
+    for (attack, affected, source) in attacks.iter() {
+        // Attacks usually hit a limb, so look for the body it belongs to
+        let target = creature_of(affected.0);
+        if target.is_some_and(|c| safety.is_protected(c)) {
+            commands.entity(attack).despawn();
+            continue;
+        }
+
+        // Whoever primed a grenade or threw an item counts as an attacker too
+        let attackers = source
+            .into_iter()
+            .flat_map(|s| [Some(s.attacker), s.instigator.filter(|&i| i != s.attacker)])
+            .flatten()
+            .filter_map(creature_of);
+        let mut blocked = false;
+        for attacker in attackers {
+            // Starting a fight ends spawn protection, the first attack is still blocked
+            if safety.spawn_protected(attacker) {
+                commands.entity(attacker).remove::<SpawnProtected>();
+            } else if !safety.in_safe_zone(attacker) {
+                continue;
+            }
+            blocked = true;
+            feedback.send_to_creature(attacker, FeedbackKind::Blocked, "combat.blocked", &[]);
+        }
+        if blocked {
+            commands.entity(attack).despawn();
+        }
+    }
+}
+
+#[cfg(feature = "client")]
+fn spawn_protection_ui(
+    mut contexts: EguiContexts,
+    mut protected: Query<&mut SpawnProtectedClient, With<ClientControlled>>,
+    time: Res<Time>,
+) {
+    let Ok(mut protection) = protected.get_single_mut() else {
+        return;
+    };
+    let now = time.elapsed_seconds();
+    let received = *protection.received.get_or_insert(now);
+    let remaining = *protection.duration - (now - received);
+    if remaining <= 0.0 {
+        return;
+    }
+
+    egui::Area::new("spawn_protection_indicator")
+        .anchor(egui::Align2::CENTER_TOP, egui::vec2(0.0, 48.0))
+        .show(contexts.ctx_mut(), |ui| {
+            ui.label(
+                egui::RichText::new(format!("Spawn protection: {:.0}s", remaining.ceil()))
+                    .color(egui::Rgba::from_rgb(0.4, 0.8, 1.0))
+                    .size(16.0),
+            );
+        });
+}
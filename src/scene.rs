@@ -4,6 +4,10 @@ use bevy::{
 };
 use networking::is_client;
 
+use self::server_scene_compat::is_render_only;
+
+pub mod server_scene_compat;
+
 fn dyn_entity_has_component<T: Reflect>(entity: &DynamicEntity) -> bool {
     entity.components.iter().any(|c| c.represents::<T>())
 }
@@ -12,12 +16,19 @@ fn modify_loaded_scenes(
     mut scenes: ResMut<Assets<DynamicScene>>,
     mut events: EventReader<AssetEvent<DynamicScene>>,
     client_assets: Option<Res<ClientSceneAssets>>,
+    registry: Res<AppTypeRegistry>,
 ) {
+    let registry = registry.read();
     for event in events.iter() {
         if let AssetEvent::Created { handle } = event {
             let scene = scenes.get_mut(handle).unwrap();
 
             for dynamic_entity in &mut scene.entities {
+                // Remove components only used for rendering, only the server marks them
+                dynamic_entity
+                    .components
+                    .retain(|c| !is_render_only(&registry, c.as_ref()));
+
                 // Add a global transform to all entities
                 // This will probably change at some point, so we don't add it when it's not needed
                 dynamic_entity
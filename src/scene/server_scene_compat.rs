@@ -0,0 +1,214 @@
+use bevy::{
+    prelude::*,
+    reflect::{FromType, TypeRegistry},
+};
+
+#[cfg(feature = "server")]
+use bevy::{reflect::GetTypeRegistration, scene::SceneInstanceReady};
+
+/// Registers rendering types used in scene files on the server.
+///
+/// The server does not render anything, but needs the types registered so it can load scene files.
+/// The components are stripped from scenes when they are loaded, and from scene instances once they spawned,
+/// so they don't waste server memory.
+#[cfg(feature = "server")]
+pub struct ServerSceneCompatPlugin;
+
+#[cfg(feature = "server")]
+impl Plugin for ServerSceneCompatPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_render_only::<bevy::pbr::PointLight>()
+            .register_render_only::<bevy::pbr::CubemapVisibleEntities>()
+            .register_render_only::<bevy::render::primitives::CubemapFrusta>()
+            .register_render_only::<bevy::render::view::Visibility>()
+            .register_render_only::<bevy::render::view::ComputedVisibility>()
+            .register_render_only::<Handle<bevy::pbr::StandardMaterial>>()
+            .register_render_only::<bevy::pbr::NotShadowCaster>()
+            .add_systems(Update, scrub_spawned_scenes);
+    }
+}
+
+/// Type data of components that are only used for rendering.
+/// The server removes them from scenes, see [`RenderOnlyAppExt::register_render_only`].
+#[derive(Clone)]
+pub struct ReflectRenderOnly;
+
+impl<T> FromType<T> for ReflectRenderOnly {
+    fn from_type() -> Self {
+        Self
+    }
+}
+
+#[cfg(feature = "server")]
+pub trait RenderOnlyAppExt {
+    /// Registers the type, so scenes containing it load, and marks it to be removed from them.
+    fn register_render_only<T: GetTypeRegistration>(&mut self) -> &mut Self;
+}
+
+#[cfg(feature = "server")]
+impl RenderOnlyAppExt for App {
+    fn register_render_only<T: GetTypeRegistration>(&mut self) -> &mut Self {
+        self.register_type::<T>()
+            .register_type_data::<T, ReflectRenderOnly>()
+    }
+}
+
+/// If the component is of a type registered with [`ReflectRenderOnly`].
+pub fn is_render_only(registry: &TypeRegistry, component: &dyn Reflect) -> bool {
+    component
+        .get_represented_type_info()
+        .and_then(|info| registry.get_type_data::<ReflectRenderOnly>(info.type_id()))
+        .is_some()
+}
+
+/// Removes render-only components from spawned scene instances.
+/// glTF scenes aren't scrubbed when they load like scene files are.
+#[cfg(feature = "server")]
+fn scrub_spawned_scenes(
+    mut ready: EventReader<SceneInstanceReady>,
+    children: Query<&Children>,
+    registry: Res<AppTypeRegistry>,
+    mut commands: Commands,
+) {
+    if ready.is_empty() {
+        return;
+    }
+    let render_only: Vec<ReflectComponent> = registry
+        .read()
+        .iter()
+        .filter(|registration| registration.data::<ReflectRenderOnly>().is_some())
+        .filter_map(|registration| registration.data::<ReflectComponent>().cloned())
+        .collect();
+
+    for event in ready.iter() {
+        let entities: Vec<Entity> = children.iter_descendants(event.parent).collect();
+        let components = render_only.clone();
+        commands.add(move |world: &mut World| {
+            for entity in entities {
+                let Some(mut entity) = world.get_entity_mut(entity) else {
+                    continue;
+                };
+                for component in components.iter() {
+                    component.remove(&mut entity);
+                }
+            }
+        });
+    }
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use std::{
+        path::{Path, PathBuf},
+        time::Duration,
+    };
+
+    use bevy::{asset::LoadState, render::view::Visibility};
+
+    use super::*;
+    use crate::{config::ServerConfig, testing::server_app};
+
+    const MAX_FRAMES: u32 = 2000;
+    const FRAME_TIME: Duration = Duration::from_millis(5);
+
+    fn scene_files(directory: &Path, files: &mut Vec<PathBuf>) {
+        for entry in std::fs::read_dir(directory).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                scene_files(&path, files);
+            } else if path.to_string_lossy().ends_with(".scn.ron") {
+                files.push(path);
+            }
+        }
+    }
+
+    #[test]
+    fn every_scene_loads_without_render_components() {
+        let mut app = server_app(ServerConfig::default());
+        let assets = Path::new(env!("CARGO_MANIFEST_DIR")).join("assets");
+        let mut files = Vec::new();
+        scene_files(&assets, &mut files);
+        assert!(!files.is_empty());
+
+        let asset_server = app.world.resource::<AssetServer>().clone();
+        let scenes: Vec<(String, Handle<DynamicScene>)> = files
+            .iter()
+            .map(|file| {
+                let path = file
+                    .strip_prefix(&assets)
+                    .unwrap()
+                    .to_string_lossy()
+                    .replace('\\', "/");
+                let handle = asset_server.load(path.as_str());
+                (path, handle)
+            })
+            .collect();
+
+        let loading = |app: &App| {
+            scenes.iter().any(|(_, handle)| {
+                matches!(
+                    app.world.resource::<AssetServer>().get_load_state(handle),
+                    LoadState::NotLoaded | LoadState::Loading
+                )
+            })
+        };
+        let mut frames = 0;
+        while loading(&app) {
+            assert!(frames < MAX_FRAMES, "Scenes took too long to load");
+            app.update();
+            frames += 1;
+            std::thread::sleep(FRAME_TIME);
+        }
+        // Loaded scenes are scrubbed in the next update
+        app.update();
+
+        // Scenes with unregistered types fail to load
+        let failed: Vec<_> = scenes
+            .iter()
+            .filter(|(_, handle)| asset_server.get_load_state(handle) == LoadState::Failed)
+            .map(|(path, _)| path.as_str())
+            .collect();
+        assert!(failed.is_empty(), "Scenes failed to load: {:?}", failed);
+
+        let registry = app.world.resource::<AppTypeRegistry>().read();
+        let loaded = app.world.resource::<Assets<DynamicScene>>();
+        for (path, handle) in scenes.iter() {
+            let scene = loaded.get(handle).unwrap();
+            for component in scene.entities.iter().flat_map(|e| e.components.iter()) {
+                assert!(
+                    !is_render_only(&registry, component.as_ref()),
+                    "{} still has {}",
+                    path,
+                    component.type_name()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn spawned_scenes_are_scrubbed() {
+        let mut app = server_app(ServerConfig::default());
+        let mut world = World::new();
+        world.spawn((Transform::from_xyz(1.0, 0.0, 0.0), Visibility::Hidden));
+        let scene = app
+            .world
+            .resource_mut::<Assets<Scene>>()
+            .add(Scene::new(world));
+        let root = app
+            .world
+            .spawn(SceneBundle {
+                scene,
+                ..Default::default()
+            })
+            .id();
+        for _ in 0..5 {
+            app.update();
+        }
+
+        let spawned = app.world.get::<Children>(root).unwrap();
+        assert_eq!(spawned.len(), 1);
+        let spawned = spawned[0];
+        assert!(app.world.get::<Transform>(spawned).is_some());
+        assert!(app.world.get::<Visibility>(spawned).is_none());
+    }
+}
@@ -0,0 +1,202 @@
+use bevy::{prelude::*, reflect::TypeUuid};
+use networking::{
+    component::AppExt,
+    identity::{EntityCommandsExt, NetworkIdentity},
+    is_server,
+    spawning::ClientControls,
+    variable::{NetworkVar, ServerVar},
+    visibility::{AlwaysVisible, Relevancy, RelevancyTarget},
+    Networked, Players,
+};
+
+use crate::{
+    interaction::{
+        ActiveInteraction, GenerateInteractionList, InteractionListEvents, InteractionOption,
+        InteractionSpecificity, InteractionStatus,
+    },
+    ui::NetworkUi,
+};
+
+#[cfg(feature = "client")]
+use {
+    crate::{
+        camera::TopDownCamera,
+        ui::{has_window, CloseUiMessage},
+    },
+    bevy_egui::{egui, EguiContexts},
+    networking::{
+        identity::NetworkIdentities, messaging::MessageSender, spawning::ClientControlled,
+    },
+};
+
+pub struct SecurityCameraPlugin;
+
+impl Plugin for SecurityCameraPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<SecurityCamera>()
+            .add_networked_component::<CameraUi, CameraUiClient>();
+
+        if is_server(app) {
+            app.register_type::<ViewCameraInteraction>().add_systems(
+                Update,
+                (
+                    prepare_view_camera_interaction.in_set(GenerateInteractionList),
+                    view_camera_interaction,
+                ),
+            );
+        } else {
+            #[cfg(feature = "client")]
+            app.add_systems(
+                Update,
+                (camera_ui.run_if(has_window), reset_camera_on_close),
+            );
+        }
+    }
+}
+
+/// A stationary camera that players can look through.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct SecurityCamera;
+
+#[derive(Component, Networked)]
+#[networked(client = "CameraUiClient")]
+struct CameraUi {
+    camera: NetworkVar<NetworkIdentity>,
+}
+
+#[derive(Component, Default, TypeUuid, Networked)]
+#[uuid = "0d8a4f4e-2c1b-4c5e-9a3f-6b7e1d2c9f80"]
+#[networked(server = "CameraUi")]
+struct CameraUiClient {
+    camera: ServerVar<NetworkIdentity>,
+}
+
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+#[component(storage = "SparseSet")]
+struct ViewCameraInteraction {
+    viewer: Entity,
+}
+
+// Dummy default for Reflect
+impl Default for ViewCameraInteraction {
+    fn default() -> Self {
+        Self {
+            viewer: Entity::from_raw(0),
+        }
+    }
+}
+
+fn prepare_view_camera_interaction(
+    list: Res<InteractionListEvents>,
+    cameras: Query<(), With<SecurityCamera>>,
+) {
+    for event in list.events.iter() {
+        if !cameras.contains(event.target) {
+            continue;
+        }
+
+        event.add_interaction(InteractionOption {
+            text: "View camera".into(),
+            interaction: Box::new(ViewCameraInteraction {
+                viewer: event.source,
+            }),
+            specificity: InteractionSpecificity::Common,
+        });
+    }
+}
+
+fn view_camera_interaction(
+    mut query: Query<(&ViewCameraInteraction, &mut ActiveInteraction)>,
+    cameras: Query<(&NetworkIdentity, &GlobalTransform), With<SecurityCamera>>,
+    mut relevancy: ResMut<Relevancy>,
+    controls: Res<ClientControls>,
+    players: Res<Players>,
+    mut commands: Commands,
+) {
+    for (interaction, mut active) in query.iter_mut() {
+        let Ok((&camera_id, transform)) = cameras.get(active.target) else {
+            active.status = InteractionStatus::Canceled;
+            continue;
+        };
+        let Some(connection) = controls
+            .controlling_player(interaction.viewer)
+            .and_then(|p| players.get_connection(&p))
+        else {
+            active.status = InteractionStatus::Canceled;
+            continue;
+        };
+
+        let ui = commands
+            .spawn((
+                NetworkUi,
+                CameraUi {
+                    camera: camera_id.into(),
+                },
+                AlwaysVisible::single(interaction.viewer),
+            ))
+            .networked()
+            .id();
+
+        // Stream the area around the camera while the UI exists
+        relevancy.add_viewer(ui, active.target, connection);
+        relevancy.add_viewer(
+            ui,
+            RelevancyTarget::chunk_at(transform.translation()),
+            connection,
+        );
+        active.status = InteractionStatus::Completed;
+    }
+}
+
+#[cfg(feature = "client")]
+fn camera_ui(
+    mut contexts: EguiContexts,
+    uis: Query<(Entity, &NetworkIdentity, &CameraUiClient)>,
+    identities: Res<NetworkIdentities>,
+    mut main_camera: Query<&mut TopDownCamera>,
+    mut sender: MessageSender,
+) {
+    for (entity, identity, camera_ui) in uis.iter() {
+        if let (Some(target), Ok(mut main_camera)) = (
+            identities.get_entity(*camera_ui.camera),
+            main_camera.get_single_mut(),
+        ) {
+            if main_camera.target != target {
+                main_camera.target = target;
+            }
+        }
+
+        let mut keep_open = true;
+        egui::Window::new("Camera")
+            .id(egui::Id::new(("camera", entity)))
+            .open(&mut keep_open)
+            .resizable(false)
+            .show(contexts.ctx_mut(), |ui| {
+                ui.label("Viewing camera feed");
+            });
+
+        if !keep_open {
+            sender.send_to_server(&CloseUiMessage { ui: *identity });
+        }
+    }
+}
+
+#[cfg(feature = "client")]
+/// Moves the main camera back to the player once the camera UI is gone
+fn reset_camera_on_close(
+    mut removed: RemovedComponents<CameraUiClient>,
+    controlled: Query<Entity, With<ClientControlled>>,
+    mut main_camera: Query<&mut TopDownCamera>,
+) {
+    if removed.iter().next().is_none() {
+        return;
+    }
+
+    if let (Ok(player), Ok(mut main_camera)) =
+        (controlled.get_single(), main_camera.get_single_mut())
+    {
+        main_camera.target = player;
+    }
+}
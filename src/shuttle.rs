@@ -0,0 +1,381 @@
+use std::fs::read_to_string;
+
+use bevy::{math::Vec3Swizzles, prelude::*, utils::HashMap};
+use bevy_rapier3d::prelude::RigidBody;
+use maps::{SubGrid, SubGridData, SubGridTile};
+use networking::{
+    identity::EntityCommandsExt, is_server, spawning::ClientControls, transform::NetworkTransform,
+};
+use serde::Deserialize;
+
+use crate::{
+    communication::{AnnouncementAudience, AnnouncementEvent},
+    round::RoundState,
+};
+
+/// A shuttle that flies along a path, taking everyone standing on it along.
+pub struct ShuttlePlugin;
+
+impl Plugin for ShuttlePlugin {
+    fn build(&self, app: &mut App) {
+        if is_server(app) {
+            app.add_event::<CallShuttle>()
+                .add_systems(OnEnter(RoundState::Running), load_shuttle)
+                .add_systems(
+                    Update,
+                    (call_shuttle, depart_shuttle, move_shuttle, update_boarding).chain(),
+                );
+        }
+    }
+}
+
+const DEFAULT_SHUTTLE_FILE: &str = "shuttle.ron";
+/// How far from a tile edge the center of a creature is kept when the shuttle departs
+const BOUNDARY_MARGIN: f32 = 0.35;
+
+/// Layout and flight path of the shuttle, loaded from a file.
+#[derive(Deserialize)]
+struct ShuttleDefinition {
+    /// Turf scene paths for the characters used in the layout
+    legend: HashMap<char, String>,
+    /// Rows of tiles, characters not in the legend are empty
+    layout: Vec<String>,
+    /// Where the first tile of the layout is while docked at the station
+    dock: Vec2,
+    /// Where the shuttle goes after departing, the last waypoint is the destination
+    path: Vec<ShuttleWaypoint>,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+struct ShuttleWaypoint {
+    position: Vec2,
+    /// How long it takes to get here from the previous waypoint
+    seconds: f32,
+}
+
+impl ShuttleDefinition {
+    fn grid_data(&self) -> SubGridData {
+        let width = self
+            .layout
+            .iter()
+            .map(|row| row.chars().count())
+            .max()
+            .unwrap_or_default();
+        let mut turfs = Vec::with_capacity(width * self.layout.len());
+        for row in self.layout.iter() {
+            let mut chars = row.chars();
+            turfs.extend(
+                (0..width).map(|_| chars.next().and_then(|c| self.legend.get(&c).cloned())),
+            );
+        }
+
+        SubGridData {
+            size: UVec2::new(width as u32, self.layout.len() as u32),
+            turfs,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum ShuttleStage {
+    Docked,
+    Called {
+        departs_at: f32,
+    },
+    Moving {
+        leg: usize,
+        from: Vec2,
+        leg_started: f32,
+    },
+    Arrived,
+}
+
+/// The root of a shuttle's [`SubGrid`], moved along its path by the server.
+#[derive(Component)]
+pub struct ShuttleGrid {
+    path: Vec<ShuttleWaypoint>,
+    stage: ShuttleStage,
+}
+
+impl ShuttleGrid {
+    /// If the shuttle has been called, so the station is being evacuated.
+    pub fn evacuation_called(&self) -> bool {
+        !matches!(self.stage, ShuttleStage::Docked)
+    }
+}
+
+/// Calls the shuttle, which departs after a delay
+#[derive(Event)]
+pub struct CallShuttle {
+    pub departs_in: f32,
+}
+
+fn load_shuttle(mut commands: Commands) {
+    let text = match read_to_string(DEFAULT_SHUTTLE_FILE) {
+        Ok(t) => t,
+        Err(_) => {
+            info!("No shuttle configured");
+            return;
+        }
+    };
+
+    let definition: ShuttleDefinition = match ron::from_str(&text) {
+        Ok(d) => d,
+        Err(err) => {
+            error!(error = %err, "Error parsing {}", DEFAULT_SHUTTLE_FILE);
+            return;
+        }
+    };
+
+    let entity = commands
+        .spawn((
+            definition.grid_data(),
+            ShuttleGrid {
+                path: definition.path,
+                stage: ShuttleStage::Docked,
+            },
+            SpatialBundle::from_transform(Transform::from_xyz(
+                definition.dock.x,
+                0.0,
+                definition.dock.y,
+            )),
+            NetworkTransform::default(),
+        ))
+        .networked()
+        .id();
+    info!(entity = ?entity, "Spawned shuttle");
+}
+
+fn call_shuttle(
+    mut events: EventReader<CallShuttle>,
+    mut shuttles: Query<&mut ShuttleGrid>,
+    mut announcements: EventWriter<AnnouncementEvent>,
+    time: Res<Time>,
+) {
+    for event in events.iter() {
+        let mut called = false;
+        for mut shuttle in shuttles.iter_mut() {
+            if !matches!(shuttle.stage, ShuttleStage::Docked) {
+                continue;
+            }
+            shuttle.stage = ShuttleStage::Called {
+                departs_at: time.elapsed_seconds() + event.departs_in,
+            };
+            called = true;
+        }
+
+        if called {
+            announcements.send(AnnouncementEvent::new(
+                "announcement.shuttle_called",
+                &[&format!("{:.0}", event.departs_in)],
+                AnnouncementAudience::Everyone,
+            ));
+        } else {
+            warn!("Shuttle called, but there is no docked shuttle");
+        }
+    }
+}
+
+/// Entities that can ride the shuttle.
+/// Anything nested under something else (like held items) moves with its parent instead.
+type Passengers<'w, 's> = Query<
+    'w,
+    's,
+    (Entity, &'static GlobalTransform, Option<&'static Parent>),
+    (
+        With<NetworkTransform>,
+        With<RigidBody>,
+        Without<SubGridTile>,
+    ),
+>;
+
+fn depart_shuttle(
+    mut shuttles: Query<(Entity, &mut ShuttleGrid, &SubGrid, &GlobalTransform)>,
+    passengers: Passengers,
+    controls: Res<ClientControls>,
+    mut announcements: EventWriter<AnnouncementEvent>,
+    mut round_state: ResMut<NextState<RoundState>>,
+    time: Res<Time>,
+    mut commands: Commands,
+) {
+    let now = time.elapsed_seconds();
+    for (shuttle_entity, mut shuttle, grid, grid_transform) in shuttles.iter_mut() {
+        let ShuttleStage::Called { departs_at } = shuttle.stage else {
+            continue;
+        };
+        if now < departs_at {
+            continue;
+        }
+
+        // Everyone is either aboard or left behind, nobody is carried half-way
+        let to_local = grid_transform.affine().inverse();
+        let mut players_aboard = 0;
+        for (entity, transform, parent) in passengers.iter() {
+            if parent.is_some_and(|p| p.get() != shuttle_entity) {
+                continue;
+            }
+
+            let local = to_local.transform_point3(transform.translation());
+            let tile = SubGrid::local_tile(local);
+            let aboard = grid.contains(tile);
+            let clamped = keep_on_side(grid, local, tile, aboard);
+
+            let mut new_transform = transform.compute_transform();
+            if aboard {
+                if controls.controlling_player(entity).is_some() {
+                    players_aboard += 1;
+                }
+                new_transform.translation = clamped;
+                commands
+                    .entity(entity)
+                    .insert(new_transform)
+                    .set_parent(shuttle_entity);
+            } else if clamped != local {
+                new_transform.translation = grid_transform.transform_point(clamped);
+                commands.entity(entity).insert(new_transform);
+            }
+        }
+
+        info!(
+            entity = ?shuttle_entity,
+            players = players_aboard,
+            "Shuttle departed"
+        );
+        shuttle.stage = ShuttleStage::Moving {
+            leg: 0,
+            from: grid_transform.translation().xz(),
+            leg_started: now,
+        };
+
+        if players_aboard > 0 {
+            announcements.send(AnnouncementEvent::new(
+                "announcement.shuttle_departed",
+                &[&players_aboard.to_string()],
+                AnnouncementAudience::Everyone,
+            ));
+            round_state.set(RoundState::Ended);
+        } else {
+            announcements.send(AnnouncementEvent::new(
+                "announcement.shuttle_departed_empty",
+                &[],
+                AnnouncementAudience::Everyone,
+            ));
+        }
+    }
+}
+
+/// Moves a position away from the edges between its tile and tiles on the other side of the shuttle boundary.
+fn keep_on_side(grid: &SubGrid, local: Vec3, tile: IVec2, aboard: bool) -> Vec3 {
+    let mut result = local;
+    let center = tile.as_vec2();
+    for (offset, axis) in [
+        (IVec2::X, 0),
+        (IVec2::NEG_X, 0),
+        (IVec2::Y, 1),
+        (IVec2::NEG_Y, 1),
+    ] {
+        if grid.contains(tile + offset) == aboard {
+            continue;
+        }
+        let (value, middle) = if axis == 0 {
+            (&mut result.x, center.x)
+        } else {
+            (&mut result.z, center.y)
+        };
+        let limit = middle + offset[axis] as f32 * (0.5 - BOUNDARY_MARGIN);
+        *value = if offset[axis] > 0 {
+            value.min(limit)
+        } else {
+            value.max(limit)
+        };
+    }
+    result
+}
+
+fn move_shuttle(
+    mut shuttles: Query<(&mut ShuttleGrid, &mut Transform)>,
+    mut announcements: EventWriter<AnnouncementEvent>,
+    time: Res<Time>,
+) {
+    let now = time.elapsed_seconds();
+    for (mut shuttle, mut transform) in shuttles.iter_mut() {
+        let ShuttleStage::Moving {
+            leg,
+            from,
+            leg_started,
+        } = shuttle.stage
+        else {
+            continue;
+        };
+        let Some(waypoint) = shuttle.path.get(leg).copied() else {
+            shuttle.stage = ShuttleStage::Arrived;
+            continue;
+        };
+
+        let progress = if waypoint.seconds > 0.0 {
+            ((now - leg_started) / waypoint.seconds).min(1.0)
+        } else {
+            1.0
+        };
+        let position = from.lerp(waypoint.position, progress);
+        transform.translation.x = position.x;
+        transform.translation.z = position.y;
+
+        if progress < 1.0 {
+            continue;
+        }
+        shuttle.stage = if leg + 1 < shuttle.path.len() {
+            ShuttleStage::Moving {
+                leg: leg + 1,
+                from: waypoint.position,
+                leg_started: now,
+            }
+        } else {
+            announcements.send(AnnouncementEvent::new(
+                "announcement.shuttle_arrived",
+                &[],
+                AnnouncementAudience::Everyone,
+            ));
+            ShuttleStage::Arrived
+        };
+    }
+}
+
+/// Parents entities to the shuttle while they stand on it, so they move along with it.
+fn update_boarding(
+    shuttles: Query<(Entity, &ShuttleGrid, &SubGrid, &GlobalTransform)>,
+    passengers: Passengers,
+    mut commands: Commands,
+) {
+    for (shuttle_entity, shuttle, grid, grid_transform) in shuttles.iter() {
+        // Nobody gets on or off while flying
+        if matches!(shuttle.stage, ShuttleStage::Moving { .. }) {
+            continue;
+        }
+
+        let to_local = grid_transform.affine().inverse();
+        for (entity, transform, parent) in passengers.iter() {
+            let on_shuttle = match parent {
+                Some(parent) if parent.get() == shuttle_entity => true,
+                Some(_) => continue,
+                None => false,
+            };
+
+            let local = to_local.transform_point3(transform.translation());
+            let aboard = grid.contains(SubGrid::local_tile(local));
+            let mut new_transform = transform.compute_transform();
+            if aboard && !on_shuttle {
+                new_transform.translation = local;
+                commands
+                    .entity(entity)
+                    .insert(new_transform)
+                    .set_parent(shuttle_entity);
+            } else if !aboard && on_shuttle {
+                commands
+                    .entity(entity)
+                    .insert(new_transform)
+                    .remove_parent();
+            }
+        }
+    }
+}
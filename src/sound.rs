@@ -0,0 +1,618 @@
+use bevy::{
+    core::FrameCount,
+    ecs::system::SystemParam,
+    math::Vec3Swizzles,
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+use maps::{FootstepMaterial, TileMap};
+use networking::{
+    is_server,
+    messaging::{AppExt, MessageReceivers, MessageSender},
+    spawning::ClientControls,
+    transform::ClientMovement,
+    Players,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    body::Body,
+    coating::TileCoatings,
+    door::DoorState,
+    gravity::Weightless,
+    items::{Item, StoredItem},
+    temperature::Airtight,
+};
+
+pub use self::ambience::{PlayMusicEvent, TrackId};
+use self::muffling::{Occlusion, OcclusionCache};
+
+mod ambience;
+mod muffling;
+
+pub struct SoundPlugin;
+
+impl Plugin for SoundPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<ImpactMaterial>()
+            .add_network_message::<PlaySoundMessage>("PlaySoundMessage")
+            .add_plugins(ambience::AmbiencePlugin);
+
+        if is_server(app) {
+            app.add_systems(Update, (emit_footsteps, emit_item_drop_impacts));
+        } else {
+            #[cfg(feature = "client")]
+            app.init_resource::<AudioSettings>()
+                .add_event::<PlaySoundMessage>()
+                .add_systems(Startup, client::load_sound_registry)
+                .add_systems(Update, client::play_received_sounds);
+        }
+    }
+}
+
+/// Identifies a kind of sound. The client decides which audio asset is actually played.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum SoundId {
+    Footstep,
+    /// Stepping through a spilled liquid
+    WetFootstep,
+    ItemImpact,
+    ItemPickup,
+    Sparks,
+    Debris,
+    /// An object breaking, picked by its impact material
+    Break,
+    /// An action was refused, like a door without access
+    Denied,
+    /// Played for every station announcement
+    Announcement,
+    /// Something being cut apart, like a butchered corpse
+    Gore,
+}
+
+/// Volume preferences of the player, each from 0 to 1.
+#[cfg(feature = "client")]
+#[derive(Resource)]
+pub struct AudioSettings {
+    pub master: f32,
+    pub effects: f32,
+    pub ambience: f32,
+    pub music: f32,
+}
+
+#[cfg(feature = "client")]
+impl Default for AudioSettings {
+    fn default() -> Self {
+        Self {
+            master: 1.0,
+            effects: 1.0,
+            ambience: 0.6,
+            music: 0.8,
+        }
+    }
+}
+
+/// What an item is made of, used to pick the sound it makes when hitting something.
+#[derive(
+    Component, Reflect, Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash,
+)]
+#[reflect(Component)]
+pub enum ImpactMaterial {
+    #[default]
+    Metal,
+    Plastic,
+    Soft,
+    Wood,
+    Glass,
+}
+
+/// Server message to play a sound at a position.
+/// Can also be sent as an event on the client to play sounds locally.
+#[derive(Event, Serialize, Deserialize, Clone, Copy)]
+pub struct PlaySoundMessage {
+    pub sound: SoundId,
+    pub position: Vec3,
+    /// The material of the turf the sound originated on
+    pub surface: Option<FootstepMaterial>,
+    /// The material of the object causing the sound
+    pub impact: Option<ImpactMaterial>,
+}
+
+/// Looks up the turf material at a position in any loaded tilemap.
+fn surface_at<'a>(
+    maps: impl IntoIterator<Item = &'a TileMap>,
+    position: Vec3,
+) -> Option<FootstepMaterial> {
+    maps.into_iter()
+        .find_map(|map| map.footstep_material_at(position))
+}
+
+/// Distance at which sounds can no longer be heard.
+const HEARING_DISTANCE: f32 = 15.0;
+/// Sounds fully blocked by walls are not sent to players further away than this.
+/// Closer players still get them, muffled by their client.
+const SUPPRESS_DISTANCE: f32 = 8.0;
+
+/// Finds the players that can hear a sound, so nobody is sent sounds they can't hear.
+#[derive(SystemParam)]
+struct Hearing<'w, 's> {
+    players: Res<'w, Players>,
+    controls: Res<'w, ClientControls>,
+    transforms: Query<'w, 's, &'static GlobalTransform>,
+    maps: Query<'w, 's, &'static TileMap>,
+    airtight: Query<'w, 's, (), With<Airtight>>,
+    doors: Query<'w, 's, &'static DoorState>,
+    frame: Res<'w, FrameCount>,
+    cache: Local<'s, OcclusionCache>,
+}
+
+impl<'w, 's> Hearing<'w, 's> {
+    /// Players in hearing range of the position, or none if nobody can hear it.
+    fn receivers(&mut self, position: Vec3) -> Option<MessageReceivers> {
+        let mut receivers = HashSet::default();
+        for (&connection, player) in self.players.players() {
+            let Some(listener) = self
+                .controls
+                .controlled_entity(player.id)
+                .and_then(|e| self.transforms.get(e).ok())
+                .map(|t| t.translation())
+            else {
+                continue;
+            };
+
+            let distance = listener.distance(position);
+            if distance > HEARING_DISTANCE {
+                continue;
+            }
+            if distance > SUPPRESS_DISTANCE
+                && self.occlusion(position, listener) == Some(Occlusion::Blocked)
+            {
+                continue;
+            }
+            receivers.insert(connection);
+        }
+
+        (!receivers.is_empty()).then_some(MessageReceivers::Set(receivers))
+    }
+
+    fn occlusion(&mut self, source: Vec3, listener: Vec3) -> Option<Occlusion> {
+        // TODO: Support multiple maps
+        let map = self.maps.get_single().ok()?;
+        Some(self.cache.get(
+            self.frame.0,
+            maps::world_to_tile(source)?,
+            maps::world_to_tile(listener)?,
+            |p| map.tile(p),
+            &self.airtight,
+            &self.doors,
+        ))
+    }
+}
+
+/// How far a creature needs to move to make a footstep sound.
+const FOOTSTEP_DISTANCE: f32 = 1.2;
+
+fn emit_footsteps(
+    movers: Query<
+        (Entity, &GlobalTransform, Option<&Weightless>),
+        (With<Body>, With<ClientMovement>),
+    >,
+    maps: Query<&TileMap>,
+    coatings: Res<TileCoatings>,
+    mut travelled: Local<HashMap<Entity, (Vec2, f32)>>,
+    mut hearing: Hearing,
+    mut sender: MessageSender,
+) {
+    // Forget entities that stopped moving on their own
+    travelled.retain(|entity, _| movers.contains(*entity));
+
+    for (entity, transform, weightless) in movers.iter() {
+        // Floating creatures don't touch the floor
+        if weightless.is_some_and(|w| w.is_floating()) {
+            travelled.remove(&entity);
+            continue;
+        }
+
+        let position = transform.translation();
+        let (last_position, distance) = travelled.entry(entity).or_insert((position.xz(), 0.0));
+        *distance += position.xz().distance(*last_position);
+        *last_position = position.xz();
+
+        if *distance < FOOTSTEP_DISTANCE {
+            continue;
+        }
+        *distance = 0.0;
+
+        let Some(receivers) = hearing.receivers(position) else {
+            continue;
+        };
+        let wet = maps::world_to_tile(position)
+            .and_then(|tile| coatings.kind_at(tile))
+            .is_some();
+        sender.send(
+            &PlaySoundMessage {
+                sound: if wet {
+                    SoundId::WetFootstep
+                } else {
+                    SoundId::Footstep
+                },
+                position,
+                surface: surface_at(&maps, position),
+                impact: None,
+            },
+            receivers,
+        );
+    }
+}
+
+fn emit_item_drop_impacts(
+    mut removed: RemovedComponents<StoredItem>,
+    items: Query<(&GlobalTransform, Option<&ImpactMaterial>), (With<Item>, Without<StoredItem>)>,
+    maps: Query<&TileMap>,
+    mut hearing: Hearing,
+    mut sender: MessageSender,
+) {
+    for entity in removed.iter() {
+        // Item was deleted or moved into another container
+        let Ok((transform, material)) = items.get(entity) else {
+            continue;
+        };
+
+        let position = transform.translation();
+        let Some(receivers) = hearing.receivers(position) else {
+            continue;
+        };
+        sender.send(
+            &PlaySoundMessage {
+                sound: SoundId::ItemImpact,
+                position,
+                surface: surface_at(&maps, position),
+                impact: Some(material.copied().unwrap_or_default()),
+            },
+            receivers,
+        );
+    }
+}
+
+#[cfg(feature = "client")]
+mod client {
+    use bevy::{audio::VolumeLevel, core::FrameCount, prelude::*, utils::HashMap};
+    use maps::{FootstepMaterial, TileMapClient};
+    use networking::messaging::MessageEvent;
+
+    use crate::{
+        camera::{MainCamera, TopDownCamera},
+        door::DoorStateClient,
+        temperature::Airtight,
+    };
+
+    use super::{
+        muffling::OcclusionCache, AudioSettings, ImpactMaterial, PlaySoundMessage, SoundId,
+        TrackId, HEARING_DISTANCE,
+    };
+
+    type SoundKey = (SoundId, Option<ImpactMaterial>, Option<FootstepMaterial>);
+
+    /// Maps sound events to concrete audio assets.
+    #[derive(Resource, Default)]
+    pub(super) struct SoundRegistry {
+        sounds: HashMap<SoundKey, Handle<AudioSource>>,
+        tracks: HashMap<TrackId, Handle<AudioSource>>,
+        /// Played for sounds without any mapping
+        fallback: Handle<AudioSource>,
+    }
+
+    impl SoundRegistry {
+        fn register(&mut self, server: &AssetServer, key: SoundKey, path: &'static str) {
+            self.sounds.insert(key, server.load(path));
+        }
+
+        fn register_track(&mut self, server: &AssetServer, track_id: TrackId, path: &'static str) {
+            self.tracks.insert(track_id, server.load(path));
+        }
+
+        pub(in crate::sound) fn track(&self, track_id: TrackId) -> Option<&Handle<AudioSource>> {
+            self.tracks.get(&track_id)
+        }
+
+        /// Finds the most specific sound for the given materials.
+        /// Falls back to less specific sounds and then the default sound,
+        /// so a missing mapping never results in silence.
+        fn get(
+            &self,
+            sound: SoundId,
+            impact: Option<ImpactMaterial>,
+            surface: Option<FootstepMaterial>,
+        ) -> &Handle<AudioSource> {
+            self.sounds
+                .get(&(sound, impact, surface))
+                .or_else(|| self.sounds.get(&(sound, impact, None)))
+                .or_else(|| self.sounds.get(&(sound, None, surface)))
+                .or_else(|| self.sounds.get(&(sound, None, None)))
+                .unwrap_or(&self.fallback)
+        }
+    }
+
+    pub(super) fn load_sound_registry(mut commands: Commands, server: Res<AssetServer>) {
+        let mut registry = SoundRegistry {
+            fallback: server.load("sounds/default.ogg"),
+            ..Default::default()
+        };
+        let server = server.as_ref();
+
+        use FootstepMaterial as F;
+        use ImpactMaterial as I;
+        use SoundId::*;
+        registry.register(server, (Footstep, None, None), "sounds/footsteps/floor.ogg");
+        registry.register(
+            server,
+            (Footstep, None, Some(F::Metal)),
+            "sounds/footsteps/plating.ogg",
+        );
+        registry.register(
+            server,
+            (Footstep, None, Some(F::Carpet)),
+            "sounds/footsteps/carpet.ogg",
+        );
+        registry.register(
+            server,
+            (Footstep, None, Some(F::Tile)),
+            "sounds/footsteps/floor.ogg",
+        );
+        registry.register(
+            server,
+            (Footstep, None, Some(F::Wood)),
+            "sounds/footsteps/wood.ogg",
+        );
+        registry.register(
+            server,
+            (WetFootstep, None, None),
+            "sounds/footsteps/wet.ogg",
+        );
+        registry.register(
+            server,
+            (ItemImpact, None, None),
+            "sounds/impacts/generic.ogg",
+        );
+        registry.register(
+            server,
+            (ItemImpact, Some(I::Metal), None),
+            "sounds/impacts/metal.ogg",
+        );
+        registry.register(
+            server,
+            (ItemImpact, Some(I::Plastic), None),
+            "sounds/impacts/plastic.ogg",
+        );
+        registry.register(
+            server,
+            (ItemImpact, Some(I::Soft), None),
+            "sounds/impacts/soft.ogg",
+        );
+        registry.register(
+            server,
+            (ItemImpact, Some(I::Soft), Some(F::Carpet)),
+            "sounds/impacts/soft_carpet.ogg",
+        );
+        registry.register(server, (ItemPickup, None, None), "sounds/items/pickup.ogg");
+        registry.register(server, (Sparks, None, None), "sounds/effects/sparks.ogg");
+        registry.register(server, (Debris, None, None), "sounds/effects/debris.ogg");
+        registry.register(server, (Break, None, None), "sounds/effects/break.ogg");
+        registry.register(server, (Denied, None, None), "sounds/effects/denied.ogg");
+        registry.register(
+            server,
+            (Announcement, None, None),
+            "sounds/effects/announcement.ogg",
+        );
+        registry.register(server, (Gore, None, None), "sounds/effects/gore.ogg");
+        registry.register(
+            server,
+            (Break, Some(I::Metal), None),
+            "sounds/effects/break_metal.ogg",
+        );
+        registry.register(
+            server,
+            (Break, Some(I::Wood), None),
+            "sounds/effects/break_wood.ogg",
+        );
+        registry.register(
+            server,
+            (Break, Some(I::Glass), None),
+            "sounds/effects/break_glass.ogg",
+        );
+
+        use TrackId as T;
+        registry.register_track(server, T::StationAmbience, "sounds/ambience/station.ogg");
+        registry.register_track(
+            server,
+            T::EngineeringAmbience,
+            "sounds/ambience/engineering.ogg",
+        );
+        registry.register_track(server, T::SpaceAmbience, "sounds/ambience/space.ogg");
+        registry.register_track(server, T::RoundStart, "sounds/music/round_start.ogg");
+        registry.register_track(server, T::RoundEnd, "sounds/music/round_end.ogg");
+
+        commands.insert_resource(registry);
+    }
+
+    /// How much the pitch of a sound may randomly vary.
+    const PITCH_VARIATION: f32 = 0.1;
+
+    #[allow(clippy::too_many_arguments)]
+    pub(super) fn play_received_sounds(
+        mut messages: EventReader<MessageEvent<PlaySoundMessage>>,
+        mut local: EventReader<PlaySoundMessage>,
+        registry: Res<SoundRegistry>,
+        settings: Res<AudioSettings>,
+        listener: Query<(&GlobalTransform, &TopDownCamera), With<MainCamera>>,
+        transforms: Query<&GlobalTransform>,
+        maps: Query<&TileMapClient>,
+        airtight: Query<(), With<Airtight>>,
+        doors: Query<&DoorStateClient>,
+        frame: Res<FrameCount>,
+        mut cache: Local<OcclusionCache>,
+        mut commands: Commands,
+    ) {
+        let camera = listener.get_single().ok();
+        let listener_position = camera.map(|(t, _)| t.translation());
+        // Walls are checked from the creature, the camera floats above them
+        let listener_tile = camera
+            .and_then(|(_, c)| transforms.get(c.target).ok())
+            .and_then(|t| maps::world_to_tile(t.translation()));
+        let map = maps.get_single().ok();
+        let base_volume = settings.master * settings.effects;
+
+        for message in messages.iter().map(|e| &e.message).chain(local.iter()) {
+            let source = registry.get(message.sound, message.impact, message.surface);
+
+            // Quieter the further away the sound is
+            let volume = listener_position
+                .map(|p| 1.0 - (p.distance(message.position) / HEARING_DISTANCE).min(1.0))
+                .unwrap_or(1.0)
+                * base_volume;
+            let muffling = match (map, listener_tile, maps::world_to_tile(message.position)) {
+                (Some(map), Some(listener_tile), Some(source_tile)) => cache
+                    .get(
+                        frame.0,
+                        source_tile,
+                        listener_tile,
+                        |p| map.tile(p),
+                        &airtight,
+                        &doors,
+                    )
+                    .volume(),
+                _ => 1.0,
+            };
+            let volume = volume * muffling;
+            if volume <= 0.0 {
+                continue;
+            }
+
+            let pitch = 1.0 + (fastrand::f32() * 2.0 - 1.0) * PITCH_VARIATION;
+            commands.spawn(AudioBundle {
+                source: source.clone(),
+                settings: PlaybackSettings::DESPAWN
+                    .with_speed(pitch)
+                    .with_volume(bevy::audio::Volume::Relative(VolumeLevel::new(volume))),
+            });
+        }
+    }
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use maps::TileReference;
+    use networking::{
+        loopback::LinkConditions,
+        messaging::{AppExt, MessageEvent},
+        testing, NetworkRole,
+    };
+
+    use super::*;
+    use crate::{config::ServerConfig, testing::server_app};
+
+    /// Where the player stands, west of a wall along x = 6
+    const LISTENER: Vec3 = Vec3::new(2.0, 0.0, 2.0);
+    const WALL_X: u32 = 6;
+
+    #[derive(Resource, Default)]
+    struct Heard(Vec<Vec3>);
+
+    fn record_sounds(
+        mut messages: EventReader<MessageEvent<PlaySoundMessage>>,
+        mut heard: ResMut<Heard>,
+    ) {
+        heard.0.extend(messages.iter().map(|e| e.message.position));
+    }
+
+    /// Positions of the footsteps the client got from a creature walking north from `start`.
+    fn footsteps_heard(start: Vec3) -> Vec<Vec3> {
+        let mut server = server_app(ServerConfig::default());
+        let mut client = testing::app(NetworkRole::Client);
+        client
+            .add_network_message::<PlaySoundMessage>("PlaySoundMessage")
+            .init_resource::<Heard>()
+            .add_systems(Update, record_sounds);
+        let connector = testing::listen(&mut server);
+        testing::join(&mut client, &connector, LinkConditions::default());
+        testing::connect(&mut server, &mut [&mut client], 200);
+
+        let mut map = TileMap::new(UVec2::ONE);
+        for y in 0..maps::CHUNK_SIZE {
+            let wall = server.world.spawn(Airtight).id();
+            let tile = TileReference {
+                turf: Some(wall),
+                ..Default::default()
+            };
+            map.set_tile(UVec2::new(WALL_X, y), tile).unwrap();
+        }
+        server.world.spawn(map);
+
+        let player = server
+            .world
+            .resource::<Players>()
+            .players()
+            .values()
+            .next()
+            .unwrap()
+            .id;
+        let body = server
+            .world
+            .spawn(TransformBundle::from_transform(
+                Transform::from_translation(LISTENER),
+            ))
+            .id();
+        server
+            .world
+            .resource_mut::<ClientControls>()
+            .give_control(player, body);
+        let walker = server
+            .world
+            .spawn((
+                Body::default(),
+                ClientMovement,
+                TransformBundle::from_transform(Transform::from_translation(start)),
+            ))
+            .id();
+        testing::update(&mut server, &mut [&mut client], 2);
+
+        for _ in 0..4 {
+            server
+                .world
+                .get_mut::<Transform>(walker)
+                .unwrap()
+                .translation
+                .z += 0.7;
+            testing::update(&mut server, &mut [&mut client], 1);
+        }
+        testing::update(&mut server, &mut [&mut client], 5);
+        std::mem::take(&mut client.world.resource_mut::<Heard>().0)
+    }
+
+    #[test]
+    fn walled_off_sounds_beyond_the_cutoff_are_not_sent() {
+        let start = Vec3::new(12.0, 0.0, 2.0);
+        assert!(start.distance(LISTENER) > SUPPRESS_DISTANCE);
+
+        assert!(footsteps_heard(start).is_empty());
+    }
+
+    #[test]
+    fn walled_off_sounds_close_by_are_sent_to_be_muffled() {
+        let start = Vec3::new(8.0, 0.0, 2.0);
+        assert!(start.distance(LISTENER) < SUPPRESS_DISTANCE);
+
+        let heard = footsteps_heard(start);
+        assert!(!heard.is_empty());
+        assert!(heard.iter().all(|p| p.x == start.x));
+    }
+
+    #[test]
+    fn distant_sounds_without_a_wall_in_the_way_are_sent() {
+        // Same distance as the walled off one, but on this side of the wall
+        let start = Vec3::new(2.0, 0.0, 12.0);
+        assert!(start.distance(LISTENER) > SUPPRESS_DISTANCE);
+
+        assert!(!footsteps_heard(start).is_empty());
+    }
+}
@@ -0,0 +1,372 @@
+use std::{fmt, str::FromStr};
+
+use bevy::{prelude::*, utils::HashMap};
+use maps::TileMap;
+use networking::{
+    is_server,
+    messaging::{AppExt, MessageReceivers, MessageSender},
+    spawning::ClientControls,
+    ConnectionId, Players,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    console::{
+        ArgumentKind, CommandContext, CommandResult, ConsoleAppExt, ConsoleCommand, PermissionLevel,
+    },
+    round::RoundState,
+};
+
+/// Loops ambient sounds depending on where the player is and plays music the server asks for.
+pub(super) struct AmbiencePlugin;
+
+impl Plugin for AmbiencePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_network_message::<AmbienceMessage>("AmbienceMessage")
+            .add_network_message::<PlayMusic>("PlayMusic");
+
+        if is_server(app) {
+            app.add_event::<PlayMusicEvent>()
+                .add_console_command(ConsoleCommand {
+                    name: "music",
+                    description: "Plays a track for everyone",
+                    parameters: &[("track", ArgumentKind::Text)],
+                    permission: PermissionLevel::Admin,
+                    handler: music_command,
+                })
+                .add_systems(OnEnter(RoundState::Running), round_start_music)
+                .add_systems(OnEnter(RoundState::Ended), round_end_music)
+                .add_systems(Update, (update_player_ambience, send_music));
+        } else {
+            #[cfg(feature = "client")]
+            app.init_resource::<client::AmbiencePlayer>()
+                .add_systems(Update, client::update_ambience);
+        }
+    }
+}
+
+/// Identifies a longer piece of audio. The client decides which asset is actually played.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum TrackId {
+    StationAmbience,
+    EngineeringAmbience,
+    SpaceAmbience,
+    RoundStart,
+    RoundEnd,
+}
+
+impl TrackId {
+    const ALL: [TrackId; 5] = [
+        TrackId::StationAmbience,
+        TrackId::EngineeringAmbience,
+        TrackId::SpaceAmbience,
+        TrackId::RoundStart,
+        TrackId::RoundEnd,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            TrackId::StationAmbience => "station",
+            TrackId::EngineeringAmbience => "engineering",
+            TrackId::SpaceAmbience => "space",
+            TrackId::RoundStart => "round_start",
+            TrackId::RoundEnd => "round_end",
+        }
+    }
+
+    /// The ambience that plays in an area
+    fn for_area(area: Option<&str>) -> TrackId {
+        match area {
+            None => TrackId::SpaceAmbience,
+            Some(area) if area.starts_with("/area/space") => TrackId::SpaceAmbience,
+            Some(area) if area.starts_with("/area/engine") => TrackId::EngineeringAmbience,
+            Some(_) => TrackId::StationAmbience,
+        }
+    }
+}
+
+impl fmt::Display for TrackId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for TrackId {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        TrackId::ALL
+            .into_iter()
+            .find(|track| track.name() == s)
+            .ok_or_else(|| format!("unknown track {}", s))
+    }
+}
+
+/// Server message telling a player which ambience to loop.
+#[derive(Serialize, Deserialize)]
+struct AmbienceMessage {
+    track_id: TrackId,
+}
+
+/// Server message to start a piece of music, replacing the current one.
+#[derive(Serialize, Deserialize)]
+pub struct PlayMusic {
+    pub track_id: TrackId,
+    /// Seconds until the music is at full volume
+    pub fade_in: f32,
+}
+
+/// Plays music for one player, or everyone if there is no receiver.
+#[derive(Event)]
+pub struct PlayMusicEvent {
+    pub track_id: TrackId,
+    pub fade_in: f32,
+    pub receiver: Option<ConnectionId>,
+}
+
+fn send_music(mut events: EventReader<PlayMusicEvent>, mut sender: MessageSender) {
+    for event in events.iter() {
+        info!(track = %event.track_id, receiver = ?event.receiver, "Playing music");
+        sender.send(
+            &PlayMusic {
+                track_id: event.track_id,
+                fade_in: event.fade_in,
+            },
+            match event.receiver {
+                Some(connection) => MessageReceivers::Single(connection),
+                None => MessageReceivers::AllPlayers,
+            },
+        );
+    }
+}
+
+fn music_command(world: &mut World, context: &CommandContext) -> CommandResult {
+    let track_id: TrackId = context.text(0).trim().parse()?;
+    world
+        .resource_mut::<Events<PlayMusicEvent>>()
+        .send(PlayMusicEvent {
+            track_id,
+            fade_in: 1.0,
+            receiver: None,
+        });
+    Ok(format!("Playing {}", track_id))
+}
+
+fn round_start_music(mut music: EventWriter<PlayMusicEvent>) {
+    music.send(PlayMusicEvent {
+        track_id: TrackId::RoundStart,
+        fade_in: 0.0,
+        receiver: None,
+    });
+}
+
+fn round_end_music(mut music: EventWriter<PlayMusicEvent>) {
+    music.send(PlayMusicEvent {
+        track_id: TrackId::RoundEnd,
+        fade_in: 2.0,
+        receiver: None,
+    });
+}
+
+/// Tells players about the ambience of the area they're in when it changes.
+fn update_player_ambience(
+    players: Res<Players>,
+    controls: Res<ClientControls>,
+    transforms: Query<&GlobalTransform>,
+    maps: Query<&TileMap>,
+    mut current: Local<HashMap<ConnectionId, TrackId>>,
+    mut sender: MessageSender,
+) {
+    current.retain(|connection, _| players.get(*connection).is_some());
+
+    for (&connection, player) in players.players().iter() {
+        let Some(position) = controls
+            .controlled_entity(player.id)
+            .and_then(|entity| transforms.get(entity).ok())
+            .map(|transform| transform.translation())
+        else {
+            continue;
+        };
+
+        let area = maps.iter().find_map(|map| map.area_at(position));
+        let track_id = TrackId::for_area(area);
+        if current.insert(connection, track_id) == Some(track_id) {
+            continue;
+        }
+
+        sender.send(
+            &AmbienceMessage { track_id },
+            MessageReceivers::Single(connection),
+        );
+    }
+}
+
+#[cfg(feature = "client")]
+mod client {
+    use bevy::{
+        audio::{PlaybackMode, VolumeLevel},
+        prelude::*,
+    };
+    use networking::messaging::MessageEvent;
+
+    use crate::sound::{client::SoundRegistry, AudioSettings};
+
+    use super::{AmbienceMessage, PlayMusic, TrackId};
+
+    /// Seconds it takes for one ambience to replace another
+    const CROSSFADE_SECONDS: f32 = 2.0;
+
+    /// A track that is playing at some volume.
+    struct Channel {
+        track_id: TrackId,
+        entity: Entity,
+        /// From 0 to 1, before applying the volume settings
+        level: f32,
+    }
+
+    /// Fades between tracks. At most two tracks play at once, however often the target changes.
+    #[derive(Default)]
+    struct Fader {
+        incoming: Option<Channel>,
+        outgoing: Option<Channel>,
+        fade_seconds: f32,
+    }
+
+    impl Fader {
+        fn play(
+            &mut self,
+            track_id: TrackId,
+            fade_seconds: f32,
+            settings: PlaybackSettings,
+            registry: &SoundRegistry,
+            commands: &mut Commands,
+        ) {
+            let looping = matches!(settings.mode, PlaybackMode::Loop);
+            if looping
+                && self
+                    .incoming
+                    .as_ref()
+                    .is_some_and(|c| c.track_id == track_id)
+            {
+                return;
+            }
+            self.fade_seconds = fade_seconds;
+
+            // Going back to the track that is fading out continues from its current volume
+            if looping
+                && self
+                    .outgoing
+                    .as_ref()
+                    .is_some_and(|c| c.track_id == track_id)
+            {
+                std::mem::swap(&mut self.incoming, &mut self.outgoing);
+                return;
+            }
+
+            if let Some(outgoing) = self.outgoing.take() {
+                commands.entity(outgoing.entity).despawn();
+            }
+            self.outgoing = self.incoming.take();
+
+            let Some(source) = registry.track(track_id) else {
+                warn!(track = %track_id, "No track registered");
+                return;
+            };
+            let entity = commands
+                .spawn(AudioBundle {
+                    source: source.clone(),
+                    settings: settings
+                        .with_volume(bevy::audio::Volume::Relative(VolumeLevel::new(0.0))),
+                })
+                .id();
+            self.incoming = Some(Channel {
+                track_id,
+                entity,
+                level: 0.0,
+            });
+        }
+
+        fn update(
+            &mut self,
+            delta: f32,
+            volume: f32,
+            sinks: &Query<&AudioSink>,
+            commands: &mut Commands,
+        ) {
+            let step = if self.fade_seconds > 0.0 {
+                delta / self.fade_seconds
+            } else {
+                1.0
+            };
+
+            if let Some(incoming) = self.incoming.as_mut() {
+                incoming.level = (incoming.level + step).min(1.0);
+                if let Ok(sink) = sinks.get(incoming.entity) {
+                    sink.set_volume(incoming.level * volume);
+                }
+            }
+
+            if let Some(outgoing) = self.outgoing.as_mut() {
+                outgoing.level -= step;
+                if outgoing.level <= 0.0 {
+                    commands.entity(outgoing.entity).despawn();
+                    self.outgoing = None;
+                } else if let Ok(sink) = sinks.get(outgoing.entity) {
+                    sink.set_volume(outgoing.level * volume);
+                }
+            }
+        }
+    }
+
+    #[derive(Resource, Default)]
+    pub(super) struct AmbiencePlayer {
+        ambience: Fader,
+        music: Fader,
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub(super) fn update_ambience(
+        mut ambience_messages: EventReader<MessageEvent<AmbienceMessage>>,
+        mut music_messages: EventReader<MessageEvent<PlayMusic>>,
+        mut player: ResMut<AmbiencePlayer>,
+        registry: Res<SoundRegistry>,
+        settings: Res<AudioSettings>,
+        sinks: Query<&AudioSink>,
+        time: Res<Time>,
+        mut commands: Commands,
+    ) {
+        let player = &mut *player;
+        for event in ambience_messages.iter() {
+            player.ambience.play(
+                event.message.track_id,
+                CROSSFADE_SECONDS,
+                PlaybackSettings::LOOP,
+                &registry,
+                &mut commands,
+            );
+        }
+        for event in music_messages.iter() {
+            player.music.play(
+                event.message.track_id,
+                event.message.fade_in,
+                PlaybackSettings::ONCE,
+                &registry,
+                &mut commands,
+            );
+        }
+
+        let delta = time.delta_seconds();
+        player.ambience.update(
+            delta,
+            settings.master * settings.ambience,
+            &sinks,
+            &mut commands,
+        );
+        player.music.update(
+            delta,
+            settings.master * settings.music,
+            &sinks,
+            &mut commands,
+        );
+    }
+}
@@ -0,0 +1,176 @@
+use bevy::{prelude::*, utils::HashMap};
+use maps::TileReference;
+
+use crate::{door::DoorOpen, temperature::Airtight};
+
+/// Tiles around the path in which an open door lets sound through
+const DOOR_RADIUS: i32 = 2;
+
+/// How much the walls between a sound and a listener dampen it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Occlusion {
+    Clear,
+    /// Blocked, but an open door close to the path lets some of the sound through
+    Muffled,
+    Blocked,
+}
+
+impl Occlusion {
+    /// Volume multiplier for the sound.
+    /// The audio backend has no low-pass filter, so muffling can only make sounds quieter.
+    pub(crate) fn volume(self) -> f32 {
+        match self {
+            Occlusion::Clear => 1.0,
+            Occlusion::Muffled => 0.6,
+            Occlusion::Blocked => 0.25,
+        }
+    }
+}
+
+/// Remembers occlusion between tiles for the current frame,
+/// so many sounds from the same spot only walk the tiles once.
+#[derive(Default)]
+pub(crate) struct OcclusionCache {
+    frame: u32,
+    results: HashMap<(UVec2, UVec2), Occlusion>,
+}
+
+impl OcclusionCache {
+    /// Returns the cached occlusion between two tiles, or finds it with the given tile lookup.
+    pub(crate) fn get<'a, D: DoorOpen>(
+        &mut self,
+        frame: u32,
+        source: UVec2,
+        listener: UVec2,
+        tile: impl Fn(UVec2) -> Option<&'a TileReference>,
+        airtight: &Query<(), With<Airtight>>,
+        doors: &Query<&D>,
+    ) -> Occlusion {
+        if self.frame != frame {
+            self.frame = frame;
+            self.results.clear();
+        }
+        *self.results.entry((source, listener)).or_insert_with(|| {
+            occlusion(
+                source,
+                listener,
+                |p| blocks_sound(tile(p), airtight, doors),
+                |p| is_open_door(tile(p), doors),
+            )
+        })
+    }
+}
+
+/// Walls, windows and closed doors stop sound. Open doors let it through.
+fn blocks_sound<D: DoorOpen>(
+    tile: Option<&TileReference>,
+    airtight: &Query<(), With<Airtight>>,
+    doors: &Query<&D>,
+) -> bool {
+    tile.is_some_and(|tile| {
+        tile.turf
+            .into_iter()
+            .chain(tile.furniture)
+            .any(|e| airtight.contains(e) || doors.get(e).is_ok_and(|door| !door.open()))
+    })
+}
+
+fn is_open_door<D: DoorOpen>(tile: Option<&TileReference>, doors: &Query<&D>) -> bool {
+    tile.is_some_and(|tile| {
+        tile.turf
+            .into_iter()
+            .chain(tile.furniture)
+            .any(|e| doors.get(e).is_ok_and(|door| door.open()))
+    })
+}
+
+/// Tiles on the line between two tile centers, without the ends.
+fn tiles_between(from: UVec2, to: UVec2) -> impl Iterator<Item = UVec2> {
+    const STEPS_PER_TILE: i32 = 4;
+
+    let start = from.as_ivec2();
+    let end = to.as_ivec2();
+    let offset = end - start;
+    let steps = offset.abs().max_element() * STEPS_PER_TILE;
+    (1..steps)
+        .map(move |step| {
+            let point = start.as_vec2() + offset.as_vec2() * (step as f32 / steps as f32);
+            point.round().as_ivec2()
+        })
+        .filter(move |&tile| tile != start && tile != end && tile.min_element() >= 0)
+        .map(|tile| tile.as_uvec2())
+}
+
+fn occlusion(
+    source: UVec2,
+    listener: UVec2,
+    blocks: impl Fn(UVec2) -> bool,
+    open_door: impl Fn(UVec2) -> bool,
+) -> Occlusion {
+    if !tiles_between(source, listener).any(&blocks) {
+        return Occlusion::Clear;
+    }
+
+    // Sound leaks around an open door next to the wall in the way
+    let door_nearby = tiles_between(source, listener)
+        .chain([source, listener])
+        .any(|tile| {
+            (-DOOR_RADIUS..=DOOR_RADIUS).any(|y| {
+                (-DOOR_RADIUS..=DOOR_RADIUS).any(|x| {
+                    let neighbour = tile.as_ivec2() + IVec2::new(x, y);
+                    neighbour.min_element() >= 0 && open_door(neighbour.as_uvec2())
+                })
+            })
+        });
+    if door_nearby {
+        Occlusion::Muffled
+    } else {
+        Occlusion::Blocked
+    }
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use super::*;
+
+    const LISTENER: UVec2 = UVec2::new(2, 2);
+    const SOURCE: UVec2 = UVec2::new(8, 2);
+
+    fn wall(tile: UVec2) -> bool {
+        tile.x == 5
+    }
+
+    #[test]
+    fn path_skips_the_ends() {
+        let mut path: Vec<_> = tiles_between(UVec2::new(0, 0), UVec2::new(3, 0)).collect();
+        // Tiles are sampled several times each
+        path.dedup();
+        assert_eq!(path, [UVec2::new(1, 0), UVec2::new(2, 0)]);
+        assert_eq!(tiles_between(LISTENER, LISTENER).count(), 0);
+    }
+
+    #[test]
+    fn sound_behind_a_wall_is_much_quieter() {
+        let occluded = occlusion(SOURCE, LISTENER, wall, |_| false);
+        assert_eq!(occluded, Occlusion::Blocked);
+        assert_eq!(occluded.volume(), 0.25);
+
+        // Nothing in the way
+        let clear = occlusion(UVec2::new(2, 8), LISTENER, wall, |_| false);
+        assert_eq!(clear, Occlusion::Clear);
+        assert_eq!(clear.volume(), 1.0);
+    }
+
+    #[test]
+    fn open_door_near_the_path_lets_some_sound_through() {
+        let door = |tile: UVec2| tile == UVec2::new(5, 4);
+        let occluded = occlusion(SOURCE, LISTENER, wall, door);
+        assert_eq!(occluded, Occlusion::Muffled);
+        assert!(occluded.volume() > Occlusion::Blocked.volume());
+        assert!(occluded.volume() < Occlusion::Clear.volume());
+
+        // Too far from the path to matter
+        let door = |tile: UVec2| tile == UVec2::new(5, 5 + DOOR_RADIUS as u32);
+        assert_eq!(occlusion(SOURCE, LISTENER, wall, door), Occlusion::Blocked);
+    }
+}
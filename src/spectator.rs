@@ -0,0 +1,332 @@
+use std::time::Duration;
+
+use bevy::{prelude::*, time::common_conditions::on_timer, utils::Uuid};
+use maps::TileMap;
+use networking::{
+    identity::NetworkIdentity,
+    is_server,
+    messaging::{AppExt, MessageEvent, MessageReceivers, MessageSender},
+    spawning::ClientControls,
+    visibility::{NetworkVisibilities, Relevancy, RelevancyTarget},
+    ConnectionId, Players,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    body::health::{VitalStatus, Vitals},
+    config::ServerConfig,
+};
+
+#[cfg(feature = "client")]
+use {
+    crate::{camera::SpectatorCamera, ui::has_window, GameState},
+    bevy_egui::{egui, EguiContexts},
+    networking::identity::NetworkIdentities,
+};
+
+/// Lets ghosts and observers watch other players.
+pub struct SpectatorPlugin;
+
+impl Plugin for SpectatorPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Spectator>()
+            .add_network_message::<SpectatorTargets>("SpectatorTargets")
+            .add_network_message::<SpectateRequest>("SpectateRequest");
+
+        if is_server(app) {
+            app.add_systems(
+                Update,
+                (
+                    send_spectator_targets
+                        .run_if(on_timer(Duration::from_secs_f32(TARGETS_INTERVAL))),
+                    (handle_spectate_requests, update_spectator_sessions).chain(),
+                ),
+            );
+        } else {
+            #[cfg(feature = "client")]
+            app.init_resource::<ClientSpectatorTargets>().add_systems(
+                Update,
+                (
+                    receive_spectator_targets,
+                    spectator_overlay.run_if(has_window),
+                )
+                    .chain()
+                    .run_if(in_state(GameState::Game)),
+            );
+        }
+    }
+}
+
+/// Seconds between updates of the players a spectator can follow
+const TARGETS_INTERVAL: f32 = 1.0;
+
+/// Creatures with this component can watch other players instead of only their own surroundings.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct Spectator;
+
+/// A player a spectator can follow.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SpectatorTarget {
+    pub player: Uuid,
+    pub name: String,
+    pub entity: NetworkIdentity,
+    pub status: Option<VitalStatus>,
+    pub area: Option<String>,
+}
+
+/// Server message listing the players a spectator can follow, sorted by name.
+#[derive(Serialize, Deserialize)]
+struct SpectatorTargets(Vec<SpectatorTarget>);
+
+/// Client message to follow a player, or stop following with `None`.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct SpectateRequest {
+    pub target: Option<Uuid>,
+}
+
+/// An admin spectator following a player. Owns the relevancy override for the surroundings.
+#[derive(Component)]
+struct SpectatorSession {
+    viewer: ConnectionId,
+    target: Uuid,
+}
+
+fn is_admin(config: &ServerConfig, players: &Players, connection: ConnectionId) -> bool {
+    players
+        .get(connection)
+        .is_some_and(|player| config.admins.contains(&player.id))
+}
+
+/// If the connection controls a spectator creature
+fn spectating_connection(
+    connection: ConnectionId,
+    players: &Players,
+    controls: &ClientControls,
+    spectators: &Query<(), With<Spectator>>,
+) -> bool {
+    players
+        .get(connection)
+        .and_then(|player| controls.controlled_entity(player.id))
+        .is_some_and(|entity| spectators.contains(entity))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn send_spectator_targets(
+    players: Res<Players>,
+    controls: Res<ClientControls>,
+    spectators: Query<(), With<Spectator>>,
+    creatures: Query<(&GlobalTransform, &NetworkIdentity)>,
+    visibilities: Res<NetworkVisibilities>,
+    vitals: Vitals,
+    maps: Query<&TileMap>,
+    config: Res<ServerConfig>,
+    mut sender: MessageSender,
+) {
+    // Everyone alive in the round, spectators can't follow each other
+    let mut targets: Vec<_> = players
+        .players()
+        .values()
+        .filter_map(|player| {
+            let entity = controls.controlled_entity(player.id)?;
+            if spectators.contains(entity) {
+                return None;
+            }
+            let (transform, &identity) = creatures.get(entity).ok()?;
+            let position = transform.translation();
+            Some(SpectatorTarget {
+                player: player.id,
+                name: player.username.clone(),
+                entity: identity,
+                status: vitals.status(entity),
+                area: maps
+                    .iter()
+                    .find_map(|map| map.area_at(position))
+                    .map(str::to_owned),
+            })
+        })
+        .collect();
+    targets.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+
+    for &connection in players.players().keys() {
+        if !spectating_connection(connection, &players, &controls, &spectators) {
+            continue;
+        }
+
+        // Observers only get players that are streamed to them anyway
+        let visible: Vec<_> = if is_admin(&config, &players, connection) {
+            targets.clone()
+        } else {
+            targets
+                .iter()
+                .filter(|target| {
+                    visibilities
+                        .get(target.entity)
+                        .is_some_and(|v| v.has_observer(&connection))
+                })
+                .cloned()
+                .collect()
+        };
+        sender.send(
+            &SpectatorTargets(visible),
+            MessageReceivers::Single(connection),
+        );
+    }
+}
+
+fn handle_spectate_requests(
+    mut messages: EventReader<MessageEvent<SpectateRequest>>,
+    sessions: Query<(Entity, &SpectatorSession)>,
+    players: Res<Players>,
+    controls: Res<ClientControls>,
+    spectators: Query<(), With<Spectator>>,
+    config: Res<ServerConfig>,
+    mut commands: Commands,
+) {
+    for event in messages.iter() {
+        for (entity, session) in sessions.iter() {
+            if session.viewer == event.connection {
+                commands.entity(entity).despawn();
+            }
+        }
+
+        let Some(target) = event.message.target else {
+            continue;
+        };
+        // Only admins get the surroundings of distant players
+        if !is_admin(&config, &players, event.connection)
+            || !spectating_connection(event.connection, &players, &controls, &spectators)
+        {
+            continue;
+        }
+
+        commands.spawn(SpectatorSession {
+            viewer: event.connection,
+            target,
+        });
+        debug!(connection = ?event.connection, player = %target, "Spectator following player");
+    }
+}
+
+fn update_spectator_sessions(
+    sessions: Query<(Entity, &SpectatorSession)>,
+    transforms: Query<&GlobalTransform>,
+    spectators: Query<(), With<Spectator>>,
+    mut relevancy: ResMut<Relevancy>,
+    controls: Res<ClientControls>,
+    players: Res<Players>,
+    mut commands: Commands,
+) {
+    for (session_entity, session) in sessions.iter() {
+        // Sessions end when the viewer leaves or gets a body again
+        if !spectating_connection(session.viewer, &players, &controls, &spectators) {
+            commands.entity(session_entity).despawn();
+            continue;
+        }
+
+        let Some((followed, transform)) = controls
+            .controlled_entity(session.target)
+            .and_then(|e| Some((e, transforms.get(e).ok()?)))
+        else {
+            relevancy.remove_owner(session_entity);
+            continue;
+        };
+
+        relevancy.set_viewers(
+            session_entity,
+            RelevancyTarget::chunks_around(transform.translation())
+                .chain(std::iter::once(followed.into())),
+            session.viewer,
+        );
+    }
+}
+
+/// The players the local spectator can follow.
+#[cfg(feature = "client")]
+#[derive(Resource, Default)]
+pub(crate) struct ClientSpectatorTargets {
+    pub targets: Vec<SpectatorTarget>,
+}
+
+#[cfg(feature = "client")]
+impl ClientSpectatorTargets {
+    /// The next (or previous) target after a player that exists on this client, with its index.
+    /// If the player isn't a target anymore, the search starts at the index it last had.
+    pub fn cycle(
+        &self,
+        current: Option<Uuid>,
+        last_index: usize,
+        forward: bool,
+        identities: &NetworkIdentities,
+    ) -> Option<(usize, &SpectatorTarget)> {
+        let count = self.targets.len();
+        if count == 0 {
+            return None;
+        }
+
+        let start = match current.and_then(|id| self.targets.iter().position(|t| t.player == id)) {
+            Some(index) if forward => index + 1,
+            Some(index) => index + count - 1,
+            None => last_index,
+        };
+        (0..count)
+            .map(|offset| {
+                if forward {
+                    (start + offset) % count
+                } else {
+                    (start + count - offset) % count
+                }
+            })
+            .map(|index| (index, &self.targets[index]))
+            .find(|(_, target)| identities.get_entity(target.entity).is_some())
+    }
+
+    /// If a player can still be followed
+    pub fn is_valid(&self, player: Uuid, identities: &NetworkIdentities) -> bool {
+        self.get(player)
+            .is_some_and(|target| identities.get_entity(target.entity).is_some())
+    }
+
+    pub fn get(&self, player: Uuid) -> Option<&SpectatorTarget> {
+        self.targets.iter().find(|t| t.player == player)
+    }
+}
+
+#[cfg(feature = "client")]
+fn receive_spectator_targets(
+    mut messages: EventReader<MessageEvent<SpectatorTargets>>,
+    mut targets: ResMut<ClientSpectatorTargets>,
+) {
+    if let Some(event) = messages.iter().last() {
+        targets.targets = event.message.0.clone();
+    }
+}
+
+#[cfg(feature = "client")]
+fn spectator_overlay(
+    mut contexts: EguiContexts,
+    cameras: Query<&SpectatorCamera>,
+    targets: Res<ClientSpectatorTargets>,
+) {
+    let Some(target) = cameras
+        .get_single()
+        .ok()
+        .and_then(|camera| camera.following())
+        .and_then(|player| targets.get(player))
+    else {
+        return;
+    };
+
+    egui::Window::new("Spectating")
+        .anchor(egui::Align2::RIGHT_TOP, egui::vec2(-10.0, 10.0))
+        .resizable(false)
+        .collapsible(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.heading(&target.name);
+            if let Some(status) = target.status {
+                ui.label(status.to_string());
+            }
+            ui.label(target.area.as_deref().unwrap_or("Space"));
+            ui.small("[ ] to switch, \\ to stop");
+        });
+}
@@ -0,0 +1,601 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use bevy::{
+    prelude::*,
+    tasks::{IoTaskPool, Task},
+    time::common_conditions::on_timer,
+    utils::{HashMap, HashSet, Uuid},
+};
+use futures_lite::future;
+use networking::{
+    is_server,
+    messaging::{AppExt, MessageEvent, MessageReceivers, MessageSender},
+    spawning::ClientControls,
+    ConnectionId, Players, ServerEvent,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    body::health::{Dead, VitalStatus, Vitals},
+    console::{
+        ArgumentKind, CommandContext, CommandResult, ConsoleAppExt, ConsoleCommand, PermissionLevel,
+    },
+    job::{Affiliation, JobConfig, JobDefinition, LoadoutOption, PlaytimeRequirement},
+    round::RoundState,
+};
+
+#[cfg(feature = "client")]
+use {
+    crate::ui::has_window,
+    bevy_egui::{egui, EguiContexts},
+};
+
+/// Keeps playtime per job, rounds played, kills and deaths of every player across restarts.
+pub struct StatsPlugin;
+
+impl Plugin for StatsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_network_message::<StatsRequest>("StatsRequest")
+            .add_network_message::<StatsMessage>("StatsMessage");
+
+        if is_server(app) {
+            app.insert_resource(PlayerStats::load(Path::new(STATS_FILE)))
+                .init_resource::<StatsWriter>()
+                .add_systems(
+                    Update,
+                    (
+                        track_connections,
+                        track_job_bodies,
+                        track_playtime
+                            .run_if(in_state(RoundState::Running))
+                            .run_if(on_timer(Duration::from_secs_f32(PLAYTIME_INTERVAL))),
+                        count_deaths,
+                        handle_stats_requests,
+                        write_stats,
+                    ),
+                )
+                .add_systems(OnEnter(RoundState::Ended), flush_round_stats)
+                .add_console_command(ConsoleCommand {
+                    name: "stats",
+                    description: "Shows the saved statistics of a player",
+                    parameters: &[("player", ArgumentKind::Player)],
+                    permission: PermissionLevel::Admin,
+                    handler: stats_command,
+                });
+        } else {
+            #[cfg(feature = "client")]
+            app.init_resource::<ClientStats>().add_systems(
+                Update,
+                (receive_stats, stats_window.run_if(has_window)).chain(),
+            );
+        }
+    }
+}
+
+/// Every line of the file is one player record
+const STATS_FILE: &str = "player_stats.ron";
+/// The newest version of the record format
+const RECORD_VERSION: u32 = 1;
+/// Seconds between adding playtime
+const PLAYTIME_INTERVAL: f32 = 1.0;
+
+/// Statistics of a player saved on the server.
+///
+/// All fields must have a default value, so records saved by older versions can still be loaded.
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+struct PlayerRecord {
+    /// Format version the record was saved with
+    version: u32,
+    id: Uuid,
+    /// Last known username, to find players in the file
+    username: String,
+    /// Seconds played by job id
+    playtime: HashMap<String, f64>,
+    rounds_played: u32,
+    kills: u32,
+    deaths: u32,
+    /// Unix time the player was last seen at
+    last_seen: u64,
+}
+
+/// Statistics of the running round that aren't in the saved record yet.
+#[derive(Default)]
+struct RoundRecord {
+    playtime: HashMap<String, f64>,
+    kills: u32,
+    deaths: u32,
+    /// If the player spawned with a job this round
+    played: bool,
+}
+
+/// Saved statistics of every player, and what they did this round.
+#[derive(Resource, Default)]
+pub struct PlayerStats {
+    records: HashMap<Uuid, PlayerRecord>,
+    round: HashMap<Uuid, RoundRecord>,
+    /// Players whose round was already added to the rounds they played
+    counted: HashSet<Uuid>,
+    /// Player and job of creatures spawned with a job
+    bodies: HashMap<Entity, (Uuid, String)>,
+    /// Players by connection, as they are gone from [`Players`] once they disconnected
+    connections: HashMap<ConnectionId, (Uuid, String)>,
+    /// If the records changed since they were last written
+    dirty: bool,
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Where records that couldn't be read are moved, so they can be recovered manually
+fn quarantine_path(path: &Path) -> PathBuf {
+    let mut quarantined = path.as_os_str().to_owned();
+    quarantined.push(".broken");
+    quarantined.into()
+}
+
+fn quarantine(path: &Path, lines: &[&str]) {
+    let quarantined = quarantine_path(path);
+    let result = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&quarantined)
+        .and_then(|mut file| lines.iter().try_for_each(|line| writeln!(file, "{}", line)));
+    if let Err(err) = result {
+        error!(path = ?quarantined, error = %err, "Could not quarantine broken player stats");
+    }
+}
+
+impl PlayerStats {
+    /// Reads the stats file. Broken records are skipped and moved out of the way,
+    /// so one bad line doesn't lose the statistics of every player.
+    fn load(path: &Path) -> Self {
+        let mut stats = Self::default();
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            // No stats were saved yet
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return stats,
+            Err(err) => {
+                // Moved away so it isn't overwritten with empty stats
+                error!(path = ?path, error = %err, "Could not read player stats");
+                let _ = fs::rename(path, quarantine_path(path));
+                return stats;
+            }
+        };
+
+        let text = String::from_utf8_lossy(&bytes);
+        let mut broken = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            match ron::from_str::<PlayerRecord>(line) {
+                Ok(record) if record.version > RECORD_VERSION => {
+                    warn!(
+                        line = index + 1,
+                        version = record.version,
+                        "Skipping player stats saved by a newer version"
+                    );
+                    broken.push(line);
+                }
+                Ok(mut record) => {
+                    // Version 0 records were saved before versioning, they only differ by missing fields
+                    record.version = RECORD_VERSION;
+                    stats.records.insert(record.id, record);
+                }
+                Err(err) => {
+                    warn!(line = index + 1, error = %err, "Skipping broken player stats");
+                    broken.push(line);
+                }
+            }
+        }
+
+        if !broken.is_empty() {
+            quarantine(path, &broken);
+            // Written again without the broken lines
+            stats.dirty = true;
+        }
+        info!(players = stats.records.len(), "Loaded player stats");
+        stats
+    }
+
+    /// Seconds the player spent as a job, including the running round.
+    pub fn playtime(&self, player: Uuid, job: &str) -> f64 {
+        let saved = self
+            .records
+            .get(&player)
+            .and_then(|r| r.playtime.get(job))
+            .copied()
+            .unwrap_or_default();
+        let round = self
+            .round
+            .get(&player)
+            .and_then(|r| r.playtime.get(job))
+            .copied()
+            .unwrap_or_default();
+        saved + round
+    }
+
+    /// The player responsible for an entity, from the body they spawned with or what they control.
+    fn player_of(&self, entity: Entity, controls: &ClientControls) -> Option<Uuid> {
+        self.bodies
+            .get(&entity)
+            .map(|&(player, _)| player)
+            .or_else(|| controls.controlling_player(entity))
+    }
+
+    /// Counts a kill of something that isn't a player, like a hostile creature, for whoever killed it.
+    pub fn count_kill(&mut self, killer: Entity, controls: &ClientControls) {
+        if let Some(player) = self.player_of(killer, controls) {
+            self.round.entry(player).or_default().kills += 1;
+        }
+    }
+
+    /// Why the player can't take the job yet, `None` if they can.
+    pub fn job_lock(
+        &self,
+        player: Uuid,
+        job: &JobDefinition,
+        config: &JobConfig,
+        jobs: &Assets<JobDefinition>,
+    ) -> Option<String> {
+        let requirement = config.playtime_requirements.get(&job.id)?;
+        self.requirement_lock(player, &job.name, requirement, jobs)
+    }
+
+    /// Why the player can't pick the loadout option yet, `None` if they can.
+    pub fn loadout_lock(
+        &self,
+        player: Uuid,
+        option: &LoadoutOption,
+        jobs: &Assets<JobDefinition>,
+    ) -> Option<String> {
+        let requirement = option.playtime.as_ref()?;
+        self.requirement_lock(player, &option.name, requirement, jobs)
+    }
+
+    fn requirement_lock(
+        &self,
+        player: Uuid,
+        name: &str,
+        requirement: &PlaytimeRequirement,
+        jobs: &Assets<JobDefinition>,
+    ) -> Option<String> {
+        let hours = self.playtime(player, &requirement.job) / 3600.0;
+        if hours >= requirement.hours as f64 {
+            return None;
+        }
+        Some(format!(
+            "{} needs {} hours as {}, you have played {:.1}.",
+            name,
+            requirement.hours,
+            job_name(&requirement.job, jobs),
+            hours
+        ))
+    }
+
+    /// Adds the statistics of the player's running round to their saved record.
+    fn flush(&mut self, player: Uuid, username: Option<&str>) {
+        let record = self.records.entry(player).or_insert_with(|| PlayerRecord {
+            version: RECORD_VERSION,
+            id: player,
+            ..Default::default()
+        });
+        if let Some(username) = username {
+            record.username = username.to_owned();
+        }
+        if let Some(round) = self.round.remove(&player) {
+            for (job, seconds) in round.playtime {
+                *record.playtime.entry(job).or_default() += seconds;
+            }
+            record.kills += round.kills;
+            record.deaths += round.deaths;
+            // Players reconnecting during a round are saved more than once
+            if round.played && self.counted.insert(player) {
+                record.rounds_played += 1;
+            }
+        }
+        record.last_seen = unix_now();
+        self.dirty = true;
+    }
+
+    pub fn summary(&self, player: Uuid, jobs: &Assets<JobDefinition>) -> StatsSummary {
+        let record = self.records.get(&player);
+        let round = self.round.get(&player);
+        let mut job_ids: Vec<_> = record
+            .into_iter()
+            .flat_map(|r| r.playtime.keys())
+            .chain(round.into_iter().flat_map(|r| r.playtime.keys()))
+            .collect();
+        job_ids.sort_unstable();
+        job_ids.dedup();
+
+        let mut playtime: Vec<_> = job_ids
+            .into_iter()
+            .map(|job| (job_name(job, jobs).to_owned(), self.playtime(player, job)))
+            .collect();
+        playtime.sort_unstable_by(|a, b| b.1.total_cmp(&a.1));
+        let played_now = round.is_some_and(|r| r.played) && !self.counted.contains(&player);
+        StatsSummary {
+            playtime,
+            rounds_played: record.map_or(0, |r| r.rounds_played) + played_now as u32,
+            kills: record.map_or(0, |r| r.kills) + round.map_or(0, |r| r.kills),
+            deaths: record.map_or(0, |r| r.deaths) + round.map_or(0, |r| r.deaths),
+            last_seen_ago: record
+                .filter(|r| r.last_seen > 0)
+                .map(|r| unix_now().saturating_sub(r.last_seen)),
+        }
+    }
+}
+
+/// Name of a job, or its id if there is no job with it anymore.
+fn job_name<'a>(id: &'a str, jobs: &'a Assets<JobDefinition>) -> &'a str {
+    jobs.iter()
+        .find(|(_, job)| job.id == id)
+        .map_or(id, |(_, job)| job.name.as_str())
+}
+
+/// Statistics of a player, readable without knowing about jobs.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct StatsSummary {
+    /// Job names and seconds played, most played first
+    pub playtime: Vec<(String, f64)>,
+    pub rounds_played: u32,
+    pub kills: u32,
+    pub deaths: u32,
+    /// Seconds since the player was last seen, `None` if they never were before
+    pub last_seen_ago: Option<u64>,
+}
+
+impl StatsSummary {
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = vec![
+            format!("Rounds played: {}", self.rounds_played),
+            format!("Kills: {}, deaths: {}", self.kills, self.deaths),
+        ];
+        for (job, seconds) in self.playtime.iter() {
+            lines.push(format!("{}: {:.1} hours", job, seconds / 3600.0));
+        }
+        lines.push(match self.last_seen_ago {
+            Some(seconds) if seconds >= 86400 => format!("Last seen {} days ago", seconds / 86400),
+            Some(seconds) if seconds >= 3600 => format!("Last seen {} hours ago", seconds / 3600),
+            Some(seconds) => format!("Last seen {} minutes ago", seconds / 60),
+            None => "First time seen".into(),
+        });
+        lines
+    }
+}
+
+/// Writes the stats file in the background, so a slow disk doesn't stall the server.
+#[derive(Resource, Default)]
+struct StatsWriter {
+    task: Option<Task<Result<(), String>>>,
+}
+
+fn save_records(path: &Path, records: Vec<PlayerRecord>) -> Result<(), String> {
+    let mut text = String::new();
+    for record in records {
+        text += &ron::to_string(&record).map_err(|e| e.to_string())?;
+        text.push('\n');
+    }
+    // Written next to the file first, so a crash while writing doesn't lose the old records
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    fs::write(&temporary, text).map_err(|e| e.to_string())?;
+    fs::rename(&temporary, path).map_err(|e| e.to_string())
+}
+
+fn write_stats(mut stats: ResMut<PlayerStats>, mut writer: ResMut<StatsWriter>) {
+    if let Some(task) = writer.task.as_mut() {
+        let Some(result) = future::block_on(future::poll_once(task)) else {
+            return;
+        };
+        writer.task = None;
+        if let Err(err) = result {
+            error!(error = err.as_str(), "Could not write player stats");
+        }
+    }
+
+    if !stats.dirty {
+        return;
+    }
+    stats.dirty = false;
+    let records: Vec<_> = stats.records.values().cloned().collect();
+    writer.task =
+        Some(IoTaskPool::get().spawn(async move { save_records(Path::new(STATS_FILE), records) }));
+}
+
+/// Saves the statistics of players when they leave.
+fn track_connections(
+    mut events: EventReader<ServerEvent>,
+    players: Res<Players>,
+    mut stats: ResMut<PlayerStats>,
+) {
+    for event in events.iter() {
+        if let ServerEvent::PlayerConnected(connection) = *event {
+            if let Some(player) = players.get(connection) {
+                stats
+                    .connections
+                    .insert(connection, (player.id, player.username.clone()));
+            }
+        } else if let ServerEvent::PlayerDisconnected(connection) = *event {
+            if let Some((player, username)) = stats.connections.remove(&connection) {
+                stats.flush(player, Some(&username));
+            }
+        }
+    }
+}
+
+/// Remembers which player spawned as which job, so time spent in the body counts towards it.
+fn track_job_bodies(
+    spawned: Query<(Entity, &Affiliation), Added<Affiliation>>,
+    mut removed: RemovedComponents<Affiliation>,
+    controls: Res<ClientControls>,
+    mut stats: ResMut<PlayerStats>,
+) {
+    for (entity, affiliation) in spawned.iter() {
+        let Some(player) = controls.controlling_player(entity) else {
+            continue;
+        };
+        stats
+            .bodies
+            .insert(entity, (player, affiliation.job.clone()));
+        stats.round.entry(player).or_default().played = true;
+    }
+
+    for entity in removed.iter() {
+        stats.bodies.remove(&entity);
+    }
+}
+
+/// Only time spent alive and in control of the body counts.
+fn track_playtime(
+    controls: Res<ClientControls>,
+    players: Res<Players>,
+    vitals: Vitals,
+    mut stats: ResMut<PlayerStats>,
+) {
+    let stats = stats.as_mut();
+    for (&body, (player, job)) in stats.bodies.iter() {
+        let playing = controls.controlling_player(body) == Some(*player)
+            && players.get_connection(player).is_some()
+            && !matches!(vitals.status(body), None | Some(VitalStatus::Dead));
+        if !playing {
+            continue;
+        }
+        *stats
+            .round
+            .entry(*player)
+            .or_default()
+            .playtime
+            .entry(job.clone())
+            .or_default() += PLAYTIME_INTERVAL as f64;
+    }
+}
+
+/// Counts deaths of player bodies, and a kill for the player responsible.
+fn count_deaths(
+    dead: Query<(Entity, &Dead), Added<Dead>>,
+    controls: Res<ClientControls>,
+    mut stats: ResMut<PlayerStats>,
+) {
+    for (body, dead) in dead.iter() {
+        let Some(&(victim, _)) = stats.bodies.get(&body) else {
+            continue;
+        };
+        stats.round.entry(victim).or_default().deaths += 1;
+
+        let killer = dead
+            .killer
+            .and_then(|killer| stats.player_of(killer, &controls));
+        if let Some(killer) = killer.filter(|&killer| killer != victim) {
+            stats.round.entry(killer).or_default().kills += 1;
+        }
+    }
+}
+
+fn flush_round_stats(players: Res<Players>, mut stats: ResMut<PlayerStats>) {
+    for player in players.players().values() {
+        stats.flush(player.id, Some(&player.username));
+    }
+    // Players that left were saved then, but can still have been given kills after
+    let remaining: Vec<_> = stats.round.keys().copied().collect();
+    for player in remaining {
+        stats.flush(player, None);
+    }
+    info!("Saved player stats of the round");
+}
+
+/// Client message asking for the statistics of the sender.
+#[derive(Serialize, Deserialize)]
+pub struct StatsRequest;
+
+#[derive(Serialize, Deserialize)]
+struct StatsMessage(StatsSummary);
+
+fn handle_stats_requests(
+    mut requests: EventReader<MessageEvent<StatsRequest>>,
+    players: Res<Players>,
+    stats: Res<PlayerStats>,
+    jobs: Res<Assets<JobDefinition>>,
+    mut sender: MessageSender,
+) {
+    for event in requests.iter() {
+        let Some(player) = players.get(event.connection) else {
+            continue;
+        };
+        sender.send(
+            &StatsMessage(stats.summary(player.id, &jobs)),
+            MessageReceivers::Single(event.connection),
+        );
+    }
+}
+
+fn stats_command(world: &mut World, context: &CommandContext) -> CommandResult {
+    let connection = context.player(0);
+    let Some((id, username)) = world
+        .resource::<Players>()
+        .get(connection)
+        .map(|p| (p.id, p.username.clone()))
+    else {
+        return Err("player disconnected".into());
+    };
+
+    let summary = world
+        .resource::<PlayerStats>()
+        .summary(id, world.resource::<Assets<JobDefinition>>());
+    Ok(format!(
+        "Statistics of {}:\n{}",
+        username,
+        summary.lines().join("\n")
+    ))
+}
+
+/// The statistics of the local player, shown in a window once requested.
+#[cfg(feature = "client")]
+#[derive(Resource, Default)]
+pub struct ClientStats {
+    summary: Option<StatsSummary>,
+    pub open: bool,
+}
+
+#[cfg(feature = "client")]
+fn receive_stats(
+    mut messages: EventReader<MessageEvent<StatsMessage>>,
+    mut stats: ResMut<ClientStats>,
+) {
+    if let Some(event) = messages.iter().last() {
+        stats.summary = Some(event.message.0.clone());
+    }
+}
+
+#[cfg(feature = "client")]
+fn stats_window(mut contexts: EguiContexts, mut stats: ResMut<ClientStats>) {
+    let stats = &mut *stats;
+    if !stats.open {
+        return;
+    }
+
+    egui::Window::new("My stats")
+        .open(&mut stats.open)
+        .resizable(false)
+        .show(contexts.ctx_mut(), |ui| match &stats.summary {
+            Some(summary) => {
+                for line in summary.lines() {
+                    ui.label(line);
+                }
+            }
+            None => {
+                ui.spinner();
+            }
+        });
+}
@@ -0,0 +1,634 @@
+//! A read-only HTTP endpoint for monitoring the server without a game client.
+//!
+//! `/status` answers with JSON about the round and players, `/metrics` with the same numbers
+//! and network and physics counters in the Prometheus text format.
+//! Requests are answered from a snapshot the game updates once a second, they never touch the world.
+
+use std::{
+    fmt::Write,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+use async_compat::Compat;
+use bevy::{
+    ecs::{entity::Entities, schedule::ScheduleLabel},
+    prelude::*,
+    tasks::IoTaskPool,
+    time::common_conditions::on_timer,
+    utils::HashMap,
+};
+use networking::{
+    is_server,
+    quality::{LinkQuality, LinkTier},
+    time::ServerNetworkTime,
+    Players, PROTOCOL_ID,
+};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    time::timeout,
+};
+
+use crate::{
+    config::ServerConfig,
+    physics_tuning::PhysicsTimings,
+    round::{RoundData, RoundState},
+    Map, SavedMap,
+};
+
+pub struct StatusPlugin;
+
+impl Plugin for StatusPlugin {
+    fn build(&self, app: &mut App) {
+        if !is_server(app) {
+            return;
+        }
+        let config = app
+            .world
+            .get_resource::<ServerConfig>()
+            .map(|config| config.status.clone())
+            .unwrap_or_default();
+        if config.bind_address.is_none() {
+            return;
+        }
+
+        time_ticks(app);
+        app.insert_resource(config)
+            .init_resource::<SharedStatus>()
+            .add_systems(Startup, start_status_server)
+            .add_systems(
+                Update,
+                update_snapshot.run_if(on_timer(Duration::from_secs_f32(SNAPSHOT_INTERVAL))),
+            );
+    }
+}
+
+/// Runs the main schedule through [`TimedMain`], recording how long every tick takes in [`TickTimes`].
+fn time_ticks(app: &mut App) {
+    app.init_resource::<TickTimes>()
+        .add_systems(TimedMain, run_timed_main);
+    app.main_schedule_label = Box::new(TimedMain);
+}
+
+/// Seconds between snapshots of the server state
+const SNAPSHOT_INTERVAL: f32 = 1.0;
+/// Requests larger than this are rejected, the endpoint only needs a request line
+const MAX_REQUEST_SIZE: usize = 4096;
+/// Connections that don't send a full request in time are dropped
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+/// Rate limits of this many addresses are remembered, older ones are forgotten
+const MAX_TRACKED_ADDRESSES: usize = 1024;
+
+#[derive(Resource, Deserialize, Clone)]
+#[serde(default)]
+pub struct StatusConfig {
+    /// Address the endpoint listens on, like `127.0.0.1:33999`. The endpoint is disabled if not set.
+    pub bind_address: Option<SocketAddr>,
+    /// Name reported as the server name
+    pub name: String,
+    /// If the names of connected players are included, otherwise only their count
+    pub show_player_names: bool,
+    /// Requests a single address can make per minute
+    pub requests_per_minute: u32,
+}
+
+impl Default for StatusConfig {
+    fn default() -> Self {
+        Self {
+            bind_address: None,
+            name: "Space Station Nanotrasen".into(),
+            show_player_names: false,
+            requests_per_minute: 60,
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Default)]
+struct StatusSnapshot {
+    name: String,
+    protocol: u64,
+    uptime_seconds: f64,
+    round: RoundStatus,
+    players: PlayerStatus,
+    map: Option<String>,
+    performance: Performance,
+    /// Only reported as metrics
+    #[serde(skip)]
+    network: NetworkCounters,
+    #[serde(skip)]
+    physics: Option<PhysicsTimings>,
+}
+
+#[derive(Serialize, Clone, Default)]
+struct RoundStatus {
+    state: String,
+    elapsed_seconds: Option<f64>,
+}
+
+#[derive(Serialize, Clone, Default)]
+struct PlayerStatus {
+    count: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    names: Option<Vec<String>>,
+}
+
+#[derive(Serialize, Clone, Default)]
+struct Performance {
+    ticks_per_second: f64,
+    average_tick_ms: f64,
+    max_tick_ms: f64,
+    entities: u32,
+}
+
+#[derive(Clone, Default)]
+struct NetworkCounters {
+    connections: HashMap<LinkTier, usize>,
+    average_rtt: f64,
+    average_packet_loss: f64,
+    bytes_sent_per_second: f64,
+    bytes_received_per_second: f64,
+}
+
+/// The latest snapshot, shared with the HTTP server.
+#[derive(Resource, Clone, Default)]
+struct SharedStatus(Arc<RwLock<StatusSnapshot>>);
+
+/// How long the ticks since the last snapshot took to run, without the time spent waiting for the next tick.
+#[derive(Resource, Default)]
+struct TickTimes(Vec<Duration>);
+
+/// Runs [`Main`] and measures how long it takes.
+#[derive(ScheduleLabel, Debug, Clone, PartialEq, Eq, Hash)]
+struct TimedMain;
+
+fn run_timed_main(world: &mut World) {
+    let start = Instant::now();
+    world.run_schedule(Main);
+    let duration = start.elapsed();
+    world.resource_mut::<TickTimes>().0.push(duration);
+}
+
+#[allow(clippy::too_many_arguments)]
+fn update_snapshot(
+    shared: Res<SharedStatus>,
+    config: Res<StatusConfig>,
+    mut ticks: ResMut<TickTimes>,
+    time: Res<Time>,
+    entities: &Entities,
+    players: Res<Players>,
+    round_state: Option<Res<State<RoundState>>>,
+    round_data: Option<Res<RoundData>>,
+    network_time: Res<ServerNetworkTime>,
+    map: Option<Res<Map>>,
+    saved_map: Option<Res<SavedMap>>,
+    asset_server: Res<AssetServer>,
+    links: Option<Res<LinkQuality>>,
+    physics: Option<Res<PhysicsTimings>>,
+) {
+    let elapsed_seconds = round_data.and_then(|data| data.start()).map(|start| {
+        network_time.current_tick().saturating_sub(start) as f64 * network_time.tick_in_seconds()
+    });
+    let map = if saved_map.is_some() {
+        Some("map save".to_owned())
+    } else {
+        map.and_then(|map| asset_server.get_handle_path(&map.handle))
+            .and_then(|path| path.path().file_stem().map(|s| s.to_string_lossy().into()))
+    };
+
+    let tick_count = ticks.0.len();
+    let tick_total: Duration = ticks.0.iter().sum();
+    let max_tick = ticks.0.iter().max().copied().unwrap_or_default();
+    let performance = Performance {
+        ticks_per_second: tick_count as f64 / SNAPSHOT_INTERVAL as f64,
+        average_tick_ms: if tick_count > 0 {
+            tick_total.as_secs_f64() * 1000.0 / tick_count as f64
+        } else {
+            0.0
+        },
+        max_tick_ms: max_tick.as_secs_f64() * 1000.0,
+        entities: entities.len(),
+    };
+    ticks.0.clear();
+
+    let mut network = NetworkCounters::default();
+    if let Some(links) = links {
+        let mut count = 0;
+        for (_, tier, stats) in links.iter() {
+            *network.connections.entry(tier).or_default() += 1;
+            network.average_rtt += stats.rtt;
+            network.average_packet_loss += stats.packet_loss;
+            network.bytes_sent_per_second += stats.bytes_sent_per_second;
+            network.bytes_received_per_second += stats.bytes_received_per_second;
+            count += 1;
+        }
+        if count > 0 {
+            network.average_rtt /= count as f64;
+            network.average_packet_loss /= count as f64;
+        }
+    }
+
+    let snapshot = StatusSnapshot {
+        name: config.name.clone(),
+        protocol: PROTOCOL_ID,
+        uptime_seconds: time.raw_elapsed_seconds_f64(),
+        round: RoundStatus {
+            state: round_state
+                .map(|state| format!("{:?}", state.get()).to_lowercase())
+                .unwrap_or_default(),
+            elapsed_seconds,
+        },
+        players: PlayerStatus {
+            count: players.players().len(),
+            names: config.show_player_names.then(|| {
+                players
+                    .players()
+                    .values()
+                    .map(|p| p.username.clone())
+                    .collect()
+            }),
+        },
+        map,
+        performance,
+        network,
+        physics: physics.map(|p| p.clone()),
+    };
+    *shared.0.write().unwrap() = snapshot;
+}
+
+fn start_status_server(config: Res<StatusConfig>, shared: Res<SharedStatus>) {
+    let Some(address) = config.bind_address else {
+        return;
+    };
+    let requests_per_minute = config.requests_per_minute;
+    let shared = shared.clone();
+    let server = async move {
+        let listener = match TcpListener::bind(address).await {
+            Ok(l) => l,
+            Err(err) => {
+                error!(address = %address, error = %err, "Could not start status endpoint");
+                return;
+            }
+        };
+        info!(address = %address, "Status endpoint listening");
+
+        let mut limiter = RateLimiter::new(requests_per_minute);
+        loop {
+            let (stream, peer) = match listener.accept().await {
+                Ok(c) => c,
+                Err(err) => {
+                    warn!(error = %err, "Error accepting status connection");
+                    continue;
+                }
+            };
+            let allowed = limiter.allow(peer.ip());
+            let shared = shared.clone();
+            IoTaskPool::get()
+                .spawn(Compat::new(async move {
+                    if let Err(err) = handle_connection(stream, allowed, &shared).await {
+                        debug!(peer = %peer, error = %err, "Error answering status request");
+                    }
+                }))
+                .detach();
+        }
+    };
+    IoTaskPool::get().spawn(Compat::new(server)).detach();
+}
+
+/// Gives every address a budget of requests that refills over a minute.
+struct RateLimiter {
+    per_minute: u32,
+    buckets: HashMap<IpAddr, (f32, Instant)>,
+}
+
+impl RateLimiter {
+    fn new(per_minute: u32) -> Self {
+        Self {
+            per_minute,
+            buckets: HashMap::default(),
+        }
+    }
+
+    fn allow(&mut self, address: IpAddr) -> bool {
+        let now = Instant::now();
+        let capacity = self.per_minute as f32;
+        if self.buckets.len() >= MAX_TRACKED_ADDRESSES {
+            // Addresses with a full budget don't need to be remembered
+            self.buckets.retain(|_, (tokens, last)| {
+                *tokens + (now - *last).as_secs_f32() * capacity / 60.0 < capacity
+            });
+        }
+
+        let (tokens, last) = self.buckets.entry(address).or_insert((capacity, now));
+        *tokens = (*tokens + (now - *last).as_secs_f32() * capacity / 60.0).min(capacity);
+        *last = now;
+        if *tokens < 1.0 {
+            return false;
+        }
+        *tokens -= 1.0;
+        true
+    }
+}
+
+async fn handle_connection(
+    mut stream: TcpStream,
+    allowed: bool,
+    shared: &SharedStatus,
+) -> std::io::Result<()> {
+    if !allowed {
+        return respond(&mut stream, "429 Too Many Requests", "text/plain", "").await;
+    }
+
+    let mut request = Vec::new();
+    let mut buffer = [0; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+        if request.len() > MAX_REQUEST_SIZE {
+            return respond(&mut stream, "413 Payload Too Large", "text/plain", "").await;
+        }
+        let read = match timeout(REQUEST_TIMEOUT, stream.read(&mut buffer)).await {
+            Ok(read) => read?,
+            Err(_) => return Ok(()),
+        };
+        if read == 0 {
+            return Ok(());
+        }
+        request.extend_from_slice(&buffer[..read]);
+    }
+
+    let request = String::from_utf8_lossy(&request);
+    let mut request_line = request.lines().next().unwrap_or_default().split(' ');
+    let (method, path) = (request_line.next(), request_line.next());
+    if method != Some("GET") {
+        return respond(&mut stream, "405 Method Not Allowed", "text/plain", "").await;
+    }
+
+    let snapshot = shared.0.read().unwrap().clone();
+    match path.map(|p| p.split('?').next().unwrap_or_default()) {
+        Some("/status") => {
+            let body = serde_json::to_string(&snapshot).unwrap_or_default();
+            respond(&mut stream, "200 OK", "application/json", &body).await
+        }
+        Some("/metrics") => {
+            respond(
+                &mut stream,
+                "200 OK",
+                "text/plain; version=0.0.4",
+                &metrics(&snapshot),
+            )
+            .await
+        }
+        _ => respond(&mut stream, "404 Not Found", "text/plain", "").await,
+    }
+}
+
+async fn respond(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &str,
+) -> std::io::Result<()> {
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// Formats the snapshot in the Prometheus text format.
+fn metrics(snapshot: &StatusSnapshot) -> String {
+    let mut text = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, samples: &[(&str, f64)]| {
+        let _ = writeln!(text, "# HELP ssnt_{} {}", name, help);
+        let _ = writeln!(text, "# TYPE ssnt_{} {}", name, kind);
+        for (labels, value) in samples {
+            let _ = writeln!(text, "ssnt_{}{} {}", name, labels, value);
+        }
+    };
+
+    metric(
+        "uptime_seconds",
+        "counter",
+        "Seconds since the server started",
+        &[("", snapshot.uptime_seconds)],
+    );
+    let state_label = format!("{{state=\"{}\"}}", snapshot.round.state);
+    metric(
+        "round_state",
+        "gauge",
+        "The current round state",
+        &[(&state_label, 1.0)],
+    );
+    metric(
+        "round_elapsed_seconds",
+        "gauge",
+        "Seconds since the round started",
+        &[("", snapshot.round.elapsed_seconds.unwrap_or_default())],
+    );
+    metric(
+        "players",
+        "gauge",
+        "Connected players",
+        &[("", snapshot.players.count as f64)],
+    );
+    let performance = &snapshot.performance;
+    metric(
+        "ticks_per_second",
+        "gauge",
+        "Server ticks in the last second",
+        &[("", performance.ticks_per_second)],
+    );
+    metric(
+        "tick_duration_milliseconds",
+        "gauge",
+        "Duration of server ticks in the last second",
+        &[
+            ("{stat=\"average\"}", performance.average_tick_ms),
+            ("{stat=\"max\"}", performance.max_tick_ms),
+        ],
+    );
+    metric(
+        "entities",
+        "gauge",
+        "Entities in the world",
+        &[("", performance.entities as f64)],
+    );
+
+    let network = &snapshot.network;
+    let tiers: Vec<_> = [LinkTier::Good, LinkTier::Degraded, LinkTier::Poor]
+        .into_iter()
+        .map(|tier| {
+            (
+                format!("{{tier=\"{}\"}}", tier),
+                network.connections.get(&tier).copied().unwrap_or_default() as f64,
+            )
+        })
+        .collect();
+    let tiers: Vec<_> = tiers.iter().map(|(l, v)| (l.as_str(), *v)).collect();
+    metric(
+        "connections",
+        "gauge",
+        "Client connections by link quality",
+        &tiers,
+    );
+    metric(
+        "network_rtt_milliseconds",
+        "gauge",
+        "Average round-trip time of client connections",
+        &[("", network.average_rtt)],
+    );
+    metric(
+        "network_packet_loss",
+        "gauge",
+        "Average fraction of lost packets of client connections",
+        &[("", network.average_packet_loss)],
+    );
+    metric(
+        "network_bytes_per_second",
+        "gauge",
+        "Bytes sent to and received from all clients per second",
+        &[
+            ("{direction=\"sent\"}", network.bytes_sent_per_second),
+            (
+                "{direction=\"received\"}",
+                network.bytes_received_per_second,
+            ),
+        ],
+    );
+
+    if let Some(physics) = &snapshot.physics {
+        metric(
+            "physics_step_milliseconds",
+            "gauge",
+            "Duration of the last physics step by stage",
+            &[
+                ("{stage=\"total\"}", physics.step),
+                ("{stage=\"broad_phase\"}", physics.broad_phase),
+                ("{stage=\"narrow_phase\"}", physics.narrow_phase),
+                ("{stage=\"solver\"}", physics.solver),
+            ],
+        );
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::*;
+
+    fn snapshot() -> StatusSnapshot {
+        StatusSnapshot {
+            name: "Test".into(),
+            protocol: PROTOCOL_ID,
+            uptime_seconds: 12.5,
+            round: RoundStatus {
+                state: "running".into(),
+                elapsed_seconds: Some(3.0),
+            },
+            players: PlayerStatus {
+                count: 2,
+                names: None,
+            },
+            map: Some("boxstation".into()),
+            performance: Performance {
+                ticks_per_second: 60.0,
+                average_tick_ms: 1.5,
+                max_tick_ms: 4.0,
+                entities: 100,
+            },
+            network: NetworkCounters::default(),
+            physics: None,
+        }
+    }
+
+    fn keys(value: &Value) -> Vec<&str> {
+        let mut keys: Vec<_> = value
+            .as_object()
+            .expect("not an object")
+            .keys()
+            .map(String::as_str)
+            .collect();
+        keys.sort_unstable();
+        keys
+    }
+
+    #[test]
+    fn status_json_schema() {
+        let json = serde_json::to_value(snapshot()).unwrap();
+        assert_eq!(
+            keys(&json),
+            [
+                "map",
+                "name",
+                "performance",
+                "players",
+                "protocol",
+                "round",
+                "uptime_seconds"
+            ]
+        );
+        assert!(json["name"].is_string());
+        assert!(json["protocol"].is_u64());
+        assert!(json["uptime_seconds"].is_f64());
+        assert!(json["map"].is_string());
+
+        assert_eq!(keys(&json["round"]), ["elapsed_seconds", "state"]);
+        assert!(json["round"]["state"].is_string());
+        assert!(json["round"]["elapsed_seconds"].is_f64());
+
+        // Names are left out unless enabled
+        assert_eq!(keys(&json["players"]), ["count"]);
+        assert!(json["players"]["count"].is_u64());
+
+        assert_eq!(
+            keys(&json["performance"]),
+            [
+                "average_tick_ms",
+                "entities",
+                "max_tick_ms",
+                "ticks_per_second"
+            ]
+        );
+        for key in ["average_tick_ms", "max_tick_ms", "ticks_per_second"] {
+            assert!(json["performance"][key].is_f64(), "{}", key);
+        }
+        assert!(json["performance"]["entities"].is_u64());
+    }
+
+    #[test]
+    fn status_json_optional_fields() {
+        let mut snapshot = snapshot();
+        snapshot.map = None;
+        snapshot.round.elapsed_seconds = None;
+        snapshot.players.names = Some(vec!["Alice".into(), "Bob".into()]);
+        let json = serde_json::to_value(snapshot).unwrap();
+        assert!(json["map"].is_null());
+        assert!(json["round"]["elapsed_seconds"].is_null());
+        assert_eq!(
+            json["players"]["names"],
+            serde_json::json!(["Alice", "Bob"])
+        );
+    }
+
+    #[test]
+    fn ticks_are_timed_around_main() {
+        let mut app = App::new();
+        time_ticks(&mut app);
+        app.add_systems(Update, || {
+            std::thread::sleep(Duration::from_millis(5));
+        });
+        for _ in 0..3 {
+            app.update();
+        }
+        let ticks = &app.world.resource::<TickTimes>().0;
+        assert_eq!(ticks.len(), 3);
+        assert!(ticks.iter().all(|tick| *tick >= Duration::from_millis(5)));
+    }
+}
@@ -0,0 +1,344 @@
+use std::time::Duration;
+
+use bevy::{
+    ecs::query::Has, prelude::*, reflect::TypeUuid, time::common_conditions::on_timer,
+    utils::HashMap,
+};
+use networking::{
+    component::AppExt as ComponentAppExt,
+    identity::NetworkIdentity,
+    is_server,
+    messaging::{AppExt, MessageReceivers, MessageSender},
+    spawning::ClientControls,
+    variable::{NetworkVar, ServerVar},
+    ConnectionId, Networked, Players,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    body::{
+        health::{VitalStatus, Vitals},
+        Body,
+    },
+    items::clothes::ClothingHolder,
+    machines::security::{ArrestFlag, SecurityHud},
+};
+
+#[cfg(feature = "client")]
+use {
+    crate::{
+        accessibility::{AccessibilitySettings, UiColor},
+        camera::MainCamera,
+        combat::GrabbedByClient,
+        ui::has_window,
+        GameState,
+    },
+    bevy_egui::{egui, EguiContexts},
+    networking::{messaging::MessageEvent, spawning::ClientControlled},
+};
+
+/// Icons above other players showing if they are dead or restrained.
+/// Players with a security or medical HUD also see arrest flags or how hurt someone is.
+pub struct StatusHudPlugin;
+
+impl Plugin for StatusHudPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<MedicalHud>()
+            .add_networked_component::<VisibleCondition, VisibleConditionClient>()
+            .add_network_message::<HudOverlayMessage>("HudOverlayMessage");
+
+        if is_server(app) {
+            app.add_systems(
+                Update,
+                (
+                    update_visible_conditions
+                        .run_if(on_timer(Duration::from_secs_f32(CONDITION_INTERVAL))),
+                    send_hud_overlays,
+                ),
+            );
+        } else {
+            #[cfg(feature = "client")]
+            app.init_resource::<ClientHudOverlay>().add_systems(
+                Update,
+                (receive_hud_overlay, status_icons.run_if(has_window))
+                    .chain()
+                    .run_if(in_state(GameState::Game)),
+            );
+        }
+    }
+}
+
+/// Seconds between checks if creatures died or passed out
+const CONDITION_INTERVAL: f32 = 0.5;
+/// Creatures further away than this from a HUD wearer aren't sent to them.
+/// Also the distance from the camera icons are drawn at.
+const STATUS_ICON_MAX_DISTANCE: f32 = 25.0;
+/// How many steps the health bar of medical HUDs has
+const HEALTH_TIERS: u8 = 4;
+#[cfg(feature = "client")]
+const HEALTH_BAR_WIDTH: f32 = 32.0;
+
+/// The kinds of HUD that show extra information over players.
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum HudKind {
+    Security,
+    Medical,
+}
+
+/// Clothing that shows how hurt players are while worn.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct MedicalHud;
+
+/// HUDs a creature has from its job, without wearing anything.
+#[derive(Component)]
+pub struct JobHuds(pub Vec<HudKind>);
+
+/// What everyone can tell about a creature by looking at it.
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Condition {
+    #[default]
+    Awake,
+    Unconscious,
+    Dead,
+}
+
+#[derive(Component, Networked)]
+#[networked(client = "VisibleConditionClient")]
+pub struct VisibleCondition {
+    condition: NetworkVar<Condition>,
+}
+
+#[derive(Component, Default, TypeUuid, Networked)]
+#[uuid = "c47e1b93-5a2d-4f86-b0e3-9d6a2c8f1e54"]
+#[networked(server = "VisibleCondition")]
+pub struct VisibleConditionClient {
+    condition: ServerVar<Condition>,
+}
+
+impl VisibleConditionClient {
+    pub fn condition(&self) -> Condition {
+        *self.condition
+    }
+}
+
+fn update_visible_conditions(
+    mut bodies: Query<(Entity, Option<&mut VisibleCondition>), With<Body>>,
+    vitals: Vitals,
+    mut commands: Commands,
+) {
+    for (entity, visible) in bodies.iter_mut() {
+        let condition = match vitals.status(entity) {
+            Some(VitalStatus::Dead) => Condition::Dead,
+            Some(VitalStatus::Unconscious) => Condition::Unconscious,
+            _ => Condition::Awake,
+        };
+        match visible {
+            Some(mut visible) if *visible.condition != condition => {
+                *visible.condition = condition;
+            }
+            Some(_) => {}
+            None => {
+                commands.entity(entity).insert(VisibleCondition {
+                    condition: condition.into(),
+                });
+            }
+        }
+    }
+}
+
+/// Server message with what a player's HUDs show. Only sent to players with the matching HUD,
+/// so other clients never learn who is flagged or how hurt someone is.
+#[derive(Serialize, Deserialize, Default, Clone, PartialEq)]
+struct HudOverlayMessage {
+    arrest: Vec<NetworkIdentity>,
+    /// Health between 0 and [`HEALTH_TIERS`]
+    health: Vec<(NetworkIdentity, u8)>,
+}
+
+fn health_tier(vitals: &Vitals, creature: Entity) -> u8 {
+    if vitals.status(creature) == Some(VitalStatus::Dead) {
+        return 0;
+    }
+    let Some(limbs) = vitals.limbs(creature).filter(|l| !l.is_empty()) else {
+        return HEALTH_TIERS;
+    };
+    let integrity = limbs.iter().map(|l| l.integrity).sum::<f32>() / limbs.len() as f32;
+    (integrity.clamp(0.0, 1.0) * HEALTH_TIERS as f32).ceil() as u8
+}
+
+/// Sends every HUD wearer what their HUDs show whenever it changes.
+/// Runs every frame, so putting on or taking off a HUD shows up right away.
+#[allow(clippy::too_many_arguments)]
+fn send_hud_overlays(
+    players: Res<Players>,
+    controls: Res<ClientControls>,
+    children: Query<&Children>,
+    worn: Query<(&Parent, Has<SecurityHud>, Has<MedicalHud>)>,
+    holders: Query<(), With<ClothingHolder>>,
+    job_huds: Query<&JobHuds>,
+    creatures: Query<(Entity, &NetworkIdentity, &GlobalTransform, Has<ArrestFlag>), With<Body>>,
+    vitals: Vitals,
+    mut sent: Local<HashMap<ConnectionId, HudOverlayMessage>>,
+    mut sender: MessageSender,
+) {
+    // Health is only looked up once per creature, even with several medical viewers
+    let mut health = HashMap::<Entity, u8>::default();
+    let mut overlays = HashMap::default();
+
+    for (&connection, player) in players.players().iter() {
+        let Some(viewer) = controls.controlled_entity(player.id) else {
+            continue;
+        };
+        let Ok((_, _, viewer_transform, _)) = creatures.get(viewer) else {
+            continue;
+        };
+
+        let (mut security, mut medical) = (false, false);
+        if let Ok(huds) = job_huds.get(viewer) {
+            security |= huds.0.contains(&HudKind::Security);
+            medical |= huds.0.contains(&HudKind::Medical);
+        }
+        // Only worn HUDs count, not ones in a backpack
+        for (_, is_security, is_medical) in children
+            .iter_descendants(viewer)
+            .filter_map(|e| worn.get(e).ok())
+            .filter(|(parent, ..)| holders.contains(parent.get()))
+        {
+            security |= is_security;
+            medical |= is_medical;
+        }
+
+        let mut overlay = HudOverlayMessage::default();
+        if security || medical {
+            let origin = viewer_transform.translation();
+            for (creature, &identity, transform, flagged) in creatures.iter() {
+                if creature == viewer
+                    || transform.translation().distance(origin) > STATUS_ICON_MAX_DISTANCE
+                {
+                    continue;
+                }
+                if security && flagged {
+                    overlay.arrest.push(identity);
+                }
+                if medical {
+                    let tier = *health
+                        .entry(creature)
+                        .or_insert_with(|| health_tier(&vitals, creature));
+                    overlay.health.push((identity, tier));
+                }
+            }
+        }
+        overlays.insert(connection, overlay);
+    }
+
+    for (&connection, overlay) in overlays.iter() {
+        let last = sent.get(&connection);
+        // Nothing to send to players that never had a HUD
+        if last == Some(overlay) || (last.is_none() && overlay == &HudOverlayMessage::default()) {
+            continue;
+        }
+        sender.send(overlay, MessageReceivers::Single(connection));
+    }
+    *sent = overlays;
+}
+
+#[cfg(feature = "client")]
+#[derive(Resource, Default)]
+struct ClientHudOverlay {
+    arrest: Vec<NetworkIdentity>,
+    health: HashMap<NetworkIdentity, u8>,
+}
+
+#[cfg(feature = "client")]
+fn receive_hud_overlay(
+    mut messages: EventReader<MessageEvent<HudOverlayMessage>>,
+    mut overlay: ResMut<ClientHudOverlay>,
+) {
+    if let Some(event) = messages.iter().last() {
+        overlay.arrest = event.message.arrest.clone();
+        overlay.health = event.message.health.iter().copied().collect();
+    }
+}
+
+#[cfg(feature = "client")]
+fn status_icons(
+    mut contexts: EguiContexts,
+    overlay: Res<ClientHudOverlay>,
+    camera: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    creatures: Query<
+        (
+            &NetworkIdentity,
+            &GlobalTransform,
+            Option<&VisibleConditionClient>,
+            Option<&GrabbedByClient>,
+        ),
+        (With<Body>, Without<ClientControlled>),
+    >,
+    accessibility: Res<AccessibilitySettings>,
+) {
+    let Ok((camera, camera_transform)) = camera.get_single() else {
+        return;
+    };
+
+    let ctx = contexts.ctx_mut();
+    for (&identity, transform, condition, grabbed) in creatures.iter() {
+        let condition = condition.map(|c| c.condition()).unwrap_or_default();
+        let restrained = grabbed.is_some_and(|g| g.restrains_hands());
+        let arrest = overlay.arrest.contains(&identity);
+        let health = overlay.health.get(&identity).copied();
+        if condition == Condition::Awake && !restrained && !arrest && health.is_none() {
+            continue;
+        }
+
+        // Same height speech bubbles start at. Bubbles stack upwards and icons go below,
+        // so they never cover each other.
+        // TODO: Calculate offset from character bounding box
+        let head = transform.translation() + Vec3::Y * 1.8;
+        if head.distance(camera_transform.translation()) > STATUS_ICON_MAX_DISTANCE {
+            continue;
+        }
+        let Some(screen_position) = camera.world_to_viewport(camera_transform, head) else {
+            continue;
+        };
+
+        egui::Area::new(("status_icons", identity))
+            .fixed_pos(egui::pos2(screen_position.x, screen_position.y + 4.0))
+            .pivot(egui::Align2::CENTER_TOP)
+            .interactable(false)
+            .order(egui::Order::Background)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.spacing_mut().item_spacing.x = 4.0;
+                    match condition {
+                        Condition::Dead => {
+                            ui.colored_label(egui::Color32::LIGHT_GRAY, "☠");
+                        }
+                        Condition::Unconscious => {
+                            ui.colored_label(egui::Color32::LIGHT_BLUE, "z");
+                        }
+                        Condition::Awake => {}
+                    }
+                    if restrained {
+                        ui.colored_label(accessibility.color(UiColor::Warning), "⛓");
+                    }
+                    if arrest {
+                        ui.colored_label(accessibility.color(UiColor::Danger), "⚠");
+                    }
+                    if let Some(tier) = health {
+                        let fraction = tier as f32 / HEALTH_TIERS as f32;
+                        let color = accessibility.gradient(fraction);
+                        let (rect, _) = ui.allocate_exact_size(
+                            egui::vec2(HEALTH_BAR_WIDTH, 6.0),
+                            egui::Sense::hover(),
+                        );
+                        let painter = ui.painter();
+                        painter.rect_filled(rect, 2.0, egui::Color32::from_black_alpha(180));
+                        let mut filled = rect;
+                        filled.set_width(rect.width() * fraction);
+                        painter.rect_filled(filled, 2.0, color);
+                    }
+                });
+            });
+    }
+}
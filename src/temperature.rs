@@ -0,0 +1,462 @@
+use bevy::{prelude::*, utils::HashMap};
+use maps::world_to_tile;
+use networking::{
+    is_server,
+    messaging::{AppExt, MessageReceivers, MessageSender},
+    spawning::ClientControls,
+    Players,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    body::Body,
+    diffusion::{diffuse, disturb_changed_tiles, resize_grid, Diffusing, DiffusionGrid},
+    items::clothes::ClothingHolder,
+};
+
+#[cfg(feature = "client")]
+use {
+    crate::{ui::has_window, GameState},
+    bevy_egui::{egui, EguiContexts},
+    networking::messaging::MessageEvent,
+};
+
+/// Per tile temperature that spreads between open tiles and hurts creatures outside a safe range.
+pub struct TemperaturePlugin;
+
+impl Plugin for TemperaturePlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Airtight>()
+            .register_type::<HeatSource>()
+            .register_type::<ThermalProtection>()
+            .add_network_message::<TemperatureExposureMessage>("TemperatureExposureMessage");
+
+        if is_server(app) {
+            app.init_resource::<TemperatureGrid>()
+                .add_event::<ThermalDamageEvent>()
+                .add_systems(
+                    Update,
+                    (
+                        (
+                            resize_grid::<Heat>,
+                            disturb_changed_tiles::<Heat>,
+                            apply_heat_sources,
+                            diffuse::<Heat>,
+                        )
+                            .chain(),
+                        expose_bodies,
+                    ),
+                );
+        } else {
+            #[cfg(feature = "client")]
+            app.init_resource::<ExposureHud>().add_systems(
+                Update,
+                (
+                    receive_exposure,
+                    temperature_hud
+                        .run_if(in_state(GameState::Game))
+                        .run_if(has_window),
+                ),
+            );
+        }
+    }
+}
+
+/// Temperature of air on the station, in kelvin
+pub const ROOM_TEMPERATURE: f32 = 293.15;
+/// Space tiles are always this cold
+pub const SPACE_TEMPERATURE: f32 = 2.7;
+/// Creatures are not hurt between these temperatures
+const SAFE_MIN: f32 = 260.0;
+const SAFE_MAX: f32 = 330.0;
+const EXPOSURE_INTERVAL: f32 = 1.0;
+/// Damage per second for every kelvin outside the safe range
+const BURN_RATE: f32 = 0.0005;
+const COLD_RATE: f32 = 0.0003;
+/// How much the reported exposure has to change before the player is told
+const EXPOSURE_REPORT_STEP: f32 = 0.5;
+
+/// Blocks heat and air from spreading through the tile. Used on walls and windows.
+/// Doors block it while closed.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct Airtight;
+
+/// Heats (or cools) the tile the object is on towards a temperature.
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct HeatSource {
+    /// Temperature in kelvin the tile is moved towards
+    pub target: f32,
+    /// Kelvin per second the tile temperature changes
+    pub power: f32,
+}
+
+impl Default for HeatSource {
+    fn default() -> Self {
+        Self {
+            target: ROOM_TEMPERATURE,
+            power: 0.0,
+        }
+    }
+}
+
+/// Protects the wearer from extreme temperatures.
+/// Values of all worn clothing are added up, 1 is full protection.
+#[derive(Component, Reflect, Default, Clone, Copy)]
+#[reflect(Component)]
+pub struct ThermalProtection {
+    pub heat: f32,
+    pub cold: f32,
+}
+
+impl ThermalProtection {
+    /// Fraction of damage that gets through the protection.
+    fn damage_factor(protection: f32) -> f32 {
+        1.0 - protection.clamp(0.0, 1.0)
+    }
+}
+
+impl std::ops::Add for ThermalProtection {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            heat: self.heat + other.heat,
+            cold: self.cold + other.cold,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize)]
+pub enum ThermalDamageKind {
+    Burn,
+    Cold,
+}
+
+/// Damage per second a creature wearing `worn` takes at the temperature.
+fn exposure_rate(temperature: f32, worn: ThermalProtection) -> Option<(ThermalDamageKind, f32)> {
+    if temperature > SAFE_MAX {
+        Some((
+            ThermalDamageKind::Burn,
+            (temperature - SAFE_MAX) * BURN_RATE * ThermalProtection::damage_factor(worn.heat),
+        ))
+    } else if temperature < SAFE_MIN {
+        Some((
+            ThermalDamageKind::Cold,
+            (SAFE_MIN - temperature) * COLD_RATE * ThermalProtection::damage_factor(worn.cold),
+        ))
+    } else {
+        None
+    }
+}
+
+/// A body took damage from the temperature around it.
+#[derive(Event)]
+pub struct ThermalDamageEvent {
+    pub body: Entity,
+    pub kind: ThermalDamageKind,
+    pub amount: f32,
+}
+
+/// The temperature a player's creature is exposed to, in kelvin.
+#[derive(Serialize, Deserialize)]
+struct TemperatureExposureMessage {
+    temperature: f32,
+}
+
+/// Heat spreading between tiles, measured in kelvin.
+pub struct Heat;
+
+impl Diffusing for Heat {
+    const INITIAL: f32 = ROOM_TEMPERATURE;
+    const SPACE: f32 = SPACE_TEMPERATURE;
+}
+
+/// Temperature of every tile on the map.
+pub type TemperatureGrid = DiffusionGrid<Heat>;
+
+impl TemperatureGrid {
+    /// Raises a tile to at least the temperature, for example when something burns on it.
+    pub fn heat(&mut self, position: UVec2, temperature: f32) {
+        if self.get(position) < temperature {
+            self.set(position, temperature);
+            self.disturb(position);
+        }
+    }
+}
+
+fn apply_heat_sources(
+    sources: Query<(&HeatSource, &GlobalTransform)>,
+    mut grid: ResMut<TemperatureGrid>,
+    time: Res<Time>,
+) {
+    let delta = time.delta_seconds();
+    for (source, transform) in sources.iter() {
+        let Some(position) = world_to_tile(transform.translation()) else {
+            continue;
+        };
+        let current = grid.get(position);
+        let change = (source.target - current).clamp(-source.power * delta, source.power * delta);
+        if change.abs() > f32::EPSILON {
+            grid.set(position, current + change);
+            grid.disturb(position);
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn expose_bodies(
+    bodies: Query<(Entity, &GlobalTransform), With<Body>>,
+    children: Query<&Children>,
+    protection: Query<(&ThermalProtection, &Parent)>,
+    holders: Query<(), With<ClothingHolder>>,
+    grid: Res<TemperatureGrid>,
+    controls: Res<ClientControls>,
+    players: Res<Players>,
+    mut damage: EventWriter<ThermalDamageEvent>,
+    mut sender: MessageSender,
+    mut last_reported: Local<HashMap<Entity, f32>>,
+    mut last_exposure: Local<f32>,
+    time: Res<Time>,
+) {
+    let now = time.elapsed_seconds();
+    if now - *last_exposure < EXPOSURE_INTERVAL {
+        return;
+    }
+    let interval = now - *last_exposure;
+    *last_exposure = now;
+
+    last_reported.retain(|entity, _| bodies.contains(*entity));
+    for (body, transform) in bodies.iter() {
+        let temperature = world_to_tile(transform.translation())
+            .map(|p| grid.get(p))
+            .unwrap_or(SPACE_TEMPERATURE);
+
+        // Only clothing that is worn protects
+        let worn = children
+            .iter_descendants(body)
+            .filter_map(|e| protection.get(e).ok())
+            .filter(|(_, parent)| holders.contains(parent.get()))
+            .fold(ThermalProtection::default(), |sum, (p, _)| sum + *p);
+
+        let exposure = exposure_rate(temperature, worn);
+        if let Some((kind, rate)) = exposure.filter(|(_, rate)| *rate > 0.0) {
+            damage.send(ThermalDamageEvent {
+                body,
+                kind,
+                amount: rate * interval,
+            });
+        }
+
+        let Some(connection) = controls
+            .controlling_player(body)
+            .and_then(|p| players.get_connection(&p))
+        else {
+            continue;
+        };
+        let changed = last_reported.get(&body).map_or(true, |last| {
+            (last - temperature).abs() >= EXPOSURE_REPORT_STEP
+        });
+        if changed {
+            last_reported.insert(body, temperature);
+            sender.send(
+                &TemperatureExposureMessage { temperature },
+                MessageReceivers::Single(connection),
+            );
+        }
+    }
+}
+
+/// The temperature around the player's creature.
+#[cfg(feature = "client")]
+#[derive(Resource, Default)]
+struct ExposureHud {
+    temperature: Option<f32>,
+}
+
+#[cfg(feature = "client")]
+fn receive_exposure(
+    mut messages: EventReader<MessageEvent<TemperatureExposureMessage>>,
+    mut hud: ResMut<ExposureHud>,
+) {
+    for event in messages.iter() {
+        hud.temperature = Some(event.message.temperature);
+    }
+}
+
+#[cfg(feature = "client")]
+fn temperature_hud(mut contexts: EguiContexts, hud: Res<ExposureHud>) {
+    let Some(temperature) = hud.temperature else {
+        return;
+    };
+
+    let color = if temperature > SAFE_MAX {
+        egui::Color32::from_rgb(255, 110, 40)
+    } else if temperature < SAFE_MIN {
+        egui::Color32::from_rgb(90, 170, 255)
+    } else {
+        egui::Color32::WHITE
+    };
+    egui::Area::new("temperature_hud")
+        .anchor(egui::Align2::RIGHT_TOP, egui::vec2(-10.0, 10.0))
+        .show(contexts.ctx_mut(), |ui| {
+            ui.label(
+                egui::RichText::new(format!("{:.0} °C", temperature - 273.15))
+                    .color(color)
+                    .size(16.0),
+            );
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::time::TimeUpdateStrategy;
+    use maps::{TileMap, TileReference, CHUNK_SIZE};
+
+    use super::*;
+    use crate::diffusion::SIMULATION_INTERVAL;
+
+    /// A room with floor from (2, 2) to (5, 5), surrounded by walls and space.
+    fn app() -> App {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f32(
+                SIMULATION_INTERVAL,
+            )))
+            .init_resource::<TemperatureGrid>()
+            .add_systems(
+                Update,
+                (resize_grid::<Heat>, apply_heat_sources, diffuse::<Heat>).chain(),
+            );
+
+        let floor = app.world.spawn_empty().id();
+        let wall = app.world.spawn(Airtight).id();
+        let mut map = TileMap::new(UVec2::ONE);
+        for x in 1..=6 {
+            for y in 1..=6 {
+                let edge = x == 1 || x == 6 || y == 1 || y == 6;
+                let tile = TileReference {
+                    turf: Some(if edge { wall } else { floor }),
+                    ..Default::default()
+                };
+                map.set_tile(UVec2::new(x, y), tile).unwrap();
+            }
+        }
+        app.world.spawn(map);
+        app.update();
+        app
+    }
+
+    fn room() -> impl Iterator<Item = UVec2> {
+        (2..=5).flat_map(|x| (2..=5).map(move |y| UVec2::new(x, y)))
+    }
+
+    fn run(app: &mut App, seconds: f32) {
+        for _ in 0..(seconds / SIMULATION_INTERVAL) as u32 {
+            app.update();
+        }
+    }
+
+    #[test]
+    fn hot_spot_evens_out() {
+        let mut app = app();
+        let mut grid = app.world.resource_mut::<TemperatureGrid>();
+        grid.set(UVec2::new(2, 2), ROOM_TEMPERATURE + 160.0);
+        grid.disturb(UVec2::new(2, 2));
+        run(&mut app, 120.0);
+
+        let grid = app.world.resource::<TemperatureGrid>();
+        let temperatures: Vec<_> = room().map(|p| grid.get(p)).collect();
+        let min = temperatures.iter().copied().fold(f32::MAX, f32::min);
+        let max = temperatures.iter().copied().fold(f32::MIN, f32::max);
+        assert!(max - min < 1.0, "{:?}", temperatures);
+        // The heat is shared out, not lost through the walls
+        let mean = temperatures.iter().sum::<f32>() / temperatures.len() as f32;
+        assert!(
+            (mean - (ROOM_TEMPERATURE + 10.0)).abs() < 1.0,
+            "mean {}",
+            mean
+        );
+        assert_eq!(grid.get(UVec2::new(8, 8)), SPACE_TEMPERATURE);
+    }
+
+    #[test]
+    fn untouched_room_stays_at_room_temperature() {
+        let mut app = app();
+        run(&mut app, 30.0);
+
+        let grid = app.world.resource::<TemperatureGrid>();
+        for position in room() {
+            assert_eq!(grid.get(position), ROOM_TEMPERATURE, "{}", position);
+        }
+    }
+
+    #[test]
+    fn heater_warms_sealed_room() {
+        let mut app = app();
+        app.world.spawn((
+            HeatSource {
+                target: 400.0,
+                power: 20.0,
+            },
+            GlobalTransform::from_translation(Vec3::new(2.0, 0.0, 2.0)),
+        ));
+        run(&mut app, 120.0);
+
+        let grid = app.world.resource::<TemperatureGrid>();
+        let heater = grid.get(UVec2::new(2, 2));
+        // The corner furthest from the heater warms up too
+        let far = grid.get(UVec2::new(5, 5));
+        assert!(far > ROOM_TEMPERATURE + 20.0, "far corner at {}", far);
+        assert!(
+            heater >= far && heater <= 400.0,
+            "heater tile at {}",
+            heater
+        );
+        // Walls keep the heat in the room
+        for position in [
+            UVec2::new(0, 2),
+            UVec2::new(7, 5),
+            UVec2::new(3, CHUNK_SIZE - 1),
+        ] {
+            assert_eq!(grid.get(position), SPACE_TEMPERATURE, "{}", position);
+        }
+    }
+
+    #[test]
+    fn protection_reduces_damage() {
+        let none = ThermalProtection::default();
+        let (kind, unprotected) = exposure_rate(SAFE_MAX + 100.0, none).unwrap();
+        assert_eq!(kind, ThermalDamageKind::Burn);
+        assert!((unprotected - 100.0 * BURN_RATE).abs() < 1e-6);
+
+        let suit = ThermalProtection {
+            heat: 0.5,
+            cold: 0.0,
+        };
+        let (_, protected) = exposure_rate(SAFE_MAX + 100.0, suit).unwrap();
+        assert!((protected - unprotected / 2.0).abs() < 1e-6);
+        // Heat protection does nothing against the cold
+        let (kind, cold) = exposure_rate(SAFE_MIN - 100.0, suit).unwrap();
+        assert_eq!(kind, ThermalDamageKind::Cold);
+        assert!((cold - 100.0 * COLD_RATE).abs() < 1e-6);
+
+        assert_eq!(exposure_rate(ROOM_TEMPERATURE, none), None);
+    }
+
+    #[test]
+    fn worn_pieces_add_up_to_full_protection() {
+        let piece = ThermalProtection {
+            heat: 0.6,
+            cold: 0.3,
+        };
+        let worn = piece + piece;
+        let (_, burn) = exposure_rate(SAFE_MAX + 100.0, worn).unwrap();
+        assert_eq!(burn, 0.0);
+        let (_, cold) = exposure_rate(SAFE_MIN - 100.0, worn).unwrap();
+        assert!((cold - 0.4 * 100.0 * COLD_RATE).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,124 @@
+//! Headless game servers for tests. Clients are joined with [`networking::testing`].
+
+use std::sync::{Arc, Mutex};
+
+use bevy::{
+    prelude::*,
+    utils::tracing::{
+        field::{Field, Visit},
+        Event, Level, Subscriber,
+    },
+};
+use networking::{NetworkRole, NetworkingPlugin};
+use tracing_subscriber::{
+    layer::{Context, SubscriberExt},
+    Layer,
+};
+
+use crate::{add_game_plugins, add_server_plugins, config::ServerConfig, resource_packs};
+
+/// A server with every game plugin, built like a hosted one.
+/// No map is loaded, tests spawn what they need.
+pub fn server_app(config: ServerConfig) -> App {
+    server_app_with(config, |_| {})
+}
+
+/// Like [`server_app`], with `setup` called before the plugins are added.
+/// Used for resources plugins look at while they are built, like a replay recorder.
+pub fn server_app_with(config: ServerConfig, setup: impl FnOnce(&mut App)) -> App {
+    let mut app = App::new();
+    resource_packs::install_asset_io(&mut app);
+    app.insert_resource(config.link_quality.clone())
+        .insert_resource(config);
+    setup(&mut app);
+    add_server_plugins(
+        &mut app,
+        NetworkingPlugin {
+            role: NetworkRole::Server,
+        },
+    );
+    add_game_plugins(&mut app);
+    app
+}
+
+/// A log event seen by [`capture_logs`].
+pub struct CapturedLog {
+    pub level: Level,
+    pub message: String,
+    /// The other fields, formatted like the log output shows them
+    pub fields: Vec<(&'static str, String)>,
+}
+
+impl CapturedLog {
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(field, _)| *field == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Runs `f` and returns everything logged meanwhile.
+/// Only events logged on the calling thread are seen, so systems under test have to run
+/// with a single threaded executor.
+pub fn capture_logs<T>(f: impl FnOnce() -> T) -> (T, Vec<CapturedLog>) {
+    let layer = CaptureLayer::default();
+    let subscriber = tracing_subscriber::registry().with(layer.clone());
+    let result = bevy::utils::tracing::subscriber::with_default(subscriber, f);
+    let logs = std::mem::take(&mut *layer.0.lock().unwrap());
+    (result, logs)
+}
+
+/// Like [`capture_logs`], only returning the messages of warnings.
+pub fn capture_warnings<T>(f: impl FnOnce() -> T) -> (T, Vec<String>) {
+    let (result, logs) = capture_logs(f);
+    let warnings = logs
+        .into_iter()
+        .filter(|log| log.level == Level::WARN)
+        .map(|log| log.message)
+        .collect();
+    (result, warnings)
+}
+
+#[derive(Clone, Default)]
+struct CaptureLayer(Arc<Mutex<Vec<CapturedLog>>>);
+
+impl<S: Subscriber> Layer<S> for CaptureLayer {
+    fn on_event(&self, event: &Event<'_>, _: Context<'_, S>) {
+        let mut log = CapturedLog {
+            level: *event.metadata().level(),
+            message: String::new(),
+            fields: Vec::new(),
+        };
+        event.record(&mut log);
+        self.0.lock().unwrap().push(log);
+    }
+}
+
+impl Visit for CapturedLog {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "message" => self.message = value.to_owned(),
+            name => self.fields.push((name, value.to_owned())),
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        let value = format!("{:?}", value);
+        match field.name() {
+            "message" => self.message = value,
+            name => self.fields.push((name, value)),
+        }
+    }
+}
+
+#[test]
+fn message_names_dont_collide() {
+    let app = server_app(ServerConfig::default());
+    let types = app.world.resource::<networking::messaging::MessageTypes>();
+    assert!(
+        types.collisions().is_empty(),
+        "Messages with the same id: {:?}",
+        types.collisions()
+    );
+}
@@ -0,0 +1,555 @@
+use std::{collections::VecDeque, fmt};
+
+use bevy::{ecs::system::SystemParam, prelude::*, utils::HashMap};
+use networking::{is_server, ConnectionId, ServerEvent};
+use serde::Deserialize;
+
+use crate::{
+    communication::SystemMessageEvent,
+    config::ServerConfig,
+    items::{labels::MAX_LABEL_LENGTH, paper::MAX_WRITE_LENGTH},
+    machines::command::MAX_ANNOUNCEMENT_LENGTH,
+    profile::MAX_CHARACTER_NAME_LENGTH,
+};
+
+/// Checks and cleans up all text players send to the server before it is used, logged or shown to others.
+pub struct TextFilterPlugin;
+
+impl Plugin for TextFilterPlugin {
+    fn build(&self, app: &mut App) {
+        if !is_server(app) {
+            return;
+        }
+
+        let config = app
+            .world
+            .get_resource::<ServerConfig>()
+            .map(|config| config.text_filter.clone())
+            .unwrap_or_default();
+        app.insert_resource(SpamLimiter::new(&config))
+            .insert_resource(TextFilter::new(config))
+            .add_systems(Update, forget_disconnected);
+    }
+}
+
+/// Where player provided text is used. Each has its own length limit.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TextContext {
+    Chat,
+    Label,
+    Paper,
+    CharacterName,
+    /// Typed on a command console
+    Announcement,
+}
+
+impl TextContext {
+    fn default_max_length(self) -> usize {
+        match self {
+            TextContext::Chat => 512,
+            TextContext::Label => MAX_LABEL_LENGTH,
+            TextContext::Paper => MAX_WRITE_LENGTH,
+            TextContext::CharacterName => MAX_CHARACTER_NAME_LENGTH,
+            TextContext::Announcement => MAX_ANNOUNCEMENT_LENGTH,
+        }
+    }
+
+    /// Paper is the only place where line breaks are kept
+    fn allows_line_breaks(self) -> bool {
+        self == TextContext::Paper
+    }
+}
+
+impl fmt::Display for TextContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TextContext::Chat => "message",
+            TextContext::Label => "label",
+            TextContext::Paper => "writing",
+            TextContext::CharacterName => "character name",
+            TextContext::Announcement => "announcement",
+        })
+    }
+}
+
+#[derive(Deserialize, Clone)]
+pub struct TextFilterConfig {
+    /// Words that are replaced in player text, like `badword = "****"`.
+    /// Only whole words are replaced, ignoring case.
+    #[serde(default)]
+    pub replacements: HashMap<String, String>,
+    /// Removes links from player text
+    #[serde(default)]
+    pub strip_urls: bool,
+    /// Runs of the same character are shortened to this length. 0 keeps them.
+    #[serde(default = "TextFilterConfig::default_max_repeated_characters")]
+    pub max_repeated_characters: usize,
+    /// Lower length limits (in characters) than the defaults, by context: `chat`, `label`, `paper`, `character_name` and `announcement`
+    #[serde(default)]
+    pub max_lengths: HashMap<String, usize>,
+    /// How many texts a player can send within `spam_window_seconds`. Chat, labels and paper count together.
+    #[serde(default = "TextFilterConfig::default_spam_limit")]
+    pub spam_limit: usize,
+    #[serde(default = "TextFilterConfig::default_spam_window")]
+    pub spam_window_seconds: f32,
+}
+
+impl TextFilterConfig {
+    fn default_max_repeated_characters() -> usize {
+        4
+    }
+
+    fn default_spam_limit() -> usize {
+        8
+    }
+
+    fn default_spam_window() -> f32 {
+        5.0
+    }
+}
+
+impl Default for TextFilterConfig {
+    fn default() -> Self {
+        Self {
+            replacements: Default::default(),
+            strip_urls: false,
+            max_repeated_characters: Self::default_max_repeated_characters(),
+            max_lengths: Default::default(),
+            spam_limit: Self::default_spam_limit(),
+            spam_window_seconds: Self::default_spam_window(),
+        }
+    }
+}
+
+/// An additional check on player text, like an external moderation service.
+/// Returns the text to use, or why it was rejected.
+pub trait TextCheck: Send + Sync + 'static {
+    fn check(&self, context: TextContext, text: String) -> Result<String, String>;
+}
+
+/// Cleans up player text according to the server config.
+#[derive(Resource)]
+pub struct TextFilter {
+    strip_urls: bool,
+    max_repeated_characters: usize,
+    max_lengths: HashMap<String, usize>,
+    checks: Vec<Box<dyn TextCheck>>,
+}
+
+impl TextFilter {
+    pub fn new(config: TextFilterConfig) -> Self {
+        let mut filter = Self {
+            strip_urls: config.strip_urls,
+            max_repeated_characters: config.max_repeated_characters,
+            max_lengths: config.max_lengths,
+            checks: Vec::new(),
+        };
+        if !config.replacements.is_empty() {
+            filter.add_check(WordReplacements(
+                config
+                    .replacements
+                    .into_iter()
+                    .map(|(word, replacement)| (word.to_lowercase(), replacement))
+                    .collect(),
+            ));
+        }
+        filter
+    }
+
+    /// Adds a check that runs after the length, link and repetition filters.
+    pub fn add_check(&mut self, check: impl TextCheck) {
+        self.checks.push(Box::new(check));
+    }
+
+    pub fn max_length(&self, context: TextContext) -> usize {
+        let key = match context {
+            TextContext::Chat => "chat",
+            TextContext::Label => "label",
+            TextContext::Paper => "paper",
+            TextContext::CharacterName => "character_name",
+            TextContext::Announcement => "announcement",
+        };
+        let default = context.default_max_length();
+        self.max_lengths
+            .get(key)
+            .map_or(default, |&max| max.min(default))
+    }
+
+    /// Returns the cleaned up text, or why it can't be used.
+    /// Empty text is allowed, as some contexts use it to clear something.
+    pub fn filter(&self, context: TextContext, text: &str) -> Result<String, String> {
+        let mut text: String = text
+            .trim()
+            .chars()
+            .filter(|&c| (c == '\n' && context.allows_line_breaks()) || !c.is_control())
+            .collect();
+
+        let max_length = self.max_length(context);
+        if text.chars().count() > max_length {
+            return Err(format!("too long, the limit is {} characters", max_length));
+        }
+
+        if self.strip_urls {
+            text = strip_urls(&text);
+        }
+        if self.max_repeated_characters > 0 {
+            text = collapse_repeats(&text, self.max_repeated_characters);
+        }
+
+        for check in self.checks.iter() {
+            text = check.check(context, text)?;
+        }
+        Ok(text)
+    }
+}
+
+/// Replaces whole words, keyed by their lowercase spelling.
+struct WordReplacements(HashMap<String, String>);
+
+impl TextCheck for WordReplacements {
+    fn check(&self, _: TextContext, text: String) -> Result<String, String> {
+        let mut result = String::with_capacity(text.len());
+        let mut word_start = None;
+        for (index, c) in text.char_indices().chain([(text.len(), ' ')]) {
+            match (c.is_alphanumeric(), word_start) {
+                (true, None) => word_start = Some(index),
+                (false, Some(start)) => {
+                    let word = &text[start..index];
+                    match self.0.get(&word.to_lowercase()) {
+                        Some(replacement) => result.push_str(replacement),
+                        None => result.push_str(word),
+                    }
+                    word_start = None;
+                }
+                _ => {}
+            }
+            if !c.is_alphanumeric() && index < text.len() {
+                result.push(c);
+            }
+        }
+        Ok(result)
+    }
+}
+
+fn strip_urls(text: &str) -> String {
+    let is_url = |word: &str| {
+        let word = word.to_lowercase();
+        word.contains("://") || word.starts_with("www.")
+    };
+    // Keeps the line structure, but not the exact spacing around removed links
+    text.split('\n')
+        .map(|line| {
+            line.split(' ')
+                .filter(|word| !is_url(word))
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn collapse_repeats(text: &str, max: usize) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut last = None;
+    let mut count = 0;
+    for c in text.chars() {
+        if Some(c) == last {
+            count += 1;
+        } else {
+            last = Some(c);
+            count = 1;
+        }
+        if count <= max {
+            result.push(c);
+        }
+    }
+    result
+}
+
+/// Limits how much text each connection can send, over all contexts together.
+/// Otherwise someone could get around the chat limit by writing labels instead.
+#[derive(Resource)]
+pub struct SpamLimiter {
+    limit: usize,
+    window: f32,
+    /// When each connection sent text recently, oldest first
+    recent: HashMap<ConnectionId, VecDeque<f32>>,
+}
+
+impl SpamLimiter {
+    fn new(config: &TextFilterConfig) -> Self {
+        Self {
+            limit: config.spam_limit,
+            window: config.spam_window_seconds,
+            recent: Default::default(),
+        }
+    }
+
+    /// Records that the connection sent text. Returns false if it sent too much recently.
+    pub fn allow(&mut self, connection: ConnectionId, now: f32) -> bool {
+        let recent = self.recent.entry(connection).or_default();
+        while recent.front().is_some_and(|&t| now - t >= self.window) {
+            recent.pop_front();
+        }
+        if recent.len() >= self.limit {
+            return false;
+        }
+        recent.push_back(now);
+        true
+    }
+}
+
+fn forget_disconnected(mut events: EventReader<ServerEvent>, mut limiter: ResMut<SpamLimiter>) {
+    for event in events.iter() {
+        if let ServerEvent::PlayerDisconnected(connection) = event {
+            limiter.recent.remove(connection);
+        }
+    }
+}
+
+/// Everything needed to accept text from a player.
+/// Rejected text is reported back to the player.
+#[derive(SystemParam)]
+pub struct PlayerText<'w> {
+    filter: Res<'w, TextFilter>,
+    limiter: ResMut<'w, SpamLimiter>,
+    time: Res<'w, Time>,
+    feedback: EventWriter<'w, SystemMessageEvent>,
+}
+
+impl<'w> PlayerText<'w> {
+    /// Returns the text to use, or None if the player was told why it was rejected.
+    pub fn accept(
+        &mut self,
+        connection: ConnectionId,
+        context: TextContext,
+        text: &str,
+    ) -> Option<String> {
+        if !self.limiter.allow(connection, self.time.elapsed_seconds()) {
+            debug!(connection = ?connection, ?context, "Text rate limited");
+            self.reject(connection, context, "you are sending text too quickly");
+            return None;
+        }
+
+        match self.filter.filter(context, text) {
+            Ok(text) => Some(text),
+            Err(reason) => {
+                debug!(connection = ?connection, ?context, reason = reason.as_str(), "Text rejected");
+                self.reject(connection, context, &reason);
+                None
+            }
+        }
+    }
+
+    fn reject(&mut self, connection: ConnectionId, context: TextContext, reason: &str) {
+        self.feedback.send(SystemMessageEvent {
+            receiver: connection,
+            text: format!("Your {} was not accepted: {}", context, reason),
+        });
+    }
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use bevy::ecs::{event::ManualEventReader, system::SystemState};
+    use networking::{loopback::LinkConditions, testing, NetworkRole, Players};
+
+    use super::*;
+    use crate::testing::server_app;
+
+    const CONTEXTS: [TextContext; 5] = [
+        TextContext::Chat,
+        TextContext::Label,
+        TextContext::Paper,
+        TextContext::CharacterName,
+        TextContext::Announcement,
+    ];
+
+    #[test]
+    fn every_context_has_its_length_limit() {
+        let filter = TextFilter::new(TextFilterConfig::default());
+        for context in CONTEXTS {
+            let max = context.default_max_length();
+            // Varied text, so it isn't shortened as repeated characters
+            let text: String = "abcdefghij".chars().cycle().take(max).collect();
+            assert_eq!(filter.filter(context, &text).as_deref(), Ok(text.as_str()));
+
+            let too_long = format!("{}k", text);
+            assert_eq!(
+                filter.filter(context, &too_long),
+                Err(format!("too long, the limit is {} characters", max)),
+                "{:?}",
+                context
+            );
+        }
+    }
+
+    #[test]
+    fn configured_limits_only_lower_the_default() {
+        let filter = TextFilter::new(TextFilterConfig {
+            max_lengths: [("label".to_owned(), 5), ("chat".to_owned(), 100_000)]
+                .into_iter()
+                .collect(),
+            ..Default::default()
+        });
+        assert_eq!(filter.max_length(TextContext::Label), 5);
+        assert!(filter.filter(TextContext::Label, "sixsix").is_err());
+        assert_eq!(
+            filter.max_length(TextContext::Chat),
+            TextContext::Chat.default_max_length()
+        );
+        assert_eq!(
+            filter.max_length(TextContext::Paper),
+            TextContext::Paper.default_max_length()
+        );
+    }
+
+    #[test]
+    fn only_paper_keeps_line_breaks() {
+        let filter = TextFilter::new(TextFilterConfig::default());
+        for context in CONTEXTS {
+            let expected = match context {
+                TextContext::Paper => "first\nsecond",
+                _ => "firstsecond",
+            };
+            assert_eq!(
+                filter.filter(context, "  first\n\u{7}second\t").unwrap(),
+                expected,
+                "{:?}",
+                context
+            );
+        }
+    }
+
+    #[test]
+    fn text_is_cleaned_up() {
+        let filter = TextFilter::new(TextFilterConfig {
+            replacements: [("Heck".to_owned(), "****".to_owned())]
+                .into_iter()
+                .collect(),
+            strip_urls: true,
+            max_repeated_characters: 3,
+            ..Default::default()
+        });
+        assert_eq!(
+            filter
+                .filter(
+                    TextContext::Chat,
+                    "HECK, see https://example.com or www.example.com"
+                )
+                .unwrap(),
+            "****, see or"
+        );
+        // Only whole words are replaced
+        assert_eq!(
+            filter.filter(TextContext::Chat, "checking heck").unwrap(),
+            "checking ****"
+        );
+        assert_eq!(
+            filter.filter(TextContext::Label, "nooooooo!!!!!").unwrap(),
+            "nooo!!!"
+        );
+    }
+
+    struct RejectShouting;
+
+    impl TextCheck for RejectShouting {
+        fn check(&self, context: TextContext, text: String) -> Result<String, String> {
+            if context == TextContext::Chat && !text.chars().any(|c| c.is_lowercase()) {
+                return Err("no shouting".into());
+            }
+            Ok(text)
+        }
+    }
+
+    #[test]
+    fn added_checks_can_reject_text() {
+        let mut filter = TextFilter::new(TextFilterConfig::default());
+        filter.add_check(RejectShouting);
+        assert_eq!(
+            filter.filter(TextContext::Chat, "HELLO"),
+            Err("no shouting".into())
+        );
+        assert!(filter.filter(TextContext::Chat, "hello").is_ok());
+        assert!(filter.filter(TextContext::Label, "HELLO").is_ok());
+    }
+
+    /// A server with two connected players, returning their connections.
+    fn connected() -> (App, Vec<ConnectionId>) {
+        let mut server = server_app(ServerConfig::default());
+        let mut first = testing::app(NetworkRole::Client);
+        let mut second = testing::app(NetworkRole::Client);
+        let connector = testing::listen(&mut server);
+        testing::join(&mut first, &connector, LinkConditions::default());
+        testing::join(&mut second, &connector, LinkConditions::default());
+        testing::connect(&mut server, &mut [&mut first, &mut second], 200);
+        let connections = server
+            .world
+            .resource::<Players>()
+            .players()
+            .keys()
+            .copied()
+            .collect();
+        (server, connections)
+    }
+
+    #[test]
+    fn spam_limit_is_shared_between_contexts() {
+        let (mut server, connections) = connected();
+        let (spammer, other) = (connections[0], connections[1]);
+        let mut state = SystemState::<PlayerText>::new(&mut server.world);
+        let mut text = state.get_mut(&mut server.world);
+
+        let limit = TextFilterConfig::default_spam_limit();
+        for i in 0..limit {
+            let context = [TextContext::Chat, TextContext::Label, TextContext::Paper][i % 3];
+            assert!(text.accept(spammer, context, "hello").is_some());
+        }
+        // Switching to labels doesn't get around the limit
+        assert!(text.accept(spammer, TextContext::Label, "hello").is_none());
+        assert!(text.accept(spammer, TextContext::Chat, "hello").is_none());
+        // Other players aren't affected
+        assert!(text.accept(other, TextContext::Chat, "hello").is_some());
+
+        // Allowed again once the window has passed
+        let now = server.world.resource::<Time>().elapsed_seconds();
+        let window = TextFilterConfig::default_spam_window();
+        let mut limiter = server.world.resource_mut::<SpamLimiter>();
+        assert!(!limiter.allow(spammer, now + window / 2.0));
+        assert!(limiter.allow(spammer, now + window + 0.1));
+    }
+
+    #[test]
+    fn rejected_text_is_reported_to_the_sender() {
+        let (mut server, connections) = connected();
+        let mut state = SystemState::<PlayerText>::new(&mut server.world);
+        let mut text = state.get_mut(&mut server.world);
+        let too_long = "abcdefghij".repeat(MAX_LABEL_LENGTH);
+        assert_eq!(
+            text.accept(connections[0], TextContext::Label, &too_long),
+            None
+        );
+        assert_eq!(
+            text.accept(connections[0], TextContext::Label, "fine")
+                .as_deref(),
+            Some("fine")
+        );
+
+        let events = server.world.resource::<Events<SystemMessageEvent>>();
+        let sent: Vec<_> = ManualEventReader::<SystemMessageEvent>::default()
+            .iter(events)
+            .filter(|event| event.text.contains("not accepted"))
+            .map(|event| (event.receiver, event.text.clone()))
+            .collect();
+        assert_eq!(
+            sent,
+            [(
+                connections[0],
+                format!(
+                    "Your label was not accepted: too long, the limit is {} characters",
+                    MAX_LABEL_LENGTH
+                )
+            )]
+        );
+    }
+}
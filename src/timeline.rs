@@ -0,0 +1,583 @@
+use std::path::Path;
+
+use bevy::{prelude::*, utils::HashSet};
+use maps::TileMap;
+use networking::{
+    is_server,
+    messaging::{AppExt, MessageEvent, MessageReceivers, MessageSender},
+    scene::NetworkSceneBundle,
+    ConnectionId, Players,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    communication::AnnouncementEvent,
+    config::ServerConfig,
+    device_link::{DeviceSignal, SignalKind},
+    gravity::Gravity,
+    random_event::{RandomEvents, StartRandomEvent},
+    replay::{read_config_file, RecordedFiles},
+    round::RoundState,
+    shuttle::CallShuttle,
+    sound::{PlayMusicEvent, TrackId},
+};
+
+#[cfg(feature = "client")]
+use {
+    crate::{ui::has_window, GameState},
+    bevy_egui::{egui, EguiContexts},
+};
+
+pub struct TimelinePlugin;
+
+impl Plugin for TimelinePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_network_message::<TimelineCommand>("TimelineCommand")
+            .add_network_message::<TimelineServerMessage>("TimelineServerMessage");
+
+        if is_server(app) {
+            app.init_resource::<Timeline>()
+                .add_systems(OnEnter(RoundState::Running), load_timeline)
+                .add_systems(
+                    Update,
+                    (
+                        run_timeline.run_if(in_state(RoundState::Running)),
+                        handle_timeline_commands,
+                    ),
+                );
+        } else {
+            #[cfg(feature = "client")]
+            app.init_resource::<ClientTimeline>().add_systems(
+                Update,
+                (
+                    client_receive_timeline,
+                    timeline_ui
+                        .run_if(in_state(GameState::Game))
+                        .run_if(has_window),
+                ),
+            );
+        }
+    }
+}
+
+pub(crate) const DEFAULT_TIMELINE_FILE: &str = "timeline.ron";
+
+/// An event scheduled to happen at a point in the round.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TimelineEntry {
+    /// Seconds after round start
+    pub at_seconds: f32,
+    pub event: TimelineEvent,
+}
+
+/// Actions a timeline can perform.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum TimelineEvent {
+    /// Send an announcement to all players
+    Announce(String),
+    /// Spawn a scene at a landmark. The prefab is a scene path without extension (ex. "items/wrench").
+    SpawnPrefab { prefab: String, landmark: String },
+    /// Call the evacuation shuttle, which ends the round if it departs with players aboard
+    CallShuttle { departs_in: f32 },
+    /// Play music for everyone
+    PlayMusic { track: TrackId, fade_in: f32 },
+    /// Turn gravity on or off, on the whole station or in an area (ex. "/area/engine")
+    SetGravity { enabled: bool, area: Option<String> },
+    /// Send a signal to every device linked to a channel
+    Signal { channel: String, kind: SignalKind },
+    /// Start a registered random event by its id (ex. "carp_migration")
+    RandomEvent { id: String },
+}
+
+impl std::fmt::Display for TimelineEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TimelineEvent::Announce(text) => write!(f, "announce \"{}\"", text),
+            TimelineEvent::SpawnPrefab { prefab, landmark } => {
+                write!(f, "spawn {} at {}", prefab, landmark)
+            }
+            TimelineEvent::CallShuttle { departs_in } => {
+                write!(f, "call shuttle (departs in {:.0}s)", departs_in)
+            }
+            TimelineEvent::PlayMusic { track, .. } => write!(f, "play music {}", track),
+            TimelineEvent::SetGravity { enabled, area } => {
+                let state = if *enabled { "on" } else { "off" };
+                match area {
+                    Some(area) => write!(f, "gravity {} in {}", state, area),
+                    None => write!(f, "gravity {}", state),
+                }
+            }
+            TimelineEvent::Signal { channel, kind } => {
+                write!(f, "signal {} on {}", kind, channel)
+            }
+            TimelineEvent::RandomEvent { id } => write!(f, "start event {}", id),
+        }
+    }
+}
+
+#[derive(Resource, Default)]
+struct Timeline {
+    /// Entries which haven't happened yet, sorted by time
+    pending: Vec<TimelineEntry>,
+    /// When the round started, in seconds since app startup
+    started: f32,
+}
+
+/// Admin commands to inspect and change the timeline
+#[derive(Serialize, Deserialize)]
+enum TimelineCommand {
+    List,
+    Skip(usize),
+    /// Execute an event right now
+    Inject(TimelineEvent),
+}
+
+#[derive(Serialize, Deserialize)]
+enum TimelineServerMessage {
+    Pending(Vec<TimelineEntry>),
+    Log(String),
+}
+
+/// Finds the position of a named landmark.
+/// Job spawn points can be used as landmarks.
+fn landmark_position(maps: &Query<&TileMap>, landmark: &str) -> Option<Vec3> {
+    maps.iter().find_map(|map| {
+        map.job_spawn_positions
+            .get(landmark)
+            .and_then(|positions| positions.first())
+            .map(|p| Vec3::new(p.x as f32, 1.0, p.y as f32))
+    })
+}
+
+fn prefab_path(prefab: &str) -> String {
+    format!("{}.scn.ron", prefab)
+}
+
+/// Checks that everything an event refers to exists.
+fn validate_event(
+    event: &TimelineEvent,
+    maps: &Query<&TileMap>,
+    random_events: &RandomEvents,
+) -> Result<(), String> {
+    match event {
+        TimelineEvent::Announce(_)
+        | TimelineEvent::CallShuttle { .. }
+        | TimelineEvent::PlayMusic { .. }
+        | TimelineEvent::SetGravity { .. }
+        | TimelineEvent::Signal { .. } => Ok(()),
+        TimelineEvent::SpawnPrefab { prefab, landmark } => {
+            if !Path::new("assets").join(prefab_path(prefab)).exists() {
+                return Err(format!("unknown prefab {}", prefab));
+            }
+            if landmark_position(maps, landmark).is_none() {
+                return Err(format!("unknown landmark {}", landmark));
+            }
+            Ok(())
+        }
+        TimelineEvent::RandomEvent { id } => match random_events.get(id) {
+            Some(_) => Ok(()),
+            None => Err(format!("unknown random event {}", id)),
+        },
+    }
+}
+
+/// Timeline logs and upcoming events are only shown to admins
+fn admin_receivers(players: &Players, config: &ServerConfig) -> MessageReceivers {
+    let admins: HashSet<ConnectionId> = players
+        .players()
+        .iter()
+        .filter(|(_, player)| config.admins.contains(&player.id))
+        .map(|(&connection, _)| connection)
+        .collect();
+    MessageReceivers::Set(admins)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn load_timeline(
+    mut timeline: ResMut<Timeline>,
+    maps: Query<&TileMap>,
+    random_events: Res<RandomEvents>,
+    time: Res<Time>,
+    recorded: Option<Res<RecordedFiles>>,
+    players: Res<Players>,
+    config: Res<ServerConfig>,
+    mut sender: MessageSender,
+) {
+    timeline.pending.clear();
+    timeline.started = time.elapsed_seconds();
+
+    let text = match read_config_file(recorded.as_deref(), DEFAULT_TIMELINE_FILE) {
+        Ok(t) => t,
+        Err(_) => {
+            info!("No round timeline configured");
+            return;
+        }
+    };
+
+    let entries: Vec<TimelineEntry> = match ron::from_str(&text) {
+        Ok(e) => e,
+        Err(err) => {
+            error!(error = %err, "Error parsing {}", DEFAULT_TIMELINE_FILE);
+            return;
+        }
+    };
+
+    // Report invalid entries individually, so one mistake doesn't cancel the whole timeline
+    for (index, entry) in entries.into_iter().enumerate() {
+        match validate_event(&entry.event, &maps, &random_events) {
+            Ok(_) => timeline.pending.push(entry),
+            Err(err) => {
+                warn!(
+                    entry = index,
+                    error = err.as_str(),
+                    "Skipping invalid timeline entry"
+                );
+                sender.send(
+                    &TimelineServerMessage::Log(format!("Entry {} is invalid: {}", index, err)),
+                    admin_receivers(&players, &config),
+                );
+            }
+        }
+    }
+    timeline
+        .pending
+        .sort_by(|a, b| a.at_seconds.total_cmp(&b.at_seconds));
+
+    info!(entries = timeline.pending.len(), "Loaded round timeline");
+}
+
+fn execute_event(
+    event: &TimelineEvent,
+    maps: &Query<&TileMap>,
+    random_events: &RandomEvents,
+    announcements: &mut EventWriter<AnnouncementEvent>,
+    asset_server: &AssetServer,
+    commands: &mut Commands,
+) -> Result<(), String> {
+    validate_event(event, maps, random_events)?;
+
+    match event {
+        TimelineEvent::Announce(text) => {
+            announcements.send(AnnouncementEvent::custom(text.clone()));
+        }
+        TimelineEvent::SpawnPrefab { prefab, landmark } => {
+            let position = landmark_position(maps, landmark).unwrap();
+            commands.spawn(NetworkSceneBundle {
+                scene: asset_server.load(prefab_path(prefab)).into(),
+                transform: Transform::from_translation(position),
+                ..Default::default()
+            });
+        }
+        TimelineEvent::CallShuttle { departs_in } => {
+            let departs_in = *departs_in;
+            commands.add(move |world: &mut World| {
+                world
+                    .resource_mut::<Events<CallShuttle>>()
+                    .send(CallShuttle { departs_in });
+            });
+        }
+        TimelineEvent::PlayMusic { track, fade_in } => {
+            let (track_id, fade_in) = (*track, *fade_in);
+            commands.add(move |world: &mut World| {
+                world
+                    .resource_mut::<Events<PlayMusicEvent>>()
+                    .send(PlayMusicEvent {
+                        track_id,
+                        fade_in,
+                        receiver: None,
+                    });
+            });
+        }
+        TimelineEvent::SetGravity { enabled, area } => {
+            let (enabled, area) = (*enabled, area.clone());
+            commands.add(move |world: &mut World| {
+                world
+                    .resource_mut::<Gravity>()
+                    .set(area.as_deref(), enabled);
+            });
+        }
+        TimelineEvent::Signal { channel, kind } => {
+            let signal = DeviceSignal {
+                channel: channel.clone(),
+                kind: *kind,
+            };
+            commands.add(move |world: &mut World| {
+                world.resource_mut::<Events<DeviceSignal>>().send(signal);
+            });
+        }
+        TimelineEvent::RandomEvent { id } => {
+            // Events that can't start right now, like a second migration, only log why
+            commands.add(StartRandomEvent { id: id.clone() });
+        }
+    }
+
+    Ok(())
+}
+
+fn log_execution(
+    event: &TimelineEvent,
+    result: Result<(), String>,
+    admins: MessageReceivers,
+    sender: &mut MessageSender,
+) {
+    let text = match result {
+        Ok(_) => {
+            info!(event = %event, "Executed timeline event");
+            format!("Executed {}", event)
+        }
+        Err(err) => {
+            warn!(event = %event, error = err.as_str(), "Failed to execute timeline event");
+            format!("Failed to execute {}: {}", event, err)
+        }
+    };
+
+    sender.send(&TimelineServerMessage::Log(text), admins);
+}
+
+#[allow(clippy::too_many_arguments)]
+fn run_timeline(
+    mut timeline: ResMut<Timeline>,
+    time: Res<Time>,
+    maps: Query<&TileMap>,
+    random_events: Res<RandomEvents>,
+    mut announcements: EventWriter<AnnouncementEvent>,
+    asset_server: Res<AssetServer>,
+    players: Res<Players>,
+    config: Res<ServerConfig>,
+    mut commands: Commands,
+    mut sender: MessageSender,
+) {
+    let round_time = time.elapsed_seconds() - timeline.started;
+    let due = timeline
+        .pending
+        .iter()
+        .take_while(|e| e.at_seconds <= round_time)
+        .count();
+    if due == 0 {
+        return;
+    }
+
+    for entry in timeline.pending.drain(..due) {
+        let result = execute_event(
+            &entry.event,
+            &maps,
+            &random_events,
+            &mut announcements,
+            &asset_server,
+            &mut commands,
+        );
+        log_execution(
+            &entry.event,
+            result,
+            admin_receivers(&players, &config),
+            &mut sender,
+        );
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn handle_timeline_commands(
+    mut messages: EventReader<MessageEvent<TimelineCommand>>,
+    mut timeline: ResMut<Timeline>,
+    maps: Query<&TileMap>,
+    random_events: Res<RandomEvents>,
+    mut announcements: EventWriter<AnnouncementEvent>,
+    asset_server: Res<AssetServer>,
+    players: Res<Players>,
+    config: Res<ServerConfig>,
+    mut commands: Commands,
+    mut sender: MessageSender,
+) {
+    for event in messages.iter() {
+        let is_admin = players
+            .get(event.connection)
+            .is_some_and(|player| config.admins.contains(&player.id));
+        if !is_admin {
+            warn!(connection = ?event.connection, "Non-admin sent a timeline command");
+            continue;
+        }
+
+        match &event.message {
+            TimelineCommand::List => {}
+            TimelineCommand::Skip(index) => {
+                if *index < timeline.pending.len() {
+                    let entry = timeline.pending.remove(*index);
+                    info!(connection = ?event.connection, event = %entry.event, "Skipped timeline event");
+                }
+            }
+            TimelineCommand::Inject(timeline_event) => {
+                let result = execute_event(
+                    timeline_event,
+                    &maps,
+                    &random_events,
+                    &mut announcements,
+                    &asset_server,
+                    &mut commands,
+                );
+                log_execution(
+                    timeline_event,
+                    result,
+                    admin_receivers(&players, &config),
+                    &mut sender,
+                );
+            }
+        }
+
+        sender.send(
+            &TimelineServerMessage::Pending(timeline.pending.clone()),
+            MessageReceivers::Single(event.connection),
+        );
+    }
+}
+
+#[cfg(feature = "client")]
+#[derive(Resource, Default)]
+struct ClientTimeline {
+    pending: Vec<TimelineEntry>,
+    log: Vec<String>,
+    announcement: String,
+    /// Id of the random event picked to trigger
+    random_event: Option<&'static str>,
+}
+
+#[cfg(feature = "client")]
+fn client_receive_timeline(
+    mut messages: EventReader<MessageEvent<TimelineServerMessage>>,
+    mut timeline: ResMut<ClientTimeline>,
+) {
+    for event in messages.iter() {
+        match &event.message {
+            TimelineServerMessage::Pending(pending) => timeline.pending = pending.clone(),
+            TimelineServerMessage::Log(text) => timeline.log.push(text.clone()),
+        }
+    }
+}
+
+#[cfg(feature = "client")]
+fn timeline_ui(
+    mut contexts: EguiContexts,
+    mut timeline: ResMut<ClientTimeline>,
+    random_events: Res<RandomEvents>,
+    mut sender: MessageSender,
+) {
+    let timeline = &mut *timeline;
+    egui::Window::new("Timeline")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            if ui.button("Refresh").clicked() {
+                sender.send_to_server(&TimelineCommand::List);
+            }
+
+            for (index, entry) in timeline.pending.iter().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.label(format!("{:.0}s: {}", entry.at_seconds, entry.event));
+                    if ui.button("Skip").clicked() {
+                        sender.send_to_server(&TimelineCommand::Skip(index));
+                    }
+                });
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                egui::TextEdit::singleline(&mut timeline.announcement)
+                    .hint_text("Announcement")
+                    .show(ui);
+                if ui.button("Announce").clicked() && !timeline.announcement.is_empty() {
+                    sender.send_to_server(&TimelineCommand::Inject(TimelineEvent::Announce(
+                        std::mem::take(&mut timeline.announcement),
+                    )));
+                }
+            });
+            ui.horizontal(|ui| {
+                let selected = timeline
+                    .random_event
+                    .and_then(|id| random_events.get(id))
+                    .map_or("Pick an event", |event| event.name);
+                egui::ComboBox::from_id_source("trigger event")
+                    .selected_text(selected)
+                    .show_ui(ui, |ui| {
+                        for event in random_events.iter() {
+                            ui.selectable_value(
+                                &mut timeline.random_event,
+                                Some(event.id),
+                                event.name,
+                            )
+                            .on_hover_text(event.description);
+                        }
+                    });
+                if let Some(id) = timeline.random_event {
+                    if ui.button("Trigger event").clicked() {
+                        sender.send_to_server(&TimelineCommand::Inject(
+                            TimelineEvent::RandomEvent { id: id.into() },
+                        ));
+                    }
+                }
+            });
+
+            ui.separator();
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                for line in timeline.log.iter() {
+                    ui.label(line);
+                }
+            });
+        });
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use networking::{loopback::LinkConditions, testing, NetworkRole};
+
+    use super::*;
+    use crate::testing::server_app;
+
+    /// Asks to skip the only scheduled event from a connected client.
+    /// Returns how many events are still scheduled.
+    fn skip_first(admin: bool) -> usize {
+        let mut server = server_app(ServerConfig::default());
+        let mut client = testing::app(NetworkRole::Client);
+        let connector = testing::listen(&mut server);
+        testing::join(&mut client, &connector, LinkConditions::default());
+        testing::connect(&mut server, &mut [&mut client], 200);
+
+        let (&connection, player) = server
+            .world
+            .resource::<Players>()
+            .players()
+            .iter()
+            .next()
+            .unwrap();
+        let player = player.id;
+        if admin {
+            server
+                .world
+                .resource_mut::<ServerConfig>()
+                .admins
+                .push(player);
+        }
+        server
+            .world
+            .resource_mut::<Timeline>()
+            .pending
+            .push(TimelineEntry {
+                at_seconds: 600.0,
+                event: TimelineEvent::Announce("Meteors incoming".into()),
+            });
+
+        server.world.send_event(MessageEvent {
+            message: TimelineCommand::Skip(0),
+            connection,
+        });
+        testing::update(&mut server, &mut [&mut client], 2);
+        server.world.resource::<Timeline>().pending.len()
+    }
+
+    #[test]
+    fn admin_can_skip_events() {
+        assert_eq!(skip_first(true), 0);
+    }
+
+    #[test]
+    fn non_admin_commands_are_ignored() {
+        assert_eq!(skip_first(false), 1);
+    }
+}
@@ -1,5 +1,4 @@
-use bevy::{prelude::*, window::PrimaryWindow};
-use bevy_egui::EguiContexts;
+use bevy::prelude::*;
 use networking::{
     identity::{NetworkIdentities, NetworkIdentity},
     is_server,
@@ -10,47 +9,90 @@ use networking::{
 };
 use serde::{Deserialize, Serialize};
 
-use self::{
-    lobby::LobbyPlugin, main_menu::MainMenuPlugin, pause_menu::PauseMenuPlugin,
-    splash::SplashPlugin,
+#[cfg(feature = "client")]
+use {
+    self::{
+        lobby::LobbyPlugin, main_menu::MainMenuPlugin, pause_menu::PauseMenuPlugin,
+        profiles::ProfilesPlugin, splash::SplashPlugin,
+    },
+    bevy::{utils::HashSet, window::PrimaryWindow},
+    bevy_egui::EguiContexts,
 };
 
+#[cfg(feature = "client")]
 mod lobby;
+#[cfg(feature = "client")]
 mod main_menu;
+#[cfg(feature = "client")]
 mod pause_menu;
+#[cfg(feature = "client")]
+mod profiles;
+#[cfg(feature = "client")]
 mod splash;
 
 pub struct UiPlugin;
 
 impl Plugin for UiPlugin {
     fn build(&self, app: &mut App) {
-        app.add_network_message::<CloseUiMessage>();
+        app.add_network_message::<CloseUiMessage>("CloseUiMessage");
         if is_server(app) {
             app.add_systems(Update, (handle_close_ui, close_unused_uis));
         } else {
-            app.add_plugins((SplashPlugin, MainMenuPlugin, PauseMenuPlugin, LobbyPlugin))
-                .add_systems(
-                    PreUpdate,
-                    (absorb_egui_inputs,)
-                        .after(bevy_egui::systems::process_input_system)
-                        .before(bevy_egui::EguiSet::BeginFrame),
-                );
+            #[cfg(feature = "client")]
+            app.add_plugins((
+                SplashPlugin,
+                MainMenuPlugin,
+                PauseMenuPlugin,
+                LobbyPlugin,
+                ProfilesPlugin,
+            ))
+            .init_resource::<InputBlocks>()
+            .add_systems(
+                PreUpdate,
+                (absorb_egui_inputs,)
+                    .after(bevy_egui::systems::process_input_system)
+                    .before(bevy_egui::EguiSet::BeginFrame),
+            );
         }
     }
 }
 
 /// Run criteria that returns true if the primary window exists.
+#[cfg(feature = "client")]
 pub fn has_window(query: Query<(), With<PrimaryWindow>>) -> bool {
     !query.is_empty()
 }
 
+/// UI elements that currently capture the mouse, even outside of egui areas.
+/// Mouse buttons don't reach the world while any block is active.
+#[cfg(feature = "client")]
+#[derive(Resource, Default)]
+pub struct InputBlocks(HashSet<&'static str>);
+
+#[cfg(feature = "client")]
+impl InputBlocks {
+    pub fn set(&mut self, source: &'static str, blocked: bool) {
+        if blocked {
+            self.0.insert(source);
+        } else {
+            self.0.remove(source);
+        }
+    }
+
+    pub fn is_blocked(&self) -> bool {
+        !self.0.is_empty()
+    }
+}
+
 /// Prevents bevy systems from receiving input when it's used by the UI
+#[cfg(feature = "client")]
 fn absorb_egui_inputs(
     mut mouse: ResMut<Input<MouseButton>>,
     mut keyboard: ResMut<Input<KeyCode>>,
     mut contexts: EguiContexts,
+    blocks: Res<InputBlocks>,
 ) {
-    if contexts.ctx_mut().is_pointer_over_area() {
+    if contexts.ctx_mut().is_pointer_over_area() || blocks.is_blocked() {
         mouse.reset_all();
     }
 
@@ -1,9 +1,16 @@
+use std::time::Duration;
+
 use crate::{
-    job::{JobDefinition, SelectJobMessage},
-    round::{RequestJoin, RoundDataClient, RoundState, StartRoundRequest},
+    job::{ClientJobSlots, JobDefinition, JobSlotsRequest, SelectJobMessage},
+    profile::{ProfileMessage, Profiles},
+    round::{
+        modes::{ClientModeVotes, GameMode, GameModeVote},
+        RequestJoin, RequestObserve, RoundDataClient, RoundState, StartRoundRequest,
+    },
+    stats::{ClientStats, StatsRequest},
     GameState,
 };
-use bevy::{asset::HandleId, prelude::*};
+use bevy::{asset::HandleId, prelude::*, time::common_conditions::on_timer};
 use bevy_egui::EguiContexts;
 use bevy_inspector_egui::egui;
 use networking::{messaging::MessageSender, spawning::ClientControlled};
@@ -16,18 +23,42 @@ impl Plugin for LobbyPlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(
             Update,
-            (ui, job_ui)
-                .run_if(in_state(GameState::Game))
-                .run_if(has_window),
+            (
+                (ui, job_ui).run_if(has_window),
+                request_job_slots.run_if(on_timer(Duration::from_secs_f32(SLOTS_INTERVAL))),
+            )
+                .run_if(in_state(GameState::Game)),
         );
     }
 }
 
+/// Seconds between refreshing the open and locked job slots while in the lobby
+const SLOTS_INTERVAL: f32 = 2.0;
+
+fn request_job_slots(
+    round_data: Option<Res<RoundDataClient>>,
+    client_controlled: Query<(), With<ClientControlled>>,
+    mut sender: MessageSender,
+) {
+    if !client_controlled.is_empty() {
+        return;
+    }
+    // Locked jobs matter before the round starts too
+    if round_data
+        .is_some_and(|data| matches!(data.state(), RoundState::Ready | RoundState::Running))
+    {
+        sender.send_to_server(&JobSlotsRequest);
+    }
+}
+
 fn ui(
     mut contexts: EguiContexts,
     round_data: Option<Res<RoundDataClient>>,
+    votes: Res<ClientModeVotes>,
     client_controlled: Query<(), With<ClientControlled>>,
+    mut stats: ResMut<ClientStats>,
     mut sender: MessageSender,
+    mut voted: Local<Option<GameMode>>,
 ) {
     // Only show lobby UI if not controlling any entity
     if !client_controlled.is_empty() {
@@ -42,38 +73,72 @@ fn ui(
 
                 match data.state() {
                     RoundState::Ready => {
+                        ui.label("Vote for the game mode:");
+                        ui.horizontal(|ui| {
+                            for mode in GameMode::ALL {
+                                let count = votes
+                                    .tally
+                                    .votes
+                                    .iter()
+                                    .find(|(m, _)| *m == mode)
+                                    .map_or(0, |&(_, n)| n);
+                                let label = format!("{} ({})", mode.name(), count);
+                                if ui.selectable_label(*voted == Some(mode), label).clicked() {
+                                    *voted = Some(mode);
+                                    sender.send_to_server(&GameModeVote(mode));
+                                }
+                            }
+                        });
                         if ui.button("Start round").clicked() {
                             sender.send_to_server(&StartRoundRequest);
                         }
                     }
                     RoundState::Running => {
                         ui.label(format!("Round started tick: {}", data.start().unwrap()));
-                        if ui.button("Join").clicked() {
-                            sender.send_to_server(&RequestJoin);
-                        }
+                        ui.horizontal(|ui| {
+                            if ui.button("Join").clicked() {
+                                sender.send_to_server(&RequestJoin);
+                            }
+                            if ui.button("Observe").clicked() {
+                                sender.send_to_server(&RequestObserve);
+                            }
+                        });
                     }
                     _ => {}
                 }
+
+                if ui.button("My stats").clicked() {
+                    stats.open = true;
+                    sender.send_to_server(&StatsRequest);
+                }
             } else {
                 ui.label("Loading...");
             }
         });
 }
 
+#[allow(clippy::too_many_arguments)]
 fn job_ui(
     mut contexts: EguiContexts,
     client_controlled: Query<(), With<ClientControlled>>,
     jobs: Res<Assets<JobDefinition>>,
+    mut job_events: EventReader<AssetEvent<JobDefinition>>,
+    slots: Res<ClientJobSlots>,
+    mut profiles: ResMut<Profiles>,
+    round_data: Option<Res<RoundDataClient>>,
     mut sender: MessageSender,
     mut selected_job: Local<Option<HandleId>>,
     mut sorted_jobs: Local<Vec<Handle<JobDefinition>>>,
 ) {
+    // Reloaded jobs can be renamed, so the order is rebuilt on any change
+    let jobs_changed = job_events.iter().count() > 0;
+
     // Only show lobby UI if not controlling any entity
     if !client_controlled.is_empty() {
         return;
     }
 
-    if jobs.len() != sorted_jobs.len() {
+    if jobs_changed || jobs.len() != sorted_jobs.len() {
         let mut new_sorted: Vec<_> = jobs.iter().collect();
         new_sorted.sort_unstable_by_key(|x| &x.1.name);
         sorted_jobs.clear();
@@ -84,17 +149,88 @@ fn job_ui(
         );
     }
 
+    let running = round_data.is_some_and(|data| data.state() == &RoundState::Running);
     let previous_job = *selected_job;
+    let mut loadout_choice = None;
     egui::Window::new("Jobs")
         .anchor(egui::Align2::RIGHT_CENTER, egui::vec2(-30.0, 0.0))
         .show(contexts.ctx_mut(), |ui| {
             for handle in sorted_jobs.iter() {
-                let job_definition = jobs.get(handle).unwrap();
-                ui.radio_value(&mut *selected_job, Some(handle.id()), &job_definition.name);
-                ui.label(&job_definition.description);
+                let Some(job_definition) = jobs.get(handle) else {
+                    continue;
+                };
+                let locked = slots.locked(job_definition);
+                let banned = slots.banned(job_definition);
+                let label = match slots.open(job_definition).filter(|_| running) {
+                    _ if banned.is_some() => format!("{} (banned)", job_definition.name),
+                    _ if locked.is_some() => format!("{} (locked)", job_definition.name),
+                    Some(0) => format!("{} (full)", job_definition.name),
+                    Some(open) => format!("{} ({} open)", job_definition.name, open),
+                    None => job_definition.name.clone(),
+                };
+                let response = ui
+                    .add_enabled_ui(locked.is_none() && banned.is_none(), |ui| {
+                        ui.radio_value(&mut *selected_job, Some(handle.id()), label)
+                    })
+                    .inner;
+                if let Some(ban) = banned {
+                    response.on_disabled_hover_text(ban);
+                }
+                match locked {
+                    Some(reason) => ui.colored_label(egui::Color32::LIGHT_RED, reason),
+                    None => ui.label(&job_definition.description),
+                };
+
+                if *selected_job != Some(handle.id()) {
+                    continue;
+                }
+                let choices = &profiles.selected().loadout;
+                for slot in job_definition.loadout.iter() {
+                    // Choices that are no longer offered show the default, like the server spawns them
+                    let Some(current) = slot.chosen(choices).or(slot.default_option()) else {
+                        continue;
+                    };
+                    ui.horizontal(|ui| {
+                        ui.label(&slot.name);
+                        for option in slot.options.iter() {
+                            let option_locked = slots.option_locked(job_definition, option);
+                            let response = ui
+                                .add_enabled_ui(option_locked.is_none(), |ui| {
+                                    ui.selectable_label(option.item == current.item, &option.name)
+                                })
+                                .inner;
+                            let response = match option_locked {
+                                Some(reason) => response.on_disabled_hover_text(reason),
+                                None => response,
+                            };
+                            if response.clicked() && option.item != current.item {
+                                loadout_choice = Some((slot, option.item.clone()));
+                            }
+                        }
+                    });
+                }
+            }
+
+            if let Some(ban) = slots.antagonist_ban() {
+                ui.separator();
+                ui.colored_label(
+                    egui::Color32::LIGHT_RED,
+                    "You won't be picked as an antagonist",
+                )
+                .on_hover_text(ban);
             }
         });
 
+    if let Some((slot, item)) = loadout_choice {
+        profiles.edit_selected(|profile| {
+            profile
+                .loadout
+                .retain(|chosen| slot.options.iter().all(|o| &o.item != chosen));
+            profile.loadout.push(item);
+        });
+        sender.send_to_server(&ProfileMessage::from(profiles.selected()));
+    }
+
     if previous_job != *selected_job {
         let asset_id = selected_job.map(|handle| match handle {
             HandleId::Id(_, _) => panic!("Job must be asset"),
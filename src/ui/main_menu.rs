@@ -3,11 +3,18 @@ use std::{net::SocketAddr, str::FromStr};
 use bevy::prelude::*;
 use bevy_egui::EguiContexts;
 use bevy_inspector_egui::egui::{self, TextEdit};
-use networking::{ClientEvent, TargetServer, UserData};
+use networking::{ClientEvent, TargetServer};
 
-use crate::GameState;
+use crate::{
+    invite::{InviteJoin, JoinByCode},
+    profile::Profiles,
+    GameState,
+};
 
-use super::has_window;
+use super::{
+    has_window,
+    profiles::{profile_selection, ProfileEditorOpen},
+};
 
 pub struct MainMenuPlugin;
 
@@ -28,43 +35,72 @@ struct DisconnectReason {
     reason: String,
 }
 
+#[allow(clippy::too_many_arguments)]
 fn ui(
     mut contexts: EguiContexts,
     mut ip: Local<String>,
-    mut name: Local<String>,
+    mut invite_code: Local<String>,
+    mut profiles: ResMut<Profiles>,
+    mut editor: ResMut<ProfileEditorOpen>,
     mut client_events: EventWriter<ClientEvent>,
+    mut join_by_code: EventWriter<JoinByCode>,
     disconnect: Option<Res<DisconnectReason>>,
+    invite: Option<Res<InviteJoin>>,
     mut commands: Commands,
 ) {
     egui::Area::new("main buttons")
         .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
         .show(contexts.ctx_mut(), |ui| {
             ui.horizontal(|ui| {
-                // TODO: Actually use name
-                let name_field = TextEdit::singleline(&mut *name).hint_text("Name");
-                if name_field.show(ui).response.changed() {
-                    commands.insert_resource(UserData {
-                        username: name.clone(),
-                    });
-                }
+                profile_selection(ui, &mut profiles, &mut editor);
 
                 let ip_field = TextEdit::singleline(&mut *ip).hint_text("Server IP");
                 ip_field.show(ui);
 
                 if ui.button("Join").clicked() {
                     if let Ok(address) = SocketAddr::from_str(ip.as_ref()) {
+                        commands.remove_resource::<InviteJoin>();
                         client_events.send(ClientEvent::Join(TargetServer::Raw(address)));
                     }
                 }
             });
 
+            ui.horizontal(|ui| {
+                let code_field = TextEdit::singleline(&mut *invite_code).hint_text("Invite code");
+                code_field.show(ui);
+
+                let looking_up = matches!(invite.as_deref(), Some(InviteJoin::LookingUp(_)));
+                let button = egui::Button::new("Join by code");
+                if ui
+                    .add_enabled(!invite_code.trim().is_empty() && !looking_up, button)
+                    .clicked()
+                {
+                    join_by_code.send(JoinByCode(invite_code.clone()));
+                }
+            });
+
             if !ip.is_empty() && SocketAddr::from_str(ip.as_ref()).is_err() {
                 ui.colored_label(egui::Color32::DARK_RED, "Invalid address");
             }
 
-            if let Some(disconnect) = disconnect {
-                ui.label("Connection failed");
-                ui.colored_label(egui::Color32::RED, &disconnect.reason);
+            // Failures of single invite addresses are shown together once all were tried
+            match invite.as_deref() {
+                Some(InviteJoin::LookingUp(_)) => {
+                    ui.label("Looking up invite code...");
+                }
+                Some(InviteJoin::Connecting { .. }) => {
+                    ui.label("Trying next address...");
+                }
+                Some(InviteJoin::Failed(reason)) => {
+                    ui.label("Joining by invite failed");
+                    ui.colored_label(egui::Color32::RED, reason);
+                }
+                None => {
+                    if let Some(disconnect) = disconnect {
+                        ui.label("Connection failed");
+                        ui.colored_label(egui::Color32::RED, &disconnect.reason);
+                    }
+                }
             }
         });
 }
@@ -3,7 +3,17 @@ use bevy_egui::EguiContexts;
 use bevy_inspector_egui::egui;
 use networking::{ClientState, ClientTask};
 
-use crate::GameState;
+use crate::{
+    accessibility::{AccessibilitySettings, Palette},
+    communication::{ChatDisplay, ChatSettings},
+    cursor::CursorSettings,
+    decals::DecalSettings,
+    interaction::InteractionSettings,
+    occlusion::OcclusionSettings,
+    round::modes::ClientBriefing,
+    sound::AudioSettings,
+    GameState,
+};
 
 use super::has_window;
 
@@ -18,12 +28,22 @@ impl Plugin for PauseMenuPlugin {
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn ui(
     mut contexts: EguiContexts,
     keys: Res<Input<KeyCode>>,
     mut visible: Local<bool>,
     state: Res<State<ClientState>>,
     mut tasks: EventWriter<ClientTask>,
+    mut interaction_settings: ResMut<InteractionSettings>,
+    mut occlusion_settings: ResMut<OcclusionSettings>,
+    mut audio_settings: ResMut<AudioSettings>,
+    mut chat_settings: ResMut<ChatSettings>,
+    mut cursor_settings: ResMut<CursorSettings>,
+    mut decal_settings: ResMut<DecalSettings>,
+    mut accessibility: ResMut<AccessibilitySettings>,
+    mut pending_scale: Local<Option<f32>>,
+    mut briefing: ResMut<ClientBriefing>,
 ) {
     if !matches!(state.get(), ClientState::Connected) {
         *visible = false;
@@ -48,6 +68,71 @@ fn ui(
                     *visible = !*visible;
                 }
                 ui.add_space(5.0);
+                ui.checkbox(
+                    &mut interaction_settings.radial_menu,
+                    "Radial interaction menu",
+                );
+                ui.checkbox(&mut occlusion_settings.enabled, "Hide rooms out of sight");
+                ui.checkbox(&mut cursor_settings.contextual, "Contextual cursor");
+                ui.add(
+                    egui::Slider::new(&mut decal_settings.capacity, 0..=4096).text("Floor decals"),
+                );
+                egui::ComboBox::from_label("Show speech in")
+                    .selected_text(chat_settings.display.label())
+                    .show_ui(ui, |ui| {
+                        for display in ChatDisplay::ALL {
+                            ui.selectable_value(
+                                &mut chat_settings.display,
+                                display,
+                                display.label(),
+                            );
+                        }
+                    });
+                ui.add_space(5.0);
+                let audio = &mut *audio_settings;
+                for (value, label) in [
+                    (&mut audio.master, "Volume"),
+                    (&mut audio.effects, "Effects"),
+                    (&mut audio.ambience, "Ambience"),
+                    (&mut audio.music, "Music"),
+                ] {
+                    ui.add(egui::Slider::new(value, 0.0..=1.0).text(label));
+                }
+                ui.add_space(5.0);
+                egui::ComboBox::from_label("Colors")
+                    .selected_text(accessibility.palette.label())
+                    .show_ui(ui, |ui| {
+                        for palette in Palette::ALL {
+                            ui.selectable_value(
+                                &mut accessibility.palette,
+                                palette,
+                                palette.label(),
+                            );
+                        }
+                    });
+                ui.checkbox(&mut accessibility.reduce_flashing, "Reduce flashing");
+                // Only applied once the slider is let go, as the menu changes size with it
+                let scale = pending_scale.get_or_insert(accessibility.ui_scale);
+                let dragged = ui
+                    .add(egui::Slider::new(scale, 0.75..=2.0).text("UI scale"))
+                    .dragged();
+                if !dragged {
+                    let scale = pending_scale.take().unwrap();
+                    if scale != accessibility.ui_scale {
+                        accessibility.ui_scale = scale;
+                    }
+                }
+                ui.add_space(5.0);
+                if let Some(current) = briefing.briefing.as_ref() {
+                    ui.label(egui::RichText::new(format!("You are a {}.", current.role)).strong());
+                    for objective in current.objectives.iter() {
+                        ui.label(objective);
+                    }
+                    if ui.button("Show objectives").clicked() {
+                        briefing.open = true;
+                    }
+                    ui.add_space(5.0);
+                }
                 if ui.button("Leave").clicked() {
                     tasks.send(ClientTask::Leave);
                 }
@@ -0,0 +1,145 @@
+use bevy::prelude::*;
+use bevy_egui::EguiContexts;
+use bevy_inspector_egui::egui::{self, TextEdit};
+
+use crate::{
+    body::variant::BodyTemplate,
+    job::JobDefinition,
+    profile::{validate_character_name, CharacterProfile, Profiles},
+    GameState,
+};
+
+use super::has_window;
+
+pub struct ProfilesPlugin;
+
+impl Plugin for ProfilesPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ProfileEditorOpen>().add_systems(
+            Update,
+            (notices_ui, editor_ui)
+                .run_if(in_state(GameState::MainMenu))
+                .run_if(has_window),
+        );
+    }
+}
+
+/// If the profile management window is visible
+#[derive(Resource, Default)]
+pub struct ProfileEditorOpen(pub bool);
+
+/// Shows a selection of all profiles and a button to open the editor.
+pub fn profile_selection(
+    ui: &mut egui::Ui,
+    profiles: &mut ResMut<Profiles>,
+    editor: &mut ResMut<ProfileEditorOpen>,
+) {
+    let mut selected = profiles.selected_index();
+    egui::ComboBox::from_id_source("profile selection")
+        .selected_text(&profiles.selected().name)
+        .show_ui(ui, |ui| {
+            for (index, profile) in profiles.iter().enumerate() {
+                ui.selectable_value(&mut selected, index, &profile.name);
+            }
+        });
+    if selected != profiles.selected_index() {
+        profiles.select(selected);
+    }
+
+    if ui.button("Profiles").clicked() {
+        editor.0 = !editor.0;
+    }
+}
+
+fn notices_ui(mut contexts: EguiContexts, mut profiles: ResMut<Profiles>) {
+    if profiles.notices.is_empty() {
+        return;
+    }
+
+    egui::Window::new("Profile problems")
+        .anchor(egui::Align2::CENTER_TOP, egui::vec2(0.0, 30.0))
+        .show(contexts.ctx_mut(), |ui| {
+            for notice in profiles.notices.iter() {
+                ui.colored_label(egui::Color32::RED, notice);
+            }
+            if ui.button("Dismiss").clicked() {
+                profiles.notices.clear();
+            }
+        });
+}
+
+fn editor_ui(
+    mut contexts: EguiContexts,
+    mut open: ResMut<ProfileEditorOpen>,
+    mut profiles: ResMut<Profiles>,
+    jobs: Res<Assets<JobDefinition>>,
+    bodies: Res<Assets<BodyTemplate>>,
+) {
+    if !open.0 {
+        return;
+    }
+
+    egui::Window::new("Profiles")
+        .open(&mut open.0)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.horizontal(|ui| {
+                if ui.button("New").clicked() {
+                    profiles.create(CharacterProfile::default());
+                }
+                if ui.button("Duplicate").clicked() {
+                    profiles.duplicate_selected();
+                }
+                if ui
+                    .add_enabled(profiles.iter().count() > 1, egui::Button::new("Delete"))
+                    .clicked()
+                {
+                    profiles.delete_selected();
+                }
+            });
+            ui.separator();
+
+            let mut name = profiles.selected().name.clone();
+            ui.horizontal(|ui| {
+                ui.label("Name");
+                if TextEdit::singleline(&mut name).show(ui).response.changed() {
+                    profiles.edit_selected(|p| p.name = name.clone());
+                }
+            });
+            if validate_character_name(&name).is_none() {
+                ui.colored_label(egui::Color32::DARK_RED, "Invalid character name");
+            }
+
+            // Servers can disallow some bodies, players are told when they spawn
+            let mut body = profiles.selected().body.clone();
+            let mut sorted_bodies: Vec<_> = bodies.iter().map(|(_, body)| body).collect();
+            sorted_bodies.sort_unstable_by_key(|b| &b.name);
+            let selected_name =
+                BodyTemplate::find(&bodies, &body).map_or(body.as_str(), |b| b.name.as_str());
+            egui::ComboBox::from_label("Body")
+                .selected_text(selected_name)
+                .show_ui(ui, |ui| {
+                    for template in sorted_bodies {
+                        ui.selectable_value(&mut body, template.id.clone(), &template.name);
+                    }
+                });
+            if body != profiles.selected().body {
+                profiles.edit_selected(|p| p.body = body);
+            }
+
+            ui.label("Job preferences");
+            let mut sorted_jobs: Vec<_> = jobs.iter().map(|(_, job)| job).collect();
+            sorted_jobs.sort_unstable_by_key(|j| &j.name);
+            for job in sorted_jobs {
+                let mut preferred = profiles.selected().job_preferences.contains(&job.id);
+                if ui.checkbox(&mut preferred, &job.name).changed() {
+                    profiles.edit_selected(|p| {
+                        if preferred {
+                            p.job_preferences.push(job.id.clone());
+                        } else {
+                            p.job_preferences.retain(|id| id != &job.id);
+                        }
+                    });
+                }
+            }
+        });
+}
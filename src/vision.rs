@@ -0,0 +1,310 @@
+use std::time::Duration;
+
+use bevy::{
+    ecs::{query::Has, system::SystemParam},
+    prelude::*,
+    time::common_conditions::on_timer,
+    utils::HashMap,
+};
+use bevy_rapier3d::prelude::{CollisionGroups, QueryFilter, RapierContext};
+use maps::{TileEntity, TileLayer};
+use networking::{
+    is_server, spawning::ClientControls, visibility::Concealment, ConnectionId, Players,
+};
+
+use crate::{body::Body, lights::Opaque};
+
+/// Hides player creatures from players that can't see them.
+pub struct VisionPlugin;
+
+impl Plugin for VisionPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<SeeThroughWalls>();
+
+        if is_server(app) {
+            app.init_resource::<LastSeen>().add_systems(
+                Update,
+                update_line_of_sight.run_if(on_timer(Duration::from_secs_f32(VISION_INTERVAL))),
+            );
+        }
+    }
+}
+
+/// Seconds between line of sight checks
+const VISION_INTERVAL: f32 = 0.25;
+/// How long a creature stays visible after losing line of sight. Prevents flickering at corners.
+const HIDE_DELAY: f32 = 1.0;
+/// Creatures this close are always visible, as they can be heard
+const HEARING_RADIUS: f32 = 2.0;
+/// Creatures further away are out of network range anyway
+const MAX_SIGHT_DISTANCE: f32 = 32.0;
+/// Rays are cast at roughly eye level, so low furniture doesn't block sight
+const EYE_HEIGHT: f32 = 1.5;
+
+/// Lets the controlling player see all creatures, regardless of walls.
+/// Used for ghosts and admins.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct SeeThroughWalls;
+
+/// Hides the creature from every other player, like admins acting as a ghost.
+#[derive(Component, Default)]
+pub struct Invisible;
+
+/// Raycasts for line of sight. Walls and other static geometry block it,
+/// but turfs that aren't [`Opaque`], like windows, only block movement.
+#[derive(SystemParam)]
+pub struct LineOfSight<'w, 's> {
+    rapier: Res<'w, RapierContext>,
+    parents: Query<'w, 's, &'static Parent>,
+    tiles: Query<'w, 's, (&'static TileEntity, Has<Opaque>)>,
+}
+
+impl<'w, 's> LineOfSight<'w, 's> {
+    /// The first thing blocking sight along a ray and the distance to it.
+    pub fn cast(&self, origin: Vec3, direction: Vec3, max_distance: f32) -> Option<(Entity, f32)> {
+        // Tile colliders are children of the tile object
+        let blocks_sight = |collider: Entity| {
+            let owner = self.parents.get(collider).map_or(collider, |p| p.get());
+            match self.tiles.get(owner) {
+                Ok((tile, opaque)) => tile.layer() != TileLayer::Turf || opaque,
+                Err(_) => true,
+            }
+        };
+        let filter = QueryFilter::only_fixed()
+            .groups(CollisionGroups::new(
+                physics::RAYCASTING_GROUP,
+                physics::OBSTACLE_GROUPS,
+            ))
+            .predicate(&blocks_sight);
+        self.rapier
+            .cast_ray(origin, direction, max_distance, true, filter)
+    }
+
+    /// If nothing blocks sight between two points.
+    pub fn clear(&self, from: Vec3, to: Vec3) -> bool {
+        let offset = to - from;
+        let distance = offset.length();
+        distance <= f32::EPSILON || self.cast(from, offset / distance, distance).is_none()
+    }
+}
+
+/// When each connection last had line of sight to a creature.
+#[derive(Resource, Default)]
+struct LastSeen(HashMap<(ConnectionId, Entity), f32>);
+
+#[allow(clippy::too_many_arguments)]
+fn update_line_of_sight(
+    creatures: Query<
+        (
+            Entity,
+            &GlobalTransform,
+            Option<&SeeThroughWalls>,
+            Has<Invisible>,
+        ),
+        With<Body>,
+    >,
+    children: Query<&Children>,
+    sight: LineOfSight,
+    controls: Res<ClientControls>,
+    players: Res<Players>,
+    mut last_seen: ResMut<LastSeen>,
+    mut concealment: ResMut<Concealment>,
+    time: Res<Time>,
+) {
+    let now = time.elapsed_seconds();
+    // TODO: Factor in the light level of the tile
+
+    let player_creatures: Vec<_> = creatures
+        .iter()
+        .filter(|(entity, ..)| controls.controlling_player(*entity).is_some())
+        .collect();
+
+    concealment.clear();
+    for (viewer, viewer_transform, see_through, _) in player_creatures.iter() {
+        let Some(connection) = controls
+            .controlling_player(*viewer)
+            .and_then(|p| players.get_connection(&p))
+        else {
+            continue;
+        };
+
+        let mut hidden = Vec::new();
+        if see_through.is_none() {
+            let eye = viewer_transform.translation() + Vec3::Y * EYE_HEIGHT;
+            for (target, target_transform, _, invisible) in player_creatures.iter() {
+                if target == viewer || *invisible {
+                    continue;
+                }
+
+                let offset = target_transform.translation() + Vec3::Y * EYE_HEIGHT - eye;
+                let distance = offset.length();
+                if distance > MAX_SIGHT_DISTANCE {
+                    continue;
+                }
+
+                let visible = distance <= HEARING_RADIUS
+                    || sight.cast(eye, offset / distance, distance).is_none();
+                if visible {
+                    last_seen.0.insert((connection, *target), now);
+                    continue;
+                }
+
+                let recently_seen = last_seen
+                    .0
+                    .get(&(connection, *target))
+                    .map(|t| now - t < HIDE_DELAY)
+                    .unwrap_or(false);
+                if !recently_seen {
+                    // Hide limbs and held items together with the creature,
+                    // so the client doesn't keep them around after the creature despawns
+                    hidden.push(*target);
+                    hidden.extend(children.iter_descendants(*target));
+                }
+            }
+        }
+        // Invisible creatures are hidden from everyone else, even ghosts
+        for (target, ..) in player_creatures.iter().filter(|(.., invisible)| *invisible) {
+            if target != viewer {
+                hidden.push(*target);
+                hidden.extend(children.iter_descendants(*target));
+            }
+        }
+
+        concealment.set_hidden(connection, hidden);
+    }
+
+    last_seen.0.retain(|_, t| now - *t < HIDE_DELAY);
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::{time::TimeUpdateStrategy, utils::Uuid};
+    use bevy_rapier3d::prelude::{Collider, Group, RigidBody};
+    use networking::{loopback::LinkConditions, testing, NetworkRole};
+
+    use super::*;
+    use crate::{config::ServerConfig, testing::server_app};
+
+    const FRAME: Duration = Duration::from_millis(100);
+    /// Enough frames for physics to know the wall and a few vision checks
+    const FRAMES: u32 = 10;
+
+    struct Scene {
+        server: App,
+        client: App,
+        viewer: Entity,
+        target: Entity,
+        connection: ConnectionId,
+    }
+
+    /// A connected player's creature and another player's creature `distance` meters away.
+    fn setup(distance: f32) -> Scene {
+        let mut server = server_app(ServerConfig::default());
+        let mut client = testing::app(NetworkRole::Client);
+        let connector = testing::listen(&mut server);
+        testing::join(&mut client, &connector, LinkConditions::default());
+        testing::connect(&mut server, &mut [&mut client], 200);
+        server.insert_resource(TimeUpdateStrategy::ManualDuration(FRAME));
+
+        let viewer = server
+            .world
+            .spawn((Body::default(), SpatialBundle::default()))
+            .id();
+        let target = server
+            .world
+            .spawn((
+                Body::default(),
+                SpatialBundle::from_transform(Transform::from_xyz(distance, 0.0, 0.0)),
+            ))
+            .id();
+        let (&connection, player) = server
+            .world
+            .resource::<Players>()
+            .players()
+            .iter()
+            .next()
+            .unwrap();
+        let player = player.id;
+        let mut controls = server.world.resource_mut::<ClientControls>();
+        controls.give_control(player, viewer);
+        // The other player doesn't need to be connected to be seen
+        controls.give_control(Uuid::from_u128(1), target);
+
+        Scene {
+            server,
+            client,
+            viewer,
+            target,
+            connection,
+        }
+    }
+
+    /// Builds a wall halfway between the creatures.
+    fn build_wall(scene: &mut Scene, distance: f32) {
+        scene.server.world.spawn((
+            TransformBundle::from(Transform::from_xyz(distance / 2.0, 1.5, 0.0)),
+            RigidBody::Fixed,
+            Collider::cuboid(0.5, 1.5, 2.0),
+            CollisionGroups::new(physics::STATIC_GROUP, Group::ALL),
+        ));
+    }
+
+    fn is_hidden(scene: &mut Scene) -> bool {
+        testing::update(&mut scene.server, &mut [&mut scene.client], FRAMES);
+        scene
+            .server
+            .world
+            .resource::<Concealment>()
+            .is_hidden(scene.connection, scene.target)
+    }
+
+    #[test]
+    fn creature_in_plain_sight_is_visible() {
+        let mut scene = setup(6.0);
+        assert!(!is_hidden(&mut scene));
+    }
+
+    #[test]
+    fn creature_behind_a_wall_is_hidden() {
+        let mut scene = setup(6.0);
+        build_wall(&mut scene, 6.0);
+        assert!(is_hidden(&mut scene));
+    }
+
+    #[test]
+    fn nearby_creature_behind_a_wall_is_heard() {
+        let mut scene = setup(HEARING_RADIUS - 0.5);
+        build_wall(&mut scene, HEARING_RADIUS - 0.5);
+        assert!(!is_hidden(&mut scene));
+    }
+
+    #[test]
+    fn ghosts_see_through_walls() {
+        let mut scene = setup(6.0);
+        build_wall(&mut scene, 6.0);
+        let viewer = scene.viewer;
+        scene
+            .server
+            .world
+            .entity_mut(viewer)
+            .insert(SeeThroughWalls);
+        assert!(!is_hidden(&mut scene));
+    }
+
+    #[test]
+    fn creature_stays_visible_briefly_after_going_behind_a_wall() {
+        let mut scene = setup(6.0);
+        assert!(!is_hidden(&mut scene));
+
+        build_wall(&mut scene, 6.0);
+        // Less than the hide delay
+        testing::update(&mut scene.server, &mut [&mut scene.client], 3);
+        let concealment = scene.server.world.resource::<Concealment>();
+        assert!(!concealment.is_hidden(scene.connection, scene.target));
+        testing::update(&mut scene.server, &mut [&mut scene.client], 5);
+        assert!(is_hidden(&mut scene));
+    }
+}
@@ -0,0 +1,543 @@
+use std::time::Duration;
+
+use bevy::{prelude::*, time::common_conditions::on_timer};
+use bevy_rapier3d::prelude::{RigidBody, Velocity};
+use maps::{TileMap, ARRIVALS_LANDMARK, CHUNK_SIZE};
+use networking::{
+    diagnostics::DebugNames,
+    identity::NetworkIdentity,
+    is_server,
+    messaging::{MessageReceivers, MessageSender},
+    spawning::ClientControls,
+    NetworkSet, Players,
+};
+use serde::Deserialize;
+
+use crate::{
+    body::{
+        health::{LethalDamageEvent, VitalStatus, Vitals},
+        Body,
+    },
+    config::ServerConfig,
+    items::Item,
+    movement::ForcePositionMessage,
+    spectator::Spectator,
+};
+
+/// Cleans up objects that left the map, and resets objects the physics simulation broke.
+pub struct VoidPlugin;
+
+impl Plugin for VoidPlugin {
+    fn build(&self, app: &mut App) {
+        if !is_server(app) {
+            return;
+        }
+
+        let config = app
+            .world
+            .get_resource::<ServerConfig>()
+            .map(|config| config.void.clone())
+            .unwrap_or_default();
+        app.insert_resource(config)
+            .add_systems(
+                Update,
+                handle_void.run_if(on_timer(Duration::from_secs_f32(VOID_CHECK_INTERVAL))),
+            )
+            .add_systems(
+                PostUpdate,
+                // Has to run before positions are sent to clients
+                reset_invalid_transforms
+                    .after(bevy_rapier3d::plugin::PhysicsSet::Writeback)
+                    .before(NetworkSet::ServerSyncPhysics),
+            );
+    }
+}
+
+/// Seconds between looking for objects in the void
+const VOID_CHECK_INTERVAL: f32 = 0.5;
+/// Objects further from the origin than this in meters have been flung away by a physics bug
+const MAX_COORDINATE: f32 = 100_000.0;
+/// Objects faster than this in meters per second have been flung away by a physics bug
+const MAX_SPEED: f32 = 1000.0;
+
+#[derive(Deserialize, Clone)]
+pub struct VoidConfig {
+    /// Objects below this height in meters have fallen out of the map
+    #[serde(default = "VoidConfig::default_min_height")]
+    pub min_height: f32,
+    /// Tiles past the edge of the map objects can be before they are in the void
+    #[serde(default = "VoidConfig::default_map_margin")]
+    pub map_margin: u32,
+    /// Seconds an item can be in the void before it is removed
+    #[serde(default = "VoidConfig::default_item_grace_seconds")]
+    pub item_grace_seconds: f32,
+    #[serde(default)]
+    pub player_policy: VoidPlayerPolicy,
+    /// Landmark players and ghosts are brought back to
+    #[serde(default = "VoidConfig::default_recovery_landmark")]
+    pub recovery_landmark: String,
+}
+
+impl VoidConfig {
+    fn default_min_height() -> f32 {
+        -10.0
+    }
+
+    fn default_map_margin() -> u32 {
+        8
+    }
+
+    fn default_item_grace_seconds() -> f32 {
+        10.0
+    }
+
+    fn default_recovery_landmark() -> String {
+        ARRIVALS_LANDMARK.to_owned()
+    }
+}
+
+impl Default for VoidConfig {
+    fn default() -> Self {
+        Self {
+            min_height: Self::default_min_height(),
+            map_margin: Self::default_map_margin(),
+            item_grace_seconds: Self::default_item_grace_seconds(),
+            player_policy: Default::default(),
+            recovery_landmark: Self::default_recovery_landmark(),
+        }
+    }
+}
+
+/// What happens to the body of a player that falls into the void. Either way the body dies.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum VoidPlayerPolicy {
+    /// The body is brought to the recovery landmark, where it can be found
+    #[default]
+    Recover,
+    /// The body is removed and the player only continues as a ghost
+    Observe,
+}
+
+impl VoidConfig {
+    /// If a position has left the map, below it or past its edges.
+    fn is_void(&self, maps: &Query<&TileMap>, position: Vec3) -> bool {
+        if position.y < self.min_height {
+            return true;
+        }
+        let margin = self.map_margin as f32;
+        !maps.iter().any(|map| {
+            let bounds = (map.size() * CHUNK_SIZE).as_vec2();
+            let position = position.xz();
+            position.min_element() >= -margin
+                && position.x <= bounds.x + margin
+                && position.y <= bounds.y + margin
+        })
+    }
+
+    /// Where players are brought back to, the first spawn point if the landmark doesn't exist.
+    fn recovery_position(&self, maps: &Query<&TileMap>) -> Option<Vec3> {
+        // Same height as job spawn points
+        maps.iter()
+            .find_map(|map| {
+                map.job_spawn_positions
+                    .get(&self.recovery_landmark)
+                    .and_then(|positions| positions.first())
+                    .or_else(|| map.job_spawn_positions.values().flatten().next())
+            })
+            .map(|tile| Vec3::new(tile.x as f32, 1.0, tile.y as f32))
+    }
+}
+
+/// An item that left the map, removed once the grace period is over.
+#[derive(Component)]
+struct InVoid {
+    since: f32,
+}
+
+/// Last position of a physics object that wasn't broken.
+#[derive(Component)]
+struct LastSanePosition(Vec3);
+
+/// Tells the client controlling a creature that it was moved, as movement is client authoritative.
+fn force_position(
+    entity: Entity,
+    transform: &Transform,
+    controls: &ClientControls,
+    players: &Players,
+    sender: &mut MessageSender,
+) {
+    let Some(connection) = controls
+        .controlling_player(entity)
+        .and_then(|player| players.get_connection(&player))
+    else {
+        return;
+    };
+    sender.send_with_priority(
+        &ForcePositionMessage {
+            position: transform.translation,
+            rotation: transform.rotation,
+        },
+        MessageReceivers::Single(connection),
+        10,
+    );
+}
+
+/// Items are removed after a grace period, player bodies die and creatures nobody controls are removed.
+/// Ghosts and observers are brought back without harm.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn handle_void(
+    mut objects: Query<
+        (
+            Entity,
+            &mut Transform,
+            Option<&mut Velocity>,
+            Option<&InVoid>,
+            Has<Item>,
+            Has<Body>,
+            Has<Spectator>,
+        ),
+        (With<NetworkIdentity>, Without<Parent>),
+    >,
+    maps: Query<&TileMap>,
+    config: Res<VoidConfig>,
+    controls: Res<ClientControls>,
+    players: Res<Players>,
+    vitals: Vitals,
+    names: DebugNames,
+    time: Res<Time>,
+    mut lethal: EventWriter<LethalDamageEvent>,
+    mut sender: MessageSender,
+    mut commands: Commands,
+) {
+    // Everything would be in the void before the map is loaded
+    if maps.is_empty() {
+        return;
+    }
+
+    let now = time.elapsed_seconds();
+    let recovery = config.recovery_position(&maps);
+    for (entity, mut transform, velocity, in_void, is_item, is_body, is_spectator) in
+        objects.iter_mut()
+    {
+        if !config.is_void(&maps, transform.translation) {
+            if in_void.is_some() {
+                commands.entity(entity).remove::<InVoid>();
+            }
+            continue;
+        }
+
+        let recover = |transform: &mut Transform, sender: &mut MessageSender| {
+            let Some(position) = recovery else {
+                warn!(entity = %names.debug_name(entity), "No landmark to recover from the void to");
+                return;
+            };
+            transform.translation = position;
+            force_position(entity, transform, &controls, &players, sender);
+        };
+
+        if is_item {
+            match in_void {
+                Some(in_void) if now - in_void.since >= config.item_grace_seconds => {
+                    info!(
+                        item = %names.debug_name(entity),
+                        position = ?transform.translation,
+                        "Item fell into the void, removing it"
+                    );
+                    commands.entity(entity).despawn_recursive();
+                }
+                Some(_) => {}
+                None => {
+                    commands.entity(entity).insert(InVoid { since: now });
+                }
+            }
+        } else if is_body && is_spectator {
+            recover(&mut transform, &mut sender);
+        } else if is_body && controls.controlling_player(entity).is_some() {
+            if vitals.status(entity) != Some(VitalStatus::Dead) {
+                info!(
+                    creature = %names.debug_name(entity),
+                    position = ?transform.translation,
+                    "Player fell into the void"
+                );
+                lethal.send(LethalDamageEvent { body: entity });
+            }
+            // Observed bodies are removed once the player is a ghost and no longer controls them
+            if config.player_policy == VoidPlayerPolicy::Recover {
+                recover(&mut transform, &mut sender);
+                if let Some(mut velocity) = velocity {
+                    *velocity = Velocity::zero();
+                }
+            }
+        } else if is_body {
+            info!(
+                creature = %names.debug_name(entity),
+                position = ?transform.translation,
+                "Creature fell into the void, removing it"
+            );
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+/// Finds objects with positions or velocities that aren't finite or are absurdly large,
+/// which a physics bug can cause. They are put back where they last were and stopped,
+/// so the broken values don't reach clients.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn reset_invalid_transforms(
+    mut objects: Query<
+        (
+            Entity,
+            &mut Transform,
+            Option<&mut Velocity>,
+            Option<&mut LastSanePosition>,
+            Has<RigidBody>,
+        ),
+        (
+            With<NetworkIdentity>,
+            Or<(Changed<Transform>, Changed<Velocity>)>,
+        ),
+    >,
+    maps: Query<&TileMap>,
+    config: Res<VoidConfig>,
+    controls: Res<ClientControls>,
+    players: Res<Players>,
+    names: DebugNames,
+    mut sender: MessageSender,
+    mut commands: Commands,
+) {
+    for (entity, mut transform, velocity, last_sane, has_body) in objects.iter_mut() {
+        let sane_transform = transform.translation.is_finite()
+            && transform.translation.abs().max_element() < MAX_COORDINATE
+            && transform.rotation.is_finite()
+            && transform.scale.is_finite();
+        let sane_velocity = velocity.as_ref().map_or(true, |velocity| {
+            velocity.linvel.is_finite()
+                && velocity.angvel.is_finite()
+                && velocity.linvel.length() < MAX_SPEED
+        });
+
+        if sane_transform && sane_velocity {
+            match last_sane {
+                Some(mut last_sane) => last_sane.0 = transform.translation,
+                // Only physics objects get flung around
+                None if has_body => {
+                    commands
+                        .entity(entity)
+                        .insert(LastSanePosition(transform.translation));
+                }
+                None => {}
+            }
+            continue;
+        }
+
+        warn!(
+            entity = %names.debug_name(entity),
+            translation = ?transform.translation,
+            rotation = ?transform.rotation,
+            velocity = ?velocity.as_ref().map(|v| v.linvel),
+            "Resetting object with an invalid transform or velocity"
+        );
+
+        if let Some(mut velocity) = velocity {
+            *velocity = Velocity::zero();
+        }
+        if !sane_transform {
+            transform.translation = last_sane
+                .map(|last_sane| last_sane.0)
+                .or_else(|| config.recovery_position(&maps))
+                .unwrap_or(Vec3::Y);
+            if !transform.rotation.is_finite() {
+                transform.rotation = Quat::IDENTITY;
+            }
+            if !transform.scale.is_finite() {
+                transform.scale = Vec3::ONE;
+            }
+        }
+        force_position(entity, &transform, &controls, &players, &mut sender);
+    }
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use bevy::{ecs::system::Command, time::TimeUpdateStrategy};
+    use bevy_rapier3d::prelude::Collider;
+    use networking::{identity::NetworkCommand, loopback::LinkConditions, testing, NetworkRole};
+
+    use super::*;
+    use crate::testing::server_app;
+
+    const FRAME: Duration = Duration::from_millis(100);
+    const RECOVERY_TILE: UVec2 = UVec2::new(3, 4);
+
+    #[derive(Resource, Default)]
+    struct Killed(Vec<Entity>);
+
+    fn record_kills(mut events: EventReader<LethalDamageEvent>, mut killed: ResMut<Killed>) {
+        killed.0.extend(events.iter().map(|event| event.body));
+    }
+
+    struct Scene {
+        server: App,
+        client: App,
+    }
+
+    impl Scene {
+        fn update(&mut self, frames: u32) {
+            testing::update(&mut self.server, &mut [&mut self.client], frames);
+        }
+
+        fn spawn(&mut self, bundle: impl Bundle, position: Vec3) -> Entity {
+            let entity = self
+                .server
+                .world
+                .spawn((
+                    bundle,
+                    TransformBundle::from(Transform::from_translation(position)),
+                ))
+                .id();
+            NetworkCommand { entity }.apply(&mut self.server.world);
+            entity
+        }
+
+        fn teleport(&mut self, entity: Entity, position: Vec3) {
+            self.server
+                .world
+                .get_mut::<Transform>(entity)
+                .unwrap()
+                .translation = position;
+        }
+
+        fn position(&self, entity: Entity) -> Vec3 {
+            self.server
+                .world
+                .get::<Transform>(entity)
+                .unwrap()
+                .translation
+        }
+
+        /// Nothing broken was sent to the client.
+        fn assert_client_is_sane(&mut self) {
+            let mut transforms = self.client.world.query::<&Transform>();
+            for transform in transforms.iter(&self.client.world) {
+                assert!(transform.translation.is_finite(), "{:?}", transform);
+                assert!(transform.rotation.is_finite(), "{:?}", transform);
+            }
+        }
+    }
+
+    /// A one chunk map with a recovery landmark, and a connected client.
+    fn setup() -> Scene {
+        let mut config = ServerConfig::default();
+        config.void.item_grace_seconds = 1.0;
+        let mut server = server_app(config);
+        server
+            .init_resource::<Killed>()
+            .add_systems(Update, record_kills);
+        let mut client = testing::app(NetworkRole::Client);
+        let connector = testing::listen(&mut server);
+        testing::join(&mut client, &connector, LinkConditions::default());
+        testing::connect(&mut server, &mut [&mut client], 200);
+        server.insert_resource(TimeUpdateStrategy::ManualDuration(FRAME));
+
+        let mut map = TileMap::new(UVec2::ONE);
+        map.job_spawn_positions
+            .insert(ARRIVALS_LANDMARK.to_owned(), vec![RECOVERY_TILE]);
+        let map = server.world.spawn((map, SpatialBundle::default())).id();
+        NetworkCommand { entity: map }.apply(&mut server.world);
+
+        let mut scene = Scene { server, client };
+        scene.update(1);
+        scene
+    }
+
+    #[test]
+    fn nan_velocity_is_reset() {
+        let mut scene = setup();
+        let start = Vec3::new(5.0, 1.0, 5.0);
+        let object = scene.spawn(
+            (RigidBody::Dynamic, Collider::ball(0.25), Velocity::zero()),
+            start,
+        );
+        scene.update(3);
+
+        scene
+            .server
+            .world
+            .get_mut::<Velocity>(object)
+            .unwrap()
+            .linvel = Vec3::NAN;
+        scene.update(3);
+
+        let velocity = scene.server.world.get::<Velocity>(object).unwrap();
+        assert!(velocity.linvel.is_finite());
+        let position = scene.position(object);
+        assert!(position.is_finite());
+        // Falling for a few frames is fine, being flung away isn't
+        assert!(position.distance(start) < 5.0, "moved to {}", position);
+        scene.assert_client_is_sane();
+    }
+
+    #[test]
+    fn absurd_position_is_reset() {
+        let mut scene = setup();
+        let start = Vec3::new(5.0, 1.0, 5.0);
+        let object = scene.spawn((RigidBody::Fixed, Collider::ball(0.25)), start);
+        scene.update(3);
+
+        scene.teleport(object, Vec3::new(1e9, 1.0, 5.0));
+        scene.update(1);
+        assert_eq!(scene.position(object), start);
+        scene.assert_client_is_sane();
+    }
+
+    #[test]
+    fn player_out_of_bounds_dies_and_is_recovered() {
+        let mut scene = setup();
+        let body = scene.spawn(Body::default(), Vec3::new(5.0, 1.0, 5.0));
+        let player = scene
+            .server
+            .world
+            .resource::<Players>()
+            .players()
+            .values()
+            .next()
+            .unwrap()
+            .id;
+        scene
+            .server
+            .world
+            .resource_mut::<ClientControls>()
+            .give_control(player, body);
+        scene.update(1);
+
+        scene.teleport(body, Vec3::new(-500.0, 1.0, 5.0));
+        scene.update(6);
+
+        assert_eq!(scene.server.world.resource::<Killed>().0, [body]);
+        assert_eq!(
+            scene.position(body),
+            Vec3::new(RECOVERY_TILE.x as f32, 1.0, RECOVERY_TILE.y as f32)
+        );
+        scene.assert_client_is_sane();
+    }
+
+    #[test]
+    fn items_in_the_void_are_removed_after_the_grace_period() {
+        let mut scene = setup();
+        let item = scene.spawn(Item::default(), Vec3::new(5.0, 1.0, 5.0));
+        let creature = scene.spawn(Body::default(), Vec3::new(6.0, 1.0, 5.0));
+        scene.update(1);
+
+        scene.teleport(item, Vec3::new(5.0, -50.0, 5.0));
+        scene.teleport(creature, Vec3::new(5.0, -50.0, 5.0));
+        scene.update(6);
+        assert!(scene.server.world.get_entity(item).is_some());
+        // Nobody controls the creature, so it's removed right away
+        assert!(scene.server.world.get_entity(creature).is_none());
+
+        scene.update(10);
+        assert!(scene.server.world.get_entity(item).is_none());
+        assert!(scene.server.world.resource::<Killed>().0.is_empty());
+    }
+}
@@ -0,0 +1,417 @@
+use std::time::Duration;
+
+use bevy::{
+    ecs::system::Command,
+    prelude::*,
+    reflect::TypeUuid,
+    time::common_conditions::on_timer,
+    utils::{HashSet, Uuid},
+};
+use networking::{
+    component::AppExt,
+    identity::NetworkCommand,
+    is_server,
+    variable::{NetworkVar, ServerVar},
+    visibility::Relevancy,
+    ConnectionId, Networked, Players,
+};
+use serde::Deserialize;
+
+use crate::{
+    config::ServerConfig,
+    console::{
+        ArgumentKind, CommandContext, CommandResult, CommandSource, ConsoleAppExt, ConsoleCommand,
+        PermissionLevel,
+    },
+    job::{JobDefinition, SelectedJobs},
+};
+
+#[cfg(feature = "client")]
+use {
+    crate::{camera::MainCamera, ui::has_window, GameState},
+    bevy_egui::{egui, EguiContexts},
+    networking::spawning::ClientControlled,
+};
+
+/// Markers placed in the world by command, shown on the HUD of the players they are meant for.
+pub struct WaypointPlugin;
+
+impl Plugin for WaypointPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_networked_component::<Waypoint, WaypointClient>();
+
+        if is_server(app) {
+            app.init_resource::<WaypointNumbers>()
+                .add_console_command(ConsoleCommand {
+                    name: "waypoint",
+                    description:
+                        "Places a waypoint at your cursor for everyone, a job id or a player",
+                    parameters: &[
+                        ("audience", ArgumentKind::Word),
+                        ("label", ArgumentKind::Text),
+                    ],
+                    permission: PermissionLevel::Player,
+                    handler: waypoint_command,
+                })
+                .add_console_command(ConsoleCommand {
+                    name: "removewaypoint",
+                    description: "Removes a waypoint you placed by its number",
+                    parameters: &[("number", ArgumentKind::Integer)],
+                    permission: PermissionLevel::Player,
+                    handler: remove_waypoint_command,
+                })
+                .add_systems(
+                    Update,
+                    (expire_waypoints, update_audiences)
+                        .chain()
+                        .run_if(on_timer(Duration::from_secs(1))),
+                );
+        } else {
+            #[cfg(feature = "client")]
+            app.add_systems(
+                Update,
+                waypoint_hud
+                    .run_if(has_window)
+                    .run_if(in_state(GameState::Game)),
+            );
+        }
+    }
+}
+
+/// Longest label a waypoint can have, in characters
+const MAX_LABEL_LENGTH: usize = 48;
+
+#[derive(Deserialize, Clone)]
+pub struct WaypointConfig {
+    /// Seconds until a waypoint is removed on its own
+    #[serde(default = "WaypointConfig::default_lifetime_seconds")]
+    pub lifetime_seconds: f32,
+}
+
+impl WaypointConfig {
+    fn default_lifetime_seconds() -> f32 {
+        300.0
+    }
+}
+
+impl Default for WaypointConfig {
+    fn default() -> Self {
+        Self {
+            lifetime_seconds: Self::default_lifetime_seconds(),
+        }
+    }
+}
+
+/// Who can see a waypoint.
+#[derive(Clone, PartialEq, Eq, Debug)]
+enum WaypointAudience {
+    Everyone,
+    /// Players that selected the job with this id
+    Job(String),
+    Player(Uuid),
+}
+
+/// A marker at a position in the world.
+/// It has no transform, so it is only sent to its audience and not to whoever is nearby.
+#[derive(Component, Networked)]
+#[networked(client = "WaypointClient")]
+pub struct Waypoint {
+    number: NetworkVar<u32>,
+    label: NetworkVar<String>,
+    position: NetworkVar<Vec3>,
+    audience: WaypointAudience,
+    /// Player that placed the waypoint, `None` for the server console
+    creator: Option<Uuid>,
+    /// Elapsed seconds when the waypoint is removed
+    expires_at: f32,
+}
+
+#[derive(Component, Default, TypeUuid, Networked)]
+#[uuid = "b3c7e2a9-58d1-4f6e-9a0b-2d4c8e1f7a65"]
+#[networked(server = "Waypoint")]
+pub struct WaypointClient {
+    number: ServerVar<u32>,
+    label: ServerVar<String>,
+    position: ServerVar<Vec3>,
+}
+
+/// Waypoints are numbered so they can be removed by command.
+#[derive(Resource, Default)]
+struct WaypointNumbers {
+    next: u32,
+}
+
+fn is_admin(world: &World, source: CommandSource) -> bool {
+    match source {
+        CommandSource::Console => true,
+        CommandSource::Player(connection) => world
+            .resource::<Players>()
+            .get(connection)
+            .is_some_and(|player| world.resource::<ServerConfig>().admins.contains(&player.id)),
+    }
+}
+
+/// The job id a player selected this round.
+fn selected_job(world: &World, connection: ConnectionId) -> Option<String> {
+    world
+        .resource::<SelectedJobs>()
+        .get(connection, world.resource::<Assets<JobDefinition>>())
+        .map(|job| job.id.clone())
+}
+
+fn parse_audience(world: &World, text: &str) -> Result<WaypointAudience, String> {
+    if text.eq_ignore_ascii_case("everyone") {
+        return Ok(WaypointAudience::Everyone);
+    }
+    if let Some(player) = world
+        .resource::<Players>()
+        .players()
+        .values()
+        .find(|player| player.username.eq_ignore_ascii_case(text))
+    {
+        return Ok(WaypointAudience::Player(player.id));
+    }
+    let is_job = world
+        .resource::<Assets<JobDefinition>>()
+        .iter()
+        .any(|(_, job)| job.id == text);
+    if is_job {
+        return Ok(WaypointAudience::Job(text.to_owned()));
+    }
+    Err(format!(
+        "'{}' is not 'everyone', a job id or a player name",
+        text
+    ))
+}
+
+fn waypoint_command(world: &mut World, context: &CommandContext) -> CommandResult {
+    let Some(position) = context.cursor else {
+        return Err("Your cursor isn't over the map".into());
+    };
+    let audience = parse_audience(world, context.text(0))?;
+    let label = context.text(1).trim();
+    if label.is_empty() || label.chars().count() > MAX_LABEL_LENGTH {
+        return Err(format!(
+            "The label must be between 1 and {} characters",
+            MAX_LABEL_LENGTH
+        ));
+    }
+
+    let creator = match context.source {
+        CommandSource::Player(connection) => world.resource::<Players>().get(connection),
+        CommandSource::Console => None,
+    }
+    .map(|player| player.id);
+
+    // Players can only guide their own department or themselves
+    if !is_admin(world, context.source) {
+        let CommandSource::Player(connection) = context.source else {
+            unreachable!();
+        };
+        let allowed = match &audience {
+            WaypointAudience::Everyone => false,
+            WaypointAudience::Job(job) => selected_job(world, connection).as_ref() == Some(job),
+            WaypointAudience::Player(id) => creator == Some(*id),
+        };
+        if !allowed {
+            return Err("Only admins can place waypoints for others than your job or you".into());
+        }
+    }
+
+    let lifetime = world
+        .get_resource::<ServerConfig>()
+        .map(|config| config.waypoints.lifetime_seconds)
+        .unwrap_or_else(WaypointConfig::default_lifetime_seconds);
+    let expires_at = world.resource::<Time>().elapsed_seconds() + lifetime;
+    let number = {
+        let mut numbers = world.resource_mut::<WaypointNumbers>();
+        numbers.next += 1;
+        numbers.next
+    };
+
+    let entity = world
+        .spawn(Waypoint {
+            number: number.into(),
+            label: label.to_owned().into(),
+            position: position.into(),
+            audience: audience.clone(),
+            creator,
+            expires_at,
+        })
+        .id();
+    NetworkCommand { entity }.apply(world);
+    info!(number, ?audience, ?creator, "Waypoint placed");
+    Ok(format!("Placed waypoint {}", number))
+}
+
+fn remove_waypoint_command(world: &mut World, context: &CommandContext) -> CommandResult {
+    let number = context.integer(0);
+    let mut query = world.query::<(Entity, &Waypoint)>();
+    let Some((entity, creator)) = query
+        .iter(world)
+        .find(|(_, waypoint)| i64::from(*waypoint.number) == number)
+        .map(|(entity, waypoint)| (entity, waypoint.creator))
+    else {
+        return Err(format!("There is no waypoint {}", number));
+    };
+
+    let is_creator = match context.source {
+        CommandSource::Player(connection) => world
+            .resource::<Players>()
+            .get(connection)
+            .is_some_and(|player| creator == Some(player.id)),
+        CommandSource::Console => false,
+    };
+    if !is_creator && !is_admin(world, context.source) {
+        return Err("Only the creator of a waypoint or an admin can remove it".into());
+    }
+
+    world.entity_mut(entity).despawn_recursive();
+    Ok(format!("Removed waypoint {}", number))
+}
+
+fn expire_waypoints(
+    waypoints: Query<(Entity, &Waypoint)>,
+    time: Res<Time>,
+    mut commands: Commands,
+) {
+    let now = time.elapsed_seconds();
+    for (entity, waypoint) in waypoints.iter() {
+        if waypoint.expires_at <= now {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+/// Shows waypoints to the players in their audience.
+/// Job audiences change when players join or pick a job, so they are found again every time.
+fn update_audiences(
+    waypoints: Query<(Entity, &Waypoint)>,
+    players: Res<Players>,
+    selected_jobs: Res<SelectedJobs>,
+    jobs: Res<Assets<JobDefinition>>,
+    mut relevancy: ResMut<Relevancy>,
+) {
+    for (entity, waypoint) in waypoints.iter() {
+        let audience: HashSet<ConnectionId> = players
+            .players()
+            .iter()
+            .filter(|(&connection, player)| match &waypoint.audience {
+                WaypointAudience::Everyone => true,
+                WaypointAudience::Job(job) => selected_jobs
+                    .get(connection, &jobs)
+                    .is_some_and(|selected| &selected.id == job),
+                WaypointAudience::Player(id) => player.id == *id,
+            })
+            .map(|(&connection, _)| connection)
+            .collect();
+
+        // The waypoint owns its overrides, so they are dropped when it is removed
+        relevancy.remove_owner(entity);
+        for connection in audience {
+            relevancy.add_viewer(entity, entity, connection);
+        }
+    }
+}
+
+#[cfg(feature = "client")]
+const MARKER_RADIUS: f32 = 6.0;
+/// Distance of markers clamped to the screen edge from the edge, in points
+#[cfg(feature = "client")]
+const EDGE_MARGIN: f32 = 24.0;
+#[cfg(feature = "client")]
+const ARROW_SIZE: f32 = 10.0;
+
+/// Draws a marker with the distance for every waypoint.
+/// Waypoints that are off-screen stick to the screen edge with an arrow pointing at them.
+#[cfg(feature = "client")]
+fn waypoint_hud(
+    mut contexts: EguiContexts,
+    waypoints: Query<&WaypointClient>,
+    cameras: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    controlled: Query<&GlobalTransform, With<ClientControlled>>,
+) {
+    // The camera can be missing while it is being switched
+    let Ok((camera, camera_transform)) = cameras.get_single() else {
+        return;
+    };
+    let Some(viewport) = camera.logical_viewport_size() else {
+        return;
+    };
+    let player_position = controlled.get_single().ok().map(|t| t.translation());
+
+    let ctx = contexts.ctx_mut();
+    let painter = ctx.layer_painter(egui::LayerId::new(
+        egui::Order::Background,
+        egui::Id::new("waypoints"),
+    ));
+    let color = egui::Color32::from_rgb(90, 200, 230);
+    let screen = egui::Rect::from_min_size(egui::Pos2::ZERO, egui::vec2(viewport.x, viewport.y));
+    let inner = screen.shrink(EDGE_MARGIN);
+
+    for waypoint in waypoints.iter() {
+        let position = *waypoint.position;
+        let on_screen = camera
+            .world_to_ndc(camera_transform, position)
+            .filter(|ndc| ndc.x.abs() <= 1.0 && ndc.y.abs() <= 1.0 && (0.0..=1.0).contains(&ndc.z))
+            .and_then(|_| camera.world_to_viewport(camera_transform, position))
+            .map(|p| egui::pos2(p.x, p.y))
+            .filter(|p| inner.contains(*p));
+
+        let (marker, arrow) = match on_screen {
+            Some(marker) => (marker, None),
+            None => {
+                // Use the direction in view space, which also works for waypoints behind the camera
+                let local = camera_transform
+                    .affine()
+                    .inverse()
+                    .transform_point3(position);
+                let direction = egui::vec2(local.x, -local.y);
+                let direction = if direction.length_sq() > f32::EPSILON {
+                    direction.normalized()
+                } else {
+                    egui::vec2(0.0, 1.0)
+                };
+                let center = inner.center();
+                let scale = (inner.width() / 2.0 / direction.x.abs().max(f32::EPSILON))
+                    .min(inner.height() / 2.0 / direction.y.abs().max(f32::EPSILON));
+                (center + direction * scale, Some(direction))
+            }
+        };
+
+        painter.circle_stroke(marker, MARKER_RADIUS, egui::Stroke::new(2.0, color));
+        if let Some(direction) = arrow {
+            let tip = marker + direction * (MARKER_RADIUS + ARROW_SIZE);
+            let base = marker + direction * (MARKER_RADIUS + 2.0);
+            let side = egui::vec2(-direction.y, direction.x) * (ARROW_SIZE / 2.0);
+            painter.add(egui::Shape::convex_polygon(
+                vec![tip, base + side, base - side],
+                color,
+                egui::Stroke::NONE,
+            ));
+        }
+
+        let text = match player_position {
+            Some(player) => format!("{} ({:.0} m)", *waypoint.label, player.distance(position)),
+            None => waypoint.label.to_string(),
+        };
+        // Keep the text on the side facing the middle of the screen
+        let anchor = if marker.y < screen.center().y {
+            egui::Align2::CENTER_TOP
+        } else {
+            egui::Align2::CENTER_BOTTOM
+        };
+        let offset = if marker.y < screen.center().y {
+            MARKER_RADIUS + 2.0
+        } else {
+            -(MARKER_RADIUS + 2.0)
+        };
+        painter.text(
+            marker + egui::vec2(0.0, offset),
+            anchor,
+            text,
+            egui::FontId::proportional(14.0),
+            color,
+        );
+    }
+}
@@ -0,0 +1,262 @@
+use bevy::prelude::*;
+use maps::{MapCommandsExt, TileEntity, TileLayer};
+use networking::{is_server, scene::NetworkSceneBundle};
+
+use crate::{
+    body::self_or_ancestor,
+    combat::damage::{AffectedEntity, Attack, KineticDamage},
+    effects::{EffectKind, EffectSender},
+};
+
+/// Lets windows take damage and shatter.
+pub struct WindowsPlugin;
+
+impl Plugin for WindowsPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<WindowPane>();
+
+        if is_server(app) {
+            app.add_systems(Update, damage_windows);
+        }
+    }
+}
+
+/// Turf left behind by a shattered window
+const SHATTERED_TURF: &str = "tilemap/turfs/plating.scn.ron";
+const SHARDS_PREFAB: &str = "items/glass_shards.scn.ron";
+/// Meters between the shards a window leaves, so they don't start inside each other
+const SHARD_SPACING: f32 = 0.2;
+
+/// Glass turf that blocks movement, but not sight.
+/// Sight is left to [`crate::lights::Opaque`], which windows don't have.
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct WindowPane {
+    /// Joules of damage the pane takes before it shatters
+    pub integrity: f32,
+    /// How many glass shards are left on the floor when it shatters
+    pub shards: u32,
+}
+
+impl Default for WindowPane {
+    fn default() -> Self {
+        Self {
+            integrity: 20000.0,
+            shards: 2,
+        }
+    }
+}
+
+/// Attacks wear windows down until they shatter, which leaves shards on the floor below.
+fn damage_windows(
+    attacks: Query<(Entity, &AffectedEntity, &KineticDamage), Added<Attack>>,
+    parents: Query<&Parent>,
+    mut windows: Query<(&mut WindowPane, &TileEntity, &GlobalTransform)>,
+    asset_server: Res<AssetServer>,
+    mut effects: EffectSender,
+    mut commands: Commands,
+) {
+    for (attack, affected, kinetic) in attacks.iter() {
+        // Attacks hit the collider, which is a child of the window
+        let Some(window) = self_or_ancestor(&parents, affected.0, |e| windows.contains(e)) else {
+            continue;
+        };
+        commands.entity(attack).despawn();
+
+        let (mut pane, tile, transform) = windows.get_mut(window).unwrap();
+        // Already shattered by an earlier attack this frame
+        if pane.integrity <= 0.0 {
+            continue;
+        }
+        pane.integrity -= 0.5 * kinetic.mass * kinetic.velocity.powi(2) * kinetic.scale;
+        if pane.integrity > 0.0 {
+            continue;
+        }
+
+        let position = transform.translation();
+        effects.send(EffectKind::GlassBreak, position + Vec3::Y, 1.5);
+        let spread = pane.shards.saturating_sub(1) as f32 * SHARD_SPACING;
+        for i in 0..pane.shards {
+            let offset = Vec3::new(i as f32 * SHARD_SPACING - spread / 2.0, 0.5, 0.0);
+            commands.spawn(NetworkSceneBundle {
+                scene: asset_server.load(SHARDS_PREFAB).into(),
+                transform: Transform::from_translation(position + offset),
+                ..Default::default()
+            });
+        }
+        // Takes the place of the window, which despawns it
+        commands.spawn_tile_entity(
+            tile.tilemap(),
+            tile.position(),
+            TileLayer::Turf,
+            asset_server.load(SHATTERED_TURF),
+        );
+        debug!(position = ?tile.position(), "Window shattered");
+    }
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::ecs::system::{Command, CommandQueue, SystemState};
+    use bevy_rapier3d::prelude::{Collider, CollisionGroups, QueryFilter, RapierContext};
+    use maps::{Direction, TileMap};
+    use networking::identity::NetworkCommand;
+
+    use super::*;
+    use crate::{
+        combat::damage::KineticShape, config::ServerConfig, items::Item, lights::Opaque,
+        testing::server_app, vision::LineOfSight,
+    };
+
+    const WINDOW_TURF: &str = "tilemap/turfs/window.scn.ron";
+    const WINDOW: UVec2 = UVec2::new(3, 3);
+    const MAX_FRAMES: u32 = 1000;
+    const FRAME_TIME: Duration = Duration::from_millis(5);
+
+    fn update_until(app: &mut App, what: &str, mut done: impl FnMut(&mut App) -> bool) {
+        for _ in 0..MAX_FRAMES {
+            app.update();
+            if done(app) {
+                return;
+            }
+            std::thread::sleep(FRAME_TIME);
+        }
+        panic!("Waited too long for {}", what);
+    }
+
+    /// The physics collider of a turf and where it is.
+    fn collider(app: &App, turf: Entity) -> Option<(Collider, Vec3)> {
+        app.world.get::<Children>(turf)?.iter().find_map(|&child| {
+            let collider = app.world.get::<Collider>(child)?;
+            let transform = app.world.get::<GlobalTransform>(child)?;
+            Some((collider.clone(), transform.translation()))
+        })
+    }
+
+    /// A server with a map that only has the turf on [`WINDOW`], once physics knows its collider.
+    fn setup(turf: &str) -> (App, Entity) {
+        let mut app = server_app(ServerConfig::default());
+        // The test brings its own map
+        app.update();
+        app.world.remove_resource::<crate::Map>();
+        let map = app
+            .world
+            .spawn((TileMap::new(UVec2::ONE), SpatialBundle::default()))
+            .id();
+        NetworkCommand { entity: map }.apply(&mut app.world);
+
+        let scene = app.world.resource::<AssetServer>().load(turf);
+        let mut queue = CommandQueue::default();
+        let window = Commands::new(&mut queue, &app.world).spawn_tile_entity(
+            map,
+            WINDOW,
+            TileLayer::Turf,
+            scene,
+        );
+        queue.apply(&mut app.world);
+
+        update_until(&mut app, "the turf collider", |app| {
+            collider(app, window).is_some()
+        });
+        // Lets the physics step add the collider to the query pipeline
+        for _ in 0..3 {
+            app.update();
+        }
+        (app, window)
+    }
+
+    fn attack(app: &mut App, window: Entity, velocity: f32) {
+        app.world.spawn((
+            Attack,
+            AffectedEntity(window),
+            KineticDamage {
+                velocity,
+                mass: 10.0,
+                shape: KineticShape::Blunt,
+                scale: 1.0,
+            },
+        ));
+        app.update();
+    }
+
+    #[test]
+    fn window_shatters_into_shards_and_plating() {
+        let (mut app, window) = setup(WINDOW_TURF);
+
+        // 5000 joules only crack the pane
+        attack(&mut app, window, 10.0 * 10f32.sqrt());
+        let integrity = app.world.get::<WindowPane>(window).unwrap().integrity;
+        assert!((integrity - 15000.0).abs() < 1.0, "integrity {}", integrity);
+
+        attack(&mut app, window, 100.0);
+        update_until(&mut app, "the shards", |app| {
+            app.world
+                .query_filtered::<(), With<Item>>()
+                .iter(&app.world)
+                .count()
+                == 2
+        });
+        assert!(app.world.get_entity(window).is_none());
+        let map = app.world.query::<&TileMap>().single(&app.world);
+        let turf = map.tile(WINDOW).unwrap().turf.unwrap();
+        assert_ne!(turf, window);
+        assert!(app.world.get::<WindowPane>(turf).is_none());
+    }
+
+    #[test]
+    fn thin_window_colliders_sit_on_their_edge() {
+        for (name, direction) in [
+            ("north", Direction::North),
+            ("south", Direction::South),
+            ("east", Direction::East),
+            ("west", Direction::West),
+        ] {
+            let (app, window) = setup(&format!("tilemap/turfs/thin window {}.scn.ron", name));
+            let (collider, position) = collider(&app, window).unwrap();
+
+            let edge = IVec2::from(direction).as_vec2() * 0.45;
+            let expected = WINDOW.as_vec2() + edge;
+            assert!(
+                position.xz().distance(expected) < 0.01,
+                "{} window collider at {}",
+                name,
+                position
+            );
+            // Thin across the edge, covering the whole edge along it
+            let half_extents = collider.as_cuboid().unwrap().half_extents();
+            let (across, along) = if edge.x != 0.0 {
+                (half_extents.x, half_extents.z)
+            } else {
+                (half_extents.z, half_extents.x)
+            };
+            assert!(across < 0.1, "{} window is {} thick", name, across * 2.0);
+            assert!((along - 0.5).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn window_blocks_movement_but_not_sight() {
+        let (mut app, window) = setup(WINDOW_TURF);
+        let from = Vec3::new(WINDOW.x as f32 - 2.0, 1.5, WINDOW.y as f32);
+        let to = from + Vec3::X * 4.0;
+        let mut state = SystemState::<(LineOfSight, Res<RapierContext>)>::new(&mut app.world);
+
+        let (sight, rapier) = state.get(&app.world);
+        assert!(sight.clear(from, to));
+        // Creatures collide with every static collider
+        let movement = QueryFilter::only_fixed().groups(CollisionGroups::new(
+            physics::DEFAULT_GROUP,
+            physics::OBSTACLE_GROUPS,
+        ));
+        assert!(rapier
+            .cast_ray(from, Vec3::X, 4.0, true, movement)
+            .is_some());
+
+        // The same turf blocks sight once it is opaque, like a wall
+        app.world.entity_mut(window).insert(Opaque);
+        let (sight, _) = state.get(&app.world);
+        assert!(!sight.clear(from, to));
+    }
+}
@@ -0,0 +1,96 @@
+//! Builds the client-only and server-only feature sets, so code used by only one side stays gated.
+//! The builds use their own target directory, cargo keeps the one running the tests locked.
+
+use std::{
+    io::{BufRead, BufReader, Read},
+    path::PathBuf,
+    process::{Command, Stdio},
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+
+/// How long the dedicated server may take to open its socket
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(120);
+
+fn target_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("target/feature-builds")
+}
+
+/// A cargo command building only the given features of the game.
+fn cargo(command: &str, features: &str) -> Command {
+    let mut cargo = Command::new(env!("CARGO"));
+    cargo
+        .args([command, "--no-default-features", "--features", features])
+        .arg("--target-dir")
+        .arg(target_dir())
+        .current_dir(env!("CARGO_MANIFEST_DIR"));
+    cargo
+}
+
+fn check(features: &str) {
+    let status = cargo("check", features).status().unwrap();
+    assert!(status.success(), "The {} build doesn't compile", features);
+}
+
+#[test]
+fn client_only_build_compiles() {
+    check("client");
+}
+
+#[test]
+fn server_only_build_compiles() {
+    check("server");
+}
+
+/// Sends every line the process prints, from both output streams.
+fn forward_lines(stream: impl Read + Send + 'static, lines: mpsc::Sender<String>) {
+    std::thread::spawn(move || {
+        for line in BufReader::new(stream).lines() {
+            let Ok(line) = line else {
+                return;
+            };
+            if lines.send(line).is_err() {
+                return;
+            }
+        }
+    });
+}
+
+#[test]
+fn dedicated_server_starts_without_display() {
+    let status = cargo("build", "server").status().unwrap();
+    assert!(status.success(), "The server build doesn't compile");
+
+    let binary = target_dir()
+        .join("debug")
+        .join(format!("ssnt{}", std::env::consts::EXE_SUFFIX));
+    let mut server = Command::new(binary)
+        .args(["host", "127.0.0.1:0"])
+        .env_remove("DISPLAY")
+        .env_remove("WAYLAND_DISPLAY")
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let (sender, lines) = mpsc::channel();
+    forward_lines(server.stdout.take().unwrap(), sender.clone());
+    forward_lines(server.stderr.take().unwrap(), sender);
+
+    let deadline = Instant::now() + STARTUP_TIMEOUT;
+    let mut output = Vec::new();
+    let listening = loop {
+        match lines.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok(line) if line.contains("Server listening") => break true,
+            Ok(line) => output.push(line),
+            Err(_) => break false,
+        }
+    };
+    let _ = server.kill();
+    let _ = server.wait();
+    assert!(
+        listening,
+        "The server didn't start listening:\n{}",
+        output.join("\n")
+    );
+}